@@ -123,6 +123,10 @@ list_of_functions(
                 "title": "Transcendental functions",
                 "modules": ["math::transcendental"],
             },
+            {
+                "title": "Logarithmic levels",
+                "modules": ["math::decibel"],
+            },
             {
                 "title": "Trigonometry",
                 "modules": ["math::trigonometry"],
@@ -143,6 +147,10 @@ list_of_functions(
                 "title": "Numerical methods",
                 "modules": ["numerics::diff", "numerics::solve"],
             },
+            {
+                "title": "Matrices",
+                "modules": ["numerics::matrices"],
+            },
             {
                 "title": "Geometry",
                 "modules": ["math::geometry"],
@@ -190,7 +198,12 @@ list_of_functions(
         "introduction": "See [this page](./date-and-time.md) for a general introduction to date and time handling in Numbat.",
         "sections": [
             {
-                "modules": ["datetime::functions", "datetime::human"],
+                "modules": [
+                    "datetime::functions",
+                    "datetime::human",
+                    "datetime::benchmark",
+                    "datetime::series",
+                ],
             },
         ],
     },
@@ -210,10 +223,22 @@ list_of_functions(
                 "title": "Floating point",
                 "modules": ["core::numbers"],
             },
+            {
+                "title": "Booleans",
+                "modules": ["core::booleans"],
+            },
+            {
+                "title": "JSON",
+                "modules": ["core::json"],
+            },
             {
                 "title": "Quantities",
                 "modules": ["core::quantities"],
             },
+            {
+                "title": "Scripts",
+                "modules": ["core::scripts"],
+            },
             {
                 "title": "Chemical elements",
                 "modules": ["chemistry::elements"],
@@ -222,10 +247,18 @@ list_of_functions(
                 "title": "Mixed unit conversion",
                 "modules": ["units::mixed"],
             },
+            {
+                "title": "Currencies",
+                "modules": ["units::currency"],
+            },
             {
                 "title": "Temperature conversion",
                 "modules": ["physics::temperature_conversion"],
             },
+            {
+                "title": "Non-linear units",
+                "modules": ["units::nonlinear"],
+            },
         ],
     },
 )
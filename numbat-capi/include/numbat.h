@@ -0,0 +1,261 @@
+#ifndef NUMBAT_H
+#define NUMBAT_H
+
+/* Generated with cbindgen from numbat-capi/src/lib.rs. Do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum NumbatStatus {
+  NUMBAT_STATUS_OK = 0,
+  /**
+   * The code could not be parsed, type checked or evaluated. See the diagnostics
+   * of the result for details.
+   */
+  NUMBAT_STATUS_ERROR = 1,
+  /**
+   * A null pointer or invalid UTF-8 was passed.
+   */
+  NUMBAT_STATUS_INVALID_ARGUMENT = 2,
+  /**
+   * Numbat panicked. This is always a bug.
+   */
+  NUMBAT_STATUS_PANIC = 3,
+} NumbatStatus;
+
+typedef enum NumbatValueKind {
+  /**
+   * A number with a unit (which can be empty, for scalars)
+   */
+  NUMBAT_VALUE_KIND_QUANTITY = 0,
+  NUMBAT_VALUE_KIND_BOOLEAN = 1,
+  NUMBAT_VALUE_KIND_STRING = 2,
+  NUMBAT_VALUE_KIND_DATE_TIME = 3,
+  NUMBAT_VALUE_KIND_FUNCTION = 4,
+  NUMBAT_VALUE_KIND_STRUCT = 5,
+  NUMBAT_VALUE_KIND_LIST = 6,
+  NUMBAT_VALUE_KIND_OTHER = 7,
+} NumbatValueKind;
+
+/**
+ * A Numbat interpreter with its own set of definitions.
+ */
+typedef struct NumbatContext NumbatContext;
+
+/**
+ * An error, with its location in the source code.
+ */
+typedef struct NumbatDiagnostic NumbatDiagnostic;
+
+/**
+ * The outcome of [`numbat_interpret`] or [`numbat_register_function`].
+ */
+typedef struct NumbatResult NumbatResult;
+
+/**
+ * A value, either the result of a calculation or an argument of a native function.
+ */
+typedef struct NumbatValue NumbatValue;
+
+/**
+ * A function implemented by the host application. It receives the evaluated
+ * arguments, which are only valid during the call, and returns a new value
+ * (see `numbat_value_new_*`), whose ownership is transferred to Numbat. The
+ * value must be of the declared return type. Returning null signals an error.
+ */
+typedef NumbatValue *(*NumbatNativeFunction)(void *user_data,
+                                             const NumbatValue *const *args,
+                                             size_t num_args);
+
+/**
+ * A borrowed UTF-8 string (not zero-terminated).
+ */
+typedef struct NumbatStr {
+  const uint8_t *ptr;
+  size_t len;
+} NumbatStr;
+
+/**
+ * A location in the source code that a diagnostic refers to. Lines and
+ * columns start at 1.
+ */
+typedef struct NumbatSpan {
+  /**
+   * Name of the code source, e.g. `<input:1>`
+   */
+  struct NumbatStr file;
+  size_t byte_start;
+  size_t byte_end;
+  size_t line_start;
+  size_t column_start;
+  size_t line_end;
+  size_t column_end;
+  struct NumbatStr label;
+} NumbatSpan;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Create a new context. If `load_prelude` is set, the standard library
+ * (units, constants and functions) is loaded. Returns null on failure.
+ */
+struct NumbatContext *numbat_context_new(bool load_prelude);
+
+void numbat_context_free(struct NumbatContext *context);
+
+/**
+ * Interpret the given code. Definitions stay available for subsequent calls.
+ * The result is never null.
+ */
+struct NumbatResult *numbat_interpret(struct NumbatContext *context,
+                                      const uint8_t *code,
+                                      size_t code_len);
+
+/**
+ * Make `function` available to Numbat code under the given `name`. The
+ * `signature` declares its type like a foreign function in a Numbat module,
+ * without the name, e.g. `(radius: Length) -> Area`. `user_data` is passed
+ * to every call. It has to stay valid until the function is unregistered or
+ * the context is freed.
+ */
+struct NumbatResult *numbat_register_function(struct NumbatContext *context,
+                                              const uint8_t *name,
+                                              size_t name_len,
+                                              const uint8_t *signature,
+                                              size_t signature_len,
+                                              NumbatNativeFunction function,
+                                              void *user_data);
+
+/**
+ * Remove a function that has been added with [`numbat_register_function`].
+ * Returns false if there is no such function.
+ */
+bool numbat_unregister_function(struct NumbatContext *context,
+                                const uint8_t *name,
+                                size_t name_len);
+
+void numbat_result_free(struct NumbatResult *result);
+
+enum NumbatStatus numbat_result_status(const struct NumbatResult *result);
+
+/**
+ * A short description of what went wrong, or an empty string.
+ */
+struct NumbatStr numbat_result_error_message(const struct NumbatResult *result);
+
+/**
+ * The value of the last expression, or null if there is none (e.g. for
+ * definitions, or in case of an error).
+ */
+const struct NumbatValue *numbat_result_value(const struct NumbatResult *result);
+
+/**
+ * The type of the last expression (e.g. `Length / Time`), or an empty string.
+ */
+struct NumbatStr numbat_result_type(const struct NumbatResult *result);
+
+/**
+ * Output of `print` calls, one line per call.
+ */
+struct NumbatStr numbat_result_output(const struct NumbatResult *result);
+
+size_t numbat_result_diagnostic_count(const struct NumbatResult *result);
+
+/**
+ * The diagnostic with the given index, or null if the index is out of range.
+ */
+const struct NumbatDiagnostic *numbat_result_diagnostic(const struct NumbatResult *result,
+                                                        size_t index);
+
+/**
+ * The stable error code, e.g. `E0501`, or an empty string.
+ */
+struct NumbatStr numbat_diagnostic_code(const struct NumbatDiagnostic *diagnostic);
+
+struct NumbatStr numbat_diagnostic_message(const struct NumbatDiagnostic *diagnostic);
+
+/**
+ * Write the main location of the diagnostic to `span`. Returns false (and
+ * leaves `span` untouched) if the diagnostic has no location.
+ */
+bool numbat_diagnostic_primary_span(const struct NumbatDiagnostic *diagnostic,
+                                    struct NumbatSpan *span);
+
+size_t numbat_diagnostic_secondary_span_count(const struct NumbatDiagnostic *diagnostic);
+
+/**
+ * Write the secondary location with the given index to `span`. Returns false
+ * if the index is out of range.
+ */
+bool numbat_diagnostic_secondary_span(const struct NumbatDiagnostic *diagnostic,
+                                      size_t index,
+                                      struct NumbatSpan *span);
+
+size_t numbat_diagnostic_note_count(const struct NumbatDiagnostic *diagnostic);
+
+struct NumbatStr numbat_diagnostic_note(const struct NumbatDiagnostic *diagnostic, size_t index);
+
+enum NumbatValueKind numbat_value_kind(const struct NumbatValue *value);
+
+/**
+ * The numerical value of a quantity (in its own unit), or NaN for all other
+ * kinds of values.
+ */
+double numbat_value_magnitude(const struct NumbatValue *value);
+
+/**
+ * The unit of a quantity, e.g. `km/h`. Empty for scalars and all other
+ * kinds of values.
+ */
+struct NumbatStr numbat_value_unit(const struct NumbatValue *value);
+
+/**
+ * The value as it would be printed by Numbat, e.g. `2.5 km/h`.
+ */
+struct NumbatStr numbat_value_display(const struct NumbatValue *value);
+
+/**
+ * The value of a boolean. False for all other kinds of values.
+ */
+bool numbat_value_bool(const struct NumbatValue *value);
+
+/**
+ * The content of a string. Empty for all other kinds of values.
+ */
+struct NumbatStr numbat_value_string(const struct NumbatValue *value);
+
+/**
+ * A dimensionless number.
+ */
+struct NumbatValue *numbat_value_new_scalar(double magnitude);
+
+/**
+ * A quantity with the same unit as `prototype`, which must be a quantity.
+ * Returns null otherwise.
+ */
+struct NumbatValue *numbat_value_new_quantity_like(const struct NumbatValue *prototype,
+                                                   double magnitude);
+
+struct NumbatValue *numbat_value_new_bool(bool value);
+
+/**
+ * Returns null if the string is not valid UTF-8.
+ */
+struct NumbatValue *numbat_value_new_string(const uint8_t *ptr, size_t len);
+
+/**
+ * Free a value that was created with `numbat_value_new_*`, but not returned
+ * from a native function.
+ */
+void numbat_value_free(struct NumbatValue *value);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* NUMBAT_H */
@@ -0,0 +1,705 @@
+//! C API for embedding Numbat in applications that are not written in Rust.
+//! The corresponding header is `include/numbat.h`.
+//!
+//! General rules:
+//!
+//! - Strings are passed as UTF-8 without a terminating zero byte, as a pointer
+//!   and a length in bytes ([`NumbatStr`] for returned strings).
+//! - Objects returned by `numbat_*_new`, [`numbat_interpret`] and
+//!   [`numbat_register_function`] are owned by the caller and have to be released
+//!   with the corresponding `numbat_*_free` function.
+//! - Everything else that is returned as a pointer (values, diagnostics, strings)
+//!   is borrowed from the object it was obtained from, and stays valid until that
+//!   object is freed.
+//! - Passing invalid pointers is undefined behavior. Null pointers are detected
+//!   and reported as [`NumbatStatus::InvalidArgument`] where possible.
+//! - A context must not be used from multiple threads at the same time.
+//!
+//! Panics never cross the API boundary. They are reported as
+//! [`NumbatStatus::Panic`], after which the context will refuse to do anything
+//! else (its state could be inconsistent).
+
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::OnceCell;
+use std::ffi::c_void;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use numbat::diagnostic::{DiagnosticSpan, StructuredDiagnostic};
+use numbat::markup::{Formatter, PlainTextFormatter};
+use numbat::module_importer::BuiltinModuleImporter;
+use numbat::output::BufferingSink;
+use numbat::pretty_print::PrettyPrint;
+use numbat::resolver::CodeSource;
+use numbat::value::Value;
+use numbat::{Context, InterpreterResult, NumbatError, Quantity};
+
+/// A borrowed UTF-8 string (not zero-terminated).
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct NumbatStr {
+    pub ptr: *const u8,
+    pub len: usize,
+}
+
+impl NumbatStr {
+    fn new(s: &str) -> Self {
+        NumbatStr {
+            ptr: s.as_ptr(),
+            len: s.len(),
+        }
+    }
+
+    fn empty() -> Self {
+        NumbatStr {
+            ptr: std::ptr::null(),
+            len: 0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumbatStatus {
+    Ok = 0,
+    /// The code could not be parsed, type checked or evaluated. See the diagnostics
+    /// of the result for details.
+    Error = 1,
+    /// A null pointer or invalid UTF-8 was passed.
+    InvalidArgument = 2,
+    /// Numbat panicked. This is always a bug.
+    Panic = 3,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumbatValueKind {
+    /// A number with a unit (which can be empty, for scalars)
+    Quantity = 0,
+    Boolean = 1,
+    String = 2,
+    DateTime = 3,
+    Function = 4,
+    Struct = 5,
+    List = 6,
+    Other = 7,
+}
+
+/// A location in the source code that a diagnostic refers to. Lines and
+/// columns start at 1.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct NumbatSpan {
+    /// Name of the code source, e.g. `<input:1>`
+    pub file: NumbatStr,
+    pub byte_start: usize,
+    pub byte_end: usize,
+    pub line_start: usize,
+    pub column_start: usize,
+    pub line_end: usize,
+    pub column_end: usize,
+    pub label: NumbatStr,
+}
+
+impl NumbatSpan {
+    fn new(span: &DiagnosticSpan) -> Self {
+        NumbatSpan {
+            file: NumbatStr::new(&span.file),
+            byte_start: span.byte_start,
+            byte_end: span.byte_end,
+            line_start: span.line_start,
+            column_start: span.column_start,
+            line_end: span.line_end,
+            column_end: span.column_end,
+            label: NumbatStr::new(&span.label),
+        }
+    }
+}
+
+/// A Numbat interpreter with its own set of definitions.
+pub struct NumbatContext {
+    context: Context,
+    /// Set after a panic
+    poisoned: bool,
+}
+
+/// A value, either the result of a calculation or an argument of a native function.
+pub struct NumbatValue {
+    value: Value,
+    display: OnceCell<String>,
+    unit: OnceCell<String>,
+}
+
+impl NumbatValue {
+    fn new(value: Value) -> Self {
+        NumbatValue {
+            value,
+            display: OnceCell::new(),
+            unit: OnceCell::new(),
+        }
+    }
+
+    fn into_raw(self) -> *mut NumbatValue {
+        Box::into_raw(Box::new(self))
+    }
+}
+
+/// An error, with its location in the source code.
+pub struct NumbatDiagnostic(StructuredDiagnostic);
+
+/// The outcome of [`numbat_interpret`] or [`numbat_register_function`].
+pub struct NumbatResult {
+    status: NumbatStatus,
+    error_message: String,
+    value: Option<NumbatValue>,
+    type_: Option<String>,
+    output: String,
+    diagnostics: Vec<NumbatDiagnostic>,
+}
+
+impl NumbatResult {
+    fn new(status: NumbatStatus, error_message: impl Into<String>) -> Self {
+        NumbatResult {
+            status,
+            error_message: error_message.into(),
+            value: None,
+            type_: None,
+            output: String::new(),
+            diagnostics: vec![],
+        }
+    }
+
+    fn from_error(context: &Context, error: NumbatError) -> Self {
+        let files = &context.resolver().files;
+        NumbatResult {
+            diagnostics: context
+                .diagnostics(&error)
+                .iter()
+                .map(|diagnostic| NumbatDiagnostic(StructuredDiagnostic::new(diagnostic, files)))
+                .collect(),
+            ..Self::new(NumbatStatus::Error, error.to_string())
+        }
+    }
+
+    fn into_raw(self) -> *mut NumbatResult {
+        Box::into_raw(Box::new(self))
+    }
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    match payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+    {
+        Some(message) => format!("Numbat panicked: {message}"),
+        None => "Numbat panicked".into(),
+    }
+}
+
+unsafe fn str_arg<'a>(ptr: *const u8, len: usize) -> Option<&'a str> {
+    if ptr.is_null() {
+        return if len == 0 { Some("") } else { None };
+    }
+    std::str::from_utf8(std::slice::from_raw_parts(ptr, len)).ok()
+}
+
+/// Run `f` on the context, catching panics.
+unsafe fn with_context(
+    context: *mut NumbatContext,
+    f: impl FnOnce(&mut Context) -> NumbatResult,
+) -> *mut NumbatResult {
+    let Some(context) = context.as_mut() else {
+        return NumbatResult::new(NumbatStatus::InvalidArgument, "context is null").into_raw();
+    };
+    if context.poisoned {
+        return NumbatResult::new(
+            NumbatStatus::Panic,
+            "the context can not be used after a panic",
+        )
+        .into_raw();
+    }
+
+    let result = catch_unwind(AssertUnwindSafe(|| f(&mut context.context)));
+    match result {
+        Ok(result) => result.into_raw(),
+        Err(payload) => {
+            context.poisoned = true;
+            NumbatResult::new(NumbatStatus::Panic, panic_message(&*payload)).into_raw()
+        }
+    }
+}
+
+/// Catch panics in accessors, returning `default` instead.
+fn guard<T>(default: T, f: impl FnOnce() -> T) -> T {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or(default)
+}
+
+/// Create a new context. If `load_prelude` is set, the standard library
+/// (units, constants and functions) is loaded. Returns null on failure.
+#[no_mangle]
+pub extern "C" fn numbat_context_new(load_prelude: bool) -> *mut NumbatContext {
+    guard(std::ptr::null_mut(), || {
+        let mut context = Context::new(BuiltinModuleImporter::default());
+        if load_prelude
+            && context
+                .interpret_with_sink(
+                    &mut BufferingSink::default(),
+                    "use prelude",
+                    CodeSource::Internal,
+                )
+                .is_err()
+        {
+            return std::ptr::null_mut();
+        }
+        Box::into_raw(Box::new(NumbatContext {
+            context,
+            poisoned: false,
+        }))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn numbat_context_free(context: *mut NumbatContext) {
+    if !context.is_null() {
+        drop(Box::from_raw(context));
+    }
+}
+
+/// Interpret the given code. Definitions stay available for subsequent calls.
+/// The result is never null.
+#[no_mangle]
+pub unsafe extern "C" fn numbat_interpret(
+    context: *mut NumbatContext,
+    code: *const u8,
+    code_len: usize,
+) -> *mut NumbatResult {
+    let Some(code) = str_arg(code, code_len) else {
+        return NumbatResult::new(NumbatStatus::InvalidArgument, "code is not valid UTF-8")
+            .into_raw();
+    };
+
+    with_context(context, |context| {
+        let mut sink = BufferingSink::default();
+        let result = context.interpret_with_sink(&mut sink, code, CodeSource::Text);
+
+        let output = sink
+            .printed()
+            .map(|markup| PlainTextFormatter {}.format(markup, false) + "\n")
+            .collect();
+
+        match result {
+            Ok((statements, result)) => {
+                let type_ = statements
+                    .last()
+                    .and_then(|statement| {
+                        statement.readable_expression_type(context.dimension_registry())
+                    })
+                    .map(|type_| type_.to_string());
+                let value = match result {
+                    InterpreterResult::Value(value) => Some(NumbatValue::new(value)),
+                    InterpreterResult::Continue => None,
+                };
+                NumbatResult {
+                    value,
+                    type_,
+                    output,
+                    ..NumbatResult::new(NumbatStatus::Ok, "")
+                }
+            }
+            Err(error) => NumbatResult {
+                output,
+                ..NumbatResult::from_error(context, error)
+            },
+        }
+    })
+}
+
+/// A function implemented by the host application. It receives the evaluated
+/// arguments, which are only valid during the call, and returns a new value
+/// (see `numbat_value_new_*`), whose ownership is transferred to Numbat. The
+/// value must be of the declared return type. Returning null signals an error.
+pub type NumbatNativeFunction = Option<
+    unsafe extern "C" fn(
+        user_data: *mut c_void,
+        args: *const *const NumbatValue,
+        num_args: usize,
+    ) -> *mut NumbatValue,
+>;
+
+struct NativeCallback {
+    function:
+        unsafe extern "C" fn(*mut c_void, *const *const NumbatValue, usize) -> *mut NumbatValue,
+    user_data: *mut c_void,
+}
+
+// SAFETY: native functions are never evaluated in parallel, so the callback is
+// only called from the thread that calls `numbat_interpret`. Whether the
+// context may be moved to another thread at all is up to the host application.
+unsafe impl Send for NativeCallback {}
+unsafe impl Sync for NativeCallback {}
+
+impl NativeCallback {
+    unsafe fn call(&self, args: &[NumbatValue]) -> *mut NumbatValue {
+        let arg_pointers: Vec<*const NumbatValue> =
+            args.iter().map(|arg| arg as *const _).collect();
+        (self.function)(self.user_data, arg_pointers.as_ptr(), arg_pointers.len())
+    }
+}
+
+/// Make `function` available to Numbat code under the given `name`. The
+/// `signature` declares its type like a foreign function in a Numbat module,
+/// without the name, e.g. `(radius: Length) -> Area`. `user_data` is passed
+/// to every call. It has to stay valid until the function is unregistered or
+/// the context is freed.
+#[no_mangle]
+pub unsafe extern "C" fn numbat_register_function(
+    context: *mut NumbatContext,
+    name: *const u8,
+    name_len: usize,
+    signature: *const u8,
+    signature_len: usize,
+    function: NumbatNativeFunction,
+    user_data: *mut c_void,
+) -> *mut NumbatResult {
+    let Some(name) = str_arg(name, name_len) else {
+        return NumbatResult::new(NumbatStatus::InvalidArgument, "name is not valid UTF-8")
+            .into_raw();
+    };
+    let Some(signature) = str_arg(signature, signature_len) else {
+        return NumbatResult::new(
+            NumbatStatus::InvalidArgument,
+            "signature is not valid UTF-8",
+        )
+        .into_raw();
+    };
+    let Some(function) = function else {
+        return NumbatResult::new(NumbatStatus::InvalidArgument, "function is null").into_raw();
+    };
+
+    let callback = NativeCallback {
+        function,
+        user_data,
+    };
+
+    with_context(context, |context| {
+        let result = context.register_native_function(name, signature, move |args| {
+            let args: Vec<NumbatValue> = args.into_iter().map(NumbatValue::new).collect();
+            let result = callback.call(&args);
+            if result.is_null() {
+                return Err("the function returned null".into());
+            }
+            Ok(Box::from_raw(result).value)
+        });
+
+        match result {
+            Ok(()) => NumbatResult::new(NumbatStatus::Ok, ""),
+            Err(error) => NumbatResult::from_error(context, error),
+        }
+    })
+}
+
+/// Remove a function that has been added with [`numbat_register_function`].
+/// Returns false if there is no such function.
+#[no_mangle]
+pub unsafe extern "C" fn numbat_unregister_function(
+    context: *mut NumbatContext,
+    name: *const u8,
+    name_len: usize,
+) -> bool {
+    let Some(name) = str_arg(name, name_len) else {
+        return false;
+    };
+    let Some(context) = context.as_mut() else {
+        return false;
+    };
+    if context.poisoned {
+        return false;
+    }
+
+    catch_unwind(AssertUnwindSafe(|| {
+        context.context.unregister_native_function(name)
+    }))
+    .unwrap_or_else(|_| {
+        context.poisoned = true;
+        false
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn numbat_result_free(result: *mut NumbatResult) {
+    if !result.is_null() {
+        drop(Box::from_raw(result));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn numbat_result_status(result: *const NumbatResult) -> NumbatStatus {
+    result
+        .as_ref()
+        .map_or(NumbatStatus::InvalidArgument, |result| result.status)
+}
+
+/// A short description of what went wrong, or an empty string.
+#[no_mangle]
+pub unsafe extern "C" fn numbat_result_error_message(result: *const NumbatResult) -> NumbatStr {
+    result.as_ref().map_or(NumbatStr::empty(), |result| {
+        NumbatStr::new(&result.error_message)
+    })
+}
+
+/// The value of the last expression, or null if there is none (e.g. for
+/// definitions, or in case of an error).
+#[no_mangle]
+pub unsafe extern "C" fn numbat_result_value(result: *const NumbatResult) -> *const NumbatValue {
+    result
+        .as_ref()
+        .and_then(|result| result.value.as_ref())
+        .map_or(std::ptr::null(), |value| value as *const _)
+}
+
+/// The type of the last expression (e.g. `Length / Time`), or an empty string.
+#[no_mangle]
+pub unsafe extern "C" fn numbat_result_type(result: *const NumbatResult) -> NumbatStr {
+    result
+        .as_ref()
+        .and_then(|result| result.type_.as_deref())
+        .map_or(NumbatStr::empty(), NumbatStr::new)
+}
+
+/// Output of `print` calls, one line per call.
+#[no_mangle]
+pub unsafe extern "C" fn numbat_result_output(result: *const NumbatResult) -> NumbatStr {
+    result
+        .as_ref()
+        .map_or(NumbatStr::empty(), |result| NumbatStr::new(&result.output))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn numbat_result_diagnostic_count(result: *const NumbatResult) -> usize {
+    result.as_ref().map_or(0, |result| result.diagnostics.len())
+}
+
+/// The diagnostic with the given index, or null if the index is out of range.
+#[no_mangle]
+pub unsafe extern "C" fn numbat_result_diagnostic(
+    result: *const NumbatResult,
+    index: usize,
+) -> *const NumbatDiagnostic {
+    result
+        .as_ref()
+        .and_then(|result| result.diagnostics.get(index))
+        .map_or(std::ptr::null(), |diagnostic| diagnostic as *const _)
+}
+
+/// The stable error code, e.g. `E0501`, or an empty string.
+#[no_mangle]
+pub unsafe extern "C" fn numbat_diagnostic_code(diagnostic: *const NumbatDiagnostic) -> NumbatStr {
+    diagnostic
+        .as_ref()
+        .and_then(|diagnostic| diagnostic.0.code)
+        .map_or(NumbatStr::empty(), |code| NumbatStr::new(code.as_str()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn numbat_diagnostic_message(
+    diagnostic: *const NumbatDiagnostic,
+) -> NumbatStr {
+    diagnostic
+        .as_ref()
+        .map_or(NumbatStr::empty(), |diagnostic| {
+            NumbatStr::new(&diagnostic.0.message)
+        })
+}
+
+/// Write the main location of the diagnostic to `span`. Returns false (and
+/// leaves `span` untouched) if the diagnostic has no location.
+#[no_mangle]
+pub unsafe extern "C" fn numbat_diagnostic_primary_span(
+    diagnostic: *const NumbatDiagnostic,
+    span: *mut NumbatSpan,
+) -> bool {
+    match (
+        diagnostic
+            .as_ref()
+            .and_then(|diagnostic| diagnostic.0.primary_span.as_ref()),
+        span.as_mut(),
+    ) {
+        (Some(primary_span), Some(span)) => {
+            *span = NumbatSpan::new(primary_span);
+            true
+        }
+        _ => false,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn numbat_diagnostic_secondary_span_count(
+    diagnostic: *const NumbatDiagnostic,
+) -> usize {
+    diagnostic
+        .as_ref()
+        .map_or(0, |diagnostic| diagnostic.0.secondary_spans.len())
+}
+
+/// Write the secondary location with the given index to `span`. Returns false
+/// if the index is out of range.
+#[no_mangle]
+pub unsafe extern "C" fn numbat_diagnostic_secondary_span(
+    diagnostic: *const NumbatDiagnostic,
+    index: usize,
+    span: *mut NumbatSpan,
+) -> bool {
+    match (
+        diagnostic
+            .as_ref()
+            .and_then(|diagnostic| diagnostic.0.secondary_spans.get(index)),
+        span.as_mut(),
+    ) {
+        (Some(secondary_span), Some(span)) => {
+            *span = NumbatSpan::new(secondary_span);
+            true
+        }
+        _ => false,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn numbat_diagnostic_note_count(
+    diagnostic: *const NumbatDiagnostic,
+) -> usize {
+    diagnostic
+        .as_ref()
+        .map_or(0, |diagnostic| diagnostic.0.notes.len())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn numbat_diagnostic_note(
+    diagnostic: *const NumbatDiagnostic,
+    index: usize,
+) -> NumbatStr {
+    diagnostic
+        .as_ref()
+        .and_then(|diagnostic| diagnostic.0.notes.get(index))
+        .map_or(NumbatStr::empty(), |note| NumbatStr::new(note))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn numbat_value_kind(value: *const NumbatValue) -> NumbatValueKind {
+    match value.as_ref().map(|value| &value.value) {
+        Some(Value::Quantity(_) | Value::Duration(_)) => NumbatValueKind::Quantity,
+        Some(Value::Boolean(_)) => NumbatValueKind::Boolean,
+        Some(Value::String(_)) => NumbatValueKind::String,
+        Some(Value::DateTime(_)) => NumbatValueKind::DateTime,
+        Some(Value::FunctionReference(_)) => NumbatValueKind::Function,
+        Some(Value::StructInstance(..)) => NumbatValueKind::Struct,
+        Some(Value::List(_) | Value::Sequence(_)) => NumbatValueKind::List,
+        Some(Value::FormatSpecifiers(_) | Value::DefaultArgument) | None => NumbatValueKind::Other,
+    }
+}
+
+/// The numerical value of a quantity (in its own unit), or NaN for all other
+/// kinds of values.
+#[no_mangle]
+pub unsafe extern "C" fn numbat_value_magnitude(value: *const NumbatValue) -> f64 {
+    match value.as_ref().map(|value| &value.value) {
+        Some(value) if value.is_quantity() => {
+            value.clone().unsafe_as_quantity().unsafe_value().to_f64()
+        }
+        _ => f64::NAN,
+    }
+}
+
+/// The unit of a quantity, e.g. `km/h`. Empty for scalars and all other
+/// kinds of values.
+#[no_mangle]
+pub unsafe extern "C" fn numbat_value_unit(value: *const NumbatValue) -> NumbatStr {
+    let Some(value) = value.as_ref() else {
+        return NumbatStr::empty();
+    };
+    guard(NumbatStr::empty(), || {
+        NumbatStr::new(value.unit.get_or_init(|| match &value.value {
+            value if value.is_quantity() => value.clone().unsafe_as_quantity().unit().to_string(),
+            _ => String::new(),
+        }))
+    })
+}
+
+/// The value as it would be printed by Numbat, e.g. `2.5 km/h`.
+#[no_mangle]
+pub unsafe extern "C" fn numbat_value_display(value: *const NumbatValue) -> NumbatStr {
+    let Some(value) = value.as_ref() else {
+        return NumbatStr::empty();
+    };
+    guard(NumbatStr::empty(), || {
+        NumbatStr::new(
+            value
+                .display
+                .get_or_init(|| PlainTextFormatter {}.format(&value.value.pretty_print(), false)),
+        )
+    })
+}
+
+/// The value of a boolean. False for all other kinds of values.
+#[no_mangle]
+pub unsafe extern "C" fn numbat_value_bool(value: *const NumbatValue) -> bool {
+    matches!(
+        value.as_ref().map(|value| &value.value),
+        Some(Value::Boolean(true))
+    )
+}
+
+/// The content of a string. Empty for all other kinds of values.
+#[no_mangle]
+pub unsafe extern "C" fn numbat_value_string(value: *const NumbatValue) -> NumbatStr {
+    match value.as_ref().map(|value| &value.value) {
+        Some(Value::String(s)) => NumbatStr::new(s),
+        _ => NumbatStr::empty(),
+    }
+}
+
+/// A dimensionless number.
+#[no_mangle]
+pub extern "C" fn numbat_value_new_scalar(magnitude: f64) -> *mut NumbatValue {
+    NumbatValue::new(Value::Quantity(Quantity::from_scalar(magnitude))).into_raw()
+}
+
+/// A quantity with the same unit as `prototype`, which must be a quantity.
+/// Returns null otherwise.
+#[no_mangle]
+pub unsafe extern "C" fn numbat_value_new_quantity_like(
+    prototype: *const NumbatValue,
+    magnitude: f64,
+) -> *mut NumbatValue {
+    match prototype.as_ref().map(|prototype| &prototype.value) {
+        Some(value) if value.is_quantity() => NumbatValue::new(Value::Quantity(Quantity::new_f64(
+            magnitude,
+            value.clone().unsafe_as_quantity().unit().clone(),
+        )))
+        .into_raw(),
+        _ => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn numbat_value_new_bool(value: bool) -> *mut NumbatValue {
+    NumbatValue::new(Value::Boolean(value)).into_raw()
+}
+
+/// Returns null if the string is not valid UTF-8.
+#[no_mangle]
+pub unsafe extern "C" fn numbat_value_new_string(ptr: *const u8, len: usize) -> *mut NumbatValue {
+    match str_arg(ptr, len) {
+        Some(s) => NumbatValue::new(Value::String(s.to_string())).into_raw(),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// Free a value that was created with `numbat_value_new_*`, but not returned
+/// from a native function.
+#[no_mangle]
+pub unsafe extern "C" fn numbat_value_free(value: *mut NumbatValue) {
+    if !value.is_null() {
+        drop(Box::from_raw(value));
+    }
+}
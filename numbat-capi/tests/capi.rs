@@ -0,0 +1,297 @@
+//! Drives the C API the way a C program would: through the exported functions
+//! and raw pointers only.
+
+use std::ffi::c_void;
+use std::ptr;
+
+use numbat_capi::*;
+
+unsafe fn to_str<'a>(s: NumbatStr) -> &'a str {
+    if s.ptr.is_null() {
+        return "";
+    }
+    std::str::from_utf8(std::slice::from_raw_parts(s.ptr, s.len)).unwrap()
+}
+
+unsafe fn interpret(context: *mut NumbatContext, code: &str) -> *mut NumbatResult {
+    numbat_interpret(context, code.as_ptr(), code.len())
+}
+
+unsafe fn register(
+    context: *mut NumbatContext,
+    name: &str,
+    signature: &str,
+    function: NumbatNativeFunction,
+    user_data: *mut c_void,
+) -> *mut NumbatResult {
+    numbat_register_function(
+        context,
+        name.as_ptr(),
+        name.len(),
+        signature.as_ptr(),
+        signature.len(),
+        function,
+        user_data,
+    )
+}
+
+#[test]
+fn evaluation() {
+    unsafe {
+        let context = numbat_context_new(true);
+        assert!(!context.is_null());
+
+        let result = interpret(context, "let distance = 2 km\ndistance / 4 s");
+        assert_eq!(numbat_result_status(result), NumbatStatus::Ok);
+        assert_eq!(to_str(numbat_result_type(result)), "Velocity");
+
+        let value = numbat_result_value(result);
+        assert_eq!(numbat_value_kind(value), NumbatValueKind::Quantity);
+        assert_eq!(numbat_value_magnitude(value), 0.5);
+        assert_eq!(to_str(numbat_value_unit(value)), "km/s");
+        assert_eq!(to_str(numbat_value_display(value)), "0.5 km/s");
+        numbat_result_free(result);
+
+        // Definitions are kept between calls
+        let result = interpret(context, "print(distance)\n\"a\" == \"a\"");
+        assert_eq!(to_str(numbat_result_output(result)), "2 km\n");
+        let value = numbat_result_value(result);
+        assert_eq!(numbat_value_kind(value), NumbatValueKind::Boolean);
+        assert!(numbat_value_bool(value));
+        numbat_result_free(result);
+
+        let result = interpret(context, "let x = 1");
+        assert_eq!(numbat_result_status(result), NumbatStatus::Ok);
+        assert!(numbat_result_value(result).is_null());
+        numbat_result_free(result);
+
+        numbat_context_free(context);
+    }
+}
+
+#[test]
+fn diagnostics() {
+    unsafe {
+        let context = numbat_context_new(true);
+
+        let result = interpret(context, "1 + meterr");
+        assert_eq!(numbat_result_status(result), NumbatStatus::Error);
+        assert!(numbat_result_value(result).is_null());
+        assert_eq!(numbat_result_diagnostic_count(result), 1);
+        assert!(numbat_result_diagnostic(result, 1).is_null());
+
+        let diagnostic = numbat_result_diagnostic(result, 0);
+        assert_eq!(to_str(numbat_diagnostic_code(diagnostic)), "E0501");
+        assert_eq!(
+            to_str(numbat_diagnostic_message(diagnostic)),
+            "while type checking"
+        );
+        assert_eq!(numbat_diagnostic_note_count(diagnostic), 1);
+        assert!(to_str(numbat_diagnostic_note(diagnostic, 0)).starts_with("Did you mean 'meter"));
+
+        let mut span = NumbatSpan {
+            file: NumbatStr {
+                ptr: ptr::null(),
+                len: 0,
+            },
+            byte_start: 0,
+            byte_end: 0,
+            line_start: 0,
+            column_start: 0,
+            line_end: 0,
+            column_end: 0,
+            label: NumbatStr {
+                ptr: ptr::null(),
+                len: 0,
+            },
+        };
+        assert!(numbat_diagnostic_primary_span(diagnostic, &mut span));
+        assert_eq!((span.byte_start, span.byte_end), (4, 10));
+        assert_eq!((span.line_start, span.column_start), (1, 5));
+        assert_eq!(to_str(span.label), "unknown identifier");
+        assert_eq!(numbat_diagnostic_secondary_span_count(diagnostic), 0);
+        assert!(!numbat_diagnostic_secondary_span(diagnostic, 0, &mut span));
+        numbat_result_free(result);
+
+        // Runtime errors have no location
+        let result = interpret(context, "1 / 0");
+        assert_eq!(numbat_result_status(result), NumbatStatus::Error);
+        let diagnostic = numbat_result_diagnostic(result, 0);
+        assert_eq!(to_str(numbat_diagnostic_code(diagnostic)), "E0801");
+        assert!(!numbat_diagnostic_primary_span(diagnostic, &mut span));
+        numbat_result_free(result);
+
+        numbat_context_free(context);
+    }
+}
+
+#[test]
+fn invalid_arguments() {
+    unsafe {
+        let result = interpret(ptr::null_mut(), "1");
+        assert_eq!(numbat_result_status(result), NumbatStatus::InvalidArgument);
+        numbat_result_free(result);
+
+        let context = numbat_context_new(false);
+        let invalid_utf8 = [0x31, 0xff];
+        let result = numbat_interpret(context, invalid_utf8.as_ptr(), invalid_utf8.len());
+        assert_eq!(numbat_result_status(result), NumbatStatus::InvalidArgument);
+        assert_eq!(
+            to_str(numbat_result_error_message(result)),
+            "code is not valid UTF-8"
+        );
+        numbat_result_free(result);
+        numbat_context_free(context);
+
+        // Accessors accept null pointers
+        assert_eq!(
+            numbat_result_status(ptr::null()),
+            NumbatStatus::InvalidArgument
+        );
+        assert_eq!(numbat_value_kind(ptr::null()), NumbatValueKind::Other);
+        assert!(numbat_value_magnitude(ptr::null()).is_nan());
+        assert_eq!(to_str(numbat_value_unit(ptr::null())), "");
+        numbat_result_free(ptr::null_mut());
+        numbat_context_free(ptr::null_mut());
+    }
+}
+
+unsafe extern "C" fn double_length(
+    user_data: *mut c_void,
+    args: *const *const NumbatValue,
+    num_args: usize,
+) -> *mut NumbatValue {
+    *(user_data as *mut usize) += 1;
+
+    let args = std::slice::from_raw_parts(args, num_args);
+    numbat_value_new_quantity_like(args[0], 2.0 * numbat_value_magnitude(args[0]))
+}
+
+unsafe extern "C" fn greet(
+    _: *mut c_void,
+    args: *const *const NumbatValue,
+    _: usize,
+) -> *mut NumbatValue {
+    let greeting = format!("Hello, {}!", to_str(numbat_value_string(*args)));
+    numbat_value_new_string(greeting.as_ptr(), greeting.len())
+}
+
+unsafe extern "C" fn answer(
+    _: *mut c_void,
+    _: *const *const NumbatValue,
+    _: usize,
+) -> *mut NumbatValue {
+    numbat_value_new_scalar(42.0)
+}
+
+unsafe extern "C" fn fail(
+    _: *mut c_void,
+    _: *const *const NumbatValue,
+    _: usize,
+) -> *mut NumbatValue {
+    ptr::null_mut()
+}
+
+#[test]
+fn native_functions() {
+    unsafe {
+        let context = numbat_context_new(true);
+        let mut num_calls: usize = 0;
+        let num_calls_ptr = &mut num_calls as *mut usize as *mut c_void;
+
+        for (name, signature, function) in [
+            ("double_length", "(x: Length) -> Length", double_length as _),
+            ("greet", "(name: String) -> String", greet as _),
+            ("answer", "() -> Scalar", answer as _),
+            ("fail", "(x: Scalar) -> Scalar", fail as _),
+        ] {
+            let result = register(context, name, signature, Some(function), num_calls_ptr);
+            assert_eq!(numbat_result_status(result), NumbatStatus::Ok);
+            numbat_result_free(result);
+        }
+
+        let result = interpret(context, "double_length(3 ft) -> inch");
+        let value = numbat_result_value(result);
+        assert_eq!(to_str(numbat_value_display(value)), "72 in");
+        numbat_result_free(result);
+
+        let result = interpret(context, "sum(map(double_length, [1 m, 2 m, 3 m]))");
+        let value = numbat_result_value(result);
+        assert_eq!(numbat_value_magnitude(value), 12.0);
+        numbat_result_free(result);
+        assert_eq!(num_calls, 4);
+
+        let result = interpret(context, "greet(\"C\")");
+        let value = numbat_result_value(result);
+        assert_eq!(to_str(numbat_value_string(value)), "Hello, C!");
+        numbat_result_free(result);
+
+        let result = interpret(context, "answer() + 1");
+        assert_eq!(numbat_value_magnitude(numbat_result_value(result)), 43.0);
+        numbat_result_free(result);
+
+        // Calls are type checked against the declared signature
+        let result = interpret(context, "double_length(2 s)");
+        assert_eq!(numbat_result_status(result), NumbatStatus::Error);
+        numbat_result_free(result);
+
+        // Returning null is a runtime error
+        let result = interpret(context, "fail(1)");
+        assert_eq!(numbat_result_status(result), NumbatStatus::Error);
+        assert_eq!(
+            to_str(numbat_result_error_message(result)),
+            "Native function 'fail' failed: the function returned null"
+        );
+        numbat_result_free(result);
+
+        // Invalid signatures
+        let result = register(context, "f", "(x) = x", Some(answer), ptr::null_mut());
+        assert_eq!(numbat_result_status(result), NumbatStatus::Error);
+        let diagnostic = numbat_result_diagnostic(result, 0);
+        assert_eq!(to_str(numbat_diagnostic_code(diagnostic)), "E0264");
+        numbat_result_free(result);
+
+        let result = register(context, "answer", "() -> Scalar", None, ptr::null_mut());
+        assert_eq!(numbat_result_status(result), NumbatStatus::InvalidArgument);
+        numbat_result_free(result);
+
+        // Unregistered functions are unknown to new code
+        let name = "greet";
+        assert!(numbat_unregister_function(
+            context,
+            name.as_ptr(),
+            name.len()
+        ));
+        assert!(!numbat_unregister_function(
+            context,
+            name.as_ptr(),
+            name.len()
+        ));
+        let result = interpret(context, "greet(\"C\")");
+        assert_eq!(numbat_result_status(result), NumbatStatus::Error);
+        numbat_result_free(result);
+
+        numbat_context_free(context);
+    }
+}
+
+#[test]
+fn header_declares_all_functions() {
+    let source = include_str!("../src/lib.rs");
+    let header = include_str!("../include/numbat.h");
+
+    let exported: Vec<&str> = source
+        .split("extern \"C\" fn ")
+        .skip(1)
+        .filter_map(|rest| rest.split('(').next())
+        .filter(|name| name.starts_with("numbat_"))
+        .collect();
+    assert!(exported.len() > 20);
+
+    for name in exported {
+        assert!(
+            header.contains(&format!("{name}(")),
+            "'{name}' is missing in include/numbat.h, regenerate it with cbindgen"
+        );
+    }
+}
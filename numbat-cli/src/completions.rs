@@ -0,0 +1,361 @@
+//! Shell completion scripts for `numbat completions <shell>`. The scripts are generated
+//! from the argument definitions, so new options are picked up automatically.
+
+use clap::builder::ValueHint;
+use clap::{Arg, Command, ValueEnum};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// An option (like `--color <WHEN>`) of a command
+struct Opt {
+    short: Option<char>,
+    long: Option<String>,
+    help: String,
+    takes_value: bool,
+    /// The allowed values, if they are restricted
+    values: Vec<String>,
+    path: bool,
+}
+
+impl Opt {
+    fn new(arg: &Arg) -> Self {
+        Self {
+            short: arg.get_short(),
+            long: arg.get_long().map(String::from),
+            help: help_line(arg.get_help()),
+            takes_value: arg.get_action().takes_values(),
+            values: possible_values(arg),
+            path: is_path(arg),
+        }
+    }
+
+    fn flags(&self) -> Vec<String> {
+        self.short
+            .map(|s| format!("-{s}"))
+            .into_iter()
+            .chain(self.long.iter().map(|l| format!("--{l}")))
+            .collect()
+    }
+}
+
+/// A (sub)command with its options and the values of its positional arguments
+struct Cmd {
+    name: String,
+    about: String,
+    opts: Vec<Opt>,
+    /// The allowed values for the positional arguments, if they are restricted
+    values: Vec<String>,
+    /// Whether the positional arguments are paths
+    files: bool,
+}
+
+impl Cmd {
+    fn new(cmd: &Command) -> Self {
+        let visible = || cmd.get_arguments().filter(|a| !a.is_hide_set());
+        let opts = visible()
+            .filter(|a| !a.is_positional())
+            .map(Opt::new)
+            .collect();
+        let positionals: Vec<_> = visible().filter(|a| a.is_positional()).collect();
+        Self {
+            name: cmd.get_name().to_string(),
+            about: help_line(cmd.get_about()),
+            opts,
+            values: positionals
+                .iter()
+                .flat_map(|a| possible_values(a))
+                .collect(),
+            files: positionals.iter().any(|a| is_path(a)),
+        }
+    }
+}
+
+fn help_line(help: Option<&clap::builder::StyledStr>) -> String {
+    help.map(|h| h.to_string())
+        .unwrap_or_default()
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches('.')
+        .to_string()
+}
+
+fn possible_values(arg: &Arg) -> Vec<String> {
+    arg.get_possible_values()
+        .iter()
+        .filter(|v| !v.is_hide_set())
+        .map(|v| v.get_name().to_string())
+        .collect()
+}
+
+fn is_path(arg: &Arg) -> bool {
+    matches!(
+        arg.get_value_hint(),
+        ValueHint::AnyPath | ValueHint::FilePath | ValueHint::DirPath
+    )
+}
+
+pub fn generate(shell: Shell, mut command: Command) -> String {
+    command.build();
+    let name = command.get_name().to_string();
+    let main = Cmd::new(&command);
+    let subcommands: Vec<_> = command
+        .get_subcommands()
+        .filter(|c| !c.is_hide_set() && c.get_name() != "help")
+        .map(Cmd::new)
+        .collect();
+
+    match shell {
+        Shell::Bash => bash(&name, &main, &subcommands),
+        Shell::Zsh => zsh(&name, &main, &subcommands),
+        Shell::Fish => fish(&name, &main, &subcommands),
+    }
+}
+
+fn bash_cases(cmd: &Cmd) -> String {
+    let mut cases = String::new();
+    for opt in cmd.opts.iter().filter(|o| o.takes_value) {
+        let action = if !opt.values.is_empty() {
+            format!(
+                "COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+                opt.values.join(" ")
+            )
+        } else if opt.path {
+            "COMPREPLY=($(compgen -f -- \"$cur\"))".to_string()
+        } else {
+            "COMPREPLY=()".to_string()
+        };
+        cases += &format!(
+            "                {})\n                    {action}\n                    return\n                    ;;\n",
+            opt.flags().join("|")
+        );
+    }
+    cases
+}
+
+fn bash_command(cmd: &Cmd, words: &[String]) -> String {
+    let opts = cmd.opts.iter().flat_map(Opt::flags).collect::<Vec<_>>();
+    let words = words.iter().chain(&cmd.values).cloned().collect::<Vec<_>>();
+    let cases = bash_cases(cmd);
+
+    let mut script = String::new();
+    if !cases.is_empty() {
+        script += &format!("            case \"$prev\" in\n{cases}            esac\n");
+    }
+    script += &format!(
+        "            if [[ \"$cur\" == -* ]]; then
+                COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))
+                return
+            fi
+            COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))
+",
+        opts.join(" "),
+        words.join(" "),
+    );
+    if cmd.files {
+        script += "            COMPREPLY+=($(compgen -f -- \"$cur\"))\n";
+    }
+    script
+}
+
+fn bash(name: &str, main: &Cmd, subcommands: &[Cmd]) -> String {
+    let names: Vec<_> = subcommands.iter().map(|c| c.name.clone()).collect();
+    let mut cases = String::new();
+    for sub in subcommands {
+        cases += &format!(
+            "        {})\n{}            ;;\n",
+            sub.name,
+            bash_command(sub, &[])
+        );
+    }
+    cases += &format!("        *)\n{}            ;;\n", bash_command(main, &names));
+
+    format!(
+        "_{name}() {{
+    local cur prev subcommand word
+    cur=\"${{COMP_WORDS[COMP_CWORD]}}\"
+    prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"
+    subcommand=\"\"
+    for word in \"${{COMP_WORDS[@]:1:COMP_CWORD-1}}\"; do
+        case \"$word\" in
+            {names})
+                subcommand=\"$word\"
+                break
+                ;;
+        esac
+    done
+
+    case \"$subcommand\" in
+{cases}    esac
+}}
+
+complete -o filenames -F _{name} {name}
+",
+        names = names.join("|"),
+    )
+}
+
+/// Escape text for a description in brackets, inside single quotes
+fn zsh_escape(text: &str) -> String {
+    text.replace('\'', "'\\''")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+        .replace(':', "\\:")
+}
+
+fn zsh_arguments(cmd: &Cmd) -> Vec<String> {
+    let mut arguments = vec![];
+    for opt in &cmd.opts {
+        let flags = opt.flags();
+        let value = if !opt.takes_value {
+            String::new()
+        } else if !opt.values.is_empty() {
+            format!(":value:({})", opt.values.join(" "))
+        } else if opt.path {
+            ":path:_files".to_string()
+        } else {
+            ":value: ".to_string()
+        };
+        let spec = format!("[{}]{value}", zsh_escape(&opt.help));
+        if flags.len() == 1 {
+            arguments.push(format!("'{}{spec}'", flags[0]));
+        } else {
+            arguments.push(format!(
+                "'({})'{{{}}}'{spec}'",
+                flags.join(" "),
+                flags.join(",")
+            ));
+        }
+    }
+    arguments
+}
+
+fn zsh(name: &str, main: &Cmd, subcommands: &[Cmd]) -> String {
+    let join = |arguments: Vec<String>, indent: &str| {
+        arguments
+            .iter()
+            .map(|a| format!("{indent}{a} \\\n"))
+            .collect::<String>()
+    };
+
+    let descriptions = subcommands
+        .iter()
+        .map(|c| format!("        '{}:{}'\n", c.name, zsh_escape(&c.about)))
+        .collect::<String>();
+
+    let mut cases = String::new();
+    for sub in subcommands {
+        let positional = if !sub.values.is_empty() {
+            format!("'1:value:({})'", sub.values.join(" "))
+        } else {
+            "'*:file:_files'".to_string()
+        };
+        cases += &format!(
+            "                {})\n                    _arguments \\\n{}                        {positional}\n                    ;;\n",
+            sub.name,
+            join(zsh_arguments(sub), "                        ")
+        );
+    }
+
+    format!(
+        "#compdef {name}
+
+_{name}_commands() {{
+    local -a commands
+    commands=(
+{descriptions}    )
+    _describe -t commands 'command' commands
+    _files
+}}
+
+_{name}() {{
+    local context state state_descr line
+    typeset -A opt_args
+
+    _arguments -C \\
+{arguments}        '1: :_{name}_commands' \\
+        '*::arg:->args'
+
+    case $state in
+        args)
+            case $line[1] in
+{cases}                *)
+                    _files
+                    ;;
+            esac
+            ;;
+    esac
+}}
+
+if [ \"$funcstack[1]\" = \"_{name}\" ]; then
+    _{name} \"$@\"
+else
+    compdef _{name} {name}
+fi
+",
+        arguments = join(zsh_arguments(main), "        "),
+    )
+}
+
+fn fish_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+fn fish_options(name: &str, cmd: &Cmd, condition: &str) -> String {
+    let mut lines = String::new();
+    for opt in &cmd.opts {
+        let mut line = format!("complete -c {name} -n '{condition}'");
+        if let Some(short) = opt.short {
+            line += &format!(" -s {short}");
+        }
+        if let Some(long) = &opt.long {
+            line += &format!(" -l {long}");
+        }
+        if opt.takes_value {
+            line += " -r";
+            if !opt.values.is_empty() {
+                line += &format!(" -f -a '{}'", opt.values.join(" "));
+            } else if opt.path {
+                line += " -F";
+            } else {
+                line += " -f";
+            }
+        }
+        lines += &format!("{line} -d '{}'\n", fish_escape(&opt.help));
+    }
+    lines
+}
+
+fn fish(name: &str, main: &Cmd, subcommands: &[Cmd]) -> String {
+    let names = subcommands
+        .iter()
+        .map(|c| c.name.as_str())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let at_top_level = format!("not __fish_seen_subcommand_from {names}");
+
+    let mut script = fish_options(name, main, &at_top_level);
+    for sub in subcommands {
+        script += &format!(
+            "complete -c {name} -n '{at_top_level}' -a {} -d '{}'\n",
+            sub.name,
+            fish_escape(&sub.about)
+        );
+    }
+    for sub in subcommands {
+        let condition = format!("__fish_seen_subcommand_from {}", sub.name);
+        script += &fish_options(name, sub, &condition);
+        if !sub.values.is_empty() {
+            script += &format!(
+                "complete -c {name} -n '{condition}' -f -a '{}'\n",
+                sub.values.join(" ")
+            );
+        }
+    }
+    script
+}
@@ -1,5 +1,6 @@
 use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Default, Debug, Clone, Copy, ValueEnum)]
 #[serde(rename_all = "kebab-case")]
@@ -28,15 +29,70 @@ pub enum ExchangeRateFetchingPolicy {
     /// Fetch exchange rates when a currency symbol is used
     OnFirstUse,
 
+    /// Start with the cached exchange rates right away, and replace them when
+    /// fresh ones have been fetched in the background
+    InBackground,
+
     /// Never fetch exchange rates
     Never,
 }
 
 #[derive(Serialize, Deserialize)]
-#[serde(rename_all = "kebab-case", default, deny_unknown_fields)]
-#[derive(Default)]
+#[serde(rename_all = "kebab-case", default)]
 pub struct ExchangeRateConfig {
     pub fetching_policy: ExchangeRateFetchingPolicy,
+    /// In seconds. Cached exchange rates are used if fetching takes longer.
+    pub timeout: u64,
+}
+
+impl Default for ExchangeRateConfig {
+    fn default() -> Self {
+        Self {
+            fetching_policy: ExchangeRateFetchingPolicy::default(),
+            timeout: 5,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct LocaleConfig {
+    pub decimal_mark: char,
+    pub grouping_separator: char,
+    pub decimal_comma_input: bool,
+    pub ascii_exponents: bool,
+}
+
+impl Default for LocaleConfig {
+    fn default() -> Self {
+        Self {
+            decimal_mark: '.',
+            grouping_separator: '_',
+            decimal_comma_input: false,
+            ascii_exponents: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct HistoryConfig {
+    /// Defaults to a `history` file in the data directory
+    pub file: Option<PathBuf>,
+    pub max_size: usize,
+    pub ignore_space: bool,
+    pub exclude_patterns: Vec<String>,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            file: None,
+            max_size: 1000,
+            ignore_space: true,
+            exclude_patterns: vec![],
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Default, Debug, Clone, ValueEnum)]
@@ -49,12 +105,14 @@ pub enum ColorMode {
 }
 
 #[derive(Serialize, Deserialize)]
-#[serde(rename_all = "kebab-case", default, deny_unknown_fields)]
+#[serde(rename_all = "kebab-case", default)]
 pub struct Config {
     pub intro_banner: IntroBanner,
     pub prompt: String,
     pub pretty_print: PrettyPrintMode,
     pub color: ColorMode,
+    pub long_units: bool,
+    pub max_unit_complexity: u32,
 
     #[serde(skip)]
     pub enter_repl: bool,
@@ -65,6 +123,8 @@ pub struct Config {
     #[serde(skip_serializing)]
     pub load_user_init: bool,
     pub exchange_rates: ExchangeRateConfig,
+    pub locale: LocaleConfig,
+    pub history: HistoryConfig,
 }
 
 impl Default for Config {
@@ -74,10 +134,126 @@ impl Default for Config {
             intro_banner: IntroBanner::default(),
             pretty_print: PrettyPrintMode::Auto,
             color: ColorMode::default(),
+            long_units: false,
+            max_unit_complexity: 0,
             load_prelude: true,
             load_user_init: true,
             exchange_rates: Default::default(),
+            locale: Default::default(),
+            history: Default::default(),
             enter_repl: true,
         }
     }
 }
+
+impl Config {
+    /// Parse a TOML configuration file. Unknown keys do not lead to an error,
+    /// they are returned (as dotted paths) so that they can be reported.
+    pub fn from_toml(contents: &str) -> Result<(Config, Vec<String>), toml::de::Error> {
+        let table: toml::Table = toml::from_str(contents)?;
+
+        let mut known =
+            toml::Table::try_from(Config::default()).expect("default config can be serialized");
+        for key in ["load-prelude", "load-user-init"] {
+            known.insert(key.to_owned(), toml::Value::Boolean(true));
+        }
+        if let Some(toml::Value::Table(history)) = known.get_mut("history") {
+            history.insert("file".to_owned(), toml::Value::String(String::new()));
+        }
+
+        let mut unknown_keys = vec![];
+        collect_unknown_keys(&table, &known, "", &mut unknown_keys);
+
+        let config = table.try_into()?;
+
+        Ok((config, unknown_keys))
+    }
+}
+
+fn collect_unknown_keys(
+    table: &toml::Table,
+    known: &toml::Table,
+    prefix: &str,
+    unknown_keys: &mut Vec<String>,
+) {
+    for (key, value) in table {
+        let path = format!("{prefix}{key}");
+        match (value, known.get(key)) {
+            (_, None) => unknown_keys.push(path),
+            (toml::Value::Table(inner), Some(toml::Value::Table(known_inner))) => {
+                collect_unknown_keys(inner, known_inner, &format!("{path}."), unknown_keys)
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_config() {
+        let (config, unknown_keys) = Config::from_toml(
+            r#"
+            prompt = "> "
+            pretty-print = "always"
+
+            [exchange-rates]
+            fetching-policy = "never"
+            timeout = 2
+
+            [locale]
+            decimal-mark = ","
+            grouping-separator = "."
+
+            [history]
+            file = "/tmp/numbat-history"
+            exclude-patterns = ["api_key"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.prompt, "> ");
+        assert_eq!(config.pretty_print, PrettyPrintMode::Always);
+        assert!(config.exchange_rates.fetching_policy == ExchangeRateFetchingPolicy::Never);
+        assert_eq!(config.exchange_rates.timeout, 2);
+        assert_eq!(config.locale.decimal_mark, ',');
+        assert_eq!(config.locale.grouping_separator, '.');
+        assert!(!config.locale.decimal_comma_input);
+        assert_eq!(
+            config.history.file,
+            Some(PathBuf::from("/tmp/numbat-history"))
+        );
+        assert_eq!(config.history.max_size, 1000);
+        assert_eq!(config.history.exclude_patterns, ["api_key"]);
+        assert!(config.load_prelude);
+        assert!(unknown_keys.is_empty());
+    }
+
+    #[test]
+    fn unknown_keys_are_reported() {
+        let (config, unknown_keys) = Config::from_toml(
+            r#"
+            prompt = "> "
+            precission = 3
+            load-prelude = false
+
+            [exchange-rates]
+            fetching-policy = "on-first-use"
+            refresh = true
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.prompt, "> ");
+        assert!(!config.load_prelude);
+        assert_eq!(unknown_keys, vec!["exchange-rates.refresh", "precission"]);
+    }
+
+    #[test]
+    fn invalid_values_are_errors() {
+        assert!(Config::from_toml("prompt = 3").is_err());
+        assert!(Config::from_toml(r#"color = "sometimes""#).is_err());
+    }
+}
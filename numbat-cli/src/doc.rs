@@ -0,0 +1,176 @@
+//! Output of `numbat doc`: a listing of all functions, units, dimensions, structs and
+//! formulas, as plain text or as JSON (for generating documentation).
+
+use numbat::introspection::{Catalog, Origin};
+use serde_json::{json, Value};
+
+fn origin_to_json(origin: &Origin) -> Value {
+    json!({
+        "module": origin.module,
+        "prelude": origin.prelude,
+    })
+}
+
+pub fn catalog_to_json(catalog: &Catalog) -> Value {
+    let functions = catalog
+        .functions
+        .iter()
+        .map(|f| {
+            json!({
+                "name": f.name,
+                "display_name": f.display_name,
+                "signature": f.signature,
+                "type_parameters": f.type_parameters.iter().map(|p| json!({
+                    "name": p.name,
+                    "bound": p.bound,
+                })).collect::<Vec<_>>(),
+                "parameters": f.parameters.iter().map(|p| json!({
+                    "name": p.name,
+                    "type": p.type_,
+                    "has_default": p.has_default,
+                    "variadic": p.variadic,
+                })).collect::<Vec<_>>(),
+                "return_type": f.return_type,
+                "description": f.description,
+                "url": f.url,
+                "deprecated": f.deprecated,
+                "origin": origin_to_json(&f.origin),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let units = catalog
+        .units
+        .iter()
+        .map(|u| {
+            json!({
+                "name": u.name,
+                "display_name": u.display_name,
+                "aliases": u.aliases,
+                "dimension": u.dimension,
+                "definition": u.definition,
+                "metric_prefixes": u.metric_prefixes,
+                "binary_prefixes": u.binary_prefixes,
+                "description": u.description,
+                "url": u.url,
+                "deprecated": u.deprecated,
+                "origin": origin_to_json(&u.origin),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let dimensions = catalog
+        .dimensions
+        .iter()
+        .map(|d| {
+            json!({
+                "name": d.name,
+                "base_representation": d.base_representation,
+                "origin": origin_to_json(&d.origin),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let structs = catalog
+        .structs
+        .iter()
+        .map(|s| {
+            json!({
+                "name": s.name,
+                "fields": s.fields.iter().map(|(name, type_)| json!({
+                    "name": name,
+                    "type": type_,
+                })).collect::<Vec<_>>(),
+                "origin": origin_to_json(&s.origin),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let formulas = catalog
+        .formulas
+        .iter()
+        .map(|f| {
+            json!({
+                "name": f.name,
+                "equation": f.equation,
+                "quantities": f.quantities.iter().map(|(name, type_)| json!({
+                    "name": name,
+                    "type": type_,
+                })).collect::<Vec<_>>(),
+                "functions": f.functions,
+                "origin": origin_to_json(&f.origin),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "functions": functions,
+        "units": units,
+        "dimensions": dimensions,
+        "structs": structs,
+        "formulas": formulas,
+    })
+}
+
+/// Where a definition comes from, like `(math::statistics)` or `(session)`
+fn origin_to_text(origin: &Origin) -> String {
+    match &origin.module {
+        Some(module) => format!("({module})"),
+        None if origin.prelude => "(prelude)".into(),
+        None => "(session)".into(),
+    }
+}
+
+pub fn catalog_to_text(catalog: &Catalog) -> String {
+    let mut output = String::new();
+
+    output += "Functions:\n";
+    for f in &catalog.functions {
+        output += &format!("  {}  {}\n", f.signature, origin_to_text(&f.origin));
+    }
+
+    output += "\nUnits:\n";
+    for u in &catalog.units {
+        output += &format!("  {}: {}", u.name, u.dimension);
+        if let Some(definition) = &u.definition {
+            output += &format!(" = {definition}");
+        }
+        output += &format!("  {}\n", origin_to_text(&u.origin));
+    }
+
+    output += "\nDimensions:\n";
+    for d in &catalog.dimensions {
+        output += &format!("  {}", d.name);
+        if d.base_representation != d.name {
+            output += &format!(" = {}", d.base_representation);
+        }
+        output += &format!("  {}\n", origin_to_text(&d.origin));
+    }
+
+    output += "\nStructs:\n";
+    for s in &catalog.structs {
+        let fields = s
+            .fields
+            .iter()
+            .map(|(name, type_)| format!("{name}: {type_}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        output += &format!(
+            "  struct {} {{ {fields} }}  {}\n",
+            s.name,
+            origin_to_text(&s.origin)
+        );
+    }
+
+    output += "\nFormulas:\n";
+    for f in &catalog.formulas {
+        output += &format!(
+            "  formula {}: {}  {}\n",
+            f.name,
+            f.equation,
+            origin_to_text(&f.origin)
+        );
+    }
+
+    output
+}
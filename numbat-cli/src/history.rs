@@ -0,0 +1,290 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Header of the history file format used by rustyline. Entries are written one
+/// per line, with backslashes and newlines escaped.
+const HEADER: &str = "#V2";
+
+/// Decides which lines are stored in the history.
+#[derive(Debug, Clone)]
+pub struct HistoryPolicy {
+    /// Maximum number of entries, older entries are dropped first
+    pub max_size: usize,
+    /// Do not store lines that start with a space
+    pub ignore_space: bool,
+    /// Do not store lines that contain one of these strings
+    pub exclude_patterns: Vec<String>,
+}
+
+impl HistoryPolicy {
+    pub fn accepts(&self, line: &str) -> bool {
+        let excluded = line.trim().is_empty()
+            || (self.ignore_space && line.starts_with(' '))
+            || self
+                .exclude_patterns
+                .iter()
+                .any(|pattern| !pattern.is_empty() && line.contains(pattern.as_str()));
+        !excluded
+    }
+}
+
+/// The persistent command history of the REPL, independent of the line editor.
+#[derive(Debug)]
+pub struct History {
+    policy: HistoryPolicy,
+    entries: Vec<String>,
+    /// Entries added in this session. They are appended to the current contents
+    /// of the file when saving, so that concurrent sessions don't lose entries.
+    new_entries: Vec<String>,
+}
+
+impl History {
+    pub fn new(policy: HistoryPolicy) -> Self {
+        History {
+            policy,
+            entries: vec![],
+            new_entries: vec![],
+        }
+    }
+
+    /// Load the history from `path`. A missing, unreadable or corrupt file leads
+    /// to an empty history.
+    pub fn load(path: &Path, policy: HistoryPolicy) -> Self {
+        let mut history = History::new(policy);
+        let entries = fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| parse(&contents))
+            .unwrap_or_default();
+        for entry in entries {
+            history.push(entry);
+        }
+        history
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// Add a line to the history. Returns false if the line is rejected by the
+    /// policy or repeats the previous entry.
+    pub fn add(&mut self, line: &str) -> bool {
+        if !self.policy.accepts(line) || !self.push(line.to_owned()) {
+            return false;
+        }
+
+        self.new_entries.push(line.to_owned());
+        if self.new_entries.len() > self.policy.max_size {
+            self.new_entries.remove(0);
+        }
+        true
+    }
+
+    fn push(&mut self, entry: String) -> bool {
+        if self.entries.last() == Some(&entry) {
+            return false;
+        }
+
+        self.entries.push(entry);
+        if self.entries.len() > self.policy.max_size {
+            let excess = self.entries.len() - self.policy.max_size;
+            self.entries.drain(..excess);
+        }
+        true
+    }
+
+    /// Append the entries of this session to the history file. The file is
+    /// replaced atomically, so concurrent sessions never see a partial file.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut merged = History::load(path, self.policy.clone());
+        for entry in &self.new_entries {
+            merged.push(entry.clone());
+        }
+
+        let temporary_path = path.with_extension(format!("tmp-{}", std::process::id()));
+        fs::write(&temporary_path, serialize(&merged.entries))?;
+        fs::rename(&temporary_path, path).map_err(|e| {
+            let _ = fs::remove_file(&temporary_path);
+            e
+        })
+    }
+}
+
+/// Parse the contents of a history file, or return `None` if it is corrupt. Files
+/// without a header (from older versions) contain one unescaped entry per line.
+fn parse(contents: &str) -> Option<Vec<String>> {
+    let mut lines = contents.lines().peekable();
+    if lines.peek() != Some(&HEADER) {
+        return Some(lines.map(str::to_owned).collect());
+    }
+
+    lines.skip(1).map(unescape).collect()
+}
+
+fn serialize(entries: &[String]) -> String {
+    let mut contents = format!("{HEADER}\n");
+    for entry in entries {
+        contents.push_str(&escape(entry));
+        contents.push('\n');
+    }
+    contents
+}
+
+/// Write an entry on a single line, with backslashes and newlines escaped
+pub fn escape(entry: &str) -> String {
+    entry.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+pub fn unescape(line: &str) -> Option<String> {
+    let mut entry = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next()? {
+                '\\' => entry.push('\\'),
+                'n' => entry.push('\n'),
+                _ => return None,
+            }
+        } else {
+            entry.push(c);
+        }
+    }
+    Some(entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn policy() -> HistoryPolicy {
+        HistoryPolicy {
+            max_size: 3,
+            ignore_space: true,
+            exclude_patterns: vec!["api_key".into()],
+        }
+    }
+
+    fn temporary_file(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("numbat-history-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn consecutive_duplicates_are_removed() {
+        let mut history = History::new(policy());
+        assert!(history.add("1 + 2"));
+        assert!(!history.add("1 + 2"));
+        assert!(history.add("3 m"));
+        assert!(history.add("1 + 2"));
+        assert_eq!(history.entries(), ["1 + 2", "3 m", "1 + 2"]);
+    }
+
+    #[test]
+    fn excluded_lines_are_not_stored() {
+        let mut history = History::new(policy());
+        assert!(!history.add(" let secret = 42"));
+        assert!(!history.add("let api_key = \"abc123\""));
+        assert!(!history.add("   "));
+        assert!(history.add("api key"));
+        assert_eq!(history.entries(), ["api key"]);
+
+        let mut history = History::new(HistoryPolicy {
+            ignore_space: false,
+            exclude_patterns: vec!["".into()],
+            ..policy()
+        });
+        assert!(history.add(" let x = 1"));
+    }
+
+    #[test]
+    fn history_is_truncated_at_max_size() {
+        let path = temporary_file("truncation");
+        let mut history = History::new(policy());
+        for line in ["1", "2", "3", "4", "5"] {
+            history.add(line);
+        }
+        assert_eq!(history.entries(), ["3", "4", "5"]);
+
+        history.save(&path).unwrap();
+        let loaded = History::load(&path, policy());
+        assert_eq!(loaded.entries(), ["3", "4", "5"]);
+
+        // Loading also truncates, for example if the maximum size was reduced
+        let loaded = History::load(
+            &path,
+            HistoryPolicy {
+                max_size: 1,
+                ..policy()
+            },
+        );
+        assert_eq!(loaded.entries(), ["5"]);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn saving_and_loading() {
+        let path = temporary_file("roundtrip");
+        assert!(History::load(&path, policy()).entries().is_empty());
+
+        let mut history = History::new(policy());
+        history.add("fn f(x) =\n  x \\ 2");
+        history.add("f(3)");
+        history.save(&path).unwrap();
+
+        let loaded = History::load(&path, policy());
+        assert_eq!(loaded.entries(), ["fn f(x) =\n  x \\ 2", "f(3)"]);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "#V2\nfn f(x) =\\n  x \\\\ 2\nf(3)\n"
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn concurrent_sessions_are_merged() {
+        let path = temporary_file("concurrent");
+        let mut first = History::load(&path, policy());
+        let mut second = History::load(&path, policy());
+
+        first.add("1");
+        second.add("2");
+        first.save(&path).unwrap();
+        second.save(&path).unwrap();
+
+        assert_eq!(History::load(&path, policy()).entries(), ["1", "2"]);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn corrupt_files_lead_to_empty_history() {
+        let path = temporary_file("corrupt");
+
+        fs::write(&path, "#V2\n1 + 2\ninvalid \\escape\n").unwrap();
+        let mut history = History::load(&path, policy());
+        assert!(history.entries().is_empty());
+
+        fs::write(&path, [0xff, 0xfe, b'\n', 0x80]).unwrap();
+        assert!(History::load(&path, policy()).entries().is_empty());
+
+        // The corrupt file is replaced when saving
+        history.add("3 m");
+        history.save(&path).unwrap();
+        assert_eq!(History::load(&path, policy()).entries(), ["3 m"]);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn files_without_header() {
+        assert_eq!(
+            parse("1 + 2\nlet x = \\n\n").unwrap(),
+            ["1 + 2", "let x = \\n"]
+        );
+    }
+}
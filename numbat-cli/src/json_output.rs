@@ -0,0 +1,73 @@
+use numbat::diagnostic::{
+    Applicability, Diagnostic, DiagnosticSpan, Severity, StructuredDiagnostic, StructuredSuggestion,
+};
+use numbat::markup::Markup;
+use numbat::output::OutputSink;
+
+use codespan_reporting::files::SimpleFiles;
+use serde_json::{json, Value};
+
+fn severity_name(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Bug => "bug",
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Note => "note",
+        Severity::Help => "help",
+    }
+}
+
+fn applicability_name(applicability: Applicability) -> &'static str {
+    match applicability {
+        Applicability::MachineApplicable => "machine-applicable",
+        Applicability::MaybeIncorrect => "maybe-incorrect",
+    }
+}
+
+fn span_to_json(span: &DiagnosticSpan) -> Value {
+    json!({
+        "file": span.file,
+        "byte_start": span.byte_start,
+        "byte_end": span.byte_end,
+        "line_start": span.line_start,
+        "column_start": span.column_start,
+        "line_end": span.line_end,
+        "column_end": span.column_end,
+        "label": span.label,
+    })
+}
+
+fn suggestion_to_json(suggestion: &StructuredSuggestion) -> Value {
+    json!({
+        "span": span_to_json(&suggestion.span),
+        "replacement": suggestion.replacement,
+        "applicability": applicability_name(suggestion.applicability),
+    })
+}
+
+pub fn diagnostic_to_json(diagnostic: &StructuredDiagnostic) -> Value {
+    json!({
+        "code": diagnostic.code.map(|c| c.as_str()),
+        "severity": severity_name(diagnostic.severity),
+        "message": diagnostic.message,
+        "primary_span": diagnostic.primary_span.as_ref().map(span_to_json),
+        "secondary_spans": diagnostic.secondary_spans.iter().map(span_to_json).collect::<Vec<_>>(),
+        "notes": diagnostic.notes,
+        "suggestions": diagnostic.suggestions.iter().map(suggestion_to_json).collect::<Vec<_>>(),
+    })
+}
+
+/// Used for `--error-format json`. Printed output goes to stdout as usual,
+/// diagnostics are written to stderr as one JSON object per line.
+pub struct JsonSink;
+
+impl OutputSink for JsonSink {
+    fn print(&mut self, markup: &Markup) {
+        println!("{markup}");
+    }
+
+    fn diagnostic(&mut self, diagnostic: &Diagnostic, files: &SimpleFiles<String, String>) {
+        let diagnostic = StructuredDiagnostic::new(diagnostic, files);
+        eprintln!("{}", diagnostic_to_json(&diagnostic));
+    }
+}
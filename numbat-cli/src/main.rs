@@ -1,26 +1,40 @@
 mod ansi_formatter;
 mod completer;
+mod completions;
 mod config;
+mod doc;
 mod highlighter;
+mod history;
+mod json_output;
+mod recovery;
+mod watch;
 
 use ansi_formatter::ansi_format;
 use colored::control::SHOULD_COLORIZE;
+use colored::Colorize;
 use completer::NumbatCompleter;
+use completions::Shell;
 use config::{ColorMode, Config, ExchangeRateFetchingPolicy, IntroBanner, PrettyPrintMode};
 use highlighter::NumbatHighlighter;
+use history::{History, HistoryPolicy};
+use json_output::JsonSink;
+use recovery::{contain_panics, Journal};
 
 use itertools::Itertools;
-use numbat::diagnostic::ErrorDiagnostic;
+use numbat::build_info::BuildInfo;
+use numbat::diagnostic::{apply_suggestions, Applicability, ErrorDiagnostic};
+use numbat::file_system::OsFileSystem;
 use numbat::help::help_markup;
+use numbat::locale::Locale;
 use numbat::markup as m;
 use numbat::module_importer::{BuiltinModuleImporter, ChainedImporter, FileSystemImporter};
+use numbat::output::{BufferingSink, OutputEvent};
 use numbat::pretty_print::PrettyPrint;
 use numbat::resolver::CodeSource;
 use numbat::{Context, NumbatError};
-use numbat::{InterpreterSettings, NameResolutionError};
 
 use anyhow::{bail, Context as AnyhowContext, Result};
-use clap::Parser;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
 use rustyline::config::Configurer;
 use rustyline::{
     error::ReadlineError, history::DefaultHistory, Completer, Editor, Helper, Hinter, Validator,
@@ -28,8 +42,9 @@ use rustyline::{
 use rustyline::{EventHandler, Highlighter, KeyCode, KeyEvent, Modifiers};
 
 use std::io::IsTerminal;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use std::{fs, thread};
 
 #[derive(Debug, PartialEq, Eq)]
@@ -40,12 +55,44 @@ pub enum ExitStatus {
 
 type ControlFlow = std::ops::ControlFlow<ExitStatus>;
 
+/// How many inputs can be reverted with `undo` in an interactive session. Each
+/// step keeps a copy of the environment.
+const UNDO_LIMIT: usize = 20;
+
+/// Fixes can enable further fixes (e.g. a parse error hides all warnings), so
+/// `--fix` checks the code repeatedly, up to this many times.
+const MAX_FIX_PASSES: usize = 5;
+
 #[derive(Parser, Debug)]
-#[command(version, about, name("numbat"), max_term_width = 90)]
+#[command(
+    version,
+    about,
+    name("numbat"),
+    max_term_width = 90,
+    disable_version_flag = true
+)]
 struct Args {
-    /// Path to source file with Numbat code. If none is given, an interactive
-    /// session is started.
-    file: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Paths to source files with Numbat code. The files are evaluated in order and
+    /// share definitions. If none is given, an interactive session is started.
+    #[arg(value_name = "FILE")]
+    files: Vec<PathBuf>,
+
+    /// Keep evaluating the remaining files and expressions after an error.
+    #[arg(long)]
+    continue_on_error: bool,
+
+    /// Evaluate the file again whenever it (or a module that it uses) changes, and
+    /// print the results that changed.
+    #[arg(long, conflicts_with_all = ["expression", "inspect_interactively"])]
+    watch: bool,
+
+    /// Apply the suggested fixes that are known to be correct to the files before
+    /// evaluating them. The original files are kept with a '.bak' suffix.
+    #[arg(long, conflicts_with = "watch")]
+    fix: bool,
 
     /// Evaluate a single expression. Can be specified multiple times to evaluate several expressions in sequence.
     #[arg(
@@ -64,6 +111,10 @@ struct Args {
     #[arg(long, hide_short_help = true)]
     no_config: bool,
 
+    /// Load the configuration from the given file instead of the default location.
+    #[arg(long, value_name = "PATH", conflicts_with = "no_config")]
+    config: Option<PathBuf>,
+
     /// Do not load the prelude with predefined physical dimensions and units. This implies --no-init.
     #[arg(short = 'N', long, hide_short_help = true)]
     no_prelude: bool,
@@ -80,17 +131,75 @@ struct Args {
     #[arg(long, value_name = "WHEN")]
     color: Option<ColorMode>,
 
+    /// Write the units of results out in full, e.g. "3 kilometres" instead of "3 km".
+    #[arg(long)]
+    long_units: bool,
+
     /// What kind of intro banner to show (if any).
     #[arg(long, value_name = "MODE")]
     intro_banner: Option<IntroBanner>,
 
+    /// How to report errors: as human-readable diagnostics, or as one JSON object per line.
+    #[arg(long, value_name = "FORMAT", default_value = "human")]
+    error_format: ErrorFormat,
+
     /// Generate a default configuration file
     #[arg(long, hide_short_help = true)]
     generate_config: bool,
 
+    /// Measure the number of calls and the time spent in each function, and print a
+    /// table after the file or expressions have been evaluated.
+    #[arg(long)]
+    profile: bool,
+
+    /// Warn if the estimated relative rounding error of a sum (e.g. in 'sum' or 'mean')
+    /// exceeds the given threshold.
+    #[arg(long, value_name = "THRESHOLD", hide_short_help = true)]
+    warn_summation_error: Option<f64>,
+
+    /// Print version information.
+    #[arg(short = 'V', long)]
+    version: bool,
+
+    /// Together with --version: also print the enabled features and the state of the
+    /// exchange rates.
+    #[arg(long, requires = "version", hide_short_help = true)]
+    verbose: bool,
+
     /// Turn on debug mode and print disassembler output (hidden, mainly for development)
     #[arg(long, short, hide = true)]
     debug: bool,
+
+    /// Arguments for the script, available through 'args()' and 'arg(n)'.
+    #[arg(last = true, value_name = "ARGS")]
+    script_arguments: Vec<String>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// List all functions, units, dimensions and structs, e.g. for generating
+    /// documentation.
+    Doc {
+        /// Print the listing as JSON.
+        #[arg(long)]
+        json: bool,
+
+        /// Files whose definitions are listed in addition to the prelude.
+        #[arg(value_name = "FILE")]
+        files: Vec<PathBuf>,
+    },
+
+    /// Print a completion script for the given shell.
+    Completions {
+        #[arg(value_name = "SHELL")]
+        shell: Shell,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ErrorFormat {
+    Human,
+    Json,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -117,24 +226,93 @@ struct NumbatHelper {
     highlighter: NumbatHighlighter,
 }
 
+/// Everything that is needed to set up a new [`Context`].
+struct ContextOptions {
+    debug: bool,
+    script_arguments: Vec<String>,
+    error_format: ErrorFormat,
+    locale: Locale,
+    long_unit_names: bool,
+    max_unit_complexity: Option<u32>,
+    summation_error_threshold: Option<f64>,
+}
+
+impl ContextOptions {
+    fn create_context(&self) -> Context {
+        let mut fs_importer = FileSystemImporter::default();
+        for path in Cli::get_modules_paths() {
+            fs_importer.add_path(path);
+        }
+
+        let importer = ChainedImporter::new(
+            Box::new(fs_importer),
+            Box::<BuiltinModuleImporter>::default(),
+        );
+
+        let mut context = Context::new(importer);
+        context.set_debug(self.debug);
+        context.set_script_arguments(self.script_arguments.clone());
+        if self.error_format == ErrorFormat::Json {
+            context.set_output_sink(JsonSink);
+        }
+
+        context.set_locale(self.locale.clone());
+        context.set_long_unit_names(self.long_unit_names);
+        context.set_max_unit_complexity(self.max_unit_complexity);
+        context.set_summation_error_threshold(self.summation_error_threshold);
+
+        context.set_terminal_width(
+            terminal_size::terminal_size().map(|(terminal_size::Width(w), _)| w as usize),
+        );
+
+        context
+    }
+}
+
 struct Cli {
     config: Config,
+    context_options: ContextOptions,
     context: Arc<Mutex<Context>>,
-    file: Option<PathBuf>,
+    command: Option<Command>,
+    files: Vec<PathBuf>,
     expression: Option<Vec<String>>,
+    continue_on_error: bool,
+    watch: bool,
+    fix: bool,
+    profile: bool,
+    verbose: bool,
 }
 
 impl Cli {
     fn new(args: Args) -> Result<Self> {
-        let user_config_path = Self::get_config_path().join("config.toml");
+        let user_config_path = args
+            .config
+            .clone()
+            .unwrap_or_else(|| Self::get_config_path().join("config.toml"));
+
+        let contents = if args.no_config {
+            None
+        } else if args.config.is_some() {
+            Some(fs::read_to_string(&user_config_path).context(format!(
+                "Could not read configuration file '{}'",
+                user_config_path.to_string_lossy()
+            ))?)
+        } else {
+            fs::read_to_string(&user_config_path).ok()
+        };
 
-        let mut config = if args.no_config {
-            Config::default()
-        } else if let Ok(contents) = fs::read_to_string(&user_config_path) {
-            toml::from_str(&contents).context(format!(
+        let mut config = if let Some(contents) = contents {
+            let (config, unknown_keys) = Config::from_toml(&contents).context(format!(
                 "Error while loading {}",
                 user_config_path.to_string_lossy()
-            ))?
+            ))?;
+            for key in unknown_keys {
+                eprintln!(
+                    "Warning: ignoring unknown key '{key}' in {}",
+                    user_config_path.to_string_lossy()
+                );
+            }
+            config
         } else {
             Config::default()
         };
@@ -145,42 +323,43 @@ impl Cli {
         config.intro_banner = args.intro_banner.unwrap_or(config.intro_banner);
         config.pretty_print = args.pretty_print.unwrap_or(config.pretty_print);
         config.color = args.color.unwrap_or(config.color);
-
-        config.enter_repl =
-            (args.file.is_none() && args.expression.is_none()) || args.inspect_interactively;
-
-        let mut fs_importer = FileSystemImporter::default();
-        for path in Self::get_modules_paths() {
-            fs_importer.add_path(path);
-        }
-
-        let importer = ChainedImporter::new(
-            Box::new(fs_importer),
-            Box::<BuiltinModuleImporter>::default(),
-        );
-
-        let mut context = Context::new(importer);
-        context.set_debug(args.debug);
-
-        context.set_terminal_width(
-            terminal_size::terminal_size().map(|(terminal_size::Width(w), _)| w as usize),
-        );
+        config.long_units |= args.long_units;
+
+        config.enter_repl = args.command.is_none()
+            && ((args.files.is_empty() && args.expression.is_none()) || args.inspect_interactively);
+
+        let locale = Locale::new(config.locale.decimal_mark, config.locale.grouping_separator)
+            .context("Invalid [locale] configuration")?
+            .with_decimal_comma_input(config.locale.decimal_comma_input)
+            .with_ascii_exponents(config.locale.ascii_exponents);
+
+        let context_options = ContextOptions {
+            debug: args.debug,
+            script_arguments: args.script_arguments,
+            error_format: args.error_format,
+            locale,
+            long_unit_names: config.long_units,
+            max_unit_complexity: Some(config.max_unit_complexity).filter(|&max| max > 0),
+            summation_error_threshold: args.warn_summation_error,
+        };
 
         Ok(Self {
-            context: Arc::new(Mutex::new(context)),
+            context: Arc::new(Mutex::new(context_options.create_context())),
+            context_options,
             config,
-            file: args.file,
+            command: args.command,
+            files: args.files,
             expression: args.expression,
+            continue_on_error: args.continue_on_error,
+            watch: args.watch,
+            fix: args.fix,
+            profile: args.profile,
+            verbose: args.verbose,
         })
     }
 
-    fn run(&mut self) -> Result<()> {
-        match self.config.color {
-            ColorMode::Never => SHOULD_COLORIZE.set_override(false),
-            ColorMode::Always => SHOULD_COLORIZE.set_override(true),
-            ColorMode::Auto => (), // Let colored itself decide whether coloring should occur or not
-        }
-
+    /// Load the prelude and the user init file, depending on the configuration.
+    fn load_startup_code(&mut self) -> Result<()> {
         if self.config.load_prelude {
             let result = self.parse_and_evaluate(
                 "use prelude",
@@ -199,12 +378,20 @@ impl Cli {
             if let Ok(user_init_code) = fs::read_to_string(&user_init_path) {
                 let result = self.parse_and_evaluate(
                     &user_init_code,
-                    CodeSource::File(user_init_path),
+                    CodeSource::File(user_init_path.clone()),
                     ExecutionMode::Normal,
                     PrettyPrintMode::Never,
                 );
                 if result.is_break() {
-                    bail!("Interpreter error in user initialization code")
+                    // A broken init file should not lock the user out of the REPL
+                    if self.config.enter_repl {
+                        eprintln!(
+                            "Warning: errors while loading '{}', continuing without it",
+                            user_init_path.to_string_lossy()
+                        );
+                    } else {
+                        bail!("Interpreter error in user initialization code")
+                    }
                 }
             }
         }
@@ -212,15 +399,48 @@ impl Cli {
         if self.config.load_prelude
             && self.config.exchange_rates.fetching_policy != ExchangeRateFetchingPolicy::Never
         {
+            numbat::Context::set_exchange_rates_timeout(Duration::from_secs(
+                self.config.exchange_rates.timeout,
+            ));
+            numbat::Context::set_exchange_rates_cache_file(Self::get_exchange_rates_cache_path());
             self.context
                 .lock()
                 .unwrap()
                 .load_currency_module_on_demand(true);
         }
 
+        Ok(())
+    }
+
+    fn run(&mut self) -> Result<()> {
+        match self.config.color {
+            ColorMode::Never => SHOULD_COLORIZE.set_override(false),
+            ColorMode::Always => SHOULD_COLORIZE.set_override(true),
+            ColorMode::Auto => (), // Let colored itself decide whether coloring should occur or not
+        }
+
+        if self.verbose {
+            self.print_version();
+            return Ok(());
+        }
+
+        if let Some(Command::Doc { json, files }) = self.command.clone() {
+            return self.doc(json, &files);
+        }
+
+        if self.watch {
+            return self.watch();
+        }
+
+        self.load_startup_code()?;
+
+        if self.profile {
+            self.context.lock().unwrap().set_profiling(true);
+        }
+
         let mut code_and_source = Vec::new();
 
-        if let Some(ref path) = self.file {
+        for path in &self.files {
             code_and_source.push((
                 (fs::read_to_string(path).context(format!(
                     "Could not load source file '{}'",
@@ -228,7 +448,7 @@ impl Cli {
                 ))?),
                 CodeSource::File(path.clone()),
             ));
-        };
+        }
 
         if let Some(expressions) = &self.expression {
             code_and_source.push((expressions.iter().join("\n"), CodeSource::Text));
@@ -237,7 +457,12 @@ impl Cli {
         let mut run_result = Ok(());
 
         if !code_and_source.is_empty() {
-            for (code, code_source) in code_and_source {
+            let mut failed = false;
+            for (mut code, code_source) in code_and_source {
+                if let (true, CodeSource::File(path)) = (self.fix, &code_source) {
+                    code = self.fix(path, code)?;
+                }
+
                 let result = self.parse_and_evaluate(
                     &code,
                     code_source,
@@ -245,45 +470,171 @@ impl Cli {
                     self.config.pretty_print,
                 );
 
-                let result_status = match result {
-                    std::ops::ControlFlow::Continue(()) => Ok(()),
-                    std::ops::ControlFlow::Break(_) => {
-                        bail!("Interpreter stopped")
+                if result.is_break() {
+                    failed = true;
+                    if !self.continue_on_error {
+                        break;
                     }
-                };
+                }
+            }
 
-                run_result = run_result.and(result_status);
+            self.print_profile();
+            if failed {
+                bail!("Interpreter stopped")
             }
         }
 
         if self.config.enter_repl {
-            let mut currency_fetch_thread = if self.config.load_prelude
-                && self.config.exchange_rates.fetching_policy
-                    == ExchangeRateFetchingPolicy::OnStartup
-            {
-                Some(thread::spawn(move || {
-                    numbat::Context::prefetch_exchange_rates();
-                }))
-            } else {
-                None
-            };
+            let fetching_policy = &self.config.exchange_rates.fetching_policy;
+            let mut currency_fetch_thread = (self.config.load_prelude
+                && *fetching_policy == ExchangeRateFetchingPolicy::OnStartup)
+                .then(|| {
+                    thread::spawn(move || {
+                        numbat::Context::prefetch_exchange_rates();
+                    })
+                });
+            // The cached exchange rates are used until fresh ones have been fetched
+            let mut currency_refresh_thread = (self.config.load_prelude
+                && *fetching_policy == ExchangeRateFetchingPolicy::InBackground)
+                .then(numbat::Context::refresh_exchange_rates_in_background);
 
             let repl_result = self.repl();
             if let Some(thread) = currency_fetch_thread.take() {
                 let _ = thread.join();
             }
+            if let Some(thread) = currency_refresh_thread.take() {
+                let _ = thread.join();
+            }
             run_result = run_result.and(repl_result);
         }
 
         run_result
     }
 
+    /// Apply all machine-applicable suggestions to the code of a file (`--fix`),
+    /// and return the fixed code. The file is only written if something changed.
+    fn fix(&mut self, path: &Path, original: String) -> Result<String> {
+        let mut code = original.clone();
+        for _ in 0..MAX_FIX_PASSES {
+            let suggestions = {
+                let mut ctx = self.context.lock().unwrap();
+                let (result, mut diagnostics) =
+                    ctx.check_with_warnings(&code, CodeSource::File(path.into()));
+                if let Err(e) = result {
+                    diagnostics.extend(ctx.diagnostics(&e));
+                }
+
+                diagnostics
+                    .into_iter()
+                    .flat_map(|diagnostic| diagnostic.suggestions)
+                    .filter(|suggestion| {
+                        suggestion.applicability == Applicability::MachineApplicable
+                            && matches!(
+                                ctx.resolver().get_code_source(suggestion.file_id),
+                                CodeSource::File(file) if file == path
+                            )
+                    })
+                    .collect::<Vec<_>>()
+            };
+
+            let fixed = apply_suggestions(&code, &suggestions);
+            if fixed == code {
+                break;
+            }
+            code = fixed;
+        }
+
+        if code != original {
+            let mut backup = path.as_os_str().to_owned();
+            backup.push(".bak");
+            let backup = PathBuf::from(backup);
+
+            fs::write(&backup, &original).context(format!(
+                "Could not write backup file '{}'",
+                backup.to_string_lossy()
+            ))?;
+            fs::write(path, &code).context(format!(
+                "Could not write fixed source file '{}'",
+                path.to_string_lossy()
+            ))?;
+            eprintln!(
+                "Fixed '{}', the original file has been saved as '{}'",
+                path.to_string_lossy(),
+                backup.to_string_lossy()
+            );
+        }
+
+        Ok(code)
+    }
+
+    /// Print all definitions of the prelude and of the given files (`numbat doc`).
+    fn doc(&mut self, json: bool, files: &[PathBuf]) -> Result<()> {
+        self.load_startup_code()?;
+
+        for path in files {
+            let code = fs::read_to_string(path).context(format!(
+                "Could not load source file '{}'",
+                path.to_string_lossy()
+            ))?;
+            let result = self.parse_and_evaluate(
+                &code,
+                CodeSource::File(path.clone()),
+                ExecutionMode::Normal,
+                PrettyPrintMode::Never,
+            );
+            if result.is_break() {
+                bail!("Interpreter stopped")
+            }
+        }
+
+        let catalog = self.context.lock().unwrap().introspect();
+        if json {
+            println!("{:#}", doc::catalog_to_json(&catalog));
+        } else {
+            print!("{}", doc::catalog_to_text(&catalog));
+        }
+
+        Ok(())
+    }
+
+    /// Print the time spent in each function, if profiling is enabled.
+    fn print_profile(&self) {
+        if let Some(profile) = self.context.lock().unwrap().profile() {
+            eprint!("{}", ansi_format(&profile.to_markup(), false));
+        }
+    }
+
+    fn print_version(&self) {
+        println!("numbat {}", env!("CARGO_PKG_VERSION"));
+        print!("{}", ansi_format(&BuildInfo::collect().to_markup(), false));
+        let policy = if !self.config.load_prelude {
+            "never (no prelude)"
+        } else {
+            match self.config.exchange_rates.fetching_policy {
+                ExchangeRateFetchingPolicy::OnStartup => "on startup",
+                ExchangeRateFetchingPolicy::OnFirstUse => "on first use",
+                ExchangeRateFetchingPolicy::InBackground => "in background",
+                ExchangeRateFetchingPolicy::Never => "never",
+            }
+        };
+        println!("{:<16}{policy}", "fetching:");
+    }
+
     fn repl(&mut self) -> Result<()> {
         let interactive = std::io::stdin().is_terminal();
         let history_path = self.get_history_path()?;
+        let history_config = &self.config.history;
+        let mut history = History::load(
+            &history_path,
+            HistoryPolicy {
+                max_size: history_config.max_size,
+                ignore_space: history_config.ignore_space,
+                exclude_patterns: history_config.exclude_patterns.clone(),
+            },
+        );
 
         let mut rl = Editor::<NumbatHelper, DefaultHistory>::new()?;
-        rl.set_max_history_size(1000)
+        rl.set_max_history_size(history_config.max_size)
             .context("Error while configuring history size")?;
         rl.set_completion_type(rustyline::CompletionType::List);
         rl.set_helper(Some(NumbatHelper {
@@ -300,7 +651,9 @@ impl Cli {
             KeyEvent(KeyCode::Enter, Modifiers::ALT),
             EventHandler::Simple(rustyline::Cmd::Newline),
         );
-        rl.load_history(&history_path).ok();
+        for entry in history.entries() {
+            rl.add_history_entry(entry)?;
+        }
 
         if interactive {
             match self.config.intro_banner {
@@ -323,21 +676,51 @@ impl Cli {
             }
         }
 
-        let result = self.repl_loop(&mut rl, interactive);
+        self.context.lock().unwrap().set_undo_limit(UNDO_LIMIT);
+
+        // Like the history, the journal is only kept for interactive sessions. A
+        // journal that can not be read is ignored.
+        let mut journal = if interactive {
+            Journal::open(Arc::new(OsFileSystem), self.get_journal_path()).ok()
+        } else {
+            None
+        };
+        if let Some(num_recoverable) = journal
+            .as_ref()
+            .map(Journal::num_recoverable)
+            .filter(|&n| n > 0)
+        {
+            println!(
+                "The previous session has not been closed normally. Use `:recover` to restore its {num_recoverable} statement{}.",
+                if num_recoverable == 1 { "" } else { "s" }
+            );
+            println!();
+        }
+
+        let result = self.repl_loop(&mut rl, &mut history, &mut journal, interactive);
 
         if interactive {
-            rl.save_history(&history_path).context(format!(
+            history.save(&history_path).context(format!(
                 "Error while saving history to '{}'",
                 history_path.to_string_lossy()
             ))?;
         }
 
+        if let (Ok(()), Some(journal)) = (&result, &mut journal) {
+            journal.truncate().context(format!(
+                "Error while truncating the session journal '{}'",
+                self.get_journal_path().to_string_lossy()
+            ))?;
+        }
+
         result
     }
 
     fn repl_loop(
         &mut self,
         rl: &mut Editor<NumbatHelper, DefaultHistory>,
+        history: &mut History,
+        journal: &mut Option<Journal>,
         interactive: bool,
     ) -> Result<()> {
         loop {
@@ -345,7 +728,9 @@ impl Cli {
             match readline {
                 Ok(line) => {
                     if !line.trim().is_empty() {
-                        rl.add_history_entry(&line)?;
+                        if history.add(&line) {
+                            rl.add_history_entry(&line)?;
+                        }
 
                         match line.trim() {
                             "list" | "ls" => {
@@ -396,6 +781,12 @@ impl Cli {
                             "quit" | "exit" => {
                                 return Ok(());
                             }
+                            "recover" | ":recover" => {
+                                self.recover(journal);
+                            }
+                            "refresh_rates" | ":refresh_rates" => {
+                                self.refresh_exchange_rates();
+                            }
                             "help" | "?" => {
                                 let help = help_markup();
                                 print!("{}", ansi_format(&help, true));
@@ -405,6 +796,19 @@ impl Cli {
                                 println!();
                             }
                             _ => {
+                                if let Some(steps) = parse_undo_command(&line) {
+                                    let mut ctx = self.context.lock().unwrap();
+                                    if !ctx.undo(steps) {
+                                        let available = ctx.undo_steps_available();
+                                        eprintln!(
+                                            "Can not undo {steps} step{}, only {available} available",
+                                            if steps == 1 { "" } else { "s" }
+                                        );
+                                    } else if let Some(journal) = journal {
+                                        journal.remove_last(steps).ok();
+                                    }
+                                    continue;
+                                }
                                 if let Some(keyword) = line.strip_prefix("info ") {
                                     let help = self
                                         .context
@@ -414,25 +818,41 @@ impl Cli {
                                     println!("{}", ansi_format(&help, true));
                                     continue;
                                 }
-                                let result = self.parse_and_evaluate(
-                                    &line,
-                                    CodeSource::Text,
-                                    if interactive {
-                                        ExecutionMode::Interactive
-                                    } else {
-                                        ExecutionMode::Normal
-                                    },
-                                    self.config.pretty_print,
-                                );
+                                let execution_mode = if interactive {
+                                    ExecutionMode::Interactive
+                                } else {
+                                    ExecutionMode::Normal
+                                };
+                                let pretty_print = self.config.pretty_print;
+
+                                // A panic in the interpreter should not take the session
+                                // (and the journal) down with it
+                                let mut context = self.context.clone();
+                                let result = contain_panics(&mut context, &line, || {
+                                    self.try_parse_and_evaluate(
+                                        &line,
+                                        CodeSource::Text,
+                                        execution_mode,
+                                        pretty_print,
+                                    )
+                                });
 
                                 match result {
-                                    std::ops::ControlFlow::Continue(()) => {}
-                                    std::ops::ControlFlow::Break(ExitStatus::Success) => {
-                                        return Ok(());
+                                    Ok(true) => {
+                                        if let Some(journal) = journal {
+                                            // The journal is a safety net, failing to
+                                            // write it should not interrupt the session
+                                            journal.record(&line).ok();
+                                        }
                                     }
-                                    std::ops::ControlFlow::Break(ExitStatus::Error) => {
+                                    Ok(false) if !interactive => {
                                         bail!("Interpreter stopped due to error")
                                     }
+                                    Ok(false) => {}
+                                    Err(internal_error) => {
+                                        self.set_context(rl, context);
+                                        self.print_diagnostic(internal_error);
+                                    }
                                 }
                             }
                         }
@@ -457,21 +877,36 @@ impl Cli {
         execution_mode: ExecutionMode,
         pretty_print_mode: PrettyPrintMode,
     ) -> ControlFlow {
-        let to_be_printed: Arc<Mutex<Vec<m::Markup>>> = Arc::new(Mutex::new(vec![]));
-        let to_be_printed_c = to_be_printed.clone();
-        let mut settings = InterpreterSettings {
-            print_fn: Box::new(move |s: &m::Markup| {
-                to_be_printed_c.lock().unwrap().push(s.clone());
-            }),
-        };
+        if self.try_parse_and_evaluate(input, code_source, execution_mode, pretty_print_mode) {
+            ControlFlow::Continue(())
+        } else {
+            execution_mode.exit_status_in_case_of_error()
+        }
+    }
+
+    /// Evaluate the input and print its results or errors. Returns whether it has
+    /// been evaluated successfully.
+    fn try_parse_and_evaluate(
+        &mut self,
+        input: &str,
+        code_source: CodeSource,
+        execution_mode: ExecutionMode,
+        pretty_print_mode: PrettyPrintMode,
+    ) -> bool {
+        let mut to_be_printed = BufferingSink::default();
 
-        let (result, registry) = {
+        let (result, locale) = {
             let mut ctx = self.context.lock().unwrap();
-            let registry = ctx.dimension_registry().clone(); // TODO: get rid of this clone
-            (
-                ctx.interpret_with_settings(&mut settings, input, code_source),
-                registry,
-            )
+            let result = ctx.interpret_with_sink(&mut to_be_printed, input, code_source);
+
+            // Warnings are shown right away, errors are reported below
+            for event in to_be_printed.events() {
+                if let OutputEvent::Diagnostic(diagnostic) = event {
+                    ctx.forward_diagnostic(diagnostic);
+                }
+            }
+
+            (result, ctx.locale().clone())
         };
 
         let interactive = execution_mode == ExecutionMode::Interactive;
@@ -490,54 +925,213 @@ impl Cli {
 
                 if pretty_print {
                     for statement in &statements {
-                        let repr = ansi_format(&statement.pretty_print(), true);
+                        let repr = ansi_format(&locale.localize(&statement.pretty_print()), true);
                         println!("{}", repr);
                         println!();
                     }
                 }
 
-                let to_be_printed = to_be_printed.lock().unwrap();
-                for s in to_be_printed.iter() {
+                for s in to_be_printed.printed() {
                     println!("{}", ansi_format(s, interactive));
                 }
-                if interactive && !to_be_printed.is_empty() {
+                if interactive && to_be_printed.printed().next().is_some() {
                     println!();
                 }
 
-                let result_markup = interpreter_result.to_markup(
+                let result_markup = self.context.lock().unwrap().result_to_markup(
+                    &interpreter_result,
                     statements.last(),
-                    &registry,
                     interactive || pretty_print,
                     interactive || pretty_print,
                 );
-                print!("{}", ansi_format(&result_markup, false));
+                print!("{}", ansi_format(&locale.localize(&result_markup), false));
 
                 if (interactive || pretty_print) && interpreter_result.is_value() {
                     println!();
                 }
 
-                ControlFlow::Continue(())
+                true
+            }
+            Err(e) => {
+                self.print_error(e);
+                false
+            }
+        }
+    }
+
+    /// Fetch the exchange rates again (`:refresh_rates`), e.g. after the network
+    /// has been unavailable. Currency units are updated before the next input.
+    fn refresh_exchange_rates(&mut self) {
+        match numbat::Context::refresh_exchange_rates() {
+            Ok(None) => println!("The exchange rates have been updated"),
+            Ok(Some(warning)) => self.print_diagnostic(warning),
+            Err(e) => self.print_diagnostic(e),
+        }
+    }
+
+    /// Replay the statements of the previous session (`:recover`), see [`Journal`]
+    fn recover(&mut self, journal: &mut Option<Journal>) {
+        let Some(journal) = journal
+            .as_mut()
+            .filter(|journal| journal.num_recoverable() > 0)
+        else {
+            eprintln!("There is no previous session to recover");
+            return;
+        };
+
+        let replayed = journal.recover(&mut self.context.lock().unwrap());
+        let replayed = match replayed {
+            Ok(replayed) => replayed,
+            Err(e) => {
+                eprintln!("Could not recover the previous session: {e}");
+                return;
+            }
+        };
+
+        let num_statements = replayed.len();
+        let num_recovered = replayed.iter().filter(|r| r.error.is_none()).count();
+        for statement in replayed {
+            let indented = statement.statement.replace('\n', "\n  ");
+            match statement.error {
+                None => println!("{} {indented}", "✓".green()),
+                Some(e) => {
+                    println!("{} {indented}", "✗".red());
+                    self.print_error(e);
+                }
             }
-            Err(NumbatError::ResolverError(e)) => {
-                self.print_diagnostic(e.clone());
-                execution_mode.exit_status_in_case_of_error()
+        }
+        println!();
+        println!("Recovered {num_recovered} of {num_statements} statements");
+    }
+
+    /// Replace the context of the session, including the one that is used for
+    /// completions and highlighting
+    fn set_context(
+        &mut self,
+        rl: &mut Editor<NumbatHelper, DefaultHistory>,
+        context: Arc<Mutex<Context>>,
+    ) {
+        if let Some(helper) = rl.helper_mut() {
+            helper.completer.context = context.clone();
+            helper.highlighter.context = context.clone();
+        }
+        self.context = context;
+    }
+
+    fn print_error(&mut self, error: NumbatError) {
+        match error {
+            NumbatError::ResolverError(e) => self.print_diagnostic(e),
+            NumbatError::NameResolutionError(e) => self.print_diagnostic(e),
+            NumbatError::TypeCheckError(e) => self.print_diagnostic(e),
+            NumbatError::RuntimeError(e) => self.print_diagnostic(e),
+        }
+    }
+
+    fn watch(&mut self) -> Result<()> {
+        let [path] = self.files.as_slice() else {
+            bail!("--watch needs exactly one file");
+        };
+        let path = path.clone();
+
+        let locale = self.context_options.locale.clone();
+        watch::watch(
+            &mut watch::PollingWatcher::default(),
+            || self.evaluate_for_watch(&path),
+            |changed| {
+                if changed.is_empty() {
+                    println!("{}", "(no changes)".dimmed());
+                }
+                for result in changed {
+                    let markup = result.label.clone()
+                        + m::space()
+                        + m::operator("=")
+                        + m::space()
+                        + result.value.pretty_print();
+                    println!("{}", ansi_format(&locale.localize(&markup), false));
+                }
+                println!();
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Evaluate the script in a fresh context, so that definitions which have been
+    /// removed from the script do not linger.
+    fn evaluate_for_watch(&mut self, path: &Path) -> watch::Run {
+        let mut run = watch::Run {
+            dependencies: vec![path.to_owned()],
+            ..Default::default()
+        };
+
+        self.context = Arc::new(Mutex::new(self.context_options.create_context()));
+        if let Err(e) = self.load_startup_code() {
+            eprintln!("{e:#}");
+            return run;
+        }
+
+        let code = match fs::read_to_string(path) {
+            Ok(code) => code,
+            Err(e) => {
+                eprintln!(
+                    "{}: could not read '{}': {e}",
+                    "error".red().bold(),
+                    path.to_string_lossy()
+                );
+                return run;
             }
-            Err(NumbatError::NameResolutionError(
-                e @ (NameResolutionError::IdentifierClash { .. }
-                | NameResolutionError::ReservedIdentifier(_)),
-            )) => {
-                self.print_diagnostic(e);
-                execution_mode.exit_status_in_case_of_error()
+        };
+
+        let mut output = BufferingSink::default();
+        let result = {
+            let mut ctx = self.context.lock().unwrap();
+            let previously_loaded_files = ctx.resolver().loaded_files();
+            let num_previous_results = ctx.results().len();
+
+            let result = ctx.interpret_with_sink(&mut output, &code, CodeSource::File(path.into()));
+
+            run.dependencies = ctx
+                .resolver()
+                .loaded_files()
+                .into_iter()
+                .filter(|file| !previously_loaded_files.contains(file))
+                .collect();
+
+            for event in output.events() {
+                if let OutputEvent::Diagnostic(diagnostic) = event {
+                    ctx.forward_diagnostic(diagnostic);
+                }
             }
-            Err(NumbatError::TypeCheckError(e)) => {
-                self.print_diagnostic(e);
-                execution_mode.exit_status_in_case_of_error()
+
+            result.map(|(program, _)| {
+                watch::collect_results(&ctx, &program, &ctx.results()[num_previous_results..])
+            })
+        };
+
+        for markup in output.printed() {
+            println!("{}", ansi_format(markup, false));
+        }
+
+        match result {
+            Ok(results) => {
+                run.succeeded = true;
+                run.results = results;
             }
-            Err(NumbatError::RuntimeError(e)) => {
-                self.print_diagnostic(e);
-                execution_mode.exit_status_in_case_of_error()
+            Err(e) => {
+                eprintln!(
+                    "{}",
+                    format!(
+                        "Error in '{}', the results above are from the last successful run:",
+                        path.to_string_lossy()
+                    )
+                    .red()
+                    .bold()
+                );
+                self.print_error(e);
             }
         }
+
+        run
     }
 
     fn print_diagnostic(&mut self, error: impl ErrorDiagnostic) {
@@ -545,7 +1139,12 @@ impl Cli {
     }
 
     fn get_config_path() -> PathBuf {
-        let config_dir = dirs::config_dir().unwrap_or_else(|| PathBuf::from("."));
+        // Respect XDG_CONFIG_HOME on all platforms, not just on Linux
+        let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .or_else(dirs::config_dir)
+            .unwrap_or_else(|| PathBuf::from("."));
         config_dir.join("numbat")
     }
 
@@ -576,12 +1175,46 @@ impl Cli {
     }
 
     fn get_history_path(&self) -> Result<PathBuf> {
+        if let Some(path) = &self.config.history.file {
+            return Ok(path.clone());
+        }
+
         let data_dir = dirs::data_dir()
             .unwrap_or_else(|| PathBuf::from("."))
             .join("numbat");
         fs::create_dir_all(&data_dir).ok();
         Ok(data_dir.join("history"))
     }
+
+    /// Fetched exchange rates are kept here, to be used if fetching fails later on
+    fn get_exchange_rates_cache_path() -> PathBuf {
+        let data_dir = dirs::data_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("numbat");
+        fs::create_dir_all(&data_dir).ok();
+        data_dir.join("exchange-rates.xml")
+    }
+
+    /// The session journal, which is used to recover a session after a crash
+    fn get_journal_path(&self) -> PathBuf {
+        dirs::data_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("numbat")
+            .join("journal")
+    }
+}
+
+/// Parses the REPL command `undo` (or `:undo`), with an optional number of steps
+fn parse_undo_command(line: &str) -> Option<usize> {
+    let mut words = line.split_whitespace();
+    if !matches!(words.next(), Some("undo" | ":undo")) {
+        return None;
+    }
+    match (words.next(), words.next()) {
+        (None, _) => Some(1),
+        (Some(steps), None) => steps.parse().ok(),
+        _ => None,
+    }
 }
 
 fn generate_config() -> Result<()> {
@@ -617,6 +1250,16 @@ fn generate_config() -> Result<()> {
 fn main() {
     let args = Args::parse();
 
+    if let Some(Command::Completions { shell }) = args.command {
+        print!("{}", completions::generate(shell, Args::command()));
+        std::process::exit(0);
+    }
+
+    if args.version && !args.verbose {
+        println!("numbat {}", env!("CARGO_PKG_VERSION"));
+        std::process::exit(0);
+    }
+
     if args.generate_config {
         if let Err(e) = generate_config() {
             eprintln!("{:#}", e);
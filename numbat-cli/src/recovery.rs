@@ -0,0 +1,342 @@
+//! Crash recovery for the REPL.
+//!
+//! Every statement that has been evaluated successfully is appended to a journal.
+//! The journal is truncated when the REPL is closed normally. If it is not empty
+//! on the next start, the previous session has ended unexpectedly, and its
+//! statements can be replayed with `:recover`.
+//!
+//! Panics in the interpreter do not end the session, see [`contain_panics`].
+
+use std::any::Any;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use numbat::diagnostic::ErrorDiagnostic;
+use numbat::file_system::FileSystem;
+use numbat::output::BufferingSink;
+use numbat::resolver::CodeSource;
+use numbat::{Context, Diagnostic, NumbatError};
+
+use crate::history::{escape, unescape};
+
+/// The statements of the current session, one per line (escaped like in the
+/// history file).
+pub struct Journal {
+    file_system: Arc<dyn FileSystem>,
+    path: PathBuf,
+    /// The number of entries at the start of the journal that have been left
+    /// over from a previous session
+    num_recoverable: usize,
+}
+
+/// A statement that has been replayed by [`Journal::recover`]
+#[derive(Debug)]
+pub struct Replayed {
+    pub statement: String,
+    pub error: Option<NumbatError>,
+}
+
+impl Journal {
+    /// Open the journal at `path`. Entries that are already in it are from a
+    /// previous session that has not been closed normally.
+    pub fn open(file_system: Arc<dyn FileSystem>, path: PathBuf) -> io::Result<Self> {
+        let mut journal = Journal {
+            file_system,
+            path,
+            num_recoverable: 0,
+        };
+
+        let entries = journal.entries()?;
+        let contents = journal.file_system.read(&journal.path)?;
+        if contents.is_some_and(|contents| !contents.is_empty() && !contents.ends_with('\n')) {
+            // Remove the incomplete entry, before new ones are appended to it
+            journal.replace(&entries)?;
+        }
+
+        journal.num_recoverable = entries.len();
+        Ok(journal)
+    }
+
+    /// The number of statements of a previous session that can be recovered
+    pub fn num_recoverable(&self) -> usize {
+        self.num_recoverable
+    }
+
+    /// Append a statement that has been evaluated successfully. The entry is
+    /// written with a single call, so a crash can at most leave an incomplete
+    /// last line behind, which is ignored.
+    pub fn record(&self, statement: &str) -> io::Result<()> {
+        self.file_system
+            .append(&self.path, &format!("{}\n", escape(statement)))
+    }
+
+    pub fn entries(&self) -> io::Result<Vec<String>> {
+        let contents = self.file_system.read(&self.path)?.unwrap_or_default();
+        Ok(contents
+            .split_inclusive('\n')
+            .filter_map(|line| line.strip_suffix('\n'))
+            .filter_map(unescape)
+            .collect())
+    }
+
+    /// Remove the last `steps` entries, after they have been reverted with `undo`
+    pub fn remove_last(&mut self, steps: usize) -> io::Result<()> {
+        let mut entries = self.entries()?;
+        entries.truncate(entries.len().saturating_sub(steps));
+        self.num_recoverable = self.num_recoverable.min(entries.len());
+        self.replace(&entries)
+    }
+
+    /// Remove all entries, when the session is closed normally
+    pub fn truncate(&mut self) -> io::Result<()> {
+        self.num_recoverable = 0;
+        self.replace(&[])
+    }
+
+    /// Evaluate the statements of the previous session in `context`, one by one.
+    /// Afterwards, the journal contains the statements of the current session,
+    /// followed by the recovered statements that have been evaluated successfully.
+    pub fn recover(&mut self, context: &mut Context) -> io::Result<Vec<Replayed>> {
+        let mut previous_session = self.entries()?;
+        let current_session =
+            previous_session.split_off(self.num_recoverable.min(previous_session.len()));
+
+        let replayed = previous_session
+            .into_iter()
+            .map(|statement| {
+                // Output of `print` calls is not shown again
+                let error = context
+                    .interpret_with_sink(
+                        &mut BufferingSink::default(),
+                        &statement,
+                        CodeSource::Text,
+                    )
+                    .err();
+                Replayed { statement, error }
+            })
+            .collect::<Vec<_>>();
+
+        let recovered = replayed
+            .iter()
+            .filter(|replayed| replayed.error.is_none())
+            .map(|replayed| replayed.statement.clone());
+        let entries = current_session
+            .into_iter()
+            .chain(recovered)
+            .collect::<Vec<_>>();
+
+        self.num_recoverable = 0;
+        self.replace(&entries)?;
+
+        Ok(replayed)
+    }
+
+    fn replace(&self, entries: &[String]) -> io::Result<()> {
+        let contents: String = entries
+            .iter()
+            .map(|entry| format!("{}\n", escape(entry)))
+            .collect();
+        self.file_system.write(&self.path, &contents)
+    }
+}
+
+/// A panic while evaluating a statement, see [`contain_panics`]
+#[derive(Debug)]
+pub struct InternalError {
+    pub statement: String,
+    pub message: String,
+}
+
+impl ErrorDiagnostic for InternalError {
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        vec![Diagnostic::bug()
+            .with_message(format!("internal error: {}", self.message))
+            .with_notes(vec![
+                "the session has been restored to its state before this input".into(),
+                format!(
+                    "this is a bug in Numbat. Please report it at \
+                     https://github.com/sharkdp/numbat/issues, including the input:\n\n    {}",
+                    self.statement.replace('\n', "\n    ")
+                ),
+            ])]
+    }
+}
+
+/// Run `evaluate`, which evaluates `statement` in `context`. If it panics, the
+/// context is returned to its state before the statement and moved into a new
+/// mutex (the previous one has been poisoned), and the panic is returned as an
+/// error. Only a [`Checkpoint`](numbat::Checkpoint) is kept as a backup, not the
+/// undo history.
+pub fn contain_panics<T>(
+    context: &mut Arc<Mutex<Context>>,
+    statement: &str,
+    evaluate: impl FnOnce() -> T,
+) -> Result<T, InternalError> {
+    let checkpoint = context.lock().unwrap().checkpoint();
+
+    panic::catch_unwind(AssertUnwindSafe(evaluate)).map_err(|payload| {
+        let mut poisoned = context.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut restored = std::mem::replace(&mut *poisoned, Context::new_without_importer());
+        drop(poisoned);
+        restored.restore(checkpoint);
+        *context = Arc::new(Mutex::new(restored));
+
+        InternalError {
+            statement: statement.to_owned(),
+            message: panic_message(&*payload),
+        }
+    })
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "the interpreter panicked".into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use numbat::file_system::InMemoryFileSystem;
+    use std::time::Instant;
+
+    const PATH: &str = "/journal";
+
+    fn evaluate(context: &mut Context, code: &str) -> String {
+        let (statements, result) = context.interpret(code, CodeSource::Text).unwrap();
+        let markup = context.result_to_markup(&result, statements.last(), false, false);
+        markup.to_string().trim().to_string()
+    }
+
+    #[test]
+    fn replaying_the_journal() {
+        let file_system = Arc::new(InMemoryFileSystem::new());
+
+        let journal = Journal::open(file_system.clone(), PATH.into()).unwrap();
+        assert_eq!(journal.num_recoverable(), 0);
+        journal.record("let a = 2").unwrap();
+        journal.record("let b = a + c").unwrap();
+        journal.record("fn f(x) =\n  x + a").unwrap();
+        // A crash while writing leaves an incomplete entry behind
+        file_system.append(PATH.as_ref(), "let d = ").unwrap();
+
+        let mut journal = Journal::open(file_system.clone(), PATH.into()).unwrap();
+        assert_eq!(journal.num_recoverable(), 3);
+        journal.record("let e = 5").unwrap();
+
+        let mut context = Context::new_without_importer();
+        evaluate(&mut context, "let e = 5");
+
+        let replayed = journal.recover(&mut context).unwrap();
+        let statements = replayed.iter().map(|r| r.statement.as_str());
+        assert!(statements.eq(["let a = 2", "let b = a + c", "fn f(x) =\n  x + a"]));
+        assert!(replayed[0].error.is_none());
+        assert!(matches!(
+            replayed[1].error,
+            Some(NumbatError::TypeCheckError(_))
+        ));
+        assert!(replayed[2].error.is_none());
+
+        assert_eq!(evaluate(&mut context, "f(e)"), "7");
+
+        assert_eq!(journal.num_recoverable(), 0);
+        assert_eq!(
+            journal.entries().unwrap(),
+            ["let e = 5", "let a = 2", "fn f(x) =\n  x + a"]
+        );
+        assert_eq!(
+            file_system.contents(PATH).unwrap(),
+            "let e = 5\nlet a = 2\nfn f(x) =\\n  x + a\n"
+        );
+    }
+
+    #[test]
+    fn journal_is_truncated() {
+        let file_system = Arc::new(InMemoryFileSystem::new());
+        let journal = Journal::open(file_system.clone(), PATH.into()).unwrap();
+        for statement in ["let a = 1", "let b = 2", "let c = 3"] {
+            journal.record(statement).unwrap();
+        }
+
+        let mut journal = Journal::open(file_system.clone(), PATH.into()).unwrap();
+        journal.remove_last(2).unwrap();
+        assert_eq!(journal.entries().unwrap(), ["let a = 1"]);
+        assert_eq!(journal.num_recoverable(), 1);
+
+        journal.truncate().unwrap();
+        assert_eq!(journal.num_recoverable(), 0);
+        assert_eq!(file_system.contents(PATH).unwrap(), "");
+
+        let journal = Journal::open(file_system, PATH.into()).unwrap();
+        assert_eq!(journal.num_recoverable(), 0);
+    }
+
+    #[test]
+    fn panics_are_contained() {
+        let mut context = Context::new_without_importer();
+        evaluate(&mut context, "let a = 2");
+        context
+            .register_native_function("explode", "(x: String) -> String", |_| panic!("boom"))
+            .unwrap();
+        let mut context = Arc::new(Mutex::new(context));
+
+        let shared = context.clone();
+        let result = contain_panics(&mut context, "let b = explode(\"a\")", || {
+            shared
+                .lock()
+                .unwrap()
+                .interpret_with_sink(
+                    &mut BufferingSink::default(),
+                    "let b = explode(\"a\")",
+                    CodeSource::Text,
+                )
+                .is_ok()
+        });
+        let error = result.unwrap_err();
+        assert_eq!(error.statement, "let b = explode(\"a\")");
+        assert_eq!(error.message, "boom");
+        assert!(shared.lock().is_err());
+
+        // The session is still usable, in its state before the panic
+        let mut context = context.lock().unwrap();
+        assert_eq!(evaluate(&mut context, "a + 1"), "3");
+        assert!(context.get_variable("b").is_none());
+        evaluate(&mut context, "let b = 4");
+        assert_eq!(evaluate(&mut context, "a + b"), "6");
+
+        let result = contain_panics(&mut Arc::new(Mutex::new(context.clone())), "1", || 42);
+        assert_eq!(result.unwrap(), 42);
+    }
+
+    #[test]
+    fn backup_does_not_grow_with_the_undo_history() {
+        let time_per_statement = |undo_limit: usize| {
+            let mut context = Context::new_without_importer();
+            context.set_undo_limit(undo_limit);
+            for i in 0..200 {
+                evaluate(&mut context, &format!("let v{i} = {i}"));
+            }
+            let mut context = Arc::new(Mutex::new(context));
+
+            let start = Instant::now();
+            for _ in 0..20 {
+                contain_panics(&mut context, "1", || ()).unwrap();
+            }
+            start.elapsed()
+        };
+
+        // Copying the undo history would make each statement ~100 times slower
+        let without_history = time_per_statement(0);
+        let with_history = time_per_statement(100);
+        assert!(
+            with_history < 5 * without_history,
+            "{with_history:?} with undo history, {without_history:?} without"
+        );
+    }
+}
@@ -0,0 +1,276 @@
+//! Watch mode (`numbat --watch script.nbt`): the script is evaluated again whenever
+//! it or one of the modules it uses changes, and only the results that changed since
+//! the last successful run are printed.
+
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use numbat::markup::Markup;
+use numbat::pretty_print::PrettyPrint;
+use numbat::value::Value;
+use numbat::{Context, TypedProgram};
+
+/// Identifies the result of a statement across runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResultKey {
+    /// A variable definition, by the name of the variable
+    Variable(String),
+    /// The n-th expression statement of the script
+    Expression(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatementResult {
+    pub key: ResultKey,
+    /// The variable name or the expression, as it is shown to the user
+    pub label: Markup,
+    pub value: Value,
+}
+
+/// Collect the values of all variable definitions and expression statements of the
+/// program. `expression_values` are the values of the expression statements, in order.
+pub fn collect_results(
+    ctx: &Context,
+    program: &TypedProgram,
+    expression_values: &[Value],
+) -> Vec<StatementResult> {
+    let mut results = vec![];
+    let mut expression_values = expression_values.iter().enumerate();
+
+    for statement in program {
+        if statement.is_expression() {
+            let Some((index, value)) = expression_values.next() else {
+                break;
+            };
+            results.push(StatementResult {
+                key: ResultKey::Expression(index),
+                label: statement.pretty_print(),
+                value: value.clone(),
+            });
+        }
+
+        for name in statement.variable_names() {
+            if let Some((value, _)) = ctx.get_variable(&name) {
+                results.push(StatementResult {
+                    key: ResultKey::Variable(name.clone()),
+                    label: numbat::markup::identifier(&name),
+                    value,
+                });
+            }
+        }
+    }
+
+    results
+}
+
+/// The results of the current run that did not exist or had a different value in
+/// the previous run.
+pub fn changed_results<'a>(
+    previous: &[StatementResult],
+    current: &'a [StatementResult],
+) -> Vec<&'a StatementResult> {
+    current
+        .iter()
+        .filter(|result| {
+            !previous
+                .iter()
+                .any(|p| p.key == result.key && p.value == result.value)
+        })
+        .collect()
+}
+
+/// The outcome of evaluating the script once.
+#[derive(Debug, Default)]
+pub struct Run {
+    /// Whether the script could be evaluated without errors
+    pub succeeded: bool,
+    pub results: Vec<StatementResult>,
+    /// The script and all files that were loaded by it
+    pub dependencies: Vec<PathBuf>,
+}
+
+pub trait FileWatcher {
+    /// Wait until one of the given files changes. Returns `false` if there will be
+    /// no further changes, which ends the watch mode.
+    fn wait_for_change(&mut self, paths: &[PathBuf]) -> bool;
+}
+
+/// Checks the modification times of the files in regular intervals.
+pub struct PollingWatcher {
+    pub interval: Duration,
+    /// A change is only reported once the files have not been modified for this
+    /// long, so that editors which save in several steps trigger a single run.
+    pub debounce: Duration,
+}
+
+impl Default for PollingWatcher {
+    fn default() -> Self {
+        PollingWatcher {
+            interval: Duration::from_millis(200),
+            debounce: Duration::from_millis(100),
+        }
+    }
+}
+
+impl PollingWatcher {
+    fn modification_times(paths: &[PathBuf]) -> Vec<Option<SystemTime>> {
+        paths
+            .iter()
+            .map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+            .collect()
+    }
+}
+
+impl FileWatcher for PollingWatcher {
+    fn wait_for_change(&mut self, paths: &[PathBuf]) -> bool {
+        let initial = Self::modification_times(paths);
+        loop {
+            thread::sleep(self.interval);
+            if Self::modification_times(paths) != initial {
+                break;
+            }
+        }
+
+        let mut last = Self::modification_times(paths);
+        loop {
+            thread::sleep(self.debounce);
+            let current = Self::modification_times(paths);
+            if current == last {
+                return true;
+            }
+            last = current;
+        }
+    }
+}
+
+/// Evaluate the script, and again after every change. `show_changes` is called after
+/// every successful run with the results that changed since the last successful run.
+/// Failed runs are expected to report their errors themselves, the last good results
+/// are kept for comparison.
+pub fn watch(
+    watcher: &mut dyn FileWatcher,
+    mut evaluate: impl FnMut() -> Run,
+    mut show_changes: impl FnMut(&[&StatementResult]),
+) {
+    let mut last_good_results = vec![];
+    loop {
+        let run = evaluate();
+        if run.succeeded {
+            show_changes(&changed_results(&last_good_results, &run.results));
+            last_good_results = run.results;
+        }
+
+        if !watcher.wait_for_change(&run.dependencies) {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use numbat::module_importer::BuiltinModuleImporter;
+    use numbat::resolver::CodeSource;
+
+    /// Reports a fixed number of changes, without looking at the file system
+    struct ManualWatcher {
+        remaining_changes: usize,
+        watched: Vec<Vec<PathBuf>>,
+    }
+
+    impl FileWatcher for ManualWatcher {
+        fn wait_for_change(&mut self, paths: &[PathBuf]) -> bool {
+            self.watched.push(paths.to_vec());
+            if self.remaining_changes == 0 {
+                return false;
+            }
+            self.remaining_changes -= 1;
+            true
+        }
+    }
+
+    fn evaluate(code: &str) -> Vec<StatementResult> {
+        let mut ctx = Context::new(BuiltinModuleImporter::default());
+        let (program, _) = ctx.interpret(code, CodeSource::Internal).unwrap();
+        let values = ctx.results().to_vec();
+        collect_results(&ctx, &program, &values)
+    }
+
+    fn labels(results: &[&StatementResult]) -> Vec<String> {
+        results
+            .iter()
+            .map(|r| format!("{} = {}", r.label, r.value.pretty_print()))
+            .collect()
+    }
+
+    #[test]
+    fn results_are_collected_per_statement() {
+        let results = evaluate("let x = 2\nx + 1\nlet [a, b] = [3, 4]\nfn f(y) = y\nf(5)");
+        assert_eq!(
+            results.iter().map(|r| r.key.clone()).collect::<Vec<_>>(),
+            [
+                ResultKey::Variable("x".into()),
+                ResultKey::Expression(0),
+                ResultKey::Variable("a".into()),
+                ResultKey::Variable("b".into()),
+                ResultKey::Expression(1),
+            ]
+        );
+        assert_eq!(
+            labels(&results.iter().collect::<Vec<_>>()),
+            ["x = 2", "x + 1 = 3", "a = 3", "b = 4", "f(5) = 5"]
+        );
+    }
+
+    #[test]
+    fn only_changed_results_are_reported() {
+        let previous = evaluate("let x = 2\nlet y = 3\nx * y");
+        let current = evaluate("let x = 2\nlet y = 4\nx * y\nlet z = 1");
+        assert_eq!(
+            labels(&changed_results(&previous, &current)),
+            ["y = 4", "x × y = 8", "z = 1"]
+        );
+
+        assert!(changed_results(&current, &current).is_empty());
+        assert_eq!(changed_results(&[], &current).len(), 4);
+    }
+
+    #[test]
+    fn failed_runs_keep_the_last_good_results() {
+        let mut watcher = ManualWatcher {
+            remaining_changes: 3,
+            watched: vec![],
+        };
+        let mut runs = vec![
+            ("let x = 1", true),
+            ("let x = 2", false),
+            ("let x = 1", true),
+            ("let x = 3", true),
+        ]
+        .into_iter();
+        let mut shown = vec![];
+
+        watch(
+            &mut watcher,
+            || {
+                let (code, succeeded) = runs.next().unwrap();
+                Run {
+                    succeeded,
+                    results: evaluate(code),
+                    dependencies: vec![PathBuf::from(format!("{code}.nbt"))],
+                }
+            },
+            |changed| shown.push(labels(changed)),
+        );
+
+        // The failed second run is not compared against, so the third run is unchanged
+        assert_eq!(
+            shown,
+            [vec!["x = 1".to_string()], vec![], vec!["x = 3".to_string()]]
+        );
+        assert_eq!(watcher.watched.len(), 4);
+        assert_eq!(watcher.watched[1], [PathBuf::from("let x = 2.nbt")]);
+    }
+}
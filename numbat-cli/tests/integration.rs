@@ -1,22 +1,38 @@
-use std::path::Path;
+use std::path::PathBuf;
 
 use assert_cmd::Command;
 use predicates::boolean::PredicateBooleanExt;
 
-fn numbat() -> Command {
-    let module_path = Path::new(&std::env::var_os("CARGO_MANIFEST_DIR").unwrap())
+fn manifest_dir() -> PathBuf {
+    PathBuf::from(std::env::var_os("CARGO_MANIFEST_DIR").unwrap())
+}
+
+fn numbat_with_user_files() -> Command {
+    let module_path = manifest_dir()
         .parent()
         .unwrap()
         .join("numbat")
         .join("modules");
     std::env::set_var("NUMBAT_MODULES_PATH", module_path);
 
-    let mut cmd = Command::cargo_bin("numbat").unwrap();
+    Command::cargo_bin("numbat").unwrap()
+}
+
+fn numbat() -> Command {
+    let mut cmd = numbat_with_user_files();
     cmd.arg("--no-init");
     cmd.arg("--no-config");
     cmd
 }
 
+/// Run numbat with the config directory (containing 'numbat/config.toml'
+/// and 'numbat/init.nbt') set to the given folder in 'tests/'.
+fn numbat_with_config_home(folder: &str) -> Command {
+    let mut cmd = numbat_with_user_files();
+    cmd.env("XDG_CONFIG_HOME", manifest_dir().join("tests").join(folder));
+    cmd
+}
+
 #[test]
 fn pass_expression_on_command_line() {
     numbat()
@@ -95,6 +111,83 @@ fn process_code_from_file_and_cli_expression() {
         .stderr(predicates::str::contains("while type checking"));
 }
 
+#[test]
+fn read_code_from_multiple_files() {
+    numbat()
+        .arg("tests/examples/definitions.nbt")
+        .arg("tests/examples/steps.nbt")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("2500"));
+
+    numbat()
+        .arg("tests/examples/definitions.nbt")
+        .arg("tests/examples/type_error.nbt")
+        .arg("tests/examples/steps.nbt")
+        .assert()
+        .failure()
+        .stdout(predicates::str::contains("2500").not())
+        .stderr(predicates::str::contains(
+            "tests/examples/type_error.nbt:3:13",
+        ));
+
+    numbat()
+        .arg("--continue-on-error")
+        .arg("tests/examples/definitions.nbt")
+        .arg("tests/examples/type_error.nbt")
+        .arg("tests/examples/steps.nbt")
+        .assert()
+        .failure()
+        .stdout(predicates::str::contains("2500"))
+        .stderr(predicates::str::contains(
+            "tests/examples/type_error.nbt:3:13",
+        ));
+}
+
+#[test]
+fn pass_arguments_to_script() {
+    numbat()
+        .arg("tests/examples/arguments.nbt")
+        .arg("--")
+        .arg("3.5")
+        .arg("km")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("2 arguments: 3500 m"));
+
+    // Arguments that look like options are passed through as well
+    numbat()
+        .arg("--expression")
+        .arg("args()")
+        .arg("--")
+        .arg("--debug")
+        .arg("-1")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("[\"--debug\", \"-1\"]"));
+
+    numbat()
+        .arg("tests/examples/arguments.nbt")
+        .arg("--")
+        .arg("3.5")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "Script argument 1 is not available, the script was called with 1 argument(s)",
+        ));
+
+    numbat()
+        .arg("tests/examples/arguments.nbt")
+        .arg("--")
+        .arg("3.5")
+        .arg("s")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "expected a quantity of type Length, got 's' of type Time",
+        ));
+}
+
 #[test]
 fn print_calls() {
     numbat()
@@ -106,6 +199,21 @@ fn print_calls() {
         ));
 }
 
+#[test]
+fn profile_functions() {
+    numbat()
+        .arg("--profile")
+        .arg("--expression")
+        .arg("fn square(x) = x * x")
+        .arg("--expression")
+        .arg("square(2) + square(3)")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("13"))
+        .stderr(predicates::str::contains("Function"))
+        .stderr(predicates::str::is_match(r"square +2 ").unwrap());
+}
+
 #[test]
 fn without_prelude() {
     numbat()
@@ -168,4 +276,359 @@ fn info_text() {
             predicates::str::contains("Round")
                 .and(predicates::str::contains("Round to the nearest integer.")),
         );
+
+    numbat()
+        .write_stdin("info meter")
+        .assert()
+        .success()
+        .stdout(
+            predicates::str::contains("Long names: metre, metres, meter, meters")
+                .and(predicates::str::contains("Short names: m")),
+        );
+}
+
+#[test]
+fn long_unit_names() {
+    numbat()
+        .arg("--long-units")
+        .arg("--expression")
+        .arg("60 km/h")
+        .assert()
+        .success()
+        .stdout("60 kilometres/hour\n");
+
+    numbat()
+        .arg("--expression")
+        .arg("60 km/h")
+        .assert()
+        .success()
+        .stdout("60 km/h\n");
+}
+
+#[test]
+fn undo() {
+    numbat()
+        .write_stdin("let a = 9.81 m/s^2\nlet a = 9.8\nundo\na")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("9.81 m/s²"));
+
+    numbat()
+        .write_stdin("unit bathtub\n3 bathtub\nundo 2\n2 bathtub")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("unknown identifier"));
+
+    numbat()
+        .write_stdin("1 + 2\n:undo 2\nans")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("3"))
+        .stderr(predicates::str::contains(
+            "Can not undo 2 steps, only 1 available",
+        ));
+}
+
+#[test]
+fn user_config_and_init_file() {
+    numbat_with_config_home("config")
+        .arg("--expression")
+        .arg("my_constant")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("my_constant\n\n    = 42 m"))
+        .stderr(predicates::str::contains(
+            "Warning: ignoring unknown key 'no-such-setting'",
+        ));
+
+    numbat_with_config_home("config")
+        .arg("--no-init")
+        .arg("--expression")
+        .arg("my_constant")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("unknown identifier"));
+
+    numbat_with_config_home("config")
+        .arg("--no-config")
+        .arg("--expression")
+        .arg("my_constant")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("42 m").and(predicates::str::contains("=").not()));
+}
+
+#[test]
+fn config_flags_override_config_file() {
+    numbat_with_config_home("config")
+        .arg("--pretty-print=never")
+        .arg("--expression")
+        .arg("my_constant")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("=").not());
+
+    numbat_with_config_home("config-broken-init")
+        .arg("--no-init")
+        .arg("--config")
+        .arg("tests/config/numbat/config.toml")
+        .arg("--expression")
+        .arg("2 m")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("    = 2 m"));
+
+    numbat_with_user_files()
+        .arg("--config")
+        .arg("tests/config/numbat/does-not-exist.toml")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "Could not read configuration file",
+        ));
+}
+
+#[test]
+fn broken_init_file_does_not_prevent_repl_from_starting() {
+    numbat_with_config_home("config-broken-init")
+        .write_stdin("3 m + 4 m")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("7 m"))
+        .stderr(
+            predicates::str::contains("init.nbt")
+                .and(predicates::str::contains("continuing without it")),
+        );
+
+    numbat_with_config_home("config-broken-init")
+        .arg("--expression")
+        .arg("my_constant")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("user initialization code"));
+}
+
+/// Run the given code with `--error-format json` and return the first reported diagnostic
+fn json_diagnostic(code: &str) -> serde_json::Value {
+    let output = numbat()
+        .arg("--error-format")
+        .arg("json")
+        .arg("--expression")
+        .arg(code)
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let line = stderr
+        .lines()
+        .find(|line| line.starts_with('{'))
+        .unwrap_or_else(|| panic!("No JSON diagnostic in output:\n{stderr}"));
+    serde_json::from_str(line).unwrap()
+}
+
+#[test]
+fn error_format_json() {
+    // (code, error code, byte range of the primary span)
+    let cases = [
+        ("2 +/ 3", "E0201", Some((3, 4))),
+        ("use foo::bar", "E0301", Some((4, 12))),
+        ("let m = 2", "E0401", Some((4, 5))),
+        ("2 meter + 3 second", "E0502", Some((0, 7))),
+        ("dimension Foo = Bar", "E0702", None),
+        ("1 / 0", "E0801", None),
+    ];
+
+    for (code, error_code, primary_span) in cases {
+        let diagnostic = json_diagnostic(code);
+        assert_eq!(diagnostic["code"], error_code, "for '{code}'");
+        assert_eq!(diagnostic["severity"], "error");
+
+        match primary_span {
+            Some((start, end)) => {
+                let span = &diagnostic["primary_span"];
+                assert_eq!(span["file"], "<input:1>");
+                assert_eq!(span["byte_start"], start, "for '{code}'");
+                assert_eq!(span["byte_end"], end, "for '{code}'");
+                assert_eq!(span["line_start"], 1);
+                assert_eq!(span["column_start"], start + 1);
+            }
+            None => assert!(diagnostic["primary_span"].is_null(), "for '{code}'"),
+        }
+    }
+
+    let diagnostic = json_diagnostic("2 meter + 3 second");
+    let secondary_spans = diagnostic["secondary_spans"].as_array().unwrap();
+    assert_eq!(secondary_spans[0]["label"], "Time");
+    assert_eq!(secondary_spans[0]["byte_start"], 10);
+    assert_eq!(
+        secondary_spans[1]["label"],
+        "incompatible dimensions in addition"
+    );
+
+    let diagnostic = json_diagnostic("foo");
+    assert_eq!(diagnostic["notes"][0], "Did you mean 'foot'?");
+    let suggestion = &diagnostic["suggestions"][0];
+    assert_eq!(suggestion["replacement"], "foot");
+    assert_eq!(suggestion["applicability"], "maybe-incorrect");
+    assert_eq!(suggestion["span"]["byte_start"], 0);
+    assert_eq!(suggestion["span"]["byte_end"], 3);
+}
+
+#[test]
+fn fix_files() {
+    let dir = std::env::temp_dir().join(format!("numbat-fix-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("script.nbt");
+    let backup = dir.join("script.nbt.bak");
+
+    // Two machine-applicable fixes (one for an error that hides the warning of the
+    // other) and a suggestion that is only a guess
+    let original = "let x = 2\n\
+                    print(x > 0 && < 5)\n\
+                    print(9007199254740993)\n\
+                    print(meter -> 5 km)\n";
+    std::fs::write(&path, original).unwrap();
+
+    numbat()
+        .arg("--fix")
+        .arg(&path)
+        .assert()
+        .success()
+        .stdout("true\n9.0072e+15\n0.001 km\n")
+        .stderr(
+            predicates::str::contains("Fixed").and(predicates::str::contains(
+                "help: replace with `5 kilometre -> metre`",
+            )),
+        );
+
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "let x = 2\n\
+         print(x > 0 && x < 5)\n\
+         print(9007199254740992)\n\
+         print(meter -> 5 km)\n"
+    );
+    assert_eq!(std::fs::read_to_string(&backup).unwrap(), original);
+
+    // Nothing left to fix: the file is not written again
+    std::fs::remove_file(&backup).unwrap();
+    numbat()
+        .arg("--fix")
+        .arg(&path)
+        .assert()
+        .success()
+        .stderr(predicates::str::contains("Fixed").not());
+    assert!(!backup.exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn doc_json() {
+    let output = numbat()
+        .arg("doc")
+        .arg("--json")
+        .arg("tests/examples/pendulum.nbt")
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let catalog: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    let function = |name: &str| {
+        catalog["functions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|f| f["name"] == name)
+            .unwrap_or_else(|| panic!("No function '{name}'"))
+            .clone()
+    };
+
+    let sqrt = function("sqrt");
+    assert_eq!(sqrt["signature"], "fn sqrt<D: Dim>(x: D^2) -> D");
+    assert_eq!(sqrt["origin"]["module"], "core::functions");
+    assert_eq!(sqrt["origin"]["prelude"], true);
+
+    let oscillation_time = function("oscillation_time");
+    assert_eq!(oscillation_time["parameters"][0]["type"], "Length");
+    assert_eq!(oscillation_time["origin"]["prelude"], false);
+
+    assert!(catalog["units"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|u| u["name"] == "metre" && u["dimension"] == "Length"));
+}
+
+#[test]
+fn doc_text() {
+    numbat()
+        .arg("doc")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "fn sqrt<D: Dim>(x: D^2) -> D  (core::functions)",
+        ))
+        .stdout(predicates::str::contains("inch: Length = 0.0254 m"));
+}
+
+#[test]
+fn completions() {
+    for shell in ["bash", "zsh", "fish"] {
+        numbat()
+            .arg("completions")
+            .arg(shell)
+            .assert()
+            .success()
+            .stdout(
+                predicates::str::contains("expression")
+                    .and(predicates::str::contains("completions"))
+                    .and(predicates::str::contains("always never auto")),
+            );
+    }
+
+    // Check the syntax of the bash script, if bash is available
+    let script = numbat().arg("completions").arg("bash").output().unwrap();
+    if let Ok(status) = std::process::Command::new("bash")
+        .arg("-n")
+        .arg("-c")
+        .arg(String::from_utf8(script.stdout).unwrap())
+        .status()
+    {
+        assert!(status.success());
+    }
+
+    numbat()
+        .arg("completions")
+        .arg("powershell")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "possible values: bash, zsh, fish",
+        ));
+}
+
+#[test]
+fn version() {
+    numbat()
+        .arg("--version")
+        .assert()
+        .success()
+        .stdout(predicates::str::is_match("^numbat [0-9.]+\n$").unwrap());
+
+    numbat()
+        .arg("--version")
+        .arg("--verbose")
+        .assert()
+        .success()
+        .stdout(
+            predicates::str::contains("prelude:")
+                .and(predicates::str::contains("+fetch-exchangerates"))
+                .and(predicates::str::contains("exchange rates: not loaded")),
+        );
+
+    numbat().arg("--verbose").assert().failure();
 }
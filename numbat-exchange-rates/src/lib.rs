@@ -1,10 +1,15 @@
 use std::collections::HashMap;
+use std::time::Duration;
 
 use quick_xml::events::Event;
 use quick_xml::reader::Reader;
 
 pub type ExchangeRates = HashMap<String, f64>;
 
+/// How long to wait for the European Central Bank, unless a different timeout
+/// is given.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
 pub fn parse_exchange_rates(xml_content: &str) -> Option<ExchangeRates> {
     let mut rates = ExchangeRates::default();
 
@@ -36,14 +41,23 @@ pub fn parse_exchange_rates(xml_content: &str) -> Option<ExchangeRates> {
 #[cfg(feature = "fetch-exchangerates")]
 const ECB_XML_URL: &str = "https://www.ecb.europa.eu/stats/eurofxref/eurofxref-daily.xml";
 
+/// Fetches the XML document with the current exchange rates. Gives up if the
+/// connection can not be established or the request does not complete within
+/// the given timeout.
 #[cfg(feature = "fetch-exchangerates")]
-fn fetch_ecb_xml() -> Option<String> {
-    attohttpc::get(ECB_XML_URL).send().ok()?.text().ok()
+pub fn fetch_exchange_rates_xml(timeout: Duration) -> Option<String> {
+    attohttpc::get(ECB_XML_URL)
+        .connect_timeout(timeout)
+        .timeout(timeout)
+        .send()
+        .ok()?
+        .text()
+        .ok()
 }
 
 #[cfg(feature = "fetch-exchangerates")]
 pub fn fetch_exchange_rates() -> Option<ExchangeRates> {
-    let xml_content = fetch_ecb_xml()?;
+    let xml_content = fetch_exchange_rates_xml(DEFAULT_TIMEOUT)?;
     parse_exchange_rates(&xml_content)
 }
 
@@ -0,0 +1,173 @@
+//! Conversion of interpreter results to plain JavaScript objects, and adapters
+//! for JavaScript callbacks.
+//!
+//! Results are returned as plain objects (instead of `#[wasm_bindgen]` structs)
+//! so that they are garbage collected like any other JavaScript value and do
+//! not need to be freed explicitly.
+
+use std::path::PathBuf;
+
+use js_sys::{Array, Function, Object, Reflect};
+use wasm_bindgen::prelude::*;
+
+use numbat::diagnostic::{DiagnosticSpan, Severity, StructuredDiagnostic};
+use numbat::highlighting::ClassifiedToken;
+use numbat::module_importer::ModuleImporter;
+use numbat::resolver::ModulePath;
+use numbat::value::Value;
+
+pub fn object(entries: &[(&str, JsValue)]) -> JsValue {
+    let object = Object::new();
+    for (key, value) in entries {
+        Reflect::set(&object, &JsValue::from_str(key), value).unwrap();
+    }
+    object.into()
+}
+
+fn optional(value: Option<impl Into<JsValue>>) -> JsValue {
+    value.map_or(JsValue::NULL, Into::into)
+}
+
+fn value_kind(value: &Value) -> &'static str {
+    match value {
+        Value::Quantity(_) | Value::Duration(_) => "quantity",
+        Value::Boolean(_) => "boolean",
+        Value::String(_) => "string",
+        Value::DateTime(_) => "datetime",
+        Value::FunctionReference(_) => "function",
+        Value::FormatSpecifiers(_) => "format-specifiers",
+        Value::DefaultArgument => "default-argument",
+        Value::StructInstance(..) => "struct",
+        Value::List(_) | Value::Sequence(_) => "list",
+    }
+}
+
+/// `{ kind, display, magnitude, unit }`, where `magnitude` and `unit` are
+/// `null` for everything except quantities.
+pub fn value_to_js(value: &Value, display: String) -> JsValue {
+    let (magnitude, unit) = if value.is_quantity() {
+        let q = value.clone().unsafe_as_quantity();
+        (Some(q.unsafe_value().to_f64()), Some(q.unit().to_string()))
+    } else {
+        (None, None)
+    };
+
+    object(&[
+        ("kind", value_kind(value).into()),
+        ("display", display.into()),
+        ("magnitude", optional(magnitude)),
+        ("unit", optional(unit)),
+    ])
+}
+
+fn severity_name(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Bug => "bug",
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Note => "note",
+        Severity::Help => "help",
+    }
+}
+
+fn span_to_js(span: &DiagnosticSpan) -> JsValue {
+    object(&[
+        ("file", span.file.as_str().into()),
+        ("byte_start", span.byte_start.into()),
+        ("byte_end", span.byte_end.into()),
+        ("line_start", span.line_start.into()),
+        ("column_start", span.column_start.into()),
+        ("line_end", span.line_end.into()),
+        ("column_end", span.column_end.into()),
+        ("label", span.label.as_str().into()),
+    ])
+}
+
+pub fn diagnostic_to_js(diagnostic: &StructuredDiagnostic) -> JsValue {
+    object(&[
+        ("code", optional(diagnostic.code.map(|c| c.as_str()))),
+        ("severity", severity_name(diagnostic.severity).into()),
+        ("message", diagnostic.message.as_str().into()),
+        (
+            "primary_span",
+            diagnostic
+                .primary_span
+                .as_ref()
+                .map_or(JsValue::NULL, span_to_js),
+        ),
+        (
+            "secondary_spans",
+            diagnostic
+                .secondary_spans
+                .iter()
+                .map(span_to_js)
+                .collect::<Array>()
+                .into(),
+        ),
+        (
+            "notes",
+            diagnostic
+                .notes
+                .iter()
+                .map(|note| JsValue::from_str(note))
+                .collect::<Array>()
+                .into(),
+        ),
+    ])
+}
+
+pub fn token_to_js(token: &ClassifiedToken) -> JsValue {
+    object(&[
+        ("class", token.class.name().into()),
+        ("start", token.start.into()),
+        ("end", token.end.into()),
+    ])
+}
+
+/// A JavaScript function that can be called from the interpreter.
+pub struct JsCallback(Function);
+
+// SAFETY: WebAssembly modules built without the `atomics` target feature are
+// single-threaded, so the function is never accessed from another thread.
+unsafe impl Send for JsCallback {}
+unsafe impl Sync for JsCallback {}
+
+impl JsCallback {
+    pub fn new(function: Function) -> Self {
+        JsCallback(function)
+    }
+
+    /// Call the function and return its result if it is a string. Exceptions
+    /// and all other return values are mapped to `None`.
+    pub fn call(&self, args: &[&str]) -> Option<String> {
+        let args: Array = args.iter().map(|arg| JsValue::from_str(arg)).collect();
+        self.0.apply(&JsValue::NULL, &args).ok()?.as_string()
+    }
+}
+
+/// Loads modules by calling a JavaScript function with the module path
+/// (e.g. `"physics::constants"`), which returns the code of the module, or
+/// `undefined` if there is no such module.
+pub struct JsModuleImporter {
+    resolver: JsCallback,
+}
+
+impl JsModuleImporter {
+    pub fn new(resolver: Function) -> Self {
+        JsModuleImporter {
+            resolver: JsCallback::new(resolver),
+        }
+    }
+}
+
+impl ModuleImporter for JsModuleImporter {
+    fn import(&self, path: &ModulePath) -> Option<(String, Option<PathBuf>)> {
+        self.resolver
+            .call(&[&path.to_string()])
+            .map(|code| (code, None))
+    }
+
+    fn list_modules(&self) -> Vec<ModulePath> {
+        vec![]
+    }
+}
@@ -1,21 +1,27 @@
 mod jquery_terminal_formatter;
+mod js_api;
 mod utils;
 
-use std::sync::{Arc, Mutex};
 use wasm_bindgen::prelude::*;
 
+use js_sys::{Array, Function};
+
 use numbat::buffered_writer::BufferedWriter;
-use numbat::diagnostic::ErrorDiagnostic;
+use numbat::build_info::BuildInfo;
+use numbat::diagnostic::{ErrorDiagnostic, StructuredDiagnostic};
 use numbat::help::help_markup;
 use numbat::html_formatter::{HtmlFormatter, HtmlWriter};
-use numbat::markup::Formatter;
-use numbat::module_importer::BuiltinModuleImporter;
+use numbat::locale::Locale;
+use numbat::markup::{Formatter, PlainTextFormatter};
+use numbat::module_importer::{BuiltinModuleImporter, ChainedImporter};
+use numbat::output::BufferingSink;
 use numbat::pretty_print::PrettyPrint;
 use numbat::resolver::CodeSource;
-use numbat::{markup as m, NameResolutionError, NumbatError};
-use numbat::{Context, InterpreterSettings};
+use numbat::NumbatError;
+use numbat::{Context, InterpreterResult};
 
 use jquery_terminal_formatter::{JqueryTerminalFormatter, JqueryTerminalWriter};
+use js_api::{JsCallback, JsModuleImporter};
 
 #[wasm_bindgen]
 pub fn setup_panic_hook() {
@@ -29,6 +35,63 @@ pub enum FormatType {
     Html,
 }
 
+#[wasm_bindgen]
+pub struct Settings {
+    pub load_prelude: bool,
+    pub enable_pretty_printing: bool,
+    pub format_type: FormatType,
+    /// Width (in characters) of the output area, used to lay out lists
+    pub terminal_width: Option<usize>,
+    /// Show units with their long names, e.g. `meter` instead of `m`
+    pub long_units: bool,
+    /// Simplify results to units with at most this many factors (0: no limit)
+    pub max_unit_complexity: u32,
+    locale: Locale,
+    module_resolver: Option<Function>,
+}
+
+#[wasm_bindgen]
+impl Settings {
+    #[wasm_bindgen(constructor)]
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Settings {
+            load_prelude: true,
+            enable_pretty_printing: false,
+            format_type: FormatType::Html,
+            terminal_width: None,
+            long_units: false,
+            max_unit_complexity: 0,
+            locale: Locale::c(),
+            module_resolver: None,
+        }
+    }
+
+    /// Write numbers with the given decimal mark (`.` or `,`) and grouping
+    /// separator, like the `[locale]` section of the CLI configuration. Fails if
+    /// the combination is not supported.
+    pub fn set_locale(
+        &mut self,
+        decimal_mark: char,
+        grouping_separator: char,
+        decimal_comma_input: bool,
+        ascii_exponents: bool,
+    ) -> Result<(), JsError> {
+        self.locale = Locale::new(decimal_mark, grouping_separator)
+            .map_err(|e| JsError::new(&e.to_string()))?
+            .with_decimal_comma_input(decimal_comma_input)
+            .with_ascii_exponents(ascii_exponents);
+        Ok(())
+    }
+
+    /// Look up modules by calling `resolver` with the module path (e.g.
+    /// `"physics::constants"`). It should return the code of the module, or
+    /// `undefined` to fall back to the builtin modules.
+    pub fn set_module_resolver(&mut self, resolver: Function) {
+        self.module_resolver = Some(resolver);
+    }
+}
+
 #[wasm_bindgen]
 pub struct Numbat {
     ctx: Context,
@@ -54,18 +117,40 @@ impl InterpreterOutput {
 #[wasm_bindgen]
 impl Numbat {
     pub fn new(load_prelude: bool, enable_pretty_printing: bool, format_type: FormatType) -> Self {
-        let mut ctx = Context::new(BuiltinModuleImporter::default());
-        if load_prelude {
+        Self::with_settings(Settings {
+            load_prelude,
+            enable_pretty_printing,
+            format_type,
+            terminal_width: Some(84), // terminal width with current layout
+            ..Settings::new()
+        })
+    }
+
+    /// Create a new instance. The context is kept across calls to interpret,
+    /// so definitions from earlier calls stay available.
+    pub fn with_settings(settings: Settings) -> Self {
+        let mut ctx = match settings.module_resolver {
+            Some(resolver) => Context::new(ChainedImporter::new(
+                Box::new(JsModuleImporter::new(resolver)),
+                Box::<BuiltinModuleImporter>::default(),
+            )),
+            None => Context::new(BuiltinModuleImporter::default()),
+        };
+        if settings.load_prelude {
             let _ = ctx.interpret("use prelude", CodeSource::Internal).unwrap();
         }
-        ctx.set_terminal_width(Some(84)); // terminal width with current layout
+        ctx.set_terminal_width(settings.terminal_width);
+        ctx.set_locale(settings.locale);
+        ctx.set_long_unit_names(settings.long_units);
+        ctx.set_max_unit_complexity(Some(settings.max_unit_complexity).filter(|&max| max > 0));
         Numbat {
             ctx,
-            enable_pretty_printing,
-            format_type,
+            enable_pretty_printing: settings.enable_pretty_printing,
+            format_type: settings.format_type,
         }
     }
 
+    #[cfg(feature = "currency")]
     pub fn set_exchange_rates(&mut self, xml_content: &str) {
         Context::set_exchange_rates(xml_content);
         let _ = self
@@ -74,6 +159,16 @@ impl Numbat {
             .unwrap();
     }
 
+    /// Get the exchange rates by calling `provider` (without arguments) when
+    /// they are needed for the first time. It should return the XML content of
+    /// the European Central Bank exchange rates, or `undefined`.
+    #[cfg(feature = "currency")]
+    pub fn set_exchange_rate_provider(&mut self, provider: Function) {
+        let provider = JsCallback::new(provider);
+        Context::set_exchange_rates_provider(move || provider.call(&[]));
+        self.ctx.load_currency_module_on_demand(true);
+    }
+
     fn format(&self, markup: &numbat::markup::Markup, indent: bool) -> String {
         let fmt: Box<dyn Formatter> = match self.format_type {
             FormatType::JqueryTerminal => Box::new(JqueryTerminalFormatter {}),
@@ -85,13 +180,7 @@ impl Numbat {
     pub fn interpret(&mut self, code: &str) -> InterpreterOutput {
         let mut output = String::new();
 
-        let to_be_printed: Arc<Mutex<Vec<m::Markup>>> = Arc::new(Mutex::new(vec![]));
-        let to_be_printed_c = to_be_printed.clone();
-        let mut settings = InterpreterSettings {
-            print_fn: Box::new(move |s: &m::Markup| {
-                to_be_printed_c.lock().unwrap().push(s.clone());
-            }),
-        };
+        let mut to_be_printed = BufferingSink::default();
 
         let nl = &self.format(&numbat::markup::nl(), false);
 
@@ -102,33 +191,32 @@ impl Numbat {
 
         match self
             .ctx
-            .interpret_with_settings(&mut settings, code, CodeSource::Text)
+            .interpret_with_sink(&mut to_be_printed, code, CodeSource::Text)
         {
             Ok((statements, result)) => {
                 // Pretty print
                 if self.enable_pretty_printing {
                     output.push_str(nl);
                     for statement in &statements {
-                        output
-                            .push_str(&self.format(&statement.pretty_print(), enable_indentation));
+                        let markup = self.ctx.locale().localize(&statement.pretty_print());
+                        output.push_str(&self.format(&markup, enable_indentation));
                         output.push_str(nl);
                     }
                     output.push_str(nl);
                 }
 
                 // print(…) and type(…) results
-                let to_be_printed = to_be_printed.lock().unwrap();
-                for content in to_be_printed.iter() {
+                for content in to_be_printed.printed() {
                     output.push_str(&self.format(content, enable_indentation));
                     output.push_str(nl);
                 }
 
-                let result_markup = result.to_markup(
+                let result_markup = self.ctx.locale().localize(&self.ctx.result_to_markup(
+                    &result,
                     statements.last(),
-                    &self.ctx.dimension_registry().clone(),
                     true,
                     true,
-                );
+                ));
                 output.push_str(&self.format(&result_markup, enable_indentation));
 
                 InterpreterOutput {
@@ -137,15 +225,80 @@ impl Numbat {
                 }
             }
             Err(NumbatError::ResolverError(e)) => self.print_diagnostic(&e),
-            Err(NumbatError::NameResolutionError(
-                e @ (NameResolutionError::IdentifierClash { .. }
-                | NameResolutionError::ReservedIdentifier(_)),
-            )) => self.print_diagnostic(&e),
+            Err(NumbatError::NameResolutionError(e)) => self.print_diagnostic(&e),
             Err(NumbatError::TypeCheckError(e)) => self.print_diagnostic(&e),
             Err(NumbatError::RuntimeError(e)) => self.print_diagnostic(&e),
         }
     }
 
+    /// Interpret `code` and return the result as a plain object:
+    ///
+    /// - `is_error`: whether an error occurred
+    /// - `value`: `null` or `{ kind, display, magnitude, unit }` (the latter two
+    ///   are only set for quantities)
+    /// - `type`: the type of the result, e.g. `"Length / Time"`, or `null`
+    /// - `printed`: output of `print` calls, as plain text
+    /// - `diagnostics`: errors, with byte offsets into `code`
+    pub fn evaluate(&mut self, code: &str) -> JsValue {
+        let mut to_be_printed = BufferingSink::default();
+        let result = self
+            .ctx
+            .interpret_with_sink(&mut to_be_printed, code, CodeSource::Text);
+
+        let printed: Array = to_be_printed
+            .printed()
+            .map(|markup| JsValue::from_str(&PlainTextFormatter {}.format(markup, false)))
+            .collect();
+
+        let (value, type_, diagnostics) = match result {
+            Ok((statements, InterpreterResult::Value(value))) => {
+                let type_ = statements
+                    .last()
+                    .and_then(|statement| {
+                        statement.readable_expression_type(self.ctx.dimension_registry())
+                    })
+                    .map(|type_| type_.to_string());
+                let display = PlainTextFormatter {}.format(&value.pretty_print(), false);
+                (
+                    js_api::value_to_js(&value, display),
+                    type_.map_or(JsValue::NULL, JsValue::from),
+                    Array::new(),
+                )
+            }
+            Ok((_, InterpreterResult::Continue)) => (JsValue::NULL, JsValue::NULL, Array::new()),
+            Err(error) => {
+                let files = &self.ctx.resolver().files;
+                let diagnostics = self
+                    .ctx
+                    .diagnostics(&error)
+                    .iter()
+                    .map(|diagnostic| {
+                        js_api::diagnostic_to_js(&StructuredDiagnostic::new(diagnostic, files))
+                    })
+                    .collect();
+                (JsValue::NULL, JsValue::NULL, diagnostics)
+            }
+        };
+
+        js_api::object(&[
+            ("is_error", (diagnostics.length() > 0).into()),
+            ("value", value),
+            ("type", type_),
+            ("printed", printed.into()),
+            ("diagnostics", diagnostics.into()),
+        ])
+    }
+
+    /// Classify the tokens in `code` for syntax highlighting. Returns an array
+    /// of `{ class, start, end }` objects with byte offsets into `code`.
+    pub fn classify_tokens(&self, code: &str) -> Vec<JsValue> {
+        self.ctx
+            .classify_tokens(code)
+            .iter()
+            .map(js_api::token_to_js)
+            .collect()
+    }
+
     pub fn print_environment(&self) -> JsValue {
         self.format(&self.ctx.print_environment(), false).into()
     }
@@ -170,6 +323,10 @@ impl Numbat {
         self.format(&help_markup(), true).into()
     }
 
+    pub fn build_info(&self) -> JsValue {
+        self.format(&BuildInfo::collect().to_markup(), false).into()
+    }
+
     pub fn print_info(&mut self, keyword: &str) -> JsValue {
         let output = self.ctx.print_info_for_keyword(keyword);
         self.format(&output, true).into()
@@ -193,8 +350,14 @@ impl Numbat {
 
         let resolver = self.ctx.resolver();
 
-        for diagnostic in error.diagnostics() {
-            term::emit(&mut writer, &config, &resolver.files, &diagnostic).unwrap();
+        for diagnostic in self.ctx.diagnostics(error) {
+            term::emit(
+                &mut writer,
+                &config,
+                &resolver.files,
+                &diagnostic.rendered(),
+            )
+            .unwrap();
         }
 
         InterpreterOutput {
@@ -1,6 +1,8 @@
 #![cfg(target_arch = "wasm32")]
 
-use numbat_wasm::{FormatType, Numbat};
+use js_sys::{Array, Function, Reflect};
+use numbat_wasm::{FormatType, Numbat, Settings};
+use wasm_bindgen::JsValue;
 use wasm_bindgen_test::*;
 
 wasm_bindgen_test_configure!(run_in_browser);
@@ -13,3 +15,83 @@ fn basic() {
         r#"<span class="numbat-operator">=</span> <span class="numbat-value">14</span>"#
     );
 }
+
+fn field(object: &JsValue, key: &str) -> JsValue {
+    Reflect::get(object, &JsValue::from_str(key)).unwrap()
+}
+
+#[wasm_bindgen_test]
+fn evaluate_returns_structured_values() {
+    let mut numbat = Numbat::with_settings(Settings::new());
+
+    let result = numbat.evaluate("2 km / 4 s");
+    assert_eq!(field(&result, "is_error"), false);
+    assert_eq!(field(&result, "type").as_string().unwrap(), "Velocity");
+
+    let value = field(&result, "value");
+    assert_eq!(field(&value, "kind").as_string().unwrap(), "quantity");
+    assert_eq!(field(&value, "magnitude").as_f64().unwrap(), 0.5);
+    assert_eq!(field(&value, "unit").as_string().unwrap(), "km/s");
+
+    let result = numbat.evaluate("let x = 3\nprint(x)");
+    assert!(field(&result, "value").is_null());
+    let printed: Array = field(&result, "printed").into();
+    assert_eq!(printed.get(0).as_string().unwrap(), "3");
+}
+
+#[wasm_bindgen_test]
+fn evaluate_returns_diagnostics() {
+    let mut numbat = Numbat::with_settings(Settings::new());
+
+    let result = numbat.evaluate("1 + foo");
+    assert_eq!(field(&result, "is_error"), true);
+
+    let diagnostics: Array = field(&result, "diagnostics").into();
+    assert_eq!(diagnostics.length(), 1);
+    let diagnostic = diagnostics.get(0);
+    assert_eq!(field(&diagnostic, "code").as_string().unwrap(), "E0501");
+
+    let span = field(&diagnostic, "primary_span");
+    assert_eq!(field(&span, "byte_start").as_f64().unwrap(), 4.0);
+    assert_eq!(field(&span, "byte_end").as_f64().unwrap(), 7.0);
+}
+
+#[wasm_bindgen_test]
+fn module_resolver_callback() {
+    let mut settings = Settings::new();
+    settings.load_prelude = false;
+    settings.set_module_resolver(Function::new_with_args(
+        "path",
+        "return path === 'my::module' ? 'let answer = 42' : undefined",
+    ));
+    let mut numbat = Numbat::with_settings(settings);
+
+    let result = numbat.evaluate("use my::module\nanswer");
+    assert_eq!(
+        field(&field(&result, "value"), "magnitude")
+            .as_f64()
+            .unwrap(),
+        42.0
+    );
+
+    let result = numbat.evaluate("use other::module");
+    let diagnostics: Array = field(&result, "diagnostics").into();
+    assert_eq!(
+        field(&diagnostics.get(0), "code").as_string().unwrap(),
+        "E0301"
+    );
+}
+
+#[wasm_bindgen_test]
+fn settings_from_the_cli_configuration() {
+    let mut settings = Settings::new();
+    settings.long_units = true;
+    settings.set_locale(',', '.', false, false).unwrap();
+    let mut numbat = Numbat::with_settings(settings);
+
+    let output = numbat.interpret("1234.5 m").output();
+    assert!(output.contains("1.234,5"));
+    assert!(output.contains("meter"));
+
+    assert!(Settings::new().set_locale(',', ',', false, false).is_err());
+}
@@ -0,0 +1,33 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use numbat::module_importer::BuiltinModuleImporter;
+use numbat::resolver::CodeSource;
+use numbat::Context;
+
+/// Converting many values between the same pair of units only needs to compute
+/// the conversion factor once.
+fn convert_in_loop(c: &mut Criterion) {
+    let importer = BuiltinModuleImporter::default();
+    let mut context = Context::new(importer);
+    let _ = context
+        .interpret(
+            "use prelude\nfn to_kmh(v) = v mph -> km/h",
+            CodeSource::Internal,
+        )
+        .unwrap();
+
+    let mut group = c.benchmark_group("Conversions");
+    group.sample_size(10);
+    group.bench_function("1M mph to km/h", |b| {
+        b.iter_with_setup(
+            || context.clone(),
+            |mut ctx| {
+                ctx.interpret("sum(map(to_kmh, range(1, 1_000_000)))", CodeSource::Text)
+                    .unwrap()
+            },
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, convert_in_loop);
+criterion_main!(benches);
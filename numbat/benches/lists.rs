@@ -0,0 +1,68 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use numbat::module_importer::BuiltinModuleImporter;
+use numbat::resolver::CodeSource;
+use numbat::Context;
+
+/// Folding over a lazy range should run in constant memory, independent of the
+/// length of the range.
+fn fold_over_range(c: &mut Criterion) {
+    let importer = BuiltinModuleImporter::default();
+    let mut context = Context::new(importer);
+    let _ = context
+        .interpret("use prelude\nfn square(x) = x^2", CodeSource::Internal)
+        .unwrap();
+
+    let mut group = c.benchmark_group("Fold over range");
+    for n in [1_000, 10_000, 100_000] {
+        group.bench_with_input(BenchmarkId::from_parameter(n), &n, |b, n| {
+            b.iter_with_setup(
+                || context.clone(),
+                |mut ctx| {
+                    ctx.interpret(
+                        &format!("sum(map(square, range(1, {n})))"),
+                        CodeSource::Text,
+                    )
+                },
+            )
+        });
+    }
+    group.finish();
+}
+
+/// Mapping a pure function over a large list should scale with the number of threads.
+fn map_over_list(c: &mut Criterion) {
+    let importer = BuiltinModuleImporter::default();
+    let mut context = Context::new(importer);
+    let _ = context
+        .interpret(
+            "use prelude
+             fn wobble(x) = sqrt(x^2 + 1) × sin(x) + cos(x / 3)
+             let xs = reverse(range(1, 100_000))",
+            CodeSource::Internal,
+        )
+        .unwrap();
+
+    let mut group = c.benchmark_group("Map over list");
+    group.sample_size(10);
+    let max_threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    for num_threads in [1, 2, 4, 8].into_iter().filter(|&n| n <= max_threads) {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(num_threads),
+            &num_threads,
+            |b, &num_threads| {
+                b.iter_with_setup(
+                    || {
+                        let mut ctx = context.clone();
+                        ctx.set_num_threads(num_threads);
+                        ctx
+                    },
+                    |mut ctx| ctx.interpret("map(wobble, xs)", CodeSource::Text),
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, fold_over_range, map_over_list);
+criterion_main!(benches);
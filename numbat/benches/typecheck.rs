@@ -0,0 +1,89 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use numbat::module_importer::BuiltinModuleImporter;
+use numbat::resolver::CodeSource;
+use numbat::Context;
+
+/// Generate a program with `n` statements: variable definitions, (generic)
+/// function definitions and top-level expressions that refer to each other.
+fn generate_program(n: usize) -> String {
+    let mut code = String::from("let v0 = 1 m\nfn f0<D: Dim>(x: D, y: D) -> D = x + y\n");
+    let mut j = 0; // index of the last variable
+    for i in 1..n {
+        let statement = match i % 10 {
+            0 => format!(
+                "fn f{i}<D: Dim>(x: D, y: D) -> D = if x > y then f0(x, y) else -f0(y, x) + 0 x"
+            ),
+            k if k % 2 == 0 => format!("f0(v{j}, 3 ft) / (v{j} + 1 inch) + len([1, 2, {i}])"),
+            _ => {
+                let definition =
+                    format!("let v{i} = (v{j} + {i} cm) × (2 + 3 / {i}) - sqrt(v{j}^2 + 4 mm^2)");
+                j = i;
+                definition
+            }
+        };
+        code.push_str(&statement);
+        code.push('\n');
+    }
+    code
+}
+
+/// Generate `n` expressions that combine many different units, so that most of the
+/// time is spent looking up and merging base representations.
+fn generate_unit_expressions(n: usize) -> String {
+    let units = [
+        "m", "s", "kg", "A", "K", "mol", "cd", "N", "J", "W", "Pa", "V",
+    ];
+    let mut code = String::new();
+    for i in 0..n {
+        let a = units[i % units.len()];
+        let b = units[(i / 2 + 5) % units.len()];
+        let c = units[(i / 3 + 7) % units.len()];
+        code.push_str(&format!(
+            "({i} {a} × 2 {b}^2 / 3 {c}) × (4 {c} / {b}) + 5 {a} × {b} / {b}^0 × {c} / {c}\n"
+        ));
+    }
+    code
+}
+
+fn typecheck_large_program(c: &mut Criterion) {
+    let importer = BuiltinModuleImporter::default();
+    let mut context = Context::new(importer);
+    let _ = context
+        .interpret("use prelude", CodeSource::Internal)
+        .unwrap();
+
+    let code = generate_program(10_000);
+
+    let mut group = c.benchmark_group("Type check");
+    group.sample_size(10);
+    group.bench_function("10k statements", |b| {
+        b.iter_with_setup(
+            || context.clone(),
+            |mut ctx| ctx.interpret(&code, CodeSource::Text).unwrap(),
+        )
+    });
+    group.finish();
+}
+
+fn typecheck_unit_expressions(c: &mut Criterion) {
+    let importer = BuiltinModuleImporter::default();
+    let mut context = Context::new(importer);
+    let _ = context
+        .interpret("use prelude", CodeSource::Internal)
+        .unwrap();
+
+    let code = generate_unit_expressions(2_000);
+
+    let mut group = c.benchmark_group("Type check");
+    group.sample_size(10);
+    group.bench_function("2k unit expressions", |b| {
+        b.iter_with_setup(
+            || context.clone(),
+            |mut ctx| ctx.interpret(&code, CodeSource::Text).unwrap(),
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, typecheck_large_program, typecheck_unit_expressions);
+criterion_main!(benches);
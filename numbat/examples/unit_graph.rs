@@ -29,7 +29,7 @@ fn main() {
         (
             u,
             b.0.iter()
-                .map(|BaseRepresentationFactor(name, exp)| (name.clone(), exp.to_integer()))
+                .map(|BaseRepresentationFactor(name, exp)| (*name, exp.to_integer()))
                 .collect::<Vec<_>>(),
         )
     })
@@ -0,0 +1,11 @@
+#![no_main]
+
+use std::sync::OnceLock;
+
+use libfuzzer_sys::fuzz_target;
+use numbat::Context;
+
+fuzz_target!(|data: &[u8]| {
+    static CONTEXT: OnceLock<Context> = OnceLock::new();
+    numbat_fuzz::check(CONTEXT.get_or_init(numbat_fuzz::prelude_context), data);
+});
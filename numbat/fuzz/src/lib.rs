@@ -0,0 +1,28 @@
+//! The code that is run for each input of the fuzz targets. It is also compiled
+//! as part of the test suite of `numbat` (see `tests/fuzz_harness.rs`), such that
+//! it does not break without anyone noticing.
+
+use numbat::{module_importer::BuiltinModuleImporter, resolver::CodeSource, Context};
+
+/// A context with the prelude loaded, to be cloned for every input
+pub fn prelude_context() -> Context {
+    let mut context = Context::new(BuiltinModuleImporter::default());
+    let _ = context
+        .interpret("use prelude", CodeSource::Internal)
+        .expect("The prelude can be loaded");
+    context
+}
+
+/// Tokenize, parse and type check the input. Errors are fine, any panic (or stack
+/// overflow) is a bug.
+pub fn check(context: &Context, data: &[u8]) {
+    let Ok(code) = std::str::from_utf8(data) else {
+        return;
+    };
+
+    let mut context = context.clone();
+    if let Err(error) = context.check(code, CodeSource::Text) {
+        let _ = error.to_string();
+        let _ = context.diagnostics(&error);
+    }
+}
@@ -2,7 +2,7 @@ use crate::markup as m;
 use crate::span::Span;
 use crate::{
     arithmetic::Exponent, decorator::Decorator, markup::Markup, number::Number, prefix::Prefix,
-    pretty_print::PrettyPrint, resolver::ModulePath,
+    pretty_print::PrettyPrint, resolver::ModulePath, symbol::Symbol,
 };
 use itertools::Itertools;
 use num_traits::Signed;
@@ -21,7 +21,6 @@ pub enum BinaryOperator {
     Mul,
     Div,
     Power,
-    ConvertTo,
     LessThan,
     GreaterThan,
     LessOrEqual,
@@ -32,6 +31,24 @@ pub enum BinaryOperator {
     LogicalOr,
 }
 
+impl BinaryOperator {
+    /// `<`, `>`, `<=`, `>=`, `==` and `!=`
+    pub fn is_comparison(&self) -> bool {
+        self.is_ordering() || matches!(self, BinaryOperator::Equal | BinaryOperator::NotEqual)
+    }
+
+    /// Comparisons that require ordered operands (`<`, `>`, `<=` and `>=`)
+    pub fn is_ordering(&self) -> bool {
+        matches!(
+            self,
+            BinaryOperator::LessThan
+                | BinaryOperator::GreaterThan
+                | BinaryOperator::LessOrEqual
+                | BinaryOperator::GreaterOrEqual
+        )
+    }
+}
+
 impl PrettyPrint for BinaryOperator {
     fn pretty_print(&self) -> Markup {
         use BinaryOperator::*;
@@ -42,7 +59,6 @@ impl PrettyPrint for BinaryOperator {
             Mul => m::space() + m::operator("×") + m::space(),
             Div => m::space() + m::operator("/") + m::space(),
             Power => m::operator("^"),
-            ConvertTo => m::space() + m::operator("➞") + m::space(),
             LessThan => m::space() + m::operator("<") + m::space(),
             GreaterThan => m::space() + m::operator(">") + m::space(),
             LessOrEqual => m::space() + m::operator("≤") + m::space(),
@@ -55,6 +71,37 @@ impl PrettyPrint for BinaryOperator {
     }
 }
 
+/// How a function call was written in the source code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallSyntax {
+    /// `f(x, y)`
+    Regular,
+    /// `y |> f(x)`
+    Pipe,
+}
+
+/// The right hand side of a conversion `source -> target`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConversionTarget {
+    /// A unit expression like `km/h`, a unit system like `SI`, or a function like
+    /// `hex`. These can only be told apart by the type checker.
+    Expression(Box<Expression>),
+    /// A list of units like `[foot, inch]`, for a mixed-unit result
+    UnitList(Span, Vec<Expression>),
+}
+
+impl ConversionTarget {
+    pub fn full_span(&self) -> Span {
+        match self {
+            ConversionTarget::Expression(expr) => expr.full_span(),
+            ConversionTarget::UnitList(span, _) => *span,
+        }
+    }
+}
+
+/// A named argument `name = expr` in a function call, together with the span of the name
+pub type NamedArgument = (Span, Symbol, Expression);
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum StringPart {
     Fixed(String),
@@ -65,11 +112,11 @@ pub enum StringPart {
     },
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, PartialEq)]
 pub enum Expression {
     Scalar(Span, Number),
-    Identifier(Span, String),
-    UnitIdentifier(Span, Prefix, String, String),
+    Identifier(Span, Symbol),
+    UnitIdentifier(Span, Prefix, Symbol, Symbol),
     TypedHole(Span),
     UnaryOperator {
         op: UnaryOperator,
@@ -82,20 +129,89 @@ pub enum Expression {
         rhs: Box<Expression>,
         span_op: Option<Span>, // not available for implicit multiplication and unicode exponents
     },
-    FunctionCall(Span, Span, Box<Expression>, Vec<Expression>),
+    /// A conversion `source -> target`. Chains like `a -> b -> c` are applied
+    /// from left to right, i.e. the result of `a -> b` is converted to `c`.
+    Conversion {
+        source: Box<Expression>,
+        target: ConversionTarget,
+        span_op: Span,
+        /// Whether the source is a binary operation that is written in parentheses,
+        /// like `(x + y) -> unit`. Without them, `->` has the lowest precedence, so
+        /// `x + y -> unit` converts the whole sum as well, but might not have been
+        /// meant that way.
+        parenthesized_source: bool,
+    },
+    /// Two or more ordering comparisons in a row, like `0 m < x <= 10 m`: the
+    /// first operand, followed by all operators (with their spans) and the
+    /// operands on their right hand side.
+    ChainedComparison(Box<Expression>, Vec<(Span, BinaryOperator, Expression)>),
+    FunctionCall(
+        Span,
+        Span,
+        Box<Expression>,
+        Vec<Expression>,
+        Vec<NamedArgument>,
+        CallSyntax,
+    ),
     Boolean(Span, bool),
     String(Span, Vec<StringPart>),
     Condition(Span, Box<Expression>, Box<Expression>, Box<Expression>),
     InstantiateStruct {
         full_span: Span,
         ident_span: Span,
-        name: String,
-        fields: Vec<(Span, String, Expression)>,
+        name: Symbol,
+        /// The struct instance that is being copied in an update expression,
+        /// `Name { base with field = value }`.
+        base: Option<Box<Expression>>,
+        fields: Vec<(Span, Symbol, Expression)>,
     },
-    AccessField(Span, Span, Box<Expression>, String),
+    AccessField(Span, Span, Box<Expression>, Symbol),
     List(Span, Vec<Expression>),
+    /// `trace(expr)`: full span, source code of the argument, argument
+    Trace(Span, String, Box<Expression>),
+    /// `partial(f, args…)`: full span, function, leading arguments and named
+    /// arguments that are bound to `f`
+    Partial(Span, Box<Expression>, Vec<Expression>, Vec<NamedArgument>),
+    /// `autodiff(f)`: full span, function that is differentiated
+    AutoDiff(Span, Box<Expression>),
+    /// `is_dimensionless(x)` or `has_dimension(x, D)`: full span, argument and the
+    /// dimension (`None` for `is_dimensionless`). Both are decided by the type
+    /// checker, so they can be used to branch on the dimension in generic functions.
+    DimensionPredicate(Span, Box<Expression>, Option<TypeExpression>),
+    /// `for x in xs { body }` evaluates to the list of all values of the body. With
+    /// an accumulator, `for x in xs with acc = init { body }`, the body computes the
+    /// next value of `acc`, and the loop evaluates to the last one.
+    For {
+        full_span: Span,
+        variable: (Span, Symbol),
+        list: Box<Expression>,
+        accumulator: Option<Accumulator>,
+        body: Box<Expression>,
+    },
+    /// `while condition with acc = init { body }` computes the next value of `acc`
+    /// as long as the condition holds, and evaluates to the last one.
+    While {
+        full_span: Span,
+        condition: Box<Expression>,
+        accumulator: Accumulator,
+        body: Box<Expression>,
+    },
+    /// `try { expr } catch message { fallback }` evaluates to `fallback` if `expr`
+    /// raises an error. The name for the error message is optional.
+    TryCatch {
+        full_span: Span,
+        expr: Box<Expression>,
+        message: Option<(Span, Symbol)>,
+        fallback: Box<Expression>,
+    },
 }
 
+/// `with acc = init` in a loop: span of the name, name, initial value
+pub type Accumulator = (Span, Symbol, Box<Expression>);
+
+/// An operator in a chain like `a + b - c`, with its span and right hand side
+pub(crate) type ChainLink<E> = (BinaryOperator, Option<Span>, E);
+
 impl Expression {
     pub fn full_span(&self) -> Span {
         match self {
@@ -107,19 +223,23 @@ impl Expression {
                 expr,
                 span_op,
             } => span_op.extend(&expr.full_span()),
-            Expression::BinaryOperator {
-                op: _,
-                lhs,
-                rhs,
-                span_op,
-            } => {
-                let mut span = lhs.full_span().extend(&rhs.full_span());
-                if let Some(span_op) = span_op {
-                    span = span.extend(span_op);
-                }
-                span
+            Expression::BinaryOperator { .. } => {
+                let (first, operands) = self.binary_operator_chain();
+                operands
+                    .into_iter()
+                    .fold(first.full_span(), |span, (_, span_op, rhs)| {
+                        let span = span.extend(&rhs.full_span());
+                        span_op.map_or(span, |span_op| span.extend(&span_op))
+                    })
+            }
+            Expression::Conversion { source, target, .. } => {
+                source.full_span().extend(&target.full_span())
+            }
+            Expression::ChainedComparison(first, rest) => {
+                let last = &rest.last().expect("at least one comparison").2;
+                first.full_span().extend(&last.full_span())
             }
-            Expression::FunctionCall(_identifier_span, full_span, _, _) => *full_span,
+            Expression::FunctionCall(_identifier_span, full_span, _, _, _, _) => *full_span,
             Expression::Boolean(span, _) => *span,
             Expression::Condition(span_if, _, _, then_expr) => {
                 span_if.extend(&then_expr.full_span())
@@ -129,6 +249,303 @@ impl Expression {
             Expression::AccessField(full_span, _ident_span, _, _) => *full_span,
             Expression::List(span, _) => *span,
             Expression::TypedHole(span) => *span,
+            Expression::Trace(span, _, _) => *span,
+            Expression::Partial(span, _, _, _) => *span,
+            Expression::AutoDiff(span, _) => *span,
+            Expression::DimensionPredicate(span, _, _) => *span,
+            Expression::For { full_span, .. }
+            | Expression::While { full_span, .. }
+            | Expression::TryCatch { full_span, .. } => *full_span,
+        }
+    }
+
+    /// The operands of a chain of binary operators like `a + b - c`, which is
+    /// parsed as a left-leaning tree `(a + b) - c`: the first operand and then
+    /// the operators with their right hand sides, from left to right. Long
+    /// chains are traversed with this instead of recursing into every left
+    /// hand side. Any other expression is a chain without operators.
+    pub(crate) fn binary_operator_chain(&self) -> (&Expression, Vec<ChainLink<&Expression>>) {
+        let mut operands = vec![];
+        let mut current = self;
+        while let Expression::BinaryOperator {
+            op,
+            lhs,
+            rhs,
+            span_op,
+        } = current
+        {
+            operands.push((*op, *span_op, rhs.as_ref()));
+            current = lhs;
+        }
+        operands.reverse();
+        (current, operands)
+    }
+
+    /// Like [`Expression::binary_operator_chain`], for modifying the operands.
+    pub(crate) fn binary_operator_chain_mut(
+        &mut self,
+    ) -> (&mut Expression, Vec<ChainLink<&mut Expression>>) {
+        let mut operands = vec![];
+        let mut current = self;
+        loop {
+            match current {
+                Expression::BinaryOperator {
+                    op,
+                    lhs,
+                    rhs,
+                    span_op,
+                } => {
+                    operands.push((*op, *span_op, rhs.as_mut()));
+                    current = lhs;
+                }
+                first => {
+                    operands.reverse();
+                    return (first, operands);
+                }
+            }
+        }
+    }
+
+    /// Whether this expression or one of its sub-expressions satisfies `predicate`
+    pub(crate) fn contains(&self, predicate: &impl Fn(&Expression) -> bool) -> bool {
+        if predicate(self) {
+            return true;
+        }
+
+        fn any<'e>(
+            exprs: impl IntoIterator<Item = &'e Expression>,
+            predicate: &impl Fn(&Expression) -> bool,
+        ) -> bool {
+            exprs.into_iter().any(|expr| expr.contains(predicate))
+        }
+
+        match self {
+            Expression::Scalar(..)
+            | Expression::Identifier(..)
+            | Expression::UnitIdentifier(..)
+            | Expression::TypedHole(..)
+            | Expression::Boolean(..) => false,
+            Expression::UnaryOperator { expr, .. }
+            | Expression::AccessField(_, _, expr, _)
+            | Expression::Trace(_, _, expr)
+            | Expression::AutoDiff(_, expr)
+            | Expression::DimensionPredicate(_, expr, _) => expr.contains(predicate),
+            Expression::BinaryOperator { .. } => {
+                // Walk down long chains like `a + b + c + …` instead of recursing
+                // into every left hand side
+                let mut current = self;
+                while let Expression::BinaryOperator { lhs, rhs, .. } = current {
+                    if rhs.contains(predicate) {
+                        return true;
+                    }
+                    current = lhs;
+                    if !matches!(current, Expression::BinaryOperator { .. }) {
+                        break;
+                    }
+                    if predicate(current) {
+                        return true;
+                    }
+                }
+                current.contains(predicate)
+            }
+            Expression::Conversion { source, target, .. } => {
+                source.contains(predicate)
+                    || match target {
+                        ConversionTarget::Expression(target) => target.contains(predicate),
+                        ConversionTarget::UnitList(_, units) => any(units, predicate),
+                    }
+            }
+            Expression::ChainedComparison(first, rest) => {
+                first.contains(predicate) || any(rest.iter().map(|(_, _, e)| e), predicate)
+            }
+            Expression::FunctionCall(_, _, callable, args, named_args, _)
+            | Expression::Partial(_, callable, args, named_args) => {
+                callable.contains(predicate)
+                    || any(args, predicate)
+                    || any(named_args.iter().map(|(_, _, e)| e), predicate)
+            }
+            Expression::String(_, parts) => any(
+                parts.iter().filter_map(|part| match part {
+                    StringPart::Interpolation { expr, .. } => Some(expr.as_ref()),
+                    StringPart::Fixed(_) => None,
+                }),
+                predicate,
+            ),
+            Expression::Condition(_, condition, then, else_) => {
+                condition.contains(predicate)
+                    || then.contains(predicate)
+                    || else_.contains(predicate)
+            }
+            Expression::InstantiateStruct { base, fields, .. } => {
+                base.as_deref().is_some_and(|base| base.contains(predicate))
+                    || any(fields.iter().map(|(_, _, e)| e), predicate)
+            }
+            Expression::List(_, elements) => any(elements, predicate),
+            Expression::For {
+                list,
+                accumulator,
+                body,
+                ..
+            } => {
+                list.contains(predicate)
+                    || accumulator
+                        .as_ref()
+                        .is_some_and(|(_, _, init)| init.contains(predicate))
+                    || body.contains(predicate)
+            }
+            Expression::While {
+                condition,
+                accumulator: (_, _, init),
+                body,
+                ..
+            } => {
+                condition.contains(predicate)
+                    || init.contains(predicate)
+                    || body.contains(predicate)
+            }
+            Expression::TryCatch { expr, fallback, .. } => {
+                expr.contains(predicate) || fallback.contains(predicate)
+            }
+        }
+    }
+}
+
+impl Clone for Expression {
+    fn clone(&self) -> Self {
+        match self {
+            Expression::Scalar(span, n) => Expression::Scalar(*span, *n),
+            Expression::Identifier(span, name) => Expression::Identifier(*span, *name),
+            Expression::UnitIdentifier(span, prefix, name, full_name) => {
+                Expression::UnitIdentifier(*span, *prefix, *name, *full_name)
+            }
+            Expression::TypedHole(span) => Expression::TypedHole(*span),
+            Expression::UnaryOperator { op, expr, span_op } => Expression::UnaryOperator {
+                op: *op,
+                expr: expr.clone(),
+                span_op: *span_op,
+            },
+            Expression::BinaryOperator { .. } => {
+                // Rebuild long chains like `a + b + c + …` from the first operand,
+                // instead of recursing into every left hand side
+                let (first, operands) = self.binary_operator_chain();
+                operands
+                    .into_iter()
+                    .fold(first.clone(), |lhs, (op, span_op, rhs)| {
+                        Expression::BinaryOperator {
+                            op,
+                            lhs: Box::new(lhs),
+                            rhs: Box::new(rhs.clone()),
+                            span_op,
+                        }
+                    })
+            }
+            Expression::Conversion {
+                source,
+                target,
+                span_op,
+                parenthesized_source,
+            } => Expression::Conversion {
+                source: source.clone(),
+                target: target.clone(),
+                span_op: *span_op,
+                parenthesized_source: *parenthesized_source,
+            },
+            Expression::ChainedComparison(first, comparisons) => {
+                Expression::ChainedComparison(first.clone(), comparisons.clone())
+            }
+            Expression::FunctionCall(span, full_span, callee, args, named_args, syntax) => {
+                Expression::FunctionCall(
+                    *span,
+                    *full_span,
+                    callee.clone(),
+                    args.clone(),
+                    named_args.clone(),
+                    *syntax,
+                )
+            }
+            Expression::Boolean(span, value) => Expression::Boolean(*span, *value),
+            Expression::String(span, parts) => Expression::String(*span, parts.clone()),
+            Expression::Condition(span, condition, then_expr, else_expr) => Expression::Condition(
+                *span,
+                condition.clone(),
+                then_expr.clone(),
+                else_expr.clone(),
+            ),
+            Expression::InstantiateStruct {
+                full_span,
+                ident_span,
+                name,
+                base,
+                fields,
+            } => Expression::InstantiateStruct {
+                full_span: *full_span,
+                ident_span: *ident_span,
+                name: *name,
+                base: base.clone(),
+                fields: fields.clone(),
+            },
+            Expression::AccessField(full_span, ident_span, expr, field) => {
+                Expression::AccessField(*full_span, *ident_span, expr.clone(), *field)
+            }
+            Expression::List(span, elements) => Expression::List(*span, elements.clone()),
+            Expression::Trace(span, code, expr) => {
+                Expression::Trace(*span, code.clone(), expr.clone())
+            }
+            Expression::Partial(span, function, args, named_args) => {
+                Expression::Partial(*span, function.clone(), args.clone(), named_args.clone())
+            }
+            Expression::AutoDiff(span, function) => Expression::AutoDiff(*span, function.clone()),
+            Expression::DimensionPredicate(span, expr, dimension) => {
+                Expression::DimensionPredicate(*span, expr.clone(), dimension.clone())
+            }
+            Expression::For {
+                full_span,
+                variable,
+                list,
+                accumulator,
+                body,
+            } => Expression::For {
+                full_span: *full_span,
+                variable: *variable,
+                list: list.clone(),
+                accumulator: accumulator.clone(),
+                body: body.clone(),
+            },
+            Expression::While {
+                full_span,
+                condition,
+                accumulator,
+                body,
+            } => Expression::While {
+                full_span: *full_span,
+                condition: condition.clone(),
+                accumulator: accumulator.clone(),
+                body: body.clone(),
+            },
+            Expression::TryCatch {
+                full_span,
+                expr,
+                message,
+                fallback,
+            } => Expression::TryCatch {
+                full_span: *full_span,
+                expr: expr.clone(),
+                message: *message,
+                fallback: fallback.clone(),
+            },
+        }
+    }
+}
+
+impl Drop for Expression {
+    fn drop(&mut self) {
+        // Take long chains like `a + b + c + …` apart from the top, so that
+        // dropping them does not recurse into every left hand side
+        if let Expression::BinaryOperator { lhs, .. } = self {
+            let mut lhs = std::mem::replace(lhs.as_mut(), Expression::TypedHole(Span::dummy()));
+            while let Expression::BinaryOperator { lhs: next, .. } = &mut lhs {
+                lhs = std::mem::replace(next.as_mut(), Expression::TypedHole(Span::dummy()));
+            }
         }
     }
 }
@@ -199,6 +616,28 @@ macro_rules! binop {
     }};
 }
 
+#[cfg(test)]
+macro_rules! conversion {
+    ( $source:expr, $target:expr ) => {{
+        crate::ast::Expression::Conversion {
+            source: Box::new($source),
+            target: crate::ast::ConversionTarget::Expression(Box::new($target)),
+            span_op: Span::dummy(),
+            parenthesized_source: false,
+        }
+    }};
+}
+
+#[cfg(test)]
+macro_rules! chained_comparison {
+    ( $first:expr, $( $op:ident, $rhs:expr ),+ ) => {{
+        crate::ast::Expression::ChainedComparison(
+            Box::new($first),
+            vec![$((Span::dummy(), BinaryOperator::$op, $rhs)),+],
+        )
+    }};
+}
+
 #[cfg(test)]
 macro_rules! conditional {
     ( $cond:expr, $lhs:expr, $rhs: expr ) => {{
@@ -217,9 +656,10 @@ macro_rules! struct_ {
         crate::ast::Expression::InstantiateStruct {
             full_span: Span::dummy(),
             ident_span: Span::dummy(),
-            name: stringify!($name).to_owned(),
+            name: stringify!($name).into(),
+            base: None,
             fields: vec![
-                $((Span::dummy(), stringify!($field).to_owned(), $val)),*
+                $((Span::dummy(), stringify!($field).into(), $val)),*
             ]
         }
     }};
@@ -240,8 +680,12 @@ pub(crate) use binop;
 #[cfg(test)]
 pub(crate) use boolean;
 #[cfg(test)]
+pub(crate) use chained_comparison;
+#[cfg(test)]
 pub(crate) use conditional;
 #[cfg(test)]
+pub(crate) use conversion;
+#[cfg(test)]
 pub(crate) use factorial;
 #[cfg(test)]
 pub(crate) use identifier;
@@ -262,8 +706,13 @@ pub enum TypeAnnotation {
     Bool(Span),
     String(Span),
     DateTime(Span),
+    /// `Dynamic`, a value whose type is only checked at runtime
+    Dynamic(Span),
     Fn(Span, Vec<TypeAnnotation>, Box<TypeAnnotation>),
     List(Span, Box<TypeAnnotation>),
+    /// `D…` for the last parameter of a function, which takes one or more
+    /// arguments of type `D`. Inside of the function, it is a `List<D>`.
+    Variadic(Span, Box<TypeAnnotation>),
 }
 
 impl TypeAnnotation {
@@ -273,8 +722,10 @@ impl TypeAnnotation {
             TypeAnnotation::Bool(span) => *span,
             TypeAnnotation::String(span) => *span,
             TypeAnnotation::DateTime(span) => *span,
+            TypeAnnotation::Dynamic(span) => *span,
             TypeAnnotation::Fn(span, _, _) => *span,
             TypeAnnotation::List(span, _) => *span,
+            TypeAnnotation::Variadic(span, _) => *span,
         }
     }
 }
@@ -286,6 +737,7 @@ impl PrettyPrint for TypeAnnotation {
             TypeAnnotation::Bool(_) => m::type_identifier("Bool"),
             TypeAnnotation::String(_) => m::type_identifier("String"),
             TypeAnnotation::DateTime(_) => m::type_identifier("DateTime"),
+            TypeAnnotation::Dynamic(_) => m::type_identifier("Dynamic"),
             TypeAnnotation::Fn(_, parameter_types, return_type) => {
                 m::type_identifier("Fn")
                     + m::operator("[(")
@@ -307,6 +759,9 @@ impl PrettyPrint for TypeAnnotation {
                     + element_type.pretty_print()
                     + m::operator(">")
             }
+            TypeAnnotation::Variadic(_, element_type) => {
+                element_type.pretty_print() + m::operator("…")
+            }
         }
     }
 }
@@ -315,7 +770,7 @@ impl PrettyPrint for TypeAnnotation {
 
 pub enum TypeExpression {
     Unity(Span),
-    TypeIdentifier(Span, String),
+    TypeIdentifier(Span, Symbol),
     Multiply(Span, Box<TypeExpression>, Box<TypeExpression>),
     Divide(Span, Box<TypeExpression>, Box<TypeExpression>),
     Power(
@@ -386,17 +841,57 @@ pub enum ProcedureKind {
     Assert,
     AssertEq,
     Type,
+    ConversionCacheStats,
+    RegexCacheStats,
+    InstantiationCacheStats,
+    SetFixedTime,
+    SetImplicitConversions,
+    WriteText,
+    AppendCsv,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum TypeParameterBound {
     Dim,
+    Ord,
+}
+
+/// The left hand side of a variable definition
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Identifier(Span, Symbol),
+    /// Binds fields of a struct, like `{x, y: height}`. A field without an
+    /// explicit pattern is bound to a variable of the same name.
+    Struct(Span, Vec<(Span, Symbol, Pattern)>),
+    /// Binds the elements of a list of known length, like `[a, b, c]`
+    List(Span, Vec<Pattern>),
+}
+
+impl Pattern {
+    pub fn full_span(&self) -> Span {
+        match self {
+            Pattern::Identifier(span, _) | Pattern::Struct(span, _) | Pattern::List(span, _) => {
+                *span
+            }
+        }
+    }
+
+    /// All identifiers that are introduced by this pattern, from left to right
+    pub fn identifiers(&self) -> Vec<(Span, Symbol)> {
+        match self {
+            Pattern::Identifier(span, identifier) => vec![(*span, *identifier)],
+            Pattern::Struct(_, fields) => fields
+                .iter()
+                .flat_map(|(_, _, pattern)| pattern.identifiers())
+                .collect(),
+            Pattern::List(_, elements) => elements.iter().flat_map(Pattern::identifiers).collect(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct DefineVariable {
-    pub identifier_span: Span,
-    pub identifier: String,
+    pub pattern: Pattern,
     pub expr: Expression,
     pub type_annotation: Option<TypeAnnotation>,
     pub decorators: Vec<Decorator>,
@@ -408,40 +903,159 @@ pub enum Statement {
     DefineVariable(DefineVariable),
     DefineFunction {
         function_name_span: Span,
-        function_name: String,
-        type_parameters: Vec<(Span, String, Option<TypeParameterBound>)>,
-        /// Parameters, optionally with type annotations.
-        parameters: Vec<(Span, String, Option<TypeAnnotation>)>,
+        function_name: Symbol,
+        type_parameters: Vec<(Span, Symbol, Option<TypeParameterBound>)>,
+        /// Parameters, optionally with type annotations and default values.
+        parameters: Vec<(Span, Symbol, Option<TypeAnnotation>, Option<Expression>)>,
         /// Function body. If it is absent, the function is implemented via FFI
         body: Option<Expression>,
         /// Local variables
         local_variables: Vec<DefineVariable>,
+        /// Unit and dimension definitions from the `where` clause, which are only
+        /// visible inside the function
+        local_units: Vec<Statement>,
         /// Optional annotated return type
         return_type_annotation: Option<TypeAnnotation>,
         decorators: Vec<Decorator>,
     },
-    DefineDimension(Span, String, Vec<TypeExpression>),
-    DefineBaseUnit(Span, String, Option<TypeExpression>, Vec<Decorator>),
+    DefineDimension(Span, Symbol, Vec<TypeExpression>),
+    DefineBaseUnit(Span, Symbol, Option<TypeExpression>, Vec<Decorator>),
     DefineDerivedUnit {
         identifier_span: Span,
-        identifier: String,
+        identifier: Symbol,
         expr: Expression,
         type_annotation_span: Option<Span>,
         type_annotation: Option<TypeAnnotation>,
         decorators: Vec<Decorator>,
     },
     ProcedureCall(Span, ProcedureKind, Vec<Expression>),
-    ModuleImport(Span, ModulePath),
+    /// `if … then print(…)`, where both branches are procedure calls or nested
+    /// conditional statements. In contrast to an `if-then-else` expression, no
+    /// value is produced, so the `else` branch is optional.
+    Conditional {
+        span_if: Span,
+        condition: Expression,
+        then_statement: Box<Statement>,
+        else_statement: Option<Box<Statement>>,
+    },
+    /// `use foo::bar`, or `use foo::bar::{baz, qux}` to import only some items
+    ModuleImport(Span, ModulePath, Option<Vec<(Span, Symbol)>>),
+    /// Inserted by the resolver around the code of a module that has been imported
+    /// selectively, see [`ImportScope`]
+    ImportScope(Span, ImportScope),
     DefineStruct {
         struct_name_span: Span,
-        struct_name: String,
-        fields: Vec<(Span, String, TypeAnnotation)>,
+        struct_name: Symbol,
+        fields: Vec<(Span, Symbol, TypeAnnotation)>,
+    },
+    /// A named system of units. The units are listed in the order of preference.
+    DefineUnitSystem {
+        system_name_span: Span,
+        system_name: Symbol,
+        units: Vec<Expression>,
+    },
+    /// `unitfn awg(gauge: Scalar) -> Length = …`, an invertible function that can be
+    /// used like a unit on both sides of a conversion. Both the function and its
+    /// optional inverse are [`Statement::DefineFunction`]s.
+    DefineUnitFunction {
+        function: Box<Statement>,
+        inverse: Option<Box<Statement>>,
+    },
+    /// `impl Circle { fn area(self) -> Area = … }`, methods of a struct. The methods
+    /// are [`Statement::DefineFunction`]s, named with [`method_name`].
+    DefineMethods {
+        struct_name_span: Span,
+        struct_name: Symbol,
+        functions: Vec<Statement>,
+    },
+    /// `formula ohms_law: voltage = current * resistance`, an equation between
+    /// named quantities. The type checker solves it for each of the quantities,
+    /// and defines a function for each of them, see [`formula_function_name`].
+    DefineFormula {
+        formula_name_span: Span,
+        formula_name: Symbol,
+        /// All quantities, in the order of their declaration or of their first
+        /// occurrence in the equation
+        quantities: Vec<(Span, Symbol, Option<TypeAnnotation>)>,
+        lhs: Expression,
+        rhs: Expression,
     },
 }
 
+/// A definition from a module, identified by the module and its name
+pub type ModuleItem = (ModulePath, Symbol);
+
+/// For `use foo::{bar}`, the whole module is evaluated, but only `bar` stays in
+/// scope. All other definitions of the module are hidden after it has been
+/// evaluated, such that they do not clash with definitions of the same name.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportScope {
+    /// Set aside the current definitions of the given names, before the module
+    /// defines them
+    Begin(Vec<Symbol>),
+    /// Hide the definitions of the module, and bring back the definitions that
+    /// have been set aside by the corresponding `Begin`
+    End(Vec<ModuleItem>),
+    /// Bring hidden definitions back into scope, for a later import of the module
+    Reveal(Vec<ModuleItem>),
+}
+
+/// The name under which a method of the given struct is defined
+pub(crate) fn method_name(struct_name: Symbol, method: Symbol) -> Symbol {
+    Symbol::new(&format!("{struct_name}::{method}"))
+}
+
+/// The name of the function that solves a formula for one of its quantities
+pub(crate) fn formula_function_name(formula_name: Symbol, quantity: Symbol) -> Symbol {
+    Symbol::new(&format!("{formula_name}_{quantity}"))
+}
+
+impl Statement {
+    /// A span inside of the statement, used to find out where it comes from
+    pub fn span(&self) -> Span {
+        match self {
+            Statement::Expression(expr) => expr.full_span(),
+            Statement::DefineVariable(DefineVariable { pattern, .. }) => pattern.full_span(),
+            Statement::DefineFunction {
+                function_name_span, ..
+            } => *function_name_span,
+            Statement::DefineDimension(span, ..)
+            | Statement::DefineBaseUnit(span, ..)
+            | Statement::ProcedureCall(span, ..)
+            | Statement::ModuleImport(span, ..)
+            | Statement::ImportScope(span, ..) => *span,
+            Statement::DefineDerivedUnit {
+                identifier_span, ..
+            } => *identifier_span,
+            Statement::Conditional { span_if, .. } => *span_if,
+            Statement::DefineStruct {
+                struct_name_span, ..
+            }
+            | Statement::DefineMethods {
+                struct_name_span, ..
+            } => *struct_name_span,
+            Statement::DefineUnitSystem {
+                system_name_span, ..
+            } => *system_name_span,
+            Statement::DefineFormula {
+                formula_name_span, ..
+            } => *formula_name_span,
+            Statement::DefineUnitFunction { function, .. } => function.span(),
+        }
+    }
+}
+
 #[cfg(test)]
 pub trait ReplaceSpans {
     fn replace_spans(&self) -> Self;
+
+    /// Structural comparison that ignores all spans
+    fn eq_modulo_spans(&self, other: &Self) -> bool
+    where
+        Self: PartialEq + Sized,
+    {
+        self.replace_spans() == other.replace_spans()
+    }
 }
 
 #[cfg(test)]
@@ -452,12 +1066,18 @@ impl ReplaceSpans for TypeAnnotation {
             TypeAnnotation::Bool(_) => TypeAnnotation::Bool(Span::dummy()),
             TypeAnnotation::String(_) => TypeAnnotation::String(Span::dummy()),
             TypeAnnotation::DateTime(_) => TypeAnnotation::DateTime(Span::dummy()),
-            TypeAnnotation::Fn(_, pt, rt) => {
-                TypeAnnotation::Fn(Span::dummy(), pt.clone(), rt.clone())
-            }
+            TypeAnnotation::Dynamic(_) => TypeAnnotation::Dynamic(Span::dummy()),
+            TypeAnnotation::Fn(_, pt, rt) => TypeAnnotation::Fn(
+                Span::dummy(),
+                pt.iter().map(|t| t.replace_spans()).collect(),
+                Box::new(rt.replace_spans()),
+            ),
             TypeAnnotation::List(_, et) => {
                 TypeAnnotation::List(Span::dummy(), Box::new(et.replace_spans()))
             }
+            TypeAnnotation::Variadic(_, et) => {
+                TypeAnnotation::Variadic(Span::dummy(), Box::new(et.replace_spans()))
+            }
         }
     }
 }
@@ -468,7 +1088,7 @@ impl ReplaceSpans for TypeExpression {
         match self {
             TypeExpression::Unity(_) => TypeExpression::Unity(Span::dummy()),
             TypeExpression::TypeIdentifier(_, d) => {
-                TypeExpression::TypeIdentifier(Span::dummy(), d.clone())
+                TypeExpression::TypeIdentifier(Span::dummy(), *d)
             }
             TypeExpression::Multiply(_, lhs, rhs) => TypeExpression::Multiply(
                 Span::dummy(),
@@ -480,8 +1100,8 @@ impl ReplaceSpans for TypeExpression {
                 Box::new(lhs.replace_spans()),
                 Box::new(rhs.replace_spans()),
             ),
-            TypeExpression::Power(span_op, lhs, _, exp) => TypeExpression::Power(
-                span_op.map(|_| Span::dummy()),
+            TypeExpression::Power(_, lhs, _, exp) => TypeExpression::Power(
+                Some(Span::dummy()),
                 Box::new(lhs.replace_spans()),
                 Span::dummy(),
                 *exp,
@@ -513,9 +1133,9 @@ impl ReplaceSpans for Expression {
     fn replace_spans(&self) -> Self {
         match self {
             Expression::Scalar(_, name) => Expression::Scalar(Span::dummy(), *name),
-            Expression::Identifier(_, name) => Expression::Identifier(Span::dummy(), name.clone()),
+            Expression::Identifier(_, name) => Expression::Identifier(Span::dummy(), *name),
             Expression::UnitIdentifier(_, prefix, name, full_name) => {
-                Expression::UnitIdentifier(Span::dummy(), *prefix, name.clone(), full_name.clone())
+                Expression::UnitIdentifier(Span::dummy(), *prefix, *name, *full_name)
             }
             Expression::UnaryOperator {
                 op,
@@ -537,12 +1157,44 @@ impl ReplaceSpans for Expression {
                 rhs: Box::new(rhs.replace_spans()),
                 span_op: Some(Span::dummy()),
             },
-            Expression::FunctionCall(_, _, callable, args) => Expression::FunctionCall(
-                Span::dummy(),
-                Span::dummy(),
-                Box::new(callable.replace_spans()),
-                args.iter().map(|a| a.replace_spans()).collect(),
+            Expression::Conversion {
+                source,
+                target,
+                span_op: _,
+                parenthesized_source,
+            } => Expression::Conversion {
+                source: Box::new(source.replace_spans()),
+                target: match target {
+                    ConversionTarget::Expression(expr) => {
+                        ConversionTarget::Expression(Box::new(expr.replace_spans()))
+                    }
+                    ConversionTarget::UnitList(_, units) => ConversionTarget::UnitList(
+                        Span::dummy(),
+                        units.iter().map(|u| u.replace_spans()).collect(),
+                    ),
+                },
+                span_op: Span::dummy(),
+                parenthesized_source: *parenthesized_source,
+            },
+            Expression::ChainedComparison(first, rest) => Expression::ChainedComparison(
+                Box::new(first.replace_spans()),
+                rest.iter()
+                    .map(|(_, op, rhs)| (Span::dummy(), *op, rhs.replace_spans()))
+                    .collect(),
             ),
+            Expression::FunctionCall(_, _, callable, args, named_args, syntax) => {
+                Expression::FunctionCall(
+                    Span::dummy(),
+                    Span::dummy(),
+                    Box::new(callable.replace_spans()),
+                    args.iter().map(|a| a.replace_spans()).collect(),
+                    named_args
+                        .iter()
+                        .map(|(_, name, arg)| (Span::dummy(), *name, arg.replace_spans()))
+                        .collect(),
+                    *syntax,
+                )
+            }
             Expression::Boolean(_, val) => Expression::Boolean(Span::dummy(), *val),
             Expression::Condition(_, condition, then, else_) => Expression::Condition(
                 Span::dummy(),
@@ -554,26 +1206,108 @@ impl ReplaceSpans for Expression {
                 Span::dummy(),
                 parts.iter().map(|p| p.replace_spans()).collect(),
             ),
-            Expression::InstantiateStruct { name, fields, .. } => Expression::InstantiateStruct {
+            Expression::InstantiateStruct {
+                name, base, fields, ..
+            } => Expression::InstantiateStruct {
                 full_span: Span::dummy(),
                 ident_span: Span::dummy(),
-                name: name.clone(),
+                name: *name,
+                base: base.as_ref().map(|b| Box::new(b.replace_spans())),
                 fields: fields
                     .iter()
-                    .map(|(_, n, v)| (Span::dummy(), n.clone(), v.replace_spans()))
+                    .map(|(_, n, v)| (Span::dummy(), *n, v.replace_spans()))
                     .collect(),
             },
             Expression::AccessField(_, _, expr, attr) => Expression::AccessField(
                 Span::dummy(),
                 Span::dummy(),
                 Box::new(expr.replace_spans()),
-                attr.clone(),
+                *attr,
             ),
             Expression::List(_, elements) => Expression::List(
                 Span::dummy(),
                 elements.iter().map(|e| e.replace_spans()).collect(),
             ),
             Expression::TypedHole(_) => Expression::TypedHole(Span::dummy()),
+            Expression::Trace(_, source, expr) => Expression::Trace(
+                Span::dummy(),
+                source.clone(),
+                Box::new(expr.replace_spans()),
+            ),
+            Expression::Partial(_, callable, args, named_args) => Expression::Partial(
+                Span::dummy(),
+                Box::new(callable.replace_spans()),
+                args.iter().map(|a| a.replace_spans()).collect(),
+                named_args
+                    .iter()
+                    .map(|(_, name, arg)| (Span::dummy(), *name, arg.replace_spans()))
+                    .collect(),
+            ),
+            Expression::AutoDiff(_, function) => {
+                Expression::AutoDiff(Span::dummy(), Box::new(function.replace_spans()))
+            }
+            Expression::DimensionPredicate(_, expr, dimension) => Expression::DimensionPredicate(
+                Span::dummy(),
+                Box::new(expr.replace_spans()),
+                dimension.as_ref().map(|d| d.replace_spans()),
+            ),
+            Expression::For {
+                variable,
+                list,
+                accumulator,
+                body,
+                ..
+            } => Expression::For {
+                full_span: Span::dummy(),
+                variable: (Span::dummy(), variable.1),
+                list: Box::new(list.replace_spans()),
+                accumulator: accumulator
+                    .as_ref()
+                    .map(|(_, name, init)| (Span::dummy(), *name, Box::new(init.replace_spans()))),
+                body: Box::new(body.replace_spans()),
+            },
+            Expression::While {
+                condition,
+                accumulator: (_, name, init),
+                body,
+                ..
+            } => Expression::While {
+                full_span: Span::dummy(),
+                condition: Box::new(condition.replace_spans()),
+                accumulator: (Span::dummy(), *name, Box::new(init.replace_spans())),
+                body: Box::new(body.replace_spans()),
+            },
+            Expression::TryCatch {
+                expr,
+                message,
+                fallback,
+                ..
+            } => Expression::TryCatch {
+                full_span: Span::dummy(),
+                expr: Box::new(expr.replace_spans()),
+                message: message.map(|(_, name)| (Span::dummy(), name)),
+                fallback: Box::new(fallback.replace_spans()),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+impl ReplaceSpans for Pattern {
+    fn replace_spans(&self) -> Self {
+        match self {
+            Pattern::Identifier(_, identifier) => Pattern::Identifier(Span::dummy(), *identifier),
+            Pattern::Struct(_, fields) => Pattern::Struct(
+                Span::dummy(),
+                fields
+                    .iter()
+                    .map(|(_, field, pattern)| (Span::dummy(), *field, pattern.replace_spans()))
+                    .collect(),
+            ),
+            Pattern::List(_, elements) => Pattern::List(
+                Span::dummy(),
+                elements.iter().map(Pattern::replace_spans).collect(),
+            ),
         }
     }
 }
@@ -582,8 +1316,7 @@ impl ReplaceSpans for Expression {
 impl ReplaceSpans for DefineVariable {
     fn replace_spans(&self) -> Self {
         Self {
-            identifier_span: Span::dummy(),
-            identifier: self.identifier.clone(),
+            pattern: self.pattern.replace_spans(),
             expr: self.expr.replace_spans(),
             type_annotation: self.type_annotation.as_ref().map(|t| t.replace_spans()),
             decorators: self.decorators.clone(),
@@ -606,22 +1339,24 @@ impl ReplaceSpans for Statement {
                 parameters,
                 body,
                 local_variables,
+                local_units,
                 return_type_annotation,
                 decorators,
             } => Statement::DefineFunction {
                 function_name_span: Span::dummy(),
-                function_name: function_name.clone(),
+                function_name: *function_name,
                 type_parameters: type_parameters
                     .iter()
-                    .map(|(_, name, bound)| (Span::dummy(), name.clone(), bound.clone()))
+                    .map(|(_, name, bound)| (Span::dummy(), *name, bound.clone()))
                     .collect(),
                 parameters: parameters
                     .iter()
-                    .map(|(_, name, type_)| {
+                    .map(|(_, name, type_, default)| {
                         (
                             Span::dummy(),
-                            name.clone(),
+                            *name,
                             type_.as_ref().map(|t| t.replace_spans()),
+                            default.as_ref().map(|d| d.replace_spans()),
                         )
                     })
                     .collect(),
@@ -630,17 +1365,18 @@ impl ReplaceSpans for Statement {
                     .iter()
                     .map(DefineVariable::replace_spans)
                     .collect(),
+                local_units: local_units.iter().map(|s| s.replace_spans()).collect(),
                 return_type_annotation: return_type_annotation.as_ref().map(|t| t.replace_spans()),
                 decorators: decorators.clone(),
             },
             Statement::DefineDimension(_, name, dexprs) => Statement::DefineDimension(
                 Span::dummy(),
-                name.clone(),
+                *name,
                 dexprs.iter().map(|t| t.replace_spans()).collect(),
             ),
             Statement::DefineBaseUnit(_, name, type_, decorators) => Statement::DefineBaseUnit(
                 Span::dummy(),
-                name.clone(),
+                *name,
                 type_.as_ref().map(|t| t.replace_spans()),
                 decorators.clone(),
             ),
@@ -653,7 +1389,7 @@ impl ReplaceSpans for Statement {
                 decorators,
             } => Statement::DefineDerivedUnit {
                 identifier_span: Span::dummy(),
-                identifier: identifier.clone(),
+                identifier: *identifier,
                 expr: expr.replace_spans(),
                 type_annotation_span: type_annotation_span.map(|_| Span::dummy()),
                 type_annotation: type_annotation.as_ref().map(|t| t.replace_spans()),
@@ -664,8 +1400,29 @@ impl ReplaceSpans for Statement {
                 proc.clone(),
                 args.iter().map(|a| a.replace_spans()).collect(),
             ),
-            Statement::ModuleImport(_, module_path) => {
-                Statement::ModuleImport(Span::dummy(), module_path.clone())
+            Statement::Conditional {
+                condition,
+                then_statement,
+                else_statement,
+                ..
+            } => Statement::Conditional {
+                span_if: Span::dummy(),
+                condition: condition.replace_spans(),
+                then_statement: Box::new(then_statement.replace_spans()),
+                else_statement: else_statement.as_ref().map(|s| Box::new(s.replace_spans())),
+            },
+            Statement::ModuleImport(_, module_path, items) => Statement::ModuleImport(
+                Span::dummy(),
+                module_path.clone(),
+                items.as_ref().map(|items| {
+                    items
+                        .iter()
+                        .map(|(_, item)| (Span::dummy(), *item))
+                        .collect()
+                }),
+            ),
+            Statement::ImportScope(_, scope) => {
+                Statement::ImportScope(Span::dummy(), scope.clone())
             }
             Statement::DefineStruct {
                 struct_name,
@@ -673,13 +1430,53 @@ impl ReplaceSpans for Statement {
                 ..
             } => Statement::DefineStruct {
                 struct_name_span: Span::dummy(),
-                struct_name: struct_name.clone(),
+                struct_name: *struct_name,
                 fields: fields
                     .iter()
-                    .map(|(_span, name, type_)| {
-                        (Span::dummy(), name.clone(), type_.replace_spans())
+                    .map(|(_span, name, type_)| (Span::dummy(), *name, type_.replace_spans()))
+                    .collect(),
+            },
+            Statement::DefineUnitSystem {
+                system_name, units, ..
+            } => Statement::DefineUnitSystem {
+                system_name_span: Span::dummy(),
+                system_name: *system_name,
+                units: units.iter().map(|u| u.replace_spans()).collect(),
+            },
+            Statement::DefineUnitFunction { function, inverse } => Statement::DefineUnitFunction {
+                function: Box::new(function.replace_spans()),
+                inverse: inverse.as_ref().map(|s| Box::new(s.replace_spans())),
+            },
+            Statement::DefineMethods {
+                struct_name,
+                functions,
+                ..
+            } => Statement::DefineMethods {
+                struct_name_span: Span::dummy(),
+                struct_name: *struct_name,
+                functions: functions.replace_spans(),
+            },
+            Statement::DefineFormula {
+                formula_name,
+                quantities,
+                lhs,
+                rhs,
+                ..
+            } => Statement::DefineFormula {
+                formula_name_span: Span::dummy(),
+                formula_name: *formula_name,
+                quantities: quantities
+                    .iter()
+                    .map(|(_, name, annotation)| {
+                        (
+                            Span::dummy(),
+                            *name,
+                            annotation.as_ref().map(|a| a.replace_spans()),
+                        )
                     })
                     .collect(),
+                lhs: lhs.replace_spans(),
+                rhs: rhs.replace_spans(),
             },
         }
     }
@@ -0,0 +1,1564 @@
+//! Random generation of well-formed programs for property tests of the parser
+//! and the pretty printer.
+//!
+//! The [`Generator`] builds untyped ASTs that type check by construction: it
+//! keeps track of the variables, functions, units and structs that have been
+//! defined so far and only generates expressions of a requested type. Programs
+//! cover definitions of variables, functions, units, dimensions and structs
+//! (with decorators), as well as lists, conditionals, comparisons, strings and
+//! procedure calls.
+//!
+//! The pretty printer does not reproduce the source code literally. It adds
+//! type annotations and uses the full names of units, for example. The round
+//! trip property is therefore checked on pretty printed programs: formatting
+//! and parsing them again has to yield the same AST (modulo spans) and the same
+//! types. Counterexamples are shrunk to a small program by repeatedly replacing
+//! the program with a simpler one (fewer statements, smaller expressions) that
+//! still type checks and still violates the property.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::ast::{
+    BinaryOperator, CallSyntax, ConversionTarget, DefineVariable, Expression, Pattern,
+    ProcedureKind, ReplaceSpans, Statement, StringPart, TypeAnnotation, TypeExpression,
+    UnaryOperator,
+};
+use crate::decorator::Decorator;
+use crate::number::Number;
+use crate::prefix_parser::AcceptsPrefix;
+use crate::prefix_transformer::Transformer;
+use crate::pretty_print::PrettyPrint;
+use crate::span::Span;
+use crate::symbol::Symbol;
+use crate::typechecker::TypeChecker;
+use crate::typed_ast::{self, TypedProgram};
+
+/// Definitions that are available to all generated programs
+const PRELUDE: &str = "
+    dimension Scalar = 1
+    dimension Length
+    dimension Time
+    dimension Velocity = Length / Time
+
+    @aliases(m: short)
+    @metric_prefixes
+    unit meter: Length
+
+    @aliases(s: short)
+    @metric_prefixes
+    unit second: Time
+
+    @aliases(min: none)
+    unit minute = 60 s
+
+    @aliases(ft: none)
+    unit foot = 0.3048 m
+
+    fn abs<T: Dim>(x: T) -> T
+";
+
+/// Dimensions of the generated quantities
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Dimension {
+    Scalar,
+    Length,
+    Time,
+    Velocity,
+}
+
+const DIMENSIONS: [Dimension; 4] = [
+    Dimension::Scalar,
+    Dimension::Length,
+    Dimension::Time,
+    Dimension::Velocity,
+];
+
+impl Dimension {
+    fn name(self) -> &'static str {
+        match self {
+            Dimension::Scalar => "Scalar",
+            Dimension::Length => "Length",
+            Dimension::Time => "Time",
+            Dimension::Velocity => "Velocity",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Type {
+    Quantity(Dimension),
+    Bool,
+    String,
+    List(Dimension),
+    /// Index into [`Generator::structs`]
+    Struct(usize),
+}
+
+struct Function {
+    name: Symbol,
+    parameters: Vec<(Symbol, Type)>,
+    return_type: Type,
+}
+
+struct Struct {
+    name: Symbol,
+    fields: Vec<(Symbol, Type)>,
+}
+
+fn dummy() -> Span {
+    Span::dummy()
+}
+
+fn binop(op: BinaryOperator, lhs: Expression, rhs: Expression) -> Expression {
+    Expression::BinaryOperator {
+        op,
+        lhs: Box::new(lhs),
+        rhs: Box::new(rhs),
+        span_op: Some(dummy()),
+    }
+}
+
+fn type_identifier(name: &str) -> TypeAnnotation {
+    TypeAnnotation::TypeExpression(TypeExpression::TypeIdentifier(dummy(), name.into()))
+}
+
+pub(crate) struct Generator {
+    rng: StdRng,
+    variables: Vec<(Symbol, Type)>,
+    functions: Vec<Function>,
+    units: Vec<(Symbol, Dimension)>,
+    structs: Vec<Struct>,
+    next_id: usize,
+}
+
+impl Generator {
+    pub(crate) fn new(seed: u64) -> Self {
+        let units = [
+            ("m", Dimension::Length),
+            ("meter", Dimension::Length),
+            ("cm", Dimension::Length),
+            ("kilometer", Dimension::Length),
+            ("ft", Dimension::Length),
+            ("s", Dimension::Time),
+            ("ms", Dimension::Time),
+            ("minute", Dimension::Time),
+        ];
+
+        Generator {
+            rng: StdRng::seed_from_u64(seed),
+            variables: vec![],
+            functions: vec![],
+            units: units.iter().map(|(n, d)| ((*n).into(), *d)).collect(),
+            structs: vec![],
+            next_id: 0,
+        }
+    }
+
+    fn fresh_name(&mut self, prefix: &str) -> Symbol {
+        self.next_id += 1;
+        Symbol::new(&format!("{prefix}{}", self.next_id))
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        self.rng.gen_bool(probability)
+    }
+
+    fn choose<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.rng.gen_range(0..items.len())]
+    }
+
+    fn dimension(&mut self) -> Dimension {
+        *self.choose(&DIMENSIONS)
+    }
+
+    fn type_(&mut self) -> Type {
+        match self.rng.gen_range(0..10) {
+            0 => Type::Bool,
+            1 => Type::String,
+            2 => Type::List(self.dimension()),
+            3 if !self.structs.is_empty() => {
+                Type::Struct(self.rng.gen_range(0..self.structs.len()))
+            }
+            _ => Type::Quantity(self.dimension()),
+        }
+    }
+
+    fn type_annotation(&self, type_: &Type) -> TypeAnnotation {
+        match type_ {
+            Type::Quantity(Dimension::Velocity) => {
+                TypeAnnotation::TypeExpression(TypeExpression::Divide(
+                    dummy(),
+                    Box::new(TypeExpression::TypeIdentifier(dummy(), "Length".into())),
+                    Box::new(TypeExpression::TypeIdentifier(dummy(), "Time".into())),
+                ))
+            }
+            Type::Quantity(d) => type_identifier(d.name()),
+            Type::Bool => TypeAnnotation::Bool(dummy()),
+            Type::String => TypeAnnotation::String(dummy()),
+            Type::List(d) => {
+                TypeAnnotation::List(dummy(), Box::new(self.type_annotation(&Type::Quantity(*d))))
+            }
+            Type::Struct(i) => type_identifier(self.structs[*i].name.as_str()),
+        }
+    }
+
+    /// A positive number. Zero is avoided since it is polymorphic in its
+    /// dimension, which would make many definitions generic.
+    fn number(&mut self) -> Number {
+        match self.rng.gen_range(0..3) {
+            0 => Number::from_f64(self.rng.gen_range(1..10) as f64),
+            1 => Number::from_f64(self.rng.gen_range(1..100_000) as f64),
+            _ => Number::from_f64(self.rng.gen_range(1..1000) as f64 / 8.0),
+        }
+    }
+
+    fn scalar(&mut self) -> Expression {
+        Expression::Scalar(dummy(), self.number())
+    }
+
+    fn unit(&mut self, dimension: Dimension) -> Expression {
+        match dimension {
+            Dimension::Scalar => self.scalar(),
+            Dimension::Velocity => binop(
+                BinaryOperator::Div,
+                self.unit(Dimension::Length),
+                self.unit(Dimension::Time),
+            ),
+            _ => {
+                let units: Vec<_> = self
+                    .units
+                    .iter()
+                    .filter(|(_, d)| *d == dimension)
+                    .map(|(n, _)| *n)
+                    .collect();
+                Expression::Identifier(dummy(), *self.choose(&units))
+            }
+        }
+    }
+
+    fn variable(&mut self, type_: &Type) -> Option<Expression> {
+        let candidates: Vec<_> = self
+            .variables
+            .iter()
+            .filter(|(_, t)| t == type_)
+            .map(|(n, _)| *n)
+            .collect();
+        if candidates.is_empty() {
+            None
+        } else {
+            Some(Expression::Identifier(dummy(), *self.choose(&candidates)))
+        }
+    }
+
+    fn string(&mut self, depth: usize) -> Expression {
+        const PIECES: [&str; 8] = ["a", "xyz", " ", "\"", "\\", "\n", "{", "é"];
+
+        let mut parts = vec![];
+        for _ in 0..self.rng.gen_range(0..4) {
+            if depth > 0 && self.chance(0.3) {
+                let d = self.dimension();
+                let format_specifiers = if self.chance(0.3) {
+                    Some(":.2".to_string())
+                } else {
+                    None
+                };
+                // String literals can not be nested in interpolations, so only
+                // simple expressions are used here
+                let type_ = Type::Quantity(d);
+                let expr = if self.chance(0.5) {
+                    binop(BinaryOperator::Add, self.leaf(&type_), self.leaf(&type_))
+                } else {
+                    self.leaf(&type_)
+                };
+                parts.push(StringPart::Interpolation {
+                    span: dummy(),
+                    expr: Box::new(expr),
+                    format_specifiers,
+                });
+            } else {
+                let piece = self.choose(&PIECES).to_string();
+                // Adjacent fixed parts are merged by the parser
+                if let Some(StringPart::Fixed(s)) = parts.last_mut() {
+                    s.push_str(&piece);
+                } else {
+                    parts.push(StringPart::Fixed(piece));
+                }
+            }
+        }
+        Expression::String(dummy(), parts)
+    }
+
+    fn leaf(&mut self, type_: &Type) -> Expression {
+        if self.chance(0.3) {
+            if let Some(variable) = self.variable(type_) {
+                return variable;
+            }
+        }
+
+        match type_ {
+            Type::Quantity(Dimension::Scalar) => self.scalar(),
+            Type::Quantity(d) => {
+                if self.chance(0.2) {
+                    self.unit(*d)
+                } else {
+                    Expression::BinaryOperator {
+                        op: BinaryOperator::Mul,
+                        lhs: Box::new(self.scalar()),
+                        rhs: Box::new(self.unit(*d)),
+                        span_op: None,
+                    }
+                }
+            }
+            Type::Bool => Expression::Boolean(dummy(), self.chance(0.5)),
+            Type::String => self.string(0),
+            Type::List(d) => Expression::List(dummy(), vec![self.leaf(&Type::Quantity(*d))]),
+            Type::Struct(_) => self.struct_instance(type_, 0),
+        }
+    }
+
+    fn struct_instance(&mut self, type_: &Type, depth: usize) -> Expression {
+        let Type::Struct(i) = type_ else {
+            unreachable!()
+        };
+        let name = self.structs[*i].name;
+        let fields = self.structs[*i].fields.clone();
+
+        if depth > 0 && self.chance(0.3) {
+            // Struct update, `S { base with field = value }`
+            let base = self.expression(type_, depth - 1);
+            let (field, field_type) = self.choose(&fields).clone();
+            let value = self.expression(&field_type, depth - 1);
+            return Expression::InstantiateStruct {
+                full_span: dummy(),
+                ident_span: dummy(),
+                name,
+                base: Some(Box::new(base)),
+                fields: vec![(dummy(), field, value)],
+            };
+        }
+
+        let fields = fields
+            .iter()
+            .map(|(field, field_type)| {
+                (
+                    dummy(),
+                    *field,
+                    self.expression(field_type, depth.saturating_sub(1)),
+                )
+            })
+            .collect();
+        Expression::InstantiateStruct {
+            full_span: dummy(),
+            ident_span: dummy(),
+            name,
+            base: None,
+            fields,
+        }
+    }
+
+    fn function_call(&mut self, type_: &Type, depth: usize) -> Option<Expression> {
+        let candidates: Vec<_> = (0..self.functions.len())
+            .filter(|i| self.functions[*i].return_type == *type_)
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+        let f = *self.choose(&candidates);
+        let name = self.functions[f].name;
+        let parameters: Vec<_> = self.functions[f].parameters.clone();
+
+        let mut args: Vec<_> = parameters
+            .iter()
+            .map(|(_, t)| self.expression(t, depth - 1))
+            .collect();
+
+        let mut named_args = vec![];
+        if !args.is_empty() && self.chance(0.2) {
+            let last = args.pop().unwrap();
+            named_args.push((dummy(), parameters[args.len()].0, last));
+        }
+
+        let syntax = if !args.is_empty() && self.chance(0.2) {
+            CallSyntax::Pipe
+        } else {
+            CallSyntax::Regular
+        };
+
+        Some(Expression::FunctionCall(
+            dummy(),
+            dummy(),
+            Box::new(Expression::Identifier(dummy(), name)),
+            args,
+            named_args,
+            syntax,
+        ))
+    }
+
+    fn field_access(&mut self, type_: &Type, depth: usize) -> Option<Expression> {
+        let candidates: Vec<_> = self
+            .structs
+            .iter()
+            .enumerate()
+            .flat_map(|(i, s)| {
+                s.fields
+                    .iter()
+                    .filter(|(_, t)| t == type_)
+                    .map(move |(field, _)| (i, *field))
+            })
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+        let (i, field) = *self.choose(&candidates);
+        let instance = self.expression(&Type::Struct(i), depth - 1);
+        Some(Expression::AccessField(
+            dummy(),
+            dummy(),
+            Box::new(instance),
+            field,
+        ))
+    }
+
+    fn quantity(&mut self, dimension: Dimension, depth: usize) -> Expression {
+        let d = depth - 1;
+        let same = Type::Quantity(dimension);
+        let scalar = Type::Quantity(Dimension::Scalar);
+
+        match self.rng.gen_range(0..12) {
+            0 => binop(
+                BinaryOperator::Add,
+                self.expression(&same, d),
+                self.expression(&same, d),
+            ),
+            1 => binop(
+                BinaryOperator::Sub,
+                self.expression(&same, d),
+                self.expression(&same, d),
+            ),
+            2 => Expression::UnaryOperator {
+                op: UnaryOperator::Negate,
+                expr: Box::new(self.expression(&same, d)),
+                span_op: dummy(),
+            },
+            3 => binop(
+                BinaryOperator::Mul,
+                self.expression(&scalar, d),
+                self.expression(&same, d),
+            ),
+            4 => binop(
+                BinaryOperator::Div,
+                self.expression(&same, d),
+                self.expression(&scalar, d),
+            ),
+            5 if dimension == Dimension::Velocity => binop(
+                BinaryOperator::Div,
+                self.expression(&Type::Quantity(Dimension::Length), d),
+                self.expression(&Type::Quantity(Dimension::Time), d),
+            ),
+            5 if dimension == Dimension::Scalar => {
+                let exponent =
+                    Expression::Scalar(dummy(), Number::from_f64(self.rng.gen_range(1..4) as f64));
+                binop(BinaryOperator::Power, self.expression(&scalar, d), exponent)
+            }
+            6 if dimension == Dimension::Scalar => Expression::UnaryOperator {
+                op: UnaryOperator::Factorial,
+                expr: Box::new(self.expression(&scalar, d)),
+                span_op: dummy(),
+            },
+            6 => {
+                let target = self.unit(dimension);
+                Expression::Conversion {
+                    source: Box::new(self.expression(&same, d)),
+                    target: ConversionTarget::Expression(Box::new(target)),
+                    span_op: dummy(),
+                    parenthesized_source: false,
+                }
+            }
+            7 => self.condition(&same, depth),
+            8 => self.function_call(&same, depth).unwrap_or_else(|| {
+                // A generic function from the prelude
+                Expression::FunctionCall(
+                    dummy(),
+                    dummy(),
+                    Box::new(Expression::Identifier(dummy(), "abs".into())),
+                    vec![self.expression(&same, d)],
+                    vec![],
+                    CallSyntax::Regular,
+                )
+            }),
+            9 => self
+                .field_access(&same, depth)
+                .unwrap_or_else(|| self.leaf(&same)),
+            _ => self.leaf(&same),
+        }
+    }
+
+    fn boolean(&mut self, depth: usize) -> Expression {
+        let d = depth - 1;
+
+        match self.rng.gen_range(0..8) {
+            0 | 1 => {
+                let dimension = self.dimension();
+                let op = *self.choose(&[
+                    BinaryOperator::LessThan,
+                    BinaryOperator::GreaterThan,
+                    BinaryOperator::LessOrEqual,
+                    BinaryOperator::GreaterOrEqual,
+                    BinaryOperator::Equal,
+                    BinaryOperator::NotEqual,
+                ]);
+                binop(
+                    op,
+                    self.expression(&Type::Quantity(dimension), d),
+                    self.expression(&Type::Quantity(dimension), d),
+                )
+            }
+            2 => {
+                let type_ = Type::Quantity(self.dimension());
+                let first = self.expression(&type_, d);
+                let rest = (0..2)
+                    .map(|_| {
+                        let op =
+                            *self.choose(&[BinaryOperator::LessThan, BinaryOperator::LessOrEqual]);
+                        (dummy(), op, self.expression(&type_, d))
+                    })
+                    .collect();
+                Expression::ChainedComparison(Box::new(first), rest)
+            }
+            3 => {
+                let op = *self.choose(&[BinaryOperator::LogicalAnd, BinaryOperator::LogicalOr]);
+                binop(
+                    op,
+                    self.expression(&Type::Bool, d),
+                    self.expression(&Type::Bool, d),
+                )
+            }
+            4 => Expression::UnaryOperator {
+                op: UnaryOperator::LogicalNeg,
+                expr: Box::new(self.expression(&Type::Bool, d)),
+                span_op: dummy(),
+            },
+            5 => self.condition(&Type::Bool, depth),
+            6 => self
+                .field_access(&Type::Bool, depth)
+                .or_else(|| self.function_call(&Type::Bool, depth))
+                .unwrap_or_else(|| self.leaf(&Type::Bool)),
+            _ => self.leaf(&Type::Bool),
+        }
+    }
+
+    fn condition(&mut self, type_: &Type, depth: usize) -> Expression {
+        Expression::Condition(
+            dummy(),
+            Box::new(self.expression(&Type::Bool, depth - 1)),
+            Box::new(self.expression(type_, depth - 1)),
+            Box::new(self.expression(type_, depth - 1)),
+        )
+    }
+
+    /// An expression of the given type, with a nesting depth of at most `depth`
+    fn expression(&mut self, type_: &Type, depth: usize) -> Expression {
+        if depth == 0 || self.chance(0.25) {
+            return self.leaf(type_);
+        }
+
+        match type_ {
+            Type::Quantity(dimension) => self.quantity(*dimension, depth),
+            Type::Bool => self.boolean(depth),
+            Type::String => match self.rng.gen_range(0..4) {
+                0 => self.condition(type_, depth),
+                1 => self
+                    .field_access(type_, depth)
+                    .or_else(|| self.function_call(type_, depth))
+                    .unwrap_or_else(|| self.string(depth)),
+                _ => self.string(depth),
+            },
+            Type::List(dimension) => match self.rng.gen_range(0..4) {
+                0 => self.condition(type_, depth),
+                1 => self
+                    .field_access(type_, depth)
+                    .unwrap_or_else(|| self.leaf(type_)),
+                _ => Expression::List(
+                    dummy(),
+                    (0..self.rng.gen_range(1..4))
+                        .map(|_| self.expression(&Type::Quantity(*dimension), depth - 1))
+                        .collect(),
+                ),
+            },
+            Type::Struct(_) => match self.rng.gen_range(0..4) {
+                0 => self.condition(type_, depth),
+                1 => self
+                    .field_access(type_, depth)
+                    .or_else(|| self.function_call(type_, depth))
+                    .unwrap_or_else(|| self.struct_instance(type_, depth)),
+                _ => self.struct_instance(type_, depth),
+            },
+        }
+    }
+
+    fn string_decorators(&mut self, allow_deprecated: bool) -> Vec<Decorator> {
+        let mut decorators = vec![];
+        if self.chance(0.2) {
+            decorators.push(Decorator::Name("A \"name\"".into()));
+        }
+        if self.chance(0.2) {
+            decorators.push(Decorator::Url("https://example.com/?q={x}".into()));
+        }
+        if self.chance(0.2) {
+            decorators.push(Decorator::Description("Line 1\nline 2\\".into()));
+        }
+        if allow_deprecated && self.chance(0.1) {
+            decorators.push(Decorator::Deprecated("use something else".into()));
+        }
+        decorators
+    }
+
+    fn define_variable(
+        &mut self,
+        depth: usize,
+        allow_destructuring: bool,
+    ) -> (DefineVariable, Vec<(Symbol, Type)>) {
+        let type_ = self.type_();
+        let expr = self.expression(&type_, depth);
+
+        if let Type::Struct(i) = &type_ {
+            if allow_destructuring && self.chance(0.2) {
+                // Destructuring, `let {a, b: c} = …`
+                let fields = self.structs[*i].fields.clone();
+                let mut bound = vec![];
+                let patterns = fields
+                    .iter()
+                    .map(|(field, field_type)| {
+                        let is_bound = self.variables.iter().any(|(n, _)| n == field);
+                        let name = if !is_bound && self.chance(0.5) {
+                            *field
+                        } else {
+                            self.fresh_name("var")
+                        };
+                        bound.push((name, field_type.clone()));
+                        (dummy(), *field, Pattern::Identifier(dummy(), name))
+                    })
+                    .collect();
+                let definition = DefineVariable {
+                    pattern: Pattern::Struct(dummy(), patterns),
+                    expr,
+                    type_annotation: None,
+                    decorators: vec![],
+                };
+                return (definition, bound);
+            }
+        }
+
+        let name = self.fresh_name("var");
+        let type_annotation = if self.chance(0.3) {
+            Some(self.type_annotation(&type_))
+        } else {
+            None
+        };
+        let definition = DefineVariable {
+            pattern: Pattern::Identifier(dummy(), name),
+            expr,
+            type_annotation,
+            decorators: self.string_decorators(true),
+        };
+        (definition, vec![(name, type_)])
+    }
+
+    fn define_function(&mut self) -> Statement {
+        let function_name = self.fresh_name("fun");
+        let return_type = self.type_();
+
+        let outer_variables = self.variables.len();
+
+        let mut parameters = vec![];
+        let mut signature = vec![];
+        // Parameters with default values have to come last
+        let number_of_parameters = self.rng.gen_range(0..4);
+        let first_default = self.rng.gen_range(1..=number_of_parameters.max(1) + 2);
+        for i in 0..number_of_parameters {
+            let name = self.fresh_name("arg");
+            let type_ = self.type_();
+            let default = if i >= first_default {
+                Some(self.leaf(&type_))
+            } else {
+                None
+            };
+            parameters.push((dummy(), name, Some(self.type_annotation(&type_)), default));
+            signature.push((name, type_));
+        }
+        self.variables.extend(signature.iter().cloned());
+
+        let mut local_variables = vec![];
+        for _ in 0..self.rng.gen_range(0..3) {
+            let (mut definition, bound) = self.define_variable(2, false);
+            definition.decorators.clear();
+            local_variables.push(definition);
+            self.variables.extend(bound);
+        }
+
+        let body = self.expression(&return_type, 3);
+        self.variables.truncate(outer_variables);
+
+        let return_type_annotation = if self.chance(0.5) {
+            Some(self.type_annotation(&return_type))
+        } else {
+            None
+        };
+
+        self.functions.push(Function {
+            name: function_name,
+            parameters: signature,
+            return_type,
+        });
+
+        Statement::DefineFunction {
+            function_name_span: dummy(),
+            function_name,
+            type_parameters: vec![],
+            parameters,
+            body: Some(body),
+            local_variables,
+            local_units: vec![],
+            return_type_annotation,
+            decorators: self.string_decorators(true),
+        }
+    }
+
+    fn define_struct(&mut self) -> Statement {
+        let struct_name = self.fresh_name("Struct");
+        let fields: Vec<_> = (0..self.rng.gen_range(1..4))
+            .map(|i| (Symbol::new(&format!("field{i}")), self.type_()))
+            .collect();
+
+        let statement = Statement::DefineStruct {
+            struct_name_span: dummy(),
+            struct_name,
+            fields: fields
+                .iter()
+                .map(|(n, t)| (dummy(), *n, self.type_annotation(t)))
+                .collect(),
+        };
+        self.structs.push(Struct {
+            name: struct_name,
+            fields,
+        });
+        statement
+    }
+
+    fn define_unit(&mut self) -> Statement {
+        let identifier = self.fresh_name("gizmo");
+        let alias = Symbol::new(&format!("gz{}", self.next_id));
+
+        let mut decorators = vec![];
+        if self.chance(0.5) {
+            let accepts_prefix = *self.choose(&[
+                None,
+                Some(AcceptsPrefix::only_short()),
+                Some(AcceptsPrefix::only_long()),
+                Some(AcceptsPrefix::both()),
+                Some(AcceptsPrefix::none()),
+            ]);
+            decorators.push(Decorator::Aliases(vec![(alias, accepts_prefix)]));
+        }
+        if self.chance(0.3) {
+            decorators.push(Decorator::MetricPrefixes);
+        }
+        decorators.extend(self.string_decorators(false));
+
+        let dimension = *self.choose(&DIMENSIONS[1..]);
+        if self.chance(0.2) {
+            // A base unit
+            let (dimension_annotation, dimension) = if self.chance(0.5) {
+                if self.chance(0.5) {
+                    decorators.push(Decorator::Dimension(self.fresh_name("Dim")));
+                }
+                (None, None)
+            } else {
+                let TypeAnnotation::TypeExpression(e) =
+                    self.type_annotation(&Type::Quantity(dimension))
+                else {
+                    unreachable!()
+                };
+                (Some(e), Some(dimension))
+            };
+            if let Some(dimension) = dimension {
+                self.units.push((identifier, dimension));
+            }
+            return Statement::DefineBaseUnit(
+                dummy(),
+                identifier,
+                dimension_annotation,
+                decorators,
+            );
+        }
+
+        let expr = self.expression(&Type::Quantity(dimension), 2);
+        let type_annotation = if self.chance(0.3) {
+            Some(self.type_annotation(&Type::Quantity(dimension)))
+        } else {
+            None
+        };
+
+        self.units.push((identifier, dimension));
+        if decorators
+            .iter()
+            .any(|d| matches!(d, Decorator::Aliases(_)))
+        {
+            self.units.push((alias, dimension));
+        }
+
+        Statement::DefineDerivedUnit {
+            identifier_span: dummy(),
+            identifier,
+            expr,
+            type_annotation_span: type_annotation.as_ref().map(|_| dummy()),
+            type_annotation,
+            decorators,
+        }
+    }
+
+    fn define_dimension(&mut self) -> Statement {
+        let length = || Box::new(TypeExpression::TypeIdentifier(dummy(), "Length".into()));
+        let time = || Box::new(TypeExpression::TypeIdentifier(dummy(), "Time".into()));
+
+        let name = self.fresh_name("Dim");
+        let definitions = match self.rng.gen_range(0..4) {
+            0 => vec![],
+            1 => vec![TypeExpression::Multiply(dummy(), length(), time())],
+            2 => vec![TypeExpression::Power(
+                Some(dummy()),
+                length(),
+                dummy(),
+                crate::arithmetic::Exponent::from_integer(-2),
+            )],
+            _ => vec![
+                TypeExpression::Divide(dummy(), length(), time()),
+                TypeExpression::TypeIdentifier(dummy(), "Velocity".into()),
+            ],
+        };
+        Statement::DefineDimension(dummy(), name, definitions)
+    }
+
+    fn procedure_call(&mut self) -> Statement {
+        match self.rng.gen_range(0..3) {
+            0 => {
+                let type_ = self.type_();
+                Statement::ProcedureCall(
+                    dummy(),
+                    ProcedureKind::Print,
+                    vec![self.expression(&type_, 2)],
+                )
+            }
+            1 => Statement::ProcedureCall(
+                dummy(),
+                ProcedureKind::Assert,
+                vec![self.expression(&Type::Bool, 2)],
+            ),
+            _ => {
+                let type_ = Type::Quantity(self.dimension());
+                Statement::ProcedureCall(
+                    dummy(),
+                    ProcedureKind::AssertEq,
+                    vec![self.expression(&type_, 2), self.expression(&type_, 2)],
+                )
+            }
+        }
+    }
+
+    fn statement(&mut self) -> Statement {
+        match self.rng.gen_range(0..20) {
+            0..=5 => {
+                let (definition, bound) = self.define_variable(3, true);
+                self.variables.extend(bound);
+                Statement::DefineVariable(definition)
+            }
+            6..=8 => self.define_function(),
+            9 | 10 => self.define_struct(),
+            11 | 12 => self.define_unit(),
+            13 => self.define_dimension(),
+            14 | 15 => self.procedure_call(),
+            16 => Statement::Conditional {
+                span_if: dummy(),
+                condition: self.expression(&Type::Bool, 2),
+                then_statement: Box::new(self.procedure_call()),
+                else_statement: if self.chance(0.5) {
+                    Some(Box::new(self.procedure_call()))
+                } else {
+                    None
+                },
+            },
+            _ => {
+                let type_ = self.type_();
+                Statement::Expression(self.expression(&type_, 3))
+            }
+        }
+    }
+
+    pub(crate) fn program(&mut self) -> Vec<Statement> {
+        (0..self.rng.gen_range(1..8))
+            .map(|_| self.statement())
+            .collect()
+    }
+}
+
+/// Simpler versions of a value, used to minimize counterexamples
+pub(crate) trait Shrink: Sized {
+    fn shrink(&self) -> Vec<Self>;
+}
+
+impl Expression {
+    /// The direct subexpressions
+    fn children(&self) -> Vec<&Expression> {
+        match self {
+            Expression::UnaryOperator { expr, .. } => vec![expr],
+            Expression::BinaryOperator { lhs, rhs, .. } => vec![lhs, rhs],
+            Expression::Conversion { source, target, .. } => match target {
+                ConversionTarget::Expression(target) => vec![source, target],
+                ConversionTarget::UnitList(_, units) => {
+                    std::iter::once(source.as_ref()).chain(units).collect()
+                }
+            },
+            Expression::ChainedComparison(first, rest) => std::iter::once(first.as_ref())
+                .chain(rest.iter().map(|(_, _, e)| e))
+                .collect(),
+            Expression::FunctionCall(_, _, _, args, named_args, _)
+            | Expression::Partial(_, _, args, named_args) => args
+                .iter()
+                .chain(named_args.iter().map(|(_, _, e)| e))
+                .collect(),
+            Expression::Condition(_, condition, then, else_) => vec![then, else_, condition],
+            Expression::String(_, parts) => parts
+                .iter()
+                .filter_map(|p| match p {
+                    StringPart::Interpolation { expr, .. } => Some(expr.as_ref()),
+                    StringPart::Fixed(_) => None,
+                })
+                .collect(),
+            Expression::InstantiateStruct { base, fields, .. } => base
+                .iter()
+                .map(|b| b.as_ref())
+                .chain(fields.iter().map(|(_, _, e)| e))
+                .collect(),
+            Expression::AccessField(_, _, expr, _) => vec![expr],
+            Expression::List(_, elements) => elements.iter().collect(),
+            Expression::Trace(_, _, expr) => vec![expr],
+            Expression::For {
+                list,
+                accumulator,
+                body,
+                ..
+            } => std::iter::once(list.as_ref())
+                .chain(accumulator.iter().map(|(_, _, init)| init.as_ref()))
+                .chain([body.as_ref()])
+                .collect(),
+            Expression::While {
+                condition,
+                accumulator: (_, _, init),
+                body,
+                ..
+            } => vec![init, condition, body],
+            Expression::TryCatch { expr, fallback, .. } => vec![expr, fallback],
+            Expression::Scalar(..)
+            | Expression::Identifier(..)
+            | Expression::UnitIdentifier(..)
+            | Expression::TypedHole(..)
+            | Expression::Boolean(..)
+            | Expression::AutoDiff(..)
+            | Expression::DimensionPredicate(..) => vec![],
+        }
+    }
+}
+
+impl Shrink for Expression {
+    fn shrink(&self) -> Vec<Self> {
+        let mut candidates: Vec<Expression> = self.children().into_iter().cloned().collect();
+
+        match self {
+            Expression::Scalar(span, n) if n.to_f64() != 1.0 => {
+                candidates.push(Expression::Scalar(*span, Number::from_f64(1.0)));
+            }
+            Expression::List(span, elements) if elements.len() > 1 => {
+                for i in 0..elements.len() {
+                    let mut elements = elements.clone();
+                    elements.remove(i);
+                    candidates.push(Expression::List(*span, elements));
+                }
+            }
+            Expression::String(span, parts) if !parts.is_empty() => {
+                for i in 0..parts.len() {
+                    let mut parts = parts.clone();
+                    parts.remove(i);
+                    candidates.push(Expression::String(*span, parts));
+                }
+            }
+            _ => {}
+        }
+
+        candidates.extend(self.shrink_children());
+        candidates
+    }
+}
+
+impl Expression {
+    /// The expression with one of its direct children replaced by a simpler version
+    fn shrink_children(&self) -> Vec<Expression> {
+        let mut candidates = vec![];
+        let boxed = |e: &Expression| -> Vec<Box<Expression>> {
+            e.shrink().into_iter().map(Box::new).collect()
+        };
+
+        match self {
+            Expression::UnaryOperator { op, expr, span_op } => {
+                for expr in boxed(expr) {
+                    candidates.push(Expression::UnaryOperator {
+                        op: *op,
+                        expr,
+                        span_op: *span_op,
+                    });
+                }
+            }
+            Expression::BinaryOperator {
+                op,
+                lhs,
+                rhs,
+                span_op,
+            } => {
+                for lhs in boxed(lhs) {
+                    candidates.push(Expression::BinaryOperator {
+                        op: *op,
+                        lhs,
+                        rhs: rhs.clone(),
+                        span_op: *span_op,
+                    });
+                }
+                for rhs in boxed(rhs) {
+                    candidates.push(Expression::BinaryOperator {
+                        op: *op,
+                        lhs: lhs.clone(),
+                        rhs,
+                        span_op: *span_op,
+                    });
+                }
+            }
+            Expression::Conversion {
+                source,
+                target,
+                span_op,
+                parenthesized_source,
+            } => {
+                for source in boxed(source) {
+                    candidates.push(Expression::Conversion {
+                        source,
+                        target: target.clone(),
+                        span_op: *span_op,
+                        parenthesized_source: *parenthesized_source,
+                    });
+                }
+            }
+            Expression::Condition(span, condition, then, else_) => {
+                for condition in boxed(condition) {
+                    candidates.push(Expression::Condition(
+                        *span,
+                        condition,
+                        then.clone(),
+                        else_.clone(),
+                    ));
+                }
+                for then in boxed(then) {
+                    candidates.push(Expression::Condition(
+                        *span,
+                        condition.clone(),
+                        then,
+                        else_.clone(),
+                    ));
+                }
+                for else_ in boxed(else_) {
+                    candidates.push(Expression::Condition(
+                        *span,
+                        condition.clone(),
+                        then.clone(),
+                        else_,
+                    ));
+                }
+            }
+            Expression::FunctionCall(span, full_span, callable, args, named_args, syntax) => {
+                for i in 0..args.len() {
+                    for arg in args[i].shrink() {
+                        let mut args = args.clone();
+                        args[i] = arg;
+                        candidates.push(Expression::FunctionCall(
+                            *span,
+                            *full_span,
+                            callable.clone(),
+                            args,
+                            named_args.clone(),
+                            *syntax,
+                        ));
+                    }
+                }
+            }
+            Expression::List(span, elements) => {
+                for i in 0..elements.len() {
+                    for element in elements[i].shrink() {
+                        let mut elements = elements.clone();
+                        elements[i] = element;
+                        candidates.push(Expression::List(*span, elements));
+                    }
+                }
+            }
+            Expression::AccessField(full_span, ident_span, expr, field) => {
+                for expr in boxed(expr) {
+                    candidates.push(Expression::AccessField(
+                        *full_span,
+                        *ident_span,
+                        expr,
+                        *field,
+                    ));
+                }
+            }
+            Expression::InstantiateStruct {
+                full_span,
+                ident_span,
+                name,
+                base,
+                fields,
+            } => {
+                for i in 0..fields.len() {
+                    for value in fields[i].2.shrink() {
+                        let mut fields = fields.clone();
+                        fields[i].2 = value;
+                        candidates.push(Expression::InstantiateStruct {
+                            full_span: *full_span,
+                            ident_span: *ident_span,
+                            name: *name,
+                            base: base.clone(),
+                            fields,
+                        });
+                    }
+                }
+            }
+            _ => {}
+        }
+        candidates
+    }
+}
+
+impl Shrink for DefineVariable {
+    fn shrink(&self) -> Vec<Self> {
+        let mut candidates = vec![];
+        if !self.decorators.is_empty() {
+            candidates.push(DefineVariable {
+                decorators: vec![],
+                ..self.clone()
+            });
+        }
+        if self.type_annotation.is_some() {
+            candidates.push(DefineVariable {
+                type_annotation: None,
+                ..self.clone()
+            });
+        }
+        for expr in self.expr.shrink() {
+            candidates.push(DefineVariable {
+                expr,
+                ..self.clone()
+            });
+        }
+        candidates
+    }
+}
+
+impl Shrink for Statement {
+    fn shrink(&self) -> Vec<Self> {
+        let mut candidates = vec![];
+        match self {
+            Statement::Expression(expr) => {
+                candidates.extend(expr.shrink().into_iter().map(Statement::Expression));
+            }
+            Statement::DefineVariable(definition) => {
+                candidates.extend(
+                    definition
+                        .shrink()
+                        .into_iter()
+                        .map(Statement::DefineVariable),
+                );
+            }
+            Statement::DefineFunction {
+                body: Some(body),
+                local_variables,
+                decorators,
+                return_type_annotation,
+                parameters,
+                ..
+            } => {
+                // A copy of the function definition, modified by `f`
+                let modified = |f: &dyn Fn(&mut Statement)| {
+                    let mut statement = self.clone();
+                    f(&mut statement);
+                    statement
+                };
+
+                if !decorators.is_empty() {
+                    candidates.push(modified(&|s| {
+                        if let Statement::DefineFunction { decorators, .. } = s {
+                            decorators.clear();
+                        }
+                    }));
+                }
+                if return_type_annotation.is_some() {
+                    candidates.push(modified(&|s| {
+                        if let Statement::DefineFunction {
+                            return_type_annotation,
+                            ..
+                        } = s
+                        {
+                            *return_type_annotation = None;
+                        }
+                    }));
+                }
+                if parameters.iter().any(|p| p.3.is_some()) {
+                    candidates.push(modified(&|s| {
+                        if let Statement::DefineFunction { parameters, .. } = s {
+                            for parameter in parameters {
+                                parameter.3 = None;
+                            }
+                        }
+                    }));
+                }
+                for i in 0..local_variables.len() {
+                    candidates.push(modified(&|s| {
+                        if let Statement::DefineFunction {
+                            local_variables, ..
+                        } = s
+                        {
+                            local_variables.remove(i);
+                        }
+                    }));
+                    for definition in local_variables[i].shrink() {
+                        candidates.push(modified(&|s| {
+                            if let Statement::DefineFunction {
+                                local_variables, ..
+                            } = s
+                            {
+                                local_variables[i] = definition.clone();
+                            }
+                        }));
+                    }
+                }
+                for new_body in body.shrink() {
+                    candidates.push(modified(&|s| {
+                        if let Statement::DefineFunction { body, .. } = s {
+                            *body = Some(new_body.clone());
+                        }
+                    }));
+                }
+            }
+            Statement::DefineDerivedUnit {
+                identifier_span,
+                identifier,
+                expr,
+                type_annotation_span,
+                type_annotation,
+                decorators,
+            } => {
+                if !decorators.is_empty() {
+                    candidates.push(Statement::DefineDerivedUnit {
+                        identifier_span: *identifier_span,
+                        identifier: *identifier,
+                        expr: expr.clone(),
+                        type_annotation_span: *type_annotation_span,
+                        type_annotation: type_annotation.clone(),
+                        decorators: vec![],
+                    });
+                }
+                for expr in expr.shrink() {
+                    candidates.push(Statement::DefineDerivedUnit {
+                        identifier_span: *identifier_span,
+                        identifier: *identifier,
+                        expr,
+                        type_annotation_span: *type_annotation_span,
+                        type_annotation: type_annotation.clone(),
+                        decorators: decorators.clone(),
+                    });
+                }
+            }
+            Statement::DefineBaseUnit(span, identifier, annotation, decorators)
+                if !decorators.is_empty() =>
+            {
+                candidates.push(Statement::DefineBaseUnit(
+                    *span,
+                    *identifier,
+                    annotation.clone(),
+                    vec![],
+                ));
+            }
+            Statement::ProcedureCall(span, kind, args) => {
+                for i in 0..args.len() {
+                    for arg in args[i].shrink() {
+                        let mut args = args.clone();
+                        args[i] = arg;
+                        candidates.push(Statement::ProcedureCall(*span, kind.clone(), args));
+                    }
+                }
+            }
+            Statement::Conditional {
+                span_if,
+                condition,
+                then_statement,
+                else_statement,
+            } => {
+                candidates.push(then_statement.as_ref().clone());
+                for then_statement in then_statement.shrink() {
+                    candidates.push(Statement::Conditional {
+                        span_if: *span_if,
+                        condition: condition.clone(),
+                        then_statement: Box::new(then_statement),
+                        else_statement: else_statement.clone(),
+                    });
+                }
+                if else_statement.is_some() {
+                    candidates.push(Statement::Conditional {
+                        span_if: *span_if,
+                        condition: condition.clone(),
+                        then_statement: then_statement.clone(),
+                        else_statement: None,
+                    });
+                }
+                for condition in condition.shrink() {
+                    candidates.push(Statement::Conditional {
+                        span_if: *span_if,
+                        condition,
+                        then_statement: then_statement.clone(),
+                        else_statement: else_statement.clone(),
+                    });
+                }
+            }
+            _ => {}
+        }
+        candidates
+    }
+}
+
+impl Shrink for Vec<Statement> {
+    fn shrink(&self) -> Vec<Self> {
+        let mut candidates = vec![];
+        for i in 0..self.len() {
+            let mut program = self.clone();
+            program.remove(i);
+            candidates.push(program);
+        }
+        for i in 0..self.len() {
+            for statement in self[i].shrink() {
+                let mut program = self.clone();
+                program[i] = statement;
+                candidates.push(program);
+            }
+        }
+        candidates
+    }
+}
+
+/// Type checks the given program after the [`PRELUDE`]. Only the statements of
+/// the program itself are part of the result.
+pub(crate) fn check(program: Vec<Statement>) -> Result<TypedProgram, String> {
+    let prelude = crate::parser::parse(PRELUDE, 0).expect("prelude can be parsed");
+
+    let mut transformer = Transformer::new();
+    let mut typechecker = TypeChecker::default();
+
+    let prelude = transformer.transform(prelude).expect("prelude is valid");
+    typechecker.check(prelude).expect("prelude type checks");
+
+    let program = transformer
+        .transform(program)
+        .map_err(|e| format!("name resolution error: {e}"))?;
+    typechecker
+        .check(program)
+        .map_err(|e| format!("type check error: {e}"))
+}
+
+/// The pretty printed program, one statement per line
+pub(crate) fn format(program: &TypedProgram) -> String {
+    program
+        .iter()
+        .map(|statement| statement.pretty_print().to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub(crate) fn parse(code: &str) -> Result<Vec<Statement>, String> {
+    crate::parser::parse(code, 0)
+        .map(|statements| statements.replace_spans())
+        .map_err(|(_, errors)| {
+            errors
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+}
+
+/// The types of all statements that have one (definitions and expressions)
+fn types(program: &TypedProgram) -> Vec<String> {
+    program
+        .statements
+        .iter()
+        .filter_map(|statement| match statement {
+            typed_ast::Statement::Expression(id) => {
+                Some(program.arena[*id].get_type_scheme(&program.arena))
+            }
+            typed_ast::Statement::DefineVariable(typed_ast::DefineVariable(_, _, _, _, t, _))
+            | typed_ast::Statement::DefineFunction(_, _, _, _, _, _, _, t, _, _)
+            | typed_ast::Statement::DefineBaseUnit(_, _, _, _, t, _)
+            | typed_ast::Statement::DefineDerivedUnit(_, _, _, _, _, t, _) => Some(t.clone()),
+            _ => None,
+        })
+        .map(|t| t.pretty_print().to_string())
+        .collect()
+}
+
+/// The decorators of all definitions in the program
+fn decorators(program: &[Statement]) -> Vec<Vec<Decorator>> {
+    program
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::DefineVariable(DefineVariable { decorators, .. })
+            | Statement::DefineFunction { decorators, .. }
+            | Statement::DefineBaseUnit(_, _, _, decorators)
+            | Statement::DefineDerivedUnit { decorators, .. } => Some(decorators.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Checks that formatting and parsing a pretty printed program again leads to
+/// the same AST (modulo spans) and the same types.
+pub(crate) fn check_roundtrip(program: &[Statement]) -> Result<(), String> {
+    let typed = check(program.to_vec())?;
+    let code = format(&typed);
+
+    let reparsed =
+        parse(&code).map_err(|e| format!("formatted code can not be parsed ({e}):\n{code}"))?;
+    if decorators(program) != decorators(&reparsed) {
+        return Err(format!(
+            "decorators changed after formatting:\n{code}\n{:?}\n{:?}",
+            decorators(program),
+            decorators(&reparsed)
+        ));
+    }
+    let retyped = check(reparsed.clone())
+        .map_err(|e| format!("formatted code does not type check ({e}):\n{code}"))?;
+
+    if types(&typed) != types(&retyped) {
+        return Err(format!(
+            "types changed after formatting:\n{code}\n{:?}\n{:?}",
+            types(&typed),
+            types(&retyped)
+        ));
+    }
+
+    let code_again = format(&retyped);
+    let reparsed_again = parse(&code_again)
+        .map_err(|e| format!("formatted code can not be parsed ({e}):\n{code_again}"))?;
+    if !reparsed.eq_modulo_spans(&reparsed_again) {
+        return Err(format!(
+            "AST changed after formatting:\n{code}\n\nvs.\n\n{code_again}"
+        ));
+    }
+
+    Ok(())
+}
+
+/// Repeatedly replaces the program by a simpler one that still type checks but
+/// fails the given property, until no simpler program can be found.
+pub(crate) fn shrink(
+    mut program: Vec<Statement>,
+    property: impl Fn(&[Statement]) -> Result<(), String>,
+) -> Vec<Statement> {
+    'outer: loop {
+        for candidate in program.shrink() {
+            if check(candidate.clone()).is_ok() && property(&candidate).is_err() {
+                program = candidate;
+                continue 'outer;
+            }
+        }
+        return program;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NUMBER_OF_PROGRAMS: u64 = 500;
+
+    /// The number of programs can be increased for a more thorough check, e.g.
+    /// `NUMBAT_GENERATED_PROGRAMS=100000 cargo test ast_generator`
+    fn number_of_programs() -> u64 {
+        std::env::var("NUMBAT_GENERATED_PROGRAMS")
+            .ok()
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(NUMBER_OF_PROGRAMS)
+    }
+
+    fn check_property(property: impl Fn(&[Statement]) -> Result<(), String>) {
+        for seed in 0..number_of_programs() {
+            let program = Generator::new(seed).program();
+
+            if let Err(e) = check(program.clone()) {
+                panic!("Generated program does not type check ({e}): {program:?}");
+            }
+
+            if property(&program).is_err() {
+                let minimal = shrink(program, &property);
+                let error = property(&minimal).unwrap_err();
+                let code = format(&check(minimal).unwrap());
+                panic!("Property failed for seed {seed}: {error}\n\nMinimal program:\n{code}");
+            }
+        }
+    }
+
+    #[test]
+    fn formatting_roundtrip() {
+        check_property(check_roundtrip);
+    }
+
+    #[test]
+    fn shrinking_finds_small_counterexamples() {
+        // A property that fails for all programs that contain a list
+        fn contains_list(e: &Expression) -> bool {
+            matches!(e, Expression::List(..)) || e.children().into_iter().any(contains_list)
+        }
+        let property = |program: &[Statement]| {
+            let has_list = program.iter().any(|s| match s {
+                Statement::Expression(e) => contains_list(e),
+                Statement::DefineVariable(d) => contains_list(&d.expr),
+                _ => false,
+            });
+            if has_list {
+                Err("program contains a list".to_string())
+            } else {
+                Ok(())
+            }
+        };
+
+        let seed = (0..NUMBER_OF_PROGRAMS)
+            .find(|seed| property(&Generator::new(*seed).program()).is_err())
+            .unwrap();
+        let minimal = shrink(Generator::new(seed).program(), property);
+
+        assert_eq!(minimal.len(), 1);
+        let (Statement::Expression(e) | Statement::DefineVariable(DefineVariable { expr: e, .. })) =
+            &minimal[0]
+        else {
+            panic!("unexpected statement: {minimal:?}")
+        };
+        assert!(matches!(e, Expression::List(_, elements) if elements.len() == 1));
+    }
+
+    #[test]
+    fn generated_programs_are_deterministic() {
+        assert_eq!(Generator::new(42).program(), Generator::new(42).program());
+    }
+}
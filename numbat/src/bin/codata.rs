@@ -0,0 +1,44 @@
+//! Generates the `physics::codata` module, see [`numbat::codata`].
+//!
+//! Usage: `numbat-codata [DATA_FILE] > modules/physics/codata.nbt`
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use numbat::{codata, module_importer::FileSystemImporter, resolver::CodeSource, Context};
+
+fn main() -> ExitCode {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+
+    let data_path = std::env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| manifest_dir.join("data").join("codata.txt"));
+    let data = match std::fs::read_to_string(&data_path) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("error: could not read '{}': {e}", data_path.display());
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut importer = FileSystemImporter::default();
+    importer.add_path(manifest_dir.join("modules"));
+    let mut ctx = Context::new(importer);
+    let _ = ctx
+        .interpret("use units::si", CodeSource::Internal)
+        .expect("units can be loaded");
+
+    match codata::generate(&data, &ctx) {
+        Ok(module) => {
+            print!("{module}");
+            ExitCode::SUCCESS
+        }
+        Err(errors) => {
+            for error in errors {
+                eprintln!("error: {error}");
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
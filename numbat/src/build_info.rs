@@ -0,0 +1,101 @@
+//! Information about this build of Numbat and the capabilities that are available
+//! at runtime, for `numbat --version --verbose` or an "about" box.
+
+#[cfg(feature = "currency")]
+use crate::currency::ExchangeRatesCache;
+use crate::markup as m;
+use crate::module_importer::BuiltinModuleImporter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExchangeRatesStatus {
+    /// The exchange rates have not been requested yet
+    NotLoaded,
+    /// Fetching or parsing the exchange rates failed, or Numbat was built without
+    /// currency support
+    Unavailable,
+    Loaded {
+        currencies: usize,
+    },
+}
+
+/// An optional Cargo feature of the `numbat` crate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Feature {
+    pub name: &'static str,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildInfo {
+    /// The version of the `numbat` crate, which ships the standard library
+    pub version: &'static str,
+    /// A fingerprint of the builtin modules, see [`BuiltinModuleImporter::fingerprint`]
+    pub prelude_fingerprint: String,
+    pub features: Vec<Feature>,
+    pub exchange_rates: ExchangeRatesStatus,
+}
+
+impl BuildInfo {
+    pub fn collect() -> Self {
+        let features = [
+            ("currency", cfg!(feature = "currency")),
+            ("fetch-exchangerates", cfg!(feature = "fetch-exchangerates")),
+            ("datetime", cfg!(feature = "datetime")),
+            ("fs", cfg!(feature = "fs")),
+            ("html-formatter", cfg!(feature = "html-formatter")),
+            ("codata", cfg!(feature = "codata")),
+        ]
+        .into_iter()
+        .map(|(name, enabled)| Feature { name, enabled })
+        .collect();
+
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            prelude_fingerprint: BuiltinModuleImporter::fingerprint(),
+            features,
+            #[cfg(feature = "currency")]
+            exchange_rates: ExchangeRatesCache::status(),
+            #[cfg(not(feature = "currency"))]
+            exchange_rates: ExchangeRatesStatus::Unavailable,
+        }
+    }
+
+    pub fn to_markup(&self) -> m::Markup {
+        let field = |name: &str| m::text(format!("{name:<16}"));
+
+        let features = self
+            .features
+            .iter()
+            .enumerate()
+            .map(|(i, f)| {
+                let separator = if i == 0 { m::empty() } else { m::space() };
+                if f.enabled {
+                    separator + m::keyword(format!("+{}", f.name))
+                } else {
+                    separator + m::dimmed(format!("-{}", f.name))
+                }
+            })
+            .sum::<m::Markup>();
+
+        let exchange_rates = match self.exchange_rates {
+            ExchangeRatesStatus::NotLoaded => m::text("not loaded"),
+            ExchangeRatesStatus::Unavailable => m::text("unavailable"),
+            ExchangeRatesStatus::Loaded { currencies } => {
+                m::text("loaded (") + m::value(currencies.to_string()) + m::text(" currencies)")
+            }
+        };
+
+        field("library:")
+            + m::value(self.version)
+            + m::nl()
+            + field("prelude:")
+            + m::value(&self.prelude_fingerprint)
+            + m::nl()
+            + field("features:")
+            + features
+            + m::nl()
+            + field("exchange rates:")
+            + exchange_rates
+            + m::nl()
+    }
+}
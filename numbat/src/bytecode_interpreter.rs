@@ -1,37 +1,59 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use itertools::Itertools;
 
-use crate::ast::ProcedureKind;
+use crate::ast::{ImportScope, ModuleItem, ProcedureKind, TypeAnnotation};
+use crate::clock::Clock;
+#[cfg(feature = "currency")]
+use crate::currency::ExchangeRatesCache;
 use crate::decorator::Decorator;
 use crate::dimension::DimensionRegistry;
+use crate::file_system::FileSystem;
+use crate::instantiation_cache::{self, InstantiationCache};
 use crate::interpreter::{
-    Interpreter, InterpreterResult, InterpreterSettings, Result, RuntimeError,
+    ImplicitConversions, Interpreter, InterpreterResult, Result, RuntimeError, RuntimeWarning,
 };
-use crate::name_resolution::LAST_RESULT_IDENTIFIERS;
+use crate::name_resolution::{indexed_result_identifier, LAST_RESULT_IDENTIFIERS};
+use crate::number::Number;
+use crate::output::OutputSink;
 use crate::prefix::Prefix;
 use crate::prefix_parser::AcceptsPrefix;
 use crate::pretty_print::PrettyPrint;
+use crate::profile::Profile;
+use crate::sandbox::SandboxPolicy;
+use crate::symbol::Symbol;
+use crate::traversal::ForAllExpressions;
+use crate::type_variable::TypeVariable;
+use crate::typechecker::substitutions::{ApplySubstitution, Substitution};
+use crate::typechecker::type_scheme::TypeScheme;
 use crate::typed_ast::{
-    BinaryOperator, DefineVariable, Expression, Statement, StringPart, UnaryOperator,
+    BinaryOperator, ConversionTarget, DefineVariable, Expression, ExpressionArena, ExpressionId,
+    FunctionParameter, Pattern, Statement, StringPart, TypedProgram, UnaryOperator,
 };
 use crate::unit::{CanonicalName, Unit};
-use crate::unit_registry::{UnitMetadata, UnitRegistry};
-use crate::value::FunctionReference;
+use crate::unit_registry::{UnitMetadata, UnitRegistry, UnitRegistryError};
+use crate::value::{FunctionReference, Value};
 use crate::vm::{Constant, ExecutionContext, Op, Vm};
 use crate::{decorator, ffi, Type};
 
+/// The name of local variables that hold values during destructuring. It is not
+/// a valid identifier, so these variables can not be referred to.
+const HIDDEN_LOCAL: &str = "<destructured>";
+
 #[derive(Debug, Clone, Default)]
 pub struct LocalMetadata {
     pub name: Option<String>,
     pub url: Option<String>,
     pub description: Option<String>,
-    pub aliases: Vec<String>,
+    pub aliases: Vec<Symbol>,
+    /// Standard uncertainty, in the unit of the value
+    pub uncertainty: Option<Number>,
 }
 
 #[derive(Debug, Clone)]
 pub struct Local {
-    identifier: String,
+    identifier: Symbol,
     depth: usize,
     pub metadata: LocalMetadata,
 }
@@ -42,14 +64,143 @@ pub struct BytecodeInterpreter {
     /// List of local variables currently in scope, one vector for each scope (for now: 0: 'global' scope, 1: function scope)
     locals: Vec<Vec<Local>>,
     // Maps names of units to indices of the respective constants in the VM
-    unit_name_to_constant_index: HashMap<String, u16>,
+    unit_name_to_constant_index: HashMap<Symbol, u16>,
     /// List of functions
-    functions: HashMap<String, bool>,
+    functions: HashMap<Symbol, bool>,
+    /// Number of parameters of variadic functions. The arguments for the last
+    /// parameter are collected into a list when the function is called.
+    variadic_functions: HashMap<Symbol, usize>,
+    /// Definitions that have been set aside while a module is imported selectively
+    stashed_definitions: Vec<Vec<(Symbol, NameDefinition)>>,
+    /// Definitions of selectively imported modules that are not in scope
+    hidden_definitions: HashMap<ModuleItem, NameDefinition>,
+    /// Generic functions that can be specialized for the types of a call, see
+    /// [`Self::specialize`]
+    generic_functions: HashMap<Symbol, Arc<GenericFunction>>,
+    /// Specializations of generic functions that have already been compiled
+    instantiations: InstantiationCache,
+    /// The concrete types of the type variables while a specialization is compiled
+    instantiation: Option<Substitution>,
+    /// The exchange rates that the currency units have been defined with, see
+    /// [`crate::currency::ExchangeRatesCache::generation`]
+    #[cfg(feature = "currency")]
+    exchange_rates_generation: u64,
+}
+
+/// The definition of a generic function, which is needed to compile its
+/// specializations later on
+#[derive(Debug)]
+struct GenericFunction {
+    definition: Statement,
+    arena: Arc<ExpressionArena>,
+}
+
+/// Everything the compiler knows about a name, see [`BytecodeInterpreter::take_definition`]
+#[derive(Debug, Clone, Default)]
+struct NameDefinition {
+    unit_constant_index: Option<u16>,
+    /// Whether the function is a foreign function, and its index in the bytecode
+    /// otherwise
+    function: Option<(bool, Option<u16>)>,
+    variadic_parameters: Option<usize>,
+    /// Positions of the global variables of this name
+    global_positions: Vec<usize>,
+    generic_function: Option<Arc<GenericFunction>>,
+}
+
+/// Returns the type if it does not contain any type variables or parameters,
+/// i.e. if values can be checked against it at runtime.
+fn fully_known(type_: &Type) -> Option<Type> {
+    type_.type_variables(true).is_empty().then(|| type_.clone())
 }
 
 impl BytecodeInterpreter {
-    fn compile_expression(&mut self, expr: &Expression) -> Result<()> {
-        match expr {
+    /// Compiles a chain of (arithmetic or comparison) binary operators like `1 + 2 + … + n`.
+    /// The left-hand sides are walked down without recursion, so that long chains do
+    /// not overflow the stack.
+    fn compile_binary_operator_chain(
+        &mut self,
+        arena: &ExpressionArena,
+        expr: ExpressionId,
+    ) -> Result<()> {
+        let mut chain = vec![];
+        let mut current = expr;
+        loop {
+            let lhs = match &arena[current] {
+                Expression::BinaryOperator(_, operator, lhs, _, _)
+                    if !matches!(
+                        operator,
+                        BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr
+                    ) =>
+                {
+                    lhs
+                }
+                Expression::BinaryOperatorForDate(_, _, lhs, _, _) => lhs,
+                _ => break,
+            };
+            chain.push(current);
+            current = *lhs;
+        }
+
+        self.compile_expression(arena, current)?;
+
+        for node in chain.into_iter().rev() {
+            match &arena[node] {
+                Expression::BinaryOperator(_span, operator, _lhs, rhs, _type) => {
+                    self.compile_expression(arena, *rhs)?;
+
+                    let op = match operator {
+                        BinaryOperator::Add => Op::Add,
+                        BinaryOperator::Sub => Op::Subtract,
+                        BinaryOperator::Mul => Op::Multiply,
+                        BinaryOperator::Div => Op::Divide,
+                        BinaryOperator::Power => Op::Power,
+                        BinaryOperator::LessThan => Op::LessThan,
+                        BinaryOperator::GreaterThan => Op::GreaterThan,
+                        BinaryOperator::LessOrEqual => Op::LessOrEqual,
+                        BinaryOperator::GreaterOrEqual => Op::GreatorOrEqual,
+                        BinaryOperator::Equal => Op::Equal,
+                        BinaryOperator::NotEqual => Op::NotEqual,
+                        BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr => {
+                            unreachable!("Logical operators are compiled with jumps")
+                        }
+                    };
+                    self.vm.add_op(op);
+                }
+                Expression::BinaryOperatorForDate(_span, operator, _lhs, rhs, type_) => {
+                    self.compile_expression(arena, *rhs)?;
+
+                    // if the result is a duration:
+                    let op = if type_.is_dtype() {
+                        // the VM will need to return a duration that is promoted to a value with the units of Seconds.
+                        // so look up that unit here, and push it onto the stack, so the VM can easily reference it.
+                        // TODO: We do not want to hard-code 'second' here. Instead, we might
+                        // introduce a decorator to register the 'second' unit in the prelude for
+                        // this specific purpose. We also need to handle errors in case no such unit
+                        // was registered.
+                        let second_idx =
+                            self.unit_name_to_constant_index.get(&Symbol::new("second"));
+                        self.vm.add_op1(Op::LoadConstant, *second_idx.unwrap());
+                        Op::DiffDateTime
+                    } else {
+                        match operator {
+                            BinaryOperator::Add => Op::AddToDateTime,
+                            BinaryOperator::Sub => Op::SubFromDateTime,
+                            _ => unreachable!("{operator:?} is not valid with a DateTime"), // should be unreachable, because the typechecker will error first
+                        }
+                    };
+
+                    self.vm.add_op(op);
+                }
+                _ => unreachable!("The chain consists of binary operators"),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn compile_expression(&mut self, arena: &ExpressionArena, expr: ExpressionId) -> Result<()> {
+        match &arena[expr] {
             Expression::Scalar(_span, n, _type) => {
                 let index = self.vm.add_constant(Constant::Scalar(n.to_f64()));
                 self.vm.add_op1(Op::LoadConstant, index);
@@ -61,23 +212,27 @@ impl BytecodeInterpreter {
 
                 if let Some(position) = self.locals[current_depth]
                     .iter()
-                    .rposition(|l| &l.identifier == identifier && l.depth == current_depth)
+                    .rposition(|l| l.identifier == *identifier && l.depth == current_depth)
                 {
                     self.vm.add_op1(Op::GetLocal, position as u16); // TODO: check overflow
                 } else if let Some(upvalue_position) = self.locals[0]
                     .iter()
-                    .rposition(|l| &l.identifier == identifier)
+                    .rposition(|l| l.identifier == *identifier)
                 {
                     self.vm.add_op1(Op::GetUpvalue, upvalue_position as u16);
                 } else if LAST_RESULT_IDENTIFIERS.contains(&identifier.as_str()) {
                     self.vm.add_op(Op::GetLastResult);
+                } else if let Some(index) = indexed_result_identifier(identifier) {
+                    let index = u16::try_from(index)
+                        .map_err(|_| RuntimeError::ResultIndexOutOfRange(index))?;
+                    self.vm.add_op1(Op::GetResult, index);
                 } else if let Some(is_foreign) = self.functions.get(identifier) {
                     let index = self
                         .vm
                         .add_constant(Constant::FunctionReference(if *is_foreign {
-                            FunctionReference::Foreign(identifier.clone())
+                            FunctionReference::Foreign(identifier.to_string())
                         } else {
-                            FunctionReference::Normal(identifier.clone())
+                            FunctionReference::Normal(identifier.to_string())
                         }));
                     self.vm.add_op1(Op::LoadConstant, index);
                 } else {
@@ -98,80 +253,175 @@ impl BytecodeInterpreter {
                 }
             }
             Expression::UnaryOperator(_span, UnaryOperator::Negate, rhs, _type) => {
-                self.compile_expression(rhs)?;
+                self.compile_expression(arena, *rhs)?;
                 self.vm.add_op(Op::Negate);
             }
             Expression::UnaryOperator(_span, UnaryOperator::Factorial, lhs, _type) => {
-                self.compile_expression(lhs)?;
+                self.compile_expression(arena, *lhs)?;
                 self.vm.add_op(Op::Factorial);
             }
             Expression::UnaryOperator(_span, UnaryOperator::LogicalNeg, lhs, _type) => {
-                self.compile_expression(lhs)?;
+                self.compile_expression(arena, *lhs)?;
                 self.vm.add_op(Op::LogicalNeg);
             }
-            Expression::BinaryOperator(_span, operator, lhs, rhs, _type) => {
-                self.compile_expression(lhs)?;
-                self.compile_expression(rhs)?;
-
-                let op = match operator {
-                    BinaryOperator::Add => Op::Add,
-                    BinaryOperator::Sub => Op::Subtract,
-                    BinaryOperator::Mul => Op::Multiply,
-                    BinaryOperator::Div => Op::Divide,
-                    BinaryOperator::Power => Op::Power,
-                    BinaryOperator::ConvertTo => Op::ConvertTo,
-                    BinaryOperator::LessThan => Op::LessThan,
-                    BinaryOperator::GreaterThan => Op::GreaterThan,
-                    BinaryOperator::LessOrEqual => Op::LessOrEqual,
-                    BinaryOperator::GreaterOrEqual => Op::GreatorOrEqual,
-                    BinaryOperator::Equal => Op::Equal,
-                    BinaryOperator::NotEqual => Op::NotEqual,
-                    BinaryOperator::LogicalAnd => Op::LogicalAnd,
-                    BinaryOperator::LogicalOr => Op::LogicalOr,
-                };
-                self.vm.add_op(op);
-            }
-            Expression::BinaryOperatorForDate(_span, operator, lhs, rhs, type_) => {
-                self.compile_expression(lhs)?;
-                self.compile_expression(rhs)?;
-
-                // if the result is a duration:
-                let op = if type_.is_dtype() {
-                    // the VM will need to return a value with the units of Seconds.  so look up that unit here, and push it
-                    // onto the stack, so the VM can easily reference it.
-                    // TODO: We do not want to hard-code 'second' here. Instead, we might
-                    // introduce a decorator to register the 'second' unit in the prelude for
-                    // this specific purpose. We also need to handle errors in case no such unit
-                    // was registered.
-                    let second_idx = self.unit_name_to_constant_index.get("second");
-                    self.vm.add_op1(Op::LoadConstant, *second_idx.unwrap());
-                    Op::DiffDateTime
-                } else {
-                    match operator {
-                        BinaryOperator::Add => Op::AddToDateTime,
-                        BinaryOperator::Sub => Op::SubFromDateTime,
-                        _ => unreachable!("{operator:?} is not valid with a DateTime"), // should be unreachable, because the typechecker will error first
+            Expression::BinaryOperator(
+                _span,
+                operator @ (BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr),
+                lhs,
+                rhs,
+                _type,
+            ) => {
+                // The right hand side is only evaluated if the left hand side does
+                // not already determine the result: `a && b` is compiled like
+                // `if a then b else false`, and `a || b` like `if a then true else b`.
+                let compile_branch = |ctx: &mut Self, evaluate_rhs: bool| {
+                    if evaluate_rhs {
+                        ctx.compile_expression(arena, *rhs)
+                    } else {
+                        let value = *operator == BinaryOperator::LogicalOr;
+                        let idx = ctx.vm.add_constant(Constant::Boolean(value));
+                        ctx.vm.add_op1(Op::LoadConstant, idx);
+                        Ok(())
                     }
                 };
+                let is_and = *operator == BinaryOperator::LogicalAnd;
 
-                self.vm.add_op(op);
+                self.compile_expression(arena, *lhs)?;
+
+                let if_jump_offset = self.vm.current_offset() + 1; // +1 for the opcode
+                self.vm.add_op1(Op::JumpIfFalse, 0xffff);
+
+                compile_branch(self, is_and)?;
+
+                let else_jump_offset = self.vm.current_offset() + 1;
+                self.vm.add_op1(Op::Jump, 0xffff);
+
+                let else_block_offset = self.vm.current_offset();
+                self.vm
+                    .patch_u16_value_at(if_jump_offset, else_block_offset - (if_jump_offset + 2));
+
+                compile_branch(self, !is_and)?;
+
+                let end_offset = self.vm.current_offset();
+                self.vm
+                    .patch_u16_value_at(else_jump_offset, end_offset - (else_jump_offset + 2));
             }
-            Expression::FunctionCall(_span, _full_span, name, args, _type) => {
+            Expression::BinaryOperator(..) => {
+                self.compile_binary_operator_chain(arena, expr)?;
+            }
+            Expression::ChainedComparison(comparisons) => {
+                // `a < b < c` is evaluated like `a < b && b < c`, but `b` is only
+                // evaluated once. A copy of it is kept below the result of the
+                // first comparison, to be used as the left hand side of the next.
+                let mut fail_jump_offsets = vec![];
+                for (i, comparison) in comparisons.iter().enumerate() {
+                    let Expression::BinaryOperator(_, operator, lhs, rhs, _) = &arena[*comparison]
+                    else {
+                        unreachable!("Chains consist of binary comparisons")
+                    };
+
+                    if i == 0 {
+                        self.compile_expression(arena, *lhs)?;
+                    }
+                    self.compile_expression(arena, *rhs)?;
+
+                    let is_last = i == comparisons.len() - 1;
+                    if !is_last {
+                        self.vm.add_op(Op::Tuck);
+                    }
+
+                    self.vm.add_op(match operator {
+                        BinaryOperator::LessThan => Op::LessThan,
+                        BinaryOperator::GreaterThan => Op::GreaterThan,
+                        BinaryOperator::LessOrEqual => Op::LessOrEqual,
+                        BinaryOperator::GreaterOrEqual => Op::GreatorOrEqual,
+                        _ => unreachable!("Only orderings can be chained"),
+                    });
+
+                    if !is_last {
+                        fail_jump_offsets.push(self.vm.current_offset() + 1);
+                        self.vm.add_op1(Op::JumpIfFalse, 0xffff);
+                    }
+                }
+
+                let end_jump_offset = self.vm.current_offset() + 1;
+                self.vm.add_op1(Op::Jump, 0xffff);
+
+                // One of the comparisons failed: replace the copy of its right hand side
+                let fail_block_offset = self.vm.current_offset();
+                for offset in fail_jump_offsets {
+                    self.vm
+                        .patch_u16_value_at(offset, fail_block_offset - (offset + 2));
+                }
+                self.vm.add_op(Op::Pop);
+                let idx = self.vm.add_constant(Constant::Boolean(false));
+                self.vm.add_op1(Op::LoadConstant, idx);
+
+                let end_offset = self.vm.current_offset();
+                self.vm
+                    .patch_u16_value_at(end_jump_offset, end_offset - (end_jump_offset + 2));
+            }
+            Expression::BinaryOperatorForDate(..) => {
+                self.compile_binary_operator_chain(arena, expr)?;
+            }
+            Expression::FunctionCall(_span, full_span, name, args, _syntax, type_) => {
                 // Put all arguments on top of the stack
                 for arg in args {
-                    self.compile_expression_with_simplify(arg)?;
+                    self.compile_expression_with_simplify(arena, *arg)?;
                 }
 
-                if let Some(idx) = self.vm.get_ffi_callable_idx(name) {
+                let num_args = match self.variadic_functions.get(name) {
+                    Some(&num_parameters) => {
+                        let num_variadic = args.len() - (num_parameters - 1);
+                        self.vm.add_op1(Op::BuildList, num_variadic as u16); // TODO: check overflow
+                        num_parameters as u16
+                    }
+                    None => args.len() as u16,
+                };
+
+                if let (true, Some(idx)) = (
+                    self.vm.is_native_function(name),
+                    self.vm.get_ffi_callable_idx(name),
+                ) {
+                    let call_site_idx = self
+                        .vm
+                        .add_call_site(*full_span, fully_known(&self.concrete_type(type_)));
+                    self.vm
+                        .add_op3(Op::CallNative, idx, num_args, call_site_idx);
+                } else if let Some(idx) = self
+                    .vm
+                    .get_ffi_callable_idx(name)
+                    .filter(|idx| self.vm.is_typed_function(*idx))
+                {
+                    let call_site_idx = self
+                        .vm
+                        .add_call_site(*full_span, fully_known(&self.concrete_type(type_)));
+                    self.vm
+                        .add_op3(Op::FFICallTypedFunction, idx, num_args, call_site_idx);
+                } else if let Some(idx) = self.vm.get_ffi_callable_idx(name) {
                     // TODO: check overflow:
-                    self.vm.add_op2(Op::FFICallFunction, idx, args.len() as u16);
+                    self.vm.add_op2(Op::FFICallFunction, idx, num_args);
                 } else {
-                    let idx = self.vm.get_function_idx(name);
+                    let idx = match self.specialize(arena, *name, args, type_)? {
+                        Some(idx) => idx,
+                        None => self.vm.get_function_idx(name),
+                    };
+
+                    self.vm.add_op2(Op::Call, idx, num_args); // TODO: check overflow
+                }
+            }
+            Expression::InstantiateStruct(_span, Some(base), exprs, struct_info) => {
+                // fields that are not overridden are copied from the base instance
+                self.compile_expression_with_simplify(arena, *base)?;
+
+                for (name, expr) in exprs {
+                    self.compile_expression_with_simplify(arena, *expr)?;
 
-                    self.vm.add_op2(Op::Call, idx, args.len() as u16); // TODO: check overflow
+                    let field_idx = struct_info.fields.get_index_of(name).unwrap() as u16;
+                    self.vm.add_op1(Op::UpdateStructField, field_idx);
                 }
             }
-            Expression::InstantiateStruct(_span, exprs, struct_info) => {
+            Expression::InstantiateStruct(_span, None, exprs, struct_info) => {
                 // structs must be consistently ordered in the VM, so we reorder
                 // the field values so that they are evaluated in the order the
                 // struct fields are defined.
@@ -181,16 +431,16 @@ impl BytecodeInterpreter {
                     .sorted_by_key(|(n, _)| struct_info.fields.get_index_of(n).unwrap());
 
                 for (_, expr) in sorted_exprs.rev() {
-                    self.compile_expression_with_simplify(expr)?;
+                    self.compile_expression_with_simplify(arena, *expr)?;
                 }
 
-                let struct_info_idx = self.vm.get_structinfo_idx(&struct_info.name).unwrap() as u16;
+                let struct_info_idx = self.vm.get_structinfo_idx(struct_info.name).unwrap() as u16;
 
                 self.vm
                     .add_op2(Op::BuildStructInstance, struct_info_idx, exprs.len() as u16);
             }
             Expression::AccessField(_span, _full_span, expr, attr, struct_type, _result_type) => {
-                self.compile_expression_with_simplify(expr)?;
+                self.compile_expression_with_simplify(arena, *expr)?;
 
                 let Type::Struct(ref struct_info) = struct_type.to_concrete_type() else {
                     unreachable!(
@@ -205,14 +455,62 @@ impl BytecodeInterpreter {
             Expression::CallableCall(_span, callable, args, _type) => {
                 // Put all arguments on top of the stack
                 for arg in args {
-                    self.compile_expression_with_simplify(arg)?;
+                    self.compile_expression_with_simplify(arena, *arg)?;
                 }
 
                 // Put the callable on top of the stack
-                self.compile_expression(callable)?;
+                self.compile_expression(arena, *callable)?;
 
                 self.vm.add_op1(Op::CallCallable, args.len() as u16);
             }
+            Expression::Conversion(_span_op, source, target, _type) => match target {
+                ConversionTarget::Unit(unit) => {
+                    self.compile_expression(arena, *source)?;
+                    self.compile_expression(arena, *unit)?;
+                    self.vm.add_op(Op::ConvertTo);
+                }
+                ConversionTarget::UnitList(_span, units) => {
+                    self.compile_expression(arena, *source)?;
+                    for unit in units {
+                        self.compile_expression(arena, *unit)?;
+                    }
+                    self.vm.add_op1(Op::ConvertToUnitList, units.len() as u16);
+                }
+                ConversionTarget::UnitSystem(_span, name) => {
+                    self.compile_expression(arena, *source)?;
+                    let name_idx = self.vm.add_constant(Constant::String(name.to_string()));
+                    self.vm.add_op1(Op::ConvertToUnitSystem, name_idx);
+                }
+                ConversionTarget::UnitFunction(_, _, function)
+                | ConversionTarget::Function(function) => {
+                    self.compile_expression_with_simplify(arena, *source)?;
+                    self.compile_expression(arena, *function)?;
+                    self.vm.add_op1(Op::CallCallable, 1);
+                }
+            },
+            Expression::Partial(_span, function, args, _type) => {
+                self.compile_expression(arena, *function)?;
+                for arg in args {
+                    self.compile_expression_with_simplify(arena, *arg)?;
+                }
+
+                self.vm.add_op1(Op::BindArguments, args.len() as u16);
+            }
+            Expression::AutoDiff(_span, _function, definitions, _type) => {
+                for definition in definitions {
+                    self.compile_function_definition(arena, definition)?;
+                }
+
+                let Some(Statement::DefineFunction(name, ..)) = definitions.last() else {
+                    unreachable!("autodiff defines at least one function")
+                };
+                let index =
+                    self.vm
+                        .add_constant(Constant::FunctionReference(FunctionReference::Normal(
+                            name.to_string(),
+                        )));
+                self.vm.add_op1(Op::LoadConstant, index);
+            }
             Expression::Boolean(_, val) => {
                 let index = self.vm.add_constant(Constant::Boolean(*val));
                 self.vm.add_op1(Op::LoadConstant, index);
@@ -229,7 +527,7 @@ impl BytecodeInterpreter {
                             span: _,
                             format_specifiers,
                         } => {
-                            self.compile_expression_with_simplify(expr)?;
+                            self.compile_expression_with_simplify(arena, *expr)?;
                             let index = self.vm.add_constant(Constant::FormatSpecifiers(
                                 format_specifiers.clone(),
                             ));
@@ -240,12 +538,12 @@ impl BytecodeInterpreter {
                 self.vm.add_op1(Op::JoinString, string_parts.len() as u16); // TODO: this can overflow
             }
             Expression::Condition(_, condition, then_expr, else_expr) => {
-                self.compile_expression(condition)?;
+                self.compile_expression(arena, *condition)?;
 
                 let if_jump_offset = self.vm.current_offset() + 1; // +1 for the opcode
                 self.vm.add_op1(Op::JumpIfFalse, 0xffff);
 
-                self.compile_expression(then_expr)?;
+                self.compile_expression(arena, *then_expr)?;
 
                 let else_jump_offset = self.vm.current_offset() + 1;
                 self.vm.add_op1(Op::Jump, 0xffff);
@@ -254,7 +552,7 @@ impl BytecodeInterpreter {
                 self.vm
                     .patch_u16_value_at(if_jump_offset, else_block_offset - (if_jump_offset + 2));
 
-                self.compile_expression(else_expr)?;
+                self.compile_expression(arena, *else_expr)?;
 
                 let end_offset = self.vm.current_offset();
 
@@ -263,7 +561,7 @@ impl BytecodeInterpreter {
             }
             Expression::List(_, elements, _) => {
                 for element in elements {
-                    self.compile_expression_with_simplify(element)?;
+                    self.compile_expression_with_simplify(arena, *element)?;
                 }
 
                 self.vm.add_op1(Op::BuildList, elements.len() as u16);
@@ -271,28 +569,121 @@ impl BytecodeInterpreter {
             Expression::TypedHole(_, _) => {
                 unreachable!("Typed holes cause type inference errors")
             }
+            Expression::NamedArgument(_, _, expr) => {
+                self.compile_expression_with_simplify(arena, *expr)?;
+            }
+            Expression::DefaultArgument(_, _) => {
+                let index = self.vm.add_constant(Constant::DefaultArgument);
+                self.vm.add_op1(Op::LoadConstant, index);
+            }
+            Expression::Trace(_, source, expr) => {
+                self.compile_expression(arena, *expr)?;
+
+                use crate::markup as m;
+                let source_idx = self.vm.add_string(m::text(source));
+
+                // In the body of a generic function, the type is known if this is a
+                // specialization for the types of a call. Otherwise, the dimension of
+                // the value is shown at runtime.
+                let type_scheme = arena[*expr].get_type_scheme(arena);
+                let type_ = self.concrete_type(&type_scheme);
+                let is_generic = !type_.type_variables(true).is_empty();
+                let type_idx = self.vm.add_string(if is_generic {
+                    type_scheme.pretty_print()
+                } else {
+                    type_.pretty_print()
+                });
+                self.vm
+                    .add_op3(Op::Trace, source_idx, type_idx, u16::from(is_generic));
+            }
+            Expression::For(_, variable, list, accumulator, body) => {
+                let num_captured = self.capture_locals();
+
+                self.compile_expression_with_simplify(arena, *list)?;
+
+                let (op, parameters) = if let Some((name, init)) = accumulator {
+                    self.compile_expression_with_simplify(arena, *init)?;
+                    (Op::ForLoopWithAccumulator, vec![*variable, *name])
+                } else {
+                    (Op::ForLoop, vec![*variable])
+                };
+
+                let body_idx =
+                    self.compile_nested_function("<for loop>", arena, *body, &parameters)?;
+
+                self.vm.add_op2(op, body_idx, num_captured);
+            }
+            Expression::While(_, condition, (name, init), body) => {
+                let num_captured = self.capture_locals();
+
+                self.compile_expression_with_simplify(arena, *init)?;
+
+                let condition_idx =
+                    self.compile_nested_function("<while condition>", arena, *condition, &[*name])?;
+                let body_idx =
+                    self.compile_nested_function("<while loop>", arena, *body, &[*name])?;
+
+                self.vm
+                    .add_op3(Op::WhileLoop, condition_idx, body_idx, num_captured);
+            }
+            Expression::TryCatch(_, expr, message, fallback) => {
+                let num_captured = self.capture_locals();
+
+                let expr_idx = self.compile_nested_function("<try>", arena, *expr, &[])?;
+
+                let (op, parameters) = match message {
+                    Some(name) => (Op::TryCatchWithMessage, vec![*name]),
+                    None => (Op::TryCatch, vec![]),
+                };
+                let fallback_idx =
+                    self.compile_nested_function("<catch>", arena, *fallback, &parameters)?;
+
+                self.vm.add_op3(op, expr_idx, fallback_idx, num_captured);
+            }
+            Expression::DynamicCheck(span, expr, type_, is_quantity) => {
+                self.compile_expression_with_simplify(arena, *expr)?;
+
+                let check_idx =
+                    self.vm
+                        .add_dynamic_check(*span, self.concrete_type(type_), *is_quantity);
+                self.vm.add_op1(Op::CheckDynamicType, check_idx);
+            }
         };
 
         Ok(())
     }
 
-    fn compile_expression_with_simplify(&mut self, expr: &Expression) -> Result<()> {
-        self.compile_expression(expr)?;
+    fn compile_expression_with_simplify(
+        &mut self,
+        arena: &ExpressionArena,
+        expr: ExpressionId,
+    ) -> Result<()> {
+        self.compile_expression(arena, expr)?;
 
-        match expr {
+        match &arena[expr] {
             Expression::Scalar(..)
             | Expression::Identifier(..)
             | Expression::UnitIdentifier(..)
             | Expression::FunctionCall(..)
             | Expression::CallableCall(..)
             | Expression::UnaryOperator(..)
-            | Expression::BinaryOperator(_, BinaryOperator::ConvertTo, _, _, _)
+            | Expression::Conversion(..)
             | Expression::Boolean(..)
             | Expression::String(..)
             | Expression::Condition(..)
+            | Expression::ChainedComparison(..)
             | Expression::InstantiateStruct(..)
             | Expression::AccessField(..)
-            | Expression::List(..) => {}
+            | Expression::List(..)
+            | Expression::NamedArgument(..)
+            | Expression::DefaultArgument(..)
+            | Expression::Trace(..)
+            | Expression::Partial(..)
+            | Expression::AutoDiff(..)
+            | Expression::For(..)
+            | Expression::While(..)
+            | Expression::TryCatch(..)
+            | Expression::DynamicCheck(..) => {}
             Expression::BinaryOperator(..) | Expression::BinaryOperatorForDate(..) => {
                 self.vm.add_op(Op::FullSimplify);
             }
@@ -302,28 +693,103 @@ impl BytecodeInterpreter {
         Ok(())
     }
 
-    fn compile_define_variable(&mut self, define_variable: &DefineVariable) -> Result<()> {
-        let DefineVariable(identifier, decorators, expr, _annotation, _type, _readable_type) =
+    fn register_variadic_function(&mut self, name: &Symbol, parameters: &[FunctionParameter]) {
+        if let Some((_, _, Some(TypeAnnotation::Variadic(..)), _, _)) = parameters.last() {
+            self.variadic_functions.insert(*name, parameters.len());
+        } else {
+            self.variadic_functions.remove(name);
+        }
+    }
+
+    /// Loop bodies and the branches of `try`-`catch` are compiled as separate
+    /// functions. Inside of a function, they need access to all local variables,
+    /// which are pushed onto the stack here and passed to them as additional
+    /// arguments. Returns their number.
+    fn capture_locals(&mut self) -> u16 {
+        let current_depth = self.current_depth();
+        if current_depth == 0 {
+            // Global variables are accessed as upvalues
+            return 0;
+        }
+
+        let num_captured = self.locals[current_depth].len() as u16; // TODO: check overflow
+        for position in 0..num_captured {
+            self.vm.add_op1(Op::GetLocal, position);
+        }
+        num_captured
+    }
+
+    /// Compiles `expr` as a function of the captured local variables (see
+    /// [`Self::capture_locals`]), followed by the given parameters.
+    fn compile_nested_function(
+        &mut self,
+        name: &str,
+        arena: &ExpressionArena,
+        expr: ExpressionId,
+        parameters: &[Symbol],
+    ) -> Result<u16> {
+        let current_depth = self.current_depth();
+        let depth = current_depth + 1;
+
+        let captured = if current_depth == 0 {
+            vec![]
+        } else {
+            self.locals[current_depth].clone()
+        };
+        let locals = captured
+            .into_iter()
+            .map(|local| Local { depth, ..local })
+            .chain(parameters.iter().map(|parameter| Local {
+                identifier: *parameter,
+                depth,
+                metadata: LocalMetadata::default(),
+            }))
+            .collect();
+
+        let function_idx = self.vm.begin_function(name);
+        self.locals.push(locals);
+
+        let result = self.compile_expression_with_simplify(arena, expr);
+        self.vm.add_op(Op::Return);
+
+        self.locals.pop();
+        self.vm.end_function();
+
+        result.map(|()| function_idx)
+    }
+
+    fn compile_define_variable(
+        &mut self,
+        arena: &ExpressionArena,
+        define_variable: &DefineVariable,
+    ) -> Result<()> {
+        let DefineVariable(pattern, decorators, expr, _annotation, _type, _readable_type) =
             define_variable;
         let current_depth = self.current_depth();
 
+        let Pattern::Identifier(identifier) = pattern else {
+            self.compile_expression_with_simplify(arena, *expr)?;
+            self.compile_pattern(pattern);
+            return Ok(());
+        };
+
         // For variables, we ignore the prefix info and only use the names
-        let aliases = crate::decorator::name_and_aliases(identifier, decorators)
+        let aliases = crate::decorator::name_and_aliases(*identifier, decorators)
             .map(|(name, _)| name)
-            .cloned()
             .collect::<Vec<_>>();
         let metadata = LocalMetadata {
             name: crate::decorator::name(decorators),
             url: crate::decorator::url(decorators),
             description: crate::decorator::description(decorators),
             aliases: aliases.clone(),
+            uncertainty: crate::decorator::uncertainty(decorators),
         };
 
         for alias_name in aliases {
-            self.compile_expression_with_simplify(expr)?;
+            self.compile_expression_with_simplify(arena, *expr)?;
 
             self.locals[current_depth].push(Local {
-                identifier: alias_name.clone(),
+                identifier: alias_name,
                 depth: current_depth,
                 metadata: metadata.clone(),
             });
@@ -331,54 +797,539 @@ impl BytecodeInterpreter {
         Ok(())
     }
 
-    fn compile_statement(
+    /// Binds the value on top of the stack to the identifiers in the pattern. Values
+    /// that are destructured further are kept in hidden local variables.
+    fn compile_pattern(&mut self, pattern: &Pattern) {
+        let current_depth = self.current_depth();
+
+        match pattern {
+            Pattern::Identifier(identifier) => {
+                self.locals[current_depth].push(Local {
+                    identifier: *identifier,
+                    depth: current_depth,
+                    metadata: LocalMetadata {
+                        aliases: vec![*identifier],
+                        ..LocalMetadata::default()
+                    },
+                });
+            }
+            Pattern::Struct(fields, struct_type) => {
+                let Type::Struct(ref struct_info) = struct_type.to_concrete_type() else {
+                    unreachable!(
+                        "Destructuring of non-struct types should be prevented by the type checker"
+                    );
+                };
+
+                let position = self.push_hidden_local();
+                for (field, field_pattern) in fields {
+                    let idx = struct_info.fields.get_index_of(field).unwrap();
+
+                    self.vm.add_op1(Op::GetLocal, position);
+                    self.vm.add_op1(Op::AccessStructField, idx as u16);
+                    self.compile_pattern(field_pattern);
+                }
+            }
+            Pattern::List(elements) => {
+                self.vm.add_op1(Op::DestructureList, elements.len() as u16);
+
+                let mut nested = vec![];
+                for element in elements {
+                    if let Pattern::Identifier(_) = element {
+                        self.compile_pattern(element);
+                    } else {
+                        nested.push((self.push_hidden_local(), element));
+                    }
+                }
+                for (position, element) in nested {
+                    self.vm.add_op1(Op::GetLocal, position);
+                    self.compile_pattern(element);
+                }
+            }
+        }
+    }
+
+    /// Registers the value on top of the stack as a local variable that can not be
+    /// referred to by name, and returns its slot.
+    fn push_hidden_local(&mut self) -> u16 {
+        let current_depth = self.current_depth();
+        let position = self.locals[current_depth].len();
+        self.locals[current_depth].push(Local {
+            identifier: HIDDEN_LOCAL.into(),
+            depth: current_depth,
+            metadata: LocalMetadata::default(),
+        });
+        position as u16 // TODO: check overflow
+    }
+
+    /// Units that are local to a function are not added to the unit registry, since
+    /// they are not visible outside of the function.
+    fn compile_unit_definition(
         &mut self,
+        arena: &ExpressionArena,
         stmt: &Statement,
         dimension_registry: &DimensionRegistry,
+        is_local: bool,
     ) -> Result<()> {
         match stmt {
-            Statement::Expression(expr) => {
-                self.compile_expression_with_simplify(expr)?;
-                self.vm.add_op(Op::Return);
-            }
-            Statement::DefineVariable(define_variable) => {
-                self.compile_define_variable(define_variable)?
+            Statement::DefineBaseUnit(span, unit_name, decorators, annotation, type_, _) => {
+                let aliases = decorator::name_and_aliases(*unit_name, decorators).collect();
+
+                let unit = Unit::new_base(
+                    unit_name,
+                    crate::decorator::get_canonical_unit_name(*unit_name, &decorators[..]),
+                );
+
+                if !is_local {
+                    let metadata = UnitMetadata {
+                        type_: type_.to_concrete_type(), // Base unit types can never be generic
+                        readable_type: annotation
+                            .as_ref()
+                            .map(|a| a.pretty_print())
+                            .unwrap_or(type_.to_readable_type(dimension_registry)),
+                        aliases,
+                        name: decorator::name(decorators),
+                        canonical_name: decorator::get_canonical_unit_name(*unit_name, decorators),
+                        url: decorator::url(decorators),
+                        description: decorator::description(decorators),
+                        binary_prefixes: decorators.contains(&Decorator::BinaryPrefixes),
+                        metric_prefixes: decorators.contains(&Decorator::MetricPrefixes),
+                        currency: decorator::currency(decorators),
+                        ucum: decorator::ucum(decorators),
+                        isolated: decorators.contains(&Decorator::Isolated),
+                        deprecated: decorator::deprecated(decorators),
+                    };
+                    let unit_information_idx = self
+                        .vm
+                        .add_unit_information(*unit_name, None, metadata, *span);
+                    self.vm.add_op1(Op::DefineBaseUnit, unit_information_idx);
+                }
+                if unit_name.as_str() == "radian" {
+                    // Angle is a base dimension (see `core::strict_angle`)
+                    self.vm.set_angle_unit(unit.clone());
+                }
+
+                let constant_idx = self.vm.add_constant(Constant::Unit(unit));
+                for (name, _) in decorator::name_and_aliases(*unit_name, decorators) {
+                    self.unit_name_to_constant_index.insert(name, constant_idx);
+                }
             }
-            Statement::DefineFunction(
-                name,
-                _decorators,
-                _type_parameters,
-                parameters,
-                Some(expr),
-                local_variables,
-                _return_type,
-                _return_type_annotation,
-                _readable_return_type,
+            Statement::DefineDerivedUnit(
+                span,
+                unit_name,
+                expr,
+                decorators,
+                annotation,
+                type_,
+                _readable_type,
             ) => {
-                self.vm.begin_function(name);
+                let aliases = decorator::name_and_aliases(*unit_name, decorators).collect();
 
-                self.locals.push(vec![]);
+                let constant_idx = self.vm.add_constant(Constant::Unit(Unit::new_base(
+                    "<dummy>",
+                    CanonicalName::new("<dummy>", AcceptsPrefix::both()),
+                ))); // TODO: dummy is just a temp. value until the SetUnitConstant op runs
+                let canonical_name =
+                    crate::decorator::get_canonical_unit_name(*unit_name, &decorators[..]).name;
+                let metadata = UnitMetadata {
+                    type_: type_.to_concrete_type(), // We guarantee that derived-unit definitions do not contain generics, so no TGen(..)s can escape
+                    readable_type: annotation
+                        .as_ref()
+                        .map(|a| a.pretty_print())
+                        .unwrap_or(type_.to_readable_type(dimension_registry)),
+                    aliases,
+                    name: decorator::name(decorators),
+                    canonical_name: decorator::get_canonical_unit_name(*unit_name, decorators),
+                    url: decorator::url(decorators),
+                    description: decorator::description(decorators),
+                    binary_prefixes: decorators.contains(&Decorator::BinaryPrefixes),
+                    metric_prefixes: decorators.contains(&Decorator::MetricPrefixes),
+                    currency: decorator::currency(decorators),
+                    ucum: decorator::ucum(decorators),
+                    isolated: decorators.contains(&Decorator::Isolated),
+                    deprecated: decorator::deprecated(decorators),
+                };
+                #[cfg(feature = "currency")]
+                let is_currency = metadata.currency.is_some();
+                // TODO: there is some asymmetry here because we do not introduce identifiers for base units
+                let (op, unit_information_idx) = if is_local {
+                    let idx = self.vm.add_local_unit_information(
+                        *unit_name,
+                        Some(canonical_name),
+                        metadata,
+                        *span,
+                    );
+                    (Op::SetLocalUnitConstant, idx)
+                } else {
+                    let idx = self.vm.add_unit_information(
+                        *unit_name,
+                        Some(canonical_name),
+                        metadata,
+                        *span,
+                    );
+                    (Op::SetUnitConstant, idx)
+                };
 
-                let current_depth = self.current_depth();
-                for parameter in parameters {
-                    self.locals[current_depth].push(Local {
-                        identifier: parameter.1.clone(),
-                        depth: current_depth,
-                        metadata: LocalMetadata::default(),
-                    });
+                self.compile_expression_with_simplify(arena, *expr)?;
+                self.vm.add_op2(op, unit_information_idx, constant_idx);
+
+                // The conversion factors of currencies depend on the exchange rates,
+                // which can be replaced later on. The definition is compiled into a
+                // function as well, such that it can be evaluated again.
+                #[cfg(feature = "currency")]
+                if is_currency && !is_local {
+                    let function_idx = self.vm.begin_function(&format!("<{unit_name}>"));
+                    self.locals.push(vec![]);
+                    self.compile_expression_with_simplify(arena, *expr)?;
+                    self.vm.add_op(Op::Return);
+                    self.locals.pop();
+                    self.vm.end_function();
+
+                    self.vm.add_currency_definition(
+                        unit_information_idx,
+                        constant_idx,
+                        function_idx,
+                    );
                 }
-                for local_variables in local_variables {
-                    self.compile_define_variable(local_variables)?;
+
+                // TODO: code duplication with DeclareBaseUnit branch above
+                for (name, _) in decorator::name_and_aliases(*unit_name, decorators) {
+                    self.unit_name_to_constant_index.insert(name, constant_idx);
                 }
+            }
+            _ => unreachable!("Expected a unit definition"),
+        }
 
-                self.compile_expression_with_simplify(expr)?;
-                self.vm.add_op(Op::Return);
+        Ok(())
+    }
 
-                self.locals.pop();
+    /// Compiles the parameters and the body of a function definition. Local units
+    /// from the `where` clause need to be compiled before.
+    fn compile_function_definition(
+        &mut self,
+        arena: &ExpressionArena,
+        stmt: &Statement,
+    ) -> Result<()> {
+        let Statement::DefineFunction(name, _, _, parameters, ..) = stmt else {
+            unreachable!("Expected a function definition")
+        };
 
-                self.vm.end_function();
+        self.remove_generic_function(*name);
+
+        self.vm.begin_function(name);
+        self.compile_function_body(arena, stmt)?;
+
+        self.vm
+            .set_function_dependencies(name, self.function_dependencies(arena, *name, stmt));
 
-                self.functions.insert(name.clone(), false);
+        self.functions.insert(*name, false);
+        self.register_variadic_function(name, parameters);
+
+        Ok(())
+    }
+
+    /// Compiles the parameters and the body of a function definition into the
+    /// function that has been started with [`Vm::begin_function`], and ends it.
+    fn compile_function_body(&mut self, arena: &ExpressionArena, stmt: &Statement) -> Result<()> {
+        let Statement::DefineFunction(_, _, _, parameters, Some(expr), local_variables, ..) = stmt
+        else {
+            unreachable!("Only functions with a body can be compiled")
+        };
+
+        self.locals.push(vec![]);
+
+        let current_depth = self.current_depth();
+        for (idx, parameter) in parameters.iter().enumerate() {
+            if let Some(default_value) = &parameter.4 {
+                // Only evaluate the default value if no argument was passed
+                let jump_offset = self.vm.current_offset() + 1 + 2; // +1 for the opcode, +2 for the index
+                self.vm.add_op2(Op::JumpIfArgumentGiven, idx as u16, 0xffff);
+
+                self.compile_expression_with_simplify(arena, *default_value)?;
+                self.vm.add_op1(Op::SetLocal, idx as u16);
+
+                let end_offset = self.vm.current_offset();
+                self.vm
+                    .patch_u16_value_at(jump_offset, end_offset - (jump_offset + 2));
+            }
+
+            self.locals[current_depth].push(Local {
+                identifier: parameter.1,
+                depth: current_depth,
+                metadata: LocalMetadata::default(),
+            });
+        }
+        for local_variables in local_variables {
+            self.compile_define_variable(arena, local_variables)?;
+        }
+
+        self.compile_expression_with_simplify(arena, *expr)?;
+        self.vm.add_op(Op::Return);
+
+        self.locals.pop();
+
+        self.vm.end_function();
+
+        Ok(())
+    }
+
+    /// Remembers the definition of a generic function, such that its body can be
+    /// compiled again for the concrete types of a call. Only functions at the top
+    /// level of a program qualify, as the types of their sub-expressions refer to
+    /// the type variables of the function (see `Statement::generalize_types`).
+    fn register_generic_function(
+        &mut self,
+        program_arena: &mut Option<Arc<ExpressionArena>>,
+        arena: &ExpressionArena,
+        stmt: &Statement,
+    ) {
+        let Statement::DefineFunction(
+            name,
+            _,
+            _,
+            _,
+            Some(_),
+            _,
+            local_units,
+            TypeScheme::Quantified(num_variables, _),
+            ..,
+        ) = stmt
+        else {
+            return;
+        };
+
+        // Local units are only available while the function is defined, and the
+        // arguments of variadic functions are collected into a list
+        if *num_variables == 0
+            || self.variadic_functions.contains_key(name)
+            || local_units
+                .iter()
+                .any(|local_unit| !matches!(local_unit, Statement::DefineDimension(..)))
+        {
+            return;
+        }
+
+        let arena = program_arena
+            .get_or_insert_with(|| Arc::new(arena.clone()))
+            .clone();
+        self.generic_functions.insert(
+            *name,
+            Arc::new(GenericFunction {
+                definition: stmt.clone(),
+                arena,
+            }),
+        );
+    }
+
+    /// Forgets the generic function of this name, along with its specializations
+    fn remove_generic_function(&mut self, name: Symbol) -> Option<Arc<GenericFunction>> {
+        self.instantiations.flush(name);
+        self.generic_functions.remove(&name)
+    }
+
+    /// The type of an expression, where the type variables are replaced by their
+    /// concrete types while a specialization is compiled.
+    fn concrete_type(&self, type_: &TypeScheme) -> Type {
+        let mut type_ = type_.to_concrete_type();
+        if let Some(instantiation) = &self.instantiation {
+            type_.apply(instantiation).ok();
+        }
+        type_
+    }
+
+    /// Returns the index of the specialization of a generic function for the types
+    /// of a call, which is compiled on first use. Returns `None` if the function is
+    /// not generic or if the types are not fully known at the call site.
+    fn specialize(
+        &mut self,
+        arena: &ExpressionArena,
+        name: Symbol,
+        args: &[ExpressionId],
+        return_type: &TypeScheme,
+    ) -> Result<Option<u16>> {
+        let Some(generic_function) = self.generic_functions.get(&name).cloned() else {
+            return Ok(None);
+        };
+        let Statement::DefineFunction(
+            _,
+            _,
+            _,
+            _,
+            _,
+            _,
+            _,
+            TypeScheme::Quantified(num_variables, function_type),
+            ..,
+        ) = &generic_function.definition
+        else {
+            unreachable!("Only generic functions are registered")
+        };
+
+        let argument_types = args
+            .iter()
+            .map(|arg| self.concrete_type(&arena[*arg].get_type_scheme(arena)))
+            .collect::<Vec<_>>();
+        let Some(types) = instantiation_cache::instantiation(
+            &function_type.inner,
+            *num_variables,
+            &argument_types,
+            &self.concrete_type(return_type),
+        ) else {
+            return Ok(None);
+        };
+
+        if let Some(function_idx) = self.instantiations.get(name, &types) {
+            return Ok(Some(function_idx));
+        }
+
+        let function_idx = self
+            .vm
+            .begin_function(&format!("{name}⟨{}⟩", types.iter().join(", ")));
+        // Recursive calls refer to the specialization that is being compiled
+        self.instantiations
+            .insert(name, types.clone(), function_idx);
+
+        let substitution = Substitution(
+            types
+                .into_iter()
+                .enumerate()
+                .map(|(i, type_)| (TypeVariable::Quantified(i), type_))
+                .collect(),
+        );
+        let enclosing_instantiation = self.instantiation.replace(substitution);
+        let result =
+            self.compile_function_body(&generic_function.arena, &generic_function.definition);
+        self.instantiation = enclosing_instantiation;
+        result?;
+
+        Ok(Some(function_idx))
+    }
+
+    /// Removes all definitions of the given name, see [`ImportScope`]. Global
+    /// variables stay on the stack, but they can not be referred to by name.
+    fn take_definition(&mut self, name: Symbol) -> NameDefinition {
+        let function = self.functions.remove(&name).map(|is_foreign| {
+            (
+                is_foreign,
+                (!is_foreign).then(|| self.vm.get_function_idx(&name)),
+            )
+        });
+
+        let mut global_positions = vec![];
+        for (position, local) in self.locals[0].iter_mut().enumerate() {
+            if local.identifier == name {
+                local.identifier = Symbol::new(HIDDEN_LOCAL);
+                global_positions.push(position);
+            }
+        }
+
+        NameDefinition {
+            unit_constant_index: self.unit_name_to_constant_index.remove(&name),
+            function,
+            variadic_parameters: self.variadic_functions.remove(&name),
+            global_positions,
+            generic_function: self.remove_generic_function(name),
+        }
+    }
+
+    /// Replaces the current definitions of the name by ones that have been
+    /// removed with [`Self::take_definition`]
+    fn restore_definition(&mut self, name: Symbol, definition: NameDefinition) {
+        self.take_definition(name);
+        if let Some(index) = definition.unit_constant_index {
+            self.unit_name_to_constant_index.insert(name, index);
+        }
+        if let Some((is_foreign, function_idx)) = definition.function {
+            // Calls are compiled to the latest function of that name
+            if let Some(function_idx) = function_idx {
+                if self.vm.get_function_idx(&name) != function_idx {
+                    self.vm.copy_function(function_idx);
+                }
+            }
+            self.functions.insert(name, is_foreign);
+        }
+        if let Some(num_parameters) = definition.variadic_parameters {
+            self.variadic_functions.insert(name, num_parameters);
+        }
+        for position in definition.global_positions {
+            self.locals[0][position].identifier = name;
+        }
+        if let Some(generic_function) = definition.generic_function {
+            self.generic_functions.insert(name, generic_function);
+        }
+    }
+
+    fn apply_import_scope(&mut self, scope: &ImportScope) {
+        match scope {
+            ImportScope::Begin(names) => {
+                let stashed = names
+                    .iter()
+                    .map(|name| (*name, self.take_definition(*name)))
+                    .collect();
+                self.stashed_definitions.push(stashed);
+            }
+            ImportScope::End(items) => {
+                for item in items {
+                    let definition = self.take_definition(item.1);
+                    self.hidden_definitions.insert(item.clone(), definition);
+                }
+                for (name, definition) in self.stashed_definitions.pop().unwrap_or_default() {
+                    self.restore_definition(name, definition);
+                }
+            }
+            ImportScope::Reveal(items) => {
+                for item in items {
+                    if let Some(definition) = self.hidden_definitions.remove(item) {
+                        self.restore_definition(item.1, definition);
+                    }
+                }
+            }
+        }
+
+        // Units are hidden from the unit registry when the program runs
+        let index = self.vm.add_import_scope(scope.clone());
+        self.vm.add_op1(Op::ApplyImportScope, index);
+    }
+
+    fn compile_statement(
+        &mut self,
+        arena: &ExpressionArena,
+        stmt: &Statement,
+        dimension_registry: &DimensionRegistry,
+    ) -> Result<()> {
+        match stmt {
+            Statement::Expression(expr) => {
+                self.compile_expression_with_simplify(arena, *expr)?;
+                self.vm.add_op(Op::Return);
+            }
+            Statement::DefineVariable(define_variable) => {
+                self.compile_define_variable(arena, define_variable)?
+            }
+            Statement::DefineFunction(_, _, _, _, Some(_), _, local_units, _, _, _) => {
+                // Local units are evaluated once, when the function is defined. They are
+                // only visible while the function body is compiled.
+                let mut shadowed_units = vec![];
+                for local_unit in local_units {
+                    let (Statement::DefineBaseUnit(_, unit_name, decorators, ..)
+                    | Statement::DefineDerivedUnit(_, unit_name, _, decorators, ..)) = local_unit
+                    else {
+                        // Local dimension definitions do not generate any bytecode
+                        continue;
+                    };
+                    for (alias, _) in decorator::name_and_aliases(*unit_name, decorators) {
+                        shadowed_units
+                            .push((alias, self.unit_name_to_constant_index.get(&alias).copied()));
+                    }
+                    self.compile_unit_definition(arena, local_unit, dimension_registry, true)?;
+                }
+
+                self.compile_function_definition(arena, stmt)?;
+
+                for (alias, previous_index) in shadowed_units.into_iter().rev() {
+                    match previous_index {
+                        Some(index) => self.unit_name_to_constant_index.insert(alias, index),
+                        None => self.unit_name_to_constant_index.remove(&alias),
+                    };
+                }
             }
             Statement::DefineFunction(
                 name,
@@ -387,112 +1338,34 @@ impl BytecodeInterpreter {
                 parameters,
                 None,
                 _local_variables,
-                _return_type,
+                _local_units,
+                fn_type,
                 _return_type_annotation,
                 _readable_return_type,
             ) => {
                 // Declaring a foreign function does not generate any bytecode. But we register
                 // its name and arity here to be able to distinguish it from normal functions.
 
-                self.vm
-                    .add_foreign_function(name, parameters.len()..=parameters.len());
+                let return_type = match fn_type.to_concrete_type() {
+                    Type::Fn(_, return_type) => fully_known(&return_type),
+                    _ => None,
+                };
+                self.vm.add_foreign_function(
+                    name,
+                    parameters.len()..=parameters.len(),
+                    return_type,
+                );
 
-                self.functions.insert(name.clone(), true);
+                self.remove_generic_function(*name);
+                self.functions.insert(*name, true);
+                self.register_variadic_function(name, parameters);
             }
             Statement::DefineDimension(_name, _dexprs) => {
                 // Declaring a dimension is like introducing a new type. The information
                 // is only relevant for the type checker. Nothing happens at run time.
             }
-            Statement::DefineBaseUnit(unit_name, decorators, annotation, type_) => {
-                let aliases = decorator::name_and_aliases(unit_name, decorators)
-                    .map(|(name, ap)| (name.clone(), ap))
-                    .collect();
-
-                self.vm
-                    .unit_registry
-                    .add_base_unit(
-                        unit_name,
-                        UnitMetadata {
-                            type_: type_.to_concrete_type(), // Base unit types can never be generic
-                            readable_type: annotation
-                                .as_ref()
-                                .map(|a| a.pretty_print())
-                                .unwrap_or(type_.to_readable_type(dimension_registry)),
-                            aliases,
-                            name: decorator::name(decorators),
-                            canonical_name: decorator::get_canonical_unit_name(
-                                unit_name, decorators,
-                            ),
-                            url: decorator::url(decorators),
-                            description: decorator::description(decorators),
-                            binary_prefixes: decorators.contains(&Decorator::BinaryPrefixes),
-                            metric_prefixes: decorators.contains(&Decorator::MetricPrefixes),
-                        },
-                    )
-                    .map_err(RuntimeError::UnitRegistryError)?;
-
-                let constant_idx = self.vm.add_constant(Constant::Unit(Unit::new_base(
-                    unit_name,
-                    crate::decorator::get_canonical_unit_name(unit_name.as_str(), &decorators[..]),
-                )));
-                for (name, _) in decorator::name_and_aliases(unit_name, decorators) {
-                    self.unit_name_to_constant_index
-                        .insert(name.into(), constant_idx);
-                }
-            }
-            Statement::DefineDerivedUnit(
-                unit_name,
-                expr,
-                decorators,
-                annotation,
-                type_,
-                _readable_type,
-            ) => {
-                let aliases = decorator::name_and_aliases(unit_name, decorators)
-                    .map(|(name, ap)| (name.clone(), ap))
-                    .collect();
-
-                let constant_idx = self.vm.add_constant(Constant::Unit(Unit::new_base(
-                    "<dummy>",
-                    CanonicalName {
-                        name: "<dummy>".to_string(),
-                        accepts_prefix: AcceptsPrefix::both(),
-                    },
-                ))); // TODO: dummy is just a temp. value until the SetUnitConstant op runs
-                let unit_information_idx = self.vm.add_unit_information(
-                    unit_name,
-                    Some(
-                        &crate::decorator::get_canonical_unit_name(
-                            unit_name.as_str(),
-                            &decorators[..],
-                        )
-                        .name,
-                    ),
-                    UnitMetadata {
-                        type_: type_.to_concrete_type(), // We guarantee that derived-unit definitions do not contain generics, so no TGen(..)s can escape
-                        readable_type: annotation
-                            .as_ref()
-                            .map(|a| a.pretty_print())
-                            .unwrap_or(type_.to_readable_type(dimension_registry)),
-                        aliases,
-                        name: decorator::name(decorators),
-                        canonical_name: decorator::get_canonical_unit_name(unit_name, decorators),
-                        url: decorator::url(decorators),
-                        description: decorator::description(decorators),
-                        binary_prefixes: decorators.contains(&Decorator::BinaryPrefixes),
-                        metric_prefixes: decorators.contains(&Decorator::MetricPrefixes),
-                    },
-                ); // TODO: there is some asymmetry here because we do not introduce identifiers for base units
-
-                self.compile_expression_with_simplify(expr)?;
-                self.vm
-                    .add_op2(Op::SetUnitConstant, unit_information_idx, constant_idx);
-
-                // TODO: code duplication with DeclareBaseUnit branch above
-                for (name, _) in decorator::name_and_aliases(unit_name, decorators) {
-                    self.unit_name_to_constant_index
-                        .insert(name.into(), constant_idx);
-                }
+            Statement::DefineBaseUnit(..) | Statement::DefineDerivedUnit(..) => {
+                self.compile_unit_definition(arena, stmt, dimension_registry, false)?
             }
             Statement::ProcedureCall(ProcedureKind::Type, args) => {
                 assert_eq!(args.len(), 1);
@@ -500,21 +1373,47 @@ impl BytecodeInterpreter {
 
                 use crate::markup as m;
                 let idx = self.vm.add_string(
-                    m::dimmed("=") + m::whitespace(" ") + arg.get_type_scheme().pretty_print(), // TODO
+                    m::dimmed("=")
+                        + m::whitespace(" ")
+                        + arena[*arg].get_type_scheme(arena).pretty_print(), // TODO
                 );
                 self.vm.add_op1(Op::PrintString, idx);
             }
+            Statement::ProcedureCall(ProcedureKind::ConversionCacheStats, _) => {
+                self.vm.add_op(Op::PrintConversionCacheStats);
+            }
+            Statement::ProcedureCall(ProcedureKind::RegexCacheStats, _) => {
+                self.vm.add_op(Op::PrintRegexCacheStats);
+            }
+            Statement::ProcedureCall(ProcedureKind::InstantiationCacheStats, _) => {
+                // Specializations are compiled along with the calls, so the statistics
+                // are already known at this point
+                let idx = self
+                    .vm
+                    .add_string(crate::markup::text(self.instantiations.stats().to_string()));
+                self.vm.add_op1(Op::PrintString, idx);
+            }
+            Statement::ProcedureCall(ProcedureKind::SetFixedTime, args) => {
+                assert_eq!(args.len(), 1);
+                self.compile_expression(arena, args[0])?;
+                self.vm.add_op(Op::SetFixedTime);
+            }
+            Statement::ProcedureCall(ProcedureKind::SetImplicitConversions, args) => {
+                assert_eq!(args.len(), 1);
+                self.compile_expression(arena, args[0])?;
+                self.vm.add_op(Op::SetImplicitConversions);
+            }
             Statement::ProcedureCall(kind, args) => {
                 // Put all arguments on top of the stack
                 for arg in args {
-                    self.compile_expression_with_simplify(arg)?;
+                    self.compile_expression_with_simplify(arena, *arg)?;
                 }
 
                 let name = &ffi::procedures().get(kind).unwrap().name;
 
                 let callable_idx = self.vm.get_ffi_callable_idx(name).unwrap();
 
-                let arg_spans = args.iter().map(|a| a.full_span()).collect();
+                let arg_spans = args.iter().map(|a| arena[*a].full_span(arena)).collect();
                 let spans_idx = self.vm.add_procedure_arg_span(arg_spans);
 
                 self.vm.add_op3(
@@ -525,18 +1424,104 @@ impl BytecodeInterpreter {
                 );
                 // TODO: check overflow
             }
+            Statement::Conditional(condition, then_statement, else_statement) => {
+                self.compile_expression(arena, *condition)?;
+
+                let if_jump_offset = self.vm.current_offset() + 1; // +1 for the opcode
+                self.vm.add_op1(Op::JumpIfFalse, 0xffff);
+
+                self.compile_statement(arena, then_statement, dimension_registry)?;
+
+                if let Some(else_statement) = else_statement {
+                    let else_jump_offset = self.vm.current_offset() + 1;
+                    self.vm.add_op1(Op::Jump, 0xffff);
+
+                    let else_block_offset = self.vm.current_offset();
+                    self.vm.patch_u16_value_at(
+                        if_jump_offset,
+                        else_block_offset - (if_jump_offset + 2),
+                    );
+
+                    self.compile_statement(arena, else_statement, dimension_registry)?;
+
+                    let end_offset = self.vm.current_offset();
+                    self.vm
+                        .patch_u16_value_at(else_jump_offset, end_offset - (else_jump_offset + 2));
+                } else {
+                    let end_offset = self.vm.current_offset();
+                    self.vm
+                        .patch_u16_value_at(if_jump_offset, end_offset - (if_jump_offset + 2));
+                }
+            }
             Statement::DefineStruct(struct_info) => {
                 self.vm.add_struct_info(struct_info);
             }
+            Statement::DefineUnitFunction(function, inverse) => {
+                self.compile_statement(arena, function, dimension_registry)?;
+                if let Some(inverse) = inverse {
+                    self.compile_statement(arena, inverse, dimension_registry)?;
+                }
+            }
+            Statement::ImportScope(scope) => self.apply_import_scope(scope),
+            Statement::DefineGenericFunction(_) => {
+                // Only the specializations of a generic function can be called. They
+                // are defined as normal functions.
+            }
+            Statement::DefineMethods(_, functions) | Statement::DefineFormula(_, functions) => {
+                for function in functions {
+                    self.compile_statement(arena, function, dimension_registry)?;
+                }
+            }
+            Statement::DefineUnitSystem(name, units) => {
+                let num_units = u16::try_from(units.len()).map_err(|_| {
+                    RuntimeError::UnitRegistryError(UnitRegistryError::TooManyUnitsInUnitSystem(
+                        name.to_string(),
+                        units.len(),
+                    ))
+                })?;
+                for unit in units {
+                    self.compile_expression(arena, *unit)?;
+                }
+
+                let name_idx = self.vm.add_constant(Constant::String(name.to_string()));
+                self.vm.add_op2(Op::DefineUnitSystem, name_idx, num_units);
+
+                // The unit system itself can be called like a function of one
+                // argument, which converts the argument to the system.
+                self.vm.begin_function(name);
+                self.vm.add_op1(Op::GetLocal, 0);
+                self.vm.add_op1(Op::ConvertToUnitSystem, name_idx);
+                self.vm.add_op(Op::Return);
+                self.vm.end_function();
+
+                self.vm.set_function_dependencies(
+                    name,
+                    self.function_dependencies(arena, *name, stmt),
+                );
+
+                self.remove_generic_function(*name);
+                self.functions.insert(*name, false);
+            }
         }
 
         Ok(())
     }
 
-    fn run(&mut self, settings: &mut InterpreterSettings) -> Result<InterpreterResult> {
-        let mut ctx = ExecutionContext {
-            print_fn: &mut settings.print_fn,
-        };
+    /// Define the currency units again if the exchange rates have been replaced
+    /// since they have been defined.
+    #[cfg(feature = "currency")]
+    fn update_currency_units(&mut self, output: &mut dyn OutputSink) -> Result<()> {
+        let generation = ExchangeRatesCache::generation();
+        if generation != self.exchange_rates_generation {
+            self.vm
+                .update_currency_units(&mut ExecutionContext { output })?;
+            self.exchange_rates_generation = generation;
+        }
+        Ok(())
+    }
+
+    fn run(&mut self, output: &mut dyn OutputSink) -> Result<InterpreterResult> {
+        let mut ctx = ExecutionContext { output };
 
         self.vm.disassemble();
 
@@ -547,17 +1532,138 @@ impl BytecodeInterpreter {
         result
     }
 
+    pub(crate) fn add_native_function(&mut self, name: &str, function: Arc<ffi::NativeCallback>) {
+        self.vm.add_native_function(name, function);
+    }
+
+    pub(crate) fn remove_native_function(&mut self, name: &str) -> bool {
+        self.vm.remove_native_function(name)
+    }
+
+    /// Define a global variable with the given value, as if it had been defined
+    /// by a `let` statement.
+    pub(crate) fn add_global(&mut self, name: Symbol, value: Value) {
+        self.vm.push_global(value);
+        self.locals[0].push(Local {
+            identifier: name,
+            depth: 0,
+            metadata: LocalMetadata::default(),
+        });
+    }
+
+    /// The current value of a global variable.
+    pub(crate) fn global_value(&self, name: &str) -> Option<&Value> {
+        let position = self.locals[0].iter().rposition(|l| l.identifier == name)?;
+        self.vm.global(position)
+    }
+
+    pub(crate) fn type_of_value(&self, value: &Value) -> std::result::Result<Type, &'static str> {
+        self.vm.type_of_value(value)
+    }
+
+    pub(crate) fn results(&self) -> &[Value] {
+        self.vm.results()
+    }
+
+    pub(crate) fn set_sandbox_policy(&mut self, policy: SandboxPolicy) {
+        self.vm.set_sandbox_policy(policy);
+    }
+
+    pub(crate) fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.vm.set_clock(clock);
+    }
+
+    pub(crate) fn set_file_system(&mut self, file_system: Arc<dyn FileSystem>) {
+        self.vm.set_file_system(file_system);
+    }
+
+    pub(crate) fn set_profiling(&mut self, enabled: bool) {
+        self.vm.set_profiling(enabled);
+    }
+
+    pub(crate) fn profile(&self) -> Option<Profile> {
+        self.vm.profile()
+    }
+
+    pub(crate) fn set_max_loop_iterations(&mut self, max_iterations: u64) {
+        self.vm.set_max_loop_iterations(max_iterations);
+    }
+
+    pub(crate) fn set_summation_error_threshold(&mut self, threshold: Option<f64>) {
+        self.vm.set_summation_error_threshold(threshold);
+    }
+
+    pub(crate) fn set_implicit_conversions(&mut self, implicit_conversions: ImplicitConversions) {
+        self.vm.set_implicit_conversions(implicit_conversions);
+    }
+
+    pub(crate) fn take_warnings(&mut self) -> Vec<RuntimeWarning> {
+        self.vm.take_warnings()
+    }
+
+    pub(crate) fn take_profiler_from(&mut self, other: &mut Self) {
+        self.vm.take_profiler_from(&mut other.vm);
+    }
+
+    pub(crate) fn set_script_arguments(&mut self, arguments: Vec<String>) {
+        self.vm.set_script_arguments(arguments);
+    }
+
     pub(crate) fn set_debug(&mut self, activate: bool) {
         self.vm.set_debug(activate);
     }
 
+    pub(crate) fn set_num_threads(&mut self, num_threads: usize) {
+        self.vm.set_num_threads(num_threads);
+    }
+
+    /// Find all functions that are called (or referenced) from within the given
+    /// function definition. Returns `None` if the function might have side effects
+    /// on its own, i.e. if it uses `trace` or calls a function value that is not
+    /// known at compile time.
+    fn function_dependencies(
+        &self,
+        arena: &ExpressionArena,
+        name: Symbol,
+        definition: &Statement,
+    ) -> Option<Vec<String>> {
+        let mut dependencies = vec![];
+        let mut has_side_effects = false;
+        definition.for_all_expressions(arena, &mut |expr| match expr {
+            Expression::Trace(..)
+            | Expression::CallableCall(..)
+            | Expression::Conversion(_, _, ConversionTarget::Function(_), _) => {
+                has_side_effects = true
+            }
+            Expression::AutoDiff(_, _, definitions, _) => dependencies.extend(
+                definitions
+                    .iter()
+                    .filter_map(|definition| match definition {
+                        Statement::DefineFunction(name, ..) => Some(name.to_string()),
+                        _ => None,
+                    }),
+            ),
+            Expression::FunctionCall(_, _, callee, _, _, _) => {
+                dependencies.push(callee.to_string())
+            }
+            Expression::Identifier(_, identifier, _)
+                if *identifier == name || self.functions.contains_key(identifier) =>
+            {
+                dependencies.push(identifier.to_string())
+            }
+            _ => {}
+        });
+
+        (!has_side_effects).then_some(dependencies)
+    }
+
     fn current_depth(&self) -> usize {
         self.locals.len() - 1
     }
 
     pub fn get_defining_unit(&self, unit_name: &str) -> Option<&Unit> {
-        self.unit_name_to_constant_index
-            .get(unit_name)
+        Symbol::get(unit_name)
+            .and_then(|unit_name| self.unit_name_to_constant_index.get(&unit_name))
             .and_then(|idx| self.vm.constants.get(*idx as usize))
             .and_then(|constant| match constant {
                 Constant::Unit(u) => Some(u),
@@ -577,20 +1683,33 @@ impl Interpreter for BytecodeInterpreter {
             locals: vec![vec![]],
             unit_name_to_constant_index: HashMap::new(),
             functions: HashMap::new(),
+            variadic_functions: HashMap::new(),
+            stashed_definitions: vec![],
+            hidden_definitions: HashMap::new(),
+            generic_functions: HashMap::new(),
+            instantiations: InstantiationCache::default(),
+            instantiation: None,
+            #[cfg(feature = "currency")]
+            exchange_rates_generation: 0,
         }
     }
 
     fn interpret_statements(
         &mut self,
-        settings: &mut InterpreterSettings,
-        statements: &[Statement],
+        output: &mut dyn OutputSink,
+        program: &TypedProgram,
         dimension_registry: &DimensionRegistry,
     ) -> Result<InterpreterResult> {
-        for statement in statements {
-            self.compile_statement(statement, dimension_registry)?;
+        #[cfg(feature = "currency")]
+        self.update_currency_units(output)?;
+
+        let mut program_arena = None;
+        for statement in &program.statements {
+            self.compile_statement(&program.arena, statement, dimension_registry)?;
+            self.register_generic_function(&mut program_arena, &program.arena, statement);
         }
 
-        self.run(settings)
+        self.run(output)
     }
 
     fn get_unit_registry(&self) -> &UnitRegistry {
@@ -0,0 +1,34 @@
+//! Sources for the current time, as returned by `now()`.
+
+use jiff::Zoned;
+
+/// A source for the current time, see [`crate::Context::set_clock`].
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Zoned;
+}
+
+/// The time of the operating system (the default)
+#[derive(Debug, Clone, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Zoned {
+        Zoned::now()
+    }
+}
+
+/// A clock that always returns the same time, which makes results reproducible.
+#[derive(Debug, Clone)]
+pub struct FixedClock(Zoned);
+
+impl FixedClock {
+    pub fn new(time: Zoned) -> Self {
+        FixedClock(time)
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> Zoned {
+        self.0.clone()
+    }
+}
@@ -0,0 +1,604 @@
+//! Generates the `physics::codata` module from a table of physical constants in
+//! the ASCII format that is published by NIST [1] (`data/codata.txt`).
+//!
+//! To regenerate the module after updating the data file, run
+//!
+//! ```text
+//! cargo run --features codata --bin numbat-codata > modules/physics/codata.nbt
+//! ```
+//!
+//! [1] https://physics.nist.gov/cuu/Constants/Table/allascii.txt
+
+use itertools::Itertools;
+use thiserror::Error;
+
+use crate::prefix_parser::PrefixParserResult;
+use crate::Context;
+
+/// A constant that is included in the generated module.
+struct Constant {
+    /// The name of the quantity in the data file
+    quantity: &'static str,
+    identifier: &'static str,
+    name: &'static str,
+    url: &'static str,
+    aliases: &'static [&'static str],
+    type_annotation: &'static str,
+    /// For exact constants whose value is truncated in the data file, an
+    /// expression that computes the full value from previous constants. It can
+    /// end in a conversion to choose the unit in which the constant is shown.
+    exact_value: Option<&'static str>,
+}
+
+const CONSTANTS: &[Constant] = &[
+    Constant {
+        quantity: "speed of light in vacuum",
+        identifier: "speed_of_light",
+        name: "Speed of light in vacuum",
+        url: "https://en.wikipedia.org/wiki/Speed_of_light",
+        aliases: &["c"],
+        type_annotation: "Velocity",
+        exact_value: None,
+    },
+    Constant {
+        quantity: "Newtonian constant of gravitation",
+        identifier: "gravitational_constant",
+        name: "Newtonian constant of gravitation",
+        url: "https://en.wikipedia.org/wiki/Gravitational_constant",
+        aliases: &["G"],
+        type_annotation: "Force × Length^2 / Mass^2",
+        exact_value: None,
+    },
+    Constant {
+        quantity: "standard acceleration of gravity",
+        identifier: "gravity",
+        name: "Standard acceleration of gravity on earth",
+        url: "https://en.wikipedia.org/wiki/Gravity_of_Earth",
+        aliases: &["g0"],
+        type_annotation: "Acceleration",
+        exact_value: None,
+    },
+    Constant {
+        quantity: "Planck constant",
+        identifier: "planck_constant",
+        name: "Planck constant",
+        url: "https://en.wikipedia.org/wiki/Planck_constant",
+        aliases: &["ℎ"],
+        type_annotation: "Action",
+        exact_value: None,
+    },
+    Constant {
+        quantity: "reduced Planck constant",
+        identifier: "ℏ",
+        name: "Reduced Planck constant",
+        url: "https://en.wikipedia.org/wiki/Planck_constant#Reduced_Planck_constant_%E2%84%8F",
+        aliases: &["h_bar"],
+        type_annotation: "Action",
+        exact_value: Some("planck_constant / 2π"),
+    },
+    Constant {
+        quantity: "electron mass",
+        identifier: "electron_mass",
+        name: "Electron mass",
+        url: "https://en.wikipedia.org/wiki/Electron_mass",
+        aliases: &[],
+        type_annotation: "Mass",
+        exact_value: None,
+    },
+    Constant {
+        quantity: "elementary charge",
+        identifier: "elementary_charge",
+        name: "Elementary charge",
+        url: "https://en.wikipedia.org/wiki/Elementary_charge",
+        aliases: &["electron_charge"],
+        type_annotation: "ElectricCharge",
+        exact_value: None,
+    },
+    Constant {
+        quantity: "vacuum mag. permeability",
+        identifier: "magnetic_constant",
+        name: "Vacuum permeability / magnetic constant",
+        url: "https://en.wikipedia.org/wiki/Vacuum_permeability",
+        aliases: &["μ0", "mu0"],
+        type_annotation: "MagneticPermeability",
+        exact_value: None,
+    },
+    Constant {
+        quantity: "vacuum electric permittivity",
+        identifier: "electric_constant",
+        name: "Vacuum electric permittivity / electric constant",
+        url: "https://en.wikipedia.org/wiki/Vacuum_permittivity",
+        aliases: &["ε0", "eps0"],
+        type_annotation: "ElectricPermittivity",
+        exact_value: None,
+    },
+    Constant {
+        quantity: "Bohr magneton",
+        identifier: "bohr_magneton",
+        name: "Bohr magneton",
+        url: "https://en.wikipedia.org/wiki/Bohr_magneton",
+        aliases: &["μ_B"],
+        type_annotation: "Energy / MagneticFluxDensity",
+        exact_value: None,
+    },
+    Constant {
+        quantity: "fine-structure constant",
+        identifier: "fine_structure_constant",
+        name: "Fine structure constant",
+        url: "https://en.wikipedia.org/wiki/Fine-structure_constant",
+        aliases: &["α", "alpha"],
+        type_annotation: "Scalar",
+        exact_value: None,
+    },
+    Constant {
+        quantity: "proton mass",
+        identifier: "proton_mass",
+        name: "Proton mass",
+        url: "https://en.wikipedia.org/wiki/Proton",
+        aliases: &[],
+        type_annotation: "Mass",
+        exact_value: None,
+    },
+    Constant {
+        quantity: "neutron mass",
+        identifier: "neutron_mass",
+        name: "Neutron mass",
+        url: "https://en.wikipedia.org/wiki/Neutron",
+        aliases: &[],
+        type_annotation: "Mass",
+        exact_value: None,
+    },
+    Constant {
+        quantity: "Avogadro constant",
+        identifier: "avogadro_constant",
+        name: "Avogadro constant",
+        url: "https://en.wikipedia.org/wiki/Avogadro_constant",
+        aliases: &["N_A"],
+        type_annotation: "1 / AmountOfSubstance",
+        exact_value: None,
+    },
+    Constant {
+        quantity: "Boltzmann constant",
+        identifier: "boltzmann_constant",
+        name: "Boltzmann constant",
+        url: "https://en.wikipedia.org/wiki/Boltzmann_constant",
+        aliases: &["k_B"],
+        type_annotation: "Energy / Temperature",
+        exact_value: None,
+    },
+    Constant {
+        quantity: "Stefan-Boltzmann constant",
+        identifier: "stefan_boltzmann_constant",
+        name: "Stefan-Boltzmann constant",
+        url: "https://en.wikipedia.org/wiki/Stefan%E2%80%93Boltzmann_law",
+        aliases: &[],
+        type_annotation: "Power / (Area × Temperature^4)",
+        exact_value: Some("2 π^5 k_B^4 / (15 planck_constant^3 c^2)"),
+    },
+    Constant {
+        quantity: "molar gas constant",
+        identifier: "gas_constant",
+        name: "Molar gas constant",
+        url: "https://en.wikipedia.org/wiki/Gas_constant",
+        aliases: &["R"],
+        type_annotation: "Energy / (AmountOfSubstance × Temperature)",
+        exact_value: Some("k_B × N_A"),
+    },
+    Constant {
+        quantity: "Bohr radius",
+        identifier: "bohr_radius",
+        name: "Bohr radius",
+        url: "https://en.wikipedia.org/wiki/Bohr_radius",
+        aliases: &["a0"],
+        type_annotation: "Length",
+        exact_value: None,
+    },
+    Constant {
+        quantity: "Rydberg constant",
+        identifier: "rydberg_constant",
+        name: "Rydberg constant",
+        url: "https://en.wikipedia.org/wiki/Rydberg_constant",
+        aliases: &[],
+        type_annotation: "Wavenumber",
+        exact_value: None,
+    },
+    Constant {
+        quantity: "atomic mass constant",
+        identifier: "atomic_mass_constant",
+        name: "Atomic Mass constant",
+        url: "https://en.wikipedia.org/wiki/Atomic_mass_constant",
+        aliases: &["m_u"],
+        type_annotation: "Mass",
+        exact_value: None,
+    },
+    Constant {
+        quantity: "conductance quantum",
+        identifier: "conductance_quantum",
+        name: "Conductance quantum",
+        url: "https://en.wikipedia.org/wiki/Conductance_quantum",
+        aliases: &[],
+        type_annotation: "ElectricConductance",
+        exact_value: Some("2 elementary_charge^2 / planck_constant -> S"),
+    },
+    Constant {
+        quantity: "Faraday constant",
+        identifier: "faraday_constant",
+        name: "Faraday constant",
+        url: "https://en.wikipedia.org/wiki/Faraday_constant",
+        aliases: &[],
+        type_annotation: "ElectricCharge / AmountOfSubstance",
+        exact_value: Some("avogadro_constant × elementary_charge"),
+    },
+    Constant {
+        quantity: "mag. flux quantum",
+        identifier: "magnetic_flux_quantum",
+        name: "Magnetic Flux Quantum",
+        url: "https://en.wikipedia.org/wiki/Magnetic_flux_quantum",
+        aliases: &[],
+        type_annotation: "MagneticFlux",
+        exact_value: Some("planck_constant / (2 elementary_charge) -> Wb"),
+    },
+    Constant {
+        quantity: "Josephson constant",
+        identifier: "josephson_constant",
+        name: "Josephson Constant",
+        url: "https://en.wikipedia.org/wiki/Josephson_constant",
+        aliases: &[],
+        type_annotation: "Frequency / Voltage",
+        exact_value: Some("2 elementary_charge / planck_constant -> Hz/V"),
+    },
+    Constant {
+        quantity: "von Klitzing constant",
+        identifier: "von_klitzing_constant",
+        name: "Von Klitzing Constant",
+        url: "https://en.wikipedia.org/wiki/Von_Klitzing_constant",
+        aliases: &["R_K"],
+        type_annotation: "ElectricResistance",
+        exact_value: Some("planck_constant / elementary_charge^2 -> Ω"),
+    },
+];
+
+/// Unit symbols in the data file that are written differently in Numbat. All
+/// other symbols are used as they are.
+const UNIT_SYMBOLS: &[(&str, &str)] = &[("ohm", "Ω")];
+
+/// Column widths of the quantity, value and uncertainty columns in the data file.
+const COLUMN_WIDTHS: [usize; 3] = [60, 25, 25];
+
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum CodataError {
+    #[error("The data file does not contain a table of constants")]
+    MissingTable,
+
+    #[error("Line {0} of the data file can not be parsed: '{1}'")]
+    MalformedLine(usize, String),
+
+    #[error("Constant '{0}' is not contained in the data file")]
+    UnknownConstant(String),
+
+    #[error("Unit '{unit}' of constant '{constant}' can not be mapped onto a Numbat unit")]
+    UnknownUnit { constant: String, unit: String },
+
+    #[error("Constant '{0}' is not exact, but has an expression for its exact value")]
+    InexactConstantWithExpression(String),
+}
+
+/// A constant from the data file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub quantity: String,
+    /// The value as a Numbat number literal, e.g. `6.674_30e-11`
+    pub value: String,
+    /// Whether the value is truncated (`...`), which is only the case for exact
+    /// values.
+    pub truncated: bool,
+    /// The standard uncertainty as a number literal, or `None` for exact values
+    pub uncertainty: Option<String>,
+    /// The unit as written in the data file, e.g. `m^3 kg^-1 s^-2`. Empty for
+    /// dimensionless constants.
+    pub unit: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Table {
+    /// The year of the CODATA adjustment, e.g. `2018`
+    pub year: Option<String>,
+    pub entries: Vec<Entry>,
+}
+
+/// Turns `6.674 30 e-11` into `6.674_30e-11`.
+fn number_literal(value: &str) -> String {
+    value.replace(" e", "e").replace(' ', "_")
+}
+
+pub fn parse(data: &str) -> Result<Table, CodataError> {
+    let year = data
+        .lines()
+        .find(|line| line.contains("CODATA adjustment"))
+        .and_then(|line| line.split_whitespace().next())
+        .map(str::to_string);
+
+    let mut lines = data.lines().enumerate();
+    if !lines.any(|(_, line)| line.starts_with("-----")) {
+        return Err(CodataError::MissingTable);
+    }
+
+    let mut entries = vec![];
+    for (index, line) in lines {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let malformed = || CodataError::MalformedLine(index + 1, line.to_string());
+
+        let mut columns = vec![];
+        let mut rest = line;
+        for width in COLUMN_WIDTHS {
+            let split = rest
+                .char_indices()
+                .nth(width)
+                .map_or(rest.len(), |(i, _)| i);
+            let (column, remainder) = rest.split_at(split);
+            columns.push(column.trim());
+            rest = remainder;
+        }
+        let [quantity, value, uncertainty] = columns[..] else {
+            unreachable!()
+        };
+
+        if quantity.is_empty() || value.is_empty() || uncertainty.is_empty() {
+            return Err(malformed());
+        }
+
+        let truncated = value.contains("...");
+        let value = number_literal(&value.replace("...", ""));
+        if value.replace('_', "").parse::<f64>().is_err() {
+            return Err(malformed());
+        }
+
+        let uncertainty = if uncertainty == "(exact)" {
+            None
+        } else {
+            let uncertainty = number_literal(uncertainty);
+            if uncertainty.replace('_', "").parse::<f64>().is_err() {
+                return Err(malformed());
+            }
+            Some(uncertainty)
+        };
+
+        entries.push(Entry {
+            quantity: quantity.to_string(),
+            value,
+            truncated,
+            uncertainty,
+            unit: rest.trim().to_string(),
+        });
+    }
+
+    Ok(Table { year, entries })
+}
+
+/// A unit from the data file, as Numbat unit identifiers with exponents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct UnitExpression {
+    numerator: Vec<String>,
+    denominator: Vec<String>,
+}
+
+impl UnitExpression {
+    fn factors(factors: &[String]) -> String {
+        if factors.len() > 1 {
+            format!("({})", factors.join(" "))
+        } else {
+            factors.join(" ")
+        }
+    }
+
+    /// The unit that follows a number literal, e.g. ` m^3 / (kg s^2)`
+    fn after_value(&self) -> String {
+        match (self.numerator.is_empty(), self.denominator.is_empty()) {
+            (true, true) => "".into(),
+            (false, true) => format!(" {}", self.numerator.join(" ")),
+            (true, false) => format!(" / {}", Self::factors(&self.denominator)),
+            (false, false) => format!(
+                " {} / {}",
+                self.numerator.join(" "),
+                Self::factors(&self.denominator)
+            ),
+        }
+    }
+}
+
+/// Maps a unit like `m^3 kg^-1 s^-2` onto Numbat units. Returns `None` if one of
+/// the units is not known.
+fn map_unit(unit: &str, is_known_unit: &dyn Fn(&str) -> bool) -> Option<UnitExpression> {
+    let mut expression = UnitExpression {
+        numerator: vec![],
+        denominator: vec![],
+    };
+
+    for factor in unit.split_whitespace() {
+        let (symbol, exponent) = match factor.split_once('^') {
+            Some((symbol, exponent)) => (symbol, exponent.parse::<i32>().ok()?),
+            None => (factor, 1),
+        };
+
+        let symbol = UNIT_SYMBOLS
+            .iter()
+            .find(|(codata, _)| *codata == symbol)
+            .map_or(symbol, |(_, numbat)| numbat);
+        if !is_known_unit(symbol) || exponent == 0 {
+            return None;
+        }
+
+        let factor = if exponent.abs() == 1 {
+            symbol.to_string()
+        } else {
+            format!("{symbol}^{}", exponent.abs())
+        };
+        if exponent > 0 {
+            expression.numerator.push(factor);
+        } else {
+            expression.denominator.push(factor);
+        }
+    }
+
+    Some(expression)
+}
+
+/// Generates the source code of the `physics::codata` module. Units are looked up
+/// in the unit registry of the given context. All constants that can not be
+/// generated are reported.
+pub fn generate(data: &str, context: &Context) -> Result<String, Vec<CodataError>> {
+    let table = parse(data).map_err(|e| vec![e])?;
+    let is_known_unit = |name: &str| {
+        matches!(
+            context.prefix_transformer.prefix_parser.parse(name),
+            PrefixParserResult::UnitIdentifier(..)
+        )
+    };
+
+    let mut errors = vec![];
+    let mut definitions = vec![];
+
+    for constant in CONSTANTS {
+        let Some(entry) = table
+            .entries
+            .iter()
+            .find(|e| e.quantity == constant.quantity)
+        else {
+            errors.push(CodataError::UnknownConstant(constant.quantity.into()));
+            continue;
+        };
+
+        let Some(unit) = map_unit(&entry.unit, &is_known_unit) else {
+            errors.push(CodataError::UnknownUnit {
+                constant: constant.quantity.into(),
+                unit: entry.unit.clone(),
+            });
+            continue;
+        };
+
+        let value = match constant.exact_value {
+            Some(_) if entry.uncertainty.is_some() => {
+                errors.push(CodataError::InexactConstantWithExpression(
+                    constant.quantity.into(),
+                ));
+                continue;
+            }
+            Some(expression) => expression.to_string(),
+            None => format!("{}{}", entry.value, unit.after_value()),
+        };
+
+        let mut definition = String::new();
+        definition += &format!("@name(\"{}\")\n", constant.name);
+        if let Some(year) = &table.year {
+            definition += &format!(
+                "@description(\"CODATA {year} value of the {}.\")\n",
+                constant.quantity
+            );
+        }
+        definition += &format!("@url(\"{}\")\n", constant.url);
+        if !constant.aliases.is_empty() {
+            definition += &format!("@aliases({})\n", constant.aliases.join(", "));
+        }
+        definition += &format!(
+            "@uncertainty({})\n",
+            entry.uncertainty.as_deref().unwrap_or("0")
+        );
+        definition += &format!(
+            "let {}: {} = {value}\n",
+            constant.identifier, constant.type_annotation
+        );
+
+        definitions.push(definition);
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(format!(
+        "# This module is generated from data/codata.txt, see src/codata.rs. Do not edit it by hand.\n\
+         \n\
+         use math::constants\n\
+         use units::si\n\
+         \n\
+         {}",
+        definitions.iter().join("\n")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATA: &str = "\
+             Fundamental Physical Constants --- Complete Listing
+                    2018 CODATA adjustment
+
+  Quantity                                                       Value                 Uncertainty           Unit
+-----------------------------------------------------------------------------------------------------------------------------
+fine-structure constant                                     7.297 352 5693 e-3       0.000 000 0011 e-3
+Newtonian constant of gravitation                           6.674 30 e-11            0.000 15 e-11            m^3 kg^-1 s^-2
+reduced Planck constant                                     1.054 571 817... e-34    (exact)                  J s
+";
+
+    #[test]
+    fn parse_table() {
+        let table = parse(DATA).unwrap();
+        assert_eq!(table.year.as_deref(), Some("2018"));
+        assert_eq!(
+            table.entries,
+            vec![
+                Entry {
+                    quantity: "fine-structure constant".into(),
+                    value: "7.297_352_5693e-3".into(),
+                    truncated: false,
+                    uncertainty: Some("0.000_000_0011e-3".into()),
+                    unit: "".into(),
+                },
+                Entry {
+                    quantity: "Newtonian constant of gravitation".into(),
+                    value: "6.674_30e-11".into(),
+                    truncated: false,
+                    uncertainty: Some("0.000_15e-11".into()),
+                    unit: "m^3 kg^-1 s^-2".into(),
+                },
+                Entry {
+                    quantity: "reduced Planck constant".into(),
+                    value: "1.054_571_817e-34".into(),
+                    truncated: true,
+                    uncertainty: None,
+                    unit: "J s".into(),
+                },
+            ]
+        );
+
+        assert_eq!(parse("no table"), Err(CodataError::MissingTable));
+        assert_eq!(
+            parse("-----\nspeed of light   fast"),
+            Err(CodataError::MalformedLine(
+                2,
+                "speed of light   fast".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn unit_mapping() {
+        let is_known_unit = |name: &str| ["m", "kg", "s", "mol", "Ω"].contains(&name);
+
+        let unit = map_unit("m^3 kg^-1 s^-2", &is_known_unit).unwrap();
+        assert_eq!(unit.after_value(), " m^3 / (kg s^2)");
+
+        let unit = map_unit("mol^-1", &is_known_unit).unwrap();
+        assert_eq!(unit.after_value(), " / mol");
+
+        assert_eq!(map_unit("ohm", &is_known_unit).unwrap().after_value(), " Ω");
+        assert_eq!(map_unit("", &is_known_unit).unwrap().after_value(), "");
+
+        assert_eq!(map_unit("furlong s^-1", &is_known_unit), None);
+        assert_eq!(map_unit("m^x", &is_known_unit), None);
+    }
+}
@@ -0,0 +1,162 @@
+//! A cache for the factors needed to convert quantities between two units.
+//!
+//! Computing a conversion factor requires going back to the base representation
+//! of both units, which is expensive compared to the conversion itself. Scripts
+//! that convert many values between the same pair of units (e.g. inside a `map`)
+//! only compute the factors once. All unit conversions in Numbat are purely
+//! multiplicative, so a pair of factors is all that needs to be stored.
+//!
+//! The cache is cleared whenever a new unit is defined.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use crate::quantity::{Quantity, Result};
+use crate::unit::{ConversionFactor, Unit};
+
+/// Hash the names, prefixes and exponents of both units. Units that are equal have
+/// the same hash, but the hash does not depend on the definitions of the units.
+fn hash_units(from: &Unit, to: &Unit) -> u64 {
+    let mut state = DefaultHasher::new();
+    for unit in [from, to] {
+        for factor in unit.iter() {
+            factor.prefix.hash(&mut state);
+            factor.unit_id.name.hash(&mut state);
+            factor.exponent.hash(&mut state);
+        }
+        state.write_u8(0);
+    }
+    state.finish()
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    from: Unit,
+    to: Unit,
+    factors: (ConversionFactor, ConversionFactor),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct ConversionCacheStats {
+    pub entries: usize,
+    pub hits: usize,
+    pub misses: usize,
+}
+
+impl std::fmt::Display for ConversionCacheStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Conversion cache: entries: {}, hits: {}, misses: {}",
+            self.entries, self.hits, self.misses
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ConversionCache {
+    /// Entries by the hash of the two units. Looking up an entry compares the full
+    /// units (including the definitions of all units involved), without cloning them.
+    entries: HashMap<u64, Vec<Entry>>,
+    hits: usize,
+    misses: usize,
+}
+
+impl ConversionCache {
+    /// Convert `quantity` to `target_unit`. The result is the same as the one of
+    /// [`Quantity::convert_to`].
+    pub fn convert(&mut self, quantity: &Quantity, target_unit: &Unit) -> Result<Quantity> {
+        if quantity.unit() == target_unit || quantity.is_zero() {
+            return quantity.convert_to(target_unit);
+        }
+
+        let from = quantity.unit();
+        let bucket = self
+            .entries
+            .entry(hash_units(from, target_unit))
+            .or_default();
+        let factors = if let Some(entry) = bucket
+            .iter()
+            .find(|e| &e.from == from && &e.to == target_unit)
+        {
+            self.hits += 1;
+            entry.factors
+        } else {
+            self.misses += 1;
+            let factors = Quantity::conversion_factors(from, target_unit)?;
+            bucket.push(Entry {
+                from: from.clone(),
+                to: target_unit.clone(),
+                factors,
+            });
+            factors
+        };
+
+        Ok(quantity.convert_with_factors(factors, target_unit))
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn stats(&self) -> ConversionCacheStats {
+        ConversionCacheStats {
+            entries: self.entries.values().map(Vec::len).sum(),
+            hits: self.hits,
+            misses: self.misses,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_result_as_uncached_conversion() {
+        let mut cache = ConversionCache::default();
+
+        let conversions = [
+            (Quantity::new_f64(3.3, Unit::centimeter()), Unit::meter()),
+            (Quantity::new_f64(1.7, Unit::meter()), Unit::kilometer()),
+            (Quantity::new_f64(2.5, Unit::mile()), Unit::millimeter()),
+            (
+                Quantity::new_f64(1.1, Unit::meter() / Unit::second()),
+                Unit::kph(),
+            ),
+        ];
+
+        for _ in 0..2 {
+            for (quantity, target_unit) in &conversions {
+                let expected = quantity.convert_to(target_unit).unwrap();
+                let actual = cache.convert(quantity, target_unit).unwrap();
+                assert_eq!(actual.unit(), expected.unit());
+                assert_eq!(
+                    actual.unsafe_value().to_f64(),
+                    expected.unsafe_value().to_f64()
+                );
+            }
+        }
+
+        assert_eq!(
+            cache.stats(),
+            ConversionCacheStats {
+                entries: 4,
+                hits: 4,
+                misses: 4
+            }
+        );
+
+        cache.clear();
+        assert_eq!(cache.stats().entries, 0);
+    }
+
+    #[test]
+    fn incompatible_units() {
+        let mut cache = ConversionCache::default();
+        let quantity = Quantity::new_f64(1.0, Unit::meter());
+        assert!(cache.convert(&quantity, &Unit::second()).is_err());
+        assert_eq!(cache.stats().entries, 0);
+    }
+}
@@ -1,37 +1,253 @@
-use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 
-use numbat_exchange_rates::{parse_exchange_rates, ExchangeRates};
+use jiff::Timestamp;
+use numbat_exchange_rates::{parse_exchange_rates, ExchangeRates, DEFAULT_TIMEOUT};
 
-static EXCHANGE_RATES: OnceLock<Mutex<Option<ExchangeRates>>> = OnceLock::new();
+use crate::build_info::ExchangeRatesStatus;
+
+/// Exchange rates, along with the time at which they have been fetched
+#[derive(Debug, Clone)]
+pub struct LoadedExchangeRates {
+    pub rates: ExchangeRates,
+    pub fetched_at: Timestamp,
+    /// Whether these are cached rates, which are used because fresh ones could not
+    /// be fetched (or have not been fetched yet)
+    pub cached: bool,
+}
+
+impl LoadedExchangeRates {
+    fn fresh(rates: ExchangeRates) -> Self {
+        LoadedExchangeRates {
+            rates,
+            fetched_at: Timestamp::now(),
+            cached: false,
+        }
+    }
+
+    /// The time since the rates have been fetched
+    pub fn age(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(SystemTime::from(self.fetched_at))
+            .unwrap_or_default()
+    }
+}
+
+enum State {
+    NotLoaded,
+    Unavailable,
+    Loaded(LoadedExchangeRates),
+}
+
+static EXCHANGE_RATES: Mutex<State> = Mutex::new(State::NotLoaded);
+
+/// Increased whenever the exchange rates are replaced, such that the currency
+/// units can be defined again with the new rates
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// The rates that are used if fresh ones can not be fetched
+static CACHED_EXCHANGE_RATES: Mutex<Option<LoadedExchangeRates>> = Mutex::new(None);
+
+/// Where fetched exchange rates are stored, to be used as cached rates later on
+static CACHE_FILE: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+static FETCH_TIMEOUT: Mutex<Duration> = Mutex::new(DEFAULT_TIMEOUT);
+
+type ExchangeRatesProvider = Arc<dyn Fn() -> Option<String> + Send + Sync>;
+
+/// Replaces fetching the exchange rates from the network, if set
+static EXCHANGE_RATES_PROVIDER: Mutex<Option<ExchangeRatesProvider>> = Mutex::new(None);
 
 pub struct ExchangeRatesCache {}
 
 impl ExchangeRatesCache {
-    pub fn new() -> Self {
-        Self {}
+    pub fn set_from_xml(xml_content: &str) {
+        Self::replace(match parse_exchange_rates(xml_content) {
+            Some(rates) => State::Loaded(LoadedExchangeRates::fresh(rates)),
+            None => State::Unavailable,
+        });
     }
 
-    pub fn get_rate(&self, currency: &str) -> Option<f64> {
-        let rates = Self::fetch();
-        rates.as_ref().and_then(|r| r.get(currency)).cloned()
+    pub fn set_provider(provider: impl Fn() -> Option<String> + Send + Sync + 'static) {
+        *EXCHANGE_RATES_PROVIDER.lock().unwrap() = Some(Arc::new(provider));
     }
 
-    pub fn set_from_xml(xml_content: &str) {
-        EXCHANGE_RATES
-            .set(Mutex::new(parse_exchange_rates(xml_content)))
-            .unwrap();
+    pub fn set_timeout(timeout: Duration) {
+        *FETCH_TIMEOUT.lock().unwrap() = timeout;
+    }
+
+    /// Use the given rates if fresh ones can not be fetched. If that has already
+    /// happened, they are used right away.
+    pub fn set_cached(xml_content: &str, fetched_at: SystemTime) {
+        let Some(rates) = parse_exchange_rates(xml_content) else {
+            return;
+        };
+        let Ok(fetched_at) = Timestamp::try_from(fetched_at) else {
+            return;
+        };
+        let cached = LoadedExchangeRates {
+            rates,
+            fetched_at,
+            cached: true,
+        };
+        *CACHED_EXCHANGE_RATES.lock().unwrap() = Some(cached.clone());
+
+        let mut state = EXCHANGE_RATES.lock().unwrap();
+        if matches!(*state, State::Unavailable) {
+            *state = State::Loaded(cached);
+            GENERATION.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Load cached rates from the given file (if it exists), and store all rates
+    /// that are fetched from now on in it. The age of the cached rates is the time
+    /// of the last modification of the file.
+    pub fn set_cache_file(path: PathBuf) {
+        if let Ok(xml_content) = std::fs::read_to_string(&path) {
+            let modified = std::fs::metadata(&path).and_then(|m| m.modified());
+            Self::set_cached(&xml_content, modified.unwrap_or_else(|_| SystemTime::now()));
+        }
+        *CACHE_FILE.lock().unwrap() = Some(path);
+    }
+
+    /// Fetch the XML document with the exchange rates from the provider or the
+    /// network. Gives up after the timeout.
+    fn load_xml() -> Option<String> {
+        let timeout = *FETCH_TIMEOUT.lock().unwrap();
+        let provider = EXCHANGE_RATES_PROVIDER.lock().unwrap().clone();
+        let load = move || match provider {
+            Some(provider) => provider(),
+            #[cfg(feature = "fetch-exchangerates")]
+            None => numbat_exchange_rates::fetch_exchange_rates_xml(timeout),
+            #[cfg(not(feature = "fetch-exchangerates"))]
+            None => None,
+        };
+
+        // The timeout of the request does not cover all cases (like a slow name
+        // resolution, or a slow provider), so we also stop waiting for the thread.
+        #[cfg(not(target_family = "wasm"))]
+        {
+            let (sender, receiver) = std::sync::mpsc::channel();
+            std::thread::spawn(move || sender.send(load()));
+            receiver.recv_timeout(timeout).ok().flatten()
+        }
+        #[cfg(target_family = "wasm")]
+        load()
+    }
+
+    /// Fetch fresh exchange rates, and store them in the cache file
+    fn load_fresh() -> Option<LoadedExchangeRates> {
+        let xml_content = Self::load_xml()?;
+        let rates = parse_exchange_rates(&xml_content)?;
+        if let Some(path) = CACHE_FILE.lock().unwrap().as_ref() {
+            // The cache is only a fallback, so failing to write it is not an error
+            let _ = std::fs::write(path, &xml_content);
+        }
+        Some(LoadedExchangeRates::fresh(rates))
+    }
+
+    /// Fresh exchange rates or, if they can not be fetched, the cached ones
+    fn load() -> State {
+        match Self::load_fresh().or_else(|| CACHED_EXCHANGE_RATES.lock().unwrap().clone()) {
+            Some(rates) => State::Loaded(rates),
+            None => State::Unavailable,
+        }
+    }
+
+    fn replace(new_state: State) {
+        *EXCHANGE_RATES.lock().unwrap() = new_state;
+        GENERATION.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Fetch fresh exchange rates and replace the current ones, which can still be
+    /// used in the meantime. If fetching fails, the current (or cached) rates are
+    /// kept.
+    pub fn refresh() {
+        if let Some(rates) = Self::load_fresh() {
+            Self::replace(State::Loaded(rates));
+            return;
+        }
+
+        let mut state = EXCHANGE_RATES.lock().unwrap();
+        match &mut *state {
+            State::Loaded(rates) => rates.cached = true,
+            State::NotLoaded | State::Unavailable => {
+                if let Some(cached) = CACHED_EXCHANGE_RATES.lock().unwrap().clone() {
+                    *state = State::Loaded(cached);
+                    GENERATION.fetch_add(1, Ordering::SeqCst);
+                } else {
+                    *state = State::Unavailable;
+                }
+            }
+        }
+    }
+
+    /// Use the cached rates until the exchange rates are loaded. Returns `false`
+    /// if there are no cached rates.
+    pub fn use_cached_for_now() -> bool {
+        let mut state = EXCHANGE_RATES.lock().unwrap();
+        if !matches!(*state, State::NotLoaded) {
+            return true;
+        }
+        match CACHED_EXCHANGE_RATES.lock().unwrap().clone() {
+            Some(cached) => {
+                *state = State::Loaded(cached);
+                GENERATION.fetch_add(1, Ordering::SeqCst);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Like [`ExchangeRatesCache::fetch`], but returns `None` if the exchange
+    /// rates would have to be fetched from the network.
+    pub fn fetch_without_network() -> Option<Option<LoadedExchangeRates>> {
+        if matches!(*EXCHANGE_RATES.lock().unwrap(), State::NotLoaded)
+            && EXCHANGE_RATES_PROVIDER.lock().unwrap().is_none()
+        {
+            return None;
+        }
+        Some(Self::fetch())
+    }
+
+    /// The state of the cache. This never loads the exchange rates.
+    pub fn status() -> ExchangeRatesStatus {
+        match &*EXCHANGE_RATES.lock().unwrap() {
+            State::NotLoaded => ExchangeRatesStatus::NotLoaded,
+            State::Unavailable => ExchangeRatesStatus::Unavailable,
+            State::Loaded(rates) => ExchangeRatesStatus::Loaded {
+                currencies: rates.rates.len(),
+            },
+        }
+    }
+
+    /// The current exchange rates, if they have been loaded. This never loads
+    /// the exchange rates.
+    pub fn current() -> Option<LoadedExchangeRates> {
+        match &*EXCHANGE_RATES.lock().unwrap() {
+            State::Loaded(rates) => Some(rates.clone()),
+            State::NotLoaded | State::Unavailable => None,
+        }
     }
 
-    #[cfg(feature = "fetch-exchangerates")]
-    pub fn fetch() -> MutexGuard<'static, Option<ExchangeRates>> {
-        EXCHANGE_RATES
-            .get_or_init(|| Mutex::new(numbat_exchange_rates::fetch_exchange_rates()))
-            .lock()
-            .unwrap()
+    /// Changes whenever the exchange rates are replaced
+    pub fn generation() -> u64 {
+        GENERATION.load(Ordering::SeqCst)
     }
 
-    #[cfg(not(feature = "fetch-exchangerates"))]
-    pub fn fetch() -> MutexGuard<'static, Option<ExchangeRates>> {
-        EXCHANGE_RATES.get().unwrap().lock().unwrap()
+    /// The exchange rates, which are loaded when this is called for the first
+    /// time. Blocks until they are available (or the timeout has passed).
+    pub fn fetch() -> Option<LoadedExchangeRates> {
+        let mut state = EXCHANGE_RATES.lock().unwrap();
+        if matches!(*state, State::NotLoaded) {
+            *state = Self::load();
+            GENERATION.fetch_add(1, Ordering::SeqCst);
+        }
+        match &*state {
+            State::Loaded(rates) => Some(rates.clone()),
+            State::NotLoaded | State::Unavailable => None,
+        }
     }
 }
@@ -5,7 +5,12 @@ pub fn get_local_timezone_or_utc() -> TimeZone {
     TimeZone::system()
 }
 
-pub fn parse_datetime(input: &str) -> Result<Zoned, jiff::Error> {
+/// Parse a datetime in one of the supported formats. Datetimes without timezone
+/// or offset are interpreted in the given local timezone.
+pub fn parse_datetime(
+    input: &str,
+    local_timezone: impl Fn() -> TimeZone,
+) -> Result<Zoned, jiff::Error> {
     if let zoned @ Ok(_) = Zoned::from_str(input) {
         return zoned;
     }
@@ -61,11 +66,11 @@ pub fn parse_datetime(input: &str) -> Result<Zoned, jiff::Error> {
 
         // Without timezone/offset
         if let Ok(dt) = DateTime::strptime(format, input) {
-            return dt.to_zoned(get_local_timezone_or_utc());
+            return dt.to_zoned(local_timezone());
         }
     }
 
-    Timestamp::from_str(input).map(|ts| ts.to_zoned(get_local_timezone_or_utc()))
+    Timestamp::from_str(input).map(|ts| ts.to_zoned(local_timezone()))
 }
 
 pub fn to_string(dt: &Zoned) -> String {
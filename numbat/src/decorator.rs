@@ -1,19 +1,37 @@
-use crate::{prefix_parser::AcceptsPrefix, unit::CanonicalName};
+use crate::{
+    ast::BinaryOperator, number::Number, prefix_parser::AcceptsPrefix, symbol::Symbol,
+    unit::CanonicalName,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Decorator {
     MetricPrefixes,
     BinaryPrefixes,
-    Aliases(Vec<(String, Option<AcceptsPrefix>)>),
+    Aliases(Vec<(Symbol, Option<AcceptsPrefix>)>),
     Url(String),
     Name(String),
     Description(String),
+    Operator(BinaryOperator),
+    /// The ISO 4217 code and the usual number of decimal places of a currency unit
+    Currency(String, u32),
+    /// The code of a unit in the Unified Code for Units of Measure, e.g. `mm[Hg]`
+    Ucum(String),
+    /// Quantities in this unit are never converted implicitly to other units of
+    /// the same dimension, e.g. when adding `10 USD + 10 EUR`
+    Isolated,
+    /// The name of the dimension that is created for a base unit like `unit widget`
+    Dimension(Symbol),
+    /// A message for users of a deprecated unit, function or constant
+    Deprecated(String),
+    /// The standard uncertainty of a constant, in the unit of its value. Zero for
+    /// exact values.
+    Uncertainty(Number),
 }
 
 pub fn name_and_aliases<'a>(
-    name: &'a String,
+    name: Symbol,
     decorators: &'a [Decorator],
-) -> Box<dyn Iterator<Item = (&'a String, AcceptsPrefix)> + 'a> {
+) -> Box<dyn Iterator<Item = (Symbol, AcceptsPrefix)> + 'a> {
     let aliases = {
         let mut aliases_vec = vec![];
         for decorator in decorators {
@@ -21,7 +39,7 @@ pub fn name_and_aliases<'a>(
                 aliases_vec = aliases
                     .iter()
                     .map(|(name, accepts_prefix)| {
-                        (name, accepts_prefix.unwrap_or(AcceptsPrefix::only_long()))
+                        (*name, accepts_prefix.unwrap_or(AcceptsPrefix::only_long()))
                     })
                     .collect();
             }
@@ -29,7 +47,7 @@ pub fn name_and_aliases<'a>(
         aliases_vec
     };
 
-    if !aliases.iter().any(|(n, _)| n == &name) {
+    if !aliases.iter().any(|(n, _)| *n == name) {
         let name_iter = std::iter::once((name, AcceptsPrefix::only_long()));
         Box::new(name_iter.chain(aliases))
     } else {
@@ -37,23 +55,31 @@ pub fn name_and_aliases<'a>(
     }
 }
 
-pub fn get_canonical_unit_name(unit_name: &str, decorators: &[Decorator]) -> CanonicalName {
+pub fn get_canonical_unit_name(unit_name: Symbol, decorators: &[Decorator]) -> CanonicalName {
+    // The long name is the name of the unit itself (in singular). By convention,
+    // the first alias that takes long prefixes is the plural, like in
+    // `@aliases(metres, meter, meters, m: short)`.
+    let plural = name_and_aliases(unit_name, decorators)
+        .find(|&(alias, ap)| alias != unit_name && ap.long)
+        .map_or(unit_name, |(alias, _)| alias);
+
     for decorator in decorators {
         if let Decorator::Aliases(aliases) = decorator {
             for (alias, accepts_prefix) in aliases {
-                match accepts_prefix {
-                    &Some(ap) if ap.short => {
-                        return CanonicalName::new(alias, ap);
+                match *accepts_prefix {
+                    // A unit like `bps` that is only known by its symbol has no long name
+                    Some(ap) if ap.short && *alias == unit_name => {
+                        return CanonicalName::new(*alias, ap);
+                    }
+                    Some(ap) if ap.short => {
+                        return CanonicalName::new(*alias, ap).with_long_name(unit_name, plural);
                     }
                     _ => {}
                 }
             }
         }
     }
-    CanonicalName {
-        name: unit_name.into(),
-        accepts_prefix: AcceptsPrefix::only_long(),
-    }
+    CanonicalName::new(unit_name, AcceptsPrefix::only_long()).with_long_name(unit_name, plural)
 }
 
 pub fn name(decorators: &[Decorator]) -> Option<String> {
@@ -89,6 +115,60 @@ pub fn description(decorators: &[Decorator]) -> Option<String> {
     }
 }
 
+pub fn deprecated(decorators: &[Decorator]) -> Option<String> {
+    for decorator in decorators {
+        if let Decorator::Deprecated(message) = decorator {
+            return Some(message.clone());
+        }
+    }
+    None
+}
+
+pub fn operator(decorators: &[Decorator]) -> Option<BinaryOperator> {
+    for decorator in decorators {
+        if let Decorator::Operator(op) = decorator {
+            return Some(*op);
+        }
+    }
+    None
+}
+
+pub fn currency(decorators: &[Decorator]) -> Option<(String, u32)> {
+    for decorator in decorators {
+        if let Decorator::Currency(code, decimal_places) = decorator {
+            return Some((code.clone(), *decimal_places));
+        }
+    }
+    None
+}
+
+pub fn ucum(decorators: &[Decorator]) -> Option<String> {
+    for decorator in decorators {
+        if let Decorator::Ucum(code) = decorator {
+            return Some(code.clone());
+        }
+    }
+    None
+}
+
+pub fn uncertainty(decorators: &[Decorator]) -> Option<Number> {
+    for decorator in decorators {
+        if let Decorator::Uncertainty(uncertainty) = decorator {
+            return Some(*uncertainty);
+        }
+    }
+    None
+}
+
+pub fn dimension(decorators: &[Decorator]) -> Option<Symbol> {
+    for decorator in decorators {
+        if let Decorator::Dimension(name) = decorator {
+            return Some(*name);
+        }
+    }
+    None
+}
+
 pub fn contains_aliases_with_prefixes(decorates: &[Decorator]) -> bool {
     for decorator in decorates {
         if let Decorator::Aliases(aliases) = decorator {
@@ -0,0 +1,445 @@
+//! Which definitions of a session a statement depends on, see
+//! [`crate::Context::dependencies_of`] and [`crate::Context::dependency_graph`].
+//! This is meant for notebook-like frontends that re-evaluate the cells which
+//! depend on a changed cell.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::ast::{TypeAnnotation, TypeExpression};
+use crate::decorator;
+use crate::markup::{Formatter, PlainTextFormatter};
+use crate::pretty_print::PrettyPrint;
+use crate::symbol::Symbol;
+use crate::traversal::ForAllExpressions;
+use crate::typed_ast::{
+    DefineVariable, Expression, ExpressionArena, Pattern, Statement, Type, TypedStatement,
+};
+use crate::Context;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DefinitionKind {
+    Variable,
+    Function,
+    Unit,
+    Dimension,
+    Struct,
+}
+
+/// A name that is defined by a statement. Units and variables are listed with
+/// all of their aliases.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Definition {
+    pub name: String,
+    pub kind: DefinitionKind,
+}
+
+/// A definition that a statement uses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dependency {
+    pub name: String,
+    pub kind: DefinitionKind,
+    /// The index of the defining statement in the session, see [`DependencyGraph`]
+    pub statement: usize,
+}
+
+/// A statement that has been evaluated in the current session
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DependencyNode {
+    /// The index of the statement, counting all evaluated statements of the session
+    /// (excluding those of imported modules)
+    pub statement: usize,
+    /// The pretty-printed statement
+    pub code: String,
+    pub definitions: Vec<Definition>,
+    pub dependencies: Vec<Dependency>,
+}
+
+/// The dependencies between all statements of a session. Statements only depend on
+/// earlier statements, so the nodes are in a valid order for re-evaluation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DependencyGraph {
+    pub nodes: Vec<DependencyNode>,
+    /// Pairs of statement indices `(definition, user)`, without duplicates
+    pub edges: Vec<(usize, usize)>,
+}
+
+impl DependencyGraph {
+    /// All statements that directly or indirectly depend on the given statement, in
+    /// the order in which they need to be re-evaluated if it changes.
+    pub fn dependents(&self, statement: usize) -> Vec<usize> {
+        let mut affected = BTreeSet::from([statement]);
+        // Edges always point forward, so a single pass in order is enough
+        for node in self.nodes.iter().filter(|n| n.statement > statement) {
+            if node
+                .dependencies
+                .iter()
+                .any(|d| affected.contains(&d.statement))
+            {
+                affected.insert(node.statement);
+            }
+        }
+        affected.remove(&statement);
+        affected.into_iter().collect()
+    }
+}
+
+/// A name that is used by a statement, before it is resolved to a definition
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Use {
+    /// A variable or a function that is used as a value
+    Value(Symbol),
+    Function(Symbol),
+    Unit(Symbol),
+    /// A dimension or a struct in a type annotation
+    Type(Symbol),
+    Struct(Symbol),
+}
+
+impl Use {
+    fn name(&self) -> Symbol {
+        match self {
+            Use::Value(name)
+            | Use::Function(name)
+            | Use::Unit(name)
+            | Use::Type(name)
+            | Use::Struct(name) => *name,
+        }
+    }
+
+    fn accepts(&self, kind: DefinitionKind) -> bool {
+        match self {
+            Use::Value(_) => matches!(kind, DefinitionKind::Variable | DefinitionKind::Function),
+            Use::Function(_) => kind == DefinitionKind::Function,
+            Use::Unit(_) => kind == DefinitionKind::Unit,
+            Use::Type(_) => matches!(kind, DefinitionKind::Dimension | DefinitionKind::Struct),
+            Use::Struct(_) => kind == DefinitionKind::Struct,
+        }
+    }
+}
+
+fn pattern_names(pattern: &Pattern, names: &mut Vec<Symbol>) {
+    match pattern {
+        Pattern::Identifier(name) => names.push(*name),
+        Pattern::Struct(fields, _) => {
+            for (_, pattern) in fields {
+                pattern_names(pattern, names);
+            }
+        }
+        Pattern::List(patterns) => {
+            for pattern in patterns {
+                pattern_names(pattern, names);
+            }
+        }
+    }
+}
+
+fn variable_definitions(variable: &DefineVariable) -> Vec<Definition> {
+    let DefineVariable(pattern, decorators, ..) = variable;
+    let definition = |name: Symbol| Definition {
+        name: name.to_string(),
+        kind: DefinitionKind::Variable,
+    };
+    match pattern {
+        Pattern::Identifier(name) => decorator::name_and_aliases(*name, decorators)
+            .map(|(name, _)| definition(name))
+            .collect(),
+        _ => {
+            let mut names = vec![];
+            pattern_names(pattern, &mut names);
+            names.into_iter().map(definition).collect()
+        }
+    }
+}
+
+/// The names that are defined by a statement
+fn definitions(statement: &Statement) -> Vec<Definition> {
+    let definition = |name: &Symbol, kind| Definition {
+        name: name.to_string(),
+        kind,
+    };
+    let unit = |name: &Symbol, decorators| {
+        decorator::name_and_aliases(*name, decorators)
+            .map(|(name, _)| definition(&name, DefinitionKind::Unit))
+            .collect()
+    };
+
+    match statement {
+        Statement::DefineVariable(variable) => variable_definitions(variable),
+        Statement::DefineFunction(name, ..) => vec![definition(name, DefinitionKind::Function)],
+        Statement::DefineDimension(name, _) => vec![definition(name, DefinitionKind::Dimension)],
+        Statement::DefineBaseUnit(_, name, decorators, ..) => unit(name, decorators),
+        Statement::DefineDerivedUnit(_, name, _, decorators, ..) => unit(name, decorators),
+        Statement::DefineStruct(info) => vec![definition(&info.name, DefinitionKind::Struct)],
+        Statement::DefineUnitFunction(function, inverse) => definitions(function)
+            .into_iter()
+            .chain(inverse.iter().flat_map(|inverse| definitions(inverse)))
+            .collect(),
+        Statement::DefineMethods(_, functions) | Statement::DefineFormula(_, functions) => {
+            functions.iter().flat_map(definitions).collect()
+        }
+        Statement::DefineGenericFunction(function) => definitions(function),
+        Statement::Conditional(..)
+        | Statement::Expression(_)
+        | Statement::ProcedureCall(..)
+        | Statement::DefineUnitSystem(..)
+        | Statement::ImportScope(_) => vec![],
+    }
+}
+
+fn type_expression_uses(expression: &TypeExpression, uses: &mut Vec<Use>) {
+    match expression {
+        TypeExpression::Unity(_) => {}
+        TypeExpression::TypeIdentifier(_, name) => uses.push(Use::Type(*name)),
+        TypeExpression::Multiply(_, lhs, rhs) | TypeExpression::Divide(_, lhs, rhs) => {
+            type_expression_uses(lhs, uses);
+            type_expression_uses(rhs, uses);
+        }
+        TypeExpression::Power(_, base, _, _) => type_expression_uses(base, uses),
+    }
+}
+
+fn annotation_uses(annotation: &Option<TypeAnnotation>, uses: &mut Vec<Use>) {
+    match annotation {
+        Some(TypeAnnotation::TypeExpression(expression)) => type_expression_uses(expression, uses),
+        Some(TypeAnnotation::Fn(_, parameters, result)) => {
+            for parameter in parameters {
+                annotation_uses(&Some(parameter.clone()), uses);
+            }
+            annotation_uses(&Some((**result).clone()), uses);
+        }
+        Some(TypeAnnotation::List(_, element) | TypeAnnotation::Variadic(_, element)) => {
+            annotation_uses(&Some((**element).clone()), uses)
+        }
+        Some(
+            TypeAnnotation::Bool(_)
+            | TypeAnnotation::String(_)
+            | TypeAnnotation::DateTime(_)
+            | TypeAnnotation::Dynamic(_),
+        )
+        | None => {}
+    }
+}
+
+fn struct_field_uses(type_: &Type, uses: &mut Vec<Use>) {
+    match type_ {
+        Type::Struct(info) => uses.push(Use::Struct(info.name)),
+        Type::List(element) => struct_field_uses(element, uses),
+        _ => {}
+    }
+}
+
+/// Names in type annotations and names that are only visible inside the statement,
+/// like function parameters
+fn annotations_and_local_names(
+    statement: &Statement,
+    uses: &mut Vec<Use>,
+    local_names: &mut Vec<Symbol>,
+) {
+    match statement {
+        Statement::DefineVariable(DefineVariable(_, _, _, annotation, ..)) => {
+            annotation_uses(annotation, uses)
+        }
+        Statement::DefineFunction(
+            _,
+            _,
+            type_parameters,
+            parameters,
+            _,
+            local_variables,
+            local_units,
+            _,
+            return_annotation,
+            _,
+        ) => {
+            local_names.extend(type_parameters.iter().map(|(name, _)| *name));
+            for (_, name, annotation, _, _) in parameters {
+                local_names.push(*name);
+                annotation_uses(annotation, uses);
+            }
+            for local_variable in local_variables {
+                pattern_names(&local_variable.0, local_names);
+                annotation_uses(&local_variable.3, uses);
+            }
+            for local_unit in local_units {
+                local_names.extend(definitions(local_unit).iter().map(|d| Symbol::new(&d.name)));
+                annotations_and_local_names(local_unit, uses, local_names);
+            }
+            annotation_uses(return_annotation, uses);
+        }
+        Statement::DefineDimension(_, expressions) => {
+            for expression in expressions {
+                type_expression_uses(expression, uses);
+            }
+        }
+        Statement::DefineBaseUnit(_, _, _, annotation, ..)
+        | Statement::DefineDerivedUnit(_, _, _, _, annotation, ..) => {
+            annotation_uses(annotation, uses)
+        }
+        Statement::DefineStruct(info) => {
+            for (_, type_) in info.fields.values() {
+                struct_field_uses(type_, uses);
+            }
+        }
+        Statement::Conditional(_, then_statement, else_statement) => {
+            annotations_and_local_names(then_statement, uses, local_names);
+            if let Some(else_statement) = else_statement {
+                annotations_and_local_names(else_statement, uses, local_names);
+            }
+        }
+        Statement::DefineUnitFunction(function, inverse) => {
+            annotations_and_local_names(function, uses, local_names);
+            if let Some(inverse) = inverse {
+                annotations_and_local_names(inverse, uses, local_names);
+            }
+        }
+        Statement::DefineMethods(_, functions) | Statement::DefineFormula(_, functions) => {
+            for function in functions {
+                annotations_and_local_names(function, uses, local_names);
+            }
+        }
+        Statement::DefineGenericFunction(function) => {
+            annotations_and_local_names(function, uses, local_names)
+        }
+        Statement::Expression(_)
+        | Statement::ProcedureCall(..)
+        | Statement::DefineUnitSystem(..)
+        | Statement::ImportScope(_) => {}
+    }
+}
+
+/// The free names that are used by a statement, in the order of their first use
+fn uses(arena: &ExpressionArena, statement: &Statement) -> Vec<Use> {
+    let mut uses = vec![];
+    let mut local_names = vec![];
+    annotations_and_local_names(statement, &mut uses, &mut local_names);
+
+    statement.for_all_expressions(arena, &mut |expression| match expression {
+        Expression::Identifier(_, name, _) => uses.push(Use::Value(*name)),
+        Expression::UnitIdentifier(_, _, _, full_name, _) => uses.push(Use::Unit(*full_name)),
+        Expression::FunctionCall(_, _, name, ..) => uses.push(Use::Function(*name)),
+        Expression::InstantiateStruct(_, _, _, info) => uses.push(Use::Struct(info.name)),
+        Expression::For(_, variable, _, accumulator, _) => {
+            local_names.push(*variable);
+            local_names.extend(accumulator.iter().map(|(name, _)| *name));
+        }
+        Expression::While(_, _, (accumulator, _), _) => local_names.push(*accumulator),
+        Expression::TryCatch(_, _, message, _) => local_names.extend(*message),
+        _ => {}
+    });
+
+    // A recursive function refers to itself, but 'let x = x + 1' refers to the
+    // previous definition of 'x'
+    if let Statement::DefineFunction(name, ..) = statement {
+        local_names.push(*name);
+    }
+
+    let mut seen = HashSet::new();
+    uses.into_iter()
+        .filter(|u| !local_names.contains(&u.name()))
+        .filter(|u| seen.insert(*u))
+        .collect()
+}
+
+/// The statements of a session and what they define
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SessionDependencies {
+    nodes: Vec<DependencyNode>,
+    /// The index of the latest statement that defines a name, for each kind
+    latest_definitions: HashMap<(String, DefinitionKind), usize>,
+}
+
+impl SessionDependencies {
+    pub(crate) fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Forget all statements after the first `len`, see [`Context::undo`]
+    pub(crate) fn truncate(&mut self, len: usize) {
+        self.nodes.truncate(len);
+        self.latest_definitions.clear();
+        for node in &self.nodes {
+            for definition in &node.definitions {
+                self.latest_definitions
+                    .insert((definition.name.clone(), definition.kind), node.statement);
+            }
+        }
+    }
+
+    fn resolve(&self, arena: &ExpressionArena, statement: &Statement) -> Vec<Dependency> {
+        const KINDS: [DefinitionKind; 5] = [
+            DefinitionKind::Variable,
+            DefinitionKind::Function,
+            DefinitionKind::Unit,
+            DefinitionKind::Dimension,
+            DefinitionKind::Struct,
+        ];
+
+        uses(arena, statement)
+            .into_iter()
+            .filter_map(|u| {
+                let name = u.name().to_string();
+                KINDS
+                    .into_iter()
+                    .filter(|kind| u.accepts(*kind))
+                    .filter_map(|kind| {
+                        self.latest_definitions
+                            .get(&(name.clone(), kind))
+                            .map(|statement| (kind, *statement))
+                    })
+                    .max_by_key(|(_, statement)| *statement)
+                    .map(|(kind, statement)| Dependency {
+                        name,
+                        kind,
+                        statement,
+                    })
+            })
+            .collect()
+    }
+
+    pub(crate) fn record(&mut self, statement: TypedStatement<'_>) {
+        let index = self.nodes.len();
+        let dependencies = self.resolve(statement.arena, statement.statement);
+        let definitions = definitions(statement.statement);
+        for definition in &definitions {
+            self.latest_definitions
+                .insert((definition.name.clone(), definition.kind), index);
+        }
+        self.nodes.push(DependencyNode {
+            statement: index,
+            code: PlainTextFormatter {}.format(&statement.pretty_print(), false),
+            definitions,
+            dependencies,
+        });
+    }
+}
+
+impl Context {
+    /// The definitions of the current session that the given statement uses, for
+    /// example a statement from [`Context::check`] that has not been evaluated yet.
+    /// Definitions from the prelude and from other modules are not included. Unit
+    /// literals like `3 bathtubs` depend on the definition of the unit.
+    ///
+    /// For statements that have already been evaluated, use
+    /// [`Context::dependency_graph`], which also knows about definitions that have
+    /// been replaced in the meantime.
+    pub fn dependencies_of(&self, statement: TypedStatement<'_>) -> Vec<Dependency> {
+        self.session_dependencies
+            .resolve(statement.arena, statement.statement)
+    }
+
+    /// The dependencies between all statements that have been evaluated in this
+    /// session, see [`DependencyGraph::dependents`].
+    pub fn dependency_graph(&self) -> DependencyGraph {
+        let nodes = self.session_dependencies.nodes.clone();
+        let mut edges = vec![];
+        for node in &nodes {
+            for dependency in &node.dependencies {
+                let edge = (dependency.statement, node.statement);
+                if !edges.contains(&edge) {
+                    edges.push(edge);
+                }
+            }
+        }
+        DependencyGraph { nodes, edges }
+    }
+}
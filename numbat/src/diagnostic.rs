@@ -1,28 +1,237 @@
-use codespan_reporting::diagnostic::LabelStyle;
+use std::ops::{Deref, DerefMut, Range};
+
+use codespan_reporting::diagnostic::{Label, LabelStyle};
+use codespan_reporting::files::{Files, SimpleFiles};
+use itertools::Itertools;
+use unicode_width::UnicodeWidthStr;
 
 use crate::{
-    interpreter::RuntimeError,
-    parser::ParseError,
+    error_code::ErrorCode,
+    interpreter::{RuntimeError, RuntimeWarning},
+    parser::{ParseError, ParseErrorKind, ParseWarning},
     pretty_print::PrettyPrint,
+    registry::RegistryError,
     resolver::ResolverError,
-    typechecker::{IncompatibleDimensionsError, TypeCheckError},
-    NameResolutionError,
+    span::Span,
+    tokenizer::TokenizerErrorKind,
+    typechecker::{IncompatibleDimensionsError, TypeCheckError, TypeCheckWarning},
+    unit_registry::UnitRegistryError,
+    NameResolutionError, NumbatError,
 };
 
-pub type Diagnostic = codespan_reporting::diagnostic::Diagnostic<usize>;
+pub use codespan_reporting::diagnostic::Severity;
+
+/// How sure we are that a [`Suggestion`] is what the user intended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Applicability {
+    /// The edit does not change the meaning of the code (or fixes it in the only
+    /// reasonable way), so it can be applied automatically (see `numbat --fix`).
+    MachineApplicable,
+    /// The edit is a guess, which the user should review.
+    MaybeIncorrect,
+}
+
+/// A concrete edit that fixes the problem reported by a [`Diagnostic`]: the
+/// code in `range` of the file `file_id` is replaced by `replacement`. An empty
+/// range means that the replacement is inserted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    pub file_id: usize,
+    pub range: Range<usize>,
+    pub replacement: String,
+    pub applicability: Applicability,
+}
+
+impl Suggestion {
+    /// The note that is shown for this suggestion
+    pub fn help(&self) -> String {
+        if self.range.is_empty() {
+            format!("help: insert `{}`", self.replacement)
+        } else {
+            format!("help: replace with `{}`", self.replacement)
+        }
+    }
+}
+
+/// Apply suggestions to the code of a single file. Suggestions that overlap with
+/// one that starts earlier are skipped.
+pub fn apply_suggestions<'a>(
+    code: &str,
+    suggestions: impl IntoIterator<Item = &'a Suggestion>,
+) -> String {
+    let mut result = String::new();
+    let mut position = 0;
+    for suggestion in suggestions
+        .into_iter()
+        .sorted_by_key(|s| (s.range.start, s.range.end))
+    {
+        if suggestion.range.start < position || suggestion.range.end > code.len() {
+            continue;
+        }
+        result.push_str(&code[position..suggestion.range.start]);
+        result.push_str(&suggestion.replacement);
+        position = suggestion.range.end;
+    }
+    result.push_str(&code[position..]);
+    result
+}
+
+/// A diagnostic as rendered by `codespan_reporting`, along with edits that
+/// would fix the problem.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    inner: codespan_reporting::diagnostic::Diagnostic<usize>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+impl Diagnostic {
+    fn new(severity: Severity) -> Self {
+        Diagnostic {
+            inner: codespan_reporting::diagnostic::Diagnostic::new(severity),
+            suggestions: vec![],
+        }
+    }
+
+    pub fn bug() -> Self {
+        Self::new(Severity::Bug)
+    }
+
+    pub fn error() -> Self {
+        Self::new(Severity::Error)
+    }
+
+    pub fn warning() -> Self {
+        Self::new(Severity::Warning)
+    }
+
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.inner = self.inner.with_message(message);
+        self
+    }
+
+    pub fn with_code(mut self, code: impl Into<String>) -> Self {
+        self.inner = self.inner.with_code(code);
+        self
+    }
+
+    pub fn with_labels(mut self, labels: Vec<Label<usize>>) -> Self {
+        self.inner = self.inner.with_labels(labels);
+        self
+    }
+
+    pub fn with_notes(mut self, notes: Vec<String>) -> Self {
+        self.inner = self.inner.with_notes(notes);
+        self
+    }
+
+    pub fn with_suggestions(mut self, suggestions: impl IntoIterator<Item = Suggestion>) -> Self {
+        self.suggestions.extend(suggestions);
+        self
+    }
+
+    /// The diagnostic as it is shown to users, with a `help:` note for each
+    /// suggestion.
+    pub fn rendered(&self) -> codespan_reporting::diagnostic::Diagnostic<usize> {
+        let mut rendered = self.inner.clone();
+        rendered
+            .notes
+            .extend(self.suggestions.iter().map(Suggestion::help));
+        rendered
+    }
+}
+
+impl Deref for Diagnostic {
+    type Target = codespan_reporting::diagnostic::Diagnostic<usize>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl DerefMut for Diagnostic {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
 
 pub trait ErrorDiagnostic {
     fn diagnostics(&self) -> Vec<Diagnostic>;
 }
 
+impl ErrorDiagnostic for NumbatError {
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            NumbatError::ResolverError(e) => e.diagnostics(),
+            NumbatError::NameResolutionError(e) => e.diagnostics(),
+            NumbatError::TypeCheckError(e) => e.diagnostics(),
+            NumbatError::RuntimeError(e) => e.diagnostics(),
+        }
+    }
+}
+
 impl ErrorDiagnostic for ParseError {
     fn diagnostics(&self) -> Vec<Diagnostic> {
+        let notes = match &self.kind {
+            ParseErrorKind::TokenizerError(TokenizerErrorKind::AmbiguousDecimalComma) => vec![
+                "Use a decimal point ('1.5'), or put a space after the comma to separate two numbers ('1, 5')".into(),
+            ],
+            ParseErrorKind::MissingLeftHandSideOfComparison(suggestion) => vec![match suggestion {
+                Some((suggestion, _)) => format!("Did you mean '{suggestion}'?"),
+                None => "Combine two complete comparisons instead, as in 'x > 0 && x < 5'".into(),
+            }],
+            ParseErrorKind::ExpectedElse => vec![
+                "An if-then-else expression always needs an 'else' branch, since it has to produce a value".into(),
+                "To call a procedure conditionally, use a statement like 'if condition then print(…)', which does not need an 'else' branch".into(),
+            ],
+            ParseErrorKind::NestingTooDeep(_) => vec![
+                "Split the expression into smaller parts, e.g. by introducing intermediate variables with 'let'".into(),
+            ],
+            _ => vec![],
+        };
+
+        let suggestions = match &self.kind {
+            ParseErrorKind::MissingLeftHandSideOfComparison(Some((_, lhs))) => {
+                let position = self.span.start;
+                vec![position
+                    .single_character_span(self.span.code_source_id)
+                    .suggestion(format!("{lhs} "), Applicability::MachineApplicable)]
+            }
+            _ => vec![],
+        };
+
         vec![Diagnostic::error()
             .with_message("while parsing")
+            .with_code(self.kind.error_code().as_str())
             .with_labels(vec![self
                 .span
                 .diagnostic_label(LabelStyle::Primary)
-                .with_message(self.kind.to_string())])]
+                .with_message(self.kind.to_string())])
+            .with_notes(notes)
+            .with_suggestions(suggestions)]
+    }
+}
+
+impl ErrorDiagnostic for ParseWarning {
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            ParseWarning::InexactIntegerLiteral(span, nearest) => vec![Diagnostic::warning()
+                .with_message("inexact number literal")
+                .with_labels(vec![span
+                    .diagnostic_label(LabelStyle::Primary)
+                    .with_message("can not be represented exactly")])
+                .with_notes(vec![format!(
+                    "Numbers are stored as 64-bit floating point values, so the nearest representable value {nearest} is used instead"
+                )])
+                .with_suggestions([span.suggestion(nearest, Applicability::MachineApplicable)])],
+            ParseWarning::DiscardedUncertainty(span, uncertainty) => vec![Diagnostic::warning()
+                .with_message("uncertainty is discarded")
+                .with_labels(vec![span
+                    .diagnostic_label(LabelStyle::Primary)
+                    .with_message(format!("uncertainty ±{uncertainty} is not taken into account"))])
+                .with_notes(vec![
+                    "Calculations only use the central value. Use the @uncertainty decorator to document the uncertainty of a constant".into()
+                ])],
+        }
     }
 }
 
@@ -31,25 +240,49 @@ impl ErrorDiagnostic for ResolverError {
         match self {
             ResolverError::UnknownModule(span, _) => vec![Diagnostic::error()
                 .with_message("while resolving imports in")
+                .with_code(ErrorCode::E0301.as_str())
                 .with_labels(vec![span
                     .diagnostic_label(LabelStyle::Primary)
                     .with_message("Unknown module")])],
+            ResolverError::PermissionDenied(span, _, capability) => vec![Diagnostic::error()
+                .with_message("while resolving imports in")
+                .with_code(ErrorCode::E0302.as_str())
+                .with_labels(vec![span
+                    .diagnostic_label(LabelStyle::Primary)
+                    .with_message(format!("{capability} is disabled in sandbox mode"))])],
             ResolverError::ParseErrors(errors) => {
                 errors.iter().flat_map(|e| e.diagnostics()).collect()
             }
+            ResolverError::UnknownImportItem(span, _, module_path, suggestion) => {
+                vec![Diagnostic::error()
+                    .with_message("while resolving imports in")
+                    .with_code(ErrorCode::E0303.as_str())
+                    .with_labels(vec![span
+                        .diagnostic_label(LabelStyle::Primary)
+                        .with_message(format!("not defined in module '{module_path}'"))])
+                    .with_notes(
+                        suggestion
+                            .iter()
+                            .map(|s| format!("Did you mean '{s}'?"))
+                            .collect(),
+                    )]
+            }
         }
     }
 }
 
 impl ErrorDiagnostic for NameResolutionError {
     fn diagnostics(&self) -> Vec<Diagnostic> {
+        let d = Diagnostic::error().with_code(self.error_code().as_str());
+
         match self {
             NameResolutionError::IdentifierClash {
-                conflicting_identifier: _,
+                conflicting_identifier,
                 original_item_type,
                 conflict_span,
                 original_span,
-            } => vec![Diagnostic::error()
+                unit,
+            } => vec![d
                 .with_message("identifier clash in definition")
                 .with_labels(vec![
                     original_span
@@ -62,19 +295,70 @@ impl ErrorDiagnostic for NameResolutionError {
                     conflict_span
                         .diagnostic_label(LabelStyle::Primary)
                         .with_message("identifier is already in use"),
-                ])],
-            NameResolutionError::ReservedIdentifier(span) => vec![Diagnostic::error()
+                ])
+                .with_notes(unit.iter().map(|unit| {
+                    let (unit_name, dimension) = unit.as_ref();
+                    format!(
+                        "'{conflicting_identifier}' refers to the unit {unit_name} ({dimension}). Variables, functions and units can not share a name, so choose a different one, like '{conflicting_identifier}_'"
+                    )
+                }).collect())],
+            NameResolutionError::ReservedIdentifier(span) => vec![d
                 .with_message("reserved identifier may not be used")
                 .with_labels(vec![span
                     .diagnostic_label(LabelStyle::Primary)
                     .with_message("reserved identifier")])],
+            NameResolutionError::AmbiguousUnit {
+                identifier,
+                span,
+                candidates,
+            } => {
+                let mut notes: Vec<String> = candidates
+                    .iter()
+                    .map(|c| {
+                        let dimension = c
+                            .dimension
+                            .as_ref()
+                            .map(|d| format!(" ({d})"))
+                            .unwrap_or_default();
+                        let long_form = c
+                            .long_form
+                            .as_ref()
+                            .map(|l| format!(", written as '{l}'"))
+                            .unwrap_or_default();
+                        format!(
+                            "'{identifier}' can be read as '{}' + '{}'{dimension}{long_form}",
+                            c.prefix, c.unit
+                        )
+                    })
+                    .collect();
+                notes.push(
+                    if candidates.iter().any(|c| c.long_form.is_some()) {
+                        "Use the long form of the unit you mean"
+                    } else {
+                        "None of these units can be written with a long prefix, so one of them needs to be renamed"
+                    }
+                    .to_string(),
+                );
+
+                vec![d
+                    .with_message("ambiguous unit")
+                    .with_labels(vec![span
+                        .diagnostic_label(LabelStyle::Primary)
+                        .with_message(format!(
+                            "could refer to {} different units",
+                            candidates.len()
+                        ))])
+                    .with_notes(notes)]
+            }
         }
     }
 }
 
 impl ErrorDiagnostic for TypeCheckError {
     fn diagnostics(&self) -> Vec<Diagnostic> {
-        let d = Diagnostic::error().with_message("while type checking");
+        let d = Diagnostic::error()
+            .with_message("while type checking")
+            .with_code(self.error_code().as_str());
         let inner_error = format!("{}", self);
 
         let d = match self {
@@ -88,6 +372,11 @@ impl ErrorDiagnostic for TypeCheckError {
                     .diagnostic_label(LabelStyle::Primary)
                     .with_message("unknown identifier")])
                     .with_notes(notes)
+                    .with_suggestions(
+                        suggestion
+                            .iter()
+                            .map(|s| span.suggestion(s, Applicability::MaybeIncorrect)),
+                    )
             }
             TypeCheckError::IncompatibleDimensions(IncompatibleDimensionsError {
                 operation,
@@ -98,17 +387,18 @@ impl ErrorDiagnostic for TypeCheckError {
                 span_expected,
                 expected_type,
                 expected_dimensions,
+                note,
                 ..
             }) => {
                 let expected_type = if expected_dimensions.is_empty() {
                     format!("{expected_type}")
                 } else {
-                    expected_dimensions.join(" or ")
+                    expected_dimensions.iter().join(" or ")
                 };
                 let actual_type = if actual_dimensions.is_empty() {
                     format!("{actual_type}")
                 } else {
-                    actual_dimensions.join(" or ")
+                    actual_dimensions.iter().join(" or ")
                 };
 
                 let labels = vec![
@@ -122,7 +412,8 @@ impl ErrorDiagnostic for TypeCheckError {
                         .diagnostic_label(LabelStyle::Secondary)
                         .with_message(format!("incompatible dimensions in {}", operation)),
                 ];
-                d.with_labels(labels).with_notes(vec![inner_error])
+                d.with_labels(labels)
+                    .with_notes(std::iter::once(inner_error).chain(note.clone()).collect())
             }
             TypeCheckError::NonScalarExponent(span, type_)
             | TypeCheckError::NonScalarFactorialArgument(span, type_) => d
@@ -137,8 +428,10 @@ impl ErrorDiagnostic for TypeCheckError {
                 .diagnostic_label(LabelStyle::Primary)
                 .with_message(inner_error)]),
             TypeCheckError::RegistryError(re) => match re {
-                crate::registry::RegistryError::EntryExists(_) => d.with_notes(vec![inner_error]),
-                crate::registry::RegistryError::UnknownEntry(name, suggestion) => {
+                RegistryError::EntryExists(name, span, existing_span) => d
+                    .with_labels(entry_exists_labels(name, span, existing_span))
+                    .with_notes(vec![inner_error]),
+                RegistryError::UnknownEntry(name, suggestion) => {
                     d.with_notes(vec![format!(
                         "Unknown dimension '{name}'{maybe_suggestion}",
                         maybe_suggestion = if let Some(suggestion) = suggestion {
@@ -188,6 +481,8 @@ impl ErrorDiagnostic for TypeCheckError {
                         },
                         range = if arity.start() == arity.end() {
                             format!("{}", arity.start())
+                        } else if *arity.end() == usize::MAX {
+                            format!("at least {}", arity.start())
                         } else {
                             format!("{} to {}", arity.start(), arity.end())
                         }
@@ -330,6 +625,7 @@ impl ErrorDiagnostic for TypeCheckError {
                     format!("Use 'unit {unit_name}: Scalar = …' for derived units."),
                 ]),
             TypeCheckError::ForeignFunctionNeedsTypeAnnotations(span, _)
+            | TypeCheckError::ForeignFunctionWithDefaultValue(span, _)
             | TypeCheckError::UnknownForeignFunction(span, _)
             | TypeCheckError::NonRationalExponent(span)
             | TypeCheckError::OverflowInConstExpr(span)
@@ -338,9 +634,127 @@ impl ErrorDiagnostic for TypeCheckError {
             | TypeCheckError::NoFunctionReferenceToGenericFunction(span)
             | TypeCheckError::OnlyFunctionsAndReferencesCanBeCalled(span)
             | TypeCheckError::DerivedUnitDefinitionMustNotBeGeneric(span)
+            | TypeCheckError::ExpectedUnitInUnitSystem(span, _)
+            | TypeCheckError::InvalidOperatorOverload(span, _)
+            | TypeCheckError::AmbiguousOperatorOverload(span, _, _)
             | TypeCheckError::MultipleTypedHoles(span) => d.with_labels(vec![span
                 .diagnostic_label(LabelStyle::Primary)
                 .with_message(inner_error)]),
+            TypeCheckError::UnitFunctionWithoutInverse(span, name) => d
+                .with_labels(vec![span
+                    .diagnostic_label(LabelStyle::Primary)
+                    .with_message("unit function without inverse")])
+                .with_notes(vec![format!(
+                    "Add 'inverse(x) = …' after the definition of '{name}' to convert to it."
+                )]),
+            TypeCheckError::IncompatibleInverse(span, _, expected, actual) => d
+                .with_labels(vec![span
+                    .diagnostic_label(LabelStyle::Primary)
+                    .with_message(format!("this has type '{actual}'"))])
+                .with_notes(vec![format!(
+                    "The inverse needs to map the result of the function back to its argument: '{expected}'"
+                )]),
+            TypeCheckError::PartialApplicationOfUnknownType(span) => d
+                .with_labels(vec![span
+                    .diagnostic_label(LabelStyle::Primary)
+                    .with_message(inner_error)])
+                .with_notes(vec![
+                    "Add a type annotation like 'f: Fn[(Length) -> Time]' to function parameters that are partially applied.".into(),
+                ]),
+            TypeCheckError::TooManyArgumentsInPartialApplication {
+                span,
+                function_span,
+                num_parameters,
+                ..
+            } => d.with_labels(vec![
+                span.diagnostic_label(LabelStyle::Primary)
+                    .with_message("superfluous argument"),
+                function_span
+                    .diagnostic_label(LabelStyle::Secondary)
+                    .with_message(format!(
+                        "takes {}",
+                        if *num_parameters == 1 {
+                            "one argument".into()
+                        } else {
+                            format!("{num_parameters} arguments")
+                        }
+                    )),
+            ])
+            .with_notes(vec![inner_error]),
+            TypeCheckError::UnknownMethod {
+                span,
+                receiver_span,
+                struct_name,
+                methods,
+                ..
+            } => d
+                .with_labels(vec![
+                    span.diagnostic_label(LabelStyle::Primary)
+                        .with_message(inner_error),
+                    receiver_span
+                        .diagnostic_label(LabelStyle::Secondary)
+                        .with_message(struct_name.clone()),
+                ])
+                .with_notes(vec![if methods.is_empty() {
+                    format!("Struct '{struct_name}' does not have any methods.")
+                } else {
+                    format!("Available methods: {}", methods.join(", "))
+                }]),
+            TypeCheckError::NotDifferentiable { span, .. } => d
+                .with_labels(vec![span
+                    .diagnostic_label(LabelStyle::Primary)
+                    .with_message("not differentiable")])
+                .with_notes(vec![
+                    inner_error,
+                    "'autodiff' supports arithmetic, powers, exp, ln, trigonometric and hyperbolic functions, and calls of other functions with a body.".into(),
+                ]),
+            TypeCheckError::FormulaNotSolvable { span, .. } => d
+                .with_labels(vec![span
+                    .diagnostic_label(LabelStyle::Primary)
+                    .with_message("can not be solved for")])
+                .with_notes(vec![
+                    inner_error,
+                    "A formula can be solved for a quantity that occurs exactly once, inside of sums, differences, products, quotients, and in the base of powers with a constant exponent.".into(),
+                ]),
+            TypeCheckError::InvalidAutoDiffArgument(span) => d
+                .with_labels(vec![span
+                    .diagnostic_label(LabelStyle::Primary)
+                    .with_message(inner_error)]),
+            TypeCheckError::UnresolvedDimensionPredicate(span) => d
+                .with_labels(vec![span
+                    .diagnostic_label(LabelStyle::Primary)
+                    .with_message("unknown dimension")])
+                .with_notes(vec![
+                    inner_error,
+                    "Dimension predicates are decided by the type checker. Inside of generic functions, they are decided separately for every call.".into(),
+                ]),
+            TypeCheckError::GenericFunctionNeedsTypeAnnotations(span, _)
+            | TypeCheckError::GenericFunctionNotCalled(span, _)
+            | TypeCheckError::CanNotSpecialize(span, ..) => d.with_labels(vec![span
+                .diagnostic_label(LabelStyle::Primary)
+                .with_message(inner_error)]),
+            TypeCheckError::FeatureNotAvailable(span, _, feature) => d
+                .with_labels(vec![span
+                    .diagnostic_label(LabelStyle::Primary)
+                    .with_message("not available in this build")])
+                .with_notes(vec![format!(
+                    "Enable the '{feature}' feature of the numbat crate to use it"
+                )]),
+            TypeCheckError::DynamicTypeForbidden(span) => d
+                .with_labels(vec![span
+                    .diagnostic_label(LabelStyle::Primary)
+                    .with_message(inner_error)])
+                .with_notes(vec![
+                    "Use a static type annotation instead, like 'Length' or 'String'".into(),
+                ]),
+            TypeCheckError::NoMatchingOperatorOverload(span, op, _, _) => d
+                .with_labels(vec![span
+                    .diagnostic_label(LabelStyle::Primary)
+                    .with_message(inner_error)])
+                .with_notes(vec![format!(
+                    "Operators can be overloaded for structs by defining a function with the '@operator({})' decorator",
+                    op.pretty_print().to_string().trim()
+                )]),
             TypeCheckError::MissingDimension(span, dim) => d
                 .with_labels(vec![span
                     .diagnostic_label(LabelStyle::Primary)
@@ -368,6 +782,25 @@ impl ErrorDiagnostic for TypeCheckError {
                     .diagnostic_label(LabelStyle::Secondary)
                     .with_message(rhs_type.to_string()),
             ]),
+            TypeCheckError::IncompatibleTypesInConversion(
+                span,
+                source_type,
+                source_span,
+                target,
+                target_span,
+                note,
+            ) => d
+                .with_labels(vec![
+                    span.diagnostic_label(LabelStyle::Primary)
+                        .with_message("Conversion is not possible for these types"),
+                    source_span
+                        .diagnostic_label(LabelStyle::Secondary)
+                        .with_message(source_type.to_string()),
+                    target_span
+                        .diagnostic_label(LabelStyle::Secondary)
+                        .with_message(target),
+                ])
+                .with_notes(note.iter().cloned().collect()),
             TypeCheckError::DuplicateFieldInStructInstantiation(
                 this_field_span,
                 that_field_span,
@@ -411,6 +844,19 @@ impl ErrorDiagnostic for TypeCheckError {
                     .diagnostic_label(LabelStyle::Secondary)
                     .with_message("Defined here"),
             ]),
+            TypeCheckError::IncompatibleBaseInStructUpdate(
+                ident_span,
+                _expected_type,
+                base_span,
+                _found_type,
+            ) => d.with_labels(vec![
+                base_span
+                    .diagnostic_label(LabelStyle::Primary)
+                    .with_message(inner_error),
+                ident_span
+                    .diagnostic_label(LabelStyle::Secondary)
+                    .with_message("Struct type of the update"),
+            ]),
             TypeCheckError::UnknownStruct(span, _name) => d.with_labels(vec![span
                 .diagnostic_label(LabelStyle::Primary)
                 .with_message(inner_error)]),
@@ -423,6 +869,47 @@ impl ErrorDiagnostic for TypeCheckError {
                         .diagnostic_label(LabelStyle::Secondary)
                         .with_message("Struct defined here"),
                 ]),
+            TypeCheckError::UnknownNamedArgument(
+                argument_span,
+                definition_span,
+                _,
+                _,
+                suggestion,
+            ) => d
+                .with_labels(vec![
+                    argument_span
+                        .diagnostic_label(LabelStyle::Primary)
+                        .with_message(inner_error),
+                    definition_span
+                        .diagnostic_label(LabelStyle::Secondary)
+                        .with_message("Function defined here"),
+                ])
+                .with_notes(
+                    suggestion
+                        .iter()
+                        .map(|s| format!("Did you mean '{s}'?"))
+                        .collect(),
+                ),
+            TypeCheckError::DuplicateArgument(this_argument_span, that_argument_span, _) => d
+                .with_labels(vec![
+                    this_argument_span
+                        .diagnostic_label(LabelStyle::Primary)
+                        .with_message(inner_error),
+                    that_argument_span
+                        .diagnostic_label(LabelStyle::Secondary)
+                        .with_message("Already specified here"),
+                ]),
+            TypeCheckError::MissingArgument(call_span, parameter_span, _, _) => d.with_labels(vec![
+                call_span
+                    .diagnostic_label(LabelStyle::Primary)
+                    .with_message(inner_error),
+                parameter_span
+                    .diagnostic_label(LabelStyle::Secondary)
+                    .with_message("Parameter defined here"),
+            ]),
+            TypeCheckError::NamedArgumentsInCallableCall(span) => d.with_labels(vec![span
+                .diagnostic_label(LabelStyle::Primary)
+                .with_message(inner_error)]),
             TypeCheckError::DuplicateFieldInStructDefinition(
                 this_field_span,
                 that_field_span,
@@ -435,11 +922,90 @@ impl ErrorDiagnostic for TypeCheckError {
                     .diagnostic_label(LabelStyle::Secondary)
                     .with_message("Already defined here"),
             ]),
+            TypeCheckError::DestructuringOfNonStructType(pattern_span, expr_span, type_)
+            | TypeCheckError::DestructuringOfNonListType(pattern_span, expr_span, type_) => d
+                .with_labels(vec![
+                    pattern_span
+                        .diagnostic_label(LabelStyle::Primary)
+                        .with_message(inner_error),
+                    expr_span
+                        .diagnostic_label(LabelStyle::Secondary)
+                        .with_message(type_.to_string()),
+                ]),
+            TypeCheckError::ImplicitDimensionClash(span, unit_name, dimension_name, original_span) => d
+                .with_labels(vec![
+                    span.diagnostic_label(LabelStyle::Primary)
+                        .with_message(format!("would create the dimension '{dimension_name}'")),
+                    original_span
+                        .diagnostic_label(LabelStyle::Secondary)
+                        .with_message("already defined here"),
+                ])
+                .with_notes(vec![
+                    format!("Use 'unit {unit_name}: {dimension_name}' to define a unit of the existing dimension."),
+                    format!("Use '@dimension(Name)' to choose a different name for the new dimension."),
+                ]),
+            TypeCheckError::LocalUnitOutOfScope(span, _, definition_span, function_name) => d
+                .with_labels(vec![
+                    span.diagnostic_label(LabelStyle::Primary)
+                        .with_message("unit is not in scope here"),
+                    definition_span
+                        .diagnostic_label(LabelStyle::Secondary)
+                        .with_message(format!("defined locally in function '{function_name}'")),
+                ])
+                .with_notes(vec![
+                    "Units from the 'where' clause of a function can only be used inside of that function.".into(),
+                ]),
+            TypeCheckError::LoopOverNonListType(span, _) => d.with_labels(vec![span
+                .diagnostic_label(LabelStyle::Primary)
+                .with_message(inner_error)]),
+            TypeCheckError::IncompatibleTypesInAccumulator(
+                init_span,
+                init_type,
+                body_span,
+                body_type,
+            ) => d
+                .with_labels(vec![
+                    init_span
+                        .diagnostic_label(LabelStyle::Secondary)
+                        .with_message(init_type.to_string()),
+                    body_span
+                        .diagnostic_label(LabelStyle::Primary)
+                        .with_message(body_type.to_string()),
+                ])
+                .with_notes(vec![
+                    "The value of the loop body becomes the next value of the accumulator.".into(),
+                ]),
+            TypeCheckError::IncompatibleTypesInTryCatch(
+                try_span,
+                expr_type,
+                expr_span,
+                fallback_type,
+                fallback_span,
+            ) => d.with_labels(vec![
+                expr_span
+                    .diagnostic_label(LabelStyle::Secondary)
+                    .with_message(expr_type.to_string()),
+                fallback_span
+                    .diagnostic_label(LabelStyle::Secondary)
+                    .with_message(fallback_type.to_string()),
+                try_span
+                    .diagnostic_label(LabelStyle::Primary)
+                    .with_message(inner_error),
+            ]),
+            TypeCheckError::DuplicateBindingInPattern(this_span, that_span, _) => d.with_labels(vec![
+                this_span
+                    .diagnostic_label(LabelStyle::Primary)
+                    .with_message(inner_error),
+                that_span
+                    .diagnostic_label(LabelStyle::Secondary)
+                    .with_message("Already bound here"),
+            ]),
             TypeCheckError::MissingFieldsInStructInstantiation(
                 construction_span,
                 defn_span,
                 missing,
-            ) => d
+            )
+            | TypeCheckError::MissingFieldsInDestructuring(construction_span, defn_span, missing) => d
                 .with_labels(vec![
                     construction_span
                         .diagnostic_label(LabelStyle::Primary)
@@ -470,6 +1036,35 @@ impl ErrorDiagnostic for TypeCheckError {
                 .with_notes(vec![
                     "Consider adding `: Dim` after the type parameter".to_owned()
                 ]),
+            TypeCheckError::MissingOrdBound(span) => d
+                .with_labels(vec![span
+                    .diagnostic_label(LabelStyle::Primary)
+                    .with_message(inner_error)])
+                .with_notes(vec![
+                    "Consider adding `: Ord` after the type parameter".to_owned()
+                ]),
+            TypeCheckError::NotATimeSeries(span, _, _) => d
+                .with_labels(vec![span
+                    .diagnostic_label(LabelStyle::Primary)
+                    .with_message(inner_error)])
+                .with_notes(vec![
+                    "The elements of a time series are structs with the fields 'time: DateTime' and 'value' (a quantity), like 'struct Reading { time: DateTime, value: Power }'. They can have other fields as well.".to_owned(),
+                ]),
+            TypeCheckError::NotOrderable(span, _type, function_name) => {
+                let mut notes = vec![
+                    "Only quantities, strings and date times can be ordered.".to_owned(),
+                ];
+                if function_name == "sort" {
+                    notes.push(
+                        "Use 'sort_by' with a key function to sort a list of other values."
+                            .to_owned(),
+                    );
+                }
+                d.with_labels(vec![span
+                    .diagnostic_label(LabelStyle::Primary)
+                    .with_message(inner_error)])
+                    .with_notes(notes)
+            }
             TypeCheckError::ExponentiationNeedsTypeAnnotation(span) => d.with_labels(vec![span
                 .diagnostic_label(LabelStyle::Primary)
                 .with_message(inner_error)]),
@@ -495,18 +1090,108 @@ impl ErrorDiagnostic for TypeCheckError {
     }
 }
 
+impl ErrorDiagnostic for TypeCheckWarning {
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            TypeCheckWarning::MixedComparisonDirections(first_span, span) => {
+                vec![Diagnostic::warning()
+                    .with_message("comparisons in different directions")
+                    .with_labels(vec![
+                        span.diagnostic_label(LabelStyle::Primary)
+                            .with_message("comparison in the opposite direction"),
+                        first_span.diagnostic_label(LabelStyle::Secondary)
+                            .with_message("first comparison of the chain"),
+                    ])
+                    .with_notes(vec![
+                        "'a < b > c' means 'a < b && b > c', which says nothing about how 'a' and 'c' compare".into(),
+                    ])]
+            }
+            TypeCheckWarning::DeprecatedIdentifier(span, name, message, definition_span) => {
+                vec![Diagnostic::warning()
+                    .with_message(format!("use of deprecated identifier '{name}'"))
+                    .with_labels(vec![
+                        span.diagnostic_label(LabelStyle::Primary)
+                            .with_message(message.clone()),
+                        definition_span
+                            .diagnostic_label(LabelStyle::Secondary)
+                            .with_message("deprecated here"),
+                    ])]
+            }
+            TypeCheckWarning::ReversedConversion(span, conversion_span, swapped) => {
+                vec![Diagnostic::warning()
+                    .with_message("conversion to a quantity")
+                    .with_labels(vec![span
+                        .diagnostic_label(LabelStyle::Primary)
+                        .with_message("this is a quantity, not a unit")])
+                    .with_notes(vec![format!(
+                        "The target of a conversion is usually a unit. Did you mean '{swapped}'?"
+                    )])
+                    .with_suggestions([
+                        conversion_span.suggestion(swapped, Applicability::MaybeIncorrect)
+                    ])]
+            }
+        }
+    }
+}
+
+impl ErrorDiagnostic for RuntimeWarning {
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            RuntimeWarning::InaccurateSum(relative_error, threshold, terms) => {
+                vec![Diagnostic::warning()
+                    .with_message("inaccurate sum")
+                    .with_notes(vec![
+                        format!(
+                            "The estimated relative rounding error of a sum of {terms} term(s) is {relative_error:.1e}, which exceeds the threshold of {threshold:e}."
+                        ),
+                        "The terms cancel each other out to a large extent, so the result might only have a few correct digits.".into(),
+                    ])]
+            }
+            RuntimeWarning::ImplicitConversion(from, to, factor) => {
+                vec![Diagnostic::warning()
+                    .with_message("implicit unit conversion")
+                    .with_notes(vec![
+                        format!("A quantity in '{from}' was converted to '{to}' by multiplying it with {}.", factor.pretty_print()),
+                        format!("Use '… -> {to}' to make the conversion explicit."),
+                    ])]
+            }
+            RuntimeWarning::CachedExchangeRates(age) => {
+                vec![Diagnostic::warning()
+                    .with_message("using cached exchange rates")
+                    .with_notes(vec![
+                        format!("Fresh exchange rates are not available, so currencies are converted with cached rates from {} ago.", format_age(*age)),
+                        "Use 'exchange_rate_age()' to check how old the exchange rates are.".into(),
+                    ])]
+            }
+        }
+    }
+}
+
+/// A rough description of a time span, like "3 days" or "1 hour"
+fn format_age(age: std::time::Duration) -> String {
+    let minutes = age.as_secs() / 60;
+    let (amount, unit) = match minutes {
+        0 => return "less than a minute".into(),
+        1..=59 => (minutes, "minute"),
+        60..=1439 => (minutes / 60, "hour"),
+        _ => (minutes / 1440, "day"),
+    };
+    format!("{amount} {unit}{}", if amount == 1 { "" } else { "s" })
+}
+
 impl ErrorDiagnostic for RuntimeError {
     fn diagnostics(&self) -> Vec<Diagnostic> {
         let inner = format!("{self:#}");
+        let d = Diagnostic::error().with_code(self.error_code().as_str());
 
         match self {
-            RuntimeError::AssertFailed(span) => vec![Diagnostic::error()
-                .with_message("assertion failed")
-                .with_labels(vec![span
+            RuntimeError::AssertFailed(span) => {
+                vec![d.with_message("assertion failed").with_labels(vec![span
                     .diagnostic_label(LabelStyle::Primary)
-                    .with_message("assertion failed")])],
+                    .with_message("assertion failed")])]
+            }
             RuntimeError::AssertEq2Failed(span_lhs, lhs, span_rhs, rhs) => {
-                vec![Diagnostic::error()
+                vec![d
                     .with_message("Assertion failed")
                     .with_labels(vec![
                         span_lhs
@@ -519,7 +1204,7 @@ impl ErrorDiagnostic for RuntimeError {
                     .with_notes(vec![inner])]
             }
             RuntimeError::AssertEq3Failed(span_lhs, lhs, span_rhs, rhs, _) => {
-                vec![Diagnostic::error()
+                vec![d
                     .with_message("Assertion failed")
                     .with_labels(vec![
                         span_lhs
@@ -531,9 +1216,254 @@ impl ErrorDiagnostic for RuntimeError {
                     ])
                     .with_notes(vec![format!("{self:#}")])]
             }
-            _ => vec![Diagnostic::error()
+            RuntimeError::NativeFunctionFailed(Some(span), _, message) => {
+                vec![d
+                    .with_message("runtime error")
+                    .with_labels(vec![span
+                        .diagnostic_label(LabelStyle::Primary)
+                        .with_message(message)])
+                    .with_notes(vec![inner])]
+            }
+            RuntimeError::DynamicValueHasWrongType(span, value, type_) => {
+                vec![d
+                    .with_message("runtime error")
+                    .with_labels(vec![span
+                        .diagnostic_label(LabelStyle::Primary)
+                        .with_message(format!("'{value}' is not of type {type_}"))])
+                    .with_notes(vec![
+                        inner,
+                        "This value has the type 'Dynamic', so its type is only checked at runtime"
+                            .into(),
+                    ])]
+            }
+            RuntimeError::NativeFunctionReturnedWrongType(Some(span), _, value, _) => {
+                vec![d
+                    .with_message("runtime error")
+                    .with_labels(vec![span
+                        .diagnostic_label(LabelStyle::Primary)
+                        .with_message(format!("returned '{value}'"))])
+                    .with_notes(vec![inner])]
+            }
+            RuntimeError::UnitRegistryError(UnitRegistryError::RegistryError(
+                RegistryError::EntryExists(name, span, existing_span),
+            )) => vec![d
                 .with_message("runtime error")
+                .with_labels(entry_exists_labels(name, span, existing_span))
                 .with_notes(vec![inner])],
+            RuntimeError::FactorialOfNonInteger(_)
+            | RuntimeError::NonIntegerListIndex(_)
+            | RuntimeError::NonIntegerArgument(..)
+            | RuntimeError::NonIntegerSeriesBound(_) => {
+                vec![d.with_message("runtime error").with_notes(vec![
+                    inner,
+                    "The results of divisions, roots and similar operations are not integers, \
+                     even if their value is a whole number. Use 'round', 'floor', 'ceil' or \
+                     'trunc' to turn them into integers."
+                        .into(),
+                ])]
+            }
+            RuntimeError::InvalidRegex(pattern, Some(position), _)
+            | RuntimeError::InvalidUcum(pattern, position, _) => {
+                let prefix: String = pattern.chars().take(*position).collect();
+                vec![d.with_message("runtime error").with_notes(vec![
+                    inner,
+                    format!("{pattern}\n{}^", " ".repeat(prefix.width())),
+                ])]
+            }
+            _ => vec![d.with_message("runtime error").with_notes(vec![inner])],
+        }
+    }
+}
+
+/// Labels for a unit or dimension that is defined a second time
+fn entry_exists_labels(name: &str, span: &Span, existing_span: &Span) -> Vec<Label<usize>> {
+    vec![
+        existing_span
+            .diagnostic_label(LabelStyle::Secondary)
+            .with_message("already defined here"),
+        span.diagnostic_label(LabelStyle::Primary)
+            .with_message(format!("'{name}' is defined again here")),
+    ]
+}
+
+/// A source code range that a [`StructuredDiagnostic`] refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiagnosticSpan {
+    /// Name of the code source, e.g. `<input:1>` or the path of a module
+    pub file: String,
+    pub byte_start: usize,
+    pub byte_end: usize,
+    /// 1-based line and column of the first character
+    pub line_start: usize,
+    pub column_start: usize,
+    /// 1-based line and column of the position after the last character
+    pub line_end: usize,
+    pub column_end: usize,
+    pub label: String,
+}
+
+impl DiagnosticSpan {
+    fn new(
+        file_id: usize,
+        range: &Range<usize>,
+        label: &str,
+        files: &SimpleFiles<String, String>,
+    ) -> Option<Self> {
+        let start = files.location(file_id, range.start).ok()?;
+        let end = files.location(file_id, range.end).ok()?;
+
+        Some(DiagnosticSpan {
+            file: files.name(file_id).ok()?,
+            byte_start: range.start,
+            byte_end: range.end,
+            line_start: start.line_number,
+            column_start: start.column_number,
+            line_end: end.line_number,
+            column_end: end.column_number,
+            label: label.to_string(),
+        })
+    }
+}
+
+/// A self-contained version of a [`Suggestion`]. The label of the span is empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructuredSuggestion {
+    pub span: DiagnosticSpan,
+    pub replacement: String,
+    pub applicability: Applicability,
+}
+
+/// A self-contained version of a [`Diagnostic`] that does not refer to the
+/// source files anymore, meant for machine-readable output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructuredDiagnostic {
+    pub code: Option<ErrorCode>,
+    pub severity: Severity,
+    pub message: String,
+    /// The first primary label of the diagnostic, if any
+    pub primary_span: Option<DiagnosticSpan>,
+    /// All other labels, in order
+    pub secondary_spans: Vec<DiagnosticSpan>,
+    /// Additional explanations and hints
+    pub notes: Vec<String>,
+    /// Edits that would fix the problem
+    pub suggestions: Vec<StructuredSuggestion>,
+}
+
+impl StructuredDiagnostic {
+    pub fn new(diagnostic: &Diagnostic, files: &SimpleFiles<String, String>) -> Self {
+        let mut primary_span = None;
+        let mut secondary_spans = vec![];
+        for label in &diagnostic.labels {
+            let Some(span) =
+                DiagnosticSpan::new(label.file_id, &label.range, &label.message, files)
+            else {
+                continue;
+            };
+            if primary_span.is_none() && label.style == LabelStyle::Primary {
+                primary_span = Some(span);
+            } else {
+                secondary_spans.push(span);
+            }
         }
+
+        StructuredDiagnostic {
+            code: diagnostic.code.as_deref().and_then(ErrorCode::from_code),
+            severity: diagnostic.severity,
+            message: diagnostic.message.clone(),
+            primary_span,
+            secondary_spans,
+            notes: diagnostic.notes.clone(),
+            suggestions: diagnostic
+                .suggestions
+                .iter()
+                .filter_map(|suggestion| {
+                    Some(StructuredSuggestion {
+                        span: DiagnosticSpan::new(
+                            suggestion.file_id,
+                            &suggestion.range,
+                            "",
+                            files,
+                        )?,
+                        replacement: suggestion.replacement.clone(),
+                        applicability: suggestion.applicability,
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::span::SourceCodePositition;
+
+    #[test]
+    fn entry_exists_shows_both_definitions() {
+        let span = |byte| Span {
+            start: SourceCodePositition {
+                byte,
+                line: 1,
+                position: byte + 1,
+            },
+            end: SourceCodePositition {
+                byte: byte + 3,
+                line: 1,
+                position: byte + 4,
+            },
+            code_source_id: 0,
+        };
+
+        let error = TypeCheckError::RegistryError(RegistryError::EntryExists(
+            "Foo".into(),
+            span(20),
+            span(10),
+        ));
+        let diagnostics = error.diagnostics();
+        let labels = &diagnostics[0].labels;
+
+        assert_eq!(labels.len(), 2);
+        assert_eq!(labels[0].style, LabelStyle::Secondary);
+        assert_eq!(labels[0].range, 10..13);
+        assert_eq!(labels[0].message, "already defined here");
+        assert_eq!(labels[1].style, LabelStyle::Primary);
+        assert_eq!(labels[1].range, 20..23);
+        assert_eq!(labels[1].message, "'Foo' is defined again here");
+    }
+
+    #[test]
+    fn apply_suggestions_skips_overlapping_edits() {
+        let suggestion = |range: Range<usize>, replacement: &str| Suggestion {
+            file_id: 0,
+            range,
+            replacement: replacement.into(),
+            applicability: Applicability::MachineApplicable,
+        };
+
+        let code = "x > 0 && < 5";
+        assert_eq!(
+            apply_suggestions(
+                code,
+                &[
+                    suggestion(9..9, "x "),
+                    suggestion(4..5, "1"),
+                    suggestion(2..5, "≥ 1"),
+                ]
+            ),
+            "x ≥ 1 && x < 5"
+        );
+        assert_eq!(apply_suggestions(code, &[]), code);
+    }
+
+    #[test]
+    fn ages() {
+        let age = |seconds| format_age(std::time::Duration::from_secs(seconds));
+        assert_eq!(age(30), "less than a minute");
+        assert_eq!(age(60), "1 minute");
+        assert_eq!(age(59 * 60 + 59), "59 minutes");
+        assert_eq!(age(2 * 3600 + 1800), "2 hours");
+        assert_eq!(age(86400), "1 day");
+        assert_eq!(age(3 * 86400 + 7200), "3 days");
     }
 }
@@ -1,13 +1,14 @@
 use crate::arithmetic::{Exponent, Power};
 use crate::ast::{TypeExpression, TypeParameterBound};
-use crate::registry::{BaseRepresentation, Registry, Result};
+use crate::registry::{BaseRepresentation, Registry, RegistryEntry, Result};
 use crate::span::Span;
+use crate::symbol::Symbol;
 use crate::BaseRepresentationFactor;
 
 #[derive(Default, Clone)]
 pub struct DimensionRegistry {
     registry: Registry<()>,
-    pub introduced_type_parameters: Vec<(Span, String, Option<TypeParameterBound>)>,
+    pub introduced_type_parameters: Vec<(Span, Symbol, Option<TypeParameterBound>)>,
 }
 
 impl DimensionRegistry {
@@ -24,13 +25,11 @@ impl DimensionRegistry {
                     .any(|(_, n, _)| n == name)
                 {
                     Ok(BaseRepresentation::from_factor(BaseRepresentationFactor(
-                        name.clone(),
+                        *name,
                         Exponent::from_integer(1),
                     )))
                 } else {
-                    self.registry
-                        .get_base_representation_for_name(name)
-                        .map(|r| r.0)
+                    self.registry.get_base_representation(*name).map(|r| r.0)
                 }
             }
             TypeExpression::Multiply(_, lhs, rhs) => {
@@ -51,47 +50,67 @@ impl DimensionRegistry {
         }
     }
 
-    pub fn get_base_representation_for_name(&self, name: &str) -> Result<BaseRepresentation> {
-        self.registry
-            .get_base_representation_for_name(name)
-            .map(|t| t.0)
+    pub fn get_base_representation_for_name(&self, name: Symbol) -> Result<BaseRepresentation> {
+        self.registry.get_base_representation(name).map(|t| t.0)
     }
 
     pub fn get_derived_entry_names_for(
         &self,
         base_representation: &BaseRepresentation,
-    ) -> Vec<String> {
+    ) -> Vec<Symbol> {
         self.registry
             .get_derived_entry_names_for(base_representation)
     }
 
-    pub fn add_base_dimension(&mut self, name: &str) -> Result<BaseRepresentation> {
-        self.registry.add_base_entry(name, ())?;
+    pub fn add_base_dimension(&mut self, name: Symbol, span: Span) -> Result<BaseRepresentation> {
+        self.registry.add_base_entry(name, span, ())?;
         Ok(self
             .registry
-            .get_base_representation_for_name(name)
+            .get_base_representation(name)
             .map(|t| t.0)
             .unwrap())
     }
 
     pub fn add_derived_dimension(
         &mut self,
-        name: &str,
+        name: Symbol,
+        span: Span,
         expression: &TypeExpression,
     ) -> Result<BaseRepresentation> {
         let base_representation = self.get_base_representation(expression)?;
         self.registry
-            .add_derived_entry(name, base_representation, ())?;
+            .add_derived_entry(name, span, base_representation, ())?;
         Ok(self
             .registry
-            .get_base_representation_for_name(name)
+            .get_base_representation(name)
             .map(|t| t.0)
             .unwrap())
     }
 
-    pub fn contains(&self, dimension_name: &str) -> bool {
+    pub fn contains(&self, dimension_name: Symbol) -> bool {
         self.registry.contains(dimension_name)
     }
+
+    pub fn get_definition_span(&self, dimension_name: Symbol) -> Option<Span> {
+        self.registry.get_definition_span(dimension_name)
+    }
+
+    /// Removes a dimension that was only defined in a local scope.
+    pub(crate) fn remove_dimension(&mut self, dimension_name: Symbol) {
+        self.registry.remove(dimension_name)
+    }
+
+    pub(crate) fn take_dimension(&mut self, dimension_name: Symbol) -> Option<RegistryEntry<()>> {
+        self.registry.take(dimension_name)
+    }
+
+    pub(crate) fn restore_dimension(
+        &mut self,
+        dimension_name: Symbol,
+        entry: Option<RegistryEntry<()>>,
+    ) {
+        self.registry.restore(dimension_name, entry)
+    }
 }
 
 #[test]
@@ -101,21 +120,43 @@ fn basic() {
     use crate::registry::BaseRepresentationFactor;
 
     let mut registry = DimensionRegistry::default();
-    registry.add_base_dimension("Length").unwrap();
-    registry.add_base_dimension("Time").unwrap();
     registry
-        .add_derived_dimension("Velocity", &parse_dexpr("Length / Time"))
+        .add_base_dimension("Length".into(), Span::dummy())
+        .unwrap();
+    registry
+        .add_base_dimension("Time".into(), Span::dummy())
+        .unwrap();
+    registry
+        .add_derived_dimension(
+            "Velocity".into(),
+            Span::dummy(),
+            &parse_dexpr("Length / Time"),
+        )
         .unwrap();
     registry
-        .add_derived_dimension("Acceleration", &parse_dexpr("Length / Time^2"))
+        .add_derived_dimension(
+            "Acceleration".into(),
+            Span::dummy(),
+            &parse_dexpr("Length / Time^2"),
+        )
         .unwrap();
 
-    registry.add_base_dimension("Mass").unwrap();
     registry
-        .add_derived_dimension("Momentum", &parse_dexpr("Mass * Velocity"))
+        .add_base_dimension("Mass".into(), Span::dummy())
+        .unwrap();
+    registry
+        .add_derived_dimension(
+            "Momentum".into(),
+            Span::dummy(),
+            &parse_dexpr("Mass * Velocity"),
+        )
         .unwrap();
     registry
-        .add_derived_dimension("Energy", &parse_dexpr("Momentum^2 / Mass"))
+        .add_derived_dimension(
+            "Energy".into(),
+            Span::dummy(),
+            &parse_dexpr("Momentum^2 / Mass"),
+        )
         .unwrap();
 
     assert_eq!(
@@ -171,7 +212,11 @@ fn basic() {
     );
 
     registry
-        .add_derived_dimension("Momentum2", &parse_dexpr("Velocity * Mass"))
+        .add_derived_dimension(
+            "Momentum2".into(),
+            Span::dummy(),
+            &parse_dexpr("Velocity * Mass"),
+        )
         .unwrap();
     assert_eq!(
         registry.get_base_representation(&parse_dexpr("Momentum2")),
@@ -183,7 +228,11 @@ fn basic() {
     );
 
     registry
-        .add_derived_dimension("Energy2", &parse_dexpr("Mass * Velocity^2"))
+        .add_derived_dimension(
+            "Energy2".into(),
+            Span::dummy(),
+            &parse_dexpr("Mass * Velocity^2"),
+        )
         .unwrap();
     assert_eq!(
         registry.get_base_representation(&parse_dexpr("Energy2")),
@@ -195,7 +244,11 @@ fn basic() {
     );
 
     registry
-        .add_derived_dimension("Velocity2", &parse_dexpr("Momentum / Mass"))
+        .add_derived_dimension(
+            "Velocity2".into(),
+            Span::dummy(),
+            &parse_dexpr("Momentum / Mass"),
+        )
         .unwrap();
     assert_eq!(
         registry.get_base_representation(&parse_dexpr("Velocity2")),
@@ -209,6 +262,48 @@ fn basic() {
 #[test]
 fn fails_if_same_dimension_is_added_twice() {
     let mut registry = DimensionRegistry::default();
-    assert!(registry.add_base_dimension("Length").is_ok());
-    assert!(registry.add_base_dimension("Length").is_err());
+    assert!(registry
+        .add_base_dimension("Length".into(), Span::dummy())
+        .is_ok());
+    assert!(registry
+        .add_base_dimension("Length".into(), Span::dummy())
+        .is_err());
+}
+
+#[test]
+fn definition_spans() {
+    use crate::parser::parse_dexpr;
+    use crate::registry::RegistryError;
+    use crate::span::SourceCodePositition;
+
+    let span = |line| Span {
+        start: SourceCodePositition {
+            byte: 0,
+            line,
+            position: 1,
+        },
+        end: SourceCodePositition {
+            byte: 1,
+            line,
+            position: 2,
+        },
+        code_source_id: 0,
+    };
+
+    let mut registry = DimensionRegistry::default();
+    registry
+        .add_base_dimension("Length".into(), span(1))
+        .unwrap();
+    registry
+        .add_derived_dimension("Area".into(), span(2), &parse_dexpr("Length^2"))
+        .unwrap();
+
+    assert_eq!(registry.get_definition_span("Length".into()), Some(span(1)));
+    assert_eq!(registry.get_definition_span("Area".into()), Some(span(2)));
+    assert_eq!(registry.get_definition_span("Time".into()), None);
+
+    assert_eq!(
+        registry.add_derived_dimension("Area".into(), span(3), &parse_dexpr("Length")),
+        Err(RegistryError::EntryExists("Area".into(), span(3), span(2)))
+    );
 }
@@ -0,0 +1,197 @@
+//! Exact time spans between two instants.
+
+use crate::{
+    markup as m, number::Number, pretty_print::PrettyPrint, quantity::Quantity, unit::Unit,
+};
+
+const NANOSECONDS_PER_SECOND: i128 = 1_000_000_000;
+
+/// The result of subtracting two `DateTime`s. In contrast to a quantity of
+/// dimension `Time`, whose magnitude is a floating point number, a duration is
+/// stored as an integer number of seconds and nanoseconds. It can be added to
+/// (or subtracted from) a `DateTime` and other durations without any loss of
+/// precision. When it is combined with any other quantity, it is promoted to a
+/// quantity in the unit `second`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Duration {
+    seconds: i64,
+    /// Always has the same sign as `seconds`
+    nanoseconds: i32,
+    /// The unit `second`, used when the duration is promoted to a quantity
+    second: Unit,
+}
+
+impl Duration {
+    /// Returns `None` if the number of seconds does not fit into an `i64`.
+    pub(crate) fn from_nanoseconds(nanoseconds: i128, second: Unit) -> Option<Self> {
+        Some(Duration {
+            seconds: (nanoseconds / NANOSECONDS_PER_SECOND).try_into().ok()?,
+            nanoseconds: (nanoseconds % NANOSECONDS_PER_SECOND) as i32,
+            second,
+        })
+    }
+
+    /// The duration between two instants in time, positive if `end` is after
+    /// `start`.
+    pub(crate) fn between(start: &jiff::Zoned, end: &jiff::Zoned, second: Unit) -> Option<Self> {
+        Self::from_nanoseconds(
+            end.timestamp().as_nanosecond() - start.timestamp().as_nanosecond(),
+            second,
+        )
+    }
+
+    pub fn seconds(&self) -> i64 {
+        self.seconds
+    }
+
+    pub fn subsec_nanoseconds(&self) -> i32 {
+        self.nanoseconds
+    }
+
+    pub fn as_nanoseconds(&self) -> i128 {
+        self.seconds as i128 * NANOSECONDS_PER_SECOND + self.nanoseconds as i128
+    }
+
+    pub(crate) fn checked_add(&self, other: &Duration) -> Option<Self> {
+        Self::from_nanoseconds(
+            self.as_nanoseconds().checked_add(other.as_nanoseconds())?,
+            self.second.clone(),
+        )
+    }
+
+    pub(crate) fn checked_sub(&self, other: &Duration) -> Option<Self> {
+        Self::from_nanoseconds(
+            self.as_nanoseconds().checked_sub(other.as_nanoseconds())?,
+            self.second.clone(),
+        )
+    }
+
+    /// The given instant in time, shifted by this duration. Returns `None` if the
+    /// result is out of range.
+    pub(crate) fn add_to(&self, dt: &jiff::Zoned) -> Option<jiff::Zoned> {
+        let timestamp = jiff::Timestamp::from_nanosecond(
+            dt.timestamp().as_nanosecond() + self.as_nanoseconds(),
+        )
+        .ok()?;
+        Some(timestamp.to_zoned(dt.time_zone().clone()))
+    }
+
+    /// Converts the duration to a quantity of dimension `Time`. This is where
+    /// precision can be lost.
+    pub fn to_quantity(&self) -> Quantity {
+        Quantity::new(
+            Number::from_f64(self.seconds as f64 + self.nanoseconds as f64 * 1e-9),
+            self.second.clone(),
+        )
+    }
+}
+
+impl std::ops::Neg for Duration {
+    type Output = Duration;
+
+    fn neg(self) -> Self::Output {
+        Duration {
+            seconds: -self.seconds,
+            nanoseconds: -self.nanoseconds,
+            second: self.second,
+        }
+    }
+}
+
+impl PrettyPrint for Duration {
+    /// Uses days, hours, minutes and seconds, e.g. `3 days 4 h 5 s`.
+    fn pretty_print(&self) -> m::Markup {
+        let nanoseconds = self.as_nanoseconds();
+        let total_seconds = nanoseconds.unsigned_abs() / NANOSECONDS_PER_SECOND as u128;
+        let subsec_nanoseconds = nanoseconds.unsigned_abs() % NANOSECONDS_PER_SECOND as u128;
+
+        let days = total_seconds / 86_400;
+        let hours = total_seconds % 86_400 / 3_600;
+        let minutes = total_seconds % 3_600 / 60;
+        let seconds = total_seconds % 60;
+
+        let mut parts = vec![];
+        if days > 0 {
+            parts.push((days.to_string(), if days == 1 { "day" } else { "days" }));
+        }
+        if hours > 0 {
+            parts.push((hours.to_string(), "h"));
+        }
+        if minutes > 0 {
+            parts.push((minutes.to_string(), "min"));
+        }
+        if seconds > 0 || subsec_nanoseconds > 0 || parts.is_empty() {
+            let seconds = if subsec_nanoseconds == 0 {
+                seconds.to_string()
+            } else {
+                format!("{seconds}.{subsec_nanoseconds:09}")
+                    .trim_end_matches('0')
+                    .to_string()
+            };
+            parts.push((seconds, "s"));
+        }
+
+        let sign = if nanoseconds < 0 {
+            m::operator("-")
+        } else {
+            m::empty()
+        };
+
+        sign + itertools::Itertools::intersperse(
+            parts
+                .into_iter()
+                .map(|(value, unit)| m::value(value) + m::space() + m::unit(unit)),
+            m::space(),
+        )
+        .sum()
+    }
+}
+
+impl std::fmt::Display for Duration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use crate::markup::{Formatter, PlainTextFormatter};
+
+        write!(
+            f,
+            "{}",
+            PlainTextFormatter {}.format(&self.pretty_print(), false)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn duration(nanoseconds: i128) -> Duration {
+        Duration::from_nanoseconds(nanoseconds, Unit::second()).unwrap()
+    }
+
+    #[test]
+    fn display() {
+        let second = NANOSECONDS_PER_SECOND;
+
+        assert_eq!(duration(0).to_string(), "0 s");
+        assert_eq!(duration(5 * second).to_string(), "5 s");
+        assert_eq!(duration(90 * second).to_string(), "1 min 30 s");
+        assert_eq!(duration(86_400 * second).to_string(), "1 day");
+        assert_eq!(
+            duration((3 * 86_400 + 4 * 3_600 + 5) * second).to_string(),
+            "3 days 4 h 5 s"
+        );
+        assert_eq!(duration(second + 1).to_string(), "1.000000001 s");
+        assert_eq!(
+            duration(-(7_200 * second + second / 2)).to_string(),
+            "-2 h 0.5 s"
+        );
+    }
+
+    #[test]
+    fn sign_of_parts() {
+        let d = duration(-1_500_000_000);
+        assert_eq!(d.seconds(), -1);
+        assert_eq!(d.subsec_nanoseconds(), -500_000_000);
+        assert_eq!(d.as_nanoseconds(), -1_500_000_000);
+        assert_eq!((-d).as_nanoseconds(), 1_500_000_000);
+    }
+}
@@ -0,0 +1,366 @@
+//! Stable error codes for all errors that Numbat can report.
+//!
+//! Every error gets a code of the form `E0xyy`, where `x` is the category (see
+//! below) and `yy` is a running number within that category. Codes are part of
+//! the public interface (they show up in diagnostics and in the JSON output of
+//! the CLI), so they must never be renumbered or reused. New errors get the next
+//! free number in their category. Each error type maps its variants to codes via
+//! an `error_code` method. The range of parse errors is used up, new parse
+//! errors get numbers in the category `E09yy`.
+
+macro_rules! error_codes {
+    ($($code:ident => $description:literal,)*) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub enum ErrorCode {
+            $(
+                #[doc = $description]
+                $code,
+            )*
+        }
+
+        impl ErrorCode {
+            /// All error codes, in ascending order
+            pub const ALL: &'static [ErrorCode] = &[$(ErrorCode::$code,)*];
+
+            pub fn as_str(self) -> &'static str {
+                match self {
+                    $(ErrorCode::$code => stringify!($code),)*
+                }
+            }
+
+            /// A short, generic description of the error
+            pub fn description(self) -> &'static str {
+                match self {
+                    $(ErrorCode::$code => $description,)*
+                }
+            }
+
+            pub fn from_code(code: &str) -> Option<ErrorCode> {
+                match code {
+                    $(stringify!($code) => Some(ErrorCode::$code),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+error_codes! {
+    // Tokenizer errors
+    E0101 => "Unexpected character",
+    E0102 => "Unexpected character in negative exponent",
+    E0103 => "Unexpected character in number literal",
+    E0104 => "Unexpected character in identifier",
+    E0105 => "Expected digit",
+    E0106 => "Expected digit in a number literal with a non-decimal base",
+    E0107 => "Unterminated string",
+    E0108 => "Unterminated string interpolation",
+    E0109 => "Unexpected '{' inside string interpolation",
+    E0110 => "Ambiguous comma in number literal",
+    E0111 => "Consecutive underscores in number literal",
+    E0112 => "Number literal ends with an underscore",
+    E0113 => "Underscore next to the decimal point",
+    E0114 => "Underscore next to the exponent marker",
+    E0115 => "Exponent starts with an underscore",
+    E0116 => "Expected digits in exponent",
+    E0117 => "Expected binary exponent in hexadecimal floating point literal",
+
+    // Parse errors
+    E0201 => "Expected one of: number, identifier, parenthesized expression, struct instantiation, list",
+    E0202 => "Missing closing parenthesis ')'",
+    E0203 => "Trailing characters after the end of a statement",
+    E0204 => "Trailing '=' sign",
+    E0205 => "Expected identifier after 'let' keyword",
+    E0206 => "Expected '=' or ':' after identifier (and type annotation) in 'let' assignment",
+    E0207 => "Expected identifier after 'fn' keyword",
+    E0208 => "Expected identifier",
+    E0209 => "Expected identifier or function call after postfix apply (`|>`)",
+    E0210 => "Expected dimension identifier, '1', or opening parenthesis",
+    E0211 => "Expected ',' or '>' in type parameter list",
+    E0212 => "Expected identifier (type parameter name)",
+    E0213 => "Expected opening parenthesis '(' in function definition",
+    E0214 => "Expected ',' or ')' in function parameter list",
+    E0215 => "Expected ',' or '}' in struct field list",
+    E0216 => "Expected parameter name in function definition",
+    E0217 => "Expected field name in struct",
+    E0218 => "Expected identifier (dimension name)",
+    E0219 => "Expected identifier (unit name)",
+    E0220 => "Expected '=' or ':' after identifier in unit definition",
+    E0221 => "Expected ':' after a field name",
+    E0222 => "Expected '=' after a field name in struct update",
+    E0223 => "Expected 'with' after the base expression in struct update",
+    E0224 => "Only functions can be called",
+    E0225 => "Division by zero in dimension exponent",
+    E0226 => "Expected opening parenthesis '(' after procedure name",
+    E0227 => "Expected opening parenthesis '(' after 'trace' (no longer reported)",
+    E0228 => "Procedures can not be used inside an expression",
+    E0229 => "Expected decorator name",
+    E0230 => "Unknown decorator name",
+    E0231 => "Expected module path after 'use'",
+    E0232 => "Expected module name after double colon (::)",
+    E0233 => "Overflow in number literal",
+    E0234 => "Expected dimension exponent",
+    E0235 => "Double-underscore type names are reserved for internal use",
+    E0236 => "Only integer numbers (< 2^128) are allowed in dimension exponents",
+    E0237 => "Decorators can only be used on unit, let or function definitions",
+    E0238 => "Decorators on let definitions cannot have prefix information",
+    E0239 => "Expected opening parenthesis after decorator",
+    E0240 => "Unknown alias annotation",
+    E0241 => "Aliases cannot be used on functions",
+    E0242 => "Positional arguments can not follow named arguments",
+    E0243 => "Procedures can not be called with named arguments",
+    E0244 => "Parameters without a default value can not follow parameters with a default value",
+    E0245 => "Expected one of the operators '+', '-', '*' or '/'",
+    E0246 => "The @operator decorator can only be used on function definitions",
+    E0247 => "Numerical overflow in dimension exponent",
+    E0248 => "Expected 'then' in if-then-else condition",
+    E0249 => "Expected 'else' in if-then-else condition",
+    E0250 => "Unterminated string",
+    E0251 => "Expected a string",
+    E0252 => "Expected token in function type",
+    E0253 => "Expected token in list type",
+    E0254 => "Expected '{' after struct name",
+    E0255 => "Expected ',' or ']' in list expression",
+    E0256 => "Unknown bound in type parameter definition",
+    E0257 => "Expected bound in type parameter definition",
+    E0258 => "Empty string interpolation",
+    E0259 => "Expected local variable, unit or dimension definition after where/and",
+    E0260 => "Expected identifier (unit system name)",
+    E0261 => "Expected '=' after unit system name",
+    E0262 => "Expected '[' after '=' in unit system definition",
+    E0263 => "Expected unit name in unit system definition",
+    E0264 => "Expected native function signature",
+    E0265 => "Expected a currency code and a number of decimal places",
+    E0266 => "The @currency decorator can only be used on unit definitions",
+    E0267 => "Expected identifier, '{' or '[' in destructuring pattern",
+    E0268 => "Decorators can not be used on destructuring definitions",
+    E0269 => "Expected procedure call in conditional statement",
+    // E0270 was used for chained comparisons, which are allowed now
+    E0271 => "Missing left hand side of comparison",
+    E0272 => "Expected a dimension name in @dimension decorator",
+    E0273 => "The @dimension decorator can only be used on base units without a dimension",
+    E0274 => "Expected loop variable after 'for'",
+    E0275 => "Expected 'in' after loop variable",
+    E0276 => "Expected '{' before loop body",
+    E0277 => "Expected '}' after loop body",
+    E0278 => "Expected 'with' after the condition of a while loop",
+    E0279 => "Expected accumulator definition",
+    E0280 => "Expected '{' after 'try' or 'catch'",
+    E0281 => "Expected '}' after the body of 'try' or 'catch'",
+    E0282 => "Expected 'catch' after 'try { … }'",
+    E0283 => "Variadic parameter is not the last parameter",
+    E0284 => "Variadic function with default parameter values",
+    E0285 => "Expected a number in the @uncertainty decorator",
+    E0286 => "The @uncertainty decorator can only be used on variable definitions",
+    E0287 => "Invalid unit function definition",
+    E0288 => "Expected the definition of the inverse of a unit function",
+    E0289 => "Expression too deeply nested",
+    E0290 => "Expected opening parenthesis '(' after 'partial'",
+    E0291 => "Expected a function as the first argument of 'partial'",
+    E0292 => "Expected a function definition inside of an 'impl' block",
+    E0293 => "Method without a 'self' parameter",
+    E0294 => "Expected opening parenthesis '(' after 'autodiff'",
+    E0295 => "The @isolated decorator can only be used on unit definitions",
+    E0296 => "Expected the name of an item in the list of imported items",
+    E0297 => "Missing closing brace '}' after the list of imported items",
+    E0298 => "Expected opening parenthesis '(' after a dimension predicate",
+    E0299 => "Expected a dimension as the second argument of 'has_dimension'",
+
+    // Module resolution errors
+    E0301 => "Unknown module",
+    E0302 => "Module can not be loaded in sandbox mode",
+    E0303 => "Unknown item in import",
+
+    // Name resolution errors
+    E0401 => "Identifier is already in use",
+    E0402 => "Reserved identifier",
+    E0403 => "Ambiguous unit",
+
+    // Type check errors
+    E0501 => "Unknown identifier",
+    E0502 => "Incompatible dimensions",
+    E0503 => "Exponents need to be dimensionless",
+    E0504 => "Argument of factorial needs to be dimensionless",
+    E0505 => "Unsupported expression in const-evaluation of exponent",
+    E0506 => "Division by zero in const. eval. expression",
+    E0507 => "Incompatible alternative expressions have been provided for a dimension",
+    E0508 => "Function or procedure called with the wrong number of arguments",
+    E0509 => "Type parameter name clashes with an existing dimension identifier",
+    E0510 => "Foreign function definition (without body) needs parameter and return type annotations",
+    E0511 => "Parameter of a foreign function (without body) can not have a default value",
+    E0512 => "Unknown foreign function (without body)",
+    E0513 => "Out-of bounds or non-rational exponent value",
+    E0514 => "Numerical overflow in const-eval expression",
+    E0515 => "Expected dimension type",
+    E0516 => "Expected boolean value",
+    E0517 => "Incompatible types in condition",
+    E0518 => "Argument types in assert call must be boolean",
+    E0519 => "Argument types in assert_eq calls must match",
+    E0520 => "Incompatible types in type annotation",
+    E0521 => "Incompatible types in comparison operator",
+    E0522 => "Incompatible types in operator",
+    E0523 => "No overload of operator for the given types",
+    E0524 => "Ambiguous overloads of operator",
+    E0525 => "Operator overload needs exactly two parameters, at least one of them with a struct type annotation",
+    E0526 => "Incompatible types in function call",
+    E0527 => "Incompatible types for struct field",
+    E0528 => "Incompatible base in struct update",
+    E0529 => "Missing a definition for dimension",
+    E0530 => "Function references can not point to generic functions",
+    E0531 => "Only functions and function references can be called",
+    E0532 => "Base units can not be dimensionless",
+    E0533 => "Unknown struct",
+    E0534 => "Unknown field in struct instantiation",
+    E0535 => "Duplicate field in struct definition",
+    E0536 => "Duplicate field in struct instantiation",
+    E0537 => "Function does not have a parameter with the given name",
+    E0538 => "Argument for parameter is specified more than once",
+    E0539 => "Missing argument for parameter",
+    E0540 => "Named arguments can only be used when calling a function by its name",
+    E0541 => "Can not access field of non struct type",
+    E0542 => "Unknown field in field access",
+    E0543 => "Missing fields in struct instantiation",
+    E0544 => "Incompatible types in list",
+    E0545 => "Missing dimension bound for type parameter",
+    E0546 => "Missing 'Ord' bound for type parameter",
+    E0547 => "Values of this type can not be ordered",
+    E0548 => "Type for exponentiation operation can not be inferred",
+    E0549 => "Derived unit definitions may not contain generic types",
+    E0550 => "Typed hole",
+    E0551 => "Multiple typed holes in statement",
+    E0552 => "Only units can be part of a unit system",
+    E0553 => "Can not destructure a value of non struct type with a struct pattern",
+    E0554 => "Missing fields in destructuring pattern",
+    E0555 => "Can not destructure a value of non list type with a list pattern",
+    E0556 => "Identifier is bound more than once in the same pattern",
+    E0557 => "Implicitly created dimension of a base unit already exists",
+    E0558 => "Unit that is local to a function is used outside of it",
+    E0559 => "Loop over a value that is not a list",
+    E0560 => "Incompatible types for the accumulator of a loop",
+    E0561 => "Incompatible types in 'try' and 'catch' branches",
+    E0562 => "The 'Dynamic' type is not allowed",
+    E0563 => "Unit function without inverse used as a conversion target",
+    E0564 => "Inverse of a unit function has an incompatible type",
+    E0565 => "Identifier needs a feature that is disabled in this build",
+    E0566 => "Partial application of a function whose type is not known",
+    E0567 => "Too many arguments in partial application",
+    E0568 => "Unknown method",
+    E0569 => "Function can not be differentiated",
+    E0570 => "Invalid argument for 'autodiff'",
+    E0571 => "Incompatible types in conversion",
+    E0572 => "Dimension predicate for an argument with an unknown dimension",
+    E0573 => "Function with dimension predicates needs type annotations",
+    E0574 => "Function with dimension predicates is not called directly",
+    E0575 => "Function with dimension predicates can not be specialized",
+    E0576 => "Argument is not a time series",
+    E0577 => "Formula can not be solved for a quantity",
+
+    // Type inference errors
+    E0601 => "Could not solve type constraints",
+    E0602 => "Used non-dimension type in a dimension expression",
+
+    // Registry errors
+    E0701 => "Entry exists already",
+    E0702 => "Unknown entry",
+    E0703 => "Unit system does not contain a unit for a dimension",
+    E0704 => "Unknown unit system",
+    E0705 => "Too many units in a unit system",
+
+    // Runtime errors
+    E0801 => "Division by zero",
+    E0802 => "Expected factorial argument to be a non-negative integer",
+    E0803 => "Factorial argument is not an integer",
+    E0804 => "Assertion failed",
+    E0805 => "Assertion failed because two values are not the same",
+    E0806 => "Assertion failed because two quantities differ by more than the given tolerance",
+    E0807 => "Could not load exchange rates from European Central Bank",
+    E0808 => "User error",
+    E0809 => "Unrecognized datetime format",
+    E0810 => "Unknown timezone",
+    E0811 => "Exceeded maximum size for time durations",
+    E0812 => "DateTime out of range",
+    E0813 => "Error in datetime format",
+    E0814 => "Invalid format specifiers",
+    E0815 => "Incorrect type for format specifiers",
+    E0816 => "Chemical element not found",
+    E0817 => "Empty list",
+    E0818 => "Conversion between incompatible units",
+    E0819 => "Non-rational exponent",
+    E0820 => "Native function failed",
+    E0821 => "Native function returned a value of the wrong type",
+    E0822 => "Invalid variable name",
+    E0823 => "Value can not be assigned to a variable",
+    E0824 => "Permission denied in sandbox mode",
+    E0825 => "Not a currency unit",
+    E0826 => "List length does not match destructuring pattern",
+    E0827 => "Value can not be converted to JSON",
+    E0828 => "Invalid JSON",
+    E0829 => "JSON value does not match the expected type",
+    E0830 => "Result type of a function call is not known",
+    E0831 => "Script argument is not available",
+    E0832 => "Environment variable is not set",
+    E0833 => "String can not be parsed as a quantity",
+    E0834 => "Could not write to a file",
+    E0835 => "Row does not fit into a CSV file",
+    E0836 => "Invalid number of iterations",
+    E0837 => "While loop exceeded the iteration limit",
+    E0838 => "Lower bound of clamp is greater than the upper bound",
+    E0839 => "Number can not be spelled out in words",
+    E0840 => "Value of type 'Dynamic' does not have the expected type",
+    E0841 => "Bounds of a series are not integers",
+    E0842 => "Matrix has an invalid shape for the operation",
+    E0843 => "Matrix is singular",
+    E0844 => "Implicit unit conversion is forbidden",
+    E0845 => "Unknown setting for implicit conversions",
+    E0846 => "List index is not an integer",
+    E0847 => "Argument is not an integer",
+    E0848 => "Argument is negative",
+    E0849 => "Integer result is too large",
+    E0850 => "Implicit conversion between isolated units",
+    E0851 => "Invalid regular expression",
+    E0852 => "Unknown capture group",
+    E0853 => "Capture group did not match",
+    E0854 => "Invalid UCUM unit",
+    E0855 => "Unit can not be written in UCUM",
+    E0856 => "Time series is not sorted",
+    E0857 => "Invalid resampling interval",
+    E0858 => "UCUM unit has the wrong dimension",
+    E0859 => "Result index out of range",
+
+    // Parse errors, continued
+    E0901 => "Expected the equation of a formula",
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_are_unique_and_sorted() {
+        let codes: Vec<_> = ErrorCode::ALL.iter().map(|c| c.as_str()).collect();
+        for pair in codes.windows(2) {
+            assert!(
+                pair[0] < pair[1],
+                "{} and {} are not in order",
+                pair[0],
+                pair[1]
+            );
+        }
+    }
+
+    #[test]
+    fn codes_round_trip() {
+        for code in ErrorCode::ALL {
+            assert_eq!(code.as_str().len(), 5);
+            assert_eq!(ErrorCode::from_code(code.as_str()), Some(*code));
+            assert!(!code.description().is_empty());
+        }
+        assert_eq!(ErrorCode::from_code("E9999"), None);
+    }
+}
@@ -0,0 +1,118 @@
+//! Optional parts of Numbat that can be disabled with Cargo features, e.g. for a
+//! smaller WebAssembly build. The builtin modules that need a disabled feature are
+//! not loaded, and their identifiers lead to a [`TypeCheckError::FeatureNotAvailable`]
+//! instead of an unknown identifier.
+//!
+//! [`TypeCheckError::FeatureNotAvailable`]: crate::typechecker::TypeCheckError::FeatureNotAvailable
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::ast::Statement;
+use crate::decorator;
+use crate::module_importer::{BuiltinModuleImporter, ModuleImporter};
+use crate::parser::{parse_with_options, ParserOptions};
+use crate::resolver::ModulePath;
+
+/// Builtin modules that need an optional feature, and the name of the feature
+const MODULES: &[(&str, &str)] = &[
+    ("datetime::functions", "datetime"),
+    ("datetime::human", "datetime"),
+    ("datetime::benchmark", "datetime"),
+    ("units::currencies", "currency"),
+];
+
+/// Optional features and whether they are enabled in this build
+const FEATURES: &[(&str, bool)] = &[
+    ("datetime", cfg!(feature = "datetime")),
+    ("currency", cfg!(feature = "currency")),
+];
+
+fn is_enabled(feature: &str) -> bool {
+    !FEATURES
+        .iter()
+        .any(|&(name, enabled)| name == feature && !enabled)
+}
+
+/// The disabled feature that is needed by the given module, if any
+pub(crate) fn missing_feature_for_module(module_path: &ModulePath) -> Option<&'static str> {
+    let path = module_path.to_string();
+    MODULES
+        .iter()
+        .find(|(module, feature)| *module == path && !is_enabled(feature))
+        .map(|(_, feature)| *feature)
+}
+
+/// The names of all functions, variables and units that are defined in a module
+fn defined_names(code: &str) -> Vec<String> {
+    let Ok(statements) = parse_with_options(code, 0, ParserOptions::default(), &mut vec![]) else {
+        return vec![];
+    };
+
+    statements
+        .iter()
+        .flat_map(|statement| match statement {
+            Statement::DefineFunction { function_name, .. } => vec![function_name.to_string()],
+            Statement::DefineVariable(variable) => variable
+                .pattern
+                .identifiers()
+                .into_iter()
+                .map(|(_, name)| name.to_string())
+                .collect(),
+            Statement::DefineBaseUnit(_, name, _, decorators)
+            | Statement::DefineDerivedUnit {
+                identifier: name,
+                decorators,
+                ..
+            } => decorator::name_and_aliases(*name, decorators)
+                .map(|(alias, _)| alias.to_string())
+                .collect(),
+            _ => vec![],
+        })
+        .collect()
+}
+
+/// The disabled feature that is needed for the given identifier, if it is defined
+/// in one of the builtin modules that have not been loaded because of that
+pub(crate) fn missing_feature_for_identifier(name: &str) -> Option<&'static str> {
+    static IDENTIFIERS: OnceLock<HashMap<String, &'static str>> = OnceLock::new();
+
+    if MODULES.iter().all(|(_, feature)| is_enabled(feature)) {
+        return None;
+    }
+
+    IDENTIFIERS
+        .get_or_init(|| {
+            let importer = BuiltinModuleImporter::default();
+            let mut identifiers = HashMap::new();
+            for (module, feature) in MODULES.iter().filter(|(_, f)| !is_enabled(f)) {
+                let path = ModulePath(module.split("::").map(String::from).collect());
+                if let Some((code, _)) = importer.import(&path) {
+                    for name in defined_names(&code) {
+                        identifiers.insert(name, *feature);
+                    }
+                }
+            }
+            identifiers
+        })
+        .get(name)
+        .copied()
+}
+
+/// Identifiers of the currency units that are defined in `units::currencies`,
+/// which is loaded on demand (see [`crate::Context::load_currency_module_on_demand`])
+#[cfg(feature = "currency")]
+pub(crate) fn is_currency_identifier(name: &str) -> bool {
+    static IDENTIFIERS: OnceLock<Vec<String>> = OnceLock::new();
+
+    IDENTIFIERS
+        .get_or_init(|| {
+            let path = ModulePath(vec!["units".into(), "currencies".into()]);
+            BuiltinModuleImporter::default()
+                .import(&path)
+                .map(|(code, _)| defined_names(&code))
+                .unwrap_or_default()
+        })
+        .iter()
+        .any(|n| n == name)
+}
@@ -0,0 +1,60 @@
+//! Measuring how long it takes to call a function.
+
+use std::collections::VecDeque;
+
+use super::macros::*;
+use super::{Args, Result};
+use crate::quantity::Quantity;
+use crate::sequence::FunctionCaller;
+use crate::value::Value;
+use crate::RuntimeError;
+
+/// The maximum number of empty measurements that are used to determine the
+/// overhead of reading the clock.
+const MAX_CALIBRATION_RUNS: usize = 100;
+
+/// The maximum number of measurements that space is reserved for up front. The
+/// number of iterations is chosen by the user, so the list of times grows
+/// beyond this as the calls are made.
+const MAX_PREALLOCATED_TIMES: usize = 1 << 16;
+
+/// Calls a function without arguments the given number of times and returns the
+/// time of each call in seconds, using the clock of the VM. The overhead of
+/// reading the clock (the fastest of a few empty measurements) is subtracted.
+pub fn _benchmark_seconds(caller: &mut dyn FunctionCaller, mut args: Args) -> Result<Value> {
+    let function = arg!(args);
+    let iterations = scalar_arg!(args).to_f64();
+
+    if iterations < 1.0 || iterations.fract() != 0.0 || iterations > u32::MAX as f64 {
+        return Err(RuntimeError::InvalidNumberOfIterations(
+            Quantity::from_scalar(iterations).to_string(),
+        ));
+    }
+    let iterations = iterations as usize;
+
+    let elapsed_seconds = || -> Result<f64> {
+        let start = caller.current_time()?;
+        let end = caller.current_time()?;
+        Ok(seconds_between(&start, &end))
+    };
+    let mut overhead = f64::INFINITY;
+    for _ in 0..iterations.min(MAX_CALIBRATION_RUNS) {
+        overhead = overhead.min(elapsed_seconds()?);
+    }
+
+    let mut times = VecDeque::with_capacity(iterations.min(MAX_PREALLOCATED_TIMES));
+    for _ in 0..iterations {
+        let start = caller.current_time()?;
+        caller.call(&function, vec![])?;
+        let end = caller.current_time()?;
+
+        let seconds = seconds_between(&start, &end) - overhead;
+        times.push_back(Value::Quantity(Quantity::from_scalar(seconds.max(0.0))));
+    }
+
+    Ok(Value::from(times))
+}
+
+fn seconds_between(start: &jiff::Zoned, end: &jiff::Zoned) -> f64 {
+    (end.timestamp().as_nanosecond() - start.timestamp().as_nanosecond()) as f64 / 1e9
+}
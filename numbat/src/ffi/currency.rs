@@ -1,14 +1,169 @@
+#[cfg(feature = "currency")]
+use jiff::Zoned;
+
 use super::macros::*;
 use super::Args;
 use super::Result;
-use crate::currency::ExchangeRatesCache;
+use super::UnitLookup;
+#[cfg(feature = "currency")]
+use crate::currency::{ExchangeRatesCache, LoadedExchangeRates};
+#[cfg(feature = "currency")]
+use crate::duration::Duration;
+use crate::interpreter::RuntimeError;
+#[cfg(feature = "currency")]
+use crate::interpreter::RuntimeWarning;
+use crate::prefix::Prefix;
 use crate::quantity::Quantity;
+#[cfg(feature = "currency")]
+use crate::sandbox::Capability;
+#[cfg(feature = "currency")]
+use crate::sequence::FunctionCaller;
 use crate::value::Value;
 
-pub fn exchange_rate(mut args: Args) -> Result<Value> {
-    let rate = string_arg!(args);
+/// The exchange rate of the given currency, relative to the Euro. Warns if the
+/// rates are cached ones.
+#[cfg(feature = "currency")]
+pub fn exchange_rate(caller: &mut dyn FunctionCaller, mut args: Args) -> Result<Value> {
+    let currency = string_arg!(args);
+
+    let exchange_rates = ExchangeRatesCache::fetch();
+
+    rate_of(caller, exchange_rates.as_ref(), &currency)
+}
+
+/// Like [`exchange_rate`], but fails instead of fetching the exchange rates from
+/// the network.
+#[cfg(feature = "currency")]
+pub fn exchange_rate_offline(caller: &mut dyn FunctionCaller, mut args: Args) -> Result<Value> {
+    let currency = string_arg!(args);
+
+    let exchange_rates = ExchangeRatesCache::fetch_without_network()
+        .ok_or(RuntimeError::PermissionDenied(Capability::Network))?;
+
+    rate_of(caller, exchange_rates.as_ref(), &currency)
+}
+
+#[cfg(feature = "currency")]
+fn rate_of(
+    caller: &mut dyn FunctionCaller,
+    exchange_rates: Option<&LoadedExchangeRates>,
+    currency: &str,
+) -> Result<Value> {
+    if let Some(exchange_rates) = exchange_rates.filter(|r| r.cached) {
+        caller.warn(RuntimeWarning::CachedExchangeRates(exchange_rates.age()));
+    }
+
+    return_scalar!(exchange_rates
+        .and_then(|r| r.rates.get(currency))
+        .cloned()
+        .unwrap_or(f64::NAN))
+}
+
+/// The time at which the exchange rates have been fetched
+#[cfg(feature = "currency")]
+pub fn exchange_rate_timestamp(now: Zoned, _args: Args) -> Result<Value> {
+    let exchange_rates =
+        ExchangeRatesCache::current().ok_or(RuntimeError::CouldNotLoadExchangeRates)?;
+
+    Ok(Value::DateTime(
+        exchange_rates.fetched_at.to_zoned(now.time_zone().clone()),
+    ))
+}
+
+/// The time since the exchange rates have been fetched, according to the clock
+/// of the VM
+#[cfg(feature = "currency")]
+pub fn exchange_rate_age(caller: &mut dyn FunctionCaller, _args: Args) -> Result<Value> {
+    let exchange_rates =
+        ExchangeRatesCache::current().ok_or(RuntimeError::CouldNotLoadExchangeRates)?;
+    let now = caller.current_time()?;
+    let second = caller
+        .unit_lookup()
+        .and_then(|units| units.lookup_unit("second"))
+        .expect("The unit 'second' is defined along with the currencies");
+
+    let fetched_at = exchange_rates.fetched_at.to_zoned(now.time_zone().clone());
+    Duration::between(&fetched_at, &now, second)
+        .map(Value::Duration)
+        .ok_or(RuntimeError::DurationOutOfRange)
+}
+
+/// Formats an amount of money with the usual number of decimal places of its
+/// currency and its ISO 4217 code, e.g. `1,234.50 USD`.
+pub fn format_currency(units: &dyn UnitLookup, mut args: Args) -> Result<Value> {
+    let currency_of = |amount: &Quantity| {
+        let factors: Vec<_> = amount.unit().iter().collect();
+        match factors[..] {
+            [factor] if factor.exponent == 1.into() && factor.prefix == Prefix::none() => units
+                .unit_registry()
+                .inner
+                .get_base_representation(factor.unit_id.name)
+                .ok()
+                .and_then(|(_, metadata)| metadata.currency),
+            _ => None,
+        }
+    };
+
+    // Only simplify if necessary, since converting between currencies is lossy
+    let mut amount = quantity_arg!(args);
+    if currency_of(&amount).is_none() {
+        amount = amount.full_simplify();
+    }
+    let Some((code, decimal_places)) = currency_of(&amount) else {
+        return Err(RuntimeError::NotACurrency(amount.unit().to_string()));
+    };
+
+    return_string!(format!(
+        "{} {code}",
+        format_amount(amount.unsafe_value().to_f64(), decimal_places)
+    ))
+}
+
+/// Rounds to the given number of decimal places and groups the digits of the
+/// integer part by commas.
+fn format_amount(value: f64, decimal_places: u32) -> String {
+    if !value.is_finite() {
+        return value.to_string();
+    }
+
+    // Round half-way cases away from zero, like `round`
+    let factor = 10f64.powi(decimal_places as i32);
+    let rounded = (value.abs() * factor).round() / factor;
+
+    let formatted = format!("{:.*}", decimal_places as usize, rounded);
+    let (integer_part, fractional_part) = match formatted.split_once('.') {
+        Some((integer_part, fractional_part)) => (integer_part, Some(fractional_part)),
+        None => (formatted.as_str(), None),
+    };
+
+    let mut result = String::new();
+    if value < 0.0 && formatted.chars().any(|c| matches!(c, '1'..='9')) {
+        result.push('-');
+    }
+    for (i, digit) in integer_part.chars().enumerate() {
+        if i > 0 && (integer_part.len() - i) % 3 == 0 {
+            result.push(',');
+        }
+        result.push(digit);
+    }
+    if let Some(fractional_part) = fractional_part {
+        result.push('.');
+        result.push_str(fractional_part);
+    }
+    result
+}
 
-    let exchange_rates = ExchangeRatesCache::new();
+#[cfg(test)]
+mod tests {
+    use super::format_amount;
 
-    return_scalar!(exchange_rates.get_rate(&rate).unwrap_or(f64::NAN))
+    #[test]
+    fn amounts() {
+        assert_eq!(format_amount(1234.5, 2), "1,234.50");
+        assert_eq!(format_amount(1234567.891, 2), "1,234,567.89");
+        assert_eq!(format_amount(999.999, 2), "1,000.00");
+        assert_eq!(format_amount(-1234.5, 0), "-1,235");
+        assert_eq!(format_amount(-0.001, 2), "0.00");
+        assert_eq!(format_amount(12.0, 3), "12.000");
+    }
 }
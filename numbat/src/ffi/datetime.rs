@@ -1,3 +1,4 @@
+use jiff::tz::TimeZone;
 use jiff::Span;
 use jiff::Timestamp;
 use jiff::Zoned;
@@ -14,14 +15,18 @@ use crate::RuntimeError;
 
 use std::fmt::Write;
 
-pub fn now(_args: Args) -> Result<Value> {
-    return_datetime!(Zoned::now())
+pub fn now(now: Zoned, _args: Args) -> Result<Value> {
+    return_datetime!(now)
 }
 
-pub fn datetime(mut args: Args) -> Result<Value> {
+pub fn datetime(args: Args) -> Result<Value> {
+    datetime_in(args, datetime::get_local_timezone_or_utc)
+}
+
+pub fn datetime_in(mut args: Args, local_timezone: fn() -> TimeZone) -> Result<Value> {
     let input = string_arg!(args);
 
-    let output = datetime::parse_datetime(&input)
+    let output = datetime::parse_datetime(&input, local_timezone)
         .map_err(|e| RuntimeError::DateParsingError(e.to_string()))?;
 
     return_datetime!(output)
@@ -60,12 +65,16 @@ pub fn unixtime(mut args: Args) -> Result<Value> {
     return_scalar!(output as f64)
 }
 
-pub fn from_unixtime(mut args: Args) -> Result<Value> {
+pub fn from_unixtime(args: Args) -> Result<Value> {
+    from_unixtime_in(args, datetime::get_local_timezone_or_utc)
+}
+
+pub fn from_unixtime_in(mut args: Args, local_timezone: fn() -> TimeZone) -> Result<Value> {
     let timestamp = quantity_arg!(args).unsafe_value().to_f64() as i64;
 
     let dt = Timestamp::from_second(timestamp)
         .map_err(|_| RuntimeError::DateTimeOutOfRange)?
-        .to_zoned(datetime::get_local_timezone_or_utc());
+        .to_zoned(local_timezone());
 
     return_datetime!(dt)
 }
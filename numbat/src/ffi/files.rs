@@ -0,0 +1,151 @@
+//! Procedures that write files through the [`FileSystem`] of the VM.
+
+use std::path::Path;
+
+use itertools::Itertools;
+
+use super::json::RFC3339;
+use super::macros::*;
+use super::{Args, ControlFlow};
+use crate::file_system::FileSystem;
+use crate::value::Value;
+use crate::RuntimeError;
+
+pub fn write_text(file_system: &dyn FileSystem, mut args: Args) -> ControlFlow {
+    let path = string_arg!(args);
+    let content = string_arg!(args);
+
+    match file_system.write(Path::new(&path), &content) {
+        Ok(()) => ControlFlow::Continue(()),
+        Err(e) => ControlFlow::Break(RuntimeError::FileWriteError(path, e.to_string())),
+    }
+}
+
+/// Appends a struct (or a list) as a row to a CSV file. A new file starts with a
+/// header of field names. Later rows need to have the same fields, or the same
+/// number of columns.
+pub fn append_csv(file_system: &dyn FileSystem, mut args: Args) -> ControlFlow {
+    let path = string_arg!(args);
+    let row = arg!(args);
+
+    match append_row(file_system, &path, row) {
+        Ok(()) => ControlFlow::Continue(()),
+        Err(e) => ControlFlow::Break(e),
+    }
+}
+
+fn append_row(file_system: &dyn FileSystem, path: &str, row: Value) -> Result<(), RuntimeError> {
+    let csv_error = |message: String| RuntimeError::CsvError(path.into(), message);
+    let write_error = |e: std::io::Error| RuntimeError::FileWriteError(path.into(), e.to_string());
+
+    let (header, values): (Option<Vec<String>>, Vec<Value>) = match row {
+        Value::StructInstance(info, values) => (
+            Some(info.fields.keys().map(|name| name.to_string()).collect()),
+            values,
+        ),
+        Value::List(list) => (None, list.iter().cloned().collect()),
+        value => {
+            return Err(csv_error(format!(
+                "expected a struct or a list as a row, got {value}"
+            )))
+        }
+    };
+
+    let cells = values
+        .iter()
+        .map(|value| {
+            cell(value).ok_or_else(|| csv_error(format!("can not write {value} to a cell")))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let existing = file_system.read(Path::new(path)).map_err(write_error)?;
+    let first_line = existing
+        .as_deref()
+        .and_then(|contents| contents.lines().next())
+        .filter(|line| !line.is_empty());
+
+    let mut output = String::new();
+    match (first_line, &header) {
+        (None, Some(header)) => {
+            output += &record(header);
+        }
+        (None, None) => {}
+        (Some(line), Some(header)) => {
+            let columns = split_record(line);
+            if &columns != header {
+                return Err(csv_error(format!(
+                    "expected the columns {}, got {}",
+                    columns.join(", "),
+                    header.join(", ")
+                )));
+            }
+        }
+        (Some(line), None) => {
+            let num_columns = split_record(line).len();
+            if num_columns != cells.len() {
+                return Err(csv_error(format!(
+                    "expected {num_columns} columns, got {}",
+                    cells.len()
+                )));
+            }
+        }
+    }
+    output += &record(&cells);
+
+    file_system
+        .append(Path::new(path), &output)
+        .map_err(write_error)
+}
+
+/// The text of a cell. Quantities are written with full precision, followed by
+/// their unit.
+fn cell(value: &Value) -> Option<String> {
+    Some(match value {
+        Value::Quantity(q) => {
+            let number = q.unsafe_value().to_f64().to_string();
+            if q.unit().is_scalar() {
+                number
+            } else {
+                format!("{number} {}", q.unit())
+            }
+        }
+        Value::Duration(d) => return cell(&Value::Quantity(d.to_quantity())),
+        Value::Boolean(b) => b.to_string(),
+        Value::String(s) => s.clone(),
+        Value::DateTime(dt) => dt.strftime(RFC3339).to_string(),
+        _ => return None,
+    })
+}
+
+fn record(cells: &[String]) -> String {
+    cells
+        .iter()
+        .map(|cell| {
+            if cell.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", cell.replace('"', "\"\""))
+            } else {
+                cell.clone()
+            }
+        })
+        .join(",")
+        + "\n"
+}
+
+/// Splits a line into (unescaped) cells.
+fn split_record(line: &str) -> Vec<String> {
+    let mut cells = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                cells.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => cells.push(String::new()),
+            c => cells.last_mut().unwrap().push(c),
+        }
+    }
+    cells
+}
@@ -1,20 +1,29 @@
 use std::collections::HashMap;
-use std::sync::OnceLock;
+use std::sync::{Arc, OnceLock};
 
 use super::{macros::*, Args};
 use crate::{quantity::Quantity, value::Value, RuntimeError};
 
 use super::{Callable, ForeignFunction, Result};
 
-static FFI_FUNCTIONS: OnceLock<HashMap<String, ForeignFunction>> = OnceLock::new();
+static FFI_FUNCTIONS: OnceLock<HashMap<String, Arc<ForeignFunction>>> = OnceLock::new();
 
-pub(crate) fn functions() -> &'static HashMap<String, ForeignFunction> {
+pub(crate) fn functions() -> &'static HashMap<String, Arc<ForeignFunction>> {
+    #[cfg(feature = "datetime")]
+    use super::benchmark::*;
     use super::currency::*;
+    #[cfg(feature = "datetime")]
     use super::datetime::*;
+    use super::json::*;
     use super::lists::*;
     use super::lookup::*;
     use super::math::*;
+    use super::matrices::*;
+    use super::quantities::*;
+    use super::script::*;
+    use super::series::*;
     use super::strings::*;
+    use super::ucum::*;
 
     FFI_FUNCTIONS.get_or_init(|| {
         let mut m = HashMap::new();
@@ -23,11 +32,11 @@ pub(crate) fn functions() -> &'static HashMap<String, ForeignFunction> {
             ($fn_name:expr, $callable:expr, $arity:expr) => {
                 m.insert(
                     $fn_name.to_string(),
-                    ForeignFunction {
+                    Arc::new(ForeignFunction {
                         name: $fn_name.to_string(),
                         arity: $arity,
                         callable: Callable::Function(Box::new($callable)),
-                    },
+                    }),
                 );
             };
             ($callable:expr, $arity:expr) => {
@@ -35,18 +44,80 @@ pub(crate) fn functions() -> &'static HashMap<String, ForeignFunction> {
             };
         }
 
+        macro_rules! insert_regex_function {
+            ($callable:expr, $arity:expr) => {
+                m.insert(
+                    stringify!($callable).to_string(),
+                    Arc::new(ForeignFunction {
+                        name: stringify!($callable).to_string(),
+                        arity: $arity,
+                        callable: Callable::RegexFunction($callable),
+                    }),
+                );
+            };
+        }
+
+        macro_rules! insert_sequence_function {
+            ($callable:expr, $arity:expr) => {
+                m.insert(
+                    stringify!($callable).to_string(),
+                    Arc::new(ForeignFunction {
+                        name: stringify!($callable).to_string(),
+                        arity: $arity,
+                        callable: Callable::SequenceFunction($callable),
+                    }),
+                );
+            };
+        }
+
         // Core
         insert_function!(error, 1..=1);
         insert_function!(unit_of, 1..=1);
+        m.insert(
+            "parse_quantity".to_string(),
+            Arc::new(ForeignFunction {
+                name: "parse_quantity".to_string(),
+                arity: 2..=2,
+                callable: Callable::UnitFunction(parse_quantity),
+            }),
+        );
+        m.insert(
+            "_simplify".to_string(),
+            Arc::new(ForeignFunction {
+                name: "_simplify".to_string(),
+                arity: 2..=2,
+                callable: Callable::UnitFunction(simplify),
+            }),
+        );
+        m.insert(
+            "to_ucum".to_string(),
+            Arc::new(ForeignFunction {
+                name: "to_ucum".to_string(),
+                arity: 1..=1,
+                callable: Callable::UnitFunction(to_ucum),
+            }),
+        );
+        m.insert(
+            "from_ucum".to_string(),
+            Arc::new(ForeignFunction {
+                name: "from_ucum".to_string(),
+                arity: 3..=3,
+                callable: Callable::UnitFunction(from_ucum),
+            }),
+        );
 
         // Math
         insert_function!("mod", mod_, 2..=2);
 
         insert_function!(abs, 1..=1);
+        insert_function!(sign, 1..=1);
+        insert_function!(copysign, 2..=2);
+        insert_function!(clamp, 3..=3);
         insert_function!(round, 1..=1);
         insert_function!(floor, 1..=1);
         insert_function!(ceil, 1..=1);
         insert_function!(trunc, 1..=1);
+        insert_function!(fract, 1..=1);
 
         insert_function!(sin, 1..=1);
         insert_function!(cos, 1..=1);
@@ -67,17 +138,54 @@ pub(crate) fn functions() -> &'static HashMap<String, ForeignFunction> {
         insert_function!(log2, 1..=1);
         insert_function!(gamma, 1..=1);
 
+        insert_function!(factorial, 1..=1);
+        insert_function!(binomial, 2..=2);
+        insert_function!(gcd, 2..=2);
+        insert_function!(lcm, 2..=2);
+
         insert_function!(is_nan, 1..=1);
         insert_function!(is_infinite, 1..=1);
 
         insert_function!(random, 0..=0);
 
         // Lists
-        insert_function!(len, 1..=1);
-        insert_function!(head, 1..=1);
-        insert_function!(tail, 1..=1);
+        insert_sequence_function!(len, 1..=1);
+        insert_sequence_function!(is_empty, 1..=1);
+        insert_sequence_function!(head, 1..=1);
+        insert_sequence_function!(tail, 1..=1);
+        insert_sequence_function!(element_at, 2..=2);
+        insert_sequence_function!(map, 2..=2);
+        insert_sequence_function!(filter, 2..=2);
+        insert_sequence_function!(foldl, 3..=3);
+        insert_sequence_function!(sum, 1..=1);
+        m.insert(
+            "sum_series".to_string(),
+            Arc::new(ForeignFunction {
+                name: "sum_series".to_string(),
+                arity: 3..=3,
+                callable: Callable::TypedSequenceFunction(sum_series),
+            }),
+        );
+        insert_sequence_function!(product_series, 3..=3);
+        insert_sequence_function!(maximum, 1..=1);
+        insert_sequence_function!(minimum, 1..=1);
+        insert_sequence_function!(max_of, 1..=1);
+        insert_sequence_function!(min_of, 1..=1);
+        insert_function!(range, 2..=2);
+        insert_function!(_linspace, 3..=3);
         insert_function!(cons, 2..=2);
         insert_function!(cons_end, 2..=2);
+        insert_function!(reverse, 1..=1);
+        insert_function!(contains, 2..=2);
+        insert_function!(index_of, 2..=2);
+        insert_function!(sort, 1..=1);
+        insert_function!(_sort_by_keys, 2..=2);
+
+        // Matrices
+        insert_function!(matmul, 2..=2);
+        insert_function!(transpose, 1..=1);
+        insert_function!(identity, 1..=1);
+        insert_function!(solve_linear, 2..=2);
 
         // Strings
         insert_function!(str_length, 1..=1);
@@ -86,22 +194,107 @@ pub(crate) fn functions() -> &'static HashMap<String, ForeignFunction> {
         insert_function!(str_slice, 3..=3);
         insert_function!(chr, 1..=1);
         insert_function!(ord, 1..=1);
+        insert_function!(number_to_words, 1..=1);
+        insert_regex_function!(str_match, 2..=2);
+        insert_regex_function!(str_extract, 3..=3);
+        insert_regex_function!(str_extract_all, 2..=2);
+
+        // JSON
+        insert_sequence_function!(_to_json, 2..=2);
+        m.insert(
+            "parse_json".to_string(),
+            Arc::new(ForeignFunction {
+                name: "parse_json".to_string(),
+                arity: 1..=1,
+                callable: Callable::TypedFunction(parse_json),
+            }),
+        );
 
         // Date and time
-        insert_function!(now, 0..=0);
-        insert_function!(datetime, 1..=1);
-        insert_function!(format_datetime, 2..=2);
-        insert_function!(get_local_timezone, 0..=0);
-        insert_function!(tz, 1..=1);
-        insert_function!(unixtime, 1..=1);
-        insert_function!(from_unixtime, 1..=1);
-
-        insert_function!(_add_days, 2..=2);
-        insert_function!(_add_months, 2..=2);
-        insert_function!(_add_years, 2..=2);
+        #[cfg(feature = "datetime")]
+        {
+            m.insert(
+                "now".to_string(),
+                Arc::new(ForeignFunction {
+                    name: "now".to_string(),
+                    arity: 0..=0,
+                    callable: Callable::ClockFunction(now),
+                }),
+            );
+            insert_function!(datetime, 1..=1);
+            insert_function!(format_datetime, 2..=2);
+            insert_function!(get_local_timezone, 0..=0);
+            insert_function!(tz, 1..=1);
+            insert_function!(unixtime, 1..=1);
+            insert_function!(from_unixtime, 1..=1);
+
+            insert_function!(_add_days, 2..=2);
+            insert_function!(_add_months, 2..=2);
+            insert_function!(_add_years, 2..=2);
+
+            insert_sequence_function!(_benchmark_seconds, 2..=2);
+        }
+
+        // Time series
+        m.insert(
+            "diff_series".to_string(),
+            Arc::new(ForeignFunction {
+                name: "diff_series".to_string(),
+                arity: 1..=1,
+                callable: Callable::UnitFunction(diff_series),
+            }),
+        );
+        insert_sequence_function!(resample, 3..=3);
+        m.insert(
+            "integrate_series".to_string(),
+            Arc::new(ForeignFunction {
+                name: "integrate_series".to_string(),
+                arity: 1..=1,
+                callable: Callable::TypedSequenceFunction(integrate_series),
+            }),
+        );
 
         // Currency
-        insert_function!(exchange_rate, 1..=1);
+        #[cfg(feature = "currency")]
+        {
+            insert_sequence_function!(exchange_rate, 1..=1);
+            insert_sequence_function!(exchange_rate_age, 0..=0);
+            m.insert(
+                "exchange_rate_timestamp".to_string(),
+                Arc::new(ForeignFunction {
+                    name: "exchange_rate_timestamp".to_string(),
+                    arity: 0..=0,
+                    callable: Callable::ClockFunction(exchange_rate_timestamp),
+                }),
+            );
+        }
+        m.insert(
+            "format_currency".to_string(),
+            Arc::new(ForeignFunction {
+                name: "format_currency".to_string(),
+                arity: 1..=1,
+                callable: Callable::UnitFunction(format_currency),
+            }),
+        );
+
+        // Scripts
+        m.insert(
+            "args".to_string(),
+            Arc::new(ForeignFunction {
+                name: "args".to_string(),
+                arity: 0..=0,
+                callable: Callable::ScriptFunction(args),
+            }),
+        );
+        m.insert(
+            "arg".to_string(),
+            Arc::new(ForeignFunction {
+                name: "arg".to_string(),
+                arity: 1..=1,
+                callable: Callable::ScriptFunction(arg),
+            }),
+        );
+        insert_function!(env, 1..=1);
 
         // Database lookup
         insert_function!(_get_chemical_element_data_raw, 1..=1);
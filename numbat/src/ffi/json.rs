@@ -0,0 +1,278 @@
+//! Conversion of values to and from JSON.
+//!
+//! Structs are written as objects, lists as arrays and date-times as RFC 3339
+//! strings. Quantities are written as `{"value": 3, "unit": "m"}`, unless they
+//! are dimensionless (or plain numbers are requested).
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use serde_json::{Map, Value as Json};
+
+use super::macros::*;
+use super::quantities::parse_unit;
+use super::{Args, Result, UnitLookup};
+use crate::number::Number;
+use crate::pretty_print::PrettyPrint;
+use crate::quantity::Quantity;
+use crate::sequence::{self, FunctionCaller};
+use crate::symbol::Symbol;
+use crate::typed_ast::{DType, Type};
+use crate::unit::Unit;
+use crate::value::Value;
+use crate::RuntimeError;
+
+pub(super) const RFC3339: &str = "%Y-%m-%dT%H:%M:%S%.f%:z";
+
+/// Integers up to this magnitude are exactly representable as `f64`. They are
+/// written without a fractional part.
+const MAX_EXACT_INTEGER: f64 = 9_007_199_254_740_992.0;
+
+pub fn _to_json(caller: &mut dyn FunctionCaller, mut args: Args) -> Result<Value> {
+    let value = arg!(args);
+
+    let Value::StructInstance(info, options) = arg!(args) else {
+        unreachable!("The options are a JsonOptions struct")
+    };
+    let option = |name: &str| {
+        let idx = info.fields.get_index_of(&Symbol::new(name)).unwrap();
+        options[idx].clone().unsafe_as_bool()
+    };
+
+    let mut serializer = Serializer {
+        caller,
+        plain_numbers: option("plain_numbers"),
+    };
+    let json = serializer.serialize(value)?;
+
+    let output = if option("pretty") {
+        serde_json::to_string_pretty(&json)
+    } else {
+        serde_json::to_string(&json)
+    }
+    .map_err(|e| RuntimeError::JsonSerializationError(e.to_string()))?;
+
+    return_string!(output)
+}
+
+/// Reads a value of the result type at the call site, e.g. `let p: Point =
+/// parse_json(…)`.
+pub fn parse_json(units: &dyn UnitLookup, type_: &Type, mut args: Args) -> Result<Value> {
+    let input = string_arg!(args);
+
+    let json: Json =
+        serde_json::from_str(&input).map_err(|e| RuntimeError::JsonParsingError(e.to_string()))?;
+
+    Deserializer { units }.deserialize(&json, type_, "$")
+}
+
+struct Serializer<'a> {
+    caller: &'a mut dyn FunctionCaller,
+    plain_numbers: bool,
+}
+
+impl Serializer<'_> {
+    fn serialize(&mut self, value: Value) -> Result<Json> {
+        Ok(match sequence::force_value(value, self.caller)? {
+            Value::Quantity(q) => self.quantity(&q)?,
+            Value::Duration(d) => self.quantity(&d.to_quantity())?,
+            Value::Boolean(b) => Json::Bool(b),
+            Value::String(s) => Json::String(s),
+            Value::DateTime(dt) => Json::String(dt.strftime(RFC3339).to_string()),
+            Value::StructInstance(info, values) => Json::Object(
+                info.fields
+                    .keys()
+                    .zip(values)
+                    .map(|(name, value)| Ok((name.to_string(), self.serialize(value)?)))
+                    .collect::<Result<Map<_, _>>>()?,
+            ),
+            Value::List(list) => Json::Array(
+                list.iter()
+                    .map(|value| self.serialize(value.clone()))
+                    .collect::<Result<_>>()?,
+            ),
+            value @ (Value::FunctionReference(_)
+            | Value::FormatSpecifiers(_)
+            | Value::DefaultArgument) => {
+                return Err(RuntimeError::JsonSerializationError(format!(
+                    "{value} can not be represented in JSON"
+                )))
+            }
+            Value::Sequence(_) => unreachable!("Sequences have been forced above"),
+        })
+    }
+
+    fn quantity(&self, quantity: &Quantity) -> Result<Json> {
+        let value = number(quantity.unsafe_value().to_f64())?;
+
+        if self.plain_numbers || quantity.unit().is_scalar() {
+            return Ok(value);
+        }
+
+        let mut object = Map::new();
+        object.insert("value".into(), value);
+        object.insert("unit".into(), Json::String(quantity.unit().to_string()));
+        Ok(Json::Object(object))
+    }
+}
+
+/// Uses the shortest representation that reads back as the same `f64`.
+fn number(n: f64) -> Result<Json> {
+    if n.fract() == 0.0 && n.abs() <= MAX_EXACT_INTEGER {
+        return Ok(Json::from(n as i64));
+    }
+
+    serde_json::Number::from_f64(n)
+        .map(Json::Number)
+        .ok_or_else(|| {
+            RuntimeError::JsonSerializationError(format!(
+                "{} can not be represented in JSON",
+                Number::from_f64(n).pretty_print()
+            ))
+        })
+}
+
+fn describe(json: &Json) -> &'static str {
+    match json {
+        Json::Null => "null",
+        Json::Bool(_) => "a boolean",
+        Json::Number(_) => "a number",
+        Json::String(_) => "a string",
+        Json::Array(_) => "an array",
+        Json::Object(_) => "an object",
+    }
+}
+
+struct Deserializer<'a> {
+    units: &'a dyn UnitLookup,
+}
+
+impl Deserializer<'_> {
+    fn deserialize(&self, json: &Json, type_: &Type, path: &str) -> Result<Value> {
+        let error = |message: String| RuntimeError::JsonDeserializationError(path.into(), message);
+
+        match (type_, json) {
+            (Type::Dimension(dtype), json) => self.quantity(json, Some(dtype), path),
+            (Type::Boolean, Json::Bool(b)) => Ok(Value::Boolean(*b)),
+            (Type::String, Json::String(s)) => Ok(Value::String(s.clone())),
+            (Type::DateTime, Json::String(s)) => jiff::Zoned::strptime(RFC3339, s)
+                .or_else(|_| crate::datetime::parse_datetime(s, || jiff::tz::TimeZone::UTC))
+                .map(Value::DateTime)
+                .map_err(|_| error(format!("'{s}' is not a valid date and time"))),
+            (Type::List(element_type), Json::Array(elements)) => elements
+                .iter()
+                .enumerate()
+                .map(|(i, element)| {
+                    self.deserialize(element, element_type, &format!("{path}[{i}]"))
+                })
+                .collect::<Result<VecDeque<_>>>()
+                .map(Value::from),
+            (Type::Struct(info), Json::Object(object)) => {
+                let values = info
+                    .fields
+                    .iter()
+                    .map(|(name, (_, field_type))| match object.get(name.as_str()) {
+                        Some(value) => {
+                            self.deserialize(value, field_type, &format!("{path}.{name}"))
+                        }
+                        None => Err(error(format!(
+                            "missing field '{name}' of struct {}",
+                            info.name
+                        ))),
+                    })
+                    .collect::<Result<_>>()?;
+                Ok(Value::StructInstance(Arc::new(info.clone()), values))
+            }
+            (Type::Dynamic, Json::Bool(b)) => Ok(Value::Boolean(*b)),
+            (Type::Dynamic, Json::String(s)) => Ok(Value::String(s.clone())),
+            (Type::Dynamic, Json::Array(_)) => {
+                self.deserialize(json, &Type::List(Box::new(Type::Dynamic)), path)
+            }
+            (Type::Dynamic, Json::Number(_) | Json::Object(_)) => self.quantity(json, None, path),
+            (Type::Fn(..), _) => Err(error("functions can not be read from JSON".into())),
+            (Type::TVar(_) | Type::TPar(_), _) => {
+                unreachable!("The result type of a typed function is fully known")
+            }
+            (type_, json) => Err(error(format!(
+                "expected a value of type {}, got {}",
+                type_.pretty_print(),
+                describe(json)
+            ))),
+        }
+    }
+
+    /// Reads a quantity. Its dimension is only checked if `dtype` is given.
+    fn quantity(&self, json: &Json, dtype: Option<&DType>, path: &str) -> Result<Value> {
+        let error = |path: &str, message: String| {
+            RuntimeError::JsonDeserializationError(path.into(), message)
+        };
+
+        let (value, unit) = match json {
+            Json::Number(n) => (n, Unit::scalar()),
+            Json::Object(object) => {
+                let value_path = format!("{path}.value");
+                let value = match object.get("value") {
+                    Some(Json::Number(n)) => n,
+                    Some(other) => {
+                        return Err(error(
+                            &value_path,
+                            format!("expected a number, got {}", describe(other)),
+                        ))
+                    }
+                    None => return Err(error(path, "missing field 'value' of quantity".into())),
+                };
+
+                let unit_path = format!("{path}.unit");
+                let unit = match object.get("unit") {
+                    Some(Json::String(name)) => parse_unit(name, self.units)
+                        .ok_or_else(|| error(&unit_path, format!("unknown unit '{name}'")))?,
+                    Some(other) => {
+                        return Err(error(
+                            &unit_path,
+                            format!("expected a string, got {}", describe(other)),
+                        ))
+                    }
+                    None => Unit::scalar(),
+                };
+
+                (value, unit)
+            }
+            other => {
+                return Err(error(
+                    path,
+                    format!(
+                        "expected a quantity of type {}, got {}",
+                        dtype.map_or_else(
+                            || Type::Dynamic.pretty_print(),
+                            |dtype| dtype.pretty_print()
+                        ),
+                        describe(other)
+                    ),
+                ))
+            }
+        };
+
+        if let (Some(dtype), Some(dimension)) = (dtype, self.units.dimension_of(&unit)) {
+            if dimension.to_base_representation() != dtype.to_base_representation() {
+                let actual = if unit.is_scalar() {
+                    "a number without unit".to_string()
+                } else {
+                    format!("'{unit}' of type {}", dimension.pretty_print())
+                };
+                return Err(error(
+                    path,
+                    format!(
+                        "expected a quantity of type {}, got {actual}",
+                        dtype.pretty_print()
+                    ),
+                ));
+            }
+        }
+
+        let value = value.as_f64().expect("JSON numbers can be read as f64");
+        Ok(Value::Quantity(Quantity::new(
+            Number::from_f64(value),
+            unit,
+        )))
+    }
+}
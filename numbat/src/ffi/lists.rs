@@ -1,30 +1,442 @@
+use std::collections::VecDeque;
+
 use super::macros::*;
-use super::{Args, Result};
-use crate::quantity::Quantity;
+use super::{Args, Result, UnitLookup};
+use crate::arithmetic::Power;
+use crate::quantity::{Quantity, QuantityError};
+use crate::sequence::{force_value, FunctionCaller, Reduce, Sequence};
+use crate::typed_ast::{DType, DTypeFactor, Type};
+use crate::unit::Unit;
 use crate::value::Value;
-use crate::RuntimeError;
+use crate::{RuntimeError, RuntimeWarning};
 
-pub fn len(mut args: Args) -> Result<Value> {
-    let list = list_arg!(args);
+pub fn len(caller: &mut dyn FunctionCaller, mut args: Args) -> Result<Value> {
+    let len = match arg!(args) {
+        Value::Sequence(sequence) => sequence.len(caller)?,
+        list => list.unsafe_as_list().len(),
+    };
 
-    return_scalar!(list.len() as f64)
+    return_integer!(len as f64)
 }
 
-pub fn head(mut args: Args) -> Result<Value> {
-    let list = list_arg!(args);
+pub fn is_empty(caller: &mut dyn FunctionCaller, mut args: Args) -> Result<Value> {
+    let is_empty = match arg!(args) {
+        Value::Sequence(sequence) => sequence.is_empty(caller)?,
+        list => list.unsafe_as_list().is_empty(),
+    };
+
+    return_boolean!(is_empty)
+}
 
-    if let Some(first) = list.head() {
-        Ok(first)
-    } else {
-        Err(RuntimeError::EmptyList)
+pub fn head(caller: &mut dyn FunctionCaller, mut args: Args) -> Result<Value> {
+    match arg!(args) {
+        Value::Sequence(sequence) => sequence.head(caller),
+        list => list.unsafe_as_list().head().ok_or(RuntimeError::EmptyList),
     }
 }
 
-pub fn tail(mut args: Args) -> Result<Value> {
-    let mut list = list_arg!(args);
+pub fn tail(caller: &mut dyn FunctionCaller, mut args: Args) -> Result<Value> {
+    match arg!(args) {
+        Value::Sequence(sequence) => Ok(Value::Sequence(sequence.tail(caller)?)),
+        list => {
+            let mut list = list.unsafe_as_list();
+            list.tail()?;
+            Ok(list.into())
+        }
+    }
+}
+
+pub fn element_at(caller: &mut dyn FunctionCaller, mut args: Args) -> Result<Value> {
+    let index = quantity_arg!(args);
+    let index = index
+        .as_integer()
+        .ok_or_else(|| RuntimeError::NonIntegerListIndex(index.to_exact_string()))?;
+    let index = usize::try_from(index).map_err(|_| RuntimeError::EmptyList)?;
+
+    match arg!(args) {
+        Value::Sequence(sequence) => sequence.element_at(index, caller),
+        list => list
+            .unsafe_as_list()
+            .iter()
+            .nth(index)
+            .cloned()
+            .ok_or(RuntimeError::EmptyList),
+    }
+}
+
+pub fn map(caller: &mut dyn FunctionCaller, mut args: Args) -> Result<Value> {
+    let function = arg!(args);
+
+    match arg!(args) {
+        Value::Sequence(sequence) => Ok(Value::Sequence(Sequence::Map(
+            Box::new(function),
+            Box::new(sequence),
+        ))),
+        list => {
+            let list = list.unsafe_as_list();
+            if let Some(result) = caller.parallel_map(&function, &list) {
+                return Ok(VecDeque::from(result?).into());
+            }
+
+            let mut result = VecDeque::with_capacity(list.len());
+            for element in list.iter() {
+                let value = caller.call(&function, vec![element.clone()])?;
+                result.push_back(force_value(value, caller)?);
+            }
+            Ok(result.into())
+        }
+    }
+}
+
+pub fn filter(caller: &mut dyn FunctionCaller, mut args: Args) -> Result<Value> {
+    let predicate = arg!(args);
+
+    match arg!(args) {
+        Value::Sequence(sequence) => Ok(Value::Sequence(Sequence::Filter(
+            Box::new(predicate),
+            Box::new(sequence),
+        ))),
+        list => {
+            let mut result = VecDeque::new();
+            for element in list.unsafe_as_list().iter() {
+                if caller
+                    .call(&predicate, vec![element.clone()])?
+                    .unsafe_as_bool()
+                {
+                    result.push_back(element.clone());
+                }
+            }
+            Ok(result.into())
+        }
+    }
+}
+
+pub fn foldl(caller: &mut dyn FunctionCaller, mut args: Args) -> Result<Value> {
+    let function = arg!(args);
+    let mut acc = arg!(args);
+
+    match arg!(args) {
+        Value::Sequence(mut sequence) => {
+            while let Some(element) = sequence.pop_front(caller)? {
+                acc = caller.call(&function, vec![acc, element])?;
+            }
+        }
+        list => {
+            for element in list.unsafe_as_list().iter() {
+                acc = caller.call(&function, vec![acc, element.clone()])?;
+            }
+        }
+    }
+
+    Ok(acc)
+}
+
+fn reduce(caller: &mut dyn FunctionCaller, reduce: Reduce, mut args: Args) -> Result<Value> {
+    match arg!(args) {
+        Value::Sequence(mut sequence) => {
+            let mut error = None;
+            let result = reduce(&mut std::iter::from_fn(|| {
+                sequence.pop_front(caller).unwrap_or_else(|e| {
+                    error = Some(e);
+                    None
+                })
+            }));
+            match error {
+                Some(e) => Err(e),
+                None => result,
+            }
+        }
+        list => {
+            let list = list.unsafe_as_list();
+            match caller.parallel_reduce(&list, reduce) {
+                Some(result) => result,
+                None => reduce(&mut list.iter().cloned()),
+            }
+        }
+    }
+}
+
+/// Compensated (Kahan-Babuška-Neumaier) summation of quantities. Like `foldl(_add, 0, xs)`,
+/// the result is in the (simplified) unit of the first non-zero term, but the rounding
+/// errors of the additions are accumulated separately and added at the end.
+struct CompensatedSum {
+    unit: Unit,
+    sum: f64,
+    compensation: f64,
+    /// The sum of the absolute values of all terms, in `unit`
+    magnitude: f64,
+    terms: usize,
+}
 
-    list.tail()?;
-    Ok(list.into())
+impl CompensatedSum {
+    fn new() -> Self {
+        CompensatedSum {
+            unit: Unit::scalar(),
+            sum: 0.0,
+            compensation: 0.0,
+            magnitude: 0.0,
+            terms: 0,
+        }
+    }
+
+    fn add(&mut self, term: &Quantity) -> Result<()> {
+        self.terms += 1;
+        if term.is_zero() {
+            return Ok(());
+        }
+
+        let value = if self.total() == 0.0 {
+            // As with `0 + x`, the unit of a zero sum is replaced by the unit of the term
+            let term = term.full_simplify();
+            if self.magnitude != 0.0 {
+                self.magnitude = Quantity::new_f64(self.magnitude, self.unit.clone())
+                    .convert_to(term.unit())
+                    .map_err(RuntimeError::QuantityError)?
+                    .unsafe_value()
+                    .to_f64();
+            }
+            self.unit = term.unit().clone();
+            self.sum = 0.0;
+            self.compensation = 0.0;
+            term.unsafe_value().to_f64()
+        } else {
+            term.convert_to(&self.unit)
+                .map_err(RuntimeError::QuantityError)?
+                .unsafe_value()
+                .to_f64()
+        };
+
+        let sum = self.sum + value;
+        if self.sum.abs() >= value.abs() {
+            self.compensation += (self.sum - sum) + value;
+        } else {
+            self.compensation += (value - sum) + self.sum;
+        }
+        self.sum = sum;
+        self.magnitude += value.abs();
+        Ok(())
+    }
+
+    fn total(&self) -> f64 {
+        // The compensation is NaN if the sum overflowed
+        if self.sum.is_finite() {
+            self.sum + self.compensation
+        } else {
+            self.sum
+        }
+    }
+
+    /// An estimate for the relative rounding error of the result, or `None` if the
+    /// result is zero or not finite. It is large if the terms cancel each other.
+    fn relative_error(&self) -> Option<f64> {
+        let total = self.total();
+        (total != 0.0 && total.is_finite()).then(|| {
+            let epsilon = f64::EPSILON / 2.0;
+            (2.0 * epsilon + self.terms as f64 * epsilon * epsilon) * self.magnitude / total.abs()
+        })
+    }
+
+    fn into_quantity(self) -> Quantity {
+        Quantity::new_f64(self.total(), self.unit)
+    }
+}
+
+/// Warn about the rounding error of a sum, if this is enabled.
+fn check_rounding_error(caller: &mut dyn FunctionCaller, sum: &CompensatedSum) {
+    let Some(threshold) = caller.summation_error_threshold() else {
+        return;
+    };
+    if let Some(relative_error) = sum.relative_error().filter(|e| *e > threshold) {
+        caller.warn(RuntimeWarning::InaccurateSum(
+            relative_error,
+            threshold,
+            sum.terms,
+        ));
+    }
+}
+
+fn add_all(elements: &mut dyn Iterator<Item = Value>) -> Result<Value> {
+    let mut sum = CompensatedSum::new();
+    for element in elements {
+        sum.add(&element.unsafe_as_quantity())?;
+    }
+    Ok(Value::Quantity(sum.into_quantity()))
+}
+
+/// Find the largest (or smallest) element. Among equal elements, the last one wins.
+/// NaN propagates: the extremum of a list that contains NaN is NaN.
+fn find_extremum(
+    elements: &mut dyn Iterator<Item = Value>,
+    replaces: std::cmp::Ordering,
+) -> Result<Value> {
+    let mut extremum = elements
+        .next()
+        .ok_or(RuntimeError::EmptyList)?
+        .unsafe_as_quantity();
+    if extremum.is_nan() {
+        return Ok(Value::Quantity(extremum));
+    }
+    for element in elements {
+        let candidate = element.unsafe_as_quantity();
+        if candidate.is_nan() {
+            return Ok(Value::Quantity(candidate));
+        }
+        let ordering = candidate.partial_cmp(&extremum).ok_or_else(|| {
+            RuntimeError::QuantityError(QuantityError::IncompatibleUnits(
+                extremum.unit().clone(),
+                candidate.unit().clone(),
+            ))
+        })?;
+        if ordering == replaces || ordering == std::cmp::Ordering::Equal {
+            extremum = candidate;
+        }
+    }
+    Ok(Value::Quantity(extremum))
+}
+
+fn find_maximum(elements: &mut dyn Iterator<Item = Value>) -> Result<Value> {
+    find_extremum(elements, std::cmp::Ordering::Greater)
+}
+
+fn find_minimum(elements: &mut dyn Iterator<Item = Value>) -> Result<Value> {
+    find_extremum(elements, std::cmp::Ordering::Less)
+}
+
+pub fn sum(caller: &mut dyn FunctionCaller, mut args: Args) -> Result<Value> {
+    if caller.summation_error_threshold().is_none() {
+        return reduce(caller, add_all, args);
+    }
+
+    // The error estimate needs all terms, so the list is summed sequentially
+    let mut sum = CompensatedSum::new();
+    for element in force_value(arg!(args), caller)?.unsafe_as_list().iter() {
+        sum.add(&element.clone().unsafe_as_quantity())?;
+    }
+    check_rounding_error(caller, &sum);
+    Ok(Value::Quantity(sum.into_quantity()))
+}
+
+pub fn max_of(caller: &mut dyn FunctionCaller, args: Args) -> Result<Value> {
+    reduce(caller, find_maximum, args)
+}
+
+pub fn min_of(caller: &mut dyn FunctionCaller, args: Args) -> Result<Value> {
+    reduce(caller, find_minimum, args)
+}
+
+/// The variadic versions, which receive their arguments as a list
+pub fn maximum(caller: &mut dyn FunctionCaller, args: Args) -> Result<Value> {
+    max_of(caller, args)
+}
+
+pub fn minimum(caller: &mut dyn FunctionCaller, args: Args) -> Result<Value> {
+    min_of(caller, args)
+}
+
+/// The integer bounds of `sum_series` and `product_series`
+fn series_bounds(args: &mut Args) -> Result<(f64, f64)> {
+    let mut bound = || {
+        let bound = quantity_arg!(args);
+        match bound.as_integer() {
+            Some(n) => Ok(n as f64),
+            None => Err(RuntimeError::NonIntegerSeriesBound(bound.to_exact_string())),
+        }
+    };
+    Ok((bound()?, bound()?))
+}
+
+pub fn sum_series(
+    caller: &mut dyn FunctionCaller,
+    result_type: Option<&Type>,
+    mut args: Args,
+) -> Result<Value> {
+    let function = arg!(args);
+    let (from, to) = series_bounds(&mut args)?;
+
+    if from > to {
+        let zero = match (result_type, caller.unit_lookup()) {
+            (Some(Type::Dimension(dtype)), Some(units)) => zero(units, dtype),
+            _ => Quantity::from_scalar(0.0),
+        };
+        return Ok(Value::Quantity(zero));
+    }
+
+    // Equivalent to `sum(map(f, range(from, to)))`
+    let mut sum = CompensatedSum::new();
+    let mut n = from;
+    while n <= to {
+        let term = caller.call(&function, vec![Value::Quantity(Quantity::from_integer(n))])?;
+        sum.add(&force_value(term, caller)?.unsafe_as_quantity())?;
+        n += 1.0;
+    }
+    check_rounding_error(caller, &sum);
+    Ok(Value::Quantity(sum.into_quantity()))
+}
+
+pub fn product_series(caller: &mut dyn FunctionCaller, mut args: Args) -> Result<Value> {
+    let function = arg!(args);
+    let (from, to) = series_bounds(&mut args)?;
+
+    let mut product = Quantity::from_scalar(1.0);
+    let mut n = from;
+    while n <= to {
+        let factor = caller.call(&function, vec![Value::Quantity(Quantity::from_integer(n))])?;
+        product = (product * force_value(factor, caller)?.unsafe_as_quantity()).full_simplify();
+        n += 1.0;
+    }
+    Ok(Value::Quantity(product))
+}
+
+/// Zero of the given dimension, in base units (e.g. `0 m/s` for a velocity). If one
+/// of the base dimensions has no base unit, the result is a plain `0`.
+pub(super) fn zero(units: &dyn UnitLookup, dtype: &DType) -> Quantity {
+    let registry = &units.unit_registry().inner;
+    let base_unit = |dimension: &DType| {
+        registry.iter_base_entries().find_map(|name| {
+            let (_, metadata) = registry.get_base_representation(name).ok()?;
+            match metadata.type_ {
+                Type::Dimension(d)
+                    if d.to_base_representation() == dimension.to_base_representation() =>
+                {
+                    units.lookup_unit(&name)
+                }
+                _ => None,
+            }
+        })
+    };
+
+    let unit = dtype
+        .factors
+        .iter()
+        .try_fold(Unit::scalar(), |unit, (factor, exponent)| {
+            let DTypeFactor::BaseDimension(name) = factor else {
+                return None;
+            };
+            Some(unit * base_unit(&DType::base_dimension(name))?.power(*exponent))
+        });
+    Quantity::new(
+        crate::number::Number::from_f64(0.0),
+        unit.unwrap_or_else(Unit::scalar),
+    )
+}
+
+pub fn range(mut args: Args) -> Result<Value> {
+    let start = scalar_arg!(args).to_f64();
+    let end = scalar_arg!(args).to_f64();
+
+    Ok(Value::Sequence(Sequence::range(start, end)))
+}
+
+pub fn _linspace(mut args: Args) -> Result<Value> {
+    let start = quantity_arg!(args);
+    let end = quantity_arg!(args);
+    let n_steps = scalar_arg!(args).to_f64();
+
+    Ok(Value::Sequence(Sequence::linspace(
+        start,
+        end,
+        n_steps as usize,
+    )))
 }
 
 pub fn cons(mut args: Args) -> Result<Value> {
@@ -42,3 +454,94 @@ pub fn cons_end(mut args: Args) -> Result<Value> {
 
     return_list!(list)
 }
+
+/// Compute the permutation that sorts the given keys. The sort is stable and
+/// quantities are compared with their units taken into account. The type checker
+/// makes sure that all keys are of the same (orderable) type.
+fn sorting_permutation(keys: &[Value]) -> Result<Vec<usize>> {
+    let mut indices = (0..keys.len()).collect::<Vec<_>>();
+
+    match keys.first() {
+        Some(first) if first.is_quantity() => {
+            let unit = first.clone().unsafe_as_quantity().unit().clone();
+            let values = keys
+                .iter()
+                .map(|k| {
+                    let q = k.clone().unsafe_as_quantity();
+                    Ok(q.convert_to(&unit)
+                        .map_err(RuntimeError::QuantityError)?
+                        .unsafe_value()
+                        .to_f64())
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            indices.sort_by(|&i, &j| values[i].total_cmp(&values[j]));
+        }
+        Some(Value::String(_)) => {
+            indices.sort_by(|&i, &j| match (&keys[i], &keys[j]) {
+                (Value::String(a), Value::String(b)) => a.cmp(b),
+                _ => unreachable!("Expected a list of strings"),
+            });
+        }
+        Some(Value::DateTime(_)) => {
+            indices.sort_by(|&i, &j| match (&keys[i], &keys[j]) {
+                (Value::DateTime(a), Value::DateTime(b)) => a.cmp(b),
+                _ => unreachable!("Expected a list of datetimes"),
+            });
+        }
+        Some(_) => unreachable!("Values of this type can not be sorted"),
+        None => {}
+    }
+
+    Ok(indices)
+}
+
+pub fn sort(mut args: Args) -> Result<Value> {
+    let list = list_arg!(args);
+    let values = list.iter().cloned().collect::<Vec<_>>();
+
+    let sorted = sorting_permutation(&values)?
+        .into_iter()
+        .map(|i| values[i].clone())
+        .collect::<VecDeque<_>>();
+
+    Ok(sorted.into())
+}
+
+pub fn _sort_by_keys(mut args: Args) -> Result<Value> {
+    let keys = list_arg!(args).iter().cloned().collect::<Vec<_>>();
+    let values = list_arg!(args).iter().cloned().collect::<Vec<_>>();
+
+    let sorted = sorting_permutation(&keys)?
+        .into_iter()
+        .map(|i| values[i].clone())
+        .collect::<VecDeque<_>>();
+
+    Ok(sorted.into())
+}
+
+pub fn reverse(mut args: Args) -> Result<Value> {
+    let list = list_arg!(args);
+    let mut values = list.iter().cloned().collect::<VecDeque<_>>();
+    values.make_contiguous().reverse();
+
+    Ok(values.into())
+}
+
+pub fn contains(mut args: Args) -> Result<Value> {
+    let element = arg!(args);
+    let list = list_arg!(args);
+
+    let result = list.iter().any(|x| x == &element);
+
+    return_boolean!(result)
+}
+
+pub fn index_of(mut args: Args) -> Result<Value> {
+    let element = arg!(args);
+    let list = list_arg!(args);
+
+    let index = list.iter().position(|x| x == &element);
+
+    return_integer!(index.map(|i| i as f64).unwrap_or(-1.0))
+}
@@ -8,6 +8,7 @@ use crate::RuntimeError;
 
 pub fn _get_chemical_element_data_raw(mut args: Args) -> Result<Value> {
     use crate::span::{SourceCodePositition, Span};
+    use crate::symbol::Symbol;
     use crate::typed_ast::StructInfo;
     use crate::typed_ast::Type;
     use indexmap::IndexMap;
@@ -28,43 +29,40 @@ pub fn _get_chemical_element_data_raw(mut args: Args) -> Result<Value> {
 
         let type_scalar = Type::Dimension(DType::scalar());
 
-        let mut fields: IndexMap<String, (Span, Type)> = IndexMap::new();
-        fields.insert("symbol".to_string(), (unknown_span, Type::String));
-        fields.insert("name".to_string(), (unknown_span, Type::String));
+        let mut fields: IndexMap<Symbol, (Span, Type)> = IndexMap::new();
+        fields.insert("symbol".into(), (unknown_span, Type::String));
+        fields.insert("name".into(), (unknown_span, Type::String));
+        fields.insert("atomic_number".into(), (unknown_span, type_scalar.clone()));
+        fields.insert("group".into(), (unknown_span, type_scalar.clone()));
+        fields.insert("group_name".into(), (unknown_span, Type::String));
+        fields.insert("period".into(), (unknown_span, type_scalar.clone()));
         fields.insert(
-            "atomic_number".to_string(),
+            "melting_point_kelvin".into(),
             (unknown_span, type_scalar.clone()),
         );
-        fields.insert("group".to_string(), (unknown_span, type_scalar.clone()));
-        fields.insert("group_name".to_string(), (unknown_span, Type::String));
-        fields.insert("period".to_string(), (unknown_span, type_scalar.clone()));
         fields.insert(
-            "melting_point_kelvin".to_string(),
+            "boiling_point_kelvin".into(),
             (unknown_span, type_scalar.clone()),
         );
         fields.insert(
-            "boiling_point_kelvin".to_string(),
+            "density_gram_per_cm3".into(),
             (unknown_span, type_scalar.clone()),
         );
         fields.insert(
-            "density_gram_per_cm3".to_string(),
+            "electron_affinity_electronvolt".into(),
             (unknown_span, type_scalar.clone()),
         );
         fields.insert(
-            "electron_affinity_electronvolt".to_string(),
+            "ionization_energy_electronvolt".into(),
             (unknown_span, type_scalar.clone()),
         );
         fields.insert(
-            "ionization_energy_electronvolt".to_string(),
-            (unknown_span, type_scalar.clone()),
-        );
-        fields.insert(
-            "vaporization_heat_kilojoule_per_mole".to_string(),
+            "vaporization_heat_kilojoule_per_mole".into(),
             (unknown_span, type_scalar.clone()),
         );
 
         let info = StructInfo {
-            name: "_ChemicalElementRaw".to_string(),
+            name: "_ChemicalElementRaw".into(),
             definition_span: unknown_span,
             fields,
         };
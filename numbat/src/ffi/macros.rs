@@ -35,11 +35,13 @@ macro_rules! string_arg {
 }
 pub(crate) use string_arg;
 
+#[cfg(feature = "datetime")]
 macro_rules! datetime_arg {
     ($args:ident) => {
         arg!($args).unsafe_as_datetime()
     };
 }
+#[cfg(feature = "datetime")]
 pub(crate) use datetime_arg;
 
 macro_rules! return_scalar {
@@ -49,6 +51,13 @@ macro_rules! return_scalar {
 }
 pub(crate) use return_scalar;
 
+macro_rules! return_integer {
+    ( $value:expr) => {
+        Ok(Value::Quantity(Quantity::from_integer($value)))
+    };
+}
+pub(crate) use return_integer;
+
 macro_rules! return_quantity {
     ($value:expr, $unit:expr) => {
         Ok(Value::Quantity(Quantity::new_f64($value, $unit)))
@@ -77,9 +86,11 @@ macro_rules! return_string {
 }
 pub(crate) use return_string;
 
+#[cfg(feature = "datetime")]
 macro_rules! return_datetime {
     ($value:expr) => {
         Ok(Value::DateTime($value))
     };
 }
+#[cfg(feature = "datetime")]
 pub(crate) use return_datetime;
@@ -1,47 +1,65 @@
 use super::macros::*;
 use super::Args;
+use super::Callable;
 use super::Result;
 
+use crate::math;
 use crate::quantity::Quantity;
+use crate::unit::Unit;
 use crate::value::Value;
+use crate::RuntimeError;
 
 pub fn mod_(mut args: Args) -> Result<Value> {
     let x = quantity_arg!(args);
     let y = quantity_arg!(args);
 
+    let y = y.convert_to(x.unit()).unwrap();
+    let is_integer = x.is_integer() && y.is_integer();
+
     let x_value = x.unsafe_value().to_f64();
-    let y_value = y.convert_to(x.unit()).unwrap().unsafe_value().to_f64();
+    let y_value = y.unsafe_value().to_f64();
 
-    return_quantity!(x_value.rem_euclid(y_value), x.unit().clone())
+    let result = Quantity::new_f64(x_value.rem_euclid(y_value), x.unit().clone());
+    Ok(Value::Quantity(result.with_integer_flag(is_integer)))
 }
 
-// A simple math function with signature 'Dim D. Fn[(D) -> D]', which only operates on the value of the quantity
-macro_rules! simple_polymorphic_math_function {
+// A simple math function with signature 'Fn[(Scalar) -> Scalar]'
+macro_rules! simple_scalar_math_function {
     ($name:ident, $op:ident) => {
         pub fn $name(mut args: Args) -> Result<Value> {
-            let arg = quantity_arg!(args);
-
-            let value = arg.unsafe_value().to_f64();
-            return_quantity!(value.$op(), arg.unit().clone())
+            let value = scalar_arg!(args).to_f64();
+            return_scalar!(value.$op())
         }
     };
 }
 
-// Similar, but with signature 'Fn[(Scalar) -> Scalar]'
-macro_rules! simple_scalar_math_function {
+// A rounding function with signature 'Dim D. Fn[(D) -> D]'. The result is always an integer.
+macro_rules! rounding_function {
     ($name:ident, $op:ident) => {
         pub fn $name(mut args: Args) -> Result<Value> {
-            let value = scalar_arg!(args).to_f64();
-            return_scalar!(value.$op())
+            let arg = quantity_arg!(args);
+
+            let value = arg.unsafe_value().to_f64();
+            let result = Quantity::new_f64(value.$op(), arg.unit().clone());
+            Ok(Value::Quantity(result.with_integer_flag(true)))
         }
     };
 }
 
-simple_polymorphic_math_function!(abs, abs);
-simple_polymorphic_math_function!(round, round);
-simple_polymorphic_math_function!(floor, floor);
-simple_polymorphic_math_function!(ceil, ceil);
-simple_polymorphic_math_function!(trunc, trunc);
+pub fn abs(mut args: Args) -> Result<Value> {
+    let arg = quantity_arg!(args);
+
+    let value = arg.unsafe_value().to_f64();
+    let result = Quantity::new_f64(value.abs(), arg.unit().clone());
+    Ok(Value::Quantity(result.with_integer_flag(arg.is_integer())))
+}
+
+rounding_function!(round, round);
+rounding_function!(floor, floor);
+rounding_function!(ceil, ceil);
+rounding_function!(trunc, trunc);
+
+simple_scalar_math_function!(fract, fract);
 
 simple_scalar_math_function!(sin, sin);
 simple_scalar_math_function!(cos, cos);
@@ -50,6 +68,46 @@ simple_scalar_math_function!(asin, asin);
 simple_scalar_math_function!(acos, acos);
 simple_scalar_math_function!(atan, atan);
 
+/// Unlike `f64::signum`, this returns 0 for ±0.
+pub fn sign(mut args: Args) -> Result<Value> {
+    let value = quantity_arg!(args).unsafe_value().to_f64();
+
+    return_integer!(if value == 0.0 { 0.0 } else { value.signum() })
+}
+
+pub fn copysign(mut args: Args) -> Result<Value> {
+    let x = quantity_arg!(args);
+    let y = quantity_arg!(args);
+
+    let x_value = x.unsafe_value().to_f64();
+    let y_value = y.unsafe_value().to_f64();
+
+    return_quantity!(x_value.copysign(y_value), x.unit().clone())
+}
+
+/// If one of the arguments is NaN, the result is NaN.
+pub fn clamp(mut args: Args) -> Result<Value> {
+    let x = quantity_arg!(args);
+    let lower = quantity_arg!(args);
+    let upper = quantity_arg!(args);
+
+    if x.is_nan() || lower.is_nan() || upper.is_nan() {
+        return return_quantity!(f64::NAN, x.unit().clone());
+    }
+
+    if lower > upper {
+        return Err(RuntimeError::InvalidClampBounds(lower, upper));
+    }
+
+    Ok(Value::Quantity(if x < lower {
+        lower
+    } else if x > upper {
+        upper
+    } else {
+        x
+    }))
+}
+
 pub fn atan2(mut args: Args) -> Result<Value> {
     let y = quantity_arg!(args);
     let x = quantity_arg!(args);
@@ -60,6 +118,42 @@ pub fn atan2(mut args: Args) -> Result<Value> {
     return_scalar!(y_value.atan2(x_value))
 }
 
+/// Versions of the trigonometric functions for the case where `Angle` is a base
+/// dimension (with the given `radian` as its base unit). Arguments are converted
+/// to radians, and the inverse functions return their result in radians.
+pub(crate) fn strict_angle_function(name: &str, radian: &Unit) -> Option<Callable> {
+    fn from_angle(op: fn(f64) -> f64, radian: Unit) -> Callable {
+        Callable::Function(Box::new(move |mut args| {
+            let x = quantity_arg!(args);
+            let value = x.convert_to(&radian).unwrap().unsafe_value().to_f64();
+            return_scalar!(op(value))
+        }))
+    }
+
+    fn to_angle(op: fn(f64) -> f64, radian: Unit) -> Callable {
+        Callable::Function(Box::new(move |mut args| {
+            return_quantity!(op(scalar_arg!(args).to_f64()), radian.clone())
+        }))
+    }
+
+    let radian = radian.clone();
+    Some(match name {
+        "sin" => from_angle(f64::sin, radian),
+        "cos" => from_angle(f64::cos, radian),
+        "tan" => from_angle(f64::tan, radian),
+        "asin" => to_angle(f64::asin, radian),
+        "acos" => to_angle(f64::acos, radian),
+        "atan" => to_angle(f64::atan, radian),
+        "atan2" => Callable::Function(Box::new(move |args| {
+            let Value::Quantity(angle) = atan2(args)? else {
+                unreachable!()
+            };
+            return_quantity!(angle.unsafe_value().to_f64(), radian.clone())
+        })),
+        _ => return None,
+    })
+}
+
 simple_scalar_math_function!(sinh, sinh);
 simple_scalar_math_function!(cosh, cosh);
 simple_scalar_math_function!(tanh, tanh);
@@ -89,6 +183,50 @@ pub fn is_infinite(mut args: Args) -> Result<Value> {
     return_boolean!(arg.unsafe_value().to_f64().is_infinite())
 }
 
+/// The argument of `function` as an integer. Values that are not known to be
+/// integers, like the result of a division, are rejected.
+pub(super) fn integer_arg(function: &str, arg: Quantity) -> Result<i64> {
+    arg.as_integer()
+        .ok_or_else(|| RuntimeError::NonIntegerArgument(function.into(), arg.to_exact_string()))
+}
+
+/// Like `integer_arg`, but also rejects negative integers.
+pub(super) fn natural_arg(function: &str, arg: Quantity) -> Result<u64> {
+    let n = integer_arg(function, arg)?;
+    u64::try_from(n).map_err(|_| RuntimeError::NegativeArgument(function.into(), n.to_string()))
+}
+
+pub fn factorial(mut args: Args) -> Result<Value> {
+    let n = natural_arg("factorial", quantity_arg!(args))?;
+
+    let result =
+        math::factorial(n).ok_or_else(|| RuntimeError::IntegerResultTooLarge(format!("{n}!")))?;
+    return_integer!(result)
+}
+
+pub fn binomial(mut args: Args) -> Result<Value> {
+    let n = natural_arg("binomial", quantity_arg!(args))?;
+    let k = natural_arg("binomial", quantity_arg!(args))?;
+
+    let result = math::binomial(n, k)
+        .ok_or_else(|| RuntimeError::IntegerResultTooLarge(format!("binomial({n}, {k})")))?;
+    return_integer!(result)
+}
+
+pub fn gcd(mut args: Args) -> Result<Value> {
+    let a = integer_arg("gcd", quantity_arg!(args))?;
+    let b = integer_arg("gcd", quantity_arg!(args))?;
+
+    return_integer!(math::gcd(a, b) as f64)
+}
+
+pub fn lcm(mut args: Args) -> Result<Value> {
+    let a = integer_arg("lcm", quantity_arg!(args))?;
+    let b = integer_arg("lcm", quantity_arg!(args))?;
+
+    return_integer!(math::lcm(a, b) as f64)
+}
+
 pub fn random(_args: Args) -> Result<Value> {
     return_scalar!(rand::random::<f64>())
 }
@@ -0,0 +1,216 @@
+use std::collections::VecDeque;
+
+use super::macros::*;
+use super::{Args, Result};
+use crate::quantity::Quantity;
+use crate::unit::Unit;
+use crate::value::Value;
+use crate::RuntimeError;
+
+/// A matrix, as a list of rows of quantities
+type Matrix = Vec<Vec<Quantity>>;
+
+fn to_matrix(value: Value) -> Result<Matrix> {
+    let matrix: Matrix = value
+        .unsafe_as_list()
+        .iter()
+        .map(|row| {
+            row.clone()
+                .unsafe_as_list()
+                .iter()
+                .map(|element| element.clone().unsafe_as_quantity())
+                .collect()
+        })
+        .collect();
+
+    if let Some(first) = matrix.first() {
+        if let Some((i, row)) = matrix
+            .iter()
+            .enumerate()
+            .find(|(_, row)| row.len() != first.len())
+        {
+            return Err(RuntimeError::InvalidMatrixShape(format!(
+                "row {i} has {} element(s), but row 0 has {}",
+                row.len(),
+                first.len()
+            )));
+        }
+    }
+    Ok(matrix)
+}
+
+fn from_matrix(matrix: Matrix) -> Value {
+    matrix
+        .into_iter()
+        .map(|row| {
+            row.into_iter()
+                .map(Value::Quantity)
+                .collect::<VecDeque<_>>()
+                .into()
+        })
+        .collect::<VecDeque<_>>()
+        .into()
+}
+
+/// The shape of a matrix, like `2×3`
+fn shape(matrix: &Matrix) -> String {
+    format!("{}×{}", matrix.len(), matrix.first().map_or(0, Vec::len))
+}
+
+pub fn matmul(mut args: Args) -> Result<Value> {
+    let a = to_matrix(arg!(args))?;
+    let b = to_matrix(arg!(args))?;
+
+    let inner = a.first().map_or(0, Vec::len);
+    if inner == 0 || inner != b.len() {
+        return Err(RuntimeError::InvalidMatrixShape(format!(
+            "can not multiply {} by {}",
+            shape(&a),
+            shape(&b)
+        )));
+    }
+
+    let columns = b[0].len();
+    let mut product = Matrix::with_capacity(a.len());
+    for row in &a {
+        let mut product_row = Vec::with_capacity(columns);
+        for j in 0..columns {
+            let mut terms = row
+                .iter()
+                .zip(&b)
+                .map(|(x, b_row)| x.clone() * b_row[j].clone());
+            let mut element = terms.next().unwrap();
+            for term in terms {
+                element = (&element + &term).map_err(RuntimeError::QuantityError)?;
+            }
+            product_row.push(element.full_simplify());
+        }
+        product.push(product_row);
+    }
+
+    Ok(from_matrix(product))
+}
+
+pub fn transpose(mut args: Args) -> Result<Value> {
+    let matrix = to_matrix(arg!(args))?;
+    let columns = matrix.first().map_or(0, Vec::len);
+
+    let transposed = (0..columns)
+        .map(|j| matrix.iter().map(|row| row[j].clone()).collect())
+        .collect();
+
+    Ok(from_matrix(transposed))
+}
+
+pub fn identity(mut args: Args) -> Result<Value> {
+    let n = scalar_arg!(args).to_f64();
+    if n < 0.0 || n.fract() != 0.0 {
+        return Err(RuntimeError::InvalidMatrixShape(format!(
+            "the size of an identity matrix needs to be a non-negative integer, got {}",
+            Quantity::from_scalar(n)
+        )));
+    }
+    let n = n as usize;
+
+    let identity = (0..n)
+        .map(|i| {
+            (0..n)
+                .map(|j| Quantity::from_scalar(if i == j { 1.0 } else { 0.0 }))
+                .collect()
+        })
+        .collect();
+
+    Ok(from_matrix(identity))
+}
+
+/// The unit of the first non-zero quantity. Zeros are not converted, since they
+/// are simplified to scalars.
+fn common_unit<'a>(mut quantities: impl Iterator<Item = &'a Quantity>) -> Unit {
+    quantities
+        .find(|q| !q.is_zero())
+        .map_or_else(Unit::scalar, |q| q.unit().clone())
+}
+
+fn values_in(quantities: &[Quantity], unit: &Unit) -> Result<Vec<f64>> {
+    quantities
+        .iter()
+        .map(|q| {
+            if q.is_zero() {
+                return Ok(0.0);
+            }
+            q.convert_to(unit)
+                .map(|q| q.unsafe_value().to_f64())
+                .map_err(RuntimeError::QuantityError)
+        })
+        .collect()
+}
+
+/// Solve `A x = b` with Gaussian elimination and partial pivoting. The elements
+/// of `A` and `b` are converted to a common unit each, the solution is given in
+/// the quotient of these units.
+pub fn solve_linear(mut args: Args) -> Result<Value> {
+    let a = to_matrix(arg!(args))?;
+    let b: Vec<Quantity> = list_arg!(args)
+        .iter()
+        .map(|element| element.clone().unsafe_as_quantity())
+        .collect();
+
+    let n = a.len();
+    if n == 0 || a[0].len() != n {
+        return Err(RuntimeError::InvalidMatrixShape(format!(
+            "can not solve a linear system with a non-square {} matrix",
+            shape(&a)
+        )));
+    }
+    if b.len() != n {
+        return Err(RuntimeError::InvalidMatrixShape(format!(
+            "can not solve a linear system with a {} matrix and a right-hand side of length {}",
+            shape(&a),
+            b.len()
+        )));
+    }
+
+    let a_unit = common_unit(a.iter().flatten());
+    let b_unit = common_unit(b.iter());
+    let mut m = a
+        .iter()
+        .map(|row| values_in(row, &a_unit))
+        .collect::<Result<Vec<_>>>()?;
+    let mut x = values_in(&b, &b_unit)?;
+
+    // Pivots below this are considered to be zero
+    let scale = m.iter().flatten().fold(0.0f64, |max, v| max.max(v.abs()));
+    let tolerance = scale * n as f64 * f64::EPSILON;
+
+    for column in 0..n {
+        let pivot = (column..n)
+            .max_by(|&i, &j| m[i][column].abs().total_cmp(&m[j][column].abs()))
+            .unwrap();
+        if m[pivot][column].abs() <= tolerance || m[pivot][column].is_nan() {
+            return Err(RuntimeError::SingularMatrix(column));
+        }
+        m.swap(column, pivot);
+        x.swap(column, pivot);
+
+        for row in column + 1..n {
+            let factor = m[row][column] / m[column][column];
+            let (upper, lower) = m.split_at_mut(row);
+            for (x, pivot_x) in lower[0][column..].iter_mut().zip(&upper[column][column..]) {
+                *x -= factor * pivot_x;
+            }
+            x[row] -= factor * x[column];
+        }
+    }
+
+    for row in (0..n).rev() {
+        let known: f64 = (row + 1..n).map(|k| m[row][k] * x[k]).sum();
+        x[row] = (x[row] - known) / m[row][row];
+    }
+
+    let unit = b_unit / a_unit;
+    let solution = x
+        .into_iter()
+        .map(|value| Value::Quantity(Quantity::new_f64(value, unit.clone()).full_simplify()))
+        .collect::<VecDeque<_>>();
+    Ok(solution.into())
+}
@@ -1,20 +1,44 @@
+#[cfg(feature = "datetime")]
+mod benchmark;
 mod currency;
+#[cfg(feature = "datetime")]
 mod datetime;
+mod files;
 mod functions;
+mod json;
 mod lists;
 mod lookup;
 mod macros;
 mod math;
+mod matrices;
 mod procedures;
+mod quantities;
+mod script;
+mod series;
 mod strings;
+mod ucum;
 
 use std::collections::VecDeque;
+use std::sync::Arc;
 
+#[cfg(feature = "datetime")]
+use jiff::tz::TimeZone;
+use jiff::Zoned;
+
+use crate::file_system::FileSystem;
 use crate::interpreter::RuntimeError;
+use crate::regex_cache::RegexCache;
+use crate::sandbox::{Capability, SandboxPolicy};
+use crate::sequence::FunctionCaller;
 use crate::span::Span;
+use crate::typed_ast::{DType, Type};
+use crate::unit::Unit;
+use crate::unit_registry::UnitRegistry;
 use crate::value::Value;
 use crate::vm::ExecutionContext;
 
+pub(crate) use series::{series_step_info, TIME_SERIES_FUNCTIONS};
+
 type ControlFlow = std::ops::ControlFlow<RuntimeError>;
 
 pub(crate) type ArityRange = std::ops::RangeInclusive<usize>;
@@ -25,9 +49,60 @@ pub(crate) type Args = VecDeque<Value>;
 
 type BoxedFunction = Box<dyn Fn(Args) -> Result<Value> + Send + Sync>;
 
+/// A function provided by the embedding application. Errors are reported as plain
+/// messages and turned into runtime errors by the VM.
+pub(crate) type NativeCallback =
+    dyn Fn(Vec<Value>) -> std::result::Result<Value, String> + Send + Sync;
+
 pub(crate) enum Callable {
     Function(BoxedFunction),
+    /// A function that operates on lists and lazy sequences. It can call function
+    /// values (like `f` in `map(f, xs)`) through the given [`FunctionCaller`].
+    /// Arguments of all other callables are forced into lists before the call.
+    SequenceFunction(fn(&mut dyn FunctionCaller, Args) -> Result<Value>),
     Procedure(fn(&mut ExecutionContext, Args, Vec<Span>) -> ControlFlow),
+    /// A procedure that writes files through the file system of the VM, see
+    /// [`crate::Context::set_file_system`].
+    FileProcedure(fn(&dyn FileSystem, Args) -> ControlFlow),
+    /// A function that needs the current time, which is provided by the clock of
+    /// the VM.
+    #[cfg_attr(not(feature = "datetime"), allow(dead_code))]
+    ClockFunction(fn(Zoned, Args) -> Result<Value>),
+    /// A function that needs the metadata of units (e.g. the number of decimal
+    /// places of a currency) or looks up units by name, which is provided by the
+    /// VM through the given [`UnitLookup`].
+    UnitFunction(fn(&dyn UnitLookup, Args) -> Result<Value>),
+    /// A function that matches strings against regular expressions. Compiled
+    /// patterns are cached by the VM in the given [`RegexCache`].
+    RegexFunction(fn(&mut RegexCache, Args) -> Result<Value>),
+    /// A function that needs the arguments that were passed to the script, see
+    /// [`crate::Context::set_script_arguments`].
+    ScriptFunction(fn(&[String], Args) -> Result<Value>),
+    /// A function that constructs a value of its result type at the call site
+    /// (like `parse_json`), which needs to be fully known. Units can be looked up
+    /// by name through the given [`UnitLookup`].
+    TypedFunction(fn(&dyn UnitLookup, &Type, Args) -> Result<Value>),
+    /// Like [`Callable::SequenceFunction`], but also receives the result type at
+    /// the call site, if it is fully known (like `sum_series`, which needs it to
+    /// construct the result for an empty range).
+    TypedSequenceFunction(fn(&mut dyn FunctionCaller, Option<&Type>, Args) -> Result<Value>),
+    /// A function registered with [`crate::Context::register_native_function`],
+    /// along with its declared return type (if it does not depend on type
+    /// parameters).
+    Native(Arc<NativeCallback>, Option<Type>),
+}
+
+/// Access to the units that are defined at the time of a call.
+pub(crate) trait UnitLookup {
+    /// A (possibly prefixed) unit with the given name, e.g. `km` or `kilometer`.
+    fn lookup_unit(&self, name: &str) -> Option<Unit>;
+
+    /// The physical dimension of a unit, or `None` if one of its base units has
+    /// no known dimension.
+    fn dimension_of(&self, unit: &Unit) -> Option<DType>;
+
+    /// The registry with the metadata of all units.
+    fn unit_registry(&self) -> &UnitRegistry;
 }
 
 pub(crate) struct ForeignFunction {
@@ -36,5 +111,51 @@ pub(crate) struct ForeignFunction {
     pub(crate) callable: Callable,
 }
 
+/// Foreign functions whose results are not determined by their arguments alone.
+/// Functions calling them are never evaluated in parallel.
+pub(crate) const NONDETERMINISTIC_FUNCTIONS: &[&str] = &[
+    "random",
+    "now",
+    "_benchmark_seconds",
+    "exchange_rate",
+    "exchange_rate_age",
+    "exchange_rate_timestamp",
+];
+
+/// A replacement for a foreign function that accesses the outside world, if the
+/// given policy restricts it.
+#[cfg_attr(
+    not(any(feature = "datetime", feature = "currency")),
+    allow(unreachable_code)
+)]
+pub(crate) fn sandboxed_function(name: &str, policy: &SandboxPolicy) -> Option<Callable> {
+    if name == "env" && !policy.allows(Capability::Environment) {
+        return Some(Callable::Function(Box::new(|_| {
+            Err(RuntimeError::PermissionDenied(Capability::Environment))
+        })));
+    }
+
+    if !policy.is_strict() {
+        return None;
+    }
+
+    Some(match name {
+        #[cfg(feature = "datetime")]
+        "get_local_timezone" => Callable::Function(Box::new(|_| Ok(Value::String("UTC".into())))),
+        #[cfg(feature = "datetime")]
+        "datetime" => Callable::Function(Box::new(|args| {
+            datetime::datetime_in(args, || TimeZone::UTC)
+        })),
+        #[cfg(feature = "datetime")]
+        "from_unixtime" => Callable::Function(Box::new(|args| {
+            datetime::from_unixtime_in(args, || TimeZone::UTC)
+        })),
+        #[cfg(feature = "currency")]
+        "exchange_rate" => Callable::SequenceFunction(currency::exchange_rate_offline),
+        _ => return None,
+    })
+}
+
 pub(crate) use functions::functions;
+pub(crate) use math::strict_angle_function;
 pub(crate) use procedures::procedures;
@@ -1,44 +1,67 @@
 use std::collections::HashMap;
 
-use std::sync::OnceLock;
+use std::sync::{Arc, OnceLock};
 
 use super::macros::*;
 use crate::{
-    ast::ProcedureKind, ffi::ControlFlow, pretty_print::PrettyPrint, span::Span, value::Value,
-    vm::ExecutionContext, RuntimeError,
+    ast::ProcedureKind,
+    ffi::ControlFlow,
+    output::{AssertionKind, AssertionResult},
+    pretty_print::PrettyPrint,
+    span::Span,
+    value::Value,
+    vm::ExecutionContext,
+    RuntimeError,
 };
 
+use super::files::{append_csv, write_text};
 use super::{Args, Callable, ForeignFunction};
 
-static FFI_PROCEDURES: OnceLock<HashMap<ProcedureKind, ForeignFunction>> = OnceLock::new();
+static FFI_PROCEDURES: OnceLock<HashMap<ProcedureKind, Arc<ForeignFunction>>> = OnceLock::new();
 
-pub(crate) fn procedures() -> &'static HashMap<ProcedureKind, ForeignFunction> {
+pub(crate) fn procedures() -> &'static HashMap<ProcedureKind, Arc<ForeignFunction>> {
     FFI_PROCEDURES.get_or_init(|| {
         let mut m = HashMap::new();
 
         m.insert(
             ProcedureKind::Print,
-            ForeignFunction {
+            Arc::new(ForeignFunction {
                 name: "print".into(),
                 arity: 0..=1,
                 callable: Callable::Procedure(print),
-            },
+            }),
         );
         m.insert(
             ProcedureKind::Assert,
-            ForeignFunction {
+            Arc::new(ForeignFunction {
                 name: "assert".into(),
                 arity: 1..=1,
                 callable: Callable::Procedure(assert),
-            },
+            }),
         );
         m.insert(
             ProcedureKind::AssertEq,
-            ForeignFunction {
+            Arc::new(ForeignFunction {
                 name: "assert_eq".into(),
                 arity: 2..=3,
                 callable: Callable::Procedure(assert_eq),
-            },
+            }),
+        );
+        m.insert(
+            ProcedureKind::WriteText,
+            Arc::new(ForeignFunction {
+                name: "write_text".into(),
+                arity: 2..=2,
+                callable: Callable::FileProcedure(write_text),
+            }),
+        );
+        m.insert(
+            ProcedureKind::AppendCsv,
+            Arc::new(ForeignFunction {
+                name: "append_csv".into(),
+                arity: 2..=2,
+                callable: Callable::FileProcedure(append_csv),
+            }),
         );
         // Note: The 'type' procedure is missing here because it has special handling code in the compiler
 
@@ -50,28 +73,73 @@ fn print(ctx: &mut ExecutionContext, mut args: Args, _: Vec<Span>) -> ControlFlo
     assert!(args.len() <= 1);
 
     if args.is_empty() {
-        (ctx.print_fn)(&crate::markup::text(""))
+        ctx.output.print(&crate::markup::text(""))
     } else {
         match arg!(args) {
-            Value::String(string) => (ctx.print_fn)(&crate::markup::text(string)), // print string without quotes
-            arg => (ctx.print_fn)(&arg.pretty_print()),
+            Value::String(string) => ctx.output.print(&crate::markup::text(string)), // print string without quotes
+            arg => ctx.output.print(&arg.pretty_print()),
         }
     }
 
     ControlFlow::Continue(())
 }
 
-fn assert(_: &mut ExecutionContext, mut args: Args, arg_spans: Vec<Span>) -> ControlFlow {
+/// Report the outcome of an assertion to the output sink. Other errors (e.g. for
+/// incompatible units in `assert_eq`) are not assertion results.
+fn report_assertion(
+    ctx: &mut ExecutionContext,
+    kind: AssertionKind,
+    values: Vec<Value>,
+    spans: Vec<Span>,
+    result: &ControlFlow,
+) {
+    let passed = match result {
+        ControlFlow::Continue(()) => true,
+        ControlFlow::Break(
+            RuntimeError::AssertFailed(..)
+            | RuntimeError::AssertEq2Failed(..)
+            | RuntimeError::AssertEq3Failed(..),
+        ) => false,
+        ControlFlow::Break(_) => return,
+    };
+
+    ctx.output.assertion(&AssertionResult {
+        kind,
+        passed,
+        values,
+        spans,
+    });
+}
+
+fn assert(ctx: &mut ExecutionContext, mut args: Args, arg_spans: Vec<Span>) -> ControlFlow {
     assert!(args.len() == 1);
 
-    if arg!(args).unsafe_as_bool() {
+    let condition = arg!(args);
+    let result = if condition.clone().unsafe_as_bool() {
         ControlFlow::Continue(())
     } else {
         ControlFlow::Break(RuntimeError::AssertFailed(arg_spans[0]))
-    }
+    };
+
+    report_assertion(
+        ctx,
+        AssertionKind::Assert,
+        vec![condition],
+        arg_spans,
+        &result,
+    );
+    result
+}
+
+fn assert_eq(ctx: &mut ExecutionContext, args: Args, arg_spans: Vec<Span>) -> ControlFlow {
+    let values = args.iter().cloned().collect();
+    let result = check_assert_eq(args, &arg_spans);
+
+    report_assertion(ctx, AssertionKind::AssertEq, values, arg_spans, &result);
+    result
 }
 
-fn assert_eq(_: &mut ExecutionContext, mut args: Args, arg_spans: Vec<Span>) -> ControlFlow {
+fn check_assert_eq(mut args: Args, arg_spans: &[Span]) -> ControlFlow {
     assert!(args.len() == 2 || args.len() == 3);
 
     let span_lhs = arg_spans[0];
@@ -0,0 +1,225 @@
+//! Reading quantities and units from strings, e.g. `3.5 km` or `m·s⁻²`, and
+//! choosing the units of quantities.
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+use super::macros::*;
+use super::{Args, Result, UnitLookup};
+use crate::arithmetic::{Exponent, Power};
+use crate::number::Number;
+use crate::pretty_print::PrettyPrint;
+use crate::quantity::Quantity;
+use crate::unit::Unit;
+use crate::value::Value;
+use crate::RuntimeError;
+
+/// Reads a quantity like `3.5 km` and converts it to the unit of the target,
+/// which has to have the same dimension.
+pub fn parse_quantity(units: &dyn UnitLookup, mut args: Args) -> Result<Value> {
+    let input = string_arg!(args);
+    let target = quantity_arg!(args);
+
+    let error = |message: String| RuntimeError::QuantityParsingError(input.clone(), message);
+
+    let (number, rest) = split_number(input.trim());
+    let rest = rest.trim_start();
+    let value: f64 = number
+        .replace('_', "")
+        .parse()
+        .map_err(|_| error("expected a number".into()))?;
+    let unit = parse_unit(rest, units).ok_or_else(|| error(format!("unknown unit '{rest}'")))?;
+
+    if let (Some(actual), Some(expected)) =
+        (units.dimension_of(&unit), units.dimension_of(target.unit()))
+    {
+        if actual.to_base_representation() != expected.to_base_representation() {
+            let actual = if unit.is_scalar() {
+                "a number without unit".to_string()
+            } else {
+                format!("'{unit}' of type {}", actual.pretty_print())
+            };
+            return Err(error(format!(
+                "expected a quantity of type {}, got {actual}",
+                expected.pretty_print()
+            )));
+        }
+    }
+
+    let quantity = Quantity::new(Number::from_f64(value), unit)
+        .convert_to(target.unit())
+        .map_err(RuntimeError::QuantityError)?;
+    Ok(Value::Quantity(quantity))
+}
+
+/// Re-expresses a quantity in its simplest unit (see [`crate::unit_registry::UnitRegistry::simplify`]),
+/// or in the units of a unit system if its name is given.
+pub fn simplify(units: &dyn UnitLookup, mut args: Args) -> Result<Value> {
+    let quantity = quantity_arg!(args);
+    let system = string_arg!(args);
+
+    let registry = units.unit_registry();
+    let simplified = if system.is_empty() {
+        registry.simplify(&quantity)
+    } else {
+        registry
+            .simplify_in_unit_system(&system, &quantity)
+            .map_err(RuntimeError::UnitRegistryError)?
+    };
+    Ok(Value::Quantity(simplified))
+}
+
+/// Splits off a leading number like `-1_000.5e3`, which may be directly
+/// followed by a unit (`3.5km`).
+fn split_number(input: &str) -> (&str, &str) {
+    let bytes = input.as_bytes();
+    let digits = |mut i: usize| {
+        while i < bytes.len() && (bytes[i].is_ascii_digit() || bytes[i] == b'_') {
+            i += 1;
+        }
+        i
+    };
+
+    let mut end = if matches!(bytes.first(), Some(b'-' | b'+')) {
+        1
+    } else {
+        0
+    };
+    end = digits(end);
+    if bytes.get(end) == Some(&b'.') {
+        end = digits(end + 1);
+    }
+    if matches!(bytes.get(end), Some(b'e' | b'E')) {
+        let sign = usize::from(matches!(bytes.get(end + 1), Some(b'-' | b'+')));
+        if bytes.get(end + 1 + sign).is_some_and(u8::is_ascii_digit) {
+            end = digits(end + 1 + sign);
+        }
+    }
+
+    input.split_at(end)
+}
+
+/// Reads a unit in the format that is used to print units, e.g. `km/h`, `m·s⁻²`
+/// or `kg/(m·s)`. Products can also be written with `*`, and exponents as `^2`,
+/// `^(-1)` or `^(1/2)`.
+pub(super) fn parse_unit(input: &str, units: &dyn UnitLookup) -> Option<Unit> {
+    let mut parser = UnitParser {
+        chars: input.chars().peekable(),
+        units,
+    };
+
+    parser.skip_whitespace();
+    if parser.chars.peek().is_none() {
+        return Some(Unit::scalar());
+    }
+
+    let unit = parser.product()?;
+    parser.chars.peek().is_none().then_some(unit)
+}
+
+struct UnitParser<'a> {
+    chars: Peekable<Chars<'a>>,
+    units: &'a dyn UnitLookup,
+}
+
+impl UnitParser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn product(&mut self) -> Option<Unit> {
+        let mut unit = self.power()?;
+        loop {
+            match self.chars.peek() {
+                Some('·' | '*' | '×') => {
+                    self.chars.next();
+                    unit = unit * self.power()?;
+                }
+                Some('/') => {
+                    self.chars.next();
+                    unit = unit / self.power()?;
+                }
+                _ => return Some(unit),
+            }
+        }
+    }
+
+    fn power(&mut self) -> Option<Unit> {
+        self.skip_whitespace();
+
+        let unit = if self.chars.next_if_eq(&'(').is_some() {
+            let unit = self.product()?;
+            self.chars.next_if_eq(&')')?;
+            unit
+        } else {
+            let name: String = std::iter::from_fn(|| {
+                self.chars.next_if(|&c| {
+                    !c.is_whitespace() && !"·*×/^()".contains(c) && superscript_digit(c).is_none()
+                })
+            })
+            .collect();
+            self.units.lookup_unit(&name)?
+        };
+
+        let unit = match self.exponent()? {
+            Some(exponent) => unit.power(exponent),
+            None => unit,
+        };
+
+        self.skip_whitespace();
+        Some(unit)
+    }
+
+    /// Returns `Some(None)` if there is no exponent, and `None` if it is invalid.
+    fn exponent(&mut self) -> Option<Option<Exponent>> {
+        if self.chars.next_if_eq(&'^').is_some() {
+            let parenthesized = self.chars.next_if_eq(&'(').is_some();
+            let text: String = std::iter::from_fn(|| {
+                self.chars
+                    .next_if(|&c| c.is_ascii_digit() || c == '-' || parenthesized && c == '/')
+            })
+            .collect();
+            if parenthesized {
+                self.chars.next_if_eq(&')')?;
+            }
+
+            let exponent = match text.split_once('/') {
+                Some((numerator, denominator)) => {
+                    let denominator: i128 = denominator.parse().ok()?;
+                    if denominator == 0 {
+                        return None;
+                    }
+                    Exponent::new(numerator.parse().ok()?, denominator)
+                }
+                None => Exponent::from_integer(text.parse().ok()?),
+            };
+            return Some(Some(exponent));
+        }
+
+        let text: String =
+            std::iter::from_fn(|| self.chars.next_if(|&c| superscript_digit(c).is_some()))
+                .map(|c| superscript_digit(c).unwrap())
+                .collect();
+        if text.is_empty() {
+            return Some(None);
+        }
+        Some(Some(Exponent::from_integer(text.parse().ok()?)))
+    }
+}
+
+fn superscript_digit(c: char) -> Option<char> {
+    Some(match c {
+        '⁻' => '-',
+        '⁰' => '0',
+        '¹' => '1',
+        '²' => '2',
+        '³' => '3',
+        '⁴' => '4',
+        '⁵' => '5',
+        '⁶' => '6',
+        '⁷' => '7',
+        '⁸' => '8',
+        '⁹' => '9',
+        _ => return None,
+    })
+}
@@ -0,0 +1,41 @@
+//! Access to the arguments and the environment of a script.
+
+use std::collections::VecDeque;
+
+use super::macros::*;
+use super::{Args, Result};
+use crate::quantity::Quantity;
+use crate::value::Value;
+use crate::RuntimeError;
+
+pub fn args(script_arguments: &[String], _args: Args) -> Result<Value> {
+    Ok(Value::from(
+        script_arguments
+            .iter()
+            .map(|argument| Value::String(argument.clone()))
+            .collect::<VecDeque<_>>(),
+    ))
+}
+
+pub fn arg(script_arguments: &[String], mut args: Args) -> Result<Value> {
+    let index = quantity_arg!(args).unsafe_value().to_f64();
+
+    let argument = (index >= 0.0 && index.fract() == 0.0)
+        .then(|| script_arguments.get(index as usize))
+        .flatten()
+        .ok_or_else(|| RuntimeError::MissingScriptArgument {
+            index: Quantity::from_scalar(index).to_string(),
+            count: script_arguments.len(),
+        })?;
+
+    return_string!(argument.clone())
+}
+
+pub fn env(mut args: Args) -> Result<Value> {
+    let name = string_arg!(args);
+
+    match std::env::var_os(&name) {
+        Some(value) => return_string!(value.to_string_lossy().into_owned()),
+        None => Err(RuntimeError::EnvironmentVariableNotSet(name)),
+    }
+}
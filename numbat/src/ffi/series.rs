@@ -0,0 +1,232 @@
+//! Functions for time series: lists of structs with a field `time: DateTime`
+//! and a field `value` of some dimension, sorted by time. The struct can have
+//! other fields as well. The shape of the elements is checked by the type
+//! checker, see [`TIME_SERIES_FUNCTIONS`].
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use indexmap::IndexMap;
+use itertools::Itertools;
+use jiff::{Timestamp, Zoned};
+
+use super::macros::*;
+use super::{Args, Result, UnitLookup};
+use crate::duration::Duration;
+use crate::list::NumbatList;
+use crate::number::Number;
+use crate::quantity::Quantity;
+use crate::sequence::{force_value, FunctionCaller};
+use crate::span::Span;
+use crate::symbol::Symbol;
+use crate::typed_ast::{DType, StructInfo, Type};
+use crate::unit::Unit;
+use crate::value::Value;
+use crate::RuntimeError;
+
+/// Foreign functions whose first argument is a time series. Their signatures
+/// can not express the shape of its elements, so the type checker adds it.
+pub(crate) const TIME_SERIES_FUNCTIONS: &[&str] = &["diff_series", "resample", "integrate_series"];
+
+/// The struct type of the elements of `diff_series(xs)`, for a time series
+/// whose values have the given type.
+pub(crate) fn series_step_info(value_type: Type) -> StructInfo {
+    let span = Span::dummy();
+
+    let mut fields = IndexMap::new();
+    fields.insert("time".into(), (span, Type::DateTime));
+    fields.insert(
+        "duration".into(),
+        (span, Type::Dimension(DType::base_dimension("Time"))),
+    );
+    fields.insert("delta".into(), (span, value_type));
+
+    StructInfo {
+        definition_span: span,
+        name: "SeriesStep".into(),
+        fields,
+    }
+}
+
+struct Sample<'a> {
+    time: Zoned,
+    value: Value,
+    /// The struct instance this sample has been taken from
+    info: &'a Arc<StructInfo>,
+    fields: &'a [Value],
+}
+
+/// The samples of a time series. Fails if they are not sorted by time.
+fn samples<'a>(function_name: &str, xs: &'a NumbatList<Value>) -> Result<Vec<Sample<'a>>> {
+    let mut samples: Vec<Sample> = Vec::with_capacity(xs.len());
+    for (index, element) in xs.iter().enumerate() {
+        let Value::StructInstance(info, fields) = element else {
+            unreachable!("Elements of time series are structs");
+        };
+        let field = |name: &str| fields[field_index(info, name)].clone();
+
+        let time = field("time").unsafe_as_datetime();
+        if samples.last().is_some_and(|previous| time < previous.time) {
+            return Err(RuntimeError::UnsortedTimeSeries(
+                function_name.into(),
+                index,
+            ));
+        }
+
+        samples.push(Sample {
+            time,
+            value: field("value"),
+            info,
+            fields,
+        });
+    }
+    Ok(samples)
+}
+
+fn field_index(info: &StructInfo, name: &str) -> usize {
+    info.fields.get_index_of(&Symbol::new(name)).unwrap()
+}
+
+fn second(units: &dyn UnitLookup) -> Unit {
+    // Just like for the difference of two `DateTime`s, durations are measured in seconds
+    units
+        .lookup_unit("second")
+        .expect("The unit 'second' is defined along with the time series functions")
+}
+
+/// Differences between consecutive samples, as a list of `SeriesStep`s.
+pub fn diff_series(units: &dyn UnitLookup, mut args: Args) -> Result<Value> {
+    let xs = list_arg!(args);
+    let samples = samples("diff_series", &xs)?;
+    let second = second(units);
+
+    let steps = samples
+        .windows(2)
+        .map(|pair| {
+            let [start, end] = pair else { unreachable!() };
+
+            let value_type = end.info.fields[field_index(end.info, "value")].1.clone();
+            let duration = Duration::between(&start.time, &end.time, second.clone())
+                .ok_or(RuntimeError::DurationOutOfRange)?;
+            let delta = (&end.value.clone().unsafe_as_quantity()
+                - &start.value.clone().unsafe_as_quantity())
+                .map_err(RuntimeError::QuantityError)?;
+
+            Ok(Value::StructInstance(
+                Arc::new(series_step_info(value_type)),
+                vec![
+                    Value::DateTime(end.time.clone()),
+                    Value::Duration(duration),
+                    Value::Quantity(delta),
+                ],
+            ))
+        })
+        .collect::<Result<VecDeque<_>>>()?;
+
+    Ok(Value::from(steps))
+}
+
+/// Groups the samples into buckets of the given interval and combines the values
+/// in each bucket with the given aggregation function. Buckets start at multiples
+/// of the interval since the Unix epoch, so they do not depend on the time zone.
+pub fn resample(caller: &mut dyn FunctionCaller, mut args: Args) -> Result<Value> {
+    let xs = force_value(arg!(args), caller)?.unsafe_as_list();
+    let interval = arg!(args);
+    let aggregate = arg!(args);
+
+    let interval_nanoseconds = match &interval {
+        Value::Duration(duration) => duration.as_nanoseconds(),
+        quantity => {
+            let seconds = quantity
+                .clone()
+                .unsafe_as_quantity()
+                .to_base_unit_representation()
+                .unsafe_value()
+                .to_f64();
+            (seconds * 1e9).round() as i128
+        }
+    };
+    if interval_nanoseconds <= 0 {
+        return Err(RuntimeError::InvalidResamplingInterval(
+            interval.to_string(),
+        ));
+    }
+
+    let samples = samples("resample", &xs)?;
+    let bucket = |sample: &Sample| {
+        sample
+            .time
+            .timestamp()
+            .as_nanosecond()
+            .div_euclid(interval_nanoseconds)
+    };
+
+    let mut resampled = VecDeque::new();
+    for (bucket, bucket_samples) in &samples.iter().group_by(|sample| bucket(sample)) {
+        let bucket_samples = bucket_samples.collect_vec();
+        let first = bucket_samples[0];
+
+        let start = Timestamp::from_nanosecond(bucket * interval_nanoseconds)
+            .map_err(|_| RuntimeError::DateTimeOutOfRange)?
+            .to_zoned(first.time.time_zone().clone());
+
+        let values = bucket_samples
+            .iter()
+            .map(|sample| sample.value.clone())
+            .collect::<VecDeque<_>>();
+        let value = caller.call(&aggregate, vec![Value::from(values)])?;
+        let value = force_value(value, caller)?;
+
+        // All other fields are taken from the first sample in the bucket
+        let mut fields = first.fields.to_vec();
+        fields[field_index(first.info, "time")] = Value::DateTime(start);
+        fields[field_index(first.info, "value")] = value;
+        resampled.push_back(Value::StructInstance(first.info.clone(), fields));
+    }
+
+    Ok(Value::from(resampled))
+}
+
+/// The integral of a time series over time, using the trapezoidal rule.
+pub fn integrate_series(
+    caller: &mut dyn FunctionCaller,
+    result_type: Option<&Type>,
+    mut args: Args,
+) -> Result<Value> {
+    let xs = force_value(arg!(args), caller)?.unsafe_as_list();
+    let samples = samples("integrate_series", &xs)?;
+
+    let Some(units) = caller.unit_lookup() else {
+        unreachable!("Units can be looked up during a call");
+    };
+    let second = second(units);
+
+    let Some(first) = samples.first() else {
+        let zero = match result_type {
+            Some(Type::Dimension(dtype)) => super::lists::zero(units, dtype),
+            _ => Quantity::from_scalar(0.0),
+        };
+        return Ok(Value::Quantity(zero));
+    };
+
+    let first_value = first.value.clone().unsafe_as_quantity();
+    let mut integral = Quantity::new(
+        Number::from_f64(0.0),
+        first_value.unit().clone() * second.clone(),
+    );
+    for pair in samples.windows(2) {
+        let [start, end] = pair else { unreachable!() };
+
+        let duration = Duration::between(&start.time, &end.time, second.clone())
+            .ok_or(RuntimeError::DurationOutOfRange)?
+            .to_quantity();
+        let sum = (&start.value.clone().unsafe_as_quantity()
+            + &end.value.clone().unsafe_as_quantity())
+            .map_err(RuntimeError::QuantityError)?;
+        let area = sum * duration / Quantity::from_scalar(2.0);
+
+        integral = (&integral + &area).map_err(RuntimeError::QuantityError)?;
+    }
+
+    Ok(Value::Quantity(integral))
+}
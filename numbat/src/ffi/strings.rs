@@ -1,7 +1,10 @@
 use super::macros::*;
+use super::math::natural_arg;
 use super::Args;
 use super::Result;
+use crate::list::NumbatList;
 use crate::quantity::Quantity;
+use crate::regex_cache::RegexCache;
 use crate::value::Value;
 use crate::RuntimeError;
 
@@ -36,6 +39,24 @@ pub fn chr(mut args: Args) -> Result<Value> {
     return_string!(output)
 }
 
+pub fn number_to_words(mut args: Args) -> Result<Value> {
+    let number = quantity_arg!(args);
+
+    match number.unsafe_value().to_words() {
+        Some(words) => return_string!(words),
+        None => {
+            // Show all digits of integers that are slightly out of range
+            let value = number.unsafe_value().to_f64();
+            let number = if value.fract() == 0.0 && value.abs() < 1e18 {
+                (value as i64).to_string()
+            } else {
+                number.to_string()
+            };
+            Err(RuntimeError::CanNotSpellOutNumber(number))
+        }
+    }
+}
+
 pub fn ord(mut args: Args) -> Result<Value> {
     let input = string_arg!(args);
 
@@ -47,3 +68,38 @@ pub fn ord(mut args: Args) -> Result<Value> {
 
     return_scalar!(output as f64)
 }
+
+pub fn str_match(regexes: &mut RegexCache, mut args: Args) -> Result<Value> {
+    let input = string_arg!(args);
+    let pattern = string_arg!(args);
+
+    return_boolean!(regexes.get(&pattern)?.is_match(&input))
+}
+
+pub fn str_extract(regexes: &mut RegexCache, mut args: Args) -> Result<Value> {
+    let input = string_arg!(args);
+    let pattern = string_arg!(args);
+    let group = natural_arg("str_extract", quantity_arg!(args))? as usize;
+
+    let regex = regexes.get(&pattern)?;
+    if group >= regex.captures_len() {
+        return Err(RuntimeError::NoSuchRegexGroup(pattern, group));
+    }
+
+    match regex.captures(&input).and_then(|c| c.get(group)) {
+        Some(m) => return_string!(m.as_str()),
+        None => Err(RuntimeError::RegexGroupDidNotMatch(pattern, group, input)),
+    }
+}
+
+pub fn str_extract_all(regexes: &mut RegexCache, mut args: Args) -> Result<Value> {
+    let input = string_arg!(args);
+    let pattern = string_arg!(args);
+
+    let mut list = NumbatList::new();
+    for m in regexes.get(&pattern)?.find_iter(&input) {
+        list.push_back(Value::String(m.as_str().into()));
+    }
+
+    return_list!(list)
+}
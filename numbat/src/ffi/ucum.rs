@@ -0,0 +1,350 @@
+//! Writing and reading units in the format of the Unified Code for Units of
+//! Measure (UCUM), e.g. `mg/dL`, `kg.m/s2` or `mm[Hg]`.
+//!
+//! The UCUM codes of units are given by their `@ucum` decorator. Prefixes are
+//! written as in UCUM (`u` for micro, `da` for deca, `Ki` for kibi, …), products
+//! with `.` and divisions with `/`. Integer exponents follow the unit directly
+//! (`m2`, `s-1`), and annotations in curly braces (`{cells}`) are ignored.
+
+use std::fmt::Write;
+
+use super::macros::*;
+use super::{Args, Result, UnitLookup};
+use crate::arithmetic::{Exponent, Power};
+use crate::number::Number;
+use crate::prefix::Prefix;
+use crate::pretty_print::PrettyPrint;
+use crate::quantity::Quantity;
+use crate::unit::Unit;
+use crate::value::Value;
+use crate::RuntimeError;
+
+/// The UCUM symbols of the metric and binary prefixes, with the ones that have
+/// two letters first (to read `dam` as deca-metre instead of deci-am…)
+const PREFIXES: &[(&str, Prefix)] = &[
+    ("da", Prefix::Metric(1)),
+    ("Ki", Prefix::Binary(10)),
+    ("Mi", Prefix::Binary(20)),
+    ("Gi", Prefix::Binary(30)),
+    ("Ti", Prefix::Binary(40)),
+    ("Y", Prefix::Metric(24)),
+    ("Z", Prefix::Metric(21)),
+    ("E", Prefix::Metric(18)),
+    ("P", Prefix::Metric(15)),
+    ("T", Prefix::Metric(12)),
+    ("G", Prefix::Metric(9)),
+    ("M", Prefix::Metric(6)),
+    ("k", Prefix::Metric(3)),
+    ("h", Prefix::Metric(2)),
+    ("d", Prefix::Metric(-1)),
+    ("c", Prefix::Metric(-2)),
+    ("m", Prefix::Metric(-3)),
+    ("u", Prefix::Metric(-6)),
+    ("n", Prefix::Metric(-9)),
+    ("p", Prefix::Metric(-12)),
+    ("f", Prefix::Metric(-15)),
+    ("a", Prefix::Metric(-18)),
+    ("z", Prefix::Metric(-21)),
+    ("y", Prefix::Metric(-24)),
+];
+
+/// Writes the unit of a quantity as a UCUM expression, e.g. `kg.m/s2`.
+pub fn to_ucum(units: &dyn UnitLookup, mut args: Args) -> Result<Value> {
+    let quantity = quantity_arg!(args);
+    let unit = quantity.unit().canonicalized();
+
+    let error = |message: String| RuntimeError::UcumExportError(unit.to_string(), message);
+
+    let mut numerator = vec![];
+    let mut denominator = vec![];
+    for factor in unit.iter() {
+        let name = factor.unit_id.canonical_name.name;
+        let code = units
+            .unit_registry()
+            .inner
+            .get_base_representation(factor.unit_id.name)
+            .ok()
+            .and_then(|(_, metadata)| metadata.ucum)
+            .ok_or_else(|| error(format!("'{name}' has no UCUM code")))?;
+
+        let prefix = if factor.prefix == Prefix::none() {
+            ""
+        } else {
+            PREFIXES
+                .iter()
+                .find(|(_, prefix)| *prefix == factor.prefix)
+                .map(|(symbol, _)| *symbol)
+                .ok_or_else(|| error(format!("the prefix of '{name}' has no UCUM symbol")))?
+        };
+
+        if !factor.exponent.is_integer() {
+            return Err(error(format!(
+                "the exponent {} of '{name}' is not an integer",
+                factor.exponent
+            )));
+        }
+        let exponent = factor.exponent.to_integer();
+
+        let mut symbol = format!("{prefix}{code}");
+        if exponent.abs() != 1 {
+            write!(symbol, "{}", exponent.abs()).unwrap();
+        }
+        if exponent > 0 {
+            numerator.push(symbol);
+        } else {
+            denominator.push(symbol);
+        }
+    }
+
+    let mut ucum = numerator.join(".");
+    match &denominator[..] {
+        [] if numerator.is_empty() => ucum.push('1'),
+        [] => {}
+        [symbol] => write!(ucum, "/{symbol}").unwrap(),
+        symbols => write!(ucum, "/({})", symbols.join(".")).unwrap(),
+    }
+
+    return_string!(ucum)
+}
+
+/// Reads a quantity with the given value and a unit in UCUM format, and converts
+/// it to the unit of the target, which has to have the same dimension.
+pub fn from_ucum(units: &dyn UnitLookup, mut args: Args) -> Result<Value> {
+    let value = scalar_arg!(args);
+    let input = string_arg!(args);
+    let target = quantity_arg!(args);
+
+    let (factor, unit) = parse_ucum(&input, units).map_err(|(position, message)| {
+        RuntimeError::InvalidUcum(input.clone(), position, message)
+    })?;
+
+    if let (Some(actual), Some(expected)) =
+        (units.dimension_of(&unit), units.dimension_of(target.unit()))
+    {
+        if actual.to_base_representation() != expected.to_base_representation() {
+            let expected = if target.unit().is_scalar() {
+                "a number without unit".to_string()
+            } else {
+                format!("'{}' of type {}", target.unit(), expected.pretty_print())
+            };
+            return Err(RuntimeError::UcumDimensionMismatch(
+                input,
+                expected,
+                actual.pretty_print().to_string(),
+            ));
+        }
+    }
+
+    let quantity = Quantity::new(Number::from_f64(value.to_f64() * factor), unit)
+        .convert_to(target.unit())
+        .map_err(RuntimeError::QuantityError)?;
+    Ok(Value::Quantity(quantity))
+}
+
+/// Parses a UCUM expression into a numerical factor (for terms like `10.L`)
+/// and a unit. Errors contain the position (in characters) where they occurred.
+fn parse_ucum(input: &str, units: &dyn UnitLookup) -> ParseResult<(f64, Unit)> {
+    let mut parser = UcumParser {
+        chars: input.chars().collect(),
+        position: 0,
+        units,
+    };
+
+    if parser.chars.is_empty() {
+        return Err((0, "expected a unit".into()));
+    }
+
+    let term = parser.term()?;
+    match parser.peek() {
+        None => Ok(term),
+        Some(')') => Err((parser.position, "unmatched ')'".into())),
+        Some(c) => Err((parser.position, format!("expected '.' or '/', found '{c}'"))),
+    }
+}
+
+type ParseResult<T> = std::result::Result<T, (usize, String)>;
+
+struct UcumParser<'a> {
+    chars: Vec<char>,
+    position: usize,
+    units: &'a dyn UnitLookup,
+}
+
+impl UcumParser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn next_if(&mut self, predicate: impl Fn(char) -> bool) -> Option<char> {
+        let c = self.peek().filter(|&c| predicate(c))?;
+        self.position += 1;
+        Some(c)
+    }
+
+    /// term ::= "/"? component (("." | "/") component)*
+    fn term(&mut self) -> ParseResult<(f64, Unit)> {
+        let (mut factor, mut unit) = if self.next_if(|c| c == '/').is_some() {
+            let (factor, unit) = self.component()?;
+            (1.0 / factor, unit.power(Exponent::from_integer(-1)))
+        } else {
+            self.component()?
+        };
+
+        while let Some(operator) = self.next_if(|c| c == '.' || c == '/') {
+            let (next_factor, next_unit) = self.component()?;
+            if operator == '.' {
+                factor *= next_factor;
+                unit = unit * next_unit;
+            } else {
+                factor /= next_factor;
+                unit = unit / next_unit;
+            }
+        }
+
+        Ok((factor, unit))
+    }
+
+    /// component ::= ("(" term ")" | factor | simple_unit exponent? | "") annotation?
+    fn component(&mut self) -> ParseResult<(f64, Unit)> {
+        let start = self.position;
+
+        let component = match self.peek() {
+            Some('(') => {
+                self.position += 1;
+                let term = self.term()?;
+                if self.next_if(|c| c == ')').is_none() {
+                    return Err((self.position, "expected ')'".into()));
+                }
+                term
+            }
+            Some(c) if c.is_ascii_digit() => {
+                let digits = self.take_while(|c| c.is_ascii_digit());
+                let factor = digits
+                    .parse()
+                    .map_err(|_| (start, format!("invalid number '{digits}'")))?;
+                (factor, Unit::scalar())
+            }
+            Some('{') => (1.0, Unit::scalar()),
+            Some(c) if is_symbol_char(c) => {
+                let unit = self.simple_unit()?;
+                match self.exponent()? {
+                    Some(exponent) => (1.0, unit.power(Exponent::from_integer(exponent))),
+                    None => (1.0, unit),
+                }
+            }
+            Some(c) => return Err((start, format!("unexpected character '{c}'"))),
+            None => return Err((start, "expected a unit".into())),
+        };
+
+        self.annotation()?;
+
+        Ok(component)
+    }
+
+    /// A unit symbol with an optional prefix, like `mg` or `[in_i]`
+    fn simple_unit(&mut self) -> ParseResult<Unit> {
+        let start = self.position;
+
+        let mut symbol = String::new();
+        while let Some(c) = self.next_if(is_symbol_char) {
+            symbol.push(c);
+            if c == '[' {
+                let content = self.take_while(|c| c != ']' && c != '[');
+                symbol.push_str(&content);
+                if self.next_if(|c| c == ']').is_none() {
+                    return Err((self.position, "expected ']'".into()));
+                }
+                symbol.push(']');
+            }
+        }
+
+        self.lookup(&symbol).map_err(|message| (start, message))
+    }
+
+    fn lookup(&self, symbol: &str) -> std::result::Result<Unit, String> {
+        let registry = self.units.unit_registry();
+        let unit_with_code = |code: &str| {
+            let name = registry.unit_with_ucum_code(code)?;
+            let unit = self.units.lookup_unit(name.as_str())?;
+            let (_, metadata) = registry.inner.get_base_representation(name).ok()?;
+            Some((unit, metadata))
+        };
+
+        if let Some((unit, _)) = unit_with_code(symbol) {
+            return Ok(unit);
+        }
+
+        for (prefix_symbol, prefix) in PREFIXES {
+            let Some((unit, metadata)) =
+                symbol.strip_prefix(prefix_symbol).and_then(unit_with_code)
+            else {
+                continue;
+            };
+
+            let accepts_prefix = match prefix {
+                Prefix::Metric(_) => metadata.metric_prefixes,
+                Prefix::Binary(_) => metadata.binary_prefixes,
+            };
+            if !accepts_prefix {
+                return Err(format!(
+                    "the unit '{}' can not be used with the prefix '{prefix_symbol}'",
+                    &symbol[prefix_symbol.len()..]
+                ));
+            }
+
+            return Ok(unit.with_prefix(*prefix));
+        }
+
+        Err(format!("unknown unit '{symbol}'"))
+    }
+
+    /// An integer exponent like `2`, `-1` or `+3` directly after a unit symbol
+    fn exponent(&mut self) -> ParseResult<Option<i128>> {
+        let start = self.position;
+        let sign = self.next_if(|c| c == '+' || c == '-');
+        let digits = self.take_while(|c| c.is_ascii_digit());
+
+        if digits.is_empty() {
+            return match sign {
+                Some(_) => Err((
+                    self.position,
+                    "expected digits after the sign of an exponent".into(),
+                )),
+                None => Ok(None),
+            };
+        }
+
+        let exponent: i128 = digits
+            .parse()
+            .map_err(|_| (start, format!("invalid exponent '{digits}'")))?;
+        Ok(Some(if sign == Some('-') {
+            -exponent
+        } else {
+            exponent
+        }))
+    }
+
+    /// An annotation like `{cells}`, which does not change the unit
+    fn annotation(&mut self) -> ParseResult<()> {
+        if self.next_if(|c| c == '{').is_some() {
+            self.take_while(|c| c != '}' && c != '{');
+            if self.next_if(|c| c == '}').is_none() {
+                return Err((
+                    self.position,
+                    "expected '}' at the end of an annotation".into(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> String {
+        std::iter::from_fn(|| self.next_if(&predicate)).collect()
+    }
+}
+
+/// Characters that can be part of a unit symbol (outside of square brackets).
+/// Digits and signs start an exponent.
+fn is_symbol_char(c: char) -> bool {
+    c.is_ascii_graphic() && !c.is_ascii_digit() && !"./(){}+-]".contains(c)
+}
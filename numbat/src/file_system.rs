@@ -0,0 +1,129 @@
+//! Destinations for files written by `write_text(…)` and `append_csv(…)`.
+
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+#[cfg(feature = "fs")]
+use std::{fs, io::Write};
+
+use crate::sandbox::Capability;
+
+/// Where files are written to, see [`crate::Context::set_file_system`].
+pub trait FileSystem: Send + Sync {
+    /// The contents of a file, or `None` if it does not exist.
+    fn read(&self, path: &Path) -> io::Result<Option<String>>;
+
+    /// Replace the contents of a file, creating it if necessary.
+    fn write(&self, path: &Path, contents: &str) -> io::Result<()>;
+
+    /// Add to the end of a file, creating it if necessary.
+    fn append(&self, path: &Path, contents: &str) -> io::Result<()>;
+
+    /// The [`Capability`] that is needed to use this file system in sandbox mode.
+    /// File systems that do not access the outside world need none.
+    fn required_capability(&self) -> Option<Capability> {
+        None
+    }
+}
+
+/// The file system of the operating system (the default)
+#[derive(Debug, Clone, Default)]
+pub struct OsFileSystem;
+
+#[cfg(feature = "fs")]
+impl FileSystem for OsFileSystem {
+    fn read(&self, path: &Path) -> io::Result<Option<String>> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Ok(Some(contents)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn write(&self, path: &Path, contents: &str) -> io::Result<()> {
+        fs::write(path, contents)
+    }
+
+    fn append(&self, path: &Path, contents: &str) -> io::Result<()> {
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?
+            .write_all(contents.as_bytes())
+    }
+
+    fn required_capability(&self) -> Option<Capability> {
+        Some(Capability::Filesystem)
+    }
+}
+
+/// Without the `fs` feature, files can only be written to other file systems, see
+/// [`crate::Context::set_file_system`].
+#[cfg(not(feature = "fs"))]
+impl FileSystem for OsFileSystem {
+    fn read(&self, _: &Path) -> io::Result<Option<String>> {
+        Err(Self::unsupported())
+    }
+
+    fn write(&self, _: &Path, _: &str) -> io::Result<()> {
+        Err(Self::unsupported())
+    }
+
+    fn append(&self, _: &Path, _: &str) -> io::Result<()> {
+        Err(Self::unsupported())
+    }
+}
+
+#[cfg(not(feature = "fs"))]
+impl OsFileSystem {
+    fn unsupported() -> io::Error {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Numbat was built without file system support",
+        )
+    }
+}
+
+/// Files that are kept in memory, e.g. to capture the output of a script.
+#[derive(Debug, Default)]
+pub struct InMemoryFileSystem {
+    files: Mutex<HashMap<PathBuf, String>>,
+}
+
+impl InMemoryFileSystem {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The contents of a file, or `None` if it has not been written.
+    pub fn contents(&self, path: impl AsRef<Path>) -> Option<String> {
+        self.files.lock().unwrap().get(path.as_ref()).cloned()
+    }
+}
+
+impl FileSystem for InMemoryFileSystem {
+    fn read(&self, path: &Path) -> io::Result<Option<String>> {
+        Ok(self.contents(path))
+    }
+
+    fn write(&self, path: &Path, contents: &str) -> io::Result<()> {
+        self.files
+            .lock()
+            .unwrap()
+            .insert(path.to_owned(), contents.to_owned());
+        Ok(())
+    }
+
+    fn append(&self, path: &Path, contents: &str) -> io::Result<()> {
+        self.files
+            .lock()
+            .unwrap()
+            .entry(path.to_owned())
+            .or_default()
+            .push_str(contents);
+        Ok(())
+    }
+}
@@ -2,39 +2,23 @@
 /// in an isolated context.
 use crate::markup as m;
 use crate::module_importer::BuiltinModuleImporter;
+use crate::output::BufferingSink;
 use crate::resolver::CodeSource;
 use crate::Context;
-use crate::InterpreterSettings;
-
-use std::sync::{Arc, Mutex};
 
 fn evaluate_example(context: &mut Context, input: &str) -> m::Markup {
-    let statement_output: Arc<Mutex<Vec<m::Markup>>> = Arc::new(Mutex::new(vec![]));
-    let statement_output_c = statement_output.clone();
-    let mut settings = InterpreterSettings {
-        print_fn: Box::new(move |s: &m::Markup| {
-            statement_output_c.lock().unwrap().push(s.clone());
-        }),
-    };
+    let mut statement_output = BufferingSink::default();
 
     let (statements, interpreter_result) = context
-        .interpret_with_settings(&mut settings, input, CodeSource::Internal)
+        .interpret_with_sink(&mut statement_output, input, CodeSource::Internal)
         .expect("No error in 'help' examples");
 
-    let markup =
-        statement_output
-            .lock()
-            .unwrap()
-            .iter()
-            .fold(m::empty(), |accumulated_mk, single_line| {
-                accumulated_mk + m::nl() + m::whitespace("  ") + single_line.clone() + m::nl()
-            })
-            + interpreter_result.to_markup(
-                statements.last(),
-                context.dimension_registry(),
-                true,
-                true,
-            );
+    let markup = statement_output
+        .printed()
+        .fold(m::empty(), |accumulated_mk, single_line| {
+            accumulated_mk + m::nl() + m::whitespace("  ") + single_line.clone() + m::nl()
+        })
+        + interpreter_result.to_markup(statements.last(), context.dimension_registry(), true, true);
 
     markup
 }
@@ -0,0 +1,242 @@
+//! Classification of source code tokens for syntax highlighting.
+//!
+//! [`classify_tokens`] only looks at the tokens themselves. Use
+//! [`Context::classify_tokens`](crate::Context::classify_tokens) to also tell
+//! units and dimensions apart from other identifiers.
+
+use crate::tokenizer::{tokenize_partial, TokenKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenClass {
+    Keyword,
+    Number,
+    String,
+    Operator,
+    /// Brackets, commas, colons, …
+    Punctuation,
+    Identifier,
+    Unit,
+    TypeIdentifier,
+    Decorator,
+    Comment,
+    /// Input that could not be tokenized, e.g. an unterminated string
+    Invalid,
+}
+
+impl TokenClass {
+    pub fn name(self) -> &'static str {
+        match self {
+            TokenClass::Keyword => "keyword",
+            TokenClass::Number => "number",
+            TokenClass::String => "string",
+            TokenClass::Operator => "operator",
+            TokenClass::Punctuation => "punctuation",
+            TokenClass::Identifier => "identifier",
+            TokenClass::Unit => "unit",
+            TokenClass::TypeIdentifier => "type-identifier",
+            TokenClass::Decorator => "decorator",
+            TokenClass::Comment => "comment",
+            TokenClass::Invalid => "invalid",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassifiedToken {
+    pub class: TokenClass,
+    /// Byte range of the token in the source code
+    pub start: usize,
+    pub end: usize,
+}
+
+fn token_class(kind: TokenKind) -> Option<TokenClass> {
+    use TokenKind::*;
+
+    Some(match kind {
+        LeftParen | RightParen | LeftBracket | RightBracket | LeftCurly | RightCurly | Comma
+        | Colon | DoubleColon | Period | Ellipsis => TokenClass::Punctuation,
+
+        Plus | Minus | Multiply | Power | Divide | Arrow | Equal | PostfixApply
+        | UnicodeExponent | ExclamationMark | EqualEqual | NotEqual | LessThan | GreaterThan
+        | LessOrEqual | GreaterOrEqual | LogicalAnd | LogicalOr | QuestionMark => {
+            TokenClass::Operator
+        }
+
+        At => TokenClass::Decorator,
+
+        Per
+        | To
+        | Let
+        | Fn
+        | UnitFn
+        | Inverse
+        | Where
+        | With
+        | And
+        | Dimension
+        | Unit
+        | Use
+        | Struct
+        | Impl
+        | Formula
+        | UnitSystem
+        | Long
+        | Short
+        | Both
+        | None
+        | If
+        | Then
+        | Else
+        | For
+        | While
+        | Try
+        | Catch
+        | True
+        | False
+        | ProcedurePrint
+        | ProcedureAssert
+        | ProcedureAssertEq
+        | ProcedureType
+        | ProcedureConversionCacheStats
+        | ProcedureRegexCacheStats
+        | ProcedureInstantiationCacheStats
+        | ProcedureSetFixedTime
+        | ProcedureSetImplicitConversions
+        | ProcedureWriteText
+        | ProcedureAppendCsv
+        | Partial
+        | AutoDiff
+        | IsDimensionless
+        | HasDimension => TokenClass::Keyword,
+
+        NaN | Inf | Number | IntegerWithBase(_) | HexadecimalFloat => TokenClass::Number,
+
+        Bool | String | DateTime | Dynamic | CapitalFn | List => TokenClass::TypeIdentifier,
+
+        Identifier => TokenClass::Identifier,
+
+        StringFixed
+        | StringInterpolationStart
+        | StringInterpolationMiddle
+        | StringInterpolationSpecifiers
+        | StringInterpolationEnd => TokenClass::String,
+
+        Newline | Eof => return Option::None,
+    })
+}
+
+/// Find comments in a stretch of code between two tokens.
+fn push_comments(code: &str, start: usize, end: usize, tokens: &mut Vec<ClassifiedToken>) {
+    let mut offset = start;
+    while let Some(hash) = code[offset..end].find('#') {
+        let comment_start = offset + hash;
+        let comment_end = code[comment_start..end]
+            .find('\n')
+            .map_or(end, |newline| comment_start + newline);
+        tokens.push(ClassifiedToken {
+            class: TokenClass::Comment,
+            start: comment_start,
+            end: comment_end,
+        });
+        offset = comment_end;
+    }
+}
+
+/// Classify all tokens in the given code, in order. Whitespace is not included.
+/// This never fails: everything after a tokenizer error is marked as
+/// [`TokenClass::Invalid`].
+pub fn classify_tokens(code: &str) -> Vec<ClassifiedToken> {
+    let (tokens, error) = tokenize_partial(code, 0);
+
+    let mut classified = vec![];
+    let mut last_end = 0;
+    let mut after_at = false;
+    for token in &tokens {
+        let Some(class) = token_class(token.kind) else {
+            continue;
+        };
+        let class = if after_at && class == TokenClass::Identifier {
+            TokenClass::Decorator
+        } else {
+            class
+        };
+        after_at = token.kind == TokenKind::At;
+
+        let start = token.span.start.byte as usize;
+        let end = token.span.end.byte as usize;
+        push_comments(code, last_end, start, &mut classified);
+        classified.push(ClassifiedToken { class, start, end });
+        last_end = end;
+    }
+
+    let rest = error.map_or(code.len(), |e| e.span.start.byte as usize);
+    push_comments(code, last_end, rest, &mut classified);
+    if rest < code.len() {
+        classified.push(ClassifiedToken {
+            class: TokenClass::Invalid,
+            start: rest,
+            end: code.len(),
+        });
+    }
+
+    classified
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classes(code: &str) -> Vec<(&str, TokenClass)> {
+        classify_tokens(code)
+            .into_iter()
+            .map(|t| (&code[t.start..t.end], t.class))
+            .collect()
+    }
+
+    #[test]
+    fn basic() {
+        use TokenClass::*;
+
+        assert_eq!(
+            classes("let x: Length = 2 m  # comment\n@aliases(y) fn f(x: Bool) = \"a {x}\""),
+            [
+                ("let", Keyword),
+                ("x", Identifier),
+                (":", Punctuation),
+                ("Length", Identifier),
+                ("=", Operator),
+                ("2", Number),
+                ("m", Identifier),
+                ("# comment", Comment),
+                ("@", Decorator),
+                ("aliases", Decorator),
+                ("(", Punctuation),
+                ("y", Identifier),
+                (")", Punctuation),
+                ("fn", Keyword),
+                ("f", Identifier),
+                ("(", Punctuation),
+                ("x", Identifier),
+                (":", Punctuation),
+                ("Bool", TypeIdentifier),
+                (")", Punctuation),
+                ("=", Operator),
+                ("\"a {", String),
+                ("x", Identifier),
+                ("}\"", String),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_input() {
+        assert_eq!(
+            classes("1 + \"abc"),
+            [
+                ("1", TokenClass::Number),
+                ("+", TokenClass::Operator),
+                ("\"abc", TokenClass::Invalid),
+            ]
+        );
+    }
+}
@@ -1,8 +1,13 @@
 use crate::buffered_writer::BufferedWriter;
+use crate::diagnostic::Diagnostic;
 use crate::markup::{FormatType, FormattedString, Formatter};
 
+use codespan_reporting::files::SimpleFiles;
 use termcolor::{Color, WriteColor};
 
+/// Formats markup as HTML, with a `numbat-<format type>` CSS class for each part.
+/// Line breaks are kept as `\n` characters, so the output should be placed in an
+/// element with `white-space: pre` (or similar).
 pub struct HtmlFormatter;
 
 pub fn html_format(class: Option<&str>, content: &str) -> String {
@@ -42,9 +47,14 @@ impl Formatter for HtmlFormatter {
     }
 }
 
+/// A [`WriteColor`] implementation that renders colored output (e.g. diagnostics
+/// from `codespan_reporting`) as HTML. Text is escaped, and consecutive writes with
+/// the same style are combined into a single `<span>`.
 pub struct HtmlWriter {
     buffer: Vec<u8>,
     color: Option<termcolor::ColorSpec>,
+    /// Text written with the current color, not yet added to the buffer
+    pending: Vec<u8>,
 }
 
 impl Default for HtmlWriter {
@@ -58,47 +68,48 @@ impl HtmlWriter {
         HtmlWriter {
             buffer: vec![],
             color: None,
+            pending: vec![],
         }
     }
+
+    fn css_class(&self) -> Option<&'static str> {
+        let color = self.color.as_ref()?;
+        if color.fg() == Some(&Color::Red) {
+            Some("diagnostic-red")
+        } else if color.fg() == Some(&Color::Blue) {
+            Some("diagnostic-blue")
+        } else if color.bold() {
+            Some("diagnostic-bold")
+        } else {
+            None
+        }
+    }
+
+    fn pending_html(&self) -> String {
+        html_format(self.css_class(), &String::from_utf8_lossy(&self.pending))
+    }
+
+    fn flush_pending(&mut self) {
+        let html = self.pending_html();
+        self.buffer.extend_from_slice(html.as_bytes());
+        self.pending.clear();
+    }
 }
 
 impl BufferedWriter for HtmlWriter {
     fn to_string(&self) -> String {
-        String::from_utf8_lossy(&self.buffer).into()
+        String::from_utf8_lossy(&self.buffer).into_owned() + &self.pending_html()
     }
 }
 
 impl std::io::Write for HtmlWriter {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        if let Some(color) = &self.color {
-            if color.fg() == Some(&Color::Red) {
-                self.buffer
-                    .write("<span class=\"numbat-diagnostic-red\">".as_bytes())?;
-                let size = self.buffer.write(buf)?;
-                self.buffer.write("</span>".as_bytes())?;
-                Ok(size)
-            } else if color.fg() == Some(&Color::Blue) {
-                self.buffer
-                    .write("<span class=\"numbat-diagnostic-blue\">".as_bytes())?;
-                let size = self.buffer.write(buf)?;
-                self.buffer.write("</span>".as_bytes())?;
-                Ok(size)
-            } else if color.bold() {
-                self.buffer
-                    .write("<span class=\"numbat-diagnostic-bold\">".as_bytes())?;
-                let size = self.buffer.write(buf)?;
-                self.buffer.write("</span>".as_bytes())?;
-                Ok(size)
-            } else {
-                self.buffer.write(buf)
-            }
-        } else {
-            self.buffer.write(buf)
-        }
+        self.pending.extend_from_slice(buf);
+        Ok(buf.len())
     }
 
     fn flush(&mut self) -> std::io::Result<()> {
-        self.buffer.flush()
+        Ok(())
     }
 }
 
@@ -108,12 +119,99 @@ impl WriteColor for HtmlWriter {
     }
 
     fn set_color(&mut self, spec: &termcolor::ColorSpec) -> std::io::Result<()> {
+        self.flush_pending();
         self.color = Some(spec.clone());
         Ok(())
     }
 
     fn reset(&mut self) -> std::io::Result<()> {
+        self.flush_pending();
         self.color = None;
         Ok(())
     }
 }
+
+/// Render diagnostics as a `<pre class="numbat-diagnostic">` block.
+pub fn html_diagnostics(files: &SimpleFiles<String, String>, diagnostics: &[Diagnostic]) -> String {
+    use codespan_reporting::term::{self, Config};
+
+    let mut writer = HtmlWriter::new();
+    let config = Config::default();
+    for diagnostic in diagnostics {
+        term::emit(&mut writer, &config, files, &diagnostic.rendered()).unwrap();
+    }
+
+    format!(
+        "<pre class=\"numbat-diagnostic\">{}</pre>",
+        writer.to_string()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::ErrorDiagnostic;
+    use crate::markup::Formatter;
+    use crate::module_importer::BuiltinModuleImporter;
+    use crate::resolver::CodeSource;
+    use crate::{Context, NumbatError};
+
+    fn result_html(ctx: &mut Context, code: &str) -> String {
+        let (statements, result) = ctx.interpret(code, CodeSource::Text).unwrap();
+        let markup = result.to_markup(statements.last(), ctx.dimension_registry(), true, true);
+        HtmlFormatter.format(&markup, false)
+    }
+
+    #[test]
+    fn quantity_and_string_results() {
+        let mut ctx = Context::new(BuiltinModuleImporter::default());
+        let _ = ctx.interpret("use prelude", CodeSource::Internal).unwrap();
+
+        assert_eq!(
+            result_html(&mut ctx, "2 km/h"),
+            "    <span class=\"numbat-operator\">=</span> \
+             <span class=\"numbat-value\">2</span> \
+             <span class=\"numbat-unit\">km/h</span>\
+             <span class=\"numbat-dimmed\">    [</span>\
+             <span class=\"numbat-type-identifier\">Velocity</span>\
+             <span class=\"numbat-dimmed\">]</span>\n"
+        );
+
+        assert_eq!(
+            result_html(&mut ctx, "\"a<b & c\""),
+            "    <span class=\"numbat-operator\">=</span> \
+             <span class=\"numbat-operator\">\"</span>\
+             <span class=\"numbat-string\">a&lt;b &amp; c</span>\
+             <span class=\"numbat-operator\">\"</span>\
+             <span class=\"numbat-dimmed\">    [</span>\
+             <span class=\"numbat-type-identifier\">String</span>\
+             <span class=\"numbat-dimmed\">]</span>\n"
+        );
+    }
+
+    #[test]
+    fn error_diagnostic() {
+        let mut ctx = Context::new_without_importer();
+        let Err(NumbatError::TypeCheckError(error)) =
+            ctx.interpret("1 + \"<x>\"", CodeSource::Text)
+        else {
+            panic!("expected type check error");
+        };
+
+        assert_eq!(
+            html_diagnostics(&ctx.resolver().files, &error.diagnostics()),
+            "<pre class=\"numbat-diagnostic\">\
+             <span class=\"numbat-diagnostic-red\">error[E0515]</span>\
+             <span class=\"numbat-diagnostic-bold\">: while type checking</span>\n  \
+             <span class=\"numbat-diagnostic-blue\">┌─</span> &lt;input:1&gt;:1:5\n  \
+             <span class=\"numbat-diagnostic-blue\">│</span>\n\
+             <span class=\"numbat-diagnostic-blue\">1</span> \
+             <span class=\"numbat-diagnostic-blue\">│</span> 1 + \
+             <span class=\"numbat-diagnostic-red\">\"&lt;x&gt;\"</span>\n  \
+             <span class=\"numbat-diagnostic-blue\">│</span>     \
+             <span class=\"numbat-diagnostic-red\">^^^^^</span> \
+             <span class=\"numbat-diagnostic-red\">Expected dimension type, got String instead</span>\n\n\
+             </pre>"
+        );
+    }
+}
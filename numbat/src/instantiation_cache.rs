@@ -0,0 +1,352 @@
+//! A cache for the specializations of generic functions.
+//!
+//! The body of a generic function like `max<D: Dim>(a: D, b: D) -> D` is compiled
+//! once for all types. Calls whose argument and return types are fully known use a
+//! specialization instead: a copy of the body that has been compiled for the concrete
+//! types of the type variables (e.g. `D = Length`). Inside of a specialization, all
+//! types are known, so calls of native functions can check their result types and
+//! runtime type checks can check the full type of a value.
+//!
+//! A specialization is compiled when the first call with its types is compiled. The
+//! cache holds a limited number of specializations. When it is full, the one that
+//! has not been used for the longest time is evicted. Redefining a function removes
+//! all of its specializations.
+
+use std::collections::HashMap;
+use std::iter;
+
+use crate::arithmetic::Exponent;
+use crate::symbol::Symbol;
+use crate::type_variable::TypeVariable;
+use crate::typed_ast::{DType, DTypeFactor, Type};
+
+/// The default number of specializations that are kept in the cache.
+const MAX_ENTRIES: usize = 256;
+
+#[derive(Debug, Clone)]
+struct Entry {
+    types: Vec<Type>,
+    function_idx: u16,
+    last_use: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct InstantiationCacheStats {
+    pub entries: usize,
+    pub hits: usize,
+    pub misses: usize,
+}
+
+impl std::fmt::Display for InstantiationCacheStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Instantiation cache: entries: {}, hits: {}, misses: {}",
+            self.entries, self.hits, self.misses
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct InstantiationCache {
+    /// Specializations by the name of the generic function
+    entries: HashMap<Symbol, Vec<Entry>>,
+    capacity: usize,
+    /// Incremented on every use of an entry, to find the least recently used one
+    clock: u64,
+    hits: usize,
+    misses: usize,
+}
+
+impl Default for InstantiationCache {
+    fn default() -> Self {
+        Self::with_capacity(MAX_ENTRIES)
+    }
+}
+
+impl InstantiationCache {
+    pub fn with_capacity(capacity: usize) -> Self {
+        InstantiationCache {
+            entries: HashMap::new(),
+            capacity,
+            clock: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// The bytecode index of the specialization of function `name` for the given
+    /// types of its type variables.
+    pub fn get(&mut self, name: Symbol, types: &[Type]) -> Option<u16> {
+        self.clock += 1;
+
+        let entry = self
+            .entries
+            .get_mut(&name)
+            .and_then(|bucket| bucket.iter_mut().find(|e| e.types == types));
+
+        if let Some(entry) = entry {
+            self.hits += 1;
+            entry.last_use = self.clock;
+            Some(entry.function_idx)
+        } else {
+            self.misses += 1;
+            None
+        }
+    }
+
+    pub fn insert(&mut self, name: Symbol, types: Vec<Type>, function_idx: u16) {
+        if self.len() >= self.capacity {
+            self.evict_least_recently_used();
+        }
+
+        self.clock += 1;
+        self.entries.entry(name).or_default().push(Entry {
+            types,
+            function_idx,
+            last_use: self.clock,
+        });
+    }
+
+    /// Remove all specializations of function `name`
+    pub fn flush(&mut self, name: Symbol) {
+        self.entries.remove(&name);
+    }
+
+    fn len(&self) -> usize {
+        self.entries.values().map(Vec::len).sum()
+    }
+
+    fn evict_least_recently_used(&mut self) {
+        let oldest = self
+            .entries
+            .iter()
+            .flat_map(|(name, bucket)| {
+                bucket
+                    .iter()
+                    .enumerate()
+                    .map(move |(position, entry)| (entry.last_use, *name, position))
+            })
+            .min();
+
+        if let Some((_, name, position)) = oldest {
+            let bucket = self.entries.get_mut(&name).unwrap();
+            bucket.remove(position);
+            if bucket.is_empty() {
+                self.entries.remove(&name);
+            }
+        }
+    }
+
+    pub fn stats(&self) -> InstantiationCacheStats {
+        InstantiationCacheStats {
+            entries: self.len(),
+            hits: self.hits,
+            misses: self.misses,
+        }
+    }
+}
+
+/// The concrete types of the quantified variables `0..num_variables` of a (generic)
+/// function type, for a call with the given argument and return types. Returns `None`
+/// if they can not all be determined, e.g. because the types at the call site are not
+/// fully known themselves.
+pub(crate) fn instantiation(
+    function_type: &Type,
+    num_variables: usize,
+    argument_types: &[Type],
+    return_type: &Type,
+) -> Option<Vec<Type>> {
+    let Type::Fn(parameter_types, result_type) = function_type else {
+        return None;
+    };
+
+    let mut bindings = vec![None; num_variables];
+    let mut dimensions = vec![];
+    for (pattern, type_) in parameter_types
+        .iter()
+        .zip(argument_types)
+        .chain(iter::once((&**result_type, return_type)))
+    {
+        bind(pattern, type_, &mut bindings, &mut dimensions);
+    }
+
+    // Dimension types like `D^3` or `A × B` can be solved once all but one of their
+    // type variables are known
+    loop {
+        let num_unknown = bindings.iter().filter(|b| b.is_none()).count();
+        for (pattern, dtype) in &dimensions {
+            solve_dimension(pattern, dtype, &mut bindings);
+        }
+        if bindings.iter().filter(|b| b.is_none()).count() == num_unknown {
+            break;
+        }
+    }
+
+    bindings.into_iter().collect()
+}
+
+fn is_fully_known(type_: &Type) -> bool {
+    type_.type_variables(true).is_empty()
+}
+
+fn bind(
+    pattern: &Type,
+    type_: &Type,
+    bindings: &mut [Option<Type>],
+    dimensions: &mut Vec<(DType, DType)>,
+) {
+    if let Some(TypeVariable::Quantified(i)) = pattern.as_single_type_variable() {
+        if i < bindings.len() && is_fully_known(type_) {
+            bindings[i] = Some(type_.clone());
+        }
+        return;
+    }
+
+    match (pattern, type_) {
+        (Type::Dimension(pattern), Type::Dimension(dtype)) if is_fully_known(type_) => {
+            dimensions.push((pattern.clone(), dtype.clone()))
+        }
+        (Type::Fn(pattern_parameters, pattern_result), Type::Fn(parameters, result))
+            if pattern_parameters.len() == parameters.len() =>
+        {
+            for (pattern, type_) in pattern_parameters.iter().zip(parameters) {
+                bind(pattern, type_, bindings, dimensions);
+            }
+            bind(pattern_result, result, bindings, dimensions);
+        }
+        (Type::List(pattern), Type::List(element_type)) => {
+            bind(pattern, element_type, bindings, dimensions)
+        }
+        _ => {}
+    }
+}
+
+/// Determine the type variable in `pattern` if it is the only unknown one
+fn solve_dimension(pattern: &DType, dtype: &DType, bindings: &mut [Option<Type>]) {
+    let mut known = DType::scalar();
+    let mut unknown = None;
+    for (factor, exponent) in &pattern.factors {
+        match factor {
+            DTypeFactor::TVar(TypeVariable::Quantified(i)) if *i < bindings.len() => {
+                match &bindings[*i] {
+                    Some(Type::Dimension(d)) => known = known.multiply(&d.power(*exponent)),
+                    None if unknown.is_none() => unknown = Some((*i, *exponent)),
+                    _ => return,
+                }
+            }
+            DTypeFactor::BaseDimension(_) => {
+                known = known.multiply(&DType::from_factors(&[(factor.clone(), *exponent)]))
+            }
+            _ => return,
+        }
+    }
+
+    if let Some((i, exponent)) = unknown {
+        let solution = dtype
+            .divide(&known)
+            .power(Exponent::from_integer(1) / exponent);
+        bindings[i] = Some(Type::Dimension(solution));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn length() -> Type {
+        Type::Dimension(DType::base_dimension("Length"))
+    }
+
+    fn time() -> Type {
+        Type::Dimension(DType::base_dimension("Time"))
+    }
+
+    fn quantified(i: usize) -> DType {
+        DType::from_tgen(i)
+    }
+
+    #[test]
+    fn least_recently_used_entries_are_evicted() {
+        let mut cache = InstantiationCache::with_capacity(2);
+        let max = Symbol::new("max");
+
+        assert_eq!(cache.get(max, &[length()]), None);
+        cache.insert(max, vec![length()], 10);
+        assert_eq!(cache.get(max, &[time()]), None);
+        cache.insert(max, vec![time()], 11);
+
+        assert_eq!(cache.get(max, &[length()]), Some(10));
+
+        cache.insert(max, vec![Type::scalar()], 12);
+        assert_eq!(cache.get(max, &[time()]), None);
+        assert_eq!(cache.get(max, &[length()]), Some(10));
+        assert_eq!(cache.get(max, &[Type::scalar()]), Some(12));
+
+        assert_eq!(
+            cache.stats(),
+            InstantiationCacheStats {
+                entries: 2,
+                hits: 3,
+                misses: 3
+            }
+        );
+
+        cache.flush(max);
+        assert_eq!(cache.stats().entries, 0);
+    }
+
+    #[test]
+    fn instantiation_from_argument_types() {
+        // fn(A, B) -> A × B
+        let function_type = Type::Fn(
+            vec![
+                Type::Dimension(quantified(0)),
+                Type::Dimension(quantified(1)),
+            ],
+            Box::new(Type::Dimension(quantified(0).multiply(&quantified(1)))),
+        );
+        let area =
+            Type::Dimension(DType::base_dimension("Length").power(Exponent::from_integer(2)));
+        assert_eq!(
+            instantiation(&function_type, 2, &[length(), length()], &area),
+            Some(vec![length(), length()])
+        );
+
+        // fn(T^3) -> T
+        let cube_root = Type::Fn(
+            vec![Type::Dimension(
+                quantified(0).power(Exponent::from_integer(3)),
+            )],
+            Box::new(Type::Dimension(quantified(0))),
+        );
+        let volume =
+            Type::Dimension(DType::base_dimension("Length").power(Exponent::from_integer(3)));
+        assert_eq!(
+            instantiation(&cube_root, 1, &[volume], &Type::Dimension(quantified(0))),
+            Some(vec![length()])
+        );
+
+        // fn(List<A>) -> A, called with a list of unknown type
+        let head = Type::Fn(
+            vec![Type::List(Box::new(Type::TVar(TypeVariable::Quantified(
+                0,
+            ))))],
+            Box::new(Type::TVar(TypeVariable::Quantified(0))),
+        );
+        let unknown = Type::TVar(TypeVariable::new_fresh(0));
+        assert_eq!(
+            instantiation(&head, 1, &[Type::List(Box::new(unknown.clone()))], &unknown),
+            None
+        );
+        assert_eq!(
+            instantiation(
+                &head,
+                1,
+                &[Type::List(Box::new(Type::String))],
+                &Type::String
+            ),
+            Some(vec![Type::String])
+        );
+    }
+}
@@ -1,10 +1,15 @@
 use crate::{
     dimension::DimensionRegistry,
+    error_code::ErrorCode,
     markup::Markup,
+    number::Number,
+    output::OutputSink,
     pretty_print::PrettyPrint,
     quantity::{Quantity, QuantityError},
+    sandbox::Capability,
     span::Span,
-    typed_ast::Statement,
+    typed_ast::{TypedProgram, TypedStatement},
+    unit::Unit,
     unit_registry::{UnitRegistry, UnitRegistryError},
 };
 
@@ -14,14 +19,55 @@ use thiserror::Error;
 
 pub use crate::value::Value;
 
+/// Code that runs, but whose result is probably inaccurate
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeWarning {
+    /// A sum whose estimated relative rounding error exceeds the configured threshold:
+    /// the estimated error, the threshold and the number of terms
+    InaccurateSum(f64, f64, usize),
+    /// An addition or subtraction of quantities with different units, where the
+    /// right hand side was converted: the original unit, the target unit and the
+    /// conversion factor
+    ImplicitConversion(Unit, Unit, Number),
+    /// Fresh exchange rates could not be fetched, so cached ones are used: their
+    /// age
+    CachedExchangeRates(std::time::Duration),
+}
+
+/// How implicit unit conversions in additions and subtractions (like `1 m + 1 ft`)
+/// are handled, see [`crate::Context::set_implicit_conversions`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImplicitConversions {
+    /// Convert the right hand side to the unit of the left hand side
+    #[default]
+    Silent,
+    /// Convert, but report a warning with the conversion factor
+    Warn,
+    /// Report an error, conversions need to be made explicit with `->`
+    Forbid,
+}
+
+impl std::str::FromStr for ImplicitConversions {
+    type Err = RuntimeError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "silent" => Ok(ImplicitConversions::Silent),
+            "warn" => Ok(ImplicitConversions::Warn),
+            "forbid" => Ok(ImplicitConversions::Forbid),
+            _ => Err(RuntimeError::InvalidImplicitConversions(s.into())),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Error, PartialEq, Eq)]
 pub enum RuntimeError {
     #[error("Division by zero")]
     DivisionByZero,
     #[error("Expected factorial argument to be a non-negative integer")]
     FactorialOfNegativeNumber,
-    #[error("Expected factorial argument to be a finite integer number")]
-    FactorialOfNonInteger,
+    #[error("Factorial argument must be an integer (got {0})")]
+    FactorialOfNonInteger(String),
     #[error("{0}")]
     UnitRegistryError(UnitRegistryError), // TODO: can this even be triggered?
     #[error("{0}")]
@@ -57,6 +103,205 @@ pub enum RuntimeError {
 
     #[error("Empty list")]
     EmptyList,
+
+    #[error("Native function '{1}' failed: {2}")]
+    NativeFunctionFailed(Option<Span>, String, String),
+    #[error("Native function '{1}' returned '{2}', which is not of type {3}")]
+    NativeFunctionReturnedWrongType(Option<Span>, String, Value, String),
+
+    #[error("'{0}' is not a valid variable name")]
+    InvalidVariableName(String),
+    #[error("Can not assign '{0}' to a variable: {1}")]
+    UnsupportedVariableValue(String, String),
+
+    #[error("Permission denied: {0} is disabled in sandbox mode")]
+    PermissionDenied(Capability),
+
+    #[error("'{0}' is not a currency unit")]
+    NotACurrency(String),
+
+    #[error("Can not destructure a list with {actual} element(s) into {expected} variable(s)")]
+    ListLengthMismatch { expected: usize, actual: usize },
+
+    #[error("Can not convert value to JSON: {0}")]
+    JsonSerializationError(String),
+    #[error("Invalid JSON: {0}")]
+    JsonParsingError(String),
+    #[error("Can not read JSON value at '{0}': {1}")]
+    JsonDeserializationError(String, String),
+    #[error("The result type of '{0}' is not known here. Add a type annotation, e.g. 'let x: MyStruct = {0}(…)'")]
+    UnknownResultType(String),
+
+    #[error(
+        "Script argument {index} is not available, the script was called with {count} argument(s)"
+    )]
+    MissingScriptArgument { index: String, count: usize },
+    #[error("Environment variable '{0}' is not set")]
+    EnvironmentVariableNotSet(String),
+    #[error("Can not parse '{0}' as a quantity: {1}")]
+    QuantityParsingError(String, String),
+
+    #[error("Could not write to '{0}': {1}")]
+    FileWriteError(String, String),
+    #[error("Can not append to CSV file '{0}': {1}")]
+    CsvError(String, String),
+
+    #[error("The number of iterations needs to be a positive integer, got {0}")]
+    InvalidNumberOfIterations(String),
+
+    #[error("While loop did not terminate within {0} iterations")]
+    LoopIterationLimitExceeded(u64),
+
+    #[error("The lower bound of clamp ({0}) is greater than the upper bound ({1})")]
+    InvalidClampBounds(Quantity, Quantity),
+
+    #[error("Can not spell out {0}: only integers between -10^15 and 10^15 are supported")]
+    CanNotSpellOutNumber(String),
+
+    #[error("Expected a value of type {2}, got '{1}'")]
+    DynamicValueHasWrongType(Span, Value, String),
+
+    #[error("The bounds of a series need to be integers, got {0}")]
+    NonIntegerSeriesBound(String),
+
+    #[error("Invalid matrix shape: {0}")]
+    InvalidMatrixShape(String),
+    #[error("Matrix is singular (zero pivot in column {0})")]
+    SingularMatrix(usize),
+
+    #[error("Implicit conversion from '{0}' to '{1}' is not allowed. Convert explicitly, e.g. with '… -> {1}'")]
+    ImplicitConversionForbidden(Unit, Unit),
+    #[error("Unknown setting '{0}' for implicit conversions, expected \"silent\", \"warn\" or \"forbid\"")]
+    InvalidImplicitConversions(String),
+    #[error("Can not implicitly convert from '{0}' to '{1}', since isolated units like currencies are only converted explicitly. Use '… -> {1}'")]
+    IsolatedUnitConversion(Unit, Unit),
+
+    #[error("List index must be an integer (got {0})")]
+    NonIntegerListIndex(String),
+    #[error("Argument of '{0}' must be an integer (got {1})")]
+    NonIntegerArgument(String, String),
+    #[error("Argument of '{0}' must not be negative (got {1})")]
+    NegativeArgument(String, String),
+    #[error("The result of {0} is too large to be represented as a number")]
+    IntegerResultTooLarge(String),
+
+    #[error(
+        "Invalid regular expression '{0}'{}: {2}",
+        .1.map(|position| format!(" at position {position}")).unwrap_or_default()
+    )]
+    InvalidRegex(String, Option<usize>, String),
+    #[error("The regular expression '{0}' has no capture group {1}")]
+    NoSuchRegexGroup(String, usize),
+    #[error("Capture group {1} of the regular expression '{0}' did not match in \"{2}\"")]
+    RegexGroupDidNotMatch(String, usize, String),
+
+    #[error("Invalid UCUM unit '{0}' at position {1}: {2}")]
+    InvalidUcum(String, usize, String),
+    #[error("The unit '{0}' can not be written in UCUM: {1}")]
+    UcumExportError(String, String),
+    #[error("Expected {1}, but the UCUM unit '{0}' is of type {2}")]
+    UcumDimensionMismatch(String, String, String),
+
+    #[error("The time series passed to '{0}' is not sorted: element {1} is earlier than the one before it")]
+    UnsortedTimeSeries(String, usize),
+    #[error("The interval of 'resample' needs to be positive, got {0}")]
+    InvalidResamplingInterval(String),
+
+    #[error(
+        "Can not refer to result _{0}: only the first {} results of a session can be recalled",
+        u16::MAX
+    )]
+    ResultIndexOutOfRange(usize),
+}
+
+impl RuntimeError {
+    /// Whether the error can be handled with `try { … } catch { … }`. Failed
+    /// assertions and errors that stop runaway or forbidden computations can not.
+    pub fn is_catchable(&self) -> bool {
+        !matches!(
+            self,
+            RuntimeError::AssertFailed(..)
+                | RuntimeError::AssertEq2Failed(..)
+                | RuntimeError::AssertEq3Failed(..)
+                | RuntimeError::PermissionDenied(..)
+                | RuntimeError::LoopIterationLimitExceeded(..)
+                | RuntimeError::ImplicitConversionForbidden(..)
+                | RuntimeError::IsolatedUnitConversion(..)
+        )
+    }
+
+    /// The message that is passed to a `catch` branch. For errors raised with
+    /// `error(…)`, this is the original message.
+    pub fn message(&self) -> String {
+        match self {
+            RuntimeError::UserError(message) => message.clone(),
+            error => error.to_string(),
+        }
+    }
+
+    pub fn error_code(&self) -> ErrorCode {
+        match self {
+            RuntimeError::DivisionByZero => ErrorCode::E0801,
+            RuntimeError::FactorialOfNegativeNumber => ErrorCode::E0802,
+            RuntimeError::FactorialOfNonInteger(_) => ErrorCode::E0803,
+            RuntimeError::UnitRegistryError(inner) => inner.error_code(),
+            RuntimeError::QuantityError(inner) => inner.error_code(),
+            RuntimeError::AssertFailed(..) => ErrorCode::E0804,
+            RuntimeError::AssertEq2Failed(..) => ErrorCode::E0805,
+            RuntimeError::AssertEq3Failed(..) => ErrorCode::E0806,
+            RuntimeError::CouldNotLoadExchangeRates => ErrorCode::E0807,
+            RuntimeError::UserError(..) => ErrorCode::E0808,
+            RuntimeError::DateParsingError(..) => ErrorCode::E0809,
+            RuntimeError::UnknownTimezone(..) => ErrorCode::E0810,
+            RuntimeError::DurationOutOfRange => ErrorCode::E0811,
+            RuntimeError::DateTimeOutOfRange => ErrorCode::E0812,
+            RuntimeError::DateFormattingError => ErrorCode::E0813,
+            RuntimeError::InvalidFormatSpecifiers(..) => ErrorCode::E0814,
+            RuntimeError::InvalidTypeForFormatSpecifiers(..) => ErrorCode::E0815,
+            RuntimeError::ChemicalElementNotFound(..) => ErrorCode::E0816,
+            RuntimeError::EmptyList => ErrorCode::E0817,
+            RuntimeError::NativeFunctionFailed(..) => ErrorCode::E0820,
+            RuntimeError::NativeFunctionReturnedWrongType(..) => ErrorCode::E0821,
+            RuntimeError::InvalidVariableName(_) => ErrorCode::E0822,
+            RuntimeError::UnsupportedVariableValue(..) => ErrorCode::E0823,
+            RuntimeError::PermissionDenied(_) => ErrorCode::E0824,
+            RuntimeError::NotACurrency(_) => ErrorCode::E0825,
+            RuntimeError::ListLengthMismatch { .. } => ErrorCode::E0826,
+            RuntimeError::JsonSerializationError(_) => ErrorCode::E0827,
+            RuntimeError::JsonParsingError(_) => ErrorCode::E0828,
+            RuntimeError::JsonDeserializationError(..) => ErrorCode::E0829,
+            RuntimeError::UnknownResultType(_) => ErrorCode::E0830,
+            RuntimeError::MissingScriptArgument { .. } => ErrorCode::E0831,
+            RuntimeError::EnvironmentVariableNotSet(_) => ErrorCode::E0832,
+            RuntimeError::QuantityParsingError(..) => ErrorCode::E0833,
+            RuntimeError::FileWriteError(..) => ErrorCode::E0834,
+            RuntimeError::CsvError(..) => ErrorCode::E0835,
+            RuntimeError::InvalidNumberOfIterations(_) => ErrorCode::E0836,
+            RuntimeError::LoopIterationLimitExceeded(_) => ErrorCode::E0837,
+            RuntimeError::InvalidClampBounds(..) => ErrorCode::E0838,
+            RuntimeError::CanNotSpellOutNumber(_) => ErrorCode::E0839,
+            RuntimeError::DynamicValueHasWrongType(..) => ErrorCode::E0840,
+            RuntimeError::NonIntegerSeriesBound(_) => ErrorCode::E0841,
+            RuntimeError::InvalidMatrixShape(_) => ErrorCode::E0842,
+            RuntimeError::SingularMatrix(_) => ErrorCode::E0843,
+            RuntimeError::ImplicitConversionForbidden(..) => ErrorCode::E0844,
+            RuntimeError::InvalidImplicitConversions(_) => ErrorCode::E0845,
+            RuntimeError::NonIntegerListIndex(_) => ErrorCode::E0846,
+            RuntimeError::NonIntegerArgument(..) => ErrorCode::E0847,
+            RuntimeError::NegativeArgument(..) => ErrorCode::E0848,
+            RuntimeError::IntegerResultTooLarge(_) => ErrorCode::E0849,
+            RuntimeError::IsolatedUnitConversion(..) => ErrorCode::E0850,
+            RuntimeError::InvalidRegex(..) => ErrorCode::E0851,
+            RuntimeError::NoSuchRegexGroup(..) => ErrorCode::E0852,
+            RuntimeError::RegexGroupDidNotMatch(..) => ErrorCode::E0853,
+            RuntimeError::InvalidUcum(..) => ErrorCode::E0854,
+            RuntimeError::UcumExportError(..) => ErrorCode::E0855,
+            RuntimeError::UcumDimensionMismatch(..) => ErrorCode::E0858,
+            RuntimeError::UnsortedTimeSeries(..) => ErrorCode::E0856,
+            RuntimeError::InvalidResamplingInterval(..) => ErrorCode::E0857,
+            RuntimeError::ResultIndexOutOfRange(..) => ErrorCode::E0859,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -69,42 +314,57 @@ pub enum InterpreterResult {
 impl InterpreterResult {
     pub fn to_markup(
         &self,
-        evaluated_statement: Option<&Statement>,
+        evaluated_statement: Option<TypedStatement>,
         registry: &DimensionRegistry,
         with_type_info: bool,
         with_equal_sign: bool,
     ) -> Markup {
         match self {
-            Self::Value(value) => {
-                let leader = if with_equal_sign {
-                    m::whitespace("    ") + m::operator("=") + m::space()
-                } else {
-                    m::empty()
-                };
-
-                let type_markup = if with_type_info {
-                    evaluated_statement
-                        .and_then(Statement::as_expression)
-                        .and_then(|e| {
-                            let type_ = e.get_type_scheme();
-                            if type_.is_scalar() {
-                                None
-                            } else {
-                                let ty = type_.to_readable_type(registry);
-                                Some(m::dimmed("    [") + ty + m::dimmed("]"))
-                            }
-                        })
-                        .unwrap_or_else(m::empty)
-                } else {
-                    m::empty()
-                };
-
-                leader + value.pretty_print() + type_markup + m::nl()
-            }
+            Self::Value(value) => Self::value_to_markup(
+                value.pretty_print(),
+                evaluated_statement,
+                registry,
+                with_type_info,
+                with_equal_sign,
+            ),
             Self::Continue => m::empty(),
         }
     }
 
+    /// Like [`InterpreterResult::to_markup`], for a value that has already been
+    /// formatted.
+    pub(crate) fn value_to_markup(
+        value_markup: Markup,
+        evaluated_statement: Option<TypedStatement>,
+        registry: &DimensionRegistry,
+        with_type_info: bool,
+        with_equal_sign: bool,
+    ) -> Markup {
+        let leader = if with_equal_sign {
+            m::whitespace("    ") + m::operator("=") + m::space()
+        } else {
+            m::empty()
+        };
+
+        let type_markup = if with_type_info {
+            evaluated_statement
+                .and_then(|statement| statement.expression_type())
+                .and_then(|type_| {
+                    if type_.is_scalar() {
+                        None
+                    } else {
+                        let ty = type_.to_readable_type(registry);
+                        Some(m::dimmed("    [") + ty + m::dimmed("]"))
+                    }
+                })
+                .unwrap_or_else(m::empty)
+        } else {
+            m::empty()
+        };
+
+        leader + value_markup + type_markup + m::nl()
+    }
+
     /// Returns `true` if the interpreter result is [`Value`].
     ///
     /// [`Value`]: InterpreterResult::Value
@@ -131,29 +391,13 @@ impl InterpreterResult {
 
 pub type Result<T> = std::result::Result<T, RuntimeError>;
 
-pub type PrintFunction = dyn FnMut(&Markup) + Send;
-
-pub struct InterpreterSettings {
-    pub print_fn: Box<PrintFunction>,
-}
-
-impl Default for InterpreterSettings {
-    fn default() -> Self {
-        Self {
-            print_fn: Box::new(move |s: &Markup| {
-                print!("{}", s);
-            }),
-        }
-    }
-}
-
 pub trait Interpreter {
     fn new() -> Self;
 
     fn interpret_statements(
         &mut self,
-        settings: &mut InterpreterSettings,
-        statements: &[Statement],
+        output: &mut dyn OutputSink,
+        program: &TypedProgram,
         dimension_registry: &DimensionRegistry,
     ) -> Result<InterpreterResult>;
     fn get_unit_registry(&self) -> &UnitRegistry;
@@ -161,6 +405,7 @@ pub trait Interpreter {
 
 #[cfg(test)]
 mod tests {
+    use crate::output::TerminalSink;
     use crate::prefix_parser::AcceptsPrefix;
     use crate::unit::{CanonicalName, Unit};
     use crate::{bytecode_interpreter::BytecodeInterpreter, prefix_transformer::Transformer};
@@ -206,7 +451,7 @@ mod tests {
             .check(statements_transformed)
             .expect("No type check errors for inputs in this test suite");
         BytecodeInterpreter::new().interpret_statements(
-            &mut InterpreterSettings::default(),
+            &mut TerminalSink,
             &statements_typechecked,
             typechecker.registry(),
         )
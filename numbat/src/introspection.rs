@@ -0,0 +1,370 @@
+//! A structured catalog of everything that is defined in a context, see
+//! [`crate::Context::introspect`]. This is meant for generating documentation
+//! and for tools like editor integrations.
+
+use itertools::Itertools;
+
+use crate::ast::formula_function_name;
+use crate::interpreter::Interpreter;
+use crate::resolver::CodeSource;
+use crate::span::Span;
+use crate::unit::BaseUnitAndFactor;
+use crate::Context;
+
+/// Where a function, unit, dimension or struct has been defined.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Origin {
+    /// The module that contains the definition, like `math::statistics`. `None` for
+    /// definitions in files, on the command line or in an interactive session.
+    pub module: Option<String>,
+
+    /// Whether the definition has been loaded through the prelude, as opposed to
+    /// being part of the current session.
+    pub prelude: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeParameter {
+    pub name: String,
+    /// The bound of the type parameter, like `Dim` in `fn f<D: Dim>(…)`
+    pub bound: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Parameter {
+    pub name: String,
+    pub type_: String,
+    pub has_default: bool,
+    /// Whether the parameter takes one or more arguments (`xs: D…`)
+    pub variadic: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionEntry {
+    pub name: String,
+    /// The human-readable name from the `@name` decorator
+    pub display_name: Option<String>,
+    /// The full signature, like `fn sqrt<D: Dim>(x: D^2) -> D`
+    pub signature: String,
+    pub type_parameters: Vec<TypeParameter>,
+    pub parameters: Vec<Parameter>,
+    pub return_type: String,
+    pub description: Option<String>,
+    pub url: Option<String>,
+    /// The message of the `@deprecated` decorator
+    pub deprecated: Option<String>,
+    pub origin: Origin,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnitEntry {
+    pub name: String,
+    /// The human-readable name from the `@name` decorator
+    pub display_name: Option<String>,
+    /// All names of the unit, including the canonical one
+    pub aliases: Vec<String>,
+    /// The physical dimension, like `Length` or `Length / Time`
+    pub dimension: String,
+    /// The defining expression in terms of other units, like `0.0254 m`.
+    /// `None` for base units.
+    pub definition: Option<String>,
+    pub metric_prefixes: bool,
+    pub binary_prefixes: bool,
+    pub description: Option<String>,
+    pub url: Option<String>,
+    /// The message of the `@deprecated` decorator
+    pub deprecated: Option<String>,
+    pub origin: Origin,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DimensionEntry {
+    pub name: String,
+    /// The dimension in terms of base dimensions, like `Length / Time`
+    pub base_representation: String,
+    pub origin: Origin,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructEntry {
+    pub name: String,
+    /// Field names and their types, in the order of the definition
+    pub fields: Vec<(String, String)>,
+    /// Names of the methods from `impl` blocks, in the order of their definition
+    pub methods: Vec<String>,
+    pub origin: Origin,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormulaEntry {
+    pub name: String,
+    /// The equation as written in the definition, like `voltage = current * resistance`
+    pub equation: String,
+    /// Names of the quantities and their types, in the order of the definition
+    pub quantities: Vec<(String, String)>,
+    /// The functions that solve the formula for each of the quantities, in the
+    /// same order
+    pub functions: Vec<String>,
+    pub origin: Origin,
+}
+
+/// All functions, units, dimensions, structs and formulas of a context. Each list
+/// is sorted in the order of definition.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Catalog {
+    pub functions: Vec<FunctionEntry>,
+    pub units: Vec<UnitEntry>,
+    pub dimensions: Vec<DimensionEntry>,
+    pub structs: Vec<StructEntry>,
+    pub formulas: Vec<FormulaEntry>,
+}
+
+impl Catalog {
+    pub fn function(&self, name: &str) -> Option<&FunctionEntry> {
+        self.functions.iter().find(|f| f.name == name)
+    }
+
+    pub fn unit(&self, name: &str) -> Option<&UnitEntry> {
+        self.units.iter().find(|u| u.name == name)
+    }
+
+    pub fn dimension(&self, name: &str) -> Option<&DimensionEntry> {
+        self.dimensions.iter().find(|d| d.name == name)
+    }
+
+    pub fn struct_(&self, name: &str) -> Option<&StructEntry> {
+        self.structs.iter().find(|s| s.name == name)
+    }
+
+    pub fn formula(&self, name: &str) -> Option<&FormulaEntry> {
+        self.formulas.iter().find(|f| f.name == name)
+    }
+}
+
+/// Sort key for the order of definition
+fn definition_order(span: Option<Span>) -> (usize, u32) {
+    span.map_or((usize::MAX, 0), |s| (s.code_source_id, s.start.byte))
+}
+
+impl Context {
+    fn origin(&self, span: Option<Span>) -> Origin {
+        let Some(span) = span else {
+            return Origin {
+                module: None,
+                prelude: false,
+            };
+        };
+
+        let module = match self.resolver.get_code_source(span.code_source_id) {
+            CodeSource::Module(path, _) => Some(path.to_string()),
+            _ => None,
+        };
+        Origin {
+            module,
+            prelude: self.resolver.is_part_of_prelude(span.code_source_id),
+        }
+    }
+
+    fn function_entries(&self) -> Vec<FunctionEntry> {
+        let registry = self.dimension_registry();
+
+        self.function_names()
+            .unique()
+            .filter_map(|name| {
+                let (signature, metadata) = self.typechecker.lookup_function(&name)?;
+                let (fn_type, type_parameters, parameters, return_type) =
+                    signature.readable_types(registry);
+
+                let num_parameters = parameters.len();
+                let parameters = parameters
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, (parameter_name, type_))| Parameter {
+                        name: parameter_name.to_string(),
+                        type_: type_.to_string(),
+                        has_default: i >= num_parameters - signature.num_default_parameters,
+                        variadic: i + 1 == num_parameters && signature.is_variadic(),
+                    })
+                    .collect();
+
+                let type_parameters = type_parameters
+                    .iter()
+                    .map(|tv| TypeParameter {
+                        name: tv.unsafe_name().to_string(),
+                        bound: fn_type.bounds.bound_name(tv).map(String::from),
+                    })
+                    .collect();
+
+                Some(FunctionEntry {
+                    display_name: metadata.name.clone(),
+                    signature: signature.pretty_print(registry).to_string(),
+                    type_parameters,
+                    parameters,
+                    return_type: return_type.to_string(),
+                    description: metadata.description.clone(),
+                    url: metadata.url.clone(),
+                    deprecated: self
+                        .typechecker
+                        .deprecation_message(&name)
+                        .map(String::from),
+                    origin: self.origin(Some(signature.definition_span)),
+                    name,
+                })
+            })
+            .collect()
+    }
+
+    fn unit_entries(&self) -> Vec<UnitEntry> {
+        let units = &self.interpreter.get_unit_registry().inner;
+
+        units
+            .iter_base_entries()
+            .chain(units.iter_derived_entries())
+            .sorted_by_key(|name| definition_order(units.get_definition_span(*name)))
+            .filter_map(|name| {
+                let (_, metadata) = units.get_base_representation(name).ok()?;
+
+                let definition = self
+                    .interpreter
+                    .get_defining_unit(&name)
+                    .and_then(|unit| {
+                        unit.iter()
+                            .filter(|f| !f.unit_id.is_base())
+                            .map(|f| f.unit_id.unit_and_factor())
+                            .next()
+                    })
+                    .map(|BaseUnitAndFactor(unit, factor)| {
+                        format!(
+                            "{} {}",
+                            factor.pretty_print(),
+                            unit.pretty_print_with(|f| f.exponent, 'x', '/', true, None)
+                        )
+                    });
+
+                Some(UnitEntry {
+                    name: name.to_string(),
+                    display_name: metadata.name,
+                    aliases: metadata
+                        .aliases
+                        .iter()
+                        .map(|(alias, _)| alias.to_string())
+                        .collect(),
+                    dimension: metadata.readable_type.to_string(),
+                    definition,
+                    metric_prefixes: metadata.metric_prefixes,
+                    binary_prefixes: metadata.binary_prefixes,
+                    description: metadata.description,
+                    url: metadata.url,
+                    deprecated: metadata.deprecated,
+                    origin: self.origin(units.get_definition_span(name)),
+                })
+            })
+            .collect()
+    }
+
+    fn dimension_entries(&self) -> Vec<DimensionEntry> {
+        let registry = self.dimension_registry();
+
+        self.dimension_names()
+            .iter()
+            .unique()
+            .filter_map(|name| {
+                let symbol = crate::Symbol::get(name)?;
+                let representation = registry.get_base_representation_for_name(symbol).ok()?;
+                Some(DimensionEntry {
+                    name: name.clone(),
+                    base_representation: representation.to_string(),
+                    origin: self.origin(registry.get_definition_span(symbol)),
+                })
+            })
+            .collect()
+    }
+
+    fn struct_entries(&self) -> Vec<StructEntry> {
+        let registry = self.dimension_registry();
+
+        self.typechecker
+            .structs()
+            .filter(|info| !info.name.starts_with('_'))
+            .sorted_by_key(|info| definition_order(Some(info.definition_span)))
+            .map(|info| StructEntry {
+                name: info.name.to_string(),
+                fields: info
+                    .fields
+                    .iter()
+                    .map(|(field, (_, type_))| {
+                        (
+                            field.to_string(),
+                            type_.to_readable_type(registry).to_string(),
+                        )
+                    })
+                    .collect(),
+                methods: self
+                    .typechecker
+                    .methods(info.name)
+                    .iter()
+                    .map(|method| method.to_string())
+                    .collect(),
+                origin: self.origin(Some(info.definition_span)),
+            })
+            .collect()
+    }
+
+    fn formula_entries(&self) -> Vec<FormulaEntry> {
+        let registry = self.dimension_registry();
+
+        self.typechecker
+            .formulas()
+            .sorted_by_key(|info| definition_order(Some(info.definition_span)))
+            .map(|info| {
+                let span = info.equation_span;
+                let equation = self
+                    .resolver
+                    .files
+                    .get(span.code_source_id)
+                    .ok()
+                    .and_then(|file| {
+                        file.source()
+                            .get(span.start.byte as usize..span.end.byte as usize)
+                    })
+                    .unwrap_or_default()
+                    .to_string();
+
+                let (quantities, functions) = info
+                    .quantities
+                    .iter()
+                    .map(|quantity| {
+                        let function = formula_function_name(info.name, *quantity);
+                        let type_ = self
+                            .typechecker
+                            .lookup_function(function.as_str())
+                            .map(|(signature, _)| signature.readable_types(registry).3)
+                            .map(|type_| type_.to_string())
+                            .unwrap_or_default();
+                        ((quantity.to_string(), type_), function.to_string())
+                    })
+                    .unzip();
+
+                FormulaEntry {
+                    name: info.name.to_string(),
+                    equation,
+                    quantities,
+                    functions,
+                    origin: self.origin(Some(info.definition_span)),
+                }
+            })
+            .collect()
+    }
+
+    /// A catalog of all functions, units, dimensions, structs and formulas that are
+    /// currently defined, both from the prelude and from the current session.
+    pub fn introspect(&self) -> Catalog {
+        Catalog {
+            functions: self.function_entries(),
+            units: self.unit_entries(),
+            dimensions: self.dimension_entries(),
+            structs: self.struct_entries(),
+            formulas: self.formula_entries(),
+        }
+    }
+}
@@ -6,11 +6,16 @@ pub const KEYWORDS: &[&str] = &[
     "to ",
     "let ",
     "fn ",
+    "unitfn ",
     "where ",
+    "with ",
     "dimension ",
     "unit ",
     "use ",
     "struct ",
+    "impl ",
+    "formula ",
+    "unit_system ",
     // 'inline' keywords
     "long",
     "short",
@@ -19,6 +24,11 @@ pub const KEYWORDS: &[&str] = &[
     "if",
     "then",
     "else",
+    "for",
+    "while",
+    "try",
+    "catch",
+    "inverse",
     "true",
     "false",
     "NaN",
@@ -28,15 +38,29 @@ pub const KEYWORDS: &[&str] = &[
     "assert(",
     "assert_eq(",
     "type(",
+    "conversion_cache_stats(",
+    "regex_cache_stats(",
+    "instantiation_cache_stats(",
+    "set_fixed_time(",
+    "set_implicit_conversions(",
+    "write_text(",
+    "append_csv(",
+    "trace(",
+    "partial(",
+    "autodiff(",
+    "is_dimensionless(",
+    "has_dimension(",
     // Type names
     "Bool",
     "String",
     "DateTime",
+    "Dynamic",
     "Fn",
     "List",
     // decorators
     "metric_prefixes",
     "binary_prefixes",
+    "isolated",
     "aliases",
     "name",
     "url",
@@ -1,38 +1,60 @@
 mod arithmetic;
 mod ast;
+#[cfg(test)]
+mod ast_generator;
 #[cfg(feature = "html-formatter")]
 pub mod buffered_writer;
+pub mod build_info;
 mod bytecode_interpreter;
+pub mod clock;
+pub mod codata;
 mod column_formatter;
+mod conversion_cache;
+#[cfg(feature = "currency")]
 mod currency;
 mod datetime;
 mod decorator;
+pub mod dependencies;
 pub mod diagnostic;
 mod dimension;
+mod duration;
+pub mod error_code;
+mod features;
 mod ffi;
+pub mod file_system;
 mod gamma;
 pub mod help;
+pub mod highlighting;
 #[cfg(feature = "html-formatter")]
 pub mod html_formatter;
+mod instantiation_cache;
 mod interpreter;
+pub mod introspection;
 pub mod keywords;
 pub mod list;
+pub mod locale;
 pub mod markup;
 mod math;
 pub mod module_importer;
 mod name_resolution;
 mod number;
+pub mod output;
 mod parser;
 mod prefix;
 mod prefix_parser;
 mod prefix_transformer;
 pub mod pretty_print;
 mod product;
+pub mod profile;
 mod quantity;
+mod regex_cache;
 mod registry;
 pub mod resolver;
+pub mod sandbox;
+mod sequence;
 mod span;
 mod suggestion;
+mod symbol;
 mod tokenizer;
 mod traversal;
 mod type_variable;
@@ -44,39 +66,72 @@ mod unit_registry;
 pub mod value;
 mod vm;
 
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
 use bytecode_interpreter::BytecodeInterpreter;
+use clock::Clock;
 use column_formatter::ColumnFormatter;
+#[cfg(feature = "currency")]
 use currency::ExchangeRatesCache;
+use dependencies::SessionDependencies;
 use diagnostic::ErrorDiagnostic;
 use dimension::DimensionRegistry;
+use file_system::FileSystem;
+use highlighting::{ClassifiedToken, TokenClass};
 use interpreter::Interpreter;
+use itertools::Itertools;
 use keywords::KEYWORDS;
+use locale::Locale;
 use markup as m;
 use markup::FormatType;
 use markup::Markup;
 use module_importer::{ModuleImporter, NullImporter};
+#[cfg(feature = "currency")]
+use output::DiagnosticsOnlySink;
+use output::{LocalizingSink, NullSink, OutputSink, TerminalSink};
 use prefix_transformer::Transformer;
+use pretty_print::PrettyPrint;
+use profile::Profile;
 
 use resolver::CodeSource;
 use resolver::Resolver;
 use resolver::ResolverError;
+#[cfg(feature = "currency")]
+use sandbox::Capability;
+use sandbox::SandboxPolicy;
 use thiserror::Error;
-use typechecker::{TypeCheckError, TypeChecker};
+use tokenizer::DigitComma;
+use typechecker::{TypeCheckError, TypeCheckWarning, TypeChecker};
+use value::Value;
 
 pub use diagnostic::Diagnostic;
+pub use duration::Duration;
+pub use interpreter::ImplicitConversions;
 pub use interpreter::InterpreterResult;
-pub use interpreter::InterpreterSettings;
 pub use interpreter::RuntimeError;
+pub use interpreter::RuntimeWarning;
 pub use name_resolution::NameResolutionError;
+pub use name_resolution::UnitCandidate;
+use name_resolution::{indexed_result_identifier, LAST_RESULT_IDENTIFIERS};
 pub use parser::ParseError;
+use parser::ParseWarning;
+pub use quantity::Quantity;
 pub use registry::BaseRepresentation;
 pub use registry::BaseRepresentationFactor;
-pub use typed_ast::Statement;
+pub use span::{SourceCodePositition, Span};
+pub use symbol::Symbol;
 pub use typed_ast::Type;
-use unit::BaseUnitAndFactor;
+pub use typed_ast::TypedProgram;
+pub use typed_ast::TypedStatement;
+
+/// Former name of the statements that [`Context::interpret`] returns
+#[deprecated(note = "use `TypedStatement`, see `TypedProgram::statements`")]
+pub type Statement<'a> = TypedStatement<'a>;
+use unit::{BaseUnitAndFactor, Unit};
 use unit_registry::UnitMetadata;
 
-use crate::prefix_parser::PrefixParserResult;
+use crate::prefix_parser::{AcceptsPrefix, PrefixParser, PrefixParserResult};
 use crate::unicode_input::UNICODE_INPUT;
 
 #[derive(Debug, Clone, Error)]
@@ -93,25 +148,95 @@ pub enum NumbatError {
 
 type Result<T> = std::result::Result<T, NumbatError>;
 
+/// Holds values that are passed in by [`Context::set_variable`].
+const FOREIGN_VALUE_IDENTIFIER: &str = "__foreign_value";
+
+/// A candidate from [`Context::get_detailed_completions_for`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    pub word: String,
+    /// The unit, function or constant has been marked with `@deprecated`
+    pub deprecated: bool,
+}
+
+/// The state of a context before an input has been evaluated, see [`Context::undo`]
+#[derive(Clone)]
+struct Snapshot {
+    prefix_transformer: Transformer,
+    typechecker: TypeChecker,
+    interpreter: BytecodeInterpreter,
+    num_imported_modules: usize,
+    num_session_statements: usize,
+}
+
+/// The state of a context that can be returned to with [`Context::restore`],
+/// e.g. after a panic while evaluating an input. Settings and the undo history
+/// are not part of it.
+pub struct Checkpoint(Snapshot);
+
 #[derive(Clone)]
 pub struct Context {
     prefix_transformer: Transformer,
     typechecker: TypeChecker,
     interpreter: BytecodeInterpreter,
     resolver: Resolver,
+    #[cfg(feature = "currency")]
     load_currency_module_on_demand: bool,
     terminal_width: Option<usize>,
+    /// Receives printed output, diagnostics and assertion results. Clones of a
+    /// context share the same sink.
+    output_sink: Arc<Mutex<dyn OutputSink>>,
+    sandbox_policy: SandboxPolicy,
+    locale: Locale,
+    long_unit_names: bool,
+    max_unit_complexity: Option<u32>,
+    deprecation_warnings: bool,
+    uncertainty_warnings: bool,
+    /// The maximum length of `undo_stack`
+    undo_limit: usize,
+    /// The states before the last inputs, most recent last
+    undo_stack: VecDeque<Snapshot>,
+    /// The evaluated statements of this session, see [`Context::dependency_graph`]
+    session_dependencies: SessionDependencies,
 }
 
 impl Context {
     pub fn new(module_importer: impl ModuleImporter + 'static) -> Self {
+        Self::new_with_sandbox_policy(module_importer, SandboxPolicy::unrestricted())
+    }
+
+    /// Create a context whose evaluated code is restricted by the given policy.
+    /// The policy can not be changed afterwards.
+    pub fn new_with_sandbox_policy(
+        module_importer: impl ModuleImporter + 'static,
+        sandbox_policy: SandboxPolicy,
+    ) -> Self {
+        let mut interpreter = BytecodeInterpreter::new();
+        interpreter.set_sandbox_policy(sandbox_policy.clone());
+
+        let mut resolver = Resolver::new(module_importer);
+        if sandbox_policy.is_strict() {
+            resolver = resolver.sandboxed();
+        }
+
         Context {
             prefix_transformer: Transformer::new(),
             typechecker: TypeChecker::default(),
-            interpreter: BytecodeInterpreter::new(),
-            resolver: Resolver::new(module_importer),
+            interpreter,
+            resolver,
+            #[cfg(feature = "currency")]
             load_currency_module_on_demand: false,
             terminal_width: None,
+            output_sink: Arc::new(Mutex::new(TerminalSink)),
+            sandbox_policy,
+            locale: Locale::c(),
+            long_unit_names: false,
+            max_unit_complexity: None,
+            deprecation_warnings: true,
+            uncertainty_warnings: true,
+            undo_limit: 0,
+            undo_stack: VecDeque::new(),
+            session_dependencies: SessionDependencies::default(),
         }
     }
 
@@ -123,19 +248,467 @@ impl Context {
         self.interpreter.set_debug(activate);
     }
 
+    pub fn sandbox_policy(&self) -> &SandboxPolicy {
+        &self.sandbox_policy
+    }
+
+    /// Set the source of the current time for `now()` (and functions based on it,
+    /// like `today()`). The system clock is used by default. In strict sandbox
+    /// mode, the current time is only available if a clock has been set here.
+    pub fn set_clock(&mut self, clock: impl Clock + 'static) {
+        self.interpreter.set_clock(Arc::new(clock));
+    }
+
+    /// Set the destination of files written by `write_text(…)` and
+    /// `append_csv(…)`. The file system of the operating system is used by
+    /// default.
+    pub fn set_file_system(&mut self, file_system: Arc<dyn FileSystem>) {
+        self.interpreter.set_file_system(file_system);
+    }
+
+    /// Set the arguments that are available to scripts through `args()` and
+    /// `arg(n)`, e.g. the ones following `--` on the command line.
+    pub fn set_script_arguments(&mut self, arguments: Vec<String>) {
+        self.interpreter.set_script_arguments(arguments);
+    }
+
+    /// Record the number of calls and the time spent in each function, from now on.
+    /// Enabling profiling again starts over. Calls to pure functions in `map` are not
+    /// evaluated in parallel while profiling is enabled.
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.interpreter.set_profiling(enabled);
+    }
+
+    /// Everything that has been recorded since profiling was enabled, or `None` if
+    /// it is disabled.
+    pub fn profile(&self) -> Option<Profile> {
+        self.interpreter.profile()
+    }
+
+    /// Set the locale in which numbers are printed by `print`. Callers that turn
+    /// values into text themselves (e.g. with [`PrettyPrint`](pretty_print::PrettyPrint))
+    /// can use [`Locale::localize`]. If decimal comma input is enabled, it also
+    /// changes how number literals in code that is passed to [`Context::interpret`]
+    /// are parsed (but not those in imported modules).
+    pub fn set_locale(&mut self, locale: Locale) {
+        self.resolver.set_digit_comma(match locale.decimal_mark() {
+            '.' => DigitComma::Separator,
+            _ if locale.has_decimal_comma_input() => DigitComma::DecimalMark,
+            _ => DigitComma::Ambiguous,
+        });
+        self.locale = locale;
+    }
+
+    pub fn locale(&self) -> &Locale {
+        &self.locale
+    }
+
+    /// Write the units of results out in full, like `3 kilometres` instead of
+    /// `3 km`. Units are written with their long names (the name of the unit in
+    /// singular and, by convention, the first alias that takes long prefixes as
+    /// the plural). This does not affect the output of `print`.
+    pub fn set_long_unit_names(&mut self, enabled: bool) {
+        self.long_unit_names = enabled;
+    }
+
+    /// Do not show units of results whose base representation is more complex than
+    /// the given limit, like `kg³·m⁻⁷·s⁵·A⁻²` (complexity 17, the sum of the absolute
+    /// exponents). Such results are formatted by [`Context::result_to_markup`] as a
+    /// number in scientific notation with a note, unless there is a named unit with
+    /// the same base representation. The value itself keeps its unit, and `info`
+    /// shows it in full. Disabled (`None`) by default.
+    pub fn set_max_unit_complexity(&mut self, max_complexity: Option<u32>) {
+        self.max_unit_complexity = max_complexity;
+    }
+
+    /// Limit how deeply expressions, statements, type annotations and patterns
+    /// can be nested (the default is 256). Deeper nesting is reported as a parse
+    /// error. This protects against stack overflows when evaluating untrusted
+    /// input. Note that each stage of the pipeline is recursive, so the available
+    /// stack size has to be large enough for the chosen limit.
+    pub fn set_max_nesting_depth(&mut self, depth: usize) {
+        self.resolver.set_max_nesting_depth(depth);
+    }
+
+    /// Remember the state before each of the last `limit` successfully evaluated
+    /// inputs from [`CodeSource::Text`], such that they can be reverted with
+    /// [`Context::undo`]. This is disabled (zero) by default. Each step keeps a
+    /// copy of the environment, so the limit should be small.
+    pub fn set_undo_limit(&mut self, limit: usize) {
+        self.undo_limit = limit;
+        while self.undo_stack.len() > limit {
+            self.undo_stack.pop_front();
+        }
+    }
+
+    /// The number of inputs that can currently be reverted with [`Context::undo`]
+    pub fn undo_steps_available(&self) -> usize {
+        self.undo_stack.len()
+    }
+
+    /// Revert the last `steps` inputs: all variables, functions, units,
+    /// dimensions, structs and results (`ans`) are restored to the state before
+    /// them. Returns `false` without changing anything if fewer steps are
+    /// available, see [`Context::set_undo_limit`].
+    pub fn undo(&mut self, steps: usize) -> bool {
+        if steps > self.undo_stack.len() {
+            return false;
+        }
+        let Some(snapshot) = self
+            .undo_stack
+            .drain(self.undo_stack.len() - steps..)
+            .next()
+        else {
+            return true;
+        };
+
+        self.restore_snapshot(snapshot);
+        true
+    }
+
+    /// Copy the current state (variables, functions, units, dimensions, structs
+    /// and results), such that it can be restored with [`Context::restore`].
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(Snapshot {
+            prefix_transformer: self.prefix_transformer.clone(),
+            typechecker: self.typechecker.clone(),
+            interpreter: self.interpreter.clone(),
+            num_imported_modules: self.resolver.num_imported_modules(),
+            num_session_statements: self.session_dependencies.len(),
+        })
+    }
+
+    /// Return to the state of a [`Checkpoint`]. Settings and the inputs that can
+    /// be reverted with [`Context::undo`] are kept.
+    pub fn restore(&mut self, checkpoint: Checkpoint) {
+        self.restore_snapshot(checkpoint.0);
+    }
+
+    fn restore_snapshot(&mut self, snapshot: Snapshot) {
+        self.prefix_transformer = snapshot.prefix_transformer;
+        self.typechecker = snapshot.typechecker;
+        let mut current_interpreter =
+            std::mem::replace(&mut self.interpreter, snapshot.interpreter);
+        self.interpreter
+            .take_profiler_from(&mut current_interpreter);
+        self.resolver
+            .truncate_imported_modules(snapshot.num_imported_modules);
+        self.session_dependencies
+            .truncate(snapshot.num_session_statements);
+    }
+
+    /// Treat `Angle` as a base dimension instead of a dimensionless quantity. The
+    /// trigonometric functions then require arguments in an angle unit like `rad`
+    /// or `deg` and the inverse functions return angles. Bare numbers are no longer
+    /// accepted as radians. This needs to be set before the prelude is loaded, it
+    /// has no effect afterwards.
+    pub fn set_strict_angles(&mut self, strict: bool) {
+        self.resolver.set_strict_angles(strict);
+    }
+
+    pub fn has_strict_angles(&self) -> bool {
+        self.resolver.has_strict_angles()
+    }
+
+    /// Warn about uses of units, functions and constants that have been marked with
+    /// `@deprecated` (enabled by default). The warnings can also be disabled for a
+    /// single file by adding a `# numbat: allow-deprecated` comment line to it.
+    pub fn set_deprecation_warnings(&mut self, enabled: bool) {
+        self.deprecation_warnings = enabled;
+    }
+
+    /// Warn if the uncertainty of a number literal like `6.67430(15)e-11` is discarded
+    /// (enabled by default).
+    pub fn set_uncertainty_warnings(&mut self, enabled: bool) {
+        self.uncertainty_warnings = enabled;
+    }
+
+    /// Allow the `Dynamic` type (enabled by default). Values of this type are only
+    /// checked at runtime, where they are used as values of a static type. Strict
+    /// projects can forbid it, which turns all `Dynamic` annotations into errors.
+    pub fn set_dynamic_type_allowed(&mut self, allowed: bool) {
+        self.typechecker.set_forbid_dynamic(!allowed);
+    }
+
+    /// Set the sink that is used by [`Context::interpret`] and [`Context::print_diagnostic`].
+    /// The default is a [`TerminalSink`].
+    pub fn set_output_sink(&mut self, sink: impl OutputSink + 'static) {
+        self.output_sink = Arc::new(Mutex::new(sink));
+    }
+
+    /// Set the number of threads that are used to evaluate `map`, `sum`, `max_of`
+    /// and `min_of` on large lists. Setting this to 1 disables parallel evaluation.
+    /// By default, all available CPU cores are used (except on WebAssembly, where
+    /// parallel evaluation is not supported).
+    pub fn set_num_threads(&mut self, num_threads: usize) {
+        self.interpreter.set_num_threads(num_threads);
+    }
+
+    /// Set the maximum number of iterations of a `while` loop, after which it is
+    /// aborted with an error. The default is 10 million.
+    pub fn set_max_loop_iterations(&mut self, max_iterations: u64) {
+        self.interpreter.set_max_loop_iterations(max_iterations);
+    }
+
+    /// Warn if the estimated relative rounding error of `sum` (or of a function based
+    /// on it, like `mean`) exceeds the given threshold, e.g. `1e-9`. Sums are always
+    /// computed with compensated summation, so this only happens if the terms cancel
+    /// each other to a large extent. Disabled (`None`) by default, since the estimate
+    /// requires summing sequentially.
+    pub fn set_summation_error_threshold(&mut self, threshold: Option<f64>) {
+        self.interpreter.set_summation_error_threshold(threshold);
+    }
+
+    /// Choose whether quantities with different units (but the same dimension) can
+    /// be added and subtracted without an explicit conversion, like in `1 m + 1 ft`.
+    /// With [`ImplicitConversions::Warn`], every conversion is reported along with its
+    /// factor. The setting can also be changed with `set_implicit_conversions("…")`.
+    pub fn set_implicit_conversions(&mut self, implicit_conversions: ImplicitConversions) {
+        self.interpreter
+            .set_implicit_conversions(implicit_conversions);
+    }
+
+    /// Load `units::currencies` (and fetch the exchange rates) when a currency unit
+    /// like `USD` is used for the first time.
+    #[cfg(feature = "currency")]
     pub fn load_currency_module_on_demand(&mut self, yes: bool) {
         self.load_currency_module_on_demand = yes;
     }
 
     /// Fill the currency exchange rate cache. This call is blocking.
+    #[cfg(feature = "currency")]
     pub fn prefetch_exchange_rates() {
         let _unused = ExchangeRatesCache::fetch();
     }
 
+    /// Replace the exchange rates. Currency units that have already been defined
+    /// are updated before the next evaluation.
+    #[cfg(feature = "currency")]
     pub fn set_exchange_rates(xml_content: &str) {
         ExchangeRatesCache::set_from_xml(xml_content);
     }
 
+    /// Use the given function to get the exchange rates (in the XML format of the
+    /// European Central Bank) instead of fetching them from the network. It is
+    /// called when the rates are needed for the first time, and whenever they are
+    /// refreshed.
+    #[cfg(feature = "currency")]
+    pub fn set_exchange_rates_provider(
+        provider: impl Fn() -> Option<String> + Send + Sync + 'static,
+    ) {
+        ExchangeRatesCache::set_provider(provider);
+    }
+
+    /// How long to wait for the exchange rates (five seconds by default). After
+    /// that, the cached rates are used, if there are any.
+    #[cfg(feature = "currency")]
+    pub fn set_exchange_rates_timeout(timeout: std::time::Duration) {
+        ExchangeRatesCache::set_timeout(timeout);
+    }
+
+    /// Exchange rates (in the XML format of the European Central Bank) that have
+    /// been fetched at the given time. They are used, with a warning that states
+    /// their age, if fresh rates can not be fetched.
+    #[cfg(feature = "currency")]
+    pub fn set_cached_exchange_rates(xml_content: &str, fetched_at: std::time::SystemTime) {
+        ExchangeRatesCache::set_cached(xml_content, fetched_at);
+    }
+
+    /// Like [`Context::set_cached_exchange_rates`], but the cached rates are read
+    /// from the given file, if it exists. Fetched rates are written to it.
+    #[cfg(feature = "currency")]
+    pub fn set_exchange_rates_cache_file(path: impl Into<std::path::PathBuf>) {
+        ExchangeRatesCache::set_cache_file(path.into());
+    }
+
+    /// Fetch fresh exchange rates, which replace the current ones. Currency units
+    /// that have already been defined are updated before the next evaluation. This
+    /// call is blocking. If fresh rates can not be fetched, the current (or cached)
+    /// ones are kept, and the returned warning states their age.
+    #[cfg(feature = "currency")]
+    pub fn refresh_exchange_rates() -> std::result::Result<Option<RuntimeWarning>, RuntimeError> {
+        ExchangeRatesCache::refresh();
+        Self::exchange_rates_warning()
+    }
+
+    /// Like [`Context::refresh_exchange_rates`], but the rates are fetched in a
+    /// background thread. The cached rates (if there are any) are used until the
+    /// fetch completes.
+    #[cfg(all(feature = "currency", not(target_family = "wasm")))]
+    pub fn refresh_exchange_rates_in_background(
+    ) -> std::thread::JoinHandle<std::result::Result<Option<RuntimeWarning>, RuntimeError>> {
+        if ExchangeRatesCache::use_cached_for_now() {
+            std::thread::spawn(Self::refresh_exchange_rates)
+        } else {
+            // Without cached rates, currency conversions have to wait for the fetch
+            std::thread::spawn(|| {
+                Self::prefetch_exchange_rates();
+                Self::exchange_rates_warning()
+            })
+        }
+    }
+
+    #[cfg(feature = "currency")]
+    fn exchange_rates_warning() -> std::result::Result<Option<RuntimeWarning>, RuntimeError> {
+        match ExchangeRatesCache::current() {
+            Some(rates) if rates.cached => {
+                Ok(Some(RuntimeWarning::CachedExchangeRates(rates.age())))
+            }
+            Some(_) => Ok(None),
+            None => Err(RuntimeError::CouldNotLoadExchangeRates),
+        }
+    }
+
+    /// Make a function that is implemented in Rust available to Numbat code. The
+    /// `signature` is the part of a foreign function declaration that follows the
+    /// name, e.g. `(radius: Length) -> Area` or `<D: Dim>(x: D, n: Scalar) -> D`.
+    /// It is type checked like any other declaration.
+    ///
+    /// The function receives the evaluated arguments (quantities keep the unit
+    /// they have been passed with) and has to return a value of the declared type.
+    /// This is checked on every call. Errors are reported as runtime errors at the
+    /// call site. Calls to native functions are never evaluated in parallel.
+    ///
+    /// Registering a function with the same name again behaves like redefining a
+    /// Numbat function: code that has been compiled before keeps calling the
+    /// previous version.
+    pub fn register_native_function(
+        &mut self,
+        name: &str,
+        signature: &str,
+        function: impl Fn(Vec<Value>) -> std::result::Result<Value, String> + Send + Sync + 'static,
+    ) -> Result<()> {
+        let declaration = format!("fn {name}{signature}");
+        self.resolver
+            .parse_native_function_signature(name, &declaration)
+            .map_err(NumbatError::ResolverError)?;
+
+        let name = Symbol::new(name);
+        self.typechecker.add_native_function(name);
+        self.interpreter
+            .add_native_function(&name, Arc::new(function));
+
+        let _ = self.interpret_with_sink(&mut NullSink, &declaration, CodeSource::Internal)?;
+        Ok(())
+    }
+
+    /// Remove a function that has been added with
+    /// [`Context::register_native_function`]. Returns `false` if there is no such
+    /// function. Numbat functions that call it fail with a runtime error from now on.
+    pub fn unregister_native_function(&mut self, name: &str) -> bool {
+        if !self.typechecker.remove_native_function(Symbol::new(name)) {
+            return false;
+        }
+        self.prefix_transformer.function_names.retain(|n| n != name);
+        self.interpreter.remove_native_function(name)
+    }
+
+    /// Define a variable whose value is provided by the embedding application,
+    /// e.g. `ctx.set_variable("speed", Value::quantity(23.4, "km/h"))`. It is
+    /// type checked like a `let` definition and can be overwritten by calling this
+    /// function again, or from Numbat code. Functions that have been defined in the
+    /// meantime keep referring to the previous value.
+    pub fn set_variable(&mut self, name: &str, value: Value) -> Result<()> {
+        let is_identifier = matches!(
+            &tokenizer::tokenize(name, 0).as_deref(),
+            Ok([token, _eof]) if token.kind == tokenizer::TokenKind::Identifier
+        );
+        if !is_identifier {
+            return Err(NumbatError::RuntimeError(
+                RuntimeError::InvalidVariableName(name.into()),
+            ));
+        }
+
+        let value = self.resolve_units(value)?;
+        let type_ = self.interpreter.type_of_value(&value).map_err(|reason| {
+            NumbatError::RuntimeError(RuntimeError::UnsupportedVariableValue(
+                value.to_string(),
+                reason.into(),
+            ))
+        })?;
+
+        // The value is stored in a hidden variable, which is then assigned to the
+        // actual one. This way, the name goes through all the usual checks.
+        let foreign_value = Symbol::new(FOREIGN_VALUE_IDENTIFIER);
+        self.typechecker.add_foreign_variable(foreign_value, type_);
+        self.interpreter.add_global(foreign_value, value);
+
+        let _ = self.interpret_with_sink(
+            &mut NullSink,
+            &format!("let {name} = {FOREIGN_VALUE_IDENTIFIER}"),
+            CodeSource::Internal,
+        )?;
+        Ok(())
+    }
+
+    /// The current value of a variable and its type, or `None` if there is no
+    /// variable of that name.
+    pub fn get_variable(&self, name: &str) -> Option<(Value, Type)> {
+        let value = self.interpreter.global_value(name)?;
+        let type_ = self.typechecker.variable_type(name)?;
+        Some((value.clone(), type_))
+    }
+
+    /// The values of all expression statements that have been evaluated so far, in
+    /// order. These are the values that `ans` and `_` refer to.
+    pub fn results(&self) -> &[Value] {
+        self.interpreter.results()
+    }
+
+    /// Replace the units of quantities that have been created with
+    /// [`Value::quantity`] by the units that their names refer to.
+    fn resolve_units(&mut self, value: Value) -> Result<Value> {
+        Ok(match value {
+            Value::Quantity(q) => match self.unresolved_unit_name(q.unit()) {
+                Some(unit_name) => {
+                    let _ = self.interpret_with_sink(
+                        &mut NullSink,
+                        &format!("let {FOREIGN_VALUE_IDENTIFIER} = ({unit_name})"),
+                        CodeSource::Internal,
+                    )?;
+                    match self.interpreter.global_value(FOREIGN_VALUE_IDENTIFIER) {
+                        Some(Value::Quantity(unit)) => Value::Quantity(
+                            Quantity::new(*q.unsafe_value(), Unit::scalar()) * unit.clone(),
+                        ),
+                        _ => {
+                            return Err(NumbatError::RuntimeError(
+                                RuntimeError::UnsupportedVariableValue(
+                                    unit_name.to_string(),
+                                    "this is not a unit".into(),
+                                ),
+                            ))
+                        }
+                    }
+                }
+                None => Value::Quantity(q),
+            },
+            Value::StructInstance(info, values) => Value::StructInstance(
+                info,
+                values
+                    .into_iter()
+                    .map(|v| self.resolve_units(v))
+                    .collect::<Result<_>>()?,
+            ),
+            Value::List(elements) => elements
+                .iter()
+                .map(|v| self.resolve_units(v.clone()))
+                .collect::<Result<VecDeque<_>>>()?
+                .into(),
+            value => value,
+        })
+    }
+
+    /// The name of the unit of a quantity that has been created with
+    /// [`Value::quantity`], if it has not been looked up yet.
+    fn unresolved_unit_name(&self, unit: &Unit) -> Option<Symbol> {
+        let factor = unit.iter().exactly_one().ok()?;
+        let is_defined = self
+            .interpreter
+            .get_defining_unit(&factor.unit_id.name)
+            .is_some_and(|defining_unit| defining_unit.iter().any(|f| f.unit_id == factor.unit_id));
+        (factor.unit_id.is_base() && !is_defined).then_some(factor.unit_id.name)
+    }
+
     pub fn variable_names(&self) -> impl Iterator<Item = String> + '_ {
         self.prefix_transformer
             .variable_names
@@ -192,6 +765,27 @@ impl Context {
         &self.prefix_transformer.dimension_names
     }
 
+    /// Classify the tokens in `code` for syntax highlighting. In contrast to
+    /// [`highlighting::classify_tokens`], identifiers that refer to units or
+    /// dimensions in this context are marked as such.
+    pub fn classify_tokens(&self, code: &str) -> Vec<ClassifiedToken> {
+        let mut tokens = highlighting::classify_tokens(code);
+        for token in &mut tokens {
+            if token.class != TokenClass::Identifier {
+                continue;
+            }
+            let name = &code[token.start..token.end];
+            if self.dimension_names().iter().any(|n| n == name) {
+                token.class = TokenClass::TypeIdentifier;
+            } else if let PrefixParserResult::UnitIdentifier(..) =
+                self.prefix_transformer.prefix_parser.parse(name)
+            {
+                token.class = TokenClass::Unit;
+            }
+        }
+        tokens
+    }
+
     pub fn print_environment(&self) -> Markup {
         let mut functions: Vec<_> = self.function_names().collect();
         functions.sort();
@@ -251,6 +845,17 @@ impl Context {
         word_part: &'a str,
         add_paren: bool,
     ) -> impl Iterator<Item = String> + 'a {
+        self.get_detailed_completions_for(word_part, add_paren)
+            .map(|completion| completion.word)
+    }
+
+    /// Same as [`Context::get_completions_for`], but also reports whether the
+    /// completed identifier has been marked with `@deprecated`.
+    pub fn get_detailed_completions_for<'a>(
+        &self,
+        word_part: &'a str,
+        add_paren: bool,
+    ) -> impl Iterator<Item = Completion> + 'a {
         const COMMON_METRIC_PREFIXES: &[&str] = &[
             "pico", "nano", "micro", "milli", "centi", "kilo", "mega", "giga", "tera",
         ];
@@ -263,34 +868,48 @@ impl Context {
             })
             .collect();
 
-        let mut words: Vec<_> = KEYWORDS.iter().map(|k| k.to_string()).collect();
+        let mut words: Vec<_> = KEYWORDS.iter().map(|k| (k.to_string(), false)).collect();
 
         for (patterns, _) in UNICODE_INPUT {
             for pattern in *patterns {
-                words.push(pattern.to_string());
+                words.push((pattern.to_string(), false));
             }
         }
 
         {
             for variable in self.variable_names() {
-                words.push(variable.clone());
+                let deprecated = self.typechecker.is_deprecated(&variable);
+                words.push((variable, deprecated));
             }
 
             for function in self.function_names() {
+                let deprecated = self.typechecker.is_deprecated(&function);
                 if add_paren {
-                    words.push(format!("{}(", function));
+                    words.push((format!("{}(", function), deprecated));
                 } else {
-                    words.push(function.to_string());
+                    words.push((function, deprecated));
+                }
+            }
+
+            // Methods are only used with method call syntax, `receiver.method(…)`
+            for info in self.typechecker.structs() {
+                for method in self.typechecker.methods(info.name) {
+                    if add_paren {
+                        words.push((format!("{method}("), false));
+                    } else {
+                        words.push((method.to_string(), false));
+                    }
                 }
             }
 
             for dimension in self.dimension_names() {
-                words.push(dimension.clone());
+                words.push((dimension.clone(), false));
             }
 
             for (_, (_, meta)) in self.unit_representations() {
+                let deprecated = meta.deprecated.is_some();
                 for (unit, accepts_prefix) in meta.aliases {
-                    words.push(unit.clone());
+                    words.push((unit.to_string(), deprecated));
 
                     // Add some of the common long prefixes for units that accept them.
                     // We do not add all possible prefixes here in order to keep the
@@ -299,7 +918,7 @@ impl Context {
                     // and lots of 2-3 character words.
                     if accepts_prefix.long && meta.metric_prefixes {
                         for prefix in &metric_prefixes {
-                            words.push(format!("{prefix}{unit}"));
+                            words.push((format!("{prefix}{unit}"), deprecated));
                         }
                     }
                 }
@@ -307,9 +926,12 @@ impl Context {
         }
 
         words.sort();
-        words.dedup();
+        words.dedup_by(|(a, _), (b, _)| a == b);
 
-        words.into_iter().filter(move |w| w.starts_with(word_part))
+        words
+            .into_iter()
+            .filter(move |(w, _)| w.starts_with(word_part))
+            .map(|(word, deprecated)| Completion { word, deprecated })
     }
 
     pub fn print_info_for_keyword(&mut self, keyword: &str) -> Markup {
@@ -318,6 +940,7 @@ impl Context {
         if keyword.is_empty() {
             return m::text("Usage: info <unit or variable>");
         }
+        let keyword = &tokenizer::normalize_identifier(keyword);
         let reg = self.interpreter.get_unit_registry();
 
         if let PrefixParserResult::UnitIdentifier(_span, prefix, _, full_name) =
@@ -335,15 +958,26 @@ impl Context {
                 }
                 help += m::nl();
                 if md.aliases.len() > 1 {
-                    help += m::text("Aliases: ")
-                        + m::text(
-                            md.aliases
-                                .iter()
-                                .map(|(x, _)| x.as_str())
-                                .collect::<Vec<_>>()
-                                .join(", "),
-                        )
-                        + m::nl();
+                    // Long names take long prefixes (`kilometre`), short names take
+                    // short prefixes (`km`). Some names take both, others none at all.
+                    let alias_classes = [
+                        ("Long names: ", AcceptsPrefix::only_long()),
+                        ("Short names: ", AcceptsPrefix::only_short()),
+                        ("Names without prefixes: ", AcceptsPrefix::none()),
+                    ];
+                    for (label, class) in alias_classes {
+                        let names = md
+                            .aliases
+                            .iter()
+                            .filter(|(_, ap)| {
+                                (ap.long && class.long) || (ap.short && class.short) || *ap == class
+                            })
+                            .map(|(x, _)| x.as_str())
+                            .collect::<Vec<_>>();
+                        if !names.is_empty() {
+                            help += m::text(label) + m::text(names.join(", ")) + m::nl();
+                        }
+                    }
                 }
 
                 if let Some(description) = &md.description {
@@ -359,6 +993,10 @@ impl Context {
                     }
                 }
 
+                if let Some(message) = &md.deprecated {
+                    help += m::text("Deprecated: ") + m::text(message) + m::nl();
+                }
+
                 if matches!(md.type_, Type::Dimension(d) if d.is_scalar()) {
                     help += m::text("A dimensionless unit ([")
                         + md.readable_type
@@ -410,6 +1048,19 @@ impl Context {
             }
         };
 
+        if LAST_RESULT_IDENTIFIERS.contains(&keyword.as_str())
+            || indexed_result_identifier(keyword).is_some()
+        {
+            // Shown in full, even if the unit is too complex (see `set_max_unit_complexity`)
+            if let Ok((statements, results)) = self.interpret(keyword, CodeSource::Internal) {
+                return m::text("Result: ")
+                    + m::identifier(keyword)
+                    + m::nl()
+                    + m::nl()
+                    + results.to_markup(statements.last(), self.dimension_registry(), true, true);
+            }
+        }
+
         if let Some(l) = self.interpreter.lookup_global(keyword) {
             let mut help = m::text("Variable: ");
             if let Some(name) = &l.metadata.name {
@@ -446,7 +1097,21 @@ impl Context {
                     + m::nl();
             }
 
+            let uncertainty = l.metadata.uncertainty;
+
             if let Ok((_, results)) = self.interpret(keyword, CodeSource::Internal) {
+                if let (Some(uncertainty), InterpreterResult::Value(Value::Quantity(value))) =
+                    (uncertainty, &results)
+                {
+                    help += m::text("Uncertainty: ");
+                    if uncertainty.to_f64() == 0.0 {
+                        help += m::text("exact");
+                    } else {
+                        help += Quantity::new(uncertainty, value.unit().clone()).pretty_print();
+                    }
+                    help += m::nl();
+                }
+
                 help += m::nl() + results.to_markup(None, self.dimension_registry(), true, true);
             }
 
@@ -483,6 +1148,47 @@ impl Context {
                 }
             }
 
+            if let Some(message) = self.typechecker.deprecation_message(keyword) {
+                help += m::text("Deprecated:  ") + m::text(message) + m::nl();
+            }
+
+            return help;
+        }
+
+        if let Some(info) = self
+            .typechecker
+            .structs()
+            .find(|info| info.name.as_str() == keyword.as_str())
+        {
+            let registry = self.typechecker.registry();
+
+            let mut help = m::text("Struct: ") + m::type_identifier(keyword) + m::nl();
+            if !info.fields.is_empty() {
+                help += m::text("Fields: ")
+                    + Itertools::intersperse(
+                        info.fields.iter().map(|(field, (_, type_))| {
+                            m::identifier(field)
+                                + m::operator(":")
+                                + m::space()
+                                + type_.to_readable_type(registry)
+                        }),
+                        m::operator(",") + m::space(),
+                    )
+                    .sum()
+                    + m::nl();
+            }
+
+            let methods = self.typechecker.methods(info.name);
+            if !methods.is_empty() {
+                help += m::text("Methods: ")
+                    + Itertools::intersperse(
+                        methods.iter().map(m::identifier),
+                        m::operator(",") + m::space(),
+                    )
+                    .sum()
+                    + m::nl();
+            }
+
             return help;
         }
 
@@ -490,7 +1196,7 @@ impl Context {
     }
 
     pub fn list_modules(&self) -> impl Iterator<Item = String> {
-        let modules = self.resolver.get_importer().list_modules();
+        let modules = self.resolver.list_modules();
         modules.into_iter().map(|m| m.0.join("::"))
     }
 
@@ -498,11 +1204,53 @@ impl Context {
         self.typechecker.registry()
     }
 
+    /// Format the result of [`Context::interpret`] like [`InterpreterResult::to_markup`],
+    /// but taking the settings of this context into account (see
+    /// [`Context::set_max_unit_complexity`]).
+    pub fn result_to_markup(
+        &self,
+        result: &InterpreterResult,
+        evaluated_statement: Option<TypedStatement>,
+        with_type_info: bool,
+        with_equal_sign: bool,
+    ) -> Markup {
+        let registry = self.dimension_registry();
+        match (result, self.max_unit_complexity) {
+            (InterpreterResult::Value(Value::Quantity(quantity)), Some(max_complexity))
+                if !evaluated_statement
+                    .as_ref()
+                    .is_some_and(TypedStatement::is_conversion)
+                    && self
+                        .interpreter
+                        .get_unit_registry()
+                        .is_too_complex(quantity.unit(), max_complexity) =>
+            {
+                let value_markup = m::value(quantity.unsafe_value().pretty_print_scientific())
+                    + m::space()
+                    + m::dimmed("(unit too complex; use `-> <unit>` or `simplify`)");
+                InterpreterResult::value_to_markup(
+                    value_markup,
+                    evaluated_statement,
+                    registry,
+                    with_type_info,
+                    with_equal_sign,
+                )
+            }
+            _ => result.to_markup(
+                evaluated_statement,
+                registry,
+                with_type_info,
+                with_equal_sign,
+            ),
+        }
+    }
+
     pub fn base_units(&self) -> impl Iterator<Item = String> + '_ {
         self.interpreter
             .get_unit_registry()
             .inner
             .iter_base_entries()
+            .map(String::from)
     }
 
     pub fn unit_representations(
@@ -516,14 +1264,34 @@ impl Context {
             .chain(registry.inner.iter_derived_entries());
 
         unit_names.map(|unit_name| {
-            let info = registry
-                .inner
-                .get_base_representation_for_name(&unit_name)
-                .unwrap();
-            (unit_name, info)
+            let info = registry.inner.get_base_representation(unit_name).unwrap();
+            (unit_name.to_string(), info)
         })
     }
 
+    /// Where the unit (or unit alias) or dimension with the given name has been
+    /// defined, e.g. for go-to-definition in an editor. Use
+    /// [`Resolver::get_code_source`] to find the file or module of the span.
+    pub fn definition_span(&self, name: &str) -> Option<Span> {
+        let name = Symbol::get(name)?;
+        let units = &self.interpreter.get_unit_registry().inner;
+
+        let unit = units
+            .iter_base_entries()
+            .chain(units.iter_derived_entries())
+            .find(|unit| {
+                *unit == name
+                    || units
+                        .get_base_representation(*unit)
+                        .is_ok_and(|(_, metadata)| metadata.aliases.iter().any(|a| a.0 == name))
+            });
+
+        match unit {
+            Some(unit) => units.get_definition_span(unit),
+            None => self.dimension_registry().get_definition_span(name),
+        }
+    }
+
     pub fn resolver(&self) -> &Resolver {
         &self.resolver
     }
@@ -532,16 +1300,155 @@ impl Context {
         &mut self,
         code: &str,
         code_source: CodeSource,
-    ) -> Result<(Vec<typed_ast::Statement>, InterpreterResult)> {
-        self.interpret_with_settings(&mut InterpreterSettings::default(), code, code_source)
+    ) -> Result<(TypedProgram, InterpreterResult)> {
+        let sink = self.output_sink.clone();
+        let mut sink = sink.lock().unwrap();
+        self.interpret_with_sink(&mut *sink, code, code_source)
     }
 
-    pub fn interpret_with_settings(
+    /// Adds the name and dimension of the unit to an error about a definition whose
+    /// name is already used by a unit, and the dimensions of all candidates to an
+    /// error about an ambiguous unit.
+    fn describe_clashing_unit(&self, error: NameResolutionError) -> NameResolutionError {
+        let dimension_of = |full_name: &str| {
+            self.interpreter
+                .get_unit_registry()
+                .inner
+                .get_base_representation_for_name(full_name)
+                .ok()
+                .map(|(_, md)| md.readable_type.to_string())
+        };
+
+        match error {
+            NameResolutionError::IdentifierClash {
+                conflicting_identifier,
+                conflict_span,
+                original_span,
+                original_item_type,
+                unit: None,
+            } => {
+                let unit = match self
+                    .prefix_transformer
+                    .prefix_parser
+                    .parse(&conflicting_identifier)
+                {
+                    PrefixParserResult::UnitIdentifier(_, prefix, _, full_name) => {
+                        dimension_of(&full_name).map(|dimension| {
+                            let prefix_name = PrefixParser::long_prefix_name(prefix)
+                                .filter(|_| !prefix.is_none())
+                                .unwrap_or_default();
+                            Box::new((format!("{prefix_name}{full_name}"), dimension))
+                        })
+                    }
+                    PrefixParserResult::Identifier(_)
+                    | PrefixParserResult::AmbiguousUnitIdentifier(_) => None,
+                };
+
+                NameResolutionError::IdentifierClash {
+                    conflicting_identifier,
+                    conflict_span,
+                    original_span,
+                    original_item_type,
+                    unit,
+                }
+            }
+            NameResolutionError::AmbiguousUnit {
+                identifier,
+                span,
+                candidates,
+            } => NameResolutionError::AmbiguousUnit {
+                identifier,
+                span,
+                candidates: candidates
+                    .into_iter()
+                    .map(|candidate| UnitCandidate {
+                        dimension: dimension_of(&candidate.full_name),
+                        ..candidate
+                    })
+                    .collect(),
+            },
+            error => error,
+        }
+    }
+
+    /// Parse and type check the given code without evaluating it. In contrast to
+    /// [`Context::interpret`], none of the definitions in the code are added to
+    /// the context, and no warnings are printed.
+    pub fn check(&mut self, code: &str, code_source: CodeSource) -> Result<TypedProgram> {
+        self.check_with_warnings(code, code_source).0
+    }
+
+    /// Like [`Context::check`], but additionally returns the diagnostics for all
+    /// warnings of the parser and the type checker, even if there is an error.
+    pub fn check_with_warnings(
+        &mut self,
+        code: &str,
+        code_source: CodeSource,
+    ) -> (Result<TypedProgram>, Vec<Diagnostic>) {
+        let num_imported_modules = self.resolver.num_imported_modules();
+        let prefix_transformer_old = self.prefix_transformer.clone();
+        let typechecker_old = self.typechecker.clone();
+
+        let result = self
+            .resolver
+            .resolve(code, code_source)
+            .map_err(NumbatError::ResolverError)
+            .and_then(|statements| {
+                self.prefix_transformer
+                    .transform(statements)
+                    .map_err(|e| NumbatError::NameResolutionError(self.describe_clashing_unit(e)))
+            })
+            .and_then(|statements| {
+                self.typechecker
+                    .check(statements)
+                    .map_err(NumbatError::TypeCheckError)
+            });
+
+        let warnings = self.take_warning_diagnostics();
+        self.resolver
+            .truncate_imported_modules(num_imported_modules);
+        self.prefix_transformer = prefix_transformer_old;
+        self.typechecker = typechecker_old;
+
+        (result, warnings)
+    }
+
+    /// The diagnostics for the warnings of the parser and the type checker that
+    /// have not been reported yet, except for the kinds that are disabled.
+    fn take_warning_diagnostics(&mut self) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+        for warning in self.resolver.take_warnings() {
+            if matches!(warning, ParseWarning::DiscardedUncertainty(..))
+                && !self.uncertainty_warnings
+            {
+                continue;
+            }
+            diagnostics.extend(self.diagnostics(&warning));
+        }
+        for warning in self.typechecker.take_warnings() {
+            if let TypeCheckWarning::DeprecatedIdentifier(span, ..) = &warning {
+                if !self.deprecation_warnings
+                    || self.resolver.allows_deprecated(span.code_source_id)
+                {
+                    continue;
+                }
+            }
+            diagnostics.extend(self.diagnostics(&warning));
+        }
+        diagnostics
+    }
+
+    /// Same as [`Context::interpret`], but send all output to the given sink instead
+    /// of the one of this context.
+    pub fn interpret_with_sink(
         &mut self,
-        settings: &mut InterpreterSettings,
+        output: &mut dyn OutputSink,
         code: &str,
         code_source: CodeSource,
-    ) -> Result<(Vec<typed_ast::Statement>, InterpreterResult)> {
+    ) -> Result<(TypedProgram, InterpreterResult)> {
+        let num_imported_modules = self.resolver.num_imported_modules();
+        let is_undoable = matches!(code_source, CodeSource::Text);
+
         let statements = self
             .resolver
             .resolve(code, code_source.clone())
@@ -552,7 +1459,7 @@ impl Context {
         let result = self
             .prefix_transformer
             .transform(statements)
-            .map_err(NumbatError::NameResolutionError);
+            .map_err(|e| NumbatError::NameResolutionError(self.describe_clashing_unit(e)));
 
         if result.is_err() {
             // Reset the state of the prefix transformer to what we had before. This is necessary
@@ -571,6 +1478,11 @@ impl Context {
 
         let typechecker_old = self.typechecker.clone();
 
+        let code_source_ids = transformed_statements
+            .iter()
+            .map(|statement| statement.span().code_source_id)
+            .collect::<Vec<_>>();
+
         let result = self
             .typechecker
             .check(transformed_statements)
@@ -590,6 +1502,7 @@ impl Context {
             self.prefix_transformer = prefix_transformer_old.clone();
             self.typechecker = typechecker_old.clone();
 
+            #[cfg(feature = "currency")]
             if self.load_currency_module_on_demand {
                 if let Err(NumbatError::TypeCheckError(TypeCheckError::UnknownIdentifier(
                     _,
@@ -597,136 +1510,20 @@ impl Context {
                     _,
                 ))) = &result
                 {
-                    // TODO: maybe we can somehow load this list of identifiers from units::currencies?
-                    const CURRENCY_IDENTIFIERS: &[&str] = &[
-                        "$",
-                        "USD",
-                        "dollar",
-                        "dollars",
-                        "A$",
-                        "AUD",
-                        "australian_dollar",
-                        "australian_dollars",
-                        "C$",
-                        "CAD",
-                        "canadian_dollar",
-                        "canadian_dollars",
-                        "CHF",
-                        "swiss_franc",
-                        "swiss_francs",
-                        "CNY",
-                        "yuan",
-                        "renminbi",
-                        "元",
-                        "EUR",
-                        "euro",
-                        "euros",
-                        "€",
-                        "GBP",
-                        "british_pound",
-                        "pound_sterling",
-                        "£",
-                        "JPY",
-                        "yen",
-                        "yens",
-                        "¥",
-                        "円",
-                        "bulgarian_lev",
-                        "bulgarian_leva",
-                        "BGN",
-                        "czech_koruna",
-                        "czech_korunas",
-                        "CZK",
-                        "Kč",
-                        "hungarian_forint",
-                        "hungarian_forints",
-                        "HUF",
-                        "Ft",
-                        "polish_zloty",
-                        "polish_zlotys",
-                        "PLN",
-                        "zł",
-                        "romanian_leu",
-                        "romanian_leus",
-                        "RON",
-                        "lei",
-                        "turkish_lira",
-                        "turkish_liras",
-                        "TRY",
-                        "₺",
-                        "brazilian_real",
-                        "brazilian_reals",
-                        "BRL",
-                        "R$",
-                        "hong_kong_dollar",
-                        "hong_kong_dollars",
-                        "HKD",
-                        "HK$",
-                        "indonesian_rupiah",
-                        "indonesian_rupiahs",
-                        "IDR",
-                        "Rp",
-                        "indian_rupee",
-                        "indian_rupees",
-                        "INR",
-                        "₹",
-                        "south_korean_won",
-                        "south_korean_wons",
-                        "KRW",
-                        "₩",
-                        "malaysian_ringgit",
-                        "malaysian_ringgits",
-                        "MYR",
-                        "RM",
-                        "new_zealand_dollar",
-                        "new_zealand_dollars",
-                        "NZD",
-                        "NZ$",
-                        "philippine_peso",
-                        "philippine_pesos",
-                        "PHP",
-                        "₱",
-                        "singapore_dollar",
-                        "singapore_dollars",
-                        "SGD",
-                        "S$",
-                        "thai_baht",
-                        "thai_bahts",
-                        "THB",
-                        "฿",
-                        "danish_krone",
-                        "danish_kroner",
-                        "DKK",
-                        "swedish_krona",
-                        "swedish_kronor",
-                        "SEK",
-                        "icelandic_króna",
-                        "icelandic_krónur",
-                        "ISK",
-                        "norwegian_krone",
-                        "norwegian_kroner",
-                        "NOK",
-                        "israeli_new_shekel",
-                        "israeli_new_shekels",
-                        "ILS",
-                        "₪",
-                        "NIS",
-                        "south_african_rand",
-                        "ZAR",
-                    ];
-                    if CURRENCY_IDENTIFIERS.contains(&identifier.as_str()) {
-                        let mut no_print_settings = InterpreterSettings {
-                            print_fn: Box::new(
-                                move |_: &m::Markup| { // ignore any print statements when loading this module asynchronously
-                                },
-                            ),
-                        };
-
+                    if features::is_currency_identifier(identifier) {
                         // We also call this from a thread at program startup, so if a user only starts
                         // to use currencies later on, this will already be available and return immediately.
                         // Otherwise, we fetch it now and make sure to block on this call.
                         {
-                            let erc = ExchangeRatesCache::fetch();
+                            let erc = if self.sandbox_policy.is_strict() {
+                                ExchangeRatesCache::fetch_without_network().ok_or(
+                                    NumbatError::RuntimeError(RuntimeError::PermissionDenied(
+                                        Capability::Network,
+                                    )),
+                                )?
+                            } else {
+                                ExchangeRatesCache::fetch()
+                            };
 
                             if erc.is_none() {
                                 return Err(NumbatError::RuntimeError(
@@ -735,20 +1532,22 @@ impl Context {
                             }
                         }
 
-                        let _ = self.interpret_with_settings(
-                            &mut no_print_settings,
+                        // ignore any print statements when loading this module, but
+                        // report warnings about cached exchange rates
+                        let _ = self.interpret_with_sink(
+                            &mut DiagnosticsOnlySink(output),
                             "use units::currencies",
                             CodeSource::Internal,
                         )?;
 
                         // Make sure we do not run into an infinite loop in case loading that
                         // module did not bring in the required currency unit identifier. This
-                        // can happen if the list of currency identifiers is not in sync with
-                        // what the module actually defines.
+                        // can happen if the builtin module has been replaced by one that
+                        // defines different units.
                         self.load_currency_module_on_demand = false;
 
                         // Now we try to evaluate the user expression again:
-                        return self.interpret_with_settings(settings, code, code_source);
+                        return self.interpret_with_sink(output, code, code_source);
                     }
                 }
             }
@@ -756,50 +1555,113 @@ impl Context {
 
         let typed_statements = result?;
 
+        for diagnostic in self.take_warning_diagnostics() {
+            output.diagnostic(&diagnostic, &self.resolver.files);
+        }
+
         let interpreter_old = self.interpreter.clone();
 
         let result = self.interpreter.interpret_statements(
-            settings,
+            &mut LocalizingSink {
+                inner: output,
+                locale: &self.locale,
+            },
             &typed_statements,
             self.typechecker.registry(),
         );
 
-        if result.is_err() {
-            // Similar to above: we need to reset the state of the typechecker and the prefix transformer
-            // here for REPL use cases like:
-            //
-            //    >>> let q = 1 / 0
-            //    error: runtime error
-            //     = Division by zero
-            //
-            //    -> 'q' should not be defined, so 'q' properly leads to a "unknown identifier" error
-            //       and another 'let q = …' works as intended.
-            //
-            self.prefix_transformer = prefix_transformer_old;
-            self.typechecker = typechecker_old;
-            self.interpreter = interpreter_old;
+        for warning in self.interpreter.take_warnings() {
+            self.print_diagnostic_to(output, warning);
         }
 
-        let result = result.map_err(NumbatError::RuntimeError)?;
+        let result = match result {
+            Ok(result) => result,
+            Err(e) => {
+                // Similar to above: we need to reset the state of the typechecker and the prefix transformer
+                // here for REPL use cases like:
+                //
+                //    >>> let q = 1 / 0
+                //    error: runtime error
+                //     = Division by zero
+                //
+                //    -> 'q' should not be defined, so 'q' properly leads to a "unknown identifier" error
+                //       and another 'let q = …' works as intended.
+                //
+                self.prefix_transformer = prefix_transformer_old;
+                self.typechecker = typechecker_old;
+                let mut failed_interpreter =
+                    std::mem::replace(&mut self.interpreter, interpreter_old);
+                // Calls that happened before the error are still part of the profile
+                self.interpreter.take_profiler_from(&mut failed_interpreter);
+                return Err(NumbatError::RuntimeError(e));
+            }
+        };
 
-        Ok((typed_statements, result))
-    }
+        if is_undoable && self.undo_limit > 0 {
+            if self.undo_stack.len() == self.undo_limit {
+                self.undo_stack.pop_front();
+            }
+            self.undo_stack.push_back(Snapshot {
+                prefix_transformer: prefix_transformer_old,
+                typechecker: typechecker_old,
+                interpreter: interpreter_old,
+                num_imported_modules,
+                num_session_statements: self.session_dependencies.len(),
+            });
+        }
 
-    pub fn print_diagnostic(&self, error: impl ErrorDiagnostic) {
-        use codespan_reporting::term::{
-            self,
-            termcolor::{ColorChoice, StandardStream},
-            Config,
+        // Statements from imported modules are not part of the session
+        for (statement, code_source_id) in typed_statements.iter().zip(code_source_ids) {
+            if matches!(
+                self.resolver.get_code_source(code_source_id),
+                CodeSource::Text | CodeSource::File(_)
+            ) {
+                self.session_dependencies.record(statement);
+            }
+        }
+
+        let result = match result {
+            InterpreterResult::Value(value) if self.long_unit_names => {
+                InterpreterResult::Value(value.with_long_unit_names())
+            }
+            result => result,
         };
 
-        let writer = StandardStream::stderr(ColorChoice::Auto);
-        let config = Config::default();
+        Ok((typed_statements, result))
+    }
 
-        // we want to be sure no one can write between our diagnostics
-        let mut writer = writer.lock();
+    /// The diagnostics for the given error. Errors in imported modules additionally
+    /// point to the `use` statements that caused the module to be loaded.
+    pub fn diagnostics(&self, error: &dyn ErrorDiagnostic) -> Vec<Diagnostic> {
+        let mut diagnostics: Vec<Diagnostic> = vec![];
         for diagnostic in error.diagnostics() {
-            term::emit(&mut writer, &config, &self.resolver.files, &diagnostic).unwrap();
+            let diagnostic = self.resolver.add_import_chain(diagnostic);
+            if !diagnostics.contains(&diagnostic) {
+                diagnostics.push(diagnostic);
+            }
         }
+        diagnostics
+    }
+
+    /// Send the diagnostics for the given error to the output sink of this context.
+    pub fn print_diagnostic(&self, error: impl ErrorDiagnostic) {
+        self.print_diagnostic_to(&mut *self.output_sink.lock().unwrap(), error);
+    }
+
+    pub fn print_diagnostic_to(&self, output: &mut dyn OutputSink, error: impl ErrorDiagnostic) {
+        for diagnostic in self.diagnostics(&error) {
+            output.diagnostic(&diagnostic, &self.resolver.files);
+        }
+    }
+
+    /// Send a diagnostic that has already been created (for example, a warning
+    /// that has been recorded by a [`output::BufferingSink`]) to the output sink of this
+    /// context.
+    pub fn forward_diagnostic(&self, diagnostic: &Diagnostic) {
+        self.output_sink
+            .lock()
+            .unwrap()
+            .diagnostic(diagnostic, &self.resolver.files);
     }
 
     pub fn set_terminal_width(&mut self, width: Option<usize>) {
@@ -39,14 +39,10 @@ impl<T: PartialEq> PartialEq for NumbatList<T> {
         if self.len() != other.len() {
             return false;
         }
-        // Second best case, the other slice comes from the same allocation and
-        // has the same view => they are equal
-        if Arc::ptr_eq(&self.alloc, &other.alloc) && self.view == other.view {
-            true
-        } else {
-            // Worst case scenario, we need to compare all the elements one by one
-            self.iter().zip(other.iter()).all(|(l, r)| l == r)
-        }
+        // Otherwise, we need to compare all the elements one by one. Note that we
+        // can not take a shortcut for lists sharing the same allocation, since
+        // elements are not necessarily equal to themselves (NaN != NaN).
+        self.iter().zip(other.iter()).all(|(l, r)| l == r)
     }
 }
 
@@ -0,0 +1,258 @@
+//! Regional conventions for writing numbers and units.
+
+use crate::markup::{FormatType, FormattedString, Markup};
+
+/// Determines how numbers are printed, e.g. `1.234,56` instead of `1_234.56`.
+///
+/// By default, a locale only affects output. Input is still parsed with a
+/// decimal point, unless [`Locale::with_decimal_comma_input`] is enabled. In
+/// that case, number literals may contain the decimal comma as well (`1,5 m`).
+/// The grouping separator is never accepted in input, since it would be
+/// ambiguous; underscores can still be used for digit grouping.
+///
+/// Units are written with superscript exponents (`m²·s⁻¹`), unless
+/// [`Locale::with_ascii_exponents`] is enabled (`m^2 s^-1`).
+///
+/// Structured outputs (like the values in the C API or the JSON output of the
+/// CLI) always use the default (C) locale.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locale {
+    decimal_mark: char,
+    grouping_separator: char,
+    decimal_comma_input: bool,
+    ascii_exponents: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum LocaleError {
+    #[error("Unsupported decimal mark '{0}', expected '.' or ','")]
+    UnsupportedDecimalMark(char),
+
+    #[error("Unsupported grouping separator '{0}'")]
+    UnsupportedGroupingSeparator(char),
+
+    #[error("The grouping separator must be different from the decimal mark")]
+    SameSeparators,
+}
+
+impl Locale {
+    /// A decimal point and underscores for digit grouping (`1_234_567.89`)
+    pub fn c() -> Self {
+        Locale {
+            decimal_mark: '.',
+            grouping_separator: '_',
+            decimal_comma_input: false,
+            ascii_exponents: false,
+        }
+    }
+
+    /// The decimal mark can be `.` or `,`. Digits can be grouped by `_`, `.`,
+    /// `,`, `'`, a space or a narrow no-break space.
+    pub fn new(decimal_mark: char, grouping_separator: char) -> Result<Self, LocaleError> {
+        if !matches!(decimal_mark, '.' | ',') {
+            return Err(LocaleError::UnsupportedDecimalMark(decimal_mark));
+        }
+        if !matches!(
+            grouping_separator,
+            '_' | '.' | ',' | '\'' | ' ' | '\u{202F}'
+        ) {
+            return Err(LocaleError::UnsupportedGroupingSeparator(
+                grouping_separator,
+            ));
+        }
+        if decimal_mark == grouping_separator {
+            return Err(LocaleError::SameSeparators);
+        }
+
+        Ok(Locale {
+            decimal_mark,
+            grouping_separator,
+            decimal_comma_input: false,
+            ascii_exponents: false,
+        })
+    }
+
+    /// Accept the decimal comma in number literals. This has no effect if the
+    /// decimal mark is a point. Note that numbers need to be separated by a
+    /// comma *and* a space in this mode, for example in `max(1, 2)`.
+    pub fn with_decimal_comma_input(mut self, enabled: bool) -> Self {
+        self.decimal_comma_input = enabled;
+        self
+    }
+
+    /// Write units with ASCII characters only, like `m^2 s^-1` instead of `m²·s⁻¹`.
+    /// The output can then be entered again on keyboards without superscripts.
+    pub fn with_ascii_exponents(mut self, enabled: bool) -> Self {
+        self.ascii_exponents = enabled;
+        self
+    }
+
+    pub fn decimal_mark(&self) -> char {
+        self.decimal_mark
+    }
+
+    pub fn grouping_separator(&self) -> char {
+        self.grouping_separator
+    }
+
+    pub fn has_decimal_comma_input(&self) -> bool {
+        self.decimal_mark == ',' && self.decimal_comma_input
+    }
+
+    pub fn has_ascii_exponents(&self) -> bool {
+        self.ascii_exponents
+    }
+
+    pub(crate) fn is_c(&self) -> bool {
+        self.decimal_mark == '.' && self.grouping_separator == '_'
+    }
+
+    /// Writes a number that has been formatted in the C locale in this locale.
+    pub fn format_number(&self, number: &str) -> String {
+        number
+            .chars()
+            .map(|c| match c {
+                '.' => self.decimal_mark,
+                '_' => self.grouping_separator,
+                c => c,
+            })
+            .collect()
+    }
+
+    /// Writes a unit like `m²·s⁻¹` in this locale.
+    pub fn format_unit(&self, unit: &str) -> String {
+        if !self.ascii_exponents {
+            return unit.to_owned();
+        }
+
+        let mut formatted = String::with_capacity(unit.len());
+        let mut in_exponent = false;
+        for c in unit.chars() {
+            let ascii = match c {
+                '⁻' => Some('-'),
+                '⁰' => Some('0'),
+                '¹' => Some('1'),
+                '²' => Some('2'),
+                '³' => Some('3'),
+                '⁴'..='⁹' => char::from_digit(c as u32 - '⁴' as u32 + 4, 10),
+                _ => None,
+            };
+            match ascii {
+                Some(ascii) => {
+                    if !in_exponent {
+                        formatted.push('^');
+                    }
+                    formatted.push(ascii);
+                }
+                None if c == '·' => formatted.push(' '),
+                None => formatted.push(c),
+            }
+            in_exponent = ascii.is_some();
+        }
+        formatted
+    }
+
+    /// Formats all numbers and units in the given markup in this locale.
+    pub fn localize(&self, markup: &Markup) -> Markup {
+        if self.is_c() && !self.ascii_exponents {
+            return markup.clone();
+        }
+
+        Markup(
+            markup
+                .0
+                .iter()
+                .map(|FormattedString(output_type, format_type, text)| {
+                    let text = match format_type {
+                        FormatType::Value => self.format_number(text),
+                        FormatType::Unit => self.format_unit(text),
+                        _ => text.clone(),
+                    };
+                    FormattedString(output_type.clone(), *format_type, text)
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self::c()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::number::Number;
+
+    fn format(locale: &Locale, n: f64) -> String {
+        locale.format_number(&Number::from_f64(n).pretty_print())
+    }
+
+    #[test]
+    fn format_numbers() {
+        let c = Locale::c();
+        let german = Locale::new(',', '.').unwrap();
+        let swiss = Locale::new('.', '\'').unwrap();
+        let french = Locale::new(',', '\u{202F}').unwrap();
+
+        for (n, expected_c, expected_german, expected_swiss, expected_french) in [
+            (1234.56, "1234.56", "1234,56", "1234.56", "1234,56"),
+            (
+                1234567.0,
+                "1_234_567",
+                "1.234.567",
+                "1'234'567",
+                "1\u{202F}234\u{202F}567",
+            ),
+            (-0.5, "-0.5", "-0,5", "-0.5", "-0,5"),
+            (1.5e-10, "1.5e-10", "1,5e-10", "1.5e-10", "1,5e-10"),
+        ] {
+            assert_eq!(format(&c, n), expected_c);
+            assert_eq!(format(&german, n), expected_german);
+            assert_eq!(format(&swiss, n), expected_swiss);
+            assert_eq!(format(&french, n), expected_french);
+        }
+    }
+
+    #[test]
+    fn invalid_locales() {
+        assert_eq!(
+            Locale::new(';', '.'),
+            Err(LocaleError::UnsupportedDecimalMark(';'))
+        );
+        assert_eq!(
+            Locale::new(',', 'x'),
+            Err(LocaleError::UnsupportedGroupingSeparator('x'))
+        );
+        assert_eq!(Locale::new(',', ','), Err(LocaleError::SameSeparators));
+    }
+
+    #[test]
+    fn decimal_comma_input() {
+        assert!(Locale::new(',', '.')
+            .unwrap()
+            .with_decimal_comma_input(true)
+            .has_decimal_comma_input());
+        assert!(!Locale::new(',', '.').unwrap().has_decimal_comma_input());
+        assert!(!Locale::c()
+            .with_decimal_comma_input(true)
+            .has_decimal_comma_input());
+    }
+
+    #[test]
+    fn ascii_exponents() {
+        let ascii = Locale::c().with_ascii_exponents(true);
+        for (unit, expected) in [
+            ("m²·s⁻¹", "m^2 s^-1"),
+            ("m³/(kg·s²)", "m^3/(kg s^2)"),
+            ("mol⁻¹", "mol^-1"),
+            ("m^6", "m^6"),
+            ("km", "km"),
+        ] {
+            assert_eq!(ascii.format_unit(unit), expected);
+            assert_eq!(Locale::c().format_unit(unit), unit);
+        }
+    }
+}
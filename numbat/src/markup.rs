@@ -202,6 +202,7 @@ pub trait Formatter {
     }
 }
 
+/// Formats markup as plain text, without any styling information.
 pub struct PlainTextFormatter;
 
 impl Formatter for PlainTextFormatter {
@@ -1,14 +1,100 @@
-/// Calculates the factorial of (the floor of) the given `f64`.
+/// Calculates the factorial of `n`.
 ///
-/// It is the caller's responsibility to ensure that the given `f64` is a
-/// non-negative integer.
-pub fn factorial(mut x: f64) -> f64 {
-    debug_assert!(x >= 0.0);
-    x = x.floor();
-    let mut result = 1f64;
-    while x >= 1. && result != f64::INFINITY {
-        result *= x;
-        x -= 1.;
-    }
-    result
+/// The product is computed exactly (in integer arithmetic) as long as it fits
+/// into a `u128`, and continued in floating point arithmetic afterwards. Returns
+/// `None` if the result is too large to be represented by an `f64`.
+pub fn factorial(n: u64) -> Option<f64> {
+    let mut exact: u128 = 1;
+    let mut k = 1;
+    while k <= n {
+        match exact.checked_mul(k as u128) {
+            Some(product) => exact = product,
+            None => break,
+        }
+        k += 1;
+    }
+
+    let mut result = exact as f64;
+    while k <= n && result.is_finite() {
+        result *= k as f64;
+        k += 1;
+    }
+    result.is_finite().then_some(result)
+}
+
+/// Calculates the binomial coefficient "n choose k".
+///
+/// Like [`factorial`], this is exact as long as the intermediate results fit
+/// into a `u128`. Returns `None` if the result is too large for an `f64`.
+pub fn binomial(n: u64, k: u64) -> Option<f64> {
+    if k > n {
+        return Some(0.0);
+    }
+    let k = k.min(n - k);
+
+    // C(n, i + 1) = C(n, i) · (n - i) / (i + 1), where the division is exact
+    let mut exact: u128 = 1;
+    let mut i = 0;
+    while i < k {
+        match exact.checked_mul((n - i) as u128) {
+            Some(product) => exact = product / (i + 1) as u128,
+            None => break,
+        }
+        i += 1;
+    }
+
+    let mut result = exact as f64;
+    while i < k && result.is_finite() {
+        result = result * (n - i) as f64 / (i + 1) as f64;
+        i += 1;
+    }
+    result.is_finite().then_some(result)
+}
+
+/// The greatest common divisor of `a` and `b`, which is zero if both are zero.
+pub fn gcd(a: i64, b: i64) -> u64 {
+    let (mut a, mut b) = (a.unsigned_abs(), b.unsigned_abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// The least common multiple of `a` and `b`, which is zero if one of them is zero.
+pub fn lcm(a: i64, b: i64) -> u128 {
+    match gcd(a, b) {
+        0 => 0,
+        divisor => (a.unsigned_abs() / divisor) as u128 * b.unsigned_abs() as u128,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn factorial_is_exact() {
+        assert_eq!(factorial(0), Some(1.0));
+        assert_eq!(factorial(5), Some(120.0));
+        assert_eq!(factorial(20), Some(2_432_902_008_176_640_000.0));
+        assert_eq!(factorial(170).map(f64::is_finite), Some(true));
+        assert_eq!(factorial(171), None);
+    }
+
+    #[test]
+    fn binomial_coefficients() {
+        assert_eq!(binomial(5, 0), Some(1.0));
+        assert_eq!(binomial(5, 2), Some(10.0));
+        assert_eq!(binomial(5, 6), Some(0.0));
+        assert_eq!(binomial(60, 30), Some(118_264_581_564_861_424.0));
+        assert_eq!(binomial(2000, 1000), None);
+    }
+
+    #[test]
+    fn gcd_and_lcm() {
+        assert_eq!(gcd(0, 0), 0);
+        assert_eq!(gcd(1071, -462), 21);
+        assert_eq!(lcm(21, 6), 42);
+        assert_eq!(lcm(-8, 0), 0);
+    }
 }
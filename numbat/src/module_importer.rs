@@ -1,16 +1,30 @@
-use std::{
-    ffi::OsStr,
-    fs,
-    path::{Path, PathBuf},
-};
+use std::path::PathBuf;
+#[cfg(feature = "fs")]
+use std::{ffi::OsStr, fs, path::Path};
 
 use rust_embed::RustEmbed;
 
 use crate::resolver::ModulePath;
+use crate::sandbox::Capability;
 
 pub trait ModuleImporter: Send + Sync {
     fn import(&self, path: &ModulePath) -> Option<(String, Option<PathBuf>)>;
     fn list_modules(&self) -> Vec<ModulePath>;
+
+    /// Like [`ModuleImporter::import`], but used in sandbox mode. Importers that
+    /// need access to the outside world return the denied [`Capability`] instead
+    /// of loading the module.
+    fn import_sandboxed(
+        &self,
+        path: &ModulePath,
+    ) -> Result<Option<(String, Option<PathBuf>)>, Capability> {
+        Ok(self.import(path))
+    }
+
+    /// Like [`ModuleImporter::list_modules`], but used in sandbox mode.
+    fn list_modules_sandboxed(&self) -> Vec<ModulePath> {
+        self.list_modules()
+    }
 }
 
 #[derive(Debug, Clone, Default)]
@@ -26,17 +40,20 @@ impl ModuleImporter for NullImporter {
     }
 }
 
+#[cfg(feature = "fs")]
 #[derive(Debug, Clone, Default)]
 pub struct FileSystemImporter {
     root_paths: Vec<PathBuf>,
 }
 
+#[cfg(feature = "fs")]
 impl FileSystemImporter {
     pub fn add_path<P: AsRef<Path>>(&mut self, path: P) {
         self.root_paths.push(path.as_ref().to_owned());
     }
 }
 
+#[cfg(feature = "fs")]
 impl ModuleImporter for FileSystemImporter {
     fn import(&self, module_path: &ModulePath) -> Option<(String, Option<PathBuf>)> {
         for path in &self.root_paths {
@@ -85,6 +102,17 @@ impl ModuleImporter for FileSystemImporter {
         }
         modules
     }
+
+    fn import_sandboxed(
+        &self,
+        _: &ModulePath,
+    ) -> Result<Option<(String, Option<PathBuf>)>, Capability> {
+        Err(Capability::Filesystem)
+    }
+
+    fn list_modules_sandboxed(&self) -> Vec<ModulePath> {
+        vec![]
+    }
 }
 
 #[derive(RustEmbed)]
@@ -95,6 +123,32 @@ struct BuiltinAssets;
 
 pub struct BuiltinModuleImporter {}
 
+impl BuiltinModuleImporter {
+    /// A fingerprint of the builtin modules (the standard library that has been
+    /// compiled into Numbat), as 16 hexadecimal digits
+    pub fn fingerprint() -> String {
+        // FNV-1a over the paths and contents, which is stable across builds
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut update = |bytes: &[u8]| {
+            for byte in bytes {
+                hash ^= u64::from(*byte);
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        };
+
+        let mut paths: Vec<_> = BuiltinAssets::iter().collect();
+        paths.sort();
+        for path in paths {
+            update(path.as_bytes());
+            if let Some(file) = BuiltinAssets::get(&path) {
+                update(&file.metadata.sha256_hash());
+            }
+        }
+
+        format!("{hash:016x}")
+    }
+}
+
 impl ModuleImporter for BuiltinModuleImporter {
     fn import(&self, module_path: &ModulePath) -> Option<(String, Option<PathBuf>)> {
         let mut path = PathBuf::new();
@@ -150,12 +204,39 @@ impl ModuleImporter for ChainedImporter {
     }
 
     fn list_modules(&self) -> Vec<ModulePath> {
-        let mut modules = self.main.list_modules();
-        modules.extend(self.fallback.list_modules());
+        merge_module_lists(self.main.list_modules(), self.fallback.list_modules())
+    }
 
-        modules.sort();
-        modules.dedup();
+    fn import_sandboxed(
+        &self,
+        path: &ModulePath,
+    ) -> Result<Option<(String, Option<PathBuf>)>, Capability> {
+        let main_result = self.main.import_sandboxed(path);
+        if let Ok(Some(_)) = main_result {
+            return main_result;
+        }
 
-        modules
+        // A module that is not available from the fallback importer either is
+        // reported as denied if the main importer could not be used.
+        match self.fallback.import_sandboxed(path) {
+            Ok(None) => main_result,
+            fallback_result => fallback_result,
+        }
     }
+
+    fn list_modules_sandboxed(&self) -> Vec<ModulePath> {
+        merge_module_lists(
+            self.main.list_modules_sandboxed(),
+            self.fallback.list_modules_sandboxed(),
+        )
+    }
+}
+
+fn merge_module_lists(mut modules: Vec<ModulePath>, other: Vec<ModulePath>) -> Vec<ModulePath> {
+    modules.extend(other);
+
+    modules.sort();
+    modules.dedup();
+
+    modules
 }
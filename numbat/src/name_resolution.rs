@@ -2,10 +2,22 @@ use std::collections::HashMap;
 
 use thiserror::Error;
 
+use crate::error_code::ErrorCode;
 use crate::span::Span;
+use crate::symbol::Symbol;
 
 pub const LAST_RESULT_IDENTIFIERS: &[&str] = &["ans", "_"];
 
+/// Returns the (1-based) index `n` if the identifier is of the form `_n`,
+/// which refers to the n-th result of the session.
+pub fn indexed_result_identifier(identifier: &str) -> Option<usize> {
+    let digits = identifier.strip_prefix('_')?;
+    if digits.is_empty() || digits.starts_with('0') || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
 #[derive(Debug, Clone, Error, PartialEq, Eq)]
 pub enum NameResolutionError {
     #[error("Identifier is already in use{}: '{conflicting_identifier}'.",
@@ -15,21 +27,56 @@ pub enum NameResolutionError {
         conflict_span: Span,
         original_span: Span,
         original_item_type: Option<String>,
+        /// If the identifier refers to a unit: the name of that unit (including
+        /// its prefix) and its dimension
+        unit: Option<Box<(String, String)>>,
     },
 
     #[error("Reserved identifier")]
     ReservedIdentifier(Span),
+
+    #[error("Ambiguous unit: '{identifier}'")]
+    AmbiguousUnit {
+        identifier: String,
+        span: Span,
+        candidates: Vec<UnitCandidate>,
+    },
+}
+
+/// One of the possible meanings of an ambiguous unit identifier
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnitCandidate {
+    /// The prefix as written, e.g. 'M' in 'MiB'
+    pub prefix: String,
+    /// The unit as written, e.g. 'iB' in 'MiB'
+    pub unit: String,
+    /// The full name of the unit, e.g. 'ibyte'
+    pub full_name: String,
+    /// The way to write this unit without ambiguity, if there is one (e.g. 'megaibyte')
+    pub long_form: Option<String>,
+    /// The dimension of the unit, if known
+    pub dimension: Option<String>,
+}
+
+impl NameResolutionError {
+    pub fn error_code(&self) -> ErrorCode {
+        match self {
+            NameResolutionError::IdentifierClash { .. } => ErrorCode::E0401,
+            NameResolutionError::ReservedIdentifier(..) => ErrorCode::E0402,
+            NameResolutionError::AmbiguousUnit { .. } => ErrorCode::E0403,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct Namespace {
-    seen: HashMap<String, (String, Span)>,
+    seen: HashMap<Symbol, (String, Span)>,
 }
 
 impl Namespace {
     pub fn add_identifier_allow_override(
         &mut self,
-        name: String,
+        name: Symbol,
         span: Span,
         item_type: String,
     ) -> Result<(), NameResolutionError> {
@@ -38,20 +85,35 @@ impl Namespace {
 
     pub fn add_identifier(
         &mut self,
-        name: String,
+        name: Symbol,
         span: Span,
         item_type: String,
     ) -> Result<(), NameResolutionError> {
         self.add_impl(name, span, item_type, false)
     }
 
-    pub fn has_identifier(&self, name: &str) -> bool {
-        self.seen.contains_key(name)
+    pub(crate) fn remove_identifier(&mut self, name: Symbol) {
+        self.seen.remove(&name);
+    }
+
+    pub(crate) fn take_identifier(&mut self, name: Symbol) -> Option<(String, Span)> {
+        self.seen.remove(&name)
+    }
+
+    pub(crate) fn restore_identifier(&mut self, name: Symbol, entry: Option<(String, Span)>) {
+        self.seen.remove(&name);
+        if let Some(entry) = entry {
+            self.seen.insert(name, entry);
+        }
+    }
+
+    pub fn has_identifier(&self, name: Symbol) -> bool {
+        self.seen.contains_key(&name)
     }
 
     fn add_impl(
         &mut self,
-        name: String,
+        name: Symbol,
         span: Span,
         item_type: String,
         allow_override: bool,
@@ -66,10 +128,11 @@ impl Namespace {
             }
 
             return Err(NameResolutionError::IdentifierClash {
-                conflicting_identifier: name,
+                conflicting_identifier: name.to_string(),
                 conflict_span: span,
                 original_span: *original_span,
                 original_item_type: Some(original_item_type.clone()),
+                unit: None,
             });
         }
 
@@ -23,6 +23,123 @@ impl Number {
         self.0.trunc() == self.0
     }
 
+    /// Spells out an integer in English words, e.g. `-1234` as "minus one thousand
+    /// two hundred thirty-four". Returns `None` for non-integers and numbers beyond
+    /// ±[`Number::MAX_IN_WORDS`].
+    pub fn to_words(self) -> Option<String> {
+        const ONES: [&str; 20] = [
+            "zero",
+            "one",
+            "two",
+            "three",
+            "four",
+            "five",
+            "six",
+            "seven",
+            "eight",
+            "nine",
+            "ten",
+            "eleven",
+            "twelve",
+            "thirteen",
+            "fourteen",
+            "fifteen",
+            "sixteen",
+            "seventeen",
+            "eighteen",
+            "nineteen",
+        ];
+        const TENS: [&str; 10] = [
+            "", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety",
+        ];
+        const SCALES: [&str; 6] = [
+            "",
+            "thousand",
+            "million",
+            "billion",
+            "trillion",
+            "quadrillion",
+        ];
+
+        fn below_thousand(n: u64) -> String {
+            let (hundreds, rest) = (n / 100, n % 100);
+            let mut words = vec![];
+            if hundreds > 0 {
+                words.push(format!("{} hundred", ONES[hundreds as usize]));
+            }
+            if rest >= 20 {
+                let (tens, ones) = (rest / 10, rest % 10);
+                if ones == 0 {
+                    words.push(TENS[tens as usize].to_string());
+                } else {
+                    words.push(format!("{}-{}", TENS[tens as usize], ONES[ones as usize]));
+                }
+            } else if rest > 0 {
+                words.push(ONES[rest as usize].to_string());
+            }
+            words.join(" ")
+        }
+
+        if !self.is_integer() || self.0.abs() > Self::MAX_IN_WORDS {
+            return None;
+        }
+
+        let mut n = self.0.abs() as u64;
+        if n == 0 {
+            return Some(ONES[0].to_string());
+        }
+
+        let mut groups = vec![];
+        for scale in SCALES {
+            let group = n % 1000;
+            if group > 0 {
+                groups.push(if scale.is_empty() {
+                    below_thousand(group)
+                } else {
+                    format!("{} {scale}", below_thousand(group))
+                });
+            }
+            n /= 1000;
+        }
+        groups.reverse();
+
+        let words = groups.join(" ");
+        Some(if self.0 < 0.0 {
+            format!("minus {words}")
+        } else {
+            words
+        })
+    }
+
+    /// The largest number that can be spelled out with [`Number::to_words`]
+    pub const MAX_IN_WORDS: f64 = 1e15;
+
+    /// Like [`Number::pretty_print`], but always in scientific notation (`1.5e+3`).
+    pub fn pretty_print_scientific(self) -> String {
+        use pretty_dtoa::{dtoa, FmtFloatConfig};
+
+        let config = FmtFloatConfig::default()
+            .max_significant_digits(6)
+            .add_point_zero(false)
+            .force_e_notation()
+            .round();
+
+        let formatted_number = dtoa(self.0, config);
+        let (mantissa, exponent) = formatted_number
+            .split_once('e')
+            .expect("number is in scientific notation");
+        let mantissa = if mantissa.contains('.') {
+            mantissa.trim_end_matches('0').trim_end_matches('.')
+        } else {
+            mantissa
+        };
+        if exponent.starts_with('-') {
+            format!("{mantissa}e{exponent}")
+        } else {
+            format!("{mantissa}e+{exponent}")
+        }
+    }
+
     pub fn pretty_print(self) -> String {
         let number = self.0;
 
@@ -158,3 +275,20 @@ fn test_pretty_print() {
     assert_eq!(Number::from_f64(0.000001).pretty_print(), "0.000001");
     assert_eq!(Number::from_f64(0.0000001).pretty_print(), "1.0e-7");
 }
+
+#[test]
+fn test_pretty_print_scientific() {
+    assert_eq!(Number::from_f64(2.).pretty_print_scientific(), "2e+0");
+    assert_eq!(
+        Number::from_f64(1234.5).pretty_print_scientific(),
+        "1.2345e+3"
+    );
+    assert_eq!(
+        Number::from_f64(-0.00012).pretty_print_scientific(),
+        "-1.2e-4"
+    );
+    assert_eq!(
+        Number::from_f64(123456789.).pretty_print_scientific(),
+        "1.23457e+8"
+    );
+}
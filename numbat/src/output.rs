@@ -0,0 +1,161 @@
+//! Output that is produced while interpreting Numbat code: text from `print`
+//! calls, diagnostics, and the results of `assert`/`assert_eq` calls.
+//!
+//! Everything is routed through an [`OutputSink`]. The [`TerminalSink`] writes
+//! to stdout/stderr, while the [`BufferingSink`] records all events in order,
+//! so that embedders can display them however they want.
+
+use codespan_reporting::files::SimpleFiles;
+
+use crate::diagnostic::Diagnostic;
+use crate::locale::Locale;
+use crate::markup::Markup;
+use crate::span::Span;
+use crate::value::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssertionKind {
+    /// `assert(condition)`
+    Assert,
+    /// `assert_eq(lhs, rhs)` or `assert_eq(lhs, rhs, eps)`
+    AssertEq,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssertionResult {
+    pub kind: AssertionKind,
+    pub passed: bool,
+    /// The evaluated arguments of the assertion, in order
+    pub values: Vec<Value>,
+    /// The source spans of the arguments
+    pub spans: Vec<Span>,
+}
+
+#[derive(Debug, Clone)]
+pub enum OutputEvent {
+    Print(Markup),
+    Diagnostic(Diagnostic),
+    Assertion(AssertionResult),
+}
+
+pub trait OutputSink: Send {
+    /// Output of `print` and `type` calls. Strings are passed without quotes,
+    /// all other values as pretty-printed markup.
+    fn print(&mut self, markup: &Markup);
+
+    /// Errors (and other diagnostics). The file IDs in the diagnostic refer to
+    /// `files`.
+    fn diagnostic(&mut self, diagnostic: &Diagnostic, files: &SimpleFiles<String, String>);
+
+    /// Called for every `assert`/`assert_eq`, whether it passes or not. Failed
+    /// assertions additionally stop the program with a runtime error.
+    fn assertion(&mut self, _result: &AssertionResult) {}
+}
+
+/// Writes printed output to stdout and diagnostics to stderr.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TerminalSink;
+
+impl OutputSink for TerminalSink {
+    fn print(&mut self, markup: &Markup) {
+        println!("{markup}");
+    }
+
+    fn diagnostic(&mut self, diagnostic: &Diagnostic, files: &SimpleFiles<String, String>) {
+        use codespan_reporting::term::{
+            self,
+            termcolor::{ColorChoice, StandardStream},
+            Config,
+        };
+
+        let writer = StandardStream::stderr(ColorChoice::Auto);
+        let config = Config::default();
+
+        // we want to be sure no one can write between our diagnostics
+        let mut writer = writer.lock();
+        term::emit(&mut writer, &config, files, &diagnostic.rendered()).unwrap();
+    }
+}
+
+/// Records all events, in the order in which they occurred.
+#[derive(Debug, Clone, Default)]
+pub struct BufferingSink {
+    events: Vec<OutputEvent>,
+}
+
+impl BufferingSink {
+    pub fn events(&self) -> &[OutputEvent] {
+        &self.events
+    }
+
+    pub fn take_events(&mut self) -> Vec<OutputEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Only the output of `print` and `type` calls
+    pub fn printed(&self) -> impl Iterator<Item = &Markup> {
+        self.events.iter().filter_map(|event| match event {
+            OutputEvent::Print(markup) => Some(markup),
+            _ => None,
+        })
+    }
+}
+
+impl OutputSink for BufferingSink {
+    fn print(&mut self, markup: &Markup) {
+        self.events.push(OutputEvent::Print(markup.clone()));
+    }
+
+    fn diagnostic(&mut self, diagnostic: &Diagnostic, _files: &SimpleFiles<String, String>) {
+        self.events
+            .push(OutputEvent::Diagnostic(diagnostic.clone()));
+    }
+
+    fn assertion(&mut self, result: &AssertionResult) {
+        self.events.push(OutputEvent::Assertion(result.clone()));
+    }
+}
+
+/// Ignores everything. Used when evaluating code whose output should not be shown.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct NullSink;
+
+impl OutputSink for NullSink {
+    fn print(&mut self, _markup: &Markup) {}
+
+    fn diagnostic(&mut self, _diagnostic: &Diagnostic, _files: &SimpleFiles<String, String>) {}
+}
+
+/// Ignores printed output, but passes on diagnostics (like warnings).
+#[cfg(feature = "currency")]
+pub(crate) struct DiagnosticsOnlySink<'a>(pub(crate) &'a mut dyn OutputSink);
+
+#[cfg(feature = "currency")]
+impl OutputSink for DiagnosticsOnlySink<'_> {
+    fn print(&mut self, _markup: &Markup) {}
+
+    fn diagnostic(&mut self, diagnostic: &Diagnostic, files: &SimpleFiles<String, String>) {
+        self.0.diagnostic(diagnostic, files);
+    }
+}
+
+/// Formats the numbers in printed output in the given locale before passing it
+/// on. Diagnostics and assertion results are forwarded unchanged.
+pub(crate) struct LocalizingSink<'a> {
+    pub(crate) inner: &'a mut dyn OutputSink,
+    pub(crate) locale: &'a Locale,
+}
+
+impl OutputSink for LocalizingSink<'_> {
+    fn print(&mut self, markup: &Markup) {
+        self.inner.print(&self.locale.localize(markup));
+    }
+
+    fn diagnostic(&mut self, diagnostic: &Diagnostic, files: &SimpleFiles<String, String>) {
+        self.inner.diagnostic(diagnostic, files);
+    }
+
+    fn assertion(&mut self, result: &AssertionResult) {
+        self.inner.assertion(result);
+    }
+}
@@ -2,17 +2,23 @@
 //!
 //! Grammar:
 //! ```txt
-//! statement       ::=   variable_decl | struct_decl | function_decl | dimension_decl | unit_decl | module_import | procedure_call | expression
+//! statement       ::=   variable_decl | struct_decl | impl_decl | formula_decl | function_decl | dimension_decl | unit_decl | unit_sys_decl | module_import | procedure_call | conditional_stmt | expression
 //!
-//! variable_decl   ::=   "let" identifier ( ":" type_annotation ) ? "=" expression
+//! variable_decl   ::=   "let" pattern ( ":" type_annotation ) ? "=" expression
+//! pattern         ::=   identifier | "{" ( identifier ( ":" pattern ) ? "," )* ( identifier ( ":" pattern ) ? "," ? ) ? "}" | "[" ( pattern "," )* ( pattern "," ? ) ? "]"
 //! struct_decl     ::=   "struct" identifier "{" ( identifier ":" type_annotation "," )* ( identifier ":" type_annotation "," ? ) ? "}"
+//! impl_decl       ::=   "impl" identifier "{" function_decl * "}"
+//! formula_decl    ::=   "formula" identifier ( "(" ( identifier ( ":" type_annotation ) ? "," )* ( identifier ( ":" type_annotation ) ? ) ? ")" ) ? ":" expression "=" expression
 //! function_decl   ::=   "fn" identifier ( fn_decl_generic ) ? fn_decl_param ( "->" type_annotation ) ? ( "=" expression ) ?
 //! fn_decl_generic ::=   "<" ( identifier "," ) * identifier ">"
-//! fn_decl_param   ::=   "(" ( identifier ( ":" type_annotation ) ? "," )* ( identifier ( ":" type_annotation ) ) ? ")"
+//! fn_decl_param   ::=   "(" ( identifier ( ":" type_annotation ) ? ( "=" expression ) ? "," )* ( identifier ( ":" type_annotation ) ? ( "=" expression ) ? ) ? ")"
 //! dimension_decl  ::=   "dimension" identifier ( "=" dimension_expr ) *
 //! unit_decl       ::=   decorator * "unit" ( ":" dimension_expr ) ? ( "=" expression ) ?
-//! module_import   ::=   "use" ident ( "::" ident) *
-//! procedure_call  ::=   ( "print" | "assert" | "assert_eq" | "type" ) "(" arguments? ")"
+//! unit_sys_decl   ::=   "unit_system" identifier "=" "[" ( identifier "," )* identifier "," ? "]"
+//! module_import   ::=   "use" ident ( "::" ident) * ( "::" "{" ( ident "," )* ident "," ? "}" ) ?
+//! procedure_call  ::=   ( "print" | "assert" | "assert_eq" | "type" | "conversion_cache_stats" | "regex_cache_stats" | "instantiation_cache_stats" | "set_fixed_time" | "set_implicit_conversions" | "write_text" | "append_csv" ) "(" arguments? ")"
+//! conditional_stmt::=   "if" conversion "then" cond_branch ( "else" cond_branch ) ?
+//! cond_branch     ::=   procedure_call | conditional_stmt
 //!
 //! decorator       ::=   "@" ( "metric_prefixes" | "binary_prefixes" | ( "aliases(" list_of_aliases ")" ) )
 //!
@@ -24,9 +30,10 @@
 //! dim_primary     ::=   identifier | "1" | "(" dimension_expr ")"
 //!
 //! expression      ::=   postfix_apply
-//! postfix_apply   ::=   condition ( "|>" identifier ) *
+//! postfix_apply   ::=   condition ( "|>" ( identifier | call ) ( ( "→" | "->" | "to" ) conv_target ) * ) *
 //! condition       ::=   ( "if" conversion "then" condition "else" condition ) | conversion
-//! conversion      ::=   logical_or ( ( "→" | "->" | "to" ) logical_or ) *
+//! conversion      ::=   logical_or ( ( "→" | "->" | "to" ) conv_target ) *
+//! conv_target     ::=   logical_or | "[" logical_or ( "," logical_or ) * "]"
 //! logical_or      ::=   logical_and ( "||" logical_and ) *
 //! logical_and     ::=   logical_neg ( "&&" logical_neg ) *
 //! logical_neg     ::=   ( "!" logical_neg) | comparison
@@ -40,19 +47,23 @@
 //! factorial       ::=   unicode_power "!" *
 //! unicode_power   ::=   call ( "⁻" ? ( "¹" | "²" | "³" | "⁴" | "⁵" | "⁶" | "⁷" | "⁸" | "⁹" ) ) ?
 //! call            ::=   primary ( ( "(" arguments? ")" ) | "." identifier ) *
-//! arguments       ::=   expression ( "," expression ) *
-//! primary         ::=   boolean | string | hex_number | oct_number | bin_number | number | identifier ( struct_expr ? ) | typed_hole | list_expr | "(" expression ")"
-//! struct_expr     ::=   "{" ( identifier ":" type_annotation "," )* ( identifier ":" expression "," ? ) ? "}"
+//! arguments       ::=   argument ( "," argument ) *
+//! argument        ::=   ( identifier "=" ) ? expression
+//! primary         ::=   boolean | string | hex_number | oct_number | bin_number | number | identifier ( struct_expr ? ) | typed_hole | list_expr | partial | autodiff | "(" expression ")"
+//! struct_expr     ::=   "{" ( identifier ":" type_annotation "," )* ( identifier ":" expression "," ? ) ? "}" | struct_update
+//! struct_update   ::=   "{" expression "with" ( identifier "=" expression "," )* ( identifier "=" expression "," ? ) ? "}"
 //! list_expr       ::=   "[]" | "[" expression ( "," expression ) * "]"
+//! partial         ::=   "partial" "(" expression ( "," arguments ) ? ")"
+//! autodiff        ::=   "autodiff" "(" expression ")"
 //!
 //! number          ::=   [0-9][0-9_]*("." ([0-9][0-9_]*)?)?([eE][+-]?[0-9][0-9_]*)?
 //! hex_number      ::=   "0x" [0-9a-fA-F]*
 //! oct_number      ::=   "0o" [0-7]*
 //! bin_number      ::=   "0b" [01]*
 //! integer         ::=   [0-9]([0-9_]*[0-9])?
-//! identifier      ::=   identifier_s identifier_c*
-//! identifier_s    ::=   Unicode_XID_Start | Unicode_Currency | "%" | "°" | "′" | "″" | "_"
-//! identifier_c    ::=   Unicode_XID_Continue | Unicode_Currency  | "%"
+//! identifier      ::=   identifier_s identifier_c* | "%" | "‰"
+//! identifier_s    ::=   Unicode_XID_Start | Unicode_Currency | "°" | "′" | "″" | "_"
+//! identifier_c    ::=   Unicode_XID_Continue | Unicode_Currency
 //! typed_hole      ::=   "?"
 //! boolean         ::=   "true" | "false"
 //! plus            ::=   "+"
@@ -64,17 +75,21 @@
 
 use crate::arithmetic::{Exponent, Rational};
 use crate::ast::{
-    BinaryOperator, DefineVariable, Expression, ProcedureKind, Statement, StringPart,
-    TypeAnnotation, TypeExpression, TypeParameterBound, UnaryOperator,
+    method_name, Accumulator, BinaryOperator, CallSyntax, ConversionTarget, DefineVariable,
+    Expression, NamedArgument, Pattern, ProcedureKind, Statement, StringPart, TypeAnnotation,
+    TypeExpression, TypeParameterBound, UnaryOperator,
 };
 use crate::decorator::{self, Decorator};
+use crate::error_code::ErrorCode;
 use crate::number::Number;
 use crate::prefix_parser::AcceptsPrefix;
 use crate::resolver::ModulePath;
 use crate::span::Span;
-use crate::tokenizer::{Token, TokenKind, TokenizerError, TokenizerErrorKind};
+use crate::symbol::Symbol;
+use crate::tokenizer::{DigitComma, Token, TokenKind, TokenizerError, TokenizerErrorKind};
 
 use num_traits::{CheckedDiv, FromPrimitive, Zero};
+use std::cell::RefCell;
 use thiserror::Error;
 
 #[derive(Error, Debug, Clone, PartialEq, Eq)]
@@ -147,6 +162,12 @@ pub enum ParseErrorKind {
     #[error("Expected ':' after a field name")]
     ExpectedColonAfterFieldName,
 
+    #[error("Expected '=' after a field name in struct update")]
+    ExpectedEqualAfterFieldNameInStructUpdate,
+
+    #[error("Expected 'with' after the base expression in struct update")]
+    ExpectedWithInStructUpdate,
+
     #[error("Only functions can be called")]
     CanOnlyCallIdentifier,
 
@@ -198,6 +219,21 @@ pub enum ParseErrorKind {
     #[error("Aliases cannot be used on functions.")]
     AliasUsedOnFunction,
 
+    #[error("Positional arguments can not follow named arguments")]
+    PositionalArgumentAfterNamedArgument,
+
+    #[error("Procedures can not be called with named arguments")]
+    NamedArgumentInProcedureCall,
+
+    #[error("Parameters without a default value can not follow parameters with a default value")]
+    ParameterWithoutDefaultAfterDefault,
+
+    #[error("Expected one of the operators '+', '-', '*' or '/'")]
+    ExpectedOverloadableOperator,
+
+    #[error("The @operator decorator can only be used on function definitions")]
+    OperatorDecoratorUsedOnNonFunction,
+
     #[error("Numerical overflow in dimension exponent")]
     OverflowInDimensionExponent,
 
@@ -234,8 +270,242 @@ pub enum ParseErrorKind {
     #[error("Empty string interpolation")]
     EmptyStringInterpolation,
 
-    #[error("Expected local variable definition after where/and")]
+    #[error("Expected local variable, unit or dimension definition after where/and")]
     ExpectedLocalVariableDefinition,
+
+    #[error("Expected identifier (unit system name)")]
+    ExpectedIdentifierAfterUnitSystem,
+
+    #[error("Expected '=' after unit system name")]
+    ExpectedEqualAfterUnitSystemName,
+
+    #[error("Expected '[' after '=' in unit system definition")]
+    ExpectedLeftBracketInUnitSystemDefinition,
+
+    #[error("Expected unit name in unit system definition")]
+    ExpectedUnitNameInUnitSystemDefinition,
+
+    #[error("Expected the signature of a native function, e.g. 'fn f(x: Length) -> Time'")]
+    ExpectedNativeFunctionSignature,
+
+    #[error(
+        "Expected a currency code and a number of decimal places, e.g. '@currency(\"USD\", 2)'"
+    )]
+    ExpectedCurrencyCodeAndDecimalPlaces,
+
+    #[error("The @currency decorator can only be used on unit definitions")]
+    CurrencyDecoratorUsedOnNonUnit,
+
+    #[error("Expected identifier, '{{' or '[' in destructuring pattern")]
+    ExpectedPattern,
+
+    #[error("Decorators can not be used on destructuring definitions")]
+    DecoratorsUsedOnDestructuringDefinition,
+
+    #[error("Expected procedure call or 'if' after 'else' in conditional statement")]
+    ExpectedProcedureCallInConditionalStatement,
+
+    /// With a suggestion for how to write the comparison and the missing left
+    /// hand side, if available
+    #[error("Missing left hand side of comparison")]
+    MissingLeftHandSideOfComparison(Option<(String, String)>),
+
+    #[error("Expected a dimension name, e.g. '@dimension(Widget)'")]
+    ExpectedDimensionNameInDecorator,
+
+    #[error(
+        "The @dimension decorator can only be used on base unit definitions without a dimension"
+    )]
+    DimensionDecoratorUsedOnUnsuitableKind,
+
+    #[error("Expected identifier (loop variable) after 'for'")]
+    ExpectedLoopVariable,
+
+    #[error("Expected 'in' after loop variable")]
+    ExpectedInAfterLoopVariable,
+
+    #[error("Expected '{{' before loop body")]
+    ExpectedLeftCurlyBeforeLoopBody,
+
+    #[error("Expected '}}' after loop body")]
+    ExpectedRightCurlyAfterLoopBody,
+
+    #[error("Expected 'with' after the condition of a while loop")]
+    ExpectedWithInWhileLoop,
+
+    #[error("Expected accumulator definition, e.g. 'with total = 0'")]
+    ExpectedAccumulatorDefinition,
+
+    #[error("Expected '{{' after 'try' or 'catch'")]
+    ExpectedLeftCurlyInTryCatch,
+
+    #[error("Expected '}}' after the body of 'try' or 'catch'")]
+    ExpectedRightCurlyInTryCatch,
+
+    #[error("Expected 'catch' after 'try {{ … }}'")]
+    ExpectedCatchAfterTry,
+
+    #[error("Expected ')' after variadic parameter, it needs to be the last parameter")]
+    ExpectedRightParenAfterVariadicParameter,
+
+    #[error("Variadic functions can not have parameters with default values")]
+    DefaultParameterInVariadicFunction,
+
+    #[error("Expected a number, e.g. '@uncertainty(0.000_15e-11)'")]
+    ExpectedUncertaintyValue,
+
+    #[error("The @uncertainty decorator can only be used on variable definitions")]
+    UncertaintyDecoratorUsedOnNonVariable,
+
+    #[error("A unit function needs exactly one parameter and a body, and can not be generic")]
+    InvalidUnitFunction,
+
+    #[error("Expected the definition of the inverse, e.g. 'inverse(x) = …'")]
+    ExpectedInverseDefinition,
+
+    #[error("Expression too deeply nested (the limit is {0} levels)")]
+    NestingTooDeep(usize),
+
+    #[error("Expected opening parenthesis '(' after 'partial'")]
+    ExpectedLeftParenAfterPartial,
+
+    #[error("Expected a function as the first argument of 'partial'")]
+    ExpectedFunctionInPartial,
+
+    #[error("Expected opening parenthesis '(' after 'autodiff'")]
+    ExpectedLeftParenAfterAutoDiff,
+
+    #[error("Expected opening parenthesis '(' after '{0}'")]
+    ExpectedLeftParenAfterDimensionPredicate(&'static str),
+
+    #[error("Expected a dimension as the second argument of 'has_dimension', e.g. 'has_dimension(x, Length)'")]
+    ExpectedDimensionInHasDimension,
+
+    #[error("Expected a function definition ('fn') inside of an 'impl' block")]
+    ExpectedFunctionInImplBlock,
+
+    #[error("The first parameter of a method needs to be 'self'")]
+    ExpectedSelfParameter,
+
+    #[error("The @isolated decorator can only be used on unit definitions")]
+    IsolatedDecoratorUsedOnNonUnit,
+
+    #[error("Expected the name of an item in the list of imported items")]
+    ExpectedImportedItem,
+
+    #[error("Missing closing brace '}}' after the list of imported items")]
+    MissingClosingCurlyInImport,
+
+    #[error("Expected the equation of the formula, e.g. 'formula ohms_law: voltage = current * resistance'")]
+    ExpectedFormulaEquation,
+}
+
+impl ParseErrorKind {
+    pub fn error_code(&self) -> ErrorCode {
+        match self {
+            ParseErrorKind::TokenizerError(inner) => inner.error_code(),
+            ParseErrorKind::ExpectedPrimary => ErrorCode::E0201,
+            ParseErrorKind::MissingClosingParen => ErrorCode::E0202,
+            ParseErrorKind::TrailingCharacters(..) => ErrorCode::E0203,
+            ParseErrorKind::TrailingEqualSign(..) => ErrorCode::E0204,
+            ParseErrorKind::ExpectedIdentifierAfterLet => ErrorCode::E0205,
+            ParseErrorKind::ExpectedEqualOrColonAfterLetIdentifier => ErrorCode::E0206,
+            ParseErrorKind::ExpectedIdentifierAfterFn => ErrorCode::E0207,
+            ParseErrorKind::ExpectedIdentifier => ErrorCode::E0208,
+            ParseErrorKind::ExpectedIdentifierOrCallAfterPostfixApply => ErrorCode::E0209,
+            ParseErrorKind::ExpectedDimensionPrimary => ErrorCode::E0210,
+            ParseErrorKind::ExpectedCommaOrRightAngleBracket => ErrorCode::E0211,
+            ParseErrorKind::ExpectedTypeParameterName => ErrorCode::E0212,
+            ParseErrorKind::ExpectedLeftParenInFunctionDefinition => ErrorCode::E0213,
+            ParseErrorKind::ExpectedCommaEllipsisOrRightParenInFunctionDefinition => {
+                ErrorCode::E0214
+            }
+            ParseErrorKind::ExpectedCommaOrRightCurlyInStructFieldList => ErrorCode::E0215,
+            ParseErrorKind::ExpectedParameterNameInFunctionDefinition => ErrorCode::E0216,
+            ParseErrorKind::ExpectedFieldNameInStruct => ErrorCode::E0217,
+            ParseErrorKind::ExpectedIdentifierAfterDimension => ErrorCode::E0218,
+            ParseErrorKind::ExpectedIdentifierAfterUnit => ErrorCode::E0219,
+            ParseErrorKind::ExpectedColonOrEqualAfterUnitIdentifier => ErrorCode::E0220,
+            ParseErrorKind::ExpectedColonAfterFieldName => ErrorCode::E0221,
+            ParseErrorKind::ExpectedEqualAfterFieldNameInStructUpdate => ErrorCode::E0222,
+            ParseErrorKind::ExpectedWithInStructUpdate => ErrorCode::E0223,
+            ParseErrorKind::CanOnlyCallIdentifier => ErrorCode::E0224,
+            ParseErrorKind::DivisionByZeroInDimensionExponent => ErrorCode::E0225,
+            ParseErrorKind::ExpectedLeftParenAfterProcedureName => ErrorCode::E0226,
+            ParseErrorKind::InlineProcedureUsage => ErrorCode::E0228,
+            ParseErrorKind::ExpectedDecoratorName => ErrorCode::E0229,
+            ParseErrorKind::UnknownDecorator => ErrorCode::E0230,
+            ParseErrorKind::ExpectedModulePathAfterUse => ErrorCode::E0231,
+            ParseErrorKind::ExpectedModuleNameAfterDoubleColon => ErrorCode::E0232,
+            ParseErrorKind::OverflowInNumberLiteral => ErrorCode::E0233,
+            ParseErrorKind::ExpectedDimensionExponent => ErrorCode::E0234,
+            ParseErrorKind::DoubleUnderscoreTypeNamesReserved => ErrorCode::E0235,
+            ParseErrorKind::NumberInDimensionExponentOutOfRange => ErrorCode::E0236,
+            ParseErrorKind::DecoratorUsedOnUnsuitableKind => ErrorCode::E0237,
+            ParseErrorKind::DecoratorsWithPrefixOnLetDefinition => ErrorCode::E0238,
+            ParseErrorKind::ExpectedLeftParenAfterDecorator => ErrorCode::E0239,
+            ParseErrorKind::UnknownAliasAnnotation => ErrorCode::E0240,
+            ParseErrorKind::AliasUsedOnFunction => ErrorCode::E0241,
+            ParseErrorKind::PositionalArgumentAfterNamedArgument => ErrorCode::E0242,
+            ParseErrorKind::NamedArgumentInProcedureCall => ErrorCode::E0243,
+            ParseErrorKind::ParameterWithoutDefaultAfterDefault => ErrorCode::E0244,
+            ParseErrorKind::ExpectedOverloadableOperator => ErrorCode::E0245,
+            ParseErrorKind::OperatorDecoratorUsedOnNonFunction => ErrorCode::E0246,
+            ParseErrorKind::OverflowInDimensionExponent => ErrorCode::E0247,
+            ParseErrorKind::ExpectedThen => ErrorCode::E0248,
+            ParseErrorKind::ExpectedElse => ErrorCode::E0249,
+            ParseErrorKind::UnterminatedString => ErrorCode::E0250,
+            ParseErrorKind::ExpectedString => ErrorCode::E0251,
+            ParseErrorKind::ExpectedTokenInFunctionType(..) => ErrorCode::E0252,
+            ParseErrorKind::ExpectedTokenInListType(..) => ErrorCode::E0253,
+            ParseErrorKind::ExpectedLeftCurlyAfterStructName => ErrorCode::E0254,
+            ParseErrorKind::ExpectedCommaOrRightBracketInList => ErrorCode::E0255,
+            ParseErrorKind::UnknownBound(..) => ErrorCode::E0256,
+            ParseErrorKind::ExpectedBoundInTypeParameterDefinition => ErrorCode::E0257,
+            ParseErrorKind::EmptyStringInterpolation => ErrorCode::E0258,
+            ParseErrorKind::ExpectedLocalVariableDefinition => ErrorCode::E0259,
+            ParseErrorKind::ExpectedIdentifierAfterUnitSystem => ErrorCode::E0260,
+            ParseErrorKind::ExpectedEqualAfterUnitSystemName => ErrorCode::E0261,
+            ParseErrorKind::ExpectedLeftBracketInUnitSystemDefinition => ErrorCode::E0262,
+            ParseErrorKind::ExpectedUnitNameInUnitSystemDefinition => ErrorCode::E0263,
+            ParseErrorKind::ExpectedNativeFunctionSignature => ErrorCode::E0264,
+            ParseErrorKind::ExpectedCurrencyCodeAndDecimalPlaces => ErrorCode::E0265,
+            ParseErrorKind::CurrencyDecoratorUsedOnNonUnit => ErrorCode::E0266,
+            ParseErrorKind::ExpectedPattern => ErrorCode::E0267,
+            ParseErrorKind::DecoratorsUsedOnDestructuringDefinition => ErrorCode::E0268,
+            ParseErrorKind::ExpectedProcedureCallInConditionalStatement => ErrorCode::E0269,
+            ParseErrorKind::MissingLeftHandSideOfComparison(_) => ErrorCode::E0271,
+            ParseErrorKind::ExpectedDimensionNameInDecorator => ErrorCode::E0272,
+            ParseErrorKind::DimensionDecoratorUsedOnUnsuitableKind => ErrorCode::E0273,
+            ParseErrorKind::ExpectedLoopVariable => ErrorCode::E0274,
+            ParseErrorKind::ExpectedInAfterLoopVariable => ErrorCode::E0275,
+            ParseErrorKind::ExpectedLeftCurlyBeforeLoopBody => ErrorCode::E0276,
+            ParseErrorKind::ExpectedRightCurlyAfterLoopBody => ErrorCode::E0277,
+            ParseErrorKind::ExpectedWithInWhileLoop => ErrorCode::E0278,
+            ParseErrorKind::ExpectedAccumulatorDefinition => ErrorCode::E0279,
+            ParseErrorKind::ExpectedLeftCurlyInTryCatch => ErrorCode::E0280,
+            ParseErrorKind::ExpectedRightCurlyInTryCatch => ErrorCode::E0281,
+            ParseErrorKind::ExpectedCatchAfterTry => ErrorCode::E0282,
+            ParseErrorKind::ExpectedRightParenAfterVariadicParameter => ErrorCode::E0283,
+            ParseErrorKind::DefaultParameterInVariadicFunction => ErrorCode::E0284,
+            ParseErrorKind::ExpectedUncertaintyValue => ErrorCode::E0285,
+            ParseErrorKind::UncertaintyDecoratorUsedOnNonVariable => ErrorCode::E0286,
+            ParseErrorKind::InvalidUnitFunction => ErrorCode::E0287,
+            ParseErrorKind::ExpectedInverseDefinition => ErrorCode::E0288,
+            ParseErrorKind::NestingTooDeep(_) => ErrorCode::E0289,
+            ParseErrorKind::ExpectedLeftParenAfterPartial => ErrorCode::E0290,
+            ParseErrorKind::ExpectedFunctionInPartial => ErrorCode::E0291,
+            ParseErrorKind::ExpectedFunctionInImplBlock => ErrorCode::E0292,
+            ParseErrorKind::ExpectedSelfParameter => ErrorCode::E0293,
+            ParseErrorKind::ExpectedLeftParenAfterAutoDiff => ErrorCode::E0294,
+            ParseErrorKind::IsolatedDecoratorUsedOnNonUnit => ErrorCode::E0295,
+            ParseErrorKind::ExpectedImportedItem => ErrorCode::E0296,
+            ParseErrorKind::MissingClosingCurlyInImport => ErrorCode::E0297,
+            ParseErrorKind::ExpectedLeftParenAfterDimensionPredicate(_) => ErrorCode::E0298,
+            ParseErrorKind::ExpectedDimensionInHasDimension => ErrorCode::E0299,
+            ParseErrorKind::ExpectedFormulaEquation => ErrorCode::E0901,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Error)]
@@ -246,11 +516,23 @@ pub struct ParseError {
 }
 
 impl ParseError {
-    fn new(kind: ParseErrorKind, span: Span) -> Self {
+    pub(crate) fn new(kind: ParseErrorKind, span: Span) -> Self {
         ParseError { kind, span }
     }
 }
 
+/// Code that can be parsed, but probably does not mean what was intended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseWarning {
+    /// An integer literal like `9007199254740993` that can not be represented
+    /// exactly as a floating point number, together with the value that is used
+    /// instead
+    InexactIntegerLiteral(Span, String),
+    /// A number literal with an uncertainty in its last digits, like `1.23(4)`. Only
+    /// the central value is used, the (pretty-printed) uncertainty is discarded.
+    DiscardedUncertainty(Span, String),
+}
+
 type Result<T, E = ParseError> = std::result::Result<T, E>;
 type ParseResult = Result<Vec<Statement>, (Vec<Statement>, Vec<ParseError>)>;
 
@@ -259,21 +541,100 @@ static PROCEDURES: &[TokenKind] = &[
     TokenKind::ProcedureAssert,
     TokenKind::ProcedureAssertEq,
     TokenKind::ProcedureType,
+    TokenKind::ProcedureConversionCacheStats,
+    TokenKind::ProcedureRegexCacheStats,
+    TokenKind::ProcedureInstantiationCacheStats,
+    TokenKind::ProcedureSetFixedTime,
+    TokenKind::ProcedureSetImplicitConversions,
+    TokenKind::ProcedureWriteText,
+    TokenKind::ProcedureAppendCsv,
+];
+
+static COMPARISON_OPERATORS: &[TokenKind] = &[
+    TokenKind::LessThan,
+    TokenKind::GreaterThan,
+    TokenKind::LessOrEqual,
+    TokenKind::GreaterOrEqual,
+    TokenKind::EqualEqual,
+    TokenKind::NotEqual,
 ];
 
+/// Settings that affect how code is parsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ParserOptions {
+    pub digit_comma: DigitComma,
+    /// How deeply expressions, statements, type annotations and patterns can be
+    /// nested. The parser and the later stages are recursive, so this protects
+    /// against stack overflows.
+    pub max_nesting_depth: usize,
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        ParserOptions {
+            digit_comma: DigitComma::default(),
+            max_nesting_depth: DEFAULT_MAX_NESTING_DEPTH,
+        }
+    }
+}
+
+pub(crate) const DEFAULT_MAX_NESTING_DEPTH: usize = 256;
+
 struct Parser<'a> {
     tokens: &'a [Token],
+    source: &'a str,
     current: usize,
     decorator_stack: Vec<Decorator>,
+    warnings: Vec<ParseWarning>,
+
+    /// Whether `name {` starts a struct instantiation. This is not the case in the
+    /// header of a loop, where the `{` starts the loop body.
+    struct_literals_allowed: bool,
+
+    /// The current nesting depth, see [`Parser::nested`]
+    depth: usize,
+    max_depth: usize,
 }
 
 impl<'a> Parser<'a> {
-    pub(crate) fn new(tokens: &'a [Token]) -> Self {
+    pub(crate) fn new(tokens: &'a [Token], source: &'a str) -> Self {
         Parser {
             tokens,
+            source,
             current: 0,
             decorator_stack: vec![],
+            warnings: vec![],
+            struct_literals_allowed: true,
+            depth: 0,
+            max_depth: DEFAULT_MAX_NESTING_DEPTH,
+        }
+    }
+
+    /// Parse something one nesting level deeper, or fail if the maximum nesting
+    /// depth has been reached. Every recursive descent goes through this.
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let depth = self.depth;
+        self.chained()?;
+        let result = parse(self);
+        self.depth = depth;
+        result
+    }
+
+    /// Account for one more level in a chain like `x |> f |> g`. The parser handles
+    /// these in a loop, but they still result in a deeply nested syntax tree that
+    /// the later stages traverse recursively. The depth is restored at the end of
+    /// the enclosing [`Parser::nested`]. Chains of arithmetic operators like
+    /// `a + b + c` do not count, as the later stages walk along their left hand
+    /// sides without recursion.
+    fn chained(&mut self) -> Result<()> {
+        if self.depth >= self.max_depth {
+            return Err(ParseError::new(
+                ParseErrorKind::NestingTooDeep(self.max_depth),
+                self.peek().span,
+            ));
         }
+        self.depth += 1;
+        Ok(())
     }
 
     fn skip_empty_lines(&mut self) {
@@ -363,12 +724,12 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn list_of_aliases(&mut self) -> Result<Vec<(String, Option<AcceptsPrefix>)>> {
+    fn list_of_aliases(&mut self) -> Result<Vec<(Symbol, Option<AcceptsPrefix>)>> {
         if self.match_exact(TokenKind::RightParen).is_some() {
             return Ok(vec![]);
         }
 
-        let mut identifiers: Vec<(String, Option<AcceptsPrefix>)> =
+        let mut identifiers: Vec<(Symbol, Option<AcceptsPrefix>)> =
             vec![(self.identifier()?, self.accepts_prefix()?)];
         while self.match_exact(TokenKind::Comma).is_some() {
             identifiers.push((self.identifier()?, self.accepts_prefix()?));
@@ -389,6 +750,7 @@ impl<'a> Parser<'a> {
             || self.peek().kind == TokenKind::Unit
             || self.peek().kind == TokenKind::Let
             || self.peek().kind == TokenKind::Fn
+            || self.peek().kind == TokenKind::UnitFn
             || self.decorator_stack.is_empty())
         {
             return Err(ParseError {
@@ -401,6 +763,8 @@ impl<'a> Parser<'a> {
             self.parse_variable(true).map(Statement::DefineVariable)
         } else if self.match_exact(TokenKind::Fn).is_some() {
             self.parse_function_declaration()
+        } else if self.match_exact(TokenKind::UnitFn).is_some() {
+            self.parse_unit_function_declaration()
         } else if self.match_exact(TokenKind::Dimension).is_some() {
             self.parse_dimension_declaration()
         } else if self.match_exact(TokenKind::At).is_some() {
@@ -411,17 +775,89 @@ impl<'a> Parser<'a> {
             self.parse_use()
         } else if self.match_exact(TokenKind::Struct).is_some() {
             self.parse_struct()
+        } else if self.match_exact(TokenKind::Impl).is_some() {
+            self.parse_impl()
+        } else if self.match_exact(TokenKind::Formula).is_some() {
+            self.parse_formula()
+        } else if self.match_exact(TokenKind::UnitSystem).is_some() {
+            self.parse_unit_system()
         } else if self.match_any(PROCEDURES).is_some() {
             self.parse_procedure()
+        } else if let Some(conditional) = self.conditional_statement()? {
+            Ok(conditional)
         } else {
             Ok(Statement::Expression(self.expression()?))
         }
     }
 
-    fn parse_variable(&mut self, flush_decorators: bool) -> Result<DefineVariable> {
-        if let Some(identifier) = self.match_exact(TokenKind::Identifier) {
-            let identifier_span = self.last().unwrap().span;
+    /// Parses `if … then print(…)`, optionally followed by an `else` branch.
+    /// Returns `None` without consuming any tokens if this is not a conditional
+    /// statement, i.e. if the `then` branch is not a procedure call. It is then
+    /// parsed as an `if-then-else` expression instead.
+    fn conditional_statement(&mut self) -> Result<Option<Statement>> {
+        let start = self.current;
+        let num_warnings = self.warnings.len();
+
+        let Some(if_token) = self.match_exact(TokenKind::If) else {
+            return Ok(None);
+        };
+        let span_if = if_token.span;
+        let condition = self.conversion()?;
+
+        self.skip_empty_lines();
+
+        if self.match_exact(TokenKind::Then).is_none() {
+            return Err(ParseError::new(
+                ParseErrorKind::ExpectedThen,
+                self.peek().span,
+            ));
+        }
+
+        self.skip_empty_lines();
+
+        let Some(then_statement) = self.conditional_branch()? else {
+            // The condition is parsed again, as part of the expression
+            self.current = start;
+            self.warnings.truncate(num_warnings);
+            return Ok(None);
+        };
+
+        let end_of_then_branch = self.current;
+        self.skip_empty_lines();
 
+        let else_statement = if self.match_exact(TokenKind::Else).is_some() {
+            self.skip_empty_lines();
+
+            let Some(else_statement) = self.conditional_branch()? else {
+                return Err(ParseError::new(
+                    ParseErrorKind::ExpectedProcedureCallInConditionalStatement,
+                    self.peek().span,
+                ));
+            };
+            Some(Box::new(else_statement))
+        } else {
+            self.current = end_of_then_branch;
+            None
+        };
+
+        Ok(Some(Statement::Conditional {
+            span_if,
+            condition,
+            then_statement: Box::new(then_statement),
+            else_statement,
+        }))
+    }
+
+    fn conditional_branch(&mut self) -> Result<Option<Statement>> {
+        if self.match_any(PROCEDURES).is_some() {
+            self.parse_procedure().map(Some)
+        } else {
+            self.nested(Self::conditional_statement)
+        }
+    }
+
+    fn parse_variable(&mut self, flush_decorators: bool) -> Result<DefineVariable> {
+        if let Some(pattern) = self.pattern()? {
             let type_annotation = if self.match_exact(TokenKind::Colon).is_some() {
                 Some(self.type_annotation()?)
             } else {
@@ -445,12 +881,43 @@ impl<'a> Parser<'a> {
                             span: self.peek().span,
                         });
                     }
+                    if decorator::operator(&self.decorator_stack).is_some() {
+                        return Err(ParseError {
+                            kind: ParseErrorKind::OperatorDecoratorUsedOnNonFunction,
+                            span: self.peek().span,
+                        });
+                    }
+                    if decorator::currency(&self.decorator_stack).is_some() {
+                        return Err(ParseError {
+                            kind: ParseErrorKind::CurrencyDecoratorUsedOnNonUnit,
+                            span: self.peek().span,
+                        });
+                    }
+                    if self.decorator_stack.contains(&Decorator::Isolated) {
+                        return Err(ParseError {
+                            kind: ParseErrorKind::IsolatedDecoratorUsedOnNonUnit,
+                            span: self.peek().span,
+                        });
+                    }
+                    if decorator::dimension(&self.decorator_stack).is_some() {
+                        return Err(ParseError {
+                            kind: ParseErrorKind::DimensionDecoratorUsedOnUnsuitableKind,
+                            span: self.peek().span,
+                        });
+                    }
+                    if !matches!(pattern, Pattern::Identifier(..))
+                        && !self.decorator_stack.is_empty()
+                    {
+                        return Err(ParseError {
+                            kind: ParseErrorKind::DecoratorsUsedOnDestructuringDefinition,
+                            span: pattern.full_span(),
+                        });
+                    }
                     std::mem::swap(&mut decorators, &mut self.decorator_stack);
                 }
 
                 Ok(DefineVariable {
-                    identifier_span,
-                    identifier: identifier.lexeme.clone(),
+                    pattern,
                     expr,
                     type_annotation,
                     decorators,
@@ -464,6 +931,92 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parses the left hand side of a variable definition. Returns `None` if the
+    /// next token does not start a pattern.
+    fn pattern(&mut self) -> Result<Option<Pattern>> {
+        if let Some(identifier) = self.match_exact(TokenKind::Identifier) {
+            Ok(Some(Pattern::Identifier(
+                identifier.span,
+                identifier.lexeme.as_str().into(),
+            )))
+        } else if let Some(left_curly) = self.match_exact(TokenKind::LeftCurly) {
+            let start_span = left_curly.span;
+            self.skip_empty_lines();
+
+            let mut fields = vec![];
+            while self.match_exact(TokenKind::RightCurly).is_none() {
+                let Some(field_name) = self.match_exact(TokenKind::Identifier) else {
+                    return Err(ParseError {
+                        kind: ParseErrorKind::ExpectedFieldNameInStruct,
+                        span: self.peek().span,
+                    });
+                };
+                let field_span = field_name.span;
+                let field: Symbol = field_name.lexeme.as_str().into();
+
+                self.skip_empty_lines();
+
+                let pattern = if self.match_exact(TokenKind::Colon).is_some() {
+                    self.skip_empty_lines();
+                    self.nested_pattern()?
+                } else {
+                    Pattern::Identifier(field_span, field)
+                };
+
+                self.skip_empty_lines();
+
+                if self.match_exact(TokenKind::Comma).is_none()
+                    && self.peek().kind != TokenKind::RightCurly
+                {
+                    return Err(ParseError {
+                        kind: ParseErrorKind::ExpectedCommaOrRightCurlyInStructFieldList,
+                        span: self.peek().span,
+                    });
+                }
+
+                self.skip_empty_lines();
+
+                fields.push((field_span, field, pattern));
+            }
+
+            let span = start_span.extend(&self.last().unwrap().span);
+            Ok(Some(Pattern::Struct(span, fields)))
+        } else if let Some(left_bracket) = self.match_exact(TokenKind::LeftBracket) {
+            let start_span = left_bracket.span;
+            self.skip_empty_lines();
+
+            let mut elements = vec![];
+            while self.match_exact(TokenKind::RightBracket).is_none() {
+                elements.push(self.nested_pattern()?);
+
+                self.skip_empty_lines();
+
+                if self.match_exact(TokenKind::Comma).is_none()
+                    && self.peek().kind != TokenKind::RightBracket
+                {
+                    return Err(ParseError {
+                        kind: ParseErrorKind::ExpectedCommaOrRightBracketInList,
+                        span: self.peek().span,
+                    });
+                }
+
+                self.skip_empty_lines();
+            }
+
+            let span = start_span.extend(&self.last().unwrap().span);
+            Ok(Some(Pattern::List(span, elements)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn nested_pattern(&mut self) -> Result<Pattern> {
+        self.nested(Self::pattern)?.ok_or_else(|| ParseError {
+            kind: ParseErrorKind::ExpectedPattern,
+            span: self.peek().span,
+        })
+    }
+
     fn parse_function_declaration(&mut self) -> Result<Statement> {
         if let Some(fn_name) = self.match_exact(TokenKind::Identifier) {
             let function_name_span = self.last().unwrap().span;
@@ -477,6 +1030,9 @@ impl<'a> Parser<'a> {
                                 Some(token) if token.lexeme == "Dim" => {
                                     Some(TypeParameterBound::Dim)
                                 }
+                                Some(token) if token.lexeme == "Ord" => {
+                                    Some(TypeParameterBound::Ord)
+                                }
                                 Some(token) => {
                                     return Err(ParseError {
                                         kind: ParseErrorKind::UnknownBound(token.lexeme.clone()),
@@ -496,7 +1052,11 @@ impl<'a> Parser<'a> {
                         };
 
                         let span = self.last().unwrap().span;
-                        type_parameters.push((span, type_parameter_name.lexeme.to_string(), bound));
+                        type_parameters.push((
+                            span,
+                            type_parameter_name.lexeme.as_str().into(),
+                            bound,
+                        ));
 
                         if self.match_exact(TokenKind::Comma).is_none()
                             && self.peek().kind != TokenKind::GreaterThan
@@ -530,12 +1090,53 @@ impl<'a> Parser<'a> {
                 if let Some(param_name) = self.match_exact(TokenKind::Identifier) {
                     let span = self.last().unwrap().span;
                     let param_type_dexpr = if self.match_exact(TokenKind::Colon).is_some() {
-                        Some(self.type_annotation()?)
+                        let annotation = self.type_annotation()?;
+                        if self.match_exact(TokenKind::Ellipsis).is_some() {
+                            let span = annotation.full_span().extend(&self.last().unwrap().span);
+                            Some(TypeAnnotation::Variadic(span, Box::new(annotation)))
+                        } else {
+                            Some(annotation)
+                        }
+                    } else {
+                        None
+                    };
+                    let is_variadic =
+                        matches!(param_type_dexpr, Some(TypeAnnotation::Variadic(..)));
+
+                    if is_variadic
+                        && (self.peek().kind == TokenKind::Equal
+                            || parameters
+                                .iter()
+                                .any(|(_, _, _, default)| Option::is_some(default)))
+                    {
+                        return Err(ParseError {
+                            kind: ParseErrorKind::DefaultParameterInVariadicFunction,
+                            span,
+                        });
+                    }
+
+                    let default_value = if self.match_exact(TokenKind::Equal).is_some() {
+                        self.skip_empty_lines();
+                        Some(self.expression()?)
                     } else {
+                        if parameters
+                            .iter()
+                            .any(|(_, _, _, default)| Option::is_some(default))
+                        {
+                            return Err(ParseError {
+                                kind: ParseErrorKind::ParameterWithoutDefaultAfterDefault,
+                                span,
+                            });
+                        }
                         None
                     };
 
-                    parameters.push((span, param_name.lexeme.to_string(), param_type_dexpr));
+                    parameters.push((
+                        span,
+                        param_name.lexeme.as_str().into(),
+                        param_type_dexpr,
+                        default_value,
+                    ));
 
                     parameter_span = parameter_span.extend(&self.last().unwrap().span);
 
@@ -546,6 +1147,13 @@ impl<'a> Parser<'a> {
                         break;
                     }
 
+                    if is_variadic {
+                        return Err(ParseError {
+                            kind: ParseErrorKind::ExpectedRightParenAfterVariadicParameter,
+                            span: self.peek().span,
+                        });
+                    }
+
                     if !has_comma && self.peek().kind != TokenKind::RightParen {
                         return Err(ParseError {
                                 kind: ParseErrorKind::ExpectedCommaEllipsisOrRightParenInFunctionDefinition,
@@ -566,45 +1174,29 @@ impl<'a> Parser<'a> {
                 None
             };
 
-            let (body, local_variables) = if self.match_exact(TokenKind::Equal).is_none() {
-                (None, vec![])
-            } else {
-                self.skip_empty_lines();
-                let body = self.expression()?;
+            let (body, local_variables, local_units) =
+                if self.match_exact(TokenKind::Equal).is_none() {
+                    (None, vec![], vec![])
+                } else {
+                    self.skip_empty_lines();
+                    let body = self.expression()?;
 
-                let mut local_variables = Vec::new();
+                    let mut local_variables = Vec::new();
+                    let mut local_units = Vec::new();
 
-                if self
-                    .match_exact_beyond_linebreaks(TokenKind::Where)
-                    .is_some()
-                {
-                    let keyword_span = self.last().unwrap().span;
-                    self.skip_empty_lines();
-                    if let Ok(local_variable) = self.parse_variable(false) {
-                        local_variables.push(local_variable);
-                    } else {
-                        return Err(ParseError {
-                            kind: ParseErrorKind::ExpectedLocalVariableDefinition,
-                            span: keyword_span,
-                        });
-                    }
+                    if self
+                        .match_exact_beyond_linebreaks(TokenKind::Where)
+                        .is_some()
+                    {
+                        self.parse_local_definition(&mut local_variables, &mut local_units)?;
 
-                    while self.match_exact_beyond_linebreaks(TokenKind::And).is_some() {
-                        let keyword_span = self.last().unwrap().span;
-                        self.skip_empty_lines();
-                        if let Ok(local_variable) = self.parse_variable(false) {
-                            local_variables.push(local_variable);
-                        } else {
-                            return Err(ParseError {
-                                kind: ParseErrorKind::ExpectedLocalVariableDefinition,
-                                span: keyword_span,
-                            });
+                        while self.match_exact_beyond_linebreaks(TokenKind::And).is_some() {
+                            self.parse_local_definition(&mut local_variables, &mut local_units)?;
                         }
                     }
-                }
 
-                (Some(body), local_variables)
-            };
+                    (Some(body), local_variables, local_units)
+                };
 
             if decorator::contains_aliases(&self.decorator_stack) {
                 return Err(ParseError {
@@ -612,17 +1204,42 @@ impl<'a> Parser<'a> {
                     span: self.peek().span,
                 });
             }
+            if decorator::currency(&self.decorator_stack).is_some() {
+                return Err(ParseError {
+                    kind: ParseErrorKind::CurrencyDecoratorUsedOnNonUnit,
+                    span: self.peek().span,
+                });
+            }
+            if self.decorator_stack.contains(&Decorator::Isolated) {
+                return Err(ParseError {
+                    kind: ParseErrorKind::IsolatedDecoratorUsedOnNonUnit,
+                    span: self.peek().span,
+                });
+            }
+            if decorator::uncertainty(&self.decorator_stack).is_some() {
+                return Err(ParseError {
+                    kind: ParseErrorKind::UncertaintyDecoratorUsedOnNonVariable,
+                    span: self.peek().span,
+                });
+            }
+            if decorator::dimension(&self.decorator_stack).is_some() {
+                return Err(ParseError {
+                    kind: ParseErrorKind::DimensionDecoratorUsedOnUnsuitableKind,
+                    span: self.peek().span,
+                });
+            }
 
             let mut decorators = vec![];
             std::mem::swap(&mut decorators, &mut self.decorator_stack);
 
             Ok(Statement::DefineFunction {
                 function_name_span,
-                function_name: fn_name.lexeme.clone(),
+                function_name: fn_name.lexeme.as_str().into(),
                 type_parameters,
                 parameters,
                 body,
                 local_variables,
+                local_units,
                 return_type_annotation,
                 decorators,
             })
@@ -634,33 +1251,164 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn parse_dimension_declaration(&mut self) -> Result<Statement> {
-        if let Some(identifier) = self.match_exact(TokenKind::Identifier) {
-            if identifier.lexeme.starts_with("__") {
-                return Err(ParseError::new(
-                    ParseErrorKind::DoubleUnderscoreTypeNamesReserved,
-                    identifier.span,
-                ));
-            }
-
-            if self.match_exact(TokenKind::Equal).is_some() {
-                self.skip_empty_lines();
-                let mut dexprs = vec![self.dimension_expression()?];
+    /// Parses `unitfn name(param: Type) -> Type = …`, optionally followed by the
+    /// definition of the inverse: `inverse(x) = …`. The inverse is a function with
+    /// the name `name⁻¹`, whose types are the reverse of the original function.
+    fn parse_unit_function_declaration(&mut self) -> Result<Statement> {
+        let function = self.parse_function_declaration()?;
+        let Statement::DefineFunction {
+            function_name_span,
+            function_name,
+            type_parameters,
+            parameters,
+            body,
+            return_type_annotation,
+            ..
+        } = &function
+        else {
+            unreachable!("parse_function_declaration only returns function definitions")
+        };
 
-                while self.match_exact(TokenKind::Equal).is_some() {
-                    self.skip_empty_lines();
+        let is_valid = type_parameters.is_empty()
+            && body.is_some()
+            && matches!(
+                parameters.as_slice(),
+                [(_, _, annotation, None)] if !matches!(annotation, Some(TypeAnnotation::Variadic(..)))
+            );
+        if !is_valid {
+            return Err(ParseError::new(
+                ParseErrorKind::InvalidUnitFunction,
+                *function_name_span,
+            ));
+        }
+
+        let inverse = if self
+            .match_exact_beyond_linebreaks(TokenKind::Inverse)
+            .is_some()
+        {
+            let inverse_span = self.last().unwrap().span;
+            let expected_inverse = |parser: &Self| {
+                ParseError::new(
+                    ParseErrorKind::ExpectedInverseDefinition,
+                    parser.peek().span,
+                )
+            };
+
+            if self.match_exact(TokenKind::LeftParen).is_none() {
+                return Err(expected_inverse(self));
+            }
+            let Some(parameter) = self.match_exact(TokenKind::Identifier) else {
+                return Err(expected_inverse(self));
+            };
+            let parameter_span = self.last().unwrap().span;
+            let parameter_name = parameter.lexeme.as_str().into();
+            if self.match_exact(TokenKind::RightParen).is_none()
+                || self.match_exact(TokenKind::Equal).is_none()
+            {
+                return Err(expected_inverse(self));
+            }
+            self.skip_empty_lines();
+            let inverse_body = self.expression()?;
+
+            Some(Box::new(Statement::DefineFunction {
+                function_name_span: inverse_span,
+                function_name: format!("{function_name}⁻¹").as_str().into(),
+                type_parameters: vec![],
+                parameters: vec![(
+                    parameter_span,
+                    parameter_name,
+                    return_type_annotation.clone(),
+                    None,
+                )],
+                body: Some(inverse_body),
+                local_variables: vec![],
+                local_units: vec![],
+                return_type_annotation: parameters[0].2.clone(),
+                decorators: vec![],
+            }))
+        } else {
+            None
+        };
+
+        Ok(Statement::DefineUnitFunction {
+            function: Box::new(function),
+            inverse,
+        })
+    }
+
+    /// Parses a single definition after `where` or `and` in a function definition:
+    /// a local variable, or a unit or dimension that is local to the function.
+    fn parse_local_definition(
+        &mut self,
+        local_variables: &mut Vec<DefineVariable>,
+        local_units: &mut Vec<Statement>,
+    ) -> Result<()> {
+        let keyword_span = self.last().unwrap().span;
+        self.skip_empty_lines();
+
+        // The decorators on the stack belong to the function itself
+        let decorators = std::mem::take(&mut self.decorator_stack);
+        let result = if self.match_exact(TokenKind::Unit).is_some() {
+            self.parse_unit_declaration()
+                .map(|unit| local_units.push(unit))
+        } else if self.match_exact(TokenKind::Dimension).is_some() {
+            self.parse_dimension_declaration()
+                .map(|dimension| local_units.push(dimension))
+        } else if self.match_exact(TokenKind::At).is_some() {
+            match self.parse_decorators() {
+                Ok(
+                    unit @ (Statement::DefineBaseUnit(..) | Statement::DefineDerivedUnit { .. }),
+                ) => {
+                    local_units.push(unit);
+                    Ok(())
+                }
+                Ok(_) => Err(ParseError {
+                    kind: ParseErrorKind::ExpectedLocalVariableDefinition,
+                    span: keyword_span,
+                }),
+                Err(e) => Err(e),
+            }
+        } else if let Ok(local_variable) = self.parse_variable(false) {
+            local_variables.push(local_variable);
+            Ok(())
+        } else {
+            Err(ParseError {
+                kind: ParseErrorKind::ExpectedLocalVariableDefinition,
+                span: keyword_span,
+            })
+        };
+        self.decorator_stack = decorators;
+
+        result
+    }
+
+    fn parse_dimension_declaration(&mut self) -> Result<Statement> {
+        if let Some(identifier) = self.match_exact(TokenKind::Identifier) {
+            if identifier.lexeme.starts_with("__") {
+                return Err(ParseError::new(
+                    ParseErrorKind::DoubleUnderscoreTypeNamesReserved,
+                    identifier.span,
+                ));
+            }
+
+            if self.match_exact(TokenKind::Equal).is_some() {
+                self.skip_empty_lines();
+                let mut dexprs = vec![self.dimension_expression()?];
+
+                while self.match_exact(TokenKind::Equal).is_some() {
+                    self.skip_empty_lines();
                     dexprs.push(self.dimension_expression()?);
                 }
 
                 Ok(Statement::DefineDimension(
                     identifier.span,
-                    identifier.lexeme.clone(),
+                    identifier.lexeme.as_str().into(),
                     dexprs,
                 ))
             } else {
                 Ok(Statement::DefineDimension(
                     identifier.span,
-                    identifier.lexeme.clone(),
+                    identifier.lexeme.as_str().into(),
                     vec![],
                 ))
             }
@@ -673,10 +1421,12 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_decorators(&mut self) -> Result<Statement> {
-        if let Some(decorator) = self.match_exact(TokenKind::Identifier) {
+        // 'dimension' is a keyword, but also the name of a decorator
+        if let Some(decorator) = self.match_any(&[TokenKind::Identifier, TokenKind::Dimension]) {
             let decorator = match decorator.lexeme.as_str() {
                 "metric_prefixes" => Decorator::MetricPrefixes,
                 "binary_prefixes" => Decorator::BinaryPrefixes,
+                "isolated" => Decorator::Isolated,
                 "aliases" => {
                     if self.match_exact(TokenKind::LeftParen).is_some() {
                         let aliases = self.list_of_aliases()?;
@@ -688,7 +1438,123 @@ impl<'a> Parser<'a> {
                         });
                     }
                 }
-                "url" | "name" | "description" => {
+                "operator" => {
+                    if self.match_exact(TokenKind::LeftParen).is_some() {
+                        let op = if self.match_exact(TokenKind::Plus).is_some() {
+                            BinaryOperator::Add
+                        } else if self.match_exact(TokenKind::Minus).is_some() {
+                            BinaryOperator::Sub
+                        } else if self.match_exact(TokenKind::Multiply).is_some() {
+                            BinaryOperator::Mul
+                        } else if self.match_exact(TokenKind::Divide).is_some() {
+                            BinaryOperator::Div
+                        } else {
+                            return Err(ParseError {
+                                kind: ParseErrorKind::ExpectedOverloadableOperator,
+                                span: self.peek().span,
+                            });
+                        };
+
+                        if self.match_exact(TokenKind::RightParen).is_none() {
+                            return Err(ParseError::new(
+                                ParseErrorKind::MissingClosingParen,
+                                self.peek().span,
+                            ));
+                        }
+
+                        Decorator::Operator(op)
+                    } else {
+                        return Err(ParseError {
+                            kind: ParseErrorKind::ExpectedLeftParenAfterDecorator,
+                            span: self.peek().span,
+                        });
+                    }
+                }
+                "currency" => {
+                    if self.match_exact(TokenKind::LeftParen).is_none() {
+                        return Err(ParseError {
+                            kind: ParseErrorKind::ExpectedLeftParenAfterDecorator,
+                            span: self.peek().span,
+                        });
+                    }
+
+                    let code = self.match_exact(TokenKind::StringFixed);
+                    let decimal_places = code
+                        .and_then(|_| self.match_exact(TokenKind::Comma))
+                        .and_then(|_| self.match_exact(TokenKind::Number))
+                        .and_then(|number| number.lexeme.parse::<u32>().ok());
+                    let (Some(code), Some(decimal_places)) = (code, decimal_places) else {
+                        return Err(ParseError {
+                            kind: ParseErrorKind::ExpectedCurrencyCodeAndDecimalPlaces,
+                            span: self.peek().span,
+                        });
+                    };
+
+                    if self.match_exact(TokenKind::RightParen).is_none() {
+                        return Err(ParseError::new(
+                            ParseErrorKind::MissingClosingParen,
+                            self.peek().span,
+                        ));
+                    }
+
+                    Decorator::Currency(strip_and_escape(&code.lexeme), decimal_places)
+                }
+                "dimension" => {
+                    if self.match_exact(TokenKind::LeftParen).is_none() {
+                        return Err(ParseError {
+                            kind: ParseErrorKind::ExpectedLeftParenAfterDecorator,
+                            span: self.peek().span,
+                        });
+                    }
+
+                    let Some(name) = self.match_exact(TokenKind::Identifier) else {
+                        return Err(ParseError {
+                            kind: ParseErrorKind::ExpectedDimensionNameInDecorator,
+                            span: self.peek().span,
+                        });
+                    };
+                    let name = Symbol::new(&name.lexeme);
+
+                    if self.match_exact(TokenKind::RightParen).is_none() {
+                        return Err(ParseError::new(
+                            ParseErrorKind::MissingClosingParen,
+                            self.peek().span,
+                        ));
+                    }
+
+                    Decorator::Dimension(name)
+                }
+                "uncertainty" => {
+                    if self.match_exact(TokenKind::LeftParen).is_none() {
+                        return Err(ParseError {
+                            kind: ParseErrorKind::ExpectedLeftParenAfterDecorator,
+                            span: self.peek().span,
+                        });
+                    }
+
+                    let Some(number) = self.match_exact(TokenKind::Number) else {
+                        return Err(ParseError {
+                            kind: ParseErrorKind::ExpectedUncertaintyValue,
+                            span: self.peek().span,
+                        });
+                    };
+                    let (value, None) = decimal_literal_value(&number.lexeme) else {
+                        return Err(ParseError {
+                            kind: ParseErrorKind::ExpectedUncertaintyValue,
+                            span: number.span,
+                        });
+                    };
+
+                    if self.match_exact(TokenKind::RightParen).is_none() {
+                        return Err(ParseError::new(
+                            ParseErrorKind::MissingClosingParen,
+                            self.peek().span,
+                        ));
+                    }
+
+                    Decorator::Uncertainty(Number::from_f64(value))
+                }
+                "url" | "name" | "description" | "deprecated" | "ucum" => {
                     if self.match_exact(TokenKind::LeftParen).is_some() {
                         if let Some(token) = self.match_exact(TokenKind::StringFixed) {
                             if self.match_exact(TokenKind::RightParen).is_none() {
@@ -704,6 +1570,8 @@ impl<'a> Parser<'a> {
                                 "url" => Decorator::Url(content),
                                 "name" => Decorator::Name(content),
                                 "description" => Decorator::Description(content),
+                                "deprecated" => Decorator::Deprecated(content),
+                                "ucum" => Decorator::Ucum(content),
                                 _ => unreachable!(),
                             }
                         } else {
@@ -731,7 +1599,7 @@ impl<'a> Parser<'a> {
 
             // A decorator is not yet a full statement. Continue parsing:
             self.skip_empty_lines();
-            self.statement()
+            self.nested(Self::statement)
         } else {
             Err(ParseError {
                 kind: ParseErrorKind::ExpectedDecoratorName,
@@ -750,7 +1618,28 @@ impl<'a> Parser<'a> {
                 (None, None)
             };
 
-            let unit_name = identifier.lexeme.clone();
+            let unit_name = Symbol::new(&identifier.lexeme);
+
+            if decorator::operator(&self.decorator_stack).is_some() {
+                return Err(ParseError {
+                    kind: ParseErrorKind::OperatorDecoratorUsedOnNonFunction,
+                    span: self.peek().span,
+                });
+            }
+            if decorator::uncertainty(&self.decorator_stack).is_some() {
+                return Err(ParseError {
+                    kind: ParseErrorKind::UncertaintyDecoratorUsedOnNonVariable,
+                    span: identifier_span,
+                });
+            }
+            if (dexpr.is_some() || self.peek().kind == TokenKind::Equal)
+                && decorator::dimension(&self.decorator_stack).is_some()
+            {
+                return Err(ParseError {
+                    kind: ParseErrorKind::DimensionDecoratorUsedOnUnsuitableKind,
+                    span: identifier_span,
+                });
+            }
 
             let mut decorators = vec![];
             std::mem::swap(&mut decorators, &mut self.decorator_stack);
@@ -800,9 +1689,13 @@ impl<'a> Parser<'a> {
         if let Some(identifier) = self.match_exact(TokenKind::Identifier) {
             let mut module_path = vec![identifier.lexeme.clone()];
 
+            let mut items = None;
             while self.match_exact(TokenKind::DoubleColon).is_some() {
                 if let Some(identifier) = self.match_exact(TokenKind::Identifier) {
                     module_path.push(identifier.lexeme.clone());
+                } else if self.match_exact(TokenKind::LeftCurly).is_some() {
+                    items = Some(self.parse_imported_items()?);
+                    break;
                 } else {
                     return Err(ParseError {
                         kind: ParseErrorKind::ExpectedModuleNameAfterDoubleColon,
@@ -812,7 +1705,11 @@ impl<'a> Parser<'a> {
             }
             span = span.extend(&self.last().unwrap().span);
 
-            Ok(Statement::ModuleImport(span, ModulePath(module_path)))
+            Ok(Statement::ModuleImport(
+                span,
+                ModulePath(module_path),
+                items,
+            ))
         } else {
             Err(ParseError {
                 kind: ParseErrorKind::ExpectedModulePathAfterUse,
@@ -870,7 +1767,11 @@ impl<'a> Parser<'a> {
                 });
             }
 
-            fields.push((field_name.span, field_name.lexeme.to_owned(), attr_type));
+            fields.push((
+                field_name.span,
+                field_name.lexeme.as_str().into(),
+                attr_type,
+            ));
         }
 
         Ok(Statement::DefineStruct {
@@ -880,6 +1781,248 @@ impl<'a> Parser<'a> {
         })
     }
 
+    /// Parses `impl Name { fn method(self, …) = … }`. The methods are function
+    /// definitions whose names are qualified with the name of the struct, see
+    /// [`method_name`]. A `self` parameter without type annotation has the
+    /// struct type.
+    fn parse_impl(&mut self) -> Result<Statement> {
+        let struct_name = self.identifier()?;
+        let struct_name_span = self.last().unwrap().span;
+
+        if self.match_exact(TokenKind::LeftCurly).is_none() {
+            return Err(ParseError {
+                kind: ParseErrorKind::ExpectedLeftCurlyAfterStructName,
+                span: self.peek().span,
+            });
+        }
+
+        self.skip_empty_lines();
+
+        let mut functions = vec![];
+        while self.match_exact(TokenKind::RightCurly).is_none() {
+            if self.match_exact(TokenKind::Fn).is_none() {
+                return Err(ParseError {
+                    kind: ParseErrorKind::ExpectedFunctionInImplBlock,
+                    span: self.peek().span,
+                });
+            }
+
+            let mut function = self.parse_function_declaration()?;
+            let Statement::DefineFunction {
+                function_name_span,
+                function_name,
+                parameters,
+                ..
+            } = &mut function
+            else {
+                unreachable!("parse_function_declaration only returns function definitions")
+            };
+
+            match parameters.first_mut() {
+                Some((span, name, annotation, None)) if name.as_str() == "self" => {
+                    if annotation.is_none() {
+                        *annotation = Some(TypeAnnotation::TypeExpression(
+                            TypeExpression::TypeIdentifier(*span, struct_name),
+                        ));
+                    }
+                }
+                _ => {
+                    return Err(ParseError {
+                        kind: ParseErrorKind::ExpectedSelfParameter,
+                        span: *function_name_span,
+                    });
+                }
+            }
+            *function_name = method_name(struct_name, *function_name);
+
+            functions.push(function);
+            self.skip_empty_lines();
+        }
+
+        Ok(Statement::DefineMethods {
+            struct_name_span,
+            struct_name,
+            functions,
+        })
+    }
+
+    /// Parses `formula name: lhs = rhs`. The quantities of the formula can be
+    /// declared along with their types, `formula name(q: Type, …): lhs = rhs`.
+    /// Otherwise, all identifiers in the equation are quantities.
+    fn parse_formula(&mut self) -> Result<Statement> {
+        let formula_name = self.identifier()?;
+        let formula_name_span = self.last().unwrap().span;
+
+        let declared_quantities = if self.match_exact(TokenKind::LeftParen).is_some() {
+            Some(self.parse_formula_quantities()?)
+        } else {
+            None
+        };
+
+        if self.match_exact(TokenKind::Colon).is_none() {
+            return Err(ParseError {
+                kind: ParseErrorKind::ExpectedFormulaEquation,
+                span: self.peek().span,
+            });
+        }
+        self.skip_empty_lines();
+
+        let lhs = self.expression()?;
+        if self.match_exact(TokenKind::Equal).is_none() {
+            return Err(ParseError {
+                kind: ParseErrorKind::ExpectedFormulaEquation,
+                span: self.peek().span,
+            });
+        }
+        self.skip_empty_lines();
+        let rhs = self.expression()?;
+
+        let quantities = declared_quantities.unwrap_or_else(|| {
+            identifiers_in_equation(&lhs, &rhs)
+                .into_iter()
+                .map(|(span, name)| (span, name, None))
+                .collect()
+        });
+
+        Ok(Statement::DefineFormula {
+            formula_name_span,
+            formula_name,
+            quantities,
+            lhs,
+            rhs,
+        })
+    }
+
+    /// The list of quantities in `formula name(q: Type, …)`, after the opening
+    /// parenthesis
+    fn parse_formula_quantities(&mut self) -> Result<Vec<(Span, Symbol, Option<TypeAnnotation>)>> {
+        let mut quantities = vec![];
+        self.skip_empty_lines();
+        while self.match_exact(TokenKind::RightParen).is_none() {
+            let Some(name) = self.match_exact(TokenKind::Identifier) else {
+                return Err(ParseError {
+                    kind: ParseErrorKind::ExpectedParameterNameInFunctionDefinition,
+                    span: self.peek().span,
+                });
+            };
+            let span = name.span;
+            let name = Symbol::new(&name.lexeme);
+
+            let annotation = if self.match_exact(TokenKind::Colon).is_some() {
+                Some(self.type_annotation()?)
+            } else {
+                None
+            };
+            quantities.push((span, name, annotation));
+            self.skip_empty_lines();
+
+            if self.match_exact(TokenKind::Comma).is_some() {
+                self.skip_empty_lines();
+            } else if self.peek().kind != TokenKind::RightParen {
+                return Err(ParseError {
+                    kind: ParseErrorKind::ExpectedCommaEllipsisOrRightParenInFunctionDefinition,
+                    span: self.peek().span,
+                });
+            }
+        }
+        Ok(quantities)
+    }
+
+    /// The list of items in `use foo::{bar, baz}`, after the opening brace
+    fn parse_imported_items(&mut self) -> Result<Vec<(Span, Symbol)>> {
+        let mut items = vec![];
+        self.skip_empty_lines();
+        while self.match_exact(TokenKind::RightCurly).is_none() {
+            let Some(item) = self.match_exact(TokenKind::Identifier) else {
+                return Err(ParseError {
+                    kind: ParseErrorKind::ExpectedImportedItem,
+                    span: self.peek().span,
+                });
+            };
+            items.push((item.span, Symbol::new(&item.lexeme)));
+            self.skip_empty_lines();
+
+            if self.match_exact(TokenKind::Comma).is_some() {
+                self.skip_empty_lines();
+            } else if self.peek().kind != TokenKind::RightCurly {
+                return Err(ParseError {
+                    kind: ParseErrorKind::MissingClosingCurlyInImport,
+                    span: self.peek().span,
+                });
+            }
+        }
+
+        if items.is_empty() {
+            return Err(ParseError {
+                kind: ParseErrorKind::ExpectedImportedItem,
+                span: self.last().unwrap().span,
+            });
+        }
+
+        Ok(items)
+    }
+
+    fn parse_unit_system(&mut self) -> Result<Statement> {
+        let Some(system_name) = self.match_exact(TokenKind::Identifier) else {
+            return Err(ParseError {
+                kind: ParseErrorKind::ExpectedIdentifierAfterUnitSystem,
+                span: self.peek().span,
+            });
+        };
+
+        if self.match_exact(TokenKind::Equal).is_none() {
+            return Err(ParseError {
+                kind: ParseErrorKind::ExpectedEqualAfterUnitSystemName,
+                span: self.peek().span,
+            });
+        }
+
+        self.skip_empty_lines();
+
+        if self.match_exact(TokenKind::LeftBracket).is_none() {
+            return Err(ParseError {
+                kind: ParseErrorKind::ExpectedLeftBracketInUnitSystemDefinition,
+                span: self.peek().span,
+            });
+        }
+
+        self.skip_empty_lines();
+
+        let mut units = vec![];
+        while self.match_exact(TokenKind::RightBracket).is_none() {
+            let Some(unit) = self.match_exact(TokenKind::Identifier) else {
+                return Err(ParseError {
+                    kind: ParseErrorKind::ExpectedUnitNameInUnitSystemDefinition,
+                    span: self.peek().span,
+                });
+            };
+
+            units.push(Expression::Identifier(
+                unit.span,
+                unit.lexeme.as_str().into(),
+            ));
+
+            self.skip_empty_lines();
+
+            if self.match_exact(TokenKind::Comma).is_none()
+                && self.peek().kind != TokenKind::RightBracket
+            {
+                return Err(ParseError {
+                    kind: ParseErrorKind::ExpectedCommaOrRightBracketInList,
+                    span: self.peek().span,
+                });
+            }
+
+            self.skip_empty_lines();
+        }
+
+        Ok(Statement::DefineUnitSystem {
+            system_name_span: system_name.span,
+            system_name: system_name.lexeme.as_str().into(),
+            units,
+        })
+    }
+
     fn parse_procedure(&mut self) -> Result<Statement> {
         let span = self.last().unwrap().span;
         let procedure_kind = match self.last().unwrap().kind {
@@ -887,15 +2030,25 @@ impl<'a> Parser<'a> {
             TokenKind::ProcedureAssert => ProcedureKind::Assert,
             TokenKind::ProcedureAssertEq => ProcedureKind::AssertEq,
             TokenKind::ProcedureType => ProcedureKind::Type,
+            TokenKind::ProcedureConversionCacheStats => ProcedureKind::ConversionCacheStats,
+            TokenKind::ProcedureRegexCacheStats => ProcedureKind::RegexCacheStats,
+            TokenKind::ProcedureInstantiationCacheStats => ProcedureKind::InstantiationCacheStats,
+            TokenKind::ProcedureSetFixedTime => ProcedureKind::SetFixedTime,
+            TokenKind::ProcedureSetImplicitConversions => ProcedureKind::SetImplicitConversions,
+            TokenKind::ProcedureWriteText => ProcedureKind::WriteText,
+            TokenKind::ProcedureAppendCsv => ProcedureKind::AppendCsv,
             _ => unreachable!(),
         };
 
         if self.match_exact(TokenKind::LeftParen).is_some() {
-            Ok(Statement::ProcedureCall(
-                span,
-                procedure_kind,
-                self.arguments()?,
-            ))
+            let (args, named_args) = self.arguments()?;
+            if let Some((name_span, _, _)) = named_args.first() {
+                return Err(ParseError {
+                    kind: ParseErrorKind::NamedArgumentInProcedureCall,
+                    span: *name_span,
+                });
+            }
+            Ok(Statement::ProcedureCall(span, procedure_kind, args))
         } else {
             Err(ParseError {
                 kind: ParseErrorKind::ExpectedLeftParenAfterProcedureName,
@@ -930,12 +2083,12 @@ impl<'a> Parser<'a> {
     }
 
     pub fn expression(&mut self) -> Result<Expression> {
-        self.postfix_apply()
+        self.nested(Self::postfix_apply)
     }
 
-    fn identifier(&mut self) -> Result<String> {
+    fn identifier(&mut self) -> Result<Symbol> {
         if let Some(identifier) = self.match_exact(TokenKind::Identifier) {
-            Ok(identifier.lexeme.clone())
+            Ok(identifier.lexeme.as_str().into())
         } else {
             Err(ParseError::new(
                 ParseErrorKind::ExpectedIdentifier,
@@ -948,31 +2101,45 @@ impl<'a> Parser<'a> {
         let mut expr = self.condition()?;
         let mut full_span = expr.full_span();
         while self.match_exact(TokenKind::PostfixApply).is_some() {
+            self.chained()?;
             self.skip_empty_lines();
-            match self.call()? {
-                Expression::Identifier(span, ident) => {
+            let mut call = self.call()?;
+            match &mut call {
+                Expression::Identifier(span, _) => {
+                    let span = *span;
                     full_span = full_span.extend(&span);
 
                     expr = Expression::FunctionCall(
                         span,
                         full_span,
-                        Box::new(Expression::Identifier(span, ident)),
+                        Box::new(call),
                         vec![expr],
+                        vec![],
+                        CallSyntax::Pipe,
                     );
                 }
-                Expression::FunctionCall(call_span, fn_full_span, call, mut params) => {
-                    full_span = full_span.extend(&fn_full_span);
+                Expression::FunctionCall(_, fn_full_span, _, params, _, syntax) => {
+                    full_span = full_span.extend(fn_full_span);
 
+                    *fn_full_span = full_span;
+                    *syntax = CallSyntax::Pipe;
                     params.push(expr);
-                    expr = Expression::FunctionCall(call_span, full_span, call, params);
+                    expr = call;
                 }
-                _other => {
+                other => {
                     return Err(ParseError::new(
                         ParseErrorKind::ExpectedIdentifierOrCallAfterPostfixApply,
-                        full_span,
+                        other.full_span(),
                     ))
                 }
             }
+
+            // Allow for `x |> f -> unit`, which converts the result of the pipeline
+            while self.match_any(&[TokenKind::Arrow, TokenKind::To]).is_some() {
+                self.chained()?;
+                expr = self.conversion_to_target(expr, false)?;
+                full_span = expr.full_span();
+            }
         }
         Ok(expr)
     }
@@ -993,20 +2160,27 @@ impl<'a> Parser<'a> {
 
             self.skip_empty_lines();
 
-            let then_expr = self.condition()?;
+            let then_expr = self.nested(Self::condition)?;
+            let then_span = then_expr.full_span();
 
             self.skip_empty_lines();
 
             if self.match_exact(TokenKind::Else).is_none() {
+                // Point to where the `else` branch is missing, which is not
+                // necessarily the next token, as it might be on a later line.
                 return Err(ParseError::new(
                     ParseErrorKind::ExpectedElse,
-                    self.peek().span,
+                    Span {
+                        start: then_span.end,
+                        end: then_span.end,
+                        code_source_id: then_span.code_source_id,
+                    },
                 ));
             }
 
             self.skip_empty_lines();
 
-            let else_expr = self.condition()?;
+            let else_expr = self.nested(Self::condition)?;
 
             Ok(Expression::Condition(
                 span_if,
@@ -1019,34 +2193,177 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Conversions like `x -> unit`, which have the lowest precedence of all operators.
+    /// Chains like `a -> b -> c` are applied from left to right.
     fn conversion(&mut self) -> Result<Expression> {
-        self.parse_binop(
-            &[TokenKind::Arrow, TokenKind::To],
-            |_| BinaryOperator::ConvertTo,
-            Self::logical_or,
-        )
+        let source_start = self.current;
+        let mut expr = self.logical_or()?;
+
+        let mut parenthesized_source = matches!(
+            expr,
+            Expression::BinaryOperator { .. } | Expression::ChainedComparison(..)
+        ) && self.is_parenthesized(source_start);
+
+        while self.match_any(&[TokenKind::Arrow, TokenKind::To]).is_some() {
+            self.chained()?;
+            expr = self.conversion_to_target(expr, parenthesized_source)?;
+            parenthesized_source = false;
+        }
+        Ok(expr)
+    }
+
+    /// Parse the target of a conversion, after the `->` operator
+    fn conversion_to_target(
+        &mut self,
+        source: Expression,
+        parenthesized_source: bool,
+    ) -> Result<Expression> {
+        let span_op = self.last().unwrap().span;
+        let mut target = self.logical_or()?;
+        let target = if let Expression::List(span, units) = &mut target {
+            ConversionTarget::UnitList(*span, std::mem::take(units))
+        } else {
+            ConversionTarget::Expression(Box::new(target))
+        };
+
+        Ok(Expression::Conversion {
+            source: Box::new(source),
+            target,
+            span_op,
+            parenthesized_source,
+        })
+    }
+
+    /// Whether the tokens from `start` up to the last one are enclosed in a single
+    /// pair of parentheses, like `(a + b)`, but not `(a) + (b)`.
+    fn is_parenthesized(&self, start: usize) -> bool {
+        if self.tokens[start].kind != TokenKind::LeftParen {
+            return false;
+        }
+
+        let mut depth = 0usize;
+        for (i, token) in self.tokens[start..self.current].iter().enumerate() {
+            match token.kind {
+                TokenKind::LeftParen => depth += 1,
+                TokenKind::RightParen => {
+                    depth = depth.saturating_sub(1);
+                    if depth == 0 {
+                        return start + i + 1 == self.current;
+                    }
+                }
+                _ => {}
+            }
+        }
+        false
     }
 
     fn logical_or(&mut self) -> Result<Expression> {
-        self.parse_binop(
-            &[TokenKind::LogicalOr],
-            |_| BinaryOperator::LogicalOr,
+        self.parse_logical_binop(
+            TokenKind::LogicalOr,
+            BinaryOperator::LogicalOr,
             Self::logical_and,
         )
     }
 
     fn logical_and(&mut self) -> Result<Expression> {
-        self.parse_binop(
-            &[TokenKind::LogicalAnd],
-            |_| BinaryOperator::LogicalAnd,
+        self.parse_logical_binop(
+            TokenKind::LogicalAnd,
+            BinaryOperator::LogicalAnd,
             Self::logical_neg,
         )
     }
 
+    /// Like [`Self::parse_binop`], but reports comparisons without a left hand
+    /// side on the right of the operator, like in `x > 0 && < 5`.
+    fn parse_logical_binop(
+        &mut self,
+        op_symbol: TokenKind,
+        op: BinaryOperator,
+        next_parser: impl Fn(&mut Self) -> Result<Expression>,
+    ) -> Result<Expression> {
+        let mut expr = next_parser(self)?;
+        while let Some(matched) = self.match_exact(op_symbol) {
+            self.chained()?;
+            let span_op = Some(matched.span);
+
+            if let Some(comparison) = self.match_any(COMPARISON_OPERATORS) {
+                let suggestion = match (Self::last_comparison_lhs(&expr), self.term()) {
+                    (Some(lhs), Ok(rhs)) => self.suggest_comparison(
+                        &expr,
+                        &matched.lexeme,
+                        lhs,
+                        &comparison.lexeme,
+                        &rhs,
+                    ),
+                    _ => None,
+                };
+                return Err(ParseError::new(
+                    ParseErrorKind::MissingLeftHandSideOfComparison(suggestion),
+                    comparison.span,
+                ));
+            }
+
+            let rhs = next_parser(self)?;
+
+            expr = Expression::BinaryOperator {
+                op,
+                lhs: Box::new(expr),
+                rhs: Box::new(rhs),
+                span_op,
+            };
+        }
+        Ok(expr)
+    }
+
+    /// The left hand side of the last comparison in a chain of `&&`s and `||`s,
+    /// e.g. `x` in `y != 0 && x > 0`.
+    fn last_comparison_lhs(expr: &Expression) -> Option<&Expression> {
+        match expr {
+            Expression::BinaryOperator {
+                op: BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr,
+                rhs,
+                ..
+            } => Self::last_comparison_lhs(rhs),
+            Expression::BinaryOperator { op, lhs, .. } if op.is_comparison() => Some(lhs),
+            Expression::ChainedComparison(first, rest) => Some(match rest.len() {
+                1 => first,
+                n => &rest[n - 2].2,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Builds `<first> <connective> <lhs> <op> <rhs>` from the source code, which
+    /// is what was probably meant with an incomplete comparison.
+    fn suggest_comparison(
+        &self,
+        first: &Expression,
+        connective: &str,
+        lhs: &Expression,
+        op: &str,
+        rhs: &Expression,
+    ) -> Option<(String, String)> {
+        let source = |expr: &Expression| {
+            let span = expr.full_span();
+            self.source
+                .get(span.start.byte as usize..span.end.byte as usize)
+        };
+
+        let lhs = source(lhs)?;
+        Some((
+            format!(
+                "{} {connective} {lhs} {op} {}",
+                source(first)?,
+                source(rhs)?
+            ),
+            lhs.to_string(),
+        ))
+    }
+
     fn logical_neg(&mut self) -> Result<Expression> {
         if self.match_exact(TokenKind::ExclamationMark).is_some() {
             let span = self.last().unwrap().span;
-            let rhs = self.logical_neg()?;
+            let rhs = self.nested(Self::logical_neg)?;
 
             Ok(Expression::UnaryOperator {
                 op: UnaryOperator::LogicalNeg,
@@ -1058,17 +2375,15 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Orderings like `a < b <= c` are chained, i.e. they evaluate to
+    /// `a < b && b <= c`. (In)equalities are left-associative as usual, so that
+    /// booleans can be compared: `a < b == true` is `(a < b) == true`.
     fn comparison(&mut self) -> Result<Expression> {
-        self.parse_binop(
-            &[
-                TokenKind::LessThan,
-                TokenKind::GreaterThan,
-                TokenKind::LessOrEqual,
-                TokenKind::GreaterOrEqual,
-                TokenKind::EqualEqual,
-                TokenKind::NotEqual,
-            ],
-            |matched| match matched {
+        let mut expr = self.term()?;
+        let mut orderings = vec![];
+        while let Some(matched) = self.match_any(COMPARISON_OPERATORS) {
+            let span_op = matched.span;
+            let op = match matched.kind {
                 TokenKind::LessThan => BinaryOperator::LessThan,
                 TokenKind::GreaterThan => BinaryOperator::GreaterThan,
                 TokenKind::LessOrEqual => BinaryOperator::LessOrEqual,
@@ -1076,9 +2391,41 @@ impl<'a> Parser<'a> {
                 TokenKind::EqualEqual => BinaryOperator::Equal,
                 TokenKind::NotEqual => BinaryOperator::NotEqual,
                 _ => unreachable!(),
-            },
-            Self::term,
-        )
+            };
+            self.chained()?;
+            let rhs = self.term()?;
+
+            if op.is_ordering() {
+                orderings.push((span_op, op, rhs));
+            } else {
+                expr = Expression::BinaryOperator {
+                    op,
+                    lhs: Box::new(Self::chain_orderings(expr, std::mem::take(&mut orderings))),
+                    rhs: Box::new(rhs),
+                    span_op: Some(span_op),
+                };
+            }
+        }
+        Ok(Self::chain_orderings(expr, orderings))
+    }
+
+    fn chain_orderings(
+        first: Expression,
+        mut orderings: Vec<(Span, BinaryOperator, Expression)>,
+    ) -> Expression {
+        match orderings.len() {
+            0 => first,
+            1 => {
+                let (span_op, op, rhs) = orderings.remove(0);
+                Expression::BinaryOperator {
+                    op,
+                    lhs: Box::new(first),
+                    rhs: Box::new(rhs),
+                    span_op: Some(span_op),
+                }
+            }
+            _ => Expression::ChainedComparison(Box::new(first), orderings),
+        }
     }
 
     fn term(&mut self) -> Result<Expression> {
@@ -1112,7 +2459,7 @@ impl<'a> Parser<'a> {
     fn unary(&mut self) -> Result<Expression> {
         if self.match_exact(TokenKind::Minus).is_some() {
             let span = self.last().unwrap().span;
-            let rhs = self.unary()?;
+            let rhs = self.nested(Self::unary)?;
 
             Ok(Expression::UnaryOperator {
                 op: UnaryOperator::Negate,
@@ -1122,7 +2469,7 @@ impl<'a> Parser<'a> {
         } else if self.match_exact(TokenKind::Plus).is_some() {
             // A unary `+` is equivalent to nothing. We can get rid of the
             // symbol without inserting any nodes in the AST.
-            self.unary()
+            self.nested(Self::unary)
         } else {
             self.ifactor()
         }
@@ -1158,7 +2505,7 @@ impl<'a> Parser<'a> {
                 None
             };
 
-            let mut rhs = self.power()?;
+            let mut rhs = self.nested(Self::power)?;
 
             if let Some((op, span_op)) = unary_op {
                 rhs = Expression::UnaryOperator {
@@ -1183,6 +2530,7 @@ impl<'a> Parser<'a> {
         let mut expr = self.unicode_power()?;
 
         while self.match_exact(TokenKind::ExclamationMark).is_some() {
+            self.chained()?;
             let span = self.last().unwrap().span;
 
             expr = Expression::UnaryOperator {
@@ -1244,14 +2592,27 @@ impl<'a> Parser<'a> {
     fn call(&mut self) -> Result<Expression> {
         let mut expr = self.primary()?;
 
+        // Only calls or field accesses on the result of another one, like in
+        // `f(x)(y)` or `p.position.x`, form a chain.
+        let mut is_chain = false;
         loop {
+            if !matches!(self.peek().kind, TokenKind::LeftParen | TokenKind::Period) {
+                return Ok(expr);
+            }
+            if is_chain {
+                self.chained()?;
+            }
+            is_chain = true;
+
             if self.match_exact(TokenKind::LeftParen).is_some() {
-                let args = self.arguments()?;
+                let (args, named_args) = self.with_struct_literals(true, Self::arguments)?;
                 expr = Expression::FunctionCall(
                     expr.full_span(),
                     expr.full_span().extend(&self.last().unwrap().span),
                     Box::new(expr),
                     args,
+                    named_args,
+                    CallSyntax::Regular,
                 );
             } else if self.match_exact(TokenKind::Period).is_some() {
                 let ident = self.identifier()?;
@@ -1259,19 +2620,21 @@ impl<'a> Parser<'a> {
                 let full_span = expr.full_span().extend(&ident_span);
 
                 expr = Expression::AccessField(full_span, ident_span, Box::new(expr), ident)
-            } else {
-                return Ok(expr);
             }
         }
     }
 
-    fn arguments(&mut self) -> Result<Vec<Expression>> {
+    /// Parses a comma-separated list of positional arguments, followed by
+    /// (optional) named arguments of the form `name = expr`.
+    fn arguments(&mut self) -> Result<(Vec<Expression>, Vec<NamedArgument>)> {
         self.skip_empty_lines();
         if self.match_exact(TokenKind::RightParen).is_some() {
-            return Ok(vec![]);
+            return Ok((vec![], vec![]));
         }
 
-        let mut args: Vec<Expression> = vec![self.expression()?];
+        let mut args: Vec<Expression> = vec![];
+        let mut named_args: Vec<NamedArgument> = vec![];
+        self.argument(&mut args, &mut named_args)?;
         loop {
             self.skip_empty_lines();
 
@@ -1280,8 +2643,14 @@ impl<'a> Parser<'a> {
                 if self.match_exact(TokenKind::RightParen).is_some() {
                     break;
                 }
-                match self.expression() {
-                    Ok(expr) => args.push(expr),
+                match self.argument(&mut args, &mut named_args) {
+                    Ok(()) => {}
+                    Err(
+                        err @ ParseError {
+                            kind: ParseErrorKind::PositionalArgumentAfterNamedArgument,
+                            ..
+                        },
+                    ) => return Err(err),
                     Err(_err) => {
                         return Err(ParseError::new(
                             ParseErrorKind::MissingClosingParen,
@@ -1299,52 +2668,117 @@ impl<'a> Parser<'a> {
             }
         }
 
-        Ok(args)
+        Ok((args, named_args))
     }
 
-    fn primary(&mut self) -> Result<Expression> {
-        // This function needs to be kept in sync with `next_token_could_start_power_expression` below.
+    /// Scans ahead (without consuming tokens) to decide whether the struct
+    /// expression that was just opened with '{' is an update of the form
+    /// `Name { base with field = value }`.
+    fn is_struct_update(&self) -> bool {
+        let mut depth = 0usize;
+        for token in &self.tokens[self.current..] {
+            match token.kind {
+                TokenKind::LeftParen | TokenKind::LeftBracket | TokenKind::LeftCurly => {
+                    depth += 1;
+                }
+                TokenKind::RightParen | TokenKind::RightBracket | TokenKind::RightCurly
+                    if depth > 0 =>
+                {
+                    depth -= 1;
+                }
+                TokenKind::With if depth == 0 => return true,
+                TokenKind::Colon
+                | TokenKind::Comma
+                | TokenKind::RightParen
+                | TokenKind::RightBracket
+                | TokenKind::RightCurly
+                | TokenKind::Eof
+                    if depth == 0 =>
+                {
+                    return false;
+                }
+                _ => {}
+            }
+        }
+        false
+    }
 
-        let overflow_error = |span| {
-            Err(ParseError::new(
-                ParseErrorKind::OverflowInNumberLiteral,
-                span,
-            ))
-        };
+    fn argument(
+        &mut self,
+        args: &mut Vec<Expression>,
+        named_args: &mut Vec<NamedArgument>,
+    ) -> Result<()> {
+        let is_named_argument = self.peek().kind == TokenKind::Identifier
+            && self
+                .tokens
+                .get(self.current + 1)
+                .is_some_and(|t| t.kind == TokenKind::Equal);
+
+        if is_named_argument {
+            let name = self.match_exact(TokenKind::Identifier).unwrap();
+            self.match_exact(TokenKind::Equal);
+            self.skip_empty_lines();
+            named_args.push((name.span, name.lexeme.as_str().into(), self.expression()?));
+        } else {
+            let expr = self.expression()?;
+            if !named_args.is_empty() {
+                return Err(ParseError::new(
+                    ParseErrorKind::PositionalArgumentAfterNamedArgument,
+                    expr.full_span(),
+                ));
+            }
+            args.push(expr);
+        }
 
-        if let Some(num) = self.match_exact(TokenKind::Number) {
-            let num_string = num.lexeme.replace('_', "");
-            Ok(Expression::Scalar(
-                self.last().unwrap().span,
-                Number::from_f64(num_string.parse::<f64>().unwrap()),
-            ))
-        } else if let Some(hex_int) = self.match_exact(TokenKind::IntegerWithBase(16)) {
-            let span = self.last().unwrap().span;
-            Ok(Expression::Scalar(
-                span,
-                Number::from_f64(
-                    i128::from_str_radix(&hex_int.lexeme[2..].replace('_', ""), 16)
-                        .or_else(|_| overflow_error(span))? as f64, // TODO: i128 limits our precision here
-                ),
-            ))
-        } else if let Some(oct_int) = self.match_exact(TokenKind::IntegerWithBase(8)) {
-            let span = self.last().unwrap().span;
-            Ok(Expression::Scalar(
-                span,
-                Number::from_f64(
-                    i128::from_str_radix(&oct_int.lexeme[2..].replace('_', ""), 8)
-                        .or_else(|_| overflow_error(span))? as f64, // TODO: i128 limits our precision here
-                ),
-            ))
-        } else if let Some(bin_int) = self.match_exact(TokenKind::IntegerWithBase(2)) {
-            let span = self.last().unwrap().span;
-            Ok(Expression::Scalar(
+        Ok(())
+    }
+
+    fn primary(&mut self) -> Result<Expression> {
+        // This function needs to be kept in sync with `next_token_could_start_power_expression` below.
+
+        let overflow_error = |span| {
+            Err(ParseError::new(
+                ParseErrorKind::OverflowInNumberLiteral,
                 span,
-                Number::from_f64(
-                    i128::from_str_radix(&bin_int.lexeme[2..].replace('_', ""), 2)
-                        .or_else(|_| overflow_error(span))? as f64, // TODO: i128 limits our precision here
-                ),
             ))
+        };
+
+        if let Some(num) = self.match_exact(TokenKind::Number) {
+            let (value, uncertainty) = decimal_literal_value(&num.lexeme);
+            if let Some(uncertainty) = uncertainty {
+                self.warnings.push(ParseWarning::DiscardedUncertainty(
+                    num.span,
+                    Number::from_f64(uncertainty).pretty_print(),
+                ));
+            }
+            let num_string = num.lexeme.replace('_', "");
+            if num_string.bytes().all(|b| b.is_ascii_digit()) {
+                self.check_integer_literal(num.span, &num_string, value);
+            }
+            Ok(Expression::Scalar(num.span, Number::from_f64(value)))
+        } else if let Some(int) = self.match_any(&[
+            TokenKind::IntegerWithBase(16),
+            TokenKind::IntegerWithBase(8),
+            TokenKind::IntegerWithBase(2),
+        ]) {
+            let TokenKind::IntegerWithBase(base) = int.kind else {
+                unreachable!()
+            };
+            let span = int.span;
+            // TODO: i128 limits the range here
+            let integer = i128::from_str_radix(&int.lexeme[2..].replace('_', ""), base as u32)
+                .or_else(|_| overflow_error(span))?;
+            let value = integer as f64;
+            self.check_integer_literal(span, &integer.to_string(), value);
+            Ok(Expression::Scalar(span, Number::from_f64(value)))
+        } else if let Some(float) = self.match_exact(TokenKind::HexadecimalFloat) {
+            match hexadecimal_float_value(&float.lexeme) {
+                Some(value) => Ok(Expression::Scalar(float.span, Number::from_f64(value))),
+                None => Err(ParseError::new(
+                    ParseErrorKind::OverflowInNumberLiteral,
+                    float.span,
+                )),
+            }
         } else if self.match_exact(TokenKind::NaN).is_some() {
             let span = self.last().unwrap().span;
             Ok(Expression::Scalar(span, Number::from_f64(f64::NAN)))
@@ -1359,7 +2793,7 @@ impl<'a> Parser<'a> {
             while self.match_exact(TokenKind::RightBracket).is_none() {
                 self.skip_empty_lines();
 
-                elements.push(self.expression()?);
+                elements.push(self.with_struct_literals(true, Self::expression)?);
 
                 self.skip_empty_lines();
 
@@ -1380,12 +2814,269 @@ impl<'a> Parser<'a> {
         } else if self.match_exact(TokenKind::QuestionMark).is_some() {
             let span = self.last().unwrap().span;
             Ok(Expression::TypedHole(span))
+        } else if self.peek().kind == TokenKind::Identifier
+            && self.peek().lexeme == "trace"
+            && self.tokens[self.current + 1].kind == TokenKind::LeftParen
+        {
+            // `trace` is not a keyword, so that it can still be used as a name
+            let span = self.peek().span;
+            self.advance();
+            let left_paren = self.match_exact(TokenKind::LeftParen).unwrap();
+            let source_start = left_paren.span.end.byte as usize;
+
+            self.skip_empty_lines();
+            let expr = self.expression()?;
+            self.skip_empty_lines();
+
+            let Some(right_paren) = self.match_exact(TokenKind::RightParen) else {
+                return Err(ParseError::new(
+                    ParseErrorKind::MissingClosingParen,
+                    self.peek().span,
+                ));
+            };
+            let source_end = right_paren.span.start.byte as usize;
+
+            let source = self
+                .source
+                .get(source_start..source_end)
+                .unwrap_or_default()
+                .trim()
+                .to_string();
+
+            Ok(Expression::Trace(
+                span.extend(&right_paren.span),
+                source,
+                Box::new(expr),
+            ))
+        } else if self.match_exact(TokenKind::Partial).is_some() {
+            let span = self.last().unwrap().span;
+
+            if self.match_exact(TokenKind::LeftParen).is_none() {
+                return Err(ParseError::new(
+                    ParseErrorKind::ExpectedLeftParenAfterPartial,
+                    self.peek().span,
+                ));
+            }
+            let function_span = self.peek().span;
+            let (mut args, named_args) = self.arguments()?;
+            if args.is_empty() {
+                return Err(ParseError::new(
+                    ParseErrorKind::ExpectedFunctionInPartial,
+                    function_span,
+                ));
+            }
+            let function = args.remove(0);
+
+            Ok(Expression::Partial(
+                span.extend(&self.last().unwrap().span),
+                Box::new(function),
+                args,
+                named_args,
+            ))
+        } else if self.match_exact(TokenKind::AutoDiff).is_some() {
+            let span = self.last().unwrap().span;
+
+            if self.match_exact(TokenKind::LeftParen).is_none() {
+                return Err(ParseError::new(
+                    ParseErrorKind::ExpectedLeftParenAfterAutoDiff,
+                    self.peek().span,
+                ));
+            }
+
+            self.skip_empty_lines();
+            let function = self.expression()?;
+            self.skip_empty_lines();
+
+            let Some(right_paren) = self.match_exact(TokenKind::RightParen) else {
+                return Err(ParseError::new(
+                    ParseErrorKind::MissingClosingParen,
+                    self.peek().span,
+                ));
+            };
+
+            Ok(Expression::AutoDiff(
+                span.extend(&right_paren.span),
+                Box::new(function),
+            ))
+        } else if let Some(token) =
+            self.match_any(&[TokenKind::IsDimensionless, TokenKind::HasDimension])
+        {
+            let span = token.span;
+            let has_dimension = token.kind == TokenKind::HasDimension;
+
+            if self.match_exact(TokenKind::LeftParen).is_none() {
+                return Err(ParseError::new(
+                    ParseErrorKind::ExpectedLeftParenAfterDimensionPredicate(if has_dimension {
+                        "has_dimension"
+                    } else {
+                        "is_dimensionless"
+                    }),
+                    self.peek().span,
+                ));
+            }
+
+            self.skip_empty_lines();
+            let argument = self.expression()?;
+            self.skip_empty_lines();
+
+            let dimension = if has_dimension {
+                if self.match_exact(TokenKind::Comma).is_none() {
+                    return Err(ParseError::new(
+                        ParseErrorKind::ExpectedDimensionInHasDimension,
+                        self.peek().span,
+                    ));
+                }
+                self.skip_empty_lines();
+                let dimension = self.dimension_expression().map_err(|e| {
+                    if e.kind == ParseErrorKind::ExpectedDimensionPrimary {
+                        ParseError::new(ParseErrorKind::ExpectedDimensionInHasDimension, e.span)
+                    } else {
+                        e
+                    }
+                })?;
+                self.skip_empty_lines();
+                Some(dimension)
+            } else {
+                None
+            };
+
+            let Some(right_paren) = self.match_exact(TokenKind::RightParen) else {
+                return Err(ParseError::new(
+                    ParseErrorKind::MissingClosingParen,
+                    self.peek().span,
+                ));
+            };
+
+            Ok(Expression::DimensionPredicate(
+                span.extend(&right_paren.span),
+                Box::new(argument),
+                dimension,
+            ))
+        } else if self.match_exact(TokenKind::For).is_some() {
+            let span = self.last().unwrap().span;
+
+            let Some(variable) = self.match_exact(TokenKind::Identifier) else {
+                return Err(ParseError::new(
+                    ParseErrorKind::ExpectedLoopVariable,
+                    self.peek().span,
+                ));
+            };
+
+            // `in` is not a keyword, as it is also the symbol for inches
+            if !(self.peek().kind == TokenKind::Identifier && self.peek().lexeme == "in") {
+                return Err(ParseError::new(
+                    ParseErrorKind::ExpectedInAfterLoopVariable,
+                    self.peek().span,
+                ));
+            }
+            self.advance();
+
+            let list = self.with_struct_literals(false, Self::expression)?;
+
+            let accumulator = if self.match_exact(TokenKind::With).is_some() {
+                Some(self.loop_accumulator()?)
+            } else {
+                None
+            };
+
+            let body = self.loop_body()?;
+
+            Ok(Expression::For {
+                full_span: span.extend(&self.last().unwrap().span),
+                variable: (variable.span, variable.lexeme.as_str().into()),
+                list: Box::new(list),
+                accumulator,
+                body: Box::new(body),
+            })
+        } else if self.match_exact(TokenKind::While).is_some() {
+            let span = self.last().unwrap().span;
+
+            let condition = self.with_struct_literals(false, Self::expression)?;
+
+            if self.match_exact(TokenKind::With).is_none() {
+                return Err(ParseError::new(
+                    ParseErrorKind::ExpectedWithInWhileLoop,
+                    self.peek().span,
+                ));
+            }
+            let accumulator = self.loop_accumulator()?;
+
+            let body = self.loop_body()?;
+
+            Ok(Expression::While {
+                full_span: span.extend(&self.last().unwrap().span),
+                condition: Box::new(condition),
+                accumulator,
+                body: Box::new(body),
+            })
+        } else if self.match_exact(TokenKind::Try).is_some() {
+            let span = self.last().unwrap().span;
+
+            let expr = self.braced_expression(
+                ParseErrorKind::ExpectedLeftCurlyInTryCatch,
+                ParseErrorKind::ExpectedRightCurlyInTryCatch,
+            )?;
+
+            self.skip_empty_lines();
+            if self.match_exact(TokenKind::Catch).is_none() {
+                return Err(ParseError::new(
+                    ParseErrorKind::ExpectedCatchAfterTry,
+                    self.peek().span,
+                ));
+            }
+
+            let message = self
+                .match_exact(TokenKind::Identifier)
+                .map(|name| (name.span, name.lexeme.as_str().into()));
+
+            let fallback = self.braced_expression(
+                ParseErrorKind::ExpectedLeftCurlyInTryCatch,
+                ParseErrorKind::ExpectedRightCurlyInTryCatch,
+            )?;
+
+            Ok(Expression::TryCatch {
+                full_span: span.extend(&self.last().unwrap().span),
+                expr: Box::new(expr),
+                message,
+                fallback: Box::new(fallback),
+            })
         } else if let Some(identifier) = self.match_exact(TokenKind::Identifier) {
             let span = self.last().unwrap().span;
 
-            if self.match_exact(TokenKind::LeftCurly).is_some() {
+            if self.struct_literals_allowed && self.match_exact(TokenKind::LeftCurly).is_some() {
                 self.skip_empty_lines();
 
+                let base = if self.is_struct_update() {
+                    let base = self.expression()?;
+
+                    self.skip_empty_lines();
+
+                    if self.match_exact(TokenKind::With).is_none() {
+                        return Err(ParseError {
+                            kind: ParseErrorKind::ExpectedWithInStructUpdate,
+                            span: self.peek().span,
+                        });
+                    }
+
+                    self.skip_empty_lines();
+
+                    Some(Box::new(base))
+                } else {
+                    None
+                };
+
+                let (separator, separator_error) = if base.is_some() {
+                    (
+                        TokenKind::Equal,
+                        ParseErrorKind::ExpectedEqualAfterFieldNameInStructUpdate,
+                    )
+                } else {
+                    (
+                        TokenKind::Colon,
+                        ParseErrorKind::ExpectedColonAfterFieldName,
+                    )
+                };
+
                 let mut fields = vec![];
                 while self.match_exact(TokenKind::RightCurly).is_none() {
                     self.skip_empty_lines();
@@ -1399,9 +3090,9 @@ impl<'a> Parser<'a> {
 
                     self.skip_empty_lines();
 
-                    if self.match_exact(TokenKind::Colon).is_none() {
+                    if self.match_exact(separator).is_none() {
                         return Err(ParseError {
-                            kind: ParseErrorKind::ExpectedColonAfterFieldName,
+                            kind: separator_error.clone(),
                             span: self.peek().span,
                         });
                     }
@@ -1423,7 +3114,7 @@ impl<'a> Parser<'a> {
                         });
                     }
 
-                    fields.push((field_name.span, field_name.lexeme.to_owned(), expr));
+                    fields.push((field_name.span, field_name.lexeme.as_str().into(), expr));
                 }
 
                 let full_span = span.extend(&self.last().unwrap().span);
@@ -1431,12 +3122,16 @@ impl<'a> Parser<'a> {
                 return Ok(Expression::InstantiateStruct {
                     full_span,
                     ident_span: span,
-                    name: identifier.lexeme.clone(),
+                    name: identifier.lexeme.as_str().into(),
+                    base,
                     fields,
                 });
             }
 
-            Ok(Expression::Identifier(span, identifier.lexeme.clone()))
+            Ok(Expression::Identifier(
+                span,
+                identifier.lexeme.as_str().into(),
+            ))
         } else if let Some(inner) = self.match_any(&[TokenKind::True, TokenKind::False]) {
             Ok(Expression::Boolean(
                 inner.span,
@@ -1484,7 +3179,7 @@ impl<'a> Parser<'a> {
 
             Ok(Expression::String(span_full_string, parts))
         } else if self.match_exact(TokenKind::LeftParen).is_some() {
-            let inner = self.expression()?;
+            let inner = self.with_struct_literals(true, Self::expression)?;
 
             if self.match_exact(TokenKind::RightParen).is_none() {
                 return Err(ParseError::new(
@@ -1529,6 +3224,66 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parses `acc = init` after the `with` in a loop header.
+    fn loop_accumulator(&mut self) -> Result<Accumulator> {
+        let Some(name) = self.match_exact(TokenKind::Identifier) else {
+            return Err(ParseError::new(
+                ParseErrorKind::ExpectedAccumulatorDefinition,
+                self.peek().span,
+            ));
+        };
+
+        if self.match_exact(TokenKind::Equal).is_none() {
+            return Err(ParseError::new(
+                ParseErrorKind::ExpectedAccumulatorDefinition,
+                self.peek().span,
+            ));
+        }
+
+        let init = self.with_struct_literals(false, Self::expression)?;
+
+        Ok((name.span, name.lexeme.as_str().into(), Box::new(init)))
+    }
+
+    /// Parses the body of a loop, a single expression in curly braces.
+    fn loop_body(&mut self) -> Result<Expression> {
+        self.braced_expression(
+            ParseErrorKind::ExpectedLeftCurlyBeforeLoopBody,
+            ParseErrorKind::ExpectedRightCurlyAfterLoopBody,
+        )
+    }
+
+    fn braced_expression(
+        &mut self,
+        missing_left_curly: ParseErrorKind,
+        missing_right_curly: ParseErrorKind,
+    ) -> Result<Expression> {
+        if self.match_exact(TokenKind::LeftCurly).is_none() {
+            return Err(ParseError::new(missing_left_curly, self.peek().span));
+        }
+
+        self.skip_empty_lines();
+        let expr = self.with_struct_literals(true, Self::expression)?;
+        self.skip_empty_lines();
+
+        if self.match_exact(TokenKind::RightCurly).is_none() {
+            return Err(ParseError::new(missing_right_curly, self.peek().span));
+        }
+
+        Ok(expr)
+    }
+
+    fn with_struct_literals<T>(
+        &mut self,
+        allowed: bool,
+        parse: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        let previous = std::mem::replace(&mut self.struct_literals_allowed, allowed);
+        let result = parse(self);
+        self.struct_literals_allowed = previous;
+        result
+    }
+
     fn interpolation(&mut self, parts: &mut Vec<StringPart>, token: &Token) -> Result<()> {
         parts.push(StringPart::Fixed(strip_and_escape(&token.lexeme)));
 
@@ -1549,6 +3304,21 @@ impl<'a> Parser<'a> {
 
     /// Returns true iff the upcoming token indicates the beginning of a 'power'
     /// expression (which needs to start with a 'primary' expression).
+    /// Warns if an integer literal is not exactly representable as a floating
+    /// point number. `digits` is the decimal representation of the integer.
+    fn check_integer_literal(&mut self, span: Span, digits: &str, value: f64) {
+        // Integers of this size are always represented exactly
+        if value.abs() < 2f64.powi(53) {
+            return;
+        }
+
+        let nearest = format!("{value:.0}");
+        if nearest != digits.trim_start_matches('0') {
+            self.warnings
+                .push(ParseWarning::InexactIntegerLiteral(span, nearest));
+        }
+    }
+
     fn next_token_could_start_power_expression(&self) -> bool {
         // This function needs to be kept in sync with `primary` above.
 
@@ -1568,6 +3338,8 @@ impl<'a> Parser<'a> {
             Ok(TypeAnnotation::String(token.span))
         } else if let Some(token) = self.match_exact(TokenKind::DateTime) {
             Ok(TypeAnnotation::DateTime(token.span))
+        } else if let Some(token) = self.match_exact(TokenKind::Dynamic) {
+            Ok(TypeAnnotation::Dynamic(token.span))
         } else if self.match_exact(TokenKind::CapitalFn).is_some() {
             let span = self.last().unwrap().span;
             if self.match_exact(TokenKind::LeftBracket).is_none() {
@@ -1585,9 +3357,9 @@ impl<'a> Parser<'a> {
 
             let mut params = vec![];
             if self.peek().kind != TokenKind::RightParen {
-                params.push(self.type_annotation()?);
+                params.push(self.nested(Self::type_annotation)?);
                 while self.match_exact(TokenKind::Comma).is_some() {
-                    params.push(self.type_annotation()?);
+                    params.push(self.nested(Self::type_annotation)?);
                 }
             }
 
@@ -1605,7 +3377,7 @@ impl<'a> Parser<'a> {
                 ));
             }
 
-            let return_type = self.type_annotation()?;
+            let return_type = self.nested(Self::type_annotation)?;
 
             if self.match_exact(TokenKind::RightBracket).is_none() {
                 return Err(ParseError::new(
@@ -1627,7 +3399,7 @@ impl<'a> Parser<'a> {
                 ));
             }
 
-            let element_type = self.type_annotation()?;
+            let element_type = self.nested(Self::type_annotation)?;
 
             if self.match_exact(TokenKind::GreaterThan).is_none() {
                 return Err(ParseError::new(
@@ -1645,12 +3417,13 @@ impl<'a> Parser<'a> {
     }
 
     fn dimension_expression(&mut self) -> Result<TypeExpression> {
-        self.dimension_factor()
+        self.nested(Self::dimension_factor)
     }
 
     fn dimension_factor(&mut self) -> Result<TypeExpression> {
         let mut expr = self.dimension_power()?;
         while let Some(operator_token) = self.match_any(&[TokenKind::Multiply, TokenKind::Divide]) {
+            self.chained()?;
             let span = self.last().unwrap().span;
             let rhs = self.dimension_power()?;
 
@@ -1708,17 +3481,17 @@ impl<'a> Parser<'a> {
             ))
         } else if self.match_exact(TokenKind::Minus).is_some() {
             let span = self.last().unwrap().span;
-            let (span_inner, exponent) = self.dimension_exponent()?;
+            let (span_inner, exponent) = self.nested(Self::dimension_exponent)?;
             Ok((span.extend(&span_inner), -exponent))
         } else if self.match_exact(TokenKind::LeftParen).is_some() {
             let mut span = self.last().unwrap().span;
-            let (span_inner, exponent) = self.dimension_exponent()?;
+            let (span_inner, exponent) = self.nested(Self::dimension_exponent)?;
             span = span.extend(&span_inner);
             if self.match_exact(TokenKind::RightParen).is_some() {
                 span = span.extend(&self.last().unwrap().span);
                 Ok((span, exponent))
             } else if self.match_exact(TokenKind::Divide).is_some() {
-                let (span_rhs, rhs) = self.dimension_exponent()?;
+                let (span_rhs, rhs) = self.nested(Self::dimension_exponent)?;
                 span = span.extend(&span_rhs);
                 if rhs == Rational::zero() {
                     Err(ParseError::new(
@@ -1766,7 +3539,10 @@ impl<'a> Parser<'a> {
                 ));
             }
             let span = self.last().unwrap().span;
-            Ok(TypeExpression::TypeIdentifier(span, token.lexeme.clone()))
+            Ok(TypeExpression::TypeIdentifier(
+                span,
+                token.lexeme.as_str().into(),
+            ))
         } else if let Some(number) = self.match_exact(TokenKind::Number) {
             let span = self.last().unwrap().span;
             if number.lexeme != "1" {
@@ -1854,6 +3630,39 @@ impl<'a> Parser<'a> {
     }
 }
 
+/// All identifiers in the equation of a formula, in the order of their first
+/// occurrence. Names of called functions are not included.
+fn identifiers_in_equation(lhs: &Expression, rhs: &Expression) -> Vec<(Span, Symbol)> {
+    let callees = RefCell::new(vec![]);
+    let identifiers = RefCell::new(vec![]);
+    for side in [lhs, rhs] {
+        side.contains(&|expr| {
+            match expr {
+                Expression::FunctionCall(_, _, callable, ..) => {
+                    if let Expression::Identifier(span, _) = callable.as_ref() {
+                        callees.borrow_mut().push(*span);
+                    }
+                }
+                Expression::Identifier(span, name) if !callees.borrow().contains(span) => {
+                    identifiers.borrow_mut().push((*span, *name));
+                }
+                _ => {}
+            }
+            false
+        });
+    }
+
+    let mut identifiers = identifiers.into_inner();
+    identifiers.sort_by_key(|(span, _)| span.start.byte);
+    let mut seen = vec![];
+    identifiers.retain(|(_, name)| {
+        let first = !seen.contains(name);
+        seen.push(*name);
+        first
+    });
+    identifiers
+}
+
 fn strip_and_escape(s: &str) -> String {
     let trimmed = &s[1..(s.len() - 1)];
 
@@ -1895,22 +3704,89 @@ fn strip_and_escape(s: &str) -> String {
 /// will try to recover from the error and parse as many statements as possible
 /// while stacking all the errors in a `Vec`. At the end, it returns the complete
 /// list of statements parsed + the list of errors accumulated.
+#[cfg(test)]
 pub fn parse(input: &str, code_source_id: usize) -> ParseResult {
-    use crate::tokenizer::tokenize;
+    parse_with_options(input, code_source_id, ParserOptions::default(), &mut vec![])
+}
 
-    let tokens = tokenize(input, code_source_id)
+/// Like [`parse`], but with the given [`ParserOptions`]. All warnings are added
+/// to `warnings`.
+pub(crate) fn parse_with_options(
+    input: &str,
+    code_source_id: usize,
+    options: ParserOptions,
+    warnings: &mut Vec<ParseWarning>,
+) -> ParseResult {
+    use crate::tokenizer::tokenize_with_digit_comma;
+
+    let tokens = tokenize_with_digit_comma(input, code_source_id, options.digit_comma)
         .map_err(|TokenizerError { kind, span }| {
             ParseError::new(ParseErrorKind::TokenizerError(kind), span)
         })
         .map_err(|e| (Vec::new(), vec![e]))?;
-    let mut parser = Parser::new(&tokens);
-    parser.parse()
+    let mut parser = Parser::new(&tokens, input);
+    parser.max_depth = options.max_nesting_depth;
+    let result = parser.parse();
+    warnings.append(&mut parser.warnings);
+    result
+}
+
+/// The value of a decimal number literal, together with the uncertainty if the
+/// literal uses the `6.67430(15)e-11` shorthand. Number literals can contain a
+/// decimal comma, see `DigitComma`.
+fn decimal_literal_value(lexeme: &str) -> (f64, Option<f64>) {
+    let lexeme = lexeme.replace('_', "").replace(',', ".");
+    let Some((mantissa, rest)) = lexeme.split_once('(') else {
+        return (lexeme.parse().unwrap(), None);
+    };
+    let (digits, exponent) = rest.split_once(')').unwrap();
+
+    let value = format!("{mantissa}{exponent}").parse().unwrap();
+
+    // The uncertainty refers to the last digits of the mantissa
+    let num_fractional_digits = mantissa.split_once('.').map_or(0, |(_, f)| f.len()) as i64;
+    let exponent = exponent
+        .get(1..)
+        .map_or(0, |e| e.parse::<i64>().unwrap_or(0));
+    let uncertainty = format!("{digits}e{}", exponent - num_fractional_digits)
+        .parse()
+        .unwrap();
+
+    (value, Some(uncertainty))
+}
+
+/// The value of a hexadecimal floating point literal like `0x1.8p3`, or `None`
+/// if it is out of range.
+fn hexadecimal_float_value(lexeme: &str) -> Option<f64> {
+    let (mantissa, exponent) = lexeme[2..].split_once(['p', 'P'])?;
+    let (integer_part, fractional_part) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let fractional_part = fractional_part.trim_end_matches('0');
+
+    let digits = format!("{integer_part}{fractional_part}").replace('_', "");
+    let mantissa = u128::from_str_radix(&digits, 16).ok()?;
+    let exponent = exponent
+        .parse::<i32>()
+        .ok()?
+        .checked_sub(4 * fractional_part.len() as i32)?;
+
+    // Scale in steps, so that intermediate powers of two do not overflow or
+    // underflow while the result is still in range
+    let mut value = mantissa as f64;
+    let mut exponent = exponent;
+    while exponent.abs() > 1000 {
+        let step = exponent.signum() * 1000;
+        value *= 2f64.powi(step);
+        exponent -= step;
+    }
+    value *= 2f64.powi(exponent);
+
+    value.is_finite().then_some(value)
 }
 
 #[cfg(test)]
 pub fn parse_dexpr(input: &str) -> TypeExpression {
     let tokens = crate::tokenizer::tokenize(input, 0).expect("No tokenizer errors in tests");
-    let mut parser = crate::parser::Parser::new(&tokens);
+    let mut parser = crate::parser::Parser::new(&tokens, input);
     let expr = parser
         .dimension_expression()
         .expect("No parser errors in tests");
@@ -1925,8 +3801,8 @@ mod tests {
 
     use super::*;
     use crate::ast::{
-        binop, boolean, conditional, factorial, identifier, list, logical_neg, negate, scalar,
-        struct_, ReplaceSpans,
+        binop, boolean, chained_comparison, conditional, conversion, factorial, identifier, list,
+        logical_neg, negate, scalar, struct_, ReplaceSpans,
     };
 
     #[track_caller]
@@ -2116,7 +3992,13 @@ mod tests {
 
         should_fail(&["2e1.5", "e.2e3e"]);
 
-        parse_as_expression(&["1e", "1.0e"], binop!(scalar!(1.0), Mul, identifier!("e")));
+        should_fail(&["1e", "1.0e", "1e_3", "1_e3"]);
+
+        parse_as_expression(
+            &["1 e", "1.0 e"],
+            binop!(scalar!(1.0), Mul, identifier!("e")),
+        );
+        parse_as_expression(&["2e3 m"], binop!(scalar!(2.0e3), Mul, identifier!("m")));
         parse_as_expression(&["1ee"], binop!(scalar!(1.0), Mul, identifier!("ee")));
         parse_as_expression(&["1eV"], binop!(scalar!(1.0), Mul, identifier!("eV")));
         parse_as_expression(&["1erg"], binop!(scalar!(1.0), Mul, identifier!("erg")));
@@ -2127,23 +4009,156 @@ mod tests {
         parse_as_expression(&["0x6A", "0x6a", "0b1101010", "0o152"], scalar!(106.0));
         parse_as_expression(&["0xFF", "0xff", "0b11111111", "0o377"], scalar!(255.0));
 
-        parse_as_expression(&["-0x3", "-0b11", "-0o3"], negate!(scalar!(3.0)));
+        parse_as_expression(&["-0x3", "-0b11", "-0o3"], negate!(scalar!(3.0)));
+
+        should_fail(&["0x"]);
+        should_fail(&["0o"]);
+        should_fail(&["0b"]);
+        should_fail(&["0xG"]);
+        should_fail(&["0oG"]);
+        should_fail(&["0oA"]);
+        should_fail(&["0o8"]);
+        should_fail(&["0bF"]);
+        should_fail(&["0b2"]);
+        should_fail(&["0xABCDU"]);
+        should_fail(&["0o12348"]);
+        should_fail(&["0b10102"]);
+
+        // Only hexadecimal floats (with a binary exponent) are supported
+        should_fail(&["0x1.2", "0b1.0", "0o1.0", "0x.1", "0b.0", "0o.1"]);
+    }
+
+    #[test]
+    fn hexadecimal_floats() {
+        parse_as_expression(
+            &["0x1.8p3", "0x1.8P3", "0x1.8p+3", "0xc.0p0"],
+            scalar!(12.0),
+        );
+        parse_as_expression(&["0x1p-2", "0x4p-4", "0x0.4p0"], scalar!(0.25));
+        parse_as_expression(&["0xA.8p1"], scalar!(21.0));
+        parse_as_expression(&["0x1.921fb54442d18p1"], scalar!(std::f64::consts::PI));
+        parse_as_expression(&["0x1.fffffffffffffp1023"], scalar!(f64::MAX));
+        parse_as_expression(&["0x1p-1074"], scalar!(5e-324));
+        parse_as_expression(&["-0x1p1"], negate!(scalar!(2.0)));
+
+        should_fail_with(
+            &["0x1.8", "0x1.8 m"],
+            ParseErrorKind::TokenizerError(TokenizerErrorKind::MissingBinaryExponent),
+        );
+        should_fail_with(
+            &["0x1p", "0x1p-", "0x1.8pA"],
+            ParseErrorKind::TokenizerError(TokenizerErrorKind::MissingExponent),
+        );
+        should_fail_with(&["0x1p1024"], ParseErrorKind::OverflowInNumberLiteral);
+    }
+
+    #[test]
+    fn inexact_integer_literals() {
+        let warnings = |input: &str| {
+            let mut warnings = vec![];
+            parse_with_options(input, 0, ParserOptions::default(), &mut warnings)
+                .expect("parse error");
+            warnings
+        };
+
+        // 2^53 + 1 is the smallest positive integer that is not representable
+        for (input, literal_bytes) in [
+            ("1 + 9007199254740993", 4..20),
+            ("9_007_199_254_740_993 m", 0..21),
+        ] {
+            let [ParseWarning::InexactIntegerLiteral(span, nearest)] = &warnings(input)[..] else {
+                panic!("Expected a single warning for '{input}'");
+            };
+            assert_eq!(
+                span.start.byte as usize..span.end.byte as usize,
+                literal_bytes
+            );
+            assert_eq!(nearest, "9007199254740992");
+        }
+        assert_eq!(warnings("0x20000000000001").len(), 1);
+        assert_eq!(
+            warnings("0b100000000000000000000000000000000000000000000000000001").len(),
+            1
+        );
+        assert_eq!(warnings("123456789012345678901234567890").len(), 1);
+
+        assert!(warnings("9007199254740992").is_empty());
+        assert!(warnings("9007199254740994").is_empty());
+        assert!(warnings("0x20000000000000").is_empty());
+        assert!(warnings("1000000000000000000000").is_empty());
+        assert!(warnings("9007199254740993.0").is_empty());
+        assert!(warnings("1e30").is_empty());
+        assert!(warnings("0").is_empty());
+
+        // Conditions are only checked once, even though they are parsed twice
+        assert_eq!(warnings("if 9007199254740993 > 0 then 1 else 2").len(), 1);
+    }
+
+    #[test]
+    fn uncertainty_shorthand() {
+        let warnings = |input: &str| {
+            let mut warnings = vec![];
+            parse_with_options(
+                input,
+                0,
+                ParserOptions {
+                    digit_comma: DigitComma::DecimalMark,
+                    ..Default::default()
+                },
+                &mut warnings,
+            )
+            .expect("parse error");
+            warnings
+        };
+
+        for (input, value, uncertainty) in [
+            ("6.67430(15)e-11", 6.67430e-11, 1.5e-15),
+            ("1.23(4)", 1.23, 0.04),
+            ("1_000.5(12)", 1000.5, 1.2),
+            ("1,5(2)E3", 1.5e3, 200.0),
+            (".25(5)", 0.25, 0.05),
+        ] {
+            let (central, Some(extracted)) =
+                decimal_literal_value(input.split_whitespace().next().unwrap())
+            else {
+                panic!("Expected an uncertainty for '{input}'");
+            };
+            assert_eq!(central, value);
+            assert!(((extracted - uncertainty) / uncertainty).abs() < 1e-12);
+
+            let [ParseWarning::DiscardedUncertainty(span, printed)] = &warnings(input)[..] else {
+                panic!("Expected a single warning for '{input}'");
+            };
+            assert_eq!(span.end.byte as usize, input.len());
+            assert_eq!(printed, &Number::from_f64(uncertainty).pretty_print());
+        }
 
-        should_fail(&["0x"]);
-        should_fail(&["0o"]);
-        should_fail(&["0b"]);
-        should_fail(&["0xG"]);
-        should_fail(&["0oG"]);
-        should_fail(&["0oA"]);
-        should_fail(&["0o8"]);
-        should_fail(&["0bF"]);
-        should_fail(&["0b2"]);
-        should_fail(&["0xABCDU"]);
-        should_fail(&["0o12348"]);
-        should_fail(&["0b10102"]);
+        parse_as_expression(&["6.67430(15)e-11"], scalar!(6.67430e-11));
+        parse_as_expression(&["1.5(2) m"], binop!(scalar!(1.5), Mul, identifier!("m")));
 
-        // Until we support hexadecimal float notation
-        should_fail(&["0x1.2", "0b1.0", "0o1.0", "0x.1", "0b.0", "0o.1"]);
+        // Not a shorthand
+        let call = |callee, arg| {
+            Expression::FunctionCall(
+                Span::dummy(),
+                Span::dummy(),
+                Box::new(callee),
+                vec![arg],
+                vec![],
+                CallSyntax::Regular,
+            )
+        };
+        parse_as_expression(&["1.5 (2)"], call(scalar!(1.5), scalar!(2.0)));
+        parse_as_expression(&["3(4)"], call(scalar!(3.0), scalar!(4.0)));
+        parse_as_expression(
+            &["f(4)e"],
+            binop!(call(identifier!("f"), scalar!(4.0)), Mul, identifier!("e")),
+        );
+        assert!(warnings("f(4)e + 1.5 (2) + 3(4)").is_empty());
+
+        should_fail_with(
+            &["@uncertainty(1.5(2)) let x = 1"],
+            ParseErrorKind::ExpectedUncertaintyValue,
+        );
     }
 
     #[test]
@@ -2320,19 +4335,54 @@ mod tests {
     #[test]
     fn conversion() {
         parse_as_expression(
-            &["1->2", "1→2"],
-            binop!(scalar!(1.0), ConvertTo, scalar!(2.0)),
+            &["1->2", "1→2", "1 to 2"],
+            conversion!(scalar!(1.0), scalar!(2.0)),
         );
 
         // Conversion is left-associative
         parse_as_expression(
             &["1→2→3"],
-            binop!(
-                binop!(scalar!(1.0), ConvertTo, scalar!(2.0)),
-                ConvertTo,
-                scalar!(3.0)
+            conversion!(conversion!(scalar!(1.0), scalar!(2.0)), scalar!(3.0)),
+        );
+
+        // Conversion has the lowest precedence
+        parse_as_expression(
+            &["1 + 2 -> 3 + 4"],
+            conversion!(
+                binop!(scalar!(1.0), Add, scalar!(2.0)),
+                binop!(scalar!(3.0), Add, scalar!(4.0))
             ),
         );
+        parse_as_expression(
+            &["1 + (2 -> 3)"],
+            binop!(scalar!(1.0), Add, conversion!(scalar!(2.0), scalar!(3.0))),
+        );
+
+        // Parentheses around the source are recorded
+        parse_as_expression(
+            &["(1 + 2) -> 3"],
+            Expression::Conversion {
+                source: Box::new(binop!(scalar!(1.0), Add, scalar!(2.0))),
+                target: ConversionTarget::Expression(Box::new(scalar!(3.0))),
+                span_op: Span::dummy(),
+                parenthesized_source: true,
+            },
+        );
+        parse_as_expression(
+            &["(1) + (2) -> 3"],
+            conversion!(binop!(scalar!(1.0), Add, scalar!(2.0)), scalar!(3.0)),
+        );
+
+        // A list of units as the target
+        parse_as_expression(
+            &["1 -> [2, 3]"],
+            Expression::Conversion {
+                source: Box::new(scalar!(1.0)),
+                target: ConversionTarget::UnitList(Span::dummy(), vec![scalar!(2.0), scalar!(3.0)]),
+                span_op: Span::dummy(),
+                parenthesized_source: false,
+            },
+        );
 
         should_fail(&["1 - > 2", "1 -> -> 2"]);
     }
@@ -2342,8 +4392,7 @@ mod tests {
         parse_as(
             &["let foo = 1", "let foo=1"],
             Statement::DefineVariable(DefineVariable {
-                identifier_span: Span::dummy(),
-                identifier: "foo".into(),
+                pattern: Pattern::Identifier(Span::dummy(), "foo".into()),
                 expr: scalar!(1.0),
                 type_annotation: None,
                 decorators: Vec::new(),
@@ -2353,8 +4402,7 @@ mod tests {
         parse_as(
             &["let x: Length = 1 * meter"],
             Statement::DefineVariable(DefineVariable {
-                identifier_span: Span::dummy(),
-                identifier: "x".into(),
+                pattern: Pattern::Identifier(Span::dummy(), "x".into()),
                 expr: binop!(scalar!(1.0), Mul, identifier!("meter")),
                 type_annotation: Some(TypeAnnotation::TypeExpression(
                     TypeExpression::TypeIdentifier(Span::dummy(), "Length".into()),
@@ -2367,8 +4415,7 @@ mod tests {
         parse_as(
             &["@name(\"myvar\") @aliases(foo, bar) let x: Length = 1 * meter"],
             Statement::DefineVariable(DefineVariable {
-                identifier_span: Span::dummy(),
-                identifier: "x".into(),
+                pattern: Pattern::Identifier(Span::dummy(), "x".into()),
                 expr: binop!(scalar!(1.0), Mul, identifier!("meter")),
                 type_annotation: Some(TypeAnnotation::TypeExpression(
                     TypeExpression::TypeIdentifier(Span::dummy(), "Length".into()),
@@ -2403,6 +4450,203 @@ mod tests {
         );
     }
 
+    #[test]
+    fn deprecated_decorator() {
+        parse_as(
+            &["@deprecated(\"use newton instead\") unit kilopond = 9.80665 N"],
+            Statement::DefineDerivedUnit {
+                identifier_span: Span::dummy(),
+                identifier: "kilopond".into(),
+                expr: binop!(scalar!(9.80665), Mul, identifier!("N")),
+                type_annotation_span: None,
+                type_annotation: None,
+                decorators: vec![Decorator::Deprecated("use newton instead".into())],
+            },
+        );
+
+        should_fail_with(
+            &["@deprecated unit kilopond = 9.80665 N"],
+            ParseErrorKind::ExpectedLeftParenAfterDecorator,
+        );
+        should_fail_with(
+            &["@deprecated(kilopond) unit kilopond = 9.80665 N"],
+            ParseErrorKind::ExpectedString,
+        );
+    }
+
+    #[test]
+    fn ucum_decorator() {
+        parse_as(
+            &["@ucum(\"mm[Hg]\") unit mmHg = 133.322387415 Pa"],
+            Statement::DefineDerivedUnit {
+                identifier_span: Span::dummy(),
+                identifier: "mmHg".into(),
+                expr: binop!(scalar!(133.322387415), Mul, identifier!("Pa")),
+                type_annotation_span: None,
+                type_annotation: None,
+                decorators: vec![Decorator::Ucum("mm[Hg]".into())],
+            },
+        );
+
+        should_fail_with(
+            &["@ucum(mmHg) unit mmHg = 133.322387415 Pa"],
+            ParseErrorKind::ExpectedString,
+        );
+    }
+
+    #[test]
+    fn uncertainty_decorator() {
+        parse_as(
+            &[
+                "@uncertainty(0.000_15e-11) let G = 6.674_30e-11",
+                "@uncertainty(1.5e-15)\nlet G = 6.674_30e-11",
+            ],
+            Statement::DefineVariable(DefineVariable {
+                pattern: Pattern::Identifier(Span::dummy(), "G".into()),
+                expr: scalar!(6.674_30e-11),
+                type_annotation: None,
+                decorators: vec![Decorator::Uncertainty(Number::from_f64(1.5e-15))],
+            }),
+        );
+
+        should_fail_with(
+            &["@uncertainty let G = 1"],
+            ParseErrorKind::ExpectedLeftParenAfterDecorator,
+        );
+        should_fail_with(
+            &["@uncertainty(small) let G = 1", "@uncertainty() let G = 1"],
+            ParseErrorKind::ExpectedUncertaintyValue,
+        );
+        should_fail_with(
+            &[
+                "@uncertainty(1) unit foo = 2 m",
+                "@uncertainty(1) fn foo() = 1",
+            ],
+            ParseErrorKind::UncertaintyDecoratorUsedOnNonVariable,
+        );
+    }
+
+    #[test]
+    fn dimension_decorator() {
+        parse_as(
+            &[
+                "@dimension(Gizmo) unit widget",
+                "@dimension(Gizmo)\nunit widget",
+            ],
+            Statement::DefineBaseUnit(
+                Span::dummy(),
+                "widget".into(),
+                None,
+                vec![Decorator::Dimension("Gizmo".into())],
+            ),
+        );
+
+        should_fail_with(
+            &["@dimension unit widget"],
+            ParseErrorKind::ExpectedLeftParenAfterDecorator,
+        );
+        should_fail_with(
+            &[
+                "@dimension(\"Gizmo\") unit widget",
+                "@dimension() unit widget",
+            ],
+            ParseErrorKind::ExpectedDimensionNameInDecorator,
+        );
+        should_fail_with(
+            &[
+                "@dimension(Gizmo) unit widget: Length",
+                "@dimension(Gizmo) unit widget = 2 meter",
+                "@dimension(Gizmo) let widget = 2",
+                "@dimension(Gizmo) fn widget() = 2",
+            ],
+            ParseErrorKind::DimensionDecoratorUsedOnUnsuitableKind,
+        );
+    }
+
+    #[test]
+    fn destructuring_definition() {
+        let identifier = |name: &str| Pattern::Identifier(Span::dummy(), name.into());
+
+        parse_as(
+            &[
+                "let {x, y: width} = p",
+                "let { x , y : width , } = p",
+                "let {\n  x,\n  y: width\n} = p",
+            ],
+            Statement::DefineVariable(DefineVariable {
+                pattern: Pattern::Struct(
+                    Span::dummy(),
+                    vec![
+                        (Span::dummy(), "x".into(), identifier("x")),
+                        (Span::dummy(), "y".into(), identifier("width")),
+                    ],
+                ),
+                expr: identifier!("p"),
+                type_annotation: None,
+                decorators: Vec::new(),
+            }),
+        );
+
+        parse_as(
+            &["let [a, b, c] = xs", "let [a, b, c,] = xs"],
+            Statement::DefineVariable(DefineVariable {
+                pattern: Pattern::List(
+                    Span::dummy(),
+                    vec![identifier("a"), identifier("b"), identifier("c")],
+                ),
+                expr: identifier!("xs"),
+                type_annotation: None,
+                decorators: Vec::new(),
+            }),
+        );
+
+        parse_as(
+            &["let [{x}, [a, b]]: List<Foo> = xs"],
+            Statement::DefineVariable(DefineVariable {
+                pattern: Pattern::List(
+                    Span::dummy(),
+                    vec![
+                        Pattern::Struct(
+                            Span::dummy(),
+                            vec![(Span::dummy(), "x".into(), identifier("x"))],
+                        ),
+                        Pattern::List(Span::dummy(), vec![identifier("a"), identifier("b")]),
+                    ],
+                ),
+                expr: identifier!("xs"),
+                type_annotation: Some(TypeAnnotation::List(
+                    Span::dummy(),
+                    Box::new(TypeAnnotation::TypeExpression(
+                        TypeExpression::TypeIdentifier(Span::dummy(), "Foo".into()),
+                    )),
+                )),
+                decorators: Vec::new(),
+            }),
+        );
+
+        should_fail_with(
+            &["let {2} = p", "let {x, [a]} = p"],
+            ParseErrorKind::ExpectedFieldNameInStruct,
+        );
+        should_fail_with(
+            &["let {x y} = p", "let {x: y: z} = p"],
+            ParseErrorKind::ExpectedCommaOrRightCurlyInStructFieldList,
+        );
+        should_fail_with(
+            &["let [a b] = xs"],
+            ParseErrorKind::ExpectedCommaOrRightBracketInList,
+        );
+        should_fail_with(
+            &["let [a, 2] = xs", "let {x: 2} = p"],
+            ParseErrorKind::ExpectedPattern,
+        );
+        should_fail_with(
+            &["@name(\"Point\") let {x, y} = p"],
+            ParseErrorKind::DecoratorsUsedOnDestructuringDefinition,
+        );
+        should_fail(&["let {x, y} p", "let [a, b]", "let [a, b = xs"]);
+    }
+
     #[test]
     fn dimension_definition() {
         parse_as(
@@ -2531,6 +4775,7 @@ mod tests {
                 parameters: vec![],
                 body: Some(scalar!(1.0)),
                 local_variables: vec![],
+                local_units: vec![],
                 return_type_annotation: None,
                 decorators: vec![],
             },
@@ -2545,6 +4790,7 @@ mod tests {
                 parameters: vec![],
                 body: Some(scalar!(1.0)),
                 local_variables: vec![],
+                local_units: vec![],
                 return_type_annotation: Some(TypeAnnotation::TypeExpression(
                     TypeExpression::TypeIdentifier(Span::dummy(), "Scalar".into()),
                 )),
@@ -2558,9 +4804,10 @@ mod tests {
                 function_name_span: Span::dummy(),
                 function_name: "foo".into(),
                 type_parameters: vec![],
-                parameters: vec![(Span::dummy(), "x".into(), None)],
+                parameters: vec![(Span::dummy(), "x".into(), None, None)],
                 body: Some(scalar!(1.0)),
                 local_variables: vec![],
+                local_units: vec![],
                 return_type_annotation: None,
                 decorators: vec![],
             },
@@ -2572,9 +4819,10 @@ mod tests {
                 function_name_span: Span::dummy(),
                 function_name: "foo".into(),
                 type_parameters: vec![],
-                parameters: vec![(Span::dummy(), "x".into(), None)],
+                parameters: vec![(Span::dummy(), "x".into(), None, None)],
                 body: Some(scalar!(1.0)),
                 local_variables: vec![],
+                local_units: vec![],
                 return_type_annotation: None,
                 decorators: vec![],
             },
@@ -2590,11 +4838,12 @@ mod tests {
                 function_name: "foo".into(),
                 type_parameters: vec![],
                 parameters: vec![
-                    (Span::dummy(), "x".into(), None),
-                    (Span::dummy(), "y".into(), None),
+                    (Span::dummy(), "x".into(), None, None),
+                    (Span::dummy(), "y".into(), None, None),
                 ],
                 body: Some(scalar!(1.0)),
                 local_variables: vec![],
+                local_units: vec![],
                 return_type_annotation: None,
                 decorators: vec![],
             },
@@ -2607,12 +4856,13 @@ mod tests {
                 function_name: "foo".into(),
                 type_parameters: vec![],
                 parameters: vec![
-                    (Span::dummy(), "x".into(), None),
-                    (Span::dummy(), "y".into(), None),
-                    (Span::dummy(), "z".into(), None),
+                    (Span::dummy(), "x".into(), None, None),
+                    (Span::dummy(), "y".into(), None, None),
+                    (Span::dummy(), "z".into(), None, None),
                 ],
                 body: Some(scalar!(1.0)),
                 local_variables: vec![],
+                local_units: vec![],
                 return_type_annotation: None,
                 decorators: vec![],
             },
@@ -2631,6 +4881,7 @@ mod tests {
                         Some(TypeAnnotation::TypeExpression(
                             TypeExpression::TypeIdentifier(Span::dummy(), "Length".into()),
                         )),
+                        None,
                     ),
                     (
                         Span::dummy(),
@@ -2638,6 +4889,7 @@ mod tests {
                         Some(TypeAnnotation::TypeExpression(
                             TypeExpression::TypeIdentifier(Span::dummy(), "Time".into()),
                         )),
+                        None,
                     ),
                     (
                         Span::dummy(),
@@ -2663,10 +4915,12 @@ mod tests {
                                 Rational::new(2, 1),
                             )),
                         ))),
+                        None,
                     ),
                 ],
                 body: Some(scalar!(1.0)),
                 local_variables: vec![],
+                local_units: vec![],
                 return_type_annotation: Some(TypeAnnotation::TypeExpression(
                     TypeExpression::TypeIdentifier(Span::dummy(), "Scalar".into()),
                 )),
@@ -2686,9 +4940,11 @@ mod tests {
                     Some(TypeAnnotation::TypeExpression(
                         TypeExpression::TypeIdentifier(Span::dummy(), "X".into()),
                     )),
+                    None,
                 )],
                 body: Some(scalar!(1.0)),
                 local_variables: vec![],
+                local_units: vec![],
                 return_type_annotation: None,
                 decorators: vec![],
             },
@@ -2706,9 +4962,11 @@ mod tests {
                     Some(TypeAnnotation::TypeExpression(
                         TypeExpression::TypeIdentifier(Span::dummy(), "X".into()),
                     )),
+                    None,
                 )],
                 body: Some(scalar!(1.0)),
                 local_variables: vec![],
+                local_units: vec![],
                 return_type_annotation: None,
                 decorators: vec![],
             },
@@ -2718,85 +4976,243 @@ mod tests {
             &["@name(\"Some function\") @description(\"This is a description of some_function.\") fn some_function(x) = 1"],
             Statement::DefineFunction {
                 function_name_span: Span::dummy(),
-                function_name: "some_function".into(),
+                function_name: "some_function".into(),
+                type_parameters: vec![],
+                parameters: vec![(Span::dummy(), "x".into(), None, None)],
+                body: Some(scalar!(1.0)),
+                local_variables: vec![],
+                local_units: vec![],
+                return_type_annotation: None,
+                decorators: vec![
+                    decorator::Decorator::Name("Some function".into()),
+                    decorator::Decorator::Description(
+                        "This is a description of some_function.".into(),
+                    ),
+                ],
+            },
+        );
+
+        parse_as(
+            &["fn double_kef(x) = y where y = x * 2"],
+            Statement::DefineFunction {
+                function_name_span: Span::dummy(),
+                function_name: "double_kef".into(),
+                type_parameters: vec![],
+                parameters: vec![(Span::dummy(), "x".into(), None, None)],
+                body: Some(identifier!("y")),
+                local_variables: vec![DefineVariable {
+                    pattern: Pattern::Identifier(Span::dummy(), "y".into()),
+                    expr: binop!(identifier!("x"), Mul, scalar!(2.0)),
+                    type_annotation: None,
+                    decorators: vec![],
+                }],
+                local_units: vec![],
+                return_type_annotation: None,
+                decorators: vec![],
+            },
+        );
+
+        parse_as(
+            &["fn kefirausaure(x) = z + y
+                 where y = x + x
+                   and z = y + x"],
+            Statement::DefineFunction {
+                function_name_span: Span::dummy(),
+                function_name: "kefirausaure".into(),
+                type_parameters: vec![],
+                parameters: vec![(Span::dummy(), "x".into(), None, None)],
+                body: Some(binop!(identifier!("z"), Add, identifier!("y"))),
+                local_variables: vec![
+                    DefineVariable {
+                        pattern: Pattern::Identifier(Span::dummy(), "y".into()),
+                        expr: binop!(identifier!("x"), Add, identifier!("x")),
+                        type_annotation: None,
+                        decorators: vec![],
+                    },
+                    DefineVariable {
+                        pattern: Pattern::Identifier(Span::dummy(), "z".into()),
+                        expr: binop!(identifier!("y"), Add, identifier!("x")),
+                        type_annotation: None,
+                        decorators: vec![],
+                    },
+                ],
+                local_units: vec![],
+                return_type_annotation: None,
+                decorators: vec![],
+            },
+        );
+
+        parse_as(
+            &["fn ticks(n) = n tick
+                 where @aliases(tk) unit tick
+                   and y = 2"],
+            Statement::DefineFunction {
+                function_name_span: Span::dummy(),
+                function_name: "ticks".into(),
+                type_parameters: vec![],
+                parameters: vec![(Span::dummy(), "n".into(), None, None)],
+                body: Some(binop!(identifier!("n"), Mul, identifier!("tick"))),
+                local_variables: vec![DefineVariable {
+                    pattern: Pattern::Identifier(Span::dummy(), "y".into()),
+                    expr: scalar!(2.0),
+                    type_annotation: None,
+                    decorators: vec![],
+                }],
+                local_units: vec![Statement::DefineBaseUnit(
+                    Span::dummy(),
+                    "tick".into(),
+                    None,
+                    vec![Decorator::Aliases(vec![("tk".into(), None)])],
+                )],
+                return_type_annotation: None,
+                decorators: vec![],
+            },
+        );
+
+        should_fail_with(
+            &["fn f(x) = x where"],
+            ParseErrorKind::ExpectedLocalVariableDefinition,
+        );
+
+        should_fail_with(
+            &["fn f(x) = x where fn g(y) = y"],
+            ParseErrorKind::ExpectedLocalVariableDefinition,
+        );
+
+        should_fail_with(
+            &["fn f(x) = x where z = 1 and"],
+            ParseErrorKind::ExpectedLocalVariableDefinition,
+        );
+
+        should_fail_with(
+            &["@aliases(foo) fn foobar(a: Scalar) -> Scalar"],
+            ParseErrorKind::AliasUsedOnFunction,
+        );
+
+        parse_as(
+            &["@operator(+) fn add(a: V, b: V) -> V"],
+            Statement::DefineFunction {
+                function_name_span: Span::dummy(),
+                function_name: "add".into(),
                 type_parameters: vec![],
-                parameters: vec![(Span::dummy(), "x".into(), None)],
-                body: Some(scalar!(1.0)),
-                local_variables: vec![],
-                return_type_annotation: None,
-                decorators: vec![
-                    decorator::Decorator::Name("Some function".into()),
-                    decorator::Decorator::Description(
-                        "This is a description of some_function.".into(),
+                parameters: vec![
+                    (
+                        Span::dummy(),
+                        "a".into(),
+                        Some(TypeAnnotation::TypeExpression(
+                            TypeExpression::TypeIdentifier(Span::dummy(), "V".into()),
+                        )),
+                        None,
+                    ),
+                    (
+                        Span::dummy(),
+                        "b".into(),
+                        Some(TypeAnnotation::TypeExpression(
+                            TypeExpression::TypeIdentifier(Span::dummy(), "V".into()),
+                        )),
+                        None,
                     ),
                 ],
+                body: None,
+                local_variables: vec![],
+                local_units: vec![],
+                return_type_annotation: Some(TypeAnnotation::TypeExpression(
+                    TypeExpression::TypeIdentifier(Span::dummy(), "V".into()),
+                )),
+                decorators: vec![decorator::Decorator::Operator(BinaryOperator::Add)],
             },
         );
 
+        should_fail_with(
+            &["@operator(^) fn pow(a: V, b: V) -> V"],
+            ParseErrorKind::ExpectedOverloadableOperator,
+        );
+
+        should_fail_with(
+            &["@operator(+) let x = 1"],
+            ParseErrorKind::OperatorDecoratorUsedOnNonFunction,
+        );
+
         parse_as(
-            &["fn double_kef(x) = y where y = x * 2"],
+            &["fn foo(x, y: Scalar = x * 2) = 1"],
             Statement::DefineFunction {
                 function_name_span: Span::dummy(),
-                function_name: "double_kef".into(),
+                function_name: "foo".into(),
                 type_parameters: vec![],
-                parameters: vec![(Span::dummy(), "x".into(), None)],
-                body: Some(identifier!("y")),
-                local_variables: vec![DefineVariable {
-                    identifier_span: Span::dummy(),
-                    identifier: String::from("y"),
-                    expr: binop!(identifier!("x"), Mul, scalar!(2.0)),
-                    type_annotation: None,
-                    decorators: vec![],
-                }],
+                parameters: vec![
+                    (Span::dummy(), "x".into(), None, None),
+                    (
+                        Span::dummy(),
+                        "y".into(),
+                        Some(TypeAnnotation::TypeExpression(
+                            TypeExpression::TypeIdentifier(Span::dummy(), "Scalar".into()),
+                        )),
+                        Some(binop!(identifier!("x"), Mul, scalar!(2.0))),
+                    ),
+                ],
+                body: Some(scalar!(1.0)),
+                local_variables: vec![],
+                local_units: vec![],
                 return_type_annotation: None,
                 decorators: vec![],
             },
         );
 
+        should_fail_with(
+            &["fn foo(x = 1, y) = 1"],
+            ParseErrorKind::ParameterWithoutDefaultAfterDefault,
+        );
+
         parse_as(
-            &["fn kefirausaure(x) = z + y
-                 where y = x + x
-                   and z = y + x"],
+            &[
+                "fn foo<D: Dim>(x: D, xs: D…) -> D",
+                "fn foo<D: Dim>(x: D, xs: D...,) -> D",
+            ],
             Statement::DefineFunction {
                 function_name_span: Span::dummy(),
-                function_name: "kefirausaure".into(),
-                type_parameters: vec![],
-                parameters: vec![(Span::dummy(), "x".into(), None)],
-                body: Some(binop!(identifier!("z"), Add, identifier!("y"))),
-                local_variables: vec![
-                    DefineVariable {
-                        identifier_span: Span::dummy(),
-                        identifier: String::from("y"),
-                        expr: binop!(identifier!("x"), Add, identifier!("x")),
-                        type_annotation: None,
-                        decorators: vec![],
-                    },
-                    DefineVariable {
-                        identifier_span: Span::dummy(),
-                        identifier: String::from("z"),
-                        expr: binop!(identifier!("y"), Add, identifier!("x")),
-                        type_annotation: None,
-                        decorators: vec![],
-                    },
+                function_name: "foo".into(),
+                type_parameters: vec![(Span::dummy(), "D".into(), Some(TypeParameterBound::Dim))],
+                parameters: vec![
+                    (
+                        Span::dummy(),
+                        "x".into(),
+                        Some(TypeAnnotation::TypeExpression(
+                            TypeExpression::TypeIdentifier(Span::dummy(), "D".into()),
+                        )),
+                        None,
+                    ),
+                    (
+                        Span::dummy(),
+                        "xs".into(),
+                        Some(TypeAnnotation::Variadic(
+                            Span::dummy(),
+                            Box::new(TypeAnnotation::TypeExpression(
+                                TypeExpression::TypeIdentifier(Span::dummy(), "D".into()),
+                            )),
+                        )),
+                        None,
+                    ),
                 ],
-                return_type_annotation: None,
+                body: None,
+                local_variables: vec![],
+                local_units: vec![],
+                return_type_annotation: Some(TypeAnnotation::TypeExpression(
+                    TypeExpression::TypeIdentifier(Span::dummy(), "D".into()),
+                )),
                 decorators: vec![],
             },
         );
 
         should_fail_with(
-            &["fn f(x) = x where"],
-            ParseErrorKind::ExpectedLocalVariableDefinition,
-        );
-
-        should_fail_with(
-            &["fn f(x) = x where z = 1 and"],
-            ParseErrorKind::ExpectedLocalVariableDefinition,
+            &["fn foo(xs: Scalar…, y) = 1", "fn foo(xs: Scalar… y) = 1"],
+            ParseErrorKind::ExpectedRightParenAfterVariadicParameter,
         );
-
         should_fail_with(
-            &["@aliases(foo) fn foobar(a: Scalar) -> Scalar"],
-            ParseErrorKind::AliasUsedOnFunction,
+            &[
+                "fn foo(x = 1, xs: Scalar…) = 1",
+                "fn foo(xs: Scalar… = [1]) = 1",
+            ],
+            ParseErrorKind::DefaultParameterInVariadicFunction,
         );
     }
 
@@ -2809,6 +5225,8 @@ mod tests {
                 Span::dummy(),
                 Box::new(identifier!("foo")),
                 vec![],
+                vec![],
+                CallSyntax::Regular,
             ),
         );
 
@@ -2819,6 +5237,8 @@ mod tests {
                 Span::dummy(),
                 Box::new(identifier!("foo")),
                 vec![scalar!(1.0)],
+                vec![],
+                CallSyntax::Regular,
             ),
         );
 
@@ -2829,16 +5249,58 @@ mod tests {
                 Span::dummy(),
                 Box::new(identifier!("foo")),
                 vec![scalar!(1.0), scalar!(2.0), scalar!(3.0)],
+                vec![],
+                CallSyntax::Regular,
+            ),
+        );
+
+        parse_as_expression(
+            &["foo(1, b = 2, c = 3)"],
+            Expression::FunctionCall(
+                Span::dummy(),
+                Span::dummy(),
+                Box::new(identifier!("foo")),
+                vec![scalar!(1.0)],
+                vec![
+                    (Span::dummy(), "b".into(), scalar!(2.0)),
+                    (Span::dummy(), "c".into(), scalar!(3.0)),
+                ],
+                CallSyntax::Regular,
             ),
         );
 
+        parse_as_expression(
+            &["foo(b = 1 == 2)"],
+            Expression::FunctionCall(
+                Span::dummy(),
+                Span::dummy(),
+                Box::new(identifier!("foo")),
+                vec![],
+                vec![(
+                    Span::dummy(),
+                    "b".into(),
+                    binop!(scalar!(1.0), Equal, scalar!(2.0)),
+                )],
+                CallSyntax::Regular,
+            ),
+        );
+
+        should_fail_with(
+            &["foo(a = 1, 2)"],
+            ParseErrorKind::PositionalArgumentAfterNamedArgument,
+        );
+        should_fail_with(
+            &["print(x = 1)"],
+            ParseErrorKind::NamedArgumentInProcedureCall,
+        );
+
         // https://github.com/sharkdp/numbat/issues/507
         assert_snapshot!(snap_parse(
             "tamo(
               2 m,
               5 m
             )"), @r###"
-        Expression(FunctionCall(Span { start: SourceCodePositition { byte: 0, line: 1, position: 1 }, end: SourceCodePositition { byte: 4, line: 1, position: 5 }, code_source_id: 0 }, Span { start: SourceCodePositition { byte: 0, line: 1, position: 1 }, end: SourceCodePositition { byte: 56, line: 4, position: 14 }, code_source_id: 0 }, Identifier(Span { start: SourceCodePositition { byte: 0, line: 1, position: 1 }, end: SourceCodePositition { byte: 4, line: 1, position: 5 }, code_source_id: 0 }, "tamo"), [BinaryOperator { op: Mul, lhs: Scalar(Span { start: SourceCodePositition { byte: 20, line: 2, position: 15 }, end: SourceCodePositition { byte: 21, line: 2, position: 16 }, code_source_id: 0 }, Number(2.0)), rhs: Identifier(Span { start: SourceCodePositition { byte: 22, line: 2, position: 17 }, end: SourceCodePositition { byte: 23, line: 2, position: 18 }, code_source_id: 0 }, "m"), span_op: None }, BinaryOperator { op: Mul, lhs: Scalar(Span { start: SourceCodePositition { byte: 39, line: 3, position: 15 }, end: SourceCodePositition { byte: 40, line: 3, position: 16 }, code_source_id: 0 }, Number(5.0)), rhs: Identifier(Span { start: SourceCodePositition { byte: 41, line: 3, position: 17 }, end: SourceCodePositition { byte: 42, line: 3, position: 18 }, code_source_id: 0 }, "m"), span_op: None }]))
+        Expression(FunctionCall(Span { start: SourceCodePositition { byte: 0, line: 1, position: 1 }, end: SourceCodePositition { byte: 4, line: 1, position: 5 }, code_source_id: 0 }, Span { start: SourceCodePositition { byte: 0, line: 1, position: 1 }, end: SourceCodePositition { byte: 56, line: 4, position: 14 }, code_source_id: 0 }, Identifier(Span { start: SourceCodePositition { byte: 0, line: 1, position: 1 }, end: SourceCodePositition { byte: 4, line: 1, position: 5 }, code_source_id: 0 }, "tamo"), [BinaryOperator { op: Mul, lhs: Scalar(Span { start: SourceCodePositition { byte: 20, line: 2, position: 15 }, end: SourceCodePositition { byte: 21, line: 2, position: 16 }, code_source_id: 0 }, Number(2.0)), rhs: Identifier(Span { start: SourceCodePositition { byte: 22, line: 2, position: 17 }, end: SourceCodePositition { byte: 23, line: 2, position: 18 }, code_source_id: 0 }, "m"), span_op: None }, BinaryOperator { op: Mul, lhs: Scalar(Span { start: SourceCodePositition { byte: 39, line: 3, position: 15 }, end: SourceCodePositition { byte: 40, line: 3, position: 16 }, code_source_id: 0 }, Number(5.0)), rhs: Identifier(Span { start: SourceCodePositition { byte: 41, line: 3, position: 17 }, end: SourceCodePositition { byte: 42, line: 3, position: 18 }, code_source_id: 0 }, "m"), span_op: None }], [], Regular))
         "###);
 
         assert_snapshot!(snap_parse(
@@ -2846,12 +5308,12 @@ mod tests {
               2 m,
               5 m,
             )"), @r###"
-        Expression(FunctionCall(Span { start: SourceCodePositition { byte: 0, line: 1, position: 1 }, end: SourceCodePositition { byte: 5, line: 1, position: 6 }, code_source_id: 0 }, Span { start: SourceCodePositition { byte: 0, line: 1, position: 1 }, end: SourceCodePositition { byte: 58, line: 4, position: 14 }, code_source_id: 0 }, Identifier(Span { start: SourceCodePositition { byte: 0, line: 1, position: 1 }, end: SourceCodePositition { byte: 5, line: 1, position: 6 }, code_source_id: 0 }, "kefir"), [BinaryOperator { op: Mul, lhs: Scalar(Span { start: SourceCodePositition { byte: 21, line: 2, position: 15 }, end: SourceCodePositition { byte: 22, line: 2, position: 16 }, code_source_id: 0 }, Number(2.0)), rhs: Identifier(Span { start: SourceCodePositition { byte: 23, line: 2, position: 17 }, end: SourceCodePositition { byte: 24, line: 2, position: 18 }, code_source_id: 0 }, "m"), span_op: None }, BinaryOperator { op: Mul, lhs: Scalar(Span { start: SourceCodePositition { byte: 40, line: 3, position: 15 }, end: SourceCodePositition { byte: 41, line: 3, position: 16 }, code_source_id: 0 }, Number(5.0)), rhs: Identifier(Span { start: SourceCodePositition { byte: 42, line: 3, position: 17 }, end: SourceCodePositition { byte: 43, line: 3, position: 18 }, code_source_id: 0 }, "m"), span_op: None }]))
+        Expression(FunctionCall(Span { start: SourceCodePositition { byte: 0, line: 1, position: 1 }, end: SourceCodePositition { byte: 5, line: 1, position: 6 }, code_source_id: 0 }, Span { start: SourceCodePositition { byte: 0, line: 1, position: 1 }, end: SourceCodePositition { byte: 58, line: 4, position: 14 }, code_source_id: 0 }, Identifier(Span { start: SourceCodePositition { byte: 0, line: 1, position: 1 }, end: SourceCodePositition { byte: 5, line: 1, position: 6 }, code_source_id: 0 }, "kefir"), [BinaryOperator { op: Mul, lhs: Scalar(Span { start: SourceCodePositition { byte: 21, line: 2, position: 15 }, end: SourceCodePositition { byte: 22, line: 2, position: 16 }, code_source_id: 0 }, Number(2.0)), rhs: Identifier(Span { start: SourceCodePositition { byte: 23, line: 2, position: 17 }, end: SourceCodePositition { byte: 24, line: 2, position: 18 }, code_source_id: 0 }, "m"), span_op: None }, BinaryOperator { op: Mul, lhs: Scalar(Span { start: SourceCodePositition { byte: 40, line: 3, position: 15 }, end: SourceCodePositition { byte: 41, line: 3, position: 16 }, code_source_id: 0 }, Number(5.0)), rhs: Identifier(Span { start: SourceCodePositition { byte: 42, line: 3, position: 17 }, end: SourceCodePositition { byte: 43, line: 3, position: 18 }, code_source_id: 0 }, "m"), span_op: None }], [], Regular))
         "###);
         assert_snapshot!(snap_parse(
             "echo(
             )"), @r###"
-        Expression(FunctionCall(Span { start: SourceCodePositition { byte: 0, line: 1, position: 1 }, end: SourceCodePositition { byte: 4, line: 1, position: 5 }, code_source_id: 0 }, Span { start: SourceCodePositition { byte: 0, line: 1, position: 1 }, end: SourceCodePositition { byte: 19, line: 2, position: 14 }, code_source_id: 0 }, Identifier(Span { start: SourceCodePositition { byte: 0, line: 1, position: 1 }, end: SourceCodePositition { byte: 4, line: 1, position: 5 }, code_source_id: 0 }, "echo"), []))
+        Expression(FunctionCall(Span { start: SourceCodePositition { byte: 0, line: 1, position: 1 }, end: SourceCodePositition { byte: 4, line: 1, position: 5 }, code_source_id: 0 }, Span { start: SourceCodePositition { byte: 0, line: 1, position: 1 }, end: SourceCodePositition { byte: 19, line: 2, position: 14 }, code_source_id: 0 }, Identifier(Span { start: SourceCodePositition { byte: 0, line: 1, position: 1 }, end: SourceCodePositition { byte: 4, line: 1, position: 5 }, code_source_id: 0 }, "echo"), [], [], Regular))
         "###);
         assert_snapshot!(snap_parse(
             "jax(
@@ -2876,8 +5338,12 @@ mod tests {
                     Span::dummy(),
                     Box::new(identifier!("returns_fn")),
                     vec![],
+                    vec![],
+                    CallSyntax::Regular,
                 )),
                 vec![],
+                vec![],
+                CallSyntax::Regular,
             ),
         );
 
@@ -2891,8 +5357,12 @@ mod tests {
                     Span::dummy(),
                     Box::new(identifier!("returns_fn")),
                     vec![],
+                    vec![],
+                    CallSyntax::Regular,
                 )),
                 vec![],
+                vec![],
+                CallSyntax::Regular,
             ),
         );
     }
@@ -2906,6 +5376,8 @@ mod tests {
                 Span::dummy(),
                 Box::new(identifier!("foo")),
                 vec![binop!(scalar!(1.0), Add, scalar!(1.0))],
+                vec![],
+                CallSyntax::Pipe,
             ),
         );
         parse_as_expression(
@@ -2915,6 +5387,8 @@ mod tests {
                 Span::dummy(),
                 Box::new(identifier!("kefir")),
                 vec![scalar!(2.0), binop!(scalar!(1.0), Add, scalar!(1.0))],
+                vec![],
+                CallSyntax::Pipe,
             ),
         );
 
@@ -2926,6 +5400,43 @@ mod tests {
             &["1 |> 2", "1 |> 1 +"],
             ParseErrorKind::ExpectedIdentifierOrCallAfterPostfixApply,
         );
+
+        parse_as_expression(
+            &["1 |> foo |> bar(2)"],
+            Expression::FunctionCall(
+                Span::dummy(),
+                Span::dummy(),
+                Box::new(identifier!("bar")),
+                vec![
+                    scalar!(2.0),
+                    Expression::FunctionCall(
+                        Span::dummy(),
+                        Span::dummy(),
+                        Box::new(identifier!("foo")),
+                        vec![scalar!(1.0)],
+                        vec![],
+                        CallSyntax::Pipe,
+                    ),
+                ],
+                vec![],
+                CallSyntax::Pipe,
+            ),
+        );
+
+        parse_as_expression(
+            &["1 -> a |> foo -> b"],
+            conversion!(
+                Expression::FunctionCall(
+                    Span::dummy(),
+                    Span::dummy(),
+                    Box::new(identifier!("foo")),
+                    vec![conversion!(scalar!(1.0), identifier!("a"))],
+                    vec![],
+                    CallSyntax::Pipe,
+                ),
+                identifier!("b")
+            ),
+        );
     }
 
     #[test]
@@ -3025,6 +5536,131 @@ mod tests {
                 boolean!(false)
                 )
             );
+
+        #[rustfmt::skip]
+        parse_as_expression(
+            &["1 < 2 && 3 > 4 || !5 == 6"],
+            binop!( // Comparisons have precedence over all logical operators
+                binop!(
+                    binop!(scalar!(1.0), LessThan, scalar!(2.0)),
+                    LogicalAnd,
+                    binop!(scalar!(3.0), GreaterThan, scalar!(4.0))
+                ),
+                LogicalOr,
+                logical_neg!(binop!(scalar!(5.0), Equal, scalar!(6.0)))
+                )
+            );
+    }
+
+    #[test]
+    fn incomplete_comparisons() {
+        let error = |input: &str| match parse(input, 0) {
+            Err((_, errors)) => errors[0].kind.clone(),
+            _ => panic!("Expected a parse error for '{input}'"),
+        };
+
+        assert_eq!(
+            error("x > 0 && < 5"),
+            ParseErrorKind::MissingLeftHandSideOfComparison(Some((
+                "x > 0 && x < 5".into(),
+                "x".into()
+            )))
+        );
+        assert_eq!(
+            error("y == 0 || x ≥ 1 m || <= 2 m"),
+            ParseErrorKind::MissingLeftHandSideOfComparison(Some((
+                "y == 0 || x ≥ 1 m || x <= 2 m".into(),
+                "x".into()
+            )))
+        );
+        assert_eq!(
+            error("f(x) && < 5"),
+            ParseErrorKind::MissingLeftHandSideOfComparison(None)
+        );
+
+        assert_eq!(
+            error("0 < x < 5 && < 7"),
+            ParseErrorKind::MissingLeftHandSideOfComparison(Some((
+                "0 < x < 5 && x < 7".into(),
+                "x".into()
+            )))
+        );
+    }
+
+    #[test]
+    fn chained_comparisons() {
+        parse_as_expression(
+            &["0 < x <= 5", "0 < x ≤ 5"],
+            chained_comparison!(
+                scalar!(0.0),
+                LessThan,
+                identifier!("x"),
+                LessOrEqual,
+                scalar!(5.0)
+            ),
+        );
+        parse_as_expression(
+            &["1 > 2 >= 3 > 4"],
+            chained_comparison!(
+                scalar!(1.0),
+                GreaterThan,
+                scalar!(2.0),
+                GreaterOrEqual,
+                scalar!(3.0),
+                GreaterThan,
+                scalar!(4.0)
+            ),
+        );
+
+        // Mixed directions are allowed (but the type checker warns about them)
+        parse_as_expression(
+            &["1 < 2 > 3"],
+            chained_comparison!(
+                scalar!(1.0),
+                LessThan,
+                scalar!(2.0),
+                GreaterThan,
+                scalar!(3.0)
+            ),
+        );
+
+        // (In)equalities are not chained, so that booleans can be compared
+        parse_as_expression(
+            &["1 < 2 == true"],
+            binop!(
+                binop!(scalar!(1.0), LessThan, scalar!(2.0)),
+                Equal,
+                boolean!(true)
+            ),
+        );
+        parse_as_expression(
+            &["1 < 2 < 3 != 4 < 5"],
+            binop!(
+                binop!(
+                    chained_comparison!(
+                        scalar!(1.0),
+                        LessThan,
+                        scalar!(2.0),
+                        LessThan,
+                        scalar!(3.0)
+                    ),
+                    NotEqual,
+                    scalar!(4.0)
+                ),
+                LessThan,
+                scalar!(5.0)
+            ),
+        );
+
+        // Parentheses end a chain
+        parse_as_expression(
+            &["(1 < 2) < 3"],
+            binop!(
+                binop!(scalar!(1.0), LessThan, scalar!(2.0)),
+                LessThan,
+                scalar!(3.0)
+            ),
+        );
     }
 
     #[test]
@@ -3066,24 +5702,121 @@ mod tests {
             ),
         );
 
-        parse_as_expression(
-            &[
-                "if true then if false then 1 else 2 else 3",
-                "if true then (if false then 1 else 2) else 3",
-            ],
-            conditional!(
-                Expression::Boolean(Span::dummy(), true),
-                conditional!(
-                    Expression::Boolean(Span::dummy(), false),
-                    scalar!(1.0),
-                    scalar!(2.0)
-                ),
-                scalar!(3.0)
-            ),
+        parse_as_expression(
+            &[
+                "if true then if false then 1 else 2 else 3",
+                "if true then (if false then 1 else 2) else 3",
+            ],
+            conditional!(
+                Expression::Boolean(Span::dummy(), true),
+                conditional!(
+                    Expression::Boolean(Span::dummy(), false),
+                    scalar!(1.0),
+                    scalar!(2.0)
+                ),
+                scalar!(3.0)
+            ),
+        );
+
+        should_fail_with(&["if true 1 else 2"], ParseErrorKind::ExpectedThen);
+        should_fail_with(&["if true then 1"], ParseErrorKind::ExpectedElse);
+
+        // The error points to the end of the `then` branch, not to the next line
+        let Err((_, errors)) = parse("if true then 1\n\n2", 0) else {
+            panic!();
+        };
+        assert_eq!(errors[0].kind, ParseErrorKind::ExpectedElse);
+        assert_eq!(errors[0].span.start.line, 1);
+        assert_eq!(errors[0].span.start.position, 15);
+    }
+
+    #[test]
+    fn conditional_statements() {
+        let print = |x: f64| {
+            Box::new(Statement::ProcedureCall(
+                Span::dummy(),
+                ProcedureKind::Print,
+                vec![scalar!(x)],
+            ))
+        };
+
+        parse_as(
+            &["if true then print(1)", "if (true) then\n  print(1)"],
+            Statement::Conditional {
+                span_if: Span::dummy(),
+                condition: Expression::Boolean(Span::dummy(), true),
+                then_statement: print(1.0),
+                else_statement: None,
+            },
+        );
+
+        parse_as(
+            &[
+                "if true then print(1) else print(2)",
+                "if true\n  then print(1)\n  else print(2)",
+            ],
+            Statement::Conditional {
+                span_if: Span::dummy(),
+                condition: Expression::Boolean(Span::dummy(), true),
+                then_statement: print(1.0),
+                else_statement: Some(print(2.0)),
+            },
+        );
+
+        parse_as(
+            &["if true then if false then print(1) else print(2)"],
+            Statement::Conditional {
+                span_if: Span::dummy(),
+                condition: Expression::Boolean(Span::dummy(), true),
+                then_statement: Box::new(Statement::Conditional {
+                    span_if: Span::dummy(),
+                    condition: Expression::Boolean(Span::dummy(), false),
+                    then_statement: print(1.0),
+                    else_statement: Some(print(2.0)),
+                }),
+                else_statement: None,
+            },
+        );
+
+        parse_as(
+            &["if true then print(1) else if false then print(2)"],
+            Statement::Conditional {
+                span_if: Span::dummy(),
+                condition: Expression::Boolean(Span::dummy(), true),
+                then_statement: print(1.0),
+                else_statement: Some(Box::new(Statement::Conditional {
+                    span_if: Span::dummy(),
+                    condition: Expression::Boolean(Span::dummy(), false),
+                    then_statement: print(2.0),
+                    else_statement: None,
+                })),
+            },
+        );
+
+        // Without an `else`, the next line is a separate statement
+        assert_eq!(
+            parse("if true then print(1)\n2", 0)
+                .unwrap()
+                .replace_spans(),
+            vec![
+                Statement::Conditional {
+                    span_if: Span::dummy(),
+                    condition: Expression::Boolean(Span::dummy(), true),
+                    then_statement: print(1.0),
+                    else_statement: None,
+                },
+                Statement::Expression(scalar!(2.0)),
+            ]
         );
 
-        should_fail_with(&["if true 1 else 2"], ParseErrorKind::ExpectedThen);
-        should_fail_with(&["if true then 1"], ParseErrorKind::ExpectedElse);
+        should_fail_with(
+            &["if true then print(1) else 2"],
+            ParseErrorKind::ExpectedProcedureCallInConditionalStatement,
+        );
+        should_fail_with(
+            &["let x = if true then print(1)"],
+            ParseErrorKind::InlineProcedureUsage,
+        );
     }
 
     #[test]
@@ -3236,28 +5969,65 @@ mod tests {
         );
     }
 
+    #[test]
+    fn module_imports() {
+        parse_as(
+            &["use foo::bar"],
+            Statement::ModuleImport(
+                Span::dummy(),
+                ModulePath(vec!["foo".into(), "bar".into()]),
+                None,
+            ),
+        );
+
+        parse_as(
+            &[
+                "use foo::{baz, qux}",
+                "use foo::{baz, qux,}",
+                "use foo::{\n  baz,\n  qux,\n}",
+            ],
+            Statement::ModuleImport(
+                Span::dummy(),
+                ModulePath(vec!["foo".into()]),
+                Some(vec![
+                    (Span::dummy(), "baz".into()),
+                    (Span::dummy(), "qux".into()),
+                ]),
+            ),
+        );
+
+        should_fail_with(
+            &["use foo::{}", "use foo::{baz,,}", "use foo::{1}"],
+            ParseErrorKind::ExpectedImportedItem,
+        );
+        should_fail_with(
+            &["use foo::{baz", "use foo::{baz qux}"],
+            ParseErrorKind::MissingClosingCurlyInImport,
+        );
+    }
+
     #[test]
     fn structs() {
         parse_as(
             &["struct Foo { foo: Scalar, bar: Scalar }"],
             Statement::DefineStruct {
                 struct_name_span: Span::dummy(),
-                struct_name: "Foo".to_owned(),
+                struct_name: "Foo".into(),
                 fields: vec![
                     (
                         Span::dummy(),
-                        "foo".to_owned(),
+                        "foo".into(),
                         TypeAnnotation::TypeExpression(TypeExpression::TypeIdentifier(
                             Span::dummy(),
-                            "Scalar".to_owned(),
+                            "Scalar".into(),
                         )),
                     ),
                     (
                         Span::dummy(),
-                        "bar".to_owned(),
+                        "bar".into(),
                         TypeAnnotation::TypeExpression(TypeExpression::TypeIdentifier(
                             Span::dummy(),
-                            "Scalar".to_owned(),
+                            "Scalar".into(),
                         )),
                     ),
                 ],
@@ -3283,9 +6053,216 @@ mod tests {
                     foo: scalar!(1.0),
                     bar: scalar!(2.0)
                 }),
-                "foo".to_owned(),
+                "foo".into(),
             ),
         );
+
+        parse_as_expression(
+            &["Foo {foo with bar = 2}", "Foo {\n  foo with\n  bar = 2,\n}"],
+            Expression::InstantiateStruct {
+                full_span: Span::dummy(),
+                ident_span: Span::dummy(),
+                name: "Foo".into(),
+                base: Some(Box::new(identifier!("foo"))),
+                fields: vec![(Span::dummy(), "bar".into(), scalar!(2.0))],
+            },
+        );
+
+        parse_as_expression(
+            &["Foo {foo.bar with bar = 2}"],
+            Expression::InstantiateStruct {
+                full_span: Span::dummy(),
+                ident_span: Span::dummy(),
+                name: "Foo".into(),
+                base: Some(Box::new(Expression::AccessField(
+                    Span::dummy(),
+                    Span::dummy(),
+                    Box::new(identifier!("foo")),
+                    "bar".into(),
+                ))),
+                fields: vec![(Span::dummy(), "bar".into(), scalar!(2.0))],
+            },
+        );
+
+        should_fail_with(
+            &["Foo {foo with bar: 2}"],
+            ParseErrorKind::ExpectedEqualAfterFieldNameInStructUpdate,
+        );
+        should_fail_with(
+            &["Foo {foo = 1 with bar = 2}"],
+            ParseErrorKind::ExpectedWithInStructUpdate,
+        );
+    }
+
+    #[test]
+    fn impl_blocks() {
+        let area = |self_annotation: &str| Statement::DefineFunction {
+            function_name_span: Span::dummy(),
+            function_name: "Circle::area".into(),
+            type_parameters: vec![],
+            parameters: vec![(
+                Span::dummy(),
+                "self".into(),
+                Some(TypeAnnotation::TypeExpression(
+                    TypeExpression::TypeIdentifier(Span::dummy(), self_annotation.into()),
+                )),
+                None,
+            )],
+            body: Some(binop!(identifier!("pi"), Mul, identifier!("r"))),
+            local_variables: vec![],
+            local_units: vec![],
+            return_type_annotation: None,
+            decorators: vec![],
+        };
+
+        parse_as(
+            &[
+                "impl Circle { fn area(self) = pi r }",
+                "impl Circle {\n  fn area(self) = pi r\n}",
+            ],
+            Statement::DefineMethods {
+                struct_name_span: Span::dummy(),
+                struct_name: "Circle".into(),
+                functions: vec![area("Circle")],
+            },
+        );
+        parse_as(
+            &["impl Circle { fn area(self: Disk) = pi r }"],
+            Statement::DefineMethods {
+                struct_name_span: Span::dummy(),
+                struct_name: "Circle".into(),
+                functions: vec![area("Disk")],
+            },
+        );
+        parse_as(
+            &["impl Circle {}", "impl Circle {\n}"],
+            Statement::DefineMethods {
+                struct_name_span: Span::dummy(),
+                struct_name: "Circle".into(),
+                functions: vec![],
+            },
+        );
+
+        should_fail_with(
+            &["impl Circle", "impl Circle fn"],
+            ParseErrorKind::ExpectedLeftCurlyAfterStructName,
+        );
+        should_fail_with(
+            &[
+                "impl Circle { let x = 1 }",
+                "impl Circle { fn area(self) = 1",
+            ],
+            ParseErrorKind::ExpectedFunctionInImplBlock,
+        );
+        should_fail_with(
+            &[
+                "impl Circle { fn area() = 1 }",
+                "impl Circle { fn area(c) = 1 }",
+                "impl Circle { fn area(self = 1) = 1 }",
+            ],
+            ParseErrorKind::ExpectedSelfParameter,
+        );
+    }
+
+    #[test]
+    fn formulas() {
+        let quantity = |name: &str, annotation: Option<&str>| {
+            (
+                Span::dummy(),
+                Symbol::new(name),
+                annotation.map(|dimension| {
+                    TypeAnnotation::TypeExpression(TypeExpression::TypeIdentifier(
+                        Span::dummy(),
+                        dimension.into(),
+                    ))
+                }),
+            )
+        };
+
+        // All identifiers are quantities, except for names of called functions
+        parse_as(
+            &[
+                "formula ohms_law: voltage = current * resistance",
+                "formula ohms_law:\n  voltage =\n  current * resistance",
+            ],
+            Statement::DefineFormula {
+                formula_name_span: Span::dummy(),
+                formula_name: "ohms_law".into(),
+                quantities: vec![
+                    quantity("voltage", None),
+                    quantity("current", None),
+                    quantity("resistance", None),
+                ],
+                lhs: identifier!("voltage"),
+                rhs: binop!(identifier!("current"), Mul, identifier!("resistance")),
+            },
+        );
+        parse_as(
+            &["formula f: y = a * sqrt(x) * a"],
+            Statement::DefineFormula {
+                formula_name_span: Span::dummy(),
+                formula_name: "f".into(),
+                quantities: vec![
+                    quantity("y", None),
+                    quantity("a", None),
+                    quantity("x", None),
+                ],
+                lhs: identifier!("y"),
+                rhs: binop!(
+                    binop!(
+                        identifier!("a"),
+                        Mul,
+                        Expression::FunctionCall(
+                            Span::dummy(),
+                            Span::dummy(),
+                            Box::new(identifier!("sqrt")),
+                            vec![identifier!("x")],
+                            vec![],
+                            CallSyntax::Regular,
+                        )
+                    ),
+                    Mul,
+                    identifier!("a")
+                ),
+            },
+        );
+
+        // Declared quantities, with optional types
+        parse_as(
+            &[
+                "formula weight(force: Force, mass): force = mass * g0",
+                "formula weight(\n  force: Force,\n  mass,\n): force = mass * g0",
+            ],
+            Statement::DefineFormula {
+                formula_name_span: Span::dummy(),
+                formula_name: "weight".into(),
+                quantities: vec![quantity("force", Some("Force")), quantity("mass", None)],
+                lhs: identifier!("force"),
+                rhs: binop!(identifier!("mass"), Mul, identifier!("g0")),
+            },
+        );
+
+        should_fail_with(
+            &["formula", "formula : y = x"],
+            ParseErrorKind::ExpectedIdentifier,
+        );
+        should_fail_with(
+            &[
+                "formula f y = x",
+                "formula f: y",
+                "formula f: y x",
+                "formula f(x) y = x",
+            ],
+            ParseErrorKind::ExpectedFormulaEquation,
+        );
+        should_fail_with(
+            &["formula f(x y): y = x"],
+            ParseErrorKind::ExpectedCommaEllipsisOrRightParenInFunctionDefinition,
+        );
+        should_fail_with(
+            &["formula f(1): y = x"],
+            ParseErrorKind::ExpectedParameterNameInFunctionDefinition,
+        );
     }
 
     #[test]
@@ -3328,6 +6305,222 @@ mod tests {
         should_fail_with(&["[1,\n2,\n,\n"], ParseErrorKind::ExpectedPrimary);
     }
 
+    #[test]
+    fn trace() {
+        parse_as_expression(
+            &["trace(x)", "trace( x )"],
+            Expression::Trace(Span::dummy(), "x".into(), Box::new(identifier!("x"))),
+        );
+        parse_as_expression(
+            &["trace(2  *  x)"],
+            Expression::Trace(
+                Span::dummy(),
+                "2  *  x".into(),
+                Box::new(binop!(scalar!(2.0), Mul, identifier!("x"))),
+            ),
+        );
+        parse_as_expression(
+            &["1 + trace(x)"],
+            binop!(
+                scalar!(1.0),
+                Add,
+                Expression::Trace(Span::dummy(), "x".into(), Box::new(identifier!("x")))
+            ),
+        );
+
+        parse_as_expression(&["trace"], identifier!("trace"));
+        parse_as_expression(
+            &["trace x"],
+            binop!(identifier!("trace"), Mul, identifier!("x")),
+        );
+        should_fail_with(
+            &["trace(x", "trace(x, y)"],
+            ParseErrorKind::MissingClosingParen,
+        );
+    }
+
+    #[test]
+    fn partial() {
+        parse_as_expression(
+            &["partial(f, 1, y = 2)", "partial(\n  f,\n  1,\n  y = 2,\n)"],
+            Expression::Partial(
+                Span::dummy(),
+                Box::new(identifier!("f")),
+                vec![scalar!(1.0)],
+                vec![(Span::dummy(), "y".into(), scalar!(2.0))],
+            ),
+        );
+        parse_as_expression(
+            &["partial(f)"],
+            Expression::Partial(Span::dummy(), Box::new(identifier!("f")), vec![], vec![]),
+        );
+
+        should_fail_with(
+            &["partial", "partial f"],
+            ParseErrorKind::ExpectedLeftParenAfterPartial,
+        );
+        should_fail_with(
+            &["partial()", "partial(y = 2)"],
+            ParseErrorKind::ExpectedFunctionInPartial,
+        );
+    }
+
+    #[test]
+    fn autodiff() {
+        parse_as_expression(
+            &["autodiff(f)", "autodiff( f )", "autodiff(\n  f\n)"],
+            Expression::AutoDiff(Span::dummy(), Box::new(identifier!("f"))),
+        );
+        parse_as_expression(
+            &["autodiff(autodiff(f))"],
+            Expression::AutoDiff(
+                Span::dummy(),
+                Box::new(Expression::AutoDiff(
+                    Span::dummy(),
+                    Box::new(identifier!("f")),
+                )),
+            ),
+        );
+
+        should_fail_with(
+            &["autodiff", "autodiff f"],
+            ParseErrorKind::ExpectedLeftParenAfterAutoDiff,
+        );
+        should_fail_with(
+            &["autodiff(f", "autodiff(f, g)"],
+            ParseErrorKind::MissingClosingParen,
+        );
+    }
+
+    #[test]
+    fn loops() {
+        parse_as_expression(
+            &["for x in xs { 2 x }", "for x in xs {\n  2 x\n}"],
+            Expression::For {
+                full_span: Span::dummy(),
+                variable: (Span::dummy(), "x".into()),
+                list: Box::new(identifier!("xs")),
+                accumulator: None,
+                body: Box::new(binop!(scalar!(2.0), Mul, identifier!("x"))),
+            },
+        );
+        parse_as_expression(
+            &["for x in [1, 2] with total = 0 { total + x }"],
+            Expression::For {
+                full_span: Span::dummy(),
+                variable: (Span::dummy(), "x".into()),
+                list: Box::new(list!(scalar!(1.0), scalar!(2.0))),
+                accumulator: Some((Span::dummy(), "total".into(), Box::new(scalar!(0.0)))),
+                body: Box::new(binop!(identifier!("total"), Add, identifier!("x"))),
+            },
+        );
+        parse_as_expression(
+            &["while n < 100 with n = 1 { 2 n }"],
+            Expression::While {
+                full_span: Span::dummy(),
+                condition: Box::new(binop!(identifier!("n"), LessThan, scalar!(100.0))),
+                accumulator: (Span::dummy(), "n".into(), Box::new(scalar!(1.0))),
+                body: Box::new(binop!(scalar!(2.0), Mul, identifier!("n"))),
+            },
+        );
+
+        // Struct instantiations are only possible in parentheses in the loop header
+        parse_as_expression(
+            &["for x in xs with p = (Foo {}) { p }"],
+            Expression::For {
+                full_span: Span::dummy(),
+                variable: (Span::dummy(), "x".into()),
+                list: Box::new(identifier!("xs")),
+                accumulator: Some((
+                    Span::dummy(),
+                    "p".into(),
+                    Box::new(Expression::InstantiateStruct {
+                        full_span: Span::dummy(),
+                        ident_span: Span::dummy(),
+                        name: "Foo".into(),
+                        base: None,
+                        fields: vec![],
+                    }),
+                )),
+                body: Box::new(identifier!("p")),
+            },
+        );
+
+        should_fail_with(
+            &["for", "for 1 in xs { 1 }"],
+            ParseErrorKind::ExpectedLoopVariable,
+        );
+        should_fail_with(
+            &["for x xs { x }", "for x = xs { x }"],
+            ParseErrorKind::ExpectedInAfterLoopVariable,
+        );
+        should_fail_with(
+            &["for x in xs", "for x in xs x", "while b with a = 1"],
+            ParseErrorKind::ExpectedLeftCurlyBeforeLoopBody,
+        );
+        should_fail_with(
+            &["for x in xs { x", "for x in xs { x, x }"],
+            ParseErrorKind::ExpectedRightCurlyAfterLoopBody,
+        );
+        should_fail_with(
+            &["while b { 1 }", "while b"],
+            ParseErrorKind::ExpectedWithInWhileLoop,
+        );
+        should_fail_with(
+            &["while b with { 1 }", "for x in xs with a { a }"],
+            ParseErrorKind::ExpectedAccumulatorDefinition,
+        );
+    }
+
+    #[test]
+    fn try_catch() {
+        parse_as_expression(
+            &[
+                "try { f(x) } catch { 0 }",
+                "try {\n  f(x)\n}\ncatch {\n  0\n}",
+            ],
+            Expression::TryCatch {
+                full_span: Span::dummy(),
+                expr: Box::new(Expression::FunctionCall(
+                    Span::dummy(),
+                    Span::dummy(),
+                    Box::new(identifier!("f")),
+                    vec![identifier!("x")],
+                    vec![],
+                    CallSyntax::Regular,
+                )),
+                message: None,
+                fallback: Box::new(scalar!(0.0)),
+            },
+        );
+        parse_as_expression(
+            &["try { x } catch msg { msg }"],
+            Expression::TryCatch {
+                full_span: Span::dummy(),
+                expr: Box::new(identifier!("x")),
+                message: Some((Span::dummy(), "msg".into())),
+                fallback: Box::new(identifier!("msg")),
+            },
+        );
+
+        should_fail_with(
+            &[
+                "try x catch { 0 }",
+                "try { x } catch 0",
+                "try { x } catch msg",
+            ],
+            ParseErrorKind::ExpectedLeftCurlyInTryCatch,
+        );
+        should_fail_with(
+            &["try { x catch { 0 }", "try { x } catch { 0"],
+            ParseErrorKind::ExpectedRightCurlyInTryCatch,
+        );
+        should_fail_with(
+            &["try { x }", "try { x } { 0 }"],
+            ParseErrorKind::ExpectedCatchAfterTry,
+        );
+    }
+
     #[test]
     fn accumulate_errors() {
         // error on the last character of a line
@@ -3347,7 +6540,7 @@ mod tests {
             assert_eq(tamo + cool == 80)
             30m"), @r###"
         Successfully parsed:
-        DefineVariable(DefineVariable { identifier_span: Span { start: SourceCodePositition { byte: 17, line: 2, position: 17 }, end: SourceCodePositition { byte: 21, line: 2, position: 21 }, code_source_id: 0 }, identifier: "cool", expr: Scalar(Span { start: SourceCodePositition { byte: 24, line: 2, position: 24 }, end: SourceCodePositition { byte: 26, line: 2, position: 26 }, code_source_id: 0 }, Number(50.0)), type_annotation: None, decorators: [] })
+        DefineVariable(DefineVariable { pattern: Identifier(Span { start: SourceCodePositition { byte: 17, line: 2, position: 17 }, end: SourceCodePositition { byte: 21, line: 2, position: 21 }, code_source_id: 0 }, "cool"), expr: Scalar(Span { start: SourceCodePositition { byte: 24, line: 2, position: 24 }, end: SourceCodePositition { byte: 26, line: 2, position: 26 }, code_source_id: 0 }, Number(50.0)), type_annotation: None, decorators: [] })
         ProcedureCall(Span { start: SourceCodePositition { byte: 68, line: 4, position: 13 }, end: SourceCodePositition { byte: 77, line: 4, position: 22 }, code_source_id: 0 }, AssertEq, [BinaryOperator { op: Equal, lhs: BinaryOperator { op: Add, lhs: Identifier(Span { start: SourceCodePositition { byte: 78, line: 4, position: 23 }, end: SourceCodePositition { byte: 82, line: 4, position: 27 }, code_source_id: 0 }, "tamo"), rhs: Identifier(Span { start: SourceCodePositition { byte: 85, line: 4, position: 30 }, end: SourceCodePositition { byte: 89, line: 4, position: 34 }, code_source_id: 0 }, "cool"), span_op: Some(Span { start: SourceCodePositition { byte: 83, line: 4, position: 28 }, end: SourceCodePositition { byte: 84, line: 4, position: 29 }, code_source_id: 0 }) }, rhs: Scalar(Span { start: SourceCodePositition { byte: 93, line: 4, position: 38 }, end: SourceCodePositition { byte: 95, line: 4, position: 40 }, code_source_id: 0 }, Number(80.0)), span_op: Some(Span { start: SourceCodePositition { byte: 90, line: 4, position: 35 }, end: SourceCodePositition { byte: 92, line: 4, position: 37 }, code_source_id: 0 }) }])
         Expression(BinaryOperator { op: Mul, lhs: Scalar(Span { start: SourceCodePositition { byte: 109, line: 5, position: 13 }, end: SourceCodePositition { byte: 111, line: 5, position: 15 }, code_source_id: 0 }, Number(30.0)), rhs: Identifier(Span { start: SourceCodePositition { byte: 111, line: 5, position: 15 }, end: SourceCodePositition { byte: 112, line: 5, position: 16 }, code_source_id: 0 }, "m"), span_op: None })
         Errors encountered:
@@ -3386,4 +6579,77 @@ mod tests {
         Trailing '=' sign. Use `let x = …` if you intended to define a new constant. - ParseError { kind: TrailingEqualSign("x"), span: Span { start: SourceCodePositition { byte: 2, line: 1, position: 3 }, end: SourceCodePositition { byte: 3, line: 1, position: 4 }, code_source_id: 0 } }
         "###);
     }
+
+    #[test]
+    fn nesting_depth_limit() {
+        let parse_with_max_depth = |input: &str, max_nesting_depth: usize| {
+            parse_with_options(
+                input,
+                0,
+                ParserOptions {
+                    max_nesting_depth,
+                    ..Default::default()
+                },
+                &mut vec![],
+            )
+        };
+
+        // The top-level expression is the first level
+        let max = 16;
+        for (name, nested) in [
+            (
+                "parentheses",
+                (|n| format!("{}1{}", "(".repeat(n), ")".repeat(n))) as fn(usize) -> String,
+            ),
+            ("lists", |n| format!("{}1{}", "[".repeat(n), "]".repeat(n))),
+            ("negations", |n| format!("{}1", "-".repeat(n))),
+            ("calls", |n| format!("{}1{}", "f(".repeat(n), ")".repeat(n))),
+            ("conditions", |n| {
+                format!("{}1", "if c then 1 else ".repeat(n))
+            }),
+            ("powers", |n| format!("{}1", "2^".repeat(n))),
+            ("pipes", |n| format!("1{}", " |> f".repeat(n))),
+            ("types", |n| {
+                format!("let x: {}Length{} = 1", "List<".repeat(n), ">".repeat(n))
+            }),
+        ] {
+            assert!(
+                parse_with_max_depth(&nested(max - 1), max).is_ok(),
+                "{name} at the limit"
+            );
+            match parse_with_max_depth(&nested(max), max) {
+                Err((_, errors)) => {
+                    assert_eq!(
+                        errors[0].kind,
+                        ParseErrorKind::NestingTooDeep(max),
+                        "{name}"
+                    )
+                }
+                Ok(_) => panic!("{name} beyond the limit"),
+            }
+        }
+
+        // Chains of arithmetic operators are not limited
+        for chain in ["1 + ", "2 × ", "3 m / ", "4 - 5 ", "km per "] {
+            assert!(
+                parse_with_max_depth(&format!("{}1", chain.repeat(10 * max)), max).is_ok(),
+                "{chain}"
+            );
+        }
+    }
+
+    #[test]
+    fn large_flat_input() {
+        // A list literal with one million bytes
+        let input = format!("[{}1]", "1, ".repeat(333_333));
+
+        let start = std::time::Instant::now();
+        let statements = parse(&input, 0).expect("parse error");
+        assert!(start.elapsed() < std::time::Duration::from_secs(10));
+
+        let [Statement::Expression(Expression::List(_, elements))] = &statements[..] else {
+            panic!("Expected a single list literal");
+        };
+        assert_eq!(elements.len(), 333_334);
+    }
 }
@@ -3,7 +3,10 @@ use std::collections::HashMap;
 use std::sync::OnceLock;
 
 use crate::span::Span;
-use crate::{name_resolution::NameResolutionError, prefix::Prefix};
+use crate::{
+    name_resolution::{indexed_result_identifier, NameResolutionError},
+    prefix::Prefix,
+};
 
 static PREFIXES: OnceLock<Vec<(&'static str, &'static [&'static str], Prefix)>> = OnceLock::new();
 
@@ -12,6 +15,24 @@ pub enum PrefixParserResult {
     Identifier(String),
     /// Span, prefix, unit name in source (e.g. 'm'), full unit name (e.g. 'meter')
     UnitIdentifier(Span, Prefix, String, String),
+    /// The identifier can be read as several different (prefixed) units
+    AmbiguousUnitIdentifier(Vec<UnitInterpretation>),
+}
+
+/// One way of reading an identifier as a (prefixed) unit
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnitInterpretation {
+    pub definition_span: Span,
+    pub prefix: Prefix,
+    /// The prefix as written, e.g. 'k' in 'km'
+    pub prefix_in_source: &'static str,
+    /// The unit name as written, e.g. 'm' in 'km'
+    pub unit_name: String,
+    /// The full unit name, e.g. 'meter'
+    pub full_name: String,
+    /// An unambiguous way to write the same unit, e.g. 'kilometer'. Only
+    /// available if the full unit name accepts long prefixes.
+    pub long_form: Option<String>,
 }
 
 type Result<T> = std::result::Result<T, NameResolutionError>;
@@ -61,6 +82,90 @@ struct UnitInfo {
     full_name: String,
 }
 
+/// The unit or other identifier of a given name, see [`PrefixParser::take`]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PrefixParserEntry {
+    unit: Option<UnitInfo>,
+    other_identifier: Option<Span>,
+}
+
+impl UnitInfo {
+    fn accepts(&self, prefix: &Prefix) -> bool {
+        prefix.is_metric() && self.metric_prefixes || prefix.is_binary() && self.binary_prefixes
+    }
+}
+
+/// All ways of reading `input` as a unit, given the unit names (`units`, and the
+/// same information in the order of definition in `units_vec`). If `input` is the
+/// name of a unit, this is the only interpretation. Otherwise, every combination of
+/// a (long or short) prefix with a unit that accepts it is a candidate.
+fn resolve_unit(
+    input: &str,
+    units: &HashMap<String, UnitInfo>,
+    units_vec: &[(String, UnitInfo)],
+) -> Vec<UnitInterpretation> {
+    if let Some(info) = units.get(input) {
+        return vec![UnitInterpretation {
+            definition_span: info.definition_span,
+            prefix: Prefix::none(),
+            prefix_in_source: "",
+            unit_name: input.into(),
+            full_name: info.full_name.clone(),
+            long_form: None,
+        }];
+    }
+
+    let long_form = |prefix_long: &str, prefix: &Prefix, full_name: &str| {
+        units
+            .get(full_name)
+            .filter(|info| info.accepts_prefix.long && info.accepts(prefix))
+            .map(|_| format!("{prefix_long}{full_name}"))
+    };
+
+    let mut interpretations: Vec<UnitInterpretation> = vec![];
+    for (unit_name, info) in units_vec {
+        let Some(prefix_in_input) = input.strip_suffix(unit_name.as_str()) else {
+            continue;
+        };
+
+        for (prefix_long, prefixes_short, prefix) in PrefixParser::prefixes() {
+            if !info.accepts(prefix) {
+                continue;
+            }
+
+            let prefix_in_source = if info.accepts_prefix.long && prefix_in_input == *prefix_long {
+                *prefix_long
+            } else if let Some(prefix_short) = prefixes_short
+                .iter()
+                .find(|short| info.accepts_prefix.short && prefix_in_input == **short)
+            {
+                *prefix_short
+            } else {
+                continue;
+            };
+
+            // Different aliases of the same unit with the same prefix are not ambiguous
+            if interpretations
+                .iter()
+                .any(|i| i.prefix == *prefix && i.full_name == info.full_name)
+            {
+                continue;
+            }
+
+            interpretations.push(UnitInterpretation {
+                definition_span: info.definition_span,
+                prefix: *prefix,
+                prefix_in_source,
+                unit_name: unit_name.clone(),
+                full_name: info.full_name.clone(),
+                long_form: long_form(prefix_long, prefix, &info.full_name),
+            });
+        }
+    }
+
+    interpretations
+}
+
 #[derive(Debug, Clone)]
 pub struct PrefixParser {
     units: HashMap<String, UnitInfo>,
@@ -83,7 +188,7 @@ impl PrefixParser {
         }
     }
 
-    fn prefixes() -> &'static [(&'static str, &'static [&'static str], Prefix)] {
+    pub(crate) fn prefixes() -> &'static [(&'static str, &'static [&'static str], Prefix)] {
         PREFIXES.get_or_init(|| {
             vec![
                 // Metric prefixes:
@@ -135,44 +240,81 @@ impl PrefixParser {
         })
     }
 
+    /// The long name of a prefix, like `kilo` for [`Prefix::Metric(3)`].
+    pub(crate) fn long_prefix_name(prefix: Prefix) -> Option<&'static str> {
+        Self::prefixes()
+            .iter()
+            .find(|(_, _, p)| *p == prefix)
+            .map(|(long, _, _)| *long)
+    }
+
     fn identifier_clash_error(
         &self,
         name: &str,
         conflict_span: Span,
         original_span: Span,
+        original_item_type: Option<&str>,
     ) -> NameResolutionError {
         NameResolutionError::IdentifierClash {
             conflicting_identifier: name.to_string(),
-            original_item_type: None,
+            original_item_type: original_item_type.map(str::to_owned),
             conflict_span,
             original_span,
+            unit: None,
         }
     }
 
+    /// Make sure that `name` is not used yet. With `only_exact_names`, prefixed
+    /// forms of other units are not considered (see [`PrefixParser::add_unit`]).
     fn ensure_name_is_available(
         &self,
         name: &str,
         conflict_span: Span,
         clash_with_other_identifiers: bool,
+        only_exact_names: bool,
     ) -> Result<()> {
-        if self.reserved_identifiers.contains(&name) {
+        if self.reserved_identifiers.contains(&name) || indexed_result_identifier(name).is_some() {
             return Err(NameResolutionError::ReservedIdentifier(conflict_span));
         }
 
         if clash_with_other_identifiers {
             if let Some(original_span) = self.other_identifiers.get(name) {
-                return Err(self.identifier_clash_error(name, conflict_span, *original_span));
+                return Err(self.identifier_clash_error(name, conflict_span, *original_span, None));
             }
         }
 
+        if only_exact_names {
+            return match self.units.get(name) {
+                Some(info) => Err(self.identifier_clash_error(
+                    name,
+                    conflict_span,
+                    info.definition_span,
+                    Some("unit"),
+                )),
+                None => Ok(()),
+            };
+        }
+
         match self.parse(name) {
             PrefixParserResult::Identifier(_) => Ok(()),
             PrefixParserResult::UnitIdentifier(original_span, _, _, _) => {
-                Err(self.identifier_clash_error(name, conflict_span, original_span))
+                Err(self.identifier_clash_error(name, conflict_span, original_span, Some("unit")))
             }
+            PrefixParserResult::AmbiguousUnitIdentifier(interpretations) => Err(self
+                .identifier_clash_error(
+                    name,
+                    conflict_span,
+                    interpretations[0].definition_span,
+                    Some("unit"),
+                )),
         }
     }
 
+    /// Register a unit name. The name itself must not be in use yet, not even as a
+    /// prefixed form of another unit. Its prefixed forms only need to be distinct
+    /// from the names of other units and identifiers: if they coincide with a
+    /// prefixed form of another unit (like 'MiB' for mebi-'B' and mega-'iB'), using
+    /// that identifier is reported as ambiguous.
     pub fn add_unit(
         &mut self,
         unit_name: &str,
@@ -182,7 +324,7 @@ impl PrefixParser {
         full_name: &str,
         definition_span: Span,
     ) -> Result<()> {
-        self.ensure_name_is_available(unit_name, definition_span, true)?;
+        self.ensure_name_is_available(unit_name, definition_span, true, false)?;
 
         for (prefix_long, prefixes_short, prefix) in Self::prefixes() {
             if !(prefix.is_metric() && metric || prefix.is_binary() && binary) {
@@ -194,6 +336,7 @@ impl PrefixParser {
                     &format!("{}{}", prefix_long, unit_name),
                     definition_span,
                     true,
+                    true,
                 )?;
             }
             if accepts_prefix.short {
@@ -202,6 +345,7 @@ impl PrefixParser {
                         &format!("{}{}", prefix_short, unit_name),
                         definition_span,
                         true,
+                        true,
                     )?;
                 }
             }
@@ -220,8 +364,39 @@ impl PrefixParser {
         Ok(())
     }
 
+    /// Forgets about a unit (including its prefixed versions), so that a unit of the
+    /// same name can be defined in a local scope.
+    pub fn remove_unit(&mut self, unit_name: &str) {
+        self.units.remove(unit_name);
+        self.units_vec.retain(|(name, _)| name != unit_name);
+    }
+
+    /// Removes the unit or other identifier of the given name, such that it can be
+    /// brought back later with [`Self::restore`].
+    pub(crate) fn take(&mut self, name: &str) -> PrefixParserEntry {
+        let unit = self.units.remove(name);
+        self.units_vec.retain(|(unit_name, _)| unit_name != name);
+        PrefixParserEntry {
+            unit,
+            other_identifier: self.other_identifiers.remove(name),
+        }
+    }
+
+    /// Replaces the current definition of the name by one that has been removed
+    /// with [`Self::take`].
+    pub(crate) fn restore(&mut self, name: &str, entry: PrefixParserEntry) {
+        self.take(name);
+        if let Some(unit_info) = entry.unit {
+            self.units.insert(name.into(), unit_info.clone());
+            self.units_vec.push((name.into(), unit_info));
+        }
+        if let Some(span) = entry.other_identifier {
+            self.other_identifiers.insert(name.into(), span);
+        }
+    }
+
     pub fn add_other_identifier(&mut self, identifier: &str, definition_span: Span) -> Result<()> {
-        self.ensure_name_is_available(identifier, definition_span, false)?;
+        self.ensure_name_is_available(identifier, definition_span, false, false)?;
 
         self.other_identifiers
             .insert(identifier.into(), definition_span);
@@ -229,54 +404,20 @@ impl PrefixParser {
     }
 
     pub fn parse(&self, input: &str) -> PrefixParserResult {
-        if let Some(info) = self.units.get(input) {
-            return PrefixParserResult::UnitIdentifier(
-                info.definition_span,
-                Prefix::none(),
-                input.into(),
-                info.full_name.clone(),
-            );
-        }
-
-        for (unit_name, info) in &self.units_vec {
-            if !input.ends_with(unit_name.as_str()) {
-                continue;
-            }
-
-            for (prefix_long, prefixes_short, prefix) in Self::prefixes() {
-                let is_metric = prefix.is_metric();
-                let is_binary = prefix.is_binary();
-
-                if info.accepts_prefix.long
-                    && (is_metric && info.metric_prefixes || is_binary && info.binary_prefixes)
-                    && input.starts_with(prefix_long)
-                    && &input[prefix_long.len()..] == unit_name
-                {
-                    return PrefixParserResult::UnitIdentifier(
-                        info.definition_span,
-                        *prefix,
-                        unit_name.to_string(),
-                        info.full_name.clone(),
-                    );
-                }
-
-                if info.accepts_prefix.short
-                    && (is_metric && info.metric_prefixes || is_binary && info.binary_prefixes)
-                    && prefixes_short.iter().any(|prefix_short| {
-                        input.starts_with(prefix_short) && &input[prefix_short.len()..] == unit_name
-                    })
-                {
-                    return PrefixParserResult::UnitIdentifier(
-                        info.definition_span,
-                        *prefix,
-                        unit_name.to_string(),
-                        info.full_name.clone(),
-                    );
-                }
+        let mut interpretations = resolve_unit(input, &self.units, &self.units_vec);
+        match interpretations.len() {
+            0 => PrefixParserResult::Identifier(input.into()),
+            1 => {
+                let interpretation = interpretations.remove(0);
+                PrefixParserResult::UnitIdentifier(
+                    interpretation.definition_span,
+                    interpretation.prefix,
+                    interpretation.unit_name,
+                    interpretation.full_name,
+                )
             }
+            _ => PrefixParserResult::AmbiguousUnitIdentifier(interpretations),
         }
-
-        PrefixParserResult::Identifier(input.into())
     }
 }
 
@@ -550,4 +691,127 @@ mod tests {
             PrefixParserResult::Identifier("Kim".into())
         );
     }
+
+    /// 'B' (byte) with metric and binary prefixes, and 'iB' (a made-up unit) with
+    /// metric prefixes. 'MiB' is both mebi-'B' and mega-'iB'.
+    fn parser_with_ambiguity() -> PrefixParser {
+        let mut prefix_parser = PrefixParser::new();
+        for (name, accepts_prefix, binary, full_name) in [
+            ("byte", AcceptsPrefix::only_long(), true, "byte"),
+            ("B", AcceptsPrefix::only_short(), true, "byte"),
+            ("ibyte", AcceptsPrefix::only_long(), false, "ibyte"),
+            ("iB", AcceptsPrefix::only_short(), false, "ibyte"),
+        ] {
+            prefix_parser
+                .add_unit(name, accepts_prefix, true, binary, full_name, Span::dummy())
+                .unwrap();
+        }
+        prefix_parser
+    }
+
+    #[test]
+    fn exact_names_take_precedence() {
+        // Such tables can not be created via `add_unit`, which rejects 'kB' as
+        // the name of a unit if 'B' accepts metric prefixes.
+        let unit_info = |full_name: &str, accepts_prefix| UnitInfo {
+            definition_span: Span::dummy(),
+            accepts_prefix,
+            metric_prefixes: true,
+            binary_prefixes: false,
+            full_name: full_name.into(),
+        };
+        let units_vec = vec![
+            (
+                "B".to_string(),
+                unit_info("byte", AcceptsPrefix::only_short()),
+            ),
+            ("kB".to_string(), unit_info("kB", AcceptsPrefix::none())),
+        ];
+        let units = units_vec.iter().cloned().collect();
+
+        let interpretations = resolve_unit("kB", &units, &units_vec);
+        assert_eq!(interpretations.len(), 1);
+        assert_eq!(interpretations[0].prefix, Prefix::none());
+        assert_eq!(interpretations[0].full_name, "kB");
+
+        let interpretations = resolve_unit("MB", &units, &units_vec);
+        assert_eq!(interpretations.len(), 1);
+        assert_eq!(interpretations[0].prefix, Prefix::mega());
+
+        let mut prefix_parser = parser_with_ambiguity();
+        assert!(prefix_parser
+            .add_unit(
+                "kB",
+                AcceptsPrefix::none(),
+                false,
+                false,
+                "kB",
+                Span::dummy()
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn ambiguous_prefixes() {
+        let prefix_parser = parser_with_ambiguity();
+
+        let PrefixParserResult::AmbiguousUnitIdentifier(interpretations) =
+            prefix_parser.parse("MiB")
+        else {
+            panic!("'MiB' should be ambiguous");
+        };
+        let candidates: Vec<_> = interpretations
+            .iter()
+            .map(|i| {
+                (
+                    i.prefix,
+                    i.prefix_in_source,
+                    i.unit_name.as_str(),
+                    i.long_form.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            candidates,
+            [
+                (Prefix::mebi(), "Mi", "B", Some("mebibyte")),
+                (Prefix::mega(), "M", "iB", Some("megaibyte")),
+            ]
+        );
+    }
+
+    #[test]
+    fn long_forms_are_unambiguous() {
+        let prefix_parser = parser_with_ambiguity();
+
+        assert_eq!(
+            prefix_parser.parse("mebibyte"),
+            PrefixParserResult::UnitIdentifier(
+                Span::dummy(),
+                Prefix::mebi(),
+                "byte".into(),
+                "byte".into()
+            )
+        );
+        assert_eq!(
+            prefix_parser.parse("megaibyte"),
+            PrefixParserResult::UnitIdentifier(
+                Span::dummy(),
+                Prefix::mega(),
+                "ibyte".into(),
+                "ibyte".into()
+            )
+        );
+
+        // Only one of the two units accepts binary prefixes
+        assert_eq!(
+            prefix_parser.parse("KiB"),
+            PrefixParserResult::UnitIdentifier(
+                Span::dummy(),
+                Prefix::kibi(),
+                "B".into(),
+                "byte".into()
+            )
+        );
+    }
 }
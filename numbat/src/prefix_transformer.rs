@@ -1,9 +1,15 @@
+use std::collections::HashMap;
+
 use crate::{
-    ast::{DefineVariable, Expression, Statement, StringPart},
+    ast::{
+        formula_function_name, ConversionTarget, DefineVariable, Expression, ImportScope,
+        ModuleItem, Statement, StringPart,
+    },
     decorator::{self, Decorator},
-    name_resolution::NameResolutionError,
-    prefix_parser::{PrefixParser, PrefixParserResult},
+    name_resolution::{NameResolutionError, UnitCandidate},
+    prefix_parser::{PrefixParser, PrefixParserEntry, PrefixParserResult},
     span::Span,
+    symbol::Symbol,
 };
 
 type Result<T> = std::result::Result<T, NameResolutionError>;
@@ -16,6 +22,22 @@ pub(crate) struct Transformer {
     pub function_names: Vec<String>,
     pub unit_names: Vec<Vec<String>>,
     pub dimension_names: Vec<String>,
+
+    /// Definitions that have been set aside while a module is imported selectively
+    stashed_definitions: Vec<Vec<(Symbol, NameDefinition)>>,
+    /// Definitions of selectively imported modules that are not in scope
+    hidden_definitions: HashMap<ModuleItem, NameDefinition>,
+}
+
+/// Everything the transformer knows about a name, see [`Transformer::take_definition`]
+#[derive(Debug, Clone, Default)]
+struct NameDefinition {
+    prefix_parser_entry: PrefixParserEntry,
+    is_variable: bool,
+    is_function: bool,
+    is_dimension: bool,
+    /// The unit name, along with its aliases
+    unit_group: Option<Vec<String>>,
 }
 
 impl Transformer {
@@ -26,106 +48,185 @@ impl Transformer {
             function_names: vec![],
             unit_names: vec![],
             dimension_names: vec![],
+            stashed_definitions: vec![],
+            hidden_definitions: HashMap::new(),
         }
     }
 
-    fn transform_expression(&self, expression: Expression) -> Expression {
+    fn transform_expression(&self, mut expression: Expression) -> Result<Expression> {
+        self.transform_expression_in_place(&mut expression)?;
+        Ok(expression)
+    }
+
+    fn transform_expression_in_place(&self, expression: &mut Expression) -> Result<()> {
         match expression {
-            expr @ Expression::Scalar(..) => expr,
+            Expression::Scalar(..) | Expression::Boolean(..) | Expression::TypedHole(..) => {}
             Expression::Identifier(span, identifier) => {
-                if let PrefixParserResult::UnitIdentifier(
-                    _definition_span,
-                    prefix,
-                    unit_name,
-                    full_name,
-                ) = self.prefix_parser.parse(&identifier)
-                {
-                    Expression::UnitIdentifier(span, prefix, unit_name, full_name)
-                } else {
-                    Expression::Identifier(span, identifier)
+                match self.prefix_parser.parse(identifier) {
+                    PrefixParserResult::UnitIdentifier(
+                        _definition_span,
+                        prefix,
+                        unit_name,
+                        full_name,
+                    ) => {
+                        *expression = Expression::UnitIdentifier(
+                            *span,
+                            prefix,
+                            unit_name.into(),
+                            full_name.into(),
+                        )
+                    }
+                    PrefixParserResult::AmbiguousUnitIdentifier(interpretations) => {
+                        return Err(NameResolutionError::AmbiguousUnit {
+                            identifier: identifier.to_string(),
+                            span: *span,
+                            candidates: interpretations
+                                .into_iter()
+                                .map(|i| UnitCandidate {
+                                    prefix: i.prefix_in_source.to_string(),
+                                    unit: i.unit_name,
+                                    full_name: i.full_name,
+                                    long_form: i.long_form,
+                                    dimension: None,
+                                })
+                                .collect(),
+                        });
+                    }
+                    PrefixParserResult::Identifier(_) => {}
                 }
             }
             Expression::UnitIdentifier(_, _, _, _) => {
                 unreachable!("Prefixed identifiers should not exist prior to this stage")
             }
-            Expression::UnaryOperator { op, expr, span_op } => Expression::UnaryOperator {
-                op,
-                expr: Box::new(self.transform_expression(*expr)),
-                span_op,
-            },
-            Expression::BinaryOperator {
-                op,
-                lhs,
-                rhs,
-                span_op,
-            } => Expression::BinaryOperator {
-                op,
-                lhs: Box::new(self.transform_expression(*lhs)),
-                rhs: Box::new(self.transform_expression(*rhs)),
-                span_op,
-            },
-            Expression::FunctionCall(span, full_span, name, args) => Expression::FunctionCall(
-                span,
-                full_span,
-                name,
-                args.into_iter()
-                    .map(|arg| self.transform_expression(arg))
-                    .collect(),
-            ),
-            expr @ Expression::Boolean(_, _) => expr,
-            Expression::Condition(span, condition, then, else_) => Expression::Condition(
-                span,
-                Box::new(self.transform_expression(*condition)),
-                Box::new(self.transform_expression(*then)),
-                Box::new(self.transform_expression(*else_)),
-            ),
-            Expression::String(span, parts) => Expression::String(
-                span,
-                parts
-                    .into_iter()
-                    .map(|p| match p {
-                        f @ StringPart::Fixed(_) => f,
-                        StringPart::Interpolation {
-                            span,
-                            expr,
-                            format_specifiers,
-                        } => StringPart::Interpolation {
-                            span,
-                            expr: Box::new(self.transform_expression(*expr)),
-                            format_specifiers,
-                        },
-                    })
-                    .collect(),
-            ),
-            Expression::InstantiateStruct {
-                full_span,
-                ident_span,
-                name,
-                fields,
-            } => Expression::InstantiateStruct {
-                full_span,
-                ident_span,
-                name,
-                fields: fields
-                    .into_iter()
-                    .map(|(span, attr, arg)| (span, attr, self.transform_expression(arg)))
-                    .collect(),
-            },
-            Expression::AccessField(full_span, ident_span, expr, attr) => Expression::AccessField(
-                full_span,
-                ident_span,
-                Box::new(self.transform_expression(*expr)),
-                attr,
-            ),
-            Expression::List(span, elements) => Expression::List(
-                span,
-                elements
-                    .into_iter()
-                    .map(|e| self.transform_expression(e))
-                    .collect(),
-            ),
-            hole @ Expression::TypedHole(_) => hole,
+            Expression::UnaryOperator { expr, .. }
+            | Expression::AccessField(_, _, expr, _)
+            | Expression::Trace(_, _, expr)
+            | Expression::AutoDiff(_, expr)
+            | Expression::DimensionPredicate(_, expr, _) => {
+                self.transform_expression_in_place(expr)?
+            }
+            Expression::BinaryOperator { .. } => {
+                // Long chains like `a + b + c + …` are transformed without recursing
+                // into every left hand side, from left to right
+                let (first, operands) = expression.binary_operator_chain_mut();
+                self.transform_expression_in_place(first)?;
+                for (_, _, rhs) in operands {
+                    self.transform_expression_in_place(rhs)?;
+                }
+            }
+            Expression::Conversion { source, target, .. } => {
+                self.transform_expression_in_place(source)?;
+                match target {
+                    ConversionTarget::Expression(expr) => {
+                        self.transform_expression_in_place(expr)?
+                    }
+                    ConversionTarget::UnitList(_, units) => {
+                        self.transform_all(units)?;
+                    }
+                }
+            }
+            Expression::ChainedComparison(first, rest) => {
+                self.transform_expression_in_place(first)?;
+                for (_, _, rhs) in rest {
+                    self.transform_expression_in_place(rhs)?;
+                }
+            }
+            Expression::FunctionCall(_, _, callable, args, named_args, _)
+            | Expression::Partial(_, callable, args, named_args) => {
+                self.transform_expression_in_place(callable)?;
+                self.transform_all(args)?;
+                for (_, _, arg) in named_args {
+                    self.transform_expression_in_place(arg)?;
+                }
+            }
+            Expression::Condition(_, condition, then, else_) => {
+                self.transform_expression_in_place(condition)?;
+                self.transform_expression_in_place(then)?;
+                self.transform_expression_in_place(else_)?;
+            }
+            Expression::String(_, parts) => {
+                for part in parts {
+                    if let StringPart::Interpolation { expr, .. } = part {
+                        self.transform_expression_in_place(expr)?;
+                    }
+                }
+            }
+            Expression::InstantiateStruct { base, fields, .. } => {
+                if let Some(base) = base {
+                    self.transform_expression_in_place(base)?;
+                }
+                for (_, _, arg) in fields {
+                    self.transform_expression_in_place(arg)?;
+                }
+            }
+            Expression::List(_, elements) => self.transform_all(elements)?,
+            Expression::For {
+                variable,
+                list,
+                accumulator,
+                body,
+                ..
+            } => {
+                self.transform_expression_in_place(list)?;
+                if let Some((_, _, init)) = accumulator {
+                    self.transform_expression_in_place(init)?;
+                }
+
+                // Like function parameters, the loop variable and the accumulator
+                // are only known inside the body, but may not shadow units.
+                let mut body_transformer = self.clone();
+                body_transformer
+                    .prefix_parser
+                    .add_other_identifier(&variable.1, variable.0)?;
+                if let Some((span, name, _)) = accumulator {
+                    body_transformer
+                        .prefix_parser
+                        .add_other_identifier(name, *span)?;
+                }
+
+                body_transformer.transform_expression_in_place(body)?;
+            }
+            Expression::While {
+                condition,
+                accumulator: (span, name, init),
+                body,
+                ..
+            } => {
+                self.transform_expression_in_place(init)?;
+
+                let mut body_transformer = self.clone();
+                body_transformer
+                    .prefix_parser
+                    .add_other_identifier(name, *span)?;
+
+                body_transformer.transform_expression_in_place(condition)?;
+                body_transformer.transform_expression_in_place(body)?;
+            }
+            Expression::TryCatch {
+                expr,
+                message,
+                fallback,
+                ..
+            } => {
+                self.transform_expression_in_place(expr)?;
+
+                let mut fallback_transformer = self.clone();
+                if let Some((span, name)) = message {
+                    fallback_transformer
+                        .prefix_parser
+                        .add_other_identifier(name, *span)?;
+                }
+
+                fallback_transformer.transform_expression_in_place(fallback)?;
+            }
         }
+        Ok(())
+    }
+
+    fn transform_all(&self, expressions: &mut [Expression]) -> Result<()> {
+        expressions
+            .iter_mut()
+            .try_for_each(|expr| self.transform_expression_in_place(expr))
     }
 
     fn has_decorator(decorators: &[Decorator], decorator: Decorator) -> bool {
@@ -134,7 +235,7 @@ impl Transformer {
 
     pub(crate) fn register_name_and_aliases(
         &mut self,
-        name: &String,
+        name: Symbol,
         decorators: &[Decorator],
         conflict_span: Span,
     ) -> Result<()> {
@@ -143,11 +244,11 @@ impl Transformer {
         let binary_prefixes = Self::has_decorator(decorators, Decorator::BinaryPrefixes);
         for (alias, accepts_prefix) in decorator::name_and_aliases(name, decorators) {
             self.prefix_parser.add_unit(
-                alias,
+                &alias,
                 accepts_prefix,
                 metric_prefixes,
                 binary_prefixes,
-                name,
+                &name,
                 conflict_span,
             )?;
             unit_names.push(alias.to_string());
@@ -159,27 +260,127 @@ impl Transformer {
         Ok(())
     }
 
+    fn transform_local_unit(&mut self, statement: Statement) -> Result<Statement> {
+        if let Statement::DefineBaseUnit(_, name, _, decorators)
+        | Statement::DefineDerivedUnit {
+            identifier: name,
+            decorators,
+            ..
+        } = &statement
+        {
+            for (alias, _) in decorator::name_and_aliases(*name, decorators) {
+                self.prefix_parser.remove_unit(&alias);
+            }
+        }
+        self.transform_statement(statement)
+    }
+
+    /// Removes all definitions of the given name, see [`ImportScope`]
+    fn take_definition(&mut self, name: Symbol) -> NameDefinition {
+        let name = name.as_str();
+        let take_from = |names: &mut Vec<String>| {
+            let found = names.iter().any(|n| n == name);
+            names.retain(|n| n != name);
+            found
+        };
+        let definition = NameDefinition {
+            prefix_parser_entry: self.prefix_parser.take(name),
+            is_variable: take_from(&mut self.variable_names),
+            is_function: take_from(&mut self.function_names),
+            is_dimension: take_from(&mut self.dimension_names),
+            unit_group: self
+                .unit_names
+                .iter()
+                .find(|group| group.iter().any(|n| n == name))
+                .cloned(),
+        };
+        for group in &mut self.unit_names {
+            group.retain(|n| n != name);
+        }
+        self.unit_names.retain(|group| !group.is_empty());
+        definition
+    }
+
+    /// Replaces the current definitions of the name by ones that have been
+    /// removed with [`Self::take_definition`]
+    fn restore_definition(&mut self, name: Symbol, definition: NameDefinition) {
+        self.take_definition(name);
+        self.prefix_parser
+            .restore(name.as_str(), definition.prefix_parser_entry);
+        for (is_defined, names) in [
+            (definition.is_variable, &mut self.variable_names),
+            (definition.is_function, &mut self.function_names),
+            (definition.is_dimension, &mut self.dimension_names),
+        ] {
+            if is_defined {
+                names.push(name.to_string());
+            }
+        }
+        if let Some(unit_group) = definition.unit_group {
+            // Aliases that are restored one after the other end up in the same group
+            match self
+                .unit_names
+                .iter_mut()
+                .find(|group| group.iter().any(|n| unit_group.contains(n)))
+            {
+                Some(group) => {
+                    group.push(name.to_string());
+                    group.sort();
+                }
+                None => self.unit_names.push(vec![name.to_string()]),
+            }
+        }
+    }
+
+    fn apply_import_scope(&mut self, scope: &ImportScope) {
+        match scope {
+            ImportScope::Begin(names) => {
+                let stashed = names
+                    .iter()
+                    .map(|name| (*name, self.take_definition(*name)))
+                    .collect();
+                self.stashed_definitions.push(stashed);
+            }
+            ImportScope::End(items) => {
+                for item in items {
+                    let definition = self.take_definition(item.1);
+                    self.hidden_definitions.insert(item.clone(), definition);
+                }
+                for (name, definition) in self.stashed_definitions.pop().unwrap_or_default() {
+                    self.restore_definition(name, definition);
+                }
+            }
+            ImportScope::Reveal(items) => {
+                for item in items {
+                    if let Some(definition) = self.hidden_definitions.remove(item) {
+                        self.restore_definition(item.1, definition);
+                    }
+                }
+            }
+        }
+    }
+
     fn transform_define_variable(
         &mut self,
         define_variable: DefineVariable,
     ) -> Result<DefineVariable> {
         let DefineVariable {
-            identifier_span,
-            identifier,
+            pattern,
             expr,
             type_annotation,
             decorators,
         } = define_variable;
 
-        for (name, _) in decorator::name_and_aliases(&identifier, &decorators) {
-            self.variable_names.push(name.clone());
+        for (identifier_span, identifier) in pattern.identifiers() {
+            for (name, _) in decorator::name_and_aliases(identifier, &decorators) {
+                self.variable_names.push(name.to_string());
+            }
+            self.prefix_parser
+                .add_other_identifier(&identifier, identifier_span)?;
         }
-        self.prefix_parser
-            .add_other_identifier(&identifier, identifier_span)?;
         Ok(DefineVariable {
-            identifier_span,
-            identifier,
-            expr: self.transform_expression(expr),
+            pattern,
+            expr: self.transform_expression(expr)?,
             type_annotation,
             decorators,
         })
@@ -187,9 +388,9 @@ impl Transformer {
 
     fn transform_statement(&mut self, statement: Statement) -> Result<Statement> {
         Ok(match statement {
-            Statement::Expression(expr) => Statement::Expression(self.transform_expression(expr)),
+            Statement::Expression(expr) => Statement::Expression(self.transform_expression(expr)?),
             Statement::DefineBaseUnit(span, name, dexpr, decorators) => {
-                self.register_name_and_aliases(&name, &decorators, span)?;
+                self.register_name_and_aliases(name, &decorators, span)?;
                 Statement::DefineBaseUnit(span, name, dexpr, decorators)
             }
             Statement::DefineDerivedUnit {
@@ -200,11 +401,11 @@ impl Transformer {
                 type_annotation,
                 decorators,
             } => {
-                self.register_name_and_aliases(&identifier, &decorators, identifier_span)?;
+                self.register_name_and_aliases(identifier, &decorators, identifier_span)?;
                 Statement::DefineDerivedUnit {
                     identifier_span,
                     identifier,
-                    expr: self.transform_expression(expr),
+                    expr: self.transform_expression(expr)?,
                     type_annotation_span,
                     type_annotation,
                     decorators,
@@ -220,10 +421,11 @@ impl Transformer {
                 parameters,
                 body,
                 local_variables,
+                local_units,
                 return_type_annotation,
                 decorators,
             } => {
-                self.function_names.push(function_name.clone());
+                self.function_names.push(function_name.to_string());
                 self.prefix_parser
                     .add_other_identifier(&function_name, function_name_span)?;
 
@@ -236,22 +438,44 @@ impl Transformer {
                 //   fn foo(t: Time) -> Time = t    # not okay: shadows 't' for ton
                 //
                 let mut fn_body_transformer = self.clone();
-                for (param_span, param, _) in &parameters {
+                for (param_span, param, _, _) in &parameters {
                     fn_body_transformer
                         .prefix_parser
                         .add_other_identifier(param, *param_span)?;
                 }
 
+                // Units from the `where` clause are only known inside the function
+                // body, where they may shadow global units of the same name.
+                let local_units = local_units
+                    .into_iter()
+                    .map(|statement| fn_body_transformer.transform_local_unit(statement))
+                    .collect::<Result<_>>()?;
+
                 Statement::DefineFunction {
                     function_name_span,
                     function_name,
                     type_parameters,
-                    parameters,
-                    body: body.map(|expr| self.transform_expression(expr)),
+                    parameters: parameters
+                        .into_iter()
+                        .map(|(span, name, type_annotation, default)| {
+                            Ok((
+                                span,
+                                name,
+                                type_annotation,
+                                default
+                                    .map(|expr| fn_body_transformer.transform_expression(expr))
+                                    .transpose()?,
+                            ))
+                        })
+                        .collect::<Result<_>>()?,
                     local_variables: local_variables
                         .into_iter()
-                        .map(|def| self.transform_define_variable(def))
+                        .map(|def| fn_body_transformer.transform_define_variable(def))
                         .collect::<Result<_>>()?,
+                    body: body
+                        .map(|expr| fn_body_transformer.transform_expression(expr))
+                        .transpose()?,
+                    local_units,
                     return_type_annotation,
                     decorators,
                 }
@@ -266,7 +490,7 @@ impl Transformer {
                 fields,
             },
             Statement::DefineDimension(name_span, name, dexprs) => {
-                self.dimension_names.push(name.clone());
+                self.dimension_names.push(name.to_string());
                 Statement::DefineDimension(name_span, name, dexprs)
             }
             Statement::ProcedureCall(span, procedure, args) => Statement::ProcedureCall(
@@ -274,9 +498,113 @@ impl Transformer {
                 procedure,
                 args.into_iter()
                     .map(|arg| self.transform_expression(arg))
-                    .collect(),
+                    .collect::<Result<_>>()?,
             ),
-            statement @ Statement::ModuleImport(_, _) => statement,
+            Statement::Conditional {
+                span_if,
+                condition,
+                then_statement,
+                else_statement,
+            } => Statement::Conditional {
+                span_if,
+                condition: self.transform_expression(condition)?,
+                then_statement: Box::new(self.transform_statement(*then_statement)?),
+                else_statement: else_statement
+                    .map(|s| self.transform_statement(*s).map(Box::new))
+                    .transpose()?,
+            },
+            Statement::DefineUnitSystem {
+                system_name_span,
+                system_name,
+                units,
+            } => {
+                // Unit systems can be used like conversion functions (`-> SI`)
+                self.function_names.push(system_name.to_string());
+                self.prefix_parser
+                    .add_other_identifier(&system_name, system_name_span)?;
+
+                Statement::DefineUnitSystem {
+                    system_name_span,
+                    system_name,
+                    units: units
+                        .into_iter()
+                        .map(|unit| self.transform_expression(unit))
+                        .collect::<Result<_>>()?,
+                }
+            }
+            statement @ Statement::ModuleImport(..) => statement,
+            Statement::ImportScope(span, scope) => {
+                self.apply_import_scope(&scope);
+                Statement::ImportScope(span, scope)
+            }
+            Statement::DefineUnitFunction { function, inverse } => {
+                let function = self.transform_statement(*function)?;
+                let inverse = inverse
+                    .map(|inverse| {
+                        let inverse = self.transform_statement(*inverse)?;
+                        // The inverse is only used through conversions, so it is not
+                        // listed along with the other functions
+                        self.function_names.pop();
+                        Ok(Box::new(inverse))
+                    })
+                    .transpose()?;
+                Statement::DefineUnitFunction {
+                    function: Box::new(function),
+                    inverse,
+                }
+            }
+            Statement::DefineMethods {
+                struct_name_span,
+                struct_name,
+                functions,
+            } => {
+                let functions = functions
+                    .into_iter()
+                    .map(|function| {
+                        let function = self.transform_statement(function)?;
+                        // Methods are called with method call syntax, they are not
+                        // listed along with the other functions
+                        self.function_names.pop();
+                        Ok(function)
+                    })
+                    .collect::<Result<_>>()?;
+                Statement::DefineMethods {
+                    struct_name_span,
+                    struct_name,
+                    functions,
+                }
+            }
+            Statement::DefineFormula {
+                formula_name_span,
+                formula_name,
+                quantities,
+                lhs,
+                rhs,
+            } => {
+                for (_, quantity, _) in &quantities {
+                    let function_name = formula_function_name(formula_name, *quantity);
+                    self.function_names.push(function_name.to_string());
+                    self.prefix_parser
+                        .add_other_identifier(&function_name, formula_name_span)?;
+                }
+
+                // Like function parameters, the quantities are only known inside
+                // of the formula
+                let mut formula_transformer = self.clone();
+                for (span, quantity, _) in &quantities {
+                    formula_transformer
+                        .prefix_parser
+                        .add_other_identifier(quantity, *span)?;
+                }
+
+                Statement::DefineFormula {
+                    formula_name_span,
+                    formula_name,
+                    lhs: formula_transformer.transform_expression(lhs)?,
+                    rhs: formula_transformer.transform_expression(rhs)?,
+                    quantities,
+                }
+            }
         })
     }
 
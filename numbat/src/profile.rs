@@ -0,0 +1,218 @@
+//! Time spent in functions, see [`crate::Context::set_profiling`].
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::markup::{self as m, Markup};
+
+/// Number of calls and time spent in a single function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionProfile {
+    pub name: String,
+
+    /// Whether this is a foreign function (implemented in Rust, or provided by the
+    /// embedding application), as opposed to a function defined in Numbat.
+    pub native: bool,
+
+    pub calls: u64,
+
+    /// Time spent in the function, including the functions it calls. Recursive
+    /// calls are only counted once.
+    pub inclusive_time: Duration,
+
+    /// Time spent in the function itself, excluding the functions it calls.
+    pub exclusive_time: Duration,
+}
+
+/// Time spent in all functions that have been called, sorted by exclusive time
+/// (the most expensive function first).
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Profile {
+    pub functions: Vec<FunctionProfile>,
+}
+
+impl Profile {
+    pub fn get(&self, name: &str) -> Option<&FunctionProfile> {
+        self.functions.iter().find(|f| f.name == name)
+    }
+
+    /// A table with one row per function.
+    pub fn to_markup(&self) -> Markup {
+        let name = |f: &FunctionProfile| {
+            if f.native {
+                format!("{} (native)", f.name)
+            } else {
+                f.name.clone()
+            }
+        };
+        let milliseconds = |d: Duration| format!("{:.3} ms", d.as_secs_f64() * 1e3);
+
+        let rows: Vec<[String; 4]> = self
+            .functions
+            .iter()
+            .map(|f| {
+                [
+                    name(f),
+                    f.calls.to_string(),
+                    milliseconds(f.inclusive_time),
+                    milliseconds(f.exclusive_time),
+                ]
+            })
+            .collect();
+
+        let header = ["Function", "Calls", "Inclusive", "Exclusive"];
+        let widths: Vec<usize> = (0..header.len())
+            .map(|i| {
+                rows.iter()
+                    .map(|row| row[i].chars().count())
+                    .chain([header[i].len()])
+                    .max()
+                    .unwrap()
+            })
+            .collect();
+
+        let mut markup = m::emphasized(format!(
+            "{:<w0$}  {:>w1$}  {:>w2$}  {:>w3$}",
+            header[0],
+            header[1],
+            header[2],
+            header[3],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+            w3 = widths[3],
+        )) + m::nl();
+
+        for [name, calls, inclusive, exclusive] in rows {
+            markup += m::identifier(format!("{name:<w$}", w = widths[0]))
+                + m::space()
+                + m::space()
+                + m::value(format!("{calls:>w$}", w = widths[1]))
+                + m::space()
+                + m::space()
+                + m::text(format!("{inclusive:>w$}", w = widths[2]))
+                + m::space()
+                + m::space()
+                + m::text(format!("{exclusive:>w$}", w = widths[3]))
+                + m::nl();
+        }
+
+        markup
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    native: bool,
+    calls: u64,
+    inclusive_time: Duration,
+    exclusive_time: Duration,
+
+    /// Number of calls to this function that are currently active
+    depth: u32,
+}
+
+#[derive(Debug, Clone)]
+struct ActiveCall {
+    entry: usize,
+    start: Instant,
+
+    /// Time spent in functions called by this one (so far)
+    children: Duration,
+}
+
+/// Records function calls while the VM is running.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Profiler {
+    names: HashMap<String, usize>,
+    entries: Vec<(String, Entry)>,
+    active: Vec<ActiveCall>,
+}
+
+impl Profiler {
+    pub fn enter(&mut self, name: &str, native: bool) {
+        let entry = match self.names.get(name) {
+            Some(&entry) => entry,
+            None => {
+                self.entries.push((
+                    name.to_string(),
+                    Entry {
+                        native,
+                        calls: 0,
+                        inclusive_time: Duration::ZERO,
+                        exclusive_time: Duration::ZERO,
+                        depth: 0,
+                    },
+                ));
+                self.names.insert(name.to_string(), self.entries.len() - 1);
+                self.entries.len() - 1
+            }
+        };
+
+        let (_, e) = &mut self.entries[entry];
+        e.calls += 1;
+        e.depth += 1;
+
+        self.active.push(ActiveCall {
+            entry,
+            start: Instant::now(),
+            children: Duration::ZERO,
+        });
+    }
+
+    /// Ends the most recently entered call.
+    pub fn exit(&mut self) {
+        let Some(call) = self.active.pop() else {
+            return;
+        };
+        let elapsed = call.start.elapsed();
+
+        let (_, e) = &mut self.entries[call.entry];
+        e.exclusive_time += elapsed.saturating_sub(call.children);
+        e.depth -= 1;
+        // For recursive functions, the outermost call already includes the time of
+        // all inner calls
+        if e.depth == 0 {
+            e.inclusive_time += elapsed;
+        }
+
+        if let Some(parent) = self.active.last_mut() {
+            parent.children += elapsed;
+        }
+    }
+
+    /// Ends all active calls, e.g. after a runtime error.
+    pub fn unwind(&mut self) {
+        self.unwind_to(0);
+    }
+
+    /// Number of calls that are currently active.
+    pub fn depth(&self) -> usize {
+        self.active.len()
+    }
+
+    /// Ends active calls until only `depth` of them are left, e.g. after an error
+    /// has been caught.
+    pub fn unwind_to(&mut self, depth: usize) {
+        while self.active.len() > depth {
+            self.exit();
+        }
+    }
+
+    pub fn profile(&self) -> Profile {
+        let mut functions: Vec<FunctionProfile> = self
+            .entries
+            .iter()
+            .map(|(name, e)| FunctionProfile {
+                name: name.clone(),
+                native: e.native,
+                calls: e.calls,
+                inclusive_time: e.inclusive_time,
+                exclusive_time: e.exclusive_time,
+            })
+            .collect();
+        functions.sort_by_key(|f| std::cmp::Reverse(f.exclusive_time));
+
+        Profile { functions }
+    }
+}
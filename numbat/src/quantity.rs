@@ -1,7 +1,8 @@
 use crate::arithmetic::{Exponent, Power, Rational};
+use crate::error_code::ErrorCode;
 use crate::number::Number;
 use crate::pretty_print::PrettyPrint;
-use crate::unit::{is_multiple_of, Unit, UnitFactor};
+use crate::unit::{is_multiple_of, ConversionFactor, Unit, UnitFactor};
 
 use itertools::Itertools;
 use num_rational::Ratio;
@@ -17,30 +18,59 @@ pub enum QuantityError {
     NonRationalExponent,
 }
 
+impl QuantityError {
+    pub fn error_code(&self) -> ErrorCode {
+        match self {
+            QuantityError::IncompatibleUnits(..) => ErrorCode::E0818,
+            QuantityError::NonRationalExponent => ErrorCode::E0819,
+        }
+    }
+}
+
 pub type Result<T> = std::result::Result<T, QuantityError>;
 
+/// All integers below this bound can be represented exactly by an `f64`. Results at
+/// or above it may have been rounded (`2^53 + 1` is stored as `2^53`).
+const MAX_EXACT_INTEGER: f64 = 9_007_199_254_740_992.0; // 2^53
+
+fn is_exact_integer(value: f64) -> bool {
+    value.fract() == 0.0 && value.abs() < MAX_EXACT_INTEGER
+}
+
 #[derive(Debug, Clone)]
 pub struct Quantity {
     value: Number,
     unit: Unit,
+    /// Whether the value is known to be an exact integer. This is a runtime-only
+    /// refinement: it is set for integer literals and the results of integer
+    /// operations, and preserved by `+`, `-`, `*` and negation. A division (or
+    /// any other operation) drops it, even if the result happens to be integral.
+    is_integer: bool,
 }
 
 impl Quantity {
     pub fn new(value: Number, unit: Unit) -> Self {
-        Quantity { value, unit }
-    }
-
-    pub fn new_f64(value: f64, unit: Unit) -> Self {
         Quantity {
-            value: Number::from_f64(value),
+            value,
             unit,
+            is_integer: false,
         }
     }
 
+    pub fn new_f64(value: f64, unit: Unit) -> Self {
+        Quantity::new(Number::from_f64(value), unit)
+    }
+
     pub fn from_scalar(value: f64) -> Quantity {
         Quantity::new_f64(value, Unit::scalar())
     }
 
+    /// A scalar that is known to be an integer, if `value` is integral and small
+    /// enough to be represented exactly. Otherwise, a plain scalar.
+    pub fn from_integer(value: f64) -> Quantity {
+        Quantity::from_scalar(value).with_integer_flag(true)
+    }
+
     pub fn from_unit(unit: Unit) -> Quantity {
         Quantity::new_f64(1.0, unit)
     }
@@ -53,6 +83,31 @@ impl Quantity {
         self.value.to_f64() == 0.0
     }
 
+    pub fn is_nan(&self) -> bool {
+        self.value.to_f64().is_nan()
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.is_integer
+    }
+
+    /// Set the integer flag if `is_integer` holds and the value is still exactly
+    /// representable (results of `+`, `-` and `*` can leave the exact range).
+    pub(crate) fn with_integer_flag(mut self, is_integer: bool) -> Self {
+        self.is_integer = is_integer && is_exact_integer(self.value.to_f64());
+        self
+    }
+
+    /// The same quantity, with the unit written out in full: `3 kilometres` instead of
+    /// `3 km`. The plural form is used unless the value is (minus) one.
+    pub fn with_long_unit_names(&self) -> Quantity {
+        let plural = self.value.to_f64().abs() != 1.0;
+        Quantity {
+            unit: self.unit.with_long_names(plural),
+            ..self.clone()
+        }
+    }
+
     pub fn to_base_unit_representation(&self) -> Quantity {
         let (unit, factor) = self.unit.to_base_unit_representation();
         Quantity::new(self.value * factor, unit)
@@ -60,67 +115,85 @@ impl Quantity {
 
     pub fn convert_to(&self, target_unit: &Unit) -> Result<Quantity> {
         if &self.unit == target_unit || self.unsafe_value().to_f64().is_zero() {
-            Ok(Quantity::new(self.value, target_unit.clone()))
+            Ok(Quantity {
+                value: self.value,
+                unit: target_unit.clone(),
+                is_integer: self.is_integer,
+            })
         } else {
-            // Remove common unit factors to reduce unnecessary conversion procedures
-            // For example: when converting from km/hour to mile/hour, there is no need
-            // to also perform the hour->second conversion, which would be needed, as
-            // we go back to base units for now. Removing common factors is just one
-            // heuristic, but it would be better to solve this in a more general way.
-            // For more details on this problem, see [1].
-            //
-            // [1] https://github.com/sharkdp/numbat/issues/118.
-            let mut common_unit_factors = Unit::scalar();
-            let target_unit_canonicalized = target_unit.canonicalized();
-
-            for factor in self.unit.canonicalized().iter() {
-                if let Some(other_factor) = target_unit_canonicalized
-                    .iter()
-                    .find(|&f| factor.prefix == f.prefix && factor.unit_id == f.unit_id)
-                {
-                    if factor.exponent > Ratio::zero() && other_factor.exponent > Ratio::zero() {
-                        common_unit_factors = common_unit_factors
-                            * Unit::from_factor(UnitFactor {
-                                exponent: std::cmp::min(factor.exponent, other_factor.exponent),
-                                ..factor.clone()
-                            });
-                    } else if factor.exponent < Ratio::zero()
-                        && other_factor.exponent < Ratio::zero()
-                    {
-                        common_unit_factors = common_unit_factors
-                            * Unit::from_factor(UnitFactor {
-                                exponent: std::cmp::max(factor.exponent, other_factor.exponent),
-                                ..factor.clone()
-                            });
-                    }
+            let factors = Self::conversion_factors(&self.unit, target_unit)?;
+            Ok(self.convert_with_factors(factors, target_unit))
+        }
+    }
+
+    /// Convert to `target_unit`, given the conversion factors that have been computed
+    /// by [`Quantity::conversion_factors`].
+    pub(crate) fn convert_with_factors(
+        &self,
+        (own_factor, target_factor): (ConversionFactor, ConversionFactor),
+        target_unit: &Unit,
+    ) -> Quantity {
+        Quantity::new(self.value * own_factor / target_factor, target_unit.clone())
+    }
+
+    /// Compute the factors `(a, b)` such that a value `v` in the unit `unit` equals
+    /// `v × a / b` in `target_unit`.
+    pub(crate) fn conversion_factors(
+        unit: &Unit,
+        target_unit: &Unit,
+    ) -> Result<(ConversionFactor, ConversionFactor)> {
+        // Remove common unit factors to reduce unnecessary conversion procedures
+        // For example: when converting from km/hour to mile/hour, there is no need
+        // to also perform the hour->second conversion, which would be needed, as
+        // we go back to base units for now. Removing common factors is just one
+        // heuristic, but it would be better to solve this in a more general way.
+        // For more details on this problem, see [1].
+        //
+        // [1] https://github.com/sharkdp/numbat/issues/118.
+        let mut common_unit_factors = Unit::scalar();
+        let target_unit_canonicalized = target_unit.canonicalized();
+
+        for factor in unit.canonicalized().iter() {
+            if let Some(other_factor) = target_unit_canonicalized
+                .iter()
+                .find(|&f| factor.prefix == f.prefix && factor.unit_id == f.unit_id)
+            {
+                if factor.exponent > Ratio::zero() && other_factor.exponent > Ratio::zero() {
+                    common_unit_factors = common_unit_factors
+                        * Unit::from_factor(UnitFactor {
+                            exponent: std::cmp::min(factor.exponent, other_factor.exponent),
+                            ..factor.clone()
+                        });
+                } else if factor.exponent < Ratio::zero() && other_factor.exponent < Ratio::zero() {
+                    common_unit_factors = common_unit_factors
+                        * Unit::from_factor(UnitFactor {
+                            exponent: std::cmp::max(factor.exponent, other_factor.exponent),
+                            ..factor.clone()
+                        });
                 }
             }
+        }
 
-            let target_unit_reduced =
-                (target_unit.clone() / common_unit_factors.clone()).canonicalized();
-            let own_unit_reduced =
-                (self.unit.clone() / common_unit_factors.clone()).canonicalized();
+        let target_unit_reduced =
+            (target_unit.clone() / common_unit_factors.clone()).canonicalized();
+        let own_unit_reduced = (unit.clone() / common_unit_factors.clone()).canonicalized();
 
-            let (target_base_unit_representation, factor) =
-                target_unit_reduced.to_base_unit_representation();
+        let (target_base_unit_representation, target_factor) =
+            target_unit_reduced.to_base_unit_representation();
 
-            let quantity_base_unit_representation = (self.clone()
-                / Quantity::from_unit(common_unit_factors))
-            .to_base_unit_representation();
-            let own_base_unit_representation = own_unit_reduced.to_base_unit_representation().0;
+        let own_factor = (unit.clone() / common_unit_factors)
+            .to_base_unit_representation()
+            .1;
+        let own_base_unit_representation = own_unit_reduced.to_base_unit_representation().0;
 
-            if own_base_unit_representation == target_base_unit_representation {
-                Ok(Quantity::new(
-                    *quantity_base_unit_representation.unsafe_value() / factor,
-                    target_unit.clone(),
-                ))
-            } else {
-                // TODO: can this even be triggered? replace by an assertion?
-                Err(QuantityError::IncompatibleUnits(
-                    self.unit.clone(),
-                    target_unit.clone(),
-                ))
-            }
+        if own_base_unit_representation == target_base_unit_representation {
+            Ok((own_factor, target_factor))
+        } else {
+            // TODO: can this even be triggered? replace by an assertion?
+            Err(QuantityError::IncompatibleUnits(
+                unit.clone(),
+                target_unit.clone(),
+            ))
         }
     }
 
@@ -213,18 +286,43 @@ impl Quantity {
         Ok(self.convert_to(&Unit::scalar())?.value)
     }
 
+    /// Like [`Quantity::as_scalar`], but keeps the integer flag.
+    fn as_scalar_quantity(&self) -> Result<Quantity> {
+        self.convert_to(&Unit::scalar())
+    }
+
+    /// The value as an `i64`, if it is known to be an integer scalar.
+    pub fn as_integer(&self) -> Option<i64> {
+        let scalar = self.as_scalar_quantity().ok()?;
+        scalar.is_integer.then(|| scalar.value.to_f64() as i64)
+    }
+
     pub fn unsafe_value(&self) -> &Number {
         &self.value
     }
 
     pub fn power(self, exp: Quantity) -> Result<Self> {
-        let exponent_as_scalar = exp.as_scalar()?.to_f64();
-        Ok(Quantity::new_f64(
-            self.value.to_f64().powf(exponent_as_scalar),
+        let exponent = exp.as_scalar_quantity()?;
+        let exponent_as_scalar = exponent.value.to_f64();
+        let value = self.value.to_f64().powf(exponent_as_scalar);
+
+        // Powers of integers can be exact beyond 2^53 (like `2^60`), so they are
+        // checked against the result of integer arithmetic instead.
+        let exact_power = (self.is_integer && exponent.is_integer && exponent_as_scalar >= 0.0)
+            .then(|| {
+                let exponent = u32::try_from(exponent_as_scalar as i64).ok()?;
+                (self.value.to_f64() as i64).checked_pow(exponent)
+            })
+            .flatten();
+
+        let mut result = Quantity::new_f64(
+            value,
             self.unit.power(
                 Rational::from_f64(exponent_as_scalar).ok_or(QuantityError::NonRationalExponent)?,
             ),
-        ))
+        );
+        result.is_integer = exact_power.is_some_and(|n| n as f64 == value && value as i64 == n);
+        Ok(result)
     }
 
     pub fn checked_div(self, other: Self) -> Option<Self> {
@@ -251,10 +349,9 @@ impl std::ops::Add for &Quantity {
         } else if rhs.is_zero() {
             Ok(self.clone())
         } else {
-            Ok(Quantity {
-                value: self.value + rhs.convert_to(&self.unit)?.value,
-                unit: self.unit.clone(),
-            })
+            let rhs = rhs.convert_to(&self.unit)?;
+            Ok(Quantity::new(self.value + rhs.value, self.unit.clone())
+                .with_integer_flag(self.is_integer && rhs.is_integer))
         }
     }
 }
@@ -268,10 +365,9 @@ impl std::ops::Sub for &Quantity {
         } else if rhs.is_zero() {
             Ok(self.clone())
         } else {
-            Ok(Quantity {
-                value: self.value - rhs.convert_to(&self.unit)?.value,
-                unit: self.unit.clone(),
-            })
+            let rhs = rhs.convert_to(&self.unit)?;
+            Ok(Quantity::new(self.value - rhs.value, self.unit.clone())
+                .with_integer_flag(self.is_integer && rhs.is_integer))
         }
     }
 }
@@ -280,10 +376,8 @@ impl std::ops::Mul for Quantity {
     type Output = Quantity;
 
     fn mul(self, rhs: Self) -> Self::Output {
-        Quantity {
-            value: self.value * rhs.value,
-            unit: self.unit * rhs.unit,
-        }
+        let is_integer = self.is_integer && rhs.is_integer;
+        Quantity::new(self.value * rhs.value, self.unit * rhs.unit).with_integer_flag(is_integer)
     }
 }
 
@@ -291,10 +385,7 @@ impl std::ops::Div for Quantity {
     type Output = Quantity;
 
     fn div(self, rhs: Self) -> Self::Output {
-        Quantity {
-            value: self.value / rhs.value,
-            unit: self.unit / rhs.unit,
-        }
+        Quantity::new(self.value / rhs.value, self.unit / rhs.unit)
     }
 }
 
@@ -305,6 +396,7 @@ impl std::ops::Neg for Quantity {
         Quantity {
             value: -self.value,
             unit: self.unit,
+            is_integer: self.is_integer,
         }
     }
 }
@@ -328,11 +420,29 @@ impl PartialOrd for Quantity {
     }
 }
 
-impl PrettyPrint for Quantity {
-    fn pretty_print(&self) -> crate::markup::Markup {
-        use crate::markup;
+impl Quantity {
+    /// Like the [`Display`](std::fmt::Display) implementation, but with all digits
+    /// of the value (`1152921504606846976` instead of `1.15292e+18`). Used in
+    /// errors about non-integer values, where rounding would hide the problem.
+    pub fn to_exact_string(&self) -> String {
+        use crate::markup::{Formatter, PlainTextFormatter};
 
-        let formatted_number = self.unsafe_value().pretty_print();
+        let value = self.value.to_f64();
+        let formatted_number = if value.fract() == 0.0 && value.abs() < 1e21 {
+            format!("{}", value as i128)
+        } else {
+            format!("{value:?}")
+        };
+
+        let markup = self.pretty_print_with(formatted_number);
+        PlainTextFormatter {}
+            .format(&markup, false)
+            .trim()
+            .to_string()
+    }
+
+    fn pretty_print_with(&self, formatted_number: String) -> crate::markup::Markup {
+        use crate::markup;
 
         let unit_str = format!("{}", self.unit());
 
@@ -346,6 +456,12 @@ impl PrettyPrint for Quantity {
     }
 }
 
+impl PrettyPrint for Quantity {
+    fn pretty_print(&self) -> crate::markup::Markup {
+        self.pretty_print_with(self.unsafe_value().pretty_print())
+    }
+}
+
 impl std::fmt::Display for Quantity {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         use crate::markup::{Formatter, PlainTextFormatter};
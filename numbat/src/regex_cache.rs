@@ -0,0 +1,127 @@
+//! A cache for compiled regular expressions.
+//!
+//! Compiling a regular expression is expensive compared to matching it against a
+//! (short) string. Functions like `str_match` are typically called with the same
+//! pattern many times (e.g. inside a `map`), so every pattern is only compiled
+//! once per context. Patterns that can not be compiled are not cached.
+
+use std::collections::HashMap;
+
+use regex::Regex;
+
+use crate::interpreter::RuntimeError;
+
+/// The cache is cleared when it reaches this size, to avoid growing without bounds
+/// for patterns that are constructed at runtime.
+const MAX_ENTRIES: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct RegexCacheStats {
+    pub entries: usize,
+    pub hits: usize,
+    pub misses: usize,
+}
+
+impl std::fmt::Display for RegexCacheStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Regex cache: entries: {}, hits: {}, misses: {}",
+            self.entries, self.hits, self.misses
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RegexCache {
+    entries: HashMap<String, Regex>,
+    hits: usize,
+    misses: usize,
+}
+
+impl RegexCache {
+    /// The compiled regular expression for `pattern`.
+    pub fn get(&mut self, pattern: &str) -> Result<&Regex, RuntimeError> {
+        if self.entries.contains_key(pattern) {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+            let regex = compile(pattern)?;
+            if self.entries.len() >= MAX_ENTRIES {
+                self.entries.clear();
+            }
+            self.entries.insert(pattern.to_string(), regex);
+        }
+
+        Ok(&self.entries[pattern])
+    }
+
+    pub fn stats(&self) -> RegexCacheStats {
+        RegexCacheStats {
+            entries: self.entries.len(),
+            hits: self.hits,
+            misses: self.misses,
+        }
+    }
+}
+
+fn compile(pattern: &str) -> Result<Regex, RuntimeError> {
+    Regex::new(pattern).map_err(|error| {
+        // The errors of the 'regex' crate only come with a preformatted message,
+        // so parse the pattern again to find the position of a syntax error.
+        let (offset, message) = match regex_syntax::Parser::new().parse(pattern) {
+            Err(regex_syntax::Error::Parse(e)) => {
+                (Some(e.span().start.offset), e.kind().to_string())
+            }
+            Err(regex_syntax::Error::Translate(e)) => {
+                (Some(e.span().start.offset), e.kind().to_string())
+            }
+            _ => (None, error.to_string()),
+        };
+        let position = offset.map(|offset| pattern[..offset].chars().count());
+        RuntimeError::InvalidRegex(pattern.to_string(), position, message)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_are_compiled_once() {
+        let mut cache = RegexCache::default();
+
+        for _ in 0..3 {
+            assert!(cache.get("a+b").unwrap().is_match("xaab"));
+            assert!(!cache.get("^[0-9]+$").unwrap().is_match("12a"));
+        }
+
+        assert_eq!(
+            cache.stats(),
+            RegexCacheStats {
+                entries: 2,
+                hits: 4,
+                misses: 2
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        let mut cache = RegexCache::default();
+
+        assert_eq!(
+            cache.get("ab(c").unwrap_err(),
+            RuntimeError::InvalidRegex("ab(c".into(), Some(2), "unclosed group".into())
+        );
+        assert_eq!(
+            cache.get("µ[z-a]").unwrap_err(),
+            RuntimeError::InvalidRegex(
+                "µ[z-a]".into(),
+                Some(2),
+                "invalid character class range, the start must be <= the end".into()
+            )
+        );
+        assert_eq!(cache.stats().entries, 0);
+    }
+}
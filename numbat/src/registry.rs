@@ -6,28 +6,41 @@ use thiserror::Error;
 
 use crate::{
     arithmetic::{pretty_exponent, Exponent, Power, Rational},
+    error_code::ErrorCode,
     pretty_print::PrettyPrint,
     product::{Canonicalize, Product},
+    span::Span,
     suggestion,
+    symbol::Symbol,
 };
 
 #[derive(Clone, Error, Debug, PartialEq, Eq)]
 pub enum RegistryError {
+    /// Name of the entry, span of the new definition and span of the existing one
     #[error("Entry '{0}' exists already.")]
-    EntryExists(String),
+    EntryExists(String, Span, Span),
 
     #[error("Unknown entry '{0}'.")]
     UnknownEntry(String, Option<String>),
 }
 
+impl RegistryError {
+    pub fn error_code(&self) -> ErrorCode {
+        match self {
+            RegistryError::EntryExists(..) => ErrorCode::E0701,
+            RegistryError::UnknownEntry(..) => ErrorCode::E0702,
+        }
+    }
+}
+
 pub type Result<T> = std::result::Result<T, RegistryError>;
 
-pub type BaseEntry = String;
+pub type BaseEntry = Symbol;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct BaseIndex(isize);
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct BaseRepresentationFactor(pub BaseEntry, pub Exponent);
 
 impl Display for BaseRepresentationFactor {
@@ -40,7 +53,7 @@ impl Canonicalize for BaseRepresentationFactor {
     type MergeKey = BaseEntry;
 
     fn merge_key(&self) -> Self::MergeKey {
-        self.0.clone() // TODO(minor): can cloning be prevented here?
+        self.0
     }
 
     fn merge(self, other: Self) -> Self {
@@ -59,7 +72,6 @@ impl Power for BaseRepresentationFactor {
     }
 }
 
-// TODO(minor): this could be represented with a base index in the first tuple component instead of a cloned string
 pub type BaseRepresentation = Product<BaseRepresentationFactor, true>;
 
 impl Display for BaseRepresentation {
@@ -82,10 +94,20 @@ impl PrettyPrint for BaseRepresentation {
     }
 }
 
+/// An entry that has been removed from a registry, see [`Registry::take`]
+#[derive(Debug, Clone)]
+pub(crate) struct RegistryEntry<Metadata> {
+    /// The base representation of a derived entry, `None` for base entries
+    base_representation: Option<BaseRepresentation>,
+    metadata: Metadata,
+    definition_span: Span,
+}
+
 #[derive(Debug, Clone)]
 pub struct Registry<Metadata> {
-    base_entries: Vec<(String, Metadata)>,
-    derived_entries: HashMap<String, (BaseRepresentation, Metadata)>,
+    base_entries: Vec<(Symbol, Metadata)>,
+    derived_entries: HashMap<Symbol, (BaseRepresentation, Metadata)>,
+    definition_spans: HashMap<Symbol, Span>,
 }
 
 impl<T> Default for Registry<T> {
@@ -93,16 +115,16 @@ impl<T> Default for Registry<T> {
         Self {
             base_entries: vec![],
             derived_entries: HashMap::default(),
+            definition_spans: HashMap::default(),
         }
     }
 }
 
 impl<Metadata: Clone> Registry<Metadata> {
-    pub fn add_base_entry(&mut self, name: &str, metadata: Metadata) -> Result<()> {
-        if self.contains(name) {
-            return Err(RegistryError::EntryExists(name.to_owned()));
-        }
-        self.base_entries.push((name.to_owned(), metadata));
+    pub fn add_base_entry(&mut self, name: Symbol, span: Span, metadata: Metadata) -> Result<()> {
+        self.ensure_new_entry(name, span)?;
+        self.base_entries.push((name, metadata));
+        self.definition_spans.insert(name, span);
 
         Ok(())
     }
@@ -110,74 +132,149 @@ impl<Metadata: Clone> Registry<Metadata> {
     pub fn get_derived_entry_names_for(
         &self,
         base_representation: &BaseRepresentation,
-    ) -> Vec<String> {
+    ) -> Vec<Symbol> {
         self.derived_entries
             .iter()
             .filter(|(_, (br, _))| br == base_representation)
-            .map(|(name, _)| name.clone())
+            .map(|(name, _)| *name)
             .sorted_unstable()
             .collect()
     }
 
     pub fn add_derived_entry(
         &mut self,
-        name: &str,
+        name: Symbol,
+        span: Span,
         base_representation: BaseRepresentation,
         metadata: Metadata,
     ) -> Result<()> {
-        if self.contains(name) {
-            return Err(RegistryError::EntryExists(name.to_owned()));
-        }
-
+        self.ensure_new_entry(name, span)?;
         self.derived_entries
-            .insert(name.to_owned(), (base_representation, metadata));
+            .insert(name, (base_representation, metadata));
+        self.definition_spans.insert(name, span);
 
         Ok(())
     }
 
-    pub fn contains(&self, name: &str) -> bool {
-        self.base_entries.iter().any(|(n, _)| n == name) || self.derived_entries.contains_key(name)
+    fn ensure_new_entry(&self, name: Symbol, span: Span) -> Result<()> {
+        if !self.contains(name) {
+            return Ok(());
+        }
+
+        let existing_span = self
+            .get_definition_span(name)
+            .expect("all entries have a definition span");
+        Err(RegistryError::EntryExists(
+            name.to_string(),
+            span,
+            existing_span,
+        ))
     }
 
-    pub fn get_base_representation_for_name(
-        &self,
-        name: &str,
-    ) -> Result<(BaseRepresentation, Metadata)> {
+    /// Where the entry has been defined, e.g. for go-to-definition in an editor
+    pub fn get_definition_span(&self, name: Symbol) -> Option<Span> {
+        self.definition_spans.get(&name).copied()
+    }
+
+    pub fn contains(&self, name: Symbol) -> bool {
+        self.base_entries.iter().any(|(n, _)| *n == name)
+            || self.derived_entries.contains_key(&name)
+    }
+
+    pub(crate) fn remove(&mut self, name: Symbol) {
+        self.base_entries.retain(|(n, _)| *n != name);
+        self.derived_entries.remove(&name);
+        self.definition_spans.remove(&name);
+    }
+
+    /// Removes an entry, such that it can be brought back with [`Registry::restore`]
+    pub(crate) fn take(&mut self, name: Symbol) -> Option<RegistryEntry<Metadata>> {
+        let definition_span = self.definition_spans.remove(&name)?;
+        let entry = match self.base_entries.iter().position(|(n, _)| *n == name) {
+            Some(index) => RegistryEntry {
+                base_representation: None,
+                metadata: self.base_entries.remove(index).1,
+                definition_span,
+            },
+            None => {
+                let (base_representation, metadata) = self.derived_entries.remove(&name)?;
+                RegistryEntry {
+                    base_representation: Some(base_representation),
+                    metadata,
+                    definition_span,
+                }
+            }
+        };
+        Some(entry)
+    }
+
+    /// Replaces the current entry of the given name by one that has been removed
+    /// with [`Registry::take`]
+    pub(crate) fn restore(&mut self, name: Symbol, entry: Option<RegistryEntry<Metadata>>) {
+        self.remove(name);
+        let Some(entry) = entry else {
+            return;
+        };
+        match entry.base_representation {
+            Some(base_representation) => {
+                self.derived_entries
+                    .insert(name, (base_representation, entry.metadata));
+            }
+            None => self.base_entries.push((name, entry.metadata)),
+        }
+        self.definition_spans.insert(name, entry.definition_span);
+    }
+
+    pub fn get_base_representation(&self, name: Symbol) -> Result<(BaseRepresentation, Metadata)> {
         if let Some(metadata) = self
             .base_entries
             .iter()
-            .find(|(n, _)| n == name)
+            .find(|(n, _)| *n == name)
             .map(|(_, m)| m)
         {
             Ok((
                 BaseRepresentation::from_factor(BaseRepresentationFactor(
-                    name.to_owned(),
+                    name,
                     Rational::from_integer(1),
                 )),
                 metadata.clone(),
             ))
         } else {
             self.derived_entries
-                .get(name)
-                .ok_or_else(|| {
-                    let suggestion = suggestion::did_you_mean(
-                        self.base_entries
-                            .iter()
-                            .map(|(id, _)| id.to_string())
-                            .chain(self.derived_entries.keys().map(|s| s.to_string())),
-                        name,
-                    );
-                    RegistryError::UnknownEntry(name.to_owned(), suggestion)
-                })
+                .get(&name)
+                .ok_or_else(|| self.unknown_entry(name.as_str()))
                 .cloned()
         }
     }
 
-    pub fn iter_base_entries(&self) -> impl Iterator<Item = String> + '_ {
-        self.base_entries.iter().map(|(name, _)| name.clone())
+    /// Like [`Registry::get_base_representation`], but for a name that has not
+    /// necessarily been interned yet.
+    pub fn get_base_representation_for_name(
+        &self,
+        name: &str,
+    ) -> Result<(BaseRepresentation, Metadata)> {
+        match Symbol::get(name) {
+            Some(symbol) => self.get_base_representation(symbol),
+            None => Err(self.unknown_entry(name)),
+        }
+    }
+
+    fn unknown_entry(&self, name: &str) -> RegistryError {
+        let suggestion = suggestion::did_you_mean(
+            self.base_entries
+                .iter()
+                .map(|(id, _)| id.to_string())
+                .chain(self.derived_entries.keys().map(|s| s.to_string())),
+            name,
+        );
+        RegistryError::UnknownEntry(name.to_owned(), suggestion)
+    }
+
+    pub fn iter_base_entries(&self) -> impl Iterator<Item = Symbol> + '_ {
+        self.base_entries.iter().map(|(name, _)| *name)
     }
 
-    pub fn iter_derived_entries(&self) -> impl Iterator<Item = String> + '_ {
-        self.derived_entries.keys().cloned()
+    pub fn iter_derived_entries(&self) -> impl Iterator<Item = Symbol> + '_ {
+        self.derived_entries.keys().copied()
     }
 }
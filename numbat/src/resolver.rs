@@ -1,13 +1,30 @@
-use std::{collections::HashMap, path::PathBuf, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::Arc,
+};
 
 use crate::{
-    ast::Statement, module_importer::ModuleImporter, parser::parse, span::Span, ParseError,
+    ast::{formula_function_name, ImportScope, ModuleItem, Statement},
+    decorator,
+    diagnostic::Diagnostic,
+    features,
+    module_importer::ModuleImporter,
+    parser::{parse_with_options, ParseErrorKind, ParseWarning, ParserOptions},
+    sandbox::Capability,
+    span::{SourceCodePositition, Span},
+    suggestion,
+    symbol::Symbol,
+    tokenizer::DigitComma,
+    typechecker::implicit_dimension_name,
+    ParseError,
 };
 
+use codespan_reporting::diagnostic::LabelStyle;
 use codespan_reporting::files::SimpleFiles;
 use thiserror::Error;
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct ModulePath(pub Vec<String>);
 
 impl std::fmt::Display for ModulePath {
@@ -37,31 +54,183 @@ pub enum ResolverError {
     #[error("Unknown module '{1}'.")]
     UnknownModule(Span, ModulePath),
 
+    #[error("Module '{1}' can not be loaded: {2} is disabled in sandbox mode.")]
+    PermissionDenied(Span, ModulePath, Capability),
+
     #[error("{}", .0.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n"))]
     ParseErrors(Vec<ParseError>),
+
+    #[error("Module '{2}' does not define '{1}'.")]
+    UnknownImportItem(Span, String, ModulePath, Option<String>),
 }
 
 type Result<T> = std::result::Result<T, ResolverError>;
 
+/// Maximum number of "imported from here" labels that are attached to a diagnostic
+const MAX_IMPORT_CHAIN_LABELS: usize = 3;
+
 #[derive(Clone)]
 pub struct Resolver {
     importer: Arc<dyn ModuleImporter>,
+    /// Only use importers that do not read from the filesystem
+    sandboxed: bool,
     pub files: SimpleFiles<String, String>,
     text_code_source_count: usize,
     internal_code_source_count: usize,
     imported_modules: Vec<ModulePath>,
     codesources: HashMap<usize, CodeSource>,
+    /// For every imported module (by code source ID), the `use` statement that
+    /// caused it to be loaded
+    import_sites: HashMap<usize, Span>,
+    /// How commas in number literals are treated in top-level code (modules
+    /// are always parsed with the default), and the maximum nesting depth.
+    parser_options: ParserOptions,
+    /// Load `core::strict_angle` whenever `core::angle` is imported
+    strict_angles: bool,
+    /// Warnings for the code that has been parsed in the last call to `resolve`
+    warnings: Vec<ParseWarning>,
+    /// Code sources that contain the `ALLOW_DEPRECATED_DIRECTIVE`
+    allow_deprecated: HashSet<usize>,
+    /// The public definitions of every loaded module, see [`exported_names`]
+    module_exports: HashMap<ModulePath, Vec<Vec<Symbol>>>,
+    /// Definitions that have been hidden after a selective import, by module
+    hidden_definitions: HashMap<ModulePath, Vec<Symbol>>,
+    /// The modules that have been loaded by a selective import of a module,
+    /// including the module itself
+    selectively_loaded: HashMap<ModulePath, Vec<ModulePath>>,
 }
 
+/// The names that are defined by a top-level statement. Names that are defined
+/// together, like a unit and its aliases, are grouped.
+fn defined_names(statement: &Statement) -> Vec<Vec<Symbol>> {
+    match statement {
+        Statement::DefineVariable(define_variable) => match &define_variable.pattern {
+            crate::ast::Pattern::Identifier(_, name) => {
+                vec![
+                    decorator::name_and_aliases(*name, &define_variable.decorators)
+                        .map(|(name, _)| name)
+                        .collect(),
+                ]
+            }
+            pattern => pattern
+                .identifiers()
+                .into_iter()
+                .map(|(_, name)| vec![name])
+                .collect(),
+        },
+        Statement::DefineFunction { function_name, .. } => vec![vec![*function_name]],
+        Statement::DefineDimension(_, name, _) => vec![vec![*name]],
+        Statement::DefineBaseUnit(_, name, type_annotation, decorators) => {
+            let mut names: Vec<_> = decorator::name_and_aliases(*name, decorators)
+                .map(|(name, _)| name)
+                .collect();
+            if type_annotation.is_none() {
+                names.push(implicit_dimension_name(*name, decorators));
+            }
+            vec![names]
+        }
+        Statement::DefineDerivedUnit {
+            identifier,
+            decorators,
+            ..
+        } => vec![decorator::name_and_aliases(*identifier, decorators)
+            .map(|(name, _)| name)
+            .collect()],
+        Statement::DefineStruct { struct_name, .. } => vec![vec![*struct_name]],
+        Statement::DefineUnitSystem { system_name, .. } => vec![vec![*system_name]],
+        Statement::DefineFormula {
+            formula_name,
+            quantities,
+            ..
+        } => quantities
+            .iter()
+            .map(|(_, quantity, _)| vec![formula_function_name(*formula_name, *quantity)])
+            .collect(),
+        Statement::DefineUnitFunction { function, inverse } => vec![std::iter::once(function)
+            .chain(inverse)
+            .flat_map(|function| defined_names(function).concat())
+            .collect()],
+        Statement::Expression(_)
+        | Statement::ProcedureCall(..)
+        | Statement::Conditional { .. }
+        | Statement::ModuleImport(..)
+        | Statement::ImportScope(..)
+        | Statement::DefineMethods { .. } => vec![],
+    }
+}
+
+/// The definitions of a module that can be imported selectively. Names with a
+/// leading underscore are private to the module.
+fn exported_names(program: &[Statement]) -> Vec<Vec<Symbol>> {
+    program
+        .iter()
+        .flat_map(defined_names)
+        .filter(|names| !names[0].as_str().starts_with('_'))
+        .collect()
+}
+
+/// A comment line that disables warnings for deprecated identifiers in a file
+const ALLOW_DEPRECATED_DIRECTIVE: &str = "# numbat: allow-deprecated";
+
 impl Resolver {
     pub(crate) fn new(importer: impl ModuleImporter + 'static) -> Self {
         Self {
             importer: Arc::new(importer),
+            sandboxed: false,
             files: SimpleFiles::new(),
             text_code_source_count: 0,
             internal_code_source_count: 0,
             imported_modules: vec![],
             codesources: HashMap::new(),
+            import_sites: HashMap::new(),
+            parser_options: ParserOptions::default(),
+            strict_angles: false,
+            warnings: vec![],
+            allow_deprecated: HashSet::new(),
+            module_exports: HashMap::new(),
+            hidden_definitions: HashMap::new(),
+            selectively_loaded: HashMap::new(),
+        }
+    }
+
+    /// Do not load modules from the filesystem, see [`ModuleImporter::import_sandboxed`].
+    pub(crate) fn sandboxed(mut self) -> Self {
+        self.sandboxed = true;
+        self
+    }
+
+    pub(crate) fn set_digit_comma(&mut self, digit_comma: DigitComma) {
+        self.parser_options.digit_comma = digit_comma;
+    }
+
+    pub(crate) fn set_max_nesting_depth(&mut self, depth: usize) {
+        self.parser_options.max_nesting_depth = depth;
+    }
+
+    pub(crate) fn num_imported_modules(&self) -> usize {
+        self.imported_modules.len()
+    }
+
+    /// Forget about modules that have been imported after the first `count` ones,
+    /// such that they are loaded again by the next `use` statement.
+    pub(crate) fn truncate_imported_modules(&mut self, count: usize) {
+        self.imported_modules.truncate(count);
+    }
+
+    pub(crate) fn set_strict_angles(&mut self, strict: bool) {
+        self.strict_angles = strict;
+    }
+
+    pub(crate) fn has_strict_angles(&self) -> bool {
+        self.strict_angles
+    }
+
+    /// The module that is actually loaded for a `use` statement
+    fn source_module(&self, module_path: &ModulePath) -> ModulePath {
+        if self.strict_angles && module_path.0 == ["core", "angle"] {
+            ModulePath(vec!["core".into(), "strict_angle".into()])
+        } else {
+            module_path.clone()
         }
     }
 
@@ -88,65 +257,372 @@ impl Resolver {
 
         let id = self.files.add(code_source_name, content.to_string());
         self.codesources.insert(id, code_source);
+        if content
+            .lines()
+            .any(|line| line.trim() == ALLOW_DEPRECATED_DIRECTIVE)
+        {
+            self.allow_deprecated.insert(id);
+        }
 
         id
     }
 
+    pub(crate) fn allows_deprecated(&self, code_source_id: usize) -> bool {
+        self.allow_deprecated.contains(&code_source_id)
+    }
+
     pub fn get_code_source(&self, id: usize) -> CodeSource {
         self.codesources.get(&id).cloned().unwrap()
     }
 
-    fn parse(&self, code: &str, code_source_id: usize) -> Result<Vec<Statement>> {
-        parse(code, code_source_id).map_err(|e| ResolverError::ParseErrors(e.1))
+    /// The chain of `use` statements that led to loading the given code source,
+    /// starting with the one that imported it directly.
+    pub fn import_chain(&self, code_source_id: usize) -> Vec<Span> {
+        let mut chain = vec![];
+        let mut current = code_source_id;
+        while let Some(span) = self.import_sites.get(&current) {
+            chain.push(*span);
+            current = span.code_source_id;
+        }
+        chain
     }
 
-    fn inlining_pass(&mut self, program: &[Statement]) -> Result<Vec<Statement>> {
-        let mut new_program = vec![];
+    /// Whether the code source has been loaded through the prelude, directly or via
+    /// one of the modules that the prelude uses.
+    pub fn is_part_of_prelude(&self, code_source_id: usize) -> bool {
+        let mut current = code_source_id;
+        loop {
+            if matches!(
+                self.codesources.get(&current),
+                Some(CodeSource::Module(path, _)) if path.0 == ["prelude"]
+            ) {
+                return true;
+            }
+            match self.import_sites.get(&current) {
+                Some(span) => current = span.code_source_id,
+                None => return false,
+            }
+        }
+    }
+
+    /// Point to the `use` statements through which the code of the primary label
+    /// was imported. Only the innermost few imports of deep chains are shown.
+    pub fn add_import_chain(&self, diagnostic: Diagnostic) -> Diagnostic {
+        let Some(file_id) = diagnostic
+            .labels
+            .iter()
+            .find(|label| label.style == LabelStyle::Primary)
+            .map(|label| label.file_id)
+        else {
+            return diagnostic;
+        };
+
+        let chain = self.import_chain(file_id);
+        if chain.is_empty() {
+            return diagnostic;
+        }
+
+        let labels = chain.iter().take(MAX_IMPORT_CHAIN_LABELS).map(|span| {
+            span.diagnostic_label(LabelStyle::Secondary)
+                .with_message("imported from here")
+        });
+        let mut diagnostic = diagnostic.with_labels(labels.collect());
+
+        if chain.len() > MAX_IMPORT_CHAIN_LABELS {
+            let hidden = chain.len() - MAX_IMPORT_CHAIN_LABELS;
+            diagnostic.notes.push(format!(
+                "… imported through {hidden} more `use` statement{s}",
+                s = if hidden == 1 { "" } else { "s" }
+            ));
+        }
+
+        diagnostic
+    }
+
+    fn parse(&mut self, code: &str, code_source_id: usize) -> Result<Vec<Statement>> {
+        parse_with_options(
+            code,
+            code_source_id,
+            ParserOptions {
+                digit_comma: DigitComma::default(),
+                ..self.parser_options
+            },
+            &mut self.warnings,
+        )
+        .map_err(|e| ResolverError::ParseErrors(e.1))
+    }
+
+    /// Load the given module and inline the modules that it imports
+    fn load_module(&mut self, span: Span, module_path: &ModulePath) -> Result<Vec<Statement>> {
+        let source_module = self.source_module(module_path);
+        let imported = if self.sandboxed {
+            self.importer
+                .import_sandboxed(&source_module)
+                .map_err(|capability| {
+                    ResolverError::PermissionDenied(span, module_path.clone(), capability)
+                })?
+        } else {
+            self.importer.import(&source_module)
+        };
+
+        let Some((code, filesystem_path)) = imported else {
+            return Err(ResolverError::UnknownModule(span, module_path.clone()));
+        };
+
+        self.imported_modules.push(module_path.clone());
+        let code_source_id = self.add_code_source(
+            CodeSource::Module(source_module.clone(), filesystem_path),
+            &code,
+        );
+        self.import_sites.insert(code_source_id, span);
+
+        let imported_program = self.parse(&code, code_source_id)?;
+        self.module_exports
+            .insert(source_module, exported_names(&imported_program));
+        self.inlining_pass(imported_program)
+    }
 
+    /// All names that are brought into scope by the items of `use foo::{bar, baz}`
+    fn imported_names(
+        &self,
+        module_path: &ModulePath,
+        items: &[(Span, Symbol)],
+    ) -> Result<HashSet<Symbol>> {
+        let exports = self
+            .module_exports
+            .get(&self.source_module(module_path))
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+
+        let mut names = HashSet::new();
+        for (span, item) in items {
+            let Some(group) = exports.iter().find(|group| group.contains(item)) else {
+                return Err(ResolverError::UnknownImportItem(
+                    *span,
+                    item.to_string(),
+                    module_path.clone(),
+                    suggestion::did_you_mean(exports.iter().flatten(), item),
+                ));
+            };
+            names.extend(group);
+        }
+        Ok(names)
+    }
+
+    /// The definitions of an inlined module (and of the modules that it loaded)
+    /// which are not imported. Definitions that have already been hidden by a
+    /// nested selective import are skipped.
+    fn definitions_to_hide(
+        &self,
+        program: &[Statement],
+        imported: &HashSet<Symbol>,
+    ) -> Vec<ModuleItem> {
+        let mut already_hidden = HashSet::new();
+        let mut hidden = vec![];
         for statement in program {
-            match statement {
-                Statement::ModuleImport(span, module_path) => {
-                    if !self.imported_modules.contains(module_path) {
-                        if let Some((code, filesystem_path)) = self.importer.import(module_path) {
-                            self.imported_modules.push(module_path.clone());
-                            let code_source_id = self.add_code_source(
-                                CodeSource::Module(module_path.clone(), filesystem_path),
-                                &code,
-                            );
-
-                            let imported_program = self.parse(&code, code_source_id)?;
-                            let inlined_program = self.inlining_pass(&imported_program)?;
-                            for statement in inlined_program {
-                                new_program.push(statement);
-                            }
-                        } else {
-                            return Err(ResolverError::UnknownModule(*span, module_path.clone()));
-                        }
-                    }
+            if let Statement::ImportScope(_, ImportScope::End(items)) = statement {
+                already_hidden.extend(items.iter().cloned());
+                continue;
+            }
+
+            let Some(CodeSource::Module(module, _)) =
+                self.codesources.get(&statement.span().code_source_id)
+            else {
+                continue;
+            };
+            for name in defined_names(statement).concat() {
+                if imported.contains(&name) || already_hidden.contains(&(module.clone(), name)) {
+                    continue;
+                }
+                // A later definition of the same name replaces the earlier one
+                hidden.retain(|(_, hidden_name)| *hidden_name != name);
+                hidden.push((module.clone(), name));
+            }
+        }
+        hidden
+    }
+
+    /// The hidden definitions of the given modules that are brought back into
+    /// scope by a later import
+    fn definitions_to_reveal(
+        &self,
+        source_module: &ModulePath,
+        imported: Option<&HashSet<Symbol>>,
+    ) -> Vec<ModuleItem> {
+        let modules = match (imported, self.selectively_loaded.get(source_module)) {
+            (None, Some(modules)) => modules.clone(),
+            _ => vec![source_module.clone()],
+        };
+
+        let mut revealed = vec![];
+        for module in modules {
+            for name in self.hidden_definitions.get(&module).into_iter().flatten() {
+                if imported.map_or(true, |imported| imported.contains(name)) {
+                    revealed.push((module.clone(), *name));
                 }
-                statement => new_program.push(statement.clone()),
             }
         }
+        revealed
+    }
+
+    fn inlining_pass(&mut self, program: Vec<Statement>) -> Result<Vec<Statement>> {
+        let mut new_program = vec![];
+
+        for statement in program {
+            let Statement::ModuleImport(span, module_path, items) = &statement else {
+                new_program.push(statement);
+                continue;
+            };
+
+            // Modules that need a disabled feature are skipped. Their
+            // identifiers are reported by the type checker.
+            if features::missing_feature_for_module(module_path).is_some() {
+                continue;
+            }
+
+            let source_module = self.source_module(module_path);
+            let num_imported_modules = self.imported_modules.len();
+            let inlined_program = if self.imported_modules.contains(module_path) {
+                None
+            } else {
+                Some(self.load_module(*span, module_path)?)
+            };
+
+            let imported = items
+                .as_ref()
+                .map(|items| self.imported_names(module_path, items))
+                .transpose()?;
+
+            let Some(inlined_program) = inlined_program else {
+                // The module has been loaded before, but some of its
+                // definitions might have been hidden
+                let revealed = self.definitions_to_reveal(&source_module, imported.as_ref());
+                if !revealed.is_empty() {
+                    new_program.push(Statement::ImportScope(*span, ImportScope::Reveal(revealed)));
+                }
+                continue;
+            };
+
+            let hidden = match &imported {
+                Some(imported) => self.definitions_to_hide(&inlined_program, imported),
+                None => vec![],
+            };
+            if hidden.is_empty() {
+                new_program.extend(inlined_program);
+                continue;
+            }
 
+            let mut names = vec![];
+            for (module, name) in &hidden {
+                names.push(*name);
+                let hidden_names = self.hidden_definitions.entry(module.clone()).or_default();
+                if !hidden_names.contains(name) {
+                    hidden_names.push(*name);
+                }
+            }
+            let loaded_modules = self.imported_modules[num_imported_modules..]
+                .iter()
+                .map(|module| self.source_module(module))
+                .collect();
+            self.selectively_loaded
+                .insert(source_module, loaded_modules);
+
+            new_program.push(Statement::ImportScope(*span, ImportScope::Begin(names)));
+            new_program.extend(inlined_program);
+            new_program.push(Statement::ImportScope(*span, ImportScope::End(hidden)));
+        }
         Ok(new_program)
     }
 
     pub fn resolve(&mut self, code: &str, code_source: CodeSource) -> Result<Vec<Statement>> {
+        self.warnings.clear();
+
         let code_source_id = self.add_code_source(code_source, code);
-        let statements = self.parse(code, code_source_id)?;
+        let statements = parse_with_options(
+            code,
+            code_source_id,
+            self.parser_options,
+            &mut self.warnings,
+        )
+        .map_err(|e| ResolverError::ParseErrors(e.1))?;
+
+        self.inlining_pass(statements)
+    }
+
+    /// Returns (and removes) the warnings for the code of the last call to
+    /// [`Resolver::resolve`], including all modules imported by it.
+    pub fn take_warnings(&mut self) -> Vec<ParseWarning> {
+        std::mem::take(&mut self.warnings)
+    }
 
-        self.inlining_pass(&statements)
+    /// Check that the declaration of a native function consists of a single function
+    /// declaration without a body, for a function with the given name.
+    pub(crate) fn parse_native_function_signature(
+        &mut self,
+        name: &str,
+        declaration: &str,
+    ) -> Result<()> {
+        let code_source_id = self.add_code_source(CodeSource::Internal, declaration);
+        let statements = self.parse(declaration, code_source_id)?;
+
+        match statements.as_slice() {
+            [Statement::DefineFunction {
+                function_name,
+                body: None,
+                ..
+            }] if *function_name == name => Ok(()),
+            _ => {
+                let span = match statements.first() {
+                    Some(Statement::DefineFunction {
+                        function_name_span, ..
+                    }) => *function_name_span,
+                    _ => SourceCodePositition::start().single_character_span(code_source_id),
+                };
+                Err(ResolverError::ParseErrors(vec![ParseError::new(
+                    ParseErrorKind::ExpectedNativeFunctionSignature,
+                    span,
+                )]))
+            }
+        }
+    }
+
+    pub fn list_modules(&self) -> Vec<ModulePath> {
+        if self.sandboxed {
+            self.importer.list_modules_sandboxed()
+        } else {
+            self.importer.list_modules()
+        }
     }
 
     pub fn get_importer(&self) -> &dyn ModuleImporter {
         self.importer.as_ref()
     }
+
+    /// The paths of all files and modules that have been loaded, in the order in which
+    /// they were loaded. Each file is listed once.
+    pub fn loaded_files(&self) -> Vec<PathBuf> {
+        let mut ids: Vec<_> = self.codesources.keys().copied().collect();
+        ids.sort_unstable();
+
+        let mut files = vec![];
+        for id in ids {
+            let (CodeSource::File(path) | CodeSource::Module(_, Some(path))) =
+                &self.codesources[&id]
+            else {
+                continue;
+            };
+            if !files.contains(path) {
+                files.push(path.clone());
+            }
+        }
+        files
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
-        ast::{DefineVariable, Expression},
+        ast::{DefineVariable, Expression, Pattern},
         number::Number,
     };
 
@@ -166,6 +642,14 @@ mod tests {
                 // ----
                 ModulePath(p) if p == &["cycle_a"] => Some(("use cycle_b".into(), None)),
                 ModulePath(p) if p == &["cycle_b"] => Some(("use cycle_a".into(), None)),
+                // ----
+                ModulePath(p) if p == &["file_a"] => Some((
+                    "use file_b\nuse foo::baz".into(),
+                    Some(PathBuf::from("modules/file_a.nbt")),
+                )),
+                ModulePath(p) if p == &["file_b"] => {
+                    Some(("".into(), Some(PathBuf::from("modules/file_b.nbt"))))
+                }
                 _ => None,
             }
         }
@@ -193,8 +677,7 @@ mod tests {
             &program_inlined.replace_spans(),
             &[
                 Statement::DefineVariable(DefineVariable {
-                    identifier_span: Span::dummy(),
-                    identifier: "a".into(),
+                    pattern: Pattern::Identifier(Span::dummy(), "a".into()),
                     expr: Expression::Scalar(Span::dummy(), Number::from_f64(1.0)),
                     type_annotation: None,
                     decorators: Vec::new(),
@@ -223,8 +706,7 @@ mod tests {
             &program_inlined.replace_spans(),
             &[
                 Statement::DefineVariable(DefineVariable {
-                    identifier_span: Span::dummy(),
-                    identifier: "a".into(),
+                    pattern: Pattern::Identifier(Span::dummy(), "a".into()),
                     expr: Expression::Scalar(Span::dummy(), Number::from_f64(1.0)),
                     type_annotation: None,
                     decorators: Vec::new(),
@@ -252,15 +734,13 @@ mod tests {
             &program_inlined.replace_spans(),
             &[
                 Statement::DefineVariable(DefineVariable {
-                    identifier_span: Span::dummy(),
-                    identifier: "y".into(),
+                    pattern: Pattern::Identifier(Span::dummy(), "y".into()),
                     expr: Expression::Scalar(Span::dummy(), Number::from_f64(1.0)),
                     type_annotation: None,
                     decorators: Vec::new(),
                 }),
                 Statement::DefineVariable(DefineVariable {
-                    identifier_span: Span::dummy(),
-                    identifier: "x".into(),
+                    pattern: Pattern::Identifier(Span::dummy(), "x".into()),
                     expr: Expression::Identifier(Span::dummy(), "y".into()),
                     type_annotation: None,
                     decorators: Vec::new(),
@@ -282,4 +762,45 @@ mod tests {
 
         assert_eq!(&program_inlined, &[]);
     }
+
+    #[test]
+    fn resolver_import_chain() {
+        let program = "use mod_a";
+
+        let importer = TestImporter {};
+
+        let mut resolver = Resolver::new(importer);
+        resolver.resolve(program, CodeSource::Internal).unwrap();
+
+        // 0: program, 1: mod_a, 2: mod_b, 3: mod_c
+        let chain = resolver.import_chain(3);
+        assert_eq!(
+            chain
+                .iter()
+                .map(|span| span.code_source_id)
+                .collect::<Vec<_>>(),
+            [2, 1, 0]
+        );
+        assert!(resolver.import_chain(0).is_empty());
+    }
+
+    #[test]
+    fn resolver_loaded_files() {
+        let mut resolver = Resolver::new(TestImporter {});
+        resolver
+            .resolve("use file_a", CodeSource::File("script.nbt".into()))
+            .unwrap();
+        resolver
+            .resolve("use file_b\nuse file_a", CodeSource::Text)
+            .unwrap();
+
+        assert_eq!(
+            resolver.loaded_files(),
+            [
+                PathBuf::from("script.nbt"),
+                PathBuf::from("modules/file_a.nbt"),
+                PathBuf::from("modules/file_b.nbt"),
+            ]
+        );
+    }
 }
@@ -0,0 +1,100 @@
+//! Restrictions for evaluating untrusted code.
+
+/// A kind of access to the outside world that can be denied by a [`SandboxPolicy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    /// Loading modules from the filesystem, and writing files
+    Filesystem,
+    /// Fetching exchange rates
+    Network,
+    /// Reading the current time with `now()`
+    Clock,
+    /// Replacing the clock with `set_fixed_time(…)`
+    FixedTime,
+    /// Reading environment variables with `env(…)`
+    Environment,
+}
+
+impl std::fmt::Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Capability::Filesystem => write!(f, "filesystem access"),
+            Capability::Network => write!(f, "network access"),
+            Capability::Clock => write!(f, "access to the system clock"),
+            Capability::FixedTime => write!(f, "setting a fixed time"),
+            Capability::Environment => write!(f, "reading environment variables"),
+        }
+    }
+}
+
+/// Determines what evaluated code is allowed to do. The policy of a
+/// [`crate::Context`] is fixed when the context is created.
+///
+/// In strict mode, all [`Capability`]s are denied, and the environment of the
+/// process is not consulted:
+/// - Modules can only be imported from importers that do not read from the
+///   filesystem (like [`crate::module_importer::BuiltinModuleImporter`]).
+/// - Exchange rates are only available if they have been provided with
+///   [`crate::Context::set_exchange_rates`] or a custom provider.
+/// - `write_text(…)` and `append_csv(…)` fail, unless a file system that does
+///   not write to disk has been provided with [`crate::Context::set_file_system`].
+/// - `now()` fails, unless a clock has been provided with
+///   [`crate::Context::set_clock`].
+/// - `set_fixed_time(…)` fails, unless it has been allowed explicitly.
+/// - `env(…)` fails, unless it has been allowed explicitly.
+/// - The local timezone is UTC, instead of the one configured on the system.
+#[derive(Debug, Clone)]
+pub struct SandboxPolicy {
+    strict: bool,
+    allow_fixed_time: bool,
+    allow_environment: bool,
+}
+
+impl SandboxPolicy {
+    pub fn unrestricted() -> Self {
+        Self {
+            strict: false,
+            allow_fixed_time: true,
+            allow_environment: true,
+        }
+    }
+
+    pub fn strict() -> Self {
+        Self {
+            strict: true,
+            allow_fixed_time: false,
+            allow_environment: false,
+        }
+    }
+
+    /// Whether or not code may replace the clock with `set_fixed_time(…)`.
+    pub fn with_fixed_time_allowed(mut self, allowed: bool) -> Self {
+        self.allow_fixed_time = allowed;
+        self
+    }
+
+    /// Whether or not code may read environment variables with `env(…)`. This can
+    /// also be used to deny access outside of strict mode.
+    pub fn with_environment_allowed(mut self, allowed: bool) -> Self {
+        self.allow_environment = allowed;
+        self
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    pub fn allows(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::FixedTime => self.allow_fixed_time,
+            Capability::Environment => self.allow_environment,
+            _ => !self.strict,
+        }
+    }
+}
+
+impl Default for SandboxPolicy {
+    fn default() -> Self {
+        Self::unrestricted()
+    }
+}
@@ -0,0 +1,346 @@
+//! This module defines lazily evaluated sequences, used in [`numbat::Value::Sequence`].
+//! They are produced by functions like `range` or `linspace` and can be consumed by
+//! `map`, `filter`, `foldl` (and everything built on top of them) without ever
+//! materializing the full list in memory. The type checker treats them as ordinary
+//! lists. A sequence is forced into a [`NumbatList`] as soon as it is printed, stored
+//! inside another list or a struct, compared, or passed to a function that does not
+//! know how to handle sequences.
+//!
+//! Evaluating `map` and `filter` sequences requires calling (user-defined) functions.
+//! All operations that might need to do that are given a [`FunctionCaller`] by the
+//! virtual machine.
+
+use crate::{
+    ffi::UnitLookup,
+    interpreter::{RuntimeError, RuntimeWarning},
+    list::NumbatList,
+    quantity::Quantity,
+    sandbox::Capability,
+    value::Value,
+};
+
+type Result<T> = std::result::Result<T, RuntimeError>;
+
+/// Reduce a number of elements to a single value with an associative operation.
+pub(crate) type Reduce = fn(&mut dyn Iterator<Item = Value>) -> Result<Value>;
+
+/// Gives native functions access to the virtual machine, in order to call function
+/// values (like `f` in `map(f, xs)`).
+pub(crate) trait FunctionCaller {
+    /// Call the given function value with the given arguments.
+    fn call(&mut self, function: &Value, args: Vec<Value>) -> Result<Value>;
+
+    /// Apply a function to all elements of a list in parallel. Returns `None` if the
+    /// list should be processed sequentially instead, for example because it is too
+    /// short or because the function is not pure.
+    fn parallel_map(
+        &mut self,
+        _function: &Value,
+        _list: &NumbatList<Value>,
+    ) -> Option<Result<Vec<Value>>> {
+        None
+    }
+
+    /// Apply `reduce` to chunks of a list in parallel, and then to the partial
+    /// results. Returns `None` if the list should be processed sequentially instead.
+    fn parallel_reduce(
+        &mut self,
+        _list: &NumbatList<Value>,
+        _reduce: Reduce,
+    ) -> Option<Result<Value>> {
+        None
+    }
+
+    /// The current time, according to the clock of the VM.
+    #[cfg_attr(not(feature = "datetime"), allow(dead_code))]
+    fn current_time(&self) -> Result<jiff::Zoned> {
+        Err(RuntimeError::PermissionDenied(Capability::Clock))
+    }
+
+    /// The units that are defined at the time of the call, if available.
+    fn unit_lookup(&self) -> Option<&dyn UnitLookup> {
+        None
+    }
+
+    /// The relative rounding error above which sums cause a warning, if enabled.
+    fn summation_error_threshold(&self) -> Option<f64> {
+        None
+    }
+
+    /// Report a warning. Warnings are shown after the evaluation.
+    fn warn(&mut self, _warning: RuntimeWarning) {}
+}
+
+impl<F: FnMut(&Value, Vec<Value>) -> Result<Value>> FunctionCaller for F {
+    fn call(&mut self, function: &Value, args: Vec<Value>) -> Result<Value> {
+        self(function, args)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sequence {
+    /// The `len` numbers `start`, `start + 1`, `start + 2`, …
+    Range { start: Quantity, len: usize },
+    /// The elements with indices `offset`, `offset + 1`, … out of `n_steps`
+    /// evenly spaced quantities from `start` to `end` (inclusive).
+    Linspace {
+        start: Quantity,
+        end: Quantity,
+        n_steps: usize,
+        offset: usize,
+    },
+    /// The result of applying a function to each element of another sequence.
+    Map(Box<Value>, Box<Sequence>),
+    /// The elements of another sequence that satisfy a predicate.
+    Filter(Box<Value>, Box<Sequence>),
+}
+
+impl Sequence {
+    /// The integer numbers from `start` to `end` (inclusive).
+    pub fn range(start: f64, end: f64) -> Self {
+        let len = if start <= end {
+            (end - start).floor() as usize + 1
+        } else {
+            0
+        };
+
+        Sequence::Range {
+            start: Quantity::from_integer(start),
+            len,
+        }
+    }
+
+    pub fn linspace(start: Quantity, end: Quantity, n_steps: usize) -> Self {
+        Sequence::Linspace {
+            start,
+            end,
+            n_steps,
+            offset: 0,
+        }
+    }
+
+    fn range_element(start: &Quantity, index: usize) -> Result<Quantity> {
+        (start + &Quantity::from_integer(index as f64)).map_err(RuntimeError::QuantityError)
+    }
+
+    fn linspace_element(
+        start: &Quantity,
+        end: &Quantity,
+        n_steps: usize,
+        index: usize,
+    ) -> Result<Value> {
+        // Computed in the same way as `start + (end - start) * i / (n_steps - 1)`
+        let difference = (end - start).map_err(RuntimeError::QuantityError)?;
+        let step = (difference * Quantity::from_scalar(index as f64))
+            .checked_div(Quantity::from_scalar((n_steps - 1) as f64))
+            .ok_or(RuntimeError::DivisionByZero)?;
+        let value = (start + &step).map_err(RuntimeError::QuantityError)?;
+        Ok(Value::Quantity(value))
+    }
+
+    /// Remove the first element of the sequence and return it, or `None` if the
+    /// sequence is empty.
+    pub(crate) fn pop_front(&mut self, caller: &mut dyn FunctionCaller) -> Result<Option<Value>> {
+        match self {
+            Sequence::Range { start, len } => {
+                if *len == 0 {
+                    return Ok(None);
+                }
+                let element = Value::Quantity(start.clone());
+                *start = Self::range_element(start, 1)?;
+                *len -= 1;
+                Ok(Some(element))
+            }
+            Sequence::Linspace {
+                start,
+                end,
+                n_steps,
+                offset,
+            } => {
+                if *offset >= *n_steps {
+                    return Ok(None);
+                }
+                let element = Self::linspace_element(start, end, *n_steps, *offset)?;
+                *offset += 1;
+                Ok(Some(element))
+            }
+            Sequence::Map(function, inner) => match inner.pop_front(caller)? {
+                Some(element) => Ok(Some(caller.call(function, vec![element])?)),
+                None => Ok(None),
+            },
+            Sequence::Filter(predicate, inner) => {
+                while let Some(element) = inner.pop_front(caller)? {
+                    if caller
+                        .call(predicate, vec![element.clone()])?
+                        .unsafe_as_bool()
+                    {
+                        return Ok(Some(element));
+                    }
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// Remove the first element of the sequence without evaluating it (if possible).
+    /// Returns `false` if the sequence is empty.
+    fn drop_front(&mut self, caller: &mut dyn FunctionCaller) -> Result<bool> {
+        match self {
+            Sequence::Map(_, inner) => inner.drop_front(caller),
+            Sequence::Range { .. } | Sequence::Linspace { .. } | Sequence::Filter(..) => {
+                Ok(self.pop_front(caller)?.is_some())
+            }
+        }
+    }
+
+    pub(crate) fn len(&self, caller: &mut dyn FunctionCaller) -> Result<usize> {
+        match self {
+            Sequence::Range { len, .. } => Ok(*len),
+            Sequence::Linspace {
+                n_steps, offset, ..
+            } => Ok(n_steps.saturating_sub(*offset)),
+            Sequence::Map(_, inner) => inner.len(caller),
+            Sequence::Filter(..) => {
+                let mut rest = self.clone();
+                let mut len = 0;
+                while rest.drop_front(caller)? {
+                    len += 1;
+                }
+                Ok(len)
+            }
+        }
+    }
+
+    pub(crate) fn is_empty(&self, caller: &mut dyn FunctionCaller) -> Result<bool> {
+        match self {
+            Sequence::Range { .. } | Sequence::Linspace { .. } | Sequence::Map(..) => {
+                Ok(self.len(caller)? == 0)
+            }
+            Sequence::Filter(..) => Ok(!self.clone().drop_front(caller)?),
+        }
+    }
+
+    pub(crate) fn head(&self, caller: &mut dyn FunctionCaller) -> Result<Value> {
+        self.clone()
+            .pop_front(caller)?
+            .ok_or(RuntimeError::EmptyList)
+    }
+
+    pub(crate) fn tail(mut self, caller: &mut dyn FunctionCaller) -> Result<Sequence> {
+        if self.drop_front(caller)? {
+            Ok(self)
+        } else {
+            Err(RuntimeError::EmptyList)
+        }
+    }
+
+    pub(crate) fn element_at(
+        &self,
+        index: usize,
+        caller: &mut dyn FunctionCaller,
+    ) -> Result<Value> {
+        match self {
+            Sequence::Range { start, len } if index < *len => {
+                Ok(Value::Quantity(Self::range_element(start, index)?))
+            }
+            Sequence::Linspace {
+                start,
+                end,
+                n_steps,
+                offset,
+            } if offset + index < *n_steps => {
+                Self::linspace_element(start, end, *n_steps, offset + index)
+            }
+            Sequence::Range { .. } | Sequence::Linspace { .. } => Err(RuntimeError::EmptyList),
+            Sequence::Map(function, inner) => {
+                let element = inner.element_at(index, caller)?;
+                caller.call(function, vec![element])
+            }
+            Sequence::Filter(..) => {
+                let mut rest = self.clone();
+                for _ in 0..index {
+                    if !rest.drop_front(caller)? {
+                        return Err(RuntimeError::EmptyList);
+                    }
+                }
+                rest.head(caller)
+            }
+        }
+    }
+
+    /// Evaluate all elements of the sequence and collect them into a list. Elements
+    /// which are sequences themselves are forced as well.
+    pub(crate) fn force(mut self, caller: &mut dyn FunctionCaller) -> Result<NumbatList<Value>> {
+        let mut list = NumbatList::new();
+        while let Some(element) = self.pop_front(caller)? {
+            list.push_back(force_value(element, caller)?);
+        }
+        Ok(list)
+    }
+}
+
+/// Turn a lazy sequence into a list. All other values are returned unchanged.
+pub(crate) fn force_value(value: Value, caller: &mut dyn FunctionCaller) -> Result<Value> {
+    match value {
+        Value::Sequence(sequence) => Ok(sequence.force(caller)?.into()),
+        value => Ok(value),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn no_calls(_: &Value, _: Vec<Value>) -> Result<Value> {
+        unreachable!("range and linspace do not call any functions")
+    }
+
+    fn scalar(value: f64) -> Value {
+        Value::Quantity(Quantity::from_scalar(value))
+    }
+
+    #[test]
+    fn range() {
+        let caller = &mut no_calls;
+
+        let sequence = Sequence::range(1.0, 1e12);
+        assert_eq!(sequence.len(caller).unwrap(), 1_000_000_000_000);
+        assert_eq!(sequence.head(caller).unwrap(), scalar(1.0));
+        assert_eq!(sequence.element_at(41, caller).unwrap(), scalar(42.0));
+
+        let tail = sequence.tail(caller).unwrap();
+        assert_eq!(tail.len(caller).unwrap(), 999_999_999_999);
+        assert_eq!(tail.head(caller).unwrap(), scalar(2.0));
+
+        let sequence = Sequence::range(3.0, 5.0);
+        assert_eq!(
+            Value::from(sequence.force(caller).unwrap()),
+            Value::from(std::collections::VecDeque::from(vec![
+                scalar(3.0),
+                scalar(4.0),
+                scalar(5.0)
+            ]))
+        );
+
+        let empty = Sequence::range(5.0, 3.0);
+        assert!(empty.is_empty(caller).unwrap());
+        assert!(matches!(empty.head(caller), Err(RuntimeError::EmptyList)));
+        assert!(matches!(empty.tail(caller), Err(RuntimeError::EmptyList)));
+    }
+
+    #[test]
+    fn linspace() {
+        let caller = &mut no_calls;
+
+        let sequence =
+            Sequence::linspace(Quantity::from_scalar(0.0), Quantity::from_scalar(1.0), 5);
+        assert_eq!(sequence.len(caller).unwrap(), 5);
+        assert_eq!(sequence.element_at(1, caller).unwrap(), scalar(0.25));
+        assert!(sequence.element_at(5, caller).is_err());
+
+        let tail = sequence.tail(caller).unwrap();
+        assert_eq!(tail.len(caller).unwrap(), 4);
+        assert_eq!(tail.head(caller).unwrap(), scalar(0.25));
+        assert_eq!(tail.element_at(3, caller).unwrap(), scalar(1.0));
+    }
+}
@@ -1,5 +1,7 @@
 use codespan_reporting::diagnostic::{Label, LabelStyle};
 
+use crate::diagnostic::{Applicability, Suggestion};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct SourceCodePositition {
     pub byte: u32,
@@ -50,7 +52,20 @@ impl Span {
         )
     }
 
-    #[cfg(test)]
+    /// An edit that replaces the code of this span
+    pub fn suggestion(
+        &self,
+        replacement: impl Into<String>,
+        applicability: Applicability,
+    ) -> Suggestion {
+        Suggestion {
+            file_id: self.code_source_id,
+            range: (self.start.byte as usize)..(self.end.byte as usize),
+            replacement: replacement.into(),
+            applicability,
+        }
+    }
+
     pub fn dummy() -> Span {
         Self {
             start: SourceCodePositition::start(),
@@ -0,0 +1,180 @@
+//! Interned strings for identifiers, unit names and dimension names.
+//!
+//! The same names are compared and hashed over and over again during type checking
+//! (e.g. when merging base representations or applying substitutions). A [`Symbol`]
+//! is a cheap-to-copy handle to a string that has been stored in a global interner.
+//! Two symbols are equal if and only if they were created from the same string, so
+//! equality checks and hashing only look at the address of the interned string.
+//!
+//! Interned strings are never freed, so only names that appear in Numbat programs
+//! (identifiers, units, dimensions, type parameters, …) should be interned. Names
+//! that are generated on the fly, like the type variables of type inference (see
+//! [`crate::type_variable::TypeVariable::Fresh`]), or strings that are computed at
+//! runtime are not interned, as there is no bound on their number. Strings from
+//! runtime values are looked up with [`Symbol::get`] instead.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::{Mutex, OnceLock};
+
+static INTERNER: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+
+fn interner() -> std::sync::MutexGuard<'static, HashSet<&'static str>> {
+    INTERNER
+        .get_or_init(|| Mutex::new(HashSet::new()))
+        .lock()
+        .unwrap()
+}
+
+#[derive(Clone, Copy)]
+pub struct Symbol(&'static str);
+
+impl Symbol {
+    /// Intern the given string.
+    pub fn new(name: &str) -> Self {
+        let mut interner = interner();
+        match interner.get(name) {
+            Some(interned) => Symbol(interned),
+            None => {
+                let interned: &'static str = Box::leak(name.into());
+                interner.insert(interned);
+                Symbol(interned)
+            }
+        }
+    }
+
+    /// Look up the symbol for the given string, without interning it. Returns `None`
+    /// if no symbol with this name has been created so far.
+    pub fn get(name: &str) -> Option<Self> {
+        interner().get(name).map(|interned| Symbol(interned))
+    }
+
+    /// The string that this symbol stands for.
+    pub fn resolve(self) -> &'static str {
+        self.0
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.0, other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.as_ptr().hash(state)
+    }
+}
+
+/// Symbols are ordered alphabetically (not by the order in which they have been
+/// interned), so that sorting by name gives a deterministic result.
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        if self == other {
+            std::cmp::Ordering::Equal
+        } else {
+            self.0.cmp(other.0)
+        }
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.0
+    }
+}
+
+impl AsRef<str> for Symbol {
+    fn as_ref(&self) -> &str {
+        self.0
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Self {
+        Symbol::new(name)
+    }
+}
+
+impl From<&String> for Symbol {
+    fn from(name: &String) -> Self {
+        Symbol::new(name)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(name: String) -> Self {
+        Symbol::new(&name)
+    }
+}
+
+impl From<Symbol> for String {
+    fn from(symbol: Symbol) -> Self {
+        symbol.0.to_owned()
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.0, f)
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.0, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interning() {
+        let meter = Symbol::new("meter");
+        assert_eq!(meter, Symbol::new(&String::from("meter")));
+        assert_ne!(meter, Symbol::new("second"));
+        assert_eq!(meter.resolve(), "meter");
+        assert_eq!(meter, "meter");
+
+        assert_eq!(Symbol::get("meter"), Some(meter));
+        assert_eq!(Symbol::get("a name that was never interned"), None);
+    }
+
+    #[test]
+    fn ordering() {
+        let mut symbols = vec![Symbol::new("b"), Symbol::new("c"), Symbol::new("a")];
+        symbols.sort();
+        assert_eq!(symbols, vec!["a", "b", "c"]);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::error_code::ErrorCode;
 use crate::span::{SourceCodePositition, Span};
 
 use std::collections::HashMap;
@@ -35,6 +36,54 @@ pub enum TokenizerErrorKind {
 
     #[error("Unexpected '{{' inside string interpolation")]
     UnexpectedCurlyInInterpolation,
+
+    #[error("Ambiguous comma in number literal")]
+    AmbiguousDecimalComma,
+
+    #[error("Consecutive underscores in number literal")]
+    ConsecutiveUnderscores,
+
+    #[error("Number literal can not end with an underscore")]
+    TrailingUnderscore,
+
+    #[error("Underscore next to the decimal point")]
+    UnderscoreNextToDecimalPoint,
+
+    #[error("Underscore next to the exponent marker")]
+    UnderscoreNextToExponentMarker,
+
+    #[error("Exponent can not start with an underscore")]
+    LeadingUnderscoreInExponent,
+
+    #[error("Expected digits in exponent")]
+    MissingExponent,
+
+    #[error("Expected binary exponent ('p') in hexadecimal floating point literal")]
+    MissingBinaryExponent,
+}
+
+impl TokenizerErrorKind {
+    pub fn error_code(&self) -> ErrorCode {
+        match self {
+            TokenizerErrorKind::UnexpectedCharacter { .. } => ErrorCode::E0101,
+            TokenizerErrorKind::UnexpectedCharacterInNegativeExponent { .. } => ErrorCode::E0102,
+            TokenizerErrorKind::UnexpectedCharacterInNumberLiteral(..) => ErrorCode::E0103,
+            TokenizerErrorKind::UnexpectedCharacterInIdentifier(..) => ErrorCode::E0104,
+            TokenizerErrorKind::ExpectedDigit { .. } => ErrorCode::E0105,
+            TokenizerErrorKind::ExpectedDigitInBase { .. } => ErrorCode::E0106,
+            TokenizerErrorKind::UnterminatedString => ErrorCode::E0107,
+            TokenizerErrorKind::UnterminatedStringInterpolation => ErrorCode::E0108,
+            TokenizerErrorKind::UnexpectedCurlyInInterpolation => ErrorCode::E0109,
+            TokenizerErrorKind::AmbiguousDecimalComma => ErrorCode::E0110,
+            TokenizerErrorKind::ConsecutiveUnderscores => ErrorCode::E0111,
+            TokenizerErrorKind::TrailingUnderscore => ErrorCode::E0112,
+            TokenizerErrorKind::UnderscoreNextToDecimalPoint => ErrorCode::E0113,
+            TokenizerErrorKind::UnderscoreNextToExponentMarker => ErrorCode::E0114,
+            TokenizerErrorKind::LeadingUnderscoreInExponent => ErrorCode::E0115,
+            TokenizerErrorKind::MissingExponent => ErrorCode::E0116,
+            TokenizerErrorKind::MissingBinaryExponent => ErrorCode::E0117,
+        }
+    }
 }
 
 #[derive(Debug, Error, PartialEq, Eq)]
@@ -89,12 +138,18 @@ pub enum TokenKind {
     To,
     Let,
     Fn, // 'fn'
+    UnitFn,
+    Inverse,
     Where,
+    With,
     And,
     Dimension,
     Unit,
     Use,
     Struct,
+    Impl,
+    Formula,
+    UnitSystem,
 
     Long,
     Short,
@@ -104,6 +159,10 @@ pub enum TokenKind {
     If,
     Then,
     Else,
+    For,
+    While,
+    Try,
+    Catch,
     True,
     False,
 
@@ -114,6 +173,7 @@ pub enum TokenKind {
     Bool,
     String,
     DateTime,
+    Dynamic,
     CapitalFn, // 'Fn'
     List,
 
@@ -122,10 +182,27 @@ pub enum TokenKind {
     ProcedureAssert,
     ProcedureAssertEq,
     ProcedureType,
+    ProcedureConversionCacheStats,
+    ProcedureRegexCacheStats,
+    ProcedureInstantiationCacheStats,
+    ProcedureSetFixedTime,
+    ProcedureSetImplicitConversions,
+    ProcedureWriteText,
+    ProcedureAppendCsv,
+
+    // Partial application 'partial(f, …)'
+    Partial,
+    // Derivative of a function 'autodiff(f)'
+    AutoDiff,
+    // Dimension predicates 'is_dimensionless(x)' and 'has_dimension(x, D)'
+    IsDimensionless,
+    HasDimension,
 
     // Variable-length tokens
     Number,
     IntegerWithBase(usize),
+    /// `0x1.8p3`, which is 1.5 × 2³
+    HexadecimalFloat,
     Identifier,
 
     // A normal string without interpolation: `"hello world"`
@@ -185,7 +262,9 @@ fn is_currency_char(c: char) -> bool {
     (0x20A0..=0x20CF).contains(&c_u32) || c == '£' || c == '¥' || c == '$' || c == '฿'
 }
 
-fn is_other_allowed_identifier_char(c: char) -> bool {
+/// The units `%` and `‰` always form an identifier on their own, so `5%`, `5 %`
+/// and `x%` all denote a multiplication with the unit, independent of spacing.
+fn is_percent_char(c: char) -> bool {
     matches!(c, '%' | '‰')
 }
 
@@ -200,22 +279,28 @@ fn is_identifier_start(c: char) -> bool {
     unicode_ident::is_xid_start(c)
         || is_numerical_fraction_char(c)
         || is_currency_char(c)
-        || is_other_allowed_identifier_char(c)
+        || is_percent_char(c)
         || c == '°'
         || c == '′'
         || c == '″'
         || c == '_'
 }
 
+/// Note that `%` and `‰` are identifiers on their own, they can not be part of
+/// longer identifiers (see [`is_percent_char`]).
 fn is_identifier_continue(c: char) -> bool {
-    (unicode_ident::is_xid_continue(c)
-        || is_subscript_char(c)
-        || is_currency_char(c)
-        || is_other_allowed_identifier_char(c))
+    (unicode_ident::is_xid_continue(c) || is_subscript_char(c) || is_currency_char(c))
         && !is_exponent_char(c)
         && c != '·'
 }
 
+/// The micro sign `µ` (U+00B5) and the Greek letter `μ` (U+03BC) look the same and are
+/// both commonly typed, so identifiers are normalized to the latter. Both `µs` and `μs`
+/// refer to microseconds, and `let µx = 1` defines `μx`.
+pub(crate) fn normalize_identifier(identifier: &str) -> String {
+    identifier.replace('\u{00B5}', "\u{03BC}")
+}
+
 /// When scanning a string interpolation like `"foo = {foo}, and bar = {bar}."`,
 /// the tokenizer needs to keep track of where it currently is, because we allow
 /// for (almost) arbitrary expressions inside the {…} part.
@@ -232,6 +317,18 @@ impl InterpolationState {
     }
 }
 
+/// How a comma directly between two digits (like in `1,5`) is handled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DigitComma {
+    /// It separates two numbers
+    #[default]
+    Separator,
+    /// It is an error, because it might have been meant as a decimal comma
+    Ambiguous,
+    /// It is a decimal mark
+    DecimalMark,
+}
+
 struct Tokenizer {
     input: Vec<char>,
     current: SourceCodePositition,
@@ -240,6 +337,7 @@ struct Tokenizer {
     current_index: usize,
     token_start_index: usize,
     code_source_id: usize,
+    digit_comma: DigitComma,
 
     // Special fields / state for parsing string interpolations
     string_start: SourceCodePositition,
@@ -248,7 +346,7 @@ struct Tokenizer {
 }
 
 impl Tokenizer {
-    fn new(input: &str, code_source_id: usize) -> Self {
+    fn new(input: &str, code_source_id: usize, digit_comma: DigitComma) -> Self {
         Tokenizer {
             input: input.chars().collect(),
             current: SourceCodePositition::start(),
@@ -257,6 +355,7 @@ impl Tokenizer {
             current_index: 0,
             token_start_index: 0,
             code_source_id,
+            digit_comma,
             string_start: SourceCodePositition::start(),
             interpolation_start: SourceCodePositition::start(),
             interpolation_state: InterpolationState::Outside,
@@ -265,6 +364,12 @@ impl Tokenizer {
 
     fn scan(&mut self) -> Result<Vec<Token>> {
         let mut tokens = vec![];
+        self.scan_into(&mut tokens)?;
+        Ok(tokens)
+    }
+
+    /// Scan the input until the end or until the first error.
+    fn scan_into(&mut self, tokens: &mut Vec<Token>) -> Result<()> {
         while !self.at_end() {
             self.token_start = self.current;
             self.token_start_index = self.current_index;
@@ -279,16 +384,29 @@ impl Tokenizer {
             span: self.current.single_character_span(self.code_source_id),
         });
 
-        Ok(tokens)
+        Ok(())
+    }
+
+    /// The character before the current position (if it belongs to the input)
+    fn previous(&self) -> Option<char> {
+        self.current_index
+            .checked_sub(1)
+            .and_then(|i| self.input.get(i).copied())
     }
 
+    /// Consume digits and underscores for digit grouping. Underscores may only
+    /// appear between two digits.
     fn consume_stream_of_digits(
         &mut self,
         at_least_one_digit: bool,
-        disallow_leading_underscore: bool,
         disallow_dot_after_stream: bool,
     ) -> Result<()> {
-        if at_least_one_digit && !self.peek().map(|c| c.is_ascii_digit()).unwrap_or(false) {
+        if at_least_one_digit
+            && !self
+                .peek()
+                .map(|c| c.is_ascii_digit() || c == '_')
+                .unwrap_or(false)
+        {
             return Err(TokenizerError {
                 kind: TokenizerErrorKind::ExpectedDigit {
                     character: self.peek(),
@@ -297,27 +415,34 @@ impl Tokenizer {
             });
         }
 
-        // Make sure we don't start with an underscore
-        if disallow_leading_underscore && self.peek().map(|c| c == '_').unwrap_or(false) {
-            return Err(TokenizerError {
-                kind: TokenizerErrorKind::UnexpectedCharacterInNumberLiteral(self.peek().unwrap()),
-                span: self.current.single_character_span(self.code_source_id),
-            });
-        }
-
-        let mut last_char = None;
-        while self
-            .peek()
-            .map(|c| c.is_ascii_digit() || c == '_')
-            .unwrap_or(false)
-        {
-            last_char = Some(self.advance());
+        while let Some(c) = self.peek().filter(|c| c.is_ascii_digit() || *c == '_') {
+            if c == '_' {
+                let kind = match self.previous() {
+                    Some('_') => Some(TokenizerErrorKind::ConsecutiveUnderscores),
+                    Some('.' | ',') => Some(TokenizerErrorKind::UnderscoreNextToDecimalPoint),
+                    Some('e' | 'E') => Some(TokenizerErrorKind::UnderscoreNextToExponentMarker),
+                    Some('+' | '-') => Some(TokenizerErrorKind::LeadingUnderscoreInExponent),
+                    _ => None,
+                };
+                if let Some(kind) = kind {
+                    return Err(TokenizerError {
+                        kind,
+                        span: self.current.single_character_span(self.code_source_id),
+                    });
+                }
+            }
+            self.advance();
         }
 
         // Make sure we don't end with an underscore
-        if last_char == Some('_') {
+        if self.previous() == Some('_') {
+            let kind = match self.peek() {
+                Some('.') => TokenizerErrorKind::UnderscoreNextToDecimalPoint,
+                Some('e' | 'E') => TokenizerErrorKind::UnderscoreNextToExponentMarker,
+                _ => TokenizerErrorKind::TrailingUnderscore,
+            };
             return Err(TokenizerError {
-                kind: TokenizerErrorKind::UnexpectedCharacterInNumberLiteral('_'),
+                kind,
                 span: self.last.single_character_span(self.code_source_id),
             });
         }
@@ -332,19 +457,56 @@ impl Tokenizer {
         Ok(())
     }
 
+    /// An optional uncertainty in the last digits of a decimal number, like the `(15)`
+    /// in `6.67430(15)e-11`. It needs to follow the fractional digits immediately, so
+    /// that `1.5 (2)` or `1.5(x)` are still multiplications.
+    fn uncertainty_shorthand(&mut self) {
+        let rest = &self.input[self.current_index..];
+        let num_digits = rest
+            .iter()
+            .skip(1)
+            .take_while(|c| c.is_ascii_digit())
+            .count();
+        if rest.first() == Some(&'(') && num_digits > 0 && rest.get(num_digits + 1) == Some(&')') {
+            for _ in 0..num_digits + 2 {
+                self.advance();
+            }
+        }
+    }
+
+    /// An optional exponent like `e3` or `E-3`. An `e` that is directly followed
+    /// by other identifier characters (like in `2eV`) is not an exponent marker.
     fn scientific_notation(&mut self) -> Result<()> {
-        if self
-            .peek2()
-            .map(|c| c.is_ascii_digit() || c == '+' || c == '-')
+        if !matches!(self.peek(), Some('e' | 'E')) {
+            return Ok(());
+        }
+        match self.peek2() {
+            Some(c) if c.is_ascii_digit() || matches!(c, '+' | '-' | '_') => {}
+            Some(c) if is_identifier_continue(c) => return Ok(()),
+            _ => {
+                self.advance();
+                return Err(TokenizerError {
+                    kind: TokenizerErrorKind::MissingExponent,
+                    span: self.current.single_character_span(self.code_source_id),
+                });
+            }
+        }
+
+        self.advance();
+        let _ = self.match_char('+') || self.match_char('-');
+
+        if !self
+            .peek()
+            .map(|c| c.is_ascii_digit() || c == '_')
             .unwrap_or(false)
-            && (self.match_char('e') || self.match_char('E'))
         {
-            let _ = self.match_char('+') || self.match_char('-');
-
-            self.consume_stream_of_digits(true, true, true)?;
+            return Err(TokenizerError {
+                kind: TokenizerErrorKind::MissingExponent,
+                span: self.current.single_character_span(self.code_source_id),
+            });
         }
 
-        Ok(())
+        self.consume_stream_of_digits(true, true)
     }
 
     fn consume_string(&mut self) -> Result<()> {
@@ -376,12 +538,18 @@ impl Tokenizer {
             m.insert("to", TokenKind::To);
             m.insert("let", TokenKind::Let);
             m.insert("fn", TokenKind::Fn);
+            m.insert("unitfn", TokenKind::UnitFn);
+            m.insert("inverse", TokenKind::Inverse);
             m.insert("where", TokenKind::Where);
+            m.insert("with", TokenKind::With);
             m.insert("and", TokenKind::And);
             m.insert("dimension", TokenKind::Dimension);
             m.insert("unit", TokenKind::Unit);
             m.insert("use", TokenKind::Use);
             m.insert("struct", TokenKind::Struct);
+            m.insert("impl", TokenKind::Impl);
+            m.insert("formula", TokenKind::Formula);
+            m.insert("unit_system", TokenKind::UnitSystem);
             m.insert("long", TokenKind::Long);
             m.insert("short", TokenKind::Short);
             m.insert("both", TokenKind::Both);
@@ -389,6 +557,10 @@ impl Tokenizer {
             m.insert("if", TokenKind::If);
             m.insert("then", TokenKind::Then);
             m.insert("else", TokenKind::Else);
+            m.insert("for", TokenKind::For);
+            m.insert("while", TokenKind::While);
+            m.insert("try", TokenKind::Try);
+            m.insert("catch", TokenKind::Catch);
             m.insert("true", TokenKind::True);
             m.insert("false", TokenKind::False);
             m.insert("NaN", TokenKind::NaN);
@@ -399,11 +571,32 @@ impl Tokenizer {
             m.insert("assert", TokenKind::ProcedureAssert);
             m.insert("assert_eq", TokenKind::ProcedureAssertEq);
             m.insert("type", TokenKind::ProcedureType);
+            m.insert(
+                "conversion_cache_stats",
+                TokenKind::ProcedureConversionCacheStats,
+            );
+            m.insert("regex_cache_stats", TokenKind::ProcedureRegexCacheStats);
+            m.insert(
+                "instantiation_cache_stats",
+                TokenKind::ProcedureInstantiationCacheStats,
+            );
+            m.insert("set_fixed_time", TokenKind::ProcedureSetFixedTime);
+            m.insert(
+                "set_implicit_conversions",
+                TokenKind::ProcedureSetImplicitConversions,
+            );
+            m.insert("write_text", TokenKind::ProcedureWriteText);
+            m.insert("append_csv", TokenKind::ProcedureAppendCsv);
+            m.insert("partial", TokenKind::Partial);
+            m.insert("autodiff", TokenKind::AutoDiff);
+            m.insert("is_dimensionless", TokenKind::IsDimensionless);
+            m.insert("has_dimension", TokenKind::HasDimension);
 
             // type names
             m.insert("Bool", TokenKind::Bool);
             m.insert("String", TokenKind::String);
             m.insert("DateTime", TokenKind::DateTime);
+            m.insert("Dynamic", TokenKind::Dynamic);
             m.insert("Fn", TokenKind::CapitalFn);
             m.insert("List", TokenKind::List);
 
@@ -462,35 +655,62 @@ impl Tokenizer {
                     };
 
                 self.advance(); // skip over the x/o/b
+                let digits_start = self.current;
+
+                // An underscore may directly follow the prefix (`0x_ff`)
+                let mut has_digits = false;
+                while let Some(c) = self.peek().filter(|&c| is_digit_in_base(c) || c == '_') {
+                    if c == '_' && self.previous() == Some('_') {
+                        return tokenizer_error(
+                            &self.current,
+                            TokenizerErrorKind::ConsecutiveUnderscores,
+                        );
+                    }
+                    has_digits |= c != '_';
+                    self.advance();
+                }
 
-                // If the first character is not a digits, that's an error.
-                if !self.peek().map(&is_digit_in_base).unwrap_or(false) {
+                if !has_digits {
                     return tokenizer_error(
-                        &self.current,
+                        &digits_start,
                         TokenizerErrorKind::ExpectedDigitInBase {
                             base,
-                            character: self.peek(),
+                            character: self.input.get(self.token_start_index + 2).copied(),
                         },
                     );
                 }
 
-                let mut last_char = None;
+                if self.previous() == Some('_') {
+                    return tokenizer_error(&self.last, TokenizerErrorKind::TrailingUnderscore);
+                }
 
-                while self
-                    .peek()
-                    .map(|c| is_digit_in_base(c) || c == '_')
-                    .unwrap_or(false)
-                {
-                    last_char = self.peek();
-                    self.advance();
+                let is_float = base == 16 && matches!(self.peek(), Some('.' | 'p' | 'P'));
+                if is_float {
+                    if self.match_char('.') {
+                        while self.peek().is_some_and(|c| c.is_ascii_hexdigit()) {
+                            self.advance();
+                        }
+                    }
+
+                    if !(self.match_char('p') || self.match_char('P')) {
+                        return tokenizer_error(
+                            &self.current,
+                            TokenizerErrorKind::MissingBinaryExponent,
+                        );
+                    }
+                    let _ = self.match_char('+') || self.match_char('-');
+                    if !self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                        return tokenizer_error(&self.current, TokenizerErrorKind::MissingExponent);
+                    }
+                    while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                        self.advance();
+                    }
                 }
 
-                // Numeric literal should not end with a `_` either.
-                if last_char == Some('_')
-                    || self
-                        .peek()
-                        .map(|c| is_identifier_continue(c) || c == '.')
-                        .unwrap_or(false)
+                if self
+                    .peek()
+                    .map(|c| is_identifier_continue(c) || c == '.')
+                    .unwrap_or(false)
                 {
                     return tokenizer_error(
                         &self.current,
@@ -501,14 +721,38 @@ impl Tokenizer {
                     );
                 }
 
-                TokenKind::IntegerWithBase(base)
+                if is_float {
+                    TokenKind::HexadecimalFloat
+                } else {
+                    TokenKind::IntegerWithBase(base)
+                }
             }
             c if c.is_ascii_digit() => {
-                self.consume_stream_of_digits(false, false, false)?;
+                self.consume_stream_of_digits(false, false)?;
 
                 // decimal part
                 if self.match_char('.') {
-                    self.consume_stream_of_digits(false, true, true)?;
+                    self.consume_stream_of_digits(false, true)?;
+                    if self.previous().is_some_and(|c| c.is_ascii_digit()) {
+                        self.uncertainty_shorthand();
+                    }
+                } else if self.peek() == Some(',')
+                    && self.peek2().is_some_and(|c| c.is_ascii_digit())
+                {
+                    match self.digit_comma {
+                        DigitComma::Separator => {}
+                        DigitComma::Ambiguous => {
+                            return Err(TokenizerError {
+                                kind: TokenizerErrorKind::AmbiguousDecimalComma,
+                                span: self.current.single_character_span(self.code_source_id),
+                            });
+                        }
+                        DigitComma::DecimalMark => {
+                            self.advance();
+                            self.consume_stream_of_digits(false, true)?;
+                            self.uncertainty_shorthand();
+                        }
+                    }
                 }
 
                 self.scientific_notation()?;
@@ -523,7 +767,8 @@ impl Tokenizer {
             }
             '.' if self.peek().map_or(false, is_identifier_start) => TokenKind::Period,
             '.' => {
-                self.consume_stream_of_digits(true, true, true)?;
+                self.consume_stream_of_digits(true, true)?;
+                self.uncertainty_shorthand();
                 self.scientific_notation()?;
 
                 TokenKind::Number
@@ -656,16 +901,20 @@ impl Tokenizer {
                 });
             }
             '…' => TokenKind::Ellipsis,
+            c if is_percent_char(c) => TokenKind::Identifier,
             c if is_identifier_start(c) => {
                 while self.peek().map(is_identifier_continue).unwrap_or(false) {
                     self.advance();
                 }
 
+                // An identifier can be followed by a method call (`x.foo`) or by
+                // an ellipsis (`xs: D...`), but not by a single period
                 if self.peek().map(|c| c == '.').unwrap_or(false)
                     && self
                         .peek2()
                         .map(|c| !is_identifier_start(c))
                         .unwrap_or(true)
+                    && !self.is_at_ellipsis()
                 {
                     return tokenizer_error(
                         &self.current,
@@ -689,9 +938,15 @@ impl Tokenizer {
             }
         };
 
+        let lexeme = if kind == TokenKind::Identifier {
+            normalize_identifier(&self.lexeme())
+        } else {
+            self.lexeme()
+        };
+
         let token = Some(Token {
             kind,
-            lexeme: self.lexeme(),
+            lexeme,
             span: Span {
                 start: self.token_start,
                 end: self.current,
@@ -730,6 +985,10 @@ impl Tokenizer {
         self.input.get(self.current_index + 1).copied()
     }
 
+    fn is_at_ellipsis(&self) -> bool {
+        self.input[self.current_index..].starts_with(&['.', '.', '.'])
+    }
+
     fn match_char(&mut self, c: char) -> bool {
         if self.peek() == Some(c) {
             self.advance();
@@ -745,10 +1004,29 @@ impl Tokenizer {
 }
 
 pub fn tokenize(input: &str, code_source_id: usize) -> Result<Vec<Token>> {
-    let mut tokenizer = Tokenizer::new(input, code_source_id);
+    tokenize_with_digit_comma(input, code_source_id, DigitComma::default())
+}
+
+pub fn tokenize_with_digit_comma(
+    input: &str,
+    code_source_id: usize,
+    digit_comma: DigitComma,
+) -> Result<Vec<Token>> {
+    let mut tokenizer = Tokenizer::new(input, code_source_id, digit_comma);
     tokenizer.scan()
 }
 
+/// Like [`tokenize`], but also returns the tokens in front of a tokenizer error.
+pub fn tokenize_partial(
+    input: &str,
+    code_source_id: usize,
+) -> (Vec<Token>, Option<TokenizerError>) {
+    let mut tokenizer = Tokenizer::new(input, code_source_id, DigitComma::default());
+    let mut tokens = vec![];
+    let error = tokenizer.scan_into(&mut tokens).err();
+    (tokens, error)
+}
+
 #[cfg(test)]
 fn tokenize_reduced(input: &str) -> Result<Vec<(String, TokenKind, (u32, u32))>, String> {
     Ok(tokenize(input, 0)
@@ -969,15 +1247,150 @@ fn test_tokenize_numbers() {
     "###
     );
 
+    insta::assert_snapshot!(
+        tokenize_reduced_pretty("0x_ff").unwrap(),
+        @r###"
+    "0x_ff", IntegerWithBase(16), (1, 1)
+    "", Eof, (1, 6)
+    "###
+    );
+
+    insta::assert_snapshot!(
+        tokenize_reduced_pretty("0x1.8p-3 m").unwrap(),
+        @r###"
+    "0x1.8p-3", HexadecimalFloat, (1, 1)
+    "m", Identifier, (1, 10)
+    "", Eof, (1, 11)
+    "###
+    );
+
+    insta::assert_snapshot!(
+        tokenize_reduced_pretty("1_000.000_1e-3").unwrap(),
+        @r###"
+    "1_000.000_1e-3", Number, (1, 1)
+    "", Eof, (1, 15)
+    "###
+    );
+
+    insta::assert_snapshot!(
+        tokenize_reduced_pretty("2e3 m").unwrap(),
+        @r###"
+    "2e3", Number, (1, 1)
+    "m", Identifier, (1, 5)
+    "", Eof, (1, 6)
+    "###
+    );
+
+    insta::assert_snapshot!(
+        tokenize_reduced_pretty("6.67430(15)e-11 (4)").unwrap(),
+        @r###"
+    "6.67430(15)e-11", Number, (1, 1)
+    "(", LeftParen, (1, 17)
+    "4", Number, (1, 18)
+    ")", RightParen, (1, 19)
+    "", Eof, (1, 20)
+    "###
+    );
+
+    // Not an uncertainty
+    insta::assert_snapshot!(
+        tokenize_reduced_pretty("2(3) 1.5 (2) 1.(2)").unwrap(),
+        @r###"
+    "2", Number, (1, 1)
+    "(", LeftParen, (1, 2)
+    "3", Number, (1, 3)
+    ")", RightParen, (1, 4)
+    "1.5", Number, (1, 6)
+    "(", LeftParen, (1, 10)
+    "2", Number, (1, 11)
+    ")", RightParen, (1, 12)
+    "1.", Number, (1, 14)
+    "(", LeftParen, (1, 16)
+    "2", Number, (1, 17)
+    ")", RightParen, (1, 18)
+    "", Eof, (1, 19)
+    "###
+    );
+
+    // Not an exponent
+    insta::assert_snapshot!(
+        tokenize_reduced_pretty("2eV").unwrap(),
+        @r###"
+    "2", Number, (1, 1)
+    "eV", Identifier, (1, 2)
+    "", Eof, (1, 4)
+    "###
+    );
+
     // Failing queries
     insta::assert_snapshot!(
         tokenize_reduced_pretty("1_.2").unwrap_err(),
-        @"Error at (1, 2): `Unexpected character in number literal: '_'`"
+        @"Error at (1, 2): `Underscore next to the decimal point`"
+    );
+
+    insta::assert_snapshot!(
+        tokenize_reduced_pretty("1._2").unwrap_err(),
+        @"Error at (1, 3): `Underscore next to the decimal point`"
     );
 
     insta::assert_snapshot!(
         tokenize_reduced_pretty("1.2_").unwrap_err(),
-        @"Error at (1, 4): `Unexpected character in number literal: '_'`"
+        @"Error at (1, 4): `Number literal can not end with an underscore`"
+    );
+
+    insta::assert_snapshot!(
+        tokenize_reduced_pretty("1000_").unwrap_err(),
+        @"Error at (1, 5): `Number literal can not end with an underscore`"
+    );
+
+    insta::assert_snapshot!(
+        tokenize_reduced_pretty("1__000").unwrap_err(),
+        @"Error at (1, 3): `Consecutive underscores in number literal`"
+    );
+
+    insta::assert_snapshot!(
+        tokenize_reduced_pretty("1.000__1").unwrap_err(),
+        @"Error at (1, 7): `Consecutive underscores in number literal`"
+    );
+
+    insta::assert_snapshot!(
+        tokenize_reduced_pretty("1_e3").unwrap_err(),
+        @"Error at (1, 2): `Underscore next to the exponent marker`"
+    );
+
+    insta::assert_snapshot!(
+        tokenize_reduced_pretty("1e_3").unwrap_err(),
+        @"Error at (1, 3): `Underscore next to the exponent marker`"
+    );
+
+    insta::assert_snapshot!(
+        tokenize_reduced_pretty("1e-_3").unwrap_err(),
+        @"Error at (1, 4): `Exponent can not start with an underscore`"
+    );
+
+    insta::assert_snapshot!(
+        tokenize_reduced_pretty("1e3_").unwrap_err(),
+        @"Error at (1, 4): `Number literal can not end with an underscore`"
+    );
+
+    insta::assert_snapshot!(
+        tokenize_reduced_pretty("1e").unwrap_err(),
+        @"Error at (1, 3): `Expected digits in exponent`"
+    );
+
+    insta::assert_snapshot!(
+        tokenize_reduced_pretty("1e+").unwrap_err(),
+        @"Error at (1, 4): `Expected digits in exponent`"
+    );
+
+    insta::assert_snapshot!(
+        tokenize_reduced_pretty("1.5E- 3").unwrap_err(),
+        @"Error at (1, 6): `Expected digits in exponent`"
+    );
+
+    insta::assert_snapshot!(
+        tokenize_reduced_pretty("(2e)").unwrap_err(),
+        @"Error at (1, 4): `Expected digits in exponent`"
     );
 
     insta::assert_snapshot!(
@@ -996,13 +1409,13 @@ fn test_tokenize_numbers() {
     );
 
     insta::assert_snapshot!(
-        tokenize_reduced_pretty("0b_1").unwrap_err(),
-        @"Error at (1, 3): `Expected base-2 digit`"
+        tokenize_reduced_pretty("0b__1").unwrap_err(),
+        @"Error at (1, 4): `Consecutive underscores in number literal`"
     );
 
     insta::assert_snapshot!(
         tokenize_reduced_pretty("0b1_").unwrap_err(),
-        @"Error at (1, 5): `Expected base-2 digit`"
+        @"Error at (1, 4): `Number literal can not end with an underscore`"
     );
 
     insta::assert_snapshot!(
@@ -1021,13 +1434,13 @@ fn test_tokenize_numbers() {
     );
 
     insta::assert_snapshot!(
-        tokenize_reduced_pretty("0o_1").unwrap_err(),
-        @"Error at (1, 3): `Expected base-8 digit`"
+        tokenize_reduced_pretty("0o__1").unwrap_err(),
+        @"Error at (1, 4): `Consecutive underscores in number literal`"
     );
 
     insta::assert_snapshot!(
         tokenize_reduced_pretty("0o1_").unwrap_err(),
-        @"Error at (1, 5): `Expected base-8 digit`"
+        @"Error at (1, 4): `Number literal can not end with an underscore`"
     );
 
     insta::assert_snapshot!(
@@ -1046,13 +1459,13 @@ fn test_tokenize_numbers() {
     );
 
     insta::assert_snapshot!(
-        tokenize_reduced_pretty("0x_1").unwrap_err(),
-        @"Error at (1, 3): `Expected base-16 digit`"
+        tokenize_reduced_pretty("0x__1").unwrap_err(),
+        @"Error at (1, 4): `Consecutive underscores in number literal`"
     );
 
     insta::assert_snapshot!(
         tokenize_reduced_pretty("0x1_").unwrap_err(),
-        @"Error at (1, 5): `Expected base-16 digit`"
+        @"Error at (1, 4): `Number literal can not end with an underscore`"
     );
 }
 
@@ -1204,6 +1617,33 @@ fn test_is_subscript_char() {
     assert!(is_subscript_char('₎'));
 }
 
+#[test]
+fn test_percent() {
+    use TokenKind::*;
+
+    for input in ["5%", "5 %"] {
+        assert_eq!(
+            tokenize_reduced(input).unwrap()[..2],
+            [
+                ("5".to_string(), Number, (1, 1)),
+                ("%".to_string(), Identifier, (1, input.len() as u32))
+            ]
+        );
+    }
+
+    // `%` and `‰` are never part of longer identifiers
+    assert_eq!(
+        tokenize_reduced("x%‰y").unwrap(),
+        [
+            ("x".to_string(), Identifier, (1, 1)),
+            ("%".to_string(), Identifier, (1, 2)),
+            ("‰".to_string(), Identifier, (1, 3)),
+            ("y".to_string(), Identifier, (1, 4)),
+            ("".to_string(), Eof, (1, 5))
+        ]
+    );
+}
+
 #[test]
 fn test_field_access() {
     insta::assert_snapshot!(
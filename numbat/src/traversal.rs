@@ -1,6 +1,11 @@
 use crate::typechecker::type_scheme::TypeScheme;
-use crate::typed_ast::{DefineVariable, Expression, Statement, StructInfo};
+use crate::typed_ast::{
+    DefineVariable, Expression, ExpressionArena, ExpressionId, Pattern, Statement, StringPart,
+    StructInfo,
+};
 
+/// Visits all type schemes that are stored directly in a node. Sub-expressions live in
+/// the [`ExpressionArena`] and are not visited.
 pub trait ForAllTypeSchemes {
     fn for_all_type_schemes(&mut self, f: &mut dyn FnMut(&mut TypeScheme));
 }
@@ -11,66 +16,68 @@ impl ForAllTypeSchemes for StructInfo {
     }
 }
 
-impl ForAllTypeSchemes for Expression {
+impl ForAllTypeSchemes for Pattern {
     fn for_all_type_schemes(&mut self, f: &mut dyn FnMut(&mut TypeScheme)) {
         match self {
-            Expression::Scalar(_, _, type_) => f(type_),
-            Expression::Identifier(_, _, type_) => f(type_),
-            Expression::UnitIdentifier(_, _, _, _, type_) => f(type_),
-            Expression::UnaryOperator(_, _, expr, type_) => {
-                expr.for_all_type_schemes(f);
-                f(type_);
-            }
-            Expression::BinaryOperator(_, _, lhs, rhs, type_) => {
-                lhs.for_all_type_schemes(f);
-                rhs.for_all_type_schemes(f);
-                f(type_);
-            }
-            Expression::BinaryOperatorForDate(_, _, lhs, rhs, type_) => {
-                lhs.for_all_type_schemes(f);
-                rhs.for_all_type_schemes(f);
-                f(type_);
-            }
-            Expression::FunctionCall(_, _, _, args, type_) => {
-                for arg in args {
-                    arg.for_all_type_schemes(f);
+            Pattern::Identifier(_) => {}
+            Pattern::Struct(fields, struct_type) => {
+                for (_, pattern) in fields {
+                    pattern.for_all_type_schemes(f);
                 }
-                f(type_)
+                f(struct_type);
             }
-            Expression::CallableCall(_, callable, args, type_) => {
-                callable.for_all_type_schemes(f);
-                for arg in args {
-                    arg.for_all_type_schemes(f);
+            Pattern::List(elements) => {
+                for pattern in elements {
+                    pattern.for_all_type_schemes(f);
                 }
-                f(type_)
             }
+        }
+    }
+}
+
+impl ForAllTypeSchemes for DefineVariable {
+    fn for_all_type_schemes(&mut self, f: &mut dyn FnMut(&mut TypeScheme)) {
+        let DefineVariable(pattern, _, _, _annotation, type_, _) = self;
+        pattern.for_all_type_schemes(f);
+        f(type_);
+    }
+}
+
+impl ForAllTypeSchemes for Expression {
+    fn for_all_type_schemes(&mut self, f: &mut dyn FnMut(&mut TypeScheme)) {
+        match self {
+            Expression::Scalar(_, _, type_) => f(type_),
+            Expression::Identifier(_, _, type_) => f(type_),
+            Expression::UnitIdentifier(_, _, _, _, type_) => f(type_),
+            Expression::UnaryOperator(_, _, _, type_) => f(type_),
+            Expression::BinaryOperator(_, _, _, _, type_) => f(type_),
+            Expression::BinaryOperatorForDate(_, _, _, _, type_) => f(type_),
+            Expression::Conversion(_, _, _, type_) => f(type_),
+            Expression::ChainedComparison(_) => {}
+            Expression::FunctionCall(_, _, _, _, _, type_) => f(type_),
+            Expression::CallableCall(_, _, _, type_) => f(type_),
             Expression::Boolean(_, _) => {}
-            Expression::Condition(_, if_, then_, else_) => {
-                if_.for_all_type_schemes(f);
-                then_.for_all_type_schemes(f);
-                else_.for_all_type_schemes(f);
-            }
+            Expression::Condition(_, _, _, _) => {}
             Expression::String(_, _) => {}
-            Expression::InstantiateStruct(_, initializers, info) => {
-                for (_, expr) in initializers {
-                    expr.for_all_type_schemes(f);
-                }
-                info.for_all_type_schemes(f);
-            }
-            Expression::AccessField(_, _, expr, _, struct_type, field_type) => {
-                expr.for_all_type_schemes(f);
+            Expression::InstantiateStruct(_, _, _, info) => info.for_all_type_schemes(f),
+            Expression::AccessField(_, _, _, _, struct_type, field_type) => {
                 f(struct_type);
                 f(field_type);
             }
-            Expression::List(_, elements, type_) => {
-                for element in elements {
-                    element.for_all_type_schemes(f);
+            Expression::List(_, _, type_) => f(type_),
+            Expression::TypedHole(_, type_) => f(type_),
+            Expression::NamedArgument(_, _, _) => {}
+            Expression::DefaultArgument(_, type_) => f(type_),
+            Expression::Trace(_, _, _) => {}
+            Expression::Partial(_, _, _, type_) => f(type_),
+            Expression::AutoDiff(_, _, definitions, type_) => {
+                for definition in definitions {
+                    definition.for_all_type_schemes(f);
                 }
                 f(type_);
             }
-            Expression::TypedHole(_, type_) => {
-                f(type_);
-            }
+            Expression::For(..) | Expression::While(..) | Expression::TryCatch(..) => {}
+            Expression::DynamicCheck(_, _, type_, _) => f(type_),
         }
     }
 }
@@ -78,119 +85,250 @@ impl ForAllTypeSchemes for Expression {
 impl ForAllTypeSchemes for Statement {
     fn for_all_type_schemes(&mut self, f: &mut dyn FnMut(&mut TypeScheme)) {
         match self {
-            Statement::Expression(expr) => expr.for_all_type_schemes(f),
-            Statement::DefineVariable(DefineVariable(_, _, expr, _annotation, type_, _)) => {
-                expr.for_all_type_schemes(f);
-                f(type_);
-            }
-            Statement::DefineFunction(_, _, _, _, body, local_variables, fn_type, _, _) => {
+            Statement::Expression(_) => {}
+            Statement::DefineVariable(define_variable) => define_variable.for_all_type_schemes(f),
+            Statement::DefineFunction(
+                _,
+                _,
+                _,
+                _,
+                _,
+                local_variables,
+                local_units,
+                fn_type,
+                _,
+                _,
+            ) => {
                 for local_variable in local_variables {
-                    local_variable.2.for_all_type_schemes(f);
-                    f(&mut local_variable.4);
+                    local_variable.for_all_type_schemes(f);
                 }
-                if let Some(body) = body {
-                    body.for_all_type_schemes(f);
+                for local_unit in local_units {
+                    local_unit.for_all_type_schemes(f);
                 }
                 f(fn_type);
             }
-            Statement::DefineDimension(_, _) => {}
-            Statement::DefineBaseUnit(_, _, _annotation, type_) => {
+            Statement::DefineDimension(_, _) | Statement::ImportScope(_) => {}
+            Statement::DefineBaseUnit(_, _, _, _annotation, type_, _) => {
                 f(type_);
             }
-            Statement::DefineDerivedUnit(_, expr, _, _annotation, type_, _) => {
-                expr.for_all_type_schemes(f);
+            Statement::DefineDerivedUnit(_, _, _, _, _annotation, type_, _) => {
                 f(type_);
             }
-            Statement::ProcedureCall(_, args) => {
-                for arg in args {
-                    arg.for_all_type_schemes(f);
+            Statement::ProcedureCall(_, _) => {}
+            Statement::Conditional(_, then_statement, else_statement) => {
+                then_statement.for_all_type_schemes(f);
+                if let Some(else_statement) = else_statement {
+                    else_statement.for_all_type_schemes(f);
                 }
             }
             Statement::DefineStruct(info) => info.for_all_type_schemes(f),
+            Statement::DefineUnitSystem(_, _) => {}
+            Statement::DefineUnitFunction(function, inverse) => {
+                function.for_all_type_schemes(f);
+                if let Some(inverse) = inverse {
+                    inverse.for_all_type_schemes(f);
+                }
+            }
+            Statement::DefineMethods(_, functions) | Statement::DefineFormula(_, functions) => {
+                for function in functions {
+                    function.for_all_type_schemes(f);
+                }
+            }
+            Statement::DefineGenericFunction(function) => function.for_all_type_schemes(f),
         }
     }
 }
 
 pub trait ForAllExpressions {
-    fn for_all_expressions(&self, f: &mut dyn FnMut(&Expression));
+    fn for_all_expressions(&self, arena: &ExpressionArena, f: &mut dyn FnMut(&Expression));
 }
 
 impl ForAllExpressions for Statement {
-    fn for_all_expressions(&self, f: &mut dyn FnMut(&Expression)) {
+    fn for_all_expressions(&self, arena: &ExpressionArena, f: &mut dyn FnMut(&Expression)) {
         match self {
-            Statement::Expression(expr) => expr.for_all_expressions(f),
+            Statement::Expression(expr) => expr.for_all_expressions(arena, f),
             Statement::DefineVariable(DefineVariable(_, _, expr, _, _, _)) => {
-                expr.for_all_expressions(f)
+                expr.for_all_expressions(arena, f)
             }
-            Statement::DefineFunction(_, _, _, _, body, local_variables, _, _, _) => {
+            Statement::DefineFunction(
+                _,
+                _,
+                _,
+                parameters,
+                body,
+                local_variables,
+                local_units,
+                _,
+                _,
+                _,
+            ) => {
+                for local_unit in local_units {
+                    local_unit.for_all_expressions(arena, f);
+                }
+                for default_value in parameters.iter().filter_map(|p| p.4.as_ref()) {
+                    default_value.for_all_expressions(arena, f);
+                }
                 for local_variable in local_variables {
-                    local_variable.2.for_all_expressions(f);
+                    local_variable.2.for_all_expressions(arena, f);
                 }
                 if let Some(body) = body {
-                    body.for_all_expressions(f);
+                    body.for_all_expressions(arena, f);
                 }
             }
-            Statement::DefineDimension(_, _) => {}
-            Statement::DefineBaseUnit(_, _, _, _) => {}
-            Statement::DefineDerivedUnit(_, expr, _, _, _, _) => expr.for_all_expressions(f),
+            Statement::DefineDimension(_, _) | Statement::ImportScope(_) => {}
+            Statement::DefineBaseUnit(_, _, _, _, _, _) => {}
+            Statement::DefineDerivedUnit(_, _, expr, _, _, _, _) => {
+                expr.for_all_expressions(arena, f)
+            }
             Statement::ProcedureCall(_, args) => {
                 for arg in args {
-                    arg.for_all_expressions(f);
+                    arg.for_all_expressions(arena, f);
+                }
+            }
+            Statement::Conditional(condition, then_statement, else_statement) => {
+                condition.for_all_expressions(arena, f);
+                then_statement.for_all_expressions(arena, f);
+                if let Some(else_statement) = else_statement {
+                    else_statement.for_all_expressions(arena, f);
                 }
             }
             Statement::DefineStruct(_) => {}
+            Statement::DefineUnitSystem(_, units) => {
+                for unit in units {
+                    unit.for_all_expressions(arena, f);
+                }
+            }
+            Statement::DefineUnitFunction(function, inverse) => {
+                function.for_all_expressions(arena, f);
+                if let Some(inverse) = inverse {
+                    inverse.for_all_expressions(arena, f);
+                }
+            }
+            Statement::DefineMethods(_, functions) | Statement::DefineFormula(_, functions) => {
+                for function in functions {
+                    function.for_all_expressions(arena, f);
+                }
+            }
+            Statement::DefineGenericFunction(function) => function.for_all_expressions(arena, f),
         }
     }
 }
 
+impl ForAllExpressions for ExpressionId {
+    fn for_all_expressions(&self, arena: &ExpressionArena, f: &mut dyn FnMut(&Expression)) {
+        arena[*self].for_all_expressions(arena, f)
+    }
+}
+
 impl ForAllExpressions for Expression {
-    fn for_all_expressions(&self, f: &mut dyn FnMut(&Expression)) {
-        f(self);
+    fn for_all_expressions(&self, arena: &ExpressionArena, f: &mut dyn FnMut(&Expression)) {
+        // Chains of binary operators like `1 + 2 + … + n` are walked down along their
+        // left-hand sides without recursion, so that long chains do not overflow the stack
+        let mut right_hand_sides = vec![];
+        let mut expr = self;
+        f(expr);
+        while let Expression::BinaryOperator(_, _, lhs, rhs, _)
+        | Expression::BinaryOperatorForDate(_, _, lhs, rhs, _) = expr
+        {
+            right_hand_sides.push(*rhs);
+            expr = &arena[*lhs];
+            f(expr);
+        }
+
+        expr.for_all_child_expressions(arena, f);
+        for rhs in right_hand_sides.into_iter().rev() {
+            rhs.for_all_expressions(arena, f);
+        }
+    }
+}
+
+impl Expression {
+    /// Visits all sub-expressions of a node that is not a binary operator
+    fn for_all_child_expressions(&self, arena: &ExpressionArena, f: &mut dyn FnMut(&Expression)) {
         match self {
             Expression::Scalar(_, _, _) => {}
             Expression::Identifier(_, _, _) => {}
             Expression::UnitIdentifier(_, _, _, _, _) => {}
-            Expression::UnaryOperator(_, _, expr, _) => expr.for_all_expressions(f),
-            Expression::BinaryOperator(_, _, lhs, rhs, _) => {
-                lhs.for_all_expressions(f);
-                rhs.for_all_expressions(f);
+            Expression::UnaryOperator(_, _, expr, _) => expr.for_all_expressions(arena, f),
+            Expression::BinaryOperator(..) | Expression::BinaryOperatorForDate(..) => {
+                unreachable!("Binary operators are handled in for_all_expressions")
+            }
+            Expression::Conversion(_, source, target, _) => {
+                source.for_all_expressions(arena, f);
+                for expr in target.expressions() {
+                    expr.for_all_expressions(arena, f);
+                }
             }
-            Expression::BinaryOperatorForDate(_, _, lhs, rhs, _) => {
-                lhs.for_all_expressions(f);
-                rhs.for_all_expressions(f);
+            Expression::ChainedComparison(comparisons) => {
+                for comparison in comparisons {
+                    comparison.for_all_expressions(arena, f);
+                }
             }
-            Expression::FunctionCall(_, _, _, args, _) => {
+            Expression::FunctionCall(_, _, _, args, _, _) => {
                 for arg in args {
-                    arg.for_all_expressions(f);
+                    arg.for_all_expressions(arena, f);
                 }
             }
-            Expression::CallableCall(_, callable, args, _) => {
-                callable.for_all_expressions(f);
+            Expression::CallableCall(_, callable, args, _)
+            | Expression::Partial(_, callable, args, _) => {
+                callable.for_all_expressions(arena, f);
                 for arg in args {
-                    arg.for_all_expressions(f);
+                    arg.for_all_expressions(arena, f);
                 }
             }
             Expression::Boolean(_, _) => {}
             Expression::Condition(_, if_, then_, else_) => {
-                if_.for_all_expressions(f);
-                then_.for_all_expressions(f);
-                else_.for_all_expressions(f);
+                if_.for_all_expressions(arena, f);
+                then_.for_all_expressions(arena, f);
+                else_.for_all_expressions(arena, f);
             }
-            Expression::String(_, _) => {}
-            Expression::InstantiateStruct(_, initializers, _) => {
+            Expression::String(_, parts) => {
+                for part in parts {
+                    if let StringPart::Interpolation { expr, .. } = part {
+                        expr.for_all_expressions(arena, f);
+                    }
+                }
+            }
+            Expression::InstantiateStruct(_, base, initializers, _) => {
+                if let Some(base) = base {
+                    base.for_all_expressions(arena, f);
+                }
                 for (_, expr) in initializers {
-                    expr.for_all_expressions(f);
+                    expr.for_all_expressions(arena, f);
                 }
             }
             Expression::AccessField(_, _, expr, _, _, _) => {
-                expr.for_all_expressions(f);
+                expr.for_all_expressions(arena, f);
             }
             Expression::List(_, elements, _) => {
                 for element in elements {
-                    element.for_all_expressions(f);
+                    element.for_all_expressions(arena, f);
                 }
             }
             Expression::TypedHole(_, _) => {}
+            Expression::NamedArgument(_, _, expr) => expr.for_all_expressions(arena, f),
+            Expression::DefaultArgument(_, _) => {}
+            Expression::Trace(_, _, expr) => expr.for_all_expressions(arena, f),
+            // The definitions of the derivatives are not visited, they only use
+            // what the differentiated function uses
+            Expression::AutoDiff(_, function, _, _) => function.for_all_expressions(arena, f),
+            Expression::For(_, _, list, accumulator, body) => {
+                list.for_all_expressions(arena, f);
+                if let Some((_, init)) = accumulator {
+                    init.for_all_expressions(arena, f);
+                }
+                body.for_all_expressions(arena, f);
+            }
+            Expression::While(_, condition, (_, init), body) => {
+                init.for_all_expressions(arena, f);
+                condition.for_all_expressions(arena, f);
+                body.for_all_expressions(arena, f);
+            }
+            Expression::TryCatch(_, expr, _, fallback) => {
+                expr.for_all_expressions(arena, f);
+                fallback.for_all_expressions(arena, f);
+            }
+            Expression::DynamicCheck(_, expr, ..) => expr.for_all_expressions(arena, f),
         }
     }
 }
@@ -1,12 +1,22 @@
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+use crate::symbol::Symbol;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum TypeVariable {
-    Named(String),
+    Named(Symbol),
+    /// A type variable that has been generated during type inference. Unlike
+    /// names, these are not interned, as a new one is needed for almost every
+    /// expression.
+    Fresh(u64),
     Quantified(usize),
 }
 
 impl TypeVariable {
     pub fn new<S: AsRef<str>>(name: S) -> TypeVariable {
-        TypeVariable::Named(name.as_ref().into())
+        TypeVariable::Named(Symbol::new(name.as_ref()))
+    }
+
+    pub fn new_fresh(i: u64) -> TypeVariable {
+        TypeVariable::Fresh(i)
     }
 
     pub fn new_gen(i: usize) -> TypeVariable {
@@ -21,3 +31,15 @@ impl TypeVariable {
         }
     }
 }
+
+impl std::fmt::Display for TypeVariable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TypeVariable::Named(name) => write!(f, "{name}"),
+            TypeVariable::Fresh(i) => write!(f, "T{i}"),
+            TypeVariable::Quantified(_) => {
+                unreachable!("Quantified types should not be printed")
+            }
+        }
+    }
+}
@@ -0,0 +1,753 @@
+//! Symbolic differentiation for `autodiff(f)`. The derivative of the body of `f`
+//! is computed on the AST, before it is type checked like any other function.
+//! Calls of other Numbat functions are differentiated with the chain rule by
+//! inlining their bodies. Foreign functions are only supported if their
+//! derivative is known, see [`foreign_derivative`].
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::ast::{
+    BinaryOperator, CallSyntax, ConversionTarget, DefineVariable, Expression, Pattern, StringPart,
+    TypeAnnotation, TypeParameterBound, UnaryOperator,
+};
+use crate::number::Number;
+use crate::span::Span;
+use crate::symbol::Symbol;
+
+use super::error::{Result, TypeCheckError};
+
+/// The parts of a function definition that are needed to differentiate it
+#[derive(Debug)]
+pub(crate) struct FunctionDefinition {
+    pub(crate) type_parameters: Vec<(Span, Symbol, Option<TypeParameterBound>)>,
+    pub(crate) parameters: Vec<(Span, Symbol, Option<TypeAnnotation>, Option<Expression>)>,
+    pub(crate) local_variables: Vec<DefineVariable>,
+    /// The span of the first unit or dimension from the `where` clause, if any
+    pub(crate) local_unit_span: Option<Span>,
+    pub(crate) body: Expression,
+}
+
+impl FunctionDefinition {
+    /// A definition `fn name(x) = name(x)` for a foreign function with a known
+    /// derivative, so that it can be differentiated like any other function
+    pub(crate) fn for_foreign_function(
+        name: Symbol,
+        parameter: (Span, Symbol, Option<TypeAnnotation>),
+        type_parameters: Vec<(Span, Symbol, Option<TypeParameterBound>)>,
+    ) -> Option<Self> {
+        if !DIFFERENTIABLE_FOREIGN_FUNCTIONS.contains(&name.as_str()) {
+            return None;
+        }
+
+        let (span, parameter, annotation) = parameter;
+        Some(FunctionDefinition {
+            type_parameters,
+            parameters: vec![(span, parameter, annotation, None)],
+            local_variables: vec![],
+            local_unit_span: None,
+            body: call(
+                span,
+                name.as_str(),
+                vec![Expression::Identifier(span, parameter)],
+            ),
+        })
+    }
+}
+
+/// Names of the foreign functions with a known derivative
+const DIFFERENTIABLE_FOREIGN_FUNCTIONS: &[&str] = &[
+    "sin", "cos", "tan", "asin", "acos", "atan", "sinh", "cosh", "tanh", "asinh", "acosh", "atanh",
+    "exp", "ln", "log10", "log2", "abs",
+];
+
+pub(crate) struct Differentiator<'a> {
+    definitions: &'a HashMap<Symbol, Arc<FunctionDefinition>>,
+    /// The function that is passed to `autodiff`, for error messages
+    function: Symbol,
+    /// The parameter of that function
+    variable: Symbol,
+    /// Functions whose bodies are currently being inlined, to detect recursion
+    inlined: Vec<Symbol>,
+}
+
+impl<'a> Differentiator<'a> {
+    pub(crate) fn new(
+        definitions: &'a HashMap<Symbol, Arc<FunctionDefinition>>,
+        function: Symbol,
+        variable: Symbol,
+    ) -> Self {
+        Differentiator {
+            definitions,
+            function,
+            variable,
+            inlined: vec![function],
+        }
+    }
+
+    /// The derivative of the body of `definition` with respect to its parameter
+    pub(crate) fn differentiate(&mut self, definition: &FunctionDefinition) -> Result<Expression> {
+        let body = self.inlined_body(definition)?;
+        Ok(self
+            .derivative(&body, self.variable)?
+            .unwrap_or_else(|| scalar(body.full_span(), 0.0)))
+    }
+
+    fn not_differentiable(&self, span: Span, construct: impl Into<String>) -> TypeCheckError {
+        TypeCheckError::NotDifferentiable {
+            span,
+            construct: construct.into(),
+            function: self.function.to_string(),
+        }
+    }
+
+    /// The body of a function, with all local variables from the `where` clause
+    /// substituted by their definitions
+    fn inlined_body(&self, definition: &FunctionDefinition) -> Result<Expression> {
+        if let Some(span) = definition.local_unit_span {
+            return Err(self.not_differentiable(span, "a local unit definition"));
+        }
+
+        let mut body = definition.body.clone();
+        for variable in definition.local_variables.iter().rev() {
+            let Pattern::Identifier(_, name) = &variable.pattern else {
+                return Err(self.not_differentiable(
+                    variable.pattern.full_span(),
+                    "a destructuring local variable",
+                ));
+            };
+            body = self.substitute(
+                &body,
+                &HashMap::from([(*name, variable.expr.clone())]),
+                None,
+            )?;
+        }
+        Ok(body)
+    }
+
+    /// The derivative of `expr` with respect to `variable`, or `None` if it is zero
+    fn derivative(&mut self, expr: &Expression, variable: Symbol) -> Result<Option<Expression>> {
+        if !mentions(expr, variable) {
+            return Ok(None);
+        }
+
+        let span = expr.full_span();
+        Ok(match expr {
+            Expression::Identifier(span, _) => Some(scalar(*span, 1.0)),
+            Expression::UnaryOperator {
+                op: UnaryOperator::Negate,
+                expr,
+                ..
+            } => self.derivative(expr, variable)?.map(negate),
+            Expression::UnaryOperator {
+                op: UnaryOperator::Factorial,
+                ..
+            } => return Err(self.not_differentiable(span, "the factorial")),
+            Expression::UnaryOperator {
+                op: UnaryOperator::LogicalNeg,
+                ..
+            } => return Err(self.not_differentiable(span, "a logical operator")),
+            Expression::BinaryOperator { op, lhs, rhs, .. } => {
+                let dlhs = self.derivative(lhs, variable)?;
+                let drhs = self.derivative(rhs, variable)?;
+                match op {
+                    BinaryOperator::Add => add(dlhs, drhs),
+                    BinaryOperator::Sub => match (dlhs, drhs) {
+                        (dlhs, None) => dlhs,
+                        (None, Some(drhs)) => Some(negate(drhs)),
+                        (Some(dlhs), Some(drhs)) => Some(binop(BinaryOperator::Sub, dlhs, drhs)),
+                    },
+                    BinaryOperator::Mul => add(
+                        dlhs.map(|dlhs| mul(dlhs, rhs.as_ref().clone())),
+                        drhs.map(|drhs| mul(lhs.as_ref().clone(), drhs)),
+                    ),
+                    BinaryOperator::Div => {
+                        // (a/b)' = a'/b - a b'/b²
+                        let first = dlhs.map(|dlhs| div(dlhs, rhs.as_ref().clone()));
+                        let second = drhs.map(|drhs| {
+                            div(
+                                mul(lhs.as_ref().clone(), drhs),
+                                power(rhs.as_ref().clone(), 2.0),
+                            )
+                        });
+                        match (first, second) {
+                            (first, None) => first,
+                            (None, Some(second)) => Some(negate(second)),
+                            (Some(first), Some(second)) => {
+                                Some(binop(BinaryOperator::Sub, first, second))
+                            }
+                        }
+                    }
+                    BinaryOperator::Power => {
+                        let base = lhs.as_ref().clone();
+                        let exponent = rhs.as_ref().clone();
+                        match (dlhs, drhs) {
+                            (None, None) => None,
+                            // (u^c)' = c u^(c-1) u'
+                            (Some(dbase), None) => {
+                                let reduced = match literal(&exponent) {
+                                    Some(n) => power(base, n - 1.0),
+                                    None => binop(
+                                        BinaryOperator::Power,
+                                        base,
+                                        binop(
+                                            BinaryOperator::Sub,
+                                            exponent.clone(),
+                                            scalar(span, 1.0),
+                                        ),
+                                    ),
+                                };
+                                Some(mul(mul(exponent, reduced), dbase))
+                            }
+                            // (c^v)' = c^v ln(c) v'
+                            (None, Some(dexponent)) => Some(mul(
+                                mul(expr.clone(), call(span, "ln", vec![base])),
+                                dexponent,
+                            )),
+                            // (u^v)' = u^v (v' ln(u) + v u'/u)
+                            (Some(dbase), Some(dexponent)) => Some(mul(
+                                expr.clone(),
+                                binop(
+                                    BinaryOperator::Add,
+                                    mul(dexponent, call(span, "ln", vec![base.clone()])),
+                                    div(mul(exponent, dbase), base),
+                                ),
+                            )),
+                        }
+                    }
+                    op if op.is_comparison() => {
+                        return Err(self.not_differentiable(span, "a comparison"))
+                    }
+                    _ => return Err(self.not_differentiable(span, "a logical operator")),
+                }
+            }
+            // The derivative has a different dimension, so it is not converted
+            Expression::Conversion {
+                source,
+                target: ConversionTarget::Expression(target),
+                ..
+            } if is_unit_expression(target) => self.derivative(source, variable)?,
+            Expression::Conversion {
+                target: ConversionTarget::UnitList(..),
+                ..
+            } => return Err(self.not_differentiable(span, "a conversion to a list of units")),
+            Expression::Conversion { .. } => {
+                return Err(self.not_differentiable(span, "a conversion to a function"))
+            }
+            Expression::ChainedComparison(..) => {
+                return Err(self.not_differentiable(span, "a comparison"))
+            }
+            Expression::FunctionCall(_, full_span, callable, args, named_args, _) => {
+                let (name, args) = match callable.as_ref() {
+                    Expression::Identifier(_, name) => (*name, args.clone()),
+                    // Method call syntax, `receiver.name(args)`
+                    Expression::AccessField(_, _, receiver, name) => (
+                        *name,
+                        std::iter::once(receiver.as_ref().clone())
+                            .chain(args.iter().cloned())
+                            .collect(),
+                    ),
+                    _ => {
+                        return Err(
+                            self.not_differentiable(*full_span, "a call of a function value")
+                        )
+                    }
+                };
+                self.call_derivative(*full_span, name, args, named_args, variable)?
+            }
+            Expression::Condition(span, condition, then, else_) => {
+                if mentions(condition, variable) {
+                    return Err(self.not_differentiable(
+                        condition.full_span(),
+                        format!("a condition that depends on '{variable}'"),
+                    ));
+                }
+                let dthen = self.derivative(then, variable)?;
+                let delse = self.derivative(else_, variable)?;
+                Some(Expression::Condition(
+                    *span,
+                    condition.clone(),
+                    Box::new(dthen.unwrap_or_else(|| scalar(then.full_span(), 0.0))),
+                    Box::new(delse.unwrap_or_else(|| scalar(else_.full_span(), 0.0))),
+                ))
+            }
+            Expression::String(..) => return Err(self.not_differentiable(span, "a string")),
+            Expression::InstantiateStruct { .. } => {
+                return Err(self.not_differentiable(span, "a struct"))
+            }
+            Expression::AccessField(..) => {
+                return Err(self.not_differentiable(span, "a field access"))
+            }
+            Expression::List(..) => return Err(self.not_differentiable(span, "a list")),
+            Expression::Trace(..) => return Err(self.not_differentiable(span, "'trace'")),
+            Expression::Partial(..) => {
+                return Err(self.not_differentiable(span, "a partial application"))
+            }
+            Expression::AutoDiff(..) => return Err(self.not_differentiable(span, "'autodiff'")),
+            Expression::DimensionPredicate(..) => {
+                return Err(self.not_differentiable(span, "a dimension predicate"))
+            }
+            Expression::For { .. } | Expression::While { .. } => {
+                return Err(self.not_differentiable(span, "a loop"))
+            }
+            Expression::TryCatch { .. } => {
+                return Err(self.not_differentiable(span, "'try' … 'catch'"))
+            }
+            Expression::Scalar(..)
+            | Expression::UnitIdentifier(..)
+            | Expression::TypedHole(..)
+            | Expression::Boolean(..) => unreachable!("Does not depend on a variable"),
+        })
+    }
+
+    /// The derivative of the function call `name(args)`, using the chain rule
+    fn call_derivative(
+        &mut self,
+        span: Span,
+        name: Symbol,
+        args: Vec<Expression>,
+        named_args: &[(Span, Symbol, Expression)],
+        variable: Symbol,
+    ) -> Result<Option<Expression>> {
+        let Some(definition) = self.definitions.get(&name).cloned() else {
+            if !DIFFERENTIABLE_FOREIGN_FUNCTIONS.contains(&name.as_str())
+                || args.len() != 1
+                || !named_args.is_empty()
+            {
+                return Err(self.not_differentiable(span, format!("the function '{name}'")));
+            }
+            let argument = args.into_iter().next().unwrap();
+            let dargument = self.derivative(&argument, variable)?;
+            return Ok(dargument.map(|dargument| {
+                mul(foreign_derivative(span, name.as_str(), argument), dargument)
+            }));
+        };
+
+        if self.inlined.contains(&name) {
+            return Err(self.not_differentiable(span, format!("a recursive call of '{name}'")));
+        }
+        if let Some((_, _, Some(TypeAnnotation::Variadic(..)), _)) = definition.parameters.last() {
+            return Err(self.not_differentiable(span, format!("the variadic function '{name}'")));
+        }
+
+        // Match the arguments with the parameters
+        let mut arguments: Vec<Option<Expression>> = args.into_iter().map(Some).collect();
+        arguments.resize(definition.parameters.len().max(arguments.len()), None);
+        for (name_span, argument_name, argument) in named_args {
+            let Some(index) = definition
+                .parameters
+                .iter()
+                .position(|(_, parameter, _, _)| parameter == argument_name)
+            else {
+                return Err(self.not_differentiable(*name_span, "an unknown named argument"));
+            };
+            arguments[index] = Some(argument.clone());
+        }
+        if arguments.len() > definition.parameters.len() {
+            return Err(self.not_differentiable(span, "a call with too many arguments"));
+        }
+
+        // Default values may refer to previous parameters, they are substituted
+        // together with the function body
+        let mut substitution = HashMap::new();
+        for ((_, parameter, _, default), argument) in definition.parameters.iter().zip(arguments) {
+            let argument = match (argument, default) {
+                (Some(argument), _) => argument,
+                (None, Some(default)) => self.substitute(default, &substitution, Some(span))?,
+                (None, None) => {
+                    return Err(self.not_differentiable(span, "a call with missing arguments"))
+                }
+            };
+            substitution.insert(*parameter, argument);
+        }
+
+        self.inlined.push(name);
+        let body = self.inlined_body(&definition)?;
+
+        // A global variable in the inlined body would be captured by the parameter
+        if mentions(&body, self.variable)
+            && definition
+                .parameters
+                .iter()
+                .all(|(_, parameter, _, _)| *parameter != self.variable)
+        {
+            return Err(self.not_differentiable(
+                span,
+                format!(
+                    "a call of '{name}' that refers to a different '{}'",
+                    self.variable
+                ),
+            ));
+        }
+
+        // d/dx g(u₁, …, uₙ) = Σ ∂g/∂pᵢ(u₁, …, uₙ) · uᵢ'
+        let mut result = None;
+        for (_, parameter, _, _) in &definition.parameters {
+            let Some(dargument) = self.derivative(&substitution[parameter], variable)? else {
+                continue;
+            };
+            let Some(partial) = self.derivative(&body, *parameter)? else {
+                continue;
+            };
+            let partial = self.substitute(&partial, &substitution, Some(span))?;
+            result = add(result, Some(mul(partial, dargument)));
+        }
+        self.inlined.pop();
+
+        Ok(result)
+    }
+
+    /// Replaces all free occurrences of the given names. With `at`, all spans
+    /// (except for those of the replacements) are replaced by the given span.
+    /// This is needed for the bodies of inlined functions, which might come from
+    /// another file.
+    fn substitute(
+        &self,
+        expr: &Expression,
+        substitution: &HashMap<Symbol, Expression>,
+        at: Option<Span>,
+    ) -> Result<Expression> {
+        if substitution.is_empty() && at.is_none() {
+            return Ok(expr.clone());
+        }
+
+        let sp = |span: &Span| at.unwrap_or(*span);
+        let sub = |e: &Expression| self.substitute(e, substitution, at).map(Box::new);
+        let sub_all = |es: &[Expression]| {
+            es.iter()
+                .map(|e| self.substitute(e, substitution, at))
+                .collect::<Result<Vec<_>>>()
+        };
+        let sub_named = |args: &[(Span, Symbol, Expression)]| {
+            args.iter()
+                .map(|(span, name, e)| Ok((sp(span), *name, self.substitute(e, substitution, at)?)))
+                .collect::<Result<Vec<_>>>()
+        };
+        // Names that are bound inside of `expr` shadow the substituted names
+        let scope = |span: Span, bound: &[Symbol]| -> Result<HashMap<Symbol, Expression>> {
+            if substitution
+                .values()
+                .any(|replacement| bound.iter().any(|name| mentions(replacement, *name)))
+            {
+                return Err(self.not_differentiable(span, "a name that is bound twice"));
+            }
+            Ok(substitution
+                .iter()
+                .filter(|(name, _)| !bound.contains(name))
+                .map(|(name, replacement)| (*name, replacement.clone()))
+                .collect())
+        };
+
+        Ok(match expr {
+            Expression::Identifier(span, name) => match substitution.get(name) {
+                Some(replacement) => replacement.clone(),
+                None => Expression::Identifier(sp(span), *name),
+            },
+            Expression::Scalar(span, n) => Expression::Scalar(sp(span), *n),
+            Expression::UnitIdentifier(span, prefix, name, full_name) => {
+                Expression::UnitIdentifier(sp(span), *prefix, *name, *full_name)
+            }
+            Expression::TypedHole(span) => Expression::TypedHole(sp(span)),
+            Expression::Boolean(span, value) => Expression::Boolean(sp(span), *value),
+            Expression::UnaryOperator { op, expr, span_op } => Expression::UnaryOperator {
+                op: *op,
+                expr: sub(expr)?,
+                span_op: sp(span_op),
+            },
+            Expression::BinaryOperator {
+                op,
+                lhs,
+                rhs,
+                span_op,
+            } => Expression::BinaryOperator {
+                op: *op,
+                lhs: sub(lhs)?,
+                rhs: sub(rhs)?,
+                span_op: span_op.as_ref().map(sp),
+            },
+            Expression::Conversion {
+                source,
+                target,
+                span_op,
+                parenthesized_source,
+            } => Expression::Conversion {
+                source: sub(source)?,
+                target: match target {
+                    ConversionTarget::Expression(target) => {
+                        ConversionTarget::Expression(sub(target)?)
+                    }
+                    ConversionTarget::UnitList(span, units) => {
+                        ConversionTarget::UnitList(sp(span), sub_all(units)?)
+                    }
+                },
+                span_op: sp(span_op),
+                parenthesized_source: *parenthesized_source,
+            },
+            Expression::ChainedComparison(first, rest) => Expression::ChainedComparison(
+                sub(first)?,
+                rest.iter()
+                    .map(|(span, op, e)| Ok((sp(span), *op, self.substitute(e, substitution, at)?)))
+                    .collect::<Result<_>>()?,
+            ),
+            Expression::FunctionCall(span, full_span, callable, args, named_args, syntax) => {
+                Expression::FunctionCall(
+                    sp(span),
+                    sp(full_span),
+                    sub(callable)?,
+                    sub_all(args)?,
+                    sub_named(named_args)?,
+                    *syntax,
+                )
+            }
+            Expression::String(span, parts) => Expression::String(
+                sp(span),
+                parts
+                    .iter()
+                    .map(|part| {
+                        Ok(match part {
+                            StringPart::Fixed(s) => StringPart::Fixed(s.clone()),
+                            StringPart::Interpolation {
+                                span,
+                                expr,
+                                format_specifiers,
+                            } => StringPart::Interpolation {
+                                span: sp(span),
+                                expr: sub(expr)?,
+                                format_specifiers: format_specifiers.clone(),
+                            },
+                        })
+                    })
+                    .collect::<Result<_>>()?,
+            ),
+            Expression::Condition(span, condition, then, else_) => {
+                Expression::Condition(sp(span), sub(condition)?, sub(then)?, sub(else_)?)
+            }
+            Expression::InstantiateStruct {
+                full_span,
+                ident_span,
+                name,
+                base,
+                fields,
+            } => Expression::InstantiateStruct {
+                full_span: sp(full_span),
+                ident_span: sp(ident_span),
+                name: *name,
+                base: base.as_deref().map(sub).transpose()?,
+                fields: sub_named(fields)?,
+            },
+            Expression::AccessField(full_span, ident_span, expr, field) => {
+                Expression::AccessField(sp(full_span), sp(ident_span), sub(expr)?, *field)
+            }
+            Expression::List(span, elements) => Expression::List(sp(span), sub_all(elements)?),
+            Expression::Trace(span, source, expr) => {
+                Expression::Trace(sp(span), source.clone(), sub(expr)?)
+            }
+            Expression::Partial(span, function, args, named_args) => Expression::Partial(
+                sp(span),
+                sub(function)?,
+                sub_all(args)?,
+                sub_named(named_args)?,
+            ),
+            Expression::AutoDiff(span, function) => Expression::AutoDiff(sp(span), sub(function)?),
+            Expression::DimensionPredicate(span, expr, dimension) => {
+                Expression::DimensionPredicate(sp(span), sub(expr)?, dimension.clone())
+            }
+            Expression::For {
+                full_span,
+                variable: (variable_span, variable),
+                list,
+                accumulator,
+                body,
+            } => {
+                let mut bound = vec![*variable];
+                bound.extend(accumulator.iter().map(|(_, name, _)| *name));
+                let inner = scope(*full_span, &bound)?;
+                Expression::For {
+                    full_span: sp(full_span),
+                    variable: (sp(variable_span), *variable),
+                    list: sub(list)?,
+                    accumulator: accumulator
+                        .as_ref()
+                        .map(|(span, name, init)| {
+                            Ok::<_, TypeCheckError>((sp(span), *name, sub(init)?))
+                        })
+                        .transpose()?,
+                    body: Box::new(self.substitute(body, &inner, at)?),
+                }
+            }
+            Expression::While {
+                full_span,
+                condition,
+                accumulator: (span, name, init),
+                body,
+            } => {
+                let inner = scope(*full_span, &[*name])?;
+                Expression::While {
+                    full_span: sp(full_span),
+                    condition: Box::new(self.substitute(condition, &inner, at)?),
+                    accumulator: (sp(span), *name, sub(init)?),
+                    body: Box::new(self.substitute(body, &inner, at)?),
+                }
+            }
+            Expression::TryCatch {
+                full_span,
+                expr,
+                message,
+                fallback,
+            } => {
+                let bound: Vec<_> = message.iter().map(|(_, name)| *name).collect();
+                let inner = scope(*full_span, &bound)?;
+                Expression::TryCatch {
+                    full_span: sp(full_span),
+                    expr: sub(expr)?,
+                    message: message.map(|(span, name)| (sp(&span), name)),
+                    fallback: Box::new(self.substitute(fallback, &inner, at)?),
+                }
+            }
+        })
+    }
+}
+
+/// The derivative of the foreign function `name` at `u`
+fn foreign_derivative(span: Span, name: &str, u: Expression) -> Expression {
+    let one = || scalar(span, 1.0);
+    let f = |name: &str, u: Expression| call(span, name, vec![u]);
+    let one_minus_square = |u: Expression| binop(BinaryOperator::Sub, one(), power(u, 2.0));
+
+    match name {
+        "sin" => f("cos", u),
+        "cos" => negate(f("sin", u)),
+        "tan" => div(one(), power(f("cos", u), 2.0)),
+        "asin" => div(one(), f("sqrt", one_minus_square(u))),
+        "acos" => negate(div(one(), f("sqrt", one_minus_square(u)))),
+        "atan" => div(one(), binop(BinaryOperator::Add, one(), power(u, 2.0))),
+        "sinh" => f("cosh", u),
+        "cosh" => f("sinh", u),
+        "tanh" => div(one(), power(f("cosh", u), 2.0)),
+        "asinh" => div(
+            one(),
+            f("sqrt", binop(BinaryOperator::Add, power(u, 2.0), one())),
+        ),
+        "acosh" => div(
+            one(),
+            f("sqrt", binop(BinaryOperator::Sub, power(u, 2.0), one())),
+        ),
+        "atanh" => div(one(), one_minus_square(u)),
+        "exp" => f("exp", u),
+        "ln" => div(one(), u),
+        "log10" => div(one(), mul(u, f("ln", scalar(span, 10.0)))),
+        "log2" => div(one(), mul(u, f("ln", scalar(span, 2.0)))),
+        "abs" => f("sign", u),
+        _ => unreachable!("Unknown derivative of '{name}'"),
+    }
+}
+
+/// Whether `expr` contains the identifier `name`. Names that are bound inside
+/// of `expr` are not taken into account, so this might report false positives.
+pub(super) fn mentions(expr: &Expression, name: Symbol) -> bool {
+    expr.contains(
+        &|expr| matches!(expr, Expression::Identifier(_, identifier) if *identifier == name),
+    )
+}
+
+/// Whether `expr` only consists of units, like the right hand side of `x -> km/h`
+fn is_unit_expression(expr: &Expression) -> bool {
+    match expr {
+        Expression::UnitIdentifier(..) | Expression::Scalar(..) => true,
+        Expression::UnaryOperator {
+            op: UnaryOperator::Negate,
+            expr,
+            ..
+        } => is_unit_expression(expr),
+        Expression::BinaryOperator {
+            op: BinaryOperator::Mul | BinaryOperator::Div | BinaryOperator::Power,
+            lhs,
+            rhs,
+            ..
+        } => is_unit_expression(lhs) && is_unit_expression(rhs),
+        _ => false,
+    }
+}
+
+/// The value of a number literal like `2` or `-1`
+pub(super) fn literal(expr: &Expression) -> Option<f64> {
+    match expr {
+        Expression::Scalar(_, n) => Some(n.to_f64()),
+        Expression::UnaryOperator {
+            op: UnaryOperator::Negate,
+            expr,
+            ..
+        } => literal(expr).map(|n| -n),
+        _ => None,
+    }
+}
+
+pub(super) fn scalar(span: Span, n: f64) -> Expression {
+    if n < 0.0 {
+        negate(scalar(span, -n))
+    } else {
+        Expression::Scalar(span, Number::from_f64(n))
+    }
+}
+
+pub(super) fn call(span: Span, name: &str, args: Vec<Expression>) -> Expression {
+    Expression::FunctionCall(
+        span,
+        span,
+        Box::new(Expression::Identifier(span, Symbol::new(name))),
+        args,
+        vec![],
+        CallSyntax::Regular,
+    )
+}
+
+pub(super) fn binop(op: BinaryOperator, lhs: Expression, rhs: Expression) -> Expression {
+    Expression::BinaryOperator {
+        op,
+        lhs: Box::new(lhs),
+        rhs: Box::new(rhs),
+        span_op: None,
+    }
+}
+
+pub(super) fn negate(expr: Expression) -> Expression {
+    Expression::UnaryOperator {
+        op: UnaryOperator::Negate,
+        span_op: expr.full_span(),
+        expr: Box::new(expr),
+    }
+}
+
+fn add(lhs: Option<Expression>, rhs: Option<Expression>) -> Option<Expression> {
+    match (lhs, rhs) {
+        (Some(lhs), Some(rhs)) => Some(binop(BinaryOperator::Add, lhs, rhs)),
+        (lhs, rhs) => lhs.or(rhs),
+    }
+}
+
+pub(super) fn mul(lhs: Expression, rhs: Expression) -> Expression {
+    if literal(&lhs) == Some(1.0) {
+        rhs
+    } else if literal(&rhs) == Some(1.0) {
+        lhs
+    } else {
+        binop(BinaryOperator::Mul, lhs, rhs)
+    }
+}
+
+pub(super) fn div(lhs: Expression, rhs: Expression) -> Expression {
+    if literal(&rhs) == Some(1.0) {
+        lhs
+    } else {
+        binop(BinaryOperator::Div, lhs, rhs)
+    }
+}
+
+fn power(base: Expression, exponent: f64) -> Expression {
+    if exponent == 1.0 {
+        base
+    } else {
+        let span = base.full_span();
+        binop(BinaryOperator::Power, base, scalar(span, exponent))
+    }
+}
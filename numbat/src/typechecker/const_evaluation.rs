@@ -12,43 +12,46 @@ fn to_rational_exponent(exponent_f64: f64) -> Option<Exponent> {
 /// Evaluates a limited set of expressions *at compile time*. This is needed to
 /// support type checking of expressions like `(2 * meter)^(2*3 - 4)` where we
 /// need to know not just the *type* but also the *value* of the exponent.
-pub fn evaluate_const_expr(expr: &typed_ast::Expression) -> Result<Exponent> {
+pub fn evaluate_const_expr(
+    arena: &typed_ast::ExpressionArena,
+    expr: &typed_ast::Expression,
+) -> Result<Exponent> {
     match expr {
         typed_ast::Expression::Scalar(span, n, _type) => {
             Ok(to_rational_exponent(n.to_f64())
                 .ok_or(TypeCheckError::NonRationalExponent(*span))?)
         }
         typed_ast::Expression::UnaryOperator(_, ast::UnaryOperator::Negate, ref expr, _) => {
-            Ok(-evaluate_const_expr(expr)?)
+            Ok(-evaluate_const_expr(arena, &arena[*expr])?)
         }
         e @ typed_ast::Expression::UnaryOperator(_, ast::UnaryOperator::Factorial, _, _) => Err(
-            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(), "factorial"),
+            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(arena), "factorial"),
         ),
         e @ typed_ast::Expression::UnaryOperator(_, ast::UnaryOperator::LogicalNeg, _, _) => Err(
-            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(), "logical"),
+            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(arena), "logical"),
         ),
         e @ typed_ast::Expression::BinaryOperator(_span_op, op, lhs_expr, rhs_expr, _) => {
-            let lhs = evaluate_const_expr(lhs_expr)?;
-            let rhs = evaluate_const_expr(rhs_expr)?;
+            let lhs = evaluate_const_expr(arena, &arena[*lhs_expr])?;
+            let rhs = evaluate_const_expr(arena, &arena[*rhs_expr])?;
             match op {
                 typed_ast::BinaryOperator::Add => Ok(lhs
                     .checked_add(&rhs)
-                    .ok_or_else(|| TypeCheckError::OverflowInConstExpr(expr.full_span()))?),
+                    .ok_or_else(|| TypeCheckError::OverflowInConstExpr(expr.full_span(arena)))?),
                 typed_ast::BinaryOperator::Sub => Ok(lhs
                     .checked_sub(&rhs)
-                    .ok_or_else(|| TypeCheckError::OverflowInConstExpr(expr.full_span()))?),
+                    .ok_or_else(|| TypeCheckError::OverflowInConstExpr(expr.full_span(arena)))?),
                 typed_ast::BinaryOperator::Mul => Ok(lhs
                     .checked_mul(&rhs)
-                    .ok_or_else(|| TypeCheckError::OverflowInConstExpr(expr.full_span()))?),
+                    .ok_or_else(|| TypeCheckError::OverflowInConstExpr(expr.full_span(arena)))?),
                 typed_ast::BinaryOperator::Div => {
                     if rhs == Rational::zero() {
                         Err(TypeCheckError::DivisionByZeroInConstEvalExpression(
-                            e.full_span(),
+                            e.full_span(arena),
                         ))
                     } else {
-                        Ok(lhs
-                            .checked_div(&rhs)
-                            .ok_or_else(|| TypeCheckError::OverflowInConstExpr(expr.full_span()))?)
+                        Ok(lhs.checked_div(&rhs).ok_or_else(|| {
+                            TypeCheckError::OverflowInConstExpr(expr.full_span(arena))
+                        })?)
                     }
                 }
                 typed_ast::BinaryOperator::Power => {
@@ -56,74 +59,115 @@ pub fn evaluate_const_expr(expr: &typed_ast::Expression) -> Result<Exponent> {
                         Ok(num_traits::checked_pow(
                             lhs,
                             rhs.to_integer().try_into().map_err(|_| {
-                                TypeCheckError::OverflowInConstExpr(expr.full_span())
+                                TypeCheckError::OverflowInConstExpr(expr.full_span(arena))
                             })?,
                         )
-                        .ok_or_else(|| TypeCheckError::OverflowInConstExpr(expr.full_span()))?)
+                        .ok_or_else(|| {
+                            TypeCheckError::OverflowInConstExpr(expr.full_span(arena))
+                        })?)
                     } else {
                         Err(TypeCheckError::UnsupportedConstEvalExpression(
-                            e.full_span(),
+                            e.full_span(arena),
                             "exponentiation with non-integer exponent",
                         ))
                     }
                 }
-                typed_ast::BinaryOperator::ConvertTo => Err(
-                    TypeCheckError::UnsupportedConstEvalExpression(e.full_span(), "conversion"),
-                ),
                 typed_ast::BinaryOperator::LessThan
                 | typed_ast::BinaryOperator::GreaterThan
                 | typed_ast::BinaryOperator::LessOrEqual
                 | typed_ast::BinaryOperator::GreaterOrEqual
                 | typed_ast::BinaryOperator::Equal
-                | typed_ast::BinaryOperator::NotEqual => Err(
-                    TypeCheckError::UnsupportedConstEvalExpression(e.full_span(), "comparison"),
-                ),
+                | typed_ast::BinaryOperator::NotEqual => {
+                    Err(TypeCheckError::UnsupportedConstEvalExpression(
+                        e.full_span(arena),
+                        "comparison",
+                    ))
+                }
                 typed_ast::BinaryOperator::LogicalAnd | typed_ast::BinaryOperator::LogicalOr => {
                     Err(TypeCheckError::UnsupportedConstEvalExpression(
-                        e.full_span(),
+                        e.full_span(arena),
                         "logical",
                     ))
                 }
             }
         }
         e @ typed_ast::Expression::Identifier(..) => Err(
-            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(), "variable"),
+            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(arena), "variable"),
         ),
         e @ typed_ast::Expression::UnitIdentifier(..) => Err(
-            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(), "unit identifier"),
+            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(arena), "unit identifier"),
         ),
-        e @ typed_ast::Expression::FunctionCall(_, _, _, _, _) => Err(
-            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(), "function call"),
+        e @ typed_ast::Expression::FunctionCall(_, _, _, _, _, _) => Err(
+            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(arena), "function call"),
         ),
         e @ &typed_ast::Expression::CallableCall(_, _, _, _) => Err(
-            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(), "function call"),
+            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(arena), "function call"),
         ),
         e @ typed_ast::Expression::Boolean(_, _) => Err(
-            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(), "Boolean value"),
+            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(arena), "Boolean value"),
         ),
         e @ typed_ast::Expression::String(_, _) => Err(
-            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(), "String"),
+            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(arena), "String"),
         ),
         e @ typed_ast::Expression::Condition(..) => Err(
-            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(), "Conditional"),
+            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(arena), "Conditional"),
+        ),
+        e @ typed_ast::Expression::Conversion(..) => Err(
+            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(arena), "conversion"),
+        ),
+        e @ typed_ast::Expression::ChainedComparison(..) => Err(
+            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(arena), "comparison"),
         ),
         e @ typed_ast::Expression::BinaryOperatorForDate(..) => {
             Err(TypeCheckError::UnsupportedConstEvalExpression(
-                e.full_span(),
+                e.full_span(arena),
                 "binary operator for datetimes",
             ))
         }
-        e @ typed_ast::Expression::InstantiateStruct(_, _, _) => Err(
-            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(), "instantiate struct"),
-        ),
-        e @ typed_ast::Expression::AccessField(_, _, _, _, _, _) => Err(
-            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(), "access field of struct"),
-        ),
+        e @ typed_ast::Expression::InstantiateStruct(..) => {
+            Err(TypeCheckError::UnsupportedConstEvalExpression(
+                e.full_span(arena),
+                "instantiate struct",
+            ))
+        }
+        e @ typed_ast::Expression::AccessField(_, _, _, _, _, _) => {
+            Err(TypeCheckError::UnsupportedConstEvalExpression(
+                e.full_span(arena),
+                "access field of struct",
+            ))
+        }
         e @ typed_ast::Expression::List(_, _, _) => Err(
-            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(), "lists"),
+            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(arena), "lists"),
         ),
         e @ typed_ast::Expression::TypedHole(_, _) => Err(
-            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(), "typed hole"),
+            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(arena), "typed hole"),
+        ),
+        typed_ast::Expression::NamedArgument(_, _, expr) => {
+            evaluate_const_expr(arena, &arena[*expr])
+        }
+        e @ typed_ast::Expression::DefaultArgument(..) => Err(
+            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(arena), "default argument"),
+        ),
+        e @ typed_ast::Expression::Trace(..) => Err(
+            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(arena), "trace"),
+        ),
+        e @ typed_ast::Expression::Partial(..) => {
+            Err(TypeCheckError::UnsupportedConstEvalExpression(
+                e.full_span(arena),
+                "partial application",
+            ))
+        }
+        e @ typed_ast::Expression::AutoDiff(..) => Err(
+            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(arena), "autodiff"),
+        ),
+        e @ (typed_ast::Expression::For(..) | typed_ast::Expression::While(..)) => Err(
+            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(arena), "loops"),
+        ),
+        e @ typed_ast::Expression::TryCatch(..) => Err(
+            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(arena), "try-catch"),
+        ),
+        e @ typed_ast::Expression::DynamicCheck(..) => Err(
+            TypeCheckError::UnsupportedConstEvalExpression(e.full_span(arena), "dynamic values"),
         ),
     }
 }
@@ -1,4 +1,5 @@
 use super::substitutions::{ApplySubstitution, Substitution, SubstitutionError};
+use crate::symbol::Symbol;
 use crate::type_variable::TypeVariable;
 use crate::typed_ast::{DType, DTypeFactor, Type};
 
@@ -70,7 +71,11 @@ impl ConstraintSet {
         self.constraints.clear();
     }
 
-    pub fn solve(&mut self) -> Result<(Substitution, Vec<TypeVariable>), ConstraintSolverError> {
+    /// Solve the constraints. On success, returns the substitution and the type
+    /// variables that are left with a `Dim` or an `Ord` bound (in this order).
+    pub fn solve(
+        &mut self,
+    ) -> Result<(Substitution, Vec<TypeVariable>, Vec<TypeVariable>), ConstraintSolverError> {
         let mut substitution = Substitution::empty();
 
         let mut made_progress = true;
@@ -104,22 +109,27 @@ impl ConstraintSet {
         }
 
         // Solve remaining type class constraints (if possible), by remembering
-        // `T_i: Dim` bounds for those type variables
+        // `T_i: Dim` and `T_i: Ord` bounds for those type variables
         let mut dtypes = vec![];
+        let mut ords = vec![];
         let mut remaining_constraints = vec![];
         for c in self.iter() {
-            match c.get_dtype_constraint_type_variable() {
-                None => {
-                    remaining_constraints.push(c.clone());
-                }
-                Some(name) => {
-                    dtypes.push(name);
-                }
+            if let Some(name) = c.get_dtype_constraint_type_variable() {
+                dtypes.push(name);
+            } else if let Some(name) = c.get_ord_constraint_type_variable() {
+                ords.push(name);
+            } else {
+                remaining_constraints.push(c.clone());
             }
         }
         dtypes.sort();
         dtypes.dedup();
 
+        // Dimension types can always be ordered, so a `Dim` bound implies an `Ord` bound
+        ords.retain(|tv| !dtypes.contains(tv));
+        ords.sort();
+        ords.dedup();
+
         if !remaining_constraints.is_empty() {
             return Err(ConstraintSolverError::CouldNotSolve(
                 remaining_constraints
@@ -130,7 +140,7 @@ impl ConstraintSet {
             ));
         }
 
-        Ok((substitution, dtypes))
+        Ok((substitution, dtypes, ords))
     }
 
     fn remove(&mut self, i: usize) {
@@ -174,18 +184,25 @@ impl TrivialResultion {
 /// A type checker constraint can be one of three things:
 /// - A unification constraint `Type1 ~ Type2` which constrains two types to be equal
 /// - A 'type class' constraint `Type: DType` which constrains `Type` to be a dimension type (like `Scalar`, `Length`, or `Length × Mass / Time²`).
+/// - A 'type class' constraint `Type: Ord` which constrains `Type` to be orderable (a dimension type, `String`, or `DateTime`).
 /// - A constraint `DType ~ Scalar` which constrains a dimension type to be dimensionless.
 #[derive(Clone, Debug, PartialEq)]
 pub enum Constraint {
     Equal(Type, Type),
     IsDType(Type),
+    IsOrd(Type),
     EqualScalar(DType),
-    HasField(Type, String, Type),
+    HasField(Type, Symbol, Type),
 }
 
 impl Constraint {
     fn try_trivial_resolution(&self) -> TrivialResultion {
         match self {
+            Constraint::Equal(Type::Dynamic, t) | Constraint::Equal(t, Type::Dynamic)
+                if t.is_closed() =>
+            {
+                TrivialResultion::Satisfied
+            }
             Constraint::Equal(t1, t2) if t1.is_closed() && t2.is_closed() => {
                 if t1 == t2 {
                     TrivialResultion::Satisfied
@@ -200,10 +217,18 @@ impl Constraint {
             }
             Constraint::Equal(_, _) => TrivialResultion::Unknown,
             Constraint::IsDType(t) if t.is_closed() => match t {
-                Type::Dimension(_) => TrivialResultion::Satisfied,
+                Type::Dimension(_) | Type::Dynamic => TrivialResultion::Satisfied,
                 _ => TrivialResultion::Violated,
             },
             Constraint::IsDType(_) => TrivialResultion::Unknown,
+            Constraint::IsOrd(t) if t.is_closed() => {
+                if t.is_orderable() {
+                    TrivialResultion::Satisfied
+                } else {
+                    TrivialResultion::Violated
+                }
+            }
+            Constraint::IsOrd(_) => TrivialResultion::Unknown,
             Constraint::EqualScalar(d) if d.is_scalar() => TrivialResultion::Satisfied,
             Constraint::EqualScalar(d) if d.type_variables(false).is_empty() => {
                 TrivialResultion::Violated
@@ -224,10 +249,22 @@ impl Constraint {
                 if !t.contains(x, false) =>
             {
                 Some(Satisfied::with_substitution(Substitution::single(
-                    x.clone(),
+                    *x,
                     t.clone(),
                 )))
             }
+            // A `Dynamic` value is consistent with all types. Type variables are
+            // bound to `Dynamic`, but it is not propagated into other types.
+            Constraint::Equal(Type::Dynamic, t) | Constraint::Equal(t, Type::Dynamic) => match t {
+                Type::Dimension(dtype) => match dtype.deconstruct_as_single_type_variable() {
+                    Some(v) => Some(Satisfied::with_substitution(Substitution::single(
+                        v,
+                        Type::Dynamic,
+                    ))),
+                    None => Some(Satisfied::trivially()),
+                },
+                _ => Some(Satisfied::trivially()),
+            },
             Constraint::Equal(Type::Dimension(dtype_x), t)
                 if dtype_x
                     .deconstruct_as_single_type_variable()
@@ -236,7 +273,7 @@ impl Constraint {
             {
                 let x = dtype_x.deconstruct_as_single_type_variable().unwrap();
                 Some(Satisfied::with_substitution(Substitution::single(
-                    x.clone(),
+                    x,
                     t.clone(),
                 )))
             }
@@ -248,7 +285,7 @@ impl Constraint {
             {
                 let x = dtype_x.deconstruct_as_single_type_variable().unwrap();
                 Some(Satisfied::with_substitution(Substitution::single(
-                    x.clone(),
+                    x,
                     t.clone(),
                 )))
             }
@@ -274,7 +311,7 @@ impl Constraint {
             Constraint::Equal(Type::TVar(tv), Type::Dimension(d))
             | Constraint::Equal(Type::Dimension(d), Type::TVar(tv)) => {
                 Some(Satisfied::with_new_constraints(vec![Constraint::Equal(
-                    Type::Dimension(DType::from_type_variable(tv.clone())),
+                    Type::Dimension(DType::from_type_variable(*tv)),
                     Type::Dimension(d.clone()),
                 )]))
             }
@@ -289,11 +326,14 @@ impl Constraint {
                 let new_constraints = inner
                     .type_variables(true)
                     .iter()
-                    .map(|tv| Constraint::IsDType(Type::TVar(tv.clone())))
+                    .map(|tv| Constraint::IsDType(Type::TVar(*tv)))
                     .collect();
                 Some(Satisfied::with_new_constraints(new_constraints))
             }
+            Constraint::IsDType(Type::Dynamic) => Some(Satisfied::trivially()),
             Constraint::IsDType(_) => None,
+            Constraint::IsOrd(t) if t.is_orderable() => Some(Satisfied::trivially()),
+            Constraint::IsOrd(_) => None,
             Constraint::EqualScalar(d) if d == &DType::scalar() => Some(Satisfied::trivially()),
             Constraint::EqualScalar(dtype) => match dtype.split_first_factor() {
                 Some(((DTypeFactor::TVar(tv), k), rest)) => {
@@ -304,7 +344,7 @@ impl Constraint {
                             .collect::<Vec<_>>(),
                     );
                     Some(Satisfied::with_substitution(Substitution::single(
-                        tv.clone(),
+                        *tv,
                         Type::Dimension(result),
                     )))
                 }
@@ -336,6 +376,7 @@ impl Constraint {
                 format!("  {} ~ {}", t1, t2)
             }
             Constraint::IsDType(t) => format!("  {}: DType", t),
+            Constraint::IsOrd(t) => format!("  {}: Ord", t),
             Constraint::EqualScalar(d) => format!("  {} = Scalar", d),
             Constraint::HasField(struct_type, field_name, field_type) => {
                 format!(
@@ -349,8 +390,16 @@ impl Constraint {
     // Get the contained type variable, if this constraint is a trivial dtype constraint for a type variable
     fn get_dtype_constraint_type_variable(&self) -> Option<TypeVariable> {
         match self {
-            Constraint::IsDType(Type::TVar(tvar)) => Some(tvar.clone()),
-            Constraint::IsDType(Type::TPar(name)) => Some(TypeVariable::new(name.clone())),
+            Constraint::IsDType(Type::TVar(tvar)) => Some(*tvar),
+            Constraint::IsDType(Type::TPar(name)) => Some(TypeVariable::new(*name)),
+            _ => None,
+        }
+    }
+
+    // Get the contained type variable, if this constraint is a trivial ord constraint for a type variable
+    fn get_ord_constraint_type_variable(&self) -> Option<TypeVariable> {
+        match self {
+            Constraint::IsOrd(t) => t.as_single_type_variable(),
             _ => None,
         }
     }
@@ -363,7 +412,7 @@ impl ApplySubstitution for Constraint {
                 t1.apply(substitution)?;
                 t2.apply(substitution)?;
             }
-            Constraint::IsDType(t) => {
+            Constraint::IsDType(t) | Constraint::IsOrd(t) => {
                 t.apply(substitution)?;
             }
             Constraint::EqualScalar(d) => d.apply(substitution)?,
@@ -1,35 +1,69 @@
 use crate::ast::{TypeAnnotation, TypeParameterBound};
 use crate::dimension::DimensionRegistry;
+use crate::ffi::ArityRange;
+use crate::markup::Markup;
 use crate::pretty_print::PrettyPrint;
+use crate::registry::BaseRepresentation;
 use crate::span::Span;
+use crate::symbol::Symbol;
 use crate::type_variable::TypeVariable;
 use crate::typed_ast::pretty_print_function_signature;
 use crate::Type;
 
+use super::qualified_type::QualifiedType;
 use super::substitutions::{ApplySubstitution, Substitution, SubstitutionError};
 use super::type_scheme::TypeScheme;
 
 use std::collections::HashMap;
 
-type Identifier = String;
+type Identifier = Symbol;
 
 #[derive(Clone, Debug)]
 pub struct FunctionSignature {
-    pub name: String,
+    pub name: Symbol,
     pub definition_span: Span,
     #[allow(dead_code)]
-    pub type_parameters: Vec<(Span, String, Option<TypeParameterBound>)>,
-    pub parameters: Vec<(Span, String, Option<TypeAnnotation>)>,
+    pub type_parameters: Vec<(Span, Symbol, Option<TypeParameterBound>)>,
+    pub parameters: Vec<(Span, Symbol, Option<TypeAnnotation>)>,
+    /// Number of trailing parameters that have a default value
+    pub num_default_parameters: usize,
     pub return_type_annotation: Option<TypeAnnotation>,
     pub fn_type: TypeScheme,
 }
 
 impl FunctionSignature {
-    pub fn pretty_print(&self, registry: &DimensionRegistry) -> crate::markup::Markup {
+    /// Whether the last parameter takes one or more arguments (`xs: D…`)
+    pub fn is_variadic(&self) -> bool {
+        matches!(
+            self.parameters.last(),
+            Some((_, _, Some(TypeAnnotation::Variadic(..))))
+        )
+    }
+
+    /// The possible numbers of arguments in a call
+    pub fn arity(&self) -> ArityRange {
+        if self.is_variadic() {
+            self.parameters.len()..=usize::MAX
+        } else {
+            (self.parameters.len() - self.num_default_parameters)..=self.parameters.len()
+        }
+    }
+
+    /// The function type with named type parameters, and the readable types of the
+    /// parameters and of the return value. Type annotations are shown as written.
+    pub(crate) fn readable_types(
+        &self,
+        registry: &DimensionRegistry,
+    ) -> (
+        QualifiedType,
+        Vec<TypeVariable>,
+        Vec<(Symbol, Markup)>,
+        Markup,
+    ) {
         let (fn_type, type_parameters) = self.fn_type.instantiate_for_printing(Some(
             self.type_parameters
                 .iter()
-                .map(|(_, name, _)| name.clone())
+                .map(|(_, name, _)| *name)
                 .collect(),
         ));
 
@@ -37,28 +71,36 @@ impl FunctionSignature {
             unreachable!()
         };
 
-        let parameters =
-            self.parameters
-                .iter()
-                .zip(parameter_types)
-                .map(|((_, name, annotation), type_)| {
-                    let readable_type = match annotation {
-                        Some(annotation) => annotation.pretty_print(),
-                        None => type_.to_readable_type(registry),
-                    };
-                    (name.clone(), readable_type)
-                });
+        let parameters = self
+            .parameters
+            .iter()
+            .zip(parameter_types)
+            .map(|((_, name, annotation), type_)| {
+                let readable_type = match annotation {
+                    Some(annotation) => annotation.pretty_print(),
+                    None => type_.to_readable_type(registry),
+                };
+                (*name, readable_type)
+            })
+            .collect();
 
         let readable_return_type = match &self.return_type_annotation {
             Some(annotation) => annotation.pretty_print(),
             None => return_type.to_readable_type(registry),
         };
 
+        (fn_type, type_parameters, parameters, readable_return_type)
+    }
+
+    pub fn pretty_print(&self, registry: &DimensionRegistry) -> Markup {
+        let (fn_type, type_parameters, parameters, readable_return_type) =
+            self.readable_types(registry);
+
         pretty_print_function_signature(
             &self.name,
             &fn_type,
             &type_parameters,
-            parameters,
+            parameters.into_iter(),
             &readable_return_type,
         )
     }
@@ -75,7 +117,7 @@ pub struct FunctionMetadata {
 pub enum IdentifierKind {
     /// A normal identifier (variable, unit) with the place where it has been defined.
     /// The boolean flag signifies whether the identifier is a unit or not
-    Normal(TypeScheme, #[allow(dead_code)] Span, bool),
+    Normal(TypeScheme, Span, bool),
     /// A function
     Function(FunctionSignature, FunctionMetadata),
     /// Identifiers that are defined by the language: `_` and `ans` (see LAST_RESULT_IDENTIFIERS)
@@ -92,13 +134,20 @@ impl IdentifierKind {
     }
 }
 
+/// The previous definition of an identifier that is shadowed in a scope
+pub(crate) type ShadowedIdentifier = (Option<IdentifierKind>, Option<(Span, String)>);
+
 #[derive(Clone, Debug, Default)]
 pub struct Environment {
     identifiers: HashMap<Identifier, IdentifierKind>,
+    /// Identifiers that have been marked with `@deprecated`, with the place
+    /// where they have been defined and the deprecation message
+    deprecations: HashMap<Identifier, (Span, String)>,
 }
 
 impl Environment {
     pub fn add(&mut self, i: Identifier, type_: Type, span: Span, is_unit: bool) {
+        self.deprecations.remove(&i);
         self.identifiers.insert(
             i,
             IdentifierKind::Normal(TypeScheme::Concrete(type_), span, is_unit),
@@ -106,27 +155,75 @@ impl Environment {
     }
 
     pub fn add_scheme(&mut self, i: Identifier, scheme: TypeScheme, span: Span, is_unit: bool) {
+        self.deprecations.remove(&i);
         self.identifiers
             .insert(i, IdentifierKind::Normal(scheme, span, is_unit));
     }
 
     pub(crate) fn add_function(
         &mut self,
-        v: String,
+        v: Identifier,
         signature: FunctionSignature,
         metadata: FunctionMetadata,
     ) {
+        self.deprecations.remove(&v);
         self.identifiers
             .insert(v, IdentifierKind::Function(signature, metadata));
     }
 
+    /// Uses of the identifier will lead to a warning (until it is redefined).
+    pub(crate) fn mark_deprecated(&mut self, i: Identifier, span: Span, message: String) {
+        self.deprecations.insert(i, (span, message));
+    }
+
+    pub(crate) fn get_deprecation(&self, i: Identifier) -> Option<&(Span, String)> {
+        self.deprecations.get(&i)
+    }
+
     pub fn add_predefined(&mut self, v: Identifier, type_: TypeScheme) {
         self.identifiers
             .insert(v, IdentifierKind::Predefined(type_));
     }
 
-    pub(crate) fn get_identifier_type(&self, v: &str) -> Option<TypeScheme> {
-        self.identifiers.get(v).map(|k| k.get_type())
+    pub(crate) fn remove(&mut self, v: Identifier) {
+        self.deprecations.remove(&v);
+        self.identifiers.remove(&v);
+    }
+
+    /// Temporarily introduces an identifier, e.g. a loop variable, which may shadow
+    /// an existing one. The previous definition is restored with [`Self::end_scope`].
+    pub(crate) fn begin_scope(
+        &mut self,
+        i: Identifier,
+        type_: Type,
+        span: Span,
+    ) -> ShadowedIdentifier {
+        let shadowed = (
+            self.identifiers.get(&i).cloned(),
+            self.deprecations.get(&i).cloned(),
+        );
+        self.add(i, type_, span, false);
+        shadowed
+    }
+
+    /// Removes the identifier, such that it can be brought back with [`Self::end_scope`]
+    pub(crate) fn take(&mut self, i: Identifier) -> ShadowedIdentifier {
+        (self.identifiers.remove(&i), self.deprecations.remove(&i))
+    }
+
+    pub(crate) fn end_scope(&mut self, i: Identifier, shadowed: ShadowedIdentifier) {
+        self.remove(i);
+        let (kind, deprecation) = shadowed;
+        if let Some(kind) = kind {
+            self.identifiers.insert(i, kind);
+        }
+        if let Some(deprecation) = deprecation {
+            self.deprecations.insert(i, deprecation);
+        }
+    }
+
+    pub(crate) fn get_identifier_type(&self, v: Identifier) -> Option<TypeScheme> {
+        self.identifiers.get(&v).map(|k| k.get_type())
     }
 
     pub(crate) fn iter_identifiers(&self) -> impl Iterator<Item = &Identifier> {
@@ -145,27 +242,71 @@ impl Environment {
             .map(|(id, kind)| (id, kind.get_type()))
     }
 
+    /// A unit of the given dimension, for suggestions in error messages. Units that
+    /// have been defined first (like the SI units in the prelude) and short aliases
+    /// are preferred.
+    pub(crate) fn find_unit_of_dimension(
+        &self,
+        dimension: &BaseRepresentation,
+    ) -> Option<Identifier> {
+        self.identifiers
+            .iter()
+            .filter_map(|(id, kind)| match kind {
+                IdentifierKind::Normal(scheme, span, true)
+                    if !self.deprecations.contains_key(id)
+                        && matches!(
+                            scheme,
+                            TypeScheme::Concrete(_) | TypeScheme::Quantified(0, _)
+                        ) =>
+                {
+                    match scheme.to_concrete_type() {
+                        Type::Dimension(dtype)
+                            if dtype.type_variables(true).is_empty()
+                                && &dtype.to_base_representation() == dimension =>
+                        {
+                            Some((id, span))
+                        }
+                        _ => None,
+                    }
+                }
+                _ => None,
+            })
+            .min_by_key(|(id, span)| {
+                (
+                    span.code_source_id,
+                    span.start.byte,
+                    id.as_str().len(),
+                    id.as_str(),
+                )
+            })
+            .map(|(id, _)| *id)
+    }
+
     pub(crate) fn get_function_info(
         &self,
-        name: &str,
+        name: Identifier,
     ) -> Option<(&FunctionSignature, &FunctionMetadata)> {
-        match self.identifiers.get(name) {
+        match self.identifiers.get(&name) {
             Some(IdentifierKind::Function(signature, metadata)) => Some((signature, metadata)),
             _ => None,
         }
     }
 
-    pub(crate) fn generalize_types(&mut self, dtype_variables: &[TypeVariable]) {
+    pub(crate) fn generalize_types(
+        &mut self,
+        dtype_variables: &[TypeVariable],
+        ord_variables: &[TypeVariable],
+    ) {
         for (_, kind) in self.identifiers.iter_mut() {
             match kind {
                 IdentifierKind::Normal(t, _, _) => {
-                    t.generalize(dtype_variables);
+                    t.generalize(dtype_variables, ord_variables);
                 }
                 IdentifierKind::Function(signature, _) => {
-                    signature.fn_type.generalize(dtype_variables);
+                    signature.fn_type.generalize(dtype_variables, ord_variables);
                 }
                 IdentifierKind::Predefined(t) => {
-                    t.generalize(dtype_variables);
+                    t.generalize(dtype_variables, ord_variables);
                 }
             }
         }
@@ -1,4 +1,6 @@
+use crate::error_code::ErrorCode;
 use crate::ffi::ArityRange;
+use crate::pretty_print::PrettyPrint;
 use crate::registry::RegistryError;
 use crate::span::Span;
 use crate::typed_ast::BinaryOperator;
@@ -9,6 +11,21 @@ use thiserror::Error;
 use super::substitutions::SubstitutionError;
 use super::IncompatibleDimensionsError;
 
+/// Code that type checks, but probably does not do what was intended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeCheckWarning {
+    /// A chain like `a < b > c`: spans of the first operator and of the first
+    /// one that points in the other direction
+    MixedComparisonDirections(Span, Span),
+    /// Use of an identifier that has been marked with `@deprecated`: span of the
+    /// use site, name, deprecation message and span of the definition
+    DeprecatedIdentifier(Span, String, String, Span),
+    /// A conversion like `meter -> 5 km`, where the operands are probably swapped:
+    /// span of the right hand side, span of the whole conversion and the swapped
+    /// conversion
+    ReversedConversion(Span, Span, String),
+}
+
 #[derive(Debug, Clone, Error, PartialEq, Eq)]
 pub enum TypeCheckError {
     #[error("Unknown identifier '{1}'.")]
@@ -41,7 +58,10 @@ pub enum TypeCheckError {
         BaseRepresentation,
     ),
 
-    #[error("Function or procedure '{callable_name}' called with {num_args} arguments(s), but needs {}..{}", arity.start(), arity.end())]
+    #[error(
+        "Function or procedure '{callable_name}' called with {num_args} arguments(s), but needs {}",
+        display_arity(arity)
+    )]
     WrongArity {
         callable_span: Span,
         callable_name: String,
@@ -56,6 +76,9 @@ pub enum TypeCheckError {
     #[error("Foreign function definition (without body) '{1}' needs parameter and return type annotations.")]
     ForeignFunctionNeedsTypeAnnotations(Span, String),
 
+    #[error("Parameter '{1}' of a foreign function (without body) can not have a default value.")]
+    ForeignFunctionWithDefaultValue(Span, String),
+
     #[error("Unknown foreign function (without body) '{1}'")]
     UnknownForeignFunction(Span, String),
 
@@ -89,12 +112,24 @@ pub enum TypeCheckError {
     #[error("Incompatible types in operator")]
     IncompatibleTypesInOperator(Span, BinaryOperator, Type, Span, Type, Span),
 
+    #[error("No overload of operator '{}' for types '{2}' and '{3}'", .1.pretty_print().to_string().trim())]
+    NoMatchingOperatorOverload(Span, BinaryOperator, Type, Type),
+
+    #[error("Ambiguous overloads of operator '{}': {}", .1.pretty_print().to_string().trim(), .2.join(", "))]
+    AmbiguousOperatorOverload(Span, BinaryOperator, Vec<String>),
+
+    #[error("Operator overload '{1}' needs exactly two parameters, at least one of them with a struct type annotation")]
+    InvalidOperatorOverload(Span, String),
+
     #[error("Incompatible types in function call: expected '{1}', got '{3}' instead")]
     IncompatibleTypesInFunctionCall(Option<Span>, Type, Span, Type),
 
     #[error("Incompatible types for struct field: expected '{1}', got '{3}' instead")]
     IncompatibleTypesForStructField(Span, Type, Span, Type),
 
+    #[error("Incompatible base in struct update: expected '{1}', got '{3}' instead")]
+    IncompatibleBaseInStructUpdate(Span, Type, Span, Type),
+
     #[error("Missing a definition for dimension {1}")]
     MissingDimension(Span, String),
 
@@ -119,6 +154,18 @@ pub enum TypeCheckError {
     #[error("Duplicate field '{2}' in struct instantiation")]
     DuplicateFieldInStructInstantiation(Span, Span, String),
 
+    #[error("Function '{2}' does not have a parameter named '{3}'")]
+    UnknownNamedArgument(Span, Span, String, String, Option<String>),
+
+    #[error("Argument for parameter '{2}' is specified more than once")]
+    DuplicateArgument(Span, Span, String),
+
+    #[error("Missing argument for parameter '{3}' in call to function '{2}'")]
+    MissingArgument(Span, Span, String, String),
+
+    #[error("Named arguments can only be used when calling a function by its name")]
+    NamedArgumentsInCallableCall(Span),
+
     #[error("Can not access field '{2}' of non struct type '{3}'")]
     FieldAccessOfNonStructType(Span, Span, String, Type),
 
@@ -143,6 +190,12 @@ pub enum TypeCheckError {
     #[error("Missing dimension bound for type parameter")]
     MissingDimBound(Span),
 
+    #[error("Missing 'Ord' bound for type parameter")]
+    MissingOrdBound(Span),
+
+    #[error("Values of type '{1}' can not be ordered")]
+    NotOrderable(Span, Type, String),
+
     #[error("Type for exponentiation operation can not be inferred for this case, consider adding a type annotation for the base")]
     ExponentiationNeedsTypeAnnotation(Span),
 
@@ -154,6 +207,202 @@ pub enum TypeCheckError {
 
     #[error("Multiple typed holes in statement")]
     MultipleTypedHoles(Span),
+
+    #[error("Only units can be part of the unit system '{1}'")]
+    ExpectedUnitInUnitSystem(Span, String),
+
+    #[error("Can not destructure a value of non struct type '{2}' with a struct pattern")]
+    DestructuringOfNonStructType(Span, Span, Type),
+
+    #[error("Missing fields in destructuring pattern")]
+    MissingFieldsInDestructuring(Span, Span, Vec<(String, Type)>),
+
+    #[error("Can not destructure a value of non list type '{2}' with a list pattern")]
+    DestructuringOfNonListType(Span, Span, Type),
+
+    #[error("Identifier '{2}' is bound more than once in the same pattern")]
+    DuplicateBindingInPattern(Span, Span, String),
+
+    #[error("The dimension '{2}' for unit '{1}' already exists")]
+    ImplicitDimensionClash(Span, String, String, Span),
+
+    #[error("Unit '{1}' is local to the function '{3}' and not in scope here")]
+    LocalUnitOutOfScope(Span, String, Span, String),
+
+    #[error("Can only loop over lists, not over a value of type '{1}'")]
+    LoopOverNonListType(Span, Type),
+
+    #[error("Incompatible types for accumulator: initial value has type '{1}', loop body has type '{3}'")]
+    IncompatibleTypesInAccumulator(Span, Type, Span, Type),
+
+    #[error("Incompatible types in 'try' and 'catch' branches")]
+    IncompatibleTypesInTryCatch(Span, Type, Span, Type, Span),
+
+    #[error("The 'Dynamic' type is not allowed in this context")]
+    DynamicTypeForbidden(Span),
+
+    #[error("'{1}' can not be used as a conversion target, since it has no inverse")]
+    UnitFunctionWithoutInverse(Span, String),
+
+    #[error("The inverse of '{1}' needs to have the type '{2}', but it has the type '{3}'")]
+    IncompatibleInverse(Span, String, Type, Type),
+
+    /// An identifier from a builtin module that needs a Cargo feature which is
+    /// disabled in this build: span, name and feature
+    #[error("'{1}' is not available, because Numbat was built without {2} support")]
+    FeatureNotAvailable(Span, String, &'static str),
+
+    #[error("The type of the function in a partial application needs to be known")]
+    PartialApplicationOfUnknownType(Span),
+
+    #[error("Too many arguments in partial application: the function takes {num_parameters}, but {num_args} are given")]
+    TooManyArgumentsInPartialApplication {
+        span: Span,
+        function_span: Span,
+        num_parameters: usize,
+        num_args: usize,
+    },
+
+    #[error("Struct '{struct_name}' has no method '{name}'")]
+    UnknownMethod {
+        span: Span,
+        receiver_span: Span,
+        name: String,
+        struct_name: String,
+        methods: Vec<String>,
+    },
+
+    #[error("'{function}' can not be differentiated: {construct} is not supported")]
+    NotDifferentiable {
+        span: Span,
+        construct: String,
+        function: String,
+    },
+
+    #[error("'autodiff' needs the name of a function with a single parameter")]
+    InvalidAutoDiffArgument(Span),
+
+    #[error("Incompatible types in conversion: '{1}' can not be converted to '{3}'")]
+    IncompatibleTypesInConversion(Span, Type, Span, String, Span, Option<String>),
+
+    #[error("The dimension of this argument is not known, so the dimension predicate can not be decided")]
+    UnresolvedDimensionPredicate(Span),
+
+    #[error("Function '{1}' uses dimension predicates, so its parameters and its return type need type annotations")]
+    GenericFunctionNeedsTypeAnnotations(Span, String),
+
+    #[error("Function '{1}' uses dimension predicates, so it can only be called directly")]
+    GenericFunctionNotCalled(Span, String),
+
+    #[error("Function '{1}' can not be specialized for {3} as type parameter '{2}'")]
+    CanNotSpecialize(Span, String, String, String),
+
+    #[error("'{2}' expects a time series, but the elements of this list have type '{1}'")]
+    NotATimeSeries(Span, Type, String),
+
+    #[error("The formula '{formula}' can not be solved for '{quantity}': {reason}")]
+    FormulaNotSolvable {
+        span: Span,
+        formula: String,
+        quantity: String,
+        reason: String,
+    },
+}
+
+fn display_arity(arity: &ArityRange) -> String {
+    if *arity.end() == usize::MAX {
+        format!("at least {}", arity.start())
+    } else {
+        format!("{}..{}", arity.start(), arity.end())
+    }
+}
+
+impl TypeCheckError {
+    pub fn error_code(&self) -> ErrorCode {
+        match self {
+            TypeCheckError::UnknownIdentifier(..) => ErrorCode::E0501,
+            TypeCheckError::IncompatibleDimensions(..) => ErrorCode::E0502,
+            TypeCheckError::NonScalarExponent(..) => ErrorCode::E0503,
+            TypeCheckError::NonScalarFactorialArgument(..) => ErrorCode::E0504,
+            TypeCheckError::UnsupportedConstEvalExpression(..) => ErrorCode::E0505,
+            TypeCheckError::DivisionByZeroInConstEvalExpression(..) => ErrorCode::E0506,
+            TypeCheckError::RegistryError(inner) => inner.error_code(),
+            TypeCheckError::IncompatibleAlternativeDimensionExpression(..) => ErrorCode::E0507,
+            TypeCheckError::WrongArity { .. } => ErrorCode::E0508,
+            TypeCheckError::TypeParameterNameClash(..) => ErrorCode::E0509,
+            TypeCheckError::ForeignFunctionNeedsTypeAnnotations(..) => ErrorCode::E0510,
+            TypeCheckError::ForeignFunctionWithDefaultValue(..) => ErrorCode::E0511,
+            TypeCheckError::UnknownForeignFunction(..) => ErrorCode::E0512,
+            TypeCheckError::NonRationalExponent(..) => ErrorCode::E0513,
+            TypeCheckError::OverflowInConstExpr(..) => ErrorCode::E0514,
+            TypeCheckError::ExpectedDimensionType(..) => ErrorCode::E0515,
+            TypeCheckError::ExpectedBool(..) => ErrorCode::E0516,
+            TypeCheckError::IncompatibleTypesInCondition(..) => ErrorCode::E0517,
+            TypeCheckError::IncompatibleTypeInAssert(..) => ErrorCode::E0518,
+            TypeCheckError::IncompatibleTypesInAssertEq(..) => ErrorCode::E0519,
+            TypeCheckError::IncompatibleTypesInAnnotation(..) => ErrorCode::E0520,
+            TypeCheckError::IncompatibleTypesInComparison(..) => ErrorCode::E0521,
+            TypeCheckError::IncompatibleTypesInOperator(..) => ErrorCode::E0522,
+            TypeCheckError::NoMatchingOperatorOverload(..) => ErrorCode::E0523,
+            TypeCheckError::AmbiguousOperatorOverload(..) => ErrorCode::E0524,
+            TypeCheckError::InvalidOperatorOverload(..) => ErrorCode::E0525,
+            TypeCheckError::IncompatibleTypesInFunctionCall(..) => ErrorCode::E0526,
+            TypeCheckError::IncompatibleTypesForStructField(..) => ErrorCode::E0527,
+            TypeCheckError::IncompatibleBaseInStructUpdate(..) => ErrorCode::E0528,
+            TypeCheckError::MissingDimension(..) => ErrorCode::E0529,
+            TypeCheckError::NoFunctionReferenceToGenericFunction(..) => ErrorCode::E0530,
+            TypeCheckError::OnlyFunctionsAndReferencesCanBeCalled(..) => ErrorCode::E0531,
+            TypeCheckError::NoDimensionlessBaseUnit(..) => ErrorCode::E0532,
+            TypeCheckError::UnknownStruct(..) => ErrorCode::E0533,
+            TypeCheckError::UnknownFieldInStructInstantiation(..) => ErrorCode::E0534,
+            TypeCheckError::DuplicateFieldInStructDefinition(..) => ErrorCode::E0535,
+            TypeCheckError::DuplicateFieldInStructInstantiation(..) => ErrorCode::E0536,
+            TypeCheckError::UnknownNamedArgument(..) => ErrorCode::E0537,
+            TypeCheckError::DuplicateArgument(..) => ErrorCode::E0538,
+            TypeCheckError::MissingArgument(..) => ErrorCode::E0539,
+            TypeCheckError::NamedArgumentsInCallableCall(..) => ErrorCode::E0540,
+            TypeCheckError::FieldAccessOfNonStructType(..) => ErrorCode::E0541,
+            TypeCheckError::UnknownFieldAccess(..) => ErrorCode::E0542,
+            TypeCheckError::MissingFieldsInStructInstantiation(..) => ErrorCode::E0543,
+            TypeCheckError::IncompatibleTypesInList(..) => ErrorCode::E0544,
+            TypeCheckError::NameResolutionError(inner) => inner.error_code(),
+            TypeCheckError::ConstraintSolverError(..) => ErrorCode::E0601,
+            TypeCheckError::SubstitutionError(_, inner) => inner.error_code(),
+            TypeCheckError::MissingDimBound(..) => ErrorCode::E0545,
+            TypeCheckError::MissingOrdBound(..) => ErrorCode::E0546,
+            TypeCheckError::NotOrderable(..) => ErrorCode::E0547,
+            TypeCheckError::ExponentiationNeedsTypeAnnotation(..) => ErrorCode::E0548,
+            TypeCheckError::DerivedUnitDefinitionMustNotBeGeneric(..) => ErrorCode::E0549,
+            TypeCheckError::TypedHoleInStatement(..) => ErrorCode::E0550,
+            TypeCheckError::MultipleTypedHoles(..) => ErrorCode::E0551,
+            TypeCheckError::ExpectedUnitInUnitSystem(..) => ErrorCode::E0552,
+            TypeCheckError::DestructuringOfNonStructType(..) => ErrorCode::E0553,
+            TypeCheckError::MissingFieldsInDestructuring(..) => ErrorCode::E0554,
+            TypeCheckError::DestructuringOfNonListType(..) => ErrorCode::E0555,
+            TypeCheckError::DuplicateBindingInPattern(..) => ErrorCode::E0556,
+            TypeCheckError::ImplicitDimensionClash(..) => ErrorCode::E0557,
+            TypeCheckError::LocalUnitOutOfScope(..) => ErrorCode::E0558,
+            TypeCheckError::LoopOverNonListType(..) => ErrorCode::E0559,
+            TypeCheckError::IncompatibleTypesInAccumulator(..) => ErrorCode::E0560,
+            TypeCheckError::IncompatibleTypesInTryCatch(..) => ErrorCode::E0561,
+            TypeCheckError::DynamicTypeForbidden(_) => ErrorCode::E0562,
+            TypeCheckError::UnitFunctionWithoutInverse(..) => ErrorCode::E0563,
+            TypeCheckError::IncompatibleInverse(..) => ErrorCode::E0564,
+            TypeCheckError::FeatureNotAvailable(..) => ErrorCode::E0565,
+            TypeCheckError::PartialApplicationOfUnknownType(..) => ErrorCode::E0566,
+            TypeCheckError::TooManyArgumentsInPartialApplication { .. } => ErrorCode::E0567,
+            TypeCheckError::UnknownMethod { .. } => ErrorCode::E0568,
+            TypeCheckError::NotDifferentiable { .. } => ErrorCode::E0569,
+            TypeCheckError::InvalidAutoDiffArgument(..) => ErrorCode::E0570,
+            TypeCheckError::IncompatibleTypesInConversion(..) => ErrorCode::E0571,
+            TypeCheckError::UnresolvedDimensionPredicate(..) => ErrorCode::E0572,
+            TypeCheckError::GenericFunctionNeedsTypeAnnotations(..) => ErrorCode::E0573,
+            TypeCheckError::GenericFunctionNotCalled(..) => ErrorCode::E0574,
+            TypeCheckError::CanNotSpecialize(..) => ErrorCode::E0575,
+            TypeCheckError::NotATimeSeries(..) => ErrorCode::E0576,
+            TypeCheckError::FormulaNotSolvable { .. } => ErrorCode::E0577,
+        }
+    }
 }
 
 pub type Result<T> = std::result::Result<T, TypeCheckError>;
@@ -0,0 +1,146 @@
+//! Solving the equation of a `formula` statement for each of its quantities. The
+//! quantity is isolated by undoing the operations around it, one by one, on the
+//! other side of the equation. This only works if it occurs exactly once, and
+//! only inside of operations that can be inverted. The functions that are defined
+//! with the solutions are type checked like any other function, so the dimensions
+//! of the quantities are checked (or inferred) there.
+
+use std::cell::RefCell;
+
+use crate::ast::{BinaryOperator, Expression, UnaryOperator};
+use crate::span::Span;
+use crate::symbol::Symbol;
+
+use super::autodiff::{binop, call, div, literal, mentions, mul, negate, scalar};
+use super::error::{Result, TypeCheckError};
+
+pub(crate) struct Formula<'a> {
+    pub(crate) name: Symbol,
+    pub(crate) lhs: &'a Expression,
+    pub(crate) rhs: &'a Expression,
+}
+
+impl Formula<'_> {
+    fn not_solvable(&self, span: Span, quantity: Symbol, reason: &str) -> TypeCheckError {
+        TypeCheckError::FormulaNotSolvable {
+            span,
+            formula: self.name.to_string(),
+            quantity: quantity.to_string(),
+            reason: reason.into(),
+        }
+    }
+
+    /// An expression for `quantity` in terms of the other quantities. The span is
+    /// the one of the declaration of the quantity.
+    pub(crate) fn solve(&self, span: Span, quantity: Symbol) -> Result<Expression> {
+        let occurrences = [self.lhs, self.rhs]
+            .into_iter()
+            .flat_map(|side| occurrences(side, quantity))
+            .collect::<Vec<_>>();
+        match occurrences[..] {
+            [] => {
+                return Err(self.not_solvable(span, quantity, "it does not occur in the equation"))
+            }
+            [_] => {}
+            [_, second, ..] => {
+                return Err(self.not_solvable(second, quantity, "it occurs more than once"))
+            }
+        }
+
+        let (mut expr, mut solution) = if mentions(self.lhs, quantity) {
+            (self.lhs, self.rhs.clone())
+        } else {
+            (self.rhs, self.lhs.clone())
+        };
+
+        loop {
+            match expr {
+                Expression::Identifier(_, name) if *name == quantity => return Ok(solution),
+                Expression::UnaryOperator {
+                    op: UnaryOperator::Negate,
+                    expr: operand,
+                    ..
+                } => {
+                    solution = negate(solution);
+                    expr = operand;
+                }
+                Expression::BinaryOperator { op, lhs, rhs, .. } => {
+                    let in_lhs = mentions(lhs, quantity);
+                    let (other, operand) = if in_lhs {
+                        ((**rhs).clone(), lhs)
+                    } else {
+                        ((**lhs).clone(), rhs)
+                    };
+                    solution = match (op, in_lhs) {
+                        (BinaryOperator::Add, _) => binop(BinaryOperator::Sub, solution, other),
+                        (BinaryOperator::Sub, true) => binop(BinaryOperator::Add, solution, other),
+                        (BinaryOperator::Sub, false) => binop(BinaryOperator::Sub, other, solution),
+                        (BinaryOperator::Mul, _) => div(solution, other),
+                        (BinaryOperator::Div, true) => mul(solution, other),
+                        (BinaryOperator::Div, false) => div(other, solution),
+                        (BinaryOperator::Power, true) => root(solution, other),
+                        (BinaryOperator::Power, false) => {
+                            return Err(self.not_solvable(
+                                rhs.full_span(),
+                                quantity,
+                                "it occurs in an exponent",
+                            ))
+                        }
+                        _ => {
+                            return Err(self.not_solvable(
+                                expr.full_span(),
+                                quantity,
+                                "it occurs in a comparison or a logical operation",
+                            ))
+                        }
+                    };
+                    expr = operand;
+                }
+                Expression::FunctionCall(..) => {
+                    return Err(self.not_solvable(
+                        expr.full_span(),
+                        quantity,
+                        "it occurs in the argument of a function call",
+                    ))
+                }
+                _ => {
+                    return Err(self.not_solvable(
+                        expr.full_span(),
+                        quantity,
+                        "it occurs in an expression that can not be inverted",
+                    ))
+                }
+            }
+        }
+    }
+}
+
+/// `solution^(1 / exponent)`, or `sqrt(solution)` for square roots
+fn root(solution: Expression, exponent: Expression) -> Expression {
+    let span = exponent.full_span();
+    if literal(&exponent) == Some(2.0) {
+        call(span, "sqrt", vec![solution])
+    } else {
+        binop(
+            BinaryOperator::Power,
+            solution,
+            div(scalar(span, 1.0), exponent),
+        )
+    }
+}
+
+/// The spans of all occurrences of the identifier `name` in `expr`
+fn occurrences(expr: &Expression, name: Symbol) -> Vec<Span> {
+    let spans = RefCell::new(vec![]);
+    expr.contains(&|expr| {
+        if let Expression::Identifier(span, identifier) = expr {
+            if *identifier == name {
+                spans.borrow_mut().push(*span);
+            }
+        }
+        false
+    });
+    let mut spans = spans.into_inner();
+    spans.sort_by_key(|span| span.start.byte);
+    spans
+}
@@ -3,6 +3,7 @@ use std::{collections::HashMap, error::Error, fmt};
 use crate::arithmetic::{pretty_exponent, Exponent, Rational};
 use crate::registry::{BaseRepresentation, BaseRepresentationFactor};
 use crate::span::Span;
+use crate::symbol::Symbol;
 
 use itertools::Itertools;
 use num_traits::Zero;
@@ -15,12 +16,18 @@ pub struct IncompatibleDimensionsError {
     pub span_expected: Span,
     pub expected_name: &'static str,
     pub expected_type: BaseRepresentation,
-    pub expected_dimensions: Vec<String>,
+    pub expected_dimensions: Vec<Symbol>,
     pub span_actual: Span,
     pub actual_name: &'static str,
     pub actual_name_for_fix: &'static str,
     pub actual_type: BaseRepresentation,
-    pub actual_dimensions: Vec<String>,
+    pub actual_dimensions: Vec<Symbol>,
+    /// A suggested fix that is more specific than the one derived from the
+    /// dimensions alone
+    pub hint: Option<String>,
+    /// An additional explanation, for example if one of the operands is a unit that
+    /// might have been meant as a variable
+    pub note: Option<String>,
 }
 
 fn pad(a: &str, b: &str) -> (String, String) {
@@ -84,23 +91,23 @@ impl fmt::Display for IncompatibleDimensionsError {
             let format_factor =
                 |name: &str, exponent: &Exponent| format!(" × {name}{}", pretty_exponent(exponent));
 
-            let mut shared_factors = HashMap::<&String, (Exponent, Exponent)>::new();
-            let mut expected_factors = HashMap::<&String, Exponent>::new();
-            let mut actual_factors = HashMap::<&String, Exponent>::new();
+            let mut shared_factors = HashMap::<Symbol, (Exponent, Exponent)>::new();
+            let mut expected_factors = HashMap::<Symbol, Exponent>::new();
+            let mut actual_factors = HashMap::<Symbol, Exponent>::new();
 
             for BaseRepresentationFactor(name, expected_exponent) in self.expected_type.iter() {
                 if let Some(BaseRepresentationFactor(_, actual_exponent)) =
                     self.actual_type.iter().find(|f| *name == f.0)
                 {
-                    shared_factors.insert(name, (*expected_exponent, *actual_exponent));
+                    shared_factors.insert(*name, (*expected_exponent, *actual_exponent));
                 } else {
-                    expected_factors.insert(name, *expected_exponent);
+                    expected_factors.insert(*name, *expected_exponent);
                 }
             }
 
             for BaseRepresentationFactor(name, exponent) in self.actual_type.iter() {
-                if !shared_factors.contains_key(&name) {
-                    actual_factors.insert(name, *exponent);
+                if !shared_factors.contains_key(name) {
+                    actual_factors.insert(*name, *exponent);
                 }
             }
 
@@ -146,13 +153,17 @@ impl fmt::Display for IncompatibleDimensionsError {
         };
 
         if !self.expected_dimensions.is_empty() {
-            expected_result_string
-                .push_str(&format!("    [= {}]", self.expected_dimensions.join(", ")));
+            expected_result_string.push_str(&format!(
+                "    [= {}]",
+                self.expected_dimensions.iter().join(", ")
+            ));
         }
 
         if !self.actual_dimensions.is_empty() {
-            actual_result_string
-                .push_str(&format!("    [= {}]", self.actual_dimensions.join(", ")));
+            actual_result_string.push_str(&format!(
+                "    [= {}]",
+                self.actual_dimensions.iter().join(", ")
+            ));
         }
 
         write!(
@@ -169,11 +180,13 @@ impl fmt::Display for IncompatibleDimensionsError {
             actual_result_string.trim_start_matches(" × ").trim_end(),
         )?;
 
-        if let Some(fix) = suggested_fix(
-            &self.expected_type,
-            &self.actual_type,
-            self.actual_name_for_fix,
-        ) {
+        if let Some(fix) = self.hint.clone().or_else(|| {
+            suggested_fix(
+                &self.expected_type,
+                &self.actual_type,
+                self.actual_name_for_fix,
+            )
+        }) {
             write!(f, "\n\nSuggested fix: {fix}")?;
         }
 
@@ -1,56 +1,218 @@
 #[cfg(test)]
 mod tests;
 
+mod autodiff;
 mod const_evaluation;
 mod constraints;
 mod environment;
 mod error;
+mod formula;
 mod incompatible_dimensions;
 mod name_generator;
 pub mod qualified_type;
-mod substitutions;
+pub(crate) mod substitutions;
 pub mod type_scheme;
 
-use std::collections::HashMap;
+use std::collections::{hash_map::Entry, HashMap, HashSet};
 use std::ops::Deref;
+use std::sync::Arc;
 
 use crate::arithmetic::Exponent;
 use crate::ast::{
-    self, BinaryOperator, DefineVariable, ProcedureKind, StringPart, TypeAnnotation,
-    TypeExpression, TypeParameterBound,
+    self, formula_function_name, method_name, BinaryOperator, CallSyntax, DefineVariable,
+    ProcedureKind, StringPart, TypeAnnotation, TypeExpression, TypeParameterBound,
 };
 use crate::dimension::DimensionRegistry;
-use crate::name_resolution::Namespace;
 use crate::name_resolution::LAST_RESULT_IDENTIFIERS;
+use crate::name_resolution::{NameResolutionError, Namespace};
+use crate::prefix_parser::PrefixParser;
 use crate::pretty_print::PrettyPrint;
+use crate::registry::RegistryEntry;
 use crate::span::Span;
+use crate::symbol::Symbol;
 use crate::type_variable::TypeVariable;
-use crate::typed_ast::{self, DType, DTypeFactor, Expression, StructInfo, Type};
-use crate::{decorator, ffi, suggestion};
+use crate::typed_ast::{
+    self, DType, DTypeFactor, Expression, ExpressionArena, StructInfo, Type, TypedProgram,
+};
+use crate::{decorator, features, ffi, suggestion};
 
+use autodiff::{Differentiator, FunctionDefinition};
 use const_evaluation::evaluate_const_expr;
 use constraints::{Constraint, ConstraintSet, ConstraintSolverError, TrivialResultion};
-use environment::{Environment, FunctionMetadata, FunctionSignature};
+use environment::{Environment, FunctionMetadata, FunctionSignature, ShadowedIdentifier};
+use formula::Formula;
 use itertools::Itertools;
 use name_generator::NameGenerator;
-use num_traits::Zero;
+use num_traits::{One, Zero};
 
-pub use error::{Result, TypeCheckError};
+pub use error::{Result, TypeCheckError, TypeCheckWarning};
 pub use incompatible_dimensions::IncompatibleDimensionsError;
-use qualified_type::Bound;
+use qualified_type::{Bound, Bounds};
 use substitutions::{ApplySubstitution, Substitution};
 use type_scheme::TypeScheme;
 
-fn dtype(e: &Expression) -> Result<DType> {
-    match e.get_type() {
+fn dtype(arena: &ExpressionArena, e: &Expression) -> Result<DType> {
+    match e.get_type(arena) {
         Type::Dimension(dtype) => Ok(dtype),
-        t => Err(TypeCheckError::ExpectedDimensionType(e.full_span(), t)),
+        t => Err(TypeCheckError::ExpectedDimensionType(e.full_span(arena), t)),
+    }
+}
+
+/// Dimensionless units that are often added to other quantities by mistake, as
+/// in `100 m + 5 %`
+const PERCENTAGE_UNITS: &[&str] = &[
+    "percent",
+    "permille",
+    "partspermillion",
+    "partsperbillion",
+    "partspertrillion",
+    "partsperquadrillion",
+];
+
+/// A unit identifier that is used on its own, like `s` in `t + s`, where a variable
+/// might have been intended. Returns the name of the unit (including the prefix).
+/// Units directly after a number (`2 s`) are not reported.
+fn bare_unit_identifier(expr: &ast::Expression) -> Option<String> {
+    match expr {
+        ast::Expression::UnitIdentifier(_, prefix, _, full_name)
+            if !PERCENTAGE_UNITS.contains(&full_name.as_str()) =>
+        {
+            let prefix_name = PrefixParser::long_prefix_name(*prefix)
+                .filter(|_| !prefix.is_none())
+                .unwrap_or_default();
+            Some(format!("{prefix_name}{full_name}"))
+        }
+        _ => None,
+    }
+}
+
+/// Writes expressions like `5 %` (a number times one of the [`PERCENTAGE_UNITS`])
+/// the way they were entered.
+fn percentage_literal(expr: &ast::Expression) -> Option<String> {
+    match expr {
+        ast::Expression::UnitIdentifier(_, prefix, name, full_name)
+            if prefix.is_none() && PERCENTAGE_UNITS.contains(&full_name.as_str()) =>
+        {
+            Some(name.to_string())
+        }
+        ast::Expression::BinaryOperator {
+            op: BinaryOperator::Mul,
+            lhs,
+            rhs,
+            ..
+        } => match lhs.as_ref() {
+            ast::Expression::Scalar(_, n) => {
+                Some(format!("{} {}", n.pretty_print(), percentage_literal(rhs)?))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Whether the expression is a number times a unit, like `5 km`
+fn quantity_literal(expr: &ast::Expression) -> bool {
+    matches!(
+        expr,
+        ast::Expression::BinaryOperator {
+            op: BinaryOperator::Mul,
+            lhs,
+            rhs,
+            ..
+        } if matches!(lhs.as_ref(), ast::Expression::Scalar(..))
+            && bare_unit_identifier(rhs).is_some()
+    )
+}
+
+/// An operand of a binary operator, after it has been checked
+struct Operand<'a> {
+    ast: &'a ast::Expression,
+    /// The full span of `ast`. It is passed along since computing it for the left
+    /// hand side of a long chain like `a + b + c + …` takes time.
+    span: Span,
+    checked: typed_ast::Expression,
+}
+
+/// A function that was registered with the `@operator(…)` decorator
+#[derive(Clone)]
+struct OperatorOverload {
+    op: BinaryOperator,
+    function_name: Symbol,
+    parameter_types: Vec<Type>,
+}
+
+impl OperatorOverload {
+    fn accepts(&self, lhs_type: &Type, rhs_type: &Type) -> bool {
+        fn matches(parameter_type: &Type, argument_type: &Type) -> bool {
+            // Types that are not fully known yet are checked by the call itself
+            let is_open = |t: &Type| t == &Type::Dynamic || !t.type_variables(true).is_empty();
+            if is_open(parameter_type) || is_open(argument_type) {
+                return true;
+            }
+
+            match (parameter_type, argument_type) {
+                (Type::Struct(p), Type::Struct(a)) => p.name == a.name,
+                _ => parameter_type == argument_type,
+            }
+        }
+
+        matches(&self.parameter_types[0], lhs_type) && matches(&self.parameter_types[1], rhs_type)
     }
 }
 
+/// The name of the dimension that is created for a base unit without a specified
+/// dimension, like `Pixel` for `unit pixel`.
+pub(crate) fn implicit_dimension_name(
+    unit_name: Symbol,
+    decorators: &[decorator::Decorator],
+) -> Symbol {
+    use heck::ToUpperCamelCase;
+    decorator::dimension(decorators)
+        .unwrap_or_else(|| Symbol::new(&unit_name.to_upper_camel_case()))
+}
+
+/// A type expression like `Length / Time^2` for a dimension without type variables
+fn type_expression_for(dtype: &DType, span: Span) -> TypeExpression {
+    dtype
+        .factors
+        .iter()
+        .map(|(factor, exponent)| {
+            let base = TypeExpression::TypeIdentifier(span, factor.name());
+            if exponent.is_one() {
+                base
+            } else {
+                TypeExpression::Power(None, Box::new(base), span, *exponent)
+            }
+        })
+        .reduce(|lhs, rhs| TypeExpression::Multiply(span, Box::new(lhs), Box::new(rhs)))
+        .unwrap_or(TypeExpression::Unity(span))
+}
+
+/// A formula from a `formula` statement
+#[derive(Debug, Clone)]
+pub(crate) struct FormulaInfo {
+    pub(crate) definition_span: Span,
+    pub(crate) name: Symbol,
+    /// The span of the equation, from the start of its left hand side to the end
+    /// of its right hand side
+    pub(crate) equation_span: Span,
+    /// The quantities, each of which the formula has been solved for
+    pub(crate) quantities: Vec<Symbol>,
+}
+
+/// The operands of a binary operator after checking `Dynamic` values, see
+/// [`TypeChecker::elaborate_dynamic_operands`]
+enum DynamicOperands {
+    /// The operands, which can be type checked as usual
+    Checked(Box<(typed_ast::Expression, typed_ast::Expression)>),
+    /// The operator expression, if its type is `Dynamic` or does not depend on the
+    /// operands
+    Result(Box<typed_ast::Expression>),
+}
+
 #[derive(Clone, Default)]
 pub struct TypeChecker {
-    structs: HashMap<String, StructInfo>,
+    structs: HashMap<Symbol, StructInfo>,
     registry: DimensionRegistry,
 
     type_namespace: Namespace,
@@ -59,6 +221,92 @@ pub struct TypeChecker {
     env: Environment,
     name_generator: NameGenerator,
     constraints: ConstraintSet,
+
+    /// Number of top-level expression statements so far (`_1`, `_2`, …)
+    num_results: usize,
+
+    operator_overloads: Vec<OperatorOverload>,
+
+    /// Expressions of the statements that are currently being checked
+    arena: ExpressionArena,
+
+    /// Names of foreign functions provided by the embedding application
+    native_functions: HashSet<Symbol>,
+
+    /// Warnings for the statements that have been checked so far
+    warnings: Vec<TypeCheckWarning>,
+
+    /// Units from the `where` clause of a function, which are not in scope
+    /// anymore: the span of their definition and the name of the function
+    local_units_out_of_scope: HashMap<Symbol, (Span, Symbol)>,
+
+    /// Reject `Dynamic` type annotations
+    forbid_dynamic: bool,
+
+    /// Functions defined with `unitfn`, and the names of their inverses
+    unit_functions: HashMap<Symbol, Option<Symbol>>,
+
+    /// Names of the unit systems, which can be used as targets of conversions
+    unit_systems: HashSet<Symbol>,
+
+    /// Names of the methods from `impl` blocks, by struct name
+    methods: HashMap<Symbol, Vec<Symbol>>,
+
+    /// Formulas from `formula` statements, by name
+    formulas: HashMap<Symbol, FormulaInfo>,
+
+    /// Definitions of all functions with a body, which can be differentiated
+    /// with `autodiff`
+    function_definitions: HashMap<Symbol, Arc<FunctionDefinition>>,
+
+    /// Generic functions that use dimension predicates, directly or by calling other
+    /// such functions. Their body is only checked for the argument types of each
+    /// call, see [`TypeChecker::specialize`].
+    generic_functions: HashMap<Symbol, Arc<ast::Statement>>,
+    /// Names of the specializations that have been defined so far, and the name of
+    /// the corresponding generic function
+    specializations: HashMap<Symbol, Symbol>,
+    /// Definitions of the specializations that are needed by the current statement
+    pending_specializations: Vec<typed_ast::Statement>,
+
+    /// Definitions that have been set aside while a module is imported selectively
+    stashed_definitions: Vec<Vec<(Symbol, NameDefinition)>>,
+    /// Definitions of selectively imported modules that are not in scope
+    hidden_definitions: HashMap<ast::ModuleItem, NameDefinition>,
+}
+
+/// Everything the type checker knows about a name, see [`TypeChecker::take_definition`]
+#[derive(Clone, Default)]
+struct NameDefinition {
+    identifier: ShadowedIdentifier,
+    value_namespace_entry: Option<(String, Span)>,
+    type_namespace_entry: Option<(String, Span)>,
+    dimension: Option<RegistryEntry<()>>,
+    struct_info: Option<(StructInfo, Option<Vec<Symbol>>)>,
+    unit_function: Option<Option<Symbol>>,
+    is_unit_system: bool,
+    function_definition: Option<Arc<FunctionDefinition>>,
+}
+
+/// Find the part of `actual` that corresponds to the position of `needle` within
+/// `pattern`. For example, for a pattern `List<T0>` and an actual type `List<Length>`,
+/// the type corresponding to `T0` is `Length`.
+fn corresponding_type(pattern: &Type, actual: &Type, needle: &Type) -> Option<Type> {
+    if pattern.as_single_type_variable().is_some()
+        && pattern.as_single_type_variable() == needle.as_single_type_variable()
+    {
+        return Some(actual.clone());
+    }
+
+    match (pattern, actual) {
+        (Type::List(p), Type::List(a)) => corresponding_type(p, a, needle),
+        (Type::Fn(p_params, p_return), Type::Fn(a_params, a_return)) => p_params
+            .iter()
+            .zip(a_params)
+            .chain(std::iter::once((p_return.as_ref(), a_return.as_ref())))
+            .find_map(|(p, a)| corresponding_type(p, a, needle)),
+        _ => None,
+    }
 }
 
 impl TypeChecker {
@@ -75,6 +323,519 @@ impl TypeChecker {
         self.constraints.add(Constraint::IsDType(type_.clone()))
     }
 
+    /// Add the constraints for the bounds of an instantiated qualified type
+    fn add_bound_constraints(&mut self, bounds: &Bounds) {
+        for bound in bounds.iter() {
+            match bound {
+                Bound::IsDim(t) => self.add_dtype_constraint(t).ok(),
+                Bound::IsOrd(t) => self.constraints.add(Constraint::IsOrd(t.clone())).ok(),
+            }
+        }
+    }
+
+    /// Wrap an expression in a runtime check of its value. The expected type can
+    /// contain type variables, which are resolved like for any other expression.
+    /// A dimension type requires a quantity, even if it is generalized later on.
+    fn dynamic_check(&mut self, expr: typed_ast::Expression, type_: Type) -> typed_ast::Expression {
+        let span = expr.full_span(&self.arena);
+        let is_quantity = matches!(type_, Type::Dimension(_));
+        typed_ast::Expression::DynamicCheck(
+            span,
+            self.arena.alloc(expr),
+            TypeScheme::concrete(type_),
+            is_quantity,
+        )
+    }
+
+    /// A suggested fix for a conversion like `3 m -> 3`, where the right hand side
+    /// is a number instead of a unit
+    fn conversion_to_scalar_hint(
+        &self,
+        lhs: &ast::Expression,
+        rhs: &ast::Expression,
+        lhs_checked: &typed_ast::Expression,
+        lhs_dtype: &DType,
+    ) -> Option<String> {
+        if let (Some(unit), ast::Expression::Scalar(_, n)) = (bare_unit_identifier(lhs), rhs) {
+            return Some(format!(
+                "to write a quantity, put the number in front of the unit: `{} {unit}`",
+                n.pretty_print()
+            ));
+        }
+
+        let lhs_text = lhs_checked.pretty_print(&self.arena);
+        let unit = self
+            .env
+            .find_unit_of_dimension(&lhs_dtype.to_base_representation())?;
+        Some(format!(
+            "convert to a unit instead of a number, like `{lhs_text} -> {unit}`"
+        ))
+    }
+
+    /// Type check a conversion `source -> target`. Depending on the target, this
+    /// converts the value to a unit, a list of units or a unit system, or calls a
+    /// function with it.
+    fn elaborate_conversion(
+        &mut self,
+        source: &ast::Expression,
+        target: &ast::ConversionTarget,
+        span_op: Span,
+        parenthesized_source: bool,
+    ) -> Result<typed_ast::Expression> {
+        let source_checked = self.elaborate_expression(source)?;
+
+        let target = match target {
+            ast::ConversionTarget::Expression(target) => target.as_ref(),
+            ast::ConversionTarget::UnitList(span, units) => {
+                let source_checked = self.check_if_dynamic_quantity(source_checked);
+                let mut units_checked = vec![];
+                for unit in units {
+                    let unit_checked = self.elaborate_expression(unit)?;
+                    let unit_checked = self.check_if_dynamic_quantity(unit_checked);
+                    self.check_conversion_to_unit(
+                        (source, &source_checked),
+                        (unit, &unit_checked),
+                        span_op,
+                        parenthesized_source,
+                    )?;
+                    units_checked.push(unit_checked);
+                }
+
+                let type_ = Type::List(Box::new(source_checked.get_type(&self.arena)));
+                return Ok(typed_ast::Expression::Conversion(
+                    span_op,
+                    self.arena.alloc(source_checked),
+                    typed_ast::ConversionTarget::UnitList(*span, self.alloc_all(units_checked)),
+                    TypeScheme::concrete(type_),
+                ));
+            }
+        };
+
+        let (target_checked, type_) = match target {
+            // `2 mm -> awg` calls the inverse of the unit function `awg`
+            ast::Expression::Identifier(span, name) if self.unit_functions.contains_key(name) => {
+                let Some(inverse) = self.unit_functions[name] else {
+                    return Err(TypeCheckError::UnitFunctionWithoutInverse(
+                        *span,
+                        name.to_string(),
+                    ));
+                };
+                let inverse_checked =
+                    self.elaborate_expression(&ast::Expression::Identifier(*span, inverse))?;
+                let (source_checked, type_) =
+                    self.check_conversion_to_function(source, source_checked, &inverse_checked)?;
+                return Ok(typed_ast::Expression::Conversion(
+                    span_op,
+                    self.arena.alloc(source_checked),
+                    typed_ast::ConversionTarget::UnitFunction(
+                        *span,
+                        *name,
+                        self.arena.alloc(inverse_checked),
+                    ),
+                    TypeScheme::concrete(type_),
+                ));
+            }
+            ast::Expression::Identifier(span, name) if self.unit_systems.contains(name) => {
+                let source_checked = self.check_if_dynamic_quantity(source_checked);
+                let type_ = source_checked.get_type(&self.arena);
+                if self.add_dtype_constraint(&type_).is_trivially_violated() {
+                    return Err(TypeCheckError::IncompatibleTypesInConversion(
+                        span_op,
+                        type_,
+                        source.full_span(),
+                        format!("unit system {name}"),
+                        *span,
+                        self.conversion_precedence_note(
+                            &source_checked,
+                            None,
+                            parenthesized_source,
+                        ),
+                    ));
+                }
+                return Ok(typed_ast::Expression::Conversion(
+                    span_op,
+                    self.arena.alloc(source_checked),
+                    typed_ast::ConversionTarget::UnitSystem(*span, *name),
+                    TypeScheme::concrete(type_),
+                ));
+            }
+            _ => {
+                let target_checked = self.elaborate_expression(target)?;
+                if target_checked.get_type(&self.arena).is_fn_type() {
+                    let (source_checked, type_) =
+                        self.check_conversion_to_function(source, source_checked, &target_checked)?;
+                    return Ok(typed_ast::Expression::Conversion(
+                        span_op,
+                        self.arena.alloc(source_checked),
+                        typed_ast::ConversionTarget::Function(self.arena.alloc(target_checked)),
+                        TypeScheme::concrete(type_),
+                    ));
+                }
+
+                let source_checked = self.check_if_dynamic_quantity(source_checked);
+                let target_checked = self.check_if_dynamic_quantity(target_checked);
+                let type_ = self.check_conversion_to_unit(
+                    (source, &source_checked),
+                    (target, &target_checked),
+                    span_op,
+                    parenthesized_source,
+                )?;
+                ((source_checked, target_checked), type_)
+            }
+        };
+        let (source_checked, target_checked) = target_checked;
+
+        if bare_unit_identifier(source).is_some() && quantity_literal(target) {
+            self.warnings.push(TypeCheckWarning::ReversedConversion(
+                target.full_span(),
+                source.full_span().extend(&target.full_span()),
+                format!(
+                    "{} -> {}",
+                    target_checked.pretty_print(&self.arena),
+                    source_checked.pretty_print(&self.arena)
+                ),
+            ));
+        }
+
+        Ok(typed_ast::Expression::Conversion(
+            span_op,
+            self.arena.alloc(source_checked),
+            typed_ast::ConversionTarget::Unit(self.arena.alloc(target_checked)),
+            TypeScheme::concrete(type_),
+        ))
+    }
+
+    /// Check that the source of a conversion can be converted to the unit of the
+    /// target, i.e. that both are quantities of the same dimension. Returns the
+    /// type of the result.
+    fn check_conversion_to_unit(
+        &mut self,
+        (source, source_checked): (&ast::Expression, &typed_ast::Expression),
+        (target, target_checked): (&ast::Expression, &typed_ast::Expression),
+        span_op: Span,
+        parenthesized_source: bool,
+    ) -> Result<Type> {
+        let source_type = source_checked.get_type(&self.arena);
+        let target_type = target_checked.get_type(&self.arena);
+
+        if self
+            .add_dtype_constraint(&source_type)
+            .is_trivially_violated()
+            || self
+                .add_dtype_constraint(&target_type)
+                .is_trivially_violated()
+        {
+            return Err(TypeCheckError::IncompatibleTypesInConversion(
+                span_op,
+                source_type,
+                source.full_span(),
+                target_type.to_string(),
+                target.full_span(),
+                self.conversion_precedence_note(
+                    source_checked,
+                    Some(target_checked),
+                    parenthesized_source,
+                ),
+            ));
+        }
+
+        if self
+            .add_equal_constraint(&source_type, &target_type)
+            .is_trivially_violated()
+        {
+            let source_dtype = dtype(&self.arena, source_checked)?;
+            let target_dtype = dtype(&self.arena, target_checked)?;
+            return Err(TypeCheckError::IncompatibleDimensions(
+                IncompatibleDimensionsError {
+                    span_operation: span_op,
+                    operation: "unit conversion".into(),
+                    span_expected: source.full_span(),
+                    expected_name: " left hand side",
+                    expected_dimensions: self
+                        .registry
+                        .get_derived_entry_names_for(&source_dtype.to_base_representation()),
+                    expected_type: source_dtype.to_base_representation(),
+                    span_actual: target.full_span(),
+                    actual_name: "right hand side",
+                    actual_name_for_fix: "expression on the right hand side",
+                    actual_dimensions: self
+                        .registry
+                        .get_derived_entry_names_for(&target_dtype.to_base_representation()),
+                    actual_type: target_dtype.to_base_representation(),
+                    hint: if target_dtype.is_scalar() && !source_dtype.is_scalar() {
+                        self.conversion_to_scalar_hint(
+                            source,
+                            target,
+                            source_checked,
+                            &source_dtype,
+                        )
+                    } else {
+                        None
+                    },
+                    note: self.conversion_precedence_note(
+                        source_checked,
+                        Some(target_checked),
+                        parenthesized_source,
+                    ),
+                },
+            ));
+        }
+
+        Ok(source_type)
+    }
+
+    /// Check a conversion `source -> f` to a function of one argument, which is
+    /// called with the source. Returns the (checked) source and the type of the
+    /// result.
+    fn check_conversion_to_function(
+        &mut self,
+        source: &ast::Expression,
+        source_checked: typed_ast::Expression,
+        function_checked: &typed_ast::Expression,
+    ) -> Result<(typed_ast::Expression, Type)> {
+        let source_checked = self.check_if_dynamic(source_checked);
+        let source_type = source_checked.get_type(&self.arena);
+
+        let Type::Fn(parameter_types, return_type) = function_checked.get_type(&self.arena) else {
+            unreachable!("Conversion functions have function types")
+        };
+        if parameter_types.len() != 1 {
+            return Err(TypeCheckError::WrongArity {
+                callable_span: function_checked.full_span(&self.arena),
+                callable_name: "function".into(),
+                callable_definition_span: None,
+                arity: 1..=1,
+                num_args: parameter_types.len(),
+            });
+        }
+
+        if self
+            .add_equal_constraint(&source_type, &parameter_types[0])
+            .is_trivially_violated()
+        {
+            return Err(TypeCheckError::IncompatibleTypesInFunctionCall(
+                None,
+                parameter_types[0].clone(),
+                source.full_span(),
+                source_type,
+            ));
+        }
+
+        Ok((source_checked, *return_type))
+    }
+
+    /// A note for a failed conversion like `x + y -> unit`, which converts the
+    /// whole sum, since `->` has the lowest precedence. If the right hand side of
+    /// the operator alone has the dimension of the target (unlike the whole
+    /// expression), it probably was meant to be converted on its own, and
+    /// parentheses are suggested.
+    fn conversion_precedence_note(
+        &self,
+        source_checked: &typed_ast::Expression,
+        target_checked: Option<&typed_ast::Expression>,
+        parenthesized_source: bool,
+    ) -> Option<String> {
+        if parenthesized_source {
+            return None;
+        }
+
+        let (op, lhs, rhs) = match source_checked {
+            typed_ast::Expression::BinaryOperator(_, op, lhs, rhs, _)
+            | typed_ast::Expression::BinaryOperatorForDate(_, op, lhs, rhs, _) => (*op, *lhs, *rhs),
+            typed_ast::Expression::ChainedComparison(comparisons) => {
+                match &self.arena[*comparisons.last()?] {
+                    typed_ast::Expression::BinaryOperator(_, op, lhs, rhs, _) => (*op, *lhs, *rhs),
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        let description = match op {
+            BinaryOperator::Add => "sum",
+            BinaryOperator::Sub => "difference",
+            BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr => "logical expression",
+            op if op.is_comparison() => "comparison",
+            _ => return None,
+        };
+
+        let op_text = op.pretty_print().to_string();
+        let mut note = format!(
+            "`->` has a lower precedence than `{}`, so the whole {description} `{}` is converted.",
+            op_text.trim(),
+            source_checked.pretty_print(&self.arena)
+        );
+
+        let target_checked = target_checked?;
+        let dimension = |expr: &typed_ast::Expression| {
+            dtype(&self.arena, expr)
+                .ok()
+                .map(|dtype| dtype.to_base_representation())
+        };
+        let rhs_dimension = dimension(&self.arena[rhs]);
+        if rhs_dimension.is_some()
+            && rhs_dimension == dimension(target_checked)
+            && dimension(source_checked) != rhs_dimension
+        {
+            let lhs_text = match source_checked {
+                // Only the last comparison of a chain is shown in parentheses
+                typed_ast::Expression::ChainedComparison(comparisons) => comparisons
+                    .iter()
+                    .map(|comparison| match &self.arena[*comparison] {
+                        typed_ast::Expression::BinaryOperator(_, _, lhs, _, _) => {
+                            self.arena[*lhs].pretty_print(&self.arena)
+                        }
+                        _ => unreachable!("Chains consist of binary comparisons"),
+                    })
+                    .zip(
+                        comparisons
+                            .iter()
+                            .map(|comparison| match &self.arena[*comparison] {
+                                typed_ast::Expression::BinaryOperator(_, op, _, _, _) => {
+                                    op.pretty_print()
+                                }
+                                _ => unreachable!("Chains consist of binary comparisons"),
+                            }),
+                    )
+                    .map(|(operand, op)| operand + op)
+                    .sum::<crate::markup::Markup>()
+                    .to_string(),
+                _ => format!("{}{op_text}", self.arena[lhs].pretty_print(&self.arena)),
+            };
+            note += &format!(
+                " Use parentheses to only convert the right hand side: `{lhs_text}({} -> {})`",
+                self.arena[rhs].pretty_print(&self.arena),
+                target_checked.pretty_print(&self.arena)
+            );
+        }
+        Some(note)
+    }
+
+    /// If the expression is of type `Dynamic`, check at runtime that it is a value
+    /// of the (inferred) type at the place where it is used.
+    fn check_if_dynamic(&mut self, expr: typed_ast::Expression) -> typed_ast::Expression {
+        if expr.get_type(&self.arena) == Type::Dynamic {
+            let type_ = self.fresh_type_variable();
+            self.dynamic_check(expr, type_)
+        } else {
+            expr
+        }
+    }
+
+    /// Like [`Self::check_if_dynamic`], but the value also needs to be a quantity
+    /// if its dimension can not be inferred.
+    fn check_if_dynamic_quantity(&mut self, expr: typed_ast::Expression) -> typed_ast::Expression {
+        if expr.get_type(&self.arena) == Type::Dynamic {
+            let dtype = DType::from_type_variable(self.name_generator.fresh_type_variable());
+            self.dynamic_check(expr, Type::Dimension(dtype))
+        } else {
+            expr
+        }
+    }
+
+    /// Operands of type `Dynamic` are checked at runtime. If the type of the result
+    /// can not be inferred (like for a product with a `Dynamic` factor), it is
+    /// `Dynamic` as well.
+    fn elaborate_dynamic_operands(
+        &mut self,
+        span_op: Option<Span>,
+        op: BinaryOperator,
+        lhs: typed_ast::Expression,
+        rhs: typed_ast::Expression,
+    ) -> Result<DynamicOperands> {
+        let lhs_type = lhs.get_type(&self.arena);
+        let rhs_type = rhs.get_type(&self.arena);
+        let lhs_is_dynamic = lhs_type == Type::Dynamic;
+        let rhs_is_dynamic = rhs_type == Type::Dynamic;
+
+        let is_ordering = matches!(
+            op,
+            BinaryOperator::LessThan
+                | BinaryOperator::GreaterThan
+                | BinaryOperator::LessOrEqual
+                | BinaryOperator::GreaterOrEqual
+        );
+        let has_dynamic_result = match op {
+            BinaryOperator::Mul | BinaryOperator::Div => true,
+            BinaryOperator::Power => lhs_is_dynamic,
+            BinaryOperator::Add | BinaryOperator::Sub => lhs_is_dynamic && rhs_is_dynamic,
+            _ => is_ordering && lhs_is_dynamic && rhs_is_dynamic,
+        };
+
+        let (lhs, rhs) = match op {
+            _ if !lhs_is_dynamic && !rhs_is_dynamic => {
+                return Ok(DynamicOperands::Checked(Box::new((lhs, rhs))))
+            }
+            BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr => {
+                let mut check_bool = |expr: typed_ast::Expression| {
+                    if expr.get_type(&self.arena) == Type::Dynamic {
+                        self.dynamic_check(expr, Type::Boolean)
+                    } else {
+                        expr
+                    }
+                };
+                (check_bool(lhs), check_bool(rhs))
+            }
+            // Values of any type can be compared for equality
+            BinaryOperator::Equal | BinaryOperator::NotEqual
+                if lhs_is_dynamic && rhs_is_dynamic =>
+            {
+                return Ok(DynamicOperands::Result(Box::new(
+                    typed_ast::Expression::BinaryOperator(
+                        span_op,
+                        op,
+                        self.arena.alloc(lhs),
+                        self.arena.alloc(rhs),
+                        TypeScheme::concrete(Type::Boolean),
+                    ),
+                )));
+            }
+            BinaryOperator::Equal | BinaryOperator::NotEqual => {
+                (self.check_if_dynamic(lhs), self.check_if_dynamic(rhs))
+            }
+            _ => (
+                self.check_if_dynamic_quantity(lhs),
+                self.check_if_dynamic_quantity(rhs),
+            ),
+        };
+
+        if !has_dynamic_result {
+            return Ok(DynamicOperands::Checked(Box::new((lhs, rhs))));
+        }
+
+        if !lhs_is_dynamic {
+            self.enforce_dtype(&lhs_type, lhs.full_span(&self.arena))?;
+        }
+        if op == BinaryOperator::Power {
+            let rhs_type = rhs.get_type(&self.arena);
+            if self
+                .add_equal_constraint(&rhs_type, &Type::scalar())
+                .is_trivially_violated()
+            {
+                return Err(TypeCheckError::NonScalarExponent(
+                    rhs.full_span(&self.arena),
+                    rhs_type,
+                ));
+            }
+        } else if !rhs_is_dynamic {
+            self.enforce_dtype(&rhs_type, rhs.full_span(&self.arena))?;
+        }
+
+        let type_ = if is_ordering {
+            Type::Boolean
+        } else {
+            Type::Dynamic
+        };
+        Ok(DynamicOperands::Result(Box::new(
+            typed_ast::Expression::BinaryOperator(
+                span_op,
+                op,
+                self.arena.alloc(lhs),
+                self.arena.alloc(rhs),
+                TypeScheme::concrete(type_),
+            ),
+        )))
+    }
+
     fn enforce_dtype(&mut self, type_: &Type, span: Span) -> Result<()> {
         if self
             .constraints
@@ -87,6 +848,14 @@ impl TypeChecker {
         Ok(())
     }
 
+    /// Whether the given type is `Angle`. This is only distinguishable from
+    /// `Scalar` if angles are a base dimension (see `core::strict_angle`).
+    fn is_angle(&self, dtype: &DType) -> bool {
+        self.registry
+            .get_base_representation_for_name(Symbol::new("Angle"))
+            .is_ok_and(|angle| dtype.to_base_representation() == angle)
+    }
+
     fn type_from_annotation(&self, annotation: &TypeAnnotation) -> Result<Type> {
         match annotation {
             TypeAnnotation::TypeExpression(dexpr) => {
@@ -115,7 +884,7 @@ impl TypeChecker {
                                 .iter()
                                 .any(|(_, name, _)| name == n) =>
                         {
-                            DTypeFactor::TPar(n.clone())
+                            DTypeFactor::TPar(*n)
                         }
                         ref f => f.deref().clone(),
                     }
@@ -126,6 +895,13 @@ impl TypeChecker {
             TypeAnnotation::Bool(_) => Ok(Type::Boolean),
             TypeAnnotation::String(_) => Ok(Type::String),
             TypeAnnotation::DateTime(_) => Ok(Type::DateTime),
+            TypeAnnotation::Dynamic(span) => {
+                if self.forbid_dynamic {
+                    Err(TypeCheckError::DynamicTypeForbidden(*span))
+                } else {
+                    Ok(Type::Dynamic)
+                }
+            }
             TypeAnnotation::Fn(_, param_types, return_type) => Ok(Type::Fn(
                 param_types
                     .iter()
@@ -133,14 +909,30 @@ impl TypeChecker {
                     .collect::<Result<Vec<_>>>()?,
                 Box::new(self.type_from_annotation(return_type)?),
             )),
-            TypeAnnotation::List(_, element_type) => Ok(Type::List(Box::new(
-                self.type_from_annotation(element_type)?,
-            ))),
+            TypeAnnotation::List(_, element_type) | TypeAnnotation::Variadic(_, element_type) => {
+                Ok(Type::List(Box::new(
+                    self.type_from_annotation(element_type)?,
+                )))
+            }
         }
     }
 
-    fn identifier_type(&self, span: Span, name: &str) -> Result<TypeScheme> {
+    fn identifier_type(&self, span: Span, name: Symbol) -> Result<TypeScheme> {
         self.env.get_identifier_type(name).ok_or_else(|| {
+            if let Some((definition_span, function_name)) = self.local_units_out_of_scope.get(&name)
+            {
+                return TypeCheckError::LocalUnitOutOfScope(
+                    span,
+                    name.to_string(),
+                    *definition_span,
+                    function_name.to_string(),
+                );
+            }
+
+            if let Some(feature) = features::missing_feature_for_identifier(name.as_str()) {
+                return TypeCheckError::FeatureNotAvailable(span, name.to_string(), feature);
+            }
+
             let suggestion = suggestion::did_you_mean(
                 self.env
                     .iter_identifiers()
@@ -149,55 +941,93 @@ impl TypeChecker {
                     .chain(ffi::procedures().values().map(|p| p.name.clone())),
                 name,
             );
-            TypeCheckError::UnknownIdentifier(span, name.into(), suggestion)
+            TypeCheckError::UnknownIdentifier(span, name.to_string(), suggestion)
         })
     }
 
     fn get_proper_function_reference(
         &self,
         expr: &ast::Expression,
-    ) -> Option<(String, &FunctionSignature)> {
+    ) -> Option<(Symbol, &FunctionSignature)> {
         match expr {
             ast::Expression::Identifier(_, name) => self
                 .env
-                .get_function_info(name)
-                .map(|(signature, _)| (name.clone(), signature)),
+                .get_function_info(*name)
+                .map(|(signature, _)| (*name, signature)),
             _ => None,
         }
     }
 
+    /// Emits a warning if the identifier has been marked with `@deprecated`
+    fn check_deprecation(&mut self, span: Span, name: Symbol) {
+        if let Some((definition_span, message)) = self.env.get_deprecation(name) {
+            self.warnings.push(TypeCheckWarning::DeprecatedIdentifier(
+                span,
+                name.to_string(),
+                message.clone(),
+                *definition_span,
+            ));
+        }
+    }
+
+    /// This needs to be called after the definition has been checked, such that
+    /// the definition itself (e.g. a recursive function) does not lead to warnings.
+    fn mark_deprecated(
+        &mut self,
+        names: impl IntoIterator<Item = Symbol>,
+        definition_span: Span,
+        decorators: &[decorator::Decorator],
+    ) {
+        if let Some(message) = decorator::deprecated(decorators) {
+            for name in names {
+                self.env
+                    .mark_deprecated(name, definition_span, message.clone());
+            }
+        }
+    }
+
     fn proper_function_call(
         &mut self,
         span: &Span,
         full_span: &Span,
-        function_name: &str,
+        function_name: Symbol,
         signature: &FunctionSignature,
-        arguments: Vec<typed_ast::Expression>,
-        argument_types: Vec<Type>,
+        mut arguments: Vec<typed_ast::Expression>,
+        syntax: CallSyntax,
     ) -> Result<typed_ast::Expression> {
+        self.check_deprecation(*span, function_name);
+
         let FunctionSignature {
             name: _,
             definition_span,
             type_parameters: _,
             parameters,
+            num_default_parameters: _,
             return_type_annotation: _,
             fn_type,
         } = signature;
 
-        let fn_type = match fn_type {
+        let (fn_type, ord_bounds) = match fn_type {
             TypeScheme::Concrete(t) => {
                 // This branch is needed for recursive functions, where the type of the function
                 // is not yet known (and not yet quantified).
-                t.clone()
+                (t.clone(), vec![])
             }
             TypeScheme::Quantified(_, _) => {
                 let qt = fn_type.instantiate(&mut self.name_generator);
 
-                for Bound::IsDim(t) in qt.bounds.iter() {
-                    self.add_dtype_constraint(t).ok();
-                }
+                self.add_bound_constraints(&qt.bounds);
+
+                let ord_bounds = qt
+                    .bounds
+                    .iter()
+                    .filter_map(|b| match b {
+                        Bound::IsOrd(t) => Some(t.clone()),
+                        _ => None,
+                    })
+                    .collect_vec();
 
-                qt.inner
+                (qt.inner, ord_bounds)
             }
         };
 
@@ -205,7 +1035,7 @@ impl TypeChecker {
             unreachable!("Expected function type, got {:#?}", fn_type);
         };
 
-        let arity_range = parameters.len()..=parameters.len();
+        let arity_range = signature.arity();
 
         if !arity_range.contains(&arguments.len()) {
             return Err(TypeCheckError::WrongArity {
@@ -217,11 +1047,69 @@ impl TypeChecker {
             });
         }
 
-        for (idx, ((parameter_span, parameter_type), argument_type)) in parameters
-            .iter()
-            .map(|p| p.0)
+        // Parameters that have not been specified get their default value
+        for parameter_type in parameter_types.iter().skip(arguments.len()) {
+            arguments.push(typed_ast::Expression::DefaultArgument(
+                *span,
+                TypeScheme::concrete(parameter_type.clone()),
+            ));
+        }
+
+        // All arguments for a variadic parameter need to have its element type.
+        // They are only collected into a list when the call is compiled.
+        let (parameter_spans, parameter_types) = if signature.is_variadic() {
+            let (variadic_span, ..) = parameters.last().unwrap();
+            let Some(Type::List(element_type)) = parameter_types.last() else {
+                unreachable!("Variadic parameters have a list type")
+            };
+            let num_variadic = arguments.len() - (parameters.len() - 1);
+
+            (
+                parameters[..parameters.len() - 1]
+                    .iter()
+                    .map(|p| p.0)
+                    .chain(std::iter::repeat(*variadic_span).take(num_variadic))
+                    .collect::<Vec<_>>(),
+                parameter_types[..parameter_types.len() - 1]
+                    .iter()
+                    .cloned()
+                    .chain(std::iter::repeat(*element_type.clone()).take(num_variadic))
+                    .collect::<Vec<_>>(),
+            )
+        } else {
+            (parameters.iter().map(|p| p.0).collect(), parameter_types)
+        };
+
+        // `Dynamic` arguments are checked at runtime. If the parameter type is generic,
+        // the return type can not be inferred from the argument.
+        let mut has_dynamic_result = false;
+        let arguments = arguments
+            .into_iter()
+            .zip(&parameter_types)
+            .map(|(argument, parameter_type)| {
+                if argument.get_type(&self.arena) != Type::Dynamic
+                    || parameter_type == &Type::Dynamic
+                {
+                    return argument;
+                }
+
+                has_dynamic_result |= parameter_type
+                    .type_variables(false)
+                    .iter()
+                    .any(|tv| return_type.contains(tv, false));
+                self.dynamic_check(argument, parameter_type.clone())
+            })
+            .collect::<Vec<_>>();
+
+        for (idx, ((parameter_span, parameter_type), argument_type)) in parameter_spans
+            .into_iter()
             .zip(parameter_types.iter())
-            .zip(argument_types)
+            .zip(
+                arguments
+                    .iter()
+                    .map(|a| a.get_type(&self.arena))
+                    .collect::<Vec<_>>(),
+            )
             .enumerate()
         {
             if self
@@ -244,13 +1132,30 @@ impl TypeChecker {
                                     &parameter_dtype.to_base_representation(),
                                 ),
                                 expected_type: parameter_dtype.to_base_representation(),
-                                span_actual: arguments[idx].full_span(),
+                                span_actual: arguments[idx].full_span(&self.arena),
                                 actual_name: " argument type",
                                 actual_name_for_fix: "function argument",
                                 actual_dimensions: self.registry.get_derived_entry_names_for(
                                     &argument_dtype.to_base_representation(),
                                 ),
                                 actual_type: argument_dtype.to_base_representation(),
+                                hint: if parameter_dtype.is_scalar() {
+                                    Some(format!(
+                                        "divide the function argument by a reference quantity of dimension `{dimension}`, e.g. `{function_name}(x / x0)`",
+                                        dimension = argument_dtype.to_base_representation(),
+                                    ))
+                                } else if argument_dtype.is_scalar()
+                                    && self.is_angle(parameter_dtype)
+                                {
+                                    Some(format!(
+                                        "specify the unit of the angle, e.g. `{function_name}(x rad)` or `{function_name}(x deg)`"
+                                    ))
+                                } else {
+                                    None
+                                },
+                                note: (function_name == "fract").then(|| {
+                                    "The fractional part of a quantity depends on the unit it is given in (1.5 ft = 45.72 cm), so 'fract' only accepts scalars.".into()
+                                }),
                             },
                         ));
                     }
@@ -258,7 +1163,7 @@ impl TypeChecker {
                         return Err(TypeCheckError::IncompatibleTypesInFunctionCall(
                             Some(parameter_span),
                             parameter_type.clone(),
-                            arguments[idx].full_span(),
+                            arguments[idx].full_span(&self.arena),
                             argument_type.clone(),
                         ));
                     }
@@ -266,15 +1171,1273 @@ impl TypeChecker {
             }
         }
 
-        Ok(typed_ast::Expression::FunctionCall(
+        // Report arguments that can not be ordered right here, instead of later
+        // on as an unsolvable `Ord` constraint
+        for bound_type in &ord_bounds {
+            for (parameter_type, argument) in parameter_types.iter().zip(&arguments) {
+                if let Some(type_) =
+                    corresponding_type(parameter_type, &argument.get_type(&self.arena), bound_type)
+                {
+                    if type_.type_variables(true).is_empty() && !type_.is_orderable() {
+                        return Err(TypeCheckError::NotOrderable(
+                            argument.full_span(&self.arena),
+                            type_,
+                            function_name.into(),
+                        ));
+                    }
+                }
+            }
+        }
+
+        if ffi::TIME_SERIES_FUNCTIONS.contains(&function_name.as_str()) {
+            self.add_time_series_constraints(
+                function_name,
+                &arguments[0],
+                &parameter_types,
+                &return_type,
+            )?;
+        }
+
+        let return_type = if has_dynamic_result {
+            Type::Dynamic
+        } else {
+            return_type.as_ref().clone()
+        };
+
+        Ok(typed_ast::Expression::FunctionCall(
             *span,
             *full_span,
-            function_name.into(),
-            arguments,
-            TypeScheme::concrete(return_type.as_ref().clone()),
+            function_name,
+            self.alloc_all(arguments),
+            syntax,
+            TypeScheme::concrete(return_type),
+        ))
+    }
+
+    /// The first argument of the time series functions (see [`ffi::TIME_SERIES_FUNCTIONS`])
+    /// is a list of structs with the fields `time: DateTime` and `value: D`, which is
+    /// checked here. The other parameters and the return type depend on `D`.
+    fn add_time_series_constraints(
+        &mut self,
+        function_name: Symbol,
+        series: &typed_ast::Expression,
+        parameter_types: &[Type],
+        return_type: &Type,
+    ) -> Result<()> {
+        let Type::List(element_type) = &parameter_types[0] else {
+            unreachable!("Time series are lists");
+        };
+
+        let value_type = match series.get_type(&self.arena) {
+            Type::List(argument_element_type) if argument_element_type.is_closed() => {
+                let fields = match argument_element_type.as_ref() {
+                    Type::Struct(info) => info
+                        .fields
+                        .get(&Symbol::new("time"))
+                        .zip(info.fields.get(&Symbol::new("value"))),
+                    _ => None,
+                };
+                match fields {
+                    Some(((_, Type::DateTime), (_, value_type @ Type::Dimension(_)))) => {
+                        value_type.clone()
+                    }
+                    _ => {
+                        return Err(TypeCheckError::NotATimeSeries(
+                            series.full_span(&self.arena),
+                            *argument_element_type,
+                            function_name.to_string(),
+                        ))
+                    }
+                }
+            }
+            _ => {
+                // The fields are checked once the type of the elements is known
+                let value_type = self.fresh_type_variable();
+                self.add_dtype_constraint(&value_type).ok();
+                self.constraints
+                    .add(Constraint::HasField(
+                        element_type.as_ref().clone(),
+                        "time".into(),
+                        Type::DateTime,
+                    ))
+                    .ok();
+                self.constraints
+                    .add(Constraint::HasField(
+                        element_type.as_ref().clone(),
+                        "value".into(),
+                        value_type.clone(),
+                    ))
+                    .ok();
+                value_type
+            }
+        };
+
+        let (declared_type, time_series_type) = match function_name.as_str() {
+            "diff_series" => {
+                let step_type = Type::Struct(ffi::series_step_info(value_type));
+                (return_type, Type::List(Box::new(step_type)))
+            }
+            "resample" => {
+                let aggregate_type = Type::Fn(
+                    vec![Type::List(Box::new(value_type.clone()))],
+                    Box::new(value_type),
+                );
+                (&parameter_types[2], aggregate_type)
+            }
+            "integrate_series" => {
+                let value_dtype = match value_type {
+                    Type::Dimension(dtype) => dtype,
+                    Type::TVar(tv) => DType::from_type_variable(tv),
+                    _ => unreachable!("Values of time series are quantities"),
+                };
+                let integral_dtype = value_dtype.multiply(&DType::base_dimension("Time"));
+                (return_type, Type::Dimension(integral_dtype))
+            }
+            _ => unreachable!("Unknown time series function '{function_name}'"),
+        };
+        self.add_equal_constraint(declared_type, &time_series_type)
+            .ok();
+
+        Ok(())
+    }
+
+    /// Moves named arguments to the position of the corresponding parameter.
+    /// Parameters in between that have not been specified are filled with
+    /// placeholders for their default value.
+    fn resolve_named_arguments(
+        &mut self,
+        span: &Span,
+        function_name: Symbol,
+        signature: &FunctionSignature,
+        arguments: Vec<typed_ast::Expression>,
+        named_arguments: Vec<(Span, Symbol, typed_ast::Expression)>,
+    ) -> Result<Vec<typed_ast::Expression>> {
+        let parameters = &signature.parameters;
+
+        if arguments.len() > parameters.len() {
+            return Err(TypeCheckError::WrongArity {
+                callable_span: *span,
+                callable_name: function_name.to_string(),
+                callable_definition_span: Some(signature.definition_span),
+                arity: (parameters.len() - signature.num_default_parameters)..=parameters.len(),
+                num_args: arguments.len() + named_arguments.len(),
+            });
+        }
+
+        let mut resolved = arguments.into_iter().map(Some).collect::<Vec<_>>();
+        resolved.resize(parameters.len(), None);
+
+        for (name_span, name, argument) in named_arguments {
+            let Some(idx) = parameters.iter().position(|(_, p, _)| *p == name) else {
+                let suggestion =
+                    suggestion::did_you_mean(parameters.iter().map(|(_, p, _)| p.as_str()), name);
+                return Err(TypeCheckError::UnknownNamedArgument(
+                    name_span,
+                    signature.definition_span,
+                    function_name.to_string(),
+                    name.to_string(),
+                    suggestion,
+                ));
+            };
+
+            if let Some(previous) = &resolved[idx] {
+                return Err(TypeCheckError::DuplicateArgument(
+                    name_span,
+                    previous.full_span(&self.arena),
+                    name.to_string(),
+                ));
+            }
+
+            resolved[idx] = Some(typed_ast::Expression::NamedArgument(
+                name_span,
+                name,
+                self.arena.alloc(argument),
+            ));
+        }
+
+        let num_required_parameters = parameters.len() - signature.num_default_parameters;
+        if let Some(idx) = resolved[..num_required_parameters]
+            .iter()
+            .position(Option::is_none)
+        {
+            return Err(TypeCheckError::MissingArgument(
+                *span,
+                parameters[idx].0,
+                function_name.to_string(),
+                parameters[idx].1.to_string(),
+            ));
+        }
+
+        Ok(resolved
+            .into_iter()
+            .map(|argument| match argument {
+                Some(argument) => argument,
+                None => typed_ast::Expression::DefaultArgument(
+                    *span,
+                    TypeScheme::concrete(self.fresh_type_variable()),
+                ),
+            })
+            .collect())
+    }
+
+    /// `partial(f, args…)`: the arguments are bound to the leading parameters of `f`
+    /// (or to the named ones), the result is a function of the remaining parameters.
+    fn partial_application(
+        &mut self,
+        span: &Span,
+        function: &ast::Expression,
+        args: &[ast::Expression],
+        named_args: &[ast::NamedArgument],
+    ) -> Result<typed_ast::Expression> {
+        let function_checked = self.elaborate_expression(function)?;
+        let (parameter_types, return_type) = match function_checked.get_type(&self.arena) {
+            Type::Fn(parameter_types, return_type) => (parameter_types, return_type),
+            Type::TVar(_) => {
+                return Err(TypeCheckError::PartialApplicationOfUnknownType(
+                    function.full_span(),
+                ))
+            }
+            _ => {
+                return Err(TypeCheckError::OnlyFunctionsAndReferencesCanBeCalled(
+                    function.full_span(),
+                ))
+            }
+        };
+
+        let mut bound: Vec<Option<typed_ast::Expression>> = vec![];
+        for arg in args {
+            bound.push(Some(self.elaborate_expression(arg)?));
+        }
+        if !named_args.is_empty() {
+            let Some((function_name, signature)) = self.get_proper_function_reference(function)
+            else {
+                return Err(TypeCheckError::NamedArgumentsInCallableCall(
+                    named_args[0].0,
+                ));
+            };
+            let signature = signature.clone();
+            bound.resize_with(bound.len().max(signature.parameters.len()), || None);
+
+            for (name_span, name, arg) in named_args {
+                let Some(idx) = signature.parameters.iter().position(|(_, p, _)| p == name) else {
+                    let suggestion = suggestion::did_you_mean(
+                        signature.parameters.iter().map(|(_, p, _)| p.as_str()),
+                        *name,
+                    );
+                    return Err(TypeCheckError::UnknownNamedArgument(
+                        *name_span,
+                        signature.definition_span,
+                        function_name.to_string(),
+                        name.to_string(),
+                        suggestion,
+                    ));
+                };
+                let arg_checked = self.elaborate_expression(arg)?;
+                if let Some(previous) = &bound[idx] {
+                    return Err(TypeCheckError::DuplicateArgument(
+                        *name_span,
+                        previous.full_span(&self.arena),
+                        name.to_string(),
+                    ));
+                }
+                bound[idx] = Some(typed_ast::Expression::NamedArgument(
+                    *name_span,
+                    *name,
+                    self.arena.alloc(arg_checked),
+                ));
+            }
+            while bound.last().is_some_and(Option::is_none) {
+                bound.pop();
+            }
+        }
+
+        if bound.len() > parameter_types.len() {
+            return Err(TypeCheckError::TooManyArgumentsInPartialApplication {
+                span: args[parameter_types.len()].full_span(),
+                function_span: function.full_span(),
+                num_parameters: parameter_types.len(),
+                num_args: bound.len(),
+            });
+        }
+
+        let mut arguments = vec![];
+        let mut remaining_parameter_types = vec![];
+        for (idx, parameter_type) in parameter_types.iter().enumerate() {
+            match bound.get_mut(idx).and_then(Option::take) {
+                Some(argument) => {
+                    let argument_type = argument.get_type(&self.arena);
+                    if self
+                        .add_equal_constraint(&argument_type, parameter_type)
+                        .is_trivially_violated()
+                    {
+                        return Err(TypeCheckError::IncompatibleTypesInFunctionCall(
+                            Some(function.full_span()),
+                            parameter_type.clone(),
+                            argument.full_span(&self.arena),
+                            argument_type,
+                        ));
+                    }
+                    arguments.push(argument);
+                }
+                None => {
+                    remaining_parameter_types.push(parameter_type.clone());
+                    if idx < bound.len() {
+                        arguments.push(typed_ast::Expression::DefaultArgument(
+                            *span,
+                            TypeScheme::concrete(parameter_type.clone()),
+                        ));
+                    }
+                }
+            }
+        }
+
+        Ok(typed_ast::Expression::Partial(
+            *span,
+            self.arena.alloc(function_checked),
+            self.alloc_all(arguments),
+            TypeScheme::concrete(Type::Fn(remaining_parameter_types, return_type)),
+        ))
+    }
+
+    /// `autodiff(f)`: defines the derivative `f′` of a function with a single
+    /// parameter, see [`Differentiator`].
+    fn autodiff(
+        &mut self,
+        span: &Span,
+        function: &ast::Expression,
+    ) -> Result<typed_ast::Expression> {
+        let mut function_checked = self.elaborate_expression(function)?;
+
+        // For `autodiff(autodiff(f))`, the definition of `f′` is moved to the outer
+        // expression, such that all derivatives are defined in order.
+        let (name, mut definitions) = match &mut function_checked {
+            typed_ast::Expression::AutoDiff(_, _, definitions, _) => {
+                let definitions = std::mem::take(definitions);
+                let Some(typed_ast::Statement::DefineFunction(name, ..)) = definitions.last()
+                else {
+                    unreachable!("autodiff defines at least one function")
+                };
+                (*name, definitions)
+            }
+            _ => match self.get_proper_function_reference(function) {
+                Some((name, _)) => (name, vec![]),
+                None => {
+                    return Err(TypeCheckError::InvalidAutoDiffArgument(
+                        function.full_span(),
+                    ))
+                }
+            },
+        };
+
+        let definition = match self.function_definitions.get(&name) {
+            Some(definition) => definition.clone(),
+            None => {
+                let (signature, _) = self.env.get_function_info(name).unwrap();
+                let [(parameter_span, parameter, annotation)] = &signature.parameters[..] else {
+                    return Err(TypeCheckError::InvalidAutoDiffArgument(
+                        function.full_span(),
+                    ));
+                };
+                Arc::new(
+                    FunctionDefinition::for_foreign_function(
+                        name,
+                        (*parameter_span, *parameter, annotation.clone()),
+                        signature.type_parameters.clone(),
+                    )
+                    .ok_or_else(|| TypeCheckError::NotDifferentiable {
+                        span: function.full_span(),
+                        construct: "a foreign function without a known derivative".into(),
+                        function: name.to_string(),
+                    })?,
+                )
+            }
+        };
+        let [(parameter_span, parameter, annotation, _)] = &definition.parameters[..] else {
+            return Err(TypeCheckError::InvalidAutoDiffArgument(
+                function.full_span(),
+            ));
+        };
+
+        let body = Differentiator::new(&self.function_definitions, name, *parameter)
+            .differentiate(&definition)?;
+
+        // The type parameters of a generic function can not be used for the
+        // derivative, whose type is inferred instead.
+        let (type_parameters, annotation) = if definition.type_parameters.is_empty() {
+            (vec![], annotation.clone())
+        } else {
+            (vec![], None)
+        };
+
+        let derivative = ast::Statement::DefineFunction {
+            function_name_span: *span,
+            function_name: Symbol::new(&format!("{name}′")),
+            type_parameters,
+            parameters: vec![(*parameter_span, *parameter, annotation, None)],
+            body: Some(body),
+            local_variables: vec![],
+            local_units: vec![],
+            return_type_annotation: None,
+            decorators: vec![],
+        };
+        let derivative_checked = self.elaborate_statement(&derivative)?;
+        let typed_ast::Statement::DefineFunction(_, _, _, _, _, _, _, fn_type, _, _) =
+            &derivative_checked
+        else {
+            unreachable!("A function definition is checked as a function definition")
+        };
+        let fn_type = fn_type.clone();
+        definitions.push(derivative_checked);
+
+        Ok(typed_ast::Expression::AutoDiff(
+            *span,
+            self.arena.alloc(function_checked),
+            definitions,
+            fn_type,
+        ))
+    }
+
+    /// Decides `is_dimensionless(expr)` (without a `dimension`) or
+    /// `has_dimension(expr, dimension)`. The type of `expr` needs to be known at
+    /// this point, which is the case for specializations of generic functions.
+    fn dimension_predicate(
+        &mut self,
+        expr: &ast::Expression,
+        dimension: Option<&TypeExpression>,
+    ) -> Result<bool> {
+        let expected = match dimension {
+            Some(dexpr) => self
+                .registry
+                .get_base_representation(dexpr)
+                .map(DType::from)
+                .map_err(TypeCheckError::RegistryError)?,
+            None => DType::scalar(),
+        };
+
+        let expr_checked = self.elaborate_expression(expr)?;
+        let mut type_ = expr_checked.get_type(&self.arena);
+
+        // The type might only be known after solving the constraints so far
+        if let Ok((substitution, _, _)) = self.constraints.clone().solve() {
+            type_.apply(&substitution).ok();
+        }
+
+        match type_ {
+            Type::Dimension(dtype) if dtype.type_variables(true).is_empty() => {
+                Ok(dtype == expected)
+            }
+            Type::Dimension(_) | Type::TVar(_) | Type::TPar(_) | Type::Dynamic => Err(
+                TypeCheckError::UnresolvedDimensionPredicate(expr.full_span()),
+            ),
+            _ => Ok(false),
+        }
+    }
+
+    /// The value of a condition that only consists of dimension predicates and
+    /// logical operators
+    fn static_condition(&mut self, condition: &ast::Expression) -> Result<Option<bool>> {
+        fn is_static(condition: &ast::Expression) -> bool {
+            match condition {
+                ast::Expression::DimensionPredicate(..) => true,
+                ast::Expression::UnaryOperator {
+                    op: ast::UnaryOperator::LogicalNeg,
+                    expr,
+                    ..
+                } => is_static(expr),
+                ast::Expression::BinaryOperator {
+                    op: BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr,
+                    lhs,
+                    rhs,
+                    ..
+                } => is_static(lhs) && is_static(rhs),
+                _ => false,
+            }
+        }
+
+        if !is_static(condition) {
+            return Ok(None);
+        }
+
+        Ok(Some(match condition {
+            ast::Expression::DimensionPredicate(_, expr, dimension) => {
+                self.dimension_predicate(expr, dimension.as_ref())?
+            }
+            ast::Expression::UnaryOperator { expr, .. } => !self.static_condition(expr)?.unwrap(),
+            ast::Expression::BinaryOperator { op, lhs, rhs, .. } => {
+                let lhs = self.static_condition(lhs)?.unwrap();
+                let rhs = self.static_condition(rhs)?.unwrap();
+                if *op == BinaryOperator::LogicalAnd {
+                    lhs && rhs
+                } else {
+                    lhs || rhs
+                }
+            }
+            _ => unreachable!(),
+        }))
+    }
+
+    /// Whether a function with type parameters needs to be specialized, because
+    /// `expr` (from its body) uses dimension predicates or calls a generic function
+    /// that does.
+    fn uses_dimension_predicates(&self, expr: &ast::Expression) -> bool {
+        expr.contains(&|expr| match expr {
+            ast::Expression::DimensionPredicate(..) => true,
+            ast::Expression::Identifier(_, name) | ast::Expression::AccessField(_, _, _, name) => {
+                self.generic_functions.contains_key(name)
+            }
+            _ => false,
+        })
+    }
+
+    /// Replaces the call of a generic function that uses dimension predicates by a
+    /// call of its specialization for the types of the arguments. The specialization
+    /// is a normal function, where the type parameters are local dimensions (like
+    /// `where dimension D = Length`). This way, the dimension predicates can be
+    /// decided, and only the branches that are taken are checked. Specializations
+    /// are defined before the statement that needs them.
+    fn specialize(
+        &mut self,
+        span: &Span,
+        signature: &FunctionSignature,
+        call: typed_ast::Expression,
+    ) -> Result<typed_ast::Expression> {
+        let typed_ast::Expression::FunctionCall(call_span, full_span, name, args, syntax, type_) =
+            call
+        else {
+            return Ok(call);
+        };
+        let definition = self.generic_functions[&name].clone();
+        let ast::Statement::DefineFunction {
+            function_name_span,
+            type_parameters,
+            parameters,
+            body,
+            local_variables,
+            local_units,
+            return_type_annotation,
+            decorators,
+            ..
+        } = definition.as_ref()
+        else {
+            unreachable!("Generic functions are function definitions")
+        };
+
+        let mut dimensions = vec![];
+        for ((_, type_parameter, _), type_) in type_parameters
+            .iter()
+            .zip(self.type_parameter_types(signature, &args))
+        {
+            match type_ {
+                Some(Type::Dimension(dtype)) if dtype.type_variables(true).is_empty() => {
+                    dimensions.push(dtype)
+                }
+                type_ => {
+                    return Err(TypeCheckError::CanNotSpecialize(
+                        *span,
+                        name.to_string(),
+                        type_parameter.to_string(),
+                        match type_ {
+                            Some(type_) if type_.is_closed() => {
+                                format!("'{}'", type_.to_readable_type(&self.registry))
+                            }
+                            _ => "an unknown type".into(),
+                        },
+                    ))
+                }
+            }
+        }
+
+        let specialization_name = Symbol::new(&format!(
+            "{name}⟨{}⟩",
+            dimensions
+                .iter()
+                .map(|dtype| dtype.to_type_annotation(&self.registry))
+                .join(", ")
+        ));
+
+        if let Entry::Vacant(entry) = self.specializations.entry(specialization_name) {
+            // Recursive calls refer to the specialization that is being checked
+            entry.insert(name);
+
+            let mut local_dimensions = type_parameters
+                .iter()
+                .zip(&dimensions)
+                .map(|((span, type_parameter, _), dtype)| {
+                    ast::Statement::DefineDimension(
+                        *span,
+                        *type_parameter,
+                        vec![type_expression_for(dtype, *span)],
+                    )
+                })
+                .collect::<Vec<_>>();
+            local_dimensions.extend(local_units.iter().cloned());
+
+            let specialization = ast::Statement::DefineFunction {
+                function_name_span: *function_name_span,
+                function_name: specialization_name,
+                type_parameters: vec![],
+                parameters: parameters.clone(),
+                body: body.clone(),
+                local_variables: local_variables.clone(),
+                local_units: local_dimensions,
+                return_type_annotation: return_type_annotation.clone(),
+                decorators: decorators.clone(),
+            };
+
+            match self.check_specialization(&specialization) {
+                Ok(specialization_checked) => {
+                    self.pending_specializations.push(specialization_checked)
+                }
+                Err(e) => {
+                    self.specializations.remove(&specialization_name);
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(typed_ast::Expression::FunctionCall(
+            call_span,
+            full_span,
+            specialization_name,
+            args,
+            syntax,
+            type_,
         ))
     }
 
+    /// The types of the type parameters of a generic function for a call with the
+    /// given arguments, as far as they are known at this point
+    fn type_parameter_types(
+        &mut self,
+        signature: &FunctionSignature,
+        arguments: &[typed_ast::ExpressionId],
+    ) -> Vec<Option<Type>> {
+        let unknown = vec![None; signature.type_parameters.len()];
+        let TypeScheme::Quantified(num_quantified, _) = &signature.fn_type else {
+            return unknown;
+        };
+        if *num_quantified != signature.type_parameters.len() {
+            return unknown;
+        }
+
+        let type_variables = (0..*num_quantified)
+            .map(|_| self.name_generator.fresh_type_variable())
+            .collect::<Vec<_>>();
+        let Type::Fn(parameter_types, _) =
+            signature.fn_type.instantiate_with(&type_variables).inner
+        else {
+            unreachable!("Functions have a function type")
+        };
+
+        let mut constraints = self.constraints.clone();
+        for (argument, parameter_type) in arguments.iter().zip(parameter_types) {
+            constraints
+                .add(Constraint::Equal(
+                    self.arena[*argument].get_type(&self.arena),
+                    parameter_type,
+                ))
+                .ok();
+        }
+        let Ok((substitution, _, _)) = constraints.solve() else {
+            return unknown;
+        };
+
+        type_variables
+            .into_iter()
+            .map(|type_variable| {
+                let mut type_ = Type::TVar(type_variable);
+                type_.apply(&substitution).ok().map(|_| type_)
+            })
+            .collect()
+    }
+
+    /// Checks the definition of a specialization on its own, in the middle of the
+    /// statement that needs it
+    fn check_specialization(
+        &mut self,
+        definition: &ast::Statement,
+    ) -> Result<typed_ast::Statement> {
+        let constraints = std::mem::take(&mut self.constraints);
+        let type_parameters = std::mem::take(&mut self.registry.introduced_type_parameters);
+
+        let result = self.check_statement(definition);
+
+        self.constraints = constraints;
+        self.registry.introduced_type_parameters = type_parameters;
+        result
+    }
+
+    fn operator_overload_call(
+        &mut self,
+        span: &Span,
+        full_span: &Span,
+        op: BinaryOperator,
+        lhs: typed_ast::Expression,
+        rhs: typed_ast::Expression,
+    ) -> Result<typed_ast::Expression> {
+        let lhs_type = lhs.get_type(&self.arena);
+        let rhs_type = rhs.get_type(&self.arena);
+
+        let candidates = self
+            .operator_overloads
+            .iter()
+            .filter(|o| o.op == op && o.accepts(&lhs_type, &rhs_type))
+            .map(|o| o.function_name)
+            .collect::<Vec<_>>();
+
+        let function_name = match candidates.as_slice() {
+            [] => {
+                return Err(TypeCheckError::NoMatchingOperatorOverload(
+                    *span, op, lhs_type, rhs_type,
+                ))
+            }
+            [function_name] => *function_name,
+            _ => {
+                return Err(TypeCheckError::AmbiguousOperatorOverload(
+                    *span,
+                    op,
+                    candidates.iter().map(|c| c.to_string()).collect(),
+                ))
+            }
+        };
+
+        let (signature, _) = self.env.get_function_info(function_name).unwrap();
+        let signature = signature.clone();
+
+        self.proper_function_call(
+            span,
+            full_span,
+            function_name,
+            &signature,
+            vec![lhs, rhs],
+            CallSyntax::Regular,
+        )
+    }
+
+    fn elaborate_field_access(
+        &mut self,
+        full_span: &Span,
+        ident_span: &Span,
+        expr_checked: typed_ast::Expression,
+        field_name: Symbol,
+    ) -> Result<typed_ast::Expression> {
+        let type_ = expr_checked.get_type(&self.arena);
+
+        let field_type = if type_.is_closed() {
+            let Type::Struct(ref struct_info) = type_ else {
+                return Err(TypeCheckError::FieldAccessOfNonStructType(
+                    *ident_span,
+                    expr_checked.full_span(&self.arena),
+                    field_name.to_string(),
+                    type_.clone(),
+                ));
+            };
+
+            let Some((_, field_type)) = struct_info.fields.get(&field_name) else {
+                return Err(TypeCheckError::UnknownFieldAccess(
+                    *ident_span,
+                    expr_checked.full_span(&self.arena),
+                    field_name.to_string(),
+                    type_.clone(),
+                ));
+            };
+
+            field_type.clone()
+        } else {
+            let field_type = self.fresh_type_variable();
+
+            self.constraints
+                .add(Constraint::HasField(
+                    type_.clone(),
+                    field_name,
+                    field_type.clone(),
+                ))
+                .ok();
+
+            field_type
+        };
+
+        Ok(Expression::AccessField(
+            *ident_span,
+            *full_span,
+            self.arena.alloc(expr_checked),
+            field_name,
+            TypeScheme::concrete(type_),
+            TypeScheme::concrete(field_type),
+        ))
+    }
+
+    /// Decides whether `receiver.name(…)` calls the struct field `name` (as opposed to
+    /// being method call syntax for `name(receiver, …)`).
+    fn is_field_call(&self, receiver_type: &Type, name: Symbol) -> bool {
+        match receiver_type {
+            Type::Struct(info) => info.fields.contains_key(&name),
+            t if t.is_closed() => false,
+            _ => self.env.get_identifier_type(name).is_none(),
+        }
+    }
+
+    /// The function that is called by method call syntax `receiver.name(…)`. This is
+    /// the method `name` if the receiver is a struct that has such a method, and
+    /// the free function `name` otherwise.
+    fn method_call_target(
+        &self,
+        receiver: &typed_ast::Expression,
+        ident_span: Span,
+        name: Symbol,
+    ) -> Result<Symbol> {
+        let Type::Struct(info) = receiver.get_type(&self.arena) else {
+            return Ok(name);
+        };
+
+        // Methods are looked up in the environment, such that they can also be
+        // called recursively, before the `impl` block has been checked completely
+        let method = method_name(info.name, name);
+        if self.env.get_function_info(method).is_some() {
+            Ok(method)
+        } else if self.env.get_identifier_type(name).is_some() {
+            Ok(name)
+        } else {
+            Err(TypeCheckError::UnknownMethod {
+                span: ident_span,
+                receiver_span: receiver.full_span(&self.arena),
+                name: name.to_string(),
+                struct_name: info.name.to_string(),
+                methods: self
+                    .methods(info.name)
+                    .iter()
+                    .map(|method| method.to_string())
+                    .collect(),
+            })
+        }
+    }
+
+    fn alloc_all(
+        &mut self,
+        expressions: Vec<typed_ast::Expression>,
+    ) -> Vec<typed_ast::ExpressionId> {
+        expressions
+            .into_iter()
+            .map(|expression| self.arena.alloc(expression))
+            .collect()
+    }
+
+    /// Checks a binary operation whose operands have already been checked.
+    fn elaborate_binary_operator(
+        &mut self,
+        op: BinaryOperator,
+        span_op: Option<Span>,
+        lhs: Operand,
+        rhs: Operand,
+    ) -> Result<typed_ast::Expression> {
+        let Operand {
+            ast: lhs,
+            span: lhs_span,
+            checked: lhs_checked,
+        } = lhs;
+        let Operand {
+            ast: rhs,
+            span: rhs_span,
+            checked: rhs_checked,
+        } = rhs;
+        let full_span = lhs_span.extend(&rhs_span);
+        let full_span = span_op.map_or(full_span, |span_op| full_span.extend(&span_op));
+
+        let (lhs_checked, rhs_checked) =
+            match self.elaborate_dynamic_operands(span_op, op, lhs_checked, rhs_checked)? {
+                DynamicOperands::Checked(operands) => *operands,
+                DynamicOperands::Result(expr) => return Ok(*expr),
+            };
+
+        let lhs_type = lhs_checked.get_type(&self.arena);
+        let rhs_type = rhs_checked.get_type(&self.arena);
+
+        let is_overloadable = matches!(
+            op,
+            BinaryOperator::Add | BinaryOperator::Sub | BinaryOperator::Mul | BinaryOperator::Div
+        );
+
+        Ok(
+            if is_overloadable
+                && (matches!(lhs_type, Type::Struct(_)) || matches!(rhs_type, Type::Struct(_)))
+            {
+                self.operator_overload_call(
+                    &span_op.unwrap_or(full_span),
+                    &full_span,
+                    op,
+                    lhs_checked,
+                    rhs_checked,
+                )?
+            } else if lhs_type == Type::DateTime {
+                // DateTime types need special handling here, since they're not scalars with dimensions,
+                // yet some select binary operators can be applied to them
+
+                let rhs_is_time = dtype(&self.arena, &rhs_checked)
+                    .ok()
+                    .map(|t| t.is_time_dimension())
+                    .unwrap_or(false);
+                let rhs_is_datetime = rhs_type == Type::DateTime;
+
+                if op == BinaryOperator::Sub && rhs_is_datetime {
+                    // The result is an exact duration at runtime, which can be used
+                    // wherever a `Time` quantity is expected
+                    let time = DType::base_dimension("Time"); // TODO: error handling
+                                                              // TODO make sure the "second" unit exists
+
+                    typed_ast::Expression::BinaryOperatorForDate(
+                        span_op,
+                        op,
+                        self.arena.alloc(lhs_checked),
+                        self.arena.alloc(rhs_checked),
+                        TypeScheme::concrete(Type::Dimension(time)),
+                    )
+                } else if rhs_is_datetime
+                    && matches!(
+                        op,
+                        BinaryOperator::LessThan
+                            | BinaryOperator::GreaterThan
+                            | BinaryOperator::LessOrEqual
+                            | BinaryOperator::GreaterOrEqual
+                            | BinaryOperator::Equal
+                            | BinaryOperator::NotEqual
+                    )
+                {
+                    // DateTimes are compared by the instant in time they represent,
+                    // independent of their time zone
+                    typed_ast::Expression::BinaryOperator(
+                        span_op,
+                        op,
+                        self.arena.alloc(lhs_checked),
+                        self.arena.alloc(rhs_checked),
+                        TypeScheme::concrete(Type::Boolean),
+                    )
+                } else if (op == BinaryOperator::Add || op == BinaryOperator::Sub) && rhs_is_time {
+                    typed_ast::Expression::BinaryOperatorForDate(
+                        span_op,
+                        op,
+                        self.arena.alloc(lhs_checked),
+                        self.arena.alloc(rhs_checked),
+                        TypeScheme::concrete(Type::DateTime),
+                    )
+                } else {
+                    return Err(TypeCheckError::IncompatibleTypesInOperator(
+                        span_op.unwrap_or(full_span),
+                        op,
+                        lhs_type,
+                        lhs_span,
+                        rhs_type,
+                        rhs_span,
+                    ));
+                }
+            } else {
+                let mut get_type_and_assert_equal_dtypes = || -> Result<Type> {
+                    let lhs_type = lhs_checked.get_type(&self.arena);
+                    let rhs_type = rhs_checked.get_type(&self.arena);
+
+                    if self
+                        .add_equal_constraint(&lhs_type, &rhs_type)
+                        .is_trivially_violated()
+                    {
+                        let lhs_dtype = dtype(&self.arena, &lhs_checked)?;
+                        let rhs_dtype = dtype(&self.arena, &rhs_checked)?;
+                        return Err(TypeCheckError::IncompatibleDimensions(
+                        IncompatibleDimensionsError {
+                            span_operation: span_op.unwrap_or(full_span),
+                            operation: match op {
+                                typed_ast::BinaryOperator::Add => "addition".into(),
+                                typed_ast::BinaryOperator::Sub => "subtraction".into(),
+                                typed_ast::BinaryOperator::Mul => "multiplication".into(),
+                                typed_ast::BinaryOperator::Div => "division".into(),
+                                typed_ast::BinaryOperator::Power => "exponentiation".into(),
+                                typed_ast::BinaryOperator::LessThan
+                                | typed_ast::BinaryOperator::GreaterThan
+                                | typed_ast::BinaryOperator::LessOrEqual
+                                | typed_ast::BinaryOperator::GreaterOrEqual
+                                | typed_ast::BinaryOperator::Equal
+                                | typed_ast::BinaryOperator::NotEqual => {
+                                    "comparison".into()
+                                }
+                                typed_ast::BinaryOperator::LogicalAnd => "and".into(),
+                                typed_ast::BinaryOperator::LogicalOr => "or".into(),
+                            },
+                            span_expected: lhs_span,
+                            expected_name: " left hand side",
+                            expected_dimensions: self.registry.get_derived_entry_names_for(
+                                &lhs_dtype.to_base_representation(),
+                            ),
+                            expected_type: lhs_dtype.to_base_representation(),
+                            span_actual: rhs_span,
+                            actual_name: "right hand side",
+                            actual_name_for_fix: "expression on the right hand side",
+                            actual_dimensions: self.registry.get_derived_entry_names_for(
+                                &rhs_dtype.to_base_representation(),
+                            ),
+                            actual_type: rhs_dtype.to_base_representation(),
+                            hint: match op {
+                                BinaryOperator::Add | BinaryOperator::Sub
+                                    if !lhs_dtype.is_scalar() =>
+                                {
+                                    percentage_literal(rhs).map(|percentage| {
+                                        format!(
+                                            "to change the left hand side by {percentage}, multiply it by `(1 {op} {percentage})`",
+                                            op = if op == BinaryOperator::Add { "+" } else { "-" }
+                                        )
+                                    })
+                                }
+                                _ => None,
+                            },
+                            note: [("right", rhs, &rhs_dtype), ("left", lhs, &lhs_dtype)]
+                                .into_iter()
+                                .find_map(|(side, operand, dtype)| {
+                                    let unit = bare_unit_identifier(operand)?;
+                                    let dimension = self
+                                        .registry
+                                        .get_derived_entry_names_for(
+                                            &dtype.to_base_representation(),
+                                        )
+                                        .first()
+                                        .map_or_else(|| dtype.to_string(), |d| d.to_string());
+                                    Some(format!(
+                                        "The {side} hand side refers to the unit {unit} ({dimension}), not to a variable. Variables can not have the name of a unit"
+                                    ))
+                                }),
+                        },
+                    ));
+                    }
+
+                    self.enforce_dtype(&lhs_type, lhs_span)?;
+                    self.enforce_dtype(&rhs_type, rhs_span)?;
+
+                    Ok(lhs_type)
+                };
+
+                let type_ = match op {
+                    typed_ast::BinaryOperator::Add => get_type_and_assert_equal_dtypes()?,
+                    typed_ast::BinaryOperator::Sub => get_type_and_assert_equal_dtypes()?,
+                    typed_ast::BinaryOperator::Mul | typed_ast::BinaryOperator::Div => {
+                        let type_lhs = lhs_checked.get_type(&self.arena);
+                        let type_rhs = rhs_checked.get_type(&self.arena);
+
+                        if type_lhs.is_closed() && type_rhs.is_closed() {
+                            let lhs_dtype = dtype(&self.arena, &lhs_checked)?;
+                            let rhs_dtype = dtype(&self.arena, &rhs_checked)?;
+
+                            match op {
+                                typed_ast::BinaryOperator::Mul => {
+                                    Type::Dimension(lhs_dtype.multiply(&rhs_dtype))
+                                }
+                                typed_ast::BinaryOperator::Div => {
+                                    Type::Dimension(lhs_dtype.divide(&rhs_dtype))
+                                }
+                                _ => unreachable!(),
+                            }
+                        } else {
+                            self.enforce_dtype(&type_lhs, lhs_checked.full_span(&self.arena))?;
+                            self.enforce_dtype(&type_rhs, rhs_checked.full_span(&self.arena))?;
+
+                            // We first introduce a fresh type variable for the result
+                            let tv_result = self.name_generator.fresh_type_variable();
+                            let type_result = Type::TVar(tv_result);
+
+                            // … and make sure that it is a dimension type
+                            self.add_dtype_constraint(&type_result).ok();
+
+                            // We can't use type_lhs/type_rhs directly in a dimension expression, because
+                            // only DTypes can be used there. But we don't know if type_lhs/type_rhs are
+                            // indeed dimension types. So we make up new type variables tv_lhs/tv_rhs, and
+                            // add contraints type_lhs ~ type(tv_lhs), type_rhs ~ type(tv_rhs). We can then
+                            // use those type variables inside the dimension expression constraint.
+
+                            let tv_lhs = self.name_generator.fresh_type_variable();
+                            let tv_rhs = self.name_generator.fresh_type_variable();
+
+                            self.constraints
+                                .add(Constraint::Equal(type_lhs, Type::TVar(tv_lhs)))
+                                .ok();
+                            self.constraints
+                                .add(Constraint::Equal(type_rhs, Type::TVar(tv_rhs)))
+                                .ok();
+
+                            // we also need dtype constraints for those new type variables
+                            self.add_dtype_constraint(&Type::TVar(tv_lhs)).ok();
+                            self.add_dtype_constraint(&Type::TVar(tv_rhs)).ok();
+
+                            // Finally, we add the constraint that the result is the product of the two,
+                            // which we write as
+                            //
+                            //     dtype_lhs × dtype_rhs × dtype_result^-1 ~ Scalar
+                            //
+                            // Or for division:
+                            //
+                            //     dtype_lhs × dtype_rhs^-1 × dtype_result ~ Scalar
+                            //
+                            let dtype_lhs = DType::from_type_variable(tv_lhs);
+                            let dtype_rhs = DType::from_type_variable(tv_rhs);
+                            let dtype_result = DType::from_type_variable(tv_result);
+
+                            match op {
+                                typed_ast::BinaryOperator::Mul => {
+                                    self.constraints
+                                        .add(Constraint::EqualScalar(
+                                            dtype_lhs
+                                                .multiply(&dtype_rhs)
+                                                .multiply(&dtype_result.inverse()),
+                                        ))
+                                        .ok();
+                                }
+                                typed_ast::BinaryOperator::Div => {
+                                    self.constraints
+                                        .add(Constraint::EqualScalar(
+                                            (dtype_lhs.divide(&dtype_rhs))
+                                                .multiply(&dtype_result.inverse()),
+                                        ))
+                                        .ok();
+                                }
+                                _ => unreachable!(),
+                            }
+
+                            type_result
+                        }
+                    }
+                    typed_ast::BinaryOperator::Power => {
+                        let type_base_inferred = lhs_type;
+                        let type_exponent_inferred = rhs_type;
+
+                        self.enforce_dtype(&type_base_inferred, lhs_span)?;
+                        self.enforce_dtype(&type_exponent_inferred, rhs_span)?;
+
+                        match type_base_inferred {
+                            Type::Dimension(base_dtype) if base_dtype.is_scalar() => {
+                                // Skip evaluating the exponent if the lhs is a scalar. This allows
+                                // for arbitrary (decimal) exponents, if the base is a scalar.
+
+                                if self
+                                    .add_equal_constraint(&type_exponent_inferred, &Type::scalar())
+                                    .is_trivially_violated()
+                                {
+                                    return Err(TypeCheckError::NonScalarExponent(
+                                        rhs_span,
+                                        type_exponent_inferred,
+                                    ));
+                                }
+
+                                Type::Dimension(base_dtype)
+                            }
+                            Type::Dimension(base_dtype) => {
+                                let exponent = evaluate_const_expr(&self.arena, &rhs_checked)?;
+                                Type::Dimension(base_dtype.power(exponent))
+                            }
+                            _ => {
+                                if let Ok(exponent) = evaluate_const_expr(&self.arena, &rhs_checked)
+                                {
+                                    // Type inference in this case follows a similar pattern to multiplication/division. See
+                                    // there for an explanation
+
+                                    let tv_result = self.name_generator.fresh_type_variable();
+                                    let type_result = Type::TVar(tv_result);
+                                    let dtype_result = DType::from_type_variable(tv_result);
+                                    self.add_dtype_constraint(&type_result).ok();
+
+                                    let tv_base = self.name_generator.fresh_type_variable();
+                                    let type_base = Type::TVar(tv_base);
+                                    let dtype_base = DType::from_type_variable(tv_base);
+                                    self.add_dtype_constraint(&type_base).ok();
+
+                                    self.add_equal_constraint(&type_base, &type_base_inferred)
+                                        .ok();
+
+                                    self.constraints
+                                        .add(Constraint::EqualScalar(
+                                            dtype_result.multiply(&dtype_base.power(-exponent)),
+                                        ))
+                                        .ok();
+
+                                    type_result
+                                } else {
+                                    return Err(TypeCheckError::ExponentiationNeedsTypeAnnotation(
+                                        lhs_checked
+                                            .full_span(&self.arena)
+                                            .extend(&rhs_checked.full_span(&self.arena)),
+                                    ));
+                                }
+                            }
+                        }
+                    }
+                    typed_ast::BinaryOperator::LessThan
+                    | typed_ast::BinaryOperator::GreaterThan
+                    | typed_ast::BinaryOperator::LessOrEqual
+                    | typed_ast::BinaryOperator::GreaterOrEqual => {
+                        if lhs_type == Type::String || rhs_type == Type::String {
+                            // Strings are ordered lexicographically
+                            if self
+                                .add_equal_constraint(&lhs_type, &rhs_type)
+                                .is_trivially_violated()
+                            {
+                                return Err(TypeCheckError::IncompatibleTypesInComparison(
+                                    span_op.unwrap(),
+                                    lhs_type,
+                                    lhs_span,
+                                    rhs_type,
+                                    rhs_span,
+                                ));
+                            }
+                        } else {
+                            let _ = get_type_and_assert_equal_dtypes()?;
+                        }
+                        Type::Boolean
+                    }
+                    typed_ast::BinaryOperator::Equal | typed_ast::BinaryOperator::NotEqual => {
+                        if lhs_type.is_closed() && rhs_type.is_closed() {
+                            if lhs_type.is_dtype() && rhs_type.is_dtype() {
+                                let _ = get_type_and_assert_equal_dtypes()?;
+                            } else if lhs_type != rhs_type
+                                || lhs_type.is_fn_type()
+                                || rhs_type.is_fn_type()
+                            {
+                                return Err(TypeCheckError::IncompatibleTypesInComparison(
+                                    span_op.unwrap(),
+                                    lhs_type,
+                                    lhs_span,
+                                    rhs_type,
+                                    rhs_span,
+                                ));
+                            }
+                        } else {
+                            self.add_equal_constraint(&lhs_type, &rhs_type).ok();
+                        }
+
+                        Type::Boolean
+                    }
+                    typed_ast::BinaryOperator::LogicalAnd
+                    | typed_ast::BinaryOperator::LogicalOr => {
+                        if self
+                            .add_equal_constraint(&lhs_type, &Type::Boolean)
+                            .is_trivially_violated()
+                        {
+                            return Err(TypeCheckError::ExpectedBool(lhs_span));
+                        }
+                        if self
+                            .add_equal_constraint(&rhs_type, &Type::Boolean)
+                            .is_trivially_violated()
+                        {
+                            return Err(TypeCheckError::ExpectedBool(rhs_span));
+                        }
+
+                        Type::Boolean
+                    }
+                };
+
+                typed_ast::Expression::BinaryOperator(
+                    span_op,
+                    op,
+                    self.arena.alloc(lhs_checked),
+                    self.arena.alloc(rhs_checked),
+                    TypeScheme::concrete(type_),
+                )
+            },
+        )
+    }
+
     fn elaborate_expression(&mut self, ast: &ast::Expression) -> Result<typed_ast::Expression> {
         Ok(match ast {
             ast::Expression::Scalar(span, n)
@@ -291,43 +2454,64 @@ impl TypeChecker {
             ast::Expression::Scalar(span, n) => {
                 typed_ast::Expression::Scalar(*span, *n, TypeScheme::concrete(Type::scalar()))
             }
+            ast::Expression::Identifier(span, name)
+                if self.generic_functions.contains_key(name)
+                    && self.env.get_function_info(*name).is_some() =>
+            {
+                return Err(TypeCheckError::GenericFunctionNotCalled(
+                    *span,
+                    name.to_string(),
+                ));
+            }
             ast::Expression::Identifier(span, name) => {
-                let type_scheme = self.identifier_type(*span, name)?.clone();
+                let type_scheme = self.identifier_type(*span, *name)?.clone();
+                self.check_deprecation(*span, *name);
 
                 let ty = match type_scheme {
                     TypeScheme::Concrete(ty) => ty,
                     TypeScheme::Quantified(_, _) => {
                         let qt = type_scheme.instantiate(&mut self.name_generator);
 
-                        for Bound::IsDim(t) in qt.bounds.iter() {
-                            self.constraints.add(Constraint::IsDType(t.clone())).ok();
-                        }
+                        self.add_bound_constraints(&qt.bounds);
                         qt.inner
                     }
                 };
 
-                typed_ast::Expression::Identifier(*span, name.clone(), TypeScheme::concrete(ty))
+                typed_ast::Expression::Identifier(*span, *name, TypeScheme::concrete(ty))
             }
             ast::Expression::UnitIdentifier(span, prefix, name, full_name) => {
-                let type_scheme = self.identifier_type(*span, name)?.clone();
+                let type_scheme = self.identifier_type(*span, *name)?.clone();
+                self.check_deprecation(*span, *name);
 
-                let qt = type_scheme.instantiate(&mut self.name_generator);
+                // Local units (from the `where` clause of a function) are not generalized
+                let ty = match type_scheme {
+                    TypeScheme::Concrete(ty) => ty,
+                    TypeScheme::Quantified(_, _) => {
+                        let qt = type_scheme.instantiate(&mut self.name_generator);
 
-                for Bound::IsDim(t) in qt.bounds.iter() {
-                    self.constraints.add(Constraint::IsDType(t.clone())).ok();
-                }
+                        self.add_bound_constraints(&qt.bounds);
+                        qt.inner
+                    }
+                };
 
                 typed_ast::Expression::UnitIdentifier(
                     *span,
                     *prefix,
-                    name.clone(),
-                    full_name.clone(),
-                    TypeScheme::concrete(qt.inner),
+                    *name,
+                    *full_name,
+                    TypeScheme::concrete(ty),
                 )
             }
             ast::Expression::UnaryOperator { op, expr, span_op } => {
                 let checked_expr = self.elaborate_expression(expr)?;
-                let type_ = checked_expr.get_type();
+                let is_dynamic = checked_expr.get_type(&self.arena) == Type::Dynamic;
+                let checked_expr = match op {
+                    ast::UnaryOperator::LogicalNeg if is_dynamic => {
+                        self.dynamic_check(checked_expr, Type::Boolean)
+                    }
+                    _ => self.check_if_dynamic_quantity(checked_expr),
+                };
+                let type_ = checked_expr.get_type(&self.arena);
 
                 match op {
                     ast::UnaryOperator::Factorial => {
@@ -354,394 +2538,108 @@ impl TypeChecker {
                     }
                 }
 
+                // The dimension of a negated `Dynamic` value is not known either
+                let type_ = if is_dynamic && *op == ast::UnaryOperator::Negate {
+                    Type::Dynamic
+                } else {
+                    type_
+                };
+
                 typed_ast::Expression::UnaryOperator(
                     *span_op,
                     *op,
-                    Box::new(checked_expr),
+                    self.arena.alloc(checked_expr),
                     TypeScheme::concrete(type_),
                 )
             }
-            ast::Expression::BinaryOperator {
-                op,
-                lhs,
-                rhs,
+            ast::Expression::Conversion {
+                source,
+                target,
                 span_op,
-            } => {
-                let lhs_checked = self.elaborate_expression(lhs)?;
-                let rhs_checked = self.elaborate_expression(rhs)?;
-
-                let lhs_type = lhs_checked.get_type();
-                let rhs_type = rhs_checked.get_type();
+                parenthesized_source,
+            } => self.elaborate_conversion(source, target, *span_op, *parenthesized_source)?,
+            ast::Expression::BinaryOperator { .. } => {
+                // Long chains like `a + b + c + …` are checked from left to right,
+                // without recursing into every left hand side
+                let mut nodes = vec![];
+                let mut first = ast;
+                while let ast::Expression::BinaryOperator { lhs, .. } = first {
+                    nodes.push(first);
+                    first = lhs;
+                }
 
-                if rhs_type.is_fn_type() && op == &BinaryOperator::ConvertTo {
-                    let (parameter_types, return_type) = match rhs_type {
-                        Type::Fn(p, r) => (p, r),
-                        _ => unreachable!(),
+                let mut lhs = Operand {
+                    ast: first,
+                    span: first.full_span(),
+                    checked: self.elaborate_expression(first)?,
+                };
+                for node in nodes.into_iter().rev() {
+                    let ast::Expression::BinaryOperator {
+                        op, rhs, span_op, ..
+                    } = node
+                    else {
+                        unreachable!()
                     };
-                    // make sure that there is just one paramter (return arity error otherwise)
-                    if parameter_types.len() != 1 {
-                        return Err(TypeCheckError::WrongArity {
-                            callable_span: rhs.full_span(),
-                            callable_name: "function".into(),
-                            callable_definition_span: None,
-                            arity: 1..=1,
-                            num_args: parameter_types.len(),
-                        });
-                    }
-
-                    if self
-                        .add_equal_constraint(&lhs_type, &parameter_types[0])
-                        .is_trivially_violated()
-                    {
-                        return Err(TypeCheckError::IncompatibleTypesInFunctionCall(
-                            None,
-                            parameter_types[0].clone(),
-                            lhs.full_span(),
-                            lhs_type,
-                        ));
-                    }
-
-                    typed_ast::Expression::CallableCall(
-                        lhs.full_span(),
-                        Box::new(rhs_checked),
-                        vec![lhs_checked],
-                        TypeScheme::concrete(*return_type),
-                    )
-                } else if lhs_type == Type::DateTime {
-                    // DateTime types need special handling here, since they're not scalars with dimensions,
-                    // yet some select binary operators can be applied to them
-
-                    let rhs_is_time = dtype(&rhs_checked)
-                        .ok()
-                        .map(|t| t.is_time_dimension())
-                        .unwrap_or(false);
-                    let rhs_is_datetime = rhs_type == Type::DateTime;
-
-                    if *op == BinaryOperator::Sub && rhs_is_datetime {
-                        let time = DType::base_dimension("Time"); // TODO: error handling
-                                                                  // TODO make sure the "second" unit exists
-
-                        typed_ast::Expression::BinaryOperatorForDate(
-                            *span_op,
-                            *op,
-                            Box::new(lhs_checked),
-                            Box::new(rhs_checked),
-                            TypeScheme::concrete(Type::Dimension(time)),
-                        )
-                    } else if (*op == BinaryOperator::Add || *op == BinaryOperator::Sub)
-                        && rhs_is_time
-                    {
-                        typed_ast::Expression::BinaryOperatorForDate(
-                            *span_op,
-                            *op,
-                            Box::new(lhs_checked),
-                            Box::new(rhs_checked),
-                            TypeScheme::concrete(Type::DateTime),
-                        )
-                    } else {
-                        return Err(TypeCheckError::IncompatibleTypesInOperator(
-                            span_op.unwrap_or_else(|| {
-                                ast::Expression::BinaryOperator {
-                                    op: *op,
-                                    lhs: lhs.clone(),
-                                    rhs: rhs.clone(),
-                                    span_op: *span_op,
-                                }
-                                .full_span()
-                            }),
-                            *op,
-                            lhs_type,
-                            lhs.full_span(),
-                            rhs_type,
-                            rhs.full_span(),
-                        ));
-                    }
-                } else {
-                    let mut get_type_and_assert_equal_dtypes = || -> Result<Type> {
-                        let lhs_type = lhs_checked.get_type();
-                        let rhs_type = rhs_checked.get_type();
-
-                        if self
-                            .add_equal_constraint(&lhs_type, &rhs_type)
-                            .is_trivially_violated()
-                        {
-                            let lhs_dtype = dtype(&lhs_checked)?;
-                            let rhs_dtype = dtype(&rhs_checked)?;
-                            let full_span = ast::Expression::BinaryOperator {
-                                op: *op,
-                                lhs: lhs.clone(),
-                                rhs: rhs.clone(),
-                                span_op: *span_op,
-                            }
-                            .full_span();
-                            return Err(TypeCheckError::IncompatibleDimensions(
-                                IncompatibleDimensionsError {
-                                    span_operation: span_op.unwrap_or(full_span),
-                                    operation: match op {
-                                        typed_ast::BinaryOperator::Add => "addition".into(),
-                                        typed_ast::BinaryOperator::Sub => "subtraction".into(),
-                                        typed_ast::BinaryOperator::Mul => "multiplication".into(),
-                                        typed_ast::BinaryOperator::Div => "division".into(),
-                                        typed_ast::BinaryOperator::Power => "exponentiation".into(),
-                                        typed_ast::BinaryOperator::ConvertTo => {
-                                            "unit conversion".into()
-                                        }
-                                        typed_ast::BinaryOperator::LessThan
-                                        | typed_ast::BinaryOperator::GreaterThan
-                                        | typed_ast::BinaryOperator::LessOrEqual
-                                        | typed_ast::BinaryOperator::GreaterOrEqual
-                                        | typed_ast::BinaryOperator::Equal
-                                        | typed_ast::BinaryOperator::NotEqual => {
-                                            "comparison".into()
-                                        }
-                                        typed_ast::BinaryOperator::LogicalAnd => "and".into(),
-                                        typed_ast::BinaryOperator::LogicalOr => "or".into(),
-                                    },
-                                    span_expected: lhs.full_span(),
-                                    expected_name: " left hand side",
-                                    expected_dimensions: self.registry.get_derived_entry_names_for(
-                                        &lhs_dtype.to_base_representation(),
-                                    ),
-                                    expected_type: lhs_dtype.to_base_representation(),
-                                    span_actual: rhs.full_span(),
-                                    actual_name: "right hand side",
-                                    actual_name_for_fix: "expression on the right hand side",
-                                    actual_dimensions: self.registry.get_derived_entry_names_for(
-                                        &rhs_dtype.to_base_representation(),
-                                    ),
-                                    actual_type: rhs_dtype.to_base_representation(),
-                                },
-                            ));
-                        }
-
-                        self.enforce_dtype(&lhs_type, lhs.full_span())?;
-                        self.enforce_dtype(&rhs_type, rhs.full_span())?;
-
-                        Ok(lhs_type)
+                    let rhs = Operand {
+                        ast: rhs,
+                        span: rhs.full_span(),
+                        checked: self.elaborate_expression(rhs)?,
                     };
-
-                    let type_ = match op {
-                        typed_ast::BinaryOperator::Add => get_type_and_assert_equal_dtypes()?,
-                        typed_ast::BinaryOperator::Sub => get_type_and_assert_equal_dtypes()?,
-                        typed_ast::BinaryOperator::Mul | typed_ast::BinaryOperator::Div => {
-                            let type_lhs = lhs_checked.get_type();
-                            let type_rhs = rhs_checked.get_type();
-
-                            if type_lhs.is_closed() && type_rhs.is_closed() {
-                                let lhs_dtype = dtype(&lhs_checked)?;
-                                let rhs_dtype = dtype(&rhs_checked)?;
-
-                                match op {
-                                    typed_ast::BinaryOperator::Mul => {
-                                        Type::Dimension(lhs_dtype.multiply(&rhs_dtype))
-                                    }
-                                    typed_ast::BinaryOperator::Div => {
-                                        Type::Dimension(lhs_dtype.divide(&rhs_dtype))
-                                    }
-                                    _ => unreachable!(),
-                                }
-                            } else {
-                                self.enforce_dtype(&type_lhs, lhs_checked.full_span())?;
-                                self.enforce_dtype(&type_rhs, rhs_checked.full_span())?;
-
-                                // We first introduce a fresh type variable for the result
-                                let tv_result = self.name_generator.fresh_type_variable();
-                                let type_result = Type::TVar(tv_result.clone());
-
-                                // … and make sure that it is a dimension type
-                                self.add_dtype_constraint(&type_result).ok();
-
-                                // We can't use type_lhs/type_rhs directly in a dimension expression, because
-                                // only DTypes can be used there. But we don't know if type_lhs/type_rhs are
-                                // indeed dimension types. So we make up new type variables tv_lhs/tv_rhs, and
-                                // add contraints type_lhs ~ type(tv_lhs), type_rhs ~ type(tv_rhs). We can then
-                                // use those type variables inside the dimension expression constraint.
-
-                                let tv_lhs = self.name_generator.fresh_type_variable();
-                                let tv_rhs = self.name_generator.fresh_type_variable();
-
-                                self.constraints
-                                    .add(Constraint::Equal(type_lhs, Type::TVar(tv_lhs.clone())))
-                                    .ok();
-                                self.constraints
-                                    .add(Constraint::Equal(type_rhs, Type::TVar(tv_rhs.clone())))
-                                    .ok();
-
-                                // we also need dtype constraints for those new type variables
-                                self.add_dtype_constraint(&Type::TVar(tv_lhs.clone())).ok();
-                                self.add_dtype_constraint(&Type::TVar(tv_rhs.clone())).ok();
-
-                                // Finally, we add the constraint that the result is the product of the two,
-                                // which we write as
-                                //
-                                //     dtype_lhs × dtype_rhs × dtype_result^-1 ~ Scalar
-                                //
-                                // Or for division:
-                                //
-                                //     dtype_lhs × dtype_rhs^-1 × dtype_result ~ Scalar
-                                //
-                                let dtype_lhs = DType::from_type_variable(tv_lhs);
-                                let dtype_rhs = DType::from_type_variable(tv_rhs);
-                                let dtype_result = DType::from_type_variable(tv_result);
-
-                                match op {
-                                    typed_ast::BinaryOperator::Mul => {
-                                        self.constraints
-                                            .add(Constraint::EqualScalar(
-                                                dtype_lhs
-                                                    .multiply(&dtype_rhs)
-                                                    .multiply(&dtype_result.inverse()),
-                                            ))
-                                            .ok();
-                                    }
-                                    typed_ast::BinaryOperator::Div => {
-                                        self.constraints
-                                            .add(Constraint::EqualScalar(
-                                                (dtype_lhs.divide(&dtype_rhs))
-                                                    .multiply(&dtype_result.inverse()),
-                                            ))
-                                            .ok();
-                                    }
-                                    _ => unreachable!(),
-                                }
-
-                                type_result
-                            }
-                        }
-                        typed_ast::BinaryOperator::Power => {
-                            let type_base_inferred = lhs_type;
-                            let type_exponent_inferred = rhs_type;
-
-                            self.enforce_dtype(&type_base_inferred, lhs.full_span())?;
-                            self.enforce_dtype(&type_exponent_inferred, rhs.full_span())?;
-
-                            match type_base_inferred {
-                                Type::Dimension(base_dtype) if base_dtype.is_scalar() => {
-                                    // Skip evaluating the exponent if the lhs is a scalar. This allows
-                                    // for arbitrary (decimal) exponents, if the base is a scalar.
-
-                                    if self
-                                        .add_equal_constraint(
-                                            &type_exponent_inferred,
-                                            &Type::scalar(),
-                                        )
-                                        .is_trivially_violated()
-                                    {
-                                        return Err(TypeCheckError::NonScalarExponent(
-                                            rhs.full_span(),
-                                            type_exponent_inferred,
-                                        ));
-                                    }
-
-                                    Type::Dimension(base_dtype)
-                                }
-                                Type::Dimension(base_dtype) => {
-                                    let exponent = evaluate_const_expr(&rhs_checked)?;
-                                    Type::Dimension(base_dtype.power(exponent))
-                                }
-                                _ => {
-                                    if let Ok(exponent) = evaluate_const_expr(&rhs_checked) {
-                                        // Type inference in this case follows a similar pattern to multiplication/division. See
-                                        // there for an explanation
-
-                                        let tv_result = self.name_generator.fresh_type_variable();
-                                        let type_result = Type::TVar(tv_result.clone());
-                                        let dtype_result = DType::from_type_variable(tv_result);
-                                        self.add_dtype_constraint(&type_result).ok();
-
-                                        let tv_base = self.name_generator.fresh_type_variable();
-                                        let type_base = Type::TVar(tv_base.clone());
-                                        let dtype_base = DType::from_type_variable(tv_base);
-                                        self.add_dtype_constraint(&type_base).ok();
-
-                                        self.add_equal_constraint(&type_base, &type_base_inferred)
-                                            .ok();
-
-                                        self.constraints
-                                            .add(Constraint::EqualScalar(
-                                                dtype_result.multiply(&dtype_base.power(-exponent)),
-                                            ))
-                                            .ok();
-
-                                        type_result
-                                    } else {
-                                        return Err(
-                                            TypeCheckError::ExponentiationNeedsTypeAnnotation(
-                                                lhs_checked
-                                                    .full_span()
-                                                    .extend(&rhs_checked.full_span()),
-                                            ),
-                                        );
-                                    }
-                                }
-                            }
-                        }
-                        typed_ast::BinaryOperator::ConvertTo => get_type_and_assert_equal_dtypes()?,
-                        typed_ast::BinaryOperator::LessThan
-                        | typed_ast::BinaryOperator::GreaterThan
-                        | typed_ast::BinaryOperator::LessOrEqual
-                        | typed_ast::BinaryOperator::GreaterOrEqual => {
-                            let _ = get_type_and_assert_equal_dtypes()?;
-                            Type::Boolean
-                        }
-                        typed_ast::BinaryOperator::Equal | typed_ast::BinaryOperator::NotEqual => {
-                            if lhs_type.is_closed() && rhs_type.is_closed() {
-                                if lhs_type.is_dtype() && rhs_type.is_dtype() {
-                                    let _ = get_type_and_assert_equal_dtypes()?;
-                                } else if lhs_type != rhs_type
-                                    || lhs_type.is_fn_type()
-                                    || rhs_type.is_fn_type()
-                                {
-                                    return Err(TypeCheckError::IncompatibleTypesInComparison(
-                                        span_op.unwrap(),
-                                        lhs_type,
-                                        lhs.full_span(),
-                                        rhs_type,
-                                        rhs.full_span(),
-                                    ));
-                                }
-                            } else {
-                                self.add_equal_constraint(&lhs_type, &rhs_type).ok();
-                            }
-
-                            Type::Boolean
-                        }
-                        typed_ast::BinaryOperator::LogicalAnd
-                        | typed_ast::BinaryOperator::LogicalOr => {
-                            if self
-                                .add_equal_constraint(&lhs_type, &Type::Boolean)
-                                .is_trivially_violated()
-                            {
-                                return Err(TypeCheckError::ExpectedBool(lhs.full_span()));
-                            }
-                            if self
-                                .add_equal_constraint(&rhs_type, &Type::Boolean)
-                                .is_trivially_violated()
-                            {
-                                return Err(TypeCheckError::ExpectedBool(rhs.full_span()));
-                            }
-
-                            Type::Boolean
-                        }
+                    let span = lhs.span.extend(&rhs.span);
+                    lhs = Operand {
+                        ast: node,
+                        span: span_op.map_or(span, |span_op| span.extend(&span_op)),
+                        checked: self.elaborate_binary_operator(*op, *span_op, lhs, rhs)?,
                     };
-
-                    typed_ast::Expression::BinaryOperator(
-                        *span_op,
-                        *op,
-                        Box::new(lhs_checked),
-                        Box::new(rhs_checked),
-                        TypeScheme::concrete(type_),
-                    )
                 }
+                lhs.checked
             }
-            ast::Expression::FunctionCall(span, full_span, callable, args) => {
-                let arguments_checked = args
-                    .iter()
-                    .map(|a| self.elaborate_expression(a))
-                    .collect::<Result<Vec<_>>>()?;
+            ast::Expression::FunctionCall(span, full_span, callable, args, named_args, syntax) => {
+                let mut span = span;
+                let mut callable = callable.as_ref();
+                let mut callable_checked = None;
+                let mut arguments_checked = vec![];
+
+                // `receiver.name(args)` is either a call of the struct field `name`, or method
+                // call syntax for `name(receiver, args)`. Which one it is can only be decided
+                // after the type of the receiver has been inferred.
+                let method_callable;
+                if let ast::Expression::AccessField(field_full_span, ident_span, receiver, name) =
+                    callable
+                {
+                    let receiver_checked = self.elaborate_expression(receiver)?;
+
+                    if self.is_field_call(&receiver_checked.get_type(&self.arena), *name) {
+                        callable_checked = Some(self.elaborate_field_access(
+                            field_full_span,
+                            ident_span,
+                            receiver_checked,
+                            *name,
+                        )?);
+                    } else {
+                        let function_name =
+                            self.method_call_target(&receiver_checked, *ident_span, *name)?;
+                        arguments_checked.push(receiver_checked);
+                        method_callable = ast::Expression::Identifier(*ident_span, function_name);
+                        callable = &method_callable;
+                        span = ident_span;
+                    }
+                }
+
+                for arg in args {
+                    arguments_checked.push(self.elaborate_expression(arg)?);
+                }
+                let mut named_arguments_checked = vec![];
+                for (name_span, name, arg) in named_args {
+                    named_arguments_checked.push((
+                        *name_span,
+                        *name,
+                        self.elaborate_expression(arg)?,
+                    ));
+                }
                 let argument_types = arguments_checked
                     .iter()
-                    .map(|e| e.get_type())
+                    .map(|e| e.get_type(&self.arena))
                     .collect::<Vec<Type>>();
 
                 // There are two options here. The 'callable' can either be a direct reference
@@ -749,19 +2647,39 @@ impl TypeChecker {
                 // that evaluates to a function "pointer".
 
                 if let Some((name, signature)) = self.get_proper_function_reference(callable) {
-                    let name = name.clone(); // TODO: there is probably a better way to get around borrowing issues here
-                    let signature = signature.clone(); // TODO: same
-                    self.proper_function_call(
+                    let signature = signature.clone(); // TODO: there is probably a better way to get around borrowing issues here
+                    if !named_arguments_checked.is_empty() {
+                        arguments_checked = self.resolve_named_arguments(
+                            span,
+                            name,
+                            &signature,
+                            arguments_checked,
+                            named_arguments_checked,
+                        )?;
+                    }
+                    let call = self.proper_function_call(
                         span,
                         full_span,
-                        &name,
+                        name,
                         &signature,
                         arguments_checked,
-                        argument_types,
-                    )?
+                        *syntax,
+                    )?;
+                    if self.generic_functions.contains_key(&name) {
+                        self.specialize(span, &signature, call)?
+                    } else {
+                        call
+                    }
                 } else {
-                    let callable_checked = self.elaborate_expression(callable)?;
-                    let callable_type = callable_checked.get_type();
+                    let callable_checked = match callable_checked {
+                        Some(callable_checked) => callable_checked,
+                        None => self.elaborate_expression(callable)?,
+                    };
+
+                    if let Some((name_span, _, _)) = named_arguments_checked.first() {
+                        return Err(TypeCheckError::NamedArgumentsInCallableCall(*name_span));
+                    }
+                    let callable_type = callable_checked.get_type(&self.arena);
 
                     let parameter_types = (0..arguments_checked.len())
                         .map(|_| self.fresh_type_variable())
@@ -823,7 +2741,7 @@ impl TypeChecker {
                             .is_trivially_violated()
                         {
                             return Err(TypeCheckError::IncompatibleTypesInFunctionCall(
-                                Some(arguments_checked.full_span()),
+                                Some(arguments_checked.full_span(&self.arena)),
                                 argument_type.clone(),
                                 callable.full_span(),
                                 parameter_type.clone(),
@@ -833,8 +2751,8 @@ impl TypeChecker {
 
                     typed_ast::Expression::CallableCall(
                         *full_span,
-                        Box::new(callable_checked),
-                        arguments_checked,
+                        self.arena.alloc(callable_checked),
+                        self.alloc_all(arguments_checked),
                         TypeScheme::concrete(return_type),
                     )
                 }
@@ -853,26 +2771,86 @@ impl TypeChecker {
                         } => Ok(typed_ast::StringPart::Interpolation {
                             span: *span,
                             format_specifiers: format_specifiers.clone(),
-                            expr: Box::new(self.elaborate_expression(expr)?),
+                            expr: {
+                                let expr = self.elaborate_expression(expr)?;
+                                self.arena.alloc(expr)
+                            },
                         }),
                     })
                     .collect::<Result<_>>()?,
             ),
+            ast::Expression::ChainedComparison(first, rest) => {
+                // Each comparison is checked on its own, so orderings of DateTimes
+                // and the error messages work just like for a single comparison.
+                let mut lhs: &ast::Expression = first;
+                let mut comparisons = vec![];
+                for (span_op, op, rhs) in rest {
+                    let comparison =
+                        self.elaborate_expression(&ast::Expression::BinaryOperator {
+                            op: *op,
+                            lhs: Box::new(lhs.clone()),
+                            rhs: Box::new(rhs.clone()),
+                            span_op: Some(*span_op),
+                        })?;
+                    comparisons.push(self.arena.alloc(comparison));
+                    lhs = rhs;
+                }
+
+                let is_increasing = |op: &BinaryOperator| {
+                    matches!(op, BinaryOperator::LessThan | BinaryOperator::LessOrEqual)
+                };
+                let (first_span, first_op, _) = &rest[0];
+                if let Some((span, _, _)) = rest
+                    .iter()
+                    .find(|(_, op, _)| is_increasing(op) != is_increasing(first_op))
+                {
+                    self.warnings
+                        .push(TypeCheckWarning::MixedComparisonDirections(
+                            *first_span,
+                            *span,
+                        ));
+                }
+
+                typed_ast::Expression::ChainedComparison(comparisons)
+            }
             ast::Expression::Condition(span, condition, then, else_) => {
+                // Only the branch that is taken is checked if the condition can be
+                // decided right away, so the other one may contain type errors.
+                if let Some(value) = self.static_condition(condition)? {
+                    return self.elaborate_expression(if value { then } else { else_ });
+                }
+
                 let condition = self.elaborate_expression(condition)?;
+                let condition = if condition.get_type(&self.arena) == Type::Dynamic {
+                    self.dynamic_check(condition, Type::Boolean)
+                } else {
+                    condition
+                };
 
                 if self
-                    .add_equal_constraint(&condition.get_type(), &Type::Boolean)
+                    .add_equal_constraint(&condition.get_type(&self.arena), &Type::Boolean)
                     .is_trivially_violated()
                 {
-                    return Err(TypeCheckError::ExpectedBool(condition.full_span()));
+                    return Err(TypeCheckError::ExpectedBool(
+                        condition.full_span(&self.arena),
+                    ));
                 }
 
                 let then = self.elaborate_expression(then)?;
                 let else_ = self.elaborate_expression(else_)?;
 
-                let then_type = then.get_type();
-                let else_type = else_.get_type();
+                // If only one of the branches is `Dynamic`, the condition has the
+                // type of the other branch
+                let (then, else_) = if (then.get_type(&self.arena) == Type::Dynamic)
+                    != (else_.get_type(&self.arena) == Type::Dynamic)
+                {
+                    (self.check_if_dynamic(then), self.check_if_dynamic(else_))
+                } else {
+                    (then, else_)
+                };
+
+                let then_type = then.get_type(&self.arena);
+                let else_type = else_.get_type(&self.arena);
 
                 if self
                     .add_equal_constraint(&then_type, &else_type)
@@ -881,34 +2859,76 @@ impl TypeChecker {
                     return Err(TypeCheckError::IncompatibleTypesInCondition(
                         *span,
                         then_type,
-                        then.full_span(),
+                        then.full_span(&self.arena),
                         else_type,
-                        else_.full_span(),
+                        else_.full_span(&self.arena),
                     ));
                 }
 
                 typed_ast::Expression::Condition(
                     *span,
-                    Box::new(condition),
-                    Box::new(then),
-                    Box::new(else_),
+                    self.arena.alloc(condition),
+                    self.arena.alloc(then),
+                    self.arena.alloc(else_),
                 )
             }
             ast::Expression::InstantiateStruct {
                 full_span,
                 ident_span,
                 name,
+                base,
                 fields,
             } => {
+                let base_checked = base
+                    .as_ref()
+                    .map(|b| self.elaborate_expression(b))
+                    .transpose()?;
+
                 let fields_checked = fields
                     .iter()
-                    .map(|(_, n, v)| Ok((n.to_string(), self.elaborate_expression(v)?)))
+                    .map(|(_, n, v)| Ok((*n, self.elaborate_expression(v)?)))
                     .collect::<Result<Vec<_>>>()?;
 
                 let Some(struct_info) = self.structs.get(name).cloned() else {
-                    return Err(TypeCheckError::UnknownStruct(*ident_span, name.clone()));
+                    return Err(TypeCheckError::UnknownStruct(*ident_span, name.to_string()));
                 };
 
+                let base_checked = base_checked.map(|base| {
+                    if base.get_type(&self.arena) == Type::Dynamic {
+                        self.dynamic_check(base, Type::Struct(struct_info.clone()))
+                    } else {
+                        base
+                    }
+                });
+                let fields_checked = fields_checked
+                    .into_iter()
+                    .map(|(field, expr)| match struct_info.fields.get(&field) {
+                        Some((_, field_type))
+                            if expr.get_type(&self.arena) == Type::Dynamic
+                                && field_type != &Type::Dynamic =>
+                        {
+                            (field, self.dynamic_check(expr, field_type.clone()))
+                        }
+                        _ => (field, expr),
+                    })
+                    .collect::<Vec<_>>();
+
+                if let Some(base_checked) = &base_checked {
+                    let expected_type = Type::Struct(struct_info.clone());
+                    let found_type = base_checked.get_type(&self.arena);
+                    if self
+                        .add_equal_constraint(&found_type, &expected_type)
+                        .is_trivially_violated()
+                    {
+                        return Err(TypeCheckError::IncompatibleBaseInStructUpdate(
+                            *ident_span,
+                            expected_type,
+                            base_checked.full_span(&self.arena),
+                            found_type,
+                        ));
+                    }
+                }
+
                 let mut seen_fields = HashMap::new();
 
                 for ((field, expr), span) in
@@ -927,12 +2947,12 @@ impl TypeChecker {
                         return Err(TypeCheckError::UnknownFieldInStructInstantiation(
                             *span,
                             struct_info.definition_span,
-                            field.clone(),
-                            struct_info.name.clone(),
+                            field.to_string(),
+                            struct_info.name.to_string(),
                         ));
                     };
 
-                    let found_type = &expr.get_type();
+                    let found_type = &expr.get_type(&self.arena);
                     if self
                         .add_equal_constraint(found_type, expected_type)
                         .is_trivially_violated()
@@ -940,7 +2960,7 @@ impl TypeChecker {
                         return Err(TypeCheckError::IncompatibleTypesForStructField(
                             *expected_field_span,
                             expected_type.clone(),
-                            expr.full_span(),
+                            expr.full_span(&self.arena),
                             found_type.clone(),
                         ));
                     }
@@ -951,10 +2971,13 @@ impl TypeChecker {
                 let missing_fields = {
                     let mut fields = struct_info.fields.clone();
                     fields.retain(|f, _| !seen_fields.contains_key(f));
-                    fields.into_iter().map(|(n, (_, t))| (n, t)).collect_vec()
+                    fields
+                        .into_iter()
+                        .map(|(n, (_, t))| (n.to_string(), t))
+                        .collect_vec()
                 };
 
-                if !missing_fields.is_empty() {
+                if base_checked.is_none() && !missing_fields.is_empty() {
                     return Err(TypeCheckError::MissingFieldsInStructInstantiation(
                         *full_span,
                         struct_info.definition_span,
@@ -964,57 +2987,17 @@ impl TypeChecker {
 
                 typed_ast::Expression::InstantiateStruct(
                     *full_span,
-                    fields_checked,
+                    base_checked.map(|base| self.arena.alloc(base)),
+                    fields_checked
+                        .into_iter()
+                        .map(|(name, expr)| (name, self.arena.alloc(expr)))
+                        .collect(),
                     struct_info.clone(),
                 )
             }
             ast::Expression::AccessField(full_span, ident_span, expr, field_name) => {
                 let expr_checked = self.elaborate_expression(expr)?;
-
-                let type_ = expr_checked.get_type();
-
-                let field_type = if type_.is_closed() {
-                    let Type::Struct(ref struct_info) = type_ else {
-                        return Err(TypeCheckError::FieldAccessOfNonStructType(
-                            *ident_span,
-                            expr.full_span(),
-                            field_name.to_string(),
-                            type_.clone(),
-                        ));
-                    };
-
-                    let Some((_, field_type)) = struct_info.fields.get(field_name) else {
-                        return Err(TypeCheckError::UnknownFieldAccess(
-                            *ident_span,
-                            expr.full_span(),
-                            field_name.to_string(),
-                            type_.clone(),
-                        ));
-                    };
-
-                    field_type.clone()
-                } else {
-                    let field_type = self.fresh_type_variable();
-
-                    self.constraints
-                        .add(Constraint::HasField(
-                            type_.clone(),
-                            field_name.clone(),
-                            field_type.clone(),
-                        ))
-                        .ok();
-
-                    field_type
-                };
-
-                Expression::AccessField(
-                    *ident_span,
-                    *full_span,
-                    Box::new(expr_checked),
-                    field_name.to_owned(),
-                    TypeScheme::concrete(type_),
-                    TypeScheme::concrete(field_type),
-                )
+                self.elaborate_field_access(full_span, ident_span, expr_checked, *field_name)?
             }
             ast::Expression::List(span, elements) => {
                 let elements_checked = elements
@@ -1022,8 +3005,23 @@ impl TypeChecker {
                     .map(|e| self.elaborate_expression(e))
                     .collect::<Result<Vec<_>>>()?;
 
-                let element_types: Vec<Type> =
-                    elements_checked.iter().map(|e| e.get_type()).collect();
+                // `Dynamic` elements are checked to have the type of the other elements
+                let elements_checked = if elements_checked
+                    .iter()
+                    .all(|e| e.get_type(&self.arena) == Type::Dynamic)
+                {
+                    elements_checked
+                } else {
+                    elements_checked
+                        .into_iter()
+                        .map(|e| self.check_if_dynamic(e))
+                        .collect()
+                };
+
+                let element_types: Vec<Type> = elements_checked
+                    .iter()
+                    .map(|e| e.get_type(&self.arena))
+                    .collect();
 
                 let result_element_type = if element_types.is_empty() {
                     self.fresh_type_variable()
@@ -1044,9 +3042,9 @@ impl TypeChecker {
                             .is_trivially_violated()
                         {
                             return Err(TypeCheckError::IncompatibleTypesInList(
-                                elements_checked[0].full_span(),
+                                elements_checked[0].full_span(&self.arena),
                                 result_element_type.clone(),
-                                subsequent_element.full_span(),
+                                subsequent_element.full_span(&self.arena),
                                 type_of_subsequent_element.clone(),
                             ));
                         }
@@ -1055,7 +3053,7 @@ impl TypeChecker {
 
                 typed_ast::Expression::List(
                     *span,
-                    elements_checked,
+                    self.alloc_all(elements_checked),
                     TypeScheme::concrete(result_element_type),
                 )
             }
@@ -1063,35 +3061,256 @@ impl TypeChecker {
                 let type_ = self.fresh_type_variable();
                 typed_ast::Expression::TypedHole(*span, TypeScheme::concrete(type_))
             }
+            ast::Expression::Partial(span, function, args, named_args) => {
+                self.partial_application(span, function, args, named_args)?
+            }
+            ast::Expression::AutoDiff(span, function) => self.autodiff(span, function)?,
+            ast::Expression::DimensionPredicate(span, expr, dimension) => {
+                typed_ast::Expression::Boolean(
+                    *span,
+                    self.dimension_predicate(expr, dimension.as_ref())?,
+                )
+            }
+            ast::Expression::Trace(span, _, expr)
+                if self.env.get_identifier_type(Symbol::new("trace")).is_some() =>
+            {
+                // A user-defined `trace` takes precedence over the debugging probe
+                self.elaborate_expression(&ast::Expression::FunctionCall(
+                    *span,
+                    *span,
+                    Box::new(ast::Expression::Identifier(*span, Symbol::new("trace"))),
+                    vec![expr.as_ref().clone()],
+                    vec![],
+                    CallSyntax::Regular,
+                ))?
+            }
+            ast::Expression::Trace(span, source, expr) => {
+                typed_ast::Expression::Trace(*span, source.clone(), {
+                    let expr = self.elaborate_expression(expr)?;
+                    self.arena.alloc(expr)
+                })
+            }
+            ast::Expression::For {
+                full_span,
+                variable: (variable_span, variable),
+                list,
+                accumulator,
+                body,
+            } => {
+                let mut list_checked = self.elaborate_expression(list)?;
+                let list_type = list_checked.get_type(&self.arena);
+
+                let element_type = match &list_type {
+                    Type::List(element_type) => element_type.as_ref().clone(),
+                    Type::Dynamic => {
+                        list_checked =
+                            self.dynamic_check(list_checked, Type::List(Box::new(Type::Dynamic)));
+                        Type::Dynamic
+                    }
+                    type_ if type_.is_closed() => {
+                        return Err(TypeCheckError::LoopOverNonListType(
+                            list_checked.full_span(&self.arena),
+                            list_type,
+                        ));
+                    }
+                    type_ => {
+                        let element_type = self.fresh_type_variable();
+                        if self
+                            .add_equal_constraint(
+                                type_,
+                                &Type::List(Box::new(element_type.clone())),
+                            )
+                            .is_trivially_violated()
+                        {
+                            return Err(TypeCheckError::LoopOverNonListType(
+                                list_checked.full_span(&self.arena),
+                                list_type,
+                            ));
+                        }
+                        element_type
+                    }
+                };
+
+                let init_checked = accumulator
+                    .as_ref()
+                    .map(|(_, _, init)| self.elaborate_expression(init))
+                    .transpose()?;
+
+                let shadowed_variable =
+                    self.env
+                        .begin_scope(*variable, element_type, *variable_span);
+                let shadowed_accumulator = match (accumulator, &init_checked) {
+                    (Some((span, name, _)), Some(init)) => {
+                        let init_type = init.get_type(&self.arena);
+                        Some((*name, self.env.begin_scope(*name, init_type, *span)))
+                    }
+                    _ => None,
+                };
+
+                let body_checked = self.elaborate_expression(body);
+
+                if let Some((name, shadowed)) = shadowed_accumulator {
+                    self.env.end_scope(name, shadowed);
+                }
+                self.env.end_scope(*variable, shadowed_variable);
+
+                let body_checked = body_checked?;
+
+                let accumulator = match (accumulator, init_checked) {
+                    (Some((_, name, _)), Some(init)) => {
+                        self.check_accumulator_type(&init, &body_checked)?;
+                        Some((*name, self.arena.alloc(init)))
+                    }
+                    _ => None,
+                };
+
+                typed_ast::Expression::For(
+                    *full_span,
+                    *variable,
+                    self.arena.alloc(list_checked),
+                    accumulator,
+                    self.arena.alloc(body_checked),
+                )
+            }
+            ast::Expression::While {
+                full_span,
+                condition,
+                accumulator: (span, name, init),
+                body,
+            } => {
+                let init_checked = self.elaborate_expression(init)?;
+                let init_type = init_checked.get_type(&self.arena);
+
+                let shadowed = self.env.begin_scope(*name, init_type, *span);
+                let checked = self.elaborate_expression(condition).and_then(|condition| {
+                    let body = self.elaborate_expression(body)?;
+                    Ok((condition, body))
+                });
+                self.env.end_scope(*name, shadowed);
+
+                let (condition_checked, body_checked) = checked?;
+
+                if self
+                    .add_equal_constraint(&condition_checked.get_type(&self.arena), &Type::Boolean)
+                    .is_trivially_violated()
+                {
+                    return Err(TypeCheckError::ExpectedBool(
+                        condition_checked.full_span(&self.arena),
+                    ));
+                }
+
+                self.check_accumulator_type(&init_checked, &body_checked)?;
+
+                typed_ast::Expression::While(
+                    *full_span,
+                    self.arena.alloc(condition_checked),
+                    (*name, self.arena.alloc(init_checked)),
+                    self.arena.alloc(body_checked),
+                )
+            }
+            ast::Expression::TryCatch {
+                full_span,
+                expr,
+                message,
+                fallback,
+            } => {
+                let expr_checked = self.elaborate_expression(expr)?;
+
+                let fallback_checked = if let Some((span, name)) = message {
+                    let shadowed = self.env.begin_scope(*name, Type::String, *span);
+                    let fallback_checked = self.elaborate_expression(fallback);
+                    self.env.end_scope(*name, shadowed);
+                    fallback_checked?
+                } else {
+                    self.elaborate_expression(fallback)?
+                };
+
+                let expr_type = expr_checked.get_type(&self.arena);
+                let fallback_type = fallback_checked.get_type(&self.arena);
+
+                if self
+                    .add_equal_constraint(&expr_type, &fallback_type)
+                    .is_trivially_violated()
+                {
+                    return Err(TypeCheckError::IncompatibleTypesInTryCatch(
+                        *full_span,
+                        expr_type,
+                        expr_checked.full_span(&self.arena),
+                        fallback_type,
+                        fallback_checked.full_span(&self.arena),
+                    ));
+                }
+
+                typed_ast::Expression::TryCatch(
+                    *full_span,
+                    self.arena.alloc(expr_checked),
+                    message.map(|(_, name)| name),
+                    self.arena.alloc(fallback_checked),
+                )
+            }
         })
     }
 
+    /// The value of a loop body becomes the next value of the accumulator, so both
+    /// need to have the same type.
+    fn check_accumulator_type(
+        &mut self,
+        init: &typed_ast::Expression,
+        body: &typed_ast::Expression,
+    ) -> Result<()> {
+        let init_type = init.get_type(&self.arena);
+        let body_type = body.get_type(&self.arena);
+
+        if self
+            .add_equal_constraint(&init_type, &body_type)
+            .is_trivially_violated()
+        {
+            return Err(TypeCheckError::IncompatibleTypesInAccumulator(
+                init.full_span(&self.arena),
+                init_type,
+                body.full_span(&self.arena),
+                body_type,
+            ));
+        }
+
+        Ok(())
+    }
+
     fn elaborate_define_variable(
         &mut self,
         define_variable: &ast::DefineVariable,
     ) -> Result<typed_ast::DefineVariable> {
         let DefineVariable {
-            identifier_span,
-            identifier,
+            pattern,
             expr,
             type_annotation,
             decorators,
         } = define_variable;
+        let pattern_span = pattern.full_span();
 
-        let expr_checked = self.elaborate_expression(expr)?;
-        let type_deduced = expr_checked.get_type();
+        let mut expr_checked = self.elaborate_expression(expr)?;
+        let mut type_deduced = expr_checked.get_type(&self.arena);
 
         if let Some(ref type_annotation) = type_annotation {
             let type_annotated = self.type_from_annotation(type_annotation)?;
 
             match (&type_deduced, &type_annotated) {
+                (_, Type::Dynamic) => {
+                    self.add_equal_constraint(&type_deduced, &type_annotated)
+                        .ok();
+                    type_deduced = Type::Dynamic;
+                }
+                (Type::Dynamic, _) => {
+                    expr_checked = self.dynamic_check(expr_checked, type_annotated.clone());
+                    type_deduced = type_annotated;
+                }
                 (Type::Dimension(dexpr_deduced), Type::Dimension(dexpr_specified))
                     if type_deduced.is_closed() && type_annotated.is_closed() =>
                 {
                     if dexpr_deduced != dexpr_specified {
                         return Err(TypeCheckError::IncompatibleDimensions(
                             IncompatibleDimensionsError {
-                                span_operation: *identifier_span,
+                                span_operation: pattern_span,
                                 operation: "variable definition".into(),
                                 span_expected: type_annotation.full_span(),
                                 expected_name: "specified dimension",
@@ -1106,6 +3325,8 @@ impl TypeChecker {
                                     &dexpr_deduced.to_base_representation(),
                                 ),
                                 actual_type: dexpr_deduced.to_base_representation(),
+                                hint: None,
+                                note: None,
                             },
                         ));
                     }
@@ -1117,54 +3338,276 @@ impl TypeChecker {
                     {
                         return Err(TypeCheckError::IncompatibleTypesInAnnotation(
                             "definition".into(),
-                            *identifier_span,
+                            pattern_span,
                             annotated.clone(),
                             type_annotation.full_span(),
                             deduced.clone(),
-                            expr_checked.full_span(),
+                            expr_checked.full_span(&self.arena),
                         ));
                     }
                 }
             }
         }
 
-        for (name, _) in decorator::name_and_aliases(identifier, decorators) {
-            self.env
-                .add(name.clone(), type_deduced.clone(), *identifier_span, false);
+        let pattern_checked = if let ast::Pattern::Identifier(identifier_span, identifier) = pattern
+        {
+            for (name, _) in decorator::name_and_aliases(*identifier, decorators) {
+                self.env
+                    .add(name, type_deduced.clone(), *identifier_span, false);
 
-            self.value_namespace.add_identifier_allow_override(
-                name.clone(),
-                *identifier_span,
-                "constant".to_owned(),
-            )?;
-        }
+                self.value_namespace.add_identifier_allow_override(
+                    name,
+                    *identifier_span,
+                    "constant".to_owned(),
+                )?;
+            }
+
+            typed_ast::Pattern::Identifier(*identifier)
+        } else {
+            let mut bound = HashMap::new();
+            for (span, identifier) in pattern.identifiers() {
+                if let Some(other_span) = bound.insert(identifier, span) {
+                    return Err(TypeCheckError::DuplicateBindingInPattern(
+                        span,
+                        other_span,
+                        identifier.to_string(),
+                    ));
+                }
+            }
+
+            self.elaborate_pattern(pattern, &type_deduced, expr.full_span())?
+        };
 
         Ok(typed_ast::DefineVariable(
-            identifier.clone(),
+            pattern_checked,
             decorators.clone(),
-            expr_checked,
+            self.arena.alloc(expr_checked),
             type_annotation.clone(),
             TypeScheme::concrete(type_deduced),
             crate::markup::empty(),
         ))
     }
 
+    /// Binds all identifiers in a destructuring pattern to the corresponding parts
+    /// of a value of type `type_`.
+    fn elaborate_pattern(
+        &mut self,
+        pattern: &ast::Pattern,
+        type_: &Type,
+        expr_span: Span,
+    ) -> Result<typed_ast::Pattern> {
+        match pattern {
+            ast::Pattern::Identifier(span, identifier) => {
+                self.env.add(*identifier, type_.clone(), *span, false);
+                self.value_namespace.add_identifier_allow_override(
+                    *identifier,
+                    *span,
+                    "constant".to_owned(),
+                )?;
+
+                Ok(typed_ast::Pattern::Identifier(*identifier))
+            }
+            ast::Pattern::Struct(span, fields) => {
+                let struct_info = if type_.is_closed() {
+                    let Type::Struct(struct_info) = type_ else {
+                        return Err(TypeCheckError::DestructuringOfNonStructType(
+                            *span,
+                            expr_span,
+                            type_.clone(),
+                        ));
+                    };
+                    Some(struct_info)
+                } else {
+                    None
+                };
+
+                let mut fields_checked = vec![];
+                for (field_span, field, field_pattern) in fields {
+                    let field_type = if let Some(struct_info) = struct_info {
+                        let Some((_, field_type)) = struct_info.fields.get(field) else {
+                            return Err(TypeCheckError::UnknownFieldAccess(
+                                *field_span,
+                                expr_span,
+                                field.to_string(),
+                                type_.clone(),
+                            ));
+                        };
+                        field_type.clone()
+                    } else {
+                        let field_type = self.fresh_type_variable();
+                        self.constraints
+                            .add(Constraint::HasField(
+                                type_.clone(),
+                                *field,
+                                field_type.clone(),
+                            ))
+                            .ok();
+                        field_type
+                    };
+
+                    fields_checked.push((
+                        *field,
+                        self.elaborate_pattern(field_pattern, &field_type, expr_span)?,
+                    ));
+                }
+
+                if let Some(struct_info) = struct_info {
+                    let missing_fields: Vec<_> = struct_info
+                        .fields
+                        .iter()
+                        .filter(|(name, _)| !fields.iter().any(|(_, field, _)| field == *name))
+                        .map(|(name, (_, type_))| (name.to_string(), type_.clone()))
+                        .collect();
+
+                    if !missing_fields.is_empty() {
+                        return Err(TypeCheckError::MissingFieldsInDestructuring(
+                            *span,
+                            struct_info.definition_span,
+                            missing_fields,
+                        ));
+                    }
+                }
+
+                Ok(typed_ast::Pattern::Struct(
+                    fields_checked,
+                    TypeScheme::concrete(type_.clone()),
+                ))
+            }
+            ast::Pattern::List(span, elements) => {
+                let element_type = match type_ {
+                    Type::List(element_type) => element_type.as_ref().clone(),
+                    type_ if type_.is_closed() => {
+                        return Err(TypeCheckError::DestructuringOfNonListType(
+                            *span,
+                            expr_span,
+                            type_.clone(),
+                        ));
+                    }
+                    type_ => {
+                        let element_type = self.fresh_type_variable();
+                        if self
+                            .add_equal_constraint(
+                                type_,
+                                &Type::List(Box::new(element_type.clone())),
+                            )
+                            .is_trivially_violated()
+                        {
+                            return Err(TypeCheckError::DestructuringOfNonListType(
+                                *span,
+                                expr_span,
+                                type_.clone(),
+                            ));
+                        }
+                        element_type
+                    }
+                };
+
+                Ok(typed_ast::Pattern::List(
+                    elements
+                        .iter()
+                        .map(|element| self.elaborate_pattern(element, &element_type, expr_span))
+                        .collect::<Result<_>>()?,
+                ))
+            }
+        }
+    }
+
+    /// Removes all definitions of the given name, see [`ast::ImportScope`]
+    fn take_definition(&mut self, name: Symbol) -> NameDefinition {
+        NameDefinition {
+            identifier: self.env.take(name),
+            value_namespace_entry: self.value_namespace.take_identifier(name),
+            type_namespace_entry: self.type_namespace.take_identifier(name),
+            dimension: self.registry.take_dimension(name),
+            struct_info: self
+                .structs
+                .remove(&name)
+                .map(|info| (info, self.methods.remove(&name))),
+            unit_function: self.unit_functions.remove(&name),
+            is_unit_system: self.unit_systems.remove(&name),
+            function_definition: self.function_definitions.remove(&name),
+        }
+    }
+
+    /// Replaces the current definitions of the name by ones that have been
+    /// removed with [`Self::take_definition`]
+    fn restore_definition(&mut self, name: Symbol, definition: NameDefinition) {
+        self.take_definition(name);
+        self.env.end_scope(name, definition.identifier);
+        self.value_namespace
+            .restore_identifier(name, definition.value_namespace_entry);
+        self.type_namespace
+            .restore_identifier(name, definition.type_namespace_entry);
+        self.registry.restore_dimension(name, definition.dimension);
+        if let Some((struct_info, methods)) = definition.struct_info {
+            self.structs.insert(name, struct_info);
+            if let Some(methods) = methods {
+                self.methods.insert(name, methods);
+            }
+        }
+        if let Some(inverse) = definition.unit_function {
+            self.unit_functions.insert(name, inverse);
+        }
+        if definition.is_unit_system {
+            self.unit_systems.insert(name);
+        }
+        if let Some(function_definition) = definition.function_definition {
+            self.function_definitions.insert(name, function_definition);
+        }
+    }
+
+    fn apply_import_scope(&mut self, scope: &ast::ImportScope) {
+        match scope {
+            ast::ImportScope::Begin(names) => {
+                let stashed = names
+                    .iter()
+                    .map(|name| (*name, self.take_definition(*name)))
+                    .collect();
+                self.stashed_definitions.push(stashed);
+            }
+            ast::ImportScope::End(items) => {
+                for item in items {
+                    let definition = self.take_definition(item.1);
+                    self.hidden_definitions.insert(item.clone(), definition);
+                }
+                for (name, definition) in self.stashed_definitions.pop().unwrap_or_default() {
+                    self.restore_definition(name, definition);
+                }
+            }
+            ast::ImportScope::Reveal(items) => {
+                for item in items {
+                    if let Some(definition) = self.hidden_definitions.remove(item) {
+                        self.restore_definition(item.1, definition);
+                    }
+                }
+            }
+        }
+    }
+
     fn elaborate_statement(&mut self, ast: &ast::Statement) -> Result<typed_ast::Statement> {
         Ok(match ast {
             ast::Statement::Expression(expr) => {
                 let checked_expr = self.elaborate_expression(expr)?;
-                for &identifier in LAST_RESULT_IDENTIFIERS {
+                self.num_results += 1;
+                let indexed_identifier = format!("_{}", self.num_results);
+                for identifier in LAST_RESULT_IDENTIFIERS
+                    .iter()
+                    .copied()
+                    .chain(std::iter::once(indexed_identifier.as_str()))
+                {
                     self.env.add_predefined(
                         identifier.into(),
-                        TypeScheme::concrete(checked_expr.get_type()),
+                        TypeScheme::concrete(checked_expr.get_type(&self.arena)),
                     );
                 }
-                typed_ast::Statement::Expression(checked_expr)
+                typed_ast::Statement::Expression(self.arena.alloc(checked_expr))
             }
             ast::Statement::DefineVariable(define_variable) => {
-                typed_ast::Statement::DefineVariable(
-                    self.elaborate_define_variable(define_variable)?,
-                )
+                let typed_define_variable = self.elaborate_define_variable(define_variable)?;
+                if let ast::Pattern::Identifier(span, name) = &define_variable.pattern {
+                    self.mark_deprecated([*name], *span, &define_variable.decorators);
+                }
+                typed_ast::Statement::DefineVariable(typed_define_variable)
             }
             ast::Statement::DefineBaseUnit(span, unit_name, type_annotation, decorators) => {
                 let type_specified = if let Some(dexpr) = type_annotation {
@@ -1177,35 +3620,53 @@ impl TypeChecker {
                     if dtype.is_scalar() {
                         return Err(TypeCheckError::NoDimensionlessBaseUnit(
                             *span,
-                            unit_name.into(),
+                            unit_name.to_string(),
                         ));
                     }
 
                     dtype
                 } else {
-                    use heck::ToUpperCamelCase;
                     // In a unit definition like 'unit pixel' without a specified type,
                     // we add a new type for the user
-                    let type_name = unit_name.to_upper_camel_case();
-                    self.registry
-                        .add_base_dimension(&type_name)
-                        .map_err(TypeCheckError::RegistryError)?
-                        .into()
+                    let type_name = implicit_dimension_name(*unit_name, decorators);
+                    self.type_namespace
+                        .add_identifier(type_name, *span, "dimension".to_owned())
+                        .map_err(|e| match e {
+                            NameResolutionError::IdentifierClash { original_span, .. } => {
+                                TypeCheckError::ImplicitDimensionClash(
+                                    *span,
+                                    unit_name.to_string(),
+                                    type_name.to_string(),
+                                    original_span,
+                                )
+                            }
+                            e => TypeCheckError::NameResolutionError(e),
+                        })?;
+                    match self.registry.add_base_dimension(type_name, *span) {
+                        Ok(base_representation) => base_representation.into(),
+                        Err(e) => {
+                            self.type_namespace.remove_identifier(type_name);
+                            return Err(TypeCheckError::RegistryError(e));
+                        }
+                    }
                 };
-                for (name, _) in decorator::name_and_aliases(unit_name, decorators) {
-                    self.env.add(
-                        name.clone(),
-                        Type::Dimension(type_specified.clone()),
-                        *span,
-                        true,
-                    );
+                for (name, _) in decorator::name_and_aliases(*unit_name, decorators) {
+                    self.env
+                        .add(name, Type::Dimension(type_specified.clone()), *span, true);
                 }
+                self.mark_deprecated(
+                    decorator::name_and_aliases(*unit_name, decorators).map(|(name, _)| name),
+                    *span,
+                    decorators,
+                );
 
                 typed_ast::Statement::DefineBaseUnit(
-                    unit_name.clone(),
+                    *span,
+                    *unit_name,
                     decorators.clone(),
                     type_annotation.clone().map(TypeAnnotation::TypeExpression),
                     TypeScheme::concrete(Type::Dimension(type_specified)),
+                    type_annotation.is_none(),
                 )
             }
             ast::Statement::DefineDerivedUnit {
@@ -1220,7 +3681,7 @@ impl TypeChecker {
                 // variable definitions => deduplicate this somehow
 
                 let expr_checked = self.elaborate_expression(expr)?;
-                let type_deduced = expr_checked.get_type();
+                let type_deduced = expr_checked.get_type(&self.arena);
 
                 if let Some(ref type_annotation) = type_annotation {
                     let type_annotated = self.type_from_annotation(type_annotation)?;
@@ -1251,6 +3712,8 @@ impl TypeChecker {
                                                 &dexpr_deduced.to_base_representation(),
                                             ),
                                         actual_type: dexpr_deduced.to_base_representation(),
+                                        hint: None,
+                                        note: None,
                                     },
                                 ));
                             }
@@ -1266,20 +3729,26 @@ impl TypeChecker {
                                     annotated.clone(),
                                     type_annotation.full_span(),
                                     deduced.clone(),
-                                    expr_checked.full_span(),
+                                    expr_checked.full_span(&self.arena),
                                 ));
                             }
                         }
                     }
                 }
 
-                for (name, _) in decorator::name_and_aliases(identifier, decorators) {
+                for (name, _) in decorator::name_and_aliases(*identifier, decorators) {
                     self.env
-                        .add(name.clone(), type_deduced.clone(), *identifier_span, true);
+                        .add(name, type_deduced.clone(), *identifier_span, true);
                 }
+                self.mark_deprecated(
+                    decorator::name_and_aliases(*identifier, decorators).map(|(name, _)| name),
+                    *identifier_span,
+                    decorators,
+                );
                 typed_ast::Statement::DefineDerivedUnit(
-                    identifier.clone(),
-                    expr_checked,
+                    *identifier_span,
+                    *identifier,
+                    self.arena.alloc(expr_checked),
                     decorators.clone(),
                     type_annotation.clone(),
                     TypeScheme::Concrete(type_deduced),
@@ -1293,49 +3762,120 @@ impl TypeChecker {
                 parameters,
                 body,
                 local_variables,
+                local_units,
                 return_type_annotation,
                 decorators,
             } => {
-                if body.is_none() {
+                if body.is_none() && self.native_functions.contains(function_name) {
+                    // Native functions can be registered again by the embedder
+                    self.value_namespace.add_identifier_allow_override(
+                        *function_name,
+                        *function_name_span,
+                        "native function".to_owned(),
+                    )?;
+                } else if body.is_none() {
                     self.value_namespace.add_identifier(
-                        function_name.clone(),
+                        *function_name,
                         *function_name_span,
                         "foreign function".to_owned(),
                     )?;
                 } else {
                     self.value_namespace.add_identifier_allow_override(
-                        function_name.clone(),
+                        *function_name,
                         *function_name_span,
                         "function".to_owned(),
                     )?;
                 }
 
+                // The body of a generic function that uses dimension predicates is only
+                // checked for its specializations. Only the signature is checked here.
+                let is_generic_with_predicates = !type_parameters.is_empty()
+                    && body.as_ref().is_some_and(|body| {
+                        std::iter::once(body)
+                            .chain(local_variables.iter().map(|v| &v.expr))
+                            .chain(parameters.iter().filter_map(|(_, _, _, d)| d.as_ref()))
+                            .any(|expr| self.uses_dimension_predicates(expr))
+                    });
+
+                // Move the arena instead of cloning it. All expressions of the function
+                // are allocated by `typechecker_fn`.
+                let arena = std::mem::take(&mut self.arena);
                 let mut typechecker_fn = self.clone();
+                typechecker_fn.arena = arena;
                 let is_ffi_function = body.is_none();
+                let function_definition = body.as_ref().map(|body| FunctionDefinition {
+                    type_parameters: type_parameters.clone(),
+                    parameters: parameters.clone(),
+                    local_variables: local_variables.clone(),
+                    local_unit_span: local_units.first().map(|unit| unit.span()),
+                    body: body.clone(),
+                });
+
+                // Units and dimensions from the `where` clause are checked before the
+                // parameters are introduced, since they are only evaluated once, when
+                // the function is defined. They disappear with `typechecker_fn`, except
+                // for the dimensions, which are removed from the registry below.
+                let mut typed_local_units = vec![];
+                let mut local_dimensions = vec![];
+                let mut local_unit_names = vec![];
+                for local_unit in local_units {
+                    match local_unit {
+                        ast::Statement::DefineDimension(_, name, _) => local_dimensions.push(*name),
+                        ast::Statement::DefineBaseUnit(span, name, type_annotation, decorators) => {
+                            if type_annotation.is_none() {
+                                local_dimensions.push(implicit_dimension_name(*name, decorators));
+                            }
+                            local_unit_names.extend(
+                                decorator::name_and_aliases(*name, decorators)
+                                    .map(|(alias, _)| (alias, *span)),
+                            );
+                        }
+                        ast::Statement::DefineDerivedUnit {
+                            identifier_span,
+                            identifier,
+                            decorators,
+                            ..
+                        } => local_unit_names.extend(
+                            decorator::name_and_aliases(*identifier, decorators)
+                                .map(|(alias, _)| (alias, *identifier_span)),
+                        ),
+                        _ => unreachable!("Only units and dimensions can be defined locally"),
+                    }
+                    typed_local_units.push(typechecker_fn.elaborate_statement(local_unit)?);
+                }
 
                 for (span, type_parameter, bound) in type_parameters {
-                    if typechecker_fn.type_namespace.has_identifier(type_parameter) {
+                    if typechecker_fn
+                        .type_namespace
+                        .has_identifier(*type_parameter)
+                    {
                         return Err(TypeCheckError::TypeParameterNameClash(
                             *span,
-                            type_parameter.clone(),
+                            type_parameter.to_string(),
                         ));
                     }
 
                     typechecker_fn
                         .type_namespace
-                        .add_identifier(type_parameter.clone(), *span, "type parameter".to_owned())
+                        .add_identifier(*type_parameter, *span, "type parameter".to_owned())
                         .ok(); // TODO: is this call even correct?
 
                     typechecker_fn.registry.introduced_type_parameters.push((
                         *span,
-                        type_parameter.clone(),
+                        *type_parameter,
                         bound.clone(),
                     ));
 
                     match bound {
                         Some(TypeParameterBound::Dim) => {
                             typechecker_fn
-                                .add_dtype_constraint(&Type::TPar(type_parameter.clone()))
+                                .add_dtype_constraint(&Type::TPar(*type_parameter))
+                                .ok();
+                        }
+                        Some(TypeParameterBound::Ord) => {
+                            typechecker_fn
+                                .constraints
+                                .add(Constraint::IsOrd(Type::TPar(*type_parameter)))
                                 .ok();
                         }
                         None => {}
@@ -1343,7 +3883,7 @@ impl TypeChecker {
                 }
 
                 let mut typed_parameters = vec![];
-                for (parameter_span, parameter, type_annotation) in parameters {
+                for (parameter_span, parameter, type_annotation, default_value) in parameters {
                     let annotated_type = type_annotation
                         .as_ref()
                         .map(|a| typechecker_fn.type_from_annotation(a))
@@ -1357,21 +3897,97 @@ impl TypeChecker {
                     if is_ffi_function && annotated_type.is_none() {
                         return Err(TypeCheckError::ForeignFunctionNeedsTypeAnnotations(
                             *parameter_span,
-                            parameter.clone(),
+                            parameter.to_string(),
+                        ));
+                    }
+
+                    if is_generic_with_predicates && annotated_type.is_none() {
+                        return Err(TypeCheckError::GenericFunctionNeedsTypeAnnotations(
+                            *parameter_span,
+                            function_name.to_string(),
+                        ));
+                    }
+
+                    if is_ffi_function && default_value.is_some() {
+                        return Err(TypeCheckError::ForeignFunctionWithDefaultValue(
+                            *parameter_span,
+                            parameter.to_string(),
                         ));
                     }
 
+                    // Default values are checked in the scope of the function, where
+                    // all previous parameters are already available.
+                    let default_value_checked = default_value
+                        .as_ref()
+                        .map(|expr| typechecker_fn.elaborate_expression(expr))
+                        .transpose()?;
+
+                    if let Some(ref default_value_checked) = default_value_checked {
+                        let type_deduced = default_value_checked.get_type(&typechecker_fn.arena);
+
+                        if typechecker_fn
+                            .add_equal_constraint(&type_deduced, &parameter_type)
+                            .is_trivially_violated()
+                        {
+                            let annotation = type_annotation.as_ref().unwrap();
+                            match (&type_deduced, &parameter_type) {
+                                (
+                                    Type::Dimension(dtype_deduced),
+                                    Type::Dimension(dtype_specified),
+                                ) => {
+                                    return Err(TypeCheckError::IncompatibleDimensions(
+                                        IncompatibleDimensionsError {
+                                            span_operation: *parameter_span,
+                                            operation: "default value of parameter".into(),
+                                            span_expected: annotation.full_span(),
+                                            expected_name: "specified dimension",
+                                            expected_dimensions: typechecker_fn
+                                                .registry
+                                                .get_derived_entry_names_for(
+                                                    &dtype_specified.to_base_representation(),
+                                                ),
+                                            expected_type: dtype_specified.to_base_representation(),
+                                            span_actual: default_value_checked
+                                                .full_span(&typechecker_fn.arena),
+                                            actual_name: "   actual dimension",
+                                            actual_name_for_fix: "default value",
+                                            actual_dimensions: typechecker_fn
+                                                .registry
+                                                .get_derived_entry_names_for(
+                                                    &dtype_deduced.to_base_representation(),
+                                                ),
+                                            actual_type: dtype_deduced.to_base_representation(),
+                                            hint: None,
+                                            note: None,
+                                        },
+                                    ));
+                                }
+                                (type_deduced, type_specified) => {
+                                    return Err(TypeCheckError::IncompatibleTypesInAnnotation(
+                                        "default value".into(),
+                                        *parameter_span,
+                                        type_specified.clone(),
+                                        annotation.full_span(),
+                                        type_deduced.clone(),
+                                        default_value_checked.full_span(&typechecker_fn.arena),
+                                    ));
+                                }
+                            }
+                        }
+                    }
+
                     typechecker_fn.env.add_scheme(
-                        parameter.clone(),
+                        *parameter,
                         TypeScheme::make_quantified(parameter_type.clone()),
                         *parameter_span,
                         false,
                     );
                     typed_parameters.push((
                         *parameter_span,
-                        parameter.clone(),
+                        *parameter,
                         parameter_type,
                         type_annotation,
+                        default_value_checked,
                     ));
                 }
 
@@ -1389,23 +4005,28 @@ impl TypeChecker {
 
                 let parameters: Vec<_> = typed_parameters
                     .iter()
-                    .map(|(span, name, _, annotation)| (*span, name.clone(), (*annotation).clone()))
+                    .map(|(span, name, _, annotation, _)| (*span, *name, (*annotation).clone()))
                     .collect();
+                let num_default_parameters = typed_parameters
+                    .iter()
+                    .filter(|(_, _, _, _, default_value)| default_value.is_some())
+                    .count();
                 let parameter_types = typed_parameters
                     .iter()
-                    .map(|(_, _, type_, _)| type_.clone())
+                    .map(|(_, _, type_, _, _)| type_.clone())
                     .collect();
 
                 let fn_type =
                     TypeScheme::Concrete(Type::Fn(parameter_types, Box::new(return_type.clone())));
 
                 typechecker_fn.env.add_function(
-                    function_name.clone(),
+                    *function_name,
                     FunctionSignature {
-                        name: function_name.clone(),
+                        name: *function_name,
                         definition_span: *function_name_span,
                         type_parameters: type_parameters.clone(),
                         parameters,
+                        num_default_parameters,
                         return_type_annotation: return_type_annotation.clone(),
                         fn_type: fn_type.clone(),
                     },
@@ -1417,18 +4038,33 @@ impl TypeChecker {
                 );
 
                 let mut typed_local_variables = vec![];
-                for local_variable in local_variables {
-                    typed_local_variables
-                        .push(typechecker_fn.elaborate_define_variable(local_variable)?);
+                let mut body_checked = None;
+                if !is_generic_with_predicates {
+                    for local_variable in local_variables {
+                        typed_local_variables
+                            .push(typechecker_fn.elaborate_define_variable(local_variable)?);
+                    }
+
+                    body_checked = body
+                        .as_ref()
+                        .map(|expr| typechecker_fn.elaborate_expression(expr))
+                        .transpose()?;
                 }
 
-                let body_checked = body
-                    .as_ref()
-                    .map(|expr| typechecker_fn.elaborate_expression(expr))
-                    .transpose()?;
+                if let Some(annotated_return_type) = &annotated_return_type {
+                    body_checked = body_checked.map(|expr| {
+                        if expr.get_type(&typechecker_fn.arena) == Type::Dynamic
+                            && annotated_return_type != &Type::Dynamic
+                        {
+                            typechecker_fn.dynamic_check(expr, annotated_return_type.clone())
+                        } else {
+                            expr
+                        }
+                    });
+                }
 
                 let return_type_inferred = if let Some(ref expr) = body_checked {
-                    let return_type_inferred = expr.get_type();
+                    let return_type_inferred = expr.get_type(&typechecker_fn.arena);
 
                     if typechecker_fn
                         .add_equal_constraint(&return_type_inferred, &return_type)
@@ -1467,6 +4103,8 @@ impl TypeChecker {
                                                     &dtype_deduced.to_base_representation(),
                                                 ),
                                             actual_type: dtype_deduced.to_base_representation(),
+                                            hint: None,
+                                            note: None,
                                         },
                                     ));
                                 }
@@ -1484,18 +4122,27 @@ impl TypeChecker {
                         }
                     }
                     return_type_inferred
+                } else if is_generic_with_predicates {
+                    annotated_return_type.ok_or_else(|| {
+                        TypeCheckError::GenericFunctionNeedsTypeAnnotations(
+                            *function_name_span,
+                            function_name.to_string(),
+                        )
+                    })?
                 } else {
-                    if !ffi::functions().contains_key(function_name.as_str()) {
+                    if !ffi::functions().contains_key(function_name.as_str())
+                        && !self.native_functions.contains(function_name)
+                    {
                         return Err(TypeCheckError::UnknownForeignFunction(
                             *function_name_span,
-                            function_name.clone(),
+                            function_name.to_string(),
                         ));
                     }
 
                     annotated_return_type.ok_or_else(|| {
                         TypeCheckError::ForeignFunctionNeedsTypeAnnotations(
                             *function_name_span,
-                            function_name.clone(),
+                            function_name.to_string(),
                         )
                     })?
                 };
@@ -1504,55 +4151,124 @@ impl TypeChecker {
                     .add_equal_constraint(&return_type_inferred, &return_type)
                     .ok();
 
+                self.arena = std::mem::take(&mut typechecker_fn.arena);
                 self.constraints = typechecker_fn.constraints;
                 self.name_generator = typechecker_fn.name_generator;
                 self.registry = typechecker_fn.registry;
+                self.warnings = typechecker_fn.warnings;
+                // Specializations that are needed by the body are defined globally
+                for specialization in typechecker_fn.specializations.keys() {
+                    if !self.specializations.contains_key(specialization) {
+                        let (signature, metadata) = typechecker_fn
+                            .env
+                            .get_function_info(*specialization)
+                            .unwrap();
+                        self.env
+                            .add_function(*specialization, signature.clone(), metadata.clone());
+                    }
+                }
+                self.specializations = typechecker_fn.specializations;
+                self.pending_specializations = typechecker_fn.pending_specializations;
+                for dimension in local_dimensions {
+                    self.registry.remove_dimension(dimension);
+                }
+                for (name, span) in local_unit_names {
+                    self.local_units_out_of_scope
+                        .insert(name, (span, *function_name));
+                }
                 // Copy identifier for the new function into local env:
-                let (signature, metadata) =
-                    typechecker_fn.env.get_function_info(function_name).unwrap();
+                let (signature, metadata) = typechecker_fn
+                    .env
+                    .get_function_info(*function_name)
+                    .unwrap();
                 self.env
-                    .add_function(function_name.clone(), signature.clone(), metadata.clone());
+                    .add_function(*function_name, signature.clone(), metadata.clone());
+                self.mark_deprecated([*function_name], *function_name_span, decorators);
+
+                self.operator_overloads
+                    .retain(|o| &o.function_name != function_name);
+                if let Some(op) = decorator::operator(decorators) {
+                    let parameter_types = typed_parameters
+                        .iter()
+                        .map(|(_, _, type_, _, _)| type_.clone())
+                        .collect::<Vec<_>>();
+
+                    if parameter_types.len() != 2
+                        || !parameter_types.iter().any(|t| matches!(t, Type::Struct(_)))
+                    {
+                        return Err(TypeCheckError::InvalidOperatorOverload(
+                            *function_name_span,
+                            function_name.to_string(),
+                        ));
+                    }
+
+                    self.operator_overloads.push(OperatorOverload {
+                        op,
+                        function_name: *function_name,
+                        parameter_types,
+                    });
+                }
+
+                if let Some(definition) = function_definition {
+                    self.function_definitions
+                        .insert(*function_name, Arc::new(definition));
+                }
+
+                // A redefinition needs new specializations
+                self.specializations
+                    .retain(|_, generic_function| generic_function != function_name);
+                if is_generic_with_predicates {
+                    self.generic_functions
+                        .insert(*function_name, Arc::new(ast.clone()));
+                } else {
+                    self.generic_functions.remove(function_name);
+                }
 
-                typed_ast::Statement::DefineFunction(
-                    function_name.clone(),
+                let definition = typed_ast::Statement::DefineFunction(
+                    *function_name,
                     decorators.clone(),
                     type_parameters
                         .iter()
-                        .map(|(_, name, bound)| (name.clone(), bound.clone()))
+                        .map(|(_, name, bound)| (*name, bound.clone()))
                         .collect(),
                     typed_parameters
-                        .iter()
-                        .map(|(span, name, _, ref type_annotation)| {
+                        .into_iter()
+                        .map(|(span, name, _, type_annotation, default_value)| {
                             (
-                                *span,
-                                name.clone(),
-                                (*type_annotation).clone(),
+                                span,
+                                name,
+                                type_annotation.clone(),
                                 crate::markup::empty(),
+                                default_value.map(|d| self.arena.alloc(d)),
                             )
                         })
                         .collect(),
-                    body_checked,
+                    body_checked.map(|body| self.arena.alloc(body)),
                     typed_local_variables,
+                    typed_local_units,
                     fn_type,
                     return_type_annotation.clone(),
                     crate::markup::empty(),
-                )
+                );
+
+                if is_generic_with_predicates {
+                    typed_ast::Statement::DefineGenericFunction(Box::new(definition))
+                } else {
+                    definition
+                }
             }
             ast::Statement::DefineDimension(name_span, name, dexprs) => {
-                self.type_namespace.add_identifier(
-                    name.clone(),
-                    *name_span,
-                    "dimension".to_owned(),
-                )?;
+                self.type_namespace
+                    .add_identifier(*name, *name_span, "dimension".to_owned())?;
 
                 if let Some(dexpr) = dexprs.first() {
                     self.registry
-                        .add_derived_dimension(name, dexpr)
+                        .add_derived_dimension(*name, *name_span, dexpr)
                         .map_err(TypeCheckError::RegistryError)?;
 
                     let base_representation = self
                         .registry
-                        .get_base_representation_for_name(name)
+                        .get_base_representation_for_name(*name)
                         .expect("we just inserted it");
 
                     for alternative_expr in &dexprs[1..] {
@@ -1563,7 +4279,7 @@ impl TypeChecker {
                         if alternative_base_representation != base_representation {
                             return Err(
                                 TypeCheckError::IncompatibleAlternativeDimensionExpression(
-                                    name.clone(),
+                                    name.to_string(),
                                     dexpr.full_span(),
                                     base_representation,
                                     alternative_expr.full_span(),
@@ -1574,10 +4290,10 @@ impl TypeChecker {
                     }
                 } else {
                     self.registry
-                        .add_base_dimension(name)
+                        .add_base_dimension(*name, *name_span)
                         .map_err(TypeCheckError::RegistryError)?;
                 }
-                typed_ast::Statement::DefineDimension(name.clone(), dexprs.clone())
+                typed_ast::Statement::DefineDimension(*name, dexprs.clone())
             }
             ast::Statement::ProcedureCall(span, kind @ ProcedureKind::Type, args) => {
                 if args.len() != 1 {
@@ -1595,7 +4311,64 @@ impl TypeChecker {
                     .map(|e| self.elaborate_expression(e))
                     .collect::<Result<Vec<_>>>()?;
 
-                typed_ast::Statement::ProcedureCall(kind.clone(), checked_args)
+                typed_ast::Statement::ProcedureCall(kind.clone(), self.alloc_all(checked_args))
+            }
+            ast::Statement::ProcedureCall(
+                span,
+                kind @ (ProcedureKind::ConversionCacheStats
+                | ProcedureKind::RegexCacheStats
+                | ProcedureKind::InstantiationCacheStats),
+                args,
+            ) => {
+                if !args.is_empty() {
+                    return Err(TypeCheckError::WrongArity {
+                        callable_span: *span,
+                        callable_name: match kind {
+                            ProcedureKind::ConversionCacheStats => "conversion_cache_stats".into(),
+                            ProcedureKind::RegexCacheStats => "regex_cache_stats".into(),
+                            _ => "instantiation_cache_stats".into(),
+                        },
+                        callable_definition_span: None,
+                        arity: 0..=0,
+                        num_args: args.len(),
+                    });
+                }
+
+                typed_ast::Statement::ProcedureCall(kind.clone(), vec![])
+            }
+            ast::Statement::ProcedureCall(
+                span,
+                kind @ (ProcedureKind::SetFixedTime | ProcedureKind::SetImplicitConversions),
+                args,
+            ) => {
+                if args.len() != 1 {
+                    return Err(TypeCheckError::WrongArity {
+                        callable_span: *span,
+                        callable_name: match kind {
+                            ProcedureKind::SetFixedTime => "set_fixed_time".into(),
+                            _ => "set_implicit_conversions".into(),
+                        },
+                        callable_definition_span: None,
+                        arity: 1..=1,
+                        num_args: args.len(),
+                    });
+                }
+
+                let checked_arg = self.elaborate_expression(&args[0])?;
+                let type_arg = checked_arg.get_type(&self.arena);
+                if self
+                    .add_equal_constraint(&type_arg, &Type::String)
+                    .is_trivially_violated()
+                {
+                    return Err(TypeCheckError::IncompatibleTypesInFunctionCall(
+                        None,
+                        Type::String,
+                        checked_arg.full_span(&self.arena),
+                        type_arg,
+                    ));
+                }
+
+                typed_ast::Statement::ProcedureCall(kind.clone(), self.alloc_all(vec![checked_arg]))
             }
             ast::Statement::ProcedureCall(span, kind, args) => {
                 let procedure = ffi::procedures().get(kind).unwrap();
@@ -1620,13 +4393,16 @@ impl TypeChecker {
                     }
                     ProcedureKind::Assert => {
                         if self
-                            .add_equal_constraint(&checked_args[0].get_type(), &Type::Boolean)
+                            .add_equal_constraint(
+                                &checked_args[0].get_type(&self.arena),
+                                &Type::Boolean,
+                            )
                             .is_trivially_violated()
                         {
                             return Err(TypeCheckError::IncompatibleTypeInAssert(
                                 *span,
-                                checked_args[0].get_type(),
-                                checked_args[0].full_span(),
+                                checked_args[0].get_type(&self.arena),
+                                checked_args[0].full_span(&self.arena),
                             ));
                         }
                     }
@@ -1634,15 +4410,15 @@ impl TypeChecker {
                         // The three-argument version of assert_eq requires dtypes as inputs:
                         let needs_dtypes = checked_args.len() == 3;
 
-                        let type_first = &checked_args[0].get_type();
+                        let type_first = &checked_args[0].get_type(&self.arena);
                         if needs_dtypes {
-                            self.enforce_dtype(type_first, checked_args[0].full_span())?;
+                            self.enforce_dtype(type_first, checked_args[0].full_span(&self.arena))?;
                         }
 
                         for arg in &checked_args[1..] {
-                            let type_arg = arg.get_type();
+                            let type_arg = arg.get_type(&self.arena);
                             if needs_dtypes {
-                                self.enforce_dtype(&type_arg, arg.full_span())?;
+                                self.enforce_dtype(&type_arg, arg.full_span(&self.arena))?;
                             }
 
                             if self
@@ -1651,31 +4427,231 @@ impl TypeChecker {
                             {
                                 return Err(TypeCheckError::IncompatibleTypesInAssertEq(
                                     *span,
-                                    checked_args[0].get_type(),
-                                    checked_args[0].full_span(),
-                                    arg.get_type(),
-                                    arg.full_span(),
+                                    checked_args[0].get_type(&self.arena),
+                                    checked_args[0].full_span(&self.arena),
+                                    arg.get_type(&self.arena),
+                                    arg.full_span(&self.arena),
+                                ));
+                            }
+                        }
+                    }
+                    ProcedureKind::WriteText | ProcedureKind::AppendCsv => {
+                        // The path, and the content for write_text, are strings. Rows
+                        // for append_csv are checked at run time.
+                        let string_args = if *kind == ProcedureKind::WriteText {
+                            &checked_args[..]
+                        } else {
+                            &checked_args[..1]
+                        };
+                        for arg in string_args {
+                            let type_arg = arg.get_type(&self.arena);
+                            if self
+                                .add_equal_constraint(&type_arg, &Type::String)
+                                .is_trivially_violated()
+                            {
+                                return Err(TypeCheckError::IncompatibleTypesInFunctionCall(
+                                    None,
+                                    Type::String,
+                                    arg.full_span(&self.arena),
+                                    type_arg,
                                 ));
                             }
                         }
                     }
-                    ProcedureKind::Type => {
-                        unreachable!("type() calls have a special handling above")
+                    ProcedureKind::Type
+                    | ProcedureKind::ConversionCacheStats
+                    | ProcedureKind::RegexCacheStats
+                    | ProcedureKind::InstantiationCacheStats
+                    | ProcedureKind::SetFixedTime
+                    | ProcedureKind::SetImplicitConversions => {
+                        unreachable!("{kind:?} calls have a special handling above")
+                    }
+                }
+
+                typed_ast::Statement::ProcedureCall(kind.clone(), self.alloc_all(checked_args))
+            }
+            ast::Statement::Conditional {
+                condition,
+                then_statement,
+                else_statement,
+                ..
+            } => {
+                let condition = self.elaborate_expression(condition)?;
+
+                if self
+                    .add_equal_constraint(&condition.get_type(&self.arena), &Type::Boolean)
+                    .is_trivially_violated()
+                {
+                    return Err(TypeCheckError::ExpectedBool(
+                        condition.full_span(&self.arena),
+                    ));
+                }
+
+                let then_statement = self.elaborate_statement(then_statement)?;
+                let else_statement = else_statement
+                    .as_ref()
+                    .map(|s| self.elaborate_statement(s))
+                    .transpose()?;
+
+                typed_ast::Statement::Conditional(
+                    self.arena.alloc(condition),
+                    Box::new(then_statement),
+                    else_statement.map(Box::new),
+                )
+            }
+            ast::Statement::DefineUnitFunction { function, inverse } => {
+                let ast::Statement::DefineFunction { function_name, .. } = function.as_ref() else {
+                    unreachable!("Unit functions are function definitions")
+                };
+                let function = self.elaborate_statement(function)?;
+
+                let inverse = if let Some(inverse) = inverse {
+                    let ast::Statement::DefineFunction {
+                        function_name: inverse_name,
+                        function_name_span: inverse_span,
+                        ..
+                    } = inverse.as_ref()
+                    else {
+                        unreachable!("Unit functions are function definitions")
+                    };
+                    let inverse = self.elaborate_statement(inverse)?;
+
+                    // The inverse maps the result of the function back to its argument
+                    let Type::Fn(parameter_types, return_type) = function.function_type() else {
+                        unreachable!("Function definitions have function types")
+                    };
+                    let expected_type =
+                        Type::Fn(vec![*return_type], Box::new(parameter_types[0].clone()));
+                    let inverse_type = inverse.function_type();
+                    if self
+                        .add_equal_constraint(&inverse_type, &expected_type)
+                        .is_trivially_violated()
+                    {
+                        return Err(TypeCheckError::IncompatibleInverse(
+                            *inverse_span,
+                            function_name.to_string(),
+                            expected_type,
+                            inverse_type,
+                        ));
+                    }
+
+                    self.unit_functions
+                        .insert(*function_name, Some(*inverse_name));
+                    Some(Box::new(inverse))
+                } else {
+                    self.unit_functions.insert(*function_name, None);
+                    None
+                };
+
+                typed_ast::Statement::DefineUnitFunction(Box::new(function), inverse)
+            }
+            ast::Statement::DefineMethods {
+                struct_name_span,
+                struct_name,
+                functions,
+            } => {
+                if !self.structs.contains_key(struct_name) {
+                    return Err(TypeCheckError::UnknownStruct(
+                        *struct_name_span,
+                        struct_name.to_string(),
+                    ));
+                }
+
+                let prefix = format!("{struct_name}::");
+                let mut typed_functions = vec![];
+                for function in functions {
+                    let ast::Statement::DefineFunction { function_name, .. } = function else {
+                        unreachable!("Methods are function definitions")
+                    };
+                    typed_functions.push(self.elaborate_statement(function)?);
+
+                    let method = Symbol::new(
+                        function_name
+                            .as_str()
+                            .strip_prefix(&prefix)
+                            .unwrap_or(function_name.as_str()),
+                    );
+                    let methods = self.methods.entry(*struct_name).or_default();
+                    if !methods.contains(&method) {
+                        methods.push(method);
                     }
                 }
 
-                typed_ast::Statement::ProcedureCall(kind.clone(), checked_args)
+                typed_ast::Statement::DefineMethods(*struct_name, typed_functions)
+            }
+            ast::Statement::DefineFormula {
+                formula_name_span,
+                formula_name,
+                quantities,
+                lhs,
+                rhs,
+            } => {
+                let formula = Formula {
+                    name: *formula_name,
+                    lhs,
+                    rhs,
+                };
+
+                // Solve for all quantities first, such that a formula that can not be
+                // solved is reported before type errors in the solutions
+                let solutions = quantities
+                    .iter()
+                    .map(|(span, quantity, _)| formula.solve(*span, *quantity))
+                    .collect::<Result<Vec<_>>>()?;
+
+                let mut typed_functions = vec![];
+                for ((_, quantity, annotation), body) in quantities.iter().zip(solutions) {
+                    let function = ast::Statement::DefineFunction {
+                        function_name_span: *formula_name_span,
+                        function_name: formula_function_name(*formula_name, *quantity),
+                        type_parameters: vec![],
+                        parameters: quantities
+                            .iter()
+                            .filter(|(_, other, _)| other != quantity)
+                            .map(|(span, other, annotation)| {
+                                (*span, *other, annotation.clone(), None)
+                            })
+                            .collect(),
+                        body: Some(body),
+                        local_variables: vec![],
+                        local_units: vec![],
+                        return_type_annotation: annotation.clone(),
+                        decorators: vec![decorator::Decorator::Description(format!(
+                            "Solves the formula `{formula_name}` for `{quantity}`."
+                        ))],
+                    };
+                    typed_functions.push(self.elaborate_statement(&function)?);
+                }
+
+                self.formulas.insert(
+                    *formula_name,
+                    FormulaInfo {
+                        definition_span: *formula_name_span,
+                        name: *formula_name,
+                        equation_span: lhs.full_span().extend(&rhs.full_span()),
+                        quantities: quantities
+                            .iter()
+                            .map(|(_, quantity, _)| *quantity)
+                            .collect(),
+                    },
+                );
+
+                typed_ast::Statement::DefineFormula(*formula_name, typed_functions)
             }
-            ast::Statement::ModuleImport(_, _) => {
+            ast::Statement::ModuleImport(..) => {
                 unreachable!("Modules should have been inlined by now")
             }
+            ast::Statement::ImportScope(_, scope) => {
+                self.apply_import_scope(scope);
+                typed_ast::Statement::ImportScope(scope.clone())
+            }
             ast::Statement::DefineStruct {
                 struct_name_span,
                 struct_name,
                 fields,
             } => {
                 self.type_namespace.add_identifier(
-                    struct_name.clone(),
+                    *struct_name,
                     *struct_name_span,
                     "struct".to_owned(),
                 )?;
@@ -1696,19 +4672,76 @@ impl TypeChecker {
 
                 let struct_info = StructInfo {
                     definition_span: *struct_name_span,
-                    name: struct_name.clone(),
+                    name: *struct_name,
                     fields: fields
                         .iter()
                         .map(|(span, name, type_)| {
-                            Ok((name.clone(), (*span, self.type_from_annotation(type_)?)))
+                            Ok((*name, (*span, self.type_from_annotation(type_)?)))
                         })
                         .collect::<Result<_>>()?,
                 };
-                self.structs
-                    .insert(struct_name.clone(), struct_info.clone());
+                self.structs.insert(*struct_name, struct_info.clone());
 
                 typed_ast::Statement::DefineStruct(struct_info)
             }
+            ast::Statement::DefineUnitSystem {
+                system_name_span,
+                system_name,
+                units,
+            } => {
+                self.value_namespace.add_identifier(
+                    *system_name,
+                    *system_name_span,
+                    "unit system".to_owned(),
+                )?;
+                self.unit_systems.insert(*system_name);
+
+                let mut typed_units = vec![];
+                for unit in units {
+                    let unit_checked = self.elaborate_expression(unit)?;
+                    if !matches!(unit_checked, Expression::UnitIdentifier(..)) {
+                        return Err(TypeCheckError::ExpectedUnitInUnitSystem(
+                            unit.full_span(),
+                            system_name.to_string(),
+                        ));
+                    }
+                    typed_units.push(unit_checked);
+                }
+
+                // A unit system can be used like a conversion function of
+                // type `fn <D: Dim>(quantity: D) -> D`, e.g. `3 N -> CGS`.
+                let type_parameter = Type::TPar("D".into());
+                self.add_dtype_constraint(&type_parameter).ok();
+
+                self.env.add_function(
+                    *system_name,
+                    FunctionSignature {
+                        name: *system_name,
+                        definition_span: *system_name_span,
+                        type_parameters: vec![(
+                            *system_name_span,
+                            "D".into(),
+                            Some(TypeParameterBound::Dim),
+                        )],
+                        parameters: vec![(*system_name_span, "quantity".into(), None)],
+                        num_default_parameters: 0,
+                        return_type_annotation: None,
+                        fn_type: TypeScheme::Concrete(Type::Fn(
+                            vec![type_parameter.clone()],
+                            Box::new(type_parameter),
+                        )),
+                    },
+                    FunctionMetadata {
+                        name: None,
+                        url: None,
+                        description: Some(format!(
+                            "Convert a quantity to the units of the '{system_name}' unit system."
+                        )),
+                    },
+                );
+
+                typed_ast::Statement::DefineUnitSystem(*system_name, self.alloc_all(typed_units))
+            }
         })
     }
 
@@ -1716,61 +4749,91 @@ impl TypeChecker {
         self.constraints.clear();
         self.registry.introduced_type_parameters.clear();
 
+        let first_expression = self.arena.next_id();
+
         // Elaborate the program/statement: turn the AST into a typed AST, possibly
         // with unification variables, i.e. type variables that will only later be
         // filled in after the constraints have been solved.
         let mut elaborated_statement = self.elaborate_statement(statement)?;
 
         // Solve constraints
-        let (substitution, dtype_variables) =
+        let (substitution, dtype_variables, ord_variables) =
             self.constraints.solve().map_err(|inner| match inner {
                 ConstraintSolverError::CouldNotSolve(constraints) => {
                     TypeCheckError::ConstraintSolverError(
                         constraints,
-                        elaborated_statement.pretty_print().to_string(),
+                        elaborated_statement.pretty_print(&self.arena).to_string(),
                     )
                 }
                 ConstraintSolverError::SubstitutionError(inner) => {
                     TypeCheckError::SubstitutionError(
-                        elaborated_statement.pretty_print().to_string(),
+                        elaborated_statement.pretty_print(&self.arena).to_string(),
                         inner,
                     )
                 }
             })?;
 
-        elaborated_statement.apply(&substitution).map_err(|e| {
-            TypeCheckError::SubstitutionError(elaborated_statement.pretty_print().to_string(), e)
-        })?;
+        // Sub-expressions are not reached through the statement. Instead, we apply the
+        // substitution to all expressions that have been allocated for this statement.
+        let apply_substitution = |s: &Substitution,
+                                  statement: &mut typed_ast::Statement,
+                                  arena: &mut ExpressionArena| {
+            statement.apply(s)?;
+            for expression in arena.since_mut(first_expression) {
+                expression.apply(s)?;
+            }
+            Ok(())
+        };
+
+        apply_substitution(&substitution, &mut elaborated_statement, &mut self.arena).map_err(
+            |e| {
+                TypeCheckError::SubstitutionError(
+                    elaborated_statement.pretty_print(&self.arena).to_string(),
+                    e,
+                )
+            },
+        )?;
 
         self.env.apply(&substitution).map_err(|e| {
-            TypeCheckError::SubstitutionError(elaborated_statement.pretty_print().to_string(), e)
+            TypeCheckError::SubstitutionError(
+                elaborated_statement.pretty_print(&self.arena).to_string(),
+                e,
+            )
         })?;
 
-        if let typed_ast::Statement::DefineDerivedUnit(_, expr, _, _annotation, type_, _) =
+        if let typed_ast::Statement::DefineDerivedUnit(_, _, expr, _, _annotation, type_, _) =
             &elaborated_statement
         {
             if !type_.unsafe_as_concrete().is_closed() {
                 return Err(TypeCheckError::DerivedUnitDefinitionMustNotBeGeneric(
-                    expr.full_span(),
+                    self.arena[*expr].full_span(&self.arena),
                 ));
             }
         }
 
         // Make sure that the user-specified type parameter bounds are properly reflected:
         for (span, type_parameter, bound) in &self.registry.introduced_type_parameters {
+            let is_type_parameter = |tv: &TypeVariable| match tv {
+                TypeVariable::Named(name) => name == type_parameter,
+                _ => false,
+            };
+
             match bound {
                 Some(TypeParameterBound::Dim) => {
                     // The type parameter might be over-constrained, but that's okay
                 }
-                None => {
+                Some(TypeParameterBound::Ord) | None => {
                     // Make sure that the type parameter is not part of dtype_variables.
                     // Otherwise, a `Dim` bound is missing.
-                    if dtype_variables.iter().any(|tv| match tv {
-                        TypeVariable::Named(name) => name == type_parameter,
-                        _ => false,
-                    }) {
+                    if dtype_variables.iter().any(is_type_parameter) {
                         return Err(TypeCheckError::MissingDimBound(*span));
                     }
+
+                    // Similarly, an `Ord` bound is missing if the type parameter is
+                    // part of ord_variables.
+                    if bound.is_none() && ord_variables.iter().any(is_type_parameter) {
+                        return Err(TypeCheckError::MissingOrdBound(*span));
+                    }
                 }
             }
         }
@@ -1780,41 +4843,45 @@ impl TypeChecker {
         // multiple of the denominators of the exponents. For example, this will turn
         // T0^(1/3) -> T0^(1/5) -> T0 into T0^5 -> T0^3 -> T0^15.
         for tv in &dtype_variables {
-            let exponents = elaborated_statement.exponents_for(tv);
+            let exponents =
+                elaborated_statement.exponents_for(self.arena.since_mut(first_expression), tv);
             let lcm = exponents
                 .iter()
                 .fold(1, |acc, e| num_integer::lcm(acc, *e.denom()));
 
             if lcm != 1 {
                 let s = Substitution::single(
-                    tv.clone(),
+                    *tv,
                     Type::Dimension(
-                        DType::from_type_variable(tv.clone()).power(Exponent::from_integer(lcm)),
+                        DType::from_type_variable(*tv).power(Exponent::from_integer(lcm)),
                     ),
                 );
 
-                elaborated_statement.apply(&s).unwrap();
+                apply_substitution(&s, &mut elaborated_statement, &mut self.arena).unwrap();
             }
         }
 
-        elaborated_statement.generalize_types(&dtype_variables);
+        elaborated_statement.generalize_types(
+            self.arena.since_mut(first_expression),
+            &dtype_variables,
+            &ord_variables,
+        );
 
         elaborated_statement.update_readable_types(&self.registry);
 
-        self.env.generalize_types(&dtype_variables);
+        self.env.generalize_types(&dtype_variables, &ord_variables);
 
         // Check if there is a typed hole in the statement
-        if let Some((span, type_of_hole)) = elaborated_statement.find_typed_hole()? {
+        if let Some((span, type_of_hole)) = elaborated_statement.find_typed_hole(&self.arena)? {
             return Err(TypeCheckError::TypedHoleInStatement(
                 span,
                 type_of_hole.to_readable_type(&self.registry).to_string(),
-                elaborated_statement.pretty_print().to_string(),
+                elaborated_statement.pretty_print(&self.arena).to_string(),
                 self.env
                     .iter_relevant_matches()
                     .filter(|(_, t)| t == &type_of_hole)
                     .take(10)
-                    .map(|(n, _)| n)
-                    .cloned()
+                    .map(|(n, _)| n.to_string())
                     .collect(),
             ));
         }
@@ -1825,21 +4892,95 @@ impl TypeChecker {
     pub fn check(
         &mut self,
         statements: impl IntoIterator<Item = ast::Statement>,
-    ) -> Result<Vec<typed_ast::Statement>> {
+    ) -> Result<TypedProgram> {
         let mut checked_statements = vec![];
 
-        for statement in statements.into_iter() {
-            checked_statements.push(self.check_statement(&statement)?);
-        }
+        let result = statements
+            .into_iter()
+            .try_for_each(|statement| -> Result<()> {
+                let statement_checked = self.check_statement(&statement)?;
+                checked_statements.append(&mut self.pending_specializations);
+                checked_statements.push(statement_checked);
+                Ok(())
+            });
+
+        // The arena is handed over to the program (or dropped, in case of an error),
+        // so that it does not need to be cloned along with the type checker.
+        let arena = std::mem::take(&mut self.arena);
+        result?;
 
-        Ok(checked_statements)
+        Ok(TypedProgram {
+            arena,
+            statements: checked_statements,
+        })
+    }
+
+    /// Returns (and removes) the warnings for all statements that have been
+    /// checked since the last call.
+    pub(crate) fn set_forbid_dynamic(&mut self, forbid: bool) {
+        self.forbid_dynamic = forbid;
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<TypeCheckWarning> {
+        std::mem::take(&mut self.warnings)
     }
 
     pub(crate) fn registry(&self) -> &DimensionRegistry {
         &self.registry
     }
 
+    pub(crate) fn add_native_function(&mut self, name: Symbol) {
+        self.native_functions.insert(name);
+    }
+
+    pub(crate) fn remove_native_function(&mut self, name: Symbol) -> bool {
+        let removed = self.native_functions.remove(&name);
+        if removed {
+            self.env.remove(name);
+            self.value_namespace.remove_identifier(name);
+        }
+        removed
+    }
+
+    /// Make a variable of the given type known, whose value is provided by the
+    /// embedding application.
+    pub(crate) fn add_foreign_variable(&mut self, name: Symbol, type_: Type) {
+        self.env.add(name, type_, Span::dummy(), false);
+    }
+
+    /// The type of a variable, or `None` if there is no variable of that name.
+    pub(crate) fn variable_type(&self, name: &str) -> Option<Type> {
+        let scheme = self.env.get_identifier_type(Symbol::get(name)?)?;
+        Some(scheme.instantiate_for_printing(None).0.inner)
+    }
+
     pub fn lookup_function(&self, name: &str) -> Option<(&FunctionSignature, &FunctionMetadata)> {
-        self.env.get_function_info(name)
+        self.env.get_function_info(Symbol::new(name))
+    }
+
+    pub fn deprecation_message(&self, name: &str) -> Option<&str> {
+        self.env
+            .get_deprecation(Symbol::new(name))
+            .map(|(_, message)| message.as_str())
+    }
+
+    pub fn is_deprecated(&self, name: &str) -> bool {
+        self.deprecation_message(name).is_some()
+    }
+
+    pub(crate) fn structs(&self) -> impl Iterator<Item = &StructInfo> {
+        self.structs.values()
+    }
+
+    pub(crate) fn formulas(&self) -> impl Iterator<Item = &FormulaInfo> {
+        self.formulas.values()
+    }
+
+    /// Names of the methods of a struct, in the order of their definition
+    pub(crate) fn methods(&self, struct_name: Symbol) -> &[Symbol] {
+        self.methods
+            .get(&struct_name)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
     }
 }
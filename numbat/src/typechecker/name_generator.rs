@@ -7,8 +7,8 @@ pub struct NameGenerator {
 
 impl NameGenerator {
     pub fn fresh_type_variable(&mut self) -> TypeVariable {
-        let name = format!("T{}", self.counter);
+        let type_variable = TypeVariable::new_fresh(self.counter);
         self.counter += 1;
-        TypeVariable::new(name)
+        type_variable
     }
 }
@@ -9,6 +9,8 @@ use super::{
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Bound {
     IsDim(Type),
+    /// The type can be ordered (quantities, strings, and date times)
+    IsOrd(Type),
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -19,10 +21,6 @@ impl Bounds {
         Bounds(vec![])
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.0.is_empty()
-    }
-
     pub fn iter(&self) -> std::slice::Iter<Bound> {
         self.0.iter()
     }
@@ -31,18 +29,31 @@ impl Bounds {
         self.0.iter_mut()
     }
 
-    pub fn union(self, other: Bounds) -> Bounds {
-        let mut bounds = self;
-        bounds.0.extend(other.0);
-        bounds
-    }
-
     pub fn is_dtype_bound(&self, tv: &TypeVariable) -> bool {
         self.0.iter().any(|b| match b {
             Bound::IsDim(Type::TVar(v)) => v == tv,
             _ => false,
         })
     }
+
+    pub fn is_ord_bound(&self, tv: &TypeVariable) -> bool {
+        self.0.iter().any(|b| match b {
+            Bound::IsOrd(Type::TVar(v)) => v == tv,
+            _ => false,
+        })
+    }
+
+    /// The name of the bound on the given type variable, as it would be written
+    /// in a type parameter list (`<D: Dim>`).
+    pub fn bound_name(&self, tv: &TypeVariable) -> Option<&'static str> {
+        if self.is_dtype_bound(tv) {
+            Some("Dim")
+        } else if self.is_ord_bound(tv) {
+            Some("Ord")
+        } else {
+            None
+        }
+    }
 }
 
 impl FromIterator<Bound> for Bounds {
@@ -72,7 +83,7 @@ impl QualifiedType {
         let mut qt = self.clone();
         for (i, v) in variables.iter().enumerate() {
             qt.apply(&Substitution::single(
-                v.clone(),
+                *v,
                 Type::TVar(TypeVariable::Quantified(i)),
             ))
             .unwrap();
@@ -93,6 +104,7 @@ impl QualifiedType {
                 .iter()
                 .map(|b| match b {
                     Bound::IsDim(t) => Bound::IsDim(t.instantiate(type_variables)),
+                    Bound::IsOrd(t) => Bound::IsOrd(t.instantiate(type_variables)),
                 })
                 .collect(),
         }
@@ -103,8 +115,10 @@ impl ApplySubstitution for QualifiedType {
     fn apply(&mut self, substitution: &Substitution) -> Result<(), SubstitutionError> {
         self.inner.apply(substitution)?;
 
-        for Bound::IsDim(v) in self.bounds.iter_mut() {
-            v.apply(substitution)?;
+        for bound in self.bounds.iter_mut() {
+            match bound {
+                Bound::IsDim(v) | Bound::IsOrd(v) => v.apply(substitution)?,
+            }
         }
         Ok(())
     }
@@ -1,8 +1,10 @@
 use thiserror::Error;
 
+use crate::error_code::ErrorCode;
 use crate::type_variable::TypeVariable;
-use crate::typed_ast::{DType, DTypeFactor, DefineVariable, Expression, StructInfo, Type};
-use crate::Statement;
+use crate::typed_ast::{
+    DType, DTypeFactor, DefineVariable, Expression, Pattern, Statement, StructInfo, Type,
+};
 
 #[derive(Debug, Clone)]
 pub struct Substitution(pub Vec<(TypeVariable, Type)>);
@@ -42,6 +44,14 @@ pub enum SubstitutionError {
     SubstitutedNonDTypeWithinDType(Type),
 }
 
+impl SubstitutionError {
+    pub fn error_code(&self) -> ErrorCode {
+        match self {
+            SubstitutionError::SubstitutedNonDTypeWithinDType(..) => ErrorCode::E0602,
+        }
+    }
+}
+
 pub trait ApplySubstitution {
     fn apply(&mut self, substitution: &Substitution) -> Result<(), SubstitutionError>;
 }
@@ -85,6 +95,7 @@ impl ApplySubstitution for Type {
                 Ok(())
             }
             Type::List(element_type) => element_type.apply(s),
+            Type::Dynamic => Ok(()),
         }
     }
 }
@@ -98,7 +109,7 @@ impl ApplySubstitution for DType {
                     if let Some(type_) = substitution.lookup(tv) {
                         let dtype = match type_ {
                             Type::Dimension(dt) => dt.clone(),
-                            Type::TVar(tv) => DType::from_type_variable(tv.clone()),
+                            Type::TVar(tv) => DType::from_type_variable(*tv),
                             t => {
                                 return Err(SubstitutionError::SubstitutedNonDTypeWithinDType(
                                     t.clone(),
@@ -106,8 +117,7 @@ impl ApplySubstitution for DType {
                             }
                         };
 
-                        new_dtype =
-                            new_dtype.divide(&DType::from_type_variable(tv.clone()).power(*power));
+                        new_dtype = new_dtype.divide(&DType::from_type_variable(*tv).power(*power));
                         new_dtype = new_dtype.multiply(&dtype.power(*power));
                     }
                 }
@@ -117,7 +127,7 @@ impl ApplySubstitution for DType {
                     if let Some(type_) = substitution.lookup(&tv) {
                         let dtype = match type_ {
                             Type::Dimension(dt) => dt.clone(),
-                            Type::TVar(tv) => DType::from_type_variable(tv.clone()),
+                            Type::TVar(tv) => DType::from_type_variable(*tv),
                             t => {
                                 return Err(SubstitutionError::SubstitutedNonDTypeWithinDType(
                                     t.clone(),
@@ -125,8 +135,8 @@ impl ApplySubstitution for DType {
                             }
                         };
 
-                        new_dtype = new_dtype
-                            .divide(&DType::from_type_parameter(name.clone()).power(*power));
+                        new_dtype =
+                            new_dtype.divide(&DType::from_type_parameter(*name).power(*power));
                         new_dtype = new_dtype.multiply(&dtype.power(*power));
                     }
                 }
@@ -148,95 +158,111 @@ impl ApplySubstitution for StructInfo {
     }
 }
 
-impl ApplySubstitution for Expression {
+impl ApplySubstitution for Pattern {
     fn apply(&mut self, s: &Substitution) -> Result<(), SubstitutionError> {
         match self {
-            Expression::Scalar(_, _, type_) => type_.apply(s),
-            Expression::Identifier(_, _, type_) => type_.apply(s),
-            Expression::UnitIdentifier(_, _, _, _, type_) => type_.apply(s),
-            Expression::UnaryOperator(_, _, expr, type_) => {
-                expr.apply(s)?;
-                type_.apply(s)
-            }
-            Expression::BinaryOperator(_, _, lhs, rhs, type_) => {
-                lhs.apply(s)?;
-                rhs.apply(s)?;
-                type_.apply(s)
-            }
-            Expression::BinaryOperatorForDate(_, _, lhs, rhs, type_) => {
-                lhs.apply(s)?;
-                rhs.apply(s)?;
-                type_.apply(s)
-            }
-            Expression::FunctionCall(_, _, _, arguments, return_type) => {
-                for arg in arguments {
-                    arg.apply(s)?;
+            Pattern::Identifier(_) => Ok(()),
+            Pattern::Struct(fields, struct_type) => {
+                for (_, pattern) in fields {
+                    pattern.apply(s)?;
                 }
-                return_type.apply(s)
+                struct_type.apply(s)
             }
-            Expression::CallableCall(_, callable, arguments, return_type) => {
-                callable.apply(s)?;
-                for arg in arguments {
-                    arg.apply(s)?;
+            Pattern::List(elements) => {
+                for pattern in elements {
+                    pattern.apply(s)?;
                 }
-                return_type.apply(s)
+                Ok(())
             }
+        }
+    }
+}
+
+impl ApplySubstitution for DefineVariable {
+    fn apply(&mut self, s: &Substitution) -> Result<(), SubstitutionError> {
+        let DefineVariable(pattern, _, _, _annotation, type_, _) = self;
+        pattern.apply(s)?;
+        type_.apply(s)
+    }
+}
+
+/// Only applies the substitution to the types that are stored directly in the
+/// expression. Sub-expressions are stored in the arena and need to be handled
+/// separately.
+impl ApplySubstitution for Expression {
+    fn apply(&mut self, s: &Substitution) -> Result<(), SubstitutionError> {
+        match self {
+            Expression::Scalar(_, _, type_) => type_.apply(s),
+            Expression::Identifier(_, _, type_) => type_.apply(s),
+            Expression::UnitIdentifier(_, _, _, _, type_) => type_.apply(s),
+            Expression::UnaryOperator(_, _, _, type_) => type_.apply(s),
+            Expression::BinaryOperator(_, _, _, _, type_) => type_.apply(s),
+            Expression::BinaryOperatorForDate(_, _, _, _, type_) => type_.apply(s),
+            Expression::Conversion(_, _, _, type_) => type_.apply(s),
+            Expression::ChainedComparison(_) => Ok(()),
+            Expression::FunctionCall(_, _, _, _, _, return_type) => return_type.apply(s),
+            Expression::CallableCall(_, _, _, return_type) => return_type.apply(s),
             Expression::Boolean(_, _) => Ok(()),
-            Expression::Condition(_, if_, then_, else_) => {
-                if_.apply(s)?;
-                then_.apply(s)?;
-                else_.apply(s)
-            }
+            Expression::Condition(_, _, _, _) => Ok(()),
             Expression::String(_, _) => Ok(()),
-            Expression::InstantiateStruct(_, initializers, info) => {
-                for (_, expr) in initializers {
-                    expr.apply(s)?;
-                }
-                info.apply(s)
-            }
-            Expression::AccessField(_, _, instance, _, struct_type, field_type) => {
-                instance.apply(s)?;
+            Expression::InstantiateStruct(_, _, _, info) => info.apply(s),
+            Expression::AccessField(_, _, _, _, struct_type, field_type) => {
                 struct_type.apply(s)?;
                 field_type.apply(s)
             }
-            Expression::List(_, elements, element_type) => {
-                for element in elements {
-                    element.apply(s)?;
+            Expression::List(_, _, element_type) => element_type.apply(s),
+            Expression::TypedHole(_, type_) => type_.apply(s),
+            Expression::NamedArgument(_, _, _) => Ok(()),
+            Expression::DefaultArgument(_, type_) => type_.apply(s),
+            Expression::Trace(_, _, _) => Ok(()),
+            Expression::Partial(_, _, _, type_) => type_.apply(s),
+            Expression::AutoDiff(_, _, definitions, type_) => {
+                for definition in definitions {
+                    definition.apply(s)?;
                 }
-                element_type.apply(s)
+                type_.apply(s)
             }
-            Expression::TypedHole(_, type_) => type_.apply(s),
+            Expression::For(..) | Expression::While(..) | Expression::TryCatch(..) => Ok(()),
+            Expression::DynamicCheck(_, _, type_, _) => type_.apply(s),
         }
     }
 }
 
+/// Like for [`Expression`]s, this does not apply the substitution to the
+/// expressions of the statement.
 impl ApplySubstitution for Statement {
     fn apply(&mut self, s: &Substitution) -> Result<(), SubstitutionError> {
         match self {
-            Statement::Expression(e) => e.apply(s),
-            Statement::DefineVariable(DefineVariable(_, _, e, _annotation, type_, _)) => {
-                e.apply(s)?;
-                type_.apply(s)
-            }
-            Statement::DefineFunction(_, _, _, _, body, local_variables, fn_type, _, _) => {
+            Statement::Expression(_) => Ok(()),
+            Statement::DefineVariable(define_variable) => define_variable.apply(s),
+            Statement::DefineFunction(
+                _,
+                _,
+                _,
+                _,
+                _,
+                local_variables,
+                local_units,
+                fn_type,
+                _,
+                _,
+            ) => {
                 for local_variable in local_variables {
-                    local_variable.2.apply(s)?;
-                    local_variable.4.apply(s)?;
+                    local_variable.apply(s)?;
                 }
-                if let Some(body) = body {
-                    body.apply(s)?;
+                for local_unit in local_units {
+                    local_unit.apply(s)?;
                 }
                 fn_type.apply(s)
             }
-            Statement::DefineDimension(_, _) => Ok(()),
-            Statement::DefineBaseUnit(_, _, _annotation, type_) => type_.apply(s),
-            Statement::DefineDerivedUnit(_, e, _, _annotation, type_, _) => {
-                e.apply(s)?;
-                type_.apply(s)
-            }
-            Statement::ProcedureCall(_, args) => {
-                for arg in args {
-                    arg.apply(s)?;
+            Statement::DefineDimension(_, _) | Statement::ImportScope(_) => Ok(()),
+            Statement::DefineBaseUnit(_, _, _, _annotation, type_, _) => type_.apply(s),
+            Statement::DefineDerivedUnit(_, _, _, _, _annotation, type_, _) => type_.apply(s),
+            Statement::ProcedureCall(_, _) => Ok(()),
+            Statement::Conditional(_, then_statement, else_statement) => {
+                then_statement.apply(s)?;
+                if let Some(else_statement) = else_statement {
+                    else_statement.apply(s)?;
                 }
                 Ok(())
             }
@@ -245,6 +271,21 @@ impl ApplySubstitution for Statement {
 
                 Ok(())
             }
+            Statement::DefineUnitSystem(_, _) => Ok(()),
+            Statement::DefineUnitFunction(function, inverse) => {
+                function.apply(s)?;
+                if let Some(inverse) = inverse {
+                    inverse.apply(s)?;
+                }
+                Ok(())
+            }
+            Statement::DefineMethods(_, functions) | Statement::DefineFormula(_, functions) => {
+                for function in functions {
+                    function.apply(s)?;
+                }
+                Ok(())
+            }
+            Statement::DefineGenericFunction(function) => function.apply(s),
         }
     }
 }
@@ -4,8 +4,7 @@ mod type_inference;
 use crate::parser::parse;
 use crate::prefix_transformer::Transformer;
 use crate::typechecker::{Result, TypeCheckError};
-use crate::typed_ast::{self, DType};
-use crate::Statement;
+use crate::typed_ast::{DType, Statement, TypedProgram};
 
 use super::type_scheme::TypeScheme;
 use super::TypeChecker;
@@ -32,6 +31,7 @@ const TEST_PRELUDE: &str = "
 
     fn len<T>(x: List<T>) -> Scalar
     fn head<T>(x: List<T>) -> T
+    fn sort<T: Ord>(xs: List<T>) -> List<T>
 
     fn id<T>(x: T) -> T = x
     fn id_for_dim<T: Dim>(x: T) -> T = x
@@ -49,14 +49,12 @@ fn type_c() -> DType {
     DType::base_dimension("A").multiply(&DType::base_dimension("B"))
 }
 
-fn run_typecheck(input: &str) -> Result<typed_ast::Statement> {
+fn run_typecheck(input: &str) -> Result<TypedProgram> {
     let code = &format!("{prelude}\n{input}", prelude = TEST_PRELUDE, input = input);
     let statements = parse(code, 0).expect("No parse errors for inputs in this test suite");
     let transformed_statements = Transformer::new().transform(statements)?;
 
-    TypeChecker::default()
-        .check(transformed_statements)
-        .map(|mut statements_checked| statements_checked.pop().unwrap())
+    TypeChecker::default().check(transformed_statements)
 }
 
 fn assert_successful_typecheck(input: &str) {
@@ -66,9 +64,9 @@ fn assert_successful_typecheck(input: &str) {
 }
 
 fn get_inferred_fn_type(input: &str) -> TypeScheme {
-    let statement = run_typecheck(input).expect("Input was expected to type-check");
-    match statement {
-        Statement::DefineFunction(_, _, _, _, _, _, fn_type, _, _) => fn_type,
+    let mut program = run_typecheck(input).expect("Input was expected to type-check");
+    match program.statements.pop().unwrap() {
+        Statement::DefineFunction(_, _, _, _, _, _, _, fn_type, _, _) => fn_type,
         _ => {
             unreachable!();
         }
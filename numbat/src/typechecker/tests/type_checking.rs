@@ -90,6 +90,26 @@ fn equality() {
         TypeCheckError::IncompatibleTypesInComparison(..)
     ));
 
+    assert_successful_typecheck("[a, 2 a] == [3 a]");
+    assert_successful_typecheck("[] == [a]");
+    assert_successful_typecheck("SomeStruct {a: 1a, b: 1b} == SomeStruct {a: 2a, b: 2b}");
+
+    assert!(matches!(
+        get_typecheck_error("[a] == [b]"),
+        TypeCheckError::IncompatibleTypesInComparison(..)
+    ));
+    assert!(matches!(
+        get_typecheck_error("[[a]] == [a]"),
+        TypeCheckError::IncompatibleTypesInComparison(..)
+    ));
+    assert!(matches!(
+        get_typecheck_error(
+            "struct OtherStruct { a: A, b: B }
+             SomeStruct {a: 1a, b: 1b} == OtherStruct {a: 1a, b: 1b}"
+        ),
+        TypeCheckError::IncompatibleTypesInComparison(..)
+    ));
+
     // assert!(matches!(
     //     get_typecheck_error("callable == callable"),
     //     TypeCheckError::IncompatibleTypesInComparison { .. }
@@ -204,6 +224,34 @@ fn function_definitions_with_local_variables() {
     ));
 }
 
+#[test]
+fn function_definitions_with_local_units() {
+    assert_successful_typecheck("fn f(x: Scalar) -> A = x * half_a where unit half_a = a / 2");
+    assert_successful_typecheck(
+        "fn f(x: Scalar) -> D = x * widget * gadget
+           where dimension D
+             and unit widget: D
+             and unit gadget = 2",
+    );
+
+    // Local units can shadow global units
+    assert_successful_typecheck(
+        "fn f(x: Scalar) -> B = x * a where unit a = 3 b
+         2 a -> a",
+    );
+
+    assert!(matches!(
+        get_typecheck_error("fn f(x: Scalar) -> A = x * half_a where unit half_a = a / 2
+                             2 half_a"),
+        TypeCheckError::LocalUnitOutOfScope(_, name, _, fn_name) if name == "half_a" && fn_name == "f"
+    ));
+    assert!(matches!(
+        get_typecheck_error("fn f(x: Scalar) -> A = x * half_a where unit half_a = a / 2
+                             fn g(x: Scalar) -> A = x * half_a"),
+        TypeCheckError::LocalUnitOutOfScope(_, name, _, _) if name == "half_a"
+    ));
+}
+
 #[test]
 fn generics_basic() {
     assert_successful_typecheck(
@@ -239,6 +287,34 @@ fn generics_basic() {
     ));
 }
 
+#[test]
+fn generics_ord_bound() {
+    assert_successful_typecheck(
+        "
+            fn smaller<T: Ord>(x: T, y: T) -> T = if x < y then x else y
+            fn first_sorted<T: Ord>(xs: List<T>) -> T = head(sort(xs))
+            smaller(2 a, 3 a)
+            smaller(\"foo\", \"bar\")
+            first_sorted([3 b, 1 b])
+            ",
+    );
+
+    assert!(matches!(
+        get_typecheck_error("sort([SomeStruct { a: 1 a, b: 1 b }])"),
+        TypeCheckError::NotOrderable(_, _, function_name) if function_name == "sort"
+    ));
+
+    assert!(matches!(
+        get_typecheck_error("sort([true, false])"),
+        TypeCheckError::NotOrderable(_, Type::Boolean, _)
+    ));
+
+    assert!(matches!(
+        get_typecheck_error("fn f<T>(xs: List<T>) -> List<T> = sort(xs)"),
+        TypeCheckError::MissingOrdBound(_)
+    ));
+}
+
 // #[test]
 // fn generics_unused_type_parameter() {
 //     assert!(matches!(
@@ -339,6 +415,35 @@ fn wrong_arity() {
     ));
 }
 
+#[test]
+fn variadic_functions() {
+    assert_successful_typecheck(
+        "
+        fn total<D: Dim>(x: D, xs: D…) -> D = x
+        let x1: A = total(1 a, 2 a)
+        let x2: A = total(1 a, 2 a, 3 a)
+        ",
+    );
+
+    assert!(matches!(
+        get_typecheck_error("
+                fn total<D: Dim>(x: D, xs: D…) -> D = x
+                total(1 a)
+            "),
+        TypeCheckError::WrongArity{callable_span:_, callable_name, callable_definition_span: _, arity, num_args: 1} if arity == (2..=usize::MAX) && callable_name == "total"
+    ));
+
+    assert!(matches!(
+        get_typecheck_error(
+            "
+                fn f(xs: Scalar…) -> List<Scalar> = xs
+                f(1, 2, 3 a)
+            "
+        ),
+        TypeCheckError::IncompatibleDimensions(..)
+    ));
+}
+
 #[test]
 fn foreign_function_with_missing_return_type() {
     assert!(matches!(
@@ -546,6 +651,42 @@ fn no_dimensionless_base_units() {
     ));
 }
 
+#[test]
+fn implicit_dimensions_of_base_units() {
+    assert_successful_typecheck(
+        "
+            unit widget
+            let w: Widget = 2 widget
+
+            @dimension(Gizmo)
+            unit gadget
+            let g: Gizmo = 3 gadget
+            let ratio: Widget / Gizmo = w / g
+            ",
+    );
+
+    assert!(matches!(
+        get_typecheck_error(
+            "
+                dimension Widget
+                unit widget
+                "
+        ),
+        TypeCheckError::ImplicitDimensionClash(_, unit_name, dimension_name, _)
+            if unit_name == "widget" && dimension_name == "Widget"
+    ));
+    assert!(matches!(
+        get_typecheck_error(
+            "
+                @dimension(C)
+                unit widget
+                "
+        ),
+        TypeCheckError::ImplicitDimensionClash(_, _, dimension_name, _)
+            if dimension_name == "C"
+    ));
+}
+
 #[test]
 fn callables() {
     assert_successful_typecheck("callable(a)");
@@ -628,6 +769,28 @@ fn structs() {
         TypeCheckError::MissingFieldsInStructInstantiation(..)
     ));
 
+    assert_successful_typecheck(
+        "
+          let s = SomeStruct { a: 1a, b: 1b }
+          let t: SomeStruct = SomeStruct { s with b = 2b }
+          ",
+    );
+
+    assert!(matches!(
+        get_typecheck_error("SomeStruct { 1a with a = 2a }"),
+        TypeCheckError::IncompatibleBaseInStructUpdate(..)
+    ));
+
+    assert!(matches!(
+        get_typecheck_error("SomeStruct { SomeStruct {a: 1a, b: 1b} with a = 1b }"),
+        TypeCheckError::IncompatibleTypesForStructField(..)
+    ));
+
+    assert!(matches!(
+        get_typecheck_error("SomeStruct { SomeStruct {a: 1a, b: 1b} with not_a_field = 1 }"),
+        TypeCheckError::UnknownFieldInStructInstantiation(_, _, field, _) if field == "not_a_field"
+    ));
+
     // Regression test for https://github.com/sharkdp/numbat/issues/459
     assert_successful_typecheck("id(SomeStruct { a: 1a, b: 1b }).a");
 
@@ -638,6 +801,126 @@ fn structs() {
     ));
 }
 
+#[test]
+fn method_call_syntax() {
+    assert_successful_typecheck("let x: B = (1 a).takes_a_returns_b()");
+    assert_successful_typecheck("let x: C = (1 a).takes_a_and_b_returns_c(2 b)");
+    assert_successful_typecheck("let x: B = returns_a().takes_a_returns_a().takes_a_returns_b()");
+    assert_successful_typecheck("let x: A = [1 a, 2 a].head()");
+
+    // Fields take priority over functions with the same name
+    assert_successful_typecheck(
+        "
+        struct WithCallable { takes_a_returns_a: Fn[(B) -> B] }
+        let s = WithCallable { takes_a_returns_a: id }
+        let x: B = s.takes_a_returns_a(1 b)
+        ",
+    );
+
+    assert!(matches!(
+        get_typecheck_error("(1 a).not_a_function()"),
+        TypeCheckError::UnknownIdentifier(span, name, _) if name == "not_a_function" && span.start.position == 7
+    ));
+    assert!(matches!(
+        get_typecheck_error("(1 a).takes_a_returns_b(2 a)"),
+        TypeCheckError::WrongArity { callable_span, num_args: 2, .. } if callable_span.start.position == 7
+    ));
+    assert!(matches!(
+        get_typecheck_error("(1 b).takes_a_returns_b()"),
+        TypeCheckError::IncompatibleDimensions(..)
+    ));
+}
+
+#[test]
+fn default_parameters() {
+    assert_successful_typecheck(
+        "
+        fn f(x: A, y: B = 2 b) -> C = x * y
+        let c1: C = f(1 a)
+        let c2: C = f(1 a, 3 b)
+        ",
+    );
+    assert_successful_typecheck(
+        "
+        fn g(x: A, y: A = 2 x) -> A = x + y
+        let a1: A = g(1 a)
+        ",
+    );
+
+    assert!(matches!(
+        get_typecheck_error("fn f(x: A, y: B = 2 a) = x * y"),
+        TypeCheckError::IncompatibleDimensions(IncompatibleDimensionsError {operation, expected_type, actual_type, ..})
+            if operation == "default value of parameter" && expected_type == type_b().to_base_representation() && actual_type == type_a().to_base_representation()
+    ));
+    assert!(matches!(
+        get_typecheck_error("fn f(x: A, y: B = 2 b) = x * y
+                             f()"),
+        TypeCheckError::WrongArity { arity, num_args: 0, .. } if arity == (1..=2)
+    ));
+    assert!(matches!(
+        get_typecheck_error("fn f(x: A, y: B = 2 b) = x * y
+                             f(1 a, 2 b, 3 b)"),
+        TypeCheckError::WrongArity { arity, num_args: 3, .. } if arity == (1..=2)
+    ));
+    assert!(matches!(
+        get_typecheck_error(
+            "fn f(x: A, y: B = 2 b) = x * y
+                             f(1 a, 2 a)"
+        ),
+        TypeCheckError::IncompatibleDimensions(..)
+    ));
+}
+
+#[test]
+fn named_arguments() {
+    assert_successful_typecheck("let r: C = takes_a_and_b_returns_c(x = 1 a, y = 2 b)");
+    assert_successful_typecheck("let r: C = takes_a_and_b_returns_c(y = 2 b, x = 1 a)");
+    assert_successful_typecheck("let r: C = takes_a_and_b_returns_c(1 a, y = 2 b)");
+    assert_successful_typecheck(
+        "
+        fn f(x: A, y: B = 2 b, z: C = 3 c) -> C = x * y + z
+        let c1: C = f(1 a, z = 1 c)
+        let c2: C = f(z = 1 c, x = 1 a)
+        ",
+    );
+
+    assert!(matches!(
+        get_typecheck_error("takes_a_and_b_returns_c(x = 1 a, y = 2 a)"),
+        TypeCheckError::IncompatibleDimensions(IncompatibleDimensionsError {expected_type, actual_type, ..})
+            if expected_type == type_b().to_base_representation() && actual_type == type_a().to_base_representation()
+    ));
+    assert!(matches!(
+        get_typecheck_error("takes_a_and_b_returns_c(1 a, yy = 2 b)"),
+        TypeCheckError::UnknownNamedArgument(span, _, function_name, name, None)
+            if span.start.position == 30 && function_name == "takes_a_and_b_returns_c" && name == "yy"
+    ));
+    assert!(matches!(
+        get_typecheck_error("fn f(start: A, stop: A) = stop - start
+                             f(1 a, stpo = 2 a)"),
+        TypeCheckError::UnknownNamedArgument(_, _, _, _, Some(suggestion)) if suggestion == "stop"
+    ));
+    assert!(matches!(
+        get_typecheck_error("takes_a_and_b_returns_c(1 a, x = 2 a)"),
+        TypeCheckError::DuplicateArgument(_, previous_span, name)
+            if previous_span.start.position == 25 && name == "x"
+    ));
+    assert!(matches!(
+        get_typecheck_error("takes_a_and_b_returns_c(y = 2 b)"),
+        TypeCheckError::MissingArgument(_, _, _, name) if name == "x"
+    ));
+    assert!(matches!(
+        get_typecheck_error("takes_a_and_b_returns_c(1 a, 2 b, 3 b, y = 2 b)"),
+        TypeCheckError::WrongArity { num_args: 4, .. }
+    ));
+    assert!(matches!(
+        get_typecheck_error(
+            "let f = takes_a_returns_b
+                             f(x = 1 a)"
+        ),
+        TypeCheckError::NamedArgumentsInCallableCall(_)
+    ));
+}
+
 #[test]
 fn lists() {
     assert_successful_typecheck("[]");
@@ -671,6 +954,131 @@ fn lists() {
     ));
 }
 
+#[test]
+fn destructuring() {
+    assert_successful_typecheck(
+        "
+          let {a: p, b: q} = SomeStruct { a: 1a, b: 1b }
+          let x: A = p
+          let y: B = q
+          ",
+    );
+    assert_successful_typecheck(
+        "
+          let [x, y] = [1a, 2a]
+          let z: A = x + y
+          ",
+    );
+    assert_successful_typecheck(
+        "
+          let [{a: p, b: q}, {a: p2, b: q2}] = [SomeStruct { a: 1a, b: 1b }, SomeStruct { a: 2a, b: 2b }]
+          let x: A = p + p2
+          ",
+    );
+    assert_successful_typecheck(
+        "
+          fn f(s: SomeStruct) -> A = p where {a: p, b: q} = s
+          fn g(xs: List<B>) -> B = x + y where [x, y] = xs
+          ",
+    );
+
+    assert!(matches!(
+        get_typecheck_error("let {a: p, b: q} = SomeStruct { a: 1a, b: 1b }\nlet x: B = p"),
+        TypeCheckError::IncompatibleDimensions(..)
+    ));
+    assert!(matches!(
+        get_typecheck_error("let {a: p, b: q} = 1a"),
+        TypeCheckError::DestructuringOfNonStructType(..)
+    ));
+    assert!(matches!(
+        get_typecheck_error("let [x, y] = 1a"),
+        TypeCheckError::DestructuringOfNonListType(..)
+    ));
+    assert!(matches!(
+        get_typecheck_error("let {a: p, c: q} = SomeStruct { a: 1a, b: 1b }"),
+        TypeCheckError::UnknownFieldAccess(_, _, field, _) if field == "c"
+    ));
+    assert!(matches!(
+        get_typecheck_error("let {a: p} = SomeStruct { a: 1a, b: 1b }"),
+        TypeCheckError::MissingFieldsInDestructuring(_, _, missing) if missing.len() == 1 && missing[0].0 == "b"
+    ));
+    assert!(matches!(
+        get_typecheck_error("let [x, {a: x, b: y}] = [SomeStruct { a: 1a, b: 1b }]"),
+        TypeCheckError::DuplicateBindingInPattern(_, _, name) if name == "x"
+    ));
+}
+
+#[test]
+fn loops() {
+    assert_successful_typecheck(
+        "
+          let xs: List<C> = for x in [1 a, 2 a] { x * b }
+          let total: A = for x in [1 a, 2 a] with acc = 0 a { acc + x }
+          let n: Scalar = while n < 100 with n = 1 { 2 n }
+          ",
+    );
+    assert_successful_typecheck(
+        "
+          fn sum_a(xs: List<A>) -> A = for x in xs with acc = 0 a { acc + x }
+          fn table(xs: List<A>, ys: List<B>) -> List<List<C>> = for x in xs { for y in ys { x * y } }
+          ",
+    );
+
+    // The loop variable shadows other identifiers only inside the body
+    assert_successful_typecheck(
+        "
+          let x = 1 b
+          let ys: List<A> = for x in [1 a] { x }
+          let z: B = x
+          ",
+    );
+
+    assert!(matches!(
+        get_typecheck_error("for x in 1 a { x }"),
+        TypeCheckError::LoopOverNonListType(..)
+    ));
+    assert!(matches!(
+        get_typecheck_error("for x in [1 a] with acc = 1 b { acc * x }"),
+        TypeCheckError::IncompatibleTypesInAccumulator(..)
+    ));
+    assert!(matches!(
+        get_typecheck_error("while n < 10 with n = 1 { n a }"),
+        TypeCheckError::IncompatibleTypesInAccumulator(..)
+    ));
+    assert!(matches!(
+        get_typecheck_error("while 1 with n = 1 { n }"),
+        TypeCheckError::ExpectedBool(..)
+    ));
+    assert!(matches!(
+        get_typecheck_error("for x in [1 a] { x }\nx"),
+        TypeCheckError::UnknownIdentifier(..)
+    ));
+}
+
+#[test]
+fn try_catch() {
+    assert_successful_typecheck(
+        "
+          let x: A = try { 2 a } catch { 3 a }
+          let message: String = try { \"ok\" } catch msg { msg }
+          fn f(y: B) -> List<B> = try { [2 y] } catch { [] }
+          ",
+    );
+
+    assert!(matches!(
+        get_typecheck_error("try { 1 a } catch { 1 b }"),
+        TypeCheckError::IncompatibleTypesInTryCatch(..)
+    ));
+    assert!(matches!(
+        get_typecheck_error("try { 1 a } catch msg { msg }"),
+        TypeCheckError::IncompatibleTypesInTryCatch(..)
+    ));
+    assert!(matches!(
+        get_typecheck_error("try { 1 } catch msg { 1 }\nmsg"),
+        TypeCheckError::UnknownIdentifier(..)
+    ));
+}
+
 #[test]
 fn name_resolution() {
     assert!(matches!(
@@ -713,6 +1121,26 @@ fn name_resolution() {
         TypeCheckError::NameResolutionError(NameResolutionError::IdentifierClash { .. })
     ));
 
+    assert!(matches!(
+        get_typecheck_error(
+            "
+                dimension Foo = A * B
+                unit foo
+                "
+        ),
+        TypeCheckError::ImplicitDimensionClash(..)
+    ));
+
+    assert!(matches!(
+        get_typecheck_error(
+            "
+                unit foo
+                dimension Foo
+                "
+        ),
+        TypeCheckError::NameResolutionError(NameResolutionError::IdentifierClash { .. })
+    ));
+
     assert_successful_typecheck(
         "
             let Foo = 1
@@ -426,3 +426,14 @@ fn typed_holes() {
         TypeCheckError::TypedHoleInStatement(_, type_, _, _) if type_ == "Fn[(A, B) -> A × B]"
     ));
 }
+
+#[test]
+fn fresh_type_variables_are_not_interned() {
+    let mut name_generator = NameGenerator::default();
+    for _ in 0..100 {
+        name_generator.fresh_type_variable();
+    }
+    let type_variable = name_generator.fresh_type_variable();
+    assert_eq!(type_variable.to_string(), "T100");
+    assert_eq!(Symbol::get("T100"), None);
+}
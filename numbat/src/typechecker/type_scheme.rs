@@ -3,6 +3,7 @@ use super::qualified_type::{Bound, Bounds, QualifiedType};
 use super::substitutions::{ApplySubstitution, Substitution, SubstitutionError};
 use crate::markup as m;
 use crate::pretty_print::PrettyPrint;
+use crate::symbol::Symbol;
 use crate::type_variable::TypeVariable;
 use crate::typed_ast::Type;
 
@@ -30,7 +31,7 @@ impl TypeScheme {
         TypeScheme::Quantified(num_quantified, qt)
     }
 
-    fn instantiate_with(&self, new_type_variables: &[TypeVariable]) -> QualifiedType {
+    pub(crate) fn instantiate_with(&self, new_type_variables: &[TypeVariable]) -> QualifiedType {
         if let TypeScheme::Quantified(n_gen, qt) = &self {
             assert!(n_gen == &new_type_variables.len());
 
@@ -42,7 +43,7 @@ impl TypeScheme {
 
     pub fn instantiate_for_printing(
         &self,
-        type_parameters: Option<Vec<String>>,
+        type_parameters: Option<Vec<Symbol>>,
     ) -> (QualifiedType, Vec<TypeVariable>) {
         match self {
             TypeScheme::Concrete(t) => {
@@ -54,7 +55,7 @@ impl TypeScheme {
                 // TODO: is this a good idea? we don't take care of name clashes here
                 let type_parameters = match type_parameters {
                     Some(tp) if tp.len() == *n_gen => {
-                        tp.iter().map(|s| TypeVariable::new(s.clone())).collect()
+                        tp.into_iter().map(TypeVariable::Named).collect()
                     }
                     _ => {
                         if *n_gen <= 26 {
@@ -112,16 +113,23 @@ impl TypeScheme {
     ) -> crate::markup::Markup {
         let (instantiated_type, type_parameters) = self.instantiate_for_printing(None);
 
+        // Types in function bodies can be quantified over variables that they do
+        // not contain, see `Statement::generalize_types`
+        let used_variables = instantiated_type.inner.type_variables(true);
+
         let mut markup = m::empty();
-        for type_parameter in &type_parameters {
+        for type_parameter in type_parameters
+            .iter()
+            .filter(|v| used_variables.contains(v))
+        {
             markup += m::keyword("forall");
             markup += m::space();
             markup += m::type_identifier(type_parameter.unsafe_name());
 
-            if instantiated_type.bounds.is_dtype_bound(type_parameter) {
+            if let Some(bound) = instantiated_type.bounds.bound_name(type_parameter) {
                 markup += m::operator(":");
                 markup += m::space();
-                markup += m::type_identifier("Dim");
+                markup += m::type_identifier(bound);
             }
             markup += m::operator(".");
             markup += m::space();
@@ -138,9 +146,23 @@ impl TypeScheme {
         }
     }
 
-    pub(crate) fn generalize(&mut self, dtype_variables: &[TypeVariable]) {
+    pub(crate) fn generalize(
+        &mut self,
+        dtype_variables: &[TypeVariable],
+        ord_variables: &[TypeVariable],
+    ) {
         let free_variables = self.type_variables(true);
+        self.generalize_over(&free_variables, dtype_variables, ord_variables);
+    }
 
+    /// Like [`Self::generalize`], but quantifies over the given variables (in this
+    /// order), which need to include all free variables of the type.
+    pub(crate) fn generalize_over(
+        &mut self,
+        variables: &[TypeVariable],
+        dtype_variables: &[TypeVariable],
+        ord_variables: &[TypeVariable],
+    ) {
         let TypeScheme::Concrete(type_) = self else {
             // TODO: we currently don't report an error here because we run generalization
             // again and again on the environment, so it has a few concrete types in it. But
@@ -152,17 +174,23 @@ impl TypeScheme {
         let bounds = dtype_variables
             .iter()
             .filter(|v| type_.contains(v, true))
-            .map(|v| Bound::IsDim(Type::TVar(v.clone())))
+            .map(|v| Bound::IsDim(Type::TVar(*v)))
+            .chain(
+                ord_variables
+                    .iter()
+                    .filter(|v| type_.contains(v, true))
+                    .map(|v| Bound::IsOrd(Type::TVar(*v))),
+            )
             .collect();
         let qualified_type = QualifiedType::new(type_.clone(), bounds);
 
         // Generalization: quantify over all free type variables
-        let type_scheme = qualified_type.quantify(&free_variables);
+        let type_scheme = qualified_type.quantify(variables);
 
         *self = type_scheme;
     }
 
-    fn type_variables(&self, including_type_parameters: bool) -> Vec<TypeVariable> {
+    pub(crate) fn type_variables(&self, including_type_parameters: bool) -> Vec<TypeVariable> {
         match self {
             TypeScheme::Concrete(t) => t.type_variables(including_type_parameters),
             TypeScheme::Quantified(_, qt) => qt.type_variables(including_type_parameters),
@@ -203,17 +231,22 @@ impl PrettyPrint for TypeScheme {
                 };
                 let instantiated_type = ts.instantiate_with(&type_parameters);
 
+                let used_variables = instantiated_type.inner.type_variables(true);
+
                 let mut markup = m::empty();
 
-                for type_parameter in &type_parameters {
+                for type_parameter in type_parameters
+                    .iter()
+                    .filter(|v| used_variables.contains(v))
+                {
                     markup += m::keyword("forall");
                     markup += m::space();
                     markup += m::type_identifier(type_parameter.unsafe_name());
 
-                    if instantiated_type.bounds.is_dtype_bound(type_parameter) {
+                    if let Some(bound) = instantiated_type.bounds.bound_name(type_parameter) {
                         markup += m::operator(":");
                         markup += m::space();
-                        markup += m::type_identifier("Dim");
+                        markup += m::type_identifier(bound);
                     }
                     markup += m::operator(".");
                     markup += m::space();
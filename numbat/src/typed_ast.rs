@@ -2,10 +2,11 @@ use indexmap::IndexMap;
 use itertools::Itertools;
 
 use crate::arithmetic::Exponent;
-pub use crate::ast::{BinaryOperator, TypeExpression, UnaryOperator};
-use crate::ast::{ProcedureKind, TypeAnnotation, TypeParameterBound};
+pub use crate::ast::{BinaryOperator, CallSyntax, TypeExpression, UnaryOperator};
+use crate::ast::{ImportScope, ProcedureKind, TypeAnnotation, TypeParameterBound};
 use crate::dimension::DimensionRegistry;
 use crate::pretty_print::escape_numbat_string;
+use crate::symbol::Symbol;
 use crate::traversal::{ForAllExpressions, ForAllTypeSchemes};
 use crate::type_variable::TypeVariable;
 use crate::typechecker::qualified_type::QualifiedType;
@@ -21,17 +22,18 @@ use crate::{markup as m, BaseRepresentation, BaseRepresentationFactor};
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum DTypeFactor {
     TVar(TypeVariable),
-    TPar(String),
-    BaseDimension(String),
+    TPar(Symbol),
+    BaseDimension(Symbol),
 }
 
 impl DTypeFactor {
-    pub fn name(&self) -> &str {
+    pub fn name(&self) -> Symbol {
         match self {
-            DTypeFactor::TVar(TypeVariable::Named(name)) => name,
+            DTypeFactor::TVar(TypeVariable::Named(name)) => *name,
+            DTypeFactor::TVar(v @ TypeVariable::Fresh(_)) => Symbol::new(&v.to_string()),
             DTypeFactor::TVar(TypeVariable::Quantified(_)) => unreachable!(),
-            DTypeFactor::TPar(name) => name,
-            DTypeFactor::BaseDimension(name) => name,
+            DTypeFactor::TPar(name) => *name,
+            DTypeFactor::BaseDimension(name) => *name,
         }
     }
 }
@@ -60,6 +62,17 @@ impl DType {
     }
 
     pub fn to_readable_type(&self, registry: &DimensionRegistry) -> m::Markup {
+        self.readable_type(registry, false)
+    }
+
+    /// Like [`Self::to_readable_type`], but picks the first name if several
+    /// dimensions match (`Energy or Torque`), so that the result can be used as
+    /// a type annotation.
+    pub(crate) fn to_type_annotation(&self, registry: &DimensionRegistry) -> m::Markup {
+        self.readable_type(registry, true)
+    }
+
+    fn readable_type(&self, registry: &DimensionRegistry, single_name: bool) -> m::Markup {
         if self.is_scalar() {
             return m::type_identifier("Scalar");
         }
@@ -67,7 +80,7 @@ impl DType {
         let mut names = vec![];
 
         if self.factors.len() == 1 && self.factors[0].1 == Exponent::from_integer(1) {
-            names.push(self.factors[0].0.name().to_string());
+            names.push(self.factors[0].0.name());
         }
 
         let base_representation = self.to_base_representation();
@@ -75,6 +88,7 @@ impl DType {
         match &names[..] {
             [] => self.pretty_print(),
             [single] => m::type_identifier(single),
+            [first, ..] if single_name => m::type_identifier(first),
             multiple => {
                 Itertools::intersperse(multiple.iter().map(m::type_identifier), m::dimmed(" or "))
                     .sum()
@@ -93,14 +107,14 @@ impl DType {
         DType::from_factors(&[(DTypeFactor::TVar(v), Exponent::from_integer(1))])
     }
 
-    pub fn from_type_parameter(name: String) -> DType {
+    pub fn from_type_parameter(name: Symbol) -> DType {
         DType::from_factors(&[(DTypeFactor::TPar(name), Exponent::from_integer(1))])
     }
 
     pub fn deconstruct_as_single_type_variable(&self) -> Option<TypeVariable> {
         match &self.factors[..] {
             [(DTypeFactor::TVar(v), exponent)] if exponent == &Exponent::from_integer(1) => {
-                Some(v.clone())
+                Some(*v)
             }
             _ => None,
         }
@@ -180,7 +194,7 @@ impl DType {
             .factors
             .iter()
             .filter_map(|(f, _)| match f {
-                DTypeFactor::TVar(v) => Some(v.clone()),
+                DTypeFactor::TVar(v) => Some(*v),
                 DTypeFactor::TPar(v) => {
                     if including_type_parameters {
                         Some(TypeVariable::new(v))
@@ -213,7 +227,7 @@ impl DType {
         for (f, n) in &self.factors {
             match f {
                 DTypeFactor::TVar(TypeVariable::Quantified(i)) => {
-                    factors.push((DTypeFactor::TVar(type_variables[*i].clone()), *n));
+                    factors.push((DTypeFactor::TVar(type_variables[*i]), *n));
                 }
                 _ => {
                     factors.push((f.clone(), *n));
@@ -228,16 +242,21 @@ impl DType {
         for (f, n) in &self.factors {
             match f {
                 DTypeFactor::BaseDimension(name) => {
-                    factors.push(BaseRepresentationFactor(name.clone(), *n));
+                    factors.push(BaseRepresentationFactor(*name, *n));
                 }
                 DTypeFactor::TVar(TypeVariable::Named(name)) => {
-                    factors.push(BaseRepresentationFactor(name.clone(), *n));
+                    factors.push(BaseRepresentationFactor(*name, *n));
+                }
+                DTypeFactor::TVar(v @ TypeVariable::Fresh(_)) => {
+                    // Only happens for types that could not be inferred, when they
+                    // are shown in error messages
+                    factors.push(BaseRepresentationFactor(Symbol::new(&v.to_string()), *n));
                 }
                 DTypeFactor::TVar(TypeVariable::Quantified(_)) => {
                     unreachable!("Unexpected quantified type")
                 }
                 DTypeFactor::TPar(name) => {
-                    factors.push(BaseRepresentationFactor(name.clone(), *n));
+                    factors.push(BaseRepresentationFactor(*name, *n));
                 }
             }
         }
@@ -270,14 +289,14 @@ impl From<BaseRepresentation> for DType {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct StructInfo {
     pub definition_span: Span,
-    pub name: String,
-    pub fields: IndexMap<String, (Span, Type)>,
+    pub name: Symbol,
+    pub fields: IndexMap<Symbol, (Span, Type)>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Type {
     TVar(TypeVariable),
-    TPar(String),
+    TPar(Symbol),
     Dimension(DType),
     Boolean,
     String,
@@ -285,15 +304,15 @@ pub enum Type {
     Fn(Vec<Type>, Box<Type>),
     Struct(StructInfo),
     List(Box<Type>),
+    /// A value whose type is only known at runtime. It is consistent with every
+    /// other type and is checked where it is used as a value of a static type.
+    Dynamic,
 }
 
 impl std::fmt::Display for Type {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Type::TVar(TypeVariable::Named(name)) => write!(f, "{}", name),
-            Type::TVar(TypeVariable::Quantified(_)) => {
-                unreachable!("Quantified types should not be printed")
-            }
+            Type::TVar(v) => write!(f, "{v}"),
             Type::TPar(name) => write!(f, "{}", name),
             Type::Dimension(d) => d.fmt(f),
             Type::Boolean => write!(f, "Bool"),
@@ -317,6 +336,7 @@ impl std::fmt::Display for Type {
                 )
             }
             Type::List(element_type) => write!(f, "List<{}>", element_type),
+            Type::Dynamic => write!(f, "Dynamic"),
         }
     }
 }
@@ -324,10 +344,7 @@ impl std::fmt::Display for Type {
 impl PrettyPrint for Type {
     fn pretty_print(&self) -> Markup {
         match self {
-            Type::TVar(TypeVariable::Named(name)) => m::type_identifier(name),
-            Type::TVar(TypeVariable::Quantified(_)) => {
-                unreachable!("Quantified types should not be printed")
-            }
+            Type::TVar(v) => m::type_identifier(v.to_string()),
             Type::TPar(name) => m::type_identifier(name),
             Type::Dimension(d) => d.pretty_print(),
             Type::Boolean => m::type_identifier("Bool"),
@@ -355,6 +372,7 @@ impl PrettyPrint for Type {
                     + element_type.pretty_print()
                     + m::operator(">")
             }
+            Type::Dynamic => m::type_identifier("Dynamic"),
         }
     }
 }
@@ -367,6 +385,13 @@ impl Type {
         }
     }
 
+    pub(crate) fn to_type_annotation(&self, registry: &DimensionRegistry) -> Markup {
+        match self {
+            Type::Dimension(d) => d.to_type_annotation(registry),
+            _ => self.pretty_print(),
+        }
+    }
+
     pub fn scalar() -> Type {
         Type::Dimension(DType::scalar())
     }
@@ -379,9 +404,43 @@ impl Type {
         matches!(self, Type::Fn(..))
     }
 
+    /// Whether or not values of this type can be sorted. A dimension type that
+    /// consists of a single type variable or type parameter could stand for any
+    /// type, so it is not considered to be orderable.
+    pub fn is_orderable(&self) -> bool {
+        match self {
+            Type::Dimension(d) => !matches!(
+                &d.factors[..],
+                [(DTypeFactor::TVar(_) | DTypeFactor::TPar(_), exponent)]
+                    if exponent == &Exponent::from_integer(1)
+            ),
+            Type::String | Type::DateTime | Type::Dynamic => true,
+            _ => false,
+        }
+    }
+
+    /// Returns the type variable if this type is a single type variable or type
+    /// parameter, possibly wrapped in a dimension type.
+    pub(crate) fn as_single_type_variable(&self) -> Option<TypeVariable> {
+        match self {
+            Type::TVar(v) => Some(*v),
+            Type::TPar(name) => Some(TypeVariable::new(*name)),
+            Type::Dimension(d) => match &d.factors[..] {
+                [(DTypeFactor::TVar(v), exponent)] if exponent == &Exponent::from_integer(1) => {
+                    Some(*v)
+                }
+                [(DTypeFactor::TPar(name), exponent)] if exponent == &Exponent::from_integer(1) => {
+                    Some(TypeVariable::new(*name))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
     pub(crate) fn type_variables(&self, including_type_parameters: bool) -> Vec<TypeVariable> {
         match self {
-            Type::TVar(v) => vec![v.clone()],
+            Type::TVar(v) => vec![*v],
             Type::TPar(n) => {
                 if including_type_parameters {
                     vec![TypeVariable::new(n)]
@@ -390,7 +449,7 @@ impl Type {
                 }
             }
             Type::Dimension(d) => d.type_variables(including_type_parameters),
-            Type::Boolean | Type::String | Type::DateTime => vec![],
+            Type::Boolean | Type::String | Type::DateTime | Type::Dynamic => vec![],
             Type::Fn(param_types, return_type) => {
                 let mut vars = return_type.type_variables(including_type_parameters);
                 for param_type in param_types {
@@ -422,11 +481,11 @@ impl Type {
 
     pub(crate) fn instantiate(&self, type_variables: &[TypeVariable]) -> Type {
         match self {
-            Type::TVar(TypeVariable::Quantified(i)) => Type::TVar(type_variables[*i].clone()),
-            Type::TVar(v) => Type::TVar(v.clone()),
-            Type::TPar(n) => Type::TPar(n.clone()),
+            Type::TVar(TypeVariable::Quantified(i)) => Type::TVar(type_variables[*i]),
+            Type::TVar(v) => Type::TVar(*v),
+            Type::TPar(n) => Type::TPar(*n),
             Type::Dimension(d) => Type::Dimension(d.instantiate(type_variables)),
-            Type::Boolean | Type::String | Type::DateTime => self.clone(),
+            Type::Boolean | Type::String | Type::DateTime | Type::Dynamic => self.clone(),
             Type::Fn(param_types, return_type) => Type::Fn(
                 param_types
                     .iter()
@@ -454,51 +513,136 @@ pub enum StringPart {
     Fixed(String),
     Interpolation {
         span: Span,
-        expr: Box<Expression>,
+        expr: ExpressionId,
         format_specifiers: Option<String>,
     },
 }
 
-impl PrettyPrint for StringPart {
-    fn pretty_print(&self) -> Markup {
-        match self {
-            StringPart::Fixed(s) => m::string(escape_numbat_string(s)),
-            StringPart::Interpolation {
-                span: _,
-                expr,
-                format_specifiers,
-            } => {
-                let mut markup = m::operator("{") + expr.pretty_print();
+/// Refers to an [`Expression`] that is stored in an [`ExpressionArena`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExpressionId(u32);
+
+/// Storage for all expressions of a type checked program. Instead of owning their
+/// sub-expressions, expressions refer to them by [`ExpressionId`]. This keeps all
+/// nodes in a single allocation and allows the type checker to visit all expressions
+/// of a statement without walking the tree.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExpressionArena {
+    expressions: Vec<Expression>,
+}
 
-                if let Some(format_specifiers) = format_specifiers {
-                    markup += m::text(format_specifiers);
-                }
+impl ExpressionArena {
+    pub fn alloc(&mut self, expression: Expression) -> ExpressionId {
+        let id = self.next_id();
+        self.expressions.push(expression);
+        id
+    }
 
-                markup += m::operator("}");
+    /// The id that the next allocated expression will get.
+    pub fn next_id(&self) -> ExpressionId {
+        ExpressionId(
+            self.expressions
+                .len()
+                .try_into()
+                .expect("Number of expressions fits into 32 bits"),
+        )
+    }
 
-                markup
+    /// All expressions that have been allocated since (and including) `first`.
+    pub fn since_mut(&mut self, first: ExpressionId) -> &mut [Expression] {
+        &mut self.expressions[first.0 as usize..]
+    }
+}
+
+impl std::ops::Index<ExpressionId> for ExpressionArena {
+    type Output = Expression;
+
+    fn index(&self, id: ExpressionId) -> &Expression {
+        &self.expressions[id.0 as usize]
+    }
+}
+
+/// The target of a conversion, see [`Expression::Conversion`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConversionTarget {
+    /// A unit like `km/h`. The target can be any quantity, its unit is used.
+    Unit(ExpressionId),
+    /// A list of units like `[foot, inch]`. The result is a list with one quantity
+    /// per unit, all but the last of them integer multiples of their unit.
+    UnitList(Span, Vec<ExpressionId>),
+    /// A unit system like `SI`
+    UnitSystem(Span, Symbol),
+    /// A function defined with `unitfn`, like `awg`: span and name of the function,
+    /// and its inverse, which is called with the value
+    UnitFunction(Span, Symbol, ExpressionId),
+    /// A function that is called with the value, for formatting (`hex`) or other
+    /// conversions (`tz("Europe/Berlin")`)
+    Function(ExpressionId),
+}
+
+impl ConversionTarget {
+    pub fn full_span(&self, arena: &ExpressionArena) -> Span {
+        match self {
+            ConversionTarget::Unit(expr) | ConversionTarget::Function(expr) => {
+                arena[*expr].full_span(arena)
             }
+            ConversionTarget::UnitList(span, _)
+            | ConversionTarget::UnitSystem(span, _)
+            | ConversionTarget::UnitFunction(span, _, _) => *span,
         }
     }
-}
 
-impl PrettyPrint for &Vec<StringPart> {
-    fn pretty_print(&self) -> Markup {
-        m::operator("\"") + self.iter().map(|p| p.pretty_print()).sum() + m::operator("\"")
+    /// All sub-expressions of the target
+    pub fn expressions(&self) -> Vec<ExpressionId> {
+        match self {
+            ConversionTarget::Unit(expr)
+            | ConversionTarget::UnitFunction(_, _, expr)
+            | ConversionTarget::Function(expr) => vec![*expr],
+            ConversionTarget::UnitList(_, units) => units.clone(),
+            ConversionTarget::UnitSystem(_, _) => vec![],
+        }
+    }
+
+    fn pretty_print(&self, arena: &ExpressionArena) -> Markup {
+        match self {
+            ConversionTarget::Unit(expr) | ConversionTarget::Function(expr) => {
+                // Pipes have a lower precedence than conversions
+                if matches!(
+                    arena[*expr],
+                    Expression::FunctionCall(_, _, _, _, CallSyntax::Pipe, _)
+                ) {
+                    with_parens(arena, &arena[*expr])
+                } else {
+                    arena[*expr].pretty_print(arena)
+                }
+            }
+            ConversionTarget::UnitList(_, units) => {
+                m::operator("[")
+                    + Itertools::intersperse(
+                        units.iter().map(|unit| arena[*unit].pretty_print(arena)),
+                        m::operator(",") + m::space(),
+                    )
+                    .sum()
+                    + m::operator("]")
+            }
+            ConversionTarget::UnitSystem(_, name) | ConversionTarget::UnitFunction(_, name, _) => {
+                m::identifier(name)
+            }
+        }
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expression {
     Scalar(Span, Number, TypeScheme),
-    Identifier(Span, String, TypeScheme),
-    UnitIdentifier(Span, Prefix, String, String, TypeScheme),
-    UnaryOperator(Span, UnaryOperator, Box<Expression>, TypeScheme),
+    Identifier(Span, Symbol, TypeScheme),
+    UnitIdentifier(Span, Prefix, Symbol, Symbol, TypeScheme),
+    UnaryOperator(Span, UnaryOperator, ExpressionId, TypeScheme),
     BinaryOperator(
         Option<Span>,
         BinaryOperator,
-        Box<Expression>,
-        Box<Expression>,
+        ExpressionId,
+        ExpressionId,
         TypeScheme,
     ),
     /// A special binary operator that has a DateTime as one (or both) of the operands
@@ -506,125 +650,331 @@ pub enum Expression {
         Option<Span>,
         BinaryOperator,
         /// LHS must evaluate to a DateTime
-        Box<Expression>,
+        ExpressionId,
         /// RHS can evaluate to a DateTime or a quantity of type Time
-        Box<Expression>,
+        ExpressionId,
         TypeScheme,
     ),
+    /// A conversion `source -> target`: span of the operator, source, target
+    Conversion(Span, ExpressionId, ConversionTarget, TypeScheme),
+    /// A chain like `a < b <= c`, consisting of the comparisons of all adjacent
+    /// operands (`a < b` and `b <= c`). The operands that are shared between two
+    /// comparisons are only evaluated once, as part of the first of them.
+    ChainedComparison(Vec<ExpressionId>),
     // A 'proper' function call
-    FunctionCall(Span, Span, String, Vec<Expression>, TypeScheme),
+    FunctionCall(
+        Span,
+        Span,
+        Symbol,
+        Vec<ExpressionId>,
+        CallSyntax,
+        TypeScheme,
+    ),
     // A call via a function object
-    CallableCall(Span, Box<Expression>, Vec<Expression>, TypeScheme),
+    CallableCall(Span, ExpressionId, Vec<ExpressionId>, TypeScheme),
     Boolean(Span, bool),
-    Condition(Span, Box<Expression>, Box<Expression>, Box<Expression>),
+    Condition(Span, ExpressionId, ExpressionId, ExpressionId),
     String(Span, Vec<StringPart>),
-    InstantiateStruct(Span, Vec<(String, Expression)>, StructInfo),
+    /// Struct instantiation. The optional expression is the base instance in
+    /// an update expression, from which all fields that are not explicitly
+    /// given are copied.
+    InstantiateStruct(
+        Span,
+        Option<ExpressionId>,
+        Vec<(Symbol, ExpressionId)>,
+        StructInfo,
+    ),
     AccessField(
         Span,
         Span,
-        Box<Expression>,
-        String,     // field name
+        ExpressionId,
+        Symbol,     // field name
         TypeScheme, // struct type
         TypeScheme, // resulting field type
     ),
-    List(Span, Vec<Expression>, TypeScheme),
+    List(Span, Vec<ExpressionId>, TypeScheme),
     TypedHole(Span, TypeScheme),
+    /// A named argument `name = expr` in a function call: span of the name, name, argument
+    NamedArgument(Span, Symbol, ExpressionId),
+    /// A placeholder for an argument that has not been specified in a function call.
+    /// The default value of the corresponding parameter is used instead.
+    DefaultArgument(Span, TypeScheme),
+    /// `trace(expr)`: full span, source code of the argument, argument
+    Trace(Span, String, ExpressionId),
+    /// `partial(f, args…)`: full span, function, bound arguments and the type of the
+    /// resulting function. Parameters in between bound arguments that remain
+    /// unbound are [`Expression::DefaultArgument`]s.
+    Partial(Span, ExpressionId, Vec<ExpressionId>, TypeScheme),
+    /// `autodiff(f)`: full span, function that is differentiated, the definitions of
+    /// the derivatives (the last one is the result) and the type of the derivative
+    AutoDiff(Span, ExpressionId, Vec<Statement>, TypeScheme),
+    /// `for x in xs { body }`: full span, loop variable, list, optional accumulator
+    /// (name and initial value), body
+    For(
+        Span,
+        Symbol,
+        ExpressionId,
+        Option<(Symbol, ExpressionId)>,
+        ExpressionId,
+    ),
+    /// `while condition with acc = init { body }`: full span, condition,
+    /// accumulator (name and initial value), body
+    While(Span, ExpressionId, (Symbol, ExpressionId), ExpressionId),
+    /// `try { expr } catch message { fallback }`: full span, expression, optional
+    /// name for the error message, fallback
+    TryCatch(Span, ExpressionId, Option<Symbol>, ExpressionId),
+    /// An expression of type `Dynamic` that is used as a value of the given type.
+    /// The type of its value is checked at runtime. The flag is set if the value
+    /// also needs to be a quantity, even if its dimension is not known.
+    DynamicCheck(Span, ExpressionId, TypeScheme, bool),
 }
 
 impl Expression {
-    pub fn full_span(&self) -> Span {
+    pub fn full_span(&self, arena: &ExpressionArena) -> Span {
+        let span_of = |id: &ExpressionId| arena[*id].full_span(arena);
         match self {
             Expression::Scalar(span, ..) => *span,
             Expression::Identifier(span, ..) => *span,
             Expression::UnitIdentifier(span, ..) => *span,
-            Expression::UnaryOperator(span, _, expr, _) => span.extend(&expr.full_span()),
-            Expression::BinaryOperator(span_op, _op, lhs, rhs, _) => {
-                let mut span = lhs.full_span().extend(&rhs.full_span());
-                if let Some(span_op) = span_op {
-                    span = span.extend(span_op);
+            Expression::UnaryOperator(span, _, expr, _) => span.extend(&span_of(expr)),
+            Expression::BinaryOperator(..) | Expression::BinaryOperatorForDate(..) => {
+                // Walk down the left-hand sides of a chain of binary operators without
+                // recursion, to support long chains like `1 + 2 + … + n`
+                let mut span: Option<Span> = None;
+                let mut extend = |other: &Span| {
+                    span = Some(span.map_or(*other, |span| span.extend(other)));
+                };
+
+                let mut expr = self;
+                while let Expression::BinaryOperator(span_op, _, lhs, rhs, _)
+                | Expression::BinaryOperatorForDate(span_op, _, lhs, rhs, _) = expr
+                {
+                    extend(&span_of(rhs));
+                    if let Some(span_op) = span_op {
+                        extend(span_op);
+                    }
+                    expr = &arena[*lhs];
                 }
-                span
+                extend(&expr.full_span(arena));
+
+                span.unwrap()
             }
-            Expression::BinaryOperatorForDate(span_op, _op, lhs, rhs, ..) => {
-                let mut span = lhs.full_span().extend(&rhs.full_span());
-                if let Some(span_op) = span_op {
-                    span = span.extend(span_op);
-                }
-                span
+            Expression::Conversion(_, source, target, _) => {
+                span_of(source).extend(&target.full_span(arena))
             }
-            Expression::FunctionCall(_identifier_span, full_span, _, _, _) => *full_span,
+            Expression::ChainedComparison(comparisons) => span_of(&comparisons[0]).extend(
+                &span_of(comparisons.last().expect("at least one comparison")),
+            ),
+            Expression::FunctionCall(_identifier_span, full_span, _, _, _, _) => *full_span,
             Expression::CallableCall(full_span, _, _, _) => *full_span,
             Expression::Boolean(span, _) => *span,
-            Expression::Condition(span_if, _, _, then_expr) => {
-                span_if.extend(&then_expr.full_span())
-            }
+            Expression::Condition(span_if, _, _, then_expr) => span_if.extend(&span_of(then_expr)),
             Expression::String(span, _) => *span,
-            Expression::InstantiateStruct(span, _, _) => *span,
+            Expression::InstantiateStruct(span, _, _, _) => *span,
             Expression::AccessField(_span, full_span, _, _, _, _) => *full_span,
             Expression::List(full_span, _, _) => *full_span,
             Expression::TypedHole(span, _) => *span,
+            Expression::NamedArgument(span, _, expr) => span.extend(&span_of(expr)),
+            Expression::DefaultArgument(span, _) => *span,
+            Expression::Trace(span, _, _) => *span,
+            Expression::Partial(span, _, _, _) => *span,
+            Expression::AutoDiff(span, _, _, _) => *span,
+            Expression::For(span, ..)
+            | Expression::While(span, ..)
+            | Expression::TryCatch(span, ..) => *span,
+            Expression::DynamicCheck(span, ..) => *span,
+        }
+    }
+}
+
+/// The left hand side of a variable definition, see [`crate::ast::Pattern`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Identifier(Symbol),
+    /// The fields in the order of the pattern, and the type of the struct
+    Struct(Vec<(Symbol, Pattern)>, TypeScheme),
+    List(Vec<Pattern>),
+}
+
+impl PrettyPrint for Pattern {
+    fn pretty_print(&self) -> Markup {
+        match self {
+            Pattern::Identifier(identifier) => m::identifier(identifier),
+            Pattern::Struct(fields, _) => {
+                m::operator("{")
+                    + Itertools::intersperse(
+                        fields.iter().map(|(field, pattern)| match pattern {
+                            Pattern::Identifier(identifier) if identifier == field => {
+                                m::identifier(field)
+                            }
+                            _ => {
+                                m::identifier(field)
+                                    + m::operator(":")
+                                    + m::space()
+                                    + pattern.pretty_print()
+                            }
+                        }),
+                        m::operator(",") + m::space(),
+                    )
+                    .sum()
+                    + m::operator("}")
+            }
+            Pattern::List(elements) => {
+                m::operator("[")
+                    + Itertools::intersperse(
+                        elements.iter().map(|pattern| pattern.pretty_print()),
+                        m::operator(",") + m::space(),
+                    )
+                    .sum()
+                    + m::operator("]")
+            }
         }
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct DefineVariable(
-    pub String,
+    pub Pattern,
     pub Vec<Decorator>,
-    pub Expression,
+    pub ExpressionId,
     pub Option<TypeAnnotation>,
     pub TypeScheme,
     pub Markup,
 );
 
+pub type FunctionParameter = (
+    Span,                   // span of the parameter
+    Symbol,                 // parameter name
+    Option<TypeAnnotation>, // parameter type annotation
+    Markup,                 // readable parameter type
+    Option<ExpressionId>,   // default value
+);
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Statement {
-    Expression(Expression),
+    Expression(ExpressionId),
     DefineVariable(DefineVariable),
     DefineFunction(
-        String,
+        Symbol,
         Vec<Decorator>,                            // decorators
-        Vec<(String, Option<TypeParameterBound>)>, // type parameters
-        Vec<(
-            // parameters:
-            Span,                   // span of the parameter
-            String,                 // parameter name
-            Option<TypeAnnotation>, // parameter type annotation
-            Markup,                 // readable parameter type
-        )>,
-        Option<Expression>,     // function body
-        Vec<DefineVariable>,    // local variables
-        TypeScheme,             // function type
-        Option<TypeAnnotation>, // return type annotation
-        Markup,                 // readable return type
+        Vec<(Symbol, Option<TypeParameterBound>)>, // type parameters
+        Vec<FunctionParameter>,                    // parameters
+        Option<ExpressionId>,                      // function body
+        Vec<DefineVariable>,                       // local variables
+        Vec<Statement>,                            // local unit and dimension definitions
+        TypeScheme,                                // function type
+        Option<TypeAnnotation>,                    // return type annotation
+        Markup,                                    // readable return type
+    ),
+    DefineDimension(Symbol, Vec<TypeExpression>),
+    DefineBaseUnit(
+        Span,
+        Symbol,
+        Vec<Decorator>,
+        Option<TypeAnnotation>,
+        TypeScheme,
+        bool, // whether the dimension was created implicitly, as in 'unit widget'
     ),
-    DefineDimension(String, Vec<TypeExpression>),
-    DefineBaseUnit(String, Vec<Decorator>, Option<TypeAnnotation>, TypeScheme),
     DefineDerivedUnit(
-        String,
-        Expression,
+        Span,
+        Symbol,
+        ExpressionId,
         Vec<Decorator>,
         Option<TypeAnnotation>,
         TypeScheme,
         Markup,
     ),
-    ProcedureCall(crate::ast::ProcedureKind, Vec<Expression>),
+    ProcedureCall(crate::ast::ProcedureKind, Vec<ExpressionId>),
+    /// Condition, `then` branch and optional `else` branch
+    Conditional(ExpressionId, Box<Statement>, Option<Box<Statement>>),
     DefineStruct(StructInfo),
+    DefineUnitSystem(Symbol, Vec<ExpressionId>),
+    /// A function defined with `unitfn`, and its optional inverse. Both are
+    /// [`Statement::DefineFunction`]s.
+    DefineUnitFunction(Box<Statement>, Option<Box<Statement>>),
+    /// The methods of a struct from an `impl` block. They are
+    /// [`Statement::DefineFunction`]s, named with [`crate::ast::method_name`].
+    DefineMethods(Symbol, Vec<Statement>),
+    /// The functions that solve a formula for each of its quantities, named with
+    /// [`crate::ast::formula_function_name`]
+    DefineFormula(Symbol, Vec<Statement>),
+    /// A generic function that uses dimension predicates. The wrapped
+    /// [`Statement::DefineFunction`] only has a signature, as the body is checked
+    /// and compiled separately for each specialization.
+    DefineGenericFunction(Box<Statement>),
+    /// Hides or reveals definitions of selectively imported modules
+    ImportScope(ImportScope),
 }
 
 impl Statement {
-    pub fn as_expression(&self) -> Option<&Expression> {
+    pub fn as_expression(&self) -> Option<ExpressionId> {
         if let Self::Expression(v) = self {
-            Some(v)
+            Some(*v)
         } else {
             None
         }
     }
 
-    pub(crate) fn generalize_types(&mut self, dtype_variables: &[TypeVariable]) {
-        self.for_all_type_schemes(&mut |type_: &mut TypeScheme| type_.generalize(dtype_variables));
+    /// The (not yet generalized) type of a function definition
+    pub(crate) fn function_type(&self) -> Type {
+        match self {
+            Statement::DefineFunction(_, _, _, _, _, _, _, fn_type, _, _) => {
+                fn_type.unsafe_as_concrete()
+            }
+            _ => unreachable!("Expected a function definition"),
+        }
+    }
+
+    /// Calls `f` for all type schemes of this statement and of the given expressions,
+    /// which are the expressions that have been allocated for this statement.
+    fn for_all_type_schemes_with(
+        &mut self,
+        expressions: &mut [Expression],
+        f: &mut dyn FnMut(&mut TypeScheme),
+    ) {
+        self.for_all_type_schemes(f);
+        for expression in expressions {
+            expression.for_all_type_schemes(f);
+        }
     }
 
+    /// The types in a function definition are all quantified over the variables of
+    /// the function type (in the same order), followed by any other free variables.
+    /// A quantified variable thus stands for the same type in the whole definition,
+    /// which allows the body to be specialized for the concrete types of a call.
+    pub(crate) fn generalize_types(
+        &mut self,
+        expressions: &mut [Expression],
+        dtype_variables: &[TypeVariable],
+        ord_variables: &[TypeVariable],
+    ) {
+        let Statement::DefineFunction(_, _, _, _, _, _, _, fn_type, _, _) = self else {
+            self.for_all_type_schemes_with(expressions, &mut |type_: &mut TypeScheme| {
+                type_.generalize(dtype_variables, ord_variables)
+            });
+            return;
+        };
+
+        let mut variables = fn_type.type_variables(true);
+        fn_type.generalize(dtype_variables, ord_variables);
+
+        self.for_all_type_schemes_with(expressions, &mut |type_: &mut TypeScheme| {
+            if let TypeScheme::Concrete(type_) = type_ {
+                for variable in type_.type_variables(true) {
+                    if !variables.contains(&variable) {
+                        variables.push(variable);
+                    }
+                }
+            }
+        });
+
+        self.for_all_type_schemes_with(expressions, &mut |type_: &mut TypeScheme| {
+            type_.generalize_over(&variables, dtype_variables, ord_variables)
+        });
+    }
+
+    /// The type as it is written in the pretty printed code. This is empty for
+    /// generic definitions like `let zero = 0`, which can not be annotated.
     fn create_readable_type(
         registry: &DimensionRegistry,
         type_: &TypeScheme,
@@ -633,7 +983,12 @@ impl Statement {
         if let Some(annotation) = annotation {
             annotation.pretty_print()
         } else {
-            type_.to_readable_type(registry)
+            let (type_, type_parameters) = type_.instantiate_for_printing(None);
+            if type_parameters.is_empty() {
+                type_.inner.to_type_annotation(registry)
+            } else {
+                m::empty()
+            }
         }
     }
 
@@ -657,14 +1012,19 @@ impl Statement {
                 parameters,
                 _,
                 local_variables,
+                local_units,
                 fn_type,
                 return_type_annotation,
                 readable_return_type,
             ) => {
                 let (fn_type, _) = fn_type.instantiate_for_printing(Some(
-                    type_parameters.iter().map(|(n, _)| n.clone()).collect(),
+                    type_parameters.iter().map(|(n, _)| *n).collect(),
                 ));
 
+                for local_unit in local_units {
+                    local_unit.update_readable_types(registry);
+                }
+
                 for DefineVariable(_, _, _, type_annotation, type_, readable_type) in
                     local_variables
                 {
@@ -681,7 +1041,7 @@ impl Statement {
                     return_type_annotation,
                 );
 
-                for ((_, _, type_annotation, readable_parameter_type), parameter_type) in
+                for ((_, _, type_annotation, readable_parameter_type, _), parameter_type) in
                     parameters.iter_mut().zip(parameter_types.iter())
                 {
                     *readable_parameter_type = Self::create_readable_type(
@@ -691,23 +1051,46 @@ impl Statement {
                     );
                 }
             }
-            Statement::DefineDimension(_, _) => {}
-            Statement::DefineBaseUnit(_, _, _, _) => {}
-            Statement::DefineDerivedUnit(_, _, _, type_annotation, type_, readable_type) => {
+            Statement::DefineDimension(_, _) | Statement::ImportScope(_) => {}
+            Statement::DefineBaseUnit(_, _, _, _, _, _) => {}
+            Statement::DefineDerivedUnit(_, _, _, _, type_annotation, type_, readable_type) => {
                 *readable_type = Self::create_readable_type(registry, type_, type_annotation);
             }
             Statement::ProcedureCall(_, _) => {}
+            Statement::Conditional(_, then_statement, else_statement) => {
+                then_statement.update_readable_types(registry);
+                if let Some(else_statement) = else_statement {
+                    else_statement.update_readable_types(registry);
+                }
+            }
             Statement::DefineStruct(_) => {}
+            Statement::DefineUnitSystem(_, _) => {}
+            Statement::DefineUnitFunction(function, inverse) => {
+                function.update_readable_types(registry);
+                if let Some(inverse) = inverse {
+                    inverse.update_readable_types(registry);
+                }
+            }
+            Statement::DefineMethods(_, functions) | Statement::DefineFormula(_, functions) => {
+                for function in functions {
+                    function.update_readable_types(registry);
+                }
+            }
+            Statement::DefineGenericFunction(function) => function.update_readable_types(registry),
         }
     }
 
-    pub(crate) fn exponents_for(&mut self, tv: &TypeVariable) -> Vec<Exponent> {
+    pub(crate) fn exponents_for(
+        &mut self,
+        expressions: &mut [Expression],
+        tv: &TypeVariable,
+    ) -> Vec<Exponent> {
         // TODO: things to not need to be mutable in this function
         let mut exponents = vec![];
-        self.for_all_type_schemes(&mut |type_: &mut TypeScheme| {
+        self.for_all_type_schemes_with(expressions, &mut |type_: &mut TypeScheme| {
             if let Type::Dimension(dtype) = type_.unsafe_as_concrete() {
                 for (factor, exp) in dtype.factors {
-                    if factor == DTypeFactor::TVar(tv.clone()) {
+                    if factor == DTypeFactor::TVar(*tv) {
                         exponents.push(exp)
                     }
                 }
@@ -716,10 +1099,13 @@ impl Statement {
         exponents
     }
 
-    pub(crate) fn find_typed_hole(&self) -> Result<Option<(Span, TypeScheme)>, TypeCheckError> {
+    pub(crate) fn find_typed_hole(
+        &self,
+        arena: &ExpressionArena,
+    ) -> Result<Option<(Span, TypeScheme)>, TypeCheckError> {
         let mut hole = None;
         let mut found_multiple_holes = false;
-        self.for_all_expressions(&mut |expr| {
+        self.for_all_expressions(arena, &mut |expr| {
             if let Expression::TypedHole(span, type_) = expr {
                 if hole.is_some() {
                     found_multiple_holes = true;
@@ -737,7 +1123,7 @@ impl Statement {
 }
 
 impl Expression {
-    pub fn get_type(&self) -> Type {
+    pub fn get_type(&self, arena: &ExpressionArena) -> Type {
         match self {
             Expression::Scalar(_, _, type_) => type_.unsafe_as_concrete(),
             Expression::Identifier(_, _, type_) => type_.unsafe_as_concrete(),
@@ -745,12 +1131,14 @@ impl Expression {
             Expression::UnaryOperator(_, _, _, type_) => type_.unsafe_as_concrete(),
             Expression::BinaryOperator(_, _, _, _, type_) => type_.unsafe_as_concrete(),
             Expression::BinaryOperatorForDate(_, _, _, _, type_, ..) => type_.unsafe_as_concrete(),
-            Expression::FunctionCall(_, _, _, _, type_) => type_.unsafe_as_concrete(),
+            Expression::Conversion(_, _, _, type_) => type_.unsafe_as_concrete(),
+            Expression::ChainedComparison(_) => Type::Boolean,
+            Expression::FunctionCall(_, _, _, _, _, type_) => type_.unsafe_as_concrete(),
             Expression::CallableCall(_, _, _, type_) => type_.unsafe_as_concrete(),
             Expression::Boolean(_, _) => Type::Boolean,
-            Expression::Condition(_, _, then_, _) => then_.get_type(),
+            Expression::Condition(_, _, then_, _) => arena[*then_].get_type(arena),
             Expression::String(_, _) => Type::String,
-            Expression::InstantiateStruct(_, _, info_) => Type::Struct(info_.clone()),
+            Expression::InstantiateStruct(_, _, _, info_) => Type::Struct(info_.clone()),
             Expression::AccessField(_, _, _, _, _struct_type, field_type) => {
                 field_type.unsafe_as_concrete()
             }
@@ -758,10 +1146,24 @@ impl Expression {
                 Type::List(Box::new(element_type.unsafe_as_concrete()))
             }
             Expression::TypedHole(_, type_) => type_.unsafe_as_concrete(),
+            Expression::NamedArgument(_, _, expr) => arena[*expr].get_type(arena),
+            Expression::DefaultArgument(_, type_) => type_.unsafe_as_concrete(),
+            Expression::Trace(_, _, expr) => arena[*expr].get_type(arena),
+            Expression::Partial(_, _, _, type_) | Expression::AutoDiff(_, _, _, type_) => {
+                type_.unsafe_as_concrete()
+            }
+            Expression::For(_, _, _, None, body) => {
+                Type::List(Box::new(arena[*body].get_type(arena)))
+            }
+            Expression::For(_, _, _, Some(_), body) | Expression::While(_, _, _, body) => {
+                arena[*body].get_type(arena)
+            }
+            Expression::TryCatch(_, expr, _, _) => arena[*expr].get_type(arena),
+            Expression::DynamicCheck(_, _, type_, _) => type_.unsafe_as_concrete(),
         }
     }
 
-    pub fn get_type_scheme(&self) -> TypeScheme {
+    pub fn get_type_scheme(&self, arena: &ExpressionArena) -> TypeScheme {
         match self {
             Expression::Scalar(_, _, type_) => type_.clone(),
             Expression::Identifier(_, _, type_) => type_.clone(),
@@ -769,12 +1171,14 @@ impl Expression {
             Expression::UnaryOperator(_, _, _, type_) => type_.clone(),
             Expression::BinaryOperator(_, _, _, _, type_) => type_.clone(),
             Expression::BinaryOperatorForDate(_, _, _, _, type_, ..) => type_.clone(),
-            Expression::FunctionCall(_, _, _, _, type_) => type_.clone(),
+            Expression::Conversion(_, _, _, type_) => type_.clone(),
+            Expression::ChainedComparison(_) => TypeScheme::make_quantified(Type::Boolean),
+            Expression::FunctionCall(_, _, _, _, _, type_) => type_.clone(),
             Expression::CallableCall(_, _, _, type_) => type_.clone(),
             Expression::Boolean(_, _) => TypeScheme::make_quantified(Type::Boolean),
-            Expression::Condition(_, _, then_, _) => then_.get_type_scheme(),
+            Expression::Condition(_, _, then_, _) => arena[*then_].get_type_scheme(arena),
             Expression::String(_, _) => TypeScheme::make_quantified(Type::String),
-            Expression::InstantiateStruct(_, _, info_) => {
+            Expression::InstantiateStruct(_, _, _, info_) => {
                 TypeScheme::make_quantified(Type::Struct(info_.clone()))
             }
             Expression::AccessField(_, _, _, _, _struct_type, field_type) => field_type.clone(),
@@ -789,10 +1193,40 @@ impl Expression {
                 ),
             },
             Expression::TypedHole(_, type_) => type_.clone(),
+            Expression::NamedArgument(_, _, expr) => arena[*expr].get_type_scheme(arena),
+            Expression::DefaultArgument(_, type_) => type_.clone(),
+            Expression::Trace(_, _, expr) => arena[*expr].get_type_scheme(arena),
+            Expression::Partial(_, _, _, type_) | Expression::AutoDiff(_, _, _, type_) => {
+                type_.clone()
+            }
+            Expression::For(_, _, _, None, body) => match arena[*body].get_type_scheme(arena) {
+                TypeScheme::Concrete(t) => TypeScheme::Concrete(Type::List(Box::new(t))),
+                TypeScheme::Quantified(ngen, qt) => TypeScheme::Quantified(
+                    ngen,
+                    crate::typechecker::qualified_type::QualifiedType {
+                        inner: Type::List(Box::new(qt.inner)),
+                        bounds: qt.bounds,
+                    },
+                ),
+            },
+            Expression::For(_, _, _, Some(_), body) | Expression::While(_, _, _, body) => {
+                arena[*body].get_type_scheme(arena)
+            }
+            Expression::TryCatch(_, expr, _, _) => arena[*expr].get_type_scheme(arena),
+            Expression::DynamicCheck(_, _, type_, _) => type_.clone(),
         }
     }
 }
 
+/// `: Type`, unless the type is unknown (see `Statement::create_readable_type`)
+fn type_annotation_markup(readable_type: &Markup) -> Markup {
+    if readable_type.0.is_empty() {
+        m::empty()
+    } else {
+        m::operator(":") + m::space() + readable_type.clone()
+    }
+}
+
 fn accepts_prefix_markup(accepts_prefix: &Option<AcceptsPrefix>) -> Markup {
     if let Some(accepts_prefix) = accepts_prefix {
         m::operator(":")
@@ -826,6 +1260,7 @@ fn decorator_markup(decorators: &Vec<Decorator>) -> Markup {
         markup_decorators = markup_decorators
             + match decorator {
                 Decorator::MetricPrefixes => m::decorator("@metric_prefixes"),
+                Decorator::Isolated => m::decorator("@isolated"),
                 Decorator::BinaryPrefixes => m::decorator("@binary_prefixes"),
                 Decorator::Aliases(names) => {
                     m::decorator("@aliases")
@@ -840,15 +1275,56 @@ fn decorator_markup(decorators: &Vec<Decorator>) -> Markup {
                         + m::operator(")")
                 }
                 Decorator::Url(url) => {
-                    m::decorator("@url") + m::operator("(") + m::string(url) + m::operator(")")
+                    m::decorator("@url") + m::operator("(") + url.pretty_print() + m::operator(")")
                 }
                 Decorator::Name(name) => {
-                    m::decorator("@name") + m::operator("(") + m::string(name) + m::operator(")")
+                    m::decorator("@name")
+                        + m::operator("(")
+                        + name.pretty_print()
+                        + m::operator(")")
                 }
                 Decorator::Description(description) => {
                     m::decorator("@description")
                         + m::operator("(")
-                        + m::string(description)
+                        + description.pretty_print()
+                        + m::operator(")")
+                }
+                Decorator::Operator(op) => {
+                    m::decorator("@operator")
+                        + m::operator("(")
+                        + m::operator(op.pretty_print().to_string().trim())
+                        + m::operator(")")
+                }
+                Decorator::Currency(code, decimal_places) => {
+                    m::decorator("@currency")
+                        + m::operator("(")
+                        + code.pretty_print()
+                        + m::operator(", ")
+                        + m::value(decimal_places.to_string())
+                        + m::operator(")")
+                }
+                Decorator::Ucum(code) => {
+                    m::decorator("@ucum")
+                        + m::operator("(")
+                        + code.pretty_print()
+                        + m::operator(")")
+                }
+                Decorator::Dimension(name) => {
+                    m::decorator("@dimension")
+                        + m::operator("(")
+                        + m::type_identifier(name)
+                        + m::operator(")")
+                }
+                Decorator::Deprecated(message) => {
+                    m::decorator("@deprecated")
+                        + m::operator("(")
+                        + message.pretty_print()
+                        + m::operator(")")
+                }
+                Decorator::Uncertainty(uncertainty) => {
+                    m::decorator("@uncertainty")
+                        + m::operator("(")
+                        + m::value(uncertainty.pretty_print())
                         + m::operator(")")
                 }
             }
@@ -863,7 +1339,7 @@ pub fn pretty_print_function_signature(
     type_parameters: &[TypeVariable],
     parameters: impl Iterator<
         Item = (
-            String, // parameter name
+            Symbol, // parameter name
             Markup, // readable parameter type
         ),
     >,
@@ -876,8 +1352,8 @@ pub fn pretty_print_function_signature(
             + Itertools::intersperse(
                 type_parameters.iter().map(|tv| {
                     m::type_identifier(tv.unsafe_name())
-                        + if fn_type.bounds.is_dtype_bound(tv) {
-                            m::operator(":") + m::space() + m::type_identifier("Dim")
+                        + if let Some(bound) = fn_type.bounds.bound_name(tv) {
+                            m::operator(":") + m::space() + m::type_identifier(bound)
                         } else {
                             m::empty()
                         }
@@ -909,93 +1385,103 @@ pub fn pretty_print_function_signature(
         + markup_return_type
 }
 
-impl PrettyPrint for Statement {
-    fn pretty_print(&self) -> Markup {
+impl Statement {
+    pub fn pretty_print(&self, arena: &ExpressionArena) -> Markup {
+        let pp = |id: &ExpressionId| arena[*id].pretty_print(arena);
+
         match self {
             Statement::DefineVariable(DefineVariable(
-                identifier,
-                _decs,
+                pattern,
+                decorators,
                 expr,
                 _annotation,
                 _type,
                 readable_type,
             )) => {
-                m::keyword("let")
-                    + m::space()
-                    + m::identifier(identifier)
-                    + m::operator(":")
+                decorator_markup(decorators)
+                    + m::keyword("let")
                     + m::space()
-                    + readable_type.clone()
+                    + pattern.pretty_print()
+                    + type_annotation_markup(readable_type)
                     + m::space()
                     + m::operator("=")
                     + m::space()
-                    + expr.pretty_print()
+                    + pp(expr)
             }
             Statement::DefineFunction(
                 function_name,
-                _decorators,
+                decorators,
                 type_parameters,
                 parameters,
                 body,
                 local_variables,
+                local_units,
                 fn_type,
                 _return_type_annotation,
                 readable_return_type,
             ) => {
                 let (fn_type, type_parameters) = fn_type.instantiate_for_printing(Some(
-                    type_parameters.iter().map(|(n, _)| n.clone()).collect(),
+                    type_parameters.iter().map(|(n, _)| *n).collect(),
                 ));
 
                 let mut pretty_local_variables = None;
                 let mut first = true;
-                if !local_variables.is_empty() {
+                let mut introducer_keyword = || {
+                    if first {
+                        first = false;
+                        m::space() + m::space() + m::keyword("where")
+                    } else {
+                        m::space() + m::space() + m::space() + m::space() + m::keyword("and")
+                    }
+                };
+                if !local_variables.is_empty() || !local_units.is_empty() {
                     let mut plv = m::empty();
-                    for DefineVariable(
-                        identifier,
-                        _decs,
-                        expr,
-                        _annotation,
-                        _type,
-                        readable_type,
-                    ) in local_variables
-                    {
-                        let introducer_keyword = if first {
-                            first = false;
-                            m::space() + m::space() + m::keyword("where")
-                        } else {
-                            m::space() + m::space() + m::space() + m::space() + m::keyword("and")
-                        };
-
+                    for local_unit in local_units {
                         plv += m::nl()
-                            + introducer_keyword
+                            + introducer_keyword()
                             + m::space()
-                            + m::identifier(identifier)
-                            + m::operator(":")
+                            + local_unit.pretty_print(arena);
+                    }
+                    for DefineVariable(pattern, _decs, expr, _annotation, _type, readable_type) in
+                        local_variables
+                    {
+                        plv += m::nl()
+                            + introducer_keyword()
                             + m::space()
-                            + readable_type.clone()
+                            + pattern.pretty_print()
+                            + type_annotation_markup(readable_type)
                             + m::space()
                             + m::operator("=")
                             + m::space()
-                            + expr.pretty_print();
+                            + pp(expr);
                     }
                     pretty_local_variables = Some(plv);
                 }
 
-                pretty_print_function_signature(
-                    function_name,
-                    &fn_type,
-                    &type_parameters,
-                    parameters
-                        .iter()
-                        .map(|(_, name, _, type_)| (name.clone(), type_.clone())),
-                    readable_return_type,
-                ) + body
-                    .as_ref()
-                    .map(|e| m::space() + m::operator("=") + m::space() + e.pretty_print())
-                    .unwrap_or_default()
+                decorator_markup(decorators)
+                    + pretty_print_function_signature(
+                        function_name,
+                        &fn_type,
+                        &type_parameters,
+                        parameters.iter().map(|(_, name, _, type_, default_value)| {
+                            (
+                                *name,
+                                type_.clone()
+                                    + default_value
+                                        .as_ref()
+                                        .map(|d| m::space() + m::operator("=") + m::space() + pp(d))
+                                        .unwrap_or_default(),
+                            )
+                        }),
+                        readable_return_type,
+                    )
+                    + body
+                        .as_ref()
+                        .map(|e| m::space() + m::operator("=") + m::space() + pp(e))
+                        .unwrap_or_default()
                     + pretty_local_variables.unwrap_or_default()
             }
-            Statement::Expression(expr) => expr.pretty_print(),
+            Statement::Expression(expr) => pp(expr),
             Statement::DefineDimension(identifier, dexprs) if dexprs.is_empty() => {
                 m::keyword("dimension") + m::space() + m::type_identifier(identifier)
             }
@@ -1012,7 +1498,10 @@ impl PrettyPrint for Statement {
                     )
                     .sum()
             }
-            Statement::DefineBaseUnit(identifier, decorators, annotation, type_) => {
+            Statement::DefineBaseUnit(_, identifier, decorators, _, _, true) => {
+                decorator_markup(decorators) + m::keyword("unit") + m::space() + m::unit(identifier)
+            }
+            Statement::DefineBaseUnit(_, identifier, decorators, annotation, type_, false) => {
                 decorator_markup(decorators)
                     + m::keyword("unit")
                     + m::space()
@@ -1025,6 +1514,7 @@ impl PrettyPrint for Statement {
                         .unwrap_or(type_.pretty_print())
             }
             Statement::DefineDerivedUnit(
+                _,
                 identifier,
                 expr,
                 decorators,
@@ -1036,13 +1526,11 @@ impl PrettyPrint for Statement {
                     + m::keyword("unit")
                     + m::space()
                     + m::unit(identifier)
-                    + m::operator(":")
-                    + m::space()
-                    + readable_type.clone()
+                    + type_annotation_markup(readable_type)
                     + m::space()
                     + m::operator("=")
                     + m::space()
-                    + expr.pretty_print()
+                    + pp(expr)
             }
             Statement::ProcedureCall(kind, args) => {
                 let identifier = match kind {
@@ -1050,20 +1538,51 @@ impl PrettyPrint for Statement {
                     ProcedureKind::Assert => "assert",
                     ProcedureKind::AssertEq => "assert_eq",
                     ProcedureKind::Type => "type",
+                    ProcedureKind::ConversionCacheStats => "conversion_cache_stats",
+                    ProcedureKind::RegexCacheStats => "regex_cache_stats",
+                    ProcedureKind::InstantiationCacheStats => "instantiation_cache_stats",
+                    ProcedureKind::SetFixedTime => "set_fixed_time",
+                    ProcedureKind::SetImplicitConversions => "set_implicit_conversions",
+                    ProcedureKind::WriteText => "write_text",
+                    ProcedureKind::AppendCsv => "append_csv",
                 };
                 m::identifier(identifier)
                     + m::operator("(")
-                    + Itertools::intersperse(
-                        args.iter().map(|a| a.pretty_print()),
-                        m::operator(",") + m::space(),
-                    )
-                    .sum()
+                    + Itertools::intersperse(args.iter().map(pp), m::operator(",") + m::space())
+                        .sum()
                     + m::operator(")")
             }
+            Statement::Conditional(condition, then_statement, else_statement) => {
+                let condition = if matches!(
+                    arena[*condition],
+                    Expression::Condition(..)
+                        | Expression::FunctionCall(_, _, _, _, CallSyntax::Pipe, _)
+                ) {
+                    with_parens(arena, &arena[*condition])
+                } else {
+                    pp(condition)
+                };
+
+                m::keyword("if")
+                    + m::space()
+                    + condition
+                    + m::space()
+                    + m::keyword("then")
+                    + m::space()
+                    + then_statement.pretty_print(arena)
+                    + if let Some(else_statement) = else_statement {
+                        m::space()
+                            + m::keyword("else")
+                            + m::space()
+                            + else_statement.pretty_print(arena)
+                    } else {
+                        m::empty()
+                    }
+            }
             Statement::DefineStruct(StructInfo { name, fields, .. }) => {
                 m::keyword("struct")
                     + m::space()
-                    + m::type_identifier(name.clone())
+                    + m::type_identifier(*name)
                     + m::space()
                     + m::operator("{")
                     + if fields.is_empty() {
@@ -1084,7 +1603,199 @@ impl PrettyPrint for Statement {
                     }
                     + m::operator("}")
             }
+            Statement::DefineUnitSystem(name, units) => {
+                m::keyword("unit_system")
+                    + m::space()
+                    + m::identifier(name)
+                    + m::space()
+                    + m::operator("=")
+                    + m::space()
+                    + m::operator("[")
+                    + Itertools::intersperse(units.iter().map(pp), m::operator(",") + m::space())
+                        .sum()
+                    + m::operator("]")
+            }
+            Statement::DefineUnitFunction(function, inverse) => {
+                let mut markup = function.pretty_print(arena);
+                if let Some(keyword) = markup
+                    .0
+                    .iter_mut()
+                    .find(|f| f.1 == m::FormatType::Keyword && f.2 == "fn")
+                {
+                    keyword.2 = "unitfn".into();
+                }
+
+                if let Some(inverse) = inverse.as_deref() {
+                    let Statement::DefineFunction(_, _, _, parameters, Some(body), ..) = inverse
+                    else {
+                        unreachable!("Inverses of unit functions always have a body");
+                    };
+                    markup += m::nl()
+                        + m::space()
+                        + m::space()
+                        + m::keyword("inverse")
+                        + m::operator("(")
+                        + m::identifier(parameters[0].1)
+                        + m::operator(")")
+                        + m::space()
+                        + m::operator("=")
+                        + m::space()
+                        + pp(body);
+                }
+                markup
+            }
+            Statement::DefineMethods(struct_name, functions) => {
+                let mut markup =
+                    m::keyword("impl") + m::space() + m::type_identifier(struct_name) + m::space();
+                markup += m::operator("{");
+                for function in functions {
+                    let function = match function {
+                        Statement::DefineGenericFunction(function) => function,
+                        function => function,
+                    };
+                    let Statement::DefineFunction(name, ..) = function else {
+                        unreachable!("Methods are function definitions");
+                    };
+                    let mut function_markup = function.pretty_print(arena);
+                    // Show the method name without the struct name
+                    if let Some(identifier) = function_markup
+                        .0
+                        .iter_mut()
+                        .find(|f| f.1 == m::FormatType::Identifier && f.2 == name.as_str())
+                    {
+                        let method = name
+                            .as_str()
+                            .strip_prefix(&format!("{struct_name}::"))
+                            .unwrap_or(name.as_str());
+                        identifier.2 = method.into();
+                    }
+                    markup += m::nl() + m::space() + m::space() + function_markup;
+                }
+                markup + m::nl() + m::operator("}")
+            }
+            Statement::DefineFormula(_, functions) => Itertools::intersperse(
+                functions
+                    .iter()
+                    .map(|function| function.pretty_print(arena)),
+                m::nl(),
+            )
+            .sum(),
+            // Not part of the source code, see `ast::ImportScope`
+            Statement::ImportScope(_) => Markup::default(),
+            Statement::DefineGenericFunction(function) => function.pretty_print(arena),
+        }
+    }
+}
+
+/// The result of type checking a sequence of statements. All expressions of the
+/// program are stored in a single [`ExpressionArena`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TypedProgram {
+    pub(crate) arena: ExpressionArena,
+    pub(crate) statements: Vec<Statement>,
+}
+
+impl TypedProgram {
+    pub fn len(&self) -> usize {
+        self.statements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.statements.is_empty()
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = TypedStatement<'_>> + '_ {
+        self.statements.iter().map(|statement| TypedStatement {
+            arena: &self.arena,
+            statement,
+        })
+    }
+
+    pub fn last(&self) -> Option<TypedStatement<'_>> {
+        self.iter().next_back()
+    }
+
+    /// All statements in a list, like the `Vec<Statement>` that
+    /// [`Context::interpret`](crate::Context::interpret) used to return.
+    pub fn statements(&self) -> Vec<TypedStatement<'_>> {
+        self.iter().collect()
+    }
+}
+
+impl<'a> IntoIterator for &'a TypedProgram {
+    type Item = TypedStatement<'a>;
+    type IntoIter = Box<dyn DoubleEndedIterator<Item = TypedStatement<'a>> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.iter())
+    }
+}
+
+/// A single statement of a [`TypedProgram`].
+#[derive(Debug, Clone, Copy)]
+pub struct TypedStatement<'a> {
+    pub(crate) arena: &'a ExpressionArena,
+    pub(crate) statement: &'a Statement,
+}
+
+impl TypedStatement<'_> {
+    /// Returns `true` if this statement is a plain expression (as opposed to a
+    /// definition or a procedure call).
+    pub fn is_expression(&self) -> bool {
+        self.statement.as_expression().is_some()
+    }
+
+    /// The names of the variables that this statement defines, if it is a variable
+    /// definition (without aliases).
+    pub fn variable_names(&self) -> Vec<String> {
+        fn collect(pattern: &Pattern, names: &mut Vec<String>) {
+            match pattern {
+                Pattern::Identifier(name) => names.push(name.to_string()),
+                Pattern::Struct(fields, _) => {
+                    for (_, pattern) in fields {
+                        collect(pattern, names);
+                    }
+                }
+                Pattern::List(patterns) => {
+                    for pattern in patterns {
+                        collect(pattern, names);
+                    }
+                }
+            }
         }
+
+        let mut names = vec![];
+        if let Statement::DefineVariable(DefineVariable(pattern, ..)) = self.statement {
+            collect(pattern, &mut names);
+        }
+        names
+    }
+
+    /// Whether this statement is an explicit conversion like `x -> km`.
+    pub(crate) fn is_conversion(&self) -> bool {
+        self.statement
+            .as_expression()
+            .is_some_and(|id| matches!(self.arena[id], Expression::Conversion(..)))
+    }
+
+    /// The type of the expression, if this statement is a plain expression.
+    pub(crate) fn expression_type(&self) -> Option<TypeScheme> {
+        self.statement
+            .as_expression()
+            .map(|id| self.arena[id].get_type_scheme(self.arena))
+    }
+
+    /// The type of the expression in a readable form (e.g. `Length / Time`),
+    /// if this statement is a plain expression.
+    pub fn readable_expression_type(&self, registry: &DimensionRegistry) -> Option<Markup> {
+        self.expression_type()
+            .map(|type_| type_.to_readable_type(registry))
+    }
+}
+
+impl PrettyPrint for TypedStatement<'_> {
+    fn pretty_print(&self) -> Markup {
+        self.statement.pretty_print(self.arena)
     }
 }
 
@@ -1092,8 +1803,14 @@ fn pretty_scalar(n: Number) -> Markup {
     m::value(n.pretty_print())
 }
 
-fn with_parens(expr: &Expression) -> Markup {
+fn with_parens(arena: &ExpressionArena, expr: &Expression) -> Markup {
+    with_parens_if(needs_parens(arena, expr), arena, expr)
+}
+
+/// Whether the expression needs to be wrapped in parens when it is an operand
+fn needs_parens(arena: &ExpressionArena, expr: &Expression) -> bool {
     match expr {
+        Expression::FunctionCall(_, _, _, _, CallSyntax::Pipe, _) => true,
         Expression::Scalar(..)
         | Expression::Identifier(..)
         | Expression::UnitIdentifier(..)
@@ -1104,133 +1821,204 @@ fn with_parens(expr: &Expression) -> Markup {
         | Expression::InstantiateStruct(..)
         | Expression::AccessField(..)
         | Expression::List(..)
-        | Expression::TypedHole(_, _) => expr.pretty_print(),
+        | Expression::TypedHole(_, _)
+        | Expression::NamedArgument(..)
+        | Expression::DefaultArgument(..)
+        | Expression::Trace(..)
+        | Expression::Partial(..)
+        | Expression::AutoDiff(..)
+        | Expression::For(..)
+        | Expression::While(..)
+        | Expression::TryCatch(..) => false,
+        Expression::DynamicCheck(_, expr, ..) => needs_parens(arena, &arena[*expr]),
         Expression::UnaryOperator { .. }
         | Expression::BinaryOperator { .. }
         | Expression::BinaryOperatorForDate { .. }
-        | Expression::Condition(..) => m::operator("(") + expr.pretty_print() + m::operator(")"),
+        | Expression::Conversion(..)
+        | Expression::ChainedComparison(..)
+        | Expression::Condition(..) => true,
     }
 }
 
-/// Add parens, if needed -- liberal version, can not be used for exponentiation.
-fn with_parens_liberal(expr: &Expression) -> Markup {
+/// Whether parens are needed -- liberal version, can not be used for exponentiation.
+fn needs_parens_liberal(arena: &ExpressionArena, expr: &Expression) -> bool {
     match expr {
         Expression::BinaryOperator(_, BinaryOperator::Mul, lhs, rhs, _type)
-            if matches!(**lhs, Expression::Scalar(..))
-                && matches!(**rhs, Expression::UnitIdentifier(..)) =>
+            if matches!(arena[*lhs], Expression::Scalar(..))
+                && matches!(arena[*rhs], Expression::UnitIdentifier(..)) =>
         {
-            expr.pretty_print()
+            false
         }
-        _ => with_parens(expr),
+        _ => needs_parens(arena, expr),
     }
 }
 
-fn pretty_print_binop(op: &BinaryOperator, lhs: &Expression, rhs: &Expression) -> Markup {
-    match op {
-        BinaryOperator::ConvertTo => {
-            // never needs parens, it has the lowest precedence:
-            lhs.pretty_print() + op.pretty_print() + rhs.pretty_print()
+/// Print a chain of binary operators like `1 + 2 + … + n`. The parens for all left
+/// hand sides are opened first, followed by the innermost left hand side and the
+/// rest of each operation. This way, long chains do not overflow the stack.
+fn pretty_print_binop_chain(arena: &ExpressionArena, expr: &Expression) -> Markup {
+    let mut operations = vec![];
+    let mut innermost = expr;
+    while let Expression::BinaryOperator(_, op, lhs, rhs, _)
+    | Expression::BinaryOperatorForDate(_, op, lhs, rhs, _) = innermost
+    {
+        innermost = &arena[*lhs];
+        operations.push(pretty_print_binop(arena, op, innermost, &arena[*rhs]));
+    }
+
+    let mut markup = m::empty();
+    for _ in operations.iter().filter(|(lhs_parens, _)| *lhs_parens) {
+        markup += m::operator("(");
+    }
+    markup += innermost.pretty_print(arena);
+    for (lhs_parens, rest) in operations.into_iter().rev() {
+        if lhs_parens {
+            markup += m::operator(")");
         }
+        markup += rest;
+    }
+    markup
+}
+
+/// Returns whether the left hand side needs parens, and the markup that follows
+/// after the left hand side.
+fn pretty_print_binop(
+    arena: &ExpressionArena,
+    op: &BinaryOperator,
+    lhs: &Expression,
+    rhs: &Expression,
+) -> (bool, Markup) {
+    match op {
         BinaryOperator::Mul => match (lhs, rhs) {
             (
-                Expression::Scalar(_, s, _type_scalar),
+                Expression::Scalar(_, _s, _type_scalar),
                 Expression::UnitIdentifier(_, prefix, _name, full_name, _type),
             ) => {
                 // Fuse multiplication of a scalar and a unit to a quantity
-                pretty_scalar(*s)
-                    + m::space()
-                    + m::unit(format!("{}{}", prefix.as_string_long(), full_name))
+                (
+                    false,
+                    m::space() + m::unit(format!("{}{}", prefix.as_string_long(), full_name)),
+                )
             }
-            (Expression::Scalar(_, s, _), Expression::Identifier(_, name, _type)) => {
+            (Expression::Scalar(_, _s, _), Expression::Identifier(_, name, _type)) => {
                 // Fuse multiplication of a scalar and identifier
-                pretty_scalar(*s) + m::space() + m::identifier(name)
+                (false, m::space() + m::identifier(name))
             }
             _ => {
-                let add_parens_if_needed = |expr: &Expression| {
-                    if matches!(
+                let parens_needed = |expr: &Expression| {
+                    !matches!(
                         expr,
                         Expression::BinaryOperator(_, BinaryOperator::Power, ..)
                             | Expression::BinaryOperator(_, BinaryOperator::Mul, ..)
-                    ) {
-                        expr.pretty_print()
-                    } else {
-                        with_parens_liberal(expr)
-                    }
+                    ) && needs_parens_liberal(arena, expr)
                 };
 
-                add_parens_if_needed(lhs) + op.pretty_print() + add_parens_if_needed(rhs)
+                (
+                    parens_needed(lhs),
+                    op.pretty_print() + with_parens_if(parens_needed(rhs), arena, rhs),
+                )
             }
         },
         BinaryOperator::Div => {
-            let lhs_add_parens_if_needed = |expr: &Expression| {
-                if matches!(
+            let lhs_parens_needed = |expr: &Expression| {
+                !matches!(
                     expr,
                     Expression::BinaryOperator(_, BinaryOperator::Power, ..)
                         | Expression::BinaryOperator(_, BinaryOperator::Mul, ..)
-                ) {
-                    expr.pretty_print()
-                } else {
-                    with_parens_liberal(expr)
-                }
+                ) && needs_parens_liberal(arena, expr)
             };
-            let rhs_add_parens_if_needed = |expr: &Expression| {
-                if matches!(
+            let rhs_parens_needed = |expr: &Expression| {
+                !matches!(
                     expr,
                     Expression::BinaryOperator(_, BinaryOperator::Power, ..)
-                ) {
-                    expr.pretty_print()
-                } else {
-                    with_parens_liberal(expr)
-                }
+                ) && needs_parens_liberal(arena, expr)
             };
 
-            lhs_add_parens_if_needed(lhs) + op.pretty_print() + rhs_add_parens_if_needed(rhs)
+            (
+                lhs_parens_needed(lhs),
+                op.pretty_print() + with_parens_if(rhs_parens_needed(rhs), arena, rhs),
+            )
         }
         BinaryOperator::Add => {
-            let add_parens_if_needed = |expr: &Expression| {
-                if matches!(
+            let parens_needed = |expr: &Expression| {
+                !matches!(
                     expr,
                     Expression::BinaryOperator(_, BinaryOperator::Power, ..)
                         | Expression::BinaryOperator(_, BinaryOperator::Mul, ..)
                         | Expression::BinaryOperator(_, BinaryOperator::Add, ..)
-                ) {
-                    expr.pretty_print()
-                } else {
-                    with_parens_liberal(expr)
-                }
+                ) && needs_parens_liberal(arena, expr)
             };
 
-            add_parens_if_needed(lhs) + op.pretty_print() + add_parens_if_needed(rhs)
+            (
+                parens_needed(lhs),
+                op.pretty_print() + with_parens_if(parens_needed(rhs), arena, rhs),
+            )
         }
         BinaryOperator::Sub => {
-            let add_parens_if_needed = |expr: &Expression| {
-                if matches!(
+            let parens_needed = |expr: &Expression| {
+                !matches!(
                     expr,
                     Expression::BinaryOperator(_, BinaryOperator::Power, ..)
                         | Expression::BinaryOperator(_, BinaryOperator::Mul, ..)
-                ) {
-                    expr.pretty_print()
-                } else {
-                    with_parens_liberal(expr)
-                }
+                ) && needs_parens_liberal(arena, expr)
             };
 
-            add_parens_if_needed(lhs) + op.pretty_print() + add_parens_if_needed(rhs)
+            (
+                parens_needed(lhs),
+                op.pretty_print() + with_parens_if(parens_needed(rhs), arena, rhs),
+            )
         }
         BinaryOperator::Power if matches!(rhs, Expression::Scalar(_, n, _type) if n.to_f64() == 2.0) => {
-            with_parens(lhs) + m::operator("²")
+            (needs_parens(arena, lhs), m::operator("²"))
         }
         BinaryOperator::Power if matches!(rhs, Expression::Scalar(_, n, _type) if n.to_f64() == 3.0) => {
-            with_parens(lhs) + m::operator("³")
+            (needs_parens(arena, lhs), m::operator("³"))
         }
-        _ => with_parens(lhs) + op.pretty_print() + with_parens(rhs),
+        _ => (
+            needs_parens(arena, lhs),
+            op.pretty_print() + with_parens(arena, rhs),
+        ),
     }
 }
 
-impl PrettyPrint for Expression {
-    fn pretty_print(&self) -> Markup {
+fn with_parens_if(parens: bool, arena: &ExpressionArena, expr: &Expression) -> Markup {
+    if parens {
+        m::operator("(") + expr.pretty_print(arena) + m::operator(")")
+    } else {
+        expr.pretty_print(arena)
+    }
+}
+
+fn pretty_print_string_parts(arena: &ExpressionArena, parts: &[StringPart]) -> Markup {
+    let parts = parts.iter().map(|part| match part {
+        StringPart::Fixed(s) => m::string(escape_numbat_string(s)),
+        StringPart::Interpolation {
+            span: _,
+            expr,
+            format_specifiers,
+        } => {
+            let mut markup = m::operator("{") + arena[*expr].pretty_print(arena);
+
+            if let Some(format_specifiers) = format_specifiers {
+                markup += m::text(format_specifiers);
+            }
+
+            markup += m::operator("}");
+
+            markup
+        }
+    });
+
+    m::operator("\"") + parts.sum() + m::operator("\"")
+}
+
+impl Expression {
+    pub fn pretty_print(&self, arena: &ExpressionArena) -> Markup {
         use Expression::*;
 
+        let pp = |id: &ExpressionId| arena[*id].pretty_print(arena);
+        let parens = |id: &ExpressionId| with_parens(arena, &arena[*id]);
+
         match self {
             Scalar(_, n, _) => pretty_scalar(*n),
             Identifier(_, name, _type) => m::identifier(name),
@@ -1238,65 +2026,128 @@ impl PrettyPrint for Expression {
                 m::unit(format!("{}{}", prefix.as_string_long(), full_name))
             }
             UnaryOperator(_, self::UnaryOperator::Negate, expr, _type) => {
-                m::operator("-") + with_parens(expr)
+                m::operator("-") + parens(expr)
             }
             UnaryOperator(_, self::UnaryOperator::Factorial, expr, _type) => {
-                with_parens(expr) + m::operator("!")
+                parens(expr) + m::operator("!")
             }
             UnaryOperator(_, self::UnaryOperator::LogicalNeg, expr, _type) => {
-                m::operator("!") + with_parens(expr)
+                m::operator("!") + parens(expr)
             }
-            BinaryOperator(_, op, lhs, rhs, _type) => pretty_print_binop(op, lhs, rhs),
-            BinaryOperatorForDate(_, op, lhs, rhs, _type) => pretty_print_binop(op, lhs, rhs),
-            FunctionCall(_, _, name, args, _type) => {
+            BinaryOperator(..) | BinaryOperatorForDate(..) => pretty_print_binop_chain(arena, self),
+            Conversion(_, source, target, _type) => {
+                // Never needs parens, it has the lowest precedence (apart from pipes,
+                // which can only appear on the left hand side). A condition on the
+                // left hand side would extend over the conversion, though.
+                let source = if matches!(arena[*source], Condition(..)) {
+                    parens(source)
+                } else {
+                    pp(source)
+                };
+
+                source + m::space() + m::operator("➞") + m::space() + target.pretty_print(arena)
+            }
+            ChainedComparison(comparisons) => {
+                // Print the shared operands only once, as in the source code
+                let mut markup = pp(&comparisons[0]);
+                for comparison in &comparisons[1..] {
+                    let BinaryOperator(_, op, _lhs, rhs, _type) = &arena[*comparison] else {
+                        unreachable!("Chains consist of binary comparisons")
+                    };
+                    markup += op.pretty_print() + parens(rhs);
+                }
+                markup
+            }
+            FunctionCall(_, _, name, args, CallSyntax::Pipe, _type) => {
+                // The piped argument is the last positional one
+                let piped_idx = args
+                    .iter()
+                    .rposition(|e| !matches!(arena[*e], NamedArgument(..) | DefaultArgument(..)))
+                    .unwrap();
+                let piped = args[piped_idx];
+                let args = args
+                    .iter()
+                    .enumerate()
+                    .filter(|(idx, e)| {
+                        *idx != piped_idx && !matches!(arena[**e], DefaultArgument(..))
+                    })
+                    .map(|(_, e)| pp(e))
+                    .collect::<Vec<_>>();
+
+                let mut markup =
+                    pp(&piped) + m::space() + m::operator("|>") + m::space() + m::identifier(name);
+                if !args.is_empty() {
+                    markup += m::operator("(")
+                        + itertools::Itertools::intersperse(
+                            args.into_iter(),
+                            m::operator(",") + m::space(),
+                        )
+                        .sum()
+                        + m::operator(")");
+                }
+                markup
+            }
+            FunctionCall(_, _, name, args, CallSyntax::Regular, _type) => {
                 m::identifier(name)
                     + m::operator("(")
                     + itertools::Itertools::intersperse(
-                        args.iter().map(|e| e.pretty_print()),
+                        args.iter()
+                            .filter(|e| !matches!(arena[**e], DefaultArgument(..)))
+                            .map(pp),
                         m::operator(",") + m::space(),
                     )
                     .sum()
                     + m::operator(")")
             }
             CallableCall(_, expr, args, _type) => {
-                expr.pretty_print()
+                pp(expr)
                     + m::operator("(")
                     + itertools::Itertools::intersperse(
-                        args.iter().map(|e| e.pretty_print()),
+                        args.iter().map(pp),
                         m::operator(",") + m::space(),
                     )
                     .sum()
                     + m::operator(")")
             }
             Boolean(_, val) => val.pretty_print(),
-            String(_, parts) => parts.pretty_print(),
+            String(_, parts) => pretty_print_string_parts(arena, parts),
             Condition(_, condition, then, else_) => {
                 m::keyword("if")
                     + m::space()
-                    + with_parens(condition)
+                    + parens(condition)
                     + m::space()
                     + m::keyword("then")
                     + m::space()
-                    + with_parens(then)
+                    + parens(then)
                     + m::space()
                     + m::keyword("else")
                     + m::space()
-                    + with_parens(else_)
+                    + parens(else_)
             }
-            InstantiateStruct(_, exprs, struct_info) => {
-                m::type_identifier(struct_info.name.clone())
+            InstantiateStruct(_, base, exprs, struct_info) => {
+                m::type_identifier(struct_info.name)
                     + m::space()
                     + m::operator("{")
+                    + if let Some(base) = base {
+                        m::space() + pp(base) + m::space() + m::keyword("with")
+                    } else {
+                        m::empty()
+                    }
                     + if exprs.is_empty() {
                         m::empty()
                     } else {
                         m::space()
                             + itertools::Itertools::intersperse(
                                 exprs.iter().map(|(n, e)| {
-                                    m::identifier(n)
-                                        + m::operator(":")
-                                        + m::space()
-                                        + e.pretty_print()
+                                    if base.is_some() {
+                                        m::identifier(n)
+                                            + m::space()
+                                            + m::operator("=")
+                                            + m::space()
+                                            + pp(e)
+                                    } else {
+                                        m::identifier(n) + m::operator(":") + m::space() + pp(e)
+                                    }
                                 }),
                                 m::operator(",") + m::space(),
                             )
@@ -1306,22 +2157,109 @@ impl PrettyPrint for Expression {
                     + m::operator("}")
             }
             AccessField(_, _, expr, attr, _, _) => {
-                expr.pretty_print() + m::operator(".") + m::identifier(attr)
+                parens(expr) + m::operator(".") + m::identifier(attr)
             }
             List(_, elements, _) => {
                 m::operator("[")
                     + itertools::Itertools::intersperse(
-                        elements.iter().map(|e| e.pretty_print()),
+                        elements.iter().map(pp),
                         m::operator(",") + m::space(),
                     )
                     .sum()
                     + m::operator("]")
             }
             TypedHole(_, _) => m::operator("?"),
+            NamedArgument(_, name, expr) => {
+                m::identifier(name) + m::space() + m::operator("=") + m::space() + pp(expr)
+            }
+            DefaultArgument(_, _) => m::empty(),
+            Trace(_, _, expr) => {
+                m::keyword("trace") + m::operator("(") + pp(expr) + m::operator(")")
+            }
+            Partial(_, function, args, _) => {
+                m::keyword("partial")
+                    + m::operator("(")
+                    + itertools::Itertools::intersperse(
+                        std::iter::once(function)
+                            .chain(args.iter())
+                            .filter(|e| !matches!(arena[**e], DefaultArgument(..)))
+                            .map(pp),
+                        m::operator(",") + m::space(),
+                    )
+                    .sum()
+                    + m::operator(")")
+            }
+            AutoDiff(_, function, _, _) => {
+                m::keyword("autodiff") + m::operator("(") + pp(function) + m::operator(")")
+            }
+            DynamicCheck(_, expr, ..) => pp(expr),
+            For(_, variable, list, accumulator, body) => {
+                m::keyword("for")
+                    + m::space()
+                    + m::identifier(variable)
+                    + m::space()
+                    + m::keyword("in")
+                    + m::space()
+                    + pretty_print_loop_header(arena, list)
+                    + accumulator
+                        .as_ref()
+                        .map(|(name, init)| pretty_print_accumulator(arena, name, init))
+                        .unwrap_or_default()
+                    + pretty_print_braced(arena, body)
+            }
+            While(_, condition, (name, init), body) => {
+                m::keyword("while")
+                    + m::space()
+                    + pretty_print_loop_header(arena, condition)
+                    + pretty_print_accumulator(arena, name, init)
+                    + pretty_print_braced(arena, body)
+            }
+            TryCatch(_, expr, message, fallback) => {
+                m::keyword("try")
+                    + pretty_print_braced(arena, expr)
+                    + m::space()
+                    + m::keyword("catch")
+                    + message
+                        .as_ref()
+                        .map(|name| m::space() + m::identifier(name))
+                        .unwrap_or_default()
+                    + pretty_print_braced(arena, fallback)
+            }
+        }
+    }
+}
+
+/// Struct instantiations need parentheses in a loop header, where the `{` would
+/// otherwise start the loop body.
+fn pretty_print_loop_header(arena: &ExpressionArena, expr: &ExpressionId) -> Markup {
+    match &arena[*expr] {
+        e @ Expression::InstantiateStruct(..) => {
+            m::operator("(") + e.pretty_print(arena) + m::operator(")")
         }
+        e => e.pretty_print(arena),
     }
 }
 
+fn pretty_print_accumulator(arena: &ExpressionArena, name: &Symbol, init: &ExpressionId) -> Markup {
+    m::space()
+        + m::keyword("with")
+        + m::space()
+        + m::identifier(name)
+        + m::space()
+        + m::operator("=")
+        + m::space()
+        + pretty_print_loop_header(arena, init)
+}
+
+fn pretty_print_braced(arena: &ExpressionArena, body: &ExpressionId) -> Markup {
+    m::space()
+        + m::operator("{")
+        + m::space()
+        + arena[*body].pretty_print(arena)
+        + m::space()
+        + m::operator("}")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1329,7 +2267,7 @@ mod tests {
     use crate::markup::{Formatter, PlainTextFormatter};
     use crate::prefix_transformer::Transformer;
 
-    fn parse(code: &str) -> Statement {
+    fn parse(code: &str) -> TypedProgram {
         let statements = crate::parser::parse(
             &format!(
                 "dimension Scalar = 1
@@ -1399,13 +2337,10 @@ mod tests {
         crate::typechecker::TypeChecker::default()
             .check(transformed_statements)
             .unwrap()
-            .last()
-            .unwrap()
-            .clone()
     }
 
-    fn pretty_print(stmt: &Statement) -> String {
-        let markup = stmt.pretty_print();
+    fn pretty_print(program: &TypedProgram) -> String {
+        let markup = program.last().unwrap().pretty_print();
 
         (PlainTextFormatter {}).format(&markup, false)
     }
@@ -1493,6 +2428,38 @@ mod tests {
         roundtrip_check("\"newline: \\n\"");
     }
 
+    #[test]
+    fn pretty_print_definitions() {
+        equal_pretty(
+            "@name(\"Speed\") @description(\"A \\\"fast\\\" one\") let v = 2 m / s",
+            "@name(\"Speed\")\n@description(\"A \\\"fast\\\" one\")\nlet v: Length / Time = 2 meter / second",
+        );
+        equal_pretty(
+            "@deprecated(\"use g\") fn h(x: Length) = 2 x",
+            "@deprecated(\"use g\")\nfn h(x: Length) -> Length = 2 x",
+        );
+        equal_pretty("let zero = 0", "let zero = 0");
+        equal_pretty(
+            "dimension Speed = Length / Time\ndimension Velocity = Length / Time\nlet v = 2 m / s",
+            "let v: Speed = 2 meter / second",
+        );
+        equal_pretty(
+            "unitfn f(x: Scalar) -> Length = x m\n  inverse(y) = y / m",
+            "unitfn f(x: Scalar) -> Length = x × meter\n  inverse(y) = y / meter",
+        );
+        equal_pretty("unitfn h(x) = 2 x", "unitfn h<A: Dim>(x: A) -> A = 2 x");
+
+        roundtrip_check(
+            "(if true then Foo { foo: 1 meter, bar: 1 second } else Foo { foo: 2 meter, bar: 2 second }).foo",
+        );
+        roundtrip_check("(if true then 1 meter else 2 meter) -> centimeter");
+        roundtrip_check("if (if true then false else true) then print(1)");
+        roundtrip_check("@url(\"https://example.com/\\{x\\}\") unit z: Length = 2 meter");
+        roundtrip_check(
+            "@name(\"F\") unitfn f(x: Scalar) -> Length = x meter\ninverse(y) = y / meter",
+        );
+    }
+
     #[test]
     fn pretty_print_dexpr() {
         roundtrip_check("unit z: Length");
@@ -1505,4 +2472,59 @@ mod tests {
         roundtrip_check("unit z: Length / (Time * Mass)");
         roundtrip_check("unit z: Length^5 * Time^4 / (Time^2 * Mass^3)");
     }
+
+    #[test]
+    fn pretty_print_implicit_dimension() {
+        equal_pretty("unit widget", "unit widget");
+        equal_pretty(
+            "@dimension(Gizmo) unit widget",
+            "@dimension(Gizmo)\nunit widget",
+        );
+        equal_pretty("unit widget: Length", "unit widget: Length");
+
+        roundtrip_check("unit widget");
+        roundtrip_check("@dimension(Gizmo) unit widget");
+    }
+
+    #[test]
+    fn pretty_print_loops() {
+        equal_pretty("for y in [1 in] { 2 y }", "for y in [1 inch] { 2 y }");
+        equal_pretty(
+            "for y in [1 m, 2 m]  with acc = 0 m {acc+y}",
+            "for y in [1 meter, 2 meter] with acc = 0 meter { acc + y }",
+        );
+        equal_pretty(
+            "while n<10 with n=1 {\n  2*n\n}",
+            "while n < 10 with n = 1 { 2 n }",
+        );
+
+        roundtrip_check("for y in [1, 2] { for z in [3] { y + z } }");
+        roundtrip_check("for y in [1 meter] with acc = 0 meter { acc + y } -> centimeter");
+        roundtrip_check("for y in [1 meter] with p = (Foo { foo: 0 meter, bar: 1 second }) { Foo { p with foo = y } }");
+        roundtrip_check("while n < 10 meter with n = 1 meter { 2 n }");
+    }
+
+    #[test]
+    fn pretty_print_try_catch() {
+        equal_pretty("try {sin(1)} catch {0}", "try { sin(1) } catch { 0 }");
+        equal_pretty(
+            "try {\n  2 m\n}\ncatch msg {\n  3 m\n}",
+            "try { 2 meter } catch msg { 3 meter }",
+        );
+
+        roundtrip_check("try { Foo { foo: 1 meter, bar: 1 second } } catch { Foo { foo: 2 meter, bar: 2 second } }");
+        roundtrip_check("try { try { 1 } catch { 2 } } catch message { 3 }");
+    }
+
+    #[test]
+    fn pretty_print_local_units() {
+        equal_pretty(
+            "fn f(x: Length) = x + y foot where unit foot = 10 inch and y = 2",
+            "fn f(x: Length) -> Length = x + y × foot\n  where unit foot: Length = 10 inch\n    and y: Scalar = 2",
+        );
+        equal_pretty(
+            "fn f(x: Scalar) = x tick where dimension Tick and unit tick: Tick",
+            "fn f(x: Scalar) -> Tick = x × tick\n  where dimension Tick\n    and unit tick: Tick",
+        );
+    }
 }
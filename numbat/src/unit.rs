@@ -9,6 +9,7 @@ use crate::{
     prefix::Prefix,
     prefix_parser::AcceptsPrefix,
     product::{Canonicalize, Product},
+    symbol::Symbol,
 };
 
 pub type ConversionFactor = Number;
@@ -21,24 +22,53 @@ pub enum UnitKind {
     Derived(ConversionFactor, Unit),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Eq)]
 pub struct CanonicalName {
-    pub name: String,
+    pub name: Symbol,
     pub accepts_prefix: AcceptsPrefix,
+    /// The name of the unit in singular and plural form (e.g. `metre` and `metres`),
+    /// which is used instead of `name` when units are written out in full.
+    pub long_name: Option<(Symbol, Symbol)>,
 }
 
 impl CanonicalName {
-    pub fn new(name: &str, accepts_prefix: AcceptsPrefix) -> Self {
+    pub fn new(name: impl Into<Symbol>, accepts_prefix: AcceptsPrefix) -> Self {
         Self {
             name: name.into(),
             accepts_prefix,
+            long_name: None,
+        }
+    }
+
+    pub fn with_long_name(mut self, singular: Symbol, plural: Symbol) -> Self {
+        self.long_name = Some((singular, plural));
+        self
+    }
+
+    /// The long name (in singular or plural form), which accepts long prefixes.
+    /// Names without a long form are returned unchanged.
+    fn long(&self, plural: bool) -> CanonicalName {
+        match self.long_name {
+            Some((singular, plural_name)) => CanonicalName {
+                name: if plural { plural_name } else { singular },
+                accepts_prefix: AcceptsPrefix::only_long(),
+                long_name: self.long_name,
+            },
+            None => self.clone(),
         }
     }
 }
 
+/// The long name is only used for printing, it does not make units different.
+impl PartialEq for CanonicalName {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.accepts_prefix == other.accepts_prefix
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct UnitIdentifier {
-    pub name: String,
+    pub name: Symbol,
     pub canonical_name: CanonicalName,
     kind: UnitKind,
 }
@@ -102,13 +132,13 @@ impl UnitIdentifier {
         }
     }
 
-    pub fn sort_key(&self) -> Vec<(String, Exponent)> {
+    pub fn sort_key(&self) -> Vec<(Symbol, Exponent)> {
         use num_integer::Integer;
 
         // TODO: this is more or less a hack. instead of properly sorting by physical
         // dimension, we sort by the name of the corresponding base unit(s).
         match &self.kind {
-            UnitKind::Base => vec![(self.name.clone(), Exponent::from_integer(1))],
+            UnitKind::Base => vec![(self.name, Exponent::from_integer(1))],
             UnitKind::Derived(_, defining_unit) => {
                 let base_unit = defining_unit.to_base_unit_representation().0;
                 let mut key: Vec<_> = base_unit
@@ -268,6 +298,26 @@ impl Unit {
         Self::from_factors(factors)
     }
 
+    /// The same unit, written with long names (`kilometres/hour` instead of `km/h`).
+    /// If `plural` is set, the last factor in the numerator is in plural form, as in
+    /// `newton·metres`.
+    pub fn with_long_names(&self, plural: bool) -> Self {
+        let last_positive = self
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| f.exponent > Exponent::zero())
+            .map(|(i, _)| i)
+            .last();
+        Self::from_factors(self.iter().enumerate().map(|(i, factor)| {
+            let mut factor = factor.clone();
+            factor.unit_id.canonical_name = factor
+                .unit_id
+                .canonical_name
+                .long(plural && Some(i) == last_positive);
+            factor
+        }))
+    }
+
     pub fn to_base_unit_representation(&self) -> (Self, ConversionFactor) {
         // TODO: reduce wrapping/unwrapping and duplication.
 
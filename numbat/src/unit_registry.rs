@@ -1,8 +1,18 @@
+use std::collections::HashMap;
+
+use crate::arithmetic::{Exponent, Power};
+use crate::error_code::ErrorCode;
 use crate::markup::Markup;
+use crate::prefix::Prefix;
 use crate::prefix_parser::AcceptsPrefix;
+use crate::quantity::{Quantity, QuantityError};
 use crate::registry::{BaseRepresentation, BaseRepresentationFactor, Registry, RegistryError};
+use crate::span::Span;
+use crate::symbol::Symbol;
 use crate::typed_ast::Type;
-use crate::unit::{CanonicalName, Unit};
+use crate::unit::{CanonicalName, Unit, UnitFactor};
+
+use itertools::Itertools;
 
 use thiserror::Error;
 
@@ -10,55 +20,476 @@ use thiserror::Error;
 pub enum UnitRegistryError {
     #[error("{0}")]
     RegistryError(RegistryError),
+
+    #[error("The unit system '{0}' does not contain a unit for the dimension '{1}'")]
+    NoUnitInUnitSystem(String, String),
+
+    #[error("Unknown unit system '{0}'")]
+    UnknownUnitSystem(String),
+
+    #[error(
+        "The unit system '{0}' contains {1} units, but at most {} are supported",
+        u16::MAX
+    )]
+    TooManyUnitsInUnitSystem(String, usize),
+
+    #[error("{0}")]
+    QuantityError(QuantityError),
+}
+
+impl UnitRegistryError {
+    pub fn error_code(&self) -> ErrorCode {
+        match self {
+            UnitRegistryError::RegistryError(inner) => inner.error_code(),
+            UnitRegistryError::NoUnitInUnitSystem(..) => ErrorCode::E0703,
+            UnitRegistryError::UnknownUnitSystem(..) => ErrorCode::E0704,
+            UnitRegistryError::TooManyUnitsInUnitSystem(..) => ErrorCode::E0705,
+            UnitRegistryError::QuantityError(inner) => inner.error_code(),
+        }
+    }
 }
 
 pub type Result<T> = std::result::Result<T, UnitRegistryError>;
 
+/// The base representation of a unit as a hashable key, e.g. `[(gram, 1),
+/// (metre, 2), (second, -3)]` for `W`.
+type BaseRepresentationKey = Vec<(Symbol, Exponent)>;
+
+/// Global units by their base representation, to find a named unit for a
+/// given dimension (see [`UnitRegistry::simplify`]).
+#[derive(Clone, Default)]
+struct UnitIndex {
+    /// The position of each unit in the order of definition. Entries are kept
+    /// when a unit is removed, so that it keeps its position when it is added
+    /// back.
+    order: HashMap<Symbol, usize>,
+    units: HashMap<Symbol, (Unit, BaseRepresentationKey)>,
+    /// Names of units, in the order of definition
+    by_base_representation: HashMap<BaseRepresentationKey, Vec<Symbol>>,
+}
+
+impl UnitIndex {
+    fn insert(&mut self, name: Symbol, unit: &Unit) {
+        self.remove(name);
+
+        let next = self.order.len();
+        self.order.entry(name).or_insert(next);
+
+        let key = base_representation_key(&unit.to_base_unit_representation().0);
+        let names = self.by_base_representation.entry(key.clone()).or_default();
+        names.push(name);
+        names.sort_by_key(|name| self.order[name]);
+
+        self.units.insert(name, (unit.clone(), key));
+    }
+
+    fn remove(&mut self, name: Symbol) {
+        if let Some((_, key)) = self.units.remove(&name) {
+            if let Some(names) = self.by_base_representation.get_mut(&key) {
+                names.retain(|n| *n != name);
+            }
+        }
+    }
+
+    fn units_with_base_representation(&self, base: &Unit) -> impl Iterator<Item = &Unit> {
+        self.by_base_representation
+            .get(&base_representation_key(base))
+            .into_iter()
+            .flatten()
+            .map(|name| &self.units[name].0)
+    }
+
+    fn units_in_order(&self) -> impl Iterator<Item = &Unit> {
+        self.units
+            .iter()
+            .sorted_by_key(|(name, _)| self.order[*name])
+            .map(|(_, (unit, _))| unit)
+    }
+}
+
+fn base_representation_key(base: &Unit) -> BaseRepresentationKey {
+    base.canonicalized()
+        .iter()
+        .map(|factor| (factor.unit_id.name, factor.exponent))
+        .collect()
+}
+
+/// How complex a unit looks: the number of factors, and then the sum of the
+/// absolute values of all exponents (`W` < `J·s⁻¹` < `kg·m²·s⁻³`).
+fn complexity(unit: &Unit) -> (usize, Exponent) {
+    unit.iter().fold(
+        (0, Exponent::from_integer(0)),
+        |(count, exponents), factor| {
+            let exponent = if factor.exponent < Exponent::from_integer(0) {
+                -factor.exponent
+            } else {
+                factor.exponent
+            };
+            (count + 1, exponents + exponent)
+        },
+    )
+}
+
 #[derive(Debug, Clone)]
 pub struct UnitMetadata {
     pub type_: Type,
     pub readable_type: Markup,
-    pub aliases: Vec<(String, AcceptsPrefix)>,
+    pub aliases: Vec<(Symbol, AcceptsPrefix)>,
     pub name: Option<String>,
     pub canonical_name: CanonicalName,
     pub url: Option<String>,
     pub description: Option<String>,
     pub binary_prefixes: bool,
     pub metric_prefixes: bool,
+    /// The ISO 4217 code and the usual number of decimal places, for currencies
+    pub currency: Option<(String, u32)>,
+    /// The code in the Unified Code for Units of Measure (see `@ucum`)
+    pub ucum: Option<String>,
+    /// Whether the unit is never converted implicitly (see `@isolated`)
+    pub isolated: bool,
+    pub deprecated: Option<String>,
 }
 
 #[derive(Clone)]
 pub struct UnitRegistry {
     pub inner: Registry<UnitMetadata>,
+    /// Named unit systems (SI, CGS, …). The units of each system are
+    /// stored in the order of preference.
+    pub unit_systems: HashMap<Symbol, Vec<Unit>>,
+    index: UnitIndex,
+    /// Units by their UCUM code (see `@ucum`)
+    ucum_codes: HashMap<String, Symbol>,
 }
 
 impl UnitRegistry {
     pub fn new() -> Self {
         Self {
             inner: Registry::<UnitMetadata>::default(),
+            unit_systems: HashMap::new(),
+            index: UnitIndex::default(),
+            ucum_codes: HashMap::new(),
         }
     }
 
-    pub fn add_base_unit(&mut self, name: &str, metadata: UnitMetadata) -> Result<()> {
+    pub fn add_base_unit(
+        &mut self,
+        name: Symbol,
+        span: Span,
+        metadata: UnitMetadata,
+    ) -> Result<()> {
+        let ucum = metadata.ucum.clone();
         self.inner
-            .add_base_entry(name, metadata)
-            .map_err(UnitRegistryError::RegistryError)
+            .add_base_entry(name, span, metadata)
+            .map_err(UnitRegistryError::RegistryError)?;
+        self.index_ucum_code(name, ucum);
+
+        Ok(())
     }
 
     pub fn add_derived_unit(
         &mut self,
-        name: &str,
+        name: Symbol,
+        span: Span,
         base_representation: &Unit,
         metadata: UnitMetadata,
     ) -> Result<()> {
         let base_representation_factors = base_representation
             .iter()
-            .map(|factor| BaseRepresentationFactor(factor.unit_id.name.clone(), factor.exponent));
+            .map(|factor| BaseRepresentationFactor(factor.unit_id.name, factor.exponent));
         let base_representation = BaseRepresentation::from_factors(base_representation_factors);
+        let ucum = metadata.ucum.clone();
         self.inner
-            .add_derived_entry(name, base_representation, metadata)
+            .add_derived_entry(name, span, base_representation, metadata)
             .map_err(UnitRegistryError::RegistryError)?;
+        self.index_ucum_code(name, ucum);
 
         Ok(())
     }
+
+    /// Make a global unit available to [`UnitRegistry::simplify`]. A unit
+    /// that has been indexed under the same name before is replaced.
+    pub fn index_unit(&mut self, name: Symbol, unit: &Unit) {
+        self.index.insert(name, unit);
+    }
+
+    pub fn unindex_unit(&mut self, name: Symbol) {
+        self.index.remove(name);
+    }
+
+    fn index_ucum_code(&mut self, name: Symbol, code: Option<String>) {
+        if let Some(code) = code {
+            self.ucum_codes.insert(code, name);
+        }
+    }
+
+    /// The name of the unit with the given UCUM code, e.g. `litre` for `L`.
+    pub fn unit_with_ucum_code(&self, code: &str) -> Option<Symbol> {
+        let name = *self.ucum_codes.get(code)?;
+        // The unit might have been removed or redefined without the code since
+        let (_, metadata) = self.inner.get_base_representation(name).ok()?;
+        (metadata.ucum.as_deref() == Some(code)).then_some(name)
+    }
+
+    pub fn add_unit_system(&mut self, name: Symbol, units: Vec<Unit>) {
+        self.unit_systems.insert(name, units);
+    }
+
+    /// Re-express a quantity in terms of the units of the given unit system.
+    pub fn convert_to_unit_system(&self, name: Symbol, quantity: &Quantity) -> Result<Quantity> {
+        let target_base = quantity.unit().to_base_unit_representation().0;
+        if target_base.is_scalar() {
+            return Ok(quantity.full_simplify());
+        }
+
+        let target_unit = self.find_unit_in_unit_system(name, &target_base)?;
+
+        quantity
+            .convert_to(&target_unit)
+            .map_err(UnitRegistryError::QuantityError)
+    }
+
+    /// Re-express a quantity in the simplest unit with the same dimension,
+    /// e.g. `kg·m²/s³` as `W` or `kWh/day` as `W`. Dimensionless quantities
+    /// are converted to scalars (`m/m`).
+    ///
+    /// Only coherent units (like `N` or `J`, but not `L` or `dyn`) are used.
+    /// The candidates are a single named unit and otherwise the product of the
+    /// base units, or a named unit times a power of a base unit (e.g. `N·s`)
+    /// if that is simpler than a product of three or more base units. If
+    /// several candidates are equally simple, units that were defined first
+    /// take precedence. The unit of the quantity is only
+    /// replaced if it is more complex than that. Finally, the metric prefix
+    /// is chosen such that the value is between 1 and 1000, if possible.
+    pub fn simplify(&self, quantity: &Quantity) -> Quantity {
+        let unit = quantity.unit().canonicalized();
+        let base = unit.to_base_unit_representation().0;
+        if base.is_scalar() {
+            return quantity
+                .convert_to(&Unit::scalar())
+                .expect("dimensionless quantity can be converted to a scalar");
+        }
+
+        let simplest = self.simplest_unit(&base);
+        if complexity(&simplest) < complexity(&unit) {
+            let simplified = quantity
+                .convert_to(&simplest)
+                .expect("simplest unit has the same dimension");
+            self.with_best_prefix(simplified)
+        } else if unit.iter().count() == 1 {
+            self.with_best_prefix(quantity.clone())
+        } else {
+            quantity.clone()
+        }
+    }
+
+    /// Whether a quantity with this unit is too complex to be shown as is: the
+    /// sum of the absolute exponents of its base representation is larger than
+    /// `max_complexity`, and there is no named unit with the same base
+    /// representation (like `V` for `kg·m²·s⁻³·A⁻¹`).
+    pub fn is_too_complex(&self, unit: &Unit, max_complexity: u32) -> bool {
+        let base = unit.to_base_unit_representation().0.canonicalized();
+        let (_, exponents) = complexity(&base);
+
+        exponents > Exponent::from_integer(max_complexity.into())
+            && self
+                .index
+                .units_with_base_representation(&base)
+                .next()
+                .is_none()
+    }
+
+    /// Like [`UnitRegistry::simplify`], but using the preferred units of the
+    /// given unit system.
+    pub fn simplify_in_unit_system(&self, name: &str, quantity: &Quantity) -> Result<Quantity> {
+        // The name comes from a runtime value, so it is looked up without interning it
+        let Some(name) = Symbol::get(name).filter(|name| self.unit_systems.contains_key(name))
+        else {
+            return Err(UnitRegistryError::UnknownUnitSystem(name.to_string()));
+        };
+
+        let converted = self.convert_to_unit_system(name, quantity)?;
+        Ok(if converted.unit().iter().count() == 1 {
+            self.with_best_prefix(converted)
+        } else {
+            converted
+        })
+    }
+
+    /// Whether the conversion factor of a unit to the base units is the same
+    /// as for the SI units, whose base unit of mass is the kilogram (instead
+    /// of the gram).
+    fn is_coherent(unit: &Unit) -> bool {
+        let (base, factor) = unit.to_base_unit_representation();
+        let mass_exponent = base
+            .iter()
+            .find(|f| f.unit_id.name.as_str() == "gram")
+            .map(|f| f.exponent)
+            .unwrap_or(Exponent::from_integer(0));
+        let Some(mass_exponent) = mass_exponent
+            .is_integer()
+            .then(|| mass_exponent.to_integer())
+        else {
+            return false;
+        };
+
+        let expected = 1000f64.powi(mass_exponent as i32);
+        (factor.to_f64() / expected - 1.0).abs() < 1e-12
+    }
+
+    fn simplest_unit(&self, base: &Unit) -> Unit {
+        if let Some(unit) = self
+            .index
+            .units_with_base_representation(base)
+            .find(|u| Self::is_coherent(u))
+        {
+            return unit.clone();
+        }
+
+        if base.iter().count() <= 2 {
+            return base.clone();
+        }
+
+        // A named unit times a power of a base unit, e.g. `N·s`
+        let decompositions = self
+            .index
+            .units_in_order()
+            .filter(|u| u.to_base_unit_representation().0.iter().count() > 1)
+            .filter(|u| Self::is_coherent(u))
+            .filter_map(|named_unit| {
+                let named_base = named_unit.to_base_unit_representation().0;
+                let remainder = (base.clone() / named_base).canonicalized();
+                let remainder_factor = remainder.iter().exactly_one().ok()?;
+                if !remainder_factor.exponent.is_integer() {
+                    return None;
+                }
+
+                Some(named_unit.clone() * Unit::from_factor(remainder_factor.clone()))
+            });
+
+        decompositions
+            .chain(std::iter::once(base.clone()))
+            .fold(None, |simplest: Option<Unit>, candidate| match simplest {
+                Some(simplest) if complexity(&simplest) <= complexity(&candidate) => Some(simplest),
+                _ => Some(candidate),
+            })
+            .expect("at least one candidate")
+    }
+
+    /// Choose the metric prefix of the first unit such that the value is
+    /// between 1 and 1000 (`12000 m` → `12 km`), if that unit accepts metric
+    /// prefixes and has an exponent of one.
+    fn with_best_prefix(&self, quantity: Quantity) -> Quantity {
+        let value = quantity.unsafe_value().to_f64();
+        let Some(first) = quantity.unit().iter().next() else {
+            return quantity;
+        };
+        if first.exponent != Exponent::from_integer(1) || value == 0.0 || !value.is_finite() {
+            return quantity;
+        }
+        let accepts_metric_prefixes = self
+            .inner
+            .get_base_representation(first.unit_id.name)
+            .map(|(_, metadata)| metadata.metric_prefixes)
+            .unwrap_or(false);
+        if !accepts_metric_prefixes {
+            return quantity;
+        }
+
+        let unprefixed = Unit::from_factors(
+            std::iter::once(UnitFactor {
+                prefix: Prefix::none(),
+                ..first.clone()
+            })
+            .chain(quantity.unit().iter().skip(1).cloned()),
+        );
+        let value = quantity
+            .convert_to(&unprefixed)
+            .expect("same unit without prefix")
+            .unsafe_value()
+            .to_f64();
+        let exponent = ((value.abs().log10() / 3.0).floor() as i32).clamp(-10, 10) * 3;
+
+        quantity
+            .convert_to(&unprefixed.with_prefix(Prefix::Metric(exponent)))
+            .expect("same unit with a different prefix")
+    }
+
+    /// Find the preferred unit of a unit system for a given base representation.
+    ///
+    /// We first look for a single unit of the system that has the same dimension
+    /// (e.g. `dyn` for a force in CGS). If there is none, we try to write the
+    /// dimension as a named unit times a power of a unit for a single base
+    /// dimension (e.g. `Pa·s`), as long as that is simpler than the fallback: a
+    /// product of units for each base dimension (e.g. `kg·m⁻¹·s⁻¹`). If the
+    /// system does not contain a unit for one of those base dimensions, an error
+    /// is returned.
+    fn find_unit_in_unit_system(&self, name: Symbol, target_base: &Unit) -> Result<Unit> {
+        let units = self
+            .unit_systems
+            .get(&name)
+            .ok_or_else(|| UnitRegistryError::UnknownUnitSystem(name.to_string()))?;
+
+        let base_of = |unit: &Unit| unit.to_base_unit_representation().0;
+        let is_same_dimension =
+            |a: &Unit, b: &Unit| (a.clone() / b.clone()).canonicalized().is_scalar();
+
+        if let Some(unit) = units
+            .iter()
+            .find(|u| is_same_dimension(&base_of(u), target_base))
+        {
+            return Ok(unit.clone());
+        }
+
+        // Find the unit for a single base dimension, e.g. `m`, `kg` or `ft`
+        let unit_for_base_unit = |base_unit: &UnitFactor| {
+            let base_unit = Unit::from_factor(UnitFactor {
+                exponent: Exponent::from_integer(1),
+                ..base_unit.clone()
+            });
+            units.iter().find(|u| {
+                let base = base_of(u);
+                base.iter().count() == 1
+                    && base.iter().all(|f| f.exponent == Exponent::from_integer(1))
+                    && is_same_dimension(&base, &base_unit)
+            })
+        };
+
+        let mut fallback = Unit::scalar();
+        for factor in target_base.iter() {
+            let Some(unit) = unit_for_base_unit(factor) else {
+                let dimension = self
+                    .inner
+                    .get_base_representation(factor.unit_id.name)
+                    .map(|(_, metadata)| metadata.readable_type.to_string())
+                    .unwrap_or_else(|_| factor.unit_id.name.to_string());
+                return Err(UnitRegistryError::NoUnitInUnitSystem(
+                    name.to_string(),
+                    dimension,
+                ));
+            };
+
+            fallback = fallback * unit.clone().power(factor.exponent);
+        }
+
+        if fallback.iter().count() <= 2 {
+            return Ok(fallback);
+        }
+
+        // Units that are listed first take precedence
+        let decomposition = units
+            .iter()
+            .filter(|u| base_of(u).iter().count() > 1)
+            .find_map(|named_unit| {
+                let remainder = (target_base.clone() / base_of(named_unit)).canonicalized();
+                let remainder_factor = remainder.iter().exactly_one().ok()?;
+                if !remainder_factor.exponent.is_integer() {
+                    return None;
+                }
+                let unit = unit_for_base_unit(remainder_factor)?;
+
+                Some(named_unit.clone() * unit.clone().power(remainder_factor.exponent))
+            });
+
+        Ok(decomposition.unwrap_or(fallback))
+    }
 }
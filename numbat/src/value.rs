@@ -1,10 +1,18 @@
+use std::collections::VecDeque;
 use std::sync::Arc;
 
 use itertools::Itertools;
 use jiff::Zoned;
 
 use crate::{
-    list::NumbatList, pretty_print::PrettyPrint, quantity::Quantity, typed_ast::StructInfo,
+    duration::Duration,
+    list::NumbatList,
+    prefix_parser::AcceptsPrefix,
+    pretty_print::PrettyPrint,
+    quantity::Quantity,
+    sequence::Sequence,
+    typed_ast::StructInfo,
+    unit::{CanonicalName, Unit},
 };
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -13,6 +21,38 @@ pub enum FunctionReference {
     Normal(String),
     // TODO: We can get rid of this variant once we implement closures:
     TzConversion(String),
+    /// A function with bound arguments, created by `partial(f, args…)`. Unbound
+    /// parameters in between are [`Value::DefaultArgument`]s.
+    Partial(Box<FunctionReference>, Vec<Value>),
+}
+
+impl FunctionReference {
+    fn name(&self) -> &str {
+        match self {
+            FunctionReference::Foreign(name)
+            | FunctionReference::Normal(name)
+            | FunctionReference::TzConversion(name) => name,
+            FunctionReference::Partial(function, _) => function.name(),
+        }
+    }
+
+    /// The arguments for a call of a partially applied function: the given
+    /// arguments fill the unbound parameters, from left to right.
+    pub(crate) fn bind_arguments(
+        bound: Vec<Value>,
+        args: impl IntoIterator<Item = Value>,
+    ) -> Vec<Value> {
+        let mut args = args.into_iter();
+        let mut result = bound
+            .into_iter()
+            .map(|value| match value {
+                Value::DefaultArgument => args.next().unwrap_or(Value::DefaultArgument),
+                value => value,
+            })
+            .collect::<Vec<_>>();
+        result.extend(args);
+        result
+    }
 }
 
 impl std::fmt::Display for FunctionReference {
@@ -23,6 +63,9 @@ impl std::fmt::Display for FunctionReference {
             FunctionReference::TzConversion(tz) => {
                 write!(f, "<builtin timezone conversion function: {tz}>")
             }
+            FunctionReference::Partial(function, _) => {
+                write!(f, "<partially applied function: {}>", function.name())
+            }
         }
     }
 }
@@ -34,19 +77,56 @@ pub enum Value {
     String(String),
     /// A DateTime with an associated offset used when pretty printing
     DateTime(Zoned),
+    /// The exact difference between two DateTimes. This has the type of a `Time`
+    /// quantity, see [`Duration`].
+    Duration(Duration),
     FunctionReference(FunctionReference),
     FormatSpecifiers(Option<String>),
+    /// Placeholder for an argument that has not been specified in a function call
+    DefaultArgument,
     StructInstance(Arc<StructInfo>, Vec<Value>),
     List(NumbatList<Value>),
+    /// A lazily evaluated list, see [`Sequence`]
+    Sequence(Sequence),
 }
 
 impl Value {
+    /// A quantity with a unit given by its Numbat name, e.g. `km/h`. The unit
+    /// is looked up when the value is passed to [`crate::Context::set_variable`].
+    pub fn quantity(magnitude: f64, unit: &str) -> Value {
+        let unit = if unit.is_empty() {
+            Unit::scalar()
+        } else {
+            Unit::new_base(unit, CanonicalName::new(unit, AcceptsPrefix::none()))
+        };
+        Value::Quantity(Quantity::new_f64(magnitude, unit))
+    }
+
+    /// The same value, with all units written out in full (`3 kilometres` instead
+    /// of `3 km`), see [`Quantity::with_long_unit_names`].
+    pub fn with_long_unit_names(&self) -> Value {
+        match self {
+            Value::Quantity(q) => Value::Quantity(q.with_long_unit_names()),
+            Value::StructInstance(info, values) => Value::StructInstance(
+                info.clone(),
+                values.iter().map(Value::with_long_unit_names).collect(),
+            ),
+            Value::List(elements) => elements
+                .iter()
+                .map(Value::with_long_unit_names)
+                .collect::<VecDeque<_>>()
+                .into(),
+            value => value.clone(),
+        }
+    }
+
+    /// Durations are promoted to quantities.
     #[track_caller]
     pub fn unsafe_as_quantity(self) -> Quantity {
-        if let Value::Quantity(q) = self {
-            q
-        } else {
-            panic!("Expected value to be a quantity");
+        match self {
+            Value::Quantity(q) => q,
+            Value::Duration(d) => d.to_quantity(),
+            _ => panic!("Expected value to be a quantity"),
         }
     }
 
@@ -104,8 +184,9 @@ impl Value {
         }
     }
 
-    pub(crate) fn is_quantity(&self) -> bool {
-        matches!(self, Value::Quantity(_))
+    /// Also true for durations, which can be used like quantities.
+    pub fn is_quantity(&self) -> bool {
+        matches!(self, Value::Quantity(_) | Value::Duration(_))
     }
 }
 
@@ -116,8 +197,10 @@ impl std::fmt::Display for Value {
             Value::Boolean(b) => write!(f, "{}", b),
             Value::String(s) => write!(f, "\"{}\"", s),
             Value::DateTime(dt) => write!(f, "datetime(\"{}\")", dt),
+            Value::Duration(d) => write!(f, "{}", d),
             Value::FunctionReference(r) => write!(f, "{}", r),
             Value::FormatSpecifiers(_) => write!(f, "<format specfiers>"),
+            Value::DefaultArgument => write!(f, "<default argument>"),
             Value::StructInstance(struct_info, values) => write!(
                 f,
                 "{} {{{}}}",
@@ -131,7 +214,7 @@ impl std::fmt::Display for Value {
                             .fields
                             .keys()
                             .zip(values)
-                            .map(|(name, value)| name.to_owned() + ": " + &value.to_string())
+                            .map(|(name, value)| format!("{name}: {value}"))
                             .join(", ")
                     )
                 }
@@ -144,6 +227,7 @@ impl std::fmt::Display for Value {
                     .map(|element| element.to_string())
                     .join(", ")
             ),
+            Value::Sequence(_) => write!(f, "<lazy sequence>"),
         }
     }
 }
@@ -155,11 +239,13 @@ impl PrettyPrint for Value {
             Value::Boolean(b) => b.pretty_print(),
             Value::String(s) => s.pretty_print(),
             Value::DateTime(dt) => crate::markup::string(crate::datetime::to_string(dt)),
+            Value::Duration(d) => d.pretty_print(),
             Value::FunctionReference(r) => crate::markup::string(r.to_string()),
             Value::FormatSpecifiers(Some(s)) => crate::markup::string(s),
             Value::FormatSpecifiers(None) => crate::markup::empty(),
+            Value::DefaultArgument => crate::markup::empty(),
             Value::StructInstance(struct_info, values) => {
-                crate::markup::type_identifier(struct_info.name.clone())
+                crate::markup::type_identifier(struct_info.name)
                     + crate::markup::space()
                     + crate::markup::operator("{")
                     + if values.is_empty() {
@@ -189,6 +275,7 @@ impl PrettyPrint for Value {
                     .sum()
                     + crate::markup::operator("]")
             }
+            Value::Sequence(_) => crate::markup::string("<lazy sequence>"),
         }
     }
 }
@@ -1,26 +1,41 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
 use std::{cmp::Ordering, fmt::Display};
 
 use indexmap::IndexMap;
 use num_traits::ToPrimitive;
 
+use crate::ast::{ImportScope, ModuleItem};
+use crate::clock::{Clock, FixedClock};
+use crate::conversion_cache::ConversionCache;
+use crate::duration::Duration;
+use crate::file_system::{FileSystem, OsFileSystem};
 use crate::list::NumbatList;
+use crate::profile::{Profile, Profiler};
+use crate::regex_cache::RegexCache;
+use crate::sandbox::{Capability, SandboxPolicy};
+use crate::sequence;
 use crate::span::Span;
-use crate::typed_ast::StructInfo;
+use crate::symbol::Symbol;
+use crate::typed_ast::{DType, StructInfo, Type};
 use crate::{
-    ffi::{self, ArityRange, Callable, ForeignFunction},
-    interpreter::{InterpreterResult, PrintFunction, Result, RuntimeError},
+    ffi::{self, ArityRange, Callable, ForeignFunction, NativeCallback, UnitLookup},
+    interpreter::{ImplicitConversions, InterpreterResult, Result, RuntimeError, RuntimeWarning},
     markup::Markup,
     math,
     number::Number,
+    output::{NullSink, OutputSink},
     prefix::Prefix,
+    prefix_parser::{AcceptsPrefix, PrefixParser},
     quantity::{Quantity, QuantityError},
-    unit::Unit,
+    registry::RegistryEntry,
+    unit::{Unit, UnitFactor},
     unit_registry::{UnitMetadata, UnitRegistry},
     value::{FunctionReference, Value},
 };
 
+const REMOVED_NATIVE_FUNCTION: &str = "the function has been removed";
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum Op {
@@ -38,6 +53,16 @@ pub enum Op {
     /// identifier index. It then proceeds to assign a value of
     /// `1 <new_unit>` to the constant with the given index.
     SetUnitConstant,
+    /// Same as `SetUnitConstant`, but for units that are local to a function.
+    /// These are not added to the unit registry.
+    SetLocalUnitConstant,
+    /// Add the base unit with the given unit information index to the unit
+    /// registry.
+    DefineBaseUnit,
+
+    /// Hide or reveal units of a selectively imported module, according to the
+    /// import scope with the given index (see `ast::ImportScope`)
+    ApplyImportScope,
 
     /// Push the value of the specified local variable onto the stack (even
     /// though it is already on the stack, somewhere lower down).
@@ -49,6 +74,9 @@ pub enum Op {
     /// Get the last stored result (_ and ans)
     GetLastResult,
 
+    /// Get the n-th stored result (_1, _2, …). The operand is the 1-based index.
+    GetResult,
+
     /// Negate the top of the stack
     Negate,
 
@@ -74,22 +102,32 @@ pub enum Op {
     GreatorOrEqual,
     Equal,
     NotEqual,
-    LogicalAnd,
-    LogicalOr,
     LogicalNeg,
 
     /// Similar to Add, but has DateTime on the LHS and a quantity on the RHS
     AddToDateTime,
     /// Similar to Sub, but has DateTime on the LHS and a quantity on the RHS
     SubFromDateTime,
-    /// Computes the difference between two DateTimes
+    /// Computes the (exact) duration between two DateTimes
     DiffDateTime,
 
+    /// Copy the value on top of the stack below the second value (`a b` → `b a b`)
+    Tuck,
+    /// Remove the value on top of the stack
+    Pop,
+
     /// Move IP forward by the given offset argument if the popped-of value on
     /// top of the stack is false.
     JumpIfFalse,
     /// Unconditionally move IP forward by the given offset argument
     Jump,
+    /// Move IP forward by the given offset (second operand) if the current
+    /// function has been called with an argument for the parameter with the
+    /// given index (first operand). Used to skip over default parameter values.
+    JumpIfArgumentGiven,
+    /// Pop the value on top of the stack and store it in the specified local
+    /// variable slot. Used to replace placeholders for default arguments.
+    SetLocal,
 
     /// Call the specified function with the specified number of arguments
     Call,
@@ -98,13 +136,46 @@ pub enum Op {
     /// Same as above, but call a procedure which does not return anything (does not push a value onto the stack)
     /// It has a third argument which is an index to retrieve the source-span of the arguments
     FFICallProcedure,
+    /// Same as `FFICallFunction`, but for functions provided by the embedding
+    /// application. The third argument is an index to retrieve the call site.
+    CallNative,
+    /// Same as `FFICallFunction`, but for functions that construct a value of
+    /// their result type (like `parse_json`). The third argument is an index to
+    /// retrieve the call site.
+    FFICallTypedFunction,
 
     /// Call a callable object
     CallCallable,
+    /// Bind the given number of arguments on top of the stack to the callable
+    /// object below them, see `partial(f, args…)`
+    BindArguments,
 
     /// Print a compile-time string
     PrintString,
 
+    /// Print statistics about the cache of unit conversion factors
+    PrintConversionCacheStats,
+
+    /// Print statistics about the cache of compiled regular expressions
+    PrintRegexCacheStats,
+
+    /// Replace the clock by one that always returns the datetime parsed from
+    /// the string on top of the stack
+    SetFixedTime,
+
+    /// Change how implicit unit conversions are handled, according to the string
+    /// on top of the stack
+    SetImplicitConversions,
+
+    /// Print the value on top of the stack (without removing it), together
+    /// with the source code and the type of the traced expression. The two
+    /// operands are string indices for the source code and the type.
+    Trace,
+
+    /// Check that the value on top of the stack (which has the type `Dynamic`)
+    /// is of the type given by the check index operand
+    CheckDynamicType,
+
     /// Combine N strings on the stack into a single part, used by string interpolation
     JoinString,
 
@@ -115,9 +186,51 @@ pub enum Op {
     BuildStructInstance,
     /// Access a single field of a struct
     AccessStructField,
+    /// Replace a single field of the struct below the value on top of the stack
+    UpdateStructField,
 
     /// Build a list from the elements on the stack
     BuildList,
+    /// Replace the list on top of the stack by its elements. Fails if the list
+    /// does not have the number of elements given by the operand.
+    DestructureList,
+
+    /// Declare a unit system. It takes two operands: a constant index
+    /// for the name of the system and the number of units. The units
+    /// are popped off the stack.
+    DefineUnitSystem,
+    /// Convert the quantity on top of the stack to the unit system whose
+    /// name is given by the constant index operand.
+    ConvertToUnitSystem,
+    /// Split the quantity below the given number of units (operand) into a list
+    /// of quantities, one per unit, which add up to the original quantity. All
+    /// but the last component are whole numbers, e.g. `5 ft + 6 in`.
+    ConvertToUnitList,
+
+    /// Call the loop body with the given function index (first operand) for
+    /// each element of the list on top of the stack, and replace the list by
+    /// the list of results. The second operand is the number of captured local
+    /// variables below the list, which are passed to the body before the
+    /// element.
+    ForLoop,
+    /// Same as `ForLoop`, but with the initial value of the accumulator on top
+    /// of the list. The accumulator is passed to the body after the element,
+    /// and replaced by its result. Only the final accumulator is kept.
+    ForLoopWithAccumulator,
+    /// Call the loop body (second operand) as long as the condition (first
+    /// operand) holds. Both are called with the captured local variables (third
+    /// operand) and the accumulator on top of the stack, which is replaced by
+    /// the result of the body.
+    WhileLoop,
+
+    /// Call the function with the index given by the first operand. If it fails
+    /// with an error that can be caught, call the fallback function (second
+    /// operand) instead. Both are called with the captured local variables (third
+    /// operand) on top of the stack.
+    TryCatch,
+    /// Same as `TryCatch`, but the error message is passed to the fallback after
+    /// the captured local variables.
+    TryCatchWithMessage,
 
     /// Return from the current function
     Return,
@@ -126,19 +239,43 @@ pub enum Op {
 impl Op {
     fn num_operands(self) -> usize {
         match self {
-            Op::FFICallProcedure => 3,
-            Op::SetUnitConstant | Op::Call | Op::FFICallFunction | Op::BuildStructInstance => 2,
+            Op::FFICallProcedure
+            | Op::CallNative
+            | Op::FFICallTypedFunction
+            | Op::WhileLoop
+            | Op::TryCatch
+            | Op::TryCatchWithMessage
+            | Op::Trace => 3,
+            Op::SetUnitConstant
+            | Op::SetLocalUnitConstant
+            | Op::Call
+            | Op::FFICallFunction
+            | Op::BuildStructInstance
+            | Op::DefineUnitSystem
+            | Op::JumpIfArgumentGiven
+            | Op::ForLoop
+            | Op::ForLoopWithAccumulator => 2,
             Op::LoadConstant
             | Op::ApplyPrefix
             | Op::GetLocal
+            | Op::SetLocal
             | Op::GetUpvalue
+            | Op::GetResult
             | Op::PrintString
             | Op::JoinString
             | Op::JumpIfFalse
             | Op::Jump
             | Op::CallCallable
+            | Op::BindArguments
             | Op::AccessStructField
-            | Op::BuildList => 1,
+            | Op::UpdateStructField
+            | Op::BuildList
+            | Op::DestructureList
+            | Op::ConvertToUnitSystem
+            | Op::ConvertToUnitList
+            | Op::DefineBaseUnit
+            | Op::ApplyImportScope
+            | Op::CheckDynamicType => 1,
             Op::Negate
             | Op::Factorial
             | Op::Add
@@ -156,12 +293,16 @@ impl Op {
             | Op::GreatorOrEqual
             | Op::Equal
             | Op::NotEqual
-            | Op::LogicalAnd
-            | Op::LogicalOr
             | Op::LogicalNeg
+            | Op::Tuck
+            | Op::Pop
             | Op::FullSimplify
             | Op::Return
-            | Op::GetLastResult => 0,
+            | Op::GetLastResult
+            | Op::PrintConversionCacheStats
+            | Op::PrintRegexCacheStats
+            | Op::SetFixedTime
+            | Op::SetImplicitConversions => 0,
         }
     }
 
@@ -170,9 +311,13 @@ impl Op {
             Op::LoadConstant => "LoadConstant",
             Op::ApplyPrefix => "ApplyPrefix",
             Op::SetUnitConstant => "SetUnitConstant",
+            Op::SetLocalUnitConstant => "SetLocalUnitConstant",
+            Op::DefineBaseUnit => "DefineBaseUnit",
+            Op::ApplyImportScope => "ApplyImportScope",
             Op::GetLocal => "GetLocal",
             Op::GetUpvalue => "GetUpvalue",
             Op::GetLastResult => "GetLastResult",
+            Op::GetResult => "GetResult",
             Op::Negate => "Negate",
             Op::Factorial => "Factorial",
             Op::Add => "Add",
@@ -190,22 +335,43 @@ impl Op {
             Op::GreatorOrEqual => "GreatorOrEqual",
             Op::Equal => "Equal",
             Op::NotEqual => "NotEqual",
-            Op::LogicalAnd => "LogicalAnd",
-            Op::LogicalOr => "LogicalOr",
             Op::LogicalNeg => "LogicalNeg",
+            Op::Tuck => "Tuck",
+            Op::Pop => "Pop",
             Op::JumpIfFalse => "JumpIfFalse",
             Op::Jump => "Jump",
+            Op::JumpIfArgumentGiven => "JumpIfArgumentGiven",
+            Op::SetLocal => "SetLocal",
             Op::Call => "Call",
             Op::FFICallFunction => "FFICallFunction",
             Op::FFICallProcedure => "FFICallProcedure",
+            Op::CallNative => "CallNative",
+            Op::FFICallTypedFunction => "FFICallTypedFunction",
             Op::CallCallable => "CallCallable",
+            Op::BindArguments => "BindArguments",
             Op::PrintString => "PrintString",
+            Op::PrintConversionCacheStats => "PrintConversionCacheStats",
+            Op::PrintRegexCacheStats => "PrintRegexCacheStats",
+            Op::SetFixedTime => "SetFixedTime",
+            Op::SetImplicitConversions => "SetImplicitConversions",
+            Op::Trace => "Trace",
+            Op::CheckDynamicType => "CheckDynamicType",
             Op::JoinString => "JoinString",
             Op::FullSimplify => "FullSimplify",
             Op::Return => "Return",
             Op::BuildStructInstance => "BuildStructInstance",
             Op::AccessStructField => "AccessStructField",
+            Op::UpdateStructField => "UpdateStructField",
             Op::BuildList => "BuildList",
+            Op::DestructureList => "DestructureList",
+            Op::DefineUnitSystem => "DefineUnitSystem",
+            Op::ConvertToUnitSystem => "ConvertToUnitSystem",
+            Op::ConvertToUnitList => "ConvertToUnitList",
+            Op::ForLoop => "ForLoop",
+            Op::ForLoopWithAccumulator => "ForLoopWithAccumulator",
+            Op::WhileLoop => "WhileLoop",
+            Op::TryCatch => "TryCatch",
+            Op::TryCatchWithMessage => "TryCatchWithMessage",
         }
     }
 }
@@ -218,17 +384,19 @@ pub enum Constant {
     String(String),
     FunctionReference(FunctionReference),
     FormatSpecifiers(Option<String>),
+    DefaultArgument,
 }
 
 impl Constant {
     fn to_value(&self) -> Value {
         match self {
-            Constant::Scalar(n) => Value::Quantity(Quantity::from_scalar(*n)),
+            Constant::Scalar(n) => Value::Quantity(Quantity::from_integer(*n)),
             Constant::Unit(u) => Value::Quantity(Quantity::from_unit(u.clone())),
             Constant::Boolean(b) => Value::Boolean(*b),
             Constant::String(s) => Value::String(s.clone()),
             Constant::FunctionReference(inner) => Value::FunctionReference(inner.clone()),
             Constant::FormatSpecifiers(s) => Value::FormatSpecifiers(s.clone()),
+            Constant::DefaultArgument => Value::DefaultArgument,
         }
     }
 }
@@ -242,6 +410,7 @@ impl Display for Constant {
             Constant::String(val) => write!(f, "\"{}\"", val),
             Constant::FunctionReference(inner) => write!(f, "{}", inner),
             Constant::FormatSpecifiers(_) => write!(f, "<format specfiers>"),
+            Constant::DefaultArgument => write!(f, "<default argument>"),
         }
     }
 }
@@ -270,8 +439,106 @@ impl CallFrame {
     }
 }
 
+/// A `while` loop that runs this often is most likely an infinite loop.
+const DEFAULT_MAX_LOOP_ITERATIONS: u64 = 10_000_000;
+
+/// Lists need to have at least this many elements for `map` and reductions like `sum`
+/// to be evaluated in parallel.
+const PARALLEL_EVALUATION_THRESHOLD: usize = 10_000;
+
+fn available_parallelism() -> usize {
+    if cfg!(target_arch = "wasm32") {
+        1
+    } else {
+        std::thread::available_parallelism().map_or(1, |n| n.get())
+    }
+}
+
+/// Specializations of a generic function for concrete types (`max⟨Length⟩`) are
+/// profiled as calls of the generic function.
+fn profile_name(function_name: &str) -> &str {
+    function_name
+        .split_once('⟨')
+        .map_or(function_name, |(name, _)| name)
+}
+
+/// The expected type in errors of runtime type checks. Generic types can not be
+/// printed, only their outermost type is checked.
+fn describe_expected_type(type_: &Type) -> String {
+    if type_.type_variables(true).is_empty() {
+        return match type_ {
+            Type::Struct(info) => info.name.to_string(),
+            _ => type_.to_string(),
+        };
+    }
+
+    match type_ {
+        Type::Dimension(_) => "a quantity".into(),
+        Type::Fn(..) => "a function".into(),
+        Type::Struct(info) => info.name.to_string(),
+        Type::List(_) => "a list".into(),
+        _ => "any type".into(),
+    }
+}
+
+/// Gives native functions like `map` access to the virtual machine.
+struct VmFunctionCaller<'a, 'b> {
+    vm: &'a mut Vm,
+    ctx: &'a mut ExecutionContext<'b>,
+}
+
+impl sequence::FunctionCaller for VmFunctionCaller<'_, '_> {
+    fn call(&mut self, function: &Value, args: Vec<Value>) -> Result<Value> {
+        self.vm.call_function(self.ctx, function, args)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn parallel_map(
+        &mut self,
+        function: &Value,
+        list: &NumbatList<Value>,
+    ) -> Option<Result<Vec<Value>>> {
+        self.vm.parallel_map(function, list)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn parallel_reduce(
+        &mut self,
+        list: &NumbatList<Value>,
+        reduce: sequence::Reduce,
+    ) -> Option<Result<Value>> {
+        self.vm.parallel_reduce(list, reduce)
+    }
+
+    fn current_time(&self) -> Result<jiff::Zoned> {
+        self.vm.current_time()
+    }
+
+    fn unit_lookup(&self) -> Option<&dyn UnitLookup> {
+        Some(&*self.vm)
+    }
+
+    fn summation_error_threshold(&self) -> Option<f64> {
+        self.vm.summation_error_threshold
+    }
+
+    fn warn(&mut self, warning: RuntimeWarning) {
+        // The exchange rate of each currency leads to the same warning
+        if matches!(warning, RuntimeWarning::CachedExchangeRates(..))
+            && self
+                .vm
+                .warnings
+                .iter()
+                .any(|w| matches!(w, RuntimeWarning::CachedExchangeRates(..)))
+        {
+            return;
+        }
+        self.vm.warnings.push(warning);
+    }
+}
+
 pub struct ExecutionContext<'a> {
-    pub print_fn: &'a mut PrintFunction,
+    pub output: &'a mut dyn OutputSink,
 }
 
 #[derive(Clone)]
@@ -284,11 +551,19 @@ pub struct Vm {
     /// currently being compiled.
     current_chunk_index: usize,
 
+    /// Functions whose compilation continues after the current one, like the
+    /// function that contains the body of a loop.
+    enclosing_chunk_indices: Vec<usize>,
+
     /// Constants are numbers like '1.4' or a [Unit] like 'meter'.
     pub constants: Vec<Constant>,
 
+    /// Indices of the scalar constants, by their bit pattern. Each number is only
+    /// stored once, so that long expressions do not exhaust the constant indices.
+    scalar_constants: HashMap<u64, u16>,
+
     /// struct metadata, used so we can display struct fields at runtime
-    struct_infos: IndexMap<String, Arc<StructInfo>>,
+    struct_infos: IndexMap<Symbol, Arc<StructInfo>>,
 
     /// Unit prefixes in use
     prefixes: Vec<Prefix>,
@@ -300,18 +575,56 @@ pub struct Vm {
     /// - Unit name
     /// - Canonical name
     /// - Metadata
-    unit_information: Vec<(String, Option<String>, UnitMetadata)>,
+    unit_information: Vec<(Symbol, Option<Symbol>, UnitMetadata, Span)>,
 
-    /// Result of the last expression
-    last_result: Option<Value>,
+    /// Same as `unit_information`, but for units that are local to a function.
+    /// These can shadow global units (or each other), so they are never shared.
+    local_unit_information: Vec<(Symbol, Option<Symbol>, UnitMetadata, Span)>,
+
+    /// Results of all top-level expressions, in order. These can be
+    /// accessed via `ans`/`_` (last result) and `_1`, `_2`, …
+    results: Vec<Value>,
 
     /// List of registered native/foreign functions
-    ffi_callables: Vec<&'static ForeignFunction>,
+    ffi_callables: Vec<Arc<ForeignFunction>>,
+
+    /// Functions provided by the embedding application, see
+    /// [`Vm::add_native_function`]
+    native_functions: HashMap<String, Arc<NativeCallback>>,
 
     /// Spans for arguments of procedure calls. This is used for
     /// assertion error messages, for example.
     procedure_arg_spans: Vec<Vec<Span>>,
 
+    /// Span and (fully known) result type of calls to native functions and typed
+    /// foreign functions. The type is used to check the values returned by the
+    /// embedding application, or to construct the result of a typed function.
+    call_sites: Vec<(Span, Option<Type>)>,
+
+    /// Span and expected type of values of type `Dynamic`, which are checked at
+    /// runtime where they are used as values of a static type
+    /// Span, expected type and whether the value needs to be a quantity
+    dynamic_checks: Vec<(Span, Type, bool)>,
+
+    /// Destination of files written by procedures like `write_text(…)`
+    file_system: Arc<dyn FileSystem>,
+
+    /// Arguments that were passed to the script, see `args()`
+    script_arguments: Vec<String>,
+
+    /// Global units by all of their names, to look up units in strings at
+    /// runtime (see [`Vm::lookup_unit`])
+    unit_names: HashMap<String, NamedUnit>,
+
+    /// Import scopes of selectively imported modules, see [`Op::ApplyImportScope`]
+    import_scopes: Vec<ImportScope>,
+
+    /// Units that have been set aside while a module is imported selectively
+    stashed_units: Vec<Vec<(Symbol, ScopedUnit)>>,
+
+    /// Units of selectively imported modules that are not in scope
+    hidden_units: HashMap<ModuleItem, ScopedUnit>,
+
     /// The call stack
     frames: Vec<CallFrame>,
 
@@ -321,7 +634,71 @@ pub struct Vm {
     /// Whether or not to run in debug mode.
     debug: bool,
 
+    /// For each user-defined function, the names of all functions it refers to, or
+    /// `None` if the function might have side effects on its own. This is used to
+    /// decide whether a function is pure.
+    function_dependencies: HashMap<String, Option<Vec<String>>>,
+
+    /// Maximum number of threads used to evaluate pure functions on large lists.
+    num_threads: usize,
+
+    #[cfg(not(target_arch = "wasm32"))]
+    thread_pool: Option<Arc<rayon::ThreadPool>>,
+
     pub unit_registry: UnitRegistry,
+
+    /// Conversion factors for unit conversions (`->`) that have been performed before.
+    conversion_cache: ConversionCache,
+
+    /// Compiled regular expressions for functions like `str_match`.
+    regex_cache: RegexCache,
+
+    /// Determines which foreign functions are replaced when they are declared
+    sandbox_policy: SandboxPolicy,
+
+    /// The source of the current time. The system clock is used if this is not set.
+    clock: Option<Arc<dyn Clock>>,
+
+    /// The base unit of angles, if `Angle` is a base dimension (see
+    /// `core::strict_angle`). Trigonometric functions are replaced when this is set.
+    angle_unit: Option<Unit>,
+
+    /// Records the time spent in each function, if profiling is enabled.
+    profiler: Option<Box<Profiler>>,
+
+    /// Maximum number of iterations of a `while` loop
+    max_loop_iterations: u64,
+
+    /// Sums with a larger estimated relative rounding error cause a warning
+    summation_error_threshold: Option<f64>,
+
+    /// How unit conversions in additions and subtractions are handled
+    implicit_conversions: ImplicitConversions,
+
+    /// Warnings that have not been shown yet
+    warnings: Vec<RuntimeWarning>,
+
+    /// Global units whose conversion factor depends on the exchange rates: the
+    /// index of their unit information, of their constant and of the function
+    /// that evaluates their definition (see [`Vm::update_currency_units`])
+    #[cfg(feature = "currency")]
+    currency_definitions: Vec<(u16, u16, usize)>,
+}
+
+/// A unit that can be referred to by name at runtime, see [`Vm::lookup_unit`]
+#[derive(Clone)]
+struct NamedUnit {
+    unit: Unit,
+    accepts_prefix: AcceptsPrefix,
+    metric_prefixes: bool,
+    binary_prefixes: bool,
+}
+
+/// A unit that has been set aside or hidden by an [`ImportScope`]
+#[derive(Clone, Default)]
+struct ScopedUnit {
+    registry_entry: Option<RegistryEntry<UnitMetadata>>,
+    named_unit: Option<NamedUnit>,
 }
 
 impl Vm {
@@ -329,24 +706,287 @@ impl Vm {
         Self {
             bytecode: vec![("<main>".into(), vec![])],
             current_chunk_index: 0,
+            enclosing_chunk_indices: vec![],
             constants: vec![],
+            scalar_constants: HashMap::new(),
             struct_infos: IndexMap::new(),
             prefixes: vec![],
             strings: vec![],
             unit_information: vec![],
-            last_result: None,
-            ffi_callables: ffi::procedures().iter().map(|(_, ff)| ff).collect(),
+            local_unit_information: vec![],
+            results: vec![],
+            ffi_callables: ffi::procedures().values().cloned().collect(),
+            native_functions: HashMap::new(),
             procedure_arg_spans: vec![],
+            call_sites: vec![],
+            dynamic_checks: vec![],
+            file_system: Arc::new(OsFileSystem),
+            script_arguments: vec![],
+            unit_names: HashMap::new(),
+            import_scopes: vec![],
+            stashed_units: vec![],
+            hidden_units: HashMap::new(),
             frames: vec![CallFrame::root()],
             stack: vec![],
             debug: false,
+            function_dependencies: HashMap::new(),
+            num_threads: available_parallelism(),
+            #[cfg(not(target_arch = "wasm32"))]
+            thread_pool: None,
             unit_registry: UnitRegistry::new(),
+            conversion_cache: ConversionCache::default(),
+            regex_cache: RegexCache::default(),
+            sandbox_policy: SandboxPolicy::default(),
+            clock: None,
+            angle_unit: None,
+            profiler: None,
+            max_loop_iterations: DEFAULT_MAX_LOOP_ITERATIONS,
+            summation_error_threshold: None,
+            implicit_conversions: ImplicitConversions::default(),
+            warnings: vec![],
+            #[cfg(feature = "currency")]
+            currency_definitions: vec![],
+        }
+    }
+
+    /// Needs to be called before the trigonometric functions are declared.
+    pub(crate) fn set_angle_unit(&mut self, unit: Unit) {
+        self.angle_unit = Some(unit);
+    }
+
+    /// Needs to be called before any foreign function is declared.
+    pub(crate) fn set_sandbox_policy(&mut self, policy: SandboxPolicy) {
+        self.sandbox_policy = policy;
+    }
+
+    pub(crate) fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = Some(clock);
+    }
+
+    pub(crate) fn set_file_system(&mut self, file_system: Arc<dyn FileSystem>) {
+        self.file_system = file_system;
+    }
+
+    pub(crate) fn set_script_arguments(&mut self, arguments: Vec<String>) {
+        self.script_arguments = arguments;
+    }
+
+    /// Enabling the profiler discards everything it has recorded before.
+    pub(crate) fn set_profiling(&mut self, enabled: bool) {
+        self.profiler = enabled.then(Box::default);
+    }
+
+    pub(crate) fn profile(&self) -> Option<Profile> {
+        self.profiler.as_ref().map(|profiler| profiler.profile())
+    }
+
+    /// Keep the calls recorded by another VM, which is discarded after an error.
+    pub(crate) fn take_profiler_from(&mut self, other: &mut Vm) {
+        if self.profiler.is_some() {
+            self.profiler = other.profiler.take();
         }
     }
+
+    pub(crate) fn set_max_loop_iterations(&mut self, max_iterations: u64) {
+        self.max_loop_iterations = max_iterations;
+    }
+
+    pub(crate) fn set_summation_error_threshold(&mut self, threshold: Option<f64>) {
+        self.summation_error_threshold = threshold;
+    }
+
+    pub(crate) fn set_implicit_conversions(&mut self, implicit_conversions: ImplicitConversions) {
+        self.implicit_conversions = implicit_conversions;
+    }
+
+    pub(crate) fn take_warnings(&mut self) -> Vec<RuntimeWarning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Warn about (or forbid) the conversion of `rhs` to the unit of `lhs` that
+    /// happens when the two quantities are added or subtracted.
+    fn check_implicit_conversion(&mut self, lhs: &Quantity, rhs: &Quantity) -> Result<()> {
+        if self.implicit_conversions == ImplicitConversions::Silent
+            || lhs.is_zero()
+            || rhs.is_zero()
+            || lhs.unit().canonicalized() == rhs.unit().canonicalized()
+        {
+            return Ok(());
+        }
+
+        let (from, to) = (rhs.unit().clone(), lhs.unit().clone());
+        match self.implicit_conversions {
+            ImplicitConversions::Silent => Ok(()),
+            ImplicitConversions::Warn => {
+                let factor = *Quantity::new_f64(1.0, from.clone())
+                    .convert_to(&to)
+                    .map_err(RuntimeError::QuantityError)?
+                    .unsafe_value();
+                self.warnings
+                    .push(RuntimeWarning::ImplicitConversion(from, to, factor));
+                Ok(())
+            }
+            ImplicitConversions::Forbid => Err(RuntimeError::ImplicitConversionForbidden(from, to)),
+        }
+    }
+
+    /// Fail if `rhs` would need to be converted between two different isolated
+    /// units (like currencies) to be added to or compared with `lhs`. Other
+    /// factors of the units, like in `USD/kg` and `USD/g`, can still be converted.
+    fn check_isolated_units(&self, lhs: &Quantity, rhs: &Quantity) -> Result<()> {
+        if lhs.unit() == rhs.unit() {
+            return Ok(());
+        }
+
+        let isolated_factors = |unit: &Unit| {
+            Unit::from_factors(
+                unit.iter()
+                    .filter(|factor| {
+                        self.unit_registry
+                            .inner
+                            .get_base_representation(factor.unit_id.name)
+                            .is_ok_and(|(_, metadata)| metadata.isolated)
+                    })
+                    .map(|factor| UnitFactor {
+                        prefix: Prefix::none(),
+                        ..factor.clone()
+                    }),
+            )
+            .canonicalized()
+        };
+
+        let (lhs_isolated, rhs_isolated) =
+            (isolated_factors(lhs.unit()), isolated_factors(rhs.unit()));
+        if lhs_isolated != rhs_isolated
+            && lhs_isolated != Unit::scalar()
+            && rhs_isolated != Unit::scalar()
+        {
+            return Err(RuntimeError::IsolatedUnitConversion(
+                rhs.unit().clone(),
+                lhs.unit().clone(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn current_time(&self) -> Result<jiff::Zoned> {
+        match &self.clock {
+            Some(clock) => Ok(clock.now()),
+            None if self.sandbox_policy.allows(Capability::Clock) => Ok(jiff::Zoned::now()),
+            None => Err(RuntimeError::PermissionDenied(Capability::Clock)),
+        }
+    }
+
+    pub(crate) fn add_import_scope(&mut self, scope: ImportScope) -> u16 {
+        self.import_scopes.push(scope);
+        assert!(self.import_scopes.len() <= u16::MAX as usize);
+        (self.import_scopes.len() - 1) as u16
+    }
+
+    fn take_unit(&mut self, name: Symbol) -> ScopedUnit {
+        self.unit_registry.unindex_unit(name);
+        ScopedUnit {
+            registry_entry: self.unit_registry.inner.take(name),
+            named_unit: self.unit_names.remove(name.as_str()),
+        }
+    }
+
+    fn restore_unit(&mut self, name: Symbol, unit: ScopedUnit) {
+        self.take_unit(name);
+        self.unit_registry.inner.restore(name, unit.registry_entry);
+        if let Some(named_unit) = unit.named_unit {
+            self.unit_registry.index_unit(name, &named_unit.unit);
+            self.unit_names.insert(name.to_string(), named_unit);
+        }
+    }
+
+    /// Hide or reveal units, like [`crate::typechecker::TypeChecker`] does for
+    /// all kinds of definitions at compile time
+    fn apply_import_scope(&mut self, index: usize) {
+        match self.import_scopes[index].clone() {
+            ImportScope::Begin(names) => {
+                let stashed = names
+                    .iter()
+                    .map(|name| (*name, self.take_unit(*name)))
+                    .collect();
+                self.stashed_units.push(stashed);
+            }
+            ImportScope::End(items) => {
+                for item in items {
+                    let unit = self.take_unit(item.1);
+                    self.hidden_units.insert(item, unit);
+                }
+                for (name, unit) in self.stashed_units.pop().unwrap_or_default() {
+                    self.restore_unit(name, unit);
+                }
+            }
+            ImportScope::Reveal(items) => {
+                for item in items {
+                    if let Some(unit) = self.hidden_units.remove(&item) {
+                        self.restore_unit(item.1, unit);
+                    }
+                }
+            }
+        }
+        self.conversion_cache.clear();
+    }
+
     pub fn set_debug(&mut self, activate: bool) {
         self.debug = activate;
     }
 
+    pub fn set_num_threads(&mut self, num_threads: usize) {
+        self.num_threads = num_threads.max(1);
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.thread_pool = None;
+        }
+    }
+
+    pub(crate) fn set_function_dependencies(
+        &mut self,
+        name: &str,
+        dependencies: Option<Vec<String>>,
+    ) {
+        self.function_dependencies
+            .insert(name.to_string(), dependencies);
+    }
+
+    /// A function is pure if it has no side effects and if its result only depends on
+    /// its arguments. Calls to pure functions can be evaluated in any order.
+    fn is_pure(&self, function: &Value) -> bool {
+        match function {
+            Value::FunctionReference(FunctionReference::Normal(name))
+            | Value::FunctionReference(FunctionReference::Foreign(name)) => {
+                self.is_pure_function(name, &mut HashSet::new())
+            }
+            Value::FunctionReference(FunctionReference::TzConversion(_)) => true,
+            Value::FunctionReference(FunctionReference::Partial(function, _)) => {
+                self.is_pure(&Value::FunctionReference((**function).clone()))
+            }
+            _ => false,
+        }
+    }
+
+    fn is_pure_function<'a>(&'a self, name: &'a str, visited: &mut HashSet<&'a str>) -> bool {
+        if !visited.insert(name) {
+            // We are already checking this function further up (recursion)
+            return true;
+        }
+
+        match self.function_dependencies.get(name) {
+            Some(Some(dependencies)) => dependencies
+                .iter()
+                .all(|dependency| self.is_pure_function(dependency, visited)),
+            Some(None) => false,
+            // Native functions might have side effects
+            None => {
+                !ffi::NONDETERMINISTIC_FUNCTIONS.contains(&name)
+                    && !self.native_functions.contains_key(name)
+            }
+        }
+    }
+
     // The following functions are helpers for the compilation process
 
     fn current_chunk_mut(&mut self) -> &mut Vec<u8> {
@@ -396,21 +1036,29 @@ impl Vm {
     }
 
     pub fn add_constant(&mut self, constant: Constant) -> u16 {
+        if let Constant::Scalar(n) = constant {
+            if let Some(idx) = self.scalar_constants.get(&n.to_bits()) {
+                return *idx;
+            }
+            self.scalar_constants
+                .insert(n.to_bits(), self.constants.len() as u16);
+        }
+
         self.constants.push(constant);
         assert!(self.constants.len() <= u16::MAX as usize);
         (self.constants.len() - 1) as u16 // TODO: this can overflow, see above
     }
 
     pub fn add_struct_info(&mut self, struct_info: &StructInfo) -> usize {
-        let e = self.struct_infos.entry(struct_info.name.clone());
+        let e = self.struct_infos.entry(struct_info.name);
         let idx = e.index();
         e.or_insert_with(|| Arc::new(struct_info.clone()));
 
         idx
     }
 
-    pub fn get_structinfo_idx(&self, name: &str) -> Option<usize> {
-        self.struct_infos.get_index_of(name)
+    pub fn get_structinfo_idx(&self, name: Symbol) -> Option<usize> {
+        self.struct_infos.get_index_of(&name)
     }
 
     pub fn add_prefix(&mut self, prefix: Prefix) -> u16 {
@@ -425,31 +1073,60 @@ impl Vm {
 
     pub fn add_unit_information(
         &mut self,
-        unit_name: &str,
-        canonical_unit_name: Option<&str>,
+        unit_name: Symbol,
+        canonical_unit_name: Option<Symbol>,
         metadata: UnitMetadata,
+        span: Span,
     ) -> u16 {
-        if let Some(idx) = self.unit_information.iter().position(|i| i.0 == unit_name) {
+        // A unit of the same name can be defined again after the first one has
+        // been hidden (see `ast::ImportScope`)
+        if let Some(idx) = self
+            .unit_information
+            .iter()
+            .position(|i| i.0 == unit_name && i.3 == span)
+        {
             return idx as u16;
         }
 
-        self.unit_information.push((
-            unit_name.to_owned(),
-            canonical_unit_name.map(|s| s.to_owned()),
-            metadata,
-        ));
+        self.unit_information
+            .push((unit_name, canonical_unit_name, metadata, span));
         assert!(self.unit_information.len() <= u16::MAX as usize);
         (self.unit_information.len() - 1) as u16 // TODO: this can overflow, see above
     }
 
-    pub(crate) fn begin_function(&mut self, name: &str) {
+    pub fn add_local_unit_information(
+        &mut self,
+        unit_name: Symbol,
+        canonical_unit_name: Option<Symbol>,
+        metadata: UnitMetadata,
+        span: Span,
+    ) -> u16 {
+        self.local_unit_information
+            .push((unit_name, canonical_unit_name, metadata, span));
+        assert!(self.local_unit_information.len() <= u16::MAX as usize);
+        (self.local_unit_information.len() - 1) as u16
+    }
+
+    /// Starts the compilation of a new function and returns its index.
+    pub(crate) fn begin_function(&mut self, name: &str) -> u16 {
         self.bytecode.push((name.into(), vec![]));
-        self.current_chunk_index = self.bytecode.len() - 1
+        self.enclosing_chunk_indices.push(self.current_chunk_index);
+        self.current_chunk_index = self.bytecode.len() - 1;
+        assert!(self.current_chunk_index <= u16::MAX as usize);
+        self.current_chunk_index as u16
     }
 
     pub(crate) fn end_function(&mut self) {
-        // Continue compilation of "main"/global code
-        self.current_chunk_index = 0;
+        // Continue compilation of the enclosing function, usually "main"/global code
+        self.current_chunk_index = self.enclosing_chunk_indices.pop().unwrap_or(0);
+    }
+
+    /// Make the function with the given index the latest one of its name, such
+    /// that calls that are compiled later refer to it
+    pub(crate) fn copy_function(&mut self, function_idx: u16) {
+        let function = self.bytecode[function_idx as usize].clone();
+        self.bytecode.push(function);
+        assert!(self.bytecode.len() <= u16::MAX as usize + 1);
     }
 
     pub(crate) fn get_function_idx(&self, name: &str) -> u16 {
@@ -466,19 +1143,251 @@ impl Vm {
         position as u16
     }
 
-    pub(crate) fn add_foreign_function(&mut self, name: &str, arity: ArityRange) {
+    pub(crate) fn add_foreign_function(
+        &mut self,
+        name: &str,
+        arity: ArityRange,
+        return_type: Option<Type>,
+    ) {
+        if let Some(function) = self.native_functions.get(name) {
+            self.ffi_callables.push(Arc::new(ForeignFunction {
+                name: name.to_string(),
+                arity,
+                callable: Callable::Native(function.clone(), return_type),
+            }));
+            return;
+        }
+
         let ff = ffi::functions().get(name).unwrap();
         assert!(ff.arity == arity);
-        self.ffi_callables.push(ff);
+        let replacement = ffi::sandboxed_function(name, &self.sandbox_policy).or_else(|| {
+            self.angle_unit
+                .as_ref()
+                .and_then(|radian| ffi::strict_angle_function(name, radian))
+        });
+        if let Some(callable) = replacement {
+            self.ffi_callables.push(Arc::new(ForeignFunction {
+                name: name.to_string(),
+                arity,
+                callable,
+            }));
+        } else {
+            self.ffi_callables.push(ff.clone());
+        }
+    }
+
+    /// Register a function that is implemented by the embedding application. It
+    /// becomes available once it is declared like any other foreign function.
+    /// Code that has been compiled before keeps calling the previous function
+    /// of the same name, if there is one.
+    pub(crate) fn add_native_function(&mut self, name: &str, function: Arc<NativeCallback>) {
+        self.native_functions.insert(name.to_string(), function);
+    }
+
+    pub(crate) fn is_native_function(&self, name: &str) -> bool {
+        self.native_functions.contains_key(name)
+    }
+
+    /// Drop the function that has been registered under the given name. Calls
+    /// from code that has been compiled before fail with a runtime error.
+    pub(crate) fn remove_native_function(&mut self, name: &str) -> bool {
+        if self.native_functions.remove(name).is_none() {
+            return false;
+        }
+
+        let removed: Arc<NativeCallback> = Arc::new(|_| Err(REMOVED_NATIVE_FUNCTION.into()));
+        for ff in &mut self.ffi_callables {
+            if let (true, Callable::Native(_, return_type)) = (ff.name == name, &ff.callable) {
+                *ff = Arc::new(ForeignFunction {
+                    name: ff.name.clone(),
+                    arity: ff.arity.clone(),
+                    callable: Callable::Native(removed.clone(), return_type.clone()),
+                });
+            }
+        }
+        true
     }
 
     pub(crate) fn get_ffi_callable_idx(&self, name: &str) -> Option<u16> {
-        // TODO: this is a linear search that can certainly be optimized
-        let position = self.ffi_callables.iter().position(|ff| ff.name == name)?;
+        // TODO: this is a linear search that can certainly be optimized.
+        // We search from the back, so that re-declared functions refer to the
+        // latest definition.
+        let position = self.ffi_callables.iter().rposition(|ff| {
+            ff.name == name
+                && (!matches!(ff.callable, Callable::Native(..))
+                    || self.native_functions.contains_key(name))
+        })?;
         assert!(position <= u16::MAX as usize);
         Some(position as u16)
     }
 
+    /// Look up the foreign function that a function reference refers to. Only
+    /// native functions can disappear, when they are removed by the embedder.
+    fn function_reference_idx(&self, name: &str) -> Result<usize> {
+        self.get_ffi_callable_idx(name)
+            .map(usize::from)
+            .ok_or_else(|| {
+                RuntimeError::NativeFunctionFailed(
+                    None,
+                    name.into(),
+                    REMOVED_NATIVE_FUNCTION.into(),
+                )
+            })
+    }
+
+    pub(crate) fn is_typed_function(&self, idx: u16) -> bool {
+        matches!(
+            self.ffi_callables[idx as usize].callable,
+            Callable::TypedFunction(_) | Callable::TypedSequenceFunction(_)
+        )
+    }
+
+    pub(crate) fn add_call_site(&mut self, span: Span, result_type: Option<Type>) -> u16 {
+        self.call_sites.push((span, result_type));
+        assert!(self.call_sites.len() <= u16::MAX as usize);
+        (self.call_sites.len() - 1) as u16
+    }
+
+    pub(crate) fn add_dynamic_check(&mut self, span: Span, type_: Type, is_quantity: bool) -> u16 {
+        self.dynamic_checks.push((span, type_, is_quantity));
+        assert!(self.dynamic_checks.len() <= u16::MAX as usize);
+        (self.dynamic_checks.len() - 1) as u16
+    }
+
+    /// Make a global unit available under all of its names.
+    pub(crate) fn add_unit_names(&mut self, unit: &Unit, metadata: &UnitMetadata) {
+        for (name, accepts_prefix) in &metadata.aliases {
+            self.unit_names.insert(
+                name.to_string(),
+                NamedUnit {
+                    unit: unit.clone(),
+                    accepts_prefix: *accepts_prefix,
+                    metric_prefixes: metadata.metric_prefixes,
+                    binary_prefixes: metadata.binary_prefixes,
+                },
+            );
+        }
+    }
+
+    /// Set the constant of a global derived unit, given the value of its
+    /// definition. The unit also becomes available by name.
+    fn set_unit_constant(
+        &mut self,
+        unit_information_idx: u16,
+        constant_idx: u16,
+        conversion_value: Quantity,
+    ) {
+        let (name, _, metadata, _) = &self.unit_information[unit_information_idx as usize];
+        let (name, metadata) = (*name, metadata.clone());
+
+        let unit = Unit::new_derived(
+            &name,
+            metadata.canonical_name.clone(),
+            *conversion_value.unsafe_value(),
+            conversion_value.unit().clone(),
+        );
+        self.add_unit_names(&unit, &metadata);
+        self.unit_registry.index_unit(name, &unit);
+        self.constants[constant_idx as usize] = Constant::Unit(unit);
+    }
+
+    /// Remember the function that evaluates the definition of a currency unit,
+    /// see [`Vm::update_currency_units`].
+    #[cfg(feature = "currency")]
+    pub(crate) fn add_currency_definition(
+        &mut self,
+        unit_information_idx: u16,
+        constant_idx: u16,
+        function_idx: u16,
+    ) {
+        self.currency_definitions
+            .push((unit_information_idx, constant_idx, function_idx as usize));
+    }
+
+    /// Evaluate the definitions of all currency units again, after the exchange
+    /// rates have been replaced. Quantities that have been computed before keep
+    /// their old conversion factors.
+    #[cfg(feature = "currency")]
+    pub(crate) fn update_currency_units(&mut self, ctx: &mut ExecutionContext) -> Result<()> {
+        for (unit_information_idx, constant_idx, function_idx) in self.currency_definitions.clone()
+        {
+            let (depth, stack_size) = (self.frames.len(), self.stack.len());
+            let conversion_value = match self.call_bytecode_function(ctx, function_idx, []) {
+                Ok(value) => value.unsafe_as_quantity(),
+                Err(e) => {
+                    self.frames.truncate(depth);
+                    self.stack.truncate(stack_size);
+                    return Err(e);
+                }
+            };
+            self.set_unit_constant(unit_information_idx, constant_idx, conversion_value);
+        }
+        self.conversion_cache.clear();
+
+        Ok(())
+    }
+
+    /// Push a value that stays on the stack, as a global variable.
+    pub(crate) fn push_global(&mut self, value: Value) {
+        self.push(value);
+    }
+
+    pub(crate) fn global(&self, position: usize) -> Option<&Value> {
+        self.stack.get(position)
+    }
+
+    /// The values of all top-level expression statements, in order of evaluation
+    pub(crate) fn results(&self) -> &[Value] {
+        &self.results
+    }
+
+    /// The type of a value that has been created outside of Numbat code.
+    pub(crate) fn type_of_value(&self, value: &Value) -> std::result::Result<Type, &'static str> {
+        match value {
+            Value::Quantity(q) => self
+                .dimension_of(q.unit())
+                .map(Type::Dimension)
+                .ok_or("the unit has no known dimension"),
+            Value::Boolean(_) => Ok(Type::Boolean),
+            Value::String(_) => Ok(Type::String),
+            Value::DateTime(_) => Ok(Type::DateTime),
+            Value::Duration(d) => self
+                .dimension_of(d.to_quantity().unit())
+                .map(Type::Dimension)
+                .ok_or("the unit has no known dimension"),
+            Value::StructInstance(info, values) => {
+                if info.fields.len() == values.len()
+                    && info
+                        .fields
+                        .values()
+                        .zip(values)
+                        .all(|((_, type_), value)| self.has_type(value, type_))
+                {
+                    Ok(Type::Struct((**info).clone()))
+                } else {
+                    Err("the fields do not match the struct definition")
+                }
+            }
+            Value::List(elements) => {
+                let mut elements = elements.iter();
+                let element_type = self.type_of_value(
+                    elements
+                        .next()
+                        .ok_or("the element type of an empty list is unknown")?,
+                )?;
+                if elements.all(|element| self.has_type(element, &element_type)) {
+                    Ok(Type::List(Box::new(element_type)))
+                } else {
+                    Err("the elements of the list have different types")
+                }
+            }
+            Value::FunctionReference(_)
+            | Value::FormatSpecifiers(_)
+            | Value::DefaultArgument
+            | Value::Sequence(_) => Err("values of this kind are not supported"),
+        }
+    }
+
     pub(crate) fn add_procedure_arg_span(&mut self, spans: Vec<Span>) -> u16 {
         self.procedure_arg_spans.push(spans);
         assert!(self.procedure_arg_spans.len() <= u16::MAX as usize);
@@ -565,6 +1474,14 @@ impl Vm {
         u16::from_le_bytes(bytes)
     }
 
+    fn read_string_constant(&mut self) -> String {
+        let constant_idx = self.read_u16();
+        match &self.constants[constant_idx as usize] {
+            Constant::String(s) => s.clone(),
+            _ => unreachable!("Expected a string constant"),
+        }
+    }
+
     fn push_quantity(&mut self, quantity: Quantity) {
         self.stack.push(Value::Quantity(quantity));
     }
@@ -577,10 +1494,12 @@ impl Vm {
         self.stack.push(value);
     }
 
+    /// Durations are promoted to quantities.
     #[track_caller]
     fn pop_quantity(&mut self) -> Quantity {
         match self.pop() {
             Value::Quantity(q) => q,
+            Value::Duration(d) => d.to_quantity(),
             _ => panic!("Expected quantity to be on the top of the stack"),
         }
     }
@@ -590,6 +1509,14 @@ impl Vm {
         self.pop().unsafe_as_bool()
     }
 
+    #[track_caller]
+    fn pop_duration(&mut self) -> Duration {
+        match self.pop() {
+            Value::Duration(d) => d,
+            _ => panic!("Expected duration to be on the top of the stack"),
+        }
+    }
+
     #[track_caller]
     fn pop_datetime(&mut self) -> jiff::Zoned {
         match self.pop() {
@@ -619,6 +1546,10 @@ impl Vm {
             self.frames.clear();
             self.frames.push(CallFrame::root());
             self.frames[0].ip = self.bytecode[0].1.len();
+
+            if let Some(profiler) = &mut self.profiler {
+                profiler.unwind();
+            }
         }
         result
     }
@@ -628,8 +1559,26 @@ impl Vm {
     }
 
     fn run_without_cleanup(&mut self, ctx: &mut ExecutionContext) -> Result<InterpreterResult> {
+        if let Some(value) = self.execute(ctx, None)? {
+            Ok(InterpreterResult::Value(value))
+        } else {
+            Ok(InterpreterResult::Continue)
+        }
+    }
+
+    /// Execute bytecode until the end of the program is reached or, if a `depth`
+    /// is given, until the call stack has been unwound to that depth. Returns the
+    /// result of the last statement.
+    fn execute(
+        &mut self,
+        ctx: &mut ExecutionContext,
+        depth: Option<usize>,
+    ) -> Result<Option<Value>> {
         let mut result_last_statement = None;
-        while !self.is_at_the_end() {
+        while match depth {
+            Some(depth) => self.frames.len() > depth,
+            None => !self.is_at_the_end(),
+        } {
             self.debug();
 
             let op = unsafe { std::mem::transmute::<u8, Op>(self.read_byte()) };
@@ -649,31 +1598,60 @@ impl Vm {
                         quantity.unit().clone().with_prefix(prefix),
                     ));
                 }
-                Op::SetUnitConstant => {
+                op @ (Op::SetUnitConstant | Op::SetLocalUnitConstant) => {
                     let unit_information_idx = self.read_u16();
                     let constant_idx = self.read_u16();
 
                     let conversion_value = self.pop_quantity();
 
-                    let unit_information = &self.unit_information[unit_information_idx as usize];
-                    let defining_unit = conversion_value.unit();
-
-                    let (base_unit_representation, _) = defining_unit.to_base_unit_representation();
-
-                    self.unit_registry
-                        .add_derived_unit(
+                    if op == Op::SetUnitConstant {
+                        let unit_information =
+                            &self.unit_information[unit_information_idx as usize];
+                        let (base_unit_representation, _) =
+                            conversion_value.unit().to_base_unit_representation();
+                        self.unit_registry
+                            .add_derived_unit(
+                                unit_information.0,
+                                unit_information.3,
+                                &base_unit_representation,
+                                unit_information.2.clone(),
+                            )
+                            .map_err(RuntimeError::UnitRegistryError)?;
+                        self.conversion_cache.clear();
+
+                        self.set_unit_constant(
+                            unit_information_idx,
+                            constant_idx,
+                            conversion_value,
+                        );
+                    } else {
+                        let unit_information =
+                            &self.local_unit_information[unit_information_idx as usize];
+                        let unit = Unit::new_derived(
                             &unit_information.0,
-                            &base_unit_representation,
-                            unit_information.2.clone(),
-                        )
+                            unit_information.2.canonical_name.clone(),
+                            *conversion_value.unsafe_value(),
+                            conversion_value.unit().clone(),
+                        );
+                        self.constants[constant_idx as usize] = Constant::Unit(unit);
+                    }
+                }
+                Op::DefineBaseUnit => {
+                    let unit_information_idx = self.read_u16() as usize;
+                    let (unit_name, _, metadata, span) =
+                        self.unit_information[unit_information_idx].clone();
+
+                    let unit = Unit::new_base(&unit_name, metadata.canonical_name.clone());
+                    self.add_unit_names(&unit, &metadata);
+                    self.unit_registry.index_unit(unit_name, &unit);
+                    self.unit_registry
+                        .add_base_unit(unit_name, span, metadata)
                         .map_err(RuntimeError::UnitRegistryError)?;
-
-                    self.constants[constant_idx as usize] = Constant::Unit(Unit::new_derived(
-                        &unit_information.0,
-                        unit_information.2.canonical_name.clone(),
-                        *conversion_value.unsafe_value(),
-                        defining_unit.clone(),
-                    ));
+                    self.conversion_cache.clear();
+                }
+                Op::ApplyImportScope => {
+                    let index = self.read_u16() as usize;
+                    self.apply_import_scope(index);
                 }
                 Op::GetLocal => {
                     let slot_idx = self.read_u16() as usize;
@@ -685,7 +1663,29 @@ impl Vm {
                     self.push(self.stack[stack_idx].clone());
                 }
                 Op::GetLastResult => {
-                    self.push(self.last_result.as_ref().unwrap().clone());
+                    self.push(self.results.last().unwrap().clone());
+                }
+                Op::GetResult => {
+                    let index = self.read_u16() as usize;
+                    self.push(self.results[index - 1].clone());
+                }
+                op @ (Op::Add | Op::Subtract)
+                    if matches!(
+                        self.stack[self.stack.len() - 2..],
+                        [Value::Duration(_), Value::Duration(_)]
+                    ) =>
+                {
+                    let rhs = self.pop_duration();
+                    let lhs = self.pop_duration();
+
+                    let result = match op {
+                        Op::Add => lhs.checked_add(&rhs),
+                        Op::Subtract => lhs.checked_sub(&rhs),
+                        _ => unreachable!(),
+                    };
+                    self.push(Value::Duration(
+                        result.ok_or(RuntimeError::DurationOutOfRange)?,
+                    ));
                 }
                 op @ (Op::Add
                 | Op::Subtract
@@ -695,6 +1695,10 @@ impl Vm {
                 | Op::ConvertTo) => {
                     let rhs = self.pop_quantity();
                     let lhs = self.pop_quantity();
+                    if matches!(op, Op::Add | Op::Subtract) {
+                        self.check_isolated_units(&lhs, &rhs)?;
+                        self.check_implicit_conversion(&lhs, &rhs)?;
+                    }
                     let result = match op {
                         Op::Add => &lhs + &rhs,
                         Op::Subtract => &lhs - &rhs,
@@ -703,11 +1707,26 @@ impl Vm {
                             Ok(lhs.checked_div(rhs).ok_or(RuntimeError::DivisionByZero)?)
                         }
                         Op::Power => lhs.power(rhs),
-                        Op::ConvertTo => lhs.convert_to(rhs.unit()),
+                        Op::ConvertTo => self.conversion_cache.convert(&lhs, rhs.unit()),
                         _ => unreachable!(),
                     };
                     self.push_quantity(result.map_err(RuntimeError::QuantityError)?);
                 }
+                op @ (Op::AddToDateTime | Op::SubFromDateTime)
+                    if matches!(self.stack.last(), Some(Value::Duration(_))) =>
+                {
+                    let rhs = self.pop_duration();
+                    let lhs = self.pop_datetime();
+
+                    let rhs = match op {
+                        Op::AddToDateTime => rhs,
+                        Op::SubFromDateTime => -rhs,
+                        _ => unreachable!(),
+                    };
+                    self.push(Value::DateTime(
+                        rhs.add_to(&lhs).ok_or(RuntimeError::DateTimeOutOfRange)?,
+                    ));
+                }
                 op @ (Op::AddToDateTime | Op::SubFromDateTime) => {
                     let rhs = self.pop_quantity();
                     let lhs = self.pop_datetime();
@@ -740,30 +1759,33 @@ impl Vm {
                     let rhs = self.pop_datetime();
                     let lhs = self.pop_datetime();
 
-                    let duration = lhs
-                        .since(&rhs)
-                        .map_err(|_| RuntimeError::DateTimeOutOfRange)?;
-                    let duration = duration
-                        .total(jiff::Unit::Second)
-                        .map_err(|_| RuntimeError::DurationOutOfRange)?;
-
-                    let ret = Value::Quantity(Quantity::new(
-                        Number::from_f64(duration),
-                        unit.unit().clone(),
-                    ));
+                    let duration = Duration::between(&rhs, &lhs, unit.unit().clone())
+                        .ok_or(RuntimeError::DurationOutOfRange)?;
 
-                    self.push(ret);
+                    self.push(Value::Duration(duration));
                 }
                 op @ (Op::LessThan | Op::GreaterThan | Op::LessOrEqual | Op::GreatorOrEqual) => {
-                    let rhs = self.pop_quantity();
-                    let lhs = self.pop_quantity();
+                    let rhs = self.pop();
+                    let lhs = self.pop();
 
-                    let result = lhs.partial_cmp(&rhs).ok_or_else(|| {
-                        RuntimeError::QuantityError(QuantityError::IncompatibleUnits(
-                            lhs.unit().clone(),
-                            rhs.unit().clone(),
-                        ))
-                    })?;
+                    let result = match (lhs, rhs) {
+                        (Value::DateTime(lhs), Value::DateTime(rhs)) => lhs.cmp(&rhs),
+                        (Value::Duration(lhs), Value::Duration(rhs)) => {
+                            lhs.as_nanoseconds().cmp(&rhs.as_nanoseconds())
+                        }
+                        (Value::String(lhs), Value::String(rhs)) => lhs.cmp(&rhs),
+                        (lhs, rhs) => {
+                            let lhs = lhs.unsafe_as_quantity();
+                            let rhs = rhs.unsafe_as_quantity();
+                            self.check_isolated_units(&lhs, &rhs)?;
+                            lhs.partial_cmp(&rhs).ok_or_else(|| {
+                                RuntimeError::QuantityError(QuantityError::IncompatibleUnits(
+                                    lhs.unit().clone(),
+                                    rhs.unit().clone(),
+                                ))
+                            })?
+                        }
+                    };
 
                     let result = match op {
                         Op::LessThan => result == Ordering::Less,
@@ -777,7 +1799,23 @@ impl Vm {
                 }
                 op @ (Op::Equal | Op::NotEqual) => {
                     let rhs = self.pop();
+                    let rhs = self.force(ctx, rhs)?;
                     let lhs = self.pop();
+                    let lhs = self.force(ctx, lhs)?;
+
+                    // Durations are only compared exactly to other durations
+                    let (lhs, rhs) = match (lhs, rhs) {
+                        (Value::Duration(d), rhs @ Value::Quantity(_)) => {
+                            (Value::Quantity(d.to_quantity()), rhs)
+                        }
+                        (lhs @ Value::Quantity(_), Value::Duration(d)) => {
+                            (lhs, Value::Quantity(d.to_quantity()))
+                        }
+                        values => values,
+                    };
+                    if let (Value::Quantity(lhs), Value::Quantity(rhs)) = (&lhs, &rhs) {
+                        self.check_isolated_units(lhs, rhs)?;
+                    }
 
                     let result = match op {
                         Op::Equal => lhs == rhs,
@@ -786,39 +1824,40 @@ impl Vm {
                     };
                     self.push(Value::Boolean(result));
                 }
-                op @ (Op::LogicalAnd | Op::LogicalOr) => {
-                    let rhs = self.pop_bool();
-                    let lhs = self.pop_bool();
-
-                    let result = match op {
-                        Op::LogicalAnd => lhs && rhs,
-                        Op::LogicalOr => lhs || rhs,
-                        _ => unreachable!(),
-                    };
-                    self.push_bool(result);
-                }
                 Op::LogicalNeg => {
                     let rhs = self.pop_bool();
                     self.push_bool(!rhs);
                 }
-                Op::Negate => {
-                    let rhs = self.pop_quantity();
-                    self.push_quantity(-rhs);
-                }
+                Op::Negate => match self.pop() {
+                    Value::Duration(d) => self.push(Value::Duration(-d)),
+                    value => {
+                        let rhs = value.unsafe_as_quantity();
+                        self.push_quantity(-rhs);
+                    }
+                },
                 Op::Factorial => {
-                    let lhs = self
-                        .pop_quantity()
-                        .as_scalar()
-                        .expect("Expected factorial operand to be scalar")
-                        .to_f64();
+                    let lhs = self.pop_quantity();
+                    let n = lhs.as_integer().ok_or_else(|| {
+                        RuntimeError::FactorialOfNonInteger(lhs.to_exact_string())
+                    })?;
 
-                    if lhs < 0. {
+                    if n < 0 {
                         return Err(RuntimeError::FactorialOfNegativeNumber);
-                    } else if lhs.fract() != 0. {
-                        return Err(RuntimeError::FactorialOfNonInteger);
                     }
 
-                    self.push_quantity(Quantity::from_scalar(math::factorial(lhs)));
+                    let result = math::factorial(n as u64)
+                        .ok_or_else(|| RuntimeError::IntegerResultTooLarge(format!("{n}!")))?;
+                    self.push_quantity(Quantity::from_integer(result));
+                }
+                Op::Tuck => {
+                    let top = self.pop();
+                    let below = self.pop();
+                    self.push(top.clone());
+                    self.push(below);
+                    self.push(top);
+                }
+                Op::Pop => {
+                    self.pop();
                 }
                 Op::JumpIfFalse => {
                     let offset = self.read_u16() as usize;
@@ -830,9 +1869,26 @@ impl Vm {
                     let offset = self.read_u16() as usize;
                     self.current_frame_mut().ip += offset;
                 }
+                Op::JumpIfArgumentGiven => {
+                    let parameter_idx = self.read_u16() as usize;
+                    let offset = self.read_u16() as usize;
+                    let fp = self.current_frame().fp;
+                    if !matches!(self.stack[fp + parameter_idx], Value::DefaultArgument) {
+                        self.current_frame_mut().ip += offset;
+                    }
+                }
+                Op::SetLocal => {
+                    let slot_idx = self.read_u16() as usize;
+                    let value = self.pop();
+                    let stack_idx = self.current_frame().fp + slot_idx;
+                    self.stack[stack_idx] = value;
+                }
                 Op::Call => {
                     let function_idx = self.read_u16() as usize;
                     let num_args = self.read_u16() as usize;
+                    if let Some(profiler) = &mut self.profiler {
+                        profiler.enter(profile_name(&self.bytecode[function_idx].0), false);
+                    }
                     self.frames.push(CallFrame {
                         function_idx,
                         ip: 0,
@@ -852,15 +1908,29 @@ impl Vm {
                     }
 
                     match &self.ffi_callables[function_idx].callable {
-                        Callable::Function(function) => {
-                            let result = (function)(args);
+                        Callable::Function(_)
+                        | Callable::ClockFunction(_)
+                        | Callable::UnitFunction(_)
+                        | Callable::RegexFunction(_)
+                        | Callable::ScriptFunction(_)
+                        | Callable::SequenceFunction(_)
+                        | Callable::TypedFunction(_)
+                        | Callable::TypedSequenceFunction(_)
+                        | Callable::Native(..) => {
+                            let result = self.profile_foreign(function_idx, |vm| {
+                                vm.call_foreign_function(ctx, function_idx, args)
+                            });
                             self.push(result?);
                         }
                         Callable::Procedure(procedure) => {
+                            let procedure = *procedure;
                             let span_idx = self.read_u16() as usize;
-                            let spans = &self.procedure_arg_spans[span_idx];
+                            let spans = self.procedure_arg_spans[span_idx].clone();
 
-                            let result = (procedure)(ctx, args, spans.clone());
+                            let args = self.force_args(ctx, args)?;
+
+                            let result = self
+                                .profile_foreign(function_idx, |_| (procedure)(ctx, args, spans));
 
                             match result {
                                 std::ops::ControlFlow::Continue(()) => {}
@@ -869,17 +1939,94 @@ impl Vm {
                                 }
                             }
                         }
+                        Callable::FileProcedure(procedure) => {
+                            let procedure = *procedure;
+                            let _span_idx = self.read_u16();
+
+                            if let Some(capability) = self.file_system.required_capability() {
+                                if !self.sandbox_policy.allows(capability) {
+                                    return Err(RuntimeError::PermissionDenied(capability));
+                                }
+                            }
+
+                            let args = self.force_args(ctx, args)?;
+
+                            let result = self.profile_foreign(function_idx, |vm| {
+                                (procedure)(vm.file_system.as_ref(), args)
+                            });
+
+                            if let std::ops::ControlFlow::Break(runtime_error) = result {
+                                return Err(runtime_error);
+                            }
+                        }
                     }
                 }
-                Op::CallCallable => {
+                Op::CallNative => {
+                    let function_idx = self.read_u16() as usize;
                     let num_args = self.read_u16() as usize;
+                    let call_site_idx = self.read_u16() as usize;
+
+                    let mut args = VecDeque::new();
+                    for _ in 0..num_args {
+                        args.push_front(self.pop());
+                    }
 
-                    let callable = self.pop();
-                    match callable.unsafe_as_function_reference() {
+                    let function = self.ffi_callables[function_idx].clone();
+                    let Callable::Native(callback, declared_return_type) = &function.callable
+                    else {
+                        unreachable!("CallNative is only used for native functions")
+                    };
+                    let (span, return_type) = self.call_sites[call_site_idx].clone();
+
+                    let result = self.profile_foreign(function_idx, |vm| {
+                        vm.call_native_function(
+                            ctx,
+                            &function.name,
+                            &**callback,
+                            args,
+                            Some(span),
+                            return_type.as_ref().or(declared_return_type.as_ref()),
+                        )
+                    });
+                    self.push(result?);
+                }
+                Op::FFICallTypedFunction => {
+                    let function_idx = self.read_u16() as usize;
+                    let num_args = self.read_u16() as usize;
+                    let call_site_idx = self.read_u16() as usize;
+
+                    let mut args = VecDeque::new();
+                    for _ in 0..num_args {
+                        args.push_front(self.pop());
+                    }
+
+                    let (_, result_type) = self.call_sites[call_site_idx].clone();
+
+                    let result = self.profile_foreign(function_idx, |vm| {
+                        vm.call_typed_function(ctx, function_idx, args, result_type.as_ref())
+                    });
+                    self.push(result?);
+                }
+                Op::CallCallable => {
+                    let mut num_args = self.read_u16() as usize;
+
+                    let mut callable = self.pop().unsafe_as_function_reference();
+                    while let FunctionReference::Partial(function, bound) = callable {
+                        let args = self.stack.split_off(self.stack.len() - num_args);
+                        let args = FunctionReference::bind_arguments(bound, args);
+                        num_args = args.len();
+                        self.stack.extend(args);
+                        callable = *function;
+                    }
+
+                    match callable {
                         FunctionReference::Normal(ref name) => {
                             let function_idx = self.get_function_idx(name) as usize;
 
                             // TODO: unify code with 'Op::Call'?
+                            if let Some(profiler) = &mut self.profiler {
+                                profiler.enter(name, false);
+                            }
                             self.frames.push(CallFrame {
                                 function_idx,
                                 ip: 0,
@@ -887,43 +2034,130 @@ impl Vm {
                             })
                         }
                         FunctionReference::Foreign(ref name) => {
-                            let function_idx = self
-                                .get_ffi_callable_idx(name)
-                                .expect("Foreign function exists")
-                                as usize;
+                            let function_idx = self.function_reference_idx(name)?;
 
                             let mut args = VecDeque::new();
                             for _ in 0..num_args {
                                 args.push_front(self.pop());
                             }
 
-                            match &self.ffi_callables[function_idx].callable {
-                                Callable::Function(function) => {
-                                    let result = (function)(args);
-                                    self.push(result?);
-                                }
-                                Callable::Procedure(..) => unreachable!("Foreign procedures can not be targeted by a function reference"),
-                            }
+                            let result = self.profile_foreign(function_idx, |vm| {
+                                vm.call_foreign_function(ctx, function_idx, args)
+                            });
+                            self.push(result?);
                         }
                         FunctionReference::TzConversion(tz_name) => {
                             // TODO: implement this using a closure, once we have that in the language
 
                             let dt = self.pop_datetime();
 
-                            let tz = jiff::tz::TimeZone::get(&tz_name)
-                                .map_err(|_| RuntimeError::UnknownTimezone(tz_name.into()))?;
-
-                            let dt = dt.with_time_zone(tz);
-
-                            self.push(Value::DateTime(dt));
+                            self.push(Self::convert_timezone(&tz_name, dt)?);
                         }
+                        FunctionReference::Partial(..) => unreachable!(),
                     }
                 }
+                Op::BindArguments => {
+                    let num_args = self.read_u16() as usize;
+
+                    let args = self.stack.split_off(self.stack.len() - num_args);
+                    let function = self.pop().unsafe_as_function_reference();
+                    self.push(Value::FunctionReference(FunctionReference::Partial(
+                        Box::new(function),
+                        args,
+                    )));
+                }
                 Op::PrintString => {
                     let s_idx = self.read_u16() as usize;
                     let s = &self.strings[s_idx];
                     self.print(ctx, s);
                 }
+                Op::PrintConversionCacheStats => {
+                    let stats = self.conversion_cache.stats();
+                    self.print(ctx, &crate::markup::text(stats.to_string()));
+                }
+                Op::PrintRegexCacheStats => {
+                    let stats = self.regex_cache.stats();
+                    self.print(ctx, &crate::markup::text(stats.to_string()));
+                }
+                Op::SetFixedTime => {
+                    if !self.sandbox_policy.allows(Capability::FixedTime) {
+                        return Err(RuntimeError::PermissionDenied(Capability::FixedTime));
+                    }
+
+                    let input = self.pop();
+                    let input = self.force(ctx, input)?.unsafe_as_string();
+                    let time = if self.sandbox_policy.is_strict() {
+                        crate::datetime::parse_datetime(&input, || jiff::tz::TimeZone::UTC)
+                    } else {
+                        crate::datetime::parse_datetime(
+                            &input,
+                            crate::datetime::get_local_timezone_or_utc,
+                        )
+                    }
+                    .map_err(|e| RuntimeError::DateParsingError(e.to_string()))?;
+
+                    self.clock = Some(Arc::new(FixedClock::new(time)));
+                }
+                Op::SetImplicitConversions => {
+                    let setting = self.pop();
+                    let setting = self.force(ctx, setting)?.unsafe_as_string();
+                    self.implicit_conversions = setting.parse()?;
+                }
+                Op::Trace => {
+                    let source_idx = self.read_u16() as usize;
+                    let type_idx = self.read_u16() as usize;
+                    let is_generic = self.read_u16() == 1;
+
+                    use crate::markup as m;
+                    use crate::pretty_print::PrettyPrint;
+                    let value = self.pop();
+                    let value = self.force(ctx, value)?;
+                    let type_ = match &value {
+                        _ if !is_generic => None,
+                        Value::Quantity(q) => self
+                            .dimension_of(q.unit())
+                            .map(|dimension| dimension.pretty_print()),
+                        Value::Boolean(_) => Some(m::type_identifier("Bool")),
+                        Value::String(_) => Some(m::type_identifier("String")),
+                        Value::DateTime(_) => Some(m::type_identifier("DateTime")),
+                        Value::StructInstance(info, _) => Some(m::type_identifier(info.name)),
+                        _ => None,
+                    }
+                    .unwrap_or_else(|| self.strings[type_idx].clone());
+                    let markup = self.strings[source_idx].clone()
+                        + m::space()
+                        + m::operator("=")
+                        + m::space()
+                        + value.pretty_print()
+                        + m::space()
+                        + m::operator(":")
+                        + m::space()
+                        + type_;
+                    self.print(ctx, &markup);
+                    self.push(value);
+                }
+                Op::CheckDynamicType => {
+                    let check_idx = self.read_u16() as usize;
+                    let value = self.pop();
+
+                    let (span, type_, is_quantity) = &self.dynamic_checks[check_idx];
+                    if !self.has_dynamic_type(&value, type_) {
+                        return Err(RuntimeError::DynamicValueHasWrongType(
+                            *span,
+                            value,
+                            describe_expected_type(type_),
+                        ));
+                    }
+                    // The dimension may have been generalized to a type variable
+                    if *is_quantity && !matches!(value, Value::Quantity(_) | Value::Duration(_)) {
+                        return Err(RuntimeError::DynamicValueHasWrongType(
+                            *span,
+                            value,
+                            "a quantity".into(),
+                        ));
+                    }
+                    self.push(value);
+                }
                 Op::JoinString => {
                     let num_parts = self.read_u16() as usize;
                     let mut joined = String::new();
@@ -932,10 +2166,13 @@ impl Vm {
                         Value::Boolean(b) => b.to_string(),
                         Value::String(s) => s,
                         Value::DateTime(dt) => crate::datetime::to_string(&dt),
+                        Value::Duration(d) => d.to_string(),
                         Value::FunctionReference(r) => r.to_string(),
                         s @ Value::StructInstance(..) => s.to_string(),
                         l @ Value::List(_) => l.to_string(),
-                        Value::FormatSpecifiers(_) => unreachable!(),
+                        Value::FormatSpecifiers(_)
+                        | Value::DefaultArgument
+                        | Value::Sequence(_) => unreachable!(),
                     };
 
                     let map_strfmt_error_to_runtime_error = |err| match err {
@@ -948,34 +2185,43 @@ impl Vm {
 
                     for _ in 0..num_parts {
                         let part = match self.pop() {
-                            Value::FormatSpecifiers(Some(specifiers)) => match self.pop() {
-                                Value::Quantity(q) => {
-                                    let mut vars = HashMap::new();
-                                    vars.insert("value".to_string(), q.unsafe_value().to_f64());
+                            Value::FormatSpecifiers(Some(specifiers)) => {
+                                let value = self.pop();
+                                match self.force(ctx, value)? {
+                                    value @ (Value::Quantity(_) | Value::Duration(_)) => {
+                                        let q = value.unsafe_as_quantity();
+                                        let mut vars = HashMap::new();
+                                        vars.insert("value".to_string(), q.unsafe_value().to_f64());
+
+                                        let mut str = strfmt::strfmt(
+                                            &format!("{{value{}}}", specifiers),
+                                            &vars,
+                                        )
+                                        .map_err(map_strfmt_error_to_runtime_error)?;
+
+                                        let unit_str = q.unit().to_string();
+
+                                        if !unit_str.is_empty() {
+                                            str += " ";
+                                            str += &unit_str;
+                                        }
+
+                                        str
+                                    }
+                                    value => {
+                                        let mut vars = HashMap::new();
+                                        vars.insert("value".to_string(), to_str(value));
 
-                                    let mut str =
                                         strfmt::strfmt(&format!("{{value{}}}", specifiers), &vars)
-                                            .map_err(map_strfmt_error_to_runtime_error)?;
-
-                                    let unit_str = q.unit().to_string();
-
-                                    if !unit_str.is_empty() {
-                                        str += " ";
-                                        str += &unit_str;
+                                            .map_err(map_strfmt_error_to_runtime_error)?
                                     }
-
-                                    str
                                 }
-                                value => {
-                                    let mut vars = HashMap::new();
-                                    vars.insert("value".to_string(), to_str(value));
-
-                                    strfmt::strfmt(&format!("{{value{}}}", specifiers), &vars)
-                                        .map_err(map_strfmt_error_to_runtime_error)?
-                                }
-                            },
-                            Value::FormatSpecifiers(None) => to_str(self.pop()),
-                            v => to_str(v),
+                            }
+                            Value::FormatSpecifiers(None) => {
+                                let value = self.pop();
+                                to_str(self.force(ctx, value)?)
+                            }
+                            v => to_str(self.force(ctx, v)?),
                         };
                         joined = part + &joined; // reverse order
                     }
@@ -988,15 +2234,58 @@ impl Vm {
                     }
                     v => self.push(v),
                 },
+                op @ (Op::ForLoop | Op::ForLoopWithAccumulator) => {
+                    let body_idx = self.read_u16() as usize;
+                    let num_captured = self.read_u16() as usize;
+
+                    let accumulator = (op == Op::ForLoopWithAccumulator).then(|| self.pop());
+                    let list = self.pop();
+                    let captured = self.stack.split_off(self.stack.len() - num_captured);
+
+                    let result = self.run_for_loop(ctx, body_idx, captured, list, accumulator)?;
+                    self.push(result);
+                }
+                Op::WhileLoop => {
+                    let condition_idx = self.read_u16() as usize;
+                    let body_idx = self.read_u16() as usize;
+                    let num_captured = self.read_u16() as usize;
+
+                    let accumulator = self.pop();
+                    let captured = self.stack.split_off(self.stack.len() - num_captured);
+
+                    let result =
+                        self.run_while_loop(ctx, condition_idx, body_idx, captured, accumulator)?;
+                    self.push(result);
+                }
+                op @ (Op::TryCatch | Op::TryCatchWithMessage) => {
+                    let expr_idx = self.read_u16() as usize;
+                    let fallback_idx = self.read_u16() as usize;
+                    let num_captured = self.read_u16() as usize;
+
+                    let captured = self.stack.split_off(self.stack.len() - num_captured);
+
+                    let result = self.run_try_catch(
+                        ctx,
+                        expr_idx,
+                        fallback_idx,
+                        captured,
+                        op == Op::TryCatchWithMessage,
+                    )?;
+                    self.push(result);
+                }
                 Op::Return => {
                     if self.frames.len() == 1 {
                         let return_value = self.pop();
+                        let return_value = self.force(ctx, return_value)?;
 
-                        self.last_result = Some(return_value.clone());
+                        self.results.push(return_value.clone());
 
                         result_last_statement = Some(return_value);
                     } else {
                         let discarded_frame = self.frames.pop().unwrap();
+                        if let Some(profiler) = &mut self.profiler {
+                            profiler.exit();
+                        }
 
                         // Remember the return value which is currently on top of the stack
                         let return_value = self.stack.pop().unwrap();
@@ -1022,7 +2311,8 @@ impl Vm {
                     let mut content = Vec::with_capacity(num_args as usize);
 
                     for _ in 0..num_args {
-                        content.push(self.pop());
+                        let value = self.pop();
+                        content.push(self.force(ctx, value)?);
                     }
 
                     self.stack.push(Value::StructInstance(struct_info, content));
@@ -1035,12 +2325,116 @@ impl Vm {
                     let value = fields.swap_remove(field_idx as usize);
                     self.stack.push(value);
                 }
+                Op::UpdateStructField => {
+                    let field_idx = self.read_u16();
+
+                    let value = self.pop();
+                    let value = self.force(ctx, value)?;
+                    let Value::StructInstance(info, mut fields) = self.pop() else {
+                        panic!("Expected value to be a struct");
+                    };
+
+                    fields[field_idx as usize] = value;
+                    self.stack.push(Value::StructInstance(info, fields));
+                }
                 Op::BuildList => {
                     let length = self.read_u16();
                     let mut list = NumbatList::with_capacity(length as usize);
 
                     for _ in 0..length {
-                        list.push_front(self.pop());
+                        let value = self.pop();
+                        list.push_front(self.force(ctx, value)?);
+                    }
+
+                    self.stack.push(list.into());
+                }
+                Op::DestructureList => {
+                    let length = self.read_u16() as usize;
+
+                    let list = self.pop();
+                    let list = self.force(ctx, list)?.unsafe_as_list();
+                    if list.len() != length {
+                        return Err(RuntimeError::ListLengthMismatch {
+                            expected: length,
+                            actual: list.len(),
+                        });
+                    }
+
+                    self.stack.extend(list.iter().cloned());
+                }
+                Op::DefineUnitSystem => {
+                    let name = self.read_string_constant();
+                    let num_units = self.read_u16();
+
+                    let mut units = VecDeque::with_capacity(num_units as usize);
+                    for _ in 0..num_units {
+                        units.push_front(self.pop_quantity().unit().clone());
+                    }
+
+                    self.unit_registry
+                        .add_unit_system(Symbol::new(&name), units.into());
+                }
+                Op::ConvertToUnitSystem => {
+                    let name = self.read_string_constant();
+                    let quantity = self.pop_quantity();
+
+                    let converted = self
+                        .unit_registry
+                        .convert_to_unit_system(Symbol::new(&name), &quantity)
+                        .map_err(RuntimeError::UnitRegistryError)?;
+                    self.push_quantity(converted);
+                }
+                Op::ConvertToUnitList => {
+                    let num_units = self.read_u16() as usize;
+
+                    let mut units = VecDeque::with_capacity(num_units);
+                    for _ in 0..num_units {
+                        units.push_front(self.pop_quantity().unit().clone());
+                    }
+                    let quantity = self.pop_quantity();
+
+                    // Negative quantities are split like their absolute value,
+                    // with every component negated. The remainder is kept in the
+                    // unit of the original quantity to avoid rounding errors.
+                    let original_unit = quantity.unit().clone();
+                    let sign = quantity.unsafe_value().to_f64().signum();
+                    let mut remainder = quantity.unsafe_value().to_f64().abs();
+                    let mut list = NumbatList::with_capacity(num_units);
+                    for (i, unit) in units.iter().enumerate() {
+                        let value = self
+                            .conversion_cache
+                            .convert(
+                                &Quantity::new(Number::from_f64(remainder), original_unit.clone()),
+                                unit,
+                            )
+                            .map_err(RuntimeError::QuantityError)?
+                            .unsafe_value()
+                            .to_f64();
+
+                        let component = if i + 1 == num_units {
+                            value
+                        } else {
+                            // Avoid splitting `1.5 h` into `1 h + 29 min + 60 s`
+                            let rounded = value.round();
+                            if (value - rounded).abs() < 1e-10 * value.abs().max(1.0) {
+                                rounded
+                            } else {
+                                value.trunc()
+                            }
+                        };
+                        let component = Quantity::new(Number::from_f64(component), unit.clone());
+
+                        let used = self
+                            .conversion_cache
+                            .convert(&component, &original_unit)
+                            .map_err(RuntimeError::QuantityError)?;
+                        remainder = (remainder - used.unsafe_value().to_f64()).max(0.0);
+
+                        list.push_back(Value::Quantity(if sign < 0.0 {
+                            -component
+                        } else {
+                            component
+                        }));
                     }
 
                     self.stack.push(list.into());
@@ -1048,13 +2442,440 @@ impl Vm {
             }
         }
 
-        if let Some(value) = result_last_statement {
-            Ok(InterpreterResult::Value(value))
-        } else {
-            Ok(InterpreterResult::Continue)
+        Ok(result_last_statement)
+    }
+
+    /// Call a function value with the given arguments and return the result. This
+    /// is used by native functions like `map` that take functions as arguments.
+    fn call_function(
+        &mut self,
+        ctx: &mut ExecutionContext,
+        function: &Value,
+        args: Vec<Value>,
+    ) -> Result<Value> {
+        match function.clone().unsafe_as_function_reference() {
+            FunctionReference::Normal(ref name) => {
+                let function_idx = self.get_function_idx(name) as usize;
+                self.call_bytecode_function(ctx, function_idx, args)
+            }
+            FunctionReference::Foreign(ref name) => {
+                let function_idx = self.function_reference_idx(name)?;
+
+                self.profile_foreign(function_idx, |vm| {
+                    vm.call_foreign_function(ctx, function_idx, args.into())
+                })
+            }
+            FunctionReference::TzConversion(tz_name) => {
+                let dt = args.into_iter().next().unwrap().unsafe_as_datetime();
+                Self::convert_timezone(&tz_name, dt)
+            }
+            FunctionReference::Partial(function, bound) => self.call_function(
+                ctx,
+                &Value::FunctionReference(*function),
+                FunctionReference::bind_arguments(bound, args),
+            ),
+        }
+    }
+
+    /// Run the function with the given index until it returns, and return its result.
+    fn call_bytecode_function(
+        &mut self,
+        ctx: &mut ExecutionContext,
+        function_idx: usize,
+        args: impl IntoIterator<Item = Value>,
+    ) -> Result<Value> {
+        let depth = self.frames.len();
+        let fp = self.stack.len();
+
+        self.stack.extend(args);
+        if let Some(profiler) = &mut self.profiler {
+            profiler.enter(profile_name(&self.bytecode[function_idx].0), false);
+        }
+        self.frames.push(CallFrame {
+            function_idx,
+            ip: 0,
+            fp,
+        });
+
+        self.execute(ctx, Some(depth))?;
+
+        Ok(self.pop())
+    }
+
+    /// Run a `for` loop (see [`Op::ForLoop`]). Returns the list of all results of
+    /// the body, or the final value of the accumulator, if there is one.
+    fn run_for_loop(
+        &mut self,
+        ctx: &mut ExecutionContext,
+        body_idx: usize,
+        captured: Vec<Value>,
+        list: Value,
+        accumulator: Option<Value>,
+    ) -> Result<Value> {
+        let list = self.force(ctx, list)?.unsafe_as_list();
+
+        let Some(mut accumulator) = accumulator else {
+            let mut results = NumbatList::with_capacity(list.len());
+            for element in list.iter() {
+                let args = captured.iter().cloned().chain([element.clone()]);
+                let result = self.call_bytecode_function(ctx, body_idx, args)?;
+                results.push_back(self.force(ctx, result)?);
+            }
+            return Ok(results.into());
+        };
+
+        for element in list.iter() {
+            let args = captured
+                .iter()
+                .cloned()
+                .chain([element.clone(), accumulator]);
+            accumulator = self.call_bytecode_function(ctx, body_idx, args)?;
+        }
+        self.force(ctx, accumulator)
+    }
+
+    /// Run a `while` loop (see [`Op::WhileLoop`]) and return the final value of
+    /// the accumulator.
+    fn run_while_loop(
+        &mut self,
+        ctx: &mut ExecutionContext,
+        condition_idx: usize,
+        body_idx: usize,
+        captured: Vec<Value>,
+        mut accumulator: Value,
+    ) -> Result<Value> {
+        let mut iterations = 0;
+        loop {
+            let args = captured.iter().cloned().chain([accumulator.clone()]);
+            if !self
+                .call_bytecode_function(ctx, condition_idx, args)?
+                .unsafe_as_bool()
+            {
+                break;
+            }
+
+            if iterations == self.max_loop_iterations {
+                return Err(RuntimeError::LoopIterationLimitExceeded(
+                    self.max_loop_iterations,
+                ));
+            }
+            iterations += 1;
+
+            let args = captured.iter().cloned().chain([accumulator]);
+            accumulator = self.call_bytecode_function(ctx, body_idx, args)?;
+        }
+        self.force(ctx, accumulator)
+    }
+
+    /// Run a `try`-`catch` expression (see [`Op::TryCatch`]). If the expression
+    /// fails, the call stack is unwound to where it was before, and the fallback
+    /// is evaluated instead.
+    fn run_try_catch(
+        &mut self,
+        ctx: &mut ExecutionContext,
+        expr_idx: usize,
+        fallback_idx: usize,
+        captured: Vec<Value>,
+        pass_message: bool,
+    ) -> Result<Value> {
+        let depth = self.frames.len();
+        let stack_len = self.stack.len();
+        let profiler_depth = self.profiler.as_ref().map(|p| p.depth());
+
+        let result = self
+            .call_bytecode_function(ctx, expr_idx, captured.iter().cloned())
+            .and_then(|value| self.force(ctx, value));
+
+        let error = match result {
+            Err(error) if error.is_catchable() => error,
+            result => return result,
+        };
+
+        self.frames.truncate(depth);
+        self.stack.truncate(stack_len);
+        if let (Some(profiler), Some(profiler_depth)) = (&mut self.profiler, profiler_depth) {
+            profiler.unwind_to(profiler_depth);
+        }
+
+        let message = pass_message.then(|| Value::String(error.message()));
+        let result =
+            self.call_bytecode_function(ctx, fallback_idx, captured.into_iter().chain(message))?;
+        self.force(ctx, result)
+    }
+
+    /// Run a call to a foreign function, recording it if profiling is enabled.
+    fn profile_foreign<T>(&mut self, function_idx: usize, call: impl FnOnce(&mut Self) -> T) -> T {
+        if let Some(profiler) = &mut self.profiler {
+            profiler.enter(&self.ffi_callables[function_idx].name, true);
+        }
+        let result = call(self);
+        if let Some(profiler) = &mut self.profiler {
+            profiler.exit();
+        }
+        result
+    }
+
+    fn call_foreign_function(
+        &mut self,
+        ctx: &mut ExecutionContext,
+        function_idx: usize,
+        args: VecDeque<Value>,
+    ) -> Result<Value> {
+        let foreign_function = self.ffi_callables[function_idx].clone();
+        match &foreign_function.callable {
+            Callable::Function(function) => {
+                let args = self.force_args(ctx, args)?;
+                (function)(args)
+            }
+            Callable::ClockFunction(function) => {
+                let args = self.force_args(ctx, args)?;
+                (function)(self.current_time()?, args)
+            }
+            Callable::UnitFunction(function) => {
+                let args = self.force_args(ctx, args)?;
+                (function)(self, args)
+            }
+            Callable::RegexFunction(function) => {
+                let args = self.force_args(ctx, args)?;
+                (function)(&mut self.regex_cache, args)
+            }
+            Callable::ScriptFunction(function) => {
+                let args = self.force_args(ctx, args)?;
+                (function)(&self.script_arguments, args)
+            }
+            Callable::SequenceFunction(function) => {
+                function(&mut VmFunctionCaller { vm: self, ctx }, args)
+            }
+            Callable::TypedFunction(_) | Callable::TypedSequenceFunction(_) => {
+                self.call_typed_function(ctx, function_idx, args, None)
+            }
+            Callable::Native(callback, return_type) => self.call_native_function(
+                ctx,
+                &foreign_function.name,
+                &**callback,
+                args,
+                None,
+                return_type.as_ref(),
+            ),
+            Callable::Procedure(..) | Callable::FileProcedure(..) => {
+                unreachable!("Foreign procedures can not be called as functions")
+            }
+        }
+    }
+
+    /// Call a function of the embedding application and make sure that it returns
+    /// a value of the expected type.
+    fn call_native_function(
+        &mut self,
+        ctx: &mut ExecutionContext,
+        name: &str,
+        callback: &NativeCallback,
+        args: VecDeque<Value>,
+        span: Option<Span>,
+        return_type: Option<&Type>,
+    ) -> Result<Value> {
+        let args = self.force_args(ctx, args)?;
+        let value = callback(args.into())
+            .map_err(|message| RuntimeError::NativeFunctionFailed(span, name.into(), message))?;
+
+        match return_type {
+            Some(type_) if !self.has_type(&value, type_) => {
+                Err(RuntimeError::NativeFunctionReturnedWrongType(
+                    span,
+                    name.into(),
+                    value,
+                    type_.to_string(),
+                ))
+            }
+            _ => Ok(value),
+        }
+    }
+
+    /// Call a typed function. Its result type is `None` if it is not known at the
+    /// call site, e.g. when it is called through a function reference.
+    fn call_typed_function(
+        &mut self,
+        ctx: &mut ExecutionContext,
+        function_idx: usize,
+        args: VecDeque<Value>,
+        result_type: Option<&Type>,
+    ) -> Result<Value> {
+        let foreign_function = self.ffi_callables[function_idx].clone();
+        let function = match foreign_function.callable {
+            Callable::TypedFunction(function) => function,
+            Callable::TypedSequenceFunction(function) => {
+                return function(&mut VmFunctionCaller { vm: self, ctx }, result_type, args);
+            }
+            _ => unreachable!("Only typed functions are called here"),
+        };
+        let Some(result_type) = result_type else {
+            return Err(RuntimeError::UnknownResultType(
+                foreign_function.name.clone(),
+            ));
+        };
+
+        let args = self.force_args(ctx, args)?;
+        function(self, result_type, args)
+    }
+
+    /// Check the (outermost) type of a value that is not produced by Numbat code.
+    fn has_type(&self, value: &Value, type_: &Type) -> bool {
+        match (type_, value) {
+            (Type::Dimension(dtype), Value::Quantity(_) | Value::Duration(_))
+                if !dtype.type_variables(true).is_empty() =>
+            {
+                true
+            }
+            (Type::Dimension(dtype), value @ (Value::Quantity(_) | Value::Duration(_))) => {
+                let q = value.clone().unsafe_as_quantity();
+                self.dimension_of(q.unit()).map_or(true, |d| {
+                    d.to_base_representation() == dtype.to_base_representation()
+                })
+            }
+            (Type::Struct(info), Value::StructInstance(instance_info, _)) => {
+                info.name == instance_info.name
+            }
+            (Type::Boolean, Value::Boolean(_))
+            | (Type::String, Value::String(_))
+            | (Type::DateTime, Value::DateTime(_))
+            | (Type::Fn(..), Value::FunctionReference(_))
+            | (Type::List(_), Value::List(_) | Value::Sequence(_))
+            | (Type::TVar(_) | Type::TPar(_) | Type::Dynamic, _) => true,
+            _ => false,
+        }
+    }
+
+    /// Like [`Self::has_type`], but also checks the elements of lists.
+    fn has_dynamic_type(&self, value: &Value, type_: &Type) -> bool {
+        match (type_, value) {
+            (Type::List(element_type), Value::List(elements)) => elements
+                .iter()
+                .all(|element| self.has_dynamic_type(element, element_type)),
+            _ => self.has_type(value, type_),
         }
     }
 
+    fn force_args(
+        &mut self,
+        ctx: &mut ExecutionContext,
+        args: VecDeque<Value>,
+    ) -> Result<VecDeque<Value>> {
+        args.into_iter().map(|arg| self.force(ctx, arg)).collect()
+    }
+
+    fn convert_timezone(tz_name: &str, dt: jiff::Zoned) -> Result<Value> {
+        let tz = jiff::tz::TimeZone::get(tz_name)
+            .map_err(|_| RuntimeError::UnknownTimezone(tz_name.into()))?;
+
+        Ok(Value::DateTime(dt.with_time_zone(tz)))
+    }
+
+    /// Turn a lazy sequence into a list. All other values are returned unchanged.
+    fn force(&mut self, ctx: &mut ExecutionContext, value: Value) -> Result<Value> {
+        sequence::force_value(value, &mut VmFunctionCaller { vm: self, ctx })
+    }
+
+    /// Call `function` on all elements of the list, distributed over a number of
+    /// worker threads. Each worker operates on its own copy of the virtual machine.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn parallel_map(
+        &mut self,
+        function: &Value,
+        list: &NumbatList<Value>,
+    ) -> Option<Result<Vec<Value>>> {
+        use rayon::prelude::*;
+
+        // Calls on other threads would not be recorded by the profiler
+        if !self.is_pure(function) || self.profiler.is_some() {
+            return None;
+        }
+        let pool = self.thread_pool(list.len())?;
+
+        let elements: Vec<&Value> = list.iter().collect();
+        let chunk_size = elements.len().div_ceil(self.num_threads);
+        let vm: &Vm = self;
+
+        let partial_results: Vec<Result<Vec<Value>>> = pool.install(|| {
+            elements
+                .par_chunks(chunk_size)
+                .map(|chunk| {
+                    let mut worker = vm.clone();
+                    worker.num_threads = 1;
+
+                    // Pure functions do not print anything
+                    let mut ctx = ExecutionContext {
+                        output: &mut NullSink,
+                    };
+
+                    chunk
+                        .iter()
+                        .map(|&element| {
+                            let value =
+                                worker.call_function(&mut ctx, function, vec![element.clone()])?;
+                            worker.force(&mut ctx, value)
+                        })
+                        .collect()
+                })
+                .collect()
+        });
+
+        // Report the first error in list order, like the sequential evaluation would
+        let mut results = Vec::with_capacity(elements.len());
+        for partial_result in partial_results {
+            match partial_result {
+                Ok(values) => results.extend(values),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        Some(Ok(results))
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn parallel_reduce(
+        &mut self,
+        list: &NumbatList<Value>,
+        reduce: sequence::Reduce,
+    ) -> Option<Result<Value>> {
+        use rayon::prelude::*;
+
+        let pool = self.thread_pool(list.len())?;
+
+        let elements: Vec<&Value> = list.iter().collect();
+        let chunk_size = elements.len().div_ceil(self.num_threads);
+
+        let partial_results: Vec<Result<Value>> = pool.install(|| {
+            elements
+                .par_chunks(chunk_size)
+                .map(|chunk| reduce(&mut chunk.iter().map(|&element| element.clone())))
+                .collect()
+        });
+
+        Some(
+            partial_results
+                .into_iter()
+                .collect::<Result<Vec<_>>>()
+                .and_then(|partial_results| reduce(&mut partial_results.into_iter())),
+        )
+    }
+
+    /// Get the thread pool for processing a list of the given length in parallel,
+    /// or `None` if the list should be processed sequentially.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn thread_pool(&mut self, len: usize) -> Option<Arc<rayon::ThreadPool>> {
+        if self.num_threads <= 1 || len < PARALLEL_EVALUATION_THRESHOLD {
+            return None;
+        }
+
+        if self.thread_pool.is_none() {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(self.num_threads)
+                .build()
+                .ok()?;
+            self.thread_pool = Some(Arc::new(pool));
+        }
+        self.thread_pool.clone()
+    }
+
     pub fn debug(&self) {
         if !self.debug {
             return;
@@ -1082,7 +2903,62 @@ impl Vm {
     }
 
     fn print(&self, ctx: &mut ExecutionContext, m: &Markup) {
-        (ctx.print_fn)(m);
+        ctx.output.print(m);
+    }
+}
+
+impl UnitLookup for Vm {
+    /// Finds metric and binary prefixes where they are allowed (`km`,
+    /// `kilometer`, `KiB`).
+    fn lookup_unit(&self, name: &str) -> Option<Unit> {
+        if let Some(named_unit) = self.unit_names.get(name) {
+            return Some(named_unit.unit.clone());
+        }
+
+        PrefixParser::prefixes()
+            .iter()
+            .find_map(|(prefix_long, prefixes_short, prefix)| {
+                let accepts_prefix = |named_unit: &&NamedUnit| {
+                    prefix.is_metric() && named_unit.metric_prefixes
+                        || prefix.is_binary() && named_unit.binary_prefixes
+                };
+
+                let long = name
+                    .strip_prefix(prefix_long)
+                    .and_then(|rest| self.unit_names.get(rest))
+                    .filter(|named_unit| named_unit.accepts_prefix.long)
+                    .filter(accepts_prefix);
+                let short = || {
+                    prefixes_short.iter().find_map(|prefix_short| {
+                        name.strip_prefix(prefix_short)
+                            .and_then(|rest| self.unit_names.get(rest))
+                            .filter(|named_unit| named_unit.accepts_prefix.short)
+                            .filter(accepts_prefix)
+                    })
+                };
+
+                long.or_else(short)
+                    .map(|named_unit| named_unit.unit.clone().with_prefix(*prefix))
+            })
+    }
+
+    fn dimension_of(&self, unit: &Unit) -> Option<DType> {
+        let (base_unit, _) = unit.to_base_unit_representation();
+        base_unit.iter().try_fold(DType::scalar(), |dtype, factor| {
+            let (_, metadata) = self
+                .unit_registry
+                .inner
+                .get_base_representation(factor.unit_id.name)
+                .ok()?;
+            match metadata.type_ {
+                Type::Dimension(d) => Some(dtype.multiply(&d.power(factor.exponent))),
+                _ => None,
+            }
+        })
+    }
+
+    fn unit_registry(&self) -> &UnitRegistry {
+        &self.unit_registry
     }
 }
 
@@ -1097,9 +2973,8 @@ fn vm_basic() {
     vm.add_op(Op::Add);
     vm.add_op(Op::Return);
 
-    let mut print_fn = |_: &Markup| {};
     let mut ctx = ExecutionContext {
-        print_fn: &mut print_fn,
+        output: &mut NullSink,
     };
 
     assert_eq!(
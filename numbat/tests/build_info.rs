@@ -0,0 +1,63 @@
+use numbat::build_info::{BuildInfo, ExchangeRatesStatus};
+use numbat::markup::{Formatter, PlainTextFormatter};
+
+#[test]
+fn features() {
+    let info = BuildInfo::collect();
+    assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(info.prelude_fingerprint.len(), 16);
+
+    let enabled = |name| {
+        info.features
+            .iter()
+            .find(|f| f.name == name)
+            .unwrap()
+            .enabled
+    };
+    assert_eq!(
+        enabled("fetch-exchangerates"),
+        cfg!(feature = "fetch-exchangerates")
+    );
+    assert_eq!(enabled("currency"), cfg!(feature = "currency"));
+    assert_eq!(enabled("datetime"), cfg!(feature = "datetime"));
+    assert_eq!(enabled("fs"), cfg!(feature = "fs"));
+    assert_eq!(enabled("html-formatter"), cfg!(feature = "html-formatter"));
+    assert_eq!(enabled("codata"), cfg!(feature = "codata"));
+
+    let text = PlainTextFormatter {}.format(&info.to_markup(), false);
+    for feature in &info.features {
+        let sign = if feature.enabled { '+' } else { '-' };
+        assert!(text.contains(&format!("{sign}{}", feature.name)));
+    }
+}
+
+#[cfg(feature = "currency")]
+#[test]
+fn exchange_rates() {
+    use numbat::Context;
+
+    assert_eq!(
+        BuildInfo::collect().exchange_rates,
+        ExchangeRatesStatus::NotLoaded
+    );
+
+    Context::set_exchange_rates(
+        r#"<Cube><Cube time="2024-01-01">
+             <Cube currency="USD" rate="1.1"/>
+             <Cube currency="JPY" rate="160"/>
+           </Cube></Cube>"#,
+    );
+    assert_eq!(
+        BuildInfo::collect().exchange_rates,
+        ExchangeRatesStatus::Loaded { currencies: 2 }
+    );
+}
+
+#[cfg(not(feature = "currency"))]
+#[test]
+fn exchange_rates_without_currency_support() {
+    assert_eq!(
+        BuildInfo::collect().exchange_rates,
+        ExchangeRatesStatus::Unavailable
+    );
+}
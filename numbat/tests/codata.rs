@@ -0,0 +1,125 @@
+mod common;
+
+use common::{get_test_context, get_test_context_without_prelude};
+use numbat::{
+    codata, pretty_print::PrettyPrint, resolver::CodeSource, value::Value, Context,
+    InterpreterResult,
+};
+
+const DATA: &str = include_str!("../data/codata.txt");
+const MODULE: &str = include_str!("../modules/physics/codata.nbt");
+
+fn generated_module() -> String {
+    codata::generate(DATA, &get_test_context()).unwrap()
+}
+
+#[track_caller]
+fn expect_output(ctx: &mut Context, code: &str, expected_output: &str) {
+    let (_, result) = ctx.interpret(code, CodeSource::Internal).unwrap();
+    let InterpreterResult::Value(value) = result else {
+        panic!("'{code}' did not return a value");
+    };
+    assert_eq!(value.pretty_print().to_string().trim(), expected_output);
+}
+
+#[test]
+fn generated_module_is_up_to_date() {
+    assert!(
+        generated_module() == MODULE,
+        "modules/physics/codata.nbt is outdated, run `cargo run --features codata --bin numbat-codata > modules/physics/codata.nbt`"
+    );
+}
+
+#[test]
+fn generated_module_typechecks() {
+    let mut ctx = get_test_context_without_prelude();
+    let (statements, _) = ctx
+        .interpret(&generated_module(), CodeSource::Internal)
+        .unwrap();
+    assert!(!statements.is_empty());
+
+    // Dimensions
+    for (code, dimension) in [
+        ("speed_of_light", "Velocity"),
+        ("gravitational_constant", "Force × Length^2 / Mass^2"),
+        ("ℏ", "Action"),
+        ("electric_constant", "ElectricPermittivity"),
+        ("rydberg_constant", "Wavenumber"),
+        ("von_klitzing_constant", "ElectricResistance"),
+    ] {
+        let _ = ctx
+            .interpret(
+                &format!("let _check: {dimension} = {code}"),
+                CodeSource::Internal,
+            )
+            .unwrap_or_else(|e| panic!("{code} is not a {dimension}: {e}"));
+    }
+
+    // Values as given in the data file
+    expect_output(&mut ctx, "c", "299_792_458 m/s");
+    expect_output(&mut ctx, "G", "6.6743e-11 m³/(kg·s²)");
+    expect_output(&mut ctx, "electron_mass", "9.10938e-31 kg");
+    expect_output(&mut ctx, "α", "0.00729735");
+    expect_output(&mut ctx, "N_A", "6.02214e+23 mol⁻¹");
+
+    // Truncated exact values are computed from other constants
+    for (code, truncated) in [
+        ("ℏ / (J s)", 1.054_571_817e-34),
+        (
+            "stefan_boltzmann_constant / (W m^-2 K^-4)",
+            5.670_374_419e-8,
+        ),
+        ("gas_constant / (J / (mol K))", 8.314_462_618),
+        ("faraday_constant / (C / mol)", 96_485.332_12),
+        ("von_klitzing_constant / Ω", 25_812.807_45),
+    ] {
+        let (_, result) = ctx.interpret(code, CodeSource::Internal).unwrap();
+        let InterpreterResult::Value(Value::Quantity(value)) = result else {
+            panic!("'{code}' did not return a quantity");
+        };
+        let value = value.unsafe_value().to_f64();
+        assert!(
+            ((value - truncated) / truncated).abs() < 1e-9,
+            "{code} = {value}, expected {truncated}"
+        );
+    }
+
+    // ... and keep the units in which they were shown before
+    expect_output(&mut ctx, "ℏ", "1.05457e-34 J/Hz");
+    expect_output(
+        &mut ctx,
+        "stefan_boltzmann_constant",
+        "5.67037e-8 J/(K⁴·m²·s)",
+    );
+    expect_output(&mut ctx, "gas_constant", "8.31446 J/(K·mol)");
+    expect_output(&mut ctx, "faraday_constant", "96485.3 C/mol");
+    expect_output(&mut ctx, "von_klitzing_constant", "25812.8 Ω");
+}
+
+#[test]
+fn uncertainties() {
+    let mut ctx = get_test_context();
+    let info = |ctx: &mut Context, keyword: &str| ctx.print_info_for_keyword(keyword).to_string();
+
+    assert!(info(&mut ctx, "G").contains("Uncertainty: 1.5e-15 m³/(kg·s²)"));
+    assert!(info(&mut ctx, "c").contains("Uncertainty: exact"));
+    assert!(!info(&mut ctx, "pi").contains("Uncertainty"));
+}
+
+#[test]
+fn unknown_units_are_reported() {
+    let data = "\
+-----
+furlong constant                                            1.0                      0.1                      furlong
+speed of light in vacuum                                    299 792 458              (exact)                  smoot_xyz s^-1
+";
+    let errors = codata::generate(data, &get_test_context()).unwrap_err();
+
+    assert!(errors.contains(&codata::CodataError::UnknownUnit {
+        constant: "speed of light in vacuum".into(),
+        unit: "smoot_xyz s^-1".into()
+    }));
+    assert!(errors.contains(&codata::CodataError::UnknownConstant(
+        "Newtonian constant of gravitation".into()
+    )));
+}
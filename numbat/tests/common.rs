@@ -1,8 +1,12 @@
+#[cfg(feature = "fs")]
 use std::path::Path;
 
-use numbat::{module_importer::FileSystemImporter, resolver::CodeSource, Context, NumbatError};
+#[cfg(feature = "fs")]
+use numbat::module_importer::FileSystemImporter;
+use numbat::{resolver::CodeSource, Context, NumbatError};
 use once_cell::sync::Lazy;
 
+#[cfg(feature = "fs")]
 pub fn get_test_context_without_prelude() -> Context {
     let module_path = Path::new(
         &std::env::var_os("CARGO_MANIFEST_DIR")
@@ -16,6 +20,11 @@ pub fn get_test_context_without_prelude() -> Context {
     Context::new(importer)
 }
 
+#[cfg(not(feature = "fs"))]
+pub fn get_test_context_without_prelude() -> Context {
+    Context::new(numbat::module_importer::BuiltinModuleImporter::default())
+}
+
 pub fn get_test_context() -> Context {
     static CONTEXT: Lazy<Result<Context, NumbatError>> = Lazy::new(|| {
         let mut context = get_test_context_without_prelude();
@@ -0,0 +1,138 @@
+mod common;
+
+use common::get_test_context;
+use numbat::dependencies::{Definition, DefinitionKind, Dependency};
+use numbat::resolver::CodeSource;
+use numbat::Context;
+
+fn run(ctx: &mut Context, code: &str) {
+    let _ = ctx.interpret(code, CodeSource::Text).unwrap();
+}
+
+fn dependency(name: &str, kind: DefinitionKind, statement: usize) -> Dependency {
+    Dependency {
+        name: name.into(),
+        kind,
+        statement,
+    }
+}
+
+#[test]
+fn chain_of_definitions() {
+    let mut ctx = get_test_context();
+    run(&mut ctx, "let a = 2 m");
+    run(&mut ctx, "let b = 3 a");
+    run(&mut ctx, "let c = b + a");
+
+    let graph = ctx.dependency_graph();
+    assert_eq!(graph.nodes.len(), 3);
+    assert_eq!(graph.nodes[1].code, "let b: Length = 3 a");
+    assert_eq!(
+        graph.nodes[2].definitions,
+        [Definition {
+            name: "c".into(),
+            kind: DefinitionKind::Variable
+        }]
+    );
+    assert_eq!(
+        graph.nodes[2].dependencies,
+        [
+            dependency("b", DefinitionKind::Variable, 1),
+            dependency("a", DefinitionKind::Variable, 0)
+        ]
+    );
+    assert_eq!(graph.edges, [(0, 1), (1, 2), (0, 2)]);
+    assert_eq!(graph.dependents(0), [1, 2]);
+    assert_eq!(graph.dependents(2), [] as [usize; 0]);
+
+    // Prelude definitions like 'm' are not included
+    assert!(graph.nodes[0].dependencies.is_empty());
+
+    let program = ctx.check("c^2", CodeSource::Text).unwrap();
+    assert_eq!(
+        ctx.dependencies_of(program.last().unwrap()),
+        [dependency("c", DefinitionKind::Variable, 2)]
+    );
+}
+
+#[test]
+fn function_using_a_unit() {
+    let mut ctx = get_test_context();
+    run(
+        &mut ctx,
+        "@aliases(bathtubs)
+         unit bathtub = 150 L",
+    );
+    run(
+        &mut ctx,
+        "fn fill_rate(t: Time) -> Volume / Time = 2 bathtubs / t",
+    );
+    run(&mut ctx, "fill_rate(1 hour) -> bathtub/min");
+
+    let graph = ctx.dependency_graph();
+    assert_eq!(
+        graph.nodes[0].definitions,
+        [
+            Definition {
+                name: "bathtub".into(),
+                kind: DefinitionKind::Unit
+            },
+            Definition {
+                name: "bathtubs".into(),
+                kind: DefinitionKind::Unit
+            }
+        ]
+    );
+    assert_eq!(
+        graph.nodes[1].dependencies,
+        [dependency("bathtub", DefinitionKind::Unit, 0)]
+    );
+    assert_eq!(
+        graph.nodes[2].dependencies,
+        [
+            dependency("fill_rate", DefinitionKind::Function, 1),
+            dependency("bathtub", DefinitionKind::Unit, 0)
+        ]
+    );
+    assert_eq!(graph.dependents(0), [1, 2]);
+}
+
+#[test]
+fn redefinitions() {
+    let mut ctx = get_test_context();
+    run(&mut ctx, "let a = 1");
+    run(&mut ctx, "let b = a + 1");
+    run(&mut ctx, "let c = 2 b");
+
+    // Changing the middle definition invalidates everything that uses it
+    assert_eq!(ctx.dependency_graph().dependents(1), [2]);
+
+    run(&mut ctx, "let b = 10");
+    run(&mut ctx, "let total = c + b");
+    let graph = ctx.dependency_graph();
+    assert!(graph.nodes[3].dependencies.is_empty());
+    assert_eq!(
+        graph.nodes[4].dependencies,
+        [
+            dependency("c", DefinitionKind::Variable, 2),
+            dependency("b", DefinitionKind::Variable, 3)
+        ]
+    );
+    assert_eq!(graph.dependents(1), [2, 4]);
+    assert_eq!(graph.dependents(3), [4]);
+
+    // A definition can refer to the previous definition of the same name
+    run(&mut ctx, "let a = a + 1");
+    let graph = ctx.dependency_graph();
+    assert_eq!(
+        graph.nodes[5].dependencies,
+        [dependency("a", DefinitionKind::Variable, 0)]
+    );
+
+    // Undone statements are removed from the graph
+    ctx.set_undo_limit(10);
+    run(&mut ctx, "let e = total");
+    assert_eq!(ctx.dependency_graph().nodes.len(), 7);
+    assert!(ctx.undo(1));
+    assert_eq!(ctx.dependency_graph().nodes.len(), 6);
+}
@@ -0,0 +1,93 @@
+//! The exchange rates are shared by all contexts of a process, so the different
+//! situations are tested one after another, in a test binary of their own.
+#![cfg(feature = "currency")]
+
+use std::time::{Duration, Instant, SystemTime};
+
+use numbat::module_importer::BuiltinModuleImporter;
+use numbat::output::{BufferingSink, OutputEvent};
+use numbat::resolver::CodeSource;
+use numbat::{Context, NumbatError, RuntimeError, RuntimeWarning};
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+fn rates(usd: f64) -> String {
+    format!(r#"<Cube><Cube time="2024-06-14"><Cube currency="USD" rate="{usd}"/></Cube></Cube>"#)
+}
+
+fn context() -> Context {
+    let mut ctx = Context::new(BuiltinModuleImporter::default());
+    let _ = ctx.interpret("use prelude", CodeSource::Internal).unwrap();
+    ctx.load_currency_module_on_demand(true);
+    ctx
+}
+
+/// Evaluates the code and returns the messages of all diagnostics
+fn evaluate(ctx: &mut Context, code: &str) -> Result<Vec<String>, NumbatError> {
+    let mut sink = BufferingSink::default();
+    let _ = ctx.interpret_with_sink(&mut sink, code, CodeSource::Text)?;
+    Ok(sink
+        .events()
+        .iter()
+        .filter_map(|event| match event {
+            OutputEvent::Diagnostic(diagnostic) => Some(
+                std::iter::once(diagnostic.message.clone())
+                    .chain(diagnostic.notes.iter().cloned())
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+            _ => None,
+        })
+        .collect())
+}
+
+#[test]
+fn exchange_rates() {
+    // Without cached rates, currencies can not be used if fetching fails
+    Context::set_exchange_rates_provider(|| None);
+    let mut ctx = context();
+    assert!(matches!(
+        evaluate(&mut ctx, "10 EUR -> USD"),
+        Err(NumbatError::RuntimeError(
+            RuntimeError::CouldNotLoadExchangeRates
+        ))
+    ));
+    assert!(matches!(
+        Context::refresh_exchange_rates(),
+        Err(RuntimeError::CouldNotLoadExchangeRates)
+    ));
+
+    // If fetching takes too long, the cached rates are used, with a warning
+    Context::set_exchange_rates_timeout(Duration::from_millis(100));
+    Context::set_exchange_rates_provider(|| {
+        std::thread::sleep(Duration::from_secs(5));
+        Some(rates(3.0))
+    });
+    Context::set_cached_exchange_rates(&rates(1.1), SystemTime::now() - 3 * DAY);
+
+    let start = Instant::now();
+    let Ok(Some(RuntimeWarning::CachedExchangeRates(age))) = Context::refresh_exchange_rates()
+    else {
+        panic!("Expected a warning about cached exchange rates");
+    };
+    assert!(start.elapsed() < Duration::from_secs(2));
+    assert_eq!(age.as_secs() / DAY.as_secs(), 3);
+
+    let mut ctx = context();
+    let warnings = evaluate(&mut ctx, "assert_eq(10 EUR -> USD, 11 USD, 1e-9 USD)").unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].starts_with("using cached exchange rates"));
+    assert!(warnings[0].contains("cached rates from 3 days ago"));
+    assert!(evaluate(&mut ctx, "assert(exchange_rate_age() > 71 hours)").is_ok());
+
+    // Fresh rates replace the cached ones when they have been fetched in the
+    // background, and currency units are defined again with them
+    Context::set_exchange_rates_provider(|| Some(rates(2.0)));
+    let refresh = Context::refresh_exchange_rates_in_background();
+    assert!(matches!(refresh.join().unwrap(), Ok(None)));
+
+    let warnings = evaluate(&mut ctx, "assert_eq(10 EUR -> USD, 20 USD, 1e-9 USD)").unwrap();
+    assert!(warnings.is_empty());
+    assert!(evaluate(&mut ctx, "assert(exchange_rate_age() < 1 min)").is_ok());
+    assert!(evaluate(&mut ctx, "assert_eq(1 $ -> €, 0.5 €, 1e-9 €)").is_ok());
+}
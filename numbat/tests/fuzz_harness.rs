@@ -0,0 +1,90 @@
+//! Runs the fuzz harness (see `fuzz/`) on a few pathological inputs. Crashes that
+//! have been found by fuzzing can be added here as regression tests.
+
+#[path = "../fuzz/src/lib.rs"]
+mod harness;
+
+use once_cell::sync::Lazy;
+
+use numbat::Context;
+
+static CONTEXT: Lazy<Context> = Lazy::new(harness::prelude_context);
+
+/// Run the harness on a thread with a large stack. Without optimizations, each
+/// level of nesting needs a lot more stack space than in release builds.
+fn check(inputs: Vec<Vec<u8>>) {
+    std::thread::Builder::new()
+        .stack_size(64 * 1024 * 1024)
+        .spawn(move || {
+            for input in inputs {
+                harness::check(&CONTEXT, &input);
+            }
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+}
+
+#[test]
+fn deeply_nested_inputs() {
+    let n = 100_000;
+    check(
+        [
+            "(".repeat(n),
+            "-".repeat(n),
+            "!".repeat(n),
+            "[".repeat(n),
+            "2^".repeat(n),
+            "1+".repeat(n),
+            "x ".repeat(n),
+            "f(1)".repeat(n),
+            "1 |> f".repeat(n),
+            "if true then ".repeat(n),
+            "if true then 1 else ".repeat(n),
+            "@name(\"x\") ".repeat(n),
+            format!("let {} = 1", "{".repeat(n)),
+            format!("let x: {} = 1", "List<".repeat(n)),
+            format!("let x: {}Length = 1", "(".repeat(n)),
+            format!("let x: {}Length = 1", "Length * ".repeat(n)),
+            format!("let x: Length^{}1 = 1", "-".repeat(n)),
+            format!("fn f(x: {}Scalar) = 1", "Fn[(".repeat(n)),
+        ]
+        .map(String::into_bytes)
+        .into(),
+    );
+}
+
+#[test]
+fn nesting_at_the_limit() {
+    let n = 250;
+    check(
+        [
+            format!("{}1{}", "(".repeat(n), ")".repeat(n)),
+            format!("{}1", "-".repeat(n)),
+            format!("{}1{}", "[".repeat(n), "]".repeat(n)),
+            format!("{}1", "1 + ".repeat(n)),
+            format!("{}1{}", "sqr(".repeat(n), ")".repeat(n)),
+        ]
+        .map(String::into_bytes)
+        .into(),
+    );
+}
+
+#[test]
+fn invalid_inputs() {
+    check(
+        [
+            &b"\xff\xfe"[..],
+            b"",
+            b")",
+            b"\"{",
+            b"let = ",
+            b"fn f(x: -> = ",
+            b"1 m -> ",
+            b"struct {",
+            b"use ",
+        ]
+        .map(Vec::from)
+        .into(),
+    );
+}
@@ -1,12 +1,45 @@
 mod common;
 
+use std::path::PathBuf;
+use std::sync::Arc;
+
 use common::get_test_context;
 
 use insta::assert_snapshot;
-use numbat::markup::{Formatter, PlainTextFormatter};
-use numbat::resolver::CodeSource;
-use numbat::NumbatError;
+#[cfg(feature = "datetime")]
+use numbat::clock::FixedClock;
+use numbat::diagnostic::{apply_suggestions, Applicability, ErrorDiagnostic, Severity};
+use numbat::error_code::ErrorCode;
+use numbat::file_system::InMemoryFileSystem;
+use numbat::locale::Locale;
+use numbat::markup::{Formatter, Markup, PlainTextFormatter};
+use numbat::module_importer::ModuleImporter;
+use numbat::output::{AssertionKind, BufferingSink, OutputEvent};
+use numbat::resolver::{CodeSource, ModulePath};
+use numbat::value::Value;
 use numbat::{pretty_print::PrettyPrint, Context, InterpreterResult};
+use numbat::{Diagnostic, ImplicitConversions, NumbatError, Quantity};
+
+/// Output of `print` (and similar) calls, as plain text
+fn printed(sink: &BufferingSink) -> Vec<String> {
+    sink.printed()
+        .map(|markup| PlainTextFormatter {}.format(markup, false))
+        .collect()
+}
+
+/// Every diagnostic that is reported for an error needs a registered error code
+#[track_caller]
+fn assert_has_error_codes(error: &NumbatError) {
+    let diagnostics = error.diagnostics();
+    assert!(!diagnostics.is_empty());
+    for diagnostic in diagnostics {
+        let code = diagnostic.code.as_deref();
+        assert!(
+            code.and_then(ErrorCode::from_code).is_some(),
+            "Missing or unknown error code {code:?} for error '{error}'"
+        );
+    }
+}
 
 #[track_caller]
 fn expect_output_with_context(ctx: &mut Context, code: &str, expected_output: impl AsRef<str>) {
@@ -27,7 +60,10 @@ fn fail(code: &str) -> NumbatError {
     let mut ctx = get_test_context();
     let ret = ctx.interpret(code, CodeSource::Internal);
     match ret {
-        Err(e) => e,
+        Err(e) => {
+            assert_has_error_codes(&e);
+            e
+        }
         Ok((_stmts, ret)) => {
             if let InterpreterResult::Value(val) = ret {
                 let fmt = PlainTextFormatter {};
@@ -54,7 +90,7 @@ fn expect_pretty_print(code: &str, expected_pretty_print_output: impl AsRef<str>
 
     assert_eq!(statements.len(), 1);
 
-    let statement = &statements[0];
+    let statement = &statements.statements()[0];
     assert_eq!(
         statement.pretty_print().to_string(),
         expected_pretty_print_output.as_ref()
@@ -64,6 +100,7 @@ fn expect_pretty_print(code: &str, expected_pretty_print_output: impl AsRef<str>
 #[track_caller]
 fn expect_failure_with_context(ctx: &mut Context, code: &str, msg_part: &str) {
     if let Err(e) = ctx.interpret(code, CodeSource::Internal) {
+        assert_has_error_codes(&e);
         let error_message = e.to_string();
         println!("{}", error_message);
         assert!(
@@ -99,7 +136,7 @@ fn simple_value() {
     expect_output(".0", "0");
     expect_failure("_.0", "Unexpected character in identifier: '.'");
     expect_output(".0_0", "0");
-    expect_failure(".0_", "Unexpected character in number literal: '_'");
+    expect_failure(".0_", "Number literal can not end with an underscore");
 
     expect_output("0b0", "0");
     expect_output("0b01", "1");
@@ -107,8 +144,8 @@ fn simple_value() {
     expect_failure("0b012", "Expected base-2 digit");
     expect_failure("0b", "Expected base-2 digit");
     expect_failure("0b_", "Expected base-2 digit");
-    expect_failure("0b_0", "Expected base-2 digit");
-    expect_failure("0b0_", "Expected base-2 digit");
+    expect_output("0b_0", "0");
+    expect_failure("0b0_", "Number literal can not end with an underscore");
     expect_failure("0b0.0", "Expected base-2 digit");
 
     expect_output("0o0", "0");
@@ -117,8 +154,8 @@ fn simple_value() {
     expect_failure("0o012345678", "Expected base-8 digit");
     expect_failure("0o", "Expected base-8 digit");
     expect_failure("0o_", "Expected base-8 digit");
-    expect_failure("0o_0", "Expected base-8 digit");
-    expect_failure("0o0_", "Expected base-8 digit");
+    expect_output("0o_0", "0");
+    expect_failure("0o0_", "Number literal can not end with an underscore");
     expect_failure("0o0.0", "Expected base-8 digit");
 
     expect_output("0x0", "0");
@@ -127,9 +164,11 @@ fn simple_value() {
     expect_failure("0x0123456789abcdefg", "Expected base-16 digit");
     expect_failure("0x", "Expected base-16 digit");
     expect_failure("0x_", "Expected base-16 digit");
-    expect_failure("0x_0", "Expected base-16 digit");
-    expect_failure("0x0_", "Expected base-16 digit");
-    expect_failure("0x0.0", "Expected base-16 digit");
+    expect_output("0x_0", "0");
+    expect_failure("0x0_", "Number literal can not end with an underscore");
+    expect_failure("0x0.0", "Expected binary exponent ('p')");
+    expect_failure("0x0.0p", "Expected digits in exponent");
+    expect_output("0x0.8p1", "1");
 
     expect_output("NaN", "NaN");
     expect_output("inf", "inf");
@@ -156,18 +195,104 @@ fn test_factorial() {
         "(-1)!",
         "Expected factorial argument to be a non-negative integer",
     );
-    expect_failure(
-        "1.5!",
-        "Expected factorial argument to be a finite integer number",
-    );
+    expect_failure("1.5!", "Factorial argument must be an integer (got 1.5)");
     expect_failure(
         "(-1.5)!",
-        "Expected factorial argument to be a non-negative integer",
+        "Factorial argument must be an integer (got -1.5)",
     );
     expect_failure(
         "(2m)!",
         "Argument of factorial needs to be dimensionless (got Length).",
     );
+
+    expect_output("factorial(5)", "120");
+    expect_output("factorial(20) == 2_432_902_008_176_640_000", "true");
+    expect_output("factorial(20) == 20!", "true");
+    expect_output("170! > 1e306", "true");
+    expect_failure(
+        "factorial(171)",
+        "The result of 171! is too large to be represented as a number",
+    );
+    expect_failure(
+        "171!",
+        "The result of 171! is too large to be represented as a number",
+    );
+    expect_failure(
+        "factorial(-1)",
+        "Argument of 'factorial' must not be negative (got -1)",
+    );
+}
+
+#[test]
+fn test_integer_values() {
+    // Integers are preserved by +, -, * and integer powers
+    expect_output("element_at(1 + 1, [1, 2, 3])", "3");
+    expect_output("element_at(3 - 1, [1, 2, 3])", "3");
+    expect_output("element_at(2 * 1, [1, 2, 3])", "3");
+    expect_output("element_at(-(-2), [1, 2, 3])", "3");
+    expect_output("element_at(2^1, [1, 2, 3])", "3");
+    expect_output("element_at(len([1, 2]), [1, 2, 3])", "3");
+    expect_output("element_at(mod(5, 3), [1, 2, 3])", "3");
+    expect_output("element_at(abs(-2), [1, 2, 3])", "3");
+    expect_output("element_at(element_at(2, range(0, 5)), [1, 2, 3])", "3");
+
+    // … but not by divisions or roots, even if the value is a whole number
+    expect_failure(
+        "element_at(2.5, [1, 2, 3])",
+        "List index must be an integer (got 2.5)",
+    );
+    expect_failure(
+        "element_at(4 / 2, [1, 2, 3])",
+        "List index must be an integer (got 2)",
+    );
+    expect_failure(
+        "element_at(sqrt(4), [1, 2, 3])",
+        "List index must be an integer (got 2)",
+    );
+    expect_failure(
+        "element_at(2^(-1), [1, 2, 3])",
+        "List index must be an integer (got 0.5)",
+    );
+    expect_failure("(6 / 3)!", "Factorial argument must be an integer (got 2)");
+    expect_failure(
+        "sum_series(sqr, 1, 10 / 2)",
+        "The bounds of a series need to be integers, got 5",
+    );
+
+    // Rounding turns values into integers
+    expect_output("element_at(floor(5 / 2), [1, 2, 3])", "3");
+    expect_output("element_at(round(1.6), [1, 2, 3])", "3");
+    expect_output("round(6 / 3)!", "2");
+    expect_output("median([4, 1, 3, 2])", "2.5");
+
+    // Exact integer arithmetic
+    expect_output("binomial(49, 6)", "13_983_816");
+    expect_output("binomial(5, 6)", "0");
+    expect_output("binomial(60, 30) == 118_264_581_564_861_424", "true");
+    expect_failure(
+        "binomial(2000, 1000)",
+        "The result of binomial(2000, 1000) is too large to be represented as a number",
+    );
+    expect_failure(
+        "binomial(5, 2.5)",
+        "Argument of 'binomial' must be an integer (got 2.5)",
+    );
+    expect_output("gcd(1071, 462)", "21");
+    expect_output("lcm(21, 6)", "42");
+    expect_failure(
+        "gcd(4 / 2, 1)",
+        "Argument of 'gcd' must be an integer (got 2)",
+    );
+    expect_output("gcd(2^60, 2^59) == 2^59", "true");
+    expect_output("lcm(2^60, 2^59) == 2^60", "true");
+    expect_failure(
+        "gcd(3^40, 3)",
+        "Argument of 'gcd' must be an integer (got 12157665459056928768)",
+    );
+    expect_failure(
+        "gcd(2^53 + 1, 2)",
+        "Argument of 'gcd' must be an integer (got 9007199254740992)",
+    );
 }
 
 #[test]
@@ -198,6 +323,105 @@ fn test_conversions() {
     expect_output("55! / (6! (55 - 6)!) -> million", "28.9897 million");
 }
 
+#[test]
+fn test_conversion_targets() {
+    // chained conversions are applied from left to right
+    expect_output("1 km -> m -> cm", "100_000 cm");
+    expect_output("1 mile -> km to m", "1609.34 m");
+    expect_output("255 -> hex -> str_length", "4");
+
+    // lists of units
+    expect_output("5.5 ft -> [ft, inch]", "[5 ft, 6 in]");
+    expect_output("-5.5 ft -> [ft, inch]", "[-5 ft, -6 in]");
+    expect_output("1.5 hour -> [hour, minute, second]", "[1 h, 30 min, 0 s]");
+    expect_output("100 min -> [hour, minute]", "[1 h, 40 min]");
+    expect_output("2 m -> [cm]", "[200 cm]");
+    assert_snapshot!(
+        get_error_message("2 m -> [ft, s]"),
+        @r###"
+     left hand side: Length
+    right hand side: Time
+    "###
+    );
+
+    // unit systems, functions and unit functions
+    expect_output("3 ft -> SI", "0.9144 m");
+    expect_output("255 -> hex", "\"0xff\"");
+    expect_output("fn twice(x: Length) -> Length = 2 x\n3 m -> twice", "6 m");
+    expect_output("round(3 mm -> awg)", "9");
+
+    #[cfg(feature = "datetime")]
+    insta::assert_snapshot!(fail("now() -> m"), @"Incompatible types in conversion: 'DateTime' can not be converted to 'Length'");
+    insta::assert_snapshot!(fail("true -> m"), @"Incompatible types in conversion: 'Bool' can not be converted to 'Length'");
+    insta::assert_snapshot!(fail("\"3 m\" -> SI"), @"Incompatible types in conversion: 'String' can not be converted to 'unit system SI'");
+}
+
+#[test]
+fn test_conversion_precedence_notes() {
+    let note = |code| {
+        fail(code).diagnostics()[0]
+            .notes
+            .iter()
+            .find(|note| note.contains("precedence"))
+            .cloned()
+    };
+
+    assert_eq!(
+        note("2 m + 3 m -> s").unwrap(),
+        "`->` has a lower precedence than `+`, so the whole sum `2 metre + 3 metre` is converted."
+    );
+    assert_eq!(
+        note("let x = 2 s\n3 s - x -> m").unwrap(),
+        "`->` has a lower precedence than `-`, so the whole difference `3 second - x` is converted."
+    );
+    assert_eq!(
+        note("let x = 2 m\nx > 3 m -> cm").unwrap(),
+        "`->` has a lower precedence than `>`, so the whole comparison `x > (3 metre)` is converted. \
+         Use parentheses to only convert the right hand side: `x > (3 metre -> centimetre)`"
+    );
+
+    // no note if the source is parenthesized
+    assert_eq!(note("(2 m + 3 m) -> s"), None);
+}
+
+#[test]
+fn test_number_to_words() {
+    expect_output("0 -> number_to_words", "\"zero\"");
+    expect_output("-1 -> number_to_words", "\"minus one\"");
+    expect_output("999 -> number_to_words", "\"nine hundred ninety-nine\"");
+    expect_output("1000 -> number_to_words", "\"one thousand\"");
+    expect_output("10^15 -> number_to_words", "\"one quadrillion\"");
+    expect_output("-10^15 -> number_to_words", "\"minus one quadrillion\"");
+    expect_output(
+        "1234567 -> number_to_words",
+        "\"one million two hundred thirty-four thousand five hundred sixty-seven\"",
+    );
+    expect_output(
+        "1_000_020_000 -> number_to_words",
+        "\"one billion twenty thousand\"",
+    );
+    expect_output(
+        "let n = 42\n\"{n -> number_to_words} apples\"",
+        "\"forty-two apples\"",
+    );
+
+    expect_failure(
+        "10^15 + 1 -> number_to_words",
+        "Can not spell out 1000000000000001: only integers between -10^15 and 10^15 are supported",
+    );
+    expect_failure("1.5 -> number_to_words", "Can not spell out 1.5");
+    expect_failure(
+        "5 m -> number_to_words",
+        "expected 'Scalar', got 'Length' instead",
+    );
+
+    // Common names stay available for variables
+    expect_output(
+        "let words = [\"hello\", \"world\"]\nhead(words)",
+        "\"hello\"",
+    );
+}
+
 #[test]
 fn test_implicit_conversion() {
     let mut ctx = get_test_context();
@@ -346,7 +570,7 @@ fn test_incompatible_dimension_errors() {
     parameter type: Scalar    [= Angle, Scalar, SolidAngle]
      argument type: Length
 
-    Suggested fix: divide the function argument by a `Length` factor
+    Suggested fix: divide the function argument by a reference quantity of dimension `Length`, e.g. `sin(x / x0)`
     "###
     );
 
@@ -379,6 +603,26 @@ fn test_incompatible_dimension_errors() {
     Suggested fix: divide the expression in the function body by a `Time` factor
     "###
     );
+
+    assert_snapshot!(
+        get_error_message("km -> 3"),
+        @r###"
+     left hand side: Length
+    right hand side: Scalar    [= Angle, Scalar, SolidAngle]
+
+    Suggested fix: to write a quantity, put the number in front of the unit: `3 kilometre`
+    "###
+    );
+
+    assert_snapshot!(
+        get_error_message("3 km/h -> 5"),
+        @r###"
+     left hand side: Length / Time    [= Velocity]
+    right hand side: Scalar           [= Angle, Scalar, SolidAngle]
+
+    Suggested fix: convert to a unit instead of a number, like `3 kilometre / hour -> mph`
+    "###
+    );
 }
 
 #[test]
@@ -393,6 +637,60 @@ fn test_temperature_conversions() {
     expect_output("from_fahrenheit(123 K -> fahrenheit)", "123 K");
 
     expect_output("-40 -> from_fahrenheit -> celsius", "-40");
+
+    // Absolute temperatures in different scales are compared through Kelvin
+    expect_output("from_celsius(20) < from_fahrenheit(67)", "false");
+    expect_output("from_celsius(20) < from_fahrenheit(69)", "true");
+    expect_output("from_celsius(-40) > from_fahrenheit(-41)", "true");
+
+    // Differences of absolute temperatures are temperature differences in Kelvin
+    expect_output("from_celsius(25) - from_fahrenheit(32)", "25 K");
+    expect_output(
+        "from_celsius(10) + (from_celsius(25) - from_celsius(20))",
+        "288.15 K",
+    );
+}
+
+#[test]
+fn test_unit_systems() {
+    expect_output("1 N -> CGS", "100_000 dyne");
+    expect_output("2 km -> CGS", "200_000 cm");
+    expect_output("1 Pa s -> CGS", "10 poise");
+    expect_output("1 poise -> SI", "0.1 Pa·s");
+    expect_output("3.6 km/h -> SI", "1 m/s");
+    expect_output("1 kWh -> SI", "3_600_000 J");
+    expect_output("1 ft lbf -> SI", "1.35582 J");
+    expect_output("1 kg m^2 -> SI", "1 kg·m²");
+    expect_output("SI(1 yard)", "0.9144 m");
+    expect_output("100 km/h -> imperial", "62.1371 mph");
+
+    expect_failure(
+        "1 A -> imperial",
+        "The unit system 'imperial' does not contain a unit for the dimension 'Current'",
+    );
+    expect_failure(
+        "1 K -> CGS",
+        "The unit system 'CGS' does not contain a unit for the dimension 'Temperature'",
+    );
+
+    expect_output(
+        "unit_system my_system = [km, hour]
+         60 mph -> my_system",
+        "96.5606 km/h",
+    );
+    expect_failure(
+        "let x = 2 m
+         unit_system my_system = [x]",
+        "Only units can be part of the unit system 'my_system'",
+    );
+    expect_failure(
+        "dimension Foo
+         unit foo_a: Foo
+         unit foo_b: Foo
+         unit_system foo_system = [foo_b]
+         1 foo_a -> foo_system",
+        "The unit system 'foo_system' does not contain a unit for the dimension 'Foo'",
+    );
 }
 
 #[test]
@@ -414,6 +712,316 @@ fn test_other_functions() {
     expect_output("is_infinite(1)", "false");
 }
 
+#[test]
+fn test_rounding_to_steps() {
+    // Without a step, quantities are rounded in the unit they are given in
+    expect_output("round(1.5 km)", "2 km");
+    expect_output("round(1500 m)", "1500 m");
+    expect_output("round(-2.5 m)", "-3 m");
+
+    expect_output("round_to(1.2345 m, 1 cm)", "1.23 m");
+    expect_output("round_to(1.2345 m, 5 mm)", "1.235 m");
+    expect_output("round_to(1 mile, 100 m)", "0.994194 mi");
+    expect_output("round_to(1 mile, 100 m) -> m", "1600 m");
+    expect_output("round_to(-2.5 m, 1 m)", "-3 m");
+    expect_output("floor_to(17 min, 15 min)", "15 min");
+    expect_output("floor_to(-1 cm, 1 m)", "-100 cm");
+    expect_output("ceil_to(1.01 h, 15 min)", "1.25 h");
+
+    // Banker's rounding
+    expect_output("round_to(2.5, 1, \"half_even\")", "2");
+    expect_output("round_to(3.5, 1, \"half_even\")", "4");
+    expect_output("round_to(-2.5 m, 1 m, \"half_even\")", "-2 m");
+    expect_output("round_to(2.51, 1, \"half_even\")", "3");
+    expect_output("round_to(2.5, 1, \"half_away_from_zero\")", "3");
+
+    expect_failure("round_to(2.5, 1, \"up\")", "Unknown rounding mode 'up'");
+    expect_failure("round_to(2 m, 1 s)", "Length / Time = Scalar");
+}
+
+#[test]
+fn test_sign_functions() {
+    expect_output("abs(-3 m)", "3 m");
+    expect_output("trunc(-2.7 km)", "-2 km");
+
+    // The sign is always dimensionless
+    expect_output("sign(-3 m)", "-1");
+    expect_output("sign(0 s)", "0");
+    expect_output("sign(-0)", "0");
+    expect_output("sign(2.5 kg)", "1");
+    expect_output("is_nan(sign(NaN))", "true");
+    expect_output("sign(-3 m) + 1", "0");
+
+    // The second argument of copysign can have any dimension
+    expect_output("copysign(3 m, -2 s)", "-3 m");
+    expect_output("copysign(-3 m, 1)", "3 m");
+    expect_output("copysign(2, -1 kg)", "-2");
+
+    expect_output("fract(2.75)", "0.75");
+    expect_output("fract(-2.75)", "-0.75");
+    expect_output("fract(2.75 m / m)", "0.75");
+    expect_failure(
+        "fract(2.75 m)",
+        "Suggested fix: divide the function argument by a reference quantity of dimension `Length`, e.g. `fract(x / x0)`",
+    );
+    let diagnostics = fail("fract(1.5 ft)").diagnostics();
+    assert!(diagnostics[0].notes.iter().any(|note| note
+        == "The fractional part of a quantity depends on the unit it is given in (1.5 ft = 45.72 cm), so 'fract' only accepts scalars."));
+}
+
+#[test]
+fn test_implicit_dimensions() {
+    let mut ctx = get_test_context();
+    let _ = ctx
+        .interpret(
+            "unit widget
+             @dimension(Gizmo)
+             @aliases(gadgets)
+             unit gadget",
+            CodeSource::Internal,
+        )
+        .unwrap();
+
+    expect_output_with_context(&mut ctx, "3 widget + 2 widget", "5 widget");
+    expect_output_with_context(&mut ctx, "6 widget / 3 gadgets", "2 widget/gadget");
+    expect_output_with_context(
+        &mut ctx,
+        "fn per_gadget(n: Gizmo) -> Widget = n × 2 widget / gadget\nper_gadget(2 gadgets)",
+        "4 widget",
+    );
+
+    expect_failure(
+        "dimension Widget\nunit widget",
+        "The dimension 'Widget' for unit 'widget' already exists",
+    );
+    expect_failure(
+        "@dimension(Length) unit widget",
+        "The dimension 'Length' for unit 'widget' already exists",
+    );
+    expect_failure("unit widget\n1 widget + 1 meter", "right hand side: Length");
+}
+
+#[test]
+fn test_local_units() {
+    let mut ctx = get_test_context();
+    let _ = ctx
+        .interpret(
+            "fn frame_count(duration: Time) -> Scalar = duration / tick
+               where unit tick = 16.67 ms
+
+             fn in_local_feet(x: Length) -> Length = x -> foot
+               where unit foot = 10 cm
+
+             fn cost(count: Scalar) -> Credit = count widget -> coins
+               where dimension Credit
+                 and unit coins: Credit
+                 and unit widget = 3 coins",
+            CodeSource::Internal,
+        )
+        .unwrap();
+
+    expect_output_with_context(&mut ctx, "frame_count(1 s)", "59.988");
+    expect_output_with_context(&mut ctx, "cost(4)", "12 coins");
+
+    // Shadowing a global unit does not affect conversions outside of the function
+    expect_output_with_context(&mut ctx, "in_local_feet(1 m)", "10 foot");
+    expect_output_with_context(&mut ctx, "1 m -> foot", "3.28084 ft");
+    expect_output_with_context(&mut ctx, "in_local_feet(1 m)", "10 foot");
+
+    expect_failure_with_context(
+        &mut ctx,
+        "2 tick",
+        "Unit 'tick' is local to the function 'frame_count' and not in scope here",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "fn double_ticks(n: Scalar) = 2 n tick",
+        "Unit 'tick' is local to the function 'frame_count' and not in scope here",
+    );
+    expect_failure_with_context(&mut ctx, "let x: Credit = 2", "Unknown entry 'Credit'");
+
+    // Local units do not prevent global definitions with the same name
+    expect_output_with_context(
+        &mut ctx,
+        "unit tick = 10 ms
+3 tick -> ms",
+        "30 ms",
+    );
+    expect_output_with_context(&mut ctx, "frame_count(1 s)", "59.988");
+}
+
+#[test]
+fn test_currency_formatting() {
+    expect_output("format_currency(1234.5 EUR)", "\"1,234.50 EUR\"");
+    expect_output("format_currency(1234567.891 €)", "\"1,234,567.89 EUR\"");
+    expect_output("format_currency(-0.125 EUR)", "\"-0.13 EUR\"");
+    expect_output("format_currency(-0.001 EUR)", "\"0.00 EUR\"");
+    expect_output("format_currency(2 EUR / 3)", "\"0.67 EUR\"");
+    expect_output(
+        "format_currency(round_to(2.125 EUR, 0.01 EUR, \"half_even\"))",
+        "\"2.12 EUR\"",
+    );
+
+    let mut ctx = get_test_context();
+    let _ = ctx
+        .interpret(
+            "@currency(\"XTS\", 2)
+             unit test_dollar: Money = 0.5 EUR
+             @currency(\"XTY\", 0)
+             unit test_yen: Money = 0.01 EUR",
+            CodeSource::Internal,
+        )
+        .unwrap();
+    expect_output_with_context(
+        &mut ctx,
+        "format_currency(1234.5 test_dollar)",
+        "\"1,234.50 XTS\"",
+    );
+    expect_output_with_context(
+        &mut ctx,
+        "format_currency(1234.5 test_yen)",
+        "\"1,235 XTY\"",
+    );
+    expect_output_with_context(
+        &mut ctx,
+        "format_currency(-1234.5 test_yen)",
+        "\"-1,235 XTY\"",
+    );
+    expect_output_with_context(
+        &mut ctx,
+        "format_currency(10 test_dollar -> EUR)",
+        "\"5.00 EUR\"",
+    );
+
+    // Amounts that are not given in a single currency are simplified first
+    expect_output_with_context(
+        &mut ctx,
+        "format_currency(2 test_dollar × 3 m / m)",
+        "\"6.00 XTS\"",
+    );
+
+    expect_failure(
+        "unit credit: Money = 2 EUR
+         format_currency(3 credit)",
+        "'credit' is not a currency unit",
+    );
+    expect_failure("format_currency(2 m)", "Money");
+    expect_failure(
+        "@currency(\"EUR\")
+         unit foo: Money",
+        "Expected a currency code and a number of decimal places",
+    );
+    expect_failure(
+        "@currency(\"EUR\", 2)
+         let foo = 2 EUR",
+        "The @currency decorator can only be used on unit definitions",
+    );
+}
+
+#[test]
+fn test_isolated_units() {
+    let mut ctx = get_test_context();
+    let _ = ctx
+        .interpret(
+            "@currency(\"XTS\", 2)
+             @isolated
+             unit test_dollar: Money = 0.5 EUR",
+            CodeSource::Internal,
+        )
+        .unwrap();
+
+    // currencies are only converted explicitly
+    expect_failure_with_context(
+        &mut ctx,
+        "10 test_dollar + 10 EUR",
+        "Can not implicitly convert from '€' to 'test_dollar'",
+    );
+    expect_failure_with_context(&mut ctx, "10 EUR - 10 test_dollar", "Use '… -> €'");
+    expect_failure_with_context(&mut ctx, "10 EUR < 30 test_dollar", "implicitly convert");
+    expect_failure_with_context(
+        &mut ctx,
+        "10 EUR/kg + 10 test_dollar/kg",
+        "implicitly convert",
+    );
+    expect_output_with_context(&mut ctx, "(10 test_dollar -> EUR) + 10 EUR", "15 €");
+    expect_output_with_context(&mut ctx, "10 EUR + (10 test_dollar -> EUR)", "15 €");
+    expect_output_with_context(&mut ctx, "10 EUR < (30 test_dollar -> EUR)", "true");
+    expect_output_with_context(
+        &mut ctx,
+        "10 test_dollar + 10 test_dollar",
+        "20 test_dollar",
+    );
+
+    // other factors of the units are still converted, and scaling is unaffected
+    expect_output_with_context(&mut ctx, "2 EUR/kg + 3 EUR/g", "3002 €/kg");
+    expect_output_with_context(&mut ctx, "3 × 10 test_dollar", "30 test_dollar");
+    expect_output_with_context(&mut ctx, "10 test_dollar / 2", "5 test_dollar");
+
+    // non-isolated units of the same dimension can be mixed with isolated ones
+    expect_output_with_context(
+        &mut ctx,
+        "unit credit: Money = 2 EUR
+         1 credit + 1 EUR",
+        "1.5 credit",
+    );
+    expect_output("1 m + 1 ft", "1.3048 m");
+
+    expect_failure(
+        "@isolated
+         let foo = 2",
+        "The @isolated decorator can only be used on unit definitions",
+    );
+}
+
+#[test]
+fn test_selective_import() {
+    let mut ctx = get_test_context();
+    expect_output_with_context(
+        &mut ctx,
+        "use numerics::solve::{root_newton}
+         fn f(x) = 2 x - 4
+         fn df(x) = 2
+         root_newton(f, df, 1, 1e-10)",
+        "2",
+    );
+    // other definitions of the module are not imported
+    expect_failure_with_context(&mut ctx, "root_bisect", "Unknown identifier 'root_bisect'");
+
+    // units can be imported selectively, too
+    let mut ctx = get_test_context();
+    expect_output_with_context(
+        &mut ctx,
+        "use units::hartree::{hartree, hartrees}
+         2 hartrees -> hartree",
+        "2 hartree",
+    );
+    expect_failure_with_context(&mut ctx, "1 bohr", "Unknown identifier 'bohr'");
+
+    // names that were not imported can be defined by the user
+    expect_output_with_context(
+        &mut ctx,
+        "unit bohr: Length = 2 m
+         3 bohr -> m",
+        "6 m",
+    );
+
+    // a later import reveals the definitions of the module
+    let mut ctx = get_test_context();
+    expect_output_with_context(
+        &mut ctx,
+        "use units::hartree::{hartree}
+         use units::hartree::{bohr}
+         1 bohr -> pm",
+        "52.9177 pm",
+    );
+
+    expect_failure(
+        "use numerics::solve::{root_newtn}",
+        "Module 'numerics::solve' does not define 'root_newtn'",
+    );
+    expect_failure("use numerics::solve::{}", "Expected the name of an item");
+}
+
 #[test]
 fn test_last_result_identifier() {
     let mut ctx = get_test_context();
@@ -423,6 +1031,32 @@ fn test_last_result_identifier() {
 
     let _ = ctx.interpret("1 + 2", CodeSource::Internal).unwrap();
     expect_output_with_context(&mut ctx, "_", "3");
+
+    expect_failure("let ans = 1", "Reserved identifier");
+    expect_failure("fn _2() = 1", "Reserved identifier");
+}
+
+#[test]
+fn test_indexed_result_identifiers() {
+    let mut ctx = get_test_context();
+
+    let _ = ctx.interpret("2 m", CodeSource::Internal).unwrap(); // _1
+    let _ = ctx.interpret("let x = 3", CodeSource::Internal).unwrap();
+    let _ = ctx.interpret("fn f(y) = y", CodeSource::Internal).unwrap();
+    expect_output_with_context(&mut ctx, "\"hello\"", "\"hello\""); // _2
+    expect_output_with_context(&mut ctx, "ans", "\"hello\""); // _3
+    expect_output_with_context(&mut ctx, "_1 + 1 m", "3 m"); // _4
+    expect_output_with_context(&mut ctx, "_2", "\"hello\""); // _5
+    expect_output_with_context(&mut ctx, "str_length(_3)", "5"); // _6
+
+    // Errors do not consume an index
+    expect_failure_with_context(&mut ctx, "_1 + 1 s", "right hand side: Time");
+    expect_failure_with_context(&mut ctx, "_1 / 0", "Division by zero");
+    expect_output_with_context(&mut ctx, "_6 + _4 / m", "8"); // _7
+    expect_output_with_context(&mut ctx, "_7", "8");
+
+    expect_failure_with_context(&mut ctx, "_9", "Unknown identifier '_9'");
+    expect_failure_with_context(&mut ctx, "_0", "Unknown identifier '_0'");
 }
 
 #[test]
@@ -489,6 +1123,38 @@ fn test_prefixes() {
     expect_output("quettahertz quectosecond", "1");
 }
 
+#[test]
+fn test_long_unit_names() {
+    // Long names take long prefixes, short names take short prefixes
+    expect_output("3 kilometre", "3 km");
+    expect_output("3 kilometres -> meters", "3000 m");
+    expect_output("2 kibibytes", "2 KiB");
+    expect_failure("3 kmeter", "Unknown identifier 'kmeter'");
+    expect_failure("3 kilom", "Unknown identifier 'kilom'");
+
+    let mut ctx = get_test_context();
+    ctx.set_long_unit_names(true);
+    expect_output_with_context(&mut ctx, "3 km", "3 kilometres");
+    expect_output_with_context(&mut ctx, "1 km", "1 kilometre");
+    expect_output_with_context(&mut ctx, "-1 km", "-1 kilometre");
+    expect_output_with_context(&mut ctx, "0.5 h", "0.5 hours");
+    expect_output_with_context(&mut ctx, "60 km/h", "60 kilometres/hour");
+    expect_output_with_context(&mut ctx, "2 N m", "2 newton·metres");
+    expect_output_with_context(&mut ctx, "9.81 m/s^2", "9.81 metres/second²");
+    expect_output_with_context(&mut ctx, "2 KiB", "2 kibibytes");
+    expect_output_with_context(&mut ctx, "3 weeks", "3 weeks");
+    expect_output_with_context(&mut ctx, "[1 ms, 2 ms]", "[1 millisecond, 2 milliseconds]");
+
+    // Units without a long name keep their symbol
+    expect_output_with_context(&mut ctx, "2 Mbps", "2 Mbps");
+
+    // Only the output changes, the values are the same
+    expect_output_with_context(&mut ctx, "3 km == 3000 m", "true");
+    expect_output_with_context(&mut ctx, "let x = 3 km\nx", "3 kilometres");
+    ctx.set_long_unit_names(false);
+    expect_output_with_context(&mut ctx, "x", "3 km");
+}
+
 #[test]
 fn test_parse_errors() {
     expect_failure(
@@ -504,13 +1170,56 @@ fn test_parse_errors() {
 
 #[test]
 fn test_name_clash_errors() {
-    expect_failure("let kg=2", "Identifier is already in use: 'kg'");
-    expect_failure("fn kg(x: Scalar) = 1", "Identifier is already in use: 'kg'");
+    expect_failure("let kg=2", "Identifier is already in use by the unit: 'kg'");
+    expect_failure(
+        "fn kg(x: Scalar) = 1",
+        "Identifier is already in use by the unit: 'kg'",
+    );
     expect_failure("fn _()=0", "Reserved identifier");
+    expect_failure(
+        "dimension Velocity",
+        "Identifier is already in use by the dimension: 'Velocity'",
+    );
+    expect_failure(
+        "unit velocity",
+        "The dimension 'Velocity' for unit 'velocity' already exists",
+    );
 }
 
 #[test]
-fn test_type_check_errors() {
+fn test_dimensions_defined_after_generic_functions() {
+    let mut ctx = get_test_context();
+
+    let _ = ctx
+        .interpret("fn twice<D: Dim>(x: D) -> D = 2 x", CodeSource::Internal)
+        .unwrap();
+    let _ = ctx
+        .interpret("fn sq<D: Dim>(x: D) -> D^2 = x * x", CodeSource::Internal)
+        .unwrap();
+
+    let _ = ctx
+        .interpret("dimension Blorb", CodeSource::Internal)
+        .unwrap();
+    let _ = ctx
+        .interpret("unit blorb: Blorb", CodeSource::Internal)
+        .unwrap();
+    let _ = ctx.interpret("unit zork", CodeSource::Internal).unwrap();
+
+    expect_output_with_context(&mut ctx, "twice(3 blorb)", "6 blorb");
+    expect_output_with_context(&mut ctx, "sq(3 blorb)", "9 blorb²");
+    expect_output_with_context(&mut ctx, "twice(2 zork / blorb)", "4 zork/blorb");
+    expect_output_with_context(&mut ctx, "twice(3 blorb) -> blorb", "6 blorb");
+    expect_output_with_context(&mut ctx, "sq(2 zork) / zork", "4 zork");
+
+    expect_failure_with_context(
+        &mut ctx,
+        "fn twice_blorb(x: Blorb) -> Zork = twice(x)",
+        "Blorb",
+    );
+}
+
+#[test]
+fn test_type_check_errors() {
     expect_failure("foo", "Unknown identifier 'foo'");
 
     expect_failure(
@@ -555,6 +1264,81 @@ fn test_comparisons() {
 
     expect_output("200 cm != 2 m", "false");
     expect_output("201 cm != 2 m", "true");
+
+    // Lists are compared element-wise, taking units into account
+    expect_output("[1 m, 2 m] == [100 cm, 2 m]", "true");
+    expect_output("[1 m, 2 m] == [2 m, 1 m]", "false");
+    expect_output("[1 m, 2 m] == [1 m]", "false");
+    expect_output("[[1 m], []] == [[100 cm], []]", "true");
+    expect_output("[1, NaN] == [1, NaN]", "false");
+    expect_output("[1, NaN] != [1, NaN]", "true");
+    expect_output(
+        "
+        let xs = [1, NaN]
+        xs == xs
+        ",
+        "false",
+    );
+
+    // Structs are compared field by field
+    expect_output(
+        "
+        struct Point { x: Length, y: Length }
+        struct Segment { start: Point, end: Point }
+        let segment = Segment { start: Point { x: 0 m, y: 1 m }, end: Point { x: 2 m, y: 3 m } }
+        segment == Segment { start: Point { x: 0 cm, y: 100 cm }, end: Point { x: 2 m, y: 3 m } }
+        ",
+        "true",
+    );
+    expect_output(
+        "
+        struct Point { x: Length, y: Length }
+        struct Segment { start: Point, end: Point }
+        let segment = Segment { start: Point { x: 0 m, y: 1 m }, end: Point { x: 2 m, y: 3 m } }
+        segment != Segment { start: Point { x: 0 m, y: 1 m }, end: Point { x: 2 m, y: 4 m } }
+        ",
+        "true",
+    );
+
+    // DateTimes are compared by the instant in time, independent of the time zone
+    #[cfg(feature = "datetime")]
+    {
+        expect_output(
+            "datetime(\"2024-01-01 12:00 UTC\") == datetime(\"2024-01-01 13:00 Europe/Berlin\")",
+            "true",
+        );
+        expect_output(
+            "datetime(\"2024-01-01 12:00 UTC\") < datetime(\"2024-01-01 12:30 Europe/Berlin\")",
+            "false",
+        );
+        expect_output(
+            "datetime(\"2024-01-01 12:00 UTC\") <= datetime(\"2024-01-01 12:00 UTC\") + 1 s",
+            "true",
+        );
+    }
+
+    // Strings are ordered lexicographically
+    expect_output("\"apple\" < \"banana\"", "true");
+    expect_output("\"b\" <= \"a\"", "false");
+    expect_failure("\"a\" < 1", "Incompatible types in comparison operator");
+
+    expect_failure(
+        "[1 m] == [1 s]",
+        "Incompatible types in comparison operator",
+    );
+    expect_failure(
+        "[1 m] == [\"1 m\"]",
+        "Incompatible types in comparison operator",
+    );
+    expect_failure(
+        "
+        struct A { x: Length }
+        struct B { x: Length }
+        A { x: 1 m } == B { x: 1 m }
+        ",
+        "Incompatible types in comparison operator",
+    );
+    expect_failure("[1 m] < [2 m]", "Expected dimension type");
 }
 
 #[test]
@@ -584,6 +1368,619 @@ fn test_logical() {
     insta::assert_snapshot!(fail("1 && true"), @"Expected boolean value");
     insta::assert_snapshot!(fail("!1"), @"Expected boolean value");
     insta::assert_snapshot!(fail("!1 || true"), @"Expected boolean value");
+
+    // exclusive or
+    expect_output("xor(true, false)", "true");
+    expect_output("xor(false, true)", "true");
+    expect_output("xor(true, true)", "false");
+    expect_output("xor(false, false)", "false");
+
+    // comparisons without a left hand side
+    insta::assert_snapshot!(fail("let x = 2\nx > 0 && < 5"), @"Missing left hand side of comparison");
+    let error = fail("let x = 2\nx > 0 && < 5");
+    assert_eq!(
+        error.diagnostics()[0].notes,
+        ["Did you mean 'x > 0 && x < 5'?"]
+    );
+}
+
+#[test]
+fn test_chained_comparisons() {
+    expect_output("let x = 5 m\n0 m < x <= 10 m", "true");
+    expect_output("let x = 5 m\n0 m < x <= 4 m", "false");
+    expect_output("let x = 5 m\n6 m < x <= 10 m", "false");
+    expect_output("1 <= 1 < 2 <= 2", "true");
+    expect_output("1 <= 1 < 2 < 2", "false");
+    expect_output("3 > 2 >= 2 > 1", "true");
+    #[cfg(feature = "datetime")]
+    expect_output(
+        "datetime(\"2024-01-01 00:00\") < datetime(\"2024-06-01 00:00\") < datetime(\"2025-01-01 00:00\")",
+        "true",
+    );
+    expect_output(
+        "fn in_unit_interval(x) = 0 <= x <= 1\nin_unit_interval(0.5)",
+        "true",
+    );
+    expect_output("0 < 1 < 2 == true", "true");
+
+    // All adjacent operands need to have the same dimension
+    expect_failure("let x = 5 m\n0 m < x <= 10 s", "right hand side: Time");
+    expect_failure("0 < 1 < true", "Expected dimension type, got Bool instead");
+
+    expect_pretty_print("0 m < 5 m <= 10 m", "(0 metre) < (5 metre) ≤ (10 metre)");
+}
+
+#[test]
+fn test_chained_comparisons_evaluate_operands_once() {
+    let mut ctx = get_test_context();
+    let mut sink = BufferingSink::default();
+
+    let mut evaluate = |code: &str| {
+        let (_, result) = ctx
+            .interpret_with_sink(&mut sink, code, CodeSource::Internal)
+            .unwrap();
+        result.value_as_string().unwrap()
+    };
+
+    assert_eq!(evaluate("0 < trace(2) < trace(3) < 4"), "true");
+    // Evaluation stops at the first comparison that fails
+    assert_eq!(evaluate("0 < trace(-1) < trace(3)"), "false");
+
+    assert_eq!(
+        printed(&sink),
+        ["2 = 2 : Scalar", "3 = 3 : Scalar", "-1 = -1 : Scalar"]
+    );
+}
+
+#[test]
+fn test_inexact_integer_literals() {
+    let mut ctx = get_test_context();
+
+    let mut warnings = |code: &str| {
+        let mut sink = BufferingSink::default();
+        let _ = ctx
+            .interpret_with_sink(&mut sink, code, CodeSource::Internal)
+            .unwrap();
+        sink.events()
+            .iter()
+            .filter_map(|event| match event {
+                OutputEvent::Diagnostic(d) if d.severity == Severity::Warning => {
+                    Some(d.notes.clone())
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        warnings("let big_1 = 9007199254740993"),
+        [["Numbers are stored as 64-bit floating point values, so the nearest representable value 9007199254740992 is used instead"]]
+    );
+    assert!(warnings("let big_2 = 9007199254740992").is_empty());
+    assert!(warnings("0x1.8p3").is_empty());
+
+    expect_output("0x1.8p3", "12");
+    expect_output("0x1p-10 == 1 / 1024", "true");
+    expect_output("0x1.0p0 m -> cm", "100 cm");
+}
+
+#[test]
+fn test_chained_comparisons_in_different_directions() {
+    let mut ctx = get_test_context();
+
+    let mut warnings = |code: &str| {
+        let mut sink = BufferingSink::default();
+        let (_, result) = ctx
+            .interpret_with_sink(&mut sink, code, CodeSource::Internal)
+            .unwrap();
+        let warnings: Vec<_> = sink
+            .events()
+            .iter()
+            .filter_map(|event| match event {
+                OutputEvent::Diagnostic(d) if d.severity == Severity::Warning => {
+                    Some(d.message.clone())
+                }
+                _ => None,
+            })
+            .collect();
+        (result.value_as_string().unwrap(), warnings)
+    };
+
+    // Still evaluated pairwise, but with a warning
+    assert_eq!(
+        warnings("1 < 3 > 2"),
+        (
+            "true".into(),
+            vec!["comparisons in different directions".into()]
+        )
+    );
+    assert_eq!(
+        warnings("1 <= 1 < 3 >= 2 > 0"),
+        (
+            "true".into(),
+            vec!["comparisons in different directions".into()]
+        )
+    );
+
+    assert_eq!(warnings("1 < 2 < 3"), ("true".into(), vec![]));
+    assert_eq!(warnings("3 > 2 >= 1"), ("true".into(), vec![]));
+    assert_eq!(warnings("1 < 2 == (3 > 4)"), ("false".into(), vec![]));
+}
+
+#[test]
+fn test_reversed_conversions() {
+    let mut ctx = get_test_context();
+
+    let mut warnings = |code: &str| {
+        let mut sink = BufferingSink::default();
+        let (_, result) = ctx
+            .interpret_with_sink(&mut sink, code, CodeSource::Internal)
+            .unwrap();
+        let notes: Vec<_> = sink
+            .events()
+            .iter()
+            .filter_map(|event| match event {
+                OutputEvent::Diagnostic(d) if d.severity == Severity::Warning => {
+                    Some(d.notes.join("\n"))
+                }
+                _ => None,
+            })
+            .collect();
+        (result.value_as_string().unwrap(), notes)
+    };
+
+    // Still evaluated, but with a suggestion to swap the operands
+    assert_eq!(
+        warnings("meter -> 5 km"),
+        (
+            "0.001 km".into(),
+            vec!["The target of a conversion is usually a unit. Did you mean '5 kilometre -> metre'?".into()]
+        )
+    );
+
+    assert_eq!(warnings("5 km -> meter"), ("5000 m".into(), vec![]));
+    assert_eq!(warnings("0.5 -> %"), ("50 %".into(), vec![]));
+    assert_eq!(warnings("% -> 5 %"), ("1 %".into(), vec![]));
+    assert!(warnings("3 km -> 2 m").1.is_empty());
+}
+
+#[test]
+fn test_suggested_edits() {
+    // Applies all suggestions for the given code
+    let fix = |code: &str| {
+        let mut ctx = get_test_context();
+        let (result, mut diagnostics) = ctx.check_with_warnings(code, CodeSource::Text);
+        if let Err(e) = result {
+            diagnostics.extend(ctx.diagnostics(&e));
+        }
+        let suggestions: Vec<_> = diagnostics
+            .into_iter()
+            .flat_map(|diagnostic| diagnostic.suggestions)
+            .collect();
+        let applicabilities: Vec<_> = suggestions.iter().map(|s| s.applicability).collect();
+        (apply_suggestions(code, &suggestions), applicabilities)
+    };
+
+    for (code, fixed, applicability) in [
+        (
+            "let x = 2\nx > 0 && < 5",
+            "let x = 2\nx > 0 && x < 5",
+            Applicability::MachineApplicable,
+        ),
+        (
+            "2 × 9007199254740993",
+            "2 × 9007199254740992",
+            Applicability::MachineApplicable,
+        ),
+        (
+            "meter -> 5 km",
+            "5 kilometre -> metre",
+            Applicability::MaybeIncorrect,
+        ),
+        ("3 meterss", "3 meters", Applicability::MaybeIncorrect),
+    ] {
+        assert_eq!(fix(code), (fixed.into(), vec![applicability]));
+
+        // The fixed code is valid and does not lead to any more diagnostics
+        let mut ctx = get_test_context();
+        let (result, warnings) = ctx.check_with_warnings(fixed, CodeSource::Text);
+        assert!(result.is_ok(), "for '{fixed}'");
+        assert!(warnings.is_empty(), "for '{fixed}'");
+    }
+}
+
+#[test]
+fn test_ambiguous_units() {
+    let mut ctx = get_test_context();
+    let _ = ctx
+        .interpret(
+            "@metric_prefixes
+             @aliases(iB: short)
+             unit ibyte: Scalar = 3",
+            CodeSource::Internal,
+        )
+        .unwrap();
+
+    // 'MiB' could be mebi-'B' or mega-'iB'
+    let error = ctx.interpret("MiB", CodeSource::Internal).unwrap_err();
+    assert_has_error_codes(&error);
+    assert_eq!(error.to_string(), "Ambiguous unit: 'MiB'");
+    assert_eq!(
+        error.diagnostics()[0].notes,
+        [
+            "'MiB' can be read as 'Mi' + 'B' (DigitalInformation), written as 'mebibyte'",
+            "'MiB' can be read as 'M' + 'iB' (Scalar), written as 'megaibyte'",
+            "Use the long form of the unit you mean",
+        ]
+    );
+
+    // The long forms are unambiguous
+    expect_output_with_context(&mut ctx, "megaibyte / ibyte", "1_000_000");
+    expect_output_with_context(&mut ctx, "mebibyte -> B", "1_048_576 B");
+
+    // Other prefixed forms and exact names are not affected
+    expect_output_with_context(&mut ctx, "kiB / iB", "1000");
+    expect_output_with_context(&mut ctx, "KiB -> B", "1024 B");
+}
+
+#[test]
+fn test_nesting_depth_limit() {
+    let mut ctx = get_test_context();
+    ctx.set_max_nesting_depth(8);
+
+    expect_output_with_context(&mut ctx, &format!("{}2", "-".repeat(7)), "-2");
+
+    let error = ctx
+        .interpret(&format!("{}2", "-".repeat(8)), CodeSource::Internal)
+        .unwrap_err();
+    assert_has_error_codes(&error);
+    assert_eq!(
+        error.to_string(),
+        "Expression too deeply nested (the limit is 8 levels)"
+    );
+}
+
+#[test]
+fn test_check_without_evaluating() {
+    let mut ctx = get_test_context();
+
+    let _ = ctx
+        .check(
+            "let x = 1 m
+print(x)",
+            CodeSource::Internal,
+        )
+        .unwrap();
+    let error = ctx
+        .check("let y: Time = 1 m", CodeSource::Internal)
+        .unwrap_err();
+    assert!(matches!(error, NumbatError::TypeCheckError(_)));
+
+    // Nothing has been defined
+    assert!(ctx.get_variable("x").is_none());
+    expect_output_with_context(
+        &mut ctx,
+        "let x = 2 s
+x",
+        "2 s",
+    );
+}
+
+#[test]
+fn test_undo() {
+    // Only user input (CodeSource::Text) can be reverted
+    fn run(ctx: &mut Context, code: &str) -> String {
+        match ctx.interpret(code, CodeSource::Text).unwrap().1 {
+            InterpreterResult::Value(val) => PlainTextFormatter {}
+                .format(&val.pretty_print(), false)
+                .trim()
+                .to_string(),
+            InterpreterResult::Continue => String::new(),
+        }
+    }
+
+    let mut ctx = get_test_context();
+    ctx.set_undo_limit(3);
+    assert!(!ctx.undo(1));
+
+    run(&mut ctx, "let a = 9.81 m/s^2");
+    run(&mut ctx, "let a = 2");
+    run(&mut ctx, "unit bathtub = 150 L");
+    assert_eq!(run(&mut ctx, "2 bathtub -> L"), "300 l");
+    assert_eq!(ctx.undo_steps_available(), 3);
+
+    // Not enough steps: nothing changes
+    assert!(!ctx.undo(4));
+    expect_output_with_context(&mut ctx, "ans", "300 l");
+
+    assert!(ctx.undo(2));
+    assert!(ctx.check("bathtub", CodeSource::Internal).is_err());
+    expect_output_with_context(&mut ctx, "a", "2");
+
+    assert!(ctx.undo(1));
+    expect_output_with_context(&mut ctx, "a", "9.81 m/s²");
+
+    // Failed inputs are not recorded
+    let steps = ctx.undo_steps_available();
+    assert!(ctx
+        .interpret("let b = 1 m + 1 s", CodeSource::Text)
+        .is_err());
+    assert_eq!(ctx.undo_steps_available(), steps);
+
+    // Only the last few inputs are kept
+    for i in 0..10 {
+        run(&mut ctx, &format!("let c = {i}"));
+    }
+    assert_eq!(ctx.undo_steps_available(), 3);
+    assert!(ctx.undo(3));
+    expect_output_with_context(&mut ctx, "c", "6");
+    assert!(!ctx.undo(1));
+}
+
+#[test]
+fn test_checkpoint() {
+    let mut ctx = get_test_context();
+    ctx.set_undo_limit(3);
+    let _ = ctx.interpret("let a = 1", CodeSource::Text).unwrap();
+    let checkpoint = ctx.checkpoint();
+
+    let _ = ctx.interpret("let a = 2", CodeSource::Text).unwrap();
+    let _ = ctx
+        .interpret("unit bathtub = 150 L", CodeSource::Text)
+        .unwrap();
+    ctx.restore(checkpoint);
+    assert!(ctx.check("bathtub", CodeSource::Internal).is_err());
+    expect_output_with_context(&mut ctx, "a", "1");
+
+    // The undo history is left as it is
+    assert_eq!(ctx.undo_steps_available(), 3);
+}
+
+#[test]
+fn test_unit_names_used_as_variables() {
+    // Definitions that clash with a unit name the unit and its dimension
+    for (code, name, unit) in [
+        ("let m = 5", "m", "metre (Length)"),
+        ("let km = 5", "km", "kilometre (Length)"),
+        ("fn f(x) = x where ms = 3", "ms", "millisecond (Time)"),
+        ("fn double(N) = 2 N", "N", "newton (Force)"),
+    ] {
+        assert_eq!(
+            fail(code).diagnostics()[0].notes,
+            [format!(
+                "'{name}' refers to the unit {unit}. Variables, functions and units can not share a name, so choose a different one, like '{name}_'"
+            )]
+        );
+    }
+    assert!(fail("fn foo() = 1\nlet foo = 2").diagnostics()[0]
+        .notes
+        .is_empty());
+
+    // Units that are used on their own, where a variable might have been intended
+    let notes = |code: &str| fail(code).diagnostics()[0].notes.clone();
+    assert_eq!(
+        notes("let duration = 2 min\nduration + kg")[1],
+        "The right hand side refers to the unit kilogram (Mass), not to a variable. Variables can not have the name of a unit"
+    );
+    assert!(
+        notes("kg < 3 s")[1].starts_with("The left hand side refers to the unit kilogram (Mass)")
+    );
+
+    // Units after a number or in conversions are clearly meant as units
+    for code in ["2 min + 3 kg", "2 min -> kg", "2 min + 5 %"] {
+        assert_eq!(notes(code).len(), 1);
+    }
+}
+
+#[test]
+fn test_deprecation_warnings() {
+    let mut ctx = get_test_context();
+    let _ = ctx
+        .interpret(
+            "@deprecated(\"use newton instead\")
+             @aliases(old_forces)
+             unit old_force = 1 N
+
+             @deprecated(\"use countdown instead\")
+             fn old_countdown(x) = if x > 0 then old_countdown(x - 1) else 0
+
+             @deprecated(\"use pi instead\")
+             let old_pi = 3.14",
+            CodeSource::Internal,
+        )
+        .unwrap();
+
+    // Returns the message and the primary label of all warnings
+    fn warnings(ctx: &mut Context, code: &str) -> Vec<(String, String, std::ops::Range<usize>)> {
+        let mut sink = BufferingSink::default();
+        let _ = ctx
+            .interpret_with_sink(&mut sink, code, CodeSource::Internal)
+            .unwrap();
+        sink.events()
+            .iter()
+            .filter_map(|event| match event {
+                OutputEvent::Diagnostic(d) if d.severity == Severity::Warning => Some((
+                    d.message.clone(),
+                    d.labels[0].message.clone(),
+                    d.labels[0].range.clone(),
+                )),
+                _ => None,
+            })
+            .collect()
+    }
+
+    assert_eq!(
+        warnings(&mut ctx, "2 old_forces -> N"),
+        [(
+            "use of deprecated identifier 'old_forces'".into(),
+            "use newton instead".into(),
+            2..12
+        )]
+    );
+    assert_eq!(
+        warnings(&mut ctx, "old_countdown(3) + old_pi"),
+        [
+            (
+                "use of deprecated identifier 'old_countdown'".into(),
+                "use countdown instead".into(),
+                0..13
+            ),
+            (
+                "use of deprecated identifier 'old_pi'".into(),
+                "use pi instead".into(),
+                19..25
+            )
+        ]
+    );
+    assert_eq!(warnings(&mut ctx, "map(old_countdown, [1])").len(), 1);
+
+    // Warnings can be disabled per file, or globally
+    assert!(warnings(&mut ctx, "# numbat: allow-deprecated\n2 old_force").is_empty());
+    ctx.set_deprecation_warnings(false);
+    assert!(warnings(&mut ctx, "2 old_force").is_empty());
+    ctx.set_deprecation_warnings(true);
+
+    // Completions are marked
+    assert!(ctx
+        .get_detailed_completions_for("old_for", false)
+        .all(|c| c.deprecated));
+    assert!(!ctx
+        .get_detailed_completions_for("newton", false)
+        .any(|c| c.deprecated));
+
+    // No warnings in the definition itself, and not after a redefinition
+    assert!(warnings(
+        &mut ctx,
+        "@deprecated(\"use other_countdown instead\")
+         fn another_countdown(x) = if x > 0 then another_countdown(x - 1) else 0"
+    )
+    .is_empty());
+    assert!(warnings(&mut ctx, "fn old_countdown(x) = x\nold_countdown(1)").is_empty());
+    assert!(!ctx
+        .get_detailed_completions_for("old_countdown", false)
+        .any(|c| c.deprecated));
+}
+
+#[test]
+fn test_uncertainty_shorthand() {
+    let mut ctx = get_test_context();
+
+    fn warnings(ctx: &mut Context, code: &str) -> (String, Vec<(String, String)>) {
+        let mut sink = BufferingSink::default();
+        let (_, result) = ctx
+            .interpret_with_sink(&mut sink, code, CodeSource::Internal)
+            .unwrap();
+        let warnings = sink
+            .events()
+            .iter()
+            .filter_map(|event| match event {
+                OutputEvent::Diagnostic(d) if d.severity == Severity::Warning => {
+                    Some((d.message.clone(), d.labels[0].message.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+        (result.value_as_string().unwrap(), warnings)
+    }
+
+    // Only the central value is used
+    assert_eq!(
+        warnings(&mut ctx, "6.67430(15)e-11 m³/(kg s²)"),
+        (
+            "6.6743e-11 m³/(kg·s²)".into(),
+            vec![(
+                "uncertainty is discarded".into(),
+                "uncertainty ±1.5e-15 is not taken into account".into()
+            )]
+        )
+    );
+    assert_eq!(
+        warnings(&mut ctx, "1.0(5) + 2"),
+        (
+            "3".into(),
+            vec![(
+                "uncertainty is discarded".into(),
+                "uncertainty ±0.5 is not taken into account".into()
+            )]
+        )
+    );
+
+    // The warning can be disabled
+    ctx.set_uncertainty_warnings(false);
+    assert_eq!(warnings(&mut ctx, "1.23(4) m"), ("1.23 m".into(), vec![]));
+    ctx.set_uncertainty_warnings(true);
+
+    // Function calls are not affected
+    let _ = ctx
+        .interpret("fn f(x) = 2 x", CodeSource::Internal)
+        .unwrap();
+    assert_eq!(warnings(&mut ctx, "f(4)e"), ("21.7463".into(), vec![]));
+}
+
+#[test]
+fn test_logical_short_circuiting() {
+    let mut ctx = get_test_context();
+    let mut sink = BufferingSink::default();
+
+    fn evaluate(ctx: &mut Context, sink: &mut BufferingSink, code: &str) -> String {
+        let (_, result) = ctx
+            .interpret_with_sink(sink, code, CodeSource::Internal)
+            .unwrap();
+        result.value_as_string().unwrap()
+    }
+
+    // The right hand side is only evaluated if it is needed
+    assert_eq!(
+        evaluate(&mut ctx, &mut sink, "false && trace(true)"),
+        "false"
+    );
+    assert_eq!(
+        evaluate(&mut ctx, &mut sink, "true || trace(false)"),
+        "true"
+    );
+    assert!(printed(&sink).is_empty());
+
+    assert_eq!(
+        evaluate(&mut ctx, &mut sink, "true && trace(false)"),
+        "false"
+    );
+    assert_eq!(
+        evaluate(&mut ctx, &mut sink, "false || trace(true)"),
+        "true"
+    );
+    assert_eq!(
+        printed(&sink),
+        ["false = false : Bool", "true = true : Bool"]
+    );
+    sink.take_events();
+
+    // Operands are evaluated from left to right
+    assert_eq!(
+        evaluate(
+            &mut ctx,
+            &mut sink,
+            "trace(1 > 2) || trace(true) && trace(false) || trace(3 < 4)"
+        ),
+        "true"
+    );
+    assert_eq!(
+        printed(&sink),
+        [
+            "1 > 2 = false : Bool",
+            "true = true : Bool",
+            "false = false : Bool",
+            "3 < 4 = true : Bool"
+        ]
+    );
+
+    expect_output("false && error(\"not evaluated\")", "false");
+    expect_output("true || error(\"not evaluated\")", "true");
+    expect_failure("true && error(\"evaluated\")", "User error: evaluated");
+    expect_output(
+        "fn safe_inverse_above(x, limit) = x != 0 && 1 / x > limit
+         safe_inverse_above(0, 1)",
+        "false",
+    );
 }
 
 #[test]
@@ -596,6 +1993,50 @@ fn test_conditionals() {
     );
 }
 
+#[test]
+fn test_conditional_statements() {
+    let mut ctx = get_test_context();
+    let mut sink = BufferingSink::default();
+
+    let (_, result) = ctx
+        .interpret_with_sink(
+            &mut sink,
+            "let speed = 30 km/h
+             if speed > 50 km/h then print(\"too fast\")
+             if speed > 20 km/h then print(\"fast\")
+             if speed < 10 km/h then print(\"slow\") else print(\"not slow\")
+             if speed > 100 km/h then print(\"very fast\")
+             else if speed > 25 km/h then print(\"quite fast\")
+             else print(\"ok\")
+             if speed > 0 km/h then if speed < 40 km/h then assert(true) else print(\"?\")
+             if speed > 0 km/h then if speed > 40 km/h then print(\"nested\")",
+            CodeSource::Internal,
+        )
+        .unwrap();
+    assert!(result.is_continue());
+    assert_eq!(printed(&sink), vec!["fast", "not slow", "quite fast"]);
+
+    expect_pretty_print(
+        "if 1 < 2 then print(1 km) else print(2 m)",
+        "if 1 < 2 then print(1 kilometre) else print(2 metre)",
+    );
+
+    insta::assert_snapshot!(fail("if 1 then print(2)"), @"Expected boolean value");
+    insta::assert_snapshot!(fail("if true then print(1) else 2"), @"Expected procedure call or 'if' after 'else' in conditional statement");
+
+    // Conditional expressions still need an `else` branch
+    let error = fail("let x = if true then 1");
+    assert_eq!(
+        error.to_string(),
+        "Expected 'else' in if-then-else condition"
+    );
+    let diagnostics = error.diagnostics();
+    assert!(diagnostics[0]
+        .notes
+        .iter()
+        .any(|note| note.contains("if condition then print(…)")));
+}
+
 #[test]
 fn test_string_interpolation() {
     expect_output("\"pi = {pi}!\"", "\"pi = 3.14159!\"");
@@ -693,127 +2134,3867 @@ fn test_overwrite_captured_constant() {
 }
 
 #[test]
-fn test_pretty_print_prefixes() {
-    expect_output("1 megabarn", "1 megabarn");
-}
+fn test_pipe_operator() {
+    expect_output("[1, 2, 3] |> map(sqr) |> sum", "14");
+    expect_output("42 |> base(16)", "\"2a\"");
 
-#[test]
-fn test_full_simplify_for_function_calls() {
-    expect_output("floor(1.2 hours / hour)", "1");
-}
+    // Mixing with conversions
+    expect_output("2.5 km -> m |> round", "2500 m");
+    expect_output("100.4 cm |> round -> m", "1 m");
+    expect_output("300 K |> celsius |> round", "27");
 
-#[test]
-fn test_datetime_runtime_errors() {
-    expect_failure("datetime(\"2000-01-99\")", "Unrecognized datetime format");
-    expect_failure("now() -> tz(\"Europe/NonExisting\")", "Unknown timezone");
     expect_failure(
-        "date(\"2000-01-01\") + 1e100 years",
-        "Exceeded maximum size for time durations",
+        "1 |> 2 m",
+        "Expected identifier or function call after postfix apply",
+    );
+
+    expect_pretty_print(
+        "[1, 2, 3] |> map(sqr) |> sum",
+        "[1, 2, 3] |> map(sqr) |> sum",
+    );
+    expect_pretty_print(
+        "let x = 2 m |> sqr -> cm^2",
+        "let x: Area = 2 metre |> sqr ➞ centimetre²",
+    );
+    expect_pretty_print("(2 m |> sqr) + 1 m^2", "(2 metre |> sqr) + 1 × metre²");
+}
+
+#[test]
+fn test_method_call_syntax() {
+    expect_output("[1 m, 2 m, 6 m].mean()", "3 m");
+    expect_output("[1 m, 2 m, 6 m].mean().sqr()", "9 m²");
+    expect_output("[3, 1, 2].sort().head()", "1");
+    expect_output("\"numbat\".uppercase().str_append(\"!\")", "\"NUMBAT!\"");
+    expect_output("fn add(a, b) = a + b\n(1 m).add(2 m).add(3 m)", "6 m");
+
+    // Struct fields take priority over functions with the same name
+    expect_output(
+        "
+        struct Shape { area: Fn[(Length) -> Area], name: String }
+        fn area(shape: Shape, x: Length) -> Area = 0 m²
+        let square = Shape { area: sqr, name: \"square\" }
+        square.area(3 m)
+        ",
+        "9 m²",
     );
+
     expect_failure(
-        "date(\"2000-01-01\") + 15_000 years",
-        "DateTime out of range",
+        "[1, 2].not_a_function()",
+        "Unknown identifier 'not_a_function'",
+    );
+    expect_failure("[1, 2].mean(3)", "'mean' called with 2 arguments(s)");
+}
+
+#[test]
+fn test_default_parameters() {
+    let rounding =
+        "fn round_to(x: Length, step: Length = 1 cm) -> Length = round(x / step) * step\n";
+
+    expect_output(&format!("{rounding}round_to(1.2345 m)"), "123 cm");
+    expect_output(&format!("{rounding}round_to(1.2345 m, 10 cm)"), "120 cm");
+    expect_output(&format!("{rounding}(1.2345 m).round_to()"), "123 cm");
+
+    // Default values can refer to previous parameters
+    let scaled = "fn scaled(a: Scalar, b: Scalar = a * 2) = a + b\n";
+    expect_output(&format!("{scaled}scaled(3)"), "9");
+    expect_output(&format!("{scaled}scaled(3, 1)"), "4");
+
+    // Default values are evaluated in every call
+    expect_output(
+        "fn wrap(x, xs: List<Scalar> = [x]) = len(xs) + x\nwrap(1) + wrap(1, [1, 2, 3])",
+        "6",
+    );
+
+    expect_output("fn f(x: Length = 1 m) -> Length = x\nf()", "1 m");
+    expect_pretty_print(
+        "fn f(x: Length, y: Length = 2 x) = x + y",
+        "fn f(x: Length, y: Length = 2 x) -> Length = x + y",
     );
+
     expect_failure(
-        "format_datetime(\"%Y-%m-%dT%H%:M\", now())",
-        "Error in datetime format",
-    )
+        "fn f(x: Length, y: Length = 2 s) = x + y",
+        "actual dimension: Time",
+    );
+    expect_failure(
+        "fn f(x: Length, y: Length = 1 m) = x + y\nf()",
+        "called with 0 arguments(s), but needs 1..2",
+    );
+    expect_failure(
+        "fn f(x = 1, y) = x + y",
+        "Parameters without a default value",
+    );
 }
 
 #[test]
-fn test_user_errors() {
-    expect_failure("error(\"test\")", "User error: test");
+fn test_named_arguments() {
+    let steps =
+        "fn steps(start: Length, stop: Length, n: Scalar = 2) -> Length = (stop - start) / n\n";
+
+    expect_output(
+        &format!("{steps}steps(start = 0 m, stop = 10 m, n = 5)"),
+        "2 m",
+    );
+    expect_output(
+        &format!("{steps}steps(n = 5, stop = 10 m, start = 0 m)"),
+        "2 m",
+    );
+    expect_output(&format!("{steps}steps(0 m, n = 5, stop = 10 m)"), "2 m");
+    expect_output(&format!("{steps}steps(0 m, stop = 10 m)"), "5 m");
+    expect_output(&format!("{steps}(0 m).steps(stop = 10 m, n = 10)"), "1 m");
+    expect_output(&format!("{steps}(10 m |> steps(0 m, n = 10))"), "1 m");
+
+    // Parameters with default values can be skipped
+    expect_output(
+        "fn f(x: Scalar, y: Scalar = 2 x, z: Scalar = 3) = x + y + z\nf(1, z = 10)",
+        "13",
+    );
+
+    // Functions returning structs
+    expect_output(
+        "struct Range { lo: Scalar, hi: Scalar }
+         fn make_range(hi: Scalar, lo: Scalar = 0) = Range { lo: lo, hi: hi }
+         make_range(hi = 3).hi - make_range(lo = 1, hi = 3).lo",
+        "2",
+    );
+
+    // Foreign functions
+    expect_output("str_slice(\"numbat\", end = 3, start = 0)", "\"num\"");
+
+    expect_pretty_print(
+        "str_slice(\"numbat\", end = 3, start = 0)",
+        "str_slice(\"numbat\", start = 0, end = 3)",
+    );
 
-    // Make sure that error(…) can be used in all contexts
-    expect_failure("- error(\"test\")", "User error: test");
-    expect_failure("1 + error(\"test\")", "User error: test");
-    expect_failure("1 m + error(\"test\")", "User error: test");
-    expect_failure("if 3 < 2 then 2 m else error(\"test\")", "User error: test");
     expect_failure(
-        "if true then error(\"test\") else \"foo\"",
-        "User error: test",
+        &format!("{steps}steps(0 m, start = 1 m, stop = 10 m)"),
+        "Argument for parameter 'start' is specified more than once",
     );
     expect_failure(
-        "if false then \"foo\" else error(\"test\")",
-        "User error: test",
+        &format!("{steps}steps(0 m, n = 5)"),
+        "Missing argument for parameter 'stop' in call to function 'steps'",
+    );
+    expect_failure(
+        &format!("{steps}steps(0 m, stpo = 10 m)"),
+        "Function 'steps' does not have a parameter named 'stpo'",
+    );
+    expect_failure("steps(0 m, 10 m, n = 5)", "Unknown identifier 'steps'");
+    expect_failure(
+        "steps(n = 5, 0 m)",
+        "Positional arguments can not follow named arguments",
+    );
+
+    // Procedures like `assert_eq` take a variable number of arguments, and do not
+    // support named arguments
+    expect_failure(
+        "assert_eq(1 m, 100 cm, eps = 1 mm)",
+        "Procedures can not be called with named arguments",
     );
 }
 
 #[test]
-fn test_recovery_after_runtime_error() {
-    {
-        let mut ctx = get_test_context();
+fn test_partial_application() {
+    let force = "fn force(mass: Mass, acc: Acceleration) -> Force = mass × acc -> N\n";
 
-        expect_failure_with_context(&mut ctx, "let x = 1/0", "Division by zero");
-        expect_failure_with_context(&mut ctx, "x", "Unknown identifier 'x'");
-    }
-    {
-        let mut ctx = get_test_context();
+    expect_output(
+        &format!("{force}let f = partial(force, 2 kg)\nf(3 m/s²)"),
+        "6 N",
+    );
+    expect_output(
+        &format!("{force}map(partial(force, 2 kg), [1 m/s², 2 m/s²])"),
+        "[2 N, 4 N]",
+    );
+    expect_output(
+        &format!("{force}partial(force, 2 kg)"),
+        "<partially applied function: force>",
+    );
+    expect_output(
+        &format!("{force}partial(force, 2 kg, 1 g0)() -> N"),
+        "19.6133 N",
+    );
+    expect_output("partial(sqrt, 9 m²)()", "3 m");
 
-        expect_failure_with_context(&mut ctx, "let x = 1/0", "Division by zero");
-        assert!(ctx
-            .interpret("let x = 1", CodeSource::Internal)
-            .unwrap()
-            .1
-            .is_continue());
-        expect_output_with_context(&mut ctx, "x", "1");
-    }
+    // Nested partial applications
+    let volume = "fn volume(x: Length, y: Length, z: Length) -> Volume = x × y × z\n";
+    expect_output(
+        &format!("{volume}let v = partial(partial(volume, 1 m), 2 m)\nv(3 m)"),
+        "6 m³",
+    );
+    expect_output(
+        &format!("{volume}fn bind_first(f: Fn[(Length, Length) -> Volume]) = partial(f, 1 m)\nbind_first(partial(volume, 2 m))(3 m)"),
+        "6 m³",
+    );
+
+    // Named arguments bind parameters that are not in front
+    expect_output(
+        &format!("{volume}partial(volume, y = 2 m)(1 m, 3 m)"),
+        "6 m³",
+    );
+    expect_output(
+        &format!("{volume}partial(volume, 1 m, z = 3 m)(2 m)"),
+        "6 m³",
+    );
+    expect_pretty_print("partial(str_slice, end = 3)", "partial(str_slice, end = 3)");
+
+    expect_failure(
+        &format!("{force}partial(force, 2 kg, 1 g0, 3)"),
+        "Too many arguments in partial application: the function takes 2, but 3 are given",
+    );
+    expect_failure(
+        &format!("{force}partial(force, 2 m)"),
+        "Incompatible types in function call: expected 'Mass', got 'Length' instead",
+    );
+    expect_failure(
+        "fn bind_first(f, x) = partial(f, x)",
+        "The type of the function in a partial application needs to be known",
+    );
+    expect_failure(
+        &format!("{volume}let v = partial(volume, 1 m)\npartial(v, z = 1 m)"),
+        "Named arguments can only be used when calling a function by its name",
+    );
+    expect_failure(
+        "partial()",
+        "Expected a function as the first argument of 'partial'",
+    );
 }
 
 #[test]
-fn test_statement_pretty_printing() {
-    // Let definitions
-    expect_pretty_print("let v = 10 m/s", "let v: Velocity = 10 metre / second");
-    expect_pretty_print(
-        "let v: Length * Frequency = 10 m/s",
-        "let v: Length × Frequency = 10 metre / second",
+fn test_methods() {
+    let circle = "struct Circle { radius: Length }
+        impl Circle {
+            fn area(self) -> Area = pi × self.radius^2
+            fn scaled(self, factor: Scalar) -> Circle = Circle { radius: factor × self.radius }
+        }
+        let c = Circle { radius: 2 m }
+        ";
+
+    expect_output(&format!("{circle}c.area() -> m²"), "12.5664 m²");
+    expect_output(&format!("{circle}c.scaled(3).area() -> m²"), "113.097 m²");
+    expect_output(&format!("{circle}c.scaled(factor = 3).radius"), "6 m");
+
+    // Free functions of the same name are still used for other receivers
+    expect_output(
+        &format!(
+            "{circle}fn area(side: Length) -> Area = side^2
+(3 m).area() + c.area() -> m²"
+        ),
+        "21.5664 m²",
+    );
+    expect_output(
+        &format!(
+            "{circle}fn area(side: Length) -> Area = side^2
+area(3 m)"
+        ),
+        "9 m²",
+    );
+    // … and for structs without a method of that name
+    expect_output(
+        "struct Square { side: Length }
+         fn area(sq: Square) -> Area = sq.side^2
+         Square { side: 3 m }.area()",
+        "9 m²",
     );
 
-    expect_pretty_print("let x = 0 + 1 m", "let x: Length = 0 + 1 metre");
+    // Methods can call themselves and other methods
+    expect_output(
+        "struct Counter { n: Scalar }
+         impl Counter {
+             fn count(self, k: Scalar) -> Scalar = if k == 0 then self.n else self.count(k - 1) + 1
+             fn twice(self) -> Scalar = 2 × self.count(1)
+         }
+         Counter { n: 2 }.twice()",
+        "6",
+    );
 
-    expect_pretty_print("let x: Length = 0", "let x: Length = 0");
-    expect_pretty_print("let x = 0", "let x: forall A: Dim. A = 0"); // TODO: This is not ideal. 'forall' is not valid Numbat syntax.
+    expect_failure(
+        &format!("{circle}c.perimeter()"),
+        "Struct 'Circle' has no method 'perimeter'",
+    );
+    expect_failure(
+        "impl Unknown { fn area(self) = 1 }",
+        "Unknown struct 'Unknown",
+    );
+    expect_failure(
+        "struct Circle { radius: Length }\nimpl Circle { fn area(c) = 1 }",
+        "The first parameter of a method needs to be 'self'",
+    );
+}
 
-    // Derived unit definitions
-    expect_pretty_print(
-        "unit my_length_base_unit: Length",
-        "unit my_length_base_unit: Length",
+#[test]
+fn test_autodiff() {
+    // Polynomials
+    expect_output(
+        "fn area(x: Length) -> Area = 3 x^2 + 2 m × x + 4 m²
+         autodiff(area)(2 m)",
+        "14 m",
     );
-    expect_pretty_print(
-        "unit my_custom_base_unit",
-        "unit my_custom_base_unit: MyCustomBaseUnit",
+    expect_output(
+        "fn p(x) = x^5 - 2 x^3 + 1
+         [autodiff(p)(2), 5 × 2^4 - 6 × 2^2]",
+        "[56, 56]",
     );
-    expect_pretty_print(
-        "unit my_speed_unit = 10 m/s",
-        "unit my_speed_unit: Velocity = 10 metre / second",
+    expect_output(
+        "fn p(x: Length) -> Area = x^2
+         autodiff(autodiff(p))(3 m)",
+        "2",
     );
 
-    // Function definitions
-    expect_pretty_print(
-        "fn f(v)=(v+1 knot)/1s",
-        "fn f(v: Velocity) -> Acceleration = (v + 1 knot) / 1 second",
+    // exp, ln and trigonometric functions match the closed forms to machine precision
+    expect_output(
+        "fn fa(x) = sin(x) × exp(x)
+         let derivative = autodiff(fa)
+         fn difference(x) = derivative(x) - (cos(x) exp(x) + sin(x) exp(x))
+         map(difference, [-2, -0.5, 0.1, 1, 3.7])",
+        "[0, 0, 0, 0, 0]",
+    );
+    expect_output(
+        "fn fa(x) = ln(cos(x)) + tan(x)^2
+         fn closed(x) = -tan(x) + 2 tan(x) / cos(x)^2
+         let derivative = autodiff(fa)
+         fn relative_error(x) = abs(derivative(x) - closed(x)) / abs(closed(x))
+         max_of(map(relative_error, [0.1, 0.5, 1.2])) < 1e-15",
+        "true",
     );
+    expect_output(
+        "fn fa(x) = atan(x) + asinh(x) + exp(-x^2)
+         fn closed(x) = 1 / (1 + x^2) + 1 / sqrt(x^2 + 1) - 2 x exp(-x^2)
+         let derivative = autodiff(fa)
+         abs(derivative(0.7) - closed(0.7)) < 1e-15",
+        "true",
+    );
+    expect_output("autodiff(sin)(0)", "1");
+    expect_output("autodiff(ln)(4)", "0.25");
+    expect_output("fn fa(x: Scalar) = x^x\nautodiff(fa)(1)", "1");
 
-    expect_pretty_print("fn f(x) = x", "fn f<A>(x: A) -> A = x");
+    // Calls of other functions are differentiated with the chain rule
+    expect_output(
+        "fn inner(x, a = 2) = a x^2
+         fn outer(x) = inner(3 x) + inner(x, a = 1)
+         autodiff(outer)(1)",
+        "38",
+    );
+    expect_output(
+        "fn fa(x) = y^2 where y = 3 x
+         autodiff(fa)(1)",
+        "18",
+    );
 
-    expect_pretty_print("fn f(x, y) = y", "fn f<A, B>(x: A, y: B) -> B = y");
-    expect_pretty_print("fn f(x, y) = x", "fn f<A, B>(x: B, y: A) -> B = x"); // TODO: This is correct, but it would be nice to associate 'x' to 'A', not 'B'.
+    // The dimension of the derivative is the dimension of the output divided by
+    // the dimension of the input
+    expect_output(
+        "fn position(t: Time) -> Length = 5 m/s² × t^2 / 2 + 3 m/s × t
+         autodiff(position)(2 s) -> km/h",
+        "46.8 km/h",
+    );
+    expect_output(
+        "fn position(t: Time) -> Length = 5 m/s² × t^2 / 2 + 3 m/s × t
+         let velocity: Fn[(Time) -> Velocity] = autodiff(position)
+         let acceleration: Fn[(Time) -> Acceleration] = autodiff(autodiff(position))
+         velocity(2 s) + acceleration(2 s) × 1 s",
+        "18 m/s",
+    );
+    expect_failure(
+        "fn position(t: Time) -> Length = 3 m/s × t
+         let velocity: Fn[(Time) -> Length] = autodiff(position)",
+        "Could not solve the following constraints",
+    );
 
-    expect_pretty_print("fn f(x) = 2 x", "fn f<A: Dim>(x: A) -> A = 2 x");
+    // Unsupported constructs
+    expect_failure(
+        "fn fa(x) = if x > 0 then x else -x
+         autodiff(fa)",
+        "'fa' can not be differentiated: a condition that depends on 'x' is not supported",
+    );
+    expect_failure(
+        "fn fa(x) = [x, 2 x]
+         autodiff(fa)",
+        "'fa' can not be differentiated: a list is not supported",
+    );
+    expect_failure(
+        "fn fa(x) = sum([x, 2 x])
+         autodiff(fa)",
+        "'fa' can not be differentiated: the function 'sum' is not supported",
+    );
+    expect_failure(
+        "autodiff(floor)",
+        "'floor' can not be differentiated: a foreign function without a known derivative is not supported",
+    );
+    expect_failure(
+        "fn fa(x, y) = x y
+         autodiff(fa)",
+        "'autodiff' needs the name of a function with a single parameter",
+    );
+}
 
-    // Partially annotated functions
-    expect_pretty_print(
-        "fn f() -> Length * Frequency = c",
-        "fn f() -> Length × Frequency = c",
+#[test]
+fn test_formulas() {
+    // Ohm's law, solved for each of the three quantities
+    let ohms_law = "formula ohms_law: voltage = current * resistance\n";
+    expect_output(&format!("{ohms_law}ohms_law_voltage(2 A, 3 Ω) -> V"), "6 V");
+    expect_output(&format!("{ohms_law}ohms_law_current(6 V, 3 Ω) -> A"), "2 A");
+    expect_output(
+        &format!("{ohms_law}ohms_law_resistance(6 V, 2 A) -> Ω"),
+        "3 Ω",
     );
-    expect_pretty_print(
-        "fn f(v: Length * Frequency) = 1",
-        "fn f(v: Length × Frequency) -> Scalar = 1",
+    expect_output(
+        &format!("{ohms_law}ohms_law_current(resistance = 2 kΩ, voltage = 5 V) -> mA"),
+        "2.5 mA",
     );
 
-    expect_pretty_print("fn f(x: Length) = 2 x", "fn f(x: Length) -> Length = 2 x");
-    expect_pretty_print("fn f(x) -> Length = 2 x", "fn f(x: Length) -> Length = 2 x");
+    // The dimensions of the quantities can be declared, and are checked
+    let ohms_law =
+        "formula ohms_law(voltage: Voltage, current: Current, resistance: ElectricResistance):
+            voltage = current × resistance\n";
+    expect_output(
+        &format!("{ohms_law}ohms_law_current(12 V, 4 Ω) -> A"),
+        "3 A",
+    );
+    expect_failure(
+        &format!("{ohms_law}ohms_law_current(12 V, 4 s)"),
+        "argument type: Time",
+    );
+    expect_failure(
+        "formula ohms_law(voltage: Voltage, current: Current, resistance: Time):
+            voltage = current × resistance",
+        "[= ElectricCharge]",
+    );
 
-    // TODO:
-    // expect_pretty_print("fn f<Z>(z: Z) = z", "fn f<Z>(z: Z) -> Z = z");
+    // Powers, where solving for the velocity requires a square root
+    let kinetic_energy = "formula kinetic_energy(energy: Energy, mass: Mass, velocity: Velocity):
+            energy = 1/2 × mass × velocity^2\n";
+    expect_output(
+        &format!("{kinetic_energy}kinetic_energy_velocity(50 J, 4 kg) -> m/s"),
+        "5 m/s",
+    );
+    expect_output(
+        &format!("{kinetic_energy}kinetic_energy_mass(50 J, 5 m/s) -> kg"),
+        "4 kg",
+    );
+    expect_output(
+        &format!("{kinetic_energy}kinetic_energy_energy(4 kg, 5 m/s) -> J"),
+        "50 J",
+    );
+    expect_output(
+        "formula cube(volume: Volume, side: Length): volume = side^3
+         cube_side(8 m³)",
+        "2 m",
+    );
+
+    // Sums, differences and quotients
+    let motion = "formula motion(distance: Length, start: Length, speed: Velocity, duration: Time):
+            distance = start + speed × duration\n";
+    expect_output(&format!("{motion}motion_start(100 m, 5 m/s, 10 s)"), "50 m");
+    expect_output(
+        &format!("{motion}motion_duration(100 m, 20 m, 4 m/s)"),
+        "20 s",
+    );
+    expect_output(
+        "formula density(rho: MassDensity, mass: Mass, volume: Volume): rho = mass / volume
+         density_volume(1000 kg/m³, 2 kg) -> L",
+        "2 l",
+    );
+    expect_output(
+        "formula f: a = b - 3 × c
+         [f_b(1, 2), f_c(1, 7)]",
+        "[7, 2]",
+    );
+
+    // Quantities that can not be isolated
+    expect_failure(
+        "formula growth: n = n0 × exp(rate × t)",
+        "The formula 'growth' can not be solved for 'rate': it occurs in the argument of a function call",
+    );
+    expect_failure(
+        "formula compound: a = p × r^t",
+        "The formula 'compound' can not be solved for 't': it occurs in an exponent",
+    );
+    expect_failure(
+        "formula f: y = x × x",
+        "The formula 'f' can not be solved for 'x': it occurs more than once",
+    );
+    expect_failure(
+        "formula f(x, y, z): y = 2 x",
+        "The formula 'f' can not be solved for 'z': it does not occur in the equation",
+    );
+    expect_failure("formula f y = x", "Expected the equation of the formula");
+}
+
+#[test]
+fn test_dimension_predicates() {
+    // Predicates are decided for every call of a generic function
+    expect_output(
+        "fn describe<D: Dim>(x: D) -> String =
+           if is_dimensionless(x) then \"a number\"
+           else if has_dimension(x, Length) then \"a length\"
+           else if has_dimension(x, Length / Time) then \"a speed\"
+           else \"something else\"
+         [describe(3), describe(2 m), describe(5 ft), describe(3 km/h), describe(2 s)]",
+        "[\"a number\", \"a length\", \"a length\", \"a speed\", \"something else\"]",
+    );
+    expect_output(
+        "is_dimensionless(3 m / ft) && !has_dimension(2 s, Length)",
+        "true",
+    );
+
+    // Generic functions that call such functions are specialized as well
+    expect_output(
+        "fn is_length<D: Dim>(x: D) -> Bool = has_dimension(x, Length)
+         fn count_lengths<A: Dim, B: Dim>(a: A, b: B) -> Scalar =
+           (if is_length(a) then 1 else 0) + (if is_length(b) then 1 else 0)
+         [count_lengths(1 m, 2 s), count_lengths(1 m, 2 km), count_lengths(1, 2)]",
+        "[1, 2, 0]",
+    );
+
+    // Only the branch that is taken is checked, so the other one may be
+    // ill-typed for this specialization
+    expect_output(
+        "fn to_meters<D: Dim>(x: D) -> Scalar = if has_dimension(x, Length) then x / m else x
+         [to_meters(3 km), to_meters(4)]",
+        "[3000, 4]",
+    );
+    expect_failure(
+        "fn to_meters<D: Dim>(x: D) -> Scalar = if has_dimension(x, Length) then x / m else x
+         to_meters(2 s)",
+        "actual return type: Time",
+    );
+
+    // Errors
+    expect_failure(
+        "has_dimension(2 m, 3)",
+        "Expected a dimension as the second argument of 'has_dimension'",
+    );
+    expect_failure("has_dimension(2 m, meter)", "Unknown entry 'meter'");
+    expect_failure(
+        "fn is_scalar(x) = is_dimensionless(x)",
+        "The dimension of this argument is not known",
+    );
+    expect_failure(
+        "fn is_scalar<D: Dim>(x: D) = is_dimensionless(x)",
+        "Function 'is_scalar' uses dimension predicates, so its parameters and its return type need type annotations",
+    );
+    expect_failure(
+        "fn is_scalar<D: Dim>(x: D) -> Bool = is_dimensionless(x)
+         map(is_scalar, [1, 2])",
+        "Function 'is_scalar' uses dimension predicates, so it can only be called directly",
+    );
+    expect_failure(
+        "fn is_scalar<T>(x: T) -> Bool = is_dimensionless(x)
+         is_scalar(\"a\")",
+        "Function 'is_scalar' can not be specialized for 'String' as type parameter 'T'",
+    );
+}
+
+#[test]
+fn test_unknown_method_lists_available_methods() {
+    let mut ctx = get_test_context();
+    let _ = ctx
+        .interpret(
+            "struct Circle { radius: Length }
+             impl Circle {
+                 fn area(self) -> Area = pi × self.radius^2
+                 fn diameter(self) -> Length = 2 self.radius
+             }",
+            CodeSource::Internal,
+        )
+        .unwrap();
+
+    let error = ctx
+        .interpret("Circle { radius: 1 m }.perimeter()", CodeSource::Internal)
+        .unwrap_err();
+    assert_has_error_codes(&error);
+    assert_eq!(
+        error.to_string(),
+        "Struct 'Circle' has no method 'perimeter'"
+    );
+    assert_eq!(
+        error.diagnostics()[0].notes,
+        ["Available methods: area, diameter"]
+    );
+
+    assert_eq!(
+        ctx.print_info_for_keyword("Circle").to_string(),
+        "Struct: Circle\nFields: radius: Length\nMethods: area, diameter\n"
+    );
+    assert!(ctx
+        .get_completions_for("diam", true)
+        .any(|c| c == "diameter("));
+}
+
+#[test]
+fn test_operator_overloading() {
+    let mut ctx = get_test_context();
+
+    let _ = ctx
+        .interpret(
+            "
+            struct Vector2 { x: Length, y: Length }
+            struct Other { x: Length, y: Length }
+
+            @operator(+)
+            fn vector_add(a: Vector2, b: Vector2) -> Vector2 = Vector2 { x: a.x + b.x, y: a.y + b.y }
+
+            @operator(-)
+            fn vector_sub(a: Vector2, b: Vector2) -> Vector2 = Vector2 { x: a.x - b.x, y: a.y - b.y }
+
+            @operator(*)
+            fn vector_scale(k: Scalar, v: Vector2) -> Vector2 = Vector2 { x: k * v.x, y: k * v.y }
+
+            @operator(*)
+            fn vector_scale_right(v: Vector2, k: Scalar) -> Vector2 = k * v
+
+            let v = Vector2 { x: 1 m, y: 2 m }
+            let w = Vector2 { x: 3 m, y: 5 m }
+            ",
+            CodeSource::Internal,
+        )
+        .unwrap();
+
+    expect_output_with_context(&mut ctx, "v + w", "Vector2 { x: 4 m, y: 7 m }");
+    expect_output_with_context(&mut ctx, "w - v", "Vector2 { x: 2 m, y: 3 m }");
+    expect_output_with_context(&mut ctx, "2 * v", "Vector2 { x: 2 m, y: 4 m }");
+    expect_output_with_context(&mut ctx, "v * 2", "Vector2 { x: 2 m, y: 4 m }");
+    expect_output_with_context(&mut ctx, "(v + 2 * w).y", "12 m");
+
+    // Numeric operators are not affected
+    expect_output_with_context(&mut ctx, "2 m + 3 m", "5 m");
+
+    expect_failure_with_context(
+        &mut ctx,
+        "v / 2",
+        "No overload of operator '/' for types 'Vector2",
+    );
+    expect_failure_with_context(&mut ctx, "v + 1 m", "No overload of operator '+'");
+    expect_failure_with_context(
+        &mut ctx,
+        "Other { x: 1 m, y: 1 m } + Other { x: 1 m, y: 1 m }",
+        "No overload of operator '+' for types 'Other",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "Other { x: 1 m, y: 1 m } + v",
+        "No overload of operator '+' for types 'Other",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "2 m * v",
+        "No overload of operator '×' for types 'Length' and 'Vector2",
+    );
+
+    // Overloads can differ in a parameter that is not a struct
+    expect_output_with_context(
+        &mut ctx,
+        "
+        @operator(/)
+        fn vector_per_length(v: Vector2, length: Length) -> Scalar = v.x / length
+        @operator(/)
+        fn vector_per_time(v: Vector2, time: Time) -> Velocity = v.x / time
+        v / 2 m
+        ",
+        "0.5",
+    );
+    expect_output_with_context(&mut ctx, "v / 2 s -> m/s", "0.5 m/s");
+
+    expect_failure_with_context(
+        &mut ctx,
+        "
+        @operator(+)
+        fn vector_add_again(a: Vector2, b: Vector2) -> Vector2 = b
+        v + w
+        ",
+        "Ambiguous overloads of operator '+': vector_add, vector_add_again",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "@operator(+) fn scalar_add(a: Scalar, b: Scalar) -> Scalar = a",
+        "Operator overload 'scalar_add' needs exactly two parameters",
+    );
+
+    // Redefining a function without the decorator removes the overload
+    expect_failure_with_context(
+        &mut ctx,
+        "
+        fn vector_sub(a: Vector2, b: Vector2) -> Vector2 = a
+        w - v
+        ",
+        "No overload of operator '-'",
+    );
+}
+
+#[test]
+fn test_lazy_sequences() {
+    let mut ctx = get_test_context();
+
+    let _ = ctx
+        .interpret(
+            "
+            fn square(x) = x^2
+            fn is_even(x) = mod(x, 2) == 0
+            fn add(x, y) = x + y
+            struct Wrapper { values: List<Scalar> }
+            ",
+            CodeSource::Internal,
+        )
+        .unwrap();
+
+    // Lazy and eager evaluation give the same results
+    expect_output_with_context(
+        &mut ctx,
+        "map(square, range(1, 5)) == map(square, [1, 2, 3, 4, 5])",
+        "true",
+    );
+    expect_output_with_context(
+        &mut ctx,
+        "filter(is_even, range(1, 6)) == filter(is_even, [1, 2, 3, 4, 5, 6])",
+        "true",
+    );
+    expect_output_with_context(
+        &mut ctx,
+        "foldl(add, 0, range(1, 4)) == foldl(add, 0, [1, 2, 3, 4])",
+        "true",
+    );
+    expect_output("linspace(1 m, 2 m, 3) == [1 m, 1.5 m, 2 m]", "true");
+    expect_output("len(range(3, 1))", "0");
+
+    // Sequences are forced when printed or stored in structs and lists
+    expect_output("range(1, 4)", "[1, 2, 3, 4]");
+    expect_output("\"{range(1, 3)}\"", "\"[1, 2, 3]\"");
+    expect_output("[range(1, 2), range(3, 4)]", "[[1, 2], [3, 4]]");
+    expect_output_with_context(
+        &mut ctx,
+        "Wrapper { values: map(square, range(1, 3)) }",
+        "Wrapper { values: [1, 4, 9] }",
+    );
+
+    // Indexing does not materialize the sequence
+    expect_output("element_at(5, range(10, 1e12))", "15");
+    expect_output("len(range(1, 1e12))", "1_000_000_000_000");
+    expect_output_with_context(
+        &mut ctx,
+        "element_at(2, filter(is_even, map(square, range(1, 1e12))))",
+        "36",
+    );
+    expect_output_with_context(&mut ctx, "head(tail(filter(is_even, range(1, 1e12))))", "4");
+    expect_failure("element_at(3, range(1, 3))", "Empty list");
+
+    // Folding over a large range runs in constant memory
+    expect_output_with_context(
+        &mut ctx,
+        "sum(map(square, range(1, 100_000)))",
+        "333_338_333_350_000",
+    );
+}
+
+#[test]
+fn test_sorting_and_searching() {
+    let mut ctx = get_test_context();
+
+    let _ = ctx
+        .interpret(
+            "
+            struct Member { name: String, height: Length }
+            fn get_height(p: Member) -> Length = p.height
+            fn get_name(p: Member) -> String = p.name
+
+            let team = [
+              Member { name: \"Alice\", height: 170 cm },
+              Member { name: \"Bob\", height: 1.6 m },
+              Member { name: \"Carol\", height: 1700 mm },
+              Member { name: \"Dave\", height: 1.8 m }
+            ]
+            ",
+            CodeSource::Internal,
+        )
+        .unwrap();
+
+    expect_output_with_context(
+        &mut ctx,
+        "map(get_name, sort_by(get_height, team))",
+        "[\"Bob\", \"Alice\", \"Carol\", \"Dave\"]",
+    );
+
+    // Sorting is stable: elements with equal keys keep their relative order
+    expect_output_with_context(
+        &mut ctx,
+        "map(get_name, sort_by(get_height, reverse(team)))",
+        "[\"Bob\", \"Carol\", \"Alice\", \"Dave\"]",
+    );
+
+    expect_output("sort([1 km, 20 m, 3 cm])", "[3 cm, 20 m, 1 km]");
+    expect_output("sort([3, NaN, 1])", "[1, 3, NaN]");
+
+    expect_output("contains(100 cm, [1 m, 2 m])", "true");
+    expect_output("contains(3 m, [1 m, 2 m])", "false");
+    expect_output("index_of(2000 mm, [1 m, 2 m])", "1");
+    expect_output("index_of(3 m, [1 m, 2 m])", "-1");
+
+    expect_failure_with_context(&mut ctx, "sort(team)", "Values of type 'Member {");
+    expect_failure(
+        "fn my_sort<A>(xs: List<A>) -> List<A> = sort(xs)",
+        "Missing 'Ord' bound for type parameter",
+    );
+}
+
+#[test]
+fn test_destructuring() {
+    let mut ctx = get_test_context();
+
+    let _ = ctx
+        .interpret(
+            "
+            struct Point { x: Length, y: Length }
+            struct Particle { position: Point, mass: Mass }
+            fn get_point() = Point { x: 3 m, y: 4 m }
+
+            let {x, y: width} = get_point()
+            let [a, b, c] = [1, 2, 3]
+            let {position: {x: pos_x, y: pos_y}, mass} = Particle { position: Point { x: 5 m, y: 6 m }, mass: 7 kg }
+            let [{x: x1, y: y1}, {x: x2, y: y2}] = [get_point(), Point { x: 1 m, y: 2 m }]
+            let [u, v] = [8 s, 9 s] |> map(sqr)
+            ",
+            CodeSource::Internal,
+        )
+        .unwrap();
+
+    expect_output_with_context(&mut ctx, "x", "3 m");
+    expect_output_with_context(&mut ctx, "width", "4 m");
+    expect_output_with_context(&mut ctx, "[c, b, a]", "[3, 2, 1]");
+    expect_output_with_context(&mut ctx, "[pos_x, pos_y]", "[5 m, 6 m]");
+    expect_output_with_context(&mut ctx, "mass", "7 kg");
+    expect_output_with_context(&mut ctx, "[x1, y1, x2, y2]", "[3 m, 4 m, 1 m, 2 m]");
+    expect_output_with_context(&mut ctx, "v", "81 s²");
+
+    // Nested patterns in local variables of functions
+    expect_output_with_context(
+        &mut ctx,
+        "fn norm(p: Point) -> Length = sqrt(sqr(x) + sqr(y)) where {x, y} = p
+         norm(get_point())",
+        "5 m",
+    );
+    expect_output_with_context(
+        &mut ctx,
+        "fn total(xs: List<Length>) -> Length = l1 + l2 where [l1, l2] = xs
+         total([1 m, 2 m])",
+        "3 m",
+    );
+
+    // The length of lists is checked at runtime
+    expect_failure_with_context(
+        &mut ctx,
+        "let [d1, d2] = [1, 2, 3]",
+        "Can not destructure a list with 3 element(s) into 2 variable(s)",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "total([1 m])",
+        "Can not destructure a list with 1 element(s) into 2 variable(s)",
+    );
+
+    expect_failure(
+        "struct Point { x: Length, y: Length }
+         let {x1} = Point { x: 1 m, y: 2 m }",
+        "Field 'x1' does not exist in struct 'Point",
+    );
+    expect_failure(
+        "struct Point { x: Length, y: Length }
+         let {x: x1} = Point { x: 1 m, y: 2 m }",
+        "Missing fields in destructuring pattern",
+    );
+    expect_failure("let {x1, y1} = 2 m", "non struct type 'Length'");
+    expect_failure(
+        "let [a1, a1] = [1, 2]",
+        "Identifier 'a1' is bound more than once",
+    );
+}
+
+#[test]
+fn test_struct_update() {
+    let mut ctx = get_test_context();
+
+    let _ = ctx
+        .interpret(
+            "
+            struct Point { x: Length, y: Length }
+            struct Particle { position: Point, mass: Mass }
+            struct Other { x: Length }
+
+            let p = Point { x: 1 m, y: 2 m }
+            let particle = Particle { position: p, mass: 3 kg }
+            ",
+            CodeSource::Internal,
+        )
+        .unwrap();
+
+    expect_output_with_context(
+        &mut ctx,
+        "Point { p with x = 3 m }",
+        "Point { x: 3 m, y: 2 m }",
+    );
+    expect_output_with_context(
+        &mut ctx,
+        "Point { p with y = 5 m, x = 4 m }",
+        "Point { x: 4 m, y: 5 m }",
+    );
+    expect_output_with_context(&mut ctx, "Point { p with }", "Point { x: 1 m, y: 2 m }");
+
+    // The base instance is not modified
+    expect_output_with_context(&mut ctx, "p", "Point { x: 1 m, y: 2 m }");
+
+    // Updating a nested struct field
+    expect_output_with_context(
+        &mut ctx,
+        "Particle { particle with position = Point { particle.position with y = 4 m } }.position",
+        "Point { x: 1 m, y: 4 m }",
+    );
+    expect_output_with_context(
+        &mut ctx,
+        "Particle { particle with position = Point { particle.position with y = 4 m } }.mass",
+        "3 kg",
+    );
+
+    expect_failure_with_context(
+        &mut ctx,
+        "Point { Other { x: 1 m } with x = 2 m }",
+        "Incompatible base in struct update: expected 'Point",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "Point { p with z = 2 m }",
+        "Field 'z' does not exist in struct 'Point'",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "Point { p with x = 2 s }",
+        "Incompatible types for struct field: expected 'Length', got 'Time' instead",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "Point { p with x: 2 m }",
+        "Expected '=' after a field name in struct update",
+    );
+}
+
+#[test]
+fn test_pretty_print_prefixes() {
+    expect_output("1 megabarn", "1 megabarn");
+}
+
+#[test]
+fn test_percentages() {
+    expect_output("15 % * 200 m", "30 m");
+    expect_output("200 m * 15%", "30 m");
+    expect_output("0.153 -> %", "15.3 %");
+    expect_output("0.153 -> ‰", "153 ‰");
+    expect_output("3 ppm -> ppb", "3000 ppb");
+    expect_output("50 ppm -> %", "0.005 %");
+    expect_output("100 m * (1 + 5 %)", "105 m");
+
+    // Spacing does not matter
+    expect_output("5% == 5 %", "true");
+    expect_output("let rate = 4\nrate% -> %", "4 %");
+
+    expect_failure(
+        "100 m + 5 %",
+        "Suggested fix: to change the left hand side by 5 %, multiply it by `(1 + 5 %)`",
+    );
+    expect_failure(
+        "100 m - 2.5 ‰",
+        "Suggested fix: to change the left hand side by 2.5 ‰, multiply it by `(1 - 2.5 ‰)`",
+    );
+    expect_failure("5 % + 100 m", "Suggested fix: divide the expression");
+}
+
+#[test]
+fn test_logarithmic_levels() {
+    expect_output("decibel(100)", "20");
+    expect_output("from_decibel(decibel(100))", "100");
+    expect_output("decibel_power(2 W, 1 W)", "3.0103");
+    expect_output("from_decibel_power(30, 1 mW) -> W", "1 W");
+    expect_output(
+        "from_decibel_power(decibel_power(5 W, 1 mW), 1 mW) -> W",
+        "5 W",
+    );
+    expect_output("decibel_amplitude(200 µPa, 20 µPa)", "20");
+    expect_output("from_decibel_amplitude(20, 20 µPa) -> µPa", "200 µPa");
+    expect_output("neper(e)", "1");
+    expect_output("from_neper(neper(2))", "2");
+
+    // Angles are dimensionless
+    expect_output("sin(90 deg)", "1");
+    expect_output("cos(0.5 turn)", "-1");
+
+    expect_failure(
+        "ln(5 m)",
+        "Suggested fix: divide the function argument by a reference quantity of dimension `Length`, e.g. `ln(x / x0)`",
+    );
+    expect_failure("decibel(2 W)", "e.g. `decibel(x / x0)`");
+}
+
+fn strict_angle_context() -> Context {
+    let mut ctx = common::get_test_context_without_prelude();
+    ctx.set_strict_angles(true);
+    assert!(ctx.has_strict_angles());
+    if let Err(e) = ctx.interpret("use prelude", CodeSource::Internal) {
+        panic!("{e}");
+    }
+    ctx
+}
+
+#[test]
+fn test_strict_angles() {
+    // By default, angles are dimensionless
+    expect_output("sin(90 deg)", "1");
+    expect_output("sin(1.57)", "1");
+    expect_output("asin(1)", "1.5708");
+    expect_output("let t: Torque = 2 N m\nlet w: Energy = t\nw -> J", "2 J");
+
+    let mut ctx = strict_angle_context();
+
+    expect_output_with_context(&mut ctx, "sin(90 deg)", "1");
+    expect_output_with_context(&mut ctx, "cos(π rad)", "-1");
+    expect_output_with_context(&mut ctx, "asin(1) -> deg", "90°");
+    expect_output_with_context(&mut ctx, "atan2(1 m, 1 m) -> deg", "45°");
+    expect_output_with_context(&mut ctx, "acos(0)", "1.5708 rad");
+    expect_output_with_context(&mut ctx, "cot(45°)", "1.0");
+    expect_output_with_context(&mut ctx, "90 deg / rad", "1.5708");
+    expect_output_with_context(&mut ctx, "1 turn -> rad", "6.28319 rad");
+    expect_output_with_context(&mut ctx, "DMS(45.5 deg)", "\"45° 30′ 0″\"");
+
+    expect_failure_with_context(
+        &mut ctx,
+        "sin(1.57)",
+        "Suggested fix: specify the unit of the angle, e.g. `sin(x rad)` or `sin(x deg)`",
+    );
+    expect_failure_with_context(&mut ctx, "let x: Scalar = asin(1)", "Angle");
+    expect_failure_with_context(&mut ctx, "2 rad + 1", "Angle");
+
+    // Torque and energy can be told apart
+    let _ = ctx
+        .interpret(
+            "let t: Torque = 2 N m / rad\nlet w: Energy = t × 90 deg",
+            CodeSource::Internal,
+        )
+        .unwrap();
+    expect_output_with_context(&mut ctx, "w -> J", "3.14159 J");
+    expect_failure_with_context(&mut ctx, "let t2: Torque = 2 N m", "Torque");
+}
+
+#[test]
+fn test_number_literals() {
+    expect_output("1_000_000", "1_000_000");
+    expect_output("0x_ff", "255");
+    expect_output("0b_1010_1010", "170");
+    expect_output("1_000.000_1e-3", "1.0");
+    expect_output("1_000.5e3", "1_000_500");
+    expect_output("2e3 m", "2000 m");
+    expect_output("2e3m", "2000 m");
+    expect_output("2 e", "5.43656");
+
+    expect_failure("1__000", "Consecutive underscores in number literal");
+    expect_failure("1e", "Expected digits in exponent");
+    expect_failure("1e+", "Expected digits in exponent");
+
+    // Grouped literals survive a round trip through the pretty printer
+    for (code, pretty) in [
+        ("1_000_000 m", "1_000_000 metre"),
+        ("1234567", "1_234_567"),
+        ("0x_ffff_ffff", "4_294_967_295"),
+        ("2.5e-3 s", "0.0025 second"),
+        ("1.5e20", "1.5e+20"),
+    ] {
+        expect_pretty_print(code, pretty);
+        expect_pretty_print(pretty, pretty);
+    }
+}
+
+#[test]
+fn test_trace() {
+    let mut ctx = get_test_context();
+
+    let mut traced = BufferingSink::default();
+
+    let (_, result) = ctx
+        .interpret_with_sink(
+            &mut traced,
+            "fn f(x: Length) -> Length = trace(x^2) / meter
+             map(f, [1 m, 2 m, 3 m])",
+            CodeSource::Internal,
+        )
+        .unwrap();
+    assert_eq!(result.value_as_string().unwrap(), "[1 m, 4 m, 9 m]");
+    assert_eq!(
+        printed(&traced),
+        vec![
+            "x^2 = 1 m² : Length²",
+            "x^2 = 4 m² : Length²",
+            "x^2 = 9 m² : Length²",
+        ]
+    );
+
+    traced.take_events();
+    let (_, result) = ctx
+        .interpret_with_sink(
+            &mut traced,
+            "2 × trace(3 km + 40 m) -> m",
+            CodeSource::Internal,
+        )
+        .unwrap();
+    assert_eq!(result.value_as_string().unwrap(), "6080 m");
+    assert_eq!(printed(&traced), vec!["3 km + 40 m = 3.04 km : Length"]);
+
+    // In generic functions, the type at the call site is shown
+    traced.take_events();
+    let _ = ctx
+        .interpret_with_sink(
+            &mut traced,
+            "fn twice(x) = trace(x * 2)
+             twice(2 m)
+             twice(3 s)
+             map(twice, [1 kg])
+             fn same<T>(x: T) -> T = trace(x)
+             map(same, [true])",
+            CodeSource::Internal,
+        )
+        .unwrap();
+    assert_eq!(
+        printed(&traced),
+        vec![
+            "x * 2 = 4 m : Length",
+            "x * 2 = 6 s : Time",
+            "x * 2 = 2 kg : Mass",
+            "x = true : Bool",
+        ]
+    );
+
+    expect_output("trace(\"hello\")", "\"hello\"");
+    expect_output("trace(2 m) == 2 m", "true");
+
+    // `trace` is not a reserved word
+    expect_output("let trace = 1\ntrace + 1", "2");
+    expect_output("fn trace(x: Length) = 2 x\ntrace(3 m)", "6 m");
+    expect_output(
+        "let xs = [1, 2]\nfn trace(n) = n + 1\nmap(trace, xs)",
+        "[2, 3]",
+    );
+}
+
+#[test]
+fn test_conversion_cache() {
+    fn stats(ctx: &mut Context) -> String {
+        let mut sink = BufferingSink::default();
+        let (_, result) = ctx
+            .interpret_with_sink(&mut sink, "conversion_cache_stats()", CodeSource::Internal)
+            .unwrap();
+        assert!(result.is_continue());
+        let printed = printed(&sink);
+        assert_eq!(printed.len(), 1);
+        printed[0].clone()
+    }
+
+    let mut ctx = get_test_context();
+
+    // Repeated conversions give the same results as the first (uncached) one
+    let first = ctx
+        .interpret(
+            "fn to_kmh(v) = v -> km/h
+             map(to_kmh, [1 mph, 2.5 mph, 1e-3 mph, 7 knot, 1 m/s])",
+            CodeSource::Internal,
+        )
+        .unwrap()
+        .1;
+    let before = stats(&mut ctx);
+    let second = ctx
+        .interpret(
+            "map(to_kmh, [1 mph, 2.5 mph, 1e-3 mph, 7 knot, 1 m/s])",
+            CodeSource::Internal,
+        )
+        .unwrap()
+        .1;
+    assert_eq!(
+        first.value_as_string().unwrap(),
+        "[1.60934 km/h, 4.02336 km/h, 0.00160934 km/h, 12.964 km/h, 3.6 km/h]"
+    );
+    assert_eq!(first.value_as_string(), second.value_as_string());
+    assert_ne!(before, stats(&mut ctx));
+    expect_output_with_context(&mut ctx, "(1 mph -> km/h) == to_kmh(1 mph)", "true");
+
+    // Defining a new unit clears the cache
+    expect_output_with_context(&mut ctx, "unit foo = 2 m\n1 foo -> cm", "200 cm");
+    assert!(stats(&mut ctx).contains("entries: 1,"));
+    let _ = ctx
+        .interpret("unit bar_ = 1 foo", CodeSource::Internal)
+        .unwrap();
+    assert!(stats(&mut ctx).contains("entries: 0,"));
+
+    // A unit with the same name in a different context does not use stale entries
+    let mut cloned = ctx.clone();
+    expect_output_with_context(&mut cloned, "1 foo -> cm", "200 cm");
+    let mut fresh = get_test_context();
+    expect_output_with_context(&mut fresh, "unit foo = 3 m\n1 foo -> cm", "300 cm");
+    expect_output_with_context(&mut fresh, "1 foo -> cm", "300 cm");
+    expect_output_with_context(&mut cloned, "1 foo -> cm", "200 cm");
+
+    // Affine conversions between temperature scales use functions, not the cache
+    let mut ctx = get_test_context();
+    expect_output_with_context(&mut ctx, "from_celsius(25) -> K", "298.15 K");
+    expect_output_with_context(&mut ctx, "300 K -> fahrenheit", "80.33");
+    expect_output_with_context(&mut ctx, "from_fahrenheit(80.33) -> K", "300 K");
+    expect_output_with_context(&mut ctx, "from_celsius(25) -> K", "298.15 K");
+
+    expect_failure(
+        "conversion_cache_stats(1)",
+        "called with 1 arguments(s), but needs 0..0",
+    );
+}
+
+#[test]
+fn test_regex_functions() {
+    expect_output("str_match(\"Gate B12\", \"[A-Z][0-9]+\")", "true");
+    expect_output("str_match(\"Gate 12\", \"^[0-9]\\{2\\}$\")", "false");
+    expect_output(
+        "str_extract(\"2024-05-17\", \"([0-9]+)-([0-9]+)-([0-9]+)\", 2)",
+        "\"05\"",
+    );
+    expect_output("str_extract(\"a1b22\", \"[0-9]+\", 0)", "\"1\"");
+    expect_output(
+        "str_extract_all(\"3 m, 42 m, 7 m\", \"[0-9]+\")",
+        "[\"3\", \"42\", \"7\"]",
+    );
+    expect_output("str_extract_all(\"abc\", \"[0-9]+\")", "[]");
+
+    // extracted parts can be parsed as quantities
+    expect_output(
+        "let line = \"distance: 42 km (measured)\"
+         parse_quantity(str_extract(line, \"distance: ([^(]*)\", 1), m)",
+        "42000 m",
+    );
+
+    expect_failure(
+        "str_extract(\"2024\", \"([0-9]+)\", 2)",
+        "The regular expression '([0-9]+)' has no capture group 2",
+    );
+    expect_failure(
+        "str_extract(\"abc\", \"a|([0-9])\", 1)",
+        "Capture group 1 of the regular expression 'a|([0-9])' did not match in \"abc\"",
+    );
+    expect_failure("str_extract(\"abc\", \"a\", -1)", "must not be negative");
+    expect_failure(
+        "str_match(\"abc\", \"a(b\")",
+        "Invalid regular expression 'a(b' at position 1: unclosed group",
+    );
+    expect_failure(
+        "str_extract_all(\"abc\", \"[z-a]\")",
+        "Invalid regular expression '[z-a]' at position 1",
+    );
+}
+
+#[test]
+fn test_regex_cache() {
+    fn stats(ctx: &mut Context) -> String {
+        let mut sink = BufferingSink::default();
+        let (_, result) = ctx
+            .interpret_with_sink(&mut sink, "regex_cache_stats()", CodeSource::Internal)
+            .unwrap();
+        assert!(result.is_continue());
+        printed(&sink)[0].clone()
+    }
+
+    let mut ctx = get_test_context();
+    assert_eq!(
+        stats(&mut ctx),
+        "Regex cache: entries: 0, hits: 0, misses: 0"
+    );
+
+    // patterns are compiled once, even when they are used in a loop
+    expect_output_with_context(
+        &mut ctx,
+        "fn is_code(code) = str_match(code, \"^[A-Z][0-9]+$\")
+         filter(is_code, [\"B12\", \"b12\", \"C3\", \"D\"])",
+        "[\"B12\", \"C3\"]",
+    );
+    assert_eq!(
+        stats(&mut ctx),
+        "Regex cache: entries: 1, hits: 3, misses: 1"
+    );
+
+    expect_output_with_context(&mut ctx, "str_match(\"a1\", \"[0-9]\")", "true");
+    expect_output_with_context(&mut ctx, "is_code(\"E5\")", "true");
+    assert_eq!(
+        stats(&mut ctx),
+        "Regex cache: entries: 2, hits: 4, misses: 2"
+    );
+
+    expect_failure(
+        "regex_cache_stats(1)",
+        "called with 1 arguments(s), but needs 0..0",
+    );
+}
+
+#[test]
+fn test_generic_function_specializations() {
+    fn stats(ctx: &mut Context) -> Vec<usize> {
+        let mut sink = BufferingSink::default();
+        let (_, result) = ctx
+            .interpret_with_sink(
+                &mut sink,
+                "instantiation_cache_stats()",
+                CodeSource::Internal,
+            )
+            .unwrap();
+        assert!(result.is_continue());
+        printed(&sink)[0]
+            .split(", ")
+            .map(|part| part.rsplit(' ').next().unwrap().parse().unwrap())
+            .collect()
+    }
+
+    let mut ctx = get_test_context();
+    let [entries, hits, misses] = stats(&mut ctx)[..] else {
+        panic!()
+    };
+
+    // Specialized calls give the same results as calls through function references,
+    // which use the generic version
+    expect_output_with_context(
+        &mut ctx,
+        "fn bigger<D: Dim>(a: D, b: D) -> D = if a > b then a else b
+         fn cube_root<T: Dim>(x: T^3) -> T = x^(1/3)
+         bigger(2 m, 3 ft) + bigger(2 m, 1 m) + cube_root(8 m^3)",
+        "6 m",
+    );
+    expect_output_with_context(&mut ctx, "map(cube_root, [8 m^3, 27 m^3])", "[2 m, 3 m]");
+    expect_output_with_context(&mut ctx, "foldl(bigger, 1 m, [2 m, 3 ft])", "2 m");
+    assert_eq!(stats(&mut ctx), [entries + 2, hits + 1, misses + 2]);
+
+    expect_output_with_context(&mut ctx, "bigger(3 kg, 4 kg) + bigger(1 kg, 0 kg)", "5 kg");
+    assert_eq!(stats(&mut ctx), [entries + 3, hits + 2, misses + 3]);
+
+    // Inside of a specialization, the result types of native functions are known
+    expect_output_with_context(
+        &mut ctx,
+        "struct Place { name: String, height: Length }
+         fn load<T>(text: String) -> T = parse_json(text)
+         let place: Place = load(to_json(Place { name: \"Zugspitze\", height: 2962 m }))
+         place.height",
+        "2962 m",
+    );
+
+    // Redefining a function removes its specializations
+    let entries = stats(&mut ctx)[0];
+    expect_output_with_context(
+        &mut ctx,
+        "fn bigger(a: Length, b: Length) -> Length = b
+         bigger(2 m, 1 m)",
+        "1 m",
+    );
+    assert_eq!(stats(&mut ctx)[0], entries - 2);
+
+    expect_failure(
+        "instantiation_cache_stats(1)",
+        "called with 1 arguments(s), but needs 0..0",
+    );
+}
+
+#[test]
+fn test_output_sink() {
+    use numbat::markup::FormatType;
+    use numbat::output::OutputSink;
+    use std::sync::{Arc, Mutex};
+
+    let mut ctx = get_test_context();
+    let mut sink = BufferingSink::default();
+
+    // Events are recorded in order, across calls, while results are returned
+    let mut results = vec![];
+    for code in [
+        "print(\"start\")\nassert_eq(2 m, 200 cm)\n1",
+        "print(3 km)\nassert(1 < 2)\n2",
+        "assert_eq(1 m, 1.2 m, 1 cm)",
+    ] {
+        results.push(ctx.interpret_with_sink(&mut sink, code, CodeSource::Internal));
+    }
+    assert_eq!(
+        results[0].as_ref().unwrap().1.value_as_string().unwrap(),
+        "1"
+    );
+    assert_eq!(
+        results[1].as_ref().unwrap().1.value_as_string().unwrap(),
+        "2"
+    );
+    assert!(results[2].is_err());
+
+    let events = sink.take_events();
+    assert_eq!(events.len(), 5);
+    let OutputEvent::Print(start) = &events[0] else {
+        panic!("expected print event");
+    };
+    assert_eq!(start.to_string(), "start");
+
+    let OutputEvent::Assertion(assertion) = &events[1] else {
+        panic!("expected assertion event");
+    };
+    assert_eq!(assertion.kind, AssertionKind::AssertEq);
+    assert!(assertion.passed);
+    assert_eq!(
+        assertion
+            .values
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<_>>(),
+        ["2 m", "200 cm"]
+    );
+
+    // Printed values keep their formatting
+    let OutputEvent::Print(quantity) = &events[2] else {
+        panic!("expected print event");
+    };
+    assert_eq!(quantity.to_string(), "3 km");
+    assert!(quantity.0.iter().any(|part| part.1 == FormatType::Unit));
+
+    let OutputEvent::Assertion(assertion) = &events[3] else {
+        panic!("expected assertion event");
+    };
+    assert_eq!(assertion.kind, AssertionKind::Assert);
+    assert!(assertion.passed);
+
+    let OutputEvent::Assertion(assertion) = &events[4] else {
+        panic!("expected assertion event");
+    };
+    assert!(!assertion.passed);
+    assert_eq!(assertion.values.len(), 3);
+    assert_eq!(assertion.spans.len(), 3);
+
+    // The sink of the context is used for `interpret` and diagnostics
+    #[derive(Clone, Default)]
+    struct SharedSink(Arc<Mutex<Vec<String>>>);
+
+    impl OutputSink for SharedSink {
+        fn print(&mut self, markup: &Markup) {
+            self.0.lock().unwrap().push(format!("print: {markup}"));
+        }
+
+        fn diagnostic(
+            &mut self,
+            diagnostic: &numbat::Diagnostic,
+            _files: &codespan_reporting::files::SimpleFiles<String, String>,
+        ) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("diagnostic: {}", diagnostic.message));
+        }
+    }
+
+    let shared = SharedSink::default();
+    ctx.set_output_sink(shared.clone());
+    let _ = ctx.interpret("print(1 + 2)", CodeSource::Internal).unwrap();
+    let error = ctx.interpret("1 / 0", CodeSource::Internal).unwrap_err();
+    let numbat::NumbatError::RuntimeError(error) = error else {
+        panic!("expected runtime error");
+    };
+    ctx.print_diagnostic(error);
+    assert_eq!(
+        *shared.0.lock().unwrap(),
+        ["print: 3", "diagnostic: runtime error"]
+    );
+}
+
+#[test]
+fn test_parallel_evaluation() {
+    let mut ctx = get_test_context();
+    ctx.set_num_threads(4);
+
+    let _ = ctx
+        .interpret(
+            "
+            fn wobble(x) = sqrt(x^2 + 1) × sin(x) + cos(x / 3)
+            fn to_length(x) = x × 1 cm
+            fn traced(x) = trace(x) + 1
+            fn calls_traced(x) = 2 × traced(x)
+            let xs = reverse(range(1, 20_000))
+            let lengths = map(to_length, xs)
+            let parallel = map(wobble, xs)
+            ",
+            CodeSource::Internal,
+        )
+        .unwrap();
+
+    // Results are identical to the sequential evaluation
+    ctx.set_num_threads(1);
+    expect_output_with_context(&mut ctx, "map(wobble, xs) == parallel", "true");
+    let sequential = ["sum(parallel)", "max_of(parallel)", "min_of(lengths)"]
+        .map(|code| ctx.interpret(code, CodeSource::Internal).unwrap().1);
+    ctx.set_num_threads(4);
+    expect_output_with_context(&mut ctx, "len(parallel)", "20000");
+    for (code, sequential) in ["sum(parallel)", "max_of(parallel)", "min_of(lengths)"]
+        .iter()
+        .zip(sequential)
+    {
+        let (_, parallel) = ctx.interpret(code, CodeSource::Internal).unwrap();
+        assert_eq!(
+            parallel.value_as_string().unwrap(),
+            sequential.value_as_string().unwrap()
+        );
+    }
+    expect_output_with_context(&mut ctx, "sum(reverse(range(1, 100_000)))", "5_000_050_000");
+    expect_output_with_context(&mut ctx, "min_of(lengths)", "1 cm");
+    expect_failure_with_context(&mut ctx, "max_of([])", "Empty list");
+
+    // Functions with side effects are always evaluated sequentially
+    for function in ["traced", "calls_traced"] {
+        let mut sink = BufferingSink::default();
+        let _ = ctx
+            .interpret_with_sink(
+                &mut sink,
+                &format!("map({function}, xs)"),
+                CodeSource::Internal,
+            )
+            .unwrap();
+        let traced = printed(&sink);
+        assert_eq!(traced.len(), 20_000);
+        assert_eq!(traced[0], "x = 20000 : Scalar");
+        assert_eq!(traced[19_999], "x = 1 : Scalar");
+    }
+}
+
+#[test]
+fn test_full_simplify_for_function_calls() {
+    expect_output("floor(1.2 hours / hour)", "1");
+}
+
+#[test]
+#[cfg(feature = "datetime")]
+fn test_datetime_runtime_errors() {
+    expect_failure("datetime(\"2000-01-99\")", "Unrecognized datetime format");
+    expect_failure("now() -> tz(\"Europe/NonExisting\")", "Unknown timezone");
+    expect_failure(
+        "date(\"2000-01-01\") + 1e100 years",
+        "Exceeded maximum size for time durations",
+    );
+    expect_failure(
+        "date(\"2000-01-01\") + 15_000 years",
+        "DateTime out of range",
+    );
+    expect_failure(
+        "format_datetime(\"%Y-%m-%dT%H%:M\", now())",
+        "Error in datetime format",
+    )
+}
+
+#[test]
+#[cfg(feature = "datetime")]
+fn test_fixed_time() {
+    let mut ctx = get_test_context();
+    let _ = ctx
+        .interpret(
+            "set_fixed_time(\"2024-01-01T00:00:00Z\")",
+            CodeSource::Internal,
+        )
+        .unwrap();
+
+    expect_output_with_context(&mut ctx, "now() == now()", "true");
+    expect_output_with_context(&mut ctx, "now() - now()", "0 s");
+    expect_output_with_context(
+        &mut ctx,
+        "datetime(\"2024-01-01T12:30:00Z\") - now() -> min",
+        "750 min",
+    );
+    expect_output_with_context(&mut ctx, "unixtime(now())", "1_704_067_200");
+    expect_output_with_context(
+        &mut ctx,
+        "format_datetime(\"%Y-%m-%d\", today())",
+        "\"2024-01-01\"",
+    );
+
+    // The clock can be replaced again
+    let _ = ctx
+        .interpret(
+            "set_fixed_time(\"2000-01-01 12:00:00 +0200\")",
+            CodeSource::Internal,
+        )
+        .unwrap();
+    expect_output_with_context(&mut ctx, "unixtime(now())", "946_720_800");
+
+    // … and by the embedding application
+    ctx.set_clock(FixedClock::new(
+        "2024-02-03T10:00:00+01:00[Europe/Berlin]".parse().unwrap(),
+    ));
+    expect_output_with_context(
+        &mut ctx,
+        "format_datetime(\"%Y-%m-%d %H:%M\", now())",
+        "\"2024-02-03 10:00\"",
+    );
+
+    expect_failure_with_context(
+        &mut ctx,
+        "set_fixed_time(\"yesterday\")",
+        "Unrecognized datetime format",
+    );
+    expect_failure_with_context(&mut ctx, "set_fixed_time(1)", "Incompatible types");
+    expect_failure_with_context(&mut ctx, "set_fixed_time()", "called with 0 arguments");
+}
+
+#[test]
+#[cfg(feature = "datetime")]
+fn test_datetime_durations() {
+    let mut ctx = get_test_context();
+    let _ = ctx
+        .interpret(
+            "let start = datetime(\"2024-01-01T00:00:00Z\")
+             let end = datetime(\"2024-01-04T04:00:05.123456789Z\")
+             let diff = end - start",
+            CodeSource::Internal,
+        )
+        .unwrap();
+
+    // Differences are exact, down to nanoseconds
+    expect_output_with_context(&mut ctx, "diff", "3 days 4 h 5.123456789 s");
+    expect_output_with_context(&mut ctx, "start + diff == end", "true");
+    expect_output_with_context(&mut ctx, "end - diff == start", "true");
+    expect_output_with_context(
+        &mut ctx,
+        "format_datetime(\"%H:%M:%S%.9f\", start + diff)",
+        "\"04:00:05.123456789\"",
+    );
+    expect_output_with_context(&mut ctx, "diff + diff", "6 days 8 h 10.246913578 s");
+    expect_output_with_context(&mut ctx, "start - end", "-3 days 4 h 5.123456789 s");
+    expect_output_with_context(&mut ctx, "\"{start - start}\"", "\"0 s\"");
+
+    // … and are promoted to quantities when combined with them
+    expect_output_with_context(&mut ctx, "diff / 2", "136803 s");
+    expect_output_with_context(&mut ctx, "diff -> days", "3.16673 day");
+    expect_output_with_context(&mut ctx, "diff + 1 s", "273606 s");
+    expect_output_with_context(&mut ctx, "diff > 3 days", "true");
+    expect_output_with_context(&mut ctx, "diff == 3 days", "false");
+    expect_output_with_context(&mut ctx, "round(diff)", "273_605 s");
+}
+
+#[test]
+fn test_user_errors() {
+    expect_failure("error(\"test\")", "User error: test");
+
+    // Make sure that error(…) can be used in all contexts
+    expect_failure("- error(\"test\")", "User error: test");
+    expect_failure("1 + error(\"test\")", "User error: test");
+    expect_failure("1 m + error(\"test\")", "User error: test");
+    expect_failure("if 3 < 2 then 2 m else error(\"test\")", "User error: test");
+    expect_failure(
+        "if true then error(\"test\") else \"foo\"",
+        "User error: test",
+    );
+    expect_failure(
+        "if false then \"foo\" else error(\"test\")",
+        "User error: test",
+    );
+}
+
+#[test]
+fn test_recovery_after_runtime_error() {
+    {
+        let mut ctx = get_test_context();
+
+        expect_failure_with_context(&mut ctx, "let x = 1/0", "Division by zero");
+        expect_failure_with_context(&mut ctx, "x", "Unknown identifier 'x'");
+    }
+    {
+        let mut ctx = get_test_context();
+
+        expect_failure_with_context(&mut ctx, "let x = 1/0", "Division by zero");
+        assert!(ctx
+            .interpret("let x = 1", CodeSource::Internal)
+            .unwrap()
+            .1
+            .is_continue());
+        expect_output_with_context(&mut ctx, "x", "1");
+    }
+}
+
+#[test]
+fn test_statement_pretty_printing() {
+    // Let definitions
+    expect_pretty_print("let v = 10 m/s", "let v: Velocity = 10 metre / second");
+    expect_pretty_print(
+        "let v: Length * Frequency = 10 m/s",
+        "let v: Length × Frequency = 10 metre / second",
+    );
+
+    expect_pretty_print("let x = 0 + 1 m", "let x: Length = 0 + 1 metre");
+
+    expect_pretty_print("let x: Length = 0", "let x: Length = 0");
+    expect_pretty_print("let x = 0", "let x = 0");
+
+    // Derived unit definitions
+    expect_pretty_print(
+        "unit my_length_base_unit: Length",
+        "unit my_length_base_unit: Length",
+    );
+    expect_pretty_print("unit my_custom_base_unit", "unit my_custom_base_unit");
+    expect_pretty_print(
+        "unit my_speed_unit = 10 m/s",
+        "unit my_speed_unit: Velocity = 10 metre / second",
+    );
+
+    // Function definitions
+    expect_pretty_print(
+        "fn f(v)=(v+1 knot)/1s",
+        "fn f(v: Velocity) -> Acceleration = (v + 1 knot) / 1 second",
+    );
+
+    expect_pretty_print("fn f(x) = x", "fn f<A>(x: A) -> A = x");
+
+    expect_pretty_print("fn f(x, y) = y", "fn f<A, B>(x: A, y: B) -> B = y");
+    expect_pretty_print("fn f(x, y) = x", "fn f<A, B>(x: B, y: A) -> B = x"); // TODO: This is correct, but it would be nice to associate 'x' to 'A', not 'B'.
+
+    expect_pretty_print("fn f(x) = 2 x", "fn f<A: Dim>(x: A) -> A = 2 x");
+
+    // Partially annotated functions
+    expect_pretty_print(
+        "fn f() -> Length * Frequency = c",
+        "fn f() -> Length × Frequency = c",
+    );
+    expect_pretty_print(
+        "fn f(v: Length * Frequency) = 1",
+        "fn f(v: Length × Frequency) -> Scalar = 1",
+    );
+
+    expect_pretty_print("fn f(x: Length) = 2 x", "fn f(x: Length) -> Length = 2 x");
+    expect_pretty_print("fn f(x) -> Length = 2 x", "fn f(x: Length) -> Length = 2 x");
+
+    // TODO:
+    // expect_pretty_print("fn f<Z>(z: Z) = z", "fn f<Z>(z: Z) -> Z = z");
+}
+
+/// Modules for testing how errors in imported code are reported
+struct ImportChainImporter;
+
+impl ModuleImporter for ImportChainImporter {
+    fn import(&self, path: &ModulePath) -> Option<(String, Option<PathBuf>)> {
+        let code = match path.to_string().as_str() {
+            "outer" => "use inner\nlet outer_value = 1".to_owned(),
+            "other" => "use inner".to_owned(),
+            "inner" => "let inner_value: Bool = 1".to_owned(),
+            "level_5" => "use inner".to_owned(),
+            level => {
+                let n: usize = level.strip_prefix("level_")?.parse().ok()?;
+                format!("use level_{}", n + 1)
+            }
+        };
+        Some((code, None))
+    }
+
+    fn list_modules(&self) -> Vec<ModulePath> {
+        vec![]
+    }
+}
+
+fn import_chain_errors(code: &str) -> (Vec<Diagnostic>, String) {
+    use codespan_reporting::term::{self, termcolor::NoColor, Config};
+
+    let mut ctx = Context::new(ImportChainImporter);
+    let error = ctx.interpret(code, CodeSource::Text).unwrap_err();
+    let diagnostics = ctx.diagnostics(&error);
+
+    let mut writer = NoColor::new(vec![]);
+    for diagnostic in &diagnostics {
+        term::emit(
+            &mut writer,
+            &Config::default(),
+            &ctx.resolver().files,
+            diagnostic,
+        )
+        .unwrap();
+    }
+    (diagnostics, String::from_utf8(writer.into_inner()).unwrap())
+}
+
+#[test]
+fn test_import_chain_in_diagnostics() {
+    let (_, output) = import_chain_errors("use outer");
+    assert_snapshot!(output, @r###"
+    error[E0520]: while type checking
+      ┌─ Module 'inner', File ?:1:5
+      │
+    1 │ let inner_value: Bool = 1
+      │     ^^^^^^^^^^^  ----   - Scalar
+      │     │            │       
+      │     │            Bool
+      │     Incompatible types in definition
+      │
+      ┌─ Module 'outer', File ?:1:5
+      │
+    1 │ use inner
+      │     ----- imported from here
+      │
+      ┌─ <input:1>:1:5
+      │
+    1 │ use outer
+      │     ----- imported from here
+
+    "###);
+
+    // The module is only loaded (and the error only reported) once, through
+    // the first import
+    let (diagnostics, _) = import_chain_errors("use outer\nuse other");
+    assert_eq!(diagnostics.len(), 1);
+    let imported_from: Vec<_> = diagnostics[0]
+        .labels
+        .iter()
+        .filter(|label| label.message == "imported from here")
+        .collect();
+    assert_eq!(imported_from.len(), 2);
+
+    // Deep chains are truncated
+    let (diagnostics, output) = import_chain_errors("use level_0");
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(output.matches("imported from here").count(), 3);
+    assert!(output.contains("… imported through 4 more `use` statements"));
+}
+
+#[test]
+fn test_native_functions() {
+    let mut ctx = get_test_context();
+
+    ctx.register_native_function("add_one", "(x: Scalar) -> Scalar", |args| {
+        let x = args[0].clone().unsafe_as_quantity();
+        Ok(Value::Quantity(Quantity::from_scalar(
+            x.unsafe_value().to_f64() + 1.0,
+        )))
+    })
+    .unwrap();
+    ctx.register_native_function("shout", "(text: String) -> String", |args| {
+        Ok(Value::String(
+            args[0].clone().unsafe_as_string().to_uppercase(),
+        ))
+    })
+    .unwrap();
+
+    expect_output_with_context(&mut ctx, "add_one(2)", "3");
+    expect_output_with_context(&mut ctx, "map(add_one, [1, 2])", "[2, 3]");
+    expect_output_with_context(&mut ctx, "shout(\"numbat\")", "\"NUMBAT\"");
+    expect_failure_with_context(&mut ctx, "add_one(2 m)", "argument type: Length");
+
+    // Native functions can be used in Numbat functions
+    let _ = ctx
+        .interpret("fn add_two(x) = add_one(add_one(x))", CodeSource::Internal)
+        .unwrap();
+    expect_output_with_context(&mut ctx, "add_two(1)", "3");
+
+    // ... and show up like any other function
+    assert!(ctx.get_completions_for("sho", true).any(|c| c == "shout("));
+    let info = PlainTextFormatter {}.format(&ctx.print_info_for_keyword("shout"), false);
+    assert!(info.contains("fn shout(text: String) -> String"));
+
+    // Only function declarations without a body are accepted
+    for signature in ["(x: Scalar) -> Scalar = x", "() -> Scalar\nlet x = 2"] {
+        let error = ctx
+            .register_native_function("invalid", signature, |args| Ok(args[0].clone()))
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Expected the signature of a native function"));
+    }
+
+    // Errors in the signature are reported like any other error
+    let error = ctx
+        .register_native_function(
+            "unknown_type",
+            "(x: Foo) -> Foo",
+            |args| Ok(args[0].clone()),
+        )
+        .unwrap_err();
+    assert!(matches!(error, NumbatError::TypeCheckError(_)));
+}
+
+#[test]
+fn test_native_functions_with_generic_dimensions() {
+    let mut ctx = get_test_context();
+
+    ctx.register_native_function("unit_name", "<D: Dim>(x: D) -> String", |args| {
+        Ok(Value::String(
+            args[0].clone().unsafe_as_quantity().unit().to_string(),
+        ))
+    })
+    .unwrap();
+    ctx.register_native_function("twice", "<D: Dim>(x: D) -> D", |args| {
+        let x = args[0].clone().unsafe_as_quantity();
+        Ok(Value::Quantity(x * Quantity::from_scalar(2.0)))
+    })
+    .unwrap();
+
+    // The callback receives quantities in the unit they have been passed with
+    expect_output_with_context(&mut ctx, "unit_name(3 km/h)", "\"km/h\"");
+    expect_output_with_context(&mut ctx, "twice(3 ft)", "6 ft");
+    expect_output_with_context(&mut ctx, "twice(2 m) + 1 cm", "4.01 m");
+    expect_failure_with_context(&mut ctx, "twice(2 m) + 1 s", "Could not solve");
+}
+
+#[test]
+fn test_native_function_errors() {
+    let mut ctx = get_test_context();
+
+    ctx.register_native_function("lookup", "(key: String) -> Scalar", |args| {
+        Err(format!("no entry for '{}'", args[0]))
+    })
+    .unwrap();
+    ctx.register_native_function("broken_sensor", "() -> Length", |_| {
+        Ok(Value::Quantity(Quantity::from_scalar(3.0)))
+    })
+    .unwrap();
+    ctx.register_native_function("broken_twice", "<D: Dim>(x: D) -> D", |_| {
+        Ok(Value::Boolean(true))
+    })
+    .unwrap();
+    let three_seconds = match ctx.interpret("3 s", CodeSource::Internal).unwrap().1 {
+        InterpreterResult::Value(value) => value,
+        InterpreterResult::Continue => unreachable!(),
+    };
+    ctx.register_native_function("convert_to_time", "<D: Dim>(x: D) -> D", move |_| {
+        Ok(three_seconds.clone())
+    })
+    .unwrap();
+
+    // Errors are reported at the call site
+    let code = "1 + lookup(\"x\")";
+    let error = ctx.interpret(code, CodeSource::Text).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Native function 'lookup' failed: no entry for '\"x\"'"
+    );
+    assert_has_error_codes(&error);
+    let diagnostics = ctx.diagnostics(&error);
+    assert_eq!(diagnostics[0].code.as_deref(), Some("E0820"));
+    assert_eq!(diagnostics[0].labels[0].range, 4..code.len());
+
+    // Returned values are checked against the declared type
+    expect_failure_with_context(
+        &mut ctx,
+        "broken_sensor()",
+        "Native function 'broken_sensor' returned '3', which is not of type Length",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "broken_twice(2)",
+        "Native function 'broken_twice' returned 'true', which is not of type Scalar",
+    );
+
+    // ..., or the type at the call site, for generic functions
+    expect_failure_with_context(
+        &mut ctx,
+        "convert_to_time(2 m)",
+        "returned '3 s', which is not of type Length",
+    );
+    expect_output_with_context(&mut ctx, "convert_to_time(2 min) -> s", "3 s");
+
+    // ... also through function references, where only the declared type is known
+    let _ = ctx
+        .interpret(
+            "fn measure(f: Fn[() -> Length]) = f()",
+            CodeSource::Internal,
+        )
+        .unwrap();
+    expect_failure_with_context(
+        &mut ctx,
+        "measure(broken_sensor)",
+        "which is not of type Length",
+    );
+}
+
+#[test]
+fn test_native_function_reregistration_and_removal() {
+    let mut ctx = get_test_context();
+
+    ctx.register_native_function("version", "() -> Scalar", |_| {
+        Ok(Value::Quantity(Quantity::from_scalar(1.0)))
+    })
+    .unwrap();
+    let _ = ctx
+        .interpret("fn old_version() = version()", CodeSource::Internal)
+        .unwrap();
+
+    // Registering a function again works like redefining a Numbat function: code
+    // that has been compiled before keeps calling the previous version.
+    ctx.register_native_function("version", "() -> String", |_| {
+        Ok(Value::String("2.0".into()))
+    })
+    .unwrap();
+    expect_output_with_context(&mut ctx, "version()", "\"2.0\"");
+    expect_output_with_context(&mut ctx, "old_version()", "1");
+
+    // Native functions can not be redefined in Numbat code
+    expect_failure_with_context(&mut ctx, "fn version() = 3", "already in use");
+
+    // After removing a function, it is unknown to new code, and existing code
+    // fails at runtime
+    assert!(ctx.unregister_native_function("version"));
+    assert!(!ctx.unregister_native_function("version"));
+    assert!(!ctx.unregister_native_function("sqrt"));
+    assert!(!ctx.function_names().any(|name| name == "version"));
+    expect_failure_with_context(&mut ctx, "version()", "Unknown identifier 'version'");
+    expect_failure_with_context(
+        &mut ctx,
+        "old_version()",
+        "Native function 'version' failed: the function has been removed",
+    );
+
+    // The name can be used again
+    let _ = ctx
+        .interpret("fn version() = 3", CodeSource::Internal)
+        .unwrap();
+    expect_output_with_context(&mut ctx, "version()", "3");
+}
+
+#[test]
+fn test_set_and_get_variable() {
+    let mut ctx = get_test_context();
+
+    ctx.set_variable("sensor_speed", Value::quantity(23.4, "km/h"))
+        .unwrap();
+    expect_output_with_context(&mut ctx, "sensor_speed", "23.4 km/h");
+    let _ = ctx
+        .interpret("let distance = sensor_speed × 2 h", CodeSource::Internal)
+        .unwrap();
+
+    let (value, type_) = ctx.get_variable("distance").unwrap();
+    assert_eq!(value.to_string(), "46.8 km");
+    assert_eq!(type_.to_string(), "Length");
+
+    let (value, type_) = ctx.get_variable("sensor_speed").unwrap();
+    assert_eq!(value.to_string(), "23.4 km/h");
+    assert_eq!(type_.to_string(), "Length / Time");
+
+    // Values that come from Numbat can be passed back in
+    let (value, _) = ctx.get_variable("distance").unwrap();
+    ctx.set_variable("copy", value).unwrap();
+    expect_output_with_context(&mut ctx, "copy -> m", "46800 m");
+
+    ctx.set_variable("scalar", Value::quantity(2.0, ""))
+        .unwrap();
+    ctx.set_variable("flag", Value::Boolean(true)).unwrap();
+    ctx.set_variable("label", Value::String("sensor".into()))
+        .unwrap();
+    ctx.set_variable(
+        "readings",
+        vec![Value::quantity(1.0, "m"), Value::quantity(50.0, "cm")]
+            .into_iter()
+            .collect::<std::collections::VecDeque<_>>()
+            .into(),
+    )
+    .unwrap();
+    expect_output_with_context(
+        &mut ctx,
+        "if flag then \"{label}: {scalar × sum(readings)}\" else \"\"",
+        "\"sensor: 3 m\"",
+    );
+
+    assert!(ctx.get_variable("unknown").is_none());
+    assert!(ctx.get_variable("meter").is_none());
+    assert!(ctx.get_variable("sqrt").is_none());
+}
+
+#[test]
+fn test_set_variable_errors() {
+    let mut ctx = get_test_context();
+
+    assert!(matches!(
+        ctx.set_variable("x", Value::quantity(1.0, "meterr")),
+        Err(NumbatError::TypeCheckError(_))
+    ));
+    assert!(ctx.get_variable("x").is_none());
+
+    let error = ctx
+        .set_variable("x", Value::quantity(1.0, "true"))
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Can not assign 'true' to a variable: this is not a unit"
+    );
+
+    let error = ctx
+        .set_variable("x", Value::List(Default::default()))
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Can not assign '[]' to a variable: the element type of an empty list is unknown"
+    );
+
+    for name in ["", "1x", "let", "x = 1\nlet y"] {
+        assert!(matches!(
+            ctx.set_variable(name, Value::Boolean(true)),
+            Err(NumbatError::RuntimeError(_))
+        ));
+    }
+
+    // Names of units and functions can not be used
+    assert!(ctx.set_variable("meter", Value::Boolean(true)).is_err());
+    assert!(ctx.set_variable("sqrt", Value::Boolean(true)).is_err());
+}
+
+#[test]
+fn test_set_variable_redefinition() {
+    let mut ctx = get_test_context();
+
+    ctx.set_variable("x", Value::quantity(2.0, "m")).unwrap();
+    let _ = ctx
+        .interpret("fn double_x() = 2 x", CodeSource::Internal)
+        .unwrap();
+
+    // Overwriting a variable changes its type for new code, while functions that
+    // have been defined before keep their type and value
+    ctx.set_variable("x", Value::quantity(3.0, "s")).unwrap();
+    assert_eq!(ctx.get_variable("x").unwrap().1.to_string(), "Time");
+    expect_output_with_context(&mut ctx, "x", "3 s");
+    expect_output_with_context(&mut ctx, "double_x()", "4 m");
+    expect_failure_with_context(&mut ctx, "x + 1 m", "left hand side: Time");
+
+    // Variables from Numbat code can be overwritten, and the other way round
+    let _ = ctx.interpret("let y = 1 kg", CodeSource::Internal).unwrap();
+    ctx.set_variable("y", Value::Boolean(false)).unwrap();
+    expect_output_with_context(&mut ctx, "y", "false");
+    let _ = ctx.interpret("let x = 5 N", CodeSource::Internal).unwrap();
+    let (value, type_) = ctx.get_variable("x").unwrap();
+    assert_eq!(value.to_string(), "5 N");
+    assert_eq!(type_.to_string(), "Length × Mass / Time²");
+}
+
+/// Evaluates a few expressions and prints their results in the given locale
+fn formatted_in_locale(locale: Locale) -> String {
+    let mut ctx = get_test_context();
+    ctx.set_locale(locale);
+
+    let mut sink = BufferingSink::default();
+    let mut lines = vec![];
+    for code in [
+        "1234567 m",
+        "0.5 kg",
+        "-1e-12",
+        "[1.5, 2.25]",
+        "print(9.81 m/s²)",
+    ] {
+        match ctx
+            .interpret_with_sink(&mut sink, code, CodeSource::Internal)
+            .unwrap()
+            .1
+        {
+            InterpreterResult::Value(value) => lines.push(
+                PlainTextFormatter {}.format(&ctx.locale().localize(&value.pretty_print()), false),
+            ),
+            InterpreterResult::Continue => lines.extend(printed(&sink)),
+        }
+    }
+    lines.join("\n")
+}
+
+#[test]
+fn test_locale_output() {
+    assert_snapshot!(formatted_in_locale(Locale::c()), @r###"
+    1_234_567 m
+    0.5 kg
+    -1.0e-12
+    [1.5, 2.25]
+    9.81 m/s²
+    "###);
+    assert_snapshot!(formatted_in_locale(Locale::new(',', '.').unwrap()), @r###"
+    1.234.567 m
+    0,5 kg
+    -1,0e-12
+    [1,5, 2,25]
+    9,81 m/s²
+    "###);
+    assert_snapshot!(formatted_in_locale(Locale::new('.', '\'').unwrap()), @r###"
+    1'234'567 m
+    0.5 kg
+    -1.0e-12
+    [1.5, 2.25]
+    9.81 m/s²
+    "###);
+    assert_snapshot!(formatted_in_locale(Locale::c().with_ascii_exponents(true)), @r###"
+    1_234_567 m
+    0.5 kg
+    -1.0e-12
+    [1.5, 2.25]
+    9.81 m/s^2
+    "###);
+
+    // Values themselves are always displayed in the C locale
+    let mut ctx = get_test_context();
+    ctx.set_locale(Locale::new(',', '.').unwrap());
+    let _ = ctx
+        .interpret("let x = 1234.5 m", CodeSource::Internal)
+        .unwrap();
+    let (value, _) = ctx.get_variable("x").unwrap();
+    assert_eq!(value.to_string(), "1234.5 m");
+}
+
+#[test]
+fn test_unicode_identifiers_and_exponents() {
+    let mut ctx = get_test_context();
+
+    expect_output_with_context(&mut ctx, "let Δt = 3 s\nlet Δx₀ = 6 m\nΔx₀ / Δt", "2 m/s");
+
+    // The micro sign (U+00B5) and the Greek letter mu (U+03BC) are interchangeable
+    expect_output_with_context(&mut ctx, "1 \u{00B5}s -> \u{03BC}s", "1 µs");
+    expect_output_with_context(&mut ctx, "1000 \u{03BC}m -> mm", "1 mm");
+    expect_output_with_context(&mut ctx, "let \u{00B5}_x = 2 kg\n\u{03BC}_x", "2 kg");
+    expect_output_with_context(&mut ctx, "let \u{03BC}_y = 3 kg\n\u{00B5}_y", "3 kg");
+    assert!(ctx
+        .print_info_for_keyword("\u{00B5}_x")
+        .to_string()
+        .contains("Variable"));
+
+    // Superscript exponents in input and output
+    for (code, output, ascii_output) in [
+        ("2 m²·s⁻¹", "2 m²/s", "2 m^2/s"),
+        ("1 kg m² / s³", "1 kg·m²/s³", "1 kg m^2/s^3"),
+        ("3 m^6", "3 m^6", "3 m^6"),
+    ] {
+        let InterpreterResult::Value(value) = ctx.interpret(code, CodeSource::Internal).unwrap().1
+        else {
+            panic!("'{code}' did not return a value");
+        };
+        let markup = value.pretty_print();
+        let plain = |markup| PlainTextFormatter {}.format(&markup, false);
+        assert_eq!(plain(ctx.locale().localize(&markup)).trim(), output);
+        assert_eq!(
+            plain(Locale::c().with_ascii_exponents(true).localize(&markup)).trim(),
+            ascii_output
+        );
+        // Both representations can be entered again
+        expect_output_with_context(
+            &mut ctx,
+            &format!("{ascii_output} -> {}", output.split_once(' ').unwrap().1),
+            output,
+        );
+    }
+}
+
+#[test]
+fn test_decimal_comma_input() {
+    let mut ctx = get_test_context();
+    ctx.set_locale(
+        Locale::new(',', '.')
+            .unwrap()
+            .with_decimal_comma_input(true),
+    );
+
+    expect_output_with_context(&mut ctx, "1,5 m + 1 m", "2.5 m");
+    expect_output_with_context(&mut ctx, "1_000,25 + 0.75", "1001");
+    expect_output_with_context(&mut ctx, "2,5e3", "2500");
+    expect_output_with_context(&mut ctx, "mod(7, 4)", "3");
+    expect_output_with_context(&mut ctx, "[1,5, 2]", "[1.5, 2]");
+    expect_output_with_context(&mut ctx, "sqr(0,5)", "0.25");
+
+    // The grouping separator of the locale is never accepted for grouping,
+    // and modules are not affected by the input mode
+    expect_failure_with_context(
+        &mut ctx,
+        "1.234.567",
+        "Unexpected character in number literal",
+    );
+    expect_output_with_context(&mut ctx, "min_of([3, 1, 2])", "1");
+}
+
+#[test]
+fn test_ambiguous_decimal_comma() {
+    use codespan_reporting::term::{self, termcolor::NoColor, Config};
+
+    let mut ctx = get_test_context();
+    ctx.set_locale(Locale::new(',', '.').unwrap());
+
+    let error = ctx.interpret("1,5 m", CodeSource::Text).unwrap_err();
+    assert_has_error_codes(&error);
+
+    let mut writer = NoColor::new(vec![]);
+    for diagnostic in ctx.diagnostics(&error) {
+        term::emit(
+            &mut writer,
+            &Config::default(),
+            &ctx.resolver().files,
+            &diagnostic,
+        )
+        .unwrap();
+    }
+    assert_snapshot!(String::from_utf8(writer.into_inner()).unwrap(), @r###"
+    error[E0110]: while parsing
+      ┌─ <input:1>:1:2
+      │
+    1 │ 1,5 m
+      │  ^ Ambiguous comma in number literal
+      │
+      = Use a decimal point ('1.5'), or put a space after the comma to separate two numbers ('1, 5')
+
+    "###);
+
+    // Separating numbers with a space is fine, and the C locale is not affected
+    expect_output_with_context(&mut ctx, "mod(7, 4)", "3");
+    expect_output("mod(7,4)", "3");
+}
+
+#[test]
+#[cfg(feature = "datetime")]
+fn test_json() {
+    let mut ctx = get_test_context();
+    let _ = ctx
+        .interpret(
+            r#"struct Leg { name: String, distance: Length, durations: List<Time> }
+               struct Trip { title: String, legs: List<Leg>, start: DateTime }
+               let trip = Trip {
+                 title: "Zürich → Köln 🚆",
+                 legs: [Leg { name: "first", distance: 3.5 km, durations: [2 h, 30 min] }],
+                 start: datetime("2024-03-01 12:00:00 UTC"),
+               }"#,
+            CodeSource::Internal,
+        )
+        .unwrap();
+
+    expect_output_with_context(
+        &mut ctx,
+        "to_json(trip)",
+        r#""\{\"title\":\"Zürich → Köln 🚆\",\"legs\":[\{\"name\":\"first\",\"distance\":\{\"value\":3.5,\"unit\":\"km\"\},\"durations\":[\{\"value\":2,\"unit\":\"h\"\},\{\"value\":30,\"unit\":\"min\"\}]\}],\"start\":\"2024-03-01T12:00:00+00:00\"\}""#,
+    );
+    expect_output_with_context(
+        &mut ctx,
+        "to_json(trip.legs, JsonOptions { plain_numbers: true, pretty: false })",
+        r#""[\{\"name\":\"first\",\"distance\":3.5,\"durations\":[2,30]\}]""#,
+    );
+
+    // Round trip, including units and unicode strings
+    expect_output_with_context(
+        &mut ctx,
+        "let trip_back: Trip = parse_json(to_json(trip))
+         to_json(trip_back) == to_json(trip)",
+        "true",
+    );
+    expect_output_with_context(&mut ctx, "head(trip_back.legs).distance", "3.5 km");
+    expect_output_with_context(&mut ctx, "trip_back.title", "\"Zürich → Köln 🚆\"");
+    expect_output_with_context(
+        &mut ctx,
+        r#"let text: String = parse_json("\"\\u00e9\\ud83d\\ude80\"")
+           text"#,
+        "\"é🚀\"",
+    );
+
+    // Units are read in the format in which they are printed
+    expect_output_with_context(
+        &mut ctx,
+        r#"let v: Velocity = parse_json("\{\"value\": 3, \"unit\": \"km/h\"\}")
+           v"#,
+        "3 km/h",
+    );
+    expect_output_with_context(
+        &mut ctx,
+        r#"let a: Acceleration = parse_json(to_json(9.81 m/s^2))
+           a"#,
+        "9.81 m/s²",
+    );
+
+    // Numbers keep their full precision
+    expect_output_with_context(&mut ctx, "to_json(0.1 + 0.2)", r#""0.30000000000000004""#);
+    expect_output_with_context(&mut ctx, "to_json(2^60)", r#""1.152921504606847e18""#);
+
+    // Typed deserialization errors
+    expect_failure_with_context(
+        &mut ctx,
+        r#"let leg: Leg = parse_json("\{\"name\": \"a\", \"durations\": []\}")"#,
+        "Can not read JSON value at '$': missing field 'distance' of struct Leg",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        r#"let leg: Leg = parse_json("\{\"name\": \"a\", \"distance\": \{\"value\": 1, \"unit\": \"s\"\}, \"durations\": []\}")"#,
+        "Can not read JSON value at '$.distance': expected a quantity of type Length, got 's' of type Time",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        r#"let legs: List<Leg> = parse_json("[\{\"name\": 1\}]")"#,
+        "Can not read JSON value at '$[0].name': expected a value of type String, got a number",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        r#"let x: Scalar = parse_json("[1,")"#,
+        "Invalid JSON",
+    );
+    expect_failure_with_context(&mut ctx, "to_json(sin)", "Can not convert value to JSON");
+    expect_failure_with_context(
+        &mut ctx,
+        "let f = parse_json
+         let x: Scalar = f(\"1\")",
+        "The result type of 'parse_json' is not known here",
+    );
+}
+
+#[test]
+fn test_script_arguments() {
+    let mut ctx = get_test_context();
+    expect_output_with_context(&mut ctx, "args()", "[]");
+
+    ctx.set_script_arguments(vec!["3.5".into(), "km".into(), "Zürich".into()]);
+    expect_output_with_context(&mut ctx, "len(args())", "3");
+    expect_output_with_context(&mut ctx, "arg(2)", "\"Zürich\"");
+    expect_output_with_context(
+        &mut ctx,
+        "parse_quantity(\"{arg(0)} {arg(1)}\", m)",
+        "3500 m",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "arg(3)",
+        "Script argument 3 is not available, the script was called with 3 argument(s)",
+    );
+    expect_failure_with_context(&mut ctx, "arg(-1)", "Script argument -1 is not available");
+
+    expect_failure(
+        "env(\"NUMBAT_SURELY_UNSET_VARIABLE\")",
+        "Environment variable 'NUMBAT_SURELY_UNSET_VARIABLE' is not set",
+    );
+}
+
+#[test]
+fn test_parse_quantity() {
+    expect_output("parse_quantity(\"3.5 km\", m)", "3500 m");
+    expect_output("parse_quantity(\"3.5km\", km)", "3.5 km");
+    expect_output("parse_quantity(\"-1_000e-3 m/s\", km/h)", "-3.6 km/h");
+    expect_output("parse_quantity(\"2 kg·m²/s²\", J)", "2 J");
+    expect_output("parse_quantity(\"9.81 m/s^2\", m/s²)", "9.81 m/s²");
+    expect_output("parse_quantity(\"42\", 1)", "42");
+    expect_output(
+        "fn distance(input: String) -> Length = parse_quantity(input, m)
+         distance(\"3 mi\")",
+        "4828.03 m",
+    );
+
+    // The target dimension is checked, even for zero
+    expect_failure(
+        "parse_quantity(\"3 s\", m)",
+        "Can not parse '3 s' as a quantity: expected a quantity of type Length, got 's' of type Time",
+    );
+    expect_failure(
+        "parse_quantity(\"0 s\", m)",
+        "expected a quantity of type Length, got 's' of type Time",
+    );
+    expect_failure(
+        "parse_quantity(\"3\", m)",
+        "expected a quantity of type Length, got a number without unit",
+    );
+    expect_failure(
+        "parse_quantity(\"3 foo\", m)",
+        "Can not parse '3 foo' as a quantity: unknown unit 'foo'",
+    );
+    expect_failure(
+        "parse_quantity(\"km\", m)",
+        "Can not parse 'km' as a quantity: expected a number",
+    );
+
+    // The type of the result is determined by the target
+    expect_failure(
+        "let t: Time = parse_quantity(\"3 s\", m)",
+        "Could not solve the following constraints",
+    );
+}
+
+#[test]
+fn test_simplify() {
+    expect_output("simplify(1 kg m^2 / s^3)", "1 W");
+    expect_output("simplify(5000 kg m^2 / s^3)", "5 kW");
+    expect_output("simplify(3 m / m)", "3");
+    expect_output("simplify(2.4 kW h / day)", "100 W");
+    expect_output("simplify(12000 m)", "12 km");
+    expect_output("simplify(1 N m)", "1 J");
+    expect_output("simplify(5000 kg m / s)", "5 kN·s");
+    expect_output("simplify(2 kg / (m s))", "2 Pa·s");
+
+    // Units that are already as simple as possible are not changed
+    expect_output("simplify(20 km/h)", "20 km/h");
+    expect_output("simplify(3 ft)", "3 ft");
+    expect_output("simplify(0.003 m/s^2)", "0.003 m/s²");
+    expect_output("simplify(2 m^3)", "2 m³");
+
+    // Ties are broken by the order of definition (gray before sievert)
+    expect_output("simplify(3 J/kg)", "3 Gy");
+    expect_output("simplify(3 Sv)", "3 Sv");
+
+    // Simplifying is deterministic and idempotent
+    let mut ctx = get_test_context();
+    for _ in 0..3 {
+        expect_output_with_context(&mut ctx, "simplify(5000 kg m / s)", "5 kN·s");
+    }
+    expect_output("simplify(simplify(5000 kg m^2 / s^3))", "5 kW");
+    expect_output("simplify(simplify(2.4 kW h / day))", "100 W");
+    expect_output("simplify(1 kg m^2 / s^3) == 1 W", "true");
+
+    // Restricting to a unit system
+    expect_output("simplify(1 N, \"CGS\")", "100_000 dyne");
+    expect_output("simplify(3000 N, \"SI\")", "3 kN");
+    expect_output("simplify(100 km/h, \"imperial\")", "62.1371 mph");
+    expect_failure("simplify(1 N, \"metric\")", "Unknown unit system 'metric'");
+}
+
+#[test]
+fn test_ucum() {
+    // Numbat units and their UCUM codes
+    let mapped_units = [
+        ("m", "m"),
+        ("s", "s"),
+        ("g", "g"),
+        ("A", "A"),
+        ("K", "K"),
+        ("mol", "mol"),
+        ("cd", "cd"),
+        ("rad", "rad"),
+        ("sr", "sr"),
+        ("Hz", "Hz"),
+        ("N", "N"),
+        ("Pa", "Pa"),
+        ("J", "J"),
+        ("W", "W"),
+        ("C", "C"),
+        ("V", "V"),
+        ("F", "F"),
+        ("Ω", "Ohm"),
+        ("S", "S"),
+        ("Wb", "Wb"),
+        ("T", "T"),
+        ("H", "H"),
+        ("lm", "lm"),
+        ("lx", "lx"),
+        ("Bq", "Bq"),
+        ("Gy", "Gy"),
+        ("Sv", "Sv"),
+        ("kat", "kat"),
+        ("min", "min"),
+        ("hour", "h"),
+        ("day", "d"),
+        ("week", "wk"),
+        ("year", "a_t"),
+        ("julian_year", "a"),
+        ("au", "AU"),
+        ("deg", "deg"),
+        ("arcmin", "'"),
+        ("arcsec", "''"),
+        ("are", "ar"),
+        ("hectare", "har"),
+        ("L", "L"),
+        ("tonne", "t"),
+        ("eV", "eV"),
+        ("bar", "bar"),
+        ("cal", "cal"),
+        ("lbf", "[lbf_av]"),
+        ("mmHg", "mm[Hg]"),
+        ("psi", "[psi]"),
+        ("atm", "atm"),
+        ("percent", "%"),
+        ("bit", "bit"),
+        ("byte", "By"),
+        ("inch", "[in_i]"),
+        ("ft", "[ft_i]"),
+        ("yard", "[yd_i]"),
+        ("mile", "[mi_i]"),
+        ("grain", "[gr]"),
+        ("lb", "[lb_av]"),
+        ("oz", "[oz_av]"),
+        ("knot", "[kn_i]"),
+        ("nmi", "[nmi_i]"),
+        ("gal", "[gal_us]"),
+        ("pint", "[pt_us]"),
+        ("cup", "[cup_us]"),
+        ("tbsp", "[tbs_us]"),
+        ("tsp", "[tsp_us]"),
+        ("floz", "[foz_us]"),
+        ("pc", "pc"),
+        ("ly", "[ly]"),
+        ("dyn", "dyn"),
+        ("erg", "erg"),
+        ("gauss", "G"),
+    ];
+
+    let mut ctx = get_test_context();
+    for (unit, code) in mapped_units {
+        expect_output_with_context(&mut ctx, &format!("to_ucum({unit})"), format!("{code:?}"));
+
+        // Round trips, also for powers and products of the unit
+        for expr in [
+            format!("3.5 {unit}"),
+            format!("-2 {unit}^2"),
+            format!("0.25 / {unit}^3"),
+            format!("7 kg {unit} / s^2"),
+        ] {
+            expect_output_with_context(
+                &mut ctx,
+                &format!("let q = {expr}\n\"{{from_ucum(value_of(q), to_ucum(q), unit_of(q))}}\" == \"{{q}}\""),
+                "true",
+            );
+        }
+    }
+
+    // Prefixes
+    for (quantity, code) in [
+        ("5 mg/dL", "mg/dL"),
+        ("3 kPa", "kPa"),
+        ("2 µL", "uL"),
+        ("1 dam", "dam"),
+        ("4 MHz", "MHz"),
+        ("8 GiB", "GiBy"),
+        ("3 kByte", "kBy"),
+        ("1 nm", "nm"),
+    ] {
+        expect_output_with_context(
+            &mut ctx,
+            &format!("to_ucum({quantity})"),
+            format!("{code:?}"),
+        );
+        expect_output_with_context(
+            &mut ctx,
+            &format!(
+                "from_ucum(value_of({quantity}), \"{code}\", unit_of({quantity})) == {quantity}"
+            ),
+            "true",
+        );
+    }
+
+    // Products, quotients and exponents
+    expect_output("to_ucum(9.81 kg m / s^2)", "\"kg.m/s2\"");
+    expect_output("to_ucum(2 / s)", "\"/s\"");
+    expect_output("to_ucum(1 / (m s))", "\"/(m.s)\"");
+    expect_output("to_ucum(3)", "\"1\"");
+    expect_output("from_ucum(120, \"mm[Hg]\", kPa)", "15.9987 kPa");
+    expect_output("from_ucum(1, \"kg.m/s2\", N)", "1 N");
+    expect_output("from_ucum(2, \"kg/m/s2\", Pa)", "2 Pa");
+    expect_output("from_ucum(3, \"m+2.s-1\", m^2/s)", "3 m²/s");
+    expect_output("from_ucum(1, \"/min\", Hz)", "0.0166667 Hz");
+    expect_output("from_ucum(5, \"10.L\", L)", "50 l");
+    expect_output("from_ucum(3, \"1\", 1)", "3");
+
+    // Annotations are ignored
+    expect_output("from_ucum(4, \"\\{cells}/uL\", 1/uL)", "4 µl⁻¹");
+    expect_output("from_ucum(4, \"g\\{total}\", g)", "4 g");
+
+    // The unit needs to have the dimension of the target
+    expect_failure(
+        "from_ucum(1, \"kg\", m)",
+        "Expected 'm' of type Length, but the UCUM unit 'kg' is of type Mass",
+    );
+    expect_failure(
+        "from_ucum(1, \"/s\", 1)",
+        "Expected a number without unit, but the UCUM unit '/s' is of type Time⁻¹",
+    );
+    expect_failure(
+        "let x: Length = from_ucum(1, \"kg\", kg)",
+        "Could not solve the following constraints",
+    );
+
+    // Units with a UCUM code can be defined
+    expect_output(
+        "@ucum(\"[wdg]\") unit widget
+         to_ucum(3 widget / h)",
+        "\"[wdg]/h\"",
+    );
+    expect_output(
+        "@ucum(\"[wdg]\") unit widget
+         from_ucum(3, \"[wdg]/h\", widget/h)",
+        "3 widget/h",
+    );
+
+    // Units without a UCUM code can not be written
+    expect_failure(
+        "unit widget
+         to_ucum(3 widget / h)",
+        "The unit 'widget/h' can not be written in UCUM: 'widget' has no UCUM code",
+    );
+    expect_failure(
+        "to_ucum(2 furlong)",
+        "The unit 'furlong' can not be written in UCUM: 'furlong' has no UCUM code",
+    );
+    expect_failure(
+        "to_ucum(2 sqrt(m))",
+        "The unit 'm^(1/2)' can not be written in UCUM: the exponent 1/2 of 'm' is not an integer",
+    );
+    expect_failure(
+        "to_ucum(2 Rm)",
+        "The unit 'Rm' can not be written in UCUM: the prefix of 'm' has no UCUM symbol",
+    );
+
+    // Parse errors point to their position
+    for (code, message) in [
+        ("", "at position 0: expected a unit"),
+        ("mg/xyz", "at position 3: unknown unit 'xyz'"),
+        (
+            "kmin",
+            "at position 0: the unit 'min' can not be used with the prefix 'k'",
+        ),
+        ("m.(s", "at position 4: expected ')'"),
+        ("m.s)", "at position 3: unmatched ')'"),
+        ("[in_i", "at position 5: expected ']'"),
+        (
+            "m-",
+            "at position 2: expected digits after the sign of an exponent",
+        ),
+        ("kg m", "at position 2: expected '.' or '/', found ' '"),
+        ("m./s", "at position 2: unexpected character '/'"),
+        (
+            "g\\{total",
+            "at position 7: expected '}' at the end of an annotation",
+        ),
+        ("µg", "at position 0: unexpected character 'µ'"),
+    ] {
+        expect_failure(
+            &format!("from_ucum(1, \"{code}\", 1)"),
+            &format!("Invalid UCUM unit '{}' {message}", code.replace('\\', "")),
+        );
+    }
+}
+
+#[test]
+#[cfg(feature = "datetime")]
+fn test_time_series() {
+    let mut ctx = get_test_context();
+
+    // On 2024-03-31, the clocks in Berlin jump from 02:00 to 03:00
+    ctx.set_clock(FixedClock::new(
+        "2024-03-31T00:00:00+01:00[Europe/Berlin]".parse().unwrap(),
+    ));
+    let _ = ctx
+        .interpret(
+            "struct Reading { time: DateTime, value: Power, sensor: String }
+             let t0 = now()
+             let readings = [
+               Reading { time: t0, value: 1 kW, sensor: \"a\" },
+               Reading { time: t0 + 30 min, value: 2 kW, sensor: \"b\" },
+               Reading { time: t0 + 60 min, value: 3 kW, sensor: \"c\" },
+               Reading { time: t0 + 90 min, value: 4 kW, sensor: \"d\" },
+               Reading { time: t0 + 120 min, value: 5 kW, sensor: \"e\" },
+               Reading { time: t0 + 150 min, value: 6 kW, sensor: \"f\" },
+             ]
+             fn local_time(r: Reading) -> String = format_datetime(\"%H:%M\", r.time)
+             fn describe(r: Reading) -> String = \"{local_time(r)}: {r.value} ({r.sensor})\"",
+            CodeSource::Internal,
+        )
+        .unwrap();
+
+    // Buckets are one hour long, even across the change to daylight saving time
+    expect_output_with_context(
+        &mut ctx,
+        "map(describe, resample(readings, 1 hour, mean))",
+        "[\"00:00: 1.5 kW (a)\", \"01:00: 3.5 kW (c)\", \"03:00: 5.5 kW (e)\"]",
+    );
+    // … and they start at multiples of the interval since the Unix epoch
+    expect_output_with_context(
+        &mut ctx,
+        "map(describe, resample(readings, 90 min, maximum))",
+        "[\"23:30: 2 kW (a)\", \"01:00: 5 kW (c)\", \"03:30: 6 kW (f)\"]",
+    );
+    // … so days start at midnight UTC, which is 01:00 in Berlin
+    expect_output_with_context(
+        &mut ctx,
+        "map(describe, resample(readings, 1 day, mean))",
+        "[\"01:00: 1.5 kW (a)\", \"01:00: 4.5 kW (c)\"]",
+    );
+
+    // Steps between samples
+    expect_output_with_context(
+        &mut ctx,
+        "for step in diff_series(readings) { step.duration }",
+        "[30 min, 30 min, 30 min, 30 min, 30 min]",
+    );
+    expect_output_with_context(&mut ctx, "len(diff_series([head(readings)]))", "0");
+    expect_output_with_context(
+        &mut ctx,
+        "let step = element_at(3, diff_series(readings))
+         let end_time = format_datetime(\"%H:%M\", step.time)
+         \"{end_time}: {step.delta} in {step.duration}\"",
+        "\"03:00: 1 kW in 30 min\"",
+    );
+
+    // The integral has the dimension of the values times time
+    expect_output_with_context(&mut ctx, "integrate_series(readings) -> kWh", "8.75 kWh");
+    expect_output_with_context(
+        &mut ctx,
+        "let energy: Energy = integrate_series(readings)
+         energy -> kWh",
+        "8.75 kWh",
+    );
+    expect_output_with_context(
+        &mut ctx,
+        "integrate_series([head(readings)]) -> kWh",
+        "0 kWh",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "let power: Power = integrate_series(readings)",
+        "Could not solve the following constraints:\n  Time = Scalar",
+    );
+
+    // The elements need to have the fields `time: DateTime` and `value: D`
+    expect_failure_with_context(
+        &mut ctx,
+        "integrate_series([1 kW, 2 kW])",
+        "'integrate_series' expects a time series, but the elements of this list have type 'Length² × Mass / Time³'",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "struct Event { time: DateTime, name: String }
+         diff_series([Event { time: t0, name: \"start\" }])",
+        "'diff_series' expects a time series, but the elements of this list have type 'Event {time: DateTime, name: String}'",
+    );
+
+    // Time series need to be sorted by time
+    expect_failure_with_context(
+        &mut ctx,
+        "integrate_series([element_at(0, readings), element_at(2, readings), element_at(1, readings)])",
+        "The time series passed to 'integrate_series' is not sorted: element 2 is earlier than the one before it",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "resample(readings, -1 min, mean)",
+        "The interval of 'resample' needs to be positive, got -1 min",
+    );
+}
+
+fn evaluate_with_context(ctx: &mut Context, code: &str) {
+    let _ = ctx.interpret(code, CodeSource::Internal).unwrap();
+}
+
+#[test]
+fn test_write_text() {
+    let file_system = Arc::new(InMemoryFileSystem::new());
+    let mut ctx = get_test_context();
+    ctx.set_file_system(file_system.clone());
+
+    evaluate_with_context(&mut ctx, "write_text(\"out.txt\", \"Distance: {3 km}\")");
+    assert_eq!(
+        file_system.contents("out.txt").as_deref(),
+        Some("Distance: 3 km")
+    );
+
+    // Files are replaced
+    evaluate_with_context(&mut ctx, "write_text(\"out.txt\", \"ü\")");
+    assert_eq!(file_system.contents("out.txt").as_deref(), Some("ü"));
+
+    expect_failure_with_context(
+        &mut ctx,
+        "write_text(\"out.txt\", 3 m)",
+        "Incompatible types in function call",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "write_text(\"out.txt\")",
+        "called with 1 arguments(s)",
+    );
+    // Errors of the operating system are reported along with the path
+    #[cfg(feature = "fs")]
+    {
+        let path = std::env::temp_dir()
+            .join("numbat-missing-directory")
+            .join("out.txt");
+        let path = path.to_string_lossy();
+        expect_failure(
+            &format!("write_text(\"{path}\", \"text\")"),
+            &format!("Could not write to '{path}': No such file or directory"),
+        );
+    }
+}
+
+#[test]
+fn test_append_csv() {
+    let file_system = Arc::new(InMemoryFileSystem::new());
+    let mut ctx = get_test_context();
+    ctx.set_file_system(file_system.clone());
+
+    evaluate_with_context(
+        &mut ctx,
+        "struct Measurement { name: String, distance: Length, valid: Bool }
+         append_csv(\"results.csv\", Measurement { name: \"first, \\\"quoted\\\"\", distance: 3.5 km, valid: true })
+         append_csv(\"results.csv\", Measurement { name: \"second\", distance: (1 / 3) m, valid: false })",
+    );
+    assert_eq!(
+        file_system.contents("results.csv").unwrap(),
+        "name,distance,valid\n\
+         \"first, \"\"quoted\"\"\",3.5 km,true\n\
+         second,0.3333333333333333 m,false\n"
+    );
+
+    // Rows need to match the header
+    expect_failure_with_context(
+        &mut ctx,
+        "struct Other { name: String, time: Time }
+         append_csv(\"results.csv\", Other { name: \"third\", time: 2 s })",
+        "Can not append to CSV file 'results.csv': expected the columns name, distance, valid, got name, time",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "append_csv(\"results.csv\", [1, 2])",
+        "Can not append to CSV file 'results.csv': expected 3 columns, got 2",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "append_csv(\"results.csv\", 3 m)",
+        "expected a struct or a list as a row, got 3 m",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "append_csv(\"nested.csv\", [[1]])",
+        "can not write [1] to a cell",
+    );
+    assert_eq!(
+        file_system.contents("results.csv").unwrap().lines().count(),
+        3
+    );
+    assert_eq!(file_system.contents("nested.csv"), None);
+
+    // Lists are written without a header
+    evaluate_with_context(
+        &mut ctx,
+        "append_csv(\"list.csv\", [1, 2])
+         append_csv(\"list.csv\", [3 s, 4 s])",
+    );
+    assert_eq!(file_system.contents("list.csv").unwrap(), "1,2\n3 s,4 s\n");
+}
+
+/// A clock that moves forward by one millisecond every time it is read
+#[cfg(feature = "datetime")]
+struct TickingClock(std::sync::atomic::AtomicI64);
+
+#[cfg(feature = "datetime")]
+impl numbat::clock::Clock for TickingClock {
+    fn now(&self) -> jiff::Zoned {
+        let ticks = self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        jiff::Timestamp::from_millisecond(ticks)
+            .unwrap()
+            .to_zoned(jiff::tz::TimeZone::UTC)
+    }
+}
+
+#[test]
+#[cfg(feature = "datetime")]
+fn test_benchmark() {
+    let mut ctx = get_test_context();
+    ctx.set_clock(TickingClock(0.into()));
+
+    // Reading the clock takes one tick, which is subtracted. Every call to `tick`
+    // takes one more tick.
+    evaluate_with_context(&mut ctx, "fn tick() = now()");
+    expect_output_with_context(
+        &mut ctx,
+        "benchmark(tick, 5)",
+        "BenchmarkResult { mean: 0.001 s, min: 0.001 s, max: 0.001 s, stddev: 0 s, iterations: 5 }",
+    );
+    expect_output_with_context(&mut ctx, "benchmark(tick, 1).mean -> ms", "1 ms");
+    expect_output_with_context(&mut ctx, "benchmark(tick, 1).stddev -> ms", "0 ms");
+
+    // The function is called the given number of times
+    evaluate_with_context(&mut ctx, "fn nothing() = 0");
+    expect_output_with_context(&mut ctx, "benchmark(nothing, 3).max -> ms", "0 ms");
+    expect_output_with_context(&mut ctx, "benchmark(nothing).iterations", "100");
+
+    expect_failure_with_context(
+        &mut ctx,
+        "let distance: Length = benchmark(tick, 2).mean",
+        "specified dimension: Length",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "benchmark(tick, 0)",
+        "The number of iterations needs to be a positive integer, got 0",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "benchmark(tick, 2.5)",
+        "The number of iterations needs to be a positive integer, got 2.5",
+    );
+}
+
+#[test]
+fn test_profile() {
+    let mut ctx = get_test_context();
+    assert!(ctx.profile().is_none());
+
+    ctx.set_profiling(true);
+    evaluate_with_context(
+        &mut ctx,
+        "fn square(x) = x * x
+         fn sum_of_squares(a, b) = square(a) + square(b)
+         fn fac(n) = if n <= 1 then 1 else n * fac(n - 1)
+         sum_of_squares(1, 2) + sum_of_squares(3, 4)
+         fac(30)
+         sum(map(square, [1, 2, 3]))",
+    );
+
+    let profile = ctx.profile().unwrap();
+    let calls = |name: &str| profile.get(name).unwrap().calls;
+    assert_eq!(calls("square"), 7);
+    assert_eq!(calls("sum_of_squares"), 2);
+    assert_eq!(calls("fac"), 30);
+    assert_eq!(calls("map"), 1);
+    assert!(profile.get("map").unwrap().native);
+    assert!(!profile.get("square").unwrap().native);
+
+    for function in &profile.functions {
+        assert!(function.exclusive_time <= function.inclusive_time);
+    }
+    // Time spent in `square` is not part of the exclusive time of its caller
+    let outer = profile.get("sum_of_squares").unwrap();
+    assert!(outer.exclusive_time < outer.inclusive_time);
+
+    // Time spent in recursive calls is not counted more than once
+    let started = std::time::Instant::now();
+    ctx.set_profiling(true);
+    evaluate_with_context(&mut ctx, "fac(100)");
+    let elapsed = started.elapsed();
+    let profile = ctx.profile().unwrap();
+    let fac = profile.get("fac").unwrap();
+    assert_eq!(fac.calls, 100);
+    assert!(fac.inclusive_time <= elapsed);
+    assert!(fac.exclusive_time <= fac.inclusive_time);
+
+    // Sorted by exclusive time
+    assert!(profile
+        .functions
+        .windows(2)
+        .all(|w| w[0].exclusive_time >= w[1].exclusive_time));
+
+    // Calls that are interrupted by an error are still recorded
+    ctx.set_profiling(true);
+    expect_failure_with_context(
+        &mut ctx,
+        "fn fail(x) = error(\"stop\")
+         fn outer(x) = fail(x) + 1
+         outer(1)",
+        "stop",
+    );
+    let profile = ctx.profile().unwrap();
+    assert_eq!(profile.get("outer").unwrap().calls, 1);
+    assert_eq!(profile.get("fail").unwrap().calls, 1);
+
+    ctx.set_profiling(false);
+    assert!(ctx.profile().is_none());
+}
+
+#[test]
+fn test_loops() {
+    expect_output("for x in [1, 2, 3] { x^2 }", "[1, 4, 9]");
+    expect_output("for x in [] { x }", "[]");
+    expect_output("for x in range(1, 4) { 2 x }", "[2, 4, 6, 8]");
+
+    // Summing with units via the accumulator
+    expect_output(
+        "for distance in [1 km, 300 m, 20 m] with total = 0 m { total + distance } -> m",
+        "1320 m",
+    );
+    expect_output("for x in [] with total = 5 s { total + x }", "5 s");
+    expect_output(
+        "for x in [1, 2, 3] with acc = [] { cons(x, acc) }",
+        "[3, 2, 1]",
+    );
+
+    // Nested loops
+    expect_output(
+        "for x in [1, 2] { for y in [10, 20] { x * y } }",
+        "[[10, 20], [20, 40]]",
+    );
+    expect_output(
+        "for row in [[1, 2], [3, 4]] with total = 0 { total + for x in row with subtotal = 0 { subtotal + x } }",
+        "10",
+    );
+
+    expect_output("while n < 1000 with n = 1 { 2 n }", "1024");
+    expect_output("while n < 0 with n = 1 { 2 n }", "1");
+
+    // Loops in functions can refer to parameters and local variables
+    expect_output(
+        "fn powers(base: Scalar, count: Scalar) -> List<Scalar> = for i in range(1, count) { base^i }
+         powers(2, 4)",
+        "[2, 4, 8, 16]",
+    );
+    expect_output(
+        "fn total_length(lengths: List<Length>) -> Length = for length in lengths with acc = offset { acc + length }
+           where offset = 1 m
+         total_length([1 cm, 2 cm]) -> cm",
+        "103 cm",
+    );
+    expect_output(
+        "fn collatz_end(n) = while k != 1 with k = n { if mod(k, 2) == 0 then k / 2 else 3 k + 1 }
+         collatz_end(27)",
+        "1",
+    );
+
+    expect_failure(
+        "for x in [1 m] with acc = 1 s { acc * x }",
+        "Incompatible types for accumulator",
+    );
+    expect_failure("for x in 2 { x }", "Can only loop over lists");
+}
+
+#[test]
+fn test_loop_iteration_limit() {
+    let mut ctx = get_test_context();
+    ctx.set_max_loop_iterations(100);
+
+    expect_output_with_context(&mut ctx, "while n < 100 with n = 0 { n + 1 }", "100");
+    expect_failure_with_context(
+        &mut ctx,
+        "while n < 101 with n = 0 { n + 1 }",
+        "While loop did not terminate within 100 iterations",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "while true with n = 0 { n }",
+        "While loop did not terminate within 100 iterations",
+    );
+
+    // The context can still be used after the loop has been aborted
+    expect_output_with_context(&mut ctx, "for x in [1, 2] with n = 0 { n + x }", "3");
+}
+
+#[test]
+fn test_try_catch() {
+    let checked_mass = "
+        fn checked_mass(mass: Mass) -> Mass =
+          if mass < 0 kg then error(\"negative mass not allowed: {mass}\") else mass
+        fn double_mass(mass: Mass) -> Mass = 2 checked_mass(mass)
+        fn total_mass(masses: List<Mass>) -> Mass = sum(map(double_mass, masses))
+    ";
+    let mut ctx = get_test_context();
+    let _ = ctx.interpret(checked_mass, CodeSource::Internal).unwrap();
+
+    // Errors propagate through nested calls
+    expect_failure_with_context(
+        &mut ctx,
+        "total_mass([1 kg, -2 kg])",
+        "User error: negative mass not allowed: -2 kg",
+    );
+
+    // …unless they are caught
+    expect_output_with_context(&mut ctx, "try { double_mass(3 kg) } catch { 0 kg }", "6 kg");
+    expect_output_with_context(&mut ctx, "try { double_mass(-3 kg) } catch { 1 g }", "1 g");
+    expect_output_with_context(
+        &mut ctx,
+        "try { \"{double_mass(-3 kg)}\" } catch message { message }",
+        "\"negative mass not allowed: -3 kg\"",
+    );
+    expect_output_with_context(
+        &mut ctx,
+        "try { map(double_mass, [1 kg, -2 kg]) } catch { [] }",
+        "[]",
+    );
+    expect_output_with_context(
+        &mut ctx,
+        "fn safe_double_mass(mass: Mass) -> Mass = try { double_mass(mass) } catch { 1 g }
+         map(safe_double_mass, [1 kg, -2 kg])",
+        "[2 kg, 1 g]",
+    );
+
+    // Catch branches can refer to local variables and re-raise errors
+    expect_output_with_context(
+        &mut ctx,
+        "fn safe_div(a, b, fallback) = try { a / b } catch { fallback }
+         safe_div(1, 0, 42)",
+        "42",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "fn wrapped_div(a, b) = try { a / b } catch message { error(\"could not divide: {message}\") }
+         wrapped_div(1, 0)",
+        "User error: could not divide: Division by zero",
+    );
+
+    // Aborted loops can not be caught
+    ctx.set_max_loop_iterations(10);
+    expect_failure_with_context(
+        &mut ctx,
+        "try { while true with n = 0 { n } } catch { 1 }",
+        "While loop did not terminate within 10 iterations",
+    );
+
+    expect_failure_with_context(
+        &mut ctx,
+        "try { 1 kg } catch { 1 m }",
+        "Incompatible types in 'try' and 'catch' branches",
+    );
+
+    // The context can still be used after an error has been caught
+    expect_output_with_context(&mut ctx, "total_mass([1 kg, 2 kg])", "6 kg");
+}
+
+#[test]
+fn test_minimum_maximum_clamp() {
+    expect_output("maximum(30 cm, 2 m, 1 ft)", "2 m");
+    expect_output("minimum(30 cm, 2 m, 1 ft)", "30 cm");
+    expect_output("minimum(5 s)", "5 s");
+    expect_output("max_of([30 cm, 2 m])", "2 m");
+    expect_output("min_of([30 cm, 2 m])", "30 cm");
+
+    expect_failure(
+        "maximum()",
+        "called with 0 arguments(s), but needs at least 1",
+    );
+    expect_failure(
+        "maximum(1 m, 2 s)",
+        "Could not solve the following constraints",
+    );
+
+    // Bounds are returned in the units they are given in
+    expect_output("clamp(3 m, 50 cm, 2 m)", "2 m");
+    expect_output("clamp(10 cm, 50 cm, 2 m)", "50 cm");
+    expect_output("clamp(1 m, 50 cm, 2 m)", "1 m");
+    expect_failure(
+        "clamp(1 m, 2 m, 50 cm)",
+        "The lower bound of clamp (2 m) is greater than the upper bound (50 cm)",
+    );
+
+    // NaN propagates
+    expect_output("maximum(1, NaN, 3)", "NaN");
+    expect_output("min_of([1, NaN, 3])", "NaN");
+    expect_output("clamp(NaN, 0, 1)", "NaN");
+    expect_output("clamp(0.5, 0, NaN)", "NaN");
+
+    // Numbat functions can be variadic, too
+    expect_output(
+        "fn total<D: Dim>(x: D, xs: D…) -> D = x + sum(xs)
+         total(1 m, 20 cm, 3 mm)",
+        "1.203 m",
+    );
+    expect_failure(
+        "fn total<D: Dim>(x: D, xs: D…) -> D = x + sum(xs)
+         total(1 m)",
+        "called with 1 arguments(s), but needs at least 2",
+    );
+}
+
+#[test]
+fn test_dynamic_type() {
+    let mut ctx = get_test_context();
+    let _ = ctx
+        .interpret(
+            r#"let distance: Dynamic = 3 m
+               let name: Dynamic = "numbat"
+               let data: Dynamic = parse_json("[1, \{\"value\": 2, \"unit\": \"km\"\}, true]")"#,
+            CodeSource::Internal,
+        )
+        .unwrap();
+
+    // Values of type `Dynamic` can be used like values of a static type
+    expect_output_with_context(&mut ctx, "distance * 2", "6 m");
+    expect_output_with_context(&mut ctx, "distance + 1 km", "1003 m");
+    expect_output_with_context(&mut ctx, "sqr(distance)", "9 m²");
+    expect_output_with_context(&mut ctx, "str_length(name)", "6");
+    expect_output_with_context(
+        &mut ctx,
+        "let length: Length = distance\nlength -> cm",
+        "300 cm",
+    );
+    expect_output_with_context(&mut ctx, "[1 km, distance]", "[1 km, 3 m]");
+    expect_output_with_context(&mut ctx, "data", "[1, 2 km, true]");
+    expect_output_with_context(&mut ctx, "for x in data with n = 0 { n + 1 }", "3");
+    expect_output_with_context(
+        &mut ctx,
+        "fn travel_time(x: Dynamic) -> Time = x / (1 m/s)\ntravel_time(distance)",
+        "3 s",
+    );
+
+    // Their type is checked at runtime
+    expect_failure_with_context(
+        &mut ctx,
+        "name + 1 m",
+        r#"Expected a value of type Length, got '"numbat"'"#,
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "let t: Time = distance",
+        "Expected a value of type Time, got '3 m'",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "let xs: List<Length> = data",
+        "Expected a value of type List<Length>, got '[1, 2 km, true]'",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "if distance then 1 else 2",
+        "Expected a value of type Bool, got '3 m'",
+    );
+    for expr in ["name * 2", "2 * name", "name / 1 m", "1 m / name", "name^2"] {
+        expect_failure_with_context(
+            &mut ctx,
+            expr,
+            r#"Expected a value of type a quantity, got '"numbat"'"#,
+        );
+    }
+    expect_failure_with_context(
+        &mut ctx,
+        "2^name",
+        r#"Expected a value of type Scalar, got '"numbat"'"#,
+    );
+    match ctx.interpret("str_length(distance)", CodeSource::Internal) {
+        Err(e) => assert_eq!(
+            e.diagnostics()[0].code.as_deref(),
+            Some(ErrorCode::E0840.as_str())
+        ),
+        Ok(_) => panic!("Expected a runtime error"),
+    }
+
+    // `Dynamic` does not leak into the types of other definitions
+    let _ = ctx
+        .interpret(
+            "fn add_distance(x) = x + distance
+             let doubled = 2 distance",
+            CodeSource::Internal,
+        )
+        .unwrap();
+    assert!(ctx
+        .print_info_for_keyword("add_distance")
+        .to_string()
+        .contains("fn add_distance<A: Dim>(x: A) -> A"));
+    expect_failure_with_context(
+        &mut ctx,
+        "let t: Time = doubled",
+        "Expected a value of type Time, got '6 m'",
+    );
+
+    // The `Dynamic` type can be forbidden
+    ctx.set_dynamic_type_allowed(false);
+    expect_failure_with_context(
+        &mut ctx,
+        "let x: Dynamic = 1",
+        "The 'Dynamic' type is not allowed in this context",
+    );
+    expect_output_with_context(&mut ctx, "distance * 2", "6 m");
+}
+
+#[test]
+fn test_definition_spans() {
+    let mut ctx = get_test_context();
+    let _ = ctx
+        .interpret(
+            "dimension Bribe = Mass
+unit wingspan = 1.8 m",
+            CodeSource::Text,
+        )
+        .unwrap();
+
+    let module_of = |ctx: &Context, name: &str| {
+        let span = ctx.definition_span(name).unwrap();
+        match ctx.resolver().get_code_source(span.code_source_id) {
+            CodeSource::Module(path, _) => path.to_string(),
+            other => format!("{other:?}"),
+        }
+    };
+
+    // Units and dimensions from the prelude
+    assert_eq!(module_of(&ctx, "foot"), "units::imperial");
+    assert_eq!(module_of(&ctx, "meter"), "units::si");
+    assert_eq!(module_of(&ctx, "Velocity"), "core::dimensions");
+
+    // User definitions
+    assert_eq!(module_of(&ctx, "wingspan"), "Text");
+    assert_eq!(module_of(&ctx, "Bribe"), "Text");
+    let span = ctx.definition_span("wingspan").unwrap();
+    assert_eq!((span.start.line, span.start.position), (2, 6));
+    assert_eq!((span.end.line, span.end.position), (2, 14));
+
+    assert!(ctx.definition_span("not_a_unit").is_none());
+}
+
+#[test]
+fn test_series() {
+    let mut ctx = get_test_context();
+    let _ = ctx
+        .interpret(
+            "fn inverse_square(n) = 1 / n^2
+             fn step(n) = n × 1 m
+             fn speed(n) = n × 1 km/h
+             fn identity(n) = n",
+            CodeSource::Internal,
+        )
+        .unwrap();
+
+    // Basel problem
+    expect_output_with_context(
+        &mut ctx,
+        "abs(sum_series(inverse_square, 1, 100_000) - pi^2 / 6) < 1e-5",
+        "true",
+    );
+    expect_output_with_context(&mut ctx, "sum_series(step, 1, 4)", "10 m");
+    expect_output_with_context(&mut ctx, "sum_series(speed, 1, 2) -> m/s", "0.833333 m/s");
+    expect_output_with_context(&mut ctx, "product_series(identity, 1, 5)", "120");
+    expect_output_with_context(&mut ctx, "sum_series(identity, -2, 3)", "3");
+
+    // Empty ranges
+    expect_output_with_context(&mut ctx, "sum_series(speed, 3, 1)", "0 m/s");
+    expect_output_with_context(&mut ctx, "sum_series(identity, 3, 1)", "0");
+    expect_output_with_context(&mut ctx, "product_series(identity, 3, 1)", "1");
+
+    expect_failure_with_context(
+        &mut ctx,
+        "sum_series(step, 1, 2.5)",
+        "The bounds of a series need to be integers, got 2.5",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "product_series(identity, 0.5, 2)",
+        "The bounds of a series need to be integers, got 0.5",
+    );
+}
+
+#[test]
+fn test_matrices() {
+    let mut ctx = get_test_context();
+    let _ = ctx
+        .interpret("use numerics::matrices", CodeSource::Internal)
+        .unwrap();
+
+    expect_output_with_context(
+        &mut ctx,
+        "matmul([[1 m, 2 m], [3 m, 4 m]], [[1 s^-1, 0 s^-1], [0 s^-1, 2 s^-1]])",
+        "[[1 m/s, 4 m/s], [3 m/s, 8 m/s]]",
+    );
+    expect_output_with_context(
+        &mut ctx,
+        "let velocities: List<List<Velocity>> = matmul([[1 km, 2 m]], [[1 / s], [1 / s]])
+         velocities",
+        "[[1.002 km/s]]",
+    );
+    expect_output_with_context(
+        &mut ctx,
+        "transpose([[1, 2, 3], [4, 5, 6]])",
+        "[[1, 4], [2, 5], [3, 6]]",
+    );
+    expect_output_with_context(&mut ctx, "transpose([])", "[]");
+    expect_output_with_context(&mut ctx, "identity(2)", "[[1, 0], [0, 1]]");
+    expect_output_with_context(
+        &mut ctx,
+        "matmul(identity(2), [[1, 2], [3, 4]])",
+        "[[1, 2], [3, 4]]",
+    );
+
+    // Currents in a circuit, with partial pivoting
+    expect_output_with_context(
+        &mut ctx,
+        "let currents = solve_linear([[0 Ω, 2 Ω], [3 Ω, 1 Ω]], [4 V, 11 V])
+         element_at(0, currents) + element_at(1, currents) -> mA",
+        "5000 mA",
+    );
+    expect_output_with_context(
+        &mut ctx,
+        "solve_linear([[2 N/m, 1 kN/km], [1 N/m, 3 N/m]], [5 N, 10 N])",
+        "[1 m, 3 m]",
+    );
+
+    expect_failure_with_context(
+        &mut ctx,
+        "matmul([[1, 2, 3], [4, 5, 6]], [[1, 2], [3, 4]])",
+        "Invalid matrix shape: can not multiply 2×3 by 2×2",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "transpose([[1, 2], [3]])",
+        "Invalid matrix shape: row 1 has 1 element(s), but row 0 has 2",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "solve_linear([[1, 2, 3], [4, 5, 6]], [1, 2])",
+        "Invalid matrix shape: can not solve a linear system with a non-square 2×3 matrix",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "solve_linear([[1, 2], [3, 4]], [1, 2, 3])",
+        "right-hand side of length 3",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "solve_linear([[1 m, 2 m], [2 m, 4 m]], [1, 2])",
+        "Matrix is singular (zero pivot in column 1)",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "solve_linear([[0, 0], [0, 1]], [1, 2])",
+        "Matrix is singular (zero pivot in column 0)",
+    );
+}
+
+#[test]
+fn test_unit_functions() {
+    expect_output("awg(12) -> mm", "2.05253 mm");
+    expect_output("awg(-3) -> mm", "11.684 mm");
+    expect_output("round(2.05253 mm -> awg)", "12");
+    expect_output("round(awg(10) -> awg)", "10");
+    expect_output("ph(7)", "1.0e-7 mol/l");
+    expect_output("1e-3 mol/L -> ph", "3");
+    expect_output("dBm(30) -> W", "1 W");
+    expect_output("1 mW -> dBm", "0");
+
+    let mut ctx = get_test_context();
+    let _ = ctx
+        .interpret(
+            "unitfn stride(n: Scalar) -> Length = n × 80 cm
+               inverse(distance) = distance / 80 cm
+             unitfn offset(x) = x + 1",
+            CodeSource::Internal,
+        )
+        .unwrap();
+    expect_output_with_context(&mut ctx, "stride(5)", "400 cm");
+    expect_output_with_context(&mut ctx, "2 km -> stride", "2500");
+    expect_output_with_context(&mut ctx, "offset(2)", "3");
+    expect_failure_with_context(
+        &mut ctx,
+        "3 -> offset",
+        "'offset' can not be used as a conversion target, since it has no inverse",
+    );
+
+    // The inverse needs to map the result of the function back to its argument
+    expect_failure(
+        "unitfn stride(n: Scalar) -> Length = n × 80 cm
+           inverse(distance) = distance",
+        "specified return type: Scalar",
+    );
+    expect_failure(
+        "unitfn stride(n) = n × 80 cm
+           inverse(distance) = distance × 80 cm",
+        "Could not solve the following constraints",
+    );
+
+    expect_failure(
+        "unitfn two(x, y) = x + y",
+        "A unit function needs exactly one parameter and a body, and can not be generic",
+    );
+    expect_failure(
+        "unitfn sized<D: Dim>(x: D) -> D = x",
+        "A unit function needs exactly one parameter and a body, and can not be generic",
+    );
+    expect_failure(
+        "unitfn declared(x: Scalar) -> Length",
+        "A unit function needs exactly one parameter and a body, and can not be generic",
+    );
+    expect_failure(
+        "unitfn stride(n: Scalar) -> Length = n × 80 cm
+           inverse distance = distance / 80 cm",
+        "Expected the definition of the inverse, e.g. 'inverse(x) = …'",
+    );
+}
+
+#[test]
+fn test_compensated_summation() {
+    let mut ctx = get_test_context();
+    let _ = ctx
+        .interpret(
+            "fn add(a, b) = a + b
+             fn tenth(n) = 0.1",
+            CodeSource::Internal,
+        )
+        .unwrap();
+
+    // Naive summation loses the small terms
+    expect_output_with_context(&mut ctx, "sum([1e20, 1, -1e20])", "1");
+    expect_output_with_context(&mut ctx, "foldl(add, 0, [1e20, 1, -1e20])", "0");
+    expect_output_with_context(&mut ctx, "sum(map(tenth, range(1, 10))) == 1", "true");
+    expect_output_with_context(
+        &mut ctx,
+        "foldl(add, 0, map(tenth, range(1, 10))) == 1",
+        "false",
+    );
+    expect_output_with_context(&mut ctx, "sum_series(tenth, 1, 10) == 1", "true");
+
+    // Dimensions and units are preserved
+    expect_output_with_context(&mut ctx, "sum([1e20 m, 1 cm, -1e20 m]) -> cm", "1 cm");
+    expect_output_with_context(&mut ctx, "sum([2 km, 300 m])", "2.3 km");
+    expect_output_with_context(&mut ctx, "sum([1 m, -1 m, 5 cm])", "5 cm");
+    expect_output_with_context(&mut ctx, "mean([1e20 s, 3 s, -1e20 s])", "1 s");
+    expect_output_with_context(&mut ctx, "sum([1e308 m, 1e308 m])", "inf m");
+}
+
+#[test]
+fn test_summation_error_warnings() {
+    let mut ctx = get_test_context();
+    let _ = ctx
+        .interpret("fn square(n) = n^2", CodeSource::Internal)
+        .unwrap();
+
+    // Returns the notes of all warnings
+    fn warnings(ctx: &mut Context, code: &str) -> Vec<Vec<String>> {
+        let mut sink = BufferingSink::default();
+        let _ = ctx
+            .interpret_with_sink(&mut sink, code, CodeSource::Internal)
+            .unwrap();
+        sink.events()
+            .iter()
+            .filter_map(|event| match event {
+                OutputEvent::Diagnostic(d) if d.severity == Severity::Warning => {
+                    Some(d.notes.clone())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Disabled by default
+    assert!(warnings(&mut ctx, "sum([1e20, 1, -1e20])").is_empty());
+
+    ctx.set_summation_error_threshold(Some(1e-9));
+    let notes = warnings(&mut ctx, "sum([1e20 m, 1 m, -1e20 m])");
+    assert_eq!(notes.len(), 1);
+    assert_eq!(
+        notes[0][0],
+        "The estimated relative rounding error of a sum of 3 term(s) is 4.4e4, which exceeds the threshold of 1e-9."
+    );
+    assert_eq!(warnings(&mut ctx, "mean([1e20, 1, -1e20])").len(), 1);
+
+    // Sums without cancellation are accurate
+    assert!(warnings(&mut ctx, "sum(map(square, range(1, 10_000)))").is_empty());
+    assert!(warnings(&mut ctx, "sum([1 m, -1 m])").is_empty());
+
+    ctx.set_summation_error_threshold(Some(1e6));
+    assert!(warnings(&mut ctx, "sum([1e20, 1, -1e20])").is_empty());
+}
+
+#[test]
+fn test_implicit_conversions() {
+    // Returns the result (or the error message) and the notes of all warnings
+    fn run(ctx: &mut Context, code: &str) -> (Result<String, String>, Vec<Vec<String>>) {
+        let mut sink = BufferingSink::default();
+        let result = ctx
+            .interpret_with_sink(&mut sink, code, CodeSource::Internal)
+            .map(|(_, result)| result.value_as_string().unwrap_or_default())
+            .map_err(|e| e.to_string());
+        let notes = sink
+            .events()
+            .iter()
+            .filter_map(|event| match event {
+                OutputEvent::Diagnostic(d) if d.severity == Severity::Warning => {
+                    Some(d.notes.clone())
+                }
+                _ => None,
+            })
+            .collect();
+        (result, notes)
+    }
+
+    let program = "2 m + 30 cm - 5 mm";
+
+    let mut ctx = get_test_context();
+    assert_eq!(run(&mut ctx, program), (Ok("2.295 m".into()), vec![]));
+
+    ctx.set_implicit_conversions(ImplicitConversions::Warn);
+    let (result, notes) = run(&mut ctx, program);
+    assert_eq!(result, Ok("2.295 m".into()));
+    assert_eq!(notes.len(), 2);
+    assert_eq!(
+        notes[0][0],
+        "A quantity in 'cm' was converted to 'm' by multiplying it with 0.01."
+    );
+    assert_eq!(
+        notes[1][0],
+        "A quantity in 'mm' was converted to 'm' by multiplying it with 0.001."
+    );
+
+    ctx.set_implicit_conversions(ImplicitConversions::Forbid);
+    let (result, notes) = run(&mut ctx, program);
+    assert_eq!(
+        result,
+        Err("Implicit conversion from 'cm' to 'm' is not allowed. Convert explicitly, e.g. with '… -> m'".into())
+    );
+    assert!(notes.is_empty());
+
+    // No conversion is needed if the units already match
+    for level in [ImplicitConversions::Warn, ImplicitConversions::Forbid] {
+        ctx.set_implicit_conversions(level);
+        assert_eq!(run(&mut ctx, "2 m + 3 m"), (Ok("5 m".into()), vec![]));
+        assert_eq!(
+            run(&mut ctx, "2 m - (30 cm -> m)"),
+            (Ok("1.7 m".into()), vec![])
+        );
+        assert_eq!(run(&mut ctx, "2 m + 0 cm"), (Ok("2 m".into()), vec![]));
+        assert_eq!(run(&mut ctx, "2 m·s + 3 s·m"), (Ok("5 m·s".into()), vec![]));
+    }
+
+    // The setting can also be changed from within Numbat
+    let mut ctx = get_test_context();
+    let _ = run(&mut ctx, "set_implicit_conversions(\"forbid\")");
+    assert!(run(&mut ctx, program).0.is_err());
+    let _ = run(&mut ctx, "set_implicit_conversions(\"warn\")");
+    assert_eq!(run(&mut ctx, program).1.len(), 2);
+    let _ = run(&mut ctx, "set_implicit_conversions(\"silent\")");
+    assert_eq!(run(&mut ctx, program), (Ok("2.295 m".into()), vec![]));
+
+    expect_failure_with_context(
+        &mut ctx,
+        "set_implicit_conversions(\"loud\")",
+        "Unknown setting 'loud' for implicit conversions",
+    );
+    expect_failure_with_context(
+        &mut ctx,
+        "set_implicit_conversions(true)",
+        "Incompatible types",
+    );
+}
+
+#[test]
+#[cfg(not(feature = "datetime"))]
+fn test_datetime_feature_not_available() {
+    let error = fail("now()");
+    assert_eq!(
+        error.to_string(),
+        "'now' is not available, because Numbat was built without datetime support"
+    );
+    assert_eq!(error.diagnostics()[0].code.as_deref(), Some("E0565"));
+
+    expect_failure(
+        "datetime(\"2024-01-01 12:00 UTC\")",
+        "'datetime' is not available",
+    );
+    expect_failure("1 + foo", "Unknown identifier 'foo'");
+}
+
+#[test]
+#[cfg(not(feature = "currency"))]
+fn test_currency_feature_not_available() {
+    let error = fail("30 USD");
+    assert_eq!(
+        error.to_string(),
+        "'USD' is not available, because Numbat was built without currency support"
+    );
+    assert_eq!(error.diagnostics()[0].code.as_deref(), Some("E0565"));
+
+    expect_failure("10 GBP -> €", "'GBP' is not available");
+    expect_output("2 € + 3 euro", "5 €");
+}
+
+#[test]
+fn test_max_unit_complexity() {
+    let mut ctx = get_test_context();
+
+    let result = |ctx: &mut Context, code: &str| {
+        let (statements, result) = ctx.interpret(code, CodeSource::Internal).unwrap();
+        let markup = ctx.result_to_markup(&result, statements.last(), false, false);
+        (
+            PlainTextFormatter {}
+                .format(&markup, false)
+                .trim()
+                .to_string(),
+            result,
+        )
+    };
+
+    // Disabled by default
+    assert_eq!(
+        result(&mut ctx, "2 kg^3 s^5 / (m^7 A^2)").0,
+        "2 kg³·s⁵/(A²·m^7)"
+    );
+
+    ctx.set_max_unit_complexity(Some(17));
+    assert_eq!(
+        result(&mut ctx, "2 kg^3 s^5 / (m^7 A^2)").0,
+        "2 kg³·s⁵/(A²·m^7)"
+    );
+
+    ctx.set_max_unit_complexity(Some(16));
+    let (output, value) = result(&mut ctx, "1234.5 kg^3 s^5 / (m^7 A^2)");
+    assert_eq!(
+        output,
+        "1.2345e+3 (unit too complex; use `-> <unit>` or `simplify`)"
+    );
+
+    // The value keeps its unit
+    let InterpreterResult::Value(Value::Quantity(quantity)) = value else {
+        panic!();
+    };
+    assert_eq!(quantity.to_string(), "1234.5 kg³·s⁵/(A²·m^7)");
+
+    // … and can be shown in full
+    let info = PlainTextFormatter {}.format(&ctx.print_info_for_keyword("ans"), false);
+    assert!(info.contains("= 1234.5 kg³·s⁵/(A²·m^7)"), "{info}");
+
+    // Explicit conversions and simplifications
+    assert_eq!(
+        result(&mut ctx, "ans -> kg^3 s^5 / (m^7 A^2)").0,
+        "1234.5 kg³·s⁵/(m^7·A²)"
+    );
+    assert_eq!(
+        result(
+            &mut ctx,
+            "1 kg^3 s^5 / (m^7 A^2) * 1 m^7 / s^4 / kg^2 |> simplify"
+        )
+        .0,
+        "1 kg·s/A²"
+    );
+
+    // Named units are always shown, even if their base representation is complex
+    ctx.set_max_unit_complexity(Some(3));
+    assert_eq!(result(&mut ctx, "3 V").0, "3 V");
+    assert_eq!(result(&mut ctx, "3 kg m^2 / (A s^3)").0, "3 kg·m²/(A·s³)");
+    assert_eq!(
+        result(&mut ctx, "3 V / s").0,
+        "3e+0 (unit too complex; use `-> <unit>` or `simplify`)"
+    );
+}
+
+#[test]
+fn long_chains_of_binary_operators() {
+    // Generated programs can contain sums with hundreds of thousands of terms. They
+    // are evaluated in a thread with a small stack, to make sure that none of the
+    // stages recurses into every operand.
+    let sum = (0..200_000)
+        .map(|i| format!("{} cm", i % 9 + 1))
+        .collect::<Vec<_>>()
+        .join(" + ");
+    let code = format!("({sum}) - 4_000 m -> m");
+
+    let output = std::thread::Builder::new()
+        .stack_size(4 * 1024 * 1024)
+        .spawn(move || {
+            let mut ctx = get_test_context();
+            let (statements, result) = ctx.interpret(&code, CodeSource::Internal).unwrap();
+            let statement = statements.last().unwrap().pretty_print().to_string();
+            let markup = ctx.result_to_markup(&result, statements.last(), true, false);
+            (
+                statement.len(),
+                PlainTextFormatter {}.format(&markup, false),
+            )
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+
+    assert!(output.0 > 200_000 * 4);
+    assert_eq!(output.1.trim(), "5999.93 m    [Length]");
+}
+
+#[test]
+fn chains_of_binary_operators_match_stepwise_evaluation() {
+    // Each chain is evaluated as a whole and operation by operation, with the
+    // intermediate results stored in variables
+    let chains: &[&[&str]] = &[
+        &["1 m", "+", "2 cm", "-", "3 mm", "+", "1 ft"],
+        &["2 km", "/", "3 h", "*", "4 min", "/", "(1 s)"],
+        &["10 m^2", "/", "2 m", "/", "5 s", "*", "3"],
+        &["3", "+", "4 × 5", "-", "6 / 2", "+", "2^3^2"],
+        &["3 kg", "*", "2 m", "/", "s^2", "/", "kg"],
+        &["6 N", "-", "1 N", "+", "(2 N -> kN)", "-", "3 kg m / s^2"],
+        &["100 %", "*", "3", "/", "4", "×", "5 %"],
+        &["1 inch", "+", "2 ft", "-", "3 cm", "-", "1 mm"],
+        &["5", "-", "4", "-", "3", "-", "2"],
+        &["2^3", "*", "4", "/", "8", "/", "2"],
+        &["1 kWh / 1 h", "+", "3 W", "-", "(1 kW)"],
+        &["1 km", "+", "2 m", "-", "3 cm"],
+        &["3 m", "per", "s", "*", "2 s"],
+    ];
+
+    let evaluate = |ctx: &mut Context, code: &str| {
+        let (statements, result) = ctx.interpret(code, CodeSource::Internal).unwrap();
+        let markup = ctx.result_to_markup(&result, statements.last(), true, false);
+        PlainTextFormatter {}
+            .format(&markup, false)
+            .trim()
+            .to_string()
+    };
+
+    for chain in chains {
+        let mut ctx = get_test_context();
+        let whole = evaluate(&mut ctx, &chain.join(" "));
+
+        let mut step = chain[0].to_string();
+        for operation in chain[1..].chunks(2) {
+            let [op, operand] = operation else {
+                panic!("Chains end with an operand");
+            };
+            let _ = evaluate(&mut ctx, &format!("let acc = {step}"));
+            step = format!("acc {op} ({operand})");
+        }
+        let stepwise = evaluate(&mut ctx, &step);
+
+        assert_eq!(whole, stepwise, "{}", chain.join(" "));
+    }
 }
@@ -0,0 +1,224 @@
+mod common;
+
+use common::get_test_context;
+use numbat::introspection::{Origin, Parameter, TypeParameter};
+use numbat::resolver::CodeSource;
+
+#[test]
+fn prelude_functions() {
+    let catalog = get_test_context().introspect();
+
+    let sqrt = catalog.function("sqrt").unwrap();
+    assert_eq!(sqrt.signature, "fn sqrt<D: Dim>(x: D^2) -> D");
+    assert_eq!(
+        sqrt.type_parameters,
+        [TypeParameter {
+            name: "D".into(),
+            bound: Some("Dim".into())
+        }]
+    );
+    assert_eq!(sqrt.return_type, "D");
+    assert_eq!(
+        sqrt.origin,
+        Origin {
+            module: Some("core::functions".into()),
+            prelude: true
+        }
+    );
+    assert!(sqrt.description.is_some());
+    assert!(sqrt.deprecated.is_none());
+
+    let maximum = catalog.function("maximum").unwrap();
+    assert_eq!(maximum.signature, "fn maximum<D: Dim>(xs: D…) -> D");
+    assert!(maximum.parameters[0].variadic);
+
+    // Helper functions are not listed
+    assert!(catalog.functions.iter().all(|f| !f.name.starts_with('_')));
+}
+
+#[test]
+fn prelude_units_and_dimensions() {
+    let catalog = get_test_context().introspect();
+
+    let metre = catalog.unit("metre").unwrap();
+    assert_eq!(metre.dimension, "Length");
+    assert_eq!(metre.definition, None);
+    assert!(metre.aliases.contains(&"m".to_string()));
+    assert!(metre.metric_prefixes);
+    assert!(metre.origin.prelude);
+
+    let inch = catalog.unit("inch").unwrap();
+    assert_eq!(inch.definition.as_deref(), Some("0.0254 m"));
+    assert!(!inch.metric_prefixes);
+
+    assert_eq!(
+        catalog.dimension("Velocity").unwrap().base_representation,
+        "Length / Time"
+    );
+    assert!(catalog.dimension("Length").unwrap().origin.prelude);
+
+    // Loaded separately, not through the prelude
+    #[cfg(feature = "currency")]
+    {
+        let dollar = catalog.unit("dollar").unwrap();
+        assert!(!dollar.origin.prelude);
+        assert_eq!(dollar.origin.module.as_deref(), Some("units::currencies"));
+    }
+}
+
+#[test]
+fn session_definitions() {
+    let mut ctx = get_test_context();
+    assert!(ctx.introspect().struct_("Particle").is_none());
+
+    let _ = ctx
+        .interpret(
+            "struct Particle { mass: Mass, position: Length }
+         fn kinetic_energy(p: Particle, v: Velocity, factor: Scalar = 1/2) -> Energy =
+             factor × p.mass × v^2
+         impl Particle { fn momentum(self, v: Velocity) -> Momentum = self.mass × v }
+         dimension Strangeness
+         unit strange: Strangeness",
+            CodeSource::Text,
+        )
+        .unwrap();
+    let catalog = ctx.introspect();
+
+    let session = Origin {
+        module: None,
+        prelude: false,
+    };
+
+    let particle = catalog.struct_("Particle").unwrap();
+    assert_eq!(
+        particle.fields,
+        [
+            ("mass".to_string(), "Mass".to_string()),
+            ("position".to_string(), "Length".to_string())
+        ]
+    );
+    assert_eq!(particle.methods, ["momentum"]);
+    assert_eq!(particle.origin, session);
+
+    // Methods are not listed as free functions
+    assert!(catalog
+        .functions
+        .iter()
+        .all(|f| !f.name.contains("momentum")));
+
+    let kinetic_energy = catalog.function("kinetic_energy").unwrap();
+    assert_eq!(
+        kinetic_energy.signature,
+        "fn kinetic_energy(p: Particle, v: Velocity, factor: Scalar) -> Energy"
+    );
+    assert_eq!(
+        kinetic_energy.parameters[2],
+        Parameter {
+            name: "factor".into(),
+            type_: "Scalar".into(),
+            has_default: true,
+            variadic: false,
+        }
+    );
+    assert!(!kinetic_energy.parameters[1].has_default);
+    assert_eq!(kinetic_energy.origin, session);
+
+    assert_eq!(catalog.dimension("Strangeness").unwrap().origin, session);
+    assert_eq!(catalog.unit("strange").unwrap().dimension, "Strangeness");
+
+    // Session definitions come after the prelude
+    assert_eq!(catalog.units.last().unwrap().name, "strange");
+}
+
+#[test]
+fn deprecation() {
+    let mut ctx = get_test_context();
+    let _ = ctx
+        .interpret(
+            "@deprecated(\"Use 'new_fn' instead\")
+         fn old_fn(x: Scalar) -> Scalar = x
+
+         @deprecated(\"Use 'metre' instead\")
+         unit old_unit = 1 m",
+            CodeSource::Text,
+        )
+        .unwrap();
+    let catalog = ctx.introspect();
+
+    assert_eq!(
+        catalog.function("old_fn").unwrap().deprecated.as_deref(),
+        Some("Use 'new_fn' instead")
+    );
+    assert_eq!(
+        catalog.unit("old_unit").unwrap().deprecated.as_deref(),
+        Some("Use 'metre' instead")
+    );
+    assert!(catalog.unit("metre").unwrap().deprecated.is_none());
+}
+
+#[test]
+fn formulas() {
+    let mut ctx = get_test_context();
+    assert!(ctx.introspect().formulas.is_empty());
+
+    let _ = ctx
+        .interpret(
+            "formula ohms_law(voltage: Voltage, current: Current, resistance: ElectricResistance):
+             voltage = current × resistance
+         formula free_fall: height = 1/2 × acceleration × duration^2",
+            CodeSource::Text,
+        )
+        .unwrap();
+    let catalog = ctx.introspect();
+
+    let ohms_law = catalog.formula("ohms_law").unwrap();
+    assert_eq!(ohms_law.equation, "voltage = current × resistance");
+    assert_eq!(
+        ohms_law.quantities,
+        [
+            ("voltage".to_string(), "Voltage".to_string()),
+            ("current".to_string(), "Current".to_string()),
+            ("resistance".to_string(), "ElectricResistance".to_string()),
+        ]
+    );
+    assert_eq!(
+        ohms_law.functions,
+        [
+            "ohms_law_voltage",
+            "ohms_law_current",
+            "ohms_law_resistance"
+        ]
+    );
+    assert_eq!(
+        ohms_law.origin,
+        Origin {
+            module: None,
+            prelude: false
+        }
+    );
+
+    // The functions are listed along with all other functions
+    let current = catalog.function("ohms_law_current").unwrap();
+    assert_eq!(
+        current.signature,
+        "fn ohms_law_current(voltage: Voltage, resistance: ElectricResistance) -> Current"
+    );
+    assert_eq!(
+        current.description.as_deref().map(str::trim),
+        Some("Solves the formula `ohms_law` for `current`.")
+    );
+
+    let free_fall = catalog.formula("free_fall").unwrap();
+    assert_eq!(
+        free_fall.functions,
+        [
+            "free_fall_height",
+            "free_fall_acceleration",
+            "free_fall_duration"
+        ]
+    );
+    assert_eq!(
+        catalog.function("free_fall_duration").unwrap().signature,
+        "fn free_fall_duration<A: Dim, B: Dim>(height: A, acceleration: A / B²) -> B"
+    );
+}
@@ -2,6 +2,7 @@ mod common;
 
 use common::get_test_context;
 
+use numbat::diagnostic::ErrorDiagnostic;
 use numbat::resolver::{CodeSource, ResolverError};
 use numbat::{InterpreterResult, NumbatError};
 
@@ -10,8 +11,21 @@ use std::fs;
 
 use crate::common::get_test_context_without_prelude;
 
+/// Whether the code failed because it needs a feature that is disabled in this build
+fn needs_disabled_feature<T>(result: &Result<T, NumbatError>) -> bool {
+    result.as_ref().is_err_and(|e| {
+        e.diagnostics()
+            .iter()
+            .any(|d| d.code.as_deref() == Some("E0565"))
+    })
+}
+
 fn assert_runs(code: &str) {
     let result = get_test_context().interpret(code, CodeSource::Internal);
+    if needs_disabled_feature(&result) {
+        println!("Skipped, needs a disabled feature");
+        return;
+    }
     assert!(result.is_ok(), "Failed with: {result:#?}");
     assert!(matches!(
         result.unwrap().1,
@@ -21,6 +35,10 @@ fn assert_runs(code: &str) {
 
 fn assert_runs_without_prelude(code: &str) {
     let result = get_test_context_without_prelude().interpret(code, CodeSource::Internal);
+    if needs_disabled_feature(&result) {
+        println!("Skipped, needs a disabled feature");
+        return;
+    }
     assert!(result.is_ok());
     assert!(matches!(
         result.unwrap().1,
@@ -72,9 +90,26 @@ fn run_for_each_file(glob_pattern: &str, f: impl Fn(&str)) {
     }
 }
 
+/// Builtin modules that are only loaded with an optional feature
+const OPTIONAL_MODULES: &[(&str, bool)] = &[
+    ("modules/datetime/", cfg!(feature = "datetime")),
+    ("modules/units/currencies.nbt", cfg!(feature = "currency")),
+];
+
 #[test]
 fn modules_are_self_consistent() {
-    run_for_each_file("modules/**/*.nbt", assert_runs_without_prelude);
+    for entry in glob::glob("modules/**/*.nbt").unwrap() {
+        let path = entry.unwrap();
+        let disabled = OPTIONAL_MODULES
+            .iter()
+            .any(|(prefix, enabled)| !enabled && path.to_string_lossy().starts_with(prefix));
+        if disabled {
+            continue;
+        }
+
+        println!("Testing module {path:?}");
+        assert_runs_without_prelude(&fs::read_to_string(path).unwrap());
+    }
 }
 
 #[test]
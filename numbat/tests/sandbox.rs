@@ -0,0 +1,235 @@
+// The sandbox restricts access to the file system, the clock and the network
+#![cfg(all(feature = "fs", feature = "datetime", feature = "currency"))]
+
+use std::path::Path;
+use std::sync::Arc;
+
+use numbat::clock::FixedClock;
+use numbat::file_system::InMemoryFileSystem;
+use numbat::module_importer::{BuiltinModuleImporter, ChainedImporter, FileSystemImporter};
+use numbat::resolver::{CodeSource, ResolverError};
+use numbat::sandbox::{Capability, SandboxPolicy};
+use numbat::{Context, InterpreterResult, NumbatError, RuntimeError};
+
+fn sandboxed_context(policy: SandboxPolicy) -> Context {
+    sandboxed_context_with_module_path(policy, &std::env::temp_dir())
+}
+
+fn sandboxed_context_with_module_path(policy: SandboxPolicy, module_path: &Path) -> Context {
+    let mut importer = FileSystemImporter::default();
+    importer.add_path(module_path);
+
+    let mut ctx = Context::new_with_sandbox_policy(
+        ChainedImporter::new(
+            Box::new(importer),
+            Box::new(BuiltinModuleImporter::default()),
+        ),
+        policy,
+    );
+    let _ = ctx.interpret("use prelude", CodeSource::Internal).unwrap();
+    ctx
+}
+
+fn evaluate(ctx: &mut Context, code: &str) -> Result<String, NumbatError> {
+    let (_, result) = ctx.interpret(code, CodeSource::Internal)?;
+    match result {
+        InterpreterResult::Value(value) => Ok(value.to_string()),
+        InterpreterResult::Continue => Ok(String::new()),
+    }
+}
+
+fn expect_permission_denied(ctx: &mut Context, code: &str, capability: Capability) {
+    match evaluate(ctx, code) {
+        Err(NumbatError::RuntimeError(RuntimeError::PermissionDenied(c))) => {
+            assert_eq!(c, capability, "for code '{code}'")
+        }
+        result => panic!("Expected permission to be denied for '{code}', got {result:?}"),
+    }
+}
+
+#[test]
+fn pure_computations_are_not_affected() {
+    let mut ctx = sandboxed_context(SandboxPolicy::strict());
+    assert!(ctx.sandbox_policy().is_strict());
+
+    assert_eq!(evaluate(&mut ctx, "2 km / 4 s -> m/s").unwrap(), "500 m/s");
+    assert_eq!(
+        evaluate(&mut ctx, "sum(map(sqr, [1, 2, 3]))").unwrap(),
+        "14"
+    );
+    assert_eq!(
+        evaluate(
+            &mut ctx,
+            "format_datetime(\"%H:%M\", datetime(\"2024-02-03 10:00 +0200\"))"
+        )
+        .unwrap(),
+        "\"10:00\""
+    );
+
+    // Modules that do not come from the filesystem can still be imported
+    let _ = ctx
+        .interpret("use extra::algebra", CodeSource::Internal)
+        .unwrap();
+}
+
+#[test]
+fn filesystem_access_is_denied() {
+    let module_path = std::env::temp_dir().join(format!("numbat-sandbox-{}", std::process::id()));
+    std::fs::create_dir_all(&module_path).unwrap();
+    std::fs::write(module_path.join("local_module.nbt"), "let answer = 42").unwrap();
+
+    let mut ctx = sandboxed_context_with_module_path(SandboxPolicy::strict(), &module_path);
+    assert!(matches!(
+        ctx.interpret("use local_module", CodeSource::Internal),
+        Err(NumbatError::ResolverError(ResolverError::PermissionDenied(
+            _,
+            _,
+            Capability::Filesystem
+        )))
+    ));
+    assert!(ctx.list_modules().all(|module| module != "local_module"));
+    assert!(ctx.list_modules().any(|module| module == "prelude"));
+
+    // Without sandbox, the same module is found
+    let mut ctx = sandboxed_context_with_module_path(SandboxPolicy::unrestricted(), &module_path);
+    let _ = ctx
+        .interpret("use local_module", CodeSource::Internal)
+        .unwrap();
+    assert_eq!(evaluate(&mut ctx, "answer").unwrap(), "42");
+    assert!(ctx.list_modules().any(|module| module == "local_module"));
+
+    std::fs::remove_dir_all(&module_path).unwrap();
+}
+
+#[test]
+fn network_access_is_denied() {
+    let mut ctx = sandboxed_context(SandboxPolicy::strict());
+    ctx.load_currency_module_on_demand(true);
+    expect_permission_denied(&mut ctx, "2 USD", Capability::Network);
+
+    let mut ctx = sandboxed_context(SandboxPolicy::strict());
+    expect_permission_denied(&mut ctx, "use units::currencies", Capability::Network);
+}
+
+#[test]
+fn local_timezone_is_utc() {
+    let mut ctx = sandboxed_context(SandboxPolicy::strict());
+    assert_eq!(
+        evaluate(&mut ctx, "get_local_timezone()").unwrap(),
+        "\"UTC\""
+    );
+    assert_eq!(
+        evaluate(&mut ctx, "datetime(\"2024-02-03 10:00 +0200\") -> local").unwrap(),
+        "datetime(\"2024-02-03T08:00:00+00:00[UTC]\")"
+    );
+    assert_eq!(
+        evaluate(&mut ctx, "datetime(\"2024-02-03 10:00\") -> tz(\"UTC\")").unwrap(),
+        "datetime(\"2024-02-03T10:00:00+00:00[UTC]\")"
+    );
+    assert_eq!(
+        evaluate(&mut ctx, "from_unixtime(0) -> tz(\"UTC\")").unwrap(),
+        "datetime(\"1970-01-01T00:00:00+00:00[UTC]\")"
+    );
+}
+
+#[test]
+fn current_time() {
+    let mut ctx = sandboxed_context(SandboxPolicy::strict());
+    expect_permission_denied(&mut ctx, "now()", Capability::Clock);
+    expect_permission_denied(&mut ctx, "today()", Capability::Clock);
+
+    // A clock can be provided by the embedding application
+    ctx.set_clock(FixedClock::new(
+        "2024-02-03T10:00:00+01:00[Europe/Berlin]".parse().unwrap(),
+    ));
+    assert_eq!(
+        evaluate(&mut ctx, "format_datetime(\"%Y-%m-%d %H:%M\", now())").unwrap(),
+        "\"2024-02-03 10:00\""
+    );
+    assert_eq!(evaluate(&mut ctx, "now() - now()").unwrap(), "0 s");
+
+    // Time can be fixed without restricting anything else
+    let mut ctx = sandboxed_context(SandboxPolicy::unrestricted());
+    ctx.set_clock(FixedClock::new(
+        "2000-01-01T00:00:00Z[UTC]".parse().unwrap(),
+    ));
+    assert!(!ctx.sandbox_policy().is_strict());
+    assert_eq!(
+        evaluate(&mut ctx, "unixtime(now())").unwrap(),
+        "946_684_800"
+    );
+}
+
+#[test]
+fn setting_a_fixed_time() {
+    let mut ctx = sandboxed_context(SandboxPolicy::strict());
+    expect_permission_denied(
+        &mut ctx,
+        "set_fixed_time(\"2024-01-01T00:00:00Z\")",
+        Capability::FixedTime,
+    );
+    expect_permission_denied(&mut ctx, "now()", Capability::Clock);
+
+    let mut ctx = sandboxed_context(SandboxPolicy::strict().with_fixed_time_allowed(true));
+    evaluate(&mut ctx, "set_fixed_time(\"2024-01-01 12:00\")").unwrap();
+    assert_eq!(
+        evaluate(&mut ctx, "now()").unwrap(),
+        "datetime(\"2024-01-01T12:00:00+00:00[UTC]\")"
+    );
+
+    let mut ctx = sandboxed_context(SandboxPolicy::unrestricted().with_fixed_time_allowed(false));
+    expect_permission_denied(
+        &mut ctx,
+        "set_fixed_time(\"2024-01-01T00:00:00Z\")",
+        Capability::FixedTime,
+    );
+}
+
+#[test]
+fn reading_environment_variables() {
+    // Cargo sets this variable when running tests
+    let mut ctx = sandboxed_context(SandboxPolicy::unrestricted());
+    assert_eq!(
+        evaluate(&mut ctx, "env(\"CARGO_PKG_NAME\")").unwrap(),
+        "\"numbat\""
+    );
+
+    let mut ctx = sandboxed_context(SandboxPolicy::strict());
+    expect_permission_denied(&mut ctx, "env(\"CARGO_PKG_NAME\")", Capability::Environment);
+
+    let mut ctx = sandboxed_context(SandboxPolicy::unrestricted().with_environment_allowed(false));
+    expect_permission_denied(&mut ctx, "env(\"HOME\")", Capability::Environment);
+
+    let mut ctx = sandboxed_context(SandboxPolicy::strict().with_environment_allowed(true));
+    assert_eq!(
+        evaluate(&mut ctx, "env(\"CARGO_PKG_NAME\")").unwrap(),
+        "\"numbat\""
+    );
+
+    // Script arguments are provided by the embedding application and not restricted
+    let mut ctx = sandboxed_context(SandboxPolicy::strict());
+    ctx.set_script_arguments(vec!["3.5".into(), "km".into()]);
+    assert_eq!(evaluate(&mut ctx, "args()").unwrap(), "[\"3.5\", \"km\"]");
+}
+
+#[test]
+fn writing_files() {
+    let mut ctx = sandboxed_context(SandboxPolicy::strict());
+    expect_permission_denied(
+        &mut ctx,
+        "write_text(\"numbat-sandbox-output.txt\", \"text\")",
+        Capability::Filesystem,
+    );
+    expect_permission_denied(
+        &mut ctx,
+        "append_csv(\"numbat-sandbox-output.csv\", [1, 2])",
+        Capability::Filesystem,
+    );
+    assert!(!Path::new("numbat-sandbox-output.txt").exists());
+
+    // Files that are kept in memory can be written in sandbox mode
+    let file_system = Arc::new(InMemoryFileSystem::new());
+    ctx.set_file_system(file_system.clone());
+    evaluate(&mut ctx, "write_text(\"output.txt\", \"text\")").unwrap();
+    assert_eq!(file_system.contents("output.txt").as_deref(), Some("text"));
+}